@@ -0,0 +1,12 @@
+-- Create price_history table
+-- Every successful spot price lookup is recorded here so the agent can
+-- answer price questions from the last known value when it is offline
+CREATE TABLE price_history (
+    id SERIAL PRIMARY KEY,
+    coin_id TEXT NOT NULL,
+    price_usd DOUBLE PRECISION NOT NULL,
+    fetched_at TIMESTAMP NOT NULL DEFAULT now()
+);
+
+-- Create index for latest-price lookups per coin
+CREATE INDEX idx_price_history_coin_id_fetched_at ON price_history(coin_id, fetched_at DESC);
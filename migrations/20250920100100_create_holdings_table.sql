@@ -0,0 +1,14 @@
+-- Create holdings table
+CREATE TABLE holdings (
+    id SERIAL PRIMARY KEY,
+    user_id INTEGER NOT NULL,
+    coin_id TEXT NOT NULL,
+    amount DOUBLE PRECISION NOT NULL,
+    created_at TIMESTAMP DEFAULT now(),
+    updated_at TIMESTAMP DEFAULT now(),
+    UNIQUE(user_id, coin_id),
+    FOREIGN KEY (user_id) REFERENCES users(id) ON DELETE CASCADE
+);
+
+-- Create index on user_id for faster lookups
+CREATE INDEX idx_holdings_user_id ON holdings(user_id);
@@ -0,0 +1,25 @@
+-- Restrict messages.role to the roles the application knows about
+-- Normalize case and whitespace first, then map anything else to 'user' so the constraint can be added
+UPDATE messages
+SET role = lower(trim(role))
+WHERE lower(trim(role)) IN ('user', 'assistant', 'system', 'tool')
+  AND role <> lower(trim(role));
+
+DO $$
+DECLARE
+    unexpected INTEGER;
+BEGIN
+    SELECT count(*) INTO unexpected
+    FROM messages
+    WHERE role NOT IN ('user', 'assistant', 'system', 'tool');
+
+    IF unexpected > 0 THEN
+        RAISE WARNING 'Mapping % messages with unexpected roles to user', unexpected;
+        UPDATE messages
+        SET role = 'user'
+        WHERE role NOT IN ('user', 'assistant', 'system', 'tool');
+    END IF;
+END $$;
+
+ALTER TABLE messages
+    ADD CONSTRAINT messages_role_check CHECK (role IN ('user', 'assistant', 'system', 'tool'));
@@ -0,0 +1,32 @@
+-- Tie messages to their user so history can be archived per user
+ALTER TABLE messages ADD COLUMN user_id INTEGER REFERENCES users(id) ON DELETE CASCADE;
+
+-- Existing history belongs to the first (default) user
+UPDATE messages SET user_id = (SELECT min(id) FROM users) WHERE user_id IS NULL;
+
+CREATE INDEX idx_messages_user_id_created_at ON messages(user_id, created_at);
+
+-- Messages moved out of the live history by the retention policy
+CREATE TABLE messages_archive (
+    id INTEGER PRIMARY KEY,
+    user_id INTEGER REFERENCES users(id) ON DELETE CASCADE,
+    role TEXT NOT NULL CHECK (role IN ('user', 'assistant', 'system', 'tool')),
+    content TEXT NOT NULL,
+    created_at TIMESTAMP,
+    archived_at TIMESTAMP NOT NULL DEFAULT now()
+);
+
+CREATE INDEX idx_messages_archive_user_id_created_at ON messages_archive(user_id, created_at);
+
+-- Summaries written before messages are archived, so the context isn't lost
+CREATE TABLE conversation_summaries (
+    id SERIAL PRIMARY KEY,
+    user_id INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    period_start TIMESTAMP NOT NULL,
+    period_end TIMESTAMP NOT NULL,
+    message_count INTEGER NOT NULL,
+    summary TEXT NOT NULL,
+    created_at TIMESTAMP NOT NULL DEFAULT now()
+);
+
+CREATE INDEX idx_conversation_summaries_user_id ON conversation_summaries(user_id, period_end);
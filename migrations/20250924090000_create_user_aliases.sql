@@ -0,0 +1,10 @@
+-- Per-user shorthand for coins and projects, e.g. "big coin" -> "btc"
+CREATE TABLE user_aliases (
+    id SERIAL PRIMARY KEY,
+    user_id INTEGER NOT NULL,
+    alias TEXT NOT NULL,
+    target TEXT NOT NULL,
+    created_at TIMESTAMP NOT NULL DEFAULT now(),
+    UNIQUE(user_id, alias),
+    FOREIGN KEY (user_id) REFERENCES users(id) ON DELETE CASCADE
+);
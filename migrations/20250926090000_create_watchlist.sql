@@ -0,0 +1,10 @@
+-- Coins a user follows without holding them
+CREATE TABLE watchlist (
+    id SERIAL PRIMARY KEY,
+    user_id INTEGER NOT NULL,
+    coin_id TEXT NOT NULL,
+    note TEXT,
+    added_at TIMESTAMP NOT NULL DEFAULT now(),
+    UNIQUE(user_id, coin_id),
+    FOREIGN KEY (user_id) REFERENCES users(id) ON DELETE CASCADE
+);
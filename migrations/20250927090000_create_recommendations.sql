@@ -0,0 +1,15 @@
+-- Concrete calls Nova made in its answers, kept to score them against later prices
+CREATE TABLE recommendations (
+    id SERIAL PRIMARY KEY,
+    user_id INTEGER NOT NULL,
+    coin_id TEXT NOT NULL,
+    action TEXT NOT NULL CHECK (action IN ('accumulate', 'buy', 'take_profit', 'sell')),
+    price_low DOUBLE PRECISION NOT NULL,
+    price_high DOUBLE PRECISION NOT NULL,
+    target_date DATE,
+    made_at TIMESTAMP NOT NULL DEFAULT now(),
+    made_on DATE NOT NULL DEFAULT CURRENT_DATE,
+    -- The same call repeated on the same day is recorded once
+    UNIQUE(user_id, coin_id, action, price_low, price_high, made_on),
+    FOREIGN KEY (user_id) REFERENCES users(id) ON DELETE CASCADE
+);
@@ -0,0 +1,3 @@
+-- How long Nova's answers should be for each user
+ALTER TABLE users ADD COLUMN verbosity TEXT NOT NULL DEFAULT 'normal'
+    CHECK (verbosity IN ('brief', 'normal', 'detailed'));
@@ -0,0 +1,7 @@
+-- Token buckets of the per-user rate limiter, shared by every process using the database
+CREATE TABLE rate_limit_buckets (
+    user_id INTEGER PRIMARY KEY,
+    tokens DOUBLE PRECISION NOT NULL,
+    updated_at TIMESTAMP NOT NULL,
+    FOREIGN KEY (user_id) REFERENCES users(id) ON DELETE CASCADE
+);
@@ -0,0 +1,5 @@
+-- System prompt variant that was active when an assistant message was written, NULL for the default prompt
+ALTER TABLE messages ADD COLUMN prompt_variant TEXT;
+ALTER TABLE messages_archive ADD COLUMN prompt_variant TEXT;
+
+CREATE INDEX idx_messages_prompt_variant ON messages(prompt_variant) WHERE prompt_variant IS NOT NULL;
@@ -0,0 +1,3 @@
+-- The user's timezone as a UTC offset, e.g. 'UTC' or 'UTC+05:30'
+ALTER TABLE users ADD COLUMN timezone TEXT NOT NULL DEFAULT 'UTC'
+    CHECK (timezone ~ '^UTC([+-](0[0-9]|1[0-4]):[0-5][0-9])?$');
@@ -0,0 +1,11 @@
+-- Create gas_readings table
+-- The daemon's gas watcher records the gas price here every few minutes so
+-- gas answers can say whether the current price is high or low for the day
+CREATE TABLE gas_readings (
+    id SERIAL PRIMARY KEY,
+    base_fee_gwei DOUBLE PRECISION NOT NULL,
+    priority_fee_gwei DOUBLE PRECISION NOT NULL,
+    taken_at TIMESTAMP NOT NULL DEFAULT now()
+);
+
+CREATE INDEX idx_gas_readings_taken_at ON gas_readings(taken_at);
@@ -0,0 +1,19 @@
+-- Knowledge entries injected into the prompt that wrote an assistant message,
+-- as {"knowledge_sources": [source_id, ...]}, NULL when none were used
+ALTER TABLE messages ADD COLUMN metadata JSONB;
+ALTER TABLE messages_archive ADD COLUMN metadata JSONB;
+
+-- Create feedback table
+-- One rating per assistant message, rating again replaces it. message_id has no
+-- foreign key because rated messages move to messages_archive with the same id
+CREATE TABLE feedback (
+    id SERIAL PRIMARY KEY,
+    user_id INTEGER NOT NULL,
+    message_id INTEGER NOT NULL UNIQUE,
+    score SMALLINT NOT NULL CHECK (score IN (-1, 1)),
+    reason TEXT,
+    created_at TIMESTAMP NOT NULL DEFAULT now(),
+    FOREIGN KEY (user_id) REFERENCES users(id) ON DELETE CASCADE
+);
+
+CREATE INDEX idx_feedback_user_id_created_at ON feedback(user_id, created_at);
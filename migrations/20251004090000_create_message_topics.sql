@@ -0,0 +1,16 @@
+-- Create message_topics table
+-- Coins and keywords detected in each user message, counted by /topics and
+-- "what have we talked about most" questions. message_id has no foreign key
+-- because messages move to messages_archive with the same id
+CREATE TABLE message_topics (
+    id SERIAL PRIMARY KEY,
+    user_id INTEGER NOT NULL,
+    message_id INTEGER NOT NULL,
+    topic TEXT NOT NULL,
+    kind TEXT NOT NULL CHECK (kind IN ('coin', 'keyword')),
+    created_at TIMESTAMP NOT NULL DEFAULT now(),
+    UNIQUE (message_id, topic, kind),
+    FOREIGN KEY (user_id) REFERENCES users(id) ON DELETE CASCADE
+);
+
+CREATE INDEX idx_message_topics_user_id_created_at ON message_topics(user_id, created_at);
@@ -0,0 +1,17 @@
+-- Create turn_debug table
+-- What each chat turn sent to the model, recorded only while DEBUG_CAPTURE is on and
+-- shown by /debug. payload is the capture as JSON, encrypted like private knowledge
+-- when a knowledge key is loaded since prompts can carry decrypted entries.
+-- message_id is the user's question; no foreign key because messages get archived
+CREATE TABLE turn_debug (
+    id SERIAL PRIMARY KEY,
+    user_id INTEGER NOT NULL,
+    message_id INTEGER,
+    question TEXT NOT NULL,
+    intent TEXT NOT NULL,
+    payload TEXT NOT NULL,
+    created_at TIMESTAMP NOT NULL DEFAULT now(),
+    FOREIGN KEY (user_id) REFERENCES users(id) ON DELETE CASCADE
+);
+
+CREATE INDEX idx_turn_debug_user_id ON turn_debug(user_id, id);
@@ -0,0 +1,17 @@
+-- Create limit_orders table
+-- Limit orders staged by the trading client, kept across restarts. Filled and
+-- cancelled orders stay for the record, only open ones can change status
+CREATE TABLE limit_orders (
+    id TEXT PRIMARY KEY,
+    user_id INTEGER NOT NULL,
+    order_type TEXT NOT NULL CHECK (order_type IN ('buy', 'sell')),
+    token_address TEXT NOT NULL,
+    amount DOUBLE PRECISION NOT NULL,
+    price DOUBLE PRECISION NOT NULL,
+    status TEXT NOT NULL DEFAULT 'open' CHECK (status IN ('open', 'filled', 'cancelled')),
+    created_at TIMESTAMP NOT NULL DEFAULT now(),
+    updated_at TIMESTAMP NOT NULL DEFAULT now(),
+    FOREIGN KEY (user_id) REFERENCES users(id) ON DELETE CASCADE
+);
+
+CREATE INDEX idx_limit_orders_user_id_status ON limit_orders(user_id, status);
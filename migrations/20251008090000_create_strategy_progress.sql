@@ -0,0 +1,27 @@
+-- Create strategy_progress and strategy_outcomes tables
+-- One row per step of a strategy being worked through, completed_at stays NULL
+-- until the step is marked done. Outcomes are notes on how a finished run went
+CREATE TABLE strategy_progress (
+    strategy_id INTEGER NOT NULL,
+    user_id INTEGER NOT NULL,
+    step_index INTEGER NOT NULL CHECK (step_index >= 0),
+    completed_at TIMESTAMP,
+    note TEXT,
+    PRIMARY KEY (strategy_id, step_index),
+    FOREIGN KEY (strategy_id) REFERENCES strategies(id) ON DELETE CASCADE,
+    FOREIGN KEY (user_id) REFERENCES users(id) ON DELETE CASCADE
+);
+
+CREATE INDEX idx_strategy_progress_user_id ON strategy_progress(user_id);
+
+CREATE TABLE strategy_outcomes (
+    id SERIAL PRIMARY KEY,
+    strategy_id INTEGER NOT NULL,
+    user_id INTEGER NOT NULL,
+    note TEXT NOT NULL,
+    created_at TIMESTAMP NOT NULL DEFAULT now(),
+    FOREIGN KEY (strategy_id) REFERENCES strategies(id) ON DELETE CASCADE,
+    FOREIGN KEY (user_id) REFERENCES users(id) ON DELETE CASCADE
+);
+
+CREATE INDEX idx_strategy_outcomes_user_id ON strategy_outcomes(user_id);
@@ -0,0 +1,20 @@
+-- Create trades table
+-- Buys and sells of a coin against a USD quote. Imported rows come from exchange
+-- CSV exports; importing the same file twice doesn't duplicate them
+CREATE TABLE trades (
+    id SERIAL PRIMARY KEY,
+    user_id INTEGER NOT NULL,
+    executed_at TIMESTAMP NOT NULL,
+    side TEXT NOT NULL CHECK (side IN ('buy', 'sell')),
+    coin_id TEXT NOT NULL,
+    quote TEXT NOT NULL,
+    amount DOUBLE PRECISION NOT NULL CHECK (amount > 0),
+    price DOUBLE PRECISION NOT NULL CHECK (price > 0),
+    fee DOUBLE PRECISION NOT NULL DEFAULT 0 CHECK (fee >= 0),
+    imported BOOLEAN NOT NULL DEFAULT false,
+    created_at TIMESTAMP NOT NULL DEFAULT now(),
+    FOREIGN KEY (user_id) REFERENCES users(id) ON DELETE CASCADE
+);
+
+CREATE INDEX idx_trades_user_id_executed_at ON trades(user_id, executed_at);
+CREATE UNIQUE INDEX idx_trades_imported_unique ON trades(user_id, executed_at, side, coin_id, amount, price) WHERE imported;
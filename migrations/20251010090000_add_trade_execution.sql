@@ -0,0 +1,13 @@
+-- Add execution details to trades
+-- Swaps sent from the wallet and filled limit orders are recorded next to imported
+-- trades, with the tokens and amounts swapped, the transaction and what its gas cost
+-- in ETH. Failed swaps are kept with status 'failed' and left out of the cost basis
+ALTER TABLE trades
+    ADD COLUMN tx_hash TEXT,
+    ADD COLUMN from_token TEXT,
+    ADD COLUMN to_token TEXT,
+    ADD COLUMN from_amount DOUBLE PRECISION,
+    ADD COLUMN to_amount DOUBLE PRECISION,
+    ADD COLUMN price_usd_at_execution DOUBLE PRECISION,
+    ADD COLUMN gas_cost DOUBLE PRECISION CHECK (gas_cost >= 0),
+    ADD COLUMN status TEXT NOT NULL DEFAULT 'filled' CHECK (status IN ('filled', 'failed'));
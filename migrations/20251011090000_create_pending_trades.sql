@@ -0,0 +1,9 @@
+-- Create pending_trades table
+-- A trade asked for in the chat and waiting for the user's CONFIRM, at most one
+-- per user. Staging another trade replaces it, confirming or cancelling removes it
+CREATE TABLE pending_trades (
+    user_id INTEGER PRIMARY KEY,
+    trade JSONB NOT NULL,
+    created_at TIMESTAMP NOT NULL DEFAULT now(),
+    FOREIGN KEY (user_id) REFERENCES users(id) ON DELETE CASCADE
+);
@@ -0,0 +1,4 @@
+-- The user's timezone may also be an IANA name like 'Europe/Berlin', checked against the timezone database when set
+ALTER TABLE users DROP CONSTRAINT users_timezone_check;
+ALTER TABLE users ADD CONSTRAINT users_timezone_check
+    CHECK (timezone ~ '^UTC([+-](0[0-9]|1[0-4]):[0-5][0-9])?$' OR timezone ~ '^[A-Za-z][A-Za-z0-9_+-]*(/[A-Za-z0-9_+-]+)*$');
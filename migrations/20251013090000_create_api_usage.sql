@@ -0,0 +1,9 @@
+-- Create api_usage table
+-- Calls made to each upstream API per UTC day, so its daily budget carries
+-- over restarts and /stats usage can show what was spent
+CREATE TABLE api_usage (
+    upstream VARCHAR(50) NOT NULL,
+    day DATE NOT NULL,
+    calls BIGINT NOT NULL DEFAULT 0,
+    PRIMARY KEY (upstream, day)
+);
@@ -0,0 +1,9 @@
+-- Add expires_at to limit_orders
+-- An order with an expiry is good until then: past it, the next check moves it from
+-- open to expired instead of filling it. NULL keeps the order open until it fills or
+-- is cancelled
+ALTER TABLE limit_orders ADD COLUMN expires_at TIMESTAMP;
+
+ALTER TABLE limit_orders DROP CONSTRAINT limit_orders_status_check;
+ALTER TABLE limit_orders ADD CONSTRAINT limit_orders_status_check
+    CHECK (status IN ('open', 'filled', 'cancelled', 'expired'));
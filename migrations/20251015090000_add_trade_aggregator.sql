@@ -0,0 +1,4 @@
+-- Add aggregator to trades
+-- Swaps are routed by 1inch, or by 0x when 1inch can't quote them; the aggregator
+-- that prepared a swap is kept with it. NULL for imported trades and limit order fills
+ALTER TABLE trades ADD COLUMN aggregator TEXT;
@@ -0,0 +1,13 @@
+-- Add confirmation tracking to trades
+-- A swap is recorded as 'pending' once it is sent, then followed until it is mined:
+-- 'confirmed' or 'failed' (reverted) with the block that mined it and the gas it used,
+-- or 'stuck' when it isn't mined within the configured number of blocks
+ALTER TABLE trades
+    ADD COLUMN block_number BIGINT,
+    ADD COLUMN gas_used BIGINT CHECK (gas_used >= 0);
+
+ALTER TABLE trades DROP CONSTRAINT trades_status_check;
+ALTER TABLE trades ADD CONSTRAINT trades_status_check
+    CHECK (status IN ('filled', 'failed', 'pending', 'confirmed', 'stuck'));
+
+CREATE INDEX idx_trades_user_id_tx_hash ON trades(user_id, tx_hash) WHERE tx_hash IS NOT NULL;
@@ -0,0 +1,11 @@
+-- One row per chat session, holding the custom advisor instructions set with /system set
+-- system_prompt_override stays NULL while the session uses the default prompt
+CREATE TABLE conversations (
+    id SERIAL PRIMARY KEY,
+    user_id INTEGER NOT NULL,
+    system_prompt_override TEXT,
+    started_at TIMESTAMP NOT NULL DEFAULT now(),
+    FOREIGN KEY (user_id) REFERENCES users(id) ON DELETE CASCADE
+);
+
+CREATE INDEX idx_conversations_user_id ON conversations(user_id);
@@ -1,12 +1,45 @@
 use crate::db::{
-    self, Strategy, User, Knowledge,
-    create_strategy, create_knowledge,
+    self, ConflictMode, DbError, Strategy, User, Knowledge, UserAlias,
+    create_strategy, create_knowledge_batch,
     get_strategies_by_user_id, get_knowledge_by_user_id,
     search_strategies_by_text, search_knowledge_by_text
 };
+use crate::agent_registry;
 use sqlx::{Pool, Postgres};
 use serde::{Deserialize, Serialize};
 use sqlx::types::JsonValue;
+use std::collections::BTreeMap;
+use thiserror::Error;
+
+pub use crate::db::KnowledgeInput;
+
+/// Knowledge tags shown per page of a rendered profile
+pub const PROFILE_TAGS_PER_PAGE: usize = 10;
+
+/// Source ids listed under each knowledge tag before the rest are counted
+pub const PROFILE_SOURCES_PER_TAG: usize = 5;
+
+/// Strategy names listed before the rest are counted
+pub const PROFILE_STRATEGY_NAMES: usize = 20;
+
+/// Group for knowledge entries without tags
+const UNTAGGED: &str = "untagged";
+
+/// Errors raised while customizing an agent
+#[derive(Debug, Error)]
+pub enum CustomizerError {
+    #[error("Database error: {0}")]
+    Database(#[from] DbError),
+    
+    #[error("Failed to create user: {0}")]
+    CreateUser(DbError),
+    
+    #[error("Failed to create strategy {name}: {source}")]
+    CreateStrategy { name: String, source: DbError },
+    
+    #[error("Failed to create {count} knowledge entries: {source}")]
+    CreateKnowledge { count: usize, source: DbError },
+}
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct AgentCustomizationRequest {
@@ -31,36 +64,31 @@ pub struct StrategyInput {
     pub version: String,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-pub struct KnowledgeInput {
-    pub source_id: String,
-    pub content: String,
-    pub tags: Vec<String>,
-}
-
 #[derive(Debug, Serialize, Deserialize)]
 pub struct AgentProfile {
     pub user: User,
     pub strategies: Vec<Strategy>,
     pub knowledge: Vec<Knowledge>,
+    /// The user's own names for coins and projects
+    #[serde(default)]
+    pub aliases: Vec<UserAlias>,
 }
 
 /// Customizes an agent for a user by adding strategies and knowledge
 pub async fn customize_agent(
     pool: &Pool<Postgres>,
     request: AgentCustomizationRequest,
-) -> Result<AgentProfile, String> {
+) -> Result<AgentProfile, CustomizerError> {
     // Get or create user
     let user = match db::get_user_by_username(pool, &request.username).await {
         Ok(Some(user)) => user,
         Ok(None) => {
             // Create new user
-            match db::create_user(pool, &request.username, request.wallet_address.as_deref()).await {
-                Ok(user) => user,
-                Err(e) => return Err(format!("Failed to create user: {}", e)),
-            }
+            db::create_user(pool, &request.username, request.wallet_address.as_deref())
+                .await
+                .map_err(CustomizerError::CreateUser)?
         },
-        Err(e) => return Err(format!("Database error: {}", e)),
+        Err(e) => return Err(e.into()),
     };
     
     // Add strategies if provided
@@ -81,41 +109,33 @@ pub async fn customize_agent(
                 &strategy.author,
                 &strategy.version,
             ).await {
-                return Err(format!("Failed to create strategy {}: {}", strategy.name, e));
+                return Err(CustomizerError::CreateStrategy { name: strategy.name, source: e });
             }
         }
     }
     
-    // Add knowledge if provided
+    // Add knowledge if provided, all of it or none
     if let Some(knowledge_items) = request.knowledge {
-        for knowledge in knowledge_items {
-            if let Err(e) = create_knowledge(
-                pool,
-                user.id,
-                &knowledge.source_id,
-                &knowledge.content,
-                &knowledge.tags,
-            ).await {
-                return Err(format!("Failed to create knowledge {}: {}", knowledge.source_id, e));
-            }
-        }
+        create_knowledge_batch(pool, user.id, &knowledge_items, ConflictMode::FailAll)
+            .await
+            .map_err(|e| CustomizerError::CreateKnowledge { count: knowledge_items.len(), source: e })?;
     }
     
     // Retrieve all strategies and knowledge for the user
-    let strategies = match get_strategies_by_user_id(pool, user.id).await {
-        Ok(strategies) => strategies,
-        Err(e) => return Err(format!("Failed to retrieve strategies: {}", e)),
-    };
+    let strategies = get_strategies_by_user_id(pool, user.id).await?;
 
-    let knowledge = match get_knowledge_by_user_id(pool, user.id).await {
-        Ok(knowledge) => knowledge,
-        Err(e) => return Err(format!("Failed to retrieve knowledge: {}", e)),
-    };
+    let knowledge = get_knowledge_by_user_id(pool, user.id).await?;
+
+    let aliases = db::get_user_aliases(pool, user.id).await?;
+
+    // A shared agent of the user still has the old profile
+    agent_registry::invalidate(&user.username);
 
     Ok(AgentProfile {
         user,
         strategies,
         knowledge,
+        aliases,
     })
 }
 
@@ -124,16 +144,10 @@ pub async fn search_agent_data(
     pool: &Pool<Postgres>,
     user_id: i32,
     query: &str,
-) -> Result<(Vec<Strategy>, Vec<Knowledge>), String> {
-    let strategies = match search_strategies_by_text(pool, user_id, query).await {
-        Ok(strategies) => strategies,
-        Err(e) => return Err(format!("Failed to search strategies: {}", e)),
-    };
+) -> Result<(Vec<Strategy>, Vec<Knowledge>), CustomizerError> {
+    let strategies = search_strategies_by_text(pool, user_id, query).await?;
 
-    let knowledge = match search_knowledge_by_text(pool, user_id, query).await {
-        Ok(knowledge) => knowledge,
-        Err(e) => return Err(format!("Failed to search knowledge: {}", e)),
-    };
+    let knowledge = search_knowledge_by_text(pool, user_id, query).await?;
     
     Ok((strategies, knowledge))
 }
@@ -142,28 +156,259 @@ pub async fn search_agent_data(
 pub async fn get_agent_profile(
     pool: &Pool<Postgres>,
     username: &str,
-) -> Result<Option<AgentProfile>, String> {
+) -> Result<Option<AgentProfile>, CustomizerError> {
     // Get user
     let user = match db::get_user_by_username(pool, username).await {
         Ok(Some(user)) => user,
         Ok(None) => return Ok(None),
-        Err(e) => return Err(format!("Database error: {}", e)),
+        Err(e) => return Err(e.into()),
     };
 
     // Retrieve all strategies and knowledge for the user
-    let strategies = match get_strategies_by_user_id(pool, user.id).await {
-        Ok(strategies) => strategies,
-        Err(e) => return Err(format!("Failed to retrieve strategies: {}", e)),
-    };
+    let strategies = get_strategies_by_user_id(pool, user.id).await?;
 
-    let knowledge = match get_knowledge_by_user_id(pool, user.id).await {
-        Ok(knowledge) => knowledge,
-        Err(e) => return Err(format!("Failed to retrieve knowledge: {}", e)),
-    };
+    let knowledge = get_knowledge_by_user_id(pool, user.id).await?;
+
+    let aliases = db::get_user_aliases(pool, user.id).await?;
 
     Ok(Some(AgentProfile {
         user,
         strategies,
         knowledge,
+        aliases,
     }))
 }
+
+/// Knowledge source ids grouped by tag, largest groups first
+///
+/// An entry with several tags is listed under each of them, entries without tags under "untagged".
+pub fn group_knowledge_by_tag(knowledge: &[Knowledge]) -> Vec<(String, Vec<&str>)> {
+    let mut groups: BTreeMap<String, Vec<&str>> = BTreeMap::new();
+    for entry in knowledge {
+        if entry.tags.is_empty() {
+            groups.entry(UNTAGGED.to_string()).or_default().push(&entry.source_id);
+        }
+        for tag in &entry.tags {
+            groups.entry(tag.to_lowercase()).or_default().push(&entry.source_id);
+        }
+    }
+
+    let mut groups: Vec<(String, Vec<&str>)> = groups.into_iter().collect();
+    groups.sort_by(|a, b| b.1.len().cmp(&a.1.len()).then_with(|| a.0.cmp(&b.0)));
+    groups
+}
+
+/// Pages needed to show every knowledge tag of a profile
+pub fn profile_pages(profile: &AgentProfile) -> usize {
+    group_knowledge_by_tag(&profile.knowledge).len().div_ceil(PROFILE_TAGS_PER_PAGE).max(1)
+}
+
+/// "a, b, c and 4 more", listing at most `shown` items
+fn render_names<S: AsRef<str>>(names: &[S], shown: usize) -> String {
+    let mut output = names.iter().take(shown).map(|name| name.as_ref()).collect::<Vec<_>>().join(", ");
+    if names.len() > shown {
+        output.push_str(&format!(" and {} more", names.len() - shown));
+    }
+    output
+}
+
+/// Render a profile for the terminal, one page of knowledge tags at a time
+///
+/// The first page also shows the user, strategies and preferences; `page` starts at 1.
+pub fn render_profile(profile: &AgentProfile, page: usize) -> String {
+    let groups = group_knowledge_by_tag(&profile.knowledge);
+    let pages = profile_pages(profile);
+    if page == 0 || page > pages {
+        return format!("There's no page {} of your profile, it has {} page(s).", page, pages);
+    }
+
+    let mut output = format!("Profile for {}", profile.user.username);
+    if page == 1 {
+        output.push_str(&format!(
+            "\n- Wallet: {}\n- Member since: {}\n",
+            profile.user.wallet_address.as_deref().unwrap_or("not set"),
+            profile.user.created_at.format("%Y-%m-%d")
+        ));
+
+        output.push_str(&format!("\nStrategies ({})", profile.strategies.len()));
+        if profile.strategies.is_empty() {
+            output.push('\n');
+        } else {
+            let names: Vec<&str> = profile.strategies.iter().map(|strategy| strategy.name.as_str()).collect();
+            output.push_str(&format!(": {}\n", render_names(&names, PROFILE_STRATEGY_NAMES)));
+        }
+
+        output.push_str(&format!(
+            "\nPreferences:\n- Answer length: {}\n- Timezone: {}\n",
+            profile.user.verbosity, profile.user.timezone
+        ));
+        if profile.aliases.is_empty() {
+            output.push_str("- Aliases: none\n");
+        } else {
+            let aliases: Vec<String> = profile.aliases.iter().map(|alias| format!("{} = {}", alias.alias, alias.target)).collect();
+            output.push_str(&format!("- Aliases: {}\n", aliases.join(", ")));
+        }
+    } else {
+        output.push('\n');
+    }
+
+    output.push_str(&format!("\nKnowledge: {} entries under {} tag(s)", profile.knowledge.len(), groups.len()));
+    if pages > 1 {
+        output.push_str(&format!(", page {} of {}", page, pages));
+    }
+    output.push('\n');
+    for (tag, sources) in groups.iter().skip((page - 1) * PROFILE_TAGS_PER_PAGE).take(PROFILE_TAGS_PER_PAGE) {
+        output.push_str(&format!("- {} ({}): {}\n", tag, sources.len(), render_names(sources, PROFILE_SOURCES_PER_TAG)));
+    }
+    if page < pages {
+        output.push_str(&format!("\nMore tags: /profile {}", page + 1));
+    }
+
+    output.trim_end().to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::Verbosity;
+    use chrono::{NaiveDate, NaiveDateTime};
+
+    fn timestamp() -> NaiveDateTime {
+        NaiveDate::from_ymd_opt(2025, 9, 13).unwrap().and_hms_opt(12, 0, 0).unwrap()
+    }
+
+    fn knowledge(source_id: &str, tags: &[&str]) -> Knowledge {
+        Knowledge {
+            id: 0,
+            user_id: 1,
+            source_id: source_id.to_string(),
+            content: String::new(),
+            tags: tags.iter().map(|tag| tag.to_string()).collect(),
+            created_at: timestamp(),
+            updated_at: timestamp(),
+        }
+    }
+
+    fn strategy(name: &str) -> Strategy {
+        Strategy {
+            id: 0,
+            user_id: 1,
+            strategy_id: name.to_lowercase().replace(' ', "_"),
+            name: name.to_string(),
+            category: "yield".to_string(),
+            description: String::new(),
+            risk_level: "medium".to_string(),
+            tags: Vec::new(),
+            steps: Vec::new(),
+            requirements: Vec::new(),
+            expected_returns: JsonValue::Null,
+            created_at: timestamp(),
+            updated_at: timestamp(),
+            author: "defi_trader".to_string(),
+            version: "1.0".to_string(),
+        }
+    }
+
+    fn profile(strategies: Vec<Strategy>, knowledge: Vec<Knowledge>) -> AgentProfile {
+        AgentProfile {
+            user: User {
+                id: 1,
+                username: "defi_trader".to_string(),
+                wallet_address: Some("0x123".to_string()),
+                verbosity: Verbosity::Brief,
+                timezone: "UTC+02:00".to_string(),
+                created_at: timestamp(),
+                updated_at: timestamp(),
+            },
+            strategies,
+            knowledge,
+            aliases: vec![UserAlias {
+                id: 1,
+                user_id: 1,
+                alias: "blue chips".to_string(),
+                target: "bitcoin,ethereum".to_string(),
+                created_at: timestamp(),
+            }],
+        }
+    }
+
+    #[test]
+    fn test_render_profile() {
+        let profile = profile(
+            vec![strategy("Stable Yield"), strategy("ETH Accumulation")],
+            vec![knowledge("aave_docs", &["defi", "Lending"]), knowledge("uniswap_v3", &["defi"]), knowledge("notes", &[])],
+        );
+
+        assert_eq!(
+            render_profile(&profile, 1),
+            "Profile for defi_trader\n\
+            - Wallet: 0x123\n\
+            - Member since: 2025-09-13\n\n\
+            Strategies (2): Stable Yield, ETH Accumulation\n\n\
+            Preferences:\n\
+            - Answer length: brief\n\
+            - Timezone: UTC+02:00\n\
+            - Aliases: blue chips = bitcoin,ethereum\n\n\
+            Knowledge: 3 entries under 3 tag(s)\n\
+            - defi (2): aave_docs, uniswap_v3\n\
+            - lending (1): aave_docs\n\
+            - untagged (1): notes"
+        );
+    }
+
+    #[test]
+    fn test_render_large_profile_paginates() {
+        let strategies: Vec<Strategy> = (0..25).map(|i| strategy(&format!("Strategy {}", i))).collect();
+        let knowledge: Vec<Knowledge> = (0..300)
+            .map(|i| {
+                let tag = format!("tag{:02}", i % 25);
+                knowledge(&format!("source_{}", i), &[tag.as_str()])
+            })
+            .collect();
+        let profile = profile(strategies, knowledge);
+
+        assert_eq!(profile_pages(&profile), 3);
+
+        let first = render_profile(&profile, 1);
+        assert!(first.contains("Strategies (25): Strategy 0, "));
+        assert!(first.contains("Strategy 19 and 5 more\n"));
+        assert!(first.contains("Knowledge: 300 entries under 25 tag(s), page 1 of 3\n"));
+        assert!(first.contains("- tag00 (12): source_0, source_25, source_50, source_75, source_100 and 7 more\n"));
+        assert_eq!(first.lines().filter(|line| line.starts_with("- tag")).count(), PROFILE_TAGS_PER_PAGE);
+        assert!(first.ends_with("More tags: /profile 2"));
+
+        let last = render_profile(&profile, 3);
+        assert!(!last.contains("Strategies"));
+        assert!(last.contains("page 3 of 3\n- tag20 (12)"));
+        assert_eq!(last.lines().filter(|line| line.starts_with("- tag")).count(), 5);
+        assert!(!last.contains("More tags"));
+
+        assert_eq!(render_profile(&profile, 4), "There's no page 4 of your profile, it has 3 page(s).");
+    }
+
+    #[test]
+    fn test_render_empty_profile() {
+        let mut profile = profile(Vec::new(), Vec::new());
+        profile.aliases.clear();
+        profile.user.wallet_address = None;
+
+        let output = render_profile(&profile, 1);
+
+        assert!(output.contains("- Wallet: not set\n"));
+        assert!(output.contains("Strategies (0)\n"));
+        assert!(output.contains("- Aliases: none\n"));
+        assert!(output.ends_with("Knowledge: 0 entries under 0 tag(s)"));
+    }
+
+    #[test]
+    fn test_db_errors_keep_context() {
+        let error: CustomizerError = DbError::Query("timeout".to_string()).into();
+        assert!(matches!(error, CustomizerError::Database(DbError::Query(_))));
+
+        let error = CustomizerError::CreateStrategy {
+            name: "Grid".to_string(),
+            source: DbError::Constraint("duplicate strategy_id".to_string()),
+        };
+        assert_eq!(error.to_string(), "Failed to create strategy Grid: Database constraint violation: duplicate strategy_id");
+    }
+}
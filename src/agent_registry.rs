@@ -0,0 +1,295 @@
+use crate::investment_chat::{InvestmentChatAgent, InvestmentChatError, StreamSink};
+use async_trait::async_trait;
+use sqlx::{Pool, Postgres};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::{Duration, Instant};
+use tokio::sync::OnceCell;
+
+/// Most agents kept at once unless the registry is built with another capacity
+pub const DEFAULT_CAPACITY: usize = 100;
+
+/// How long an agent nobody asked for is kept unless the registry is built with another timeout
+pub const DEFAULT_IDLE_TIMEOUT: Duration = Duration::from_secs(30 * 60);
+
+/// Builds the agent serving a user
+#[async_trait]
+pub trait AgentFactory<A>: Send + Sync {
+    async fn create(&self, username: &str) -> Result<A, InvestmentChatError>;
+}
+
+/// Builds chat agents on a database pool
+pub struct PoolAgentFactory {
+    pool: Pool<Postgres>,
+    /// Where the agents stream their general answers, None to wait for the whole answer
+    stream: Option<StreamSink>,
+}
+
+impl PoolAgentFactory {
+    pub fn new(pool: Pool<Postgres>) -> Self {
+        Self { pool, stream: None }
+    }
+
+    /// Build agents streaming their answers to `sink`
+    pub fn with_stream(mut self, sink: StreamSink) -> Self {
+        self.stream = Some(sink);
+        self
+    }
+}
+
+#[async_trait]
+impl AgentFactory<InvestmentChatAgent> for PoolAgentFactory {
+    async fn create(&self, username: &str) -> Result<InvestmentChatAgent, InvestmentChatError> {
+        let agent = InvestmentChatAgent::with_pool(&self.pool, username).await?;
+        Ok(match &self.stream {
+            Some(sink) => agent.with_stream(sink.clone()),
+            None => agent,
+        })
+    }
+}
+
+struct Entry<A> {
+    /// Filled by the first caller, the others wait for it
+    agent: Arc<OnceCell<Arc<A>>>,
+    last_used: Instant,
+}
+
+/// Agents by username, built on first use and shared by later requests of the same user
+///
+/// Concurrent first requests for a user wait for one agent to be built. Agents unused for the idle
+/// timeout are dropped, and past the capacity the least recently used one is. A user whose profile
+/// changed is invalidated, so the next request builds an agent from the new profile; requests
+/// holding the old one keep it until they finish.
+pub struct AgentRegistry<A = InvestmentChatAgent> {
+    factory: Arc<dyn AgentFactory<A>>,
+    capacity: usize,
+    idle_timeout: Duration,
+    entries: Mutex<HashMap<String, Entry<A>>>,
+}
+
+impl<A: Send + Sync + 'static> AgentRegistry<A> {
+    pub fn new(factory: Arc<dyn AgentFactory<A>>) -> Self {
+        Self {
+            factory,
+            capacity: DEFAULT_CAPACITY,
+            idle_timeout: DEFAULT_IDLE_TIMEOUT,
+            entries: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Keep at most `capacity` agents
+    pub fn with_capacity(mut self, capacity: usize) -> Self {
+        self.capacity = capacity.max(1);
+        self
+    }
+
+    /// Drop agents unused for `idle_timeout`
+    pub fn with_idle_timeout(mut self, idle_timeout: Duration) -> Self {
+        self.idle_timeout = idle_timeout;
+        self
+    }
+
+    /// The agent serving `username`, built if there is none
+    pub async fn get(&self, username: &str) -> Result<Arc<A>, InvestmentChatError> {
+        self.get_at(username, Instant::now()).await
+    }
+
+    /// The agent serving `username` as of `now`; a failed build is retried by the next request
+    pub async fn get_at(&self, username: &str, now: Instant) -> Result<Arc<A>, InvestmentChatError> {
+        let cell = {
+            let mut entries = self.entries.lock().unwrap();
+            self.evict_locked(&mut entries, now, Some(username));
+            let entry = entries
+                .entry(username.to_string())
+                .or_insert_with(|| Entry { agent: Arc::new(OnceCell::new()), last_used: now });
+            entry.last_used = now;
+            let cell = entry.agent.clone();
+            if entries.len() > self.capacity {
+                let oldest = entries
+                    .iter()
+                    .filter(|(name, _)| name.as_str() != username)
+                    .min_by_key(|(_, entry)| entry.last_used)
+                    .map(|(name, _)| name.clone());
+                if let Some(oldest) = oldest {
+                    entries.remove(&oldest);
+                }
+            }
+            cell
+        };
+
+        cell.get_or_try_init(|| async { self.factory.create(username).await.map(Arc::new) })
+            .await
+            .cloned()
+    }
+
+    /// Drop the agent of `username`, e.g. because their profile changed
+    pub fn invalidate(&self, username: &str) -> bool {
+        self.entries.lock().unwrap().remove(username).is_some()
+    }
+
+    /// Drop the agents unused for the idle timeout as of `now`, returning how many were dropped
+    pub fn evict_idle(&self, now: Instant) -> usize {
+        let mut entries = self.entries.lock().unwrap();
+        let before = entries.len();
+        self.evict_locked(&mut entries, now, None);
+        before - entries.len()
+    }
+
+    fn evict_locked(&self, entries: &mut HashMap<String, Entry<A>>, now: Instant, keep: Option<&str>) {
+        entries.retain(|name, entry| {
+            Some(name.as_str()) == keep || now.saturating_duration_since(entry.last_used) < self.idle_timeout
+        });
+    }
+
+    /// Users with an agent kept
+    pub fn len(&self) -> usize {
+        self.entries.lock().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Whether an agent is kept for `username`
+    pub fn contains(&self, username: &str) -> bool {
+        self.entries.lock().unwrap().contains_key(username)
+    }
+}
+
+static REGISTRY: OnceLock<Arc<AgentRegistry>> = OnceLock::new();
+
+/// Serve the process's requests from agents built on `pool`
+pub fn start(pool: &Pool<Postgres>) -> Arc<AgentRegistry> {
+    start_with(PoolAgentFactory::new(pool.clone()))
+}
+
+/// Serve the process's requests from agents built by `factory`, e.g. one that streams their answers
+/// The first registry started is kept, later calls return it
+pub fn start_with(factory: PoolAgentFactory) -> Arc<AgentRegistry> {
+    REGISTRY.get_or_init(|| Arc::new(AgentRegistry::new(Arc::new(factory)))).clone()
+}
+
+/// The registry started for this process, None when agents aren't shared between requests
+pub fn configured() -> Option<Arc<AgentRegistry>> {
+    REGISTRY.get().cloned()
+}
+
+/// Drop the process's agent of `username`, if it keeps one
+pub fn invalidate(username: &str) {
+    if let Some(registry) = REGISTRY.get() {
+        registry.invalidate(username);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    /// Builds the username with a serial number, slowly enough for requests to overlap
+    #[derive(Default)]
+    struct CountingFactory {
+        built: AtomicUsize,
+    }
+
+    #[async_trait]
+    impl AgentFactory<(String, usize)> for CountingFactory {
+        async fn create(&self, username: &str) -> Result<(String, usize), InvestmentChatError> {
+            let serial = self.built.fetch_add(1, Ordering::SeqCst) + 1;
+            tokio::time::sleep(Duration::from_millis(20)).await;
+            if username == "broken" {
+                return Err(InvestmentChatError::InvalidInput("no such user".to_string()));
+            }
+            Ok((username.to_string(), serial))
+        }
+    }
+
+    fn registry() -> (Arc<CountingFactory>, AgentRegistry<(String, usize)>) {
+        let factory = Arc::new(CountingFactory::default());
+        (factory.clone(), AgentRegistry::new(factory))
+    }
+
+    #[tokio::test]
+    async fn test_agents_are_built_once_and_shared() {
+        let (factory, registry) = registry();
+        let first = registry.get("alice").await.unwrap();
+        let again = registry.get("alice").await.unwrap();
+        assert!(Arc::ptr_eq(&first, &again));
+        assert_eq!(registry.get("bob").await.unwrap().0, "bob");
+        assert_eq!(factory.built.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn test_concurrent_first_requests_build_one_agent() {
+        let (factory, registry) = registry();
+        let registry = Arc::new(registry);
+        let requests: Vec<_> = (0..8)
+            .map(|_| {
+                let registry = registry.clone();
+                tokio::spawn(async move { registry.get("alice").await.unwrap() })
+            })
+            .collect();
+        let mut agents = Vec::new();
+        for request in requests {
+            agents.push(request.await.unwrap());
+        }
+
+        assert_eq!(factory.built.load(Ordering::SeqCst), 1);
+        assert!(agents.iter().all(|agent| Arc::ptr_eq(agent, &agents[0])));
+    }
+
+    #[tokio::test]
+    async fn test_failed_builds_are_retried() {
+        let (factory, registry) = registry();
+        assert!(registry.get("broken").await.is_err());
+        assert!(registry.get("broken").await.is_err());
+        assert_eq!(factory.built.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn test_invalidated_users_get_a_new_agent() {
+        let (_, registry) = registry();
+        let before = registry.get("alice").await.unwrap();
+        assert!(registry.invalidate("alice"));
+        assert!(!registry.invalidate("alice"));
+
+        let after = registry.get("alice").await.unwrap();
+        assert_eq!((before.1, after.1), (1, 2));
+        // The request holding the old agent keeps it
+        assert_eq!(before.0, "alice");
+    }
+
+    #[tokio::test]
+    async fn test_least_recently_used_agent_is_dropped_past_capacity() {
+        let (_, registry) = registry();
+        let registry = registry.with_capacity(2);
+        let start = Instant::now();
+        registry.get_at("alice", start).await.unwrap();
+        registry.get_at("bob", start + Duration::from_secs(1)).await.unwrap();
+        registry.get_at("alice", start + Duration::from_secs(2)).await.unwrap();
+        registry.get_at("carol", start + Duration::from_secs(3)).await.unwrap();
+
+        assert_eq!(registry.len(), 2);
+        assert!(registry.contains("alice") && registry.contains("carol"));
+        assert!(!registry.contains("bob"));
+    }
+
+    #[tokio::test]
+    async fn test_idle_agents_are_dropped() {
+        let (factory, registry) = registry();
+        let registry = registry.with_idle_timeout(Duration::from_secs(60));
+        let start = Instant::now();
+        registry.get_at("alice", start).await.unwrap();
+        registry.get_at("bob", start + Duration::from_secs(50)).await.unwrap();
+
+        assert_eq!(registry.evict_idle(start + Duration::from_secs(59)), 0);
+        assert_eq!(registry.evict_idle(start + Duration::from_secs(60)), 1);
+        assert!(!registry.contains("alice") && registry.contains("bob"));
+
+        // Coming back after the timeout builds a new agent, and sweeps the other idle ones
+        let alice = registry.get_at("alice", start + Duration::from_secs(200)).await.unwrap();
+        assert_eq!(alice.1, 3);
+        assert_eq!(factory.built.load(Ordering::SeqCst), 3);
+        assert!(!registry.contains("bob"));
+    }
+}
@@ -1,60 +1,285 @@
+use async_trait::async_trait;
 use reqwest::Client;
-use serde::{Deserialize, Serialize};
-use anyhow::Result;
+use std::time::Duration;
 use crate::config::Config;
+use crate::db::MessageRole;
+use crate::llm::{self, ChatModel};
+use crate::offline;
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
-pub struct Message {
-    pub role: String,
-    pub content: String,
+pub use crate::llm::{CallOptions, Completion, END_OF_JSON, LlmError, Message, TokenUsage, json_payload};
+
+/// Default root URL of the Anthropic API
+pub const ANTHROPIC_BASE_URL: &str = "https://api.anthropic.com";
+
+/// Model used when LLM_MODEL doesn't name another
+pub const ANTHROPIC_MODEL: &str = "claude-3-opus-20240229";
+
+const ANTHROPIC_VERSION: &str = "2023-06-01";
+
+/// Thin client for the Anthropic messages endpoint
+pub struct AnthropicClient {
+    client: Client,
+    base_url: String,
+    api_key: String,
+    model: String,
 }
 
-/// Generate a response using the Anthropic API
-pub async fn generate_response(messages: &[Message]) -> Result<String> {
-    // Try to get the API key from the config first
-    let api_key = match Config::get_instance() {
-        Ok(config) => config.anthropic_api_key.clone(),
-        Err(_) => std::env::var("ANTHROPIC_API_KEY")?,
-    };
-    
-    let client = Client::new();
-    
-    // Format messages for the Anthropic API
-    let formatted_messages: Vec<serde_json::Value> = messages.iter()
-        .map(|msg| {
-            serde_json::json!({
-                "role": msg.role,
-                "content": msg.content
-            })
-        })
-        .collect();
+impl AnthropicClient {
+    /// Create a client for the public Anthropic API
+    pub fn new(api_key: &str) -> Self {
+        Self {
+            client: Client::new(),
+            base_url: ANTHROPIC_BASE_URL.to_string(),
+            api_key: api_key.to_string(),
+            model: ANTHROPIC_MODEL.to_string(),
+        }
+    }
     
-    let request_body = serde_json::json!({
-        "model": "claude-3-opus-20240229",
-        "max_tokens": 1024,
-        "messages": formatted_messages,
-        "system": "You are a helpful AI assistant."
-    });
+    /// Create a client from the application config, falling back to ANTHROPIC_API_KEY
+    pub fn from_config() -> Result<Self, LlmError> {
+        match Config::get_instance() {
+            Ok(config) => {
+                let client = Self::new(&config.anthropic_api_key).with_base_url(&config.anthropic_base_url);
+                Ok(match &config.llm_model {
+                    Some(model) => client.with_model(model),
+                    None => client,
+                })
+            },
+            Err(_) => {
+                let api_key = std::env::var("ANTHROPIC_API_KEY").map_err(|_| LlmError::ApiKeyNotFound)?;
+                Ok(Self::new(&api_key))
+            }
+        }
+    }
     
-    let response = client
-        .post("https://api.anthropic.com/v1/messages")
-        .header("x-api-key", api_key)
-        .header("anthropic-version", "2023-06-01")
-        .header("content-type", "application/json")
-        .json(&request_body)
-        .send()
-        .await?;
+    /// Send requests to a different root URL, e.g. a proxy or a mock server
+    pub fn with_base_url(mut self, base_url: &str) -> Self {
+        if !base_url.is_empty() {
+            self.base_url = base_url.trim_end_matches('/').to_string();
+        }
+        self
+    }
+    
+    /// Ask a different model than `ANTHROPIC_MODEL`
+    pub fn with_model(mut self, model: &str) -> Self {
+        if !model.is_empty() {
+            self.model = model.to_string();
+        }
+        self
+    }
+    
+    /// Fail requests that take longer than `timeout`
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.client = Client::builder()
+            .timeout(timeout)
+            .connect_timeout(timeout.min(Duration::from_secs(10)))
+            .build()
+            .unwrap_or_default();
+        self
+    }
+    
+    /// Send a conversation and return the text of the first content block
+    pub async fn complete(&self, system: &str, messages: &[Message], max_tokens: u32) -> Result<String, LlmError> {
+        self.complete_with_usage(system, messages, max_tokens)
+            .await
+            .map(|completion| completion.text)
+    }
+    
+    /// Like `complete`, also returning the token usage reported by the API
+    pub async fn complete_with_usage(&self, system: &str, messages: &[Message], max_tokens: u32) -> Result<Completion, LlmError> {
+        self.complete_with_options(system, messages, &CallOptions::new(max_tokens)).await
+    }
+    
+    /// Ask for a JSON reply and return just the JSON, see `ChatModel::generate_json`
+    pub async fn complete_json(&self, system: &str, messages: &[Message], max_tokens: u32) -> Result<String, LlmError> {
+        self.generate_json(system, messages, max_tokens).await
+    }
+    
+    /// Send a conversation with per-call settings such as stop sequences
+    pub async fn complete_with_options(&self, system: &str, messages: &[Message], options: &CallOptions) -> Result<Completion, LlmError> {
+        let response = self.send(&request_body(&self.model, system, messages, options)).await?;
         
-    if !response.status().is_success() {
-        return Err(anyhow::anyhow!("API request failed with status: {}", response.status()));
+        let body = response.text().await?;
+        let response_json: serde_json::Value = serde_json::from_str(&body)
+            .map_err(|e| LlmError::InvalidResponse(format!("Malformed JSON: {}", e)))?;
+        
+        // An empty content array is an empty reply, callers decide whether to ask again
+        let text = response_json["content"]
+            .as_array()
+            .map(|blocks| blocks.iter().filter_map(|block| block["text"].as_str()).collect())
+            .ok_or_else(|| LlmError::InvalidResponse("Failed to extract response text".to_string()))?;
+        
+        // Usage is informational, a response without it still counts
+        let usage = serde_json::from_value(response_json["usage"].clone()).unwrap_or_default();
+        let stop_sequence = response_json["stop_sequence"].as_str().map(str::to_string);
+        let request_id = response_json["id"].as_str().map(str::to_string);
+        
+        Ok(Completion { text, usage, stop_sequence, request_id })
     }
     
-    let response_json: serde_json::Value = response.json().await?;
+    async fn send(&self, request_body: &serde_json::Value) -> Result<reqwest::Response, LlmError> {
+        if offline::is_offline() {
+            return Err(LlmError::Offline);
+        }
+        
+        let response = self.client
+            .post(format!("{}/v1/messages", self.base_url))
+            .header("x-api-key", &self.api_key)
+            .header("anthropic-version", ANTHROPIC_VERSION)
+            .header("content-type", "application/json")
+            .json(request_body)
+            .send()
+            .await
+            .inspect(|_| offline::note_network_success())
+            .inspect_err(|e| {
+                offline::note_network_error(e);
+            })?;
+        
+        llm::check_response(response).await
+    }
+}
+
+#[async_trait]
+impl ChatModel for AnthropicClient {
+    async fn generate(&self, system: &str, messages: &[Message], options: &CallOptions) -> Result<Completion, LlmError> {
+        self.complete_with_options(system, messages, options).await
+    }
     
-    // Extract the response text
-    let response_text = response_json["content"][0]["text"]
-        .as_str()
-        .ok_or_else(|| anyhow::anyhow!("Failed to extract response text"))?;
+    /// Streams the text deltas of the messages endpoint, usage comes from the start and final events
+    async fn generate_streaming(
+        &self,
+        system: &str,
+        messages: &[Message],
+        options: &CallOptions,
+        on_text: &mut (dyn for<'t> FnMut(&'t str) + Send),
+    ) -> Result<Completion, LlmError> {
+        let mut body = request_body(&self.model, system, messages, options);
+        body["stream"] = serde_json::json!(true);
+        let response = self.send(&body).await?;
         
-    Ok(response_text.to_string())
+        let mut completion = Completion { text: String::new(), usage: TokenUsage::default(), stop_sequence: None, request_id: None };
+        llm::read_events(response, |payload| {
+            let event: serde_json::Value = serde_json::from_str(payload)
+                .map_err(|e| LlmError::InvalidResponse(format!("Malformed stream event: {}", e)))?;
+            match event["type"].as_str() {
+                Some("message_start") => {
+                    completion.usage = serde_json::from_value(event["message"]["usage"].clone()).unwrap_or_default();
+                    completion.request_id = event["message"]["id"].as_str().map(str::to_string);
+                },
+                Some("content_block_delta") => {
+                    if let Some(text) = event["delta"]["text"].as_str() {
+                        completion.text.push_str(text);
+                        on_text(text);
+                    }
+                },
+                Some("message_delta") => {
+                    if let Some(output_tokens) = event["usage"]["output_tokens"].as_u64() {
+                        completion.usage.output_tokens = output_tokens as u32;
+                    }
+                    completion.stop_sequence = event["delta"]["stop_sequence"].as_str().map(str::to_string);
+                },
+                Some("message_stop") => return Ok(false),
+                Some("error") => {
+                    let message = event["error"]["message"].as_str().unwrap_or("stream error").to_string();
+                    return Err(LlmError::InvalidResponse(message));
+                },
+                _ => {},
+            }
+            Ok(true)
+        })
+        .await?;
+        
+        Ok(completion)
+    }
+    
+    fn supports_tools(&self) -> bool {
+        true
+    }
+    
+    fn model_name(&self) -> Option<&str> {
+        Some(&self.model)
+    }
+}
+
+/// Build a messages request body
+/// The API only takes user and assistant turns: system messages are folded into the system prompt
+/// and tool output is sent back as a user turn
+fn request_body(model: &str, system: &str, messages: &[Message], options: &CallOptions) -> serde_json::Value {
+    let mut system_prompt = system.to_string();
+    let mut turns = Vec::with_capacity(messages.len());
+    
+    for message in messages {
+        let role = match message.role {
+            MessageRole::User | MessageRole::Tool => "user",
+            MessageRole::Assistant => "assistant",
+            MessageRole::System => {
+                if !system_prompt.is_empty() {
+                    system_prompt.push_str("\n\n");
+                }
+                system_prompt.push_str(&message.content);
+                continue;
+            }
+        };
+        turns.push(serde_json::json!({
+            "role": role,
+            "content": message.content
+        }));
+    }
+    
+    let mut body = serde_json::json!({
+        "model": model,
+        "max_tokens": options.max_tokens,
+        "messages": turns,
+        "system": system_prompt
+    });
+    if !options.stop_sequences.is_empty() {
+        body["stop_sequences"] = serde_json::json!(options.stop_sequences);
+    }
+    body
+}
+
+/// Generate a response using the configured model
+pub async fn generate_response(messages: &[Message]) -> Result<String, LlmError> {
+    llm::configured(None)?
+        .generate("You are a helpful AI assistant.", messages, &CallOptions::new(1024))
+        .await
+        .map(|completion| completion.text)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_request_body_maps_roles() {
+        let message = |role, content: &str| Message { role, content: content.to_string() };
+        let messages = [
+            message(MessageRole::System, "Answer in one line."),
+            message(MessageRole::User, "Price of AERO?"),
+            message(MessageRole::Tool, "aerodrome-finance: 1.21"),
+            message(MessageRole::Assistant, "About $1.21."),
+        ];
+
+        let body = request_body(ANTHROPIC_MODEL, "Be brief.", &messages, &CallOptions::new(256));
+        assert_eq!(body["model"], ANTHROPIC_MODEL);
+        assert_eq!(body["system"], "Be brief.\n\nAnswer in one line.");
+        assert_eq!(body["max_tokens"], 256);
+        assert!(body.get("stop_sequences").is_none());
+        assert_eq!(
+            body["messages"],
+            serde_json::json!([
+                { "role": "user", "content": "Price of AERO?" },
+                { "role": "user", "content": "aerodrome-finance: 1.21" },
+                { "role": "assistant", "content": "About $1.21." }
+            ])
+        );
+    }
+
+    #[test]
+    fn test_request_body_sends_stop_sequences() {
+        let messages = [Message { role: MessageRole::User, content: "Classify this".to_string() }];
+        let options = CallOptions::new(512).with_stop_sequence(END_OF_JSON);
+        let body = request_body(ANTHROPIC_MODEL, "", &messages, &options);
+        assert_eq!(body["stop_sequences"], serde_json::json!(["###END###"]));
+    }
 }
@@ -0,0 +1,259 @@
+use crate::db;
+use chrono::{DateTime, NaiveDate, Utc};
+use serde::Serialize;
+use sqlx::{Pool, Postgres};
+use std::collections::HashMap;
+use std::sync::{Mutex, OnceLock};
+use thiserror::Error;
+use tracing::warn;
+
+/// Daily call limits of one upstream, unset ones aren't enforced
+///
+/// Past the soft limit calls still go through and a warning is logged; at the hard limit they
+/// are skipped until midnight UTC.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct BudgetLimits {
+    pub soft: Option<u64>,
+    pub hard: Option<u64>,
+}
+
+/// The upstream's hard limit for the day is reached
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+#[error("today's budget of {limit} {upstream} calls is used up, they resume at midnight UTC")]
+pub struct BudgetExhausted {
+    pub upstream: String,
+    pub limit: u64,
+}
+
+/// Calls made to one upstream on one UTC day, driven by the times it's given
+#[derive(Debug, Clone)]
+pub struct DailyUsage {
+    limits: BudgetLimits,
+    day: Option<NaiveDate>,
+    calls: u64,
+    /// Calls refused today for being over the hard limit
+    skipped: u64,
+}
+
+impl DailyUsage {
+    pub fn new(limits: BudgetLimits) -> Self {
+        Self { limits, day: None, calls: 0, skipped: 0 }
+    }
+
+    /// Start the counts over when `now` is on a later day than the last call
+    fn roll(&mut self, now: DateTime<Utc>) {
+        let today = now.date_naive();
+        if self.day != Some(today) {
+            self.day = Some(today);
+            self.calls = 0;
+            self.skipped = 0;
+        }
+    }
+
+    /// Whether a call at `now` fits under the hard limit, a refused one is counted as skipped
+    pub fn admit(&mut self, now: DateTime<Utc>) -> bool {
+        self.roll(now);
+        match self.limits.hard {
+            Some(hard) if self.calls >= hard => {
+                self.skipped += 1;
+                false
+            },
+            _ => true,
+        }
+    }
+
+    /// Count a call made at `now`, true when it is the one that reached the soft limit
+    pub fn record(&mut self, now: DateTime<Utc>) -> bool {
+        self.roll(now);
+        self.calls += 1;
+        self.limits.soft == Some(self.calls)
+    }
+
+    /// Take `calls` made earlier on the day of `now`, e.g. by the previous run, as already made
+    pub fn restore(&mut self, now: DateTime<Utc>, calls: u64) {
+        self.roll(now);
+        self.calls = self.calls.max(calls);
+    }
+
+    pub fn limits(&self) -> BudgetLimits {
+        self.limits
+    }
+
+    /// Counts for the day of `now`, zero when nothing was called on it yet
+    pub fn snapshot(&self, upstream: &str, now: DateTime<Utc>) -> UsageSnapshot {
+        let today = now.date_naive();
+        let (calls, skipped) = if self.day == Some(today) { (self.calls, self.skipped) } else { (0, 0) };
+        UsageSnapshot {
+            upstream: upstream.to_string(),
+            day: today,
+            calls,
+            skipped,
+            soft_limit: self.limits.soft,
+            hard_limit: self.limits.hard,
+        }
+    }
+}
+
+/// One upstream's calls today, for `/stats usage`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct UsageSnapshot {
+    pub upstream: String,
+    pub day: NaiveDate,
+    pub calls: u64,
+    pub skipped: u64,
+    pub soft_limit: Option<u64>,
+    pub hard_limit: Option<u64>,
+}
+
+impl UsageSnapshot {
+    /// Whether calls are skipped for the rest of the day
+    pub fn exhausted(&self) -> bool {
+        self.hard_limit.is_some_and(|hard| self.calls >= hard)
+    }
+
+    pub fn over_soft_limit(&self) -> bool {
+        self.soft_limit.is_some_and(|soft| self.calls >= soft)
+    }
+}
+
+/// The report shown by `/stats usage`, one line per upstream
+pub fn render_usage(snapshots: &[UsageSnapshot]) -> String {
+    let Some(first) = snapshots.first() else {
+        return "No API calls made yet.".to_string();
+    };
+    let mut lines = vec![format!("API calls today ({} UTC):", first.day)];
+    for usage in snapshots {
+        let limits = match (usage.soft_limit, usage.hard_limit) {
+            (None, None) => "no limit".to_string(),
+            (soft, hard) => [soft.map(|soft| format!("soft limit {}", soft)), hard.map(|hard| format!("hard limit {}", hard))]
+                .into_iter()
+                .flatten()
+                .collect::<Vec<_>>()
+                .join(", "),
+        };
+        let mut line = format!("- {}: {} calls ({})", usage.upstream, usage.calls, limits);
+        if usage.exhausted() {
+            line.push_str(&format!(", paused until midnight UTC ({} skipped)", usage.skipped));
+        } else if usage.over_soft_limit() {
+            line.push_str(", over the soft limit");
+        }
+        lines.push(line);
+    }
+    lines.join("\n")
+}
+
+/// Pool calls are recorded in once `start` is called
+static POOL: OnceLock<Pool<Postgres>> = OnceLock::new();
+
+/// Counts of the day the table had for each upstream when `start` read it
+static RESTORED: Mutex<Option<(NaiveDate, HashMap<String, u64>)>> = Mutex::new(None);
+
+/// Count today's calls from what `api_usage` recorded, then record calls in it from now on
+///
+/// Limits are checked per process, starting from the calls earlier runs made today.
+pub async fn start(pool: &Pool<Postgres>) {
+    let now = Utc::now();
+    match db::get_api_usage(pool, now.date_naive()).await {
+        Ok(usage) => {
+            let counts: HashMap<String, u64> = usage.into_iter().map(|(upstream, calls)| (upstream, calls.max(0) as u64)).collect();
+            // Breakers created from now on read the counts when they're made, existing ones get them here
+            *RESTORED.lock().unwrap() = Some((now.date_naive(), counts.clone()));
+            crate::circuit_breaker::restore_usage(&counts, now);
+        },
+        Err(e) => warn!("Could not read today's API usage: {}", e),
+    }
+    let _ = POOL.set(pool.clone());
+}
+
+/// Calls `upstream` made on the day of `now` before this process started
+pub fn restored_calls(upstream: &str, now: DateTime<Utc>) -> u64 {
+    match RESTORED.lock().unwrap().as_ref() {
+        Some((day, counts)) if *day == now.date_naive() => counts.get(upstream).copied().unwrap_or(0),
+        _ => 0,
+    }
+}
+
+/// Add a call to `upstream` on `day` to `api_usage` in the background, once `start` was called
+pub fn record(upstream: &'static str, day: NaiveDate) {
+    let (Some(pool), Ok(runtime)) = (POOL.get(), tokio::runtime::Handle::try_current()) else {
+        return;
+    };
+    runtime.spawn(async move {
+        if let Err(e) = db::record_api_call(pool, upstream, day).await {
+            warn!("Could not record a {} call: {}", upstream, e);
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn at(day: u32, hour: u32) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2025, 10, day, hour, 0, 0).unwrap()
+    }
+
+    #[test]
+    fn test_calls_are_counted_and_the_soft_limit_reported_once() {
+        let mut usage = DailyUsage::new(BudgetLimits { soft: Some(2), hard: None });
+        assert!(usage.admit(at(13, 9)) && !usage.record(at(13, 9)));
+        assert!(usage.admit(at(13, 10)) && usage.record(at(13, 10)));
+        // Past the soft limit calls still go through, without another warning
+        assert!(usage.admit(at(13, 11)) && !usage.record(at(13, 11)));
+
+        let snapshot = usage.snapshot("exa", at(13, 12));
+        assert_eq!((snapshot.calls, snapshot.skipped), (3, 0));
+        assert!(snapshot.over_soft_limit() && !snapshot.exhausted());
+    }
+
+    #[test]
+    fn test_hard_limit_skips_calls_until_midnight_utc() {
+        let mut usage = DailyUsage::new(BudgetLimits { soft: None, hard: Some(2) });
+        for hour in [9, 10] {
+            assert!(usage.admit(at(13, hour)));
+            usage.record(at(13, hour));
+        }
+        assert!(!usage.admit(at(13, 11)));
+        assert!(!usage.admit(at(13, 23)));
+        assert_eq!(usage.snapshot("coingecko", at(13, 23)).skipped, 2);
+
+        // A new UTC day starts the counts over
+        assert!(usage.admit(at(14, 0)));
+        assert_eq!(usage.snapshot("coingecko", at(14, 0)).calls, 0);
+        // And a day nothing was called on shows nothing
+        assert_eq!(usage.snapshot("coingecko", at(15, 8)).calls, 0);
+    }
+
+    #[test]
+    fn test_restored_calls_count_toward_the_limit() {
+        let mut usage = DailyUsage::new(BudgetLimits { soft: None, hard: Some(5) });
+        usage.restore(at(13, 8), 5);
+        assert!(!usage.admit(at(13, 9)));
+        assert!(usage.admit(at(14, 9)));
+    }
+
+    #[test]
+    fn test_render_usage() {
+        let snapshot = |upstream: &str, calls, soft_limit, hard_limit, skipped| UsageSnapshot {
+            upstream: upstream.to_string(),
+            day: NaiveDate::from_ymd_opt(2025, 10, 13).unwrap(),
+            calls,
+            skipped,
+            soft_limit,
+            hard_limit,
+        };
+        assert_eq!(render_usage(&[]), "No API calls made yet.");
+        assert_eq!(
+            render_usage(&[
+                snapshot("coingecko", 120, Some(100), Some(500), 0),
+                snapshot("exa", 50, None, Some(50), 3),
+                snapshot("defillama", 4, None, None, 0),
+            ]),
+            "API calls today (2025-10-13 UTC):\n\
+             - coingecko: 120 calls (soft limit 100, hard limit 500), over the soft limit\n\
+             - exa: 50 calls (hard limit 50), paused until midnight UTC (3 skipped)\n\
+             - defillama: 4 calls (no limit)"
+        );
+    }
+}
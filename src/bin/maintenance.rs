@@ -0,0 +1,105 @@
+//! Database housekeeping for cron, without the chat
+//!
+//! Exit codes: 0 when the task ran, 1 when it failed, 2 for invalid arguments and 3 when the
+//! database can't be reached.
+
+use agent_friend::db;
+use agent_friend::maintenance::{self, DEFAULT_KNOWLEDGE_MAX_AGE_DAYS, Task};
+use agent_friend::retention::{DEFAULT_RETENTION_DAYS, ExtractiveSummarizer, LlmSummarizer, Summarizer};
+use clap::{Parser, Subcommand};
+use std::process::ExitCode;
+
+const EXIT_FAILURE: u8 = 1;
+const EXIT_USAGE: u8 = 2;
+const EXIT_DATABASE: u8 = 3;
+
+/// Nova database maintenance
+#[derive(Debug, Parser)]
+#[command(version, about)]
+struct Cli {
+    /// Show what would change without writing anything
+    #[arg(long, global = true)]
+    dry_run: bool,
+
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// Delete knowledge that hasn't been updated for a while
+    PruneKnowledge {
+        /// Age in days or weeks, e.g. 90d or 12w
+        #[arg(long, default_value_t = format!("{}d", DEFAULT_KNOWLEDGE_MAX_AGE_DAYS))]
+        older_than: String,
+    },
+    /// Summarize old messages and move them to the archive
+    ArchiveMessages {
+        /// Age in days or weeks, e.g. 90d or 12w
+        #[arg(long, default_value_t = format!("{}d", DEFAULT_RETENTION_DAYS))]
+        older_than: String,
+
+        /// Build summaries from the messages instead of asking the model
+        #[arg(long)]
+        extractive: bool,
+    },
+    /// Merge knowledge entries of a user with the same content
+    DedupeKnowledge,
+    /// Recompute knowledge embeddings
+    ReindexEmbeddings,
+    /// Row counts and sizes of the tables that grow with use
+    Stats,
+}
+
+#[tokio::main]
+async fn main() -> ExitCode {
+    let cli = Cli::parse();
+    dotenv::dotenv().ok();
+
+    let mut extractive_only = false;
+    let task = match cli.command {
+        Command::PruneKnowledge { older_than } => maintenance::parse_age_days(&older_than)
+            .map(|older_than_days| Task::PruneKnowledge { older_than_days }),
+        Command::ArchiveMessages { older_than, extractive } => {
+            extractive_only = extractive;
+            maintenance::parse_age_days(&older_than).map(|older_than_days| Task::ArchiveMessages { older_than_days })
+        },
+        Command::DedupeKnowledge => Ok(Task::DedupeKnowledge),
+        Command::ReindexEmbeddings => Ok(Task::ReindexEmbeddings),
+        Command::Stats => Ok(Task::Stats),
+    };
+    let task = match task {
+        Ok(task) => task,
+        Err(e) => {
+            eprintln!("{}", e);
+            return ExitCode::from(EXIT_USAGE);
+        },
+    };
+
+    let pool = match db::init_db_pool().await {
+        Ok(pool) => pool,
+        Err(e) => {
+            eprintln!("Database unavailable: {}", e);
+            return ExitCode::from(EXIT_DATABASE);
+        },
+    };
+
+    let summarizer: Box<dyn Summarizer> = if extractive_only || cli.dry_run {
+        Box::new(ExtractiveSummarizer)
+    } else {
+        Box::new(LlmSummarizer::from_config())
+    };
+    let result = maintenance::run(pool, task, cli.dry_run, summarizer.as_ref()).await;
+    db::close_db_pool().await;
+
+    match result {
+        Ok(report) => {
+            print!("{}", report);
+            ExitCode::SUCCESS
+        },
+        Err(e) => {
+            eprintln!("{} failed: {}", task.name(), e);
+            ExitCode::from(EXIT_FAILURE)
+        },
+    }
+}
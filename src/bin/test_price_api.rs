@@ -14,9 +14,8 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         Err(e) => {
             println!("Error fetching Ethereum price: {}", e);
             
-            // Try with API key if available
-            if let Ok(_api_key) = env::var("COINGECKO_API_KEY") {
-                println!("API key found, trying with API key...");
+            if env::var("COINGECKO_API_KEY").is_ok() {
+                println!("COINGECKO_API_KEY was sent with the request; set COINGECKO_PRO=true if it is a pro plan key.");
             } else {
                 println!("No API key found. Consider setting COINGECKO_API_KEY environment variable.");
                 println!("CoinGecko now requires an API key for most endpoints.");
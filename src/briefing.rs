@@ -0,0 +1,622 @@
+use crate::llm::{self, CallOptions, ChatModel, LlmError};
+use crate::db::{self, DbError, Knowledge, MessageRole, Notification};
+use crate::exa_api::{ExaApiClient, ExaApiError};
+use crate::notifications;
+use crate::offline;
+use crate::price_fetcher::{self, PriceError};
+use crate::price_format::format_price;
+use crate::timezone::UserTimezone;
+use crate::unlocks::{self, UnlockEvent};
+use async_trait::async_trait;
+use chrono::{Duration, NaiveDate, NaiveDateTime, Utc};
+use sqlx::{Pool, Postgres};
+use thiserror::Error;
+use tracing::warn;
+
+/// Knowledge tag shared by every stored briefing
+pub const BRIEFING_TAG: &str = "briefing";
+
+/// Headlines listed in the news section
+const HEADLINE_COUNT: usize = 5;
+
+const COMMENTARY_PROMPT: &str = "You are Nova, a crypto investment advisor. Write a short commentary (3-4 sentences) \
+on the user's morning briefing below: what stands out in their portfolio, the price moves and the news. \
+Don't repeat the numbers line by line and don't give financial guarantees.";
+
+/// Errors raised while building a briefing
+#[derive(Debug, Error)]
+pub enum BriefingError {
+    #[error("Database error: {0}")]
+    Database(#[from] DbError),
+
+    #[error("Price API error: {0}")]
+    Price(#[from] PriceError),
+
+    #[error("Exa API error: {0}")]
+    Exa(#[from] ExaApiError),
+
+    #[error("LLM API error: {0}")]
+    Llm(#[from] LlmError),
+
+    #[error("{0}")]
+    Unavailable(String),
+}
+
+/// A watched coin with its current price and the price 24 hours earlier
+#[derive(Debug, Clone, PartialEq)]
+pub struct CoinMove {
+    pub coin_id: String,
+    /// Zero for coins on the watchlist that aren't held
+    pub amount: f64,
+    pub price_usd: Option<f64>,
+    pub price_24h_ago: Option<f64>,
+}
+
+impl CoinMove {
+    pub fn change_24h_pct(&self) -> Option<f64> {
+        match (self.price_usd, self.price_24h_ago) {
+            (Some(now), Some(then)) if then > 0.0 => Some((now - then) / then * 100.0),
+            _ => None,
+        }
+    }
+}
+
+/// A news article listed in the briefing
+#[derive(Debug, Clone, PartialEq)]
+pub struct Headline {
+    pub title: String,
+    pub url: String,
+    pub published_date: Option<String>,
+}
+
+/// Where the briefing sections come from
+#[async_trait]
+pub trait BriefingSources: Send + Sync {
+    /// Holdings, then watchlist coins not held, with their current and 24h-old prices
+    async fn coin_moves(&self, user_id: i32) -> Result<Vec<CoinMove>, BriefingError>;
+
+    /// Alerts fired since `since`
+    async fn alerts(&self, user_id: i32, since: NaiveDateTime) -> Result<Vec<Notification>, BriefingError>;
+
+    /// Recent headlines, about the given coins when there are any
+    async fn headlines(&self, coin_ids: &[String]) -> Result<Vec<Headline>, BriefingError>;
+
+    /// Token unlocks of the given coins in the week from `today`
+    async fn unlocks(&self, user_id: i32, coin_ids: &[String], today: NaiveDate) -> Result<Vec<UnlockEvent>, BriefingError>;
+
+    /// A short commentary on the rest of the briefing
+    async fn commentary(&self, draft: &str) -> Result<String, BriefingError>;
+}
+
+/// Assembled sections, each holding either its content or why it is unavailable
+#[derive(Debug, Clone)]
+pub struct Briefing {
+    pub date: NaiveDate,
+    /// Where the user is, alerts are stamped in it
+    pub timezone: UserTimezone,
+    pub coin_moves: Result<Vec<CoinMove>, String>,
+    pub alerts: Result<Vec<Notification>, String>,
+    pub unlocks: Result<Vec<UnlockEvent>, String>,
+    pub headlines: Result<Vec<Headline>, String>,
+    pub commentary: Result<String, String>,
+}
+
+/// Collect every section, a failing source only blanks its own section
+pub async fn assemble(
+    sources: &dyn BriefingSources,
+    user_id: i32,
+    date: NaiveDate,
+    now: NaiveDateTime,
+    timezone: UserTimezone,
+) -> Briefing {
+    let coin_moves = sources.coin_moves(user_id).await.map_err(|e| e.to_string());
+    let alerts = sources.alerts(user_id, now - Duration::hours(24)).await.map_err(|e| e.to_string());
+
+    let coin_ids: Vec<String> = match &coin_moves {
+        Ok(moves) => moves.iter().map(|m| m.coin_id.clone()).collect(),
+        Err(_) => Vec::new(),
+    };
+    let unlocks = sources.unlocks(user_id, &coin_ids, date).await.map_err(|e| e.to_string());
+    let headlines = sources.headlines(&coin_ids).await.map_err(|e| e.to_string());
+
+    let mut briefing = Briefing {
+        date,
+        timezone,
+        coin_moves,
+        alerts,
+        unlocks,
+        headlines,
+        commentary: Err("not generated yet".to_string()),
+    };
+    briefing.commentary = sources.commentary(&render_sections(&briefing)).await.map_err(|e| e.to_string());
+    briefing
+}
+
+/// Render the briefing as markdown
+pub fn render(briefing: &Briefing) -> String {
+    let mut output = render_sections(briefing);
+    output.push_str("\n## Commentary\n");
+    match &briefing.commentary {
+        Ok(commentary) => output.push_str(commentary.trim()),
+        Err(reason) => output.push_str(&unavailable(reason)),
+    }
+    output.push('\n');
+    output
+}
+
+/// Everything except the commentary, which is written from this draft
+fn render_sections(briefing: &Briefing) -> String {
+    let mut output = format!("# Morning briefing for {}\n", briefing.date.format("%Y-%m-%d"));
+
+    output.push_str("\n## Portfolio\n");
+    match &briefing.coin_moves {
+        Ok(moves) => output.push_str(&render_portfolio(moves)),
+        Err(reason) => output.push_str(&unavailable(reason)),
+    }
+
+    output.push_str("\n\n## Prices\n");
+    match &briefing.coin_moves {
+        Ok(moves) if moves.is_empty() => output.push_str("No watched coins."),
+        Ok(moves) => {
+            let lines: Vec<String> = moves.iter().map(render_coin_move).collect();
+            output.push_str(&lines.join("\n"));
+        },
+        Err(reason) => output.push_str(&unavailable(reason)),
+    }
+
+    output.push_str("\n\n## Alerts\n");
+    match &briefing.alerts {
+        Ok(alerts) if alerts.is_empty() => output.push_str("No alerts in the last 24 hours."),
+        Ok(alerts) => {
+            let zone = briefing.timezone.name();
+            let lines: Vec<String> = alerts
+                .iter()
+                .map(|alert| render_alert(alert, briefing.timezone.local(alert.created_at), Some(&zone)))
+                .collect();
+            output.push_str(&lines.join("\n"));
+        },
+        Err(reason) => output.push_str(&unavailable(reason)),
+    }
+
+    output.push_str("\n\n## Unlocks\n");
+    match &briefing.unlocks {
+        Ok(events) if events.is_empty() => output.push_str("No token unlocks this week."),
+        Ok(events) => {
+            let lines: Vec<String> = events.iter().map(|event| format!("- {}", event.describe())).collect();
+            output.push_str(&lines.join("\n"));
+        },
+        Err(reason) => output.push_str(&unavailable(reason)),
+    }
+
+    output.push_str("\n\n## News\n");
+    match &briefing.headlines {
+        Ok(headlines) if headlines.is_empty() => output.push_str("No headlines found."),
+        Ok(headlines) => {
+            let lines: Vec<String> = headlines
+                .iter()
+                .map(|headline| match &headline.published_date {
+                    Some(date) => format!("- [{}]({}) ({})", headline.title, headline.url, date.get(..10).unwrap_or(date)),
+                    None => format!("- [{}]({})", headline.title, headline.url),
+                })
+                .collect();
+            output.push_str(&lines.join("\n"));
+        },
+        Err(reason) => output.push_str(&unavailable(reason)),
+    }
+
+    output.push('\n');
+    output
+}
+
+fn render_portfolio(moves: &[CoinMove]) -> String {
+    let moves: Vec<&CoinMove> = moves.iter().filter(|m| m.amount > 0.0).collect();
+    if moves.is_empty() {
+        return "No holdings yet. Add one with /portfolio set <coin> <amount>.".to_string();
+    }
+
+    let total: f64 = moves.iter().filter_map(|m| m.price_usd.map(|p| p * m.amount)).sum();
+    let mut output = format!("Total value: ${:.2}", total);
+
+    // Compare only the holdings priced at both ends of the window
+    let (now, then) = moves
+        .iter()
+        .filter_map(|m| Some((m.price_usd? * m.amount, m.price_24h_ago? * m.amount)))
+        .fold((0.0, 0.0), |(now, then), (n, t)| (now + n, then + t));
+    if then > 0.0 {
+        let change = now - then;
+        output.push_str(&format!(
+            " ({:+.2}%, {}${:.2} in 24h)",
+            change / then * 100.0,
+            if change < 0.0 { "-" } else { "+" },
+            change.abs()
+        ));
+    } else {
+        output.push_str(" (no 24h comparison available)");
+    }
+
+    let unpriced = moves.iter().filter(|m| m.price_usd.is_none()).count();
+    if unpriced > 0 {
+        output.push_str(&format!("\nExcluding {} unpriced holding(s).", unpriced));
+    }
+
+    output
+}
+
+fn render_coin_move(coin: &CoinMove) -> String {
+    match (coin.price_usd, coin.change_24h_pct()) {
+        (Some(price), Some(change)) => format!("- {}: {} ({:+.2}% 24h)", coin.coin_id, format_price(price), change),
+        (Some(price), None) => format!("- {}: {} (24h change unavailable)", coin.coin_id, format_price(price)),
+        (None, _) => format!("- {}: no price available", coin.coin_id),
+    }
+}
+
+/// An alert as a list item stamped with `at`, the time it fired in the caller's timezone, named by `zone` if given
+pub fn render_alert(alert: &Notification, at: NaiveDateTime, zone: Option<&str>) -> String {
+    let stamp = at.format("%Y-%m-%d %H:%M");
+    match zone {
+        Some(zone) => format!("- [{} {}] {}", stamp, zone, alert.message),
+        None => format!("- [{}] {}", stamp, alert.message),
+    }
+}
+
+fn unavailable(reason: &str) -> String {
+    format!("_Unavailable: {}_", reason)
+}
+
+/// Knowledge source id of the briefing for a date
+pub fn source_id(date: NaiveDate) -> String {
+    format!("{}-{}", BRIEFING_TAG, date.format("%Y-%m-%d"))
+}
+
+/// The stored briefing for a date, if it was generated
+pub async fn get_stored(pool: &Pool<Postgres>, user_id: i32, date: NaiveDate) -> Result<Option<Knowledge>, DbError> {
+    db::get_knowledge_by_source_id(pool, user_id, &source_id(date)).await
+}
+
+/// Build, render and store the briefing for a date in the user's timezone
+pub async fn generate(
+    pool: &Pool<Postgres>,
+    sources: &dyn BriefingSources,
+    user_id: i32,
+    date: NaiveDate,
+    timezone: UserTimezone,
+) -> Result<String, BriefingError> {
+    let briefing = assemble(sources, user_id, date, Utc::now().naive_utc(), timezone).await;
+    let markdown = render(&briefing);
+
+    let tags = vec![BRIEFING_TAG.to_string(), date.format("%Y-%m-%d").to_string()];
+    db::upsert_knowledge(pool, user_id, &source_id(date), &markdown, &tags).await?;
+
+    Ok(markdown)
+}
+
+/// Today's briefing where the user is, generated now if it doesn't exist yet
+pub async fn today_or_generate(
+    pool: &Pool<Postgres>,
+    sources: &dyn BriefingSources,
+    user_id: i32,
+    timezone: UserTimezone,
+) -> Result<String, BriefingError> {
+    let today = Utc::now().with_timezone(&timezone).date_naive();
+    match get_stored(pool, user_id, today).await? {
+        Some(stored) => Ok(stored.content),
+        None => generate(pool, sources, user_id, today, timezone).await,
+    }
+}
+
+/// Sources backed by the price watcher's history, notifications, Exa and the configured model
+pub struct LiveSources {
+    pool: Pool<Postgres>,
+    exa: Option<ExaApiClient>,
+    model: Option<Box<dyn ChatModel>>,
+}
+
+impl LiveSources {
+    pub fn new(pool: Pool<Postgres>) -> Self {
+        let exa = ExaApiClient::new()
+            .inspect_err(|e| warn!("Briefing news disabled: {}", e))
+            .ok();
+        let model = llm::configured(None)
+            .inspect_err(|e| warn!("Briefing commentary disabled: {}", e))
+            .ok();
+        Self { pool, exa, model }
+    }
+}
+
+#[async_trait]
+impl BriefingSources for LiveSources {
+    async fn coin_moves(&self, user_id: i32) -> Result<Vec<CoinMove>, BriefingError> {
+        let mut coins: Vec<(String, f64)> = db::get_holdings_by_user_id(&self.pool, user_id)
+            .await?
+            .into_iter()
+            .map(|holding| (holding.coin_id, holding.amount))
+            .collect();
+        for entry in db::get_watchlist(&self.pool, user_id).await? {
+            if !coins.iter().any(|(coin_id, _)| *coin_id == entry.coin_id) {
+                coins.push((entry.coin_id, 0.0));
+            }
+        }
+        if coins.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        // Prefer live quotes, the price history fills in when they are unavailable
+        let coin_ids: Vec<&str> = coins.iter().map(|(coin_id, _)| coin_id.as_str()).collect();
+        let live_prices = if offline::is_offline() {
+            Default::default()
+        } else {
+            price_fetcher::fetch_multiple_coin_prices(&coin_ids).await.unwrap_or_else(|e| {
+                warn!("Briefing falls back to stored prices: {}", e);
+                Default::default()
+            })
+        };
+
+        let day_ago = Utc::now().naive_utc() - Duration::hours(24);
+        let mut moves = Vec::with_capacity(coins.len());
+        for (coin_id, amount) in coins {
+            let price_usd = match live_prices.get(&coin_id) {
+                Some(price) => {
+                    db::save_price_point(&self.pool, &coin_id, *price).await?;
+                    Some(*price)
+                },
+                None => db::get_latest_price_point(&self.pool, &coin_id).await?.map(|p| p.price_usd),
+            };
+            let price_24h_ago = db::get_price_point_before(&self.pool, &coin_id, day_ago)
+                .await?
+                .map(|p| p.price_usd);
+
+            moves.push(CoinMove {
+                coin_id,
+                amount,
+                price_usd,
+                price_24h_ago,
+            });
+        }
+
+        Ok(moves)
+    }
+
+    async fn alerts(&self, user_id: i32, since: NaiveDateTime) -> Result<Vec<Notification>, BriefingError> {
+        Ok(db::get_notifications_since(&self.pool, user_id, notifications::KIND_PRICE_ALERT, since).await?)
+    }
+
+    async fn unlocks(&self, user_id: i32, coin_ids: &[String], today: NaiveDate) -> Result<Vec<UnlockEvent>, BriefingError> {
+        let events: Vec<UnlockEvent> = db::get_knowledge_by_tag(&self.pool, user_id, unlocks::UNLOCK_TAG)
+            .await?
+            .iter()
+            .filter_map(UnlockEvent::from_knowledge)
+            .filter(|event| coin_ids.contains(&event.coin_id))
+            .collect();
+        Ok(unlocks::upcoming(&events, today, unlocks::BRIEFING_WINDOW_DAYS))
+    }
+
+    async fn headlines(&self, coin_ids: &[String]) -> Result<Vec<Headline>, BriefingError> {
+        let exa = self.exa.as_ref().ok_or_else(|| BriefingError::Unavailable("Exa API key not configured".to_string()))?;
+
+        let query = if coin_ids.is_empty() {
+            "crypto market news today".to_string()
+        } else {
+            format!("crypto market news today {}", coin_ids.join(" "))
+        };
+        let response = exa.search(&query, HEADLINE_COUNT, None).await?;
+
+        Ok(response
+            .results
+            .into_iter()
+            .map(|result| Headline {
+                title: result.title,
+                url: result.url,
+                published_date: result.published_date,
+            })
+            .collect())
+    }
+
+    async fn commentary(&self, draft: &str) -> Result<String, BriefingError> {
+        let model = self.model.as_ref().ok_or_else(|| BriefingError::Unavailable("LLM API key not configured".to_string()))?;
+
+        let messages = [llm::Message {
+            role: MessageRole::User,
+            content: draft.to_string(),
+        }];
+        Ok(model.generate(COMMENTARY_PROMPT, &messages, &CallOptions::new(400)).await?.text)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::testing::test_pool;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    /// Sources returning canned sections, or failing the ones listed in `failing`
+    #[derive(Default)]
+    struct MockSources {
+        failing: Vec<&'static str>,
+        calls: AtomicUsize,
+    }
+
+    impl MockSources {
+        fn failing(sections: &[&'static str]) -> Self {
+            Self { failing: sections.to_vec(), ..Default::default() }
+        }
+
+        fn check(&self, section: &str) -> Result<(), BriefingError> {
+            self.calls.fetch_add(1, Ordering::SeqCst);
+            if self.failing.contains(&section) {
+                Err(BriefingError::Unavailable(format!("{} source is down", section)))
+            } else {
+                Ok(())
+            }
+        }
+    }
+
+    #[async_trait]
+    impl BriefingSources for MockSources {
+        async fn coin_moves(&self, _user_id: i32) -> Result<Vec<CoinMove>, BriefingError> {
+            self.check("prices")?;
+            Ok(vec![
+                CoinMove { coin_id: "bitcoin".to_string(), amount: 0.5, price_usd: Some(66_000.0), price_24h_ago: Some(60_000.0) },
+                CoinMove { coin_id: "ethereum".to_string(), amount: 2.0, price_usd: Some(2_500.0), price_24h_ago: None },
+                CoinMove { coin_id: "obscure".to_string(), amount: 10.0, price_usd: None, price_24h_ago: None },
+            ])
+        }
+
+        async fn alerts(&self, _user_id: i32, _since: NaiveDateTime) -> Result<Vec<Notification>, BriefingError> {
+            self.check("alerts")?;
+            Ok(vec![Notification {
+                id: 1,
+                user_id: 1,
+                kind: notifications::KIND_PRICE_ALERT.to_string(),
+                message: "bitcoin moved +10.0% from $60000.00 to $66000.00".to_string(),
+                created_at: NaiveDate::from_ymd_opt(2025, 9, 24).unwrap().and_hms_opt(6, 15, 0).unwrap(),
+                read_at: None,
+            }])
+        }
+
+        async fn unlocks(&self, _user_id: i32, coin_ids: &[String], today: NaiveDate) -> Result<Vec<UnlockEvent>, BriefingError> {
+            self.check("unlocks")?;
+            let events = vec![
+                UnlockEvent {
+                    coin_id: "ethereum".to_string(),
+                    date: today + Duration::days(3),
+                    percent_of_supply: 1.5,
+                    tokens: None,
+                    note: Some("foundation".to_string()),
+                },
+                UnlockEvent { coin_id: "ethereum".to_string(), date: today + Duration::days(30), percent_of_supply: 4.0, tokens: None, note: None },
+                UnlockEvent { coin_id: "arbitrum".to_string(), date: today + Duration::days(1), percent_of_supply: 2.1, tokens: None, note: None },
+            ];
+            let watched: Vec<UnlockEvent> = events.into_iter().filter(|event| coin_ids.contains(&event.coin_id)).collect();
+            Ok(unlocks::upcoming(&watched, today, unlocks::BRIEFING_WINDOW_DAYS))
+        }
+
+        async fn headlines(&self, coin_ids: &[String]) -> Result<Vec<Headline>, BriefingError> {
+            self.check("news")?;
+            Ok(vec![Headline {
+                title: format!("Markets rally, led by {}", coin_ids.first().map(String::as_str).unwrap_or("crypto")),
+                url: "https://news.example/rally".to_string(),
+                published_date: Some("2025-09-23T21:00:00.000Z".to_string()),
+            }])
+        }
+
+        async fn commentary(&self, draft: &str) -> Result<String, BriefingError> {
+            self.check("commentary")?;
+            assert!(draft.contains("## Prices") && !draft.contains("## Commentary"));
+            Ok("Bitcoin carried the portfolio overnight.".to_string())
+        }
+    }
+
+    fn date() -> NaiveDate {
+        NaiveDate::from_ymd_opt(2025, 9, 24).unwrap()
+    }
+
+    fn now() -> NaiveDateTime {
+        date().and_hms_opt(7, 0, 0).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_renders_every_section() {
+        let briefing = assemble(&MockSources::default(), 1, date(), now(), UserTimezone::default()).await;
+        let output = render(&briefing);
+
+        assert!(output.starts_with("# Morning briefing for 2025-09-24\n"));
+        // 0.5 BTC went from $30000 to $33000, ETH has no 24h price and is left out of the change
+        assert!(output.contains("Total value: $38000.00 (+10.00%, +$3000.00 in 24h)\nExcluding 1 unpriced holding(s)."));
+        assert!(output.contains("- bitcoin: $66000.00 (+10.00% 24h)"));
+        assert!(output.contains("- ethereum: $2500.00 (24h change unavailable)"));
+        assert!(output.contains("- obscure: no price available"));
+        assert!(output.contains("- [2025-09-24 06:15 UTC] bitcoin moved +10.0%"));
+
+        // Alerts are stamped where the user is
+        let tokyo = UserTimezone::Named(chrono_tz::Asia::Tokyo);
+        let output = render(&assemble(&MockSources::default(), 1, date(), now(), tokyo).await);
+        assert!(output.contains("- [2025-09-24 15:15 Asia/Tokyo] bitcoin moved +10.0%"));
+        // Only the watched coin's unlock inside the week is listed
+        assert!(output.contains("## Unlocks\n- ethereum unlock on 2025-09-27: 1.5% of supply (foundation)\n\n## News"));
+        assert!(output.contains("- [Markets rally, led by bitcoin](https://news.example/rally) (2025-09-23)"));
+        assert!(output.ends_with("## Commentary\nBitcoin carried the portfolio overnight.\n"));
+    }
+
+    #[tokio::test]
+    async fn test_failing_sources_only_blank_their_sections() {
+        let briefing = assemble(&MockSources::failing(&["prices", "news"]), 1, date(), now(), UserTimezone::default()).await;
+        let output = render(&briefing);
+
+        assert!(output.contains("## Portfolio\n_Unavailable: prices source is down_"));
+        assert!(output.contains("## Prices\n_Unavailable: prices source is down_"));
+        assert!(output.contains("## News\n_Unavailable: news source is down_"));
+        assert!(output.contains("bitcoin moved +10.0%"));
+        assert!(output.contains("Bitcoin carried the portfolio overnight."));
+
+        let output = render(&assemble(&MockSources::failing(&["alerts", "commentary"]), 1, date(), now(), UserTimezone::default()).await);
+        assert!(output.contains("## Alerts\n_Unavailable: alerts source is down_"));
+        assert!(output.ends_with("## Commentary\n_Unavailable: commentary source is down_\n"));
+        assert!(output.contains("- bitcoin: $66000.00"));
+    }
+
+    #[test]
+    fn test_empty_portfolio() {
+        let briefing = Briefing {
+            date: date(),
+            timezone: UserTimezone::default(),
+            coin_moves: Ok(Vec::new()),
+            alerts: Ok(Vec::new()),
+            unlocks: Ok(Vec::new()),
+            headlines: Ok(Vec::new()),
+            commentary: Ok("Quiet night.".to_string()),
+        };
+
+        let output = render(&briefing);
+        assert!(output.contains("## Portfolio\nNo holdings yet."));
+        assert!(output.contains("## Prices\nNo watched coins."));
+        assert!(output.contains("## Alerts\nNo alerts in the last 24 hours."));
+        assert!(output.contains("## Unlocks\nNo token unlocks this week."));
+        assert!(output.contains("## News\nNo headlines found."));
+    }
+
+    #[test]
+    fn test_watchlist_coins_are_priced_but_not_valued() {
+        let briefing = Briefing {
+            date: date(),
+            timezone: UserTimezone::default(),
+            coin_moves: Ok(vec![CoinMove {
+                coin_id: "solana".to_string(),
+                amount: 0.0,
+                price_usd: Some(150.0),
+                price_24h_ago: Some(120.0),
+            }]),
+            alerts: Ok(Vec::new()),
+            unlocks: Ok(Vec::new()),
+            headlines: Ok(Vec::new()),
+            commentary: Ok("Quiet night.".to_string()),
+        };
+
+        let output = render(&briefing);
+        assert!(output.contains("## Portfolio\nNo holdings yet."));
+        assert!(output.contains("- solana: $150.00 (+25.00% 24h)"));
+    }
+
+    #[tokio::test]
+    async fn test_briefing_is_stored_once_per_day() {
+        let Some(pool) = test_pool().await else { return };
+        let sources = MockSources::default();
+
+        let first = today_or_generate(&pool, &sources, 1, UserTimezone::default()).await.unwrap();
+        let calls = sources.calls.load(Ordering::SeqCst);
+        let second = today_or_generate(&pool, &sources, 1, UserTimezone::default()).await.unwrap();
+        assert_eq!(first, second);
+        assert_eq!(sources.calls.load(Ordering::SeqCst), calls);
+
+        let today = Utc::now().date_naive();
+        let stored = get_stored(&pool, 1, today).await.unwrap().unwrap();
+        assert_eq!(stored.tags, vec![BRIEFING_TAG.to_string(), today.format("%Y-%m-%d").to_string()]);
+        let tagged = db::get_knowledge_by_tag(&pool, 1, BRIEFING_TAG).await.unwrap();
+        assert_eq!(tagged.len(), 1);
+
+        // Regenerating replaces the stored entry
+        generate(&pool, &MockSources::failing(&["news"]), 1, today, UserTimezone::default()).await.unwrap();
+        let stored = get_stored(&pool, 1, today).await.unwrap().unwrap();
+        assert!(stored.content.contains("_Unavailable: news source is down_"));
+        assert_eq!(db::get_knowledge_by_tag(&pool, 1, BRIEFING_TAG).await.unwrap().len(), 1);
+    }
+}
@@ -0,0 +1,227 @@
+use crate::price_fetcher::{CategoryCoin, CoinCategory};
+use crate::price_format::{format_compact, format_price};
+use crate::render::Table;
+use regex::Regex;
+use std::sync::OnceLock;
+
+/// Coins listed when the question doesn't say how many
+pub const DEFAULT_RANKING_SIZE: usize = 10;
+
+/// Most coins listed in one answer
+pub const MAX_RANKING_SIZE: usize = 25;
+
+/// Coin names are cut to this many columns in the ranking
+const NAME_COLUMN_WIDTH: usize = 28;
+
+/// Words saying what's ranked rather than which theme, left out when matching
+const FILLER_WORDS: &[&str] = &[
+    "token", "tokens", "coin", "coins", "crypto", "cryptos", "project", "projects", "play", "plays", "sector",
+    "narrative", "ecosystem", "the", "of", "in",
+];
+
+/// Words that make "the best ..." about something other than a theme
+const NOT_THEMES: &[&str] = &[
+    "my", "our", "your", "way", "ways", "time", "times", "strategy", "strategies", "portfolio", "to", "for", "price",
+    "prices", "performer", "performers", "gainer", "gainers", "loser", "losers",
+];
+
+/// Shorthands CoinGecko's names don't contain
+const PHRASINGS: &[(&str, &str)] = &[("memecoin", "meme"), ("l2", "layer 2"), ("l1", "layer 1"), ("gamefi", "gaming")];
+
+/// A thematic screening question, e.g. "top 5 layer 2s by market cap"
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CategoryQuery {
+    /// The theme as the user wrote it, lowercased, e.g. "layer 2s"
+    pub phrase: String,
+    /// How many coins to list
+    pub count: usize,
+}
+
+/// The theme and size of a screening question, None for other questions
+///
+/// "show me the top AI tokens", "best RWA coins" and "top 5 layer 2s by market cap" all count;
+/// "the best way to stake ETH" doesn't, it names neither coins nor a market cap ranking.
+pub fn detect_category_query(message: &str) -> Option<CategoryQuery> {
+    static QUERY: OnceLock<Regex> = OnceLock::new();
+    let query = QUERY.get_or_init(|| {
+        Regex::new(
+            r"(?i)\b(?:top|best|biggest|largest|leading)\s+(?:(?P<count>\d{1,3})\s+)?(?P<phrase>[a-z0-9][a-z0-9 .-]*?)\s*(?P<noun>\b(?:tokens|coins|cryptos|projects)\b)?\s*(?P<mcap>\bby\s+(?:market\s+cap(?:italization)?|mcap)\b)?\s*(?:right\s+now|today|this\s+(?:week|month|year))?\s*[?.!]*\s*$",
+        )
+        .unwrap()
+    });
+
+    let captures = query.captures(message.trim())?;
+    let phrase = captures.name("phrase")?.as_str().trim().to_lowercase();
+    let words: Vec<&str> = phrase.split_whitespace().collect();
+    if words.is_empty() || words.len() > 4 || words.iter().any(|word| NOT_THEMES.contains(word)) {
+        return None;
+    }
+    // Without "tokens" or "by market cap", only a plural theme like "layer 2s" or "memecoins" counts
+    let ranks_coins = captures.name("noun").is_some() || captures.name("mcap").is_some();
+    if !ranks_coins && (words.len() > 2 || !phrase.ends_with('s')) {
+        return None;
+    }
+
+    let count = captures
+        .name("count")
+        .and_then(|count| count.as_str().parse().ok())
+        .unwrap_or(DEFAULT_RANKING_SIZE)
+        .clamp(1, MAX_RANKING_SIZE);
+    Some(CategoryQuery { phrase, count })
+}
+
+/// The category the user's phrasing means, None when none fits
+///
+/// An acronym or whole name wins ("ai" is "Artificial Intelligence (AI)", not "AI Agents"),
+/// then the category with the fewest other words containing every word of the phrase.
+pub fn match_category<'a>(phrase: &str, categories: &'a [CoinCategory]) -> Option<&'a CoinCategory> {
+    let mut wanted = theme_words(phrase).join(" ");
+    for (shorthand, expansion) in PHRASINGS {
+        if wanted == *shorthand {
+            wanted = expansion.to_string();
+        }
+    }
+    let wanted: Vec<String> = wanted.split_whitespace().map(str::to_string).collect();
+    if wanted.is_empty() {
+        return None;
+    }
+
+    categories
+        .iter()
+        .filter_map(|category| match_score(&wanted, category).map(|score| (score, category)))
+        // The first listed category wins a tie
+        .min_by_key(|(score, _)| *score)
+        .map(|(_, category)| category)
+}
+
+/// Lower is better, None when the category doesn't fit the phrase
+fn match_score(wanted: &[String], category: &CoinCategory) -> Option<usize> {
+    let (name, acronym) = match category.name.split_once('(') {
+        Some((name, acronym)) => (name, Some(acronym.trim_end_matches(')'))),
+        None => (category.name.as_str(), None),
+    };
+    let name_words = theme_words(name);
+    if name_words == wanted || acronym.is_some_and(|acronym| theme_words(acronym) == wanted) {
+        return Some(0);
+    }
+
+    let mut words = name_words;
+    words.extend(theme_words(&category.category_id.replace('-', " ")));
+    words.sort();
+    words.dedup();
+    if wanted.iter().all(|word| words.contains(word)) {
+        Some(1 + words.len() - wanted.len())
+    } else {
+        None
+    }
+}
+
+/// Lowercase singular words of a theme without filler, "Layer 2s tokens" gives ["layer", "2"]
+fn theme_words(text: &str) -> Vec<String> {
+    text.to_lowercase()
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty() && !FILLER_WORDS.contains(word))
+        .map(singular)
+        .collect()
+}
+
+fn singular(word: &str) -> String {
+    let digits_plural = word.len() > 1 && word.ends_with('s') && word[..word.len() - 1].chars().all(|c| c.is_ascii_digit());
+    let plural = word.len() > 3 && word.ends_with('s') && !word.ends_with("ss");
+    if digits_plural || plural {
+        word[..word.len() - 1].to_string()
+    } else {
+        word.to_string()
+    }
+}
+
+/// The coins of a category as a table, largest first
+pub fn render_ranking(category: &CoinCategory, coins: &[CategoryCoin]) -> String {
+    if coins.is_empty() {
+        return format!("CoinGecko doesn't list any coins in {} yet.", category.name);
+    }
+
+    let mut table = Table::new(["#", "Coin", "Price", "7d", "Market cap"]).max_width(NAME_COLUMN_WIDTH);
+    for (position, coin) in coins.iter().enumerate() {
+        table.push_row([
+            (position + 1).to_string(),
+            format!("{} ({})", coin.name, coin.symbol.to_uppercase()),
+            coin.price_usd.map(format_price).unwrap_or_else(|| "n/a".to_string()),
+            coin.change_7d_pct.map(|change| format!("{:+.1}%", change)).unwrap_or_else(|| "n/a".to_string()),
+            coin.market_cap_usd.map(|cap| format!("${}", format_compact(cap))).unwrap_or_else(|| "n/a".to_string()),
+        ]);
+    }
+    format!(
+        "Top {} {} coins by market cap (CoinGecko):\n\n{}",
+        coins.len(),
+        category.name,
+        table.render()
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn category(category_id: &str, name: &str) -> CoinCategory {
+        CoinCategory { category_id: category_id.to_string(), name: name.to_string() }
+    }
+
+    fn query(phrase: &str, count: usize) -> Option<CategoryQuery> {
+        Some(CategoryQuery { phrase: phrase.to_string(), count })
+    }
+
+    #[test]
+    fn test_detect_category_query() {
+        assert_eq!(detect_category_query("show me the top AI tokens"), query("ai", 10));
+        assert_eq!(detect_category_query("best RWA tokens?"), query("rwa", 10));
+        assert_eq!(detect_category_query("top 5 layer 2s by market cap"), query("layer 2s", 5));
+        assert_eq!(detect_category_query("What are the biggest DeFi coins right now?"), query("defi", 10));
+        assert_eq!(detect_category_query("top memecoins"), query("memecoins", 10));
+        assert_eq!(detect_category_query("top 100 gaming tokens"), query("gaming", MAX_RANKING_SIZE));
+
+        assert_eq!(detect_category_query("what's the best way to stake ETH?"), None);
+        assert_eq!(detect_category_query("best strategies for a bear market"), None);
+        assert_eq!(detect_category_query("top performers in my portfolio"), None);
+        assert_eq!(detect_category_query("is bitcoin the best store of value"), None);
+    }
+
+    #[test]
+    fn test_singular() {
+        assert_eq!(theme_words("Layer 2s tokens"), ["layer", "2"]);
+        assert_eq!(theme_words("Stablecoins"), ["stablecoin"]);
+        assert_eq!(theme_words("Gas"), ["gas"]);
+    }
+
+    #[test]
+    fn test_render_ranking() {
+        let coins = vec![
+            CategoryCoin {
+                id: "ondo-finance".to_string(),
+                symbol: "ondo".to_string(),
+                name: "Ondo".to_string(),
+                market_cap_rank: Some(44),
+                price_usd: Some(0.9812),
+                market_cap_usd: Some(3_100_000_000.0),
+                change_7d_pct: Some(-4.26),
+            },
+            CategoryCoin {
+                id: "tiny-rwa".to_string(),
+                symbol: "trwa".to_string(),
+                name: "A Rather Long Real World Asset Name".to_string(),
+                market_cap_rank: None,
+                price_usd: None,
+                market_cap_usd: None,
+                change_7d_pct: None,
+            },
+        ];
+        let rwa = category("real-world-assets-rwa", "Real World Assets (RWA)");
+
+        let output = render_ranking(&rwa, &coins);
+        assert!(output.starts_with("Top 2 Real World Assets (RWA) coins by market cap (CoinGecko):\n\n"));
+        assert!(output.contains("1  Ondo (ONDO)"));
+        assert!(output.contains("$0.9812  -4.3%  $3.10B"));
+        assert!(output.contains("2  A Rather Long Real World As…      n/a    n/a  n/a"));
+        assert_eq!(render_ranking(&rwa, &[]), "CoinGecko doesn't list any coins in Real World Assets (RWA) yet.");
+    }
+}
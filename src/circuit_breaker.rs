@@ -0,0 +1,490 @@
+use crate::api_budget::{self, BudgetExhausted, BudgetLimits, DailyUsage, UsageSnapshot};
+use crate::clock::{Clock, SystemClock};
+use crate::config::Config;
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::{Duration, Instant};
+use tracing::warn;
+
+/// Consecutive failures that open a breaker unless BREAKER_FAILURE_THRESHOLD says otherwise
+pub const DEFAULT_FAILURE_THRESHOLD: u32 = 5;
+
+/// How long an open breaker skips calls unless BREAKER_COOL_DOWN_SECS says otherwise
+pub const DEFAULT_COOL_DOWN: Duration = Duration::from_secs(30);
+
+/// Name of the breaker shared by CoinGecko clients built from the config
+pub const COINGECKO: &str = "coingecko";
+
+/// Name of the breaker shared by Exa clients built from the config
+pub const EXA: &str = "exa";
+
+/// When a breaker opens and for how long
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BreakerSettings {
+    pub failure_threshold: u32,
+    pub cool_down: Duration,
+}
+
+impl Default for BreakerSettings {
+    fn default() -> Self {
+        Self { failure_threshold: DEFAULT_FAILURE_THRESHOLD, cool_down: DEFAULT_COOL_DOWN }
+    }
+}
+
+/// Whether calls to an upstream go through
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BreakerState {
+    /// Calls go through, failures are counted
+    Closed,
+    /// Calls are skipped until the cool-down is over
+    Open,
+    /// One call goes through to see whether the upstream is back
+    HalfOpen,
+}
+
+impl BreakerState {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            BreakerState::Closed => "closed",
+            BreakerState::Open => "open",
+            BreakerState::HalfOpen => "half-open",
+        }
+    }
+}
+
+/// The breaker's state machine, driven by the times it's given
+///
+/// Closed counts consecutive failures and opens at the threshold. Open rejects calls until the
+/// cool-down is over, then lets one probe through as half-open: its success closes the breaker,
+/// its failure opens it for another cool-down. A probe that never reports back, e.g. because its
+/// caller gave up on it, is replaced by a new one after a cool-down.
+#[derive(Debug, Clone)]
+pub struct Breaker {
+    settings: BreakerSettings,
+    state: BreakerState,
+    consecutive_failures: u32,
+    /// When the breaker last opened, or when the half-open probe started
+    since: Option<Instant>,
+    trips: u64,
+    short_circuited: u64,
+}
+
+impl Breaker {
+    pub fn new(settings: BreakerSettings) -> Self {
+        Self {
+            settings: BreakerSettings { failure_threshold: settings.failure_threshold.max(1), ..settings },
+            state: BreakerState::Closed,
+            consecutive_failures: 0,
+            since: None,
+            trips: 0,
+            short_circuited: 0,
+        }
+    }
+
+    /// Whether a call may go through at `now`
+    pub fn admit(&mut self, now: Instant) -> bool {
+        let cooled_down = self.since.is_none_or(|since| now.saturating_duration_since(since) >= self.settings.cool_down);
+        match self.state {
+            BreakerState::Closed => true,
+            BreakerState::Open | BreakerState::HalfOpen if cooled_down => {
+                self.state = BreakerState::HalfOpen;
+                self.since = Some(now);
+                true
+            },
+            BreakerState::Open | BreakerState::HalfOpen => {
+                self.short_circuited += 1;
+                false
+            },
+        }
+    }
+
+    /// An admitted call reached the upstream
+    pub fn record_success(&mut self) {
+        self.state = BreakerState::Closed;
+        self.consecutive_failures = 0;
+        self.since = None;
+    }
+
+    /// An admitted call failed at `now`
+    pub fn record_failure(&mut self, now: Instant) {
+        self.consecutive_failures = self.consecutive_failures.saturating_add(1);
+        let trips = match self.state {
+            BreakerState::Closed => self.consecutive_failures >= self.settings.failure_threshold,
+            BreakerState::HalfOpen => true,
+            // A call admitted before the breaker opened doesn't restart the cool-down
+            BreakerState::Open => false,
+        };
+        if trips {
+            self.state = BreakerState::Open;
+            self.since = Some(now);
+            self.trips += 1;
+        }
+    }
+
+    pub fn state(&self) -> BreakerState {
+        self.state
+    }
+
+    /// Time left before an open breaker lets a probe through
+    pub fn retry_in(&self, now: Instant) -> Option<Duration> {
+        match (self.state, self.since) {
+            (BreakerState::Open, Some(since)) => Some(self.settings.cool_down.saturating_sub(now.saturating_duration_since(since))),
+            _ => None,
+        }
+    }
+}
+
+/// State and counters of one breaker, for `/health` and `GET /healthz`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct BreakerSnapshot {
+    pub name: String,
+    pub state: BreakerState,
+    pub consecutive_failures: u32,
+    /// Times the breaker opened
+    pub trips: u64,
+    /// Calls skipped while it was open
+    pub short_circuited: u64,
+    /// Seconds before an open breaker probes again
+    pub retry_in_secs: Option<u64>,
+}
+
+/// Why a call to an upstream was skipped
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Refused {
+    /// The breaker is open after repeated failures
+    CircuitOpen,
+    /// The upstream's daily budget is used up
+    OverBudget(BudgetExhausted),
+}
+
+/// A breaker around one upstream, shared by the clients calling it, that also keeps its daily call budget
+pub struct CircuitBreaker {
+    name: &'static str,
+    breaker: Mutex<Breaker>,
+    usage: Mutex<DailyUsage>,
+    /// What day it is for the budget, which starts over at midnight UTC
+    clock: Arc<dyn Clock>,
+}
+
+impl std::fmt::Debug for CircuitBreaker {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("CircuitBreaker").field("name", &self.name).field("breaker", &self.breaker).finish_non_exhaustive()
+    }
+}
+
+impl CircuitBreaker {
+    pub fn new(name: &'static str, settings: BreakerSettings) -> Self {
+        Self {
+            name,
+            breaker: Mutex::new(Breaker::new(settings)),
+            usage: Mutex::new(DailyUsage::new(BudgetLimits::default())),
+            clock: Arc::new(SystemClock),
+        }
+    }
+
+    /// Limit the upstream's calls per UTC day
+    pub fn with_budget(self, limits: BudgetLimits) -> Self {
+        Self { usage: Mutex::new(DailyUsage::new(limits)), ..self }
+    }
+
+    /// Tell the day from `clock` instead of the system clock
+    pub fn with_clock(self, clock: Arc<dyn Clock>) -> Self {
+        Self { clock, ..self }
+    }
+
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    /// A permit to call the upstream, refused while the breaker is open or the day's budget is used up
+    ///
+    /// Every admitted call counts toward the budget, whether it succeeds or not.
+    pub fn try_call(&self) -> Result<Permit<'_>, Refused> {
+        let now = self.clock.now();
+        let mut usage = self.usage.lock().unwrap();
+        if !usage.admit(now) {
+            let limit = usage.limits().hard.unwrap_or_default();
+            return Err(Refused::OverBudget(BudgetExhausted { upstream: self.name.to_string(), limit }));
+        }
+        if !self.breaker.lock().unwrap().admit(Instant::now()) {
+            return Err(Refused::CircuitOpen);
+        }
+        if usage.record(now) {
+            let soft = usage.limits().soft.unwrap_or_default();
+            warn!("{} reached its soft budget of {} calls for {}", self.name, soft, now.date_naive());
+        }
+        api_budget::record(self.name, now.date_naive());
+        Ok(Permit { breaker: self, resolved: false })
+    }
+
+    /// Take `calls` made earlier today as made, e.g. by the previous run
+    pub fn restore_usage(&self, calls: u64, now: DateTime<Utc>) {
+        self.usage.lock().unwrap().restore(now, calls);
+    }
+
+    /// The upstream's calls today
+    pub fn usage(&self) -> UsageSnapshot {
+        self.usage.lock().unwrap().snapshot(self.name, self.clock.now())
+    }
+
+    pub fn state(&self) -> BreakerState {
+        self.breaker.lock().unwrap().state()
+    }
+
+    pub fn snapshot(&self) -> BreakerSnapshot {
+        let breaker = self.breaker.lock().unwrap();
+        BreakerSnapshot {
+            name: self.name.to_string(),
+            state: breaker.state,
+            consecutive_failures: breaker.consecutive_failures,
+            trips: breaker.trips,
+            short_circuited: breaker.short_circuited,
+            retry_in_secs: breaker.retry_in(Instant::now()).map(|wait| wait.as_secs()),
+        }
+    }
+}
+
+/// One admitted call, which counts as failed unless it reports otherwise
+///
+/// A call dropped before it finished, e.g. cut off by the turn's latency budget, counts as a
+/// failure, so an upstream that hangs trips the breaker like one that errors.
+#[must_use]
+pub struct Permit<'a> {
+    breaker: &'a CircuitBreaker,
+    resolved: bool,
+}
+
+impl Permit<'_> {
+    pub fn succeeded(mut self) {
+        self.resolved = true;
+        self.breaker.breaker.lock().unwrap().record_success();
+    }
+
+    pub fn failed(mut self) {
+        self.resolved = true;
+        self.breaker.breaker.lock().unwrap().record_failure(Instant::now());
+    }
+
+    /// Record whether the upstream answered
+    pub fn finish(self, reached: bool) {
+        if reached { self.succeeded() } else { self.failed() }
+    }
+}
+
+impl Drop for Permit<'_> {
+    fn drop(&mut self) {
+        if !self.resolved
+            && let Ok(mut breaker) = self.breaker.breaker.lock()
+        {
+            breaker.record_failure(Instant::now());
+        }
+    }
+}
+
+/// Whether a response status means the upstream is failing, as opposed to rejecting the request
+pub fn is_outage(status: reqwest::StatusCode) -> bool {
+    status.is_server_error()
+}
+
+fn registry() -> &'static Mutex<HashMap<&'static str, Arc<CircuitBreaker>>> {
+    static REGISTRY: OnceLock<Mutex<HashMap<&'static str, Arc<CircuitBreaker>>>> = OnceLock::new();
+    REGISTRY.get_or_init(|| Mutex::new(HashMap::new()))
+}
+
+/// The breaker every client of an upstream built from the config shares, created on first use
+pub fn shared(name: &'static str) -> Arc<CircuitBreaker> {
+    registry()
+        .lock()
+        .unwrap()
+        .entry(name)
+        .or_insert_with(|| {
+            let settings = Config::get_instance().map(|config| config.circuit_breaker).unwrap_or_default();
+            let limits = Config::get_instance()
+                .ok()
+                .and_then(|config| config.api_budgets.get(name).copied())
+                .unwrap_or_default();
+            let breaker = CircuitBreaker::new(name, settings).with_budget(limits);
+            let now = Utc::now();
+            breaker.restore_usage(api_budget::restored_calls(name, now), now);
+            Arc::new(breaker)
+        })
+        .clone()
+}
+
+/// Give the shared breakers the calls `counts` says each upstream made earlier on the day of `now`
+pub fn restore_usage(counts: &HashMap<String, u64>, now: DateTime<Utc>) {
+    for breaker in registry().lock().unwrap().values() {
+        if let Some(calls) = counts.get(breaker.name) {
+            breaker.restore_usage(*calls, now);
+        }
+    }
+}
+
+/// Today's calls to CoinGecko, Exa and any other upstream with a shared breaker, by name
+pub fn usage_snapshots() -> Vec<UsageSnapshot> {
+    shared(COINGECKO);
+    shared(EXA);
+    let mut snapshots: Vec<UsageSnapshot> = registry().lock().unwrap().values().map(|breaker| breaker.usage()).collect();
+    snapshots.sort_by(|a, b| a.upstream.cmp(&b.upstream));
+    snapshots
+}
+
+/// Snapshots of the shared breakers, by name
+pub fn snapshots() -> Vec<BreakerSnapshot> {
+    let mut snapshots: Vec<BreakerSnapshot> = registry().lock().unwrap().values().map(|breaker| breaker.snapshot()).collect();
+    snapshots.sort_by(|a, b| a.name.cmp(&b.name));
+    snapshots
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn breaker() -> Breaker {
+        Breaker::new(BreakerSettings { failure_threshold: 3, cool_down: Duration::from_secs(30) })
+    }
+
+    #[test]
+    fn test_opens_after_threshold_consecutive_failures() {
+        let now = Instant::now();
+        let mut breaker = breaker();
+        breaker.record_failure(now);
+        breaker.record_failure(now);
+        // A success in between starts the count over
+        breaker.record_success();
+        breaker.record_failure(now);
+        breaker.record_failure(now);
+        assert_eq!(breaker.state(), BreakerState::Closed);
+        assert!(breaker.admit(now));
+
+        breaker.record_failure(now);
+        assert_eq!(breaker.state(), BreakerState::Open);
+        assert_eq!(breaker.trips, 1);
+        assert!(!breaker.admit(now + Duration::from_secs(1)));
+        assert!(!breaker.admit(now + Duration::from_secs(29)));
+        assert_eq!(breaker.short_circuited, 2);
+        assert_eq!(breaker.retry_in(now + Duration::from_secs(10)), Some(Duration::from_secs(20)));
+    }
+
+    #[test]
+    fn test_cool_down_expiry_lets_one_probe_through() {
+        let now = Instant::now();
+        let mut breaker = breaker();
+        (0..3).for_each(|_| breaker.record_failure(now));
+
+        let later = now + Duration::from_secs(30);
+        assert!(breaker.admit(later));
+        assert_eq!(breaker.state(), BreakerState::HalfOpen);
+        assert_eq!(breaker.retry_in(later), None);
+        // Other calls wait for the probe
+        assert!(!breaker.admit(later + Duration::from_secs(1)));
+
+        // A probe that never reports back is replaced after a cool-down
+        assert!(breaker.admit(later + Duration::from_secs(30)));
+    }
+
+    #[test]
+    fn test_probe_success_closes() {
+        let now = Instant::now();
+        let mut breaker = breaker();
+        (0..3).for_each(|_| breaker.record_failure(now));
+        assert!(breaker.admit(now + Duration::from_secs(31)));
+
+        breaker.record_success();
+        assert_eq!(breaker.state(), BreakerState::Closed);
+        assert_eq!(breaker.consecutive_failures, 0);
+        assert!(breaker.admit(now + Duration::from_secs(32)));
+    }
+
+    #[test]
+    fn test_probe_failure_reopens_for_another_cool_down() {
+        let now = Instant::now();
+        let mut breaker = breaker();
+        (0..3).for_each(|_| breaker.record_failure(now));
+        let probed = now + Duration::from_secs(31);
+        assert!(breaker.admit(probed));
+
+        breaker.record_failure(probed);
+        assert_eq!(breaker.state(), BreakerState::Open);
+        assert_eq!(breaker.trips, 2);
+        assert!(!breaker.admit(probed + Duration::from_secs(29)));
+        assert!(breaker.admit(probed + Duration::from_secs(30)));
+    }
+
+    #[test]
+    fn test_late_failures_do_not_extend_the_cool_down() {
+        let now = Instant::now();
+        let mut breaker = breaker();
+        (0..3).for_each(|_| breaker.record_failure(now));
+        breaker.record_failure(now + Duration::from_secs(20));
+        assert_eq!(breaker.trips, 1);
+        assert!(breaker.admit(now + Duration::from_secs(30)));
+    }
+
+    #[test]
+    fn test_dropped_permits_count_as_failures() {
+        let breaker = CircuitBreaker::new("test", BreakerSettings { failure_threshold: 2, cool_down: Duration::from_secs(60) });
+        breaker.try_call().unwrap().finish(true);
+        drop(breaker.try_call().unwrap());
+        assert_eq!(breaker.snapshot().consecutive_failures, 1);
+
+        breaker.try_call().unwrap().failed();
+        assert!(matches!(breaker.try_call(), Err(Refused::CircuitOpen)));
+        let snapshot = breaker.snapshot();
+        assert_eq!(snapshot.state, BreakerState::Open);
+        assert_eq!(snapshot.trips, 1);
+        assert_eq!(snapshot.short_circuited, 1);
+        assert!(snapshot.retry_in_secs.is_some_and(|secs| secs <= 60));
+    }
+
+    /// A clock the test moves forward
+    struct TestClock(Mutex<DateTime<Utc>>);
+
+    impl Clock for TestClock {
+        fn now(&self) -> DateTime<Utc> {
+            *self.0.lock().unwrap()
+        }
+    }
+
+    #[test]
+    fn test_budget_counts_calls_and_skips_them_until_midnight_utc() {
+        use chrono::TimeZone;
+        let clock = Arc::new(TestClock(Mutex::new(Utc.with_ymd_and_hms(2025, 10, 13, 22, 0, 0).unwrap())));
+        let breaker = CircuitBreaker::new("exa", BreakerSettings::default())
+            .with_budget(BudgetLimits { soft: Some(1), hard: Some(2) })
+            .with_clock(clock.clone());
+
+        // Failed calls count toward the budget like successful ones
+        breaker.try_call().unwrap().succeeded();
+        breaker.try_call().unwrap().failed();
+        let refused = breaker.try_call().err().unwrap();
+        assert_eq!(refused, Refused::OverBudget(BudgetExhausted { upstream: "exa".to_string(), limit: 2 }));
+        let Refused::OverBudget(exhausted) = refused else { unreachable!() };
+        assert_eq!(exhausted.to_string(), "today's budget of 2 exa calls is used up, they resume at midnight UTC");
+        let usage = breaker.usage();
+        assert_eq!((usage.calls, usage.skipped), (2, 1));
+        // Skipped calls are not failures of the upstream
+        assert_eq!(breaker.snapshot().state, BreakerState::Closed);
+
+        *clock.0.lock().unwrap() = Utc.with_ymd_and_hms(2025, 10, 14, 0, 0, 1).unwrap();
+        assert_eq!(breaker.usage().calls, 0);
+        breaker.try_call().unwrap().succeeded();
+        assert_eq!(breaker.usage().calls, 1);
+    }
+
+    #[test]
+    fn test_an_open_breaker_skips_calls_without_spending_the_budget() {
+        let breaker = CircuitBreaker::new("coingecko", BreakerSettings { failure_threshold: 1, cool_down: Duration::from_secs(60) })
+            .with_budget(BudgetLimits { soft: None, hard: Some(10) });
+        breaker.try_call().unwrap().failed();
+        assert!(matches!(breaker.try_call(), Err(Refused::CircuitOpen)));
+        assert_eq!(breaker.usage().calls, 1);
+
+        // Calls a previous run made today count too
+        breaker.restore_usage(10, Utc::now());
+        assert!(matches!(breaker.try_call(), Err(Refused::OverBudget(_))));
+    }
+}
@@ -0,0 +1,1139 @@
+use crate::agent_customizer::{self, AgentProfile, CustomizerError};
+use crate::agent_registry;
+use crate::api_budget;
+use crate::briefing::{self, LiveSources};
+use crate::circuit_breaker;
+use crate::cost_basis::{self, Position};
+use crate::feedback::{self, Rating};
+use crate::health::{self, HealthChecker};
+use crate::db::{self, DataStats, Holding, Knowledge, Message, NamedCount, NewTrade, Strategy};
+use crate::investment_chat::{Action, InvestmentChatAgent, InvestmentChatError};
+use crate::offline;
+use crate::price_fetcher;
+use crate::price_format::format_price;
+use crate::render::Table;
+use crate::report::{self, Week};
+use crate::retention::{self, LlmSummarizer};
+use crate::strategy_manager::{StrategyError, StrategyManager, STRATEGIES_DIR};
+use crate::strategy_progress;
+use crate::timezone::UserTimezone;
+use crate::topics::{self, Period, TopicQuery};
+use crate::trade_history;
+use crate::trade_import;
+use crate::turn_debug::{self, Redactor};
+use crate::vault::{self, Vault};
+use crate::watchlist;
+use chrono::{NaiveDate, NaiveDateTime};
+use regex::Regex;
+use std::fs;
+use std::path::Path;
+use std::sync::OnceLock;
+
+/// Help text listing the local slash commands
+pub const HELP_TEXT: &str = "Available commands:\n\
+    /strategies                       List your saved strategies\n\
+    /strategy start <name>            Work through a saved strategy's steps as a checklist\n\
+    /strategy done <step> [note]      Mark a step of the strategy you're working through done\n\
+    /strategy progress [name]         Show a strategy's checklist with completion dates and past outcomes\n\
+    /history [n]                      Show the last n messages (default 10, at most 200)\n\
+    /history purge <YYYY-MM-DD>       Summarize and archive messages older than a date\n\
+    /briefing                         Show today's briefing, generating it if needed\n\
+    /report week [YYYY-MM-DD]         Write the weekly report for this week or the week of a date to a markdown file\n\
+    /portfolio                        Show your holdings valued at the latest prices\n\
+    /portfolio set <coin> <amount>    Add or update a holding\n\
+    /portfolio remove <coin>          Remove a holding\n\
+    /trades import <csv-path>         Import trades from an exchange CSV export for cost basis and P&L\n\
+    /watchlist                        Show watched coins with prices and 24h changes\n\
+    /watchlist add <coin> [note]      Watch a coin you don't hold\n\
+    /watchlist note <coin> [note]     Set or clear the note of a watched coin\n\
+    /watchlist remove <coin>          Stop watching a coin\n\
+    /knowledge                        List your knowledge entries, private ones shown as locked without the key\n\
+    /knowledge rekey [passphrase]     Re-encrypt private knowledge under a fresh salt or a new passphrase\n\
+    /good                             Rate the last answer as helpful\n\
+    /bad [reason]                     Rate the last answer as wrong, ranking the knowledge behind it lower\n\
+    /stats data                       Show what's stored: knowledge, strategies, messages and storage\n\
+    /stats feedback                   Show your ratings of answers week by week\n\
+    /stats usage                      Show today's CoinGecko and Exa calls against their daily budgets\n\
+    /topics [period]                  Show the coins and keywords we talked about most: today, week, month, year, 30d or all\n\
+    /profile [page]                   Show your profile: strategies, knowledge by tag and preferences\n\
+    /profile json                     Show your full profile as JSON\n\
+    /health                           Check the database, AI, price, search and RPC services
+    /offline [on|off]                 Show, start or leave offline mode\n\
+    /debug last                       Show what the last answer sent to the model and got back, secrets redacted\n\
+    /debug --turn <id>                Show a recorded turn by its id\n\
+    /system set <prompt>              Use custom advisor instructions for the rest of this session\n\
+    /system show                      Show the session's custom instructions and their variant\n\
+    /system clear                     Go back to the default advisor instructions\n\
+    /account export <path>            Write all your data to a JSON file\n\
+    /account delete                   Permanently delete your account and data\n\
+    /help                             Show this help";
+
+/// Messages `/history` shows without a count
+pub const DEFAULT_HISTORY_MESSAGES: u32 = 10;
+
+/// Most messages `/history` shows, larger counts are cut to it
+pub const MAX_HISTORY_MESSAGES: u32 = 200;
+
+/// Tags and categories listed by `/stats data` before the rest are summarized
+pub const DATA_STATS_TOP_N: i64 = 10;
+
+/// Widest cell of the `/strategies` table, longer names and descriptions are cut
+const STRATEGY_COLUMN_WIDTH: usize = 60;
+
+/// Widest cell of the `/knowledge` table
+const KNOWLEDGE_COLUMN_WIDTH: usize = 40;
+
+/// A holding with the price used to value it
+#[derive(Debug, Clone)]
+pub struct PortfolioRow {
+    pub coin_id: String,
+    pub amount: f64,
+    pub price_usd: Option<f64>,
+    /// Set when the price comes from the local price history instead of a live quote
+    pub as_of: Option<NaiveDateTime>,
+    /// USD paid per coin according to the user's trades, None without trades in the coin
+    pub average_cost: Option<f64>,
+}
+
+/// Handle a slash command typed in the chat
+/// Returns None if the input is not a command
+pub async fn handle_command(agent: &InvestmentChatAgent, input: &str) -> Option<Result<String, InvestmentChatError>> {
+    let input = input.trim();
+    if !input.starts_with('/') {
+        return None;
+    }
+
+    let mut parts = input.split_whitespace();
+    let command = parts.next().unwrap_or_default().to_lowercase();
+    let args: Vec<&str> = parts.collect();
+
+    let result = match command.as_str() {
+        "/help" => Ok(HELP_TEXT.to_string()),
+        "/strategies" => strategies_command(agent).await,
+        "/strategy" => strategy_command(agent, &args).await,
+        "/history" => history_command(agent, &args).await,
+        "/portfolio" => portfolio_command(agent, &args).await,
+        "/trades" => trades_command(agent, &args).await,
+        "/watchlist" => watchlist_command(agent, &args).await,
+        "/briefing" => briefing_command(agent).await,
+        "/report" => report_command(agent, &args).await,
+        "/knowledge" => knowledge_command(agent, &args).await,
+        "/good" => rate_command(agent, Rating::Good, &args).await,
+        "/bad" => rate_command(agent, Rating::Bad, &args).await,
+        "/stats" => stats_command(agent, &args).await,
+        "/topics" => topics_command(agent, &args).await,
+        "/profile" => profile_command(agent, &args).await,
+        "/health" => Ok(health_command(agent).await),
+        "/offline" => Ok(offline_command(&args)),
+        "/debug" => debug_command(agent, &args).await,
+        "/account" => account_command(agent, &args).await,
+        "/system" => system_command(agent, input).await,
+        _ => Ok(format!("Unknown command: {}\n\n{}", command, HELP_TEXT)),
+    };
+
+    Some(result)
+}
+
+async fn strategies_command(agent: &InvestmentChatAgent) -> Result<String, InvestmentChatError> {
+    let strategies = db::get_strategies_by_user_id(agent.pool(), agent.user_id())
+        .await
+        .map_err(InvestmentChatError::Database)?;
+
+    Ok(render_strategies(&strategies))
+}
+
+async fn history_command(agent: &InvestmentChatAgent, args: &[&str]) -> Result<String, InvestmentChatError> {
+    if let Some(("purge", purge_args)) = args.split_first().map(|(first, rest)| (*first, rest)) {
+        return purge_history_command(agent, purge_args).await;
+    }
+
+    let limit = match args.first() {
+        Some(value) => parse_history_count(value)?,
+        None => DEFAULT_HISTORY_MESSAGES,
+    };
+
+    let messages = db::get_messages(agent.pool(), agent.user_id(), i64::from(limit))
+        .await
+        .map_err(InvestmentChatError::Database)?;
+
+    Ok(render_history(&messages, agent.timezone()))
+}
+
+/// Parse the `/history` message count, cutting counts above `MAX_HISTORY_MESSAGES` to it
+pub fn parse_history_count(value: &str) -> Result<u32, InvestmentChatError> {
+    match value.parse::<u32>() {
+        Ok(count) if count >= 1 => Ok(count.min(MAX_HISTORY_MESSAGES)),
+        _ => Err(InvestmentChatError::InvalidInput(format!(
+            "Invalid message count: {} (expected a number from 1 to {})",
+            value, MAX_HISTORY_MESSAGES
+        ))),
+    }
+}
+
+async fn purge_history_command(agent: &InvestmentChatAgent, args: &[&str]) -> Result<String, InvestmentChatError> {
+    let (date, confirmed) = match args {
+        [date] => (*date, false),
+        [date, "confirm"] => (*date, true),
+        _ => return Ok("Usage: /history purge <YYYY-MM-DD> [confirm]".to_string()),
+    };
+    let cutoff = parse_purge_date(date)?;
+
+    if !confirmed {
+        let pending = db::get_messages_before(agent.pool(), agent.user_id(), cutoff)
+            .await
+            .map_err(InvestmentChatError::Database)?;
+        return Ok(render_purge_preview(date, pending.len()));
+    }
+
+    let report = retention::archive_with_summary(agent.pool(), agent.user_id(), cutoff, &LlmSummarizer::from_config())
+        .await?;
+
+    if report.archived == 0 {
+        Ok(format!("No messages before {} to archive.", date))
+    } else {
+        Ok(format!(
+            "Archived {} messages from before {}. A summary was saved so the context isn't lost.",
+            report.archived, date
+        ))
+    }
+}
+
+/// Parse the purge date, archiving everything created before that day starts
+pub fn parse_purge_date(date: &str) -> Result<NaiveDateTime, InvestmentChatError> {
+    NaiveDate::parse_from_str(date, "%Y-%m-%d")
+        .ok()
+        .and_then(|day| day.and_hms_opt(0, 0, 0))
+        .ok_or_else(|| InvestmentChatError::InvalidInput(format!("Invalid date: {} (expected YYYY-MM-DD)", date)))
+}
+
+/// Ask for confirmation before archiving
+pub fn render_purge_preview(date: &str, pending: usize) -> String {
+    if pending == 0 {
+        return format!("No messages before {} to archive.", date);
+    }
+
+    format!(
+        "{} messages from before {} will be summarized and moved to the archive. \
+        They won't appear in your history anymore but can still be exported.\n\
+        Run /history purge {} confirm to continue.",
+        pending, date, date
+    )
+}
+
+async fn briefing_command(agent: &InvestmentChatAgent) -> Result<String, InvestmentChatError> {
+    let sources = LiveSources::new(agent.pool().clone());
+    Ok(briefing::today_or_generate(agent.pool(), &sources, agent.user_id(), agent.timezone()).await?)
+}
+
+async fn report_command(agent: &InvestmentChatAgent, args: &[&str]) -> Result<String, InvestmentChatError> {
+    let now = agent.local_now();
+    let date = match args {
+        ["week"] => now.date_naive(),
+        ["week", date] => parse_purge_date(date)?.date(),
+        _ => return Ok("Usage: /report week [YYYY-MM-DD]".to_string()),
+    };
+
+    let week = Week::containing(date, agent.timezone());
+    let path = report::generate(agent.pool(), &report::report_dir(), agent.user_id(), agent.username(), week).await?;
+    Ok(format!("Wrote your report for the week of {} to {}", week.monday.format("%a %-d %b %Y"), path.display()))
+}
+
+async fn portfolio_command(agent: &InvestmentChatAgent, args: &[&str]) -> Result<String, InvestmentChatError> {
+    match args {
+        ["set", coin, amount] => {
+            let amount = amount.parse::<f64>().map_err(|_| {
+                InvestmentChatError::InvalidInput(format!("Invalid amount: {}", amount))
+            })?;
+            if amount < 0.0 {
+                return Err(InvestmentChatError::InvalidInput("Amount cannot be negative".to_string()));
+            }
+
+            let coin_id = agent.map_crypto_name_to_id(coin);
+            let previous = holding_amount(agent, &coin_id).await?;
+            db::upsert_holding(agent.pool(), agent.user_id(), &coin_id, amount)
+                .await
+                .map_err(InvestmentChatError::Database)?;
+            agent.record_action(Action::HoldingSet { coin_id: coin_id.clone(), previous });
+
+            Ok(format!("Updated holding: {} {}", amount, coin_id))
+        },
+        ["remove", coin] => {
+            let coin_id = agent.map_crypto_name_to_id(coin);
+            let previous = holding_amount(agent, &coin_id).await?;
+            let removed = db::delete_holding(agent.pool(), agent.user_id(), &coin_id)
+                .await
+                .map_err(InvestmentChatError::Database)?;
+
+            if let Some(amount) = previous.filter(|_| removed) {
+                agent.record_action(Action::HoldingRemoved { coin_id: coin_id.clone(), amount });
+            }
+            if removed {
+                Ok(format!("Removed {} from your portfolio", coin_id))
+            } else {
+                Ok(format!("You don't have a holding for {}", coin_id))
+            }
+        },
+        [] => {
+            let holdings = db::get_holdings_by_user_id(agent.pool(), agent.user_id())
+                .await
+                .map_err(InvestmentChatError::Database)?;
+            let rows = value_holdings(agent, &holdings).await?;
+
+            Ok(render_portfolio(&rows))
+        },
+        _ => Ok(HELP_TEXT.to_string()),
+    }
+}
+
+/// The amount held of a coin, None when the user doesn't hold it
+async fn holding_amount(agent: &InvestmentChatAgent, coin_id: &str) -> Result<Option<f64>, InvestmentChatError> {
+    let holdings = db::get_holdings_by_user_id(agent.pool(), agent.user_id())
+        .await
+        .map_err(InvestmentChatError::Database)?;
+    Ok(holdings.into_iter().find(|holding| holding.coin_id == coin_id).map(|holding| holding.amount))
+}
+
+async fn trades_command(agent: &InvestmentChatAgent, args: &[&str]) -> Result<String, InvestmentChatError> {
+    let ["import", path] = args else {
+        return Ok("Usage: /trades import <csv-path>".to_string());
+    };
+
+    let parsed = trade_import::parse_file(path, agent.local_now().naive_utc())
+        .map_err(|e| InvestmentChatError::InvalidInput(e.to_string()))?;
+    let trades: Vec<NewTrade> = parsed
+        .trades
+        .iter()
+        .map(|trade| trade.to_new_trade(&agent.map_crypto_name_to_id(&trade.base)))
+        .collect();
+    let stored = db::import_trades(agent.pool(), agent.user_id(), &trades)
+        .await
+        .map_err(InvestmentChatError::Database)?;
+
+    // Cost basis is replayed from every trade, so the new ones show up wherever it's used
+    let all_trades = db::get_trades_by_user_id(agent.pool(), agent.user_id(), None, None)
+        .await
+        .map_err(InvestmentChatError::Database)?;
+    let mut output = trade_import::render_report(path, &parsed, stored);
+    output.push_str(&format!("\n\n{}", render_positions(&cost_basis::positions(&all_trades))));
+    Ok(output)
+}
+
+async fn watchlist_command(agent: &InvestmentChatAgent, args: &[&str]) -> Result<String, InvestmentChatError> {
+    match watchlist::parse_command_args(args) {
+        Some(command) => agent.run_watchlist_command(command).await,
+        None => Ok(HELP_TEXT.to_string()),
+    }
+}
+
+async fn strategy_command(agent: &InvestmentChatAgent, args: &[&str]) -> Result<String, InvestmentChatError> {
+    match strategy_progress::parse_command_args(args) {
+        Some(command) => agent.run_progress_command(command).await,
+        None => Ok(HELP_TEXT.to_string()),
+    }
+}
+
+async fn knowledge_command(agent: &InvestmentChatAgent, args: &[&str]) -> Result<String, InvestmentChatError> {
+    match args {
+        [] | ["list"] => {
+            let entries = db::get_knowledge_by_user_id(agent.pool(), agent.user_id())
+                .await
+                .map_err(InvestmentChatError::Database)?;
+            Ok(render_knowledge(&entries))
+        },
+        ["rekey", passphrase @ ..] => rekey_command(agent, &passphrase.join(" ")).await,
+        _ => Ok(HELP_TEXT.to_string()),
+    }
+}
+
+/// Re-encrypt private knowledge, keeping the passphrase when none is given
+async fn rekey_command(agent: &InvestmentChatAgent, passphrase: &str) -> Result<String, InvestmentChatError> {
+    let current = vault::current();
+    let new = match (passphrase.is_empty(), &current) {
+        (true, Some(current)) => current.rotated(),
+        (true, None) => {
+            return Ok("No passphrase is loaded. Set KNOWLEDGE_PASSPHRASE or a key file, or give one: /knowledge rekey <passphrase>".to_string());
+        },
+        (false, _) => Vault::new(passphrase).with_cost(current.map(|current| current.cost()).unwrap_or(vault::DEFAULT_COST)),
+    };
+
+    let rekeyed = vault::rekey(agent.pool(), agent.user_id(), new)
+        .await
+        .map_err(InvestmentChatError::Database)?;
+    let mut output = format!("Re-encrypted {} private knowledge entries.", rekeyed.reencrypted);
+    if rekeyed.locked > 0 {
+        output.push_str(&format!(" {} entries stay locked: the loaded key doesn't open them.", rekeyed.locked));
+    }
+    if !passphrase.is_empty() {
+        output.push_str(" Update KNOWLEDGE_PASSPHRASE or your key file to the new passphrase, or they will be locked next time.");
+    }
+    Ok(output)
+}
+
+async fn stats_command(agent: &InvestmentChatAgent, args: &[&str]) -> Result<String, InvestmentChatError> {
+    match args {
+        ["data"] => data_stats_report(agent).await,
+        ["feedback"] => {
+            let ratings = db::get_feedback(agent.pool(), agent.user_id())
+                .await
+                .map_err(InvestmentChatError::Database)?;
+            Ok(feedback::render_feedback_stats(&ratings))
+        },
+        ["usage"] => Ok(api_budget::render_usage(&circuit_breaker::usage_snapshots())),
+        _ => Ok(HELP_TEXT.to_string()),
+    }
+}
+
+/// Count the topics of the user's messages over the period given, all of them by default
+async fn topics_command(agent: &InvestmentChatAgent, args: &[&str]) -> Result<String, InvestmentChatError> {
+    let period = match args {
+        [] => Period::All,
+        [period] => match Period::parse(period) {
+            Some(period) => period,
+            None => return Ok(HELP_TEXT.to_string()),
+        },
+        _ => return Ok(HELP_TEXT.to_string()),
+    };
+    topics_report(agent, TopicQuery { kind: None, period }).await
+}
+
+/// The topics of the user's messages the query asks for, most mentioned first
+pub(crate) async fn topics_report(agent: &InvestmentChatAgent, query: TopicQuery) -> Result<String, InvestmentChatError> {
+    let since = query.period.start(agent.local_now());
+    let counts = db::get_topic_counts(agent.pool(), agent.user_id(), since, query.kind, topics::TOP_TOPICS)
+        .await
+        .map_err(InvestmentChatError::Database)?;
+    Ok(topics::render_topics(&query, &counts))
+}
+
+/// The user's trades over `period`, from the trade history
+pub(crate) async fn trade_history_report(agent: &InvestmentChatAgent, period: Period) -> Result<String, InvestmentChatError> {
+    let now = agent.local_now();
+    let trades = db::get_trades_by_user_id(agent.pool(), agent.user_id(), period.start(now), None)
+        .await
+        .map_err(InvestmentChatError::Database)?;
+    Ok(trade_history::render_trade_history(period, &trades, agent.timezone()))
+}
+
+/// Rate the last answer, the words after `/bad` are the reason
+async fn rate_command(agent: &InvestmentChatAgent, rating: Rating, args: &[&str]) -> Result<String, InvestmentChatError> {
+    let reason = Some(args.join(" ")).filter(|reason| !reason.is_empty());
+    feedback::rate_last_answer(agent.pool(), agent.user_id(), rating, reason.as_deref())
+        .await
+        .map_err(InvestmentChatError::Database)
+}
+
+/// Overview of everything stored for the agent's user
+pub(crate) async fn data_stats_report(agent: &InvestmentChatAgent) -> Result<String, InvestmentChatError> {
+    let stats = db::get_data_stats(agent.pool(), agent.user_id(), DATA_STATS_TOP_N)
+        .await
+        .map_err(InvestmentChatError::Database)?;
+
+    Ok(render_data_stats(&stats))
+}
+
+/// Whether a chat message asks what's stored, like "what do you have stored about me?"
+pub fn is_stored_data_query(message: &str) -> bool {
+    static QUERY: OnceLock<Regex> = OnceLock::new();
+    let query = QUERY.get_or_init(|| {
+        Regex::new(r"(?i)\bwhat\s+(?:do\s+you\s+have|have\s+you|data\s+do\s+you\s+have)\s+(?:stored|saved|kept|remembered)(?:\s+(?:about|on|for)\s+me)?\b|\bwhat\s+do\s+you\s+(?:know|remember|store)\s+about\s+me\b").unwrap()
+    });
+    query.is_match(message)
+}
+
+/// Probe the database and every configured integration
+async fn health_command(agent: &InvestmentChatAgent) -> String {
+    let health = HealthChecker::from_config(Some(agent.pool().clone())).check().await;
+    health::render_health(&health)
+}
+
+/// Show a turn recorded by debug capture, the latest one unless `--turn <id>` names another
+async fn debug_command(agent: &InvestmentChatAgent, args: &[&str]) -> Result<String, InvestmentChatError> {
+    let id = match args {
+        [] | ["last"] => None,
+        ["--turn", id] => Some(id.parse::<i32>().map_err(|_| {
+            InvestmentChatError::InvalidInput(format!("Invalid turn id: {}", id))
+        })?),
+        _ => return Ok(HELP_TEXT.to_string()),
+    };
+
+    let turn = db::get_turn_debug(agent.pool(), agent.user_id(), id)
+        .await
+        .map_err(InvestmentChatError::Database)?;
+    let Some(turn) = turn else {
+        return Ok(match (id, agent.debug_capture()) {
+            (Some(id), _) => format!("No recorded turn {}. Only the latest {} turns are kept.", id, turn_debug::KEPT_TURNS),
+            (None, true) => "No turns recorded yet, ask something first.".to_string(),
+            (None, false) => "Debug capture is off. Start with DEBUG_CAPTURE=1 to record what each turn sends to the model.".to_string(),
+        });
+    };
+    match turn_debug::decode(&turn.payload) {
+        Ok(capture) => Ok(turn_debug::render(&turn, &capture, &Redactor::from_config())),
+        Err(e) => Ok(format!("Turn {} can't be shown: {}", turn.id, e)),
+    }
+}
+
+/// Set, show or clear the session's system prompt override
+///
+/// The prompt after `set` is taken verbatim, line breaks included
+/// `/offline on` stays offline until `/offline off`, unlike offline mode started by a connect error
+pub fn offline_command(args: &[&str]) -> String {
+    match args {
+        [] if offline::is_forced() => "Offline mode is on. Use /offline off to go back online.".to_string(),
+        [] if offline::is_offline() => {
+            "Offline mode is on because the network couldn't be reached, requests are tried again shortly.".to_string()
+        },
+        [] => "Online. Use /offline on to answer from stored data only.".to_string(),
+        ["on"] => {
+            offline::enable();
+            "Offline mode is on: answers come from your stored prices, knowledge and history until /offline off.".to_string()
+        },
+        ["off"] => {
+            offline::disable();
+            "Offline mode is off, network calls are made again.".to_string()
+        },
+        _ => "Usage: /offline [on|off]".to_string(),
+    }
+}
+
+async fn system_command(agent: &InvestmentChatAgent, input: &str) -> Result<String, InvestmentChatError> {
+    let rest = input.split_once(char::is_whitespace).map(|(_, rest)| rest.trim_start()).unwrap_or_default();
+    let (action, text) = rest.split_once(char::is_whitespace).unwrap_or((rest, ""));
+
+    match action.to_lowercase().as_str() {
+        "set" => {
+            let system_override = agent.set_system_prompt(text).await?;
+            Ok(format!(
+                "Using custom instructions for the rest of this session (variant {}). Answers still note risks and uncertainty.",
+                system_override.variant()
+            ))
+        },
+        "show" => Ok(match agent.system_prompt() {
+            Some(system_override) => format!("Custom instructions (variant {}):\n{}", system_override.variant(), system_override.text()),
+            None => "No custom instructions, answers use the default advisor prompt.".to_string(),
+        }),
+        "clear" => Ok(match agent.clear_system_prompt().await? {
+            Some(system_override) => format!("Cleared variant {}, answers use the default advisor prompt.", system_override.variant()),
+            None => "No custom instructions to clear.".to_string(),
+        }),
+        _ => Ok("Usage: /system set <prompt> | /system show | /system clear".to_string()),
+    }
+}
+
+async fn profile_command(agent: &InvestmentChatAgent, args: &[&str]) -> Result<String, InvestmentChatError> {
+    match args {
+        [] => profile_report(agent, 1).await,
+        ["json"] => {
+            let profile = load_profile(agent).await?;
+            serde_json::to_string_pretty(&profile)
+                .map_err(|e| InvestmentChatError::Internal(format!("Failed to serialize profile: {}", e)))
+        },
+        [page] => match page.parse::<usize>() {
+            Ok(page) => profile_report(agent, page).await,
+            Err(_) => Ok(HELP_TEXT.to_string()),
+        },
+        _ => Ok(HELP_TEXT.to_string()),
+    }
+}
+
+async fn load_profile(agent: &InvestmentChatAgent) -> Result<AgentProfile, InvestmentChatError> {
+    let username = agent.username();
+    match agent_customizer::get_agent_profile(agent.pool(), username).await {
+        Ok(Some(profile)) => Ok(profile),
+        Ok(None) => Err(InvestmentChatError::InvalidInput(format!("Unknown user: {}", username))),
+        Err(CustomizerError::Database(e)) => Err(InvestmentChatError::Database(e)),
+        Err(e) => Err(InvestmentChatError::Internal(e.to_string())),
+    }
+}
+
+/// One page of the agent's profile of its user
+pub(crate) async fn profile_report(agent: &InvestmentChatAgent, page: usize) -> Result<String, InvestmentChatError> {
+    let profile = load_profile(agent).await?;
+    Ok(agent_customizer::render_profile(&profile, page))
+}
+
+/// Whether a chat message asks for the user's profile, like "who am I to you?"
+pub fn is_profile_query(message: &str) -> bool {
+    static QUERY: OnceLock<Regex> = OnceLock::new();
+    let query = QUERY.get_or_init(|| {
+        Regex::new(r"(?i)\bwho\s+am\s+i\s+to\s+you\b|\bwhat\s+do\s+you\s+know\s+about\s+my\s+(?:setup|profile|preferences)\b|\bshow\s+(?:me\s+)?my\s+profile\b").unwrap()
+    });
+    query.is_match(message)
+}
+
+async fn account_command(agent: &InvestmentChatAgent, args: &[&str]) -> Result<String, InvestmentChatError> {
+    let username = agent.username();
+
+    match args {
+        ["export", path] => {
+            let export = db::export_user_data(agent.pool(), username)
+                .await?
+                .ok_or_else(|| InvestmentChatError::InvalidInput(format!("Unknown user: {}", username)))?;
+            let json = serde_json::to_string_pretty(&export)
+                .map_err(|e| InvestmentChatError::Internal(format!("Failed to serialize export: {}", e)))?;
+            fs::write(path, json)
+                .map_err(|e| InvestmentChatError::Internal(format!("Failed to write {}: {}", path, e)))?;
+
+            Ok(format!("Exported your data to {}", path))
+        },
+        ["delete"] => Ok(format!(
+            "This permanently deletes your account, conversation history, strategies, knowledge, holdings and \
+            notifications, including the strategy files you authored. It can't be undone; consider \
+            /account export <path> first.\n\
+            To continue, run /account delete {}",
+            username
+        )),
+        ["delete", name] if *name == username => Ok(format!(
+            "Are you sure? This is your last chance to keep your data.\n\
+            Run /account delete {} confirm to delete everything.",
+            username
+        )),
+        ["delete", name, "confirm"] if *name == username => {
+            if !db::delete_user_cascade(agent.pool(), username).await? {
+                return Ok(format!("No account found for {}", username));
+            }
+            agent_registry::invalidate(username);
+            let removed_files = delete_user_files(Path::new(STRATEGIES_DIR), username)
+                .map_err(|e| InvestmentChatError::Internal(format!("Account deleted, but removing strategy files failed: {}", e)))?;
+            report::delete_reports(&report::report_dir(), username)
+                .map_err(|e| InvestmentChatError::Internal(format!("Account deleted, but removing weekly reports failed: {}", e)))?;
+
+            Ok(format!(
+                "Your account and all of its data have been deleted ({} strategy file(s) removed). Goodbye!",
+                removed_files
+            ))
+        },
+        ["delete", name, ..] => Err(InvestmentChatError::InvalidInput(format!(
+            "'{}' doesn't match your username, nothing was deleted",
+            name
+        ))),
+        _ => Ok(HELP_TEXT.to_string()),
+    }
+}
+
+/// Remove the on-disk files owned by a user, returning how many were deleted
+pub fn delete_user_files(strategies_dir: &Path, username: &str) -> Result<usize, StrategyError> {
+    if !strategies_dir.exists() {
+        return Ok(0);
+    }
+
+    StrategyManager::new(strategies_dir)?.delete_strategies_by_author(username)
+}
+
+/// Price holdings with live quotes, falling back to the last known prices
+pub(crate) async fn value_holdings(agent: &InvestmentChatAgent, holdings: &[Holding]) -> Result<Vec<PortfolioRow>, InvestmentChatError> {
+    let coin_ids: Vec<&str> = holdings.iter().map(|h| h.coin_id.as_str()).collect();
+
+    let live_prices = if offline::is_offline() || coin_ids.is_empty() {
+        Default::default()
+    } else {
+        match price_fetcher::fetch_multiple_coin_prices(&coin_ids).await {
+            Ok(prices) => prices,
+            Err(e) => {
+                eprintln!("Error fetching live prices for portfolio: {}", e);
+                Default::default()
+            }
+        }
+    };
+
+    let trades = db::get_trades_by_user_id(agent.pool(), agent.user_id(), None, None)
+        .await
+        .map_err(InvestmentChatError::Database)?;
+    let positions = cost_basis::positions(&trades);
+    let average_cost = |coin_id: &str| positions.get(coin_id).and_then(Position::average_cost);
+
+    let mut rows = Vec::with_capacity(holdings.len());
+    for holding in holdings {
+        let row = match live_prices.get(&holding.coin_id) {
+            Some(price) => {
+                if let Err(e) = db::save_price_point(agent.pool(), &holding.coin_id, *price).await {
+                    eprintln!("Error saving price history for {}: {}", holding.coin_id, e);
+                }
+                PortfolioRow {
+                    coin_id: holding.coin_id.clone(),
+                    amount: holding.amount,
+                    price_usd: Some(*price),
+                    as_of: None,
+                    average_cost: average_cost(&holding.coin_id),
+                }
+            },
+            None => {
+                let point = db::get_latest_price_point(agent.pool(), &holding.coin_id)
+                    .await
+                    .map_err(InvestmentChatError::Database)?;
+                PortfolioRow {
+                    coin_id: holding.coin_id.clone(),
+                    amount: holding.amount,
+                    price_usd: point.as_ref().map(|p| p.price_usd),
+                    as_of: point.map(|p| p.fetched_at),
+                    average_cost: average_cost(&holding.coin_id),
+                }
+            },
+        };
+        rows.push(row);
+    }
+
+    Ok(rows)
+}
+
+/// Render the list of saved strategies
+pub fn render_strategies(strategies: &[Strategy]) -> String {
+    if strategies.is_empty() {
+        return "You don't have any saved strategies yet.".to_string();
+    }
+
+    let mut table = Table::new(["Name", "Category", "Risk", "Description"]).max_width(STRATEGY_COLUMN_WIDTH);
+    for strategy in strategies {
+        table.push_row([&strategy.name, &strategy.category, &strategy.risk_level, &strategy.description]);
+    }
+
+    format!("Your strategies ({}):\n{}", strategies.len(), table.render())
+}
+
+/// List knowledge entries, with the content of locked ones withheld
+pub fn render_knowledge(entries: &[Knowledge]) -> String {
+    if entries.is_empty() {
+        return "You don't have any knowledge entries yet.".to_string();
+    }
+
+    let mut table = Table::new(["Source", "Tags", "Content"]).max_width(KNOWLEDGE_COLUMN_WIDTH);
+    for entry in entries {
+        let content = if vault::is_locked(entry) {
+            "(locked)".to_string()
+        } else {
+            entry.content.lines().next().unwrap_or_default().to_string()
+        };
+        table.push_row([&entry.source_id, &entry.tags.join(", "), &content]);
+    }
+
+    let locked = entries.iter().filter(|entry| vault::is_locked(entry)).count();
+    let mut output = format!("Your knowledge ({}):\n{}", entries.len(), table.render());
+    if locked > 0 {
+        output.push_str(&format!("\n{} private entries are locked, set KNOWLEDGE_PASSPHRASE or a key file to read them.", locked));
+    }
+    output
+}
+
+/// Render recent messages in chronological order, stamped in the user's timezone
+/// Expects messages newest first, as returned by `db::get_messages`
+pub fn render_history(messages: &[Message], timezone: UserTimezone) -> String {
+    if messages.is_empty() {
+        return "No conversation history yet.".to_string();
+    }
+
+    let mut output = format!("Recent conversation (times in {}):\n", timezone);
+    for message in messages.iter().rev() {
+        output.push_str(&format!(
+            "\n[{}] {}:\n{}\n",
+            timezone.local(message.created_at).format("%Y-%m-%d %H:%M"),
+            message.role,
+            message.content
+        ));
+    }
+
+    output
+}
+
+/// Render the portfolio with per-holding values and a total
+/// Cost and P&L columns are shown once trades give any holding an average cost
+pub fn render_portfolio(rows: &[PortfolioRow]) -> String {
+    if rows.is_empty() {
+        return "Your portfolio is empty. Add a holding with /portfolio set <coin> <amount>.".to_string();
+    }
+
+    let with_cost = rows.iter().any(|row| row.average_cost.is_some());
+    let mut table = if with_cost {
+        Table::new(["Coin", "Amount", "Price", "Value", "Avg cost", "P&L", "Note"])
+    } else {
+        Table::new(["Coin", "Amount", "Price", "Value", "Note"])
+    };
+    let mut total = 0.0;
+    let mut unpriced = 0;
+    let mut total_pnl = 0.0;
+
+    for row in rows {
+        let (price, value, note) = match row.price_usd {
+            Some(price) => {
+                let value = row.amount * price;
+                total += value;
+                let note = row
+                    .as_of
+                    .map(|as_of| format!("last known price, as of {} UTC", as_of.format("%Y-%m-%d %H:%M")))
+                    .unwrap_or_default();
+                (format_price(price), format!("${:.2}", value), note)
+            },
+            None => {
+                unpriced += 1;
+                ("n/a".to_string(), "n/a".to_string(), "no price available".to_string())
+            },
+        };
+        if with_cost {
+            let pnl = row.average_cost.zip(row.price_usd).map(|(cost, price)| (price - cost) * row.amount);
+            total_pnl += pnl.unwrap_or(0.0);
+            table.push_row([
+                row.coin_id.clone(),
+                row.amount.to_string(),
+                price,
+                value,
+                row.average_cost.map(format_price).unwrap_or_default(),
+                pnl.map(render_signed_usd).unwrap_or_default(),
+                note,
+            ]);
+        } else {
+            table.push_row([row.coin_id.clone(), row.amount.to_string(), price, value, note]);
+        }
+    }
+
+    let mut output = format!("Your portfolio:\n{}\n", table.render());
+    output.push_str(&format!("\nTotal value: ${:.2}", total));
+    if unpriced > 0 {
+        output.push_str(&format!(" (excluding {} unpriced holding(s))", unpriced));
+    }
+    if with_cost {
+        output.push_str(&format!("\nUnrealized P&L of holdings with a cost basis: {}", render_signed_usd(total_pnl)));
+    }
+
+    output
+}
+
+/// Positions replayed from trades, with average cost and realized P&L
+pub fn render_positions(positions: &std::collections::BTreeMap<String, Position>) -> String {
+    if positions.is_empty() {
+        return "No trades recorded yet.".to_string();
+    }
+
+    let mut table = Table::new(["Coin", "Held", "Avg cost", "Realized P&L"]);
+    for (coin_id, position) in positions {
+        table.push_row([
+            coin_id.clone(),
+            format!("{}", (position.amount * 1e8).round() / 1e8),
+            position.average_cost().map(format_price).unwrap_or_else(|| "n/a".to_string()),
+            render_signed_usd(position.realized_pnl_usd),
+        ]);
+    }
+    let mut output = format!("Cost basis from your trades:\n{}", table.render());
+    let unmatched: Vec<&String> = positions.iter().filter(|(_, position)| position.unmatched_sold > 0.0).map(|(coin_id, _)| coin_id).collect();
+    if !unmatched.is_empty() {
+        output.push_str(&format!(
+            "\nSells of {} exceed the buys recorded, the excess has no known cost and isn't in the P&L.",
+            unmatched.iter().map(|coin_id| coin_id.as_str()).collect::<Vec<_>>().join(", ")
+        ));
+    }
+    output
+}
+
+/// "+$12.50" or "-$3.00"
+fn render_signed_usd(amount: f64) -> String {
+    let sign = if amount < 0.0 { "-" } else { "+" };
+    format!("{}${:.2}", sign, amount.abs())
+}
+
+/// "defi (5), eth (3) and 4 more", listing `shown` out of `total` distinct names
+fn render_counts(counts: &[NamedCount], total: i64) -> String {
+    let mut output = counts
+        .iter()
+        .map(|entry| format!("{} ({})", entry.name, entry.count))
+        .collect::<Vec<_>>()
+        .join(", ");
+    let hidden = total - counts.len() as i64;
+    if hidden > 0 {
+        output.push_str(&format!(" and {} more", hidden));
+    }
+    output
+}
+
+fn render_bytes(bytes: i64) -> String {
+    if bytes < 1024 {
+        format!("{} B", bytes)
+    } else if bytes < 1024 * 1024 {
+        format!("{:.1} KB", bytes as f64 / 1024.0)
+    } else {
+        format!("{:.1} MB", bytes as f64 / (1024.0 * 1024.0))
+    }
+}
+
+/// Render the overview shown by `/stats data`
+pub fn render_data_stats(stats: &DataStats) -> String {
+    let mut output = String::from("Stored data:\n");
+
+    output.push_str(&format!("\nKnowledge: {} entries\n", stats.knowledge_count));
+    if !stats.knowledge_tags.is_empty() {
+        output.push_str(&format!("- Tags: {}\n", render_counts(&stats.knowledge_tags, stats.distinct_tags)));
+    }
+    if let (Some(oldest), Some(newest)) = (&stats.oldest_knowledge, &stats.newest_knowledge) {
+        output.push_str(&format!(
+            "- Oldest: {} ({}), newest: {} ({})\n",
+            oldest.source_id,
+            oldest.created_at.format("%Y-%m-%d"),
+            newest.source_id,
+            newest.created_at.format("%Y-%m-%d")
+        ));
+    }
+
+    output.push_str(&format!("\nStrategies: {}\n", stats.strategy_count));
+    if !stats.strategy_categories.is_empty() {
+        output.push_str(&format!("- Categories: {}\n", render_counts(&stats.strategy_categories, stats.distinct_categories)));
+        let risk_levels = stats.strategy_risk_levels.len() as i64;
+        output.push_str(&format!("- Risk levels: {}\n", render_counts(&stats.strategy_risk_levels, risk_levels)));
+    }
+
+    output.push_str(&format!("\nMessages: {} live, {} archived\n", stats.message_count, stats.archived_message_count));
+    if stats.conversation_count > 0 {
+        output.push_str(&format!(
+            "- {} conversation(s), {:.1} messages on average, {} in the longest\n",
+            stats.conversation_count,
+            stats.message_count as f64 / stats.conversation_count as f64,
+            stats.longest_conversation
+        ));
+    }
+
+    let total: i64 = stats.storage.iter().map(|table| table.count).sum();
+    let breakdown = stats
+        .storage
+        .iter()
+        .map(|table| format!("{} {}", table.name, render_bytes(table.count)))
+        .collect::<Vec<_>>()
+        .join(", ");
+    output.push_str(&format!("\nStorage: {} ({})", render_bytes(total), breakdown));
+
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::MessageRole;
+    use chrono::NaiveDate;
+
+    fn timestamp(hour: u32) -> NaiveDateTime {
+        NaiveDate::from_ymd_opt(2025, 9, 20).unwrap().and_hms_opt(hour, 0, 0).unwrap()
+    }
+
+    #[test]
+    fn test_render_portfolio_mixes_live_and_cached_prices() {
+        let rows = vec![
+            PortfolioRow { coin_id: "bitcoin".to_string(), amount: 0.5, price_usd: Some(60_000.0), as_of: None, average_cost: None },
+            PortfolioRow { coin_id: "ethereum".to_string(), amount: 2.0, price_usd: Some(2_500.0), as_of: Some(timestamp(9)), average_cost: None },
+            PortfolioRow { coin_id: "obscure".to_string(), amount: 10.0, price_usd: None, as_of: None, average_cost: None },
+        ];
+
+        let output = render_portfolio(&rows);
+        assert_eq!(
+            output,
+            "Your portfolio:\n\
+            Coin      Amount      Price      Value  Note\n\
+            --------  ------  ---------  ---------  --------------------------------------------\n\
+            bitcoin      0.5  $60000.00  $30000.00\n\
+            ethereum       2   $2500.00   $5000.00  last known price, as of 2025-09-20 09:00 UTC\n\
+            obscure       10        n/a        n/a  no price available\n\
+            \n\
+            Total value: $35000.00 (excluding 1 unpriced holding(s))"
+        );
+    }
+
+    #[test]
+    fn test_render_portfolio_with_cost_basis() {
+        let rows = vec![
+            PortfolioRow { coin_id: "bitcoin".to_string(), amount: 0.5, price_usd: Some(60_000.0), as_of: None, average_cost: Some(40_000.0) },
+            PortfolioRow { coin_id: "ethereum".to_string(), amount: 2.0, price_usd: Some(2_500.0), as_of: None, average_cost: Some(3_000.0) },
+            PortfolioRow { coin_id: "solana".to_string(), amount: 10.0, price_usd: Some(150.0), as_of: None, average_cost: None },
+        ];
+
+        let output = render_portfolio(&rows);
+        assert!(output.contains("Avg cost"));
+        assert!(output.contains("$40000.00  +$10000.00"));
+        assert!(output.contains("$3000.00   -$1000.00"));
+        assert!(output.ends_with("Total value: $36500.00\nUnrealized P&L of holdings with a cost basis: +$9000.00"));
+    }
+
+    #[test]
+    fn test_render_positions() {
+        let position = |amount, cost_usd, realized_pnl_usd, unmatched_sold| Position { amount, cost_usd, realized_pnl_usd, unmatched_sold };
+        let positions = std::collections::BTreeMap::from([
+            ("bitcoin".to_string(), position(0.3, 12_000.0, 2_500.0, 0.0)),
+            ("solana".to_string(), position(0.0, 0.0, -30.6, 1.0)),
+        ]);
+        assert_eq!(
+            render_positions(&positions),
+            "Cost basis from your trades:\n\
+            Coin     Held   Avg cost  Realized P&L\n\
+            -------  ----  ---------  ------------\n\
+            bitcoin   0.3  $40000.00     +$2500.00\n\
+            solana      0        n/a       -$30.60\n\
+            Sells of solana exceed the buys recorded, the excess has no known cost and isn't in the P&L."
+        );
+        assert_eq!(render_positions(&Default::default()), "No trades recorded yet.");
+    }
+
+    fn counts(entries: &[(&str, i64)]) -> Vec<NamedCount> {
+        entries.iter().map(|(name, count)| NamedCount { name: name.to_string(), count: *count }).collect()
+    }
+
+    #[test]
+    fn test_render_data_stats() {
+        let stats = DataStats {
+            knowledge_count: 14,
+            knowledge_tags: counts(&[("defi", 5), ("eth", 3)]),
+            distinct_tags: 6,
+            oldest_knowledge: Some(db::KnowledgeStamp { source_id: "aerodrome-docs".to_string(), created_at: timestamp(8) }),
+            newest_knowledge: Some(db::KnowledgeStamp { source_id: "pendle-whitepaper".to_string(), created_at: timestamp(9) }),
+            strategy_count: 3,
+            strategy_categories: counts(&[("yield", 2), ("trading", 1)]),
+            distinct_categories: 2,
+            strategy_risk_levels: counts(&[("medium", 2), ("low", 1)]),
+            message_count: 30,
+            archived_message_count: 12,
+            conversation_count: 4,
+            longest_conversation: 14,
+            storage: counts(&[("knowledge", 2048), ("strategies", 512), ("messages", 3 * 1024 * 1024), ("archived messages", 0)]),
+        };
+
+        let output = render_data_stats(&stats);
+
+        assert!(output.contains("Knowledge: 14 entries\n- Tags: defi (5), eth (3) and 4 more\n"));
+        assert!(output.contains("- Oldest: aerodrome-docs (2025-09-20), newest: pendle-whitepaper (2025-09-20)"));
+        assert!(output.contains("- Categories: yield (2), trading (1)\n- Risk levels: medium (2), low (1)\n"));
+        assert!(output.contains("Messages: 30 live, 12 archived\n- 4 conversation(s), 7.5 messages on average, 14 in the longest"));
+        assert!(output.ends_with("Storage: 3.0 MB (knowledge 2.0 KB, strategies 512 B, messages 3.0 MB, archived messages 0 B)"));
+    }
+
+    #[test]
+    fn test_render_empty_data_stats() {
+        let output = render_data_stats(&DataStats::default());
+
+        assert!(output.contains("Knowledge: 0 entries\n\nStrategies: 0\n\nMessages: 0 live, 0 archived\n"));
+        assert!(!output.contains("Tags:"));
+        assert!(!output.contains("conversation(s)"));
+    }
+
+    #[test]
+    fn test_is_stored_data_query() {
+        assert!(is_stored_data_query("What do you have stored about me?"));
+        assert!(is_stored_data_query("what have you saved on me"));
+        assert!(is_stored_data_query("what do you know about me"));
+        assert!(!is_stored_data_query("what do you have on Pendle?"));
+    }
+
+    #[test]
+    fn test_is_profile_query() {
+        assert!(is_profile_query("who am I to you?"));
+        assert!(is_profile_query("What do you know about my setup"));
+        assert!(is_profile_query("can you show me my profile"));
+        assert!(!is_profile_query("what do you know about me?"));
+        assert!(!is_profile_query("what do you know about my ETH position"));
+    }
+
+    fn strategy(name: &str, category: &str, risk_level: &str, description: &str) -> Strategy {
+        Strategy {
+            id: 0,
+            user_id: 1,
+            strategy_id: name.to_lowercase(),
+            name: name.to_string(),
+            category: category.to_string(),
+            description: description.to_string(),
+            risk_level: risk_level.to_string(),
+            tags: vec![],
+            steps: vec![],
+            requirements: vec![],
+            expected_returns: serde_json::Value::Null,
+            created_at: timestamp(8),
+            updated_at: timestamp(8),
+            author: "alice".to_string(),
+            version: "1.0".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_render_strategies_as_a_table() {
+        let long = "Provide USDC/USDT liquidity on Curve, stake the LP tokens in Convex and compound the CRV and CVX rewards every week";
+        let strategies = vec![strategy("Stable LP", "yield", "low", long), strategy("SOL DCA", "trading", "medium", "Buy $50 of SOL weekly")];
+
+        assert_eq!(
+            render_strategies(&strategies),
+            "Your strategies (2):\n\
+            Name       Category  Risk    Description\n\
+            ---------  --------  ------  ------------------------------------------------------------\n\
+            Stable LP  yield     low     Provide USDC/USDT liquidity on Curve, stake the LP tokens i…\n\
+            SOL DCA    trading   medium  Buy $50 of SOL weekly"
+        );
+        assert_eq!(render_strategies(&[]), "You don't have any saved strategies yet.");
+    }
+
+    #[test]
+    fn test_render_knowledge_hides_locked_content() {
+        let entry = |source_id: &str, content: &str, tags: &[&str]| Knowledge {
+            id: 0,
+            user_id: 1,
+            source_id: source_id.to_string(),
+            content: content.to_string(),
+            tags: tags.iter().map(|tag| tag.to_string()).collect(),
+            created_at: timestamp(8),
+            updated_at: timestamp(8),
+        };
+        let locked = Vault::new("correct horse").with_cost(4).seal("cold wallet holds 12 BTC").unwrap();
+        let entries = vec![
+            entry("aerodrome-docs", "Aerodrome is a Base DEX.\nMore lines.", &["defi"]),
+            entry("wallets", &locked, &["private"]),
+        ];
+
+        let output = render_knowledge(&entries);
+        assert!(output.starts_with("Your knowledge (2):\n"));
+        assert!(output.contains("aerodrome-docs  defi     Aerodrome is a Base DEX.\n"));
+        assert!(output.contains("wallets         private  (locked)"));
+        assert!(!output.contains("12 BTC") && !output.contains("More lines"));
+        assert!(output.ends_with("1 private entries are locked, set KNOWLEDGE_PASSPHRASE or a key file to read them."));
+        assert_eq!(render_knowledge(&[]), "You don't have any knowledge entries yet.");
+    }
+
+    #[test]
+    fn test_render_empty_portfolio() {
+        assert!(render_portfolio(&[]).contains("portfolio is empty"));
+    }
+
+    #[test]
+    fn test_parse_purge_date() {
+        assert_eq!(parse_purge_date("2025-09-20").unwrap(), timestamp(0));
+        assert!(matches!(parse_purge_date("20-09-2025"), Err(InvestmentChatError::InvalidInput(_))));
+    }
+
+    #[test]
+    fn test_parse_history_count_is_bounded() {
+        assert_eq!(parse_history_count("25").unwrap(), 25);
+        assert_eq!(parse_history_count("1000000").unwrap(), MAX_HISTORY_MESSAGES);
+        for value in ["0", "-5", "ten", "99999999999"] {
+            assert!(matches!(parse_history_count(value), Err(InvestmentChatError::InvalidInput(_))), "{}", value);
+        }
+    }
+
+    #[test]
+    fn test_render_purge_preview_asks_for_confirmation() {
+        let output = render_purge_preview("2025-09-20", 12);
+        assert!(output.starts_with("12 messages from before 2025-09-20 will be summarized"));
+        assert!(output.ends_with("Run /history purge 2025-09-20 confirm to continue."));
+        assert_eq!(render_purge_preview("2025-09-20", 0), "No messages before 2025-09-20 to archive.");
+    }
+
+    #[test]
+    fn test_delete_user_files_without_strategy_dir() {
+        let dir = std::env::temp_dir().join(format!("agent-friend-missing-{}", uuid::Uuid::new_v4()));
+        assert_eq!(delete_user_files(&dir, "alice").unwrap(), 0);
+        assert!(!dir.exists());
+    }
+
+    #[test]
+    fn test_render_history_is_chronological() {
+        let messages = vec![
+            Message { id: 2, user_id: Some(1), role: MessageRole::Assistant, content: "Hello!".to_string(), created_at: timestamp(10), truncated: false },
+            Message { id: 1, user_id: Some(1), role: MessageRole::User, content: "Hi".to_string(), created_at: timestamp(9), truncated: false },
+        ];
+
+        let output = render_history(&messages, UserTimezone::default());
+        let user_pos = output.find("user:\nHi").unwrap();
+        let assistant_pos = output.find("assistant:\nHello!").unwrap();
+        assert!(user_pos < assistant_pos);
+
+        // Stored in UTC, shown where the user is
+        let output = render_history(&messages, UserTimezone::Named(chrono_tz::America::New_York));
+        assert!(output.starts_with("Recent conversation (times in America/New_York):\n"));
+        assert!(output.contains("[2025-09-20 05:00] user:\nHi"), "{}", output);
+    }
+}
@@ -0,0 +1,226 @@
+use crate::investment_chat::{Intent, TurnResult};
+use regex::Regex;
+use std::sync::OnceLock;
+
+/// Footer ending every investment answer in compliance mode
+pub const DISCLAIMER: &str = "_Not financial advice: this is general information only and doesn't take your \
+circumstances or local rules into account. Crypto assets are volatile and you can lose what you put in. Consider a \
+licensed professional before making decisions._";
+
+/// Standing instructions added to the advisor prompt in compliance mode
+pub const PROMPT_INSTRUCTIONS: &str = "COMPLIANCE: You are talking to members of the public in an unknown \
+jurisdiction. Give general, educational information only. Never tell the user to buy, sell or hold a specific \
+asset, never give position sizes, amounts or portfolio percentages for them, and never offer to place trades. Do \
+not assume any country's laws or tax rules apply. Do not add a disclaimer of your own, one is appended to every \
+answer.\n\n";
+
+/// Disclaimer lines longer than this are kept, they carry more than the disclaimer
+const MAX_DISCLAIMER_LINE: usize = 300;
+
+/// Guardrails for deployments where the agent answers other people
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ComplianceSettings {
+    /// On for every user
+    pub enabled: bool,
+    /// Usernames it is on for when it isn't on for everyone
+    pub users: Vec<String>,
+}
+
+impl ComplianceSettings {
+    /// Whether answers to `username` follow the compliance rules
+    pub fn applies_to(&self, username: &str) -> bool {
+        self.enabled || self.users.iter().any(|name| name.eq_ignore_ascii_case(username))
+    }
+}
+
+/// Why an intent isn't answered in compliance mode, None for intents that are
+pub fn blocked_reply(intent: Intent) -> Option<&'static str> {
+    match intent {
+        Intent::PositionSizing => Some(
+            "Position sizing is turned off here: I can't suggest how much of a coin to buy or how large a position \
+             to take. I can explain how sizing from the share of an account you're willing to risk works in general.",
+        ),
+        Intent::Rebalance => Some(
+            "Rebalancing trades are turned off here: I can't work out buy and sell amounts for your portfolio or \
+             place trades. I can explain how rebalancing to target weights works in general.",
+        ),
+        Intent::Trade => Some(
+            "Trading is turned off here: I can't swap tokens or place orders for you. I can explain how swaps and \
+             limit orders work in general.",
+        ),
+        _ => None,
+    }
+}
+
+/// Replace an answer compliance mode doesn't allow with the reason it isn't given
+pub fn gate(result: TurnResult) -> TurnResult {
+    match blocked_reply(result.intent) {
+        Some(reply) => TurnResult::new(result.intent, reply),
+        None => result,
+    }
+}
+
+/// Whether answers of `intent` are about investments and end with the disclaimer
+pub fn needs_disclaimer(intent: Intent) -> bool {
+    match intent {
+        Intent::Offline
+        | Intent::ScopedQuestion
+        | Intent::Sentiment
+        | Intent::PriceMove
+        | Intent::Diversification
+        | Intent::ImpermanentLoss
+        | Intent::Scenario
+        | Intent::EntryComparison
+        | Intent::TrackRecord
+        | Intent::CoinCard
+        | Intent::Category
+        | Intent::Unlocks
+        | Intent::Derivatives
+        | Intent::Price
+        | Intent::General
+        | Intent::MultiPart => true,
+        Intent::Preference
+        | Intent::Feedback
+        | Intent::Undo
+        | Intent::Alias
+        | Intent::Watchlist
+        | Intent::StrategyProgress
+        | Intent::StoredData
+        | Intent::Topics
+        | Intent::TradeHistory
+        | Intent::Profile
+        | Intent::Calculation
+        | Intent::Dca
+        | Intent::PositionSizing
+        | Intent::Rebalance
+        | Intent::Trade
+        | Intent::Gas
+        | Intent::PortfolioValue
+        | Intent::StrategyCreation
+        | Intent::Failed => false,
+    }
+}
+
+/// `text` ending with the standard disclaimer exactly once
+///
+/// Disclaimers the model wrote on lines of their own ("Disclaimer: ...", "*This is not
+/// financial advice.*") are removed first, as is a standard footer already there, so
+/// answers never carry two. A disclaimer opening a line that goes on to say more
+/// ("not financial advice, but ...") is part of the answer and stays.
+pub fn with_disclaimer(text: &str) -> String {
+    let mut kept: Vec<&str> = Vec::new();
+    for line in text.lines() {
+        if is_disclaimer_line(line) {
+            continue;
+        }
+        // Removing a disclaimer paragraph leaves a single blank line behind
+        if line.trim().is_empty() && kept.last().is_none_or(|last| last.trim().is_empty()) {
+            continue;
+        }
+        kept.push(line);
+    }
+    let body = kept.join("\n");
+    let body = body.trim_end();
+    if body.is_empty() {
+        DISCLAIMER.to_string()
+    } else {
+        format!("{}\n\n{}", body, DISCLAIMER)
+    }
+}
+
+fn is_disclaimer_line(line: &str) -> bool {
+    static DISCLAIMER_START: OnceLock<Regex> = OnceLock::new();
+    let disclaimer_start = DISCLAIMER_START.get_or_init(|| {
+        Regex::new(
+            r"(?i)^(?:disclaimer\b|(?:please\s+)?note\b[:*_]*\s[^.]*\bnot\s+(?:financial|investment)\s+advice|(?:remember,?\s+)?(?:this|that|the\s+above|this\s+(?:answer|response|information|analysis)|none\s+of\s+this)\s+(?:is|does)\s+not\s+(?:constitute\s+)?(?:financial|investment)\s+advice|not\s+(?:financial|investment)\s+advice\b|nfa\b)",
+        )
+        .unwrap()
+    });
+
+    let line = line.trim();
+    if line == DISCLAIMER {
+        return true;
+    }
+    let stripped = line.trim_start_matches(['*', '_', '>', '-', '#', '(', '⚠', '️', ' ']);
+    stripped.len() <= MAX_DISCLAIMER_LINE
+        && disclaimer_start.is_match(stripped)
+        && !stripped.to_lowercase().contains(" but ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::investment_chat::TurnData;
+
+    #[test]
+    fn test_applies_to() {
+        let off = ComplianceSettings::default();
+        assert!(!off.applies_to("alice"));
+
+        let everyone = ComplianceSettings { enabled: true, users: vec![] };
+        assert!(everyone.applies_to("alice"));
+
+        let listed = ComplianceSettings { enabled: false, users: vec!["Guest".to_string()] };
+        assert!(listed.applies_to("guest"));
+        assert!(!listed.applies_to("alice"));
+    }
+
+    #[test]
+    fn test_gate_blocks_sizing_and_trades() {
+        let sizing = TurnResult::new(Intent::PositionSizing, "Buy 1.92 SOL ($250.00)");
+        let gated = gate(sizing);
+        assert_eq!(gated.intent, Intent::PositionSizing);
+        assert!(gated.text.starts_with("Position sizing is turned off here"));
+        assert!(!gated.text.contains("1.92"));
+
+        let rebalance = TurnResult::new(Intent::Rebalance, "Sell 0.1 BTC").with_data(TurnData::Rebalance {
+            total_usd: 10_000.0,
+            legs: vec![],
+            estimated_fees_usd: None,
+        });
+        let gated = gate(rebalance);
+        assert!(gated.text.starts_with("Rebalancing trades are turned off here"));
+        assert_eq!(gated.data, None);
+
+        let swap = gate(TurnResult::new(Intent::Trade, "Swap 0.1 ETH for about 250 USDC"));
+        assert!(swap.text.starts_with("Trading is turned off here"));
+
+        let price = TurnResult::new(Intent::Price, "BTC is $60,000.00");
+        assert_eq!(gate(price.clone()), price);
+        assert!(needs_disclaimer(Intent::General));
+        assert!(!needs_disclaimer(Intent::PositionSizing));
+        assert!(!needs_disclaimer(Intent::Watchlist));
+    }
+
+    #[test]
+    fn test_disclaimer_is_appended_once() {
+        let answer = "ETH staking yields about 3% a year.";
+        assert_eq!(with_disclaimer(answer), format!("{}\n\n{}", answer, DISCLAIMER));
+        // Already ending with the footer, nothing changes
+        assert_eq!(with_disclaimer(&with_disclaimer(answer)), with_disclaimer(answer));
+
+        for model_disclaimer in [
+            "Disclaimer: I am not a financial advisor.",
+            "*This is not financial advice. Always do your own research.*",
+            "**Note:** this is not financial advice.",
+            "_Remember, this does not constitute investment advice._",
+            "NFA, DYOR.",
+            "⚠️ Not financial advice.",
+        ] {
+            let text = format!("{}\n\n{}\n", answer, model_disclaimer);
+            assert_eq!(with_disclaimer(&text), format!("{}\n\n{}", answer, DISCLAIMER), "{}", model_disclaimer);
+        }
+
+        // A disclaimer in the middle leaves the paragraphs around it apart
+        let text = "Staking locks ETH.\n\nDisclaimer: not advice.\n\nUnstaking takes days.";
+        assert_eq!(with_disclaimer(text), format!("Staking locks ETH.\n\nUnstaking takes days.\n\n{}", DISCLAIMER));
+
+        // Lines that say more than the disclaimer are part of the answer
+        let text = "Not financial advice, but the unlock in March adds 3% to the supply.";
+        assert_eq!(with_disclaimer(text), format!("{}\n\n{}", text, DISCLAIMER));
+        let text = "Notes on fees: this is not financial advice territory, swaps cost 0.3%.";
+        assert!(with_disclaimer(text).starts_with(text));
+
+        assert_eq!(with_disclaimer("This is not financial advice."), DISCLAIMER);
+    }
+}
@@ -0,0 +1,219 @@
+use crate::db::{OrderType, Trade};
+use crate::trade_import::USD_QUOTES;
+use std::collections::BTreeMap;
+
+/// Amounts below this are treated as an emptied position
+const DUST: f64 = 1e-12;
+
+/// What a user holds of a coin according to their trades, and what it cost
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Position {
+    pub amount: f64,
+    /// USD paid for the amount held, fees included
+    pub cost_usd: f64,
+    /// USD gained or lost on sells, after fees
+    pub realized_pnl_usd: f64,
+    /// Amount sold beyond what the trades bought, e.g. coins bought before the imported history starts
+    pub unmatched_sold: f64,
+}
+
+impl Position {
+    /// USD paid per coin held, None when nothing is held
+    pub fn average_cost(&self) -> Option<f64> {
+        (self.amount > DUST).then(|| self.cost_usd / self.amount)
+    }
+
+    /// Gain or loss of the amount held at `price`
+    pub fn unrealized_pnl(&self, price: f64) -> Option<f64> {
+        self.average_cost().map(|average| (price - average) * self.amount)
+    }
+
+    fn buy(&mut self, amount: f64, price: f64, fee: f64) {
+        self.amount += amount;
+        self.cost_usd += amount * price + fee;
+    }
+
+    // Sells take the average cost out of the position, what they fetch above it is realized
+    fn sell(&mut self, amount: f64, price: f64, fee: f64) {
+        let matched = amount.min(self.amount);
+        let average = self.average_cost().unwrap_or(0.0);
+        self.realized_pnl_usd += matched * (price - average) - fee;
+        self.unmatched_sold += amount - matched;
+        self.cost_usd -= average * matched;
+        self.amount -= matched;
+        if self.amount <= DUST {
+            self.amount = 0.0;
+            self.cost_usd = 0.0;
+        }
+    }
+}
+
+/// Average-cost positions by coin id, replaying `trades` in execution order
+///
+/// Failed trades and swaps not confirmed on chain moved nothing, and trades priced in another coin have
+/// no USD cost, so they are left out.
+pub fn positions(trades: &[Trade]) -> BTreeMap<String, Position> {
+    let mut ordered: Vec<&Trade> = trades
+        .iter()
+        .filter(|trade| trade.status.is_filled() && USD_QUOTES.contains(&trade.quote.as_str()))
+        .collect();
+    ordered.sort_by_key(|trade| (trade.executed_at, trade.id));
+
+    let mut positions: BTreeMap<String, Position> = BTreeMap::new();
+    for trade in ordered {
+        let position = positions.entry(trade.coin_id.clone()).or_default();
+        match trade.side {
+            OrderType::Buy => position.buy(trade.amount, trade.price, trade.fee),
+            OrderType::Sell => position.sell(trade.amount, trade.price, trade.fee),
+        }
+    }
+    positions
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::TradeStatus;
+    use chrono::{NaiveDate, NaiveDateTime};
+
+    fn at(day: u32) -> NaiveDateTime {
+        NaiveDate::from_ymd_opt(2025, 1, day).unwrap().and_hms_opt(12, 0, 0).unwrap()
+    }
+
+    fn trade(id: i32, day: u32, side: OrderType, coin_id: &str, amount: f64, price: f64, fee: f64) -> Trade {
+        Trade {
+            id,
+            user_id: 1,
+            executed_at: at(day),
+            side,
+            coin_id: coin_id.to_string(),
+            quote: "USD".to_string(),
+            amount,
+            price,
+            fee,
+            imported: true,
+            created_at: at(day),
+            tx_hash: None,
+            from_token: None,
+            to_token: None,
+            from_amount: None,
+            to_amount: None,
+            price_usd_at_execution: Some(price),
+            gas_cost: None,
+            status: TradeStatus::Filled,
+            aggregator: None,
+            block_number: None,
+            gas_used: None,
+        }
+    }
+
+    fn assert_close(actual: f64, expected: f64) {
+        assert!((actual - expected).abs() < 1e-9, "{} != {}", actual, expected);
+    }
+
+    #[test]
+    fn test_buys_average_their_prices_and_fees() {
+        let trades = [
+            trade(1, 1, OrderType::Buy, "bitcoin", 1.0, 40_000.0, 10.0),
+            trade(2, 2, OrderType::Buy, "bitcoin", 1.0, 50_000.0, 10.0),
+        ];
+        let position = positions(&trades)["bitcoin"];
+        assert_close(position.amount, 2.0);
+        assert_close(position.cost_usd, 90_020.0);
+        assert_close(position.average_cost().unwrap(), 45_010.0);
+        assert_close(position.unrealized_pnl(50_000.0).unwrap(), 9_980.0);
+    }
+
+    #[test]
+    fn test_sells_realize_against_the_average_cost() {
+        let trades = [
+            trade(1, 1, OrderType::Buy, "ethereum", 2.0, 1_000.0, 0.0),
+            trade(2, 2, OrderType::Buy, "ethereum", 2.0, 2_000.0, 0.0),
+            trade(3, 3, OrderType::Sell, "ethereum", 1.0, 3_000.0, 5.0),
+        ];
+        let position = positions(&trades)["ethereum"];
+        // Average 1500: (3000 - 1500) * 1 - 5
+        assert_close(position.realized_pnl_usd, 1_495.0);
+        assert_close(position.amount, 3.0);
+        // Selling doesn't move the average of what's left
+        assert_close(position.average_cost().unwrap(), 1_500.0);
+
+        // A later buy averages in with what's left
+        let mut trades = trades.to_vec();
+        trades.push(trade(4, 4, OrderType::Buy, "ethereum", 1.0, 3_500.0, 0.0));
+        assert_close(positions(&trades)["ethereum"].average_cost().unwrap(), 2_000.0);
+    }
+
+    #[test]
+    fn test_trades_are_replayed_in_execution_order() {
+        // The sell is stored first but executed after both buys
+        let trades = [
+            trade(3, 5, OrderType::Sell, "bitcoin", 1.0, 30_000.0, 0.0),
+            trade(1, 1, OrderType::Buy, "bitcoin", 1.0, 10_000.0, 0.0),
+            trade(2, 2, OrderType::Buy, "bitcoin", 1.0, 20_000.0, 0.0),
+        ];
+        let position = positions(&trades)["bitcoin"];
+        assert_close(position.realized_pnl_usd, 15_000.0);
+        assert_close(position.unmatched_sold, 0.0);
+        assert_close(position.average_cost().unwrap(), 15_000.0);
+    }
+
+    #[test]
+    fn test_selling_everything_empties_the_position() {
+        let trades = [
+            trade(1, 1, OrderType::Buy, "solana", 3.0, 100.0, 0.3),
+            trade(2, 2, OrderType::Sell, "solana", 3.0, 90.0, 0.3),
+        ];
+        let position = positions(&trades)["solana"];
+        assert_close(position.amount, 0.0);
+        assert_close(position.cost_usd, 0.0);
+        assert_close(position.realized_pnl_usd, -30.6);
+        assert_eq!(position.average_cost(), None);
+        assert_eq!(position.unrealized_pnl(90.0), None);
+    }
+
+    #[test]
+    fn test_overselling_is_kept_apart() {
+        let trades = [
+            trade(1, 1, OrderType::Buy, "bitcoin", 1.0, 10_000.0, 0.0),
+            trade(2, 2, OrderType::Sell, "bitcoin", 1.5, 12_000.0, 0.0),
+        ];
+        let position = positions(&trades)["bitcoin"];
+        // Only the coin bought in the history is realized, the rest has no known cost
+        assert_close(position.realized_pnl_usd, 2_000.0);
+        assert_close(position.unmatched_sold, 0.5);
+        assert_close(position.amount, 0.0);
+    }
+
+    #[test]
+    fn test_failed_and_non_usd_trades_are_left_out() {
+        let failed = Trade { status: TradeStatus::Failed, ..trade(2, 2, OrderType::Buy, "bitcoin", 1.0, 20_000.0, 0.0) };
+        let in_eth = Trade { quote: "ETH".to_string(), ..trade(3, 3, OrderType::Buy, "bitcoin", 1.0, 15.0, 0.0) };
+        let trades = [trade(1, 1, OrderType::Buy, "bitcoin", 1.0, 10_000.0, 0.0), failed, in_eth];
+        let position = positions(&trades)["bitcoin"];
+        assert_close(position.amount, 1.0);
+        assert_close(position.average_cost().unwrap(), 10_000.0);
+    }
+
+    #[test]
+    fn test_swaps_count_once_confirmed() {
+        let confirmed = Trade { status: TradeStatus::Confirmed, ..trade(1, 1, OrderType::Buy, "ethereum", 2.0, 2_000.0, 0.0) };
+        let pending = Trade { status: TradeStatus::Pending, ..trade(2, 2, OrderType::Buy, "ethereum", 1.0, 2_600.0, 0.0) };
+        let stuck = Trade { status: TradeStatus::Stuck, ..trade(3, 3, OrderType::Sell, "ethereum", 1.0, 2_700.0, 0.0) };
+        let position = positions(&[confirmed, pending, stuck])["ethereum"];
+        assert_close(position.amount, 2.0);
+        assert_close(position.average_cost().unwrap(), 2_000.0);
+    }
+
+    #[test]
+    fn test_coins_are_tracked_separately() {
+        let trades = [
+            trade(1, 1, OrderType::Buy, "bitcoin", 1.0, 10_000.0, 0.0),
+            trade(2, 1, OrderType::Buy, "ethereum", 4.0, 500.0, 0.0),
+        ];
+        let positions = positions(&trades);
+        assert_eq!(positions.keys().collect::<Vec<_>>(), vec!["bitcoin", "ethereum"]);
+        assert_close(positions["ethereum"].average_cost().unwrap(), 500.0);
+        assert!(super::positions(&[]).is_empty());
+    }
+}
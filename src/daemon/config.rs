@@ -0,0 +1,303 @@
+use super::DaemonError;
+use crate::timezone;
+use chrono::NaiveTime;
+use serde::Deserialize;
+use std::fs;
+use std::path::Path;
+use std::time::Duration;
+
+/// Names of the engines the daemon knows how to run
+pub const PRICE_WATCHER: &str = "price_watcher";
+pub const DATA_SOURCES: &str = "data_sources";
+pub const RETENTION: &str = "retention";
+pub const BRIEFING: &str = "briefing";
+pub const GAS_WATCHER: &str = "gas_watcher";
+pub const WEEKLY_REPORT: &str = "weekly_report";
+pub const ENGINE_NAMES: &[&str] = &[PRICE_WATCHER, DATA_SOURCES, RETENTION, BRIEFING, GAS_WATCHER, WEEKLY_REPORT];
+
+/// Settings for the long-running daemon, read from the `[daemon]` section of agent.toml
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct DaemonConfig {
+    /// Seconds between heartbeat log lines
+    pub heartbeat_interval_secs: u64,
+    /// Address the /healthz endpoint listens on
+    pub healthz_addr: String,
+    pub price_watcher: PriceWatcherConfig,
+    pub data_sources: EngineConfig,
+    pub retention: RetentionConfig,
+    pub briefing: BriefingConfig,
+    /// Records gas prices so gas answers can compare against the past day
+    pub gas_watcher: EngineConfig,
+    /// Writes each user's report of the week that just ended to `REPORT_DIR`
+    pub weekly_report: EngineConfig,
+}
+
+/// Common settings shared by every engine
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct EngineConfig {
+    pub enabled: bool,
+    pub interval_secs: u64,
+}
+
+/// Settings for the price watcher engine
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct PriceWatcherConfig {
+    pub enabled: bool,
+    pub interval_secs: u64,
+    /// Percentage move since the last stored price that fires an alert
+    pub alert_threshold_pct: f64,
+}
+
+/// Settings for the message retention engine
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct RetentionConfig {
+    pub enabled: bool,
+    pub interval_secs: u64,
+    /// Messages older than this are summarized and archived
+    pub retention_days: i64,
+}
+
+/// Settings for the daily briefing engine
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct BriefingConfig {
+    pub enabled: bool,
+    /// Seconds between checks for a briefing that is due
+    pub interval_secs: u64,
+    /// Time of day each user's briefing is generated in their own timezone, as HH:MM or like "every day at 8am"
+    pub time: String,
+}
+
+impl Default for DaemonConfig {
+    fn default() -> Self {
+        Self {
+            heartbeat_interval_secs: 60,
+            healthz_addr: "127.0.0.1:8787".to_string(),
+            price_watcher: PriceWatcherConfig::default(),
+            data_sources: EngineConfig {
+                enabled: true,
+                interval_secs: 3600,
+            },
+            retention: RetentionConfig::default(),
+            briefing: BriefingConfig::default(),
+            // Needs an RPC node, so it only runs once configured
+            gas_watcher: EngineConfig {
+                enabled: false,
+                interval_secs: 300,
+            },
+            weekly_report: EngineConfig {
+                enabled: false,
+                interval_secs: 3600,
+            },
+        }
+    }
+}
+
+impl Default for EngineConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            interval_secs: 300,
+        }
+    }
+}
+
+impl Default for RetentionConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            interval_secs: 86400,
+            retention_days: crate::retention::DEFAULT_RETENTION_DAYS,
+        }
+    }
+}
+
+impl Default for BriefingConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            interval_secs: 300,
+            time: "07:00".to_string(),
+        }
+    }
+}
+
+impl BriefingConfig {
+    /// Parse the configured time of day
+    pub fn local_time(&self) -> Result<NaiveTime, DaemonError> {
+        timezone::parse_daily_time(&self.time).ok_or_else(|| {
+            DaemonError::Configuration(format!("Invalid briefing time '{}', expected HH:MM or \"every day at 8am\"", self.time))
+        })
+    }
+}
+
+impl Default for PriceWatcherConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            interval_secs: 300,
+            alert_threshold_pct: 5.0,
+        }
+    }
+}
+
+// agent.toml may hold other sections, only `[daemon]` is read here
+#[derive(Debug, Default, Deserialize)]
+struct AgentFile {
+    #[serde(default)]
+    daemon: DaemonConfig,
+}
+
+impl DaemonConfig {
+    /// Parse the `[daemon]` section from the contents of agent.toml
+    pub fn from_toml_str(contents: &str) -> Result<Self, DaemonError> {
+        let file: AgentFile = toml::from_str(contents)
+            .map_err(|e| DaemonError::Configuration(e.to_string()))?;
+        Ok(file.daemon)
+    }
+
+    /// Load the daemon settings from a file, using defaults if it does not exist
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, DaemonError> {
+        let path = path.as_ref();
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+
+        let contents = fs::read_to_string(path)?;
+        Self::from_toml_str(&contents)
+    }
+
+    /// Run only the named engines, disabling every other one
+    pub fn only_engines(&mut self, names: &[String]) -> Result<(), DaemonError> {
+        if let Some(unknown) = names.iter().find(|name| !ENGINE_NAMES.contains(&name.as_str())) {
+            return Err(DaemonError::Configuration(format!(
+                "Unknown engine '{}', expected one of: {}",
+                unknown,
+                ENGINE_NAMES.join(", ")
+            )));
+        }
+
+        self.price_watcher.enabled = names.iter().any(|name| name == PRICE_WATCHER);
+        self.data_sources.enabled = names.iter().any(|name| name == DATA_SOURCES);
+        self.retention.enabled = names.iter().any(|name| name == RETENTION);
+        self.briefing.enabled = names.iter().any(|name| name == BRIEFING);
+        self.gas_watcher.enabled = names.iter().any(|name| name == GAS_WATCHER);
+        self.weekly_report.enabled = names.iter().any(|name| name == WEEKLY_REPORT);
+        Ok(())
+    }
+
+    /// Names of the engines that will be started
+    pub fn enabled_engines(&self) -> Vec<&'static str> {
+        let mut engines = Vec::new();
+        if self.price_watcher.enabled {
+            engines.push(PRICE_WATCHER);
+        }
+        if self.data_sources.enabled {
+            engines.push(DATA_SOURCES);
+        }
+        if self.retention.enabled {
+            engines.push(RETENTION);
+        }
+        if self.briefing.enabled {
+            engines.push(BRIEFING);
+        }
+        if self.gas_watcher.enabled {
+            engines.push(GAS_WATCHER);
+        }
+        if self.weekly_report.enabled {
+            engines.push(WEEKLY_REPORT);
+        }
+        engines
+    }
+
+    pub fn heartbeat_interval(&self) -> Duration {
+        Duration::from_secs(self.heartbeat_interval_secs.max(1))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_missing_section_uses_defaults() {
+        let config = DaemonConfig::from_toml_str("[other]\nkey = 1\n").unwrap();
+        assert_eq!(config, DaemonConfig::default());
+        assert_eq!(config.enabled_engines(), vec![PRICE_WATCHER, DATA_SOURCES]);
+    }
+
+    #[test]
+    fn test_toml_toggles_engines_and_intervals() {
+        let config = DaemonConfig::from_toml_str(
+            r#"
+            [daemon]
+            heartbeat_interval_secs = 30
+            healthz_addr = "0.0.0.0:9000"
+
+            [daemon.price_watcher]
+            interval_secs = 60
+            alert_threshold_pct = 2.5
+
+            [daemon.data_sources]
+            enabled = false
+
+            [daemon.retention]
+            enabled = true
+            retention_days = 30
+
+            [daemon.briefing]
+            enabled = true
+            time = "06:30"
+
+            [daemon.gas_watcher]
+            interval_secs = 120
+
+            [daemon.weekly_report]
+            enabled = true
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(config.heartbeat_interval_secs, 30);
+        assert_eq!(config.healthz_addr, "0.0.0.0:9000");
+        assert_eq!(config.price_watcher.interval_secs, 60);
+        assert_eq!(config.price_watcher.alert_threshold_pct, 2.5);
+        assert_eq!(config.data_sources.interval_secs, 300);
+        assert_eq!(config.retention.retention_days, 30);
+        assert_eq!(config.retention.interval_secs, 86400);
+        assert_eq!(config.briefing.local_time().unwrap(), NaiveTime::from_hms_opt(6, 30, 0).unwrap());
+        assert_eq!(config.gas_watcher.interval_secs, 120);
+        assert_eq!(config.weekly_report.interval_secs, 300);
+        assert_eq!(config.enabled_engines(), vec![PRICE_WATCHER, RETENTION, BRIEFING, GAS_WATCHER, WEEKLY_REPORT]);
+    }
+
+    #[test]
+    fn test_invalid_briefing_time() {
+        let config = DaemonConfig::from_toml_str("[daemon.briefing]\ntime = \"breakfast\"\n").unwrap();
+        assert!(matches!(config.briefing.local_time(), Err(DaemonError::Configuration(_))));
+        let config = DaemonConfig::from_toml_str("[daemon.briefing]\ntime = \"7\"\n").unwrap();
+        assert!(matches!(config.briefing.local_time(), Err(DaemonError::Configuration(_))));
+    }
+
+    #[test]
+    fn test_briefing_time_as_a_schedule() {
+        let config = DaemonConfig::from_toml_str("[daemon.briefing]\ntime = \"every day at 7am\"\n").unwrap();
+        assert_eq!(config.briefing.local_time().unwrap(), NaiveTime::from_hms_opt(7, 0, 0).unwrap());
+    }
+
+    #[test]
+    fn test_only_engines_overrides_file() {
+        let mut config = DaemonConfig::default();
+        config.only_engines(&[DATA_SOURCES.to_string()]).unwrap();
+        assert_eq!(config.enabled_engines(), vec![DATA_SOURCES]);
+
+        config.only_engines(&[BRIEFING.to_string(), RETENTION.to_string(), PRICE_WATCHER.to_string()]).unwrap();
+        assert_eq!(config.enabled_engines(), vec![PRICE_WATCHER, RETENTION, BRIEFING]);
+
+        assert!(config.only_engines(&["dca".to_string()]).is_err());
+    }
+}
@@ -0,0 +1,42 @@
+use crate::briefing::BriefingError;
+use crate::data_source::DataSourceError;
+use crate::db::DbError;
+use crate::gas::GasError;
+use crate::price_fetcher::PriceError;
+use crate::report::ReportError;
+use crate::retention::RetentionError;
+use thiserror::Error;
+
+/// Errors raised while configuring or running the daemon
+#[derive(Debug, Error)]
+pub enum DaemonError {
+    #[error("Configuration error: {0}")]
+    Configuration(String),
+
+    #[error("Database error: {0}")]
+    Database(#[from] DbError),
+
+    #[error("Price API error: {0}")]
+    PriceApi(#[from] PriceError),
+
+    #[error("Data source error: {0}")]
+    DataSource(#[from] DataSourceError),
+
+    #[error("Retention error: {0}")]
+    Retention(#[from] RetentionError),
+
+    #[error("Briefing error: {0}")]
+    Briefing(#[from] BriefingError),
+
+    #[error("Report error: {0}")]
+    Report(#[from] ReportError),
+
+    #[error("Gas oracle error: {0}")]
+    Gas(#[from] GasError),
+
+    #[error("Engine error: {0}")]
+    Engine(String),
+
+    #[error("IO error: {0}")]
+    Io(#[from] std::io::Error),
+}
@@ -0,0 +1,382 @@
+mod config;
+mod engines;
+mod error;
+
+pub use config::*;
+pub use engines::*;
+pub use error::*;
+
+use crate::circuit_breaker::{self, BreakerSnapshot};
+use crate::health::{HealthChecker, SystemHealth};
+use async_trait::async_trait;
+use axum::{Json, Router, extract::State, routing::get};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::future::Future;
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant};
+use tokio::net::TcpListener;
+use tokio::sync::watch;
+use tokio::task::JoinHandle;
+use tracing::{error, info};
+
+/// A background subsystem run on a fixed interval by the daemon
+#[async_trait]
+pub trait Engine: Send {
+    /// Name used in logs and the health report
+    fn name(&self) -> &'static str;
+
+    /// Time between two ticks
+    fn interval(&self) -> Duration;
+
+    /// Run one round of work
+    async fn tick(&mut self) -> Result<(), DaemonError>;
+}
+
+/// Health information for a single engine
+#[derive(Debug, Clone, Serialize)]
+pub struct EngineStatus {
+    pub name: String,
+    pub interval_secs: u64,
+    pub runs: u64,
+    pub failures: u64,
+    pub last_run: Option<DateTime<Utc>>,
+    pub last_error: Option<String>,
+}
+
+/// Body returned by `GET /healthz`
+#[derive(Debug, Clone, Serialize)]
+pub struct HealthReport {
+    pub status: &'static str,
+    pub uptime_secs: u64,
+    pub engines: Vec<EngineStatus>,
+    /// Probes of the database and integrations, absent when the daemon has no health checker
+    pub integrations: Option<SystemHealth>,
+    /// Current state and counters of the circuit breakers, never cached
+    pub breakers: Vec<BreakerSnapshot>,
+}
+
+/// Probe results are reused for this long, so frequent polling doesn't hammer the APIs
+const INTEGRATIONS_CACHE: Duration = Duration::from_secs(30);
+
+#[derive(Clone)]
+struct HealthState {
+    started: Instant,
+    engines: Arc<RwLock<Vec<EngineStatus>>>,
+    checker: Option<Arc<HealthChecker>>,
+    integrations: Arc<tokio::sync::Mutex<Option<(Instant, SystemHealth)>>>,
+}
+
+impl HealthState {
+    fn report(&self) -> HealthReport {
+        HealthReport {
+            status: "ok",
+            uptime_secs: self.started.elapsed().as_secs(),
+            engines: self.engines.read().unwrap().clone(),
+            integrations: None,
+            breakers: circuit_breaker::snapshots(),
+        }
+    }
+
+    // Holding the lock while probing makes concurrent requests share one round of probes
+    async fn integrations(&self) -> Option<SystemHealth> {
+        let checker = self.checker.as_ref()?;
+        let mut cached = self.integrations.lock().await;
+        if let Some((checked, health)) = cached.as_ref()
+            && checked.elapsed() < INTEGRATIONS_CACHE
+        {
+            return Some(health.clone());
+        }
+        let health = checker.check().await;
+        *cached = Some((Instant::now(), health.clone()));
+        Some(health)
+    }
+}
+
+/// Host process for the background engines
+pub struct Daemon {
+    config: DaemonConfig,
+    engines: Vec<Box<dyn Engine>>,
+    checker: Option<HealthChecker>,
+}
+
+impl Daemon {
+    pub fn new(config: DaemonConfig, engines: Vec<Box<dyn Engine>>) -> Self {
+        Self { config, engines, checker: None }
+    }
+
+    /// Include the probes of this checker in `GET /healthz`
+    pub fn with_health_checker(mut self, checker: HealthChecker) -> Self {
+        self.checker = Some(checker);
+        self
+    }
+
+    /// Bind the health endpoint and run until `shutdown` resolves
+    pub async fn run(self, shutdown: impl Future<Output = ()> + Send + 'static) -> Result<(), DaemonError> {
+        let listener = TcpListener::bind(&self.config.healthz_addr).await?;
+        self.serve(listener, shutdown).await
+    }
+
+    /// Run the engines, heartbeat and health endpoint on an existing listener
+    pub async fn serve(self, listener: TcpListener, shutdown: impl Future<Output = ()> + Send + 'static) -> Result<(), DaemonError> {
+        let state = HealthState {
+            started: Instant::now(),
+            engines: Arc::new(RwLock::new(
+                self.engines
+                    .iter()
+                    .map(|engine| EngineStatus {
+                        name: engine.name().to_string(),
+                        interval_secs: engine.interval().as_secs(),
+                        runs: 0,
+                        failures: 0,
+                        last_run: None,
+                        last_error: None,
+                    })
+                    .collect(),
+            )),
+            checker: self.checker.map(Arc::new),
+            integrations: Arc::new(tokio::sync::Mutex::new(None)),
+        };
+
+        let (stop_tx, stop_rx) = watch::channel(false);
+        let mut tasks: Vec<JoinHandle<()>> = Vec::new();
+
+        for (index, engine) in self.engines.into_iter().enumerate() {
+            info!("Starting engine {} (every {:?})", engine.name(), engine.interval());
+            tasks.push(tokio::spawn(run_engine(engine, index, state.engines.clone(), stop_rx.clone())));
+        }
+        tasks.push(tokio::spawn(run_heartbeat(
+            self.config.heartbeat_interval(),
+            state.clone(),
+            stop_rx.clone(),
+        )));
+
+        info!("Health endpoint listening on http://{}/healthz", listener.local_addr()?);
+        let app = Router::new().route("/healthz", get(healthz)).with_state(state);
+        let mut server_stop = stop_rx.clone();
+        let server = tokio::spawn(async move {
+            axum::serve(listener, app)
+                .with_graceful_shutdown(async move {
+                    let _ = server_stop.wait_for(|stopped| *stopped).await;
+                })
+                .await
+        });
+
+        shutdown.await;
+        info!("Shutting down daemon");
+        let _ = stop_tx.send(true);
+
+        for task in tasks {
+            if let Err(e) = task.await {
+                error!("Daemon task failed: {}", e);
+            }
+        }
+        match server.await {
+            Ok(result) => result?,
+            Err(e) => error!("Health endpoint task failed: {}", e),
+        }
+
+        info!("Daemon stopped");
+        Ok(())
+    }
+}
+
+async fn healthz(State(state): State<HealthState>) -> Json<HealthReport> {
+    let mut report = state.report();
+    report.integrations = state.integrations().await;
+    Json(report)
+}
+
+async fn run_engine(
+    mut engine: Box<dyn Engine>,
+    index: usize,
+    statuses: Arc<RwLock<Vec<EngineStatus>>>,
+    mut stop: watch::Receiver<bool>,
+) {
+    let mut interval = tokio::time::interval(engine.interval());
+    interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+
+    loop {
+        tokio::select! {
+            _ = stop.wait_for(|stopped| *stopped) => break,
+            _ = interval.tick() => {}
+        }
+
+        // Let an in-flight tick finish so writes are not cut off halfway
+        let result = engine.tick().await;
+
+        let mut statuses = statuses.write().unwrap();
+        let status = &mut statuses[index];
+        status.runs += 1;
+        status.last_run = Some(Utc::now());
+        match result {
+            Ok(()) => status.last_error = None,
+            Err(e) => {
+                error!("Engine {} failed: {}", engine.name(), e);
+                status.failures += 1;
+                status.last_error = Some(e.to_string());
+            }
+        }
+    }
+
+    info!("Engine {} stopped", engine.name());
+}
+
+async fn run_heartbeat(every: Duration, state: HealthState, mut stop: watch::Receiver<bool>) {
+    let mut interval = tokio::time::interval(every);
+
+    loop {
+        tokio::select! {
+            _ = stop.wait_for(|stopped| *stopped) => break,
+            _ = interval.tick() => {}
+        }
+
+        let report = state.report();
+        let engines: Vec<String> = report
+            .engines
+            .iter()
+            .map(|engine| format!("{}: {} runs, {} failures", engine.name, engine.runs, engine.failures))
+            .collect();
+        info!("Heartbeat: up {}s [{}]", report.uptime_secs, engines.join("; "));
+    }
+}
+
+/// Resolve on Ctrl-C, or SIGTERM on Unix
+pub async fn shutdown_signal() {
+    let ctrl_c = async {
+        if let Err(e) = tokio::signal::ctrl_c().await {
+            error!("Failed to listen for Ctrl-C: {}", e);
+            std::future::pending::<()>().await;
+        }
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()) {
+            Ok(mut signal) => {
+                signal.recv().await;
+            },
+            Err(e) => {
+                error!("Failed to listen for SIGTERM: {}", e);
+                std::future::pending::<()>().await;
+            }
+        }
+    };
+
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => {},
+        _ = terminate => {},
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicU64, Ordering};
+
+    struct CountingEngine {
+        ticks: Arc<AtomicU64>,
+        fail: bool,
+    }
+
+    #[async_trait]
+    impl Engine for CountingEngine {
+        fn name(&self) -> &'static str {
+            if self.fail { "failing" } else { "counting" }
+        }
+
+        fn interval(&self) -> Duration {
+            Duration::from_millis(10)
+        }
+
+        async fn tick(&mut self) -> Result<(), DaemonError> {
+            self.ticks.fetch_add(1, Ordering::SeqCst);
+            if self.fail {
+                return Err(DaemonError::Engine("boom".to_string()));
+            }
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_daemon_runs_engines_and_shuts_down() {
+        let ticks = Arc::new(AtomicU64::new(0));
+        let engines: Vec<Box<dyn Engine>> = vec![
+            Box::new(CountingEngine { ticks: ticks.clone(), fail: false }),
+            Box::new(CountingEngine { ticks: Arc::new(AtomicU64::new(0)), fail: true }),
+        ];
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let (shutdown_tx, shutdown_rx) = tokio::sync::oneshot::channel::<()>();
+        let daemon = tokio::spawn(Daemon::new(DaemonConfig::default(), engines).serve(listener, async {
+            let _ = shutdown_rx.await;
+        }));
+
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        let report: serde_json::Value = reqwest::get(format!("http://{}/healthz", addr))
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+
+        assert_eq!(report["status"], "ok");
+        assert_eq!(report["engines"][0]["name"], "counting");
+        assert!(report["engines"][0]["runs"].as_u64().unwrap() > 0);
+        assert_eq!(report["engines"][0]["failures"], 0);
+        assert_eq!(report["engines"][1]["last_error"], "Engine error: boom");
+
+        shutdown_tx.send(()).unwrap();
+        tokio::time::timeout(Duration::from_secs(5), daemon).await.unwrap().unwrap().unwrap();
+
+        // No more ticks once the daemon has stopped
+        let stopped_at = ticks.load(Ordering::SeqCst);
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert_eq!(ticks.load(Ordering::SeqCst), stopped_at);
+    }
+
+    struct FlakyRpc;
+
+    #[async_trait]
+    impl crate::health::Probe for FlakyRpc {
+        fn name(&self) -> &'static str {
+            "rpc"
+        }
+
+        async fn check(&self) -> Result<Option<String>, crate::health::ProbeFailure> {
+            Err(crate::health::ProbeFailure::Degraded("RPC returned status 503".to_string()))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_healthz_includes_integrations() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let (shutdown_tx, shutdown_rx) = tokio::sync::oneshot::channel::<()>();
+        let checker = HealthChecker::new(vec![Arc::new(FlakyRpc)]);
+        let daemon = Daemon::new(DaemonConfig::default(), Vec::new()).with_health_checker(checker);
+        let daemon = tokio::spawn(daemon.serve(listener, async {
+            let _ = shutdown_rx.await;
+        }));
+
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        let report: serde_json::Value = reqwest::get(format!("http://{}/healthz", addr))
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+
+        assert_eq!(report["status"], "ok");
+        assert_eq!(report["integrations"]["status"], "degraded");
+        assert_eq!(report["integrations"]["probes"][0]["name"], "rpc");
+        assert_eq!(report["integrations"]["probes"][0]["last_error"], "RPC returned status 503");
+
+        shutdown_tx.send(()).unwrap();
+        tokio::time::timeout(Duration::from_secs(5), daemon).await.unwrap().unwrap().unwrap();
+    }
+}
@@ -26,4 +26,32 @@ pub enum DbError {
     
     #[error("Database serialization error: {0}")]
     Serialization(String),
+    
+    #[error("Invalid message role: {0}")]
+    InvalidRole(String),
+    
+    #[error("Invalid verbosity: {0}")]
+    InvalidVerbosity(String),
+    
+    #[error("Invalid topic kind: {0}")]
+    InvalidTopicKind(String),
+    
+    #[error("Invalid order type: {0}")]
+    InvalidOrderType(String),
+    
+    #[error("Invalid order status: {0}")]
+    InvalidOrderStatus(String),
+    
+    #[error("Invalid trade status: {0}")]
+    InvalidTradeStatus(String),
+    
+    #[error("Knowledge encryption error: {0}")]
+    Encryption(#[from] crate::vault::VaultError),
+}
+
+impl DbError {
+    /// Whether the database was unreachable rather than the statement wrong, so retrying can succeed
+    pub fn is_transient(&self) -> bool {
+        matches!(self, DbError::Connection(_) | DbError::Pool(_))
+    }
 }
@@ -2,10 +2,15 @@ mod models;
 mod queries;
 mod error;
 
+#[cfg(test)]
+pub(crate) mod testing;
+
 pub use models::*;
 pub use queries::*;
 pub use error::*;
 
+use crate::config::AGENT_CONFIG_PATH;
+use crate::setup::AgentSettings;
 use sqlx::{postgres::PgPoolOptions, Pool, Postgres};
 use std::env;
 use std::sync::OnceLock;
@@ -20,9 +25,17 @@ pub async fn init_db_pool() -> Result<&'static Pool<Postgres>, DbError> {
         return Ok(DB_POOL.get().unwrap());
     }
 
-    let database_url = env::var("DATABASE_URL").map_err(|_| {
-        DbError::Configuration("DATABASE_URL environment variable not set".to_string())
-    })?;
+    // Fall back to the URL saved by the setup wizard
+    let database_url = env::var("DATABASE_URL")
+        .ok()
+        .or_else(|| {
+            AgentSettings::load(AGENT_CONFIG_PATH)
+                .ok()
+                .and_then(|settings| settings.database.url)
+        })
+        .ok_or_else(|| {
+            DbError::Configuration("DATABASE_URL environment variable not set".to_string())
+        })?;
 
     let pool = PgPoolOptions::new()
         .max_connections(10)
@@ -52,10 +65,7 @@ pub async fn get_db_pool() -> Result<&'static Pool<Postgres>, DbError> {
 /// Check if the database is connected
 pub async fn is_db_connected() -> bool {
     if let Some(pool) = DB_POOL.get() {
-        match sqlx::query("SELECT 1").execute(pool).await {
-            Ok(_) => true,
-            Err(_) => false,
-        }
+        sqlx::query("SELECT 1").execute(pool).await.is_ok()
     } else {
         false
     }
@@ -1,5 +1,8 @@
 use serde::{Deserialize, Serialize};
-use sqlx::{types::JsonValue, FromRow, types::chrono::{NaiveDateTime}};
+use sqlx::{types::JsonValue, FromRow, types::chrono::{NaiveDate, NaiveDateTime}};
+use std::fmt;
+use std::str::FromStr;
+use super::DbError;
 
 /// User model
 #[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
@@ -7,10 +10,20 @@ pub struct User {
     pub id: i32,
     pub username: String,
     pub wallet_address: Option<String>,
+    #[sqlx(try_from = "String")]
+    #[serde(default)]
+    pub verbosity: Verbosity,
+    /// UTC offset like "UTC+02:00" or IANA name like "Europe/Berlin", plain "UTC" by default
+    #[serde(default = "default_timezone")]
+    pub timezone: String,
     pub created_at: NaiveDateTime,
     pub updated_at: NaiveDateTime,
 }
 
+fn default_timezone() -> String {
+    "UTC".to_string()
+}
+
 /// Strategy model
 #[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
 pub struct Strategy {
@@ -43,6 +56,33 @@ pub struct Knowledge {
     pub updated_at: NaiveDateTime,
 }
 
+/// A knowledge entry to insert
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct KnowledgeInput {
+    pub source_id: String,
+    pub content: String,
+    pub tags: Vec<String>,
+}
+
+/// What a knowledge batch does when a source id already exists for the user
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ConflictMode {
+    /// Insert nothing and return a constraint error
+    #[default]
+    FailAll,
+    /// Insert the other entries and report the conflicting source ids
+    Skip,
+}
+
+/// Outcome of a knowledge batch
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct KnowledgeBatch {
+    /// Ids of the inserted rows, in input order
+    pub created: Vec<i32>,
+    /// Source ids that already existed or repeated an earlier entry of the batch
+    pub skipped: Vec<String>,
+}
+
 /// Data source model
 #[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
 pub struct DataSource {
@@ -59,11 +99,776 @@ pub struct DataSource {
     pub last_refresh: Option<NaiveDateTime>,
 }
 
+/// Author of a stored message, kept in the `role` column as its lowercase name
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MessageRole {
+    User,
+    Assistant,
+    System,
+    Tool,
+}
+
+impl MessageRole {
+    /// Canonical name stored in the database
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            MessageRole::User => "user",
+            MessageRole::Assistant => "assistant",
+            MessageRole::System => "system",
+            MessageRole::Tool => "tool",
+        }
+    }
+    
+    /// Label used when rendering history into a prompt
+    pub fn label(&self) -> &'static str {
+        match self {
+            MessageRole::User => "USER",
+            MessageRole::Assistant => "ASSISTANT",
+            MessageRole::System => "SYSTEM",
+            MessageRole::Tool => "TOOL",
+        }
+    }
+}
+
+impl fmt::Display for MessageRole {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for MessageRole {
+    type Err = DbError;
+    
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "user" => Ok(MessageRole::User),
+            "assistant" => Ok(MessageRole::Assistant),
+            "system" => Ok(MessageRole::System),
+            "tool" => Ok(MessageRole::Tool),
+            _ => Err(DbError::InvalidRole(s.to_string())),
+        }
+    }
+}
+
+impl TryFrom<String> for MessageRole {
+    type Error = DbError;
+    
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+/// How long answers should be, kept in the `verbosity` column of `users` as its lowercase name
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Verbosity {
+    Brief,
+    #[default]
+    Normal,
+    Detailed,
+}
+
+impl Verbosity {
+    /// Canonical name stored in the database
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Verbosity::Brief => "brief",
+            Verbosity::Normal => "normal",
+            Verbosity::Detailed => "detailed",
+        }
+    }
+}
+
+impl fmt::Display for Verbosity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for Verbosity {
+    type Err = DbError;
+    
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "brief" => Ok(Verbosity::Brief),
+            "normal" => Ok(Verbosity::Normal),
+            "detailed" => Ok(Verbosity::Detailed),
+            _ => Err(DbError::InvalidVerbosity(s.to_string())),
+        }
+    }
+}
+
+impl TryFrom<String> for Verbosity {
+    type Error = DbError;
+    
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
 /// Message model
 #[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
 pub struct Message {
     pub id: i32,
-    pub role: String,
+    pub user_id: Option<i32>,
+    #[sqlx(try_from = "String")]
+    pub role: MessageRole,
     pub content: String,
     pub created_at: NaiveDateTime,
+    /// An answer whose stream stopped before it finished, saved with what had arrived
+    #[sqlx(default)]
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub truncated: bool,
+}
+
+/// Summary of a stretch of conversation, written before those messages are archived
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct ConversationSummary {
+    pub id: i32,
+    pub user_id: i32,
+    pub period_start: NaiveDateTime,
+    pub period_end: NaiveDateTime,
+    pub message_count: i32,
+    pub summary: String,
+    pub created_at: NaiveDateTime,
+}
+
+/// Price history model
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct PricePoint {
+    pub id: i32,
+    pub coin_id: String,
+    pub price_usd: f64,
+    pub fetched_at: NaiveDateTime,
+}
+
+/// Gas price recorded by the daemon's gas watcher
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct GasReading {
+    pub id: i32,
+    pub base_fee_gwei: f64,
+    pub priority_fee_gwei: f64,
+    pub taken_at: NaiveDateTime,
+}
+
+/// Holding model
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct Holding {
+    pub id: i32,
+    pub user_id: i32,
+    pub coin_id: String,
+    pub amount: f64,
+    pub created_at: NaiveDateTime,
+    pub updated_at: NaiveDateTime,
+}
+
+/// Notification model
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct Notification {
+    pub id: i32,
+    pub user_id: i32,
+    pub kind: String,
+    pub message: String,
+    pub created_at: NaiveDateTime,
+    pub read_at: Option<NaiveDateTime>,
+}
+
+/// A user's own name for a coin or project, stored lowercase
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct UserAlias {
+    pub id: i32,
+    pub user_id: i32,
+    pub alias: String,
+    pub target: String,
+    pub created_at: NaiveDateTime,
+}
+
+/// A coin the user follows without holding it
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct WatchlistEntry {
+    pub id: i32,
+    pub user_id: i32,
+    pub coin_id: String,
+    pub note: Option<String>,
+    pub added_at: NaiveDateTime,
+}
+
+/// A user's rating of an assistant message, 1 for good and -1 for bad
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct Feedback {
+    pub id: i32,
+    pub user_id: i32,
+    pub message_id: i32,
+    pub score: i16,
+    pub reason: Option<String>,
+    pub created_at: NaiveDateTime,
+}
+
+/// A rating of an answer that the knowledge entry `source_id` was injected into
+#[derive(Debug, Clone, PartialEq, FromRow)]
+pub struct SourceRating {
+    pub source_id: String,
+    pub score: i16,
+    pub rated_at: NaiveDateTime,
+}
+
+/// A name with the number of rows it covers, e.g. a knowledge tag
+#[derive(Debug, Clone, PartialEq, Eq, FromRow)]
+pub struct NamedCount {
+    pub name: String,
+    pub count: i64,
+}
+
+/// What a topic of a message is, kept in the `kind` column of `message_topics`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TopicKind {
+    /// A project or coin, by the name the project extractor gives it
+    Coin,
+    /// One of the investment keywords, e.g. "staking"
+    Keyword,
+}
+
+impl TopicKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TopicKind::Coin => "coin",
+            TopicKind::Keyword => "keyword",
+        }
+    }
+}
+
+impl FromStr for TopicKind {
+    type Err = DbError;
+    
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "coin" => Ok(TopicKind::Coin),
+            "keyword" => Ok(TopicKind::Keyword),
+            _ => Err(DbError::InvalidTopicKind(s.to_string())),
+        }
+    }
+}
+
+impl TryFrom<String> for TopicKind {
+    type Error = DbError;
+    
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+/// A coin or keyword detected in a user message
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct MessageTopic {
+    pub id: i32,
+    pub user_id: i32,
+    pub message_id: i32,
+    pub topic: String,
+    #[sqlx(try_from = "String")]
+    pub kind: TopicKind,
+    pub created_at: NaiveDateTime,
+}
+
+/// How many messages mentioned a topic
+#[derive(Debug, Clone, PartialEq, Eq, FromRow)]
+pub struct TopicCount {
+    pub topic: String,
+    #[sqlx(try_from = "String")]
+    pub kind: TopicKind,
+    pub messages: i64,
+}
+
+/// What one chat turn sent to the model, recorded while debug capture is on
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct TurnDebug {
+    pub id: i32,
+    pub user_id: i32,
+    /// The user's question, None when it was queued instead of saved
+    pub message_id: Option<i32>,
+    pub question: String,
+    pub intent: String,
+    /// The capture as JSON, sealed like private knowledge when a knowledge key was loaded
+    pub payload: String,
+    pub created_at: NaiveDateTime,
+}
+
+/// A trade staged in the chat, waiting for the user to confirm it
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct PendingTrade {
+    pub user_id: i32,
+    /// What to execute, as the chat staged it
+    pub trade: sqlx::types::JsonValue,
+    pub created_at: NaiveDateTime,
+}
+
+/// Side of a limit order or trade, kept in the `order_type` column of `limit_orders` and `side` of `trades`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OrderType {
+    Buy,
+    Sell,
+}
+
+impl OrderType {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            OrderType::Buy => "buy",
+            OrderType::Sell => "sell",
+        }
+    }
+}
+
+impl FromStr for OrderType {
+    type Err = DbError;
+    
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "buy" => Ok(OrderType::Buy),
+            "sell" => Ok(OrderType::Sell),
+            _ => Err(DbError::InvalidOrderType(s.to_string())),
+        }
+    }
+}
+
+impl TryFrom<String> for OrderType {
+    type Error = DbError;
+    
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+/// Where a limit order is, kept in the `status` column of `limit_orders`
+/// Only open orders change status, filled and cancelled are final
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OrderStatus {
+    Open,
+    Filled,
+    Cancelled,
+    /// Open past its `expires_at`
+    Expired,
+}
+
+impl OrderStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            OrderStatus::Open => "open",
+            OrderStatus::Filled => "filled",
+            OrderStatus::Cancelled => "cancelled",
+            OrderStatus::Expired => "expired",
+        }
+    }
+}
+
+impl fmt::Display for OrderStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for OrderStatus {
+    type Err = DbError;
+    
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "open" => Ok(OrderStatus::Open),
+            "filled" => Ok(OrderStatus::Filled),
+            "cancelled" => Ok(OrderStatus::Cancelled),
+            "expired" => Ok(OrderStatus::Expired),
+            _ => Err(DbError::InvalidOrderStatus(s.to_string())),
+        }
+    }
+}
+
+impl TryFrom<String> for OrderStatus {
+    type Error = DbError;
+    
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+/// A limit order staged by the trading client
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct LimitOrder {
+    pub id: String,
+    pub user_id: i32,
+    #[sqlx(try_from = "String")]
+    pub order_type: OrderType,
+    /// Token the order spends: USDC for buys, WETH for sells
+    pub token_address: String,
+    pub amount: f64,
+    pub price: f64,
+    #[sqlx(try_from = "String")]
+    pub status: OrderStatus,
+    pub created_at: NaiveDateTime,
+    pub updated_at: NaiveDateTime,
+    /// When an open order stops being good, None for one good until cancelled
+    #[serde(default)]
+    pub expires_at: Option<NaiveDateTime>,
+}
+
+/// Whether a trade went through, kept in the `status` column of `trades`
+///
+/// Imported trades and limit order fills are filled. A swap sent from the wallet is pending until
+/// it is mined, then confirmed or failed, or stuck when it isn't mined in time.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TradeStatus {
+    Filled,
+    Failed,
+    Pending,
+    Confirmed,
+    Stuck,
+}
+
+impl TradeStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TradeStatus::Filled => "filled",
+            TradeStatus::Failed => "failed",
+            TradeStatus::Pending => "pending",
+            TradeStatus::Confirmed => "confirmed",
+            TradeStatus::Stuck => "stuck",
+        }
+    }
+    
+    /// Whether the trade moved coins: filled, or a swap confirmed on chain
+    pub fn is_filled(&self) -> bool {
+        matches!(self, TradeStatus::Filled | TradeStatus::Confirmed)
+    }
+}
+
+impl fmt::Display for TradeStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for TradeStatus {
+    type Err = DbError;
+    
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "filled" => Ok(TradeStatus::Filled),
+            "failed" => Ok(TradeStatus::Failed),
+            "pending" => Ok(TradeStatus::Pending),
+            "confirmed" => Ok(TradeStatus::Confirmed),
+            "stuck" => Ok(TradeStatus::Stuck),
+            _ => Err(DbError::InvalidTradeStatus(s.to_string())),
+        }
+    }
+}
+
+impl TryFrom<String> for TradeStatus {
+    type Error = DbError;
+    
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+/// A buy or sell of a coin, imported from an exchange or executed from the wallet
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct Trade {
+    pub id: i32,
+    pub user_id: i32,
+    pub executed_at: NaiveDateTime,
+    #[sqlx(try_from = "String")]
+    pub side: OrderType,
+    pub coin_id: String,
+    /// USD or the stablecoin the trade was priced in
+    pub quote: String,
+    pub amount: f64,
+    /// Quote units per coin
+    pub price: f64,
+    /// In quote units
+    pub fee: f64,
+    /// Whether the row came from an exchange export rather than a trade made here
+    pub imported: bool,
+    pub created_at: NaiveDateTime,
+    /// Swap transaction, None for imported trades, limit order fills and swaps that were never sent
+    pub tx_hash: Option<String>,
+    /// Token addresses and whole-token amounts of a swap
+    pub from_token: Option<String>,
+    pub to_token: Option<String>,
+    pub from_amount: Option<f64>,
+    pub to_amount: Option<f64>,
+    /// USD price of the coin when the trade was made, None when the quote isn't in dollars
+    pub price_usd_at_execution: Option<f64>,
+    /// In ETH
+    pub gas_cost: Option<f64>,
+    #[sqlx(try_from = "String")]
+    pub status: TradeStatus,
+    /// DEX aggregator that routed a swap, e.g. "1inch" or "0x"
+    #[serde(default)]
+    pub aggregator: Option<String>,
+    /// Block that mined a swap and the gas it used, once it is confirmed or failed
+    #[serde(default)]
+    pub block_number: Option<i64>,
+    #[serde(default)]
+    pub gas_used: Option<i64>,
+}
+
+/// A trade to store
+#[derive(Debug, Clone, PartialEq)]
+pub struct NewTrade {
+    pub executed_at: NaiveDateTime,
+    pub side: OrderType,
+    pub coin_id: String,
+    pub quote: String,
+    pub amount: f64,
+    pub price: f64,
+    pub fee: f64,
+    pub tx_hash: Option<String>,
+    pub from_token: Option<String>,
+    pub to_token: Option<String>,
+    pub from_amount: Option<f64>,
+    pub to_amount: Option<f64>,
+    pub price_usd_at_execution: Option<f64>,
+    pub gas_cost: Option<f64>,
+    pub status: TradeStatus,
+    pub aggregator: Option<String>,
+}
+
+impl NewTrade {
+    /// A filled trade known only by its side, coin, amount and price, like a row of an exchange export
+    pub fn filled(executed_at: NaiveDateTime, side: OrderType, coin_id: &str, quote: &str, amount: f64, price: f64, fee: f64) -> Self {
+        Self {
+            executed_at,
+            side,
+            coin_id: coin_id.to_string(),
+            quote: quote.to_string(),
+            amount,
+            price,
+            fee,
+            tx_hash: None,
+            from_token: None,
+            to_token: None,
+            from_amount: None,
+            to_amount: None,
+            price_usd_at_execution: None,
+            gas_cost: None,
+            status: TradeStatus::Filled,
+            aggregator: None,
+        }
+    }
+}
+
+/// One step of a strategy being worked through
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, FromRow)]
+pub struct StrategyProgress {
+    /// `strategies.id`, not the strategy's file id
+    pub strategy_id: i32,
+    pub user_id: i32,
+    /// Position in `Strategy::steps`, from 0
+    pub step_index: i32,
+    pub completed_at: Option<NaiveDateTime>,
+    pub note: Option<String>,
+}
+
+/// One chat session, with the `/system set` instructions it answers under
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct Conversation {
+    pub id: i32,
+    pub user_id: i32,
+    pub system_prompt_override: Option<String>,
+    pub started_at: NaiveDateTime,
+}
+
+/// How a finished run of a strategy went, kept for reviewing its performance
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct StrategyOutcome {
+    pub id: i32,
+    pub strategy_id: i32,
+    pub user_id: i32,
+    pub note: String,
+    pub created_at: NaiveDateTime,
+}
+
+/// What happened to one strategy within a stretch of time, for the weekly report
+#[derive(Debug, Clone, PartialEq, Eq, FromRow)]
+pub struct StrategyActivity {
+    pub name: String,
+    pub created: bool,
+    /// Edited after it was created
+    pub updated: bool,
+    pub steps_completed: i64,
+    pub outcomes_noted: i64,
+}
+
+/// A run of messages without a long pause, with the question that opened it
+#[derive(Debug, Clone, PartialEq, Eq, FromRow)]
+pub struct ConversationDigest {
+    pub started_at: NaiveDateTime,
+    pub message_count: i64,
+    /// None when the conversation has no user message
+    pub opening: Option<String>,
+}
+
+/// Knowledge entries of one user with the same content, the most recently updated kept
+#[derive(Debug, Clone)]
+pub struct DuplicateKnowledge {
+    pub kept: Knowledge,
+    /// Removed by a dedupe, their tags merged into the kept entry
+    pub duplicates: Vec<Knowledge>,
+}
+
+/// Rows and on-disk size of a table, indexes included
+#[derive(Debug, Clone, PartialEq, Eq, FromRow)]
+pub struct TableStats {
+    pub name: String,
+    pub rows: i64,
+    pub bytes: i64,
+}
+
+/// A knowledge entry's source and when it was stored
+#[derive(Debug, Clone, PartialEq, Eq, FromRow)]
+pub struct KnowledgeStamp {
+    pub source_id: String,
+    pub created_at: NaiveDateTime,
+}
+
+/// What's stored for a user, as shown by `/stats data`
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DataStats {
+    pub knowledge_count: i64,
+    /// Most used first, cut to the requested number of tags
+    pub knowledge_tags: Vec<NamedCount>,
+    pub distinct_tags: i64,
+    pub oldest_knowledge: Option<KnowledgeStamp>,
+    pub newest_knowledge: Option<KnowledgeStamp>,
+    pub strategy_count: i64,
+    /// Most used first, cut to the requested number of categories
+    pub strategy_categories: Vec<NamedCount>,
+    pub distinct_categories: i64,
+    pub strategy_risk_levels: Vec<NamedCount>,
+    pub message_count: i64,
+    pub archived_message_count: i64,
+    /// Stretches of live messages without a long pause
+    pub conversation_count: i64,
+    pub longest_conversation: i64,
+    /// Bytes stored per table
+    pub storage: Vec<NamedCount>,
+}
+
+/// A concrete call Nova made, e.g. accumulate ethereum between $2300 and $2450
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct Recommendation {
+    pub id: i32,
+    pub user_id: i32,
+    pub coin_id: String,
+    /// One of accumulate, buy, take_profit or sell
+    pub action: String,
+    pub price_low: f64,
+    pub price_high: f64,
+    pub target_date: Option<NaiveDate>,
+    pub made_at: NaiveDateTime,
+}
+
+/// Everything stored for a user, written by `/account export`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UserDataExport {
+    pub exported_at: NaiveDateTime,
+    pub user: User,
+    /// Live and archived messages, oldest first
+    pub messages: Vec<Message>,
+    pub conversation_summaries: Vec<ConversationSummary>,
+    pub strategies: Vec<Strategy>,
+    pub knowledge: Vec<Knowledge>,
+    pub data_sources: Vec<DataSource>,
+    pub holdings: Vec<Holding>,
+    pub notifications: Vec<Notification>,
+    pub aliases: Vec<UserAlias>,
+    #[serde(default)]
+    pub watchlist: Vec<WatchlistEntry>,
+    #[serde(default)]
+    pub recommendations: Vec<Recommendation>,
+    #[serde(default)]
+    pub feedback: Vec<Feedback>,
+    #[serde(default)]
+    pub topics: Vec<MessageTopic>,
+    #[serde(default)]
+    pub debug_turns: Vec<TurnDebug>,
+    #[serde(default)]
+    pub limit_orders: Vec<LimitOrder>,
+    #[serde(default)]
+    pub strategy_progress: Vec<StrategyProgress>,
+    #[serde(default)]
+    pub strategy_outcomes: Vec<StrategyOutcome>,
+    #[serde(default)]
+    pub trades: Vec<Trade>,
+    #[serde(default)]
+    pub conversations: Vec<Conversation>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::{self, testing};
+
+    // Migration that adds the CHECK constraint on messages.role
+    const ROLE_CHECK_MIGRATION: i64 = 20250922090000;
+
+    #[test]
+    fn test_message_role_round_trip() {
+        for role in [MessageRole::User, MessageRole::Assistant, MessageRole::System, MessageRole::Tool] {
+            assert_eq!(role.to_string().parse::<MessageRole>().unwrap(), role);
+        }
+        assert_eq!(" Assistant ".parse::<MessageRole>().unwrap(), MessageRole::Assistant);
+        assert_eq!(MessageRole::Assistant.label(), "ASSISTANT");
+        assert!(matches!("assisstant".parse::<MessageRole>(), Err(DbError::InvalidRole(role)) if role == "assisstant"));
+        assert_eq!(serde_json::to_string(&MessageRole::Tool).unwrap(), "\"tool\"");
+    }
+
+    #[test]
+    fn test_verbosity_round_trip() {
+        for verbosity in [Verbosity::Brief, Verbosity::Normal, Verbosity::Detailed] {
+            assert_eq!(verbosity.to_string().parse::<Verbosity>().unwrap(), verbosity);
+        }
+        assert_eq!(Verbosity::default(), Verbosity::Normal);
+        assert!(matches!("terse".parse::<Verbosity>(), Err(DbError::InvalidVerbosity(value)) if value == "terse"));
+    }
+
+    #[tokio::test]
+    async fn test_role_constraint_rejects_unknown_roles() {
+        let Some(pool) = testing::test_pool().await else {
+            return;
+        };
+
+        db::save_message(&pool, 1, MessageRole::Assistant, "hello").await.unwrap();
+        let messages = db::get_messages(&pool, 1, 10).await.unwrap();
+        assert_eq!(messages[0].role, MessageRole::Assistant);
+
+        let result = sqlx::query("INSERT INTO messages (role, content) VALUES ('assisstant', 'typo')")
+            .execute(&pool)
+            .await;
+        assert!(result.unwrap_err().to_string().contains("messages_role_check"));
+    }
+
+    #[tokio::test]
+    async fn test_migration_maps_unexpected_roles() {
+        let Some(pool) = testing::test_pool_migrated_to(ROLE_CHECK_MIGRATION - 1).await else {
+            return;
+        };
+
+        sqlx::query("INSERT INTO messages (role, content) VALUES (' Assistant', 'a'), ('assisstant', 'b'), ('tool', 'c')")
+            .execute(&pool)
+            .await
+            .unwrap();
+        testing::run_migrations_after(&pool, ROLE_CHECK_MIGRATION - 1).await;
+
+        let roles: Vec<(String, String)> = sqlx::query_as("SELECT content, role FROM messages ORDER BY content")
+            .fetch_all(&pool)
+            .await
+            .unwrap();
+        assert_eq!(roles, vec![
+            ("a".to_string(), "assistant".to_string()),
+            ("b".to_string(), "user".to_string()),
+            ("c".to_string(), "tool".to_string()),
+        ]);
+    }
 }
@@ -1,9 +1,12 @@
-use super::{DbError, User, Strategy, Knowledge, Message};
-use sqlx::{Pool, Postgres, query, query_as};
+use super::{DbError, User, Strategy, Knowledge, KnowledgeInput, KnowledgeBatch, ConflictMode, DataSource, Message, MessageRole, Verbosity, Conversation, ConversationSummary, PricePoint, GasReading, Holding, Notification, UserAlias, UserDataExport, WatchlistEntry, Recommendation, DataStats, NamedCount, KnowledgeStamp, Feedback, SourceRating, DuplicateKnowledge, TableStats, TopicKind, MessageTopic, TopicCount, TurnDebug, LimitOrder, PendingTrade, OrderType, OrderStatus, Trade, NewTrade, TradeStatus, StrategyProgress, StrategyOutcome, StrategyActivity, ConversationDigest};
+use sqlx::{Pool, Postgres, QueryBuilder, query, query_as, query_scalar};
+use std::collections::{HashMap, HashSet};
+use sqlx::types::chrono::{NaiveDate, NaiveDateTime};
+use crate::vault;
 
 // User queries
 pub async fn get_user_by_username(pool: &Pool<Postgres>, username: &str) -> Result<Option<User>, DbError> {
-    query_as::<_, User>("SELECT id, username, wallet_address, created_at, updated_at FROM users WHERE username = $1")
+    query_as::<_, User>("SELECT id, username, wallet_address, verbosity, timezone, created_at, updated_at FROM users WHERE username = $1")
         .bind(username)
         .fetch_optional(pool)
         .await
@@ -11,15 +14,22 @@ pub async fn get_user_by_username(pool: &Pool<Postgres>, username: &str) -> Resu
 }
 
 pub async fn get_user_by_id(pool: &Pool<Postgres>, user_id: i32) -> Result<Option<User>, DbError> {
-    query_as::<_, User>("SELECT id, username, wallet_address, created_at, updated_at FROM users WHERE id = $1")
+    query_as::<_, User>("SELECT id, username, wallet_address, verbosity, timezone, created_at, updated_at FROM users WHERE id = $1")
         .bind(user_id)
         .fetch_optional(pool)
         .await
         .map_err(|e| DbError::Query(e.to_string()))
 }
 
+pub async fn get_all_users(pool: &Pool<Postgres>) -> Result<Vec<User>, DbError> {
+    query_as::<_, User>("SELECT id, username, wallet_address, verbosity, timezone, created_at, updated_at FROM users ORDER BY id")
+        .fetch_all(pool)
+        .await
+        .map_err(|e| DbError::Query(e.to_string()))
+}
+
 pub async fn create_user(pool: &Pool<Postgres>, username: &str, wallet_address: Option<&str>) -> Result<User, DbError> {
-    query_as::<_, User>("INSERT INTO users (username, wallet_address) VALUES ($1, $2) RETURNING id, username, wallet_address, created_at, updated_at")
+    query_as::<_, User>("INSERT INTO users (username, wallet_address) VALUES ($1, $2) RETURNING id, username, wallet_address, verbosity, timezone, created_at, updated_at")
         .bind(username)
         .bind(wallet_address)
         .fetch_one(pool)
@@ -27,11 +37,11 @@ pub async fn create_user(pool: &Pool<Postgres>, username: &str, wallet_address:
         .map_err(|e| DbError::Query(e.to_string()))
 }
 
-// Message queries
-pub async fn save_message(pool: &Pool<Postgres>, role: &str, content: &str) -> Result<(), DbError> {
-    query("INSERT INTO messages (role, content) VALUES ($1, $2)")
-        .bind(role)
-        .bind(content)
+/// Remember the user's timezone, a UTC offset like "UTC+02:00" or an IANA name like "Europe/Berlin"
+pub async fn set_user_timezone(pool: &Pool<Postgres>, user_id: i32, timezone: &str) -> Result<(), DbError> {
+    query("UPDATE users SET timezone = $2, updated_at = now() WHERE id = $1")
+        .bind(user_id)
+        .bind(timezone)
         .execute(pool)
         .await
         .map_err(|e| DbError::Query(e.to_string()))?;
@@ -39,140 +49,2615 @@ pub async fn save_message(pool: &Pool<Postgres>, role: &str, content: &str) -> R
     Ok(())
 }
 
-pub async fn get_messages(pool: &Pool<Postgres>, limit: i64) -> Result<Vec<Message>, DbError> {
-    query_as::<_, Message>("SELECT id, role, content, created_at FROM messages ORDER BY created_at DESC LIMIT $1")
-        .bind(limit)
-        .fetch_all(pool)
+/// Remember how long the user wants answers to be
+pub async fn set_user_verbosity(pool: &Pool<Postgres>, user_id: i32, verbosity: Verbosity) -> Result<(), DbError> {
+    query("UPDATE users SET verbosity = $2, updated_at = now() WHERE id = $1")
+        .bind(user_id)
+        .bind(verbosity.as_str())
+        .execute(pool)
         .await
-        .map_err(|e| DbError::Query(e.to_string()))
+        .map_err(|e| DbError::Query(e.to_string()))?;
+    
+    Ok(())
 }
 
-// Knowledge queries
-pub async fn create_knowledge(
+// Message queries
+pub async fn save_message(pool: &Pool<Postgres>, user_id: i32, role: MessageRole, content: &str) -> Result<(), DbError> {
+    query("INSERT INTO messages (user_id, role, content) VALUES ($1, $2, $3)")
+        .bind(user_id)
+        .bind(role.as_str())
+        .bind(content)
+        .execute(pool)
+        .await
+        .map_err(write_error)?;
+    
+    Ok(())
+}
+
+/// Save an assistant message with the system prompt variant that wrote it, None for the default prompt,
+/// and the source ids of the knowledge injected into its prompt
+///
+/// A `truncated` answer is marked `"truncated": true` in its metadata so the next prompt can say so.
+pub async fn save_assistant_message(
     pool: &Pool<Postgres>,
     user_id: i32,
-    source_id: &str,
     content: &str,
-    tags: &[String],
-) -> Result<Knowledge, DbError> {
-    query_as::<_, Knowledge>("INSERT INTO knowledge (user_id, source_id, content, tags) VALUES ($1, $2, $3, $4) RETURNING id, user_id, source_id, content, tags, created_at, updated_at")
+    prompt_variant: Option<&str>,
+    knowledge_sources: &[String],
+    truncated: bool,
+) -> Result<(), DbError> {
+    let mut metadata = serde_json::Map::new();
+    if !knowledge_sources.is_empty() {
+        metadata.insert("knowledge_sources".to_string(), serde_json::json!(knowledge_sources));
+    }
+    if truncated {
+        metadata.insert("truncated".to_string(), serde_json::json!(true));
+    }
+    let metadata = (!metadata.is_empty()).then_some(serde_json::Value::Object(metadata));
+    query("INSERT INTO messages (user_id, role, content, prompt_variant, metadata) VALUES ($1, $2, $3, $4, $5)")
         .bind(user_id)
-        .bind(source_id)
+        .bind(MessageRole::Assistant.as_str())
         .bind(content)
-        .bind(tags)
+        .bind(prompt_variant)
+        .bind(metadata)
+        .execute(pool)
+        .await
+        .map_err(write_error)?;
+    
+    Ok(())
+}
+
+/// Connection failures are `DbError::Connection` so callers can retry them, anything else is a query error
+fn write_error(e: sqlx::Error) -> DbError {
+    let connection_lost = match &e {
+        sqlx::Error::Io(_) | sqlx::Error::Tls(_) | sqlx::Error::PoolTimedOut | sqlx::Error::PoolClosed | sqlx::Error::WorkerCrashed => true,
+        // Class 08 is connection exceptions, 57P0x the server shutting down or restarting
+        sqlx::Error::Database(error) => error.code().is_some_and(|code| code.starts_with("08") || code.starts_with("57P0")),
+        _ => false,
+    };
+    if connection_lost { DbError::Connection(e.to_string()) } else { DbError::Query(e.to_string()) }
+}
+
+/// Most recent messages first
+pub async fn get_messages(pool: &Pool<Postgres>, user_id: i32, limit: i64) -> Result<Vec<Message>, DbError> {
+    query_as::<_, Message>(
+        "SELECT id, user_id, role, content, created_at, COALESCE((metadata ->> 'truncated')::boolean, false) AS truncated
+        FROM messages WHERE user_id = $1 ORDER BY created_at DESC, id DESC LIMIT $2"
+    )
+        .bind(user_id)
+        .bind(limit)
+        .fetch_all(pool)
+        .await
+        .map_err(|e| DbError::Query(e.to_string()))
+}
+
+/// Live messages created before `cutoff`, oldest first
+pub async fn get_messages_before(pool: &Pool<Postgres>, user_id: i32, cutoff: NaiveDateTime) -> Result<Vec<Message>, DbError> {
+    query_as::<_, Message>("SELECT id, user_id, role, content, created_at FROM messages WHERE user_id = $1 AND created_at < $2 ORDER BY created_at, id")
+        .bind(user_id)
+        .bind(cutoff)
+        .fetch_all(pool)
+        .await
+        .map_err(|e| DbError::Query(e.to_string()))
+}
+
+/// Users that have live messages created before `cutoff`
+pub async fn get_user_ids_with_messages_before(pool: &Pool<Postgres>, cutoff: NaiveDateTime) -> Result<Vec<i32>, DbError> {
+    query_scalar::<_, i32>("SELECT DISTINCT user_id FROM messages WHERE user_id IS NOT NULL AND created_at < $1 ORDER BY user_id")
+        .bind(cutoff)
+        .fetch_all(pool)
+        .await
+        .map_err(|e| DbError::Query(e.to_string()))
+}
+
+/// Move a user's messages created before `cutoff` into `messages_archive`
+/// Returns the number of archived messages
+pub async fn archive_messages_before(pool: &Pool<Postgres>, user_id: i32, cutoff: NaiveDateTime) -> Result<u64, DbError> {
+    // A single statement, so a message is never both live and archived
+    let result = query(
+        "WITH moved AS (
+            DELETE FROM messages WHERE user_id = $1 AND created_at < $2
+            RETURNING id, user_id, role, content, created_at, prompt_variant, metadata
+        )
+        INSERT INTO messages_archive (id, user_id, role, content, created_at, prompt_variant, metadata)
+        SELECT id, user_id, role, content, created_at, prompt_variant, metadata FROM moved"
+    )
+        .bind(user_id)
+        .bind(cutoff)
+        .execute(pool)
+        .await
+        .map_err(|e| DbError::Query(e.to_string()))?;
+    
+    Ok(result.rows_affected())
+}
+
+/// Number of live messages created before `cutoff`, by user
+pub async fn count_messages_before(pool: &Pool<Postgres>, cutoff: NaiveDateTime) -> Result<Vec<(i32, i64)>, DbError> {
+    query_as::<_, (i32, i64)>(
+        "SELECT user_id, count(*) FROM messages WHERE user_id IS NOT NULL AND created_at < $1 GROUP BY user_id ORDER BY user_id"
+    )
+        .bind(cutoff)
+        .fetch_all(pool)
+        .await
+        .map_err(|e| DbError::Query(e.to_string()))
+}
+
+/// Every message of a user, live and archived, oldest first
+pub async fn export_messages(pool: &Pool<Postgres>, user_id: i32) -> Result<Vec<Message>, DbError> {
+    query_as::<_, Message>(
+        "SELECT id, user_id, role, content, created_at FROM messages WHERE user_id = $1
+        UNION ALL
+        SELECT id, user_id, role, content, created_at FROM messages_archive WHERE user_id = $1
+        ORDER BY created_at, id"
+    )
+        .bind(user_id)
+        .fetch_all(pool)
+        .await
+        .map_err(|e| DbError::Query(e.to_string()))
+}
+
+/// Id of the user's latest assistant message, the one `/good` and `/bad` rate
+pub async fn get_last_assistant_message_id(pool: &Pool<Postgres>, user_id: i32) -> Result<Option<i32>, DbError> {
+    query_scalar::<_, i32>("SELECT id FROM messages WHERE user_id = $1 AND role = $2 ORDER BY created_at DESC, id DESC LIMIT 1")
+        .bind(user_id)
+        .bind(MessageRole::Assistant.as_str())
+        .fetch_optional(pool)
+        .await
+        .map_err(|e| DbError::Query(e.to_string()))
+}
+
+// Feedback queries
+
+/// Rate a message, replacing an earlier rating of it
+pub async fn save_feedback(pool: &Pool<Postgres>, user_id: i32, message_id: i32, score: i16, reason: Option<&str>) -> Result<Feedback, DbError> {
+    query_as::<_, Feedback>(
+        "INSERT INTO feedback (user_id, message_id, score, reason) VALUES ($1, $2, $3, $4)
+        ON CONFLICT (message_id) DO UPDATE SET score = EXCLUDED.score, reason = EXCLUDED.reason, created_at = now()
+        RETURNING id, user_id, message_id, score, reason, created_at"
+    )
+        .bind(user_id)
+        .bind(message_id)
+        .bind(score)
+        .bind(reason)
         .fetch_one(pool)
         .await
         .map_err(|e| DbError::Query(e.to_string()))
 }
 
-pub async fn get_knowledge_by_user_id(pool: &Pool<Postgres>, user_id: i32) -> Result<Vec<Knowledge>, DbError> {
-    query_as::<_, Knowledge>("SELECT id, user_id, source_id, content, tags, created_at, updated_at FROM knowledge WHERE user_id = $1")
+/// Every rating of a user, oldest first
+pub async fn get_feedback(pool: &Pool<Postgres>, user_id: i32) -> Result<Vec<Feedback>, DbError> {
+    query_as::<_, Feedback>("SELECT id, user_id, message_id, score, reason, created_at FROM feedback WHERE user_id = $1 ORDER BY created_at, id")
         .bind(user_id)
         .fetch_all(pool)
         .await
         .map_err(|e| DbError::Query(e.to_string()))
 }
 
-pub async fn get_knowledge_by_tag(
+/// Ratings of the answers each knowledge entry was injected into, live and archived messages alike
+pub async fn get_source_ratings(pool: &Pool<Postgres>, user_id: i32) -> Result<Vec<SourceRating>, DbError> {
+    query_as::<_, SourceRating>(
+        "SELECT source.source_id, f.score, f.created_at AS rated_at
+        FROM feedback f
+        JOIN (
+            SELECT id, metadata FROM messages WHERE user_id = $1
+            UNION ALL
+            SELECT id, metadata FROM messages_archive WHERE user_id = $1
+        ) m ON m.id = f.message_id
+        CROSS JOIN LATERAL jsonb_array_elements_text(m.metadata -> 'knowledge_sources') AS source(source_id)
+        WHERE f.user_id = $1
+        ORDER BY f.created_at, source.source_id"
+    )
+        .bind(user_id)
+        .fetch_all(pool)
+        .await
+        .map_err(|e| DbError::Query(e.to_string()))
+}
+
+// Topic queries
+
+/// Id of the user's latest message, the one the topics of a turn are recorded against
+pub async fn get_last_user_message_id(pool: &Pool<Postgres>, user_id: i32) -> Result<Option<i32>, DbError> {
+    query_scalar::<_, i32>("SELECT id FROM messages WHERE user_id = $1 AND role = $2 ORDER BY created_at DESC, id DESC LIMIT 1")
+        .bind(user_id)
+        .bind(MessageRole::User.as_str())
+        .fetch_optional(pool)
+        .await
+        .map_err(|e| DbError::Query(e.to_string()))
+}
+
+/// Record the topics detected in a message, skipping ones it already has
+/// Returns how many were added
+pub async fn save_message_topics(
     pool: &Pool<Postgres>,
     user_id: i32,
-    tag: &str,
-) -> Result<Vec<Knowledge>, DbError> {
-    query_as::<_, Knowledge>("SELECT id, user_id, source_id, content, tags, created_at, updated_at FROM knowledge WHERE user_id = $1 AND $2 = ANY(tags)")
+    message_id: i32,
+    topics: &[(String, TopicKind)],
+) -> Result<u64, DbError> {
+    if topics.is_empty() {
+        return Ok(0);
+    }
+    let (names, kinds): (Vec<&str>, Vec<&str>) = topics.iter().map(|(topic, kind)| (topic.as_str(), kind.as_str())).unzip();
+    let result = query(
+        "INSERT INTO message_topics (user_id, message_id, topic, kind)
+        SELECT $1, $2, topic, kind FROM unnest($3::text[], $4::text[]) AS t(topic, kind)
+        ON CONFLICT (message_id, topic, kind) DO NOTHING"
+    )
+        .bind(user_id)
+        .bind(message_id)
+        .bind(names)
+        .bind(kinds)
+        .execute(pool)
+        .await
+        .map_err(|e| DbError::Query(e.to_string()))?;
+    Ok(result.rows_affected())
+}
+
+/// Every topic recorded for a user, oldest first
+pub async fn get_message_topics(pool: &Pool<Postgres>, user_id: i32) -> Result<Vec<MessageTopic>, DbError> {
+    query_as::<_, MessageTopic>("SELECT id, user_id, message_id, topic, kind, created_at FROM message_topics WHERE user_id = $1 ORDER BY created_at, id")
         .bind(user_id)
-        .bind(tag)
         .fetch_all(pool)
         .await
         .map_err(|e| DbError::Query(e.to_string()))
 }
 
-pub async fn get_knowledge_by_tags(
+/// The topics of a user's messages since `since`, or ever, counted by the messages mentioning them
+/// Most mentioned first, `kind` limits them to coins or keywords
+pub async fn get_topic_counts(
     pool: &Pool<Postgres>,
     user_id: i32,
-    tags: &[String],
-) -> Result<Vec<Knowledge>, DbError> {
-    if tags.is_empty() {
-        return Ok(Vec::new());
-    }
-    
-    // Using the array overlap operator
-    query_as::<_, Knowledge>("SELECT id, user_id, source_id, content, tags, created_at, updated_at FROM knowledge WHERE user_id = $1 AND tags && $2 ORDER BY updated_at DESC")
+    since: Option<NaiveDateTime>,
+    kind: Option<TopicKind>,
+    limit: i64,
+) -> Result<Vec<TopicCount>, DbError> {
+    query_as::<_, TopicCount>(
+        "SELECT topic, kind, count(DISTINCT message_id) AS messages FROM message_topics
+        WHERE user_id = $1 AND ($2::timestamp IS NULL OR created_at >= $2) AND ($3::text IS NULL OR kind = $3)
+        GROUP BY topic, kind
+        ORDER BY messages DESC, topic, kind
+        LIMIT $4"
+    )
         .bind(user_id)
-        .bind(tags)
+        .bind(since)
+        .bind(kind.map(|kind| kind.as_str()))
+        .bind(limit)
         .fetch_all(pool)
         .await
         .map_err(|e| DbError::Query(e.to_string()))
 }
 
-// Strategy queries
-pub async fn get_strategies_by_user_id(pool: &Pool<Postgres>, user_id: i32) -> Result<Vec<Strategy>, DbError> {
-    query_as::<_, Strategy>("SELECT id, user_id, strategy_id, name, category, description, risk_level, tags, steps, requirements, expected_returns, created_at, updated_at, author, version FROM strategies WHERE user_id = $1")
+// Turn debug queries
+
+/// Record what a turn sent to the model, keeping only the user's latest `keep` turns
+pub async fn save_turn_debug(
+    pool: &Pool<Postgres>,
+    user_id: i32,
+    message_id: Option<i32>,
+    question: &str,
+    intent: &str,
+    payload: &str,
+    keep: i64,
+) -> Result<i32, DbError> {
+    let mut tx = pool.begin().await.map_err(|e| DbError::Transaction(e.to_string()))?;
+    let id = query_scalar::<_, i32>(
+        "INSERT INTO turn_debug (user_id, message_id, question, intent, payload) VALUES ($1, $2, $3, $4, $5) RETURNING id"
+    )
+        .bind(user_id)
+        .bind(message_id)
+        .bind(question)
+        .bind(intent)
+        .bind(payload)
+        .fetch_one(&mut *tx)
+        .await
+        .map_err(|e| DbError::Query(e.to_string()))?;
+    query(
+        "DELETE FROM turn_debug WHERE user_id = $1 AND id NOT IN
+        (SELECT id FROM turn_debug WHERE user_id = $1 ORDER BY id DESC LIMIT $2)"
+    )
+        .bind(user_id)
+        .bind(keep)
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| DbError::Query(e.to_string()))?;
+    tx.commit().await.map_err(|e| DbError::Transaction(e.to_string()))?;
+    Ok(id)
+}
+
+/// A recorded turn of the user by id, or their latest one when `id` is None
+pub async fn get_turn_debug(pool: &Pool<Postgres>, user_id: i32, id: Option<i32>) -> Result<Option<TurnDebug>, DbError> {
+    query_as::<_, TurnDebug>(
+        "SELECT id, user_id, message_id, question, intent, payload, created_at FROM turn_debug
+        WHERE user_id = $1 AND ($2::integer IS NULL OR id = $2) ORDER BY id DESC LIMIT 1"
+    )
+        .bind(user_id)
+        .bind(id)
+        .fetch_optional(pool)
+        .await
+        .map_err(|e| DbError::Query(e.to_string()))
+}
+
+/// Every recorded turn of a user, oldest first
+pub async fn get_turn_debugs(pool: &Pool<Postgres>, user_id: i32) -> Result<Vec<TurnDebug>, DbError> {
+    query_as::<_, TurnDebug>("SELECT id, user_id, message_id, question, intent, payload, created_at FROM turn_debug WHERE user_id = $1 ORDER BY id")
         .bind(user_id)
         .fetch_all(pool)
         .await
         .map_err(|e| DbError::Query(e.to_string()))
 }
 
-pub async fn create_strategy(
+// Limit order queries
+const LIMIT_ORDER_COLUMNS: &str = "id, user_id, order_type, token_address, amount, price, status, created_at, updated_at, expires_at";
+
+/// Store a new open limit order, good until `expires_at` when one is given
+#[allow(clippy::too_many_arguments)]
+pub async fn create_limit_order(
     pool: &Pool<Postgres>,
     user_id: i32,
-    strategy_id: &str,
-    name: &str,
-    category: &str,
-    description: &str,
-    risk_level: &str,
-    tags: &[String],
-    steps: &[String],
-    requirements: &[String],
-    expected_returns: sqlx::types::JsonValue,
-    author: &str,
-    version: &str,
-) -> Result<Strategy, DbError> {
-    query_as::<_, Strategy>("INSERT INTO strategies (user_id, strategy_id, name, category, description, risk_level, tags, steps, requirements, expected_returns, author, version) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12) RETURNING id, user_id, strategy_id, name, category, description, risk_level, tags, steps, requirements, expected_returns, created_at, updated_at, author, version")
+    id: &str,
+    order_type: OrderType,
+    token_address: &str,
+    amount: f64,
+    price: f64,
+    expires_at: Option<NaiveDateTime>,
+) -> Result<LimitOrder, DbError> {
+    query_as::<_, LimitOrder>(&format!(
+        "INSERT INTO limit_orders (id, user_id, order_type, token_address, amount, price, expires_at)
+        VALUES ($1, $2, $3, $4, $5, $6, $7) RETURNING {}",
+        LIMIT_ORDER_COLUMNS
+    ))
+        .bind(id)
         .bind(user_id)
-        .bind(strategy_id)
-        .bind(name)
-        .bind(category)
-        .bind(description)
-        .bind(risk_level)
-        .bind(tags)
-        .bind(steps)
-        .bind(requirements)
-        .bind(expected_returns)
-        .bind(author)
-        .bind(version)
+        .bind(order_type.as_str())
+        .bind(token_address)
+        .bind(amount)
+        .bind(price)
+        .bind(expires_at)
         .fetch_one(pool)
         .await
         .map_err(|e| DbError::Query(e.to_string()))
 }
 
-// Search functions
-pub async fn search_strategies_by_text(
+// Pending trade queries
+
+/// Stage a trade for the user to confirm, replacing the one staged before
+pub async fn save_pending_trade(
     pool: &Pool<Postgres>,
     user_id: i32,
-    search_text: &str,
-) -> Result<Vec<Strategy>, DbError> {
-    let search_pattern = format!("%{}%", search_text);
-    
-    query_as::<_, Strategy>("SELECT id, user_id, strategy_id, name, category, description, risk_level, tags, steps, requirements, expected_returns, created_at, updated_at, author, version FROM strategies WHERE user_id = $1 AND (name ILIKE $2 OR description ILIKE $2)")
+    trade: &sqlx::types::JsonValue,
+    staged_at: NaiveDateTime,
+) -> Result<PendingTrade, DbError> {
+    query_as::<_, PendingTrade>(
+        "INSERT INTO pending_trades (user_id, trade, created_at) VALUES ($1, $2, $3)
+        ON CONFLICT (user_id) DO UPDATE SET trade = EXCLUDED.trade, created_at = EXCLUDED.created_at
+        RETURNING user_id, trade, created_at"
+    )
         .bind(user_id)
-        .bind(search_pattern)
+        .bind(trade)
+        .bind(staged_at)
+        .fetch_one(pool)
+        .await
+        .map_err(|e| DbError::Query(e.to_string()))
+}
+
+/// Remove and return the user's staged trade, so two confirmations can't both execute it
+pub async fn take_pending_trade(pool: &Pool<Postgres>, user_id: i32) -> Result<Option<PendingTrade>, DbError> {
+    query_as::<_, PendingTrade>("DELETE FROM pending_trades WHERE user_id = $1 RETURNING user_id, trade, created_at")
+        .bind(user_id)
+        .fetch_optional(pool)
+        .await
+        .map_err(|e| DbError::Query(e.to_string()))
+}
+
+/// A limit order of the user, whatever its status
+pub async fn get_limit_order(pool: &Pool<Postgres>, user_id: i32, id: &str) -> Result<Option<LimitOrder>, DbError> {
+    query_as::<_, LimitOrder>(&format!("SELECT {} FROM limit_orders WHERE user_id = $1 AND id = $2", LIMIT_ORDER_COLUMNS))
+        .bind(user_id)
+        .bind(id)
+        .fetch_optional(pool)
+        .await
+        .map_err(|e| DbError::Query(e.to_string()))
+}
+
+/// The user's open limit orders still good at `now`, oldest first
+pub async fn get_open_limit_orders(pool: &Pool<Postgres>, user_id: i32, now: NaiveDateTime) -> Result<Vec<LimitOrder>, DbError> {
+    query_as::<_, LimitOrder>(&format!(
+        "SELECT {} FROM limit_orders WHERE user_id = $1 AND status = 'open' AND (expires_at IS NULL OR expires_at > $2)
+        ORDER BY created_at, id",
+        LIMIT_ORDER_COLUMNS
+    ))
+        .bind(user_id)
+        .bind(now)
         .fetch_all(pool)
         .await
         .map_err(|e| DbError::Query(e.to_string()))
 }
 
-pub async fn search_knowledge_by_text(
+/// Every limit order of the user, oldest first
+pub async fn get_limit_orders(pool: &Pool<Postgres>, user_id: i32) -> Result<Vec<LimitOrder>, DbError> {
+    query_as::<_, LimitOrder>(&format!("SELECT {} FROM limit_orders WHERE user_id = $1 ORDER BY created_at, id", LIMIT_ORDER_COLUMNS))
+        .bind(user_id)
+        .fetch_all(pool)
+        .await
+        .map_err(|e| DbError::Query(e.to_string()))
+}
+
+/// Move an open limit order to `status`
+/// Returns None when the user has no open order with that id; filled and cancelled orders don't change
+pub async fn update_limit_order_status(
     pool: &Pool<Postgres>,
     user_id: i32,
-    search_text: &str,
-) -> Result<Vec<Knowledge>, DbError> {
-    let search_pattern = format!("%{}%", search_text);
-    
-    query_as::<_, Knowledge>("SELECT id, user_id, source_id, content, tags, created_at, updated_at FROM knowledge WHERE user_id = $1 AND (source_id ILIKE $2 OR content ILIKE $2)")
+    id: &str,
+    status: OrderStatus,
+) -> Result<Option<LimitOrder>, DbError> {
+    query_as::<_, LimitOrder>(&format!(
+        "UPDATE limit_orders SET status = $3, updated_at = now() WHERE user_id = $1 AND id = $2 AND status = 'open' RETURNING {}",
+        LIMIT_ORDER_COLUMNS
+    ))
         .bind(user_id)
-        .bind(search_pattern)
+        .bind(id)
+        .bind(status.as_str())
+        .fetch_optional(pool)
+        .await
+        .map_err(|e| DbError::Query(e.to_string()))
+}
+
+/// Move the user's open limit orders whose expiry is at or before `now` to expired, returning them
+pub async fn expire_limit_orders(pool: &Pool<Postgres>, user_id: i32, now: NaiveDateTime) -> Result<Vec<LimitOrder>, DbError> {
+    query_as::<_, LimitOrder>(&format!(
+        "UPDATE limit_orders SET status = 'expired', updated_at = now()
+        WHERE user_id = $1 AND status = 'open' AND expires_at <= $2 RETURNING {}",
+        LIMIT_ORDER_COLUMNS
+    ))
+        .bind(user_id)
+        .bind(now)
+        .fetch_all(pool)
+        .await
+        .map_err(|e| DbError::Query(e.to_string()))
+}
+
+/// Cancel an open limit order, None when the user has no open order with that id
+pub async fn cancel_limit_order(pool: &Pool<Postgres>, user_id: i32, id: &str) -> Result<Option<LimitOrder>, DbError> {
+    update_limit_order_status(pool, user_id, id, OrderStatus::Cancelled).await
+}
+
+// Trade queries
+const TRADE_COLUMNS: &str = "id, user_id, executed_at, side, coin_id, quote, amount, price, fee, imported, created_at, \
+    tx_hash, from_token, to_token, from_amount, to_amount, price_usd_at_execution, gas_cost, status, aggregator, block_number, gas_used";
+
+/// Store trades imported from an exchange export in one transaction
+/// Returns how many were stored; trades already imported are skipped
+pub async fn import_trades(pool: &Pool<Postgres>, user_id: i32, trades: &[NewTrade]) -> Result<u64, DbError> {
+    let mut tx = pool.begin().await.map_err(|e| DbError::Transaction(e.to_string()))?;
+    let mut stored = 0;
+    for trade in trades {
+        let result = query(
+            "INSERT INTO trades (user_id, executed_at, side, coin_id, quote, amount, price, fee, price_usd_at_execution, imported)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, true)
+            ON CONFLICT (user_id, executed_at, side, coin_id, amount, price) WHERE imported DO NOTHING"
+        )
+            .bind(user_id)
+            .bind(trade.executed_at)
+            .bind(trade.side.as_str())
+            .bind(&trade.coin_id)
+            .bind(&trade.quote)
+            .bind(trade.amount)
+            .bind(trade.price)
+            .bind(trade.fee)
+            .bind(trade.price_usd_at_execution)
+            .execute(&mut *tx)
+            .await
+            .map_err(|e| DbError::Query(e.to_string()))?;
+        stored += result.rows_affected();
+    }
+    tx.commit().await.map_err(|e| DbError::Transaction(e.to_string()))?;
+    Ok(stored)
+}
+
+/// Record a trade made from the wallet, whether it went through or not
+pub async fn create_trade(pool: &Pool<Postgres>, user_id: i32, trade: &NewTrade) -> Result<Trade, DbError> {
+    query_as::<_, Trade>(&format!(
+        "INSERT INTO trades (user_id, executed_at, side, coin_id, quote, amount, price, fee, tx_hash, from_token, to_token,
+            from_amount, to_amount, price_usd_at_execution, gas_cost, status, aggregator)
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15, $16, $17) RETURNING {}",
+        TRADE_COLUMNS
+    ))
+        .bind(user_id)
+        .bind(trade.executed_at)
+        .bind(trade.side.as_str())
+        .bind(&trade.coin_id)
+        .bind(&trade.quote)
+        .bind(trade.amount)
+        .bind(trade.price)
+        .bind(trade.fee)
+        .bind(&trade.tx_hash)
+        .bind(&trade.from_token)
+        .bind(&trade.to_token)
+        .bind(trade.from_amount)
+        .bind(trade.to_amount)
+        .bind(trade.price_usd_at_execution)
+        .bind(trade.gas_cost)
+        .bind(trade.status.as_str())
+        .bind(&trade.aggregator)
+        .fetch_one(pool)
+        .await
+        .map_err(|e| DbError::Query(e.to_string()))
+}
+
+/// The user's trades executed in [since, until), oldest first; a missing bound leaves that side open
+pub async fn get_trades_by_user_id(
+    pool: &Pool<Postgres>,
+    user_id: i32,
+    since: Option<NaiveDateTime>,
+    until: Option<NaiveDateTime>,
+) -> Result<Vec<Trade>, DbError> {
+    query_as::<_, Trade>(&format!(
+        "SELECT {} FROM trades WHERE user_id = $1
+        AND ($2::timestamp IS NULL OR executed_at >= $2) AND ($3::timestamp IS NULL OR executed_at < $3)
+        ORDER BY executed_at, id",
+        TRADE_COLUMNS
+    ))
+        .bind(user_id)
+        .bind(since)
+        .bind(until)
         .fetch_all(pool)
         .await
         .map_err(|e| DbError::Query(e.to_string()))
 }
+
+/// Bring the swap sent in `tx_hash` up to date with the chain: confirmed or failed with the block that
+/// mined it and its gas, or stuck
+/// A missing block, gas used or gas cost keeps the stored one, and only a pending swap can get stuck.
+/// None when none of the user's trades has that transaction, or a stuck one was already settled.
+pub async fn update_trade_status(
+    pool: &Pool<Postgres>,
+    user_id: i32,
+    tx_hash: &str,
+    status: TradeStatus,
+    block_number: Option<i64>,
+    gas_used: Option<i64>,
+    gas_cost: Option<f64>,
+) -> Result<Option<Trade>, DbError> {
+    query_as::<_, Trade>(&format!(
+        "UPDATE trades SET status = $3, block_number = COALESCE($4, block_number), gas_used = COALESCE($5, gas_used),
+            gas_cost = COALESCE($6, gas_cost)
+        WHERE user_id = $1 AND tx_hash = lower($2) AND ($3 <> 'stuck' OR status IN ('pending', 'stuck'))
+        RETURNING {}",
+        TRADE_COLUMNS
+    ))
+        .bind(user_id)
+        .bind(tx_hash)
+        .bind(status.as_str())
+        .bind(block_number)
+        .bind(gas_used)
+        .bind(gas_cost)
+        .fetch_optional(pool)
+        .await
+        .map_err(|e| DbError::Query(e.to_string()))
+}
+
+/// The user's most recent swap that was sent from the wallet, None before the first
+pub async fn get_last_swap(pool: &Pool<Postgres>, user_id: i32) -> Result<Option<Trade>, DbError> {
+    query_as::<_, Trade>(&format!(
+        "SELECT {} FROM trades WHERE user_id = $1 AND tx_hash IS NOT NULL ORDER BY executed_at DESC, id DESC LIMIT 1",
+        TRADE_COLUMNS
+    ))
+        .bind(user_id)
+        .fetch_optional(pool)
+        .await
+        .map_err(|e| DbError::Query(e.to_string()))
+}
+
+// Strategy progress queries
+const STRATEGY_PROGRESS_COLUMNS: &str = "strategy_id, user_id, step_index, completed_at, note";
+
+/// Start working through a strategy of `steps` steps, all open
+/// Progress already recorded for the strategy is replaced
+pub async fn start_strategy_progress(
+    pool: &Pool<Postgres>,
+    user_id: i32,
+    strategy_id: i32,
+    steps: usize,
+) -> Result<Vec<StrategyProgress>, DbError> {
+    let mut tx = pool.begin().await.map_err(|e| DbError::Transaction(e.to_string()))?;
+
+    query("DELETE FROM strategy_progress WHERE user_id = $1 AND strategy_id = $2")
+        .bind(user_id)
+        .bind(strategy_id)
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| DbError::Query(e.to_string()))?;
+    let progress = query_as::<_, StrategyProgress>(&format!(
+        "INSERT INTO strategy_progress (strategy_id, user_id, step_index)
+        SELECT $2, $1, step FROM generate_series(0, $3 - 1) AS step
+        RETURNING {}",
+        STRATEGY_PROGRESS_COLUMNS
+    ))
+    .bind(user_id)
+    .bind(strategy_id)
+    .bind(steps as i32)
+    .fetch_all(&mut *tx)
+    .await
+    .map_err(|e| DbError::Query(e.to_string()))?;
+
+    tx.commit().await.map_err(|e| DbError::Transaction(e.to_string()))?;
+    Ok(progress)
+}
+
+/// Steps of a strategy the user is working through, in order; empty when it wasn't started
+pub async fn get_strategy_progress(
+    pool: &Pool<Postgres>,
+    user_id: i32,
+    strategy_id: i32,
+) -> Result<Vec<StrategyProgress>, DbError> {
+    query_as::<_, StrategyProgress>(&format!(
+        "SELECT {} FROM strategy_progress WHERE user_id = $1 AND strategy_id = $2 ORDER BY step_index",
+        STRATEGY_PROGRESS_COLUMNS
+    ))
+    .bind(user_id)
+    .bind(strategy_id)
+    .fetch_all(pool)
+    .await
+    .map_err(|e| DbError::Query(e.to_string()))
+}
+
+/// Every step of every strategy the user started
+pub async fn get_all_strategy_progress(pool: &Pool<Postgres>, user_id: i32) -> Result<Vec<StrategyProgress>, DbError> {
+    query_as::<_, StrategyProgress>(&format!(
+        "SELECT {} FROM strategy_progress WHERE user_id = $1 ORDER BY strategy_id, step_index",
+        STRATEGY_PROGRESS_COLUMNS
+    ))
+    .bind(user_id)
+    .fetch_all(pool)
+    .await
+    .map_err(|e| DbError::Query(e.to_string()))
+}
+
+/// Strategies the user started and hasn't finished, most recently worked on first
+pub async fn get_unfinished_strategy_ids(pool: &Pool<Postgres>, user_id: i32) -> Result<Vec<i32>, DbError> {
+    query_scalar::<_, i32>(
+        "SELECT strategy_id FROM strategy_progress WHERE user_id = $1
+        GROUP BY strategy_id HAVING bool_or(completed_at IS NULL)
+        ORDER BY max(completed_at) DESC NULLS LAST, strategy_id DESC",
+    )
+    .bind(user_id)
+    .fetch_all(pool)
+    .await
+    .map_err(|e| DbError::Query(e.to_string()))
+}
+
+/// Mark an open step done with an optional note
+/// Returns None when the step doesn't exist or is already done
+pub async fn complete_strategy_step(
+    pool: &Pool<Postgres>,
+    user_id: i32,
+    strategy_id: i32,
+    step_index: i32,
+    note: Option<&str>,
+) -> Result<Option<StrategyProgress>, DbError> {
+    query_as::<_, StrategyProgress>(&format!(
+        "UPDATE strategy_progress SET completed_at = now(), note = $4
+        WHERE user_id = $1 AND strategy_id = $2 AND step_index = $3 AND completed_at IS NULL
+        RETURNING {}",
+        STRATEGY_PROGRESS_COLUMNS
+    ))
+    .bind(user_id)
+    .bind(strategy_id)
+    .bind(step_index)
+    .bind(note)
+    .fetch_optional(pool)
+    .await
+    .map_err(|e| DbError::Query(e.to_string()))
+}
+
+/// Keep a note on how a run of a strategy went
+pub async fn save_strategy_outcome(
+    pool: &Pool<Postgres>,
+    user_id: i32,
+    strategy_id: i32,
+    note: &str,
+) -> Result<StrategyOutcome, DbError> {
+    query_as::<_, StrategyOutcome>(
+        "INSERT INTO strategy_outcomes (strategy_id, user_id, note) VALUES ($1, $2, $3)
+        RETURNING id, strategy_id, user_id, note, created_at",
+    )
+    .bind(strategy_id)
+    .bind(user_id)
+    .bind(note)
+    .fetch_one(pool)
+    .await
+    .map_err(|e| DbError::Query(e.to_string()))
+}
+
+/// Outcome notes of the user's strategies, oldest first
+pub async fn get_strategy_outcomes(pool: &Pool<Postgres>, user_id: i32) -> Result<Vec<StrategyOutcome>, DbError> {
+    query_as::<_, StrategyOutcome>(
+        "SELECT id, strategy_id, user_id, note, created_at FROM strategy_outcomes WHERE user_id = $1 ORDER BY created_at, id"
+    )
+        .bind(user_id)
+        .fetch_all(pool)
+        .await
+        .map_err(|e| DbError::Query(e.to_string()))
+}
+
+/// Start a conversation for `user_id` on the default prompt
+pub async fn create_conversation(pool: &Pool<Postgres>, user_id: i32) -> Result<Conversation, DbError> {
+    query_as::<_, Conversation>(
+        "INSERT INTO conversations (user_id) VALUES ($1)
+        RETURNING id, user_id, system_prompt_override, started_at",
+    )
+    .bind(user_id)
+    .fetch_one(pool)
+    .await
+    .map_err(|e| DbError::Query(e.to_string()))
+}
+
+pub async fn get_conversation(pool: &Pool<Postgres>, id: i32) -> Result<Option<Conversation>, DbError> {
+    query_as::<_, Conversation>("SELECT id, user_id, system_prompt_override, started_at FROM conversations WHERE id = $1")
+        .bind(id)
+        .fetch_optional(pool)
+        .await
+        .map_err(|e| DbError::Query(e.to_string()))
+}
+
+/// Oldest first
+pub async fn get_conversations(pool: &Pool<Postgres>, user_id: i32) -> Result<Vec<Conversation>, DbError> {
+    query_as::<_, Conversation>(
+        "SELECT id, user_id, system_prompt_override, started_at FROM conversations WHERE user_id = $1 ORDER BY started_at, id"
+    )
+        .bind(user_id)
+        .fetch_all(pool)
+        .await
+        .map_err(|e| DbError::Query(e.to_string()))
+}
+
+/// Set or, with None, clear the custom instructions of a conversation
+pub async fn set_conversation_system_prompt(pool: &Pool<Postgres>, id: i32, system_prompt_override: Option<&str>) -> Result<(), DbError> {
+    query("UPDATE conversations SET system_prompt_override = $2 WHERE id = $1")
+        .bind(id)
+        .bind(system_prompt_override)
+        .execute(pool)
+        .await
+        .map_err(|e| DbError::Query(e.to_string()))?;
+
+    Ok(())
+}
+
+// Weekly report queries
+
+/// Limit orders created or changed in [start, end), oldest first
+pub async fn get_limit_orders_between(
+    pool: &Pool<Postgres>,
+    user_id: i32,
+    start: NaiveDateTime,
+    end: NaiveDateTime,
+) -> Result<Vec<LimitOrder>, DbError> {
+    query_as::<_, LimitOrder>(&format!(
+        "SELECT {} FROM limit_orders WHERE user_id = $1
+        AND ((created_at >= $2 AND created_at < $3) OR (updated_at >= $2 AND updated_at < $3))
+        ORDER BY created_at, id",
+        LIMIT_ORDER_COLUMNS
+    ))
+        .bind(user_id)
+        .bind(start)
+        .bind(end)
+        .fetch_all(pool)
+        .await
+        .map_err(|e| DbError::Query(e.to_string()))
+}
+
+/// Notifications of every kind created in [start, end), oldest first
+pub async fn get_notifications_between(
+    pool: &Pool<Postgres>,
+    user_id: i32,
+    start: NaiveDateTime,
+    end: NaiveDateTime,
+) -> Result<Vec<Notification>, DbError> {
+    query_as::<_, Notification>("SELECT id, user_id, kind, message, created_at, read_at FROM notifications WHERE user_id = $1 AND created_at >= $2 AND created_at < $3 ORDER BY created_at, id")
+        .bind(user_id)
+        .bind(start)
+        .bind(end)
+        .fetch_all(pool)
+        .await
+        .map_err(|e| DbError::Query(e.to_string()))
+}
+
+/// Strategies created, edited, worked through or given an outcome note in [start, end), by name
+pub async fn get_strategy_activity_between(
+    pool: &Pool<Postgres>,
+    user_id: i32,
+    start: NaiveDateTime,
+    end: NaiveDateTime,
+) -> Result<Vec<StrategyActivity>, DbError> {
+    query_as::<_, StrategyActivity>(
+        "SELECT * FROM (
+            SELECT s.name,
+                s.created_at >= $2 AND s.created_at < $3 AS created,
+                s.updated_at >= $2 AND s.updated_at < $3 AND s.updated_at > s.created_at AS updated,
+                (SELECT count(*) FROM strategy_progress p
+                    WHERE p.strategy_id = s.id AND p.completed_at >= $2 AND p.completed_at < $3) AS steps_completed,
+                (SELECT count(*) FROM strategy_outcomes o
+                    WHERE o.strategy_id = s.id AND o.created_at >= $2 AND o.created_at < $3) AS outcomes_noted
+            FROM strategies s WHERE s.user_id = $1
+        ) activity
+        WHERE created OR updated OR steps_completed > 0 OR outcomes_noted > 0
+        ORDER BY name"
+    )
+        .bind(user_id)
+        .bind(start)
+        .bind(end)
+        .fetch_all(pool)
+        .await
+        .map_err(|e| DbError::Query(e.to_string()))
+}
+
+/// The `limit` longest conversations in [start, end), split at pauses like `get_data_stats`
+pub async fn get_conversations_between(
+    pool: &Pool<Postgres>,
+    user_id: i32,
+    start: NaiveDateTime,
+    end: NaiveDateTime,
+    limit: i64,
+) -> Result<Vec<ConversationDigest>, DbError> {
+    query_as::<_, ConversationDigest>(
+        "SELECT min(created_at) AS started_at, count(*) AS message_count,
+            (array_agg(content ORDER BY created_at, id) FILTER (WHERE role = 'user'))[1] AS opening
+        FROM (
+            SELECT created_at, id, role, content, sum(starts) OVER (ORDER BY created_at, id) AS conversation FROM (
+                SELECT id, created_at, role, content,
+                    CASE WHEN created_at - lag(created_at) OVER (ORDER BY created_at, id) <= make_interval(mins => $4)
+                        THEN 0 ELSE 1 END AS starts
+                FROM messages WHERE user_id = $1 AND created_at >= $2 AND created_at < $3
+            ) marked
+        ) numbered
+        GROUP BY conversation
+        ORDER BY count(*) DESC, min(created_at)
+        LIMIT $5"
+    )
+        .bind(user_id)
+        .bind(start)
+        .bind(end)
+        .bind(CONVERSATION_GAP_MINUTES)
+        .bind(limit)
+        .fetch_all(pool)
+        .await
+        .map_err(|e| DbError::Query(e.to_string()))
+}
+
+// Conversation summary queries
+pub async fn save_conversation_summary(
+    pool: &Pool<Postgres>,
+    user_id: i32,
+    period_start: NaiveDateTime,
+    period_end: NaiveDateTime,
+    message_count: i32,
+    summary: &str,
+) -> Result<ConversationSummary, DbError> {
+    query_as::<_, ConversationSummary>(
+        "INSERT INTO conversation_summaries (user_id, period_start, period_end, message_count, summary)
+        VALUES ($1, $2, $3, $4, $5)
+        RETURNING id, user_id, period_start, period_end, message_count, summary, created_at"
+    )
+        .bind(user_id)
+        .bind(period_start)
+        .bind(period_end)
+        .bind(message_count)
+        .bind(summary)
+        .fetch_one(pool)
+        .await
+        .map_err(|e| DbError::Query(e.to_string()))
+}
+
+/// Summaries of a user's archived conversation, oldest first
+pub async fn get_conversation_summaries(pool: &Pool<Postgres>, user_id: i32) -> Result<Vec<ConversationSummary>, DbError> {
+    query_as::<_, ConversationSummary>(
+        "SELECT id, user_id, period_start, period_end, message_count, summary, created_at
+        FROM conversation_summaries WHERE user_id = $1 ORDER BY period_end"
+    )
+        .bind(user_id)
+        .fetch_all(pool)
+        .await
+        .map_err(|e| DbError::Query(e.to_string()))
+}
+
+// Knowledge queries
+pub async fn create_knowledge(
+    pool: &Pool<Postgres>,
+    user_id: i32,
+    source_id: &str,
+    content: &str,
+    tags: &[String],
+) -> Result<Knowledge, DbError> {
+    let stored = vault::seal_for_storage(content, tags)?;
+    query_as::<_, Knowledge>("INSERT INTO knowledge (user_id, source_id, content, tags) VALUES ($1, $2, $3, $4) RETURNING id, user_id, source_id, content, tags, created_at, updated_at")
+        .bind(user_id)
+        .bind(source_id)
+        .bind(stored)
+        .bind(tags)
+        .fetch_one(pool)
+        .await
+        .map(opened)
+        .map_err(|e| DbError::Query(e.to_string()))
+}
+
+/// Rows per INSERT of a knowledge batch, 4 parameters each stays well below Postgres' 65535 limit
+const KNOWLEDGE_BATCH_ROWS: usize = 1000;
+
+/// Insert many knowledge entries in one transaction with multi-row INSERTs
+///
+/// With `ConflictMode::FailAll` an existing or repeated source id rolls the whole batch back
+/// with `DbError::Constraint`. With `ConflictMode::Skip` the other entries are inserted and the
+/// conflicting source ids are reported in `skipped`.
+pub async fn create_knowledge_batch(
+    pool: &Pool<Postgres>,
+    user_id: i32,
+    items: &[KnowledgeInput],
+    mode: ConflictMode,
+) -> Result<KnowledgeBatch, DbError> {
+    let mut batch = KnowledgeBatch::default();
+
+    // A repeated source id would conflict with the row inserted just before it
+    let mut seen = HashSet::new();
+    let mut rows = Vec::with_capacity(items.len());
+    for item in items {
+        if seen.insert(item.source_id.as_str()) {
+            rows.push(item);
+        } else if mode == ConflictMode::FailAll {
+            return Err(DbError::Constraint(format!("source_id {} appears twice in the batch", item.source_id)));
+        } else {
+            batch.skipped.push(item.source_id.clone());
+        }
+    }
+    if rows.is_empty() {
+        return Ok(batch);
+    }
+    let sealed: Vec<String> = rows
+        .iter()
+        .map(|item| vault::seal_for_storage(&item.content, &item.tags))
+        .collect::<Result<_, _>>()?;
+    let rows: Vec<(&KnowledgeInput, &str)> = rows.into_iter().zip(sealed.iter().map(String::as_str)).collect();
+
+    let mut tx = pool.begin().await.map_err(|e| DbError::Transaction(e.to_string()))?;
+    for chunk in rows.chunks(KNOWLEDGE_BATCH_ROWS) {
+        let mut insert = QueryBuilder::<Postgres>::new("INSERT INTO knowledge (user_id, source_id, content, tags) ");
+        insert.push_values(chunk, |mut row, (item, content)| {
+            row.push_bind(user_id)
+                .push_bind(item.source_id.as_str())
+                .push_bind(*content)
+                .push_bind(item.tags.as_slice());
+        });
+        if mode == ConflictMode::Skip {
+            insert.push(" ON CONFLICT (user_id, source_id) DO NOTHING");
+        }
+        insert.push(" RETURNING id, source_id");
+
+        let inserted: Vec<(i32, String)> = insert
+            .build_query_as()
+            .fetch_all(&mut *tx)
+            .await
+            .map_err(|e| match e {
+                sqlx::Error::Database(e) if e.is_unique_violation() => DbError::Constraint(e.to_string()),
+                e => DbError::Query(e.to_string()),
+            })?;
+
+        // RETURNING doesn't promise input order, match the rows back by source id
+        let ids: HashMap<String, i32> = inserted.into_iter().map(|(id, source_id)| (source_id, id)).collect();
+        for (item, _) in chunk {
+            match ids.get(&item.source_id) {
+                Some(id) => batch.created.push(*id),
+                None => batch.skipped.push(item.source_id.clone()),
+            }
+        }
+    }
+    tx.commit().await.map_err(|e| DbError::Transaction(e.to_string()))?;
+
+    Ok(batch)
+}
+
+/// Decrypt a retrieved entry when the loaded key opens it, see `vault::open_from_storage`
+fn opened(mut entry: Knowledge) -> Knowledge {
+    entry.content = vault::open_from_storage(entry.content);
+    entry
+}
+
+fn all_opened(mut entries: Vec<Knowledge>) -> Vec<Knowledge> {
+    vault::open_entries(&mut entries);
+    entries
+}
+
+/// Replace the stored content of entries as is, for re-encrypting them under a new key
+pub async fn replace_knowledge_content(pool: &Pool<Postgres>, user_id: i32, contents: &[(i32, String)]) -> Result<(), DbError> {
+    let mut tx = pool.begin().await.map_err(|e| DbError::Transaction(e.to_string()))?;
+    for (id, content) in contents {
+        query("UPDATE knowledge SET content = $1, updated_at = now() WHERE id = $2 AND user_id = $3")
+            .bind(content)
+            .bind(id)
+            .bind(user_id)
+            .execute(&mut *tx)
+            .await
+            .map_err(|e| DbError::Query(e.to_string()))?;
+    }
+    tx.commit().await.map_err(|e| DbError::Transaction(e.to_string()))
+}
+
+/// Insert a knowledge entry, replacing the content and tags if the source already exists
+pub async fn upsert_knowledge(
+    pool: &Pool<Postgres>,
+    user_id: i32,
+    source_id: &str,
+    content: &str,
+    tags: &[String],
+) -> Result<Knowledge, DbError> {
+    query_as::<_, Knowledge>(
+        "INSERT INTO knowledge (user_id, source_id, content, tags) VALUES ($1, $2, $3, $4)
+        ON CONFLICT (user_id, source_id) DO UPDATE SET content = EXCLUDED.content, tags = EXCLUDED.tags, updated_at = now()
+        RETURNING id, user_id, source_id, content, tags, created_at, updated_at"
+    )
+        .bind(user_id)
+        .bind(source_id)
+        .bind(vault::seal_for_storage(content, tags)?)
+        .bind(tags)
+        .fetch_one(pool)
+        .await
+        .map(opened)
+        .map_err(|e| DbError::Query(e.to_string()))
+}
+
+pub async fn get_knowledge_by_source_id(pool: &Pool<Postgres>, user_id: i32, source_id: &str) -> Result<Option<Knowledge>, DbError> {
+    query_as::<_, Knowledge>("SELECT id, user_id, source_id, content, tags, created_at, updated_at FROM knowledge WHERE user_id = $1 AND source_id = $2")
+        .bind(user_id)
+        .bind(source_id)
+        .fetch_optional(pool)
+        .await
+        .map(|entry| entry.map(opened))
+        .map_err(|e| DbError::Query(e.to_string()))
+}
+
+pub async fn get_knowledge_by_user_id(pool: &Pool<Postgres>, user_id: i32) -> Result<Vec<Knowledge>, DbError> {
+    query_as::<_, Knowledge>("SELECT id, user_id, source_id, content, tags, created_at, updated_at FROM knowledge WHERE user_id = $1")
+        .bind(user_id)
+        .fetch_all(pool)
+        .await
+        .map(all_opened)
+        .map_err(|e| DbError::Query(e.to_string()))
+}
+
+pub async fn get_knowledge_by_tag(
+    pool: &Pool<Postgres>,
+    user_id: i32,
+    tag: &str,
+) -> Result<Vec<Knowledge>, DbError> {
+    query_as::<_, Knowledge>("SELECT id, user_id, source_id, content, tags, created_at, updated_at FROM knowledge WHERE user_id = $1 AND $2 = ANY(tags)")
+        .bind(user_id)
+        .bind(tag)
+        .fetch_all(pool)
+        .await
+        .map(all_opened)
+        .map_err(|e| DbError::Query(e.to_string()))
+}
+
+pub async fn get_knowledge_by_tags(
+    pool: &Pool<Postgres>,
+    user_id: i32,
+    tags: &[String],
+) -> Result<Vec<Knowledge>, DbError> {
+    if tags.is_empty() {
+        return Ok(Vec::new());
+    }
+    
+    // Using the array overlap operator
+    query_as::<_, Knowledge>("SELECT id, user_id, source_id, content, tags, created_at, updated_at FROM knowledge WHERE user_id = $1 AND tags && $2 ORDER BY updated_at DESC")
+        .bind(user_id)
+        .bind(tags)
+        .fetch_all(pool)
+        .await
+        .map(all_opened)
+        .map_err(|e| DbError::Query(e.to_string()))
+}
+
+/// Knowledge of every user not updated since `cutoff`, oldest first
+/// Private entries are returned sealed
+pub async fn get_knowledge_updated_before(pool: &Pool<Postgres>, cutoff: NaiveDateTime) -> Result<Vec<Knowledge>, DbError> {
+    query_as::<_, Knowledge>(
+        "SELECT id, user_id, source_id, content, tags, created_at, updated_at FROM knowledge
+        WHERE COALESCE(updated_at, created_at) < $1 ORDER BY COALESCE(updated_at, created_at), id"
+    )
+        .bind(cutoff)
+        .fetch_all(pool)
+        .await
+        .map_err(|e| DbError::Query(e.to_string()))
+}
+
+/// Delete the knowledge of every user not updated since `cutoff`, returning the number deleted
+pub async fn delete_knowledge_updated_before(pool: &Pool<Postgres>, cutoff: NaiveDateTime) -> Result<u64, DbError> {
+    query("DELETE FROM knowledge WHERE COALESCE(updated_at, created_at) < $1")
+        .bind(cutoff)
+        .execute(pool)
+        .await
+        .map(|result| result.rows_affected())
+        .map_err(|e| DbError::Query(e.to_string()))
+}
+
+/// Groups of a user's knowledge entries with the same content, ignoring surrounding whitespace
+/// Private entries are compared sealed, so they only match when stored from the same ciphertext
+pub async fn get_duplicate_knowledge(pool: &Pool<Postgres>) -> Result<Vec<DuplicateKnowledge>, DbError> {
+    let rows = query_as::<_, Knowledge>(
+        "SELECT id, user_id, source_id, content, tags, created_at, updated_at FROM knowledge k
+        WHERE EXISTS (
+            SELECT 1 FROM knowledge other
+            WHERE other.user_id = k.user_id AND btrim(other.content, E' \\t\\r\\n') = btrim(k.content, E' \\t\\r\\n') AND other.id <> k.id
+        )
+        ORDER BY user_id, btrim(content, E' \\t\\r\\n'), updated_at DESC NULLS LAST, id DESC"
+    )
+        .fetch_all(pool)
+        .await
+        .map_err(|e| DbError::Query(e.to_string()))?;
+
+    // Rows arrive grouped, most recently updated first
+    let mut groups: Vec<DuplicateKnowledge> = Vec::new();
+    for entry in rows {
+        match groups.last_mut() {
+            Some(group) if group.kept.user_id == entry.user_id && group.kept.content.trim() == entry.content.trim() => {
+                group.duplicates.push(entry);
+            },
+            _ => groups.push(DuplicateKnowledge { kept: entry, duplicates: Vec::new() }),
+        }
+    }
+    Ok(groups)
+}
+
+/// Delete the duplicates of each group, adding their tags to the kept entry
+/// Returns the number of entries deleted
+pub async fn merge_duplicate_knowledge(pool: &Pool<Postgres>, groups: &[DuplicateKnowledge]) -> Result<u64, DbError> {
+    let mut tx = pool.begin().await.map_err(|e| DbError::Transaction(e.to_string()))?;
+    let mut deleted = 0;
+    for group in groups {
+        let mut tags = group.kept.tags.clone();
+        for tag in group.duplicates.iter().flat_map(|entry| &entry.tags) {
+            if !tags.contains(tag) {
+                tags.push(tag.clone());
+            }
+        }
+        query("UPDATE knowledge SET tags = $1 WHERE id = $2")
+            .bind(&tags)
+            .bind(group.kept.id)
+            .execute(&mut *tx)
+            .await
+            .map_err(|e| DbError::Query(e.to_string()))?;
+
+        let ids: Vec<i32> = group.duplicates.iter().map(|entry| entry.id).collect();
+        deleted += query("DELETE FROM knowledge WHERE id = ANY($1)")
+            .bind(&ids)
+            .execute(&mut *tx)
+            .await
+            .map_err(|e| DbError::Query(e.to_string()))?
+            .rows_affected();
+    }
+    tx.commit().await.map_err(|e| DbError::Transaction(e.to_string()))?;
+    Ok(deleted)
+}
+
+// Strategy queries
+pub async fn get_strategies_by_user_id(pool: &Pool<Postgres>, user_id: i32) -> Result<Vec<Strategy>, DbError> {
+    query_as::<_, Strategy>("SELECT id, user_id, strategy_id, name, category, description, risk_level, tags, steps, requirements, expected_returns, created_at, updated_at, author, version FROM strategies WHERE user_id = $1")
+        .bind(user_id)
+        .fetch_all(pool)
+        .await
+        .map_err(|e| DbError::Query(e.to_string()))
+}
+
+#[allow(clippy::too_many_arguments)]
+pub async fn create_strategy(
+    pool: &Pool<Postgres>,
+    user_id: i32,
+    strategy_id: &str,
+    name: &str,
+    category: &str,
+    description: &str,
+    risk_level: &str,
+    tags: &[String],
+    steps: &[String],
+    requirements: &[String],
+    expected_returns: sqlx::types::JsonValue,
+    author: &str,
+    version: &str,
+) -> Result<Strategy, DbError> {
+    query_as::<_, Strategy>("INSERT INTO strategies (user_id, strategy_id, name, category, description, risk_level, tags, steps, requirements, expected_returns, author, version) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12) RETURNING id, user_id, strategy_id, name, category, description, risk_level, tags, steps, requirements, expected_returns, created_at, updated_at, author, version")
+        .bind(user_id)
+        .bind(strategy_id)
+        .bind(name)
+        .bind(category)
+        .bind(description)
+        .bind(risk_level)
+        .bind(tags)
+        .bind(steps)
+        .bind(requirements)
+        .bind(expected_returns)
+        .bind(author)
+        .bind(version)
+        .fetch_one(pool)
+        .await
+        .map_err(|e| DbError::Query(e.to_string()))
+}
+
+/// Returns true if the strategy existed, its progress and outcomes go with it
+pub async fn delete_strategy(pool: &Pool<Postgres>, user_id: i32, id: i32) -> Result<bool, DbError> {
+    let result = query("DELETE FROM strategies WHERE user_id = $1 AND id = $2")
+        .bind(user_id)
+        .bind(id)
+        .execute(pool)
+        .await
+        .map_err(|e| DbError::Query(e.to_string()))?;
+    
+    Ok(result.rows_affected() > 0)
+}
+
+// Search functions
+pub async fn search_strategies_by_text(
+    pool: &Pool<Postgres>,
+    user_id: i32,
+    search_text: &str,
+) -> Result<Vec<Strategy>, DbError> {
+    let search_pattern = format!("%{}%", search_text);
+    
+    query_as::<_, Strategy>("SELECT id, user_id, strategy_id, name, category, description, risk_level, tags, steps, requirements, expected_returns, created_at, updated_at, author, version FROM strategies WHERE user_id = $1 AND (name ILIKE $2 OR description ILIKE $2)")
+        .bind(user_id)
+        .bind(search_pattern)
+        .fetch_all(pool)
+        .await
+        .map_err(|e| DbError::Query(e.to_string()))
+}
+
+pub async fn search_knowledge_by_text(
+    pool: &Pool<Postgres>,
+    user_id: i32,
+    search_text: &str,
+) -> Result<Vec<Knowledge>, DbError> {
+    let search_pattern = format!("%{}%", search_text);
+    
+    query_as::<_, Knowledge>("SELECT id, user_id, source_id, content, tags, created_at, updated_at FROM knowledge WHERE user_id = $1 AND (source_id ILIKE $2 OR content ILIKE $2)")
+        .bind(user_id)
+        .bind(search_pattern)
+        .fetch_all(pool)
+        .await
+        .map(all_opened)
+        .map_err(|e| DbError::Query(e.to_string()))
+}
+
+// Price history queries
+pub async fn save_price_point(pool: &Pool<Postgres>, coin_id: &str, price_usd: f64) -> Result<(), DbError> {
+    query("INSERT INTO price_history (coin_id, price_usd) VALUES ($1, $2)")
+        .bind(coin_id)
+        .bind(price_usd)
+        .execute(pool)
+        .await
+        .map_err(|e| DbError::Query(e.to_string()))?;
+    
+    Ok(())
+}
+
+pub async fn get_latest_price_point(pool: &Pool<Postgres>, coin_id: &str) -> Result<Option<PricePoint>, DbError> {
+    query_as::<_, PricePoint>("SELECT id, coin_id, price_usd, fetched_at FROM price_history WHERE coin_id = $1 ORDER BY fetched_at DESC LIMIT 1")
+        .bind(coin_id)
+        .fetch_optional(pool)
+        .await
+        .map_err(|e| DbError::Query(e.to_string()))
+}
+
+/// Most recent price recorded at or before `at`
+pub async fn get_price_point_before(pool: &Pool<Postgres>, coin_id: &str, at: NaiveDateTime) -> Result<Option<PricePoint>, DbError> {
+    query_as::<_, PricePoint>("SELECT id, coin_id, price_usd, fetched_at FROM price_history WHERE coin_id = $1 AND fetched_at <= $2 ORDER BY fetched_at DESC LIMIT 1")
+        .bind(coin_id)
+        .bind(at)
+        .fetch_optional(pool)
+        .await
+        .map_err(|e| DbError::Query(e.to_string()))
+}
+
+/// Prices recorded after `since`, oldest first
+pub async fn get_price_points_since(pool: &Pool<Postgres>, coin_id: &str, since: NaiveDateTime) -> Result<Vec<PricePoint>, DbError> {
+    query_as::<_, PricePoint>("SELECT id, coin_id, price_usd, fetched_at FROM price_history WHERE coin_id = $1 AND fetched_at > $2 ORDER BY fetched_at")
+        .bind(coin_id)
+        .bind(since)
+        .fetch_all(pool)
+        .await
+        .map_err(|e| DbError::Query(e.to_string()))
+}
+
+// Gas reading queries
+pub async fn save_gas_reading(pool: &Pool<Postgres>, base_fee_gwei: f64, priority_fee_gwei: f64, taken_at: NaiveDateTime) -> Result<(), DbError> {
+    query("INSERT INTO gas_readings (base_fee_gwei, priority_fee_gwei, taken_at) VALUES ($1, $2, $3)")
+        .bind(base_fee_gwei)
+        .bind(priority_fee_gwei)
+        .bind(taken_at)
+        .execute(pool)
+        .await
+        .map_err(|e| DbError::Query(e.to_string()))?;
+
+    Ok(())
+}
+
+/// Gas readings taken after `since`, oldest first
+pub async fn get_gas_readings_since(pool: &Pool<Postgres>, since: NaiveDateTime) -> Result<Vec<GasReading>, DbError> {
+    query_as::<_, GasReading>("SELECT id, base_fee_gwei, priority_fee_gwei, taken_at FROM gas_readings WHERE taken_at > $1 ORDER BY taken_at")
+        .bind(since)
+        .fetch_all(pool)
+        .await
+        .map_err(|e| DbError::Query(e.to_string()))
+}
+
+/// Delete gas readings taken before `before`, returning how many were removed
+pub async fn delete_gas_readings_before(pool: &Pool<Postgres>, before: NaiveDateTime) -> Result<u64, DbError> {
+    query("DELETE FROM gas_readings WHERE taken_at < $1")
+        .bind(before)
+        .execute(pool)
+        .await
+        .map(|result| result.rows_affected())
+        .map_err(|e| DbError::Query(e.to_string()))
+}
+
+// API usage queries
+
+/// Count one call to `upstream` on `day`
+pub async fn record_api_call(pool: &Pool<Postgres>, upstream: &str, day: NaiveDate) -> Result<(), DbError> {
+    query("INSERT INTO api_usage (upstream, day, calls) VALUES ($1, $2, 1) ON CONFLICT (upstream, day) DO UPDATE SET calls = api_usage.calls + 1")
+        .bind(upstream)
+        .bind(day)
+        .execute(pool)
+        .await
+        .map_err(|e| DbError::Query(e.to_string()))?;
+
+    Ok(())
+}
+
+/// Calls made to each upstream on `day`, by upstream name
+pub async fn get_api_usage(pool: &Pool<Postgres>, day: NaiveDate) -> Result<Vec<(String, i64)>, DbError> {
+    query_as::<_, (String, i64)>("SELECT upstream, calls FROM api_usage WHERE day = $1 ORDER BY upstream")
+        .bind(day)
+        .fetch_all(pool)
+        .await
+        .map_err(|e| DbError::Query(e.to_string()))
+}
+
+// Holding queries
+pub async fn get_holdings_by_user_id(pool: &Pool<Postgres>, user_id: i32) -> Result<Vec<Holding>, DbError> {
+    query_as::<_, Holding>("SELECT id, user_id, coin_id, amount, created_at, updated_at FROM holdings WHERE user_id = $1 ORDER BY coin_id")
+        .bind(user_id)
+        .fetch_all(pool)
+        .await
+        .map_err(|e| DbError::Query(e.to_string()))
+}
+
+pub async fn upsert_holding(pool: &Pool<Postgres>, user_id: i32, coin_id: &str, amount: f64) -> Result<Holding, DbError> {
+    query_as::<_, Holding>("INSERT INTO holdings (user_id, coin_id, amount) VALUES ($1, $2, $3) ON CONFLICT (user_id, coin_id) DO UPDATE SET amount = EXCLUDED.amount, updated_at = now() RETURNING id, user_id, coin_id, amount, created_at, updated_at")
+        .bind(user_id)
+        .bind(coin_id)
+        .bind(amount)
+        .fetch_one(pool)
+        .await
+        .map_err(|e| DbError::Query(e.to_string()))
+}
+
+pub async fn get_all_holdings(pool: &Pool<Postgres>) -> Result<Vec<Holding>, DbError> {
+    query_as::<_, Holding>("SELECT id, user_id, coin_id, amount, created_at, updated_at FROM holdings ORDER BY coin_id, user_id")
+        .fetch_all(pool)
+        .await
+        .map_err(|e| DbError::Query(e.to_string()))
+}
+
+pub async fn delete_holding(pool: &Pool<Postgres>, user_id: i32, coin_id: &str) -> Result<bool, DbError> {
+    let result = query("DELETE FROM holdings WHERE user_id = $1 AND coin_id = $2")
+        .bind(user_id)
+        .bind(coin_id)
+        .execute(pool)
+        .await
+        .map_err(|e| DbError::Query(e.to_string()))?;
+    
+    Ok(result.rows_affected() > 0)
+}
+
+// Notification queries
+pub async fn create_notification(pool: &Pool<Postgres>, user_id: i32, kind: &str, message: &str) -> Result<Notification, DbError> {
+    query_as::<_, Notification>("INSERT INTO notifications (user_id, kind, message) VALUES ($1, $2, $3) RETURNING id, user_id, kind, message, created_at, read_at")
+        .bind(user_id)
+        .bind(kind)
+        .bind(message)
+        .fetch_one(pool)
+        .await
+        .map_err(|e| DbError::Query(e.to_string()))
+}
+
+pub async fn get_unread_notifications(pool: &Pool<Postgres>, user_id: i32) -> Result<Vec<Notification>, DbError> {
+    query_as::<_, Notification>("SELECT id, user_id, kind, message, created_at, read_at FROM notifications WHERE user_id = $1 AND read_at IS NULL ORDER BY created_at, id")
+        .bind(user_id)
+        .fetch_all(pool)
+        .await
+        .map_err(|e| DbError::Query(e.to_string()))
+}
+
+/// Notifications of one kind created since `since`, read or not
+pub async fn get_notifications_since(pool: &Pool<Postgres>, user_id: i32, kind: &str, since: NaiveDateTime) -> Result<Vec<Notification>, DbError> {
+    query_as::<_, Notification>("SELECT id, user_id, kind, message, created_at, read_at FROM notifications WHERE user_id = $1 AND kind = $2 AND created_at >= $3 ORDER BY created_at, id")
+        .bind(user_id)
+        .bind(kind)
+        .bind(since)
+        .fetch_all(pool)
+        .await
+        .map_err(|e| DbError::Query(e.to_string()))
+}
+
+pub async fn mark_notifications_read(pool: &Pool<Postgres>, user_id: i32, ids: &[i32]) -> Result<u64, DbError> {
+    let result = query("UPDATE notifications SET read_at = now() WHERE user_id = $1 AND id = ANY($2) AND read_at IS NULL")
+        .bind(user_id)
+        .bind(ids)
+        .execute(pool)
+        .await
+        .map_err(|e| DbError::Query(e.to_string()))?;
+    
+    Ok(result.rows_affected())
+}
+
+// Alias queries
+pub async fn upsert_user_alias(pool: &Pool<Postgres>, user_id: i32, alias: &str, target: &str) -> Result<UserAlias, DbError> {
+    query_as::<_, UserAlias>(
+        "INSERT INTO user_aliases (user_id, alias, target) VALUES ($1, $2, $3)
+        ON CONFLICT (user_id, alias) DO UPDATE SET target = EXCLUDED.target
+        RETURNING id, user_id, alias, target, created_at"
+    )
+        .bind(user_id)
+        .bind(alias)
+        .bind(target)
+        .fetch_one(pool)
+        .await
+        .map_err(|e| DbError::Query(e.to_string()))
+}
+
+pub async fn get_user_aliases(pool: &Pool<Postgres>, user_id: i32) -> Result<Vec<UserAlias>, DbError> {
+    query_as::<_, UserAlias>("SELECT id, user_id, alias, target, created_at FROM user_aliases WHERE user_id = $1 ORDER BY alias")
+        .bind(user_id)
+        .fetch_all(pool)
+        .await
+        .map_err(|e| DbError::Query(e.to_string()))
+}
+
+/// Returns true if the alias existed
+pub async fn delete_user_alias(pool: &Pool<Postgres>, user_id: i32, alias: &str) -> Result<bool, DbError> {
+    let result = query("DELETE FROM user_aliases WHERE user_id = $1 AND alias = $2")
+        .bind(user_id)
+        .bind(alias)
+        .execute(pool)
+        .await
+        .map_err(|e| DbError::Query(e.to_string()))?;
+    
+    Ok(result.rows_affected() > 0)
+}
+
+// Watchlist queries
+
+/// Add a coin to the watchlist, keeping the existing entry (and its note) if it's already there
+/// Returns the entry and whether it was newly added
+pub async fn add_watchlist_entry(pool: &Pool<Postgres>, user_id: i32, coin_id: &str, note: Option<&str>) -> Result<(WatchlistEntry, bool), DbError> {
+    let inserted = query_as::<_, WatchlistEntry>(
+        "INSERT INTO watchlist (user_id, coin_id, note) VALUES ($1, $2, $3)
+        ON CONFLICT (user_id, coin_id) DO NOTHING
+        RETURNING id, user_id, coin_id, note, added_at"
+    )
+        .bind(user_id)
+        .bind(coin_id)
+        .bind(note)
+        .fetch_optional(pool)
+        .await
+        .map_err(|e| DbError::Query(e.to_string()))?;
+
+    match inserted {
+        Some(entry) => Ok((entry, true)),
+        None => {
+            let existing = query_as::<_, WatchlistEntry>("SELECT id, user_id, coin_id, note, added_at FROM watchlist WHERE user_id = $1 AND coin_id = $2")
+                .bind(user_id)
+                .bind(coin_id)
+                .fetch_one(pool)
+                .await
+                .map_err(|e| DbError::Query(e.to_string()))?;
+            Ok((existing, false))
+        },
+    }
+}
+
+/// Oldest entry first
+pub async fn get_watchlist(pool: &Pool<Postgres>, user_id: i32) -> Result<Vec<WatchlistEntry>, DbError> {
+    query_as::<_, WatchlistEntry>("SELECT id, user_id, coin_id, note, added_at FROM watchlist WHERE user_id = $1 ORDER BY added_at, id")
+        .bind(user_id)
+        .fetch_all(pool)
+        .await
+        .map_err(|e| DbError::Query(e.to_string()))
+}
+
+/// Every user's entries, for engines that watch coins on behalf of all users
+pub async fn get_all_watchlist_entries(pool: &Pool<Postgres>) -> Result<Vec<WatchlistEntry>, DbError> {
+    query_as::<_, WatchlistEntry>("SELECT id, user_id, coin_id, note, added_at FROM watchlist ORDER BY user_id, coin_id")
+        .fetch_all(pool)
+        .await
+        .map_err(|e| DbError::Query(e.to_string()))
+}
+
+/// Replace the note of an entry, None clears it
+/// Returns true if the coin is on the watchlist
+pub async fn set_watchlist_note(pool: &Pool<Postgres>, user_id: i32, coin_id: &str, note: Option<&str>) -> Result<bool, DbError> {
+    let result = query("UPDATE watchlist SET note = $3 WHERE user_id = $1 AND coin_id = $2")
+        .bind(user_id)
+        .bind(coin_id)
+        .bind(note)
+        .execute(pool)
+        .await
+        .map_err(|e| DbError::Query(e.to_string()))?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+/// Returns true if the coin was on the watchlist
+pub async fn remove_watchlist_entry(pool: &Pool<Postgres>, user_id: i32, coin_id: &str) -> Result<bool, DbError> {
+    let result = query("DELETE FROM watchlist WHERE user_id = $1 AND coin_id = $2")
+        .bind(user_id)
+        .bind(coin_id)
+        .execute(pool)
+        .await
+        .map_err(|e| DbError::Query(e.to_string()))?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+// Recommendation queries
+
+/// Record a call, returns None when the same call was already recorded today
+pub async fn save_recommendation(
+    pool: &Pool<Postgres>,
+    user_id: i32,
+    coin_id: &str,
+    action: &str,
+    price_low: f64,
+    price_high: f64,
+    target_date: Option<NaiveDate>,
+) -> Result<Option<Recommendation>, DbError> {
+    query_as::<_, Recommendation>(
+        "INSERT INTO recommendations (user_id, coin_id, action, price_low, price_high, target_date) VALUES ($1, $2, $3, $4, $5, $6)
+        ON CONFLICT (user_id, coin_id, action, price_low, price_high, made_on) DO NOTHING
+        RETURNING id, user_id, coin_id, action, price_low, price_high, target_date, made_at"
+    )
+        .bind(user_id)
+        .bind(coin_id)
+        .bind(action)
+        .bind(price_low)
+        .bind(price_high)
+        .bind(target_date)
+        .fetch_optional(pool)
+        .await
+        .map_err(|e| DbError::Query(e.to_string()))
+}
+
+/// Oldest call first
+pub async fn get_recommendations(pool: &Pool<Postgres>, user_id: i32) -> Result<Vec<Recommendation>, DbError> {
+    query_as::<_, Recommendation>("SELECT id, user_id, coin_id, action, price_low, price_high, target_date, made_at FROM recommendations WHERE user_id = $1 ORDER BY made_at, id")
+        .bind(user_id)
+        .fetch_all(pool)
+        .await
+        .map_err(|e| DbError::Query(e.to_string()))
+}
+
+// Statistics queries
+
+/// Pause between messages that starts a new conversation
+pub const CONVERSATION_GAP_MINUTES: i32 = 60;
+
+/// Counts and sizes of what's stored for a user, with at most `top` tags and categories
+pub async fn get_data_stats(pool: &Pool<Postgres>, user_id: i32, top: i64) -> Result<DataStats, DbError> {
+    let map_err = |e: sqlx::Error| DbError::Query(e.to_string());
+
+    let (knowledge_count, distinct_tags) = query_as::<_, (i64, i64)>(
+        "SELECT (SELECT count(*) FROM knowledge WHERE user_id = $1),
+            (SELECT count(DISTINCT tag) FROM knowledge, unnest(tags) AS tag WHERE user_id = $1)"
+    )
+        .bind(user_id)
+        .fetch_one(pool)
+        .await
+        .map_err(map_err)?;
+    let knowledge_tags = query_as::<_, NamedCount>(
+        "SELECT tag AS name, count(*) AS count FROM knowledge, unnest(tags) AS tag WHERE user_id = $1
+        GROUP BY tag ORDER BY count(*) DESC, tag LIMIT $2"
+    )
+        .bind(user_id)
+        .bind(top)
+        .fetch_all(pool)
+        .await
+        .map_err(map_err)?;
+    let stamp = |order: &str| {
+        format!(
+            "SELECT source_id, created_at FROM knowledge WHERE user_id = $1 AND created_at IS NOT NULL ORDER BY created_at {}, id {} LIMIT 1",
+            order, order
+        )
+    };
+    let oldest_knowledge = query_as::<_, KnowledgeStamp>(&stamp("ASC"))
+        .bind(user_id)
+        .fetch_optional(pool)
+        .await
+        .map_err(map_err)?;
+    let newest_knowledge = query_as::<_, KnowledgeStamp>(&stamp("DESC"))
+        .bind(user_id)
+        .fetch_optional(pool)
+        .await
+        .map_err(map_err)?;
+
+    let (strategy_count, distinct_categories) = query_as::<_, (i64, i64)>(
+        "SELECT count(*), count(DISTINCT category) FROM strategies WHERE user_id = $1"
+    )
+        .bind(user_id)
+        .fetch_one(pool)
+        .await
+        .map_err(map_err)?;
+    let strategy_categories = query_as::<_, NamedCount>(
+        "SELECT category AS name, count(*) AS count FROM strategies WHERE user_id = $1
+        GROUP BY category ORDER BY count(*) DESC, category LIMIT $2"
+    )
+        .bind(user_id)
+        .bind(top)
+        .fetch_all(pool)
+        .await
+        .map_err(map_err)?;
+    let strategy_risk_levels = query_as::<_, NamedCount>(
+        "SELECT risk_level AS name, count(*) AS count FROM strategies WHERE user_id = $1
+        GROUP BY risk_level ORDER BY count(*) DESC, risk_level LIMIT $2"
+    )
+        .bind(user_id)
+        .bind(top)
+        .fetch_all(pool)
+        .await
+        .map_err(map_err)?;
+
+    let (message_count, archived_message_count) = query_as::<_, (i64, i64)>(
+        "SELECT (SELECT count(*) FROM messages WHERE user_id = $1), (SELECT count(*) FROM messages_archive WHERE user_id = $1)"
+    )
+        .bind(user_id)
+        .fetch_one(pool)
+        .await
+        .map_err(map_err)?;
+    // A conversation starts with the first message and after every pause longer than the gap
+    let (conversation_count, longest_conversation) = query_as::<_, (i64, i64)>(
+        "SELECT count(*), COALESCE(max(size), 0) FROM (
+            SELECT count(*) AS size FROM (
+                SELECT sum(starts) OVER (ORDER BY created_at, id) AS conversation FROM (
+                    SELECT id, created_at,
+                        CASE WHEN created_at - lag(created_at) OVER (ORDER BY created_at, id) <= make_interval(mins => $2)
+                            THEN 0 ELSE 1 END AS starts
+                    FROM messages WHERE user_id = $1
+                ) marked
+            ) numbered
+            GROUP BY conversation
+        ) conversations"
+    )
+        .bind(user_id)
+        .bind(CONVERSATION_GAP_MINUTES)
+        .fetch_one(pool)
+        .await
+        .map_err(map_err)?;
+
+    let storage = query_as::<_, NamedCount>(
+        "SELECT 'knowledge' AS name, COALESCE(sum(pg_column_size(t.*)), 0)::BIGINT AS count FROM knowledge t WHERE user_id = $1
+        UNION ALL SELECT 'strategies', COALESCE(sum(pg_column_size(t.*)), 0)::BIGINT FROM strategies t WHERE user_id = $1
+        UNION ALL SELECT 'messages', COALESCE(sum(pg_column_size(t.*)), 0)::BIGINT FROM messages t WHERE user_id = $1
+        UNION ALL SELECT 'archived messages', COALESCE(sum(pg_column_size(t.*)), 0)::BIGINT FROM messages_archive t WHERE user_id = $1"
+    )
+        .bind(user_id)
+        .fetch_all(pool)
+        .await
+        .map_err(map_err)?;
+
+    Ok(DataStats {
+        knowledge_count,
+        knowledge_tags,
+        distinct_tags,
+        oldest_knowledge,
+        newest_knowledge,
+        strategy_count,
+        strategy_categories,
+        distinct_categories,
+        strategy_risk_levels,
+        message_count,
+        archived_message_count,
+        conversation_count,
+        longest_conversation,
+        storage,
+    })
+}
+
+/// Rows and size of the tables that grow with use, largest first
+pub async fn get_table_stats(pool: &Pool<Postgres>) -> Result<Vec<TableStats>, DbError> {
+    query_as::<_, TableStats>(
+        "SELECT name, rows, pg_total_relation_size(name::regclass) AS bytes FROM (
+            SELECT 'knowledge' AS name, (SELECT count(*) FROM knowledge) AS rows
+            UNION ALL SELECT 'messages', (SELECT count(*) FROM messages)
+            UNION ALL SELECT 'messages_archive', (SELECT count(*) FROM messages_archive)
+            UNION ALL SELECT 'conversation_summaries', (SELECT count(*) FROM conversation_summaries)
+            UNION ALL SELECT 'price_history', (SELECT count(*) FROM price_history)
+            UNION ALL SELECT 'gas_readings', (SELECT count(*) FROM gas_readings)
+            UNION ALL SELECT 'notifications', (SELECT count(*) FROM notifications)
+            UNION ALL SELECT 'recommendations', (SELECT count(*) FROM recommendations)
+            UNION ALL SELECT 'feedback', (SELECT count(*) FROM feedback)
+        ) counts
+        ORDER BY bytes DESC, name"
+    )
+        .fetch_all(pool)
+        .await
+        .map_err(|e| DbError::Query(e.to_string()))
+}
+
+// Rate limit queries
+
+/// Update a user's stored token bucket while holding its row lock, so processes take turns
+///
+/// `update` gets the stored tokens and refill time, if any, and returns the values to store with
+/// its own result. Two processes creating the same user's bucket at once may both see none.
+pub async fn update_rate_limit_bucket<T>(
+    pool: &Pool<Postgres>,
+    user_id: i32,
+    update: impl FnOnce(Option<(f64, NaiveDateTime)>) -> ((f64, NaiveDateTime), T),
+) -> Result<T, DbError> {
+    let mut tx = pool.begin().await.map_err(|e| DbError::Transaction(e.to_string()))?;
+
+    let stored = query_as::<_, (f64, NaiveDateTime)>("SELECT tokens, updated_at FROM rate_limit_buckets WHERE user_id = $1 FOR UPDATE")
+        .bind(user_id)
+        .fetch_optional(&mut *tx)
+        .await
+        .map_err(|e| DbError::Query(e.to_string()))?;
+
+    let ((tokens, updated_at), result) = update(stored);
+    query("INSERT INTO rate_limit_buckets (user_id, tokens, updated_at) VALUES ($1, $2, $3)
+        ON CONFLICT (user_id) DO UPDATE SET tokens = EXCLUDED.tokens, updated_at = EXCLUDED.updated_at")
+        .bind(user_id)
+        .bind(tokens)
+        .bind(updated_at)
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| DbError::Query(e.to_string()))?;
+
+    tx.commit().await.map_err(|e| DbError::Transaction(e.to_string()))?;
+    Ok(result)
+}
+
+// Account queries
+
+/// Tables holding rows owned by a user, children before parents
+pub const USER_OWNED_TABLES: &[&str] = &[
+    "messages_archive",
+    "messages",
+    "conversation_summaries",
+    "notifications",
+    "holdings",
+    "data_sources",
+    "knowledge",
+    "strategy_progress",
+    "strategy_outcomes",
+    "strategies",
+    "user_aliases",
+    "watchlist",
+    "recommendations",
+    "feedback",
+    "rate_limit_buckets",
+    "message_topics",
+    "turn_debug",
+    "limit_orders",
+    "trades",
+    "pending_trades",
+    "conversations",
+];
+
+/// Collect everything stored for a user
+/// Returns None if the user doesn't exist
+pub async fn export_user_data(pool: &Pool<Postgres>, username: &str) -> Result<Option<UserDataExport>, DbError> {
+    let Some(user) = get_user_by_username(pool, username).await? else {
+        return Ok(None);
+    };
+
+    let messages = export_messages(pool, user.id).await?;
+    let conversation_summaries = get_conversation_summaries(pool, user.id).await?;
+    let strategies = get_strategies_by_user_id(pool, user.id).await?;
+    let knowledge = get_knowledge_by_user_id(pool, user.id).await?;
+    let holdings = get_holdings_by_user_id(pool, user.id).await?;
+    let aliases = get_user_aliases(pool, user.id).await?;
+    let watchlist = get_watchlist(pool, user.id).await?;
+    let recommendations = get_recommendations(pool, user.id).await?;
+    let feedback = get_feedback(pool, user.id).await?;
+    let topics = get_message_topics(pool, user.id).await?;
+    let debug_turns = get_turn_debugs(pool, user.id).await?;
+    let limit_orders = get_limit_orders(pool, user.id).await?;
+    let strategy_progress = get_all_strategy_progress(pool, user.id).await?;
+    let strategy_outcomes = get_strategy_outcomes(pool, user.id).await?;
+    let trades = get_trades_by_user_id(pool, user.id, None, None).await?;
+    let conversations = get_conversations(pool, user.id).await?;
+
+    let data_sources = query_as::<_, DataSource>("SELECT id, user_id, source_id, name, description, source_type, refresh_interval_minutes, config, created_at, updated_at, last_refresh FROM data_sources WHERE user_id = $1 ORDER BY id")
+        .bind(user.id)
+        .fetch_all(pool)
+        .await
+        .map_err(|e| DbError::Query(e.to_string()))?;
+
+    let notifications = query_as::<_, Notification>("SELECT id, user_id, kind, message, created_at, read_at FROM notifications WHERE user_id = $1 ORDER BY created_at, id")
+        .bind(user.id)
+        .fetch_all(pool)
+        .await
+        .map_err(|e| DbError::Query(e.to_string()))?;
+
+    Ok(Some(UserDataExport {
+        exported_at: chrono::Utc::now().naive_utc(),
+        user,
+        messages,
+        conversation_summaries,
+        strategies,
+        knowledge,
+        data_sources,
+        holdings,
+        notifications,
+        aliases,
+        watchlist,
+        recommendations,
+        feedback,
+        topics,
+        debug_turns,
+        limit_orders,
+        strategy_progress,
+        strategy_outcomes,
+        trades,
+        conversations,
+    }))
+}
+
+/// Delete a user and every row they own in a single transaction
+/// Returns false if the user doesn't exist
+pub async fn delete_user_cascade(pool: &Pool<Postgres>, username: &str) -> Result<bool, DbError> {
+    let mut tx = pool.begin().await.map_err(|e| DbError::Transaction(e.to_string()))?;
+
+    let user_id = query_scalar::<_, i32>("SELECT id FROM users WHERE username = $1 FOR UPDATE")
+        .bind(username)
+        .fetch_optional(&mut *tx)
+        .await
+        .map_err(|e| DbError::Query(e.to_string()))?;
+    let Some(user_id) = user_id else {
+        return Ok(false);
+    };
+
+    // The foreign keys cascade too, deleting explicitly keeps this correct if one is ever missed
+    for table in USER_OWNED_TABLES {
+        query(&format!("DELETE FROM {} WHERE user_id = $1", table))
+            .bind(user_id)
+            .execute(&mut *tx)
+            .await
+            .map_err(|e| DbError::Query(e.to_string()))?;
+    }
+
+    query("DELETE FROM users WHERE id = $1")
+        .bind(user_id)
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| DbError::Query(e.to_string()))?;
+
+    tx.commit().await.map_err(|e| DbError::Transaction(e.to_string()))?;
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::testing::test_pool;
+
+    fn imported_trade(side: OrderType, amount: f64, price: f64) -> NewTrade {
+        let executed_at = chrono::NaiveDate::from_ymd_opt(2025, 3, 1).unwrap().and_hms_opt(12, 0, 0).unwrap();
+        NewTrade::filled(executed_at, side, "bitcoin", "USD", amount, price, 1.5)
+    }
+
+    /// Give `user_id` one row in every user-owned table, copying the seeded rows where it's simpler
+    async fn seed_user_rows(pool: &Pool<Postgres>, user_id: i32) {
+        save_message(pool, user_id, MessageRole::User, "old question").await.unwrap();
+        query("UPDATE messages SET created_at = '2025-01-01' WHERE user_id = $1").bind(user_id).execute(pool).await.unwrap();
+        let cutoff = NaiveDateTime::parse_from_str("2025-02-01 00:00:00", "%Y-%m-%d %H:%M:%S").unwrap();
+        archive_messages_before(pool, user_id, cutoff).await.unwrap();
+        save_message(pool, user_id, MessageRole::User, "new question").await.unwrap();
+        save_conversation_summary(pool, user_id, cutoff, cutoff, 1, "asked a question").await.unwrap();
+        create_notification(pool, user_id, "price_alert", "bitcoin moved").await.unwrap();
+        upsert_holding(pool, user_id, "bitcoin", 0.5).await.unwrap();
+        create_knowledge(pool, user_id, "notes", "likes stablecoins", &["defi".to_string()]).await.unwrap();
+        query("INSERT INTO strategies (user_id, strategy_id, name, category, description, risk_level, tags, steps, requirements, expected_returns, author, version)
+            SELECT $1, strategy_id || '-copy', name, category, description, risk_level, tags, steps, requirements, expected_returns, author, version FROM strategies WHERE user_id = 1 LIMIT 1")
+            .bind(user_id).execute(pool).await.unwrap();
+        query("INSERT INTO data_sources (user_id, source_id, name, description, source_type, refresh_interval_minutes, config)
+            SELECT $1, source_id || '-copy', name, description, source_type, refresh_interval_minutes, config FROM data_sources WHERE user_id = 1 LIMIT 1")
+            .bind(user_id).execute(pool).await.unwrap();
+        upsert_user_alias(pool, user_id, "big coin", "btc").await.unwrap();
+        add_watchlist_entry(pool, user_id, "solana", None).await.unwrap();
+        save_recommendation(pool, user_id, "ethereum", "accumulate", 2300.0, 2450.0, None).await.unwrap();
+        save_assistant_message(pool, user_id, "an answer", None, &[], false).await.unwrap();
+        let answer = get_last_assistant_message_id(pool, user_id).await.unwrap().unwrap();
+        save_feedback(pool, user_id, answer, 1, None).await.unwrap();
+        let question = get_last_user_message_id(pool, user_id).await.unwrap().unwrap();
+        save_message_topics(pool, user_id, question, &[("bitcoin".to_string(), TopicKind::Coin)]).await.unwrap();
+        save_turn_debug(pool, user_id, Some(question), "new question", "general", "{\"calls\":[]}", 10).await.unwrap();
+        create_limit_order(pool, user_id, &format!("order-{}", user_id), OrderType::Buy, "0xusdc", 100.0, 2300.0, None).await.unwrap();
+        let strategy = get_strategies_by_user_id(pool, user_id).await.unwrap().remove(0);
+        start_strategy_progress(pool, user_id, strategy.id, 1).await.unwrap();
+        save_strategy_outcome(pool, user_id, strategy.id, "went fine").await.unwrap();
+        import_trades(pool, user_id, &[imported_trade(OrderType::Buy, 0.5, 60_000.0)]).await.unwrap();
+        let conversation = create_conversation(pool, user_id).await.unwrap();
+        set_conversation_system_prompt(pool, conversation.id, Some("Answer tersely.")).await.unwrap();
+    }
+
+    async fn owned_rows(pool: &Pool<Postgres>, user_id: i32) -> i64 {
+        let mut total = 0;
+        for table in USER_OWNED_TABLES {
+            total += query_scalar::<_, i64>(&format!("SELECT count(*) FROM {} WHERE user_id = $1", table))
+                .bind(user_id)
+                .fetch_one(pool)
+                .await
+                .unwrap();
+        }
+        total
+    }
+
+    #[tokio::test]
+    async fn test_user_owned_tables_cover_schema() {
+        let Some(pool) = test_pool().await else { return };
+
+        let mut tables = query_scalar::<_, String>(
+            "SELECT table_name::text FROM information_schema.columns
+            WHERE table_schema = current_schema() AND column_name = 'user_id' ORDER BY table_name"
+        )
+            .fetch_all(&pool)
+            .await
+            .unwrap();
+        let mut expected: Vec<String> = USER_OWNED_TABLES.iter().map(|t| t.to_string()).collect();
+        tables.sort();
+        expected.sort();
+        assert_eq!(tables, expected);
+
+        // Every reference to users must cascade, so nothing blocks or outlives a deletion
+        let rules = query_scalar::<_, String>(
+            "SELECT rc.delete_rule::text FROM information_schema.referential_constraints rc
+            JOIN information_schema.constraint_column_usage ccu
+                ON ccu.constraint_name = rc.constraint_name AND ccu.constraint_schema = rc.constraint_schema
+            WHERE rc.constraint_schema = current_schema() AND ccu.table_name = 'users'"
+        )
+            .fetch_all(&pool)
+            .await
+            .unwrap();
+        assert_eq!(rules.len(), USER_OWNED_TABLES.len());
+        assert!(rules.iter().all(|rule| rule == "CASCADE"));
+    }
+
+    #[tokio::test]
+    async fn test_export_user_data_is_complete() {
+        let Some(pool) = test_pool().await else { return };
+        let alice = create_user(&pool, "alice", None).await.unwrap();
+        seed_user_rows(&pool, alice.id).await;
+
+        let export = export_user_data(&pool, "alice").await.unwrap().unwrap();
+        assert_eq!(export.user.id, alice.id);
+        let contents: Vec<&str> = export.messages.iter().map(|m| m.content.as_str()).collect();
+        assert_eq!(contents, vec!["old question", "new question", "an answer"]);
+        assert_eq!(export.conversation_summaries.len(), 1);
+        assert_eq!(export.notifications.len(), 1);
+        assert_eq!(export.holdings.len(), 1);
+        assert_eq!(export.knowledge.len(), 1);
+        assert_eq!(export.strategies.len(), 1);
+        assert_eq!(export.data_sources.len(), 1);
+        assert_eq!(export.aliases.len(), 1);
+        assert_eq!(export.watchlist.len(), 1);
+        assert_eq!(export.recommendations.len(), 1);
+        assert_eq!(export.feedback.len(), 1);
+        assert_eq!(export.topics.len(), 1);
+        assert_eq!(export.debug_turns.len(), 1);
+        assert_eq!(export.limit_orders.len(), 1);
+        assert_eq!(export.strategy_progress.len(), 1);
+        assert_eq!(export.strategy_outcomes.len(), 1);
+        assert_eq!(export.trades.len(), 1);
+        assert_eq!(export.conversations[0].system_prompt_override.as_deref(), Some("Answer tersely."));
+
+        // One exported row per owned row: messages_archive and messages share `messages`
+        let exported = export.messages.len() + export.conversation_summaries.len() + export.notifications.len()
+            + export.holdings.len() + export.knowledge.len() + export.strategies.len() + export.data_sources.len()
+            + export.aliases.len() + export.watchlist.len() + export.recommendations.len() + export.feedback.len()
+            + export.topics.len() + export.debug_turns.len() + export.limit_orders.len()
+            + export.strategy_progress.len() + export.strategy_outcomes.len() + export.trades.len()
+            + export.conversations.len();
+        assert_eq!(exported as i64, owned_rows(&pool, alice.id).await);
+
+        let json = serde_json::to_value(&export).unwrap();
+        assert_eq!(json["user"]["username"], "alice");
+        assert_eq!(json["messages"][0]["role"], "user");
+
+        assert!(export_user_data(&pool, "nobody").await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_delete_user_cascade_removes_everything() {
+        let Some(pool) = test_pool().await else { return };
+        let alice = create_user(&pool, "alice", None).await.unwrap();
+        seed_user_rows(&pool, alice.id).await;
+        seed_user_rows(&pool, 1).await;
+        let other_rows = owned_rows(&pool, 1).await;
+
+        assert!(delete_user_cascade(&pool, "alice").await.unwrap());
+        assert_eq!(owned_rows(&pool, alice.id).await, 0);
+        assert!(get_user_by_username(&pool, "alice").await.unwrap().is_none());
+
+        // Other users are untouched
+        assert_eq!(owned_rows(&pool, 1).await, other_rows);
+        assert!(!delete_user_cascade(&pool, "alice").await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_user_verbosity_defaults_to_normal() {
+        let Some(pool) = test_pool().await else { return };
+        let alice = create_user(&pool, "alice", None).await.unwrap();
+        assert_eq!(alice.verbosity, Verbosity::Normal);
+
+        set_user_verbosity(&pool, alice.id, Verbosity::Brief).await.unwrap();
+        assert_eq!(get_user_by_id(&pool, alice.id).await.unwrap().unwrap().verbosity, Verbosity::Brief);
+        assert_eq!(get_user_by_id(&pool, 1).await.unwrap().unwrap().verbosity, Verbosity::Normal);
+    }
+
+    #[tokio::test]
+    async fn test_user_timezone_defaults_to_utc() {
+        let Some(pool) = test_pool().await else { return };
+        let alice = create_user(&pool, "alice", None).await.unwrap();
+        assert_eq!(alice.timezone, "UTC");
+
+        set_user_timezone(&pool, alice.id, "UTC-05:30").await.unwrap();
+        assert_eq!(get_user_by_id(&pool, alice.id).await.unwrap().unwrap().timezone, "UTC-05:30");
+        set_user_timezone(&pool, alice.id, "America/Argentina/Buenos_Aires").await.unwrap();
+        assert_eq!(get_user_by_id(&pool, alice.id).await.unwrap().unwrap().timezone, "America/Argentina/Buenos_Aires");
+        // Only offsets and zone names are stored
+        assert!(set_user_timezone(&pool, alice.id, "UTC+99:00").await.is_err());
+        assert!(set_user_timezone(&pool, alice.id, "my place").await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_data_stats_counts_seeded_data() {
+        let Some(pool) = test_pool().await else { return };
+        let alice = create_user(&pool, "alice", None).await.unwrap();
+
+        for (source, tags) in [("a", vec!["defi", "eth"]), ("b", vec!["defi"]), ("c", vec!["defi", "btc"]), ("d", vec!["nft"])] {
+            let tags: Vec<String> = tags.into_iter().map(str::to_string).collect();
+            create_knowledge(&pool, alice.id, source, "notes", &tags).await.unwrap();
+        }
+        query("UPDATE knowledge SET created_at = '2025-01-01' WHERE user_id = $1 AND source_id = 'c'").bind(alice.id).execute(&pool).await.unwrap();
+        query("INSERT INTO strategies (user_id, strategy_id, name, category, description, risk_level, tags, steps, requirements, expected_returns, author, version)
+            SELECT $1, strategy_id || '-copy', name, 'yield', description, risk_level, tags, steps, requirements, expected_returns, author, version FROM strategies WHERE user_id = 1 LIMIT 2")
+            .bind(alice.id).execute(&pool).await.unwrap();
+
+        // Two conversations: three messages in the morning, one in the evening
+        for content in ["hi", "price of eth?", "thanks", "evening"] {
+            save_message(&pool, alice.id, MessageRole::User, content).await.unwrap();
+        }
+        for (content, time) in [("hi", "09:00"), ("price of eth?", "09:05"), ("thanks", "09:20"), ("evening", "19:00")] {
+            query(&format!("UPDATE messages SET created_at = '2025-09-20 {}' WHERE user_id = $1 AND content = $2", time))
+                .bind(alice.id).bind(content).execute(&pool).await.unwrap();
+        }
+
+        let stats = get_data_stats(&pool, alice.id, 2).await.unwrap();
+
+        assert_eq!(stats.knowledge_count, 4);
+        assert_eq!(stats.distinct_tags, 4);
+        assert_eq!(stats.knowledge_tags, vec![
+            NamedCount { name: "defi".to_string(), count: 3 },
+            NamedCount { name: "btc".to_string(), count: 1 },
+        ]);
+        assert_eq!(stats.oldest_knowledge.unwrap().source_id, "c");
+        assert_eq!(stats.newest_knowledge.unwrap().source_id, "d");
+        assert_eq!(stats.strategy_count, 2);
+        assert_eq!(stats.strategy_categories, vec![NamedCount { name: "yield".to_string(), count: 2 }]);
+        assert_eq!(stats.strategy_risk_levels.iter().map(|risk| risk.count).sum::<i64>(), 2);
+        assert_eq!((stats.message_count, stats.conversation_count, stats.longest_conversation), (4, 2, 3));
+        assert!(stats.storage.iter().any(|table| table.name == "knowledge" && table.count > 0));
+
+        let empty = get_data_stats(&pool, create_user(&pool, "bob", None).await.unwrap().id, 2).await.unwrap();
+        assert_eq!((empty.knowledge_count, empty.conversation_count, empty.longest_conversation), (0, 0, 0));
+        assert!(empty.oldest_knowledge.is_none());
+    }
+
+    fn knowledge_input(source_id: &str) -> KnowledgeInput {
+        KnowledgeInput {
+            source_id: source_id.to_string(),
+            content: format!("notes from {}", source_id),
+            tags: vec!["research".to_string(), source_id.to_string()],
+        }
+    }
+
+    #[tokio::test]
+    async fn test_knowledge_batch_inserts_every_entry() {
+        let Some(pool) = test_pool().await else { return };
+        let items: Vec<KnowledgeInput> = (0..(KNOWLEDGE_BATCH_ROWS + 5)).map(|i| knowledge_input(&format!("batch-{}", i))).collect();
+
+        let batch = create_knowledge_batch(&pool, 1, &items, ConflictMode::FailAll).await.unwrap();
+
+        assert_eq!(batch.created.len(), items.len());
+        assert!(batch.skipped.is_empty());
+        let stored = get_knowledge_by_source_id(&pool, 1, "batch-1003").await.unwrap().unwrap();
+        assert_eq!(stored.id, batch.created[1003]);
+        assert_eq!(stored.tags, vec!["research".to_string(), "batch-1003".to_string()]);
+
+        let empty = create_knowledge_batch(&pool, 1, &[], ConflictMode::FailAll).await.unwrap();
+        assert_eq!(empty, KnowledgeBatch::default());
+    }
+
+    #[tokio::test]
+    async fn test_knowledge_batch_fail_all_rolls_back() {
+        let Some(pool) = test_pool().await else { return };
+        create_knowledge(&pool, 1, "existing", "already stored", &[]).await.unwrap();
+        let before = get_knowledge_by_user_id(&pool, 1).await.unwrap().len();
+
+        let items = vec![knowledge_input("new-1"), knowledge_input("existing"), knowledge_input("new-2")];
+        let error = create_knowledge_batch(&pool, 1, &items, ConflictMode::FailAll).await.unwrap_err();
+        assert!(matches!(error, DbError::Constraint(_)), "unexpected error: {}", error);
+
+        let repeated = vec![knowledge_input("new-1"), knowledge_input("new-1")];
+        let error = create_knowledge_batch(&pool, 1, &repeated, ConflictMode::FailAll).await.unwrap_err();
+        assert!(matches!(error, DbError::Constraint(_)), "unexpected error: {}", error);
+
+        assert_eq!(get_knowledge_by_user_id(&pool, 1).await.unwrap().len(), before);
+        assert!(get_knowledge_by_source_id(&pool, 1, "new-1").await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_knowledge_batch_skips_and_reports_conflicts() {
+        let Some(pool) = test_pool().await else { return };
+        create_knowledge(&pool, 1, "existing", "already stored", &[]).await.unwrap();
+        let other_user = create_user(&pool, "batch_user", None).await.unwrap();
+        create_knowledge(&pool, other_user.id, "new-2", "another user's entry", &[]).await.unwrap();
+
+        let items = vec![knowledge_input("new-1"), knowledge_input("existing"), knowledge_input("new-2"), knowledge_input("new-1")];
+        let batch = create_knowledge_batch(&pool, 1, &items, ConflictMode::Skip).await.unwrap();
+
+        assert_eq!(batch.created.len(), 2);
+        assert_eq!(batch.skipped, vec!["new-1".to_string(), "existing".to_string()]);
+        assert_eq!(get_knowledge_by_source_id(&pool, 1, "existing").await.unwrap().unwrap().content, "already stored");
+        assert_eq!(get_knowledge_by_source_id(&pool, 1, "new-2").await.unwrap().unwrap().id, batch.created[1]);
+    }
+
+    #[tokio::test]
+    async fn test_user_aliases_persist_per_user() {
+        let Some(pool) = test_pool().await else { return };
+        let alice = create_user(&pool, "alice", None).await.unwrap();
+
+        upsert_user_alias(&pool, 1, "big coin", "btc").await.unwrap();
+        upsert_user_alias(&pool, 1, "aero", "aerodrome").await.unwrap();
+        upsert_user_alias(&pool, alice.id, "big coin", "eth").await.unwrap();
+
+        // Redefining an alias replaces its target
+        upsert_user_alias(&pool, 1, "big coin", "bitcoin").await.unwrap();
+        let aliases = get_user_aliases(&pool, 1).await.unwrap();
+        let pairs: Vec<(&str, &str)> = aliases.iter().map(|a| (a.alias.as_str(), a.target.as_str())).collect();
+        assert_eq!(pairs, vec![("aero", "aerodrome"), ("big coin", "bitcoin")]);
+
+        assert!(delete_user_alias(&pool, 1, "big coin").await.unwrap());
+        assert!(!delete_user_alias(&pool, 1, "big coin").await.unwrap());
+        assert_eq!(get_user_aliases(&pool, 1).await.unwrap().len(), 1);
+        assert_eq!(get_user_aliases(&pool, alice.id).await.unwrap()[0].target, "eth");
+    }
+
+    #[tokio::test]
+    async fn test_watchlist_crud() {
+        let Some(pool) = test_pool().await else { return };
+        let alice = create_user(&pool, "alice", None).await.unwrap();
+
+        let (entry, added) = add_watchlist_entry(&pool, alice.id, "solana", Some("waiting for $120")).await.unwrap();
+        assert!(added);
+        assert_eq!(entry.note.as_deref(), Some("waiting for $120"));
+        add_watchlist_entry(&pool, alice.id, "chainlink", None).await.unwrap();
+
+        // Adding again keeps the original entry and note
+        let (entry, added) = add_watchlist_entry(&pool, alice.id, "solana", None).await.unwrap();
+        assert!(!added);
+        assert_eq!(entry.note.as_deref(), Some("waiting for $120"));
+
+        assert!(set_watchlist_note(&pool, alice.id, "chainlink", Some("oracle play")).await.unwrap());
+        assert!(!set_watchlist_note(&pool, alice.id, "dogecoin", Some("nope")).await.unwrap());
+
+        let coins: Vec<(String, Option<String>)> = get_watchlist(&pool, alice.id)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|entry| (entry.coin_id, entry.note))
+            .collect();
+        assert_eq!(coins, vec![
+            ("solana".to_string(), Some("waiting for $120".to_string())),
+            ("chainlink".to_string(), Some("oracle play".to_string())),
+        ]);
+        assert_eq!(get_all_watchlist_entries(&pool).await.unwrap().len(), 2);
+
+        assert!(remove_watchlist_entry(&pool, alice.id, "solana").await.unwrap());
+        assert!(!remove_watchlist_entry(&pool, alice.id, "solana").await.unwrap());
+        assert_eq!(get_watchlist(&pool, alice.id).await.unwrap().len(), 1);
+        assert!(get_watchlist(&pool, 1).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_recommendations_are_recorded_once_per_day() {
+        let Some(pool) = test_pool().await else { return };
+
+        let saved = save_recommendation(&pool, 1, "ethereum", "accumulate", 2300.0, 2450.0, None).await.unwrap();
+        assert!(saved.is_some());
+        assert!(save_recommendation(&pool, 1, "ethereum", "accumulate", 2300.0, 2450.0, None).await.unwrap().is_none());
+        save_recommendation(&pool, 1, "ethereum", "take_profit", 2700.0, 2900.0, None).await.unwrap();
+
+        let actions: Vec<String> = get_recommendations(&pool, 1).await.unwrap().into_iter().map(|r| r.action).collect();
+        assert_eq!(actions, vec!["accumulate", "take_profit"]);
+
+        // Only the allowed actions are stored
+        assert!(save_recommendation(&pool, 1, "ethereum", "moon", 1.0, 2.0, None).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_topic_counts_by_period_and_kind() {
+        let Some(pool) = test_pool().await else { return };
+        let alice = create_user(&pool, "alice", None).await.unwrap();
+        let coin = |name: &str| (name.to_string(), TopicKind::Coin);
+        let keyword = |name: &str| (name.to_string(), TopicKind::Keyword);
+        let turns = [
+            vec![coin("bitcoin"), keyword("staking")],
+            vec![coin("bitcoin"), coin("solana")],
+            vec![coin("solana"), keyword("staking")],
+            vec![coin("bitcoin")],
+        ];
+        for topics in &turns {
+            save_message(&pool, alice.id, MessageRole::User, "a question").await.unwrap();
+            let message_id = get_last_user_message_id(&pool, alice.id).await.unwrap().unwrap();
+            assert_eq!(save_message_topics(&pool, alice.id, message_id, topics).await.unwrap(), topics.len() as u64);
+            // Recording a turn again adds nothing
+            assert_eq!(save_message_topics(&pool, alice.id, message_id, topics).await.unwrap(), 0);
+        }
+        // The first turn was two months ago
+        query("UPDATE message_topics SET created_at = now() - interval '60 days' WHERE id IN (SELECT id FROM message_topics ORDER BY id LIMIT 2)")
+            .execute(&pool)
+            .await
+            .unwrap();
+        save_message_topics(&pool, 1, get_last_user_message_id(&pool, alice.id).await.unwrap().unwrap() + 1000, &[coin("dogecoin")])
+            .await
+            .unwrap();
+
+        let counts = |since, kind| {
+            let pool = pool.clone();
+            async move {
+                get_topic_counts(&pool, alice.id, since, kind, 10)
+                    .await
+                    .unwrap()
+                    .into_iter()
+                    .map(|count| (count.topic, count.kind, count.messages))
+                    .collect::<Vec<_>>()
+            }
+        };
+        assert_eq!(counts(None, None).await, vec![
+            ("bitcoin".to_string(), TopicKind::Coin, 3),
+            ("solana".to_string(), TopicKind::Coin, 2),
+            ("staking".to_string(), TopicKind::Keyword, 2),
+        ]);
+        let month = chrono::Utc::now().naive_utc() - chrono::Duration::days(30);
+        assert_eq!(counts(Some(month), Some(TopicKind::Coin)).await, vec![
+            ("bitcoin".to_string(), TopicKind::Coin, 2),
+            ("solana".to_string(), TopicKind::Coin, 2),
+        ]);
+        assert_eq!(counts(Some(month), Some(TopicKind::Keyword)).await, vec![("staking".to_string(), TopicKind::Keyword, 1)]);
+        assert_eq!(get_topic_counts(&pool, alice.id, None, None, 1).await.unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_turn_debug_keeps_the_latest_turns() {
+        let Some(pool) = test_pool().await else { return };
+        let alice = create_user(&pool, "alice", None).await.unwrap();
+        let mut ids = Vec::new();
+        for n in 0..4 {
+            ids.push(save_turn_debug(&pool, alice.id, None, &format!("question {}", n), "general", "{}", 3).await.unwrap());
+        }
+        save_turn_debug(&pool, 1, None, "someone else's", "price", "{}", 3).await.unwrap();
+
+        let kept: Vec<i32> = get_turn_debugs(&pool, alice.id).await.unwrap().into_iter().map(|turn| turn.id).collect();
+        assert_eq!(kept, ids[1..].to_vec());
+        let latest = get_turn_debug(&pool, alice.id, None).await.unwrap().unwrap();
+        assert_eq!(latest.question, "question 3");
+        assert_eq!(get_turn_debug(&pool, alice.id, Some(ids[1])).await.unwrap().unwrap().question, "question 1");
+        // Dropped turns and other users' turns aren't found
+        assert!(get_turn_debug(&pool, alice.id, Some(ids[0])).await.unwrap().is_none());
+        let other = get_turn_debug(&pool, 1, None).await.unwrap().unwrap();
+        assert!(get_turn_debug(&pool, alice.id, Some(other.id)).await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_limit_order_lifecycle() {
+        let Some(pool) = test_pool().await else { return };
+        let now = chrono::Utc::now().naive_utc();
+        let alice = create_user(&pool, "alice", None).await.unwrap();
+        let buy = create_limit_order(&pool, alice.id, "buy-1", OrderType::Buy, "0xusdc", 100.0, 2300.0, None).await.unwrap();
+        assert_eq!((buy.order_type, buy.status), (OrderType::Buy, OrderStatus::Open));
+        create_limit_order(&pool, alice.id, "sell-1", OrderType::Sell, "0xweth", 0.5, 2900.0, None).await.unwrap();
+        create_limit_order(&pool, 1, "other-1", OrderType::Buy, "0xusdc", 10.0, 2000.0, None).await.unwrap();
+
+        let open: Vec<String> = get_open_limit_orders(&pool, alice.id, now).await.unwrap().into_iter().map(|order| order.id).collect();
+        assert_eq!(open, vec!["buy-1", "sell-1"]);
+
+        let filled = update_limit_order_status(&pool, alice.id, "sell-1", OrderStatus::Filled).await.unwrap().unwrap();
+        assert_eq!(filled.status, OrderStatus::Filled);
+        // Final statuses don't change, and other users' orders can't be touched
+        assert!(cancel_limit_order(&pool, alice.id, "sell-1").await.unwrap().is_none());
+        assert!(cancel_limit_order(&pool, alice.id, "other-1").await.unwrap().is_none());
+        assert_eq!(get_limit_order(&pool, alice.id, "sell-1").await.unwrap().unwrap().status, OrderStatus::Filled);
+
+        let cancelled = cancel_limit_order(&pool, alice.id, "buy-1").await.unwrap().unwrap();
+        assert_eq!(cancelled.status, OrderStatus::Cancelled);
+        assert!(get_open_limit_orders(&pool, alice.id, now).await.unwrap().is_empty());
+        assert_eq!(get_limit_orders(&pool, alice.id).await.unwrap().len(), 2);
+        assert_eq!(get_open_limit_orders(&pool, 1, now).await.unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_limit_orders_expire() {
+        let Some(pool) = test_pool().await else { return };
+        let alice = create_user(&pool, "alice", None).await.unwrap();
+        let at = |text: &str| NaiveDateTime::parse_from_str(text, "%Y-%m-%d %H:%M").unwrap();
+        create_limit_order(&pool, alice.id, "friday", OrderType::Buy, "0xusdc", 100.0, 2300.0, Some(at("2025-10-17 21:59"))).await.unwrap();
+        create_limit_order(&pool, alice.id, "forever", OrderType::Sell, "0xweth", 0.5, 2900.0, None).await.unwrap();
+
+        let open = |now| {
+            let pool = pool.clone();
+            async move { get_open_limit_orders(&pool, alice.id, now).await.unwrap().into_iter().map(|order| order.id).collect::<Vec<_>>() }
+        };
+        assert_eq!(open(at("2025-10-17 21:58")).await, vec!["friday", "forever"]);
+        // Past its expiry an order isn't open anymore, even before it's moved to expired
+        assert_eq!(open(at("2025-10-17 21:59")).await, vec!["forever"]);
+
+        assert!(expire_limit_orders(&pool, alice.id, at("2025-10-17 21:00")).await.unwrap().is_empty());
+        let expired = expire_limit_orders(&pool, alice.id, at("2025-10-18 09:00")).await.unwrap();
+        assert_eq!(expired.len(), 1);
+        assert_eq!((expired[0].id.as_str(), expired[0].status), ("friday", OrderStatus::Expired));
+        assert_eq!(expired[0].expires_at, Some(at("2025-10-17 21:59")));
+        // An expired order is final like a filled one
+        assert!(cancel_limit_order(&pool, alice.id, "friday").await.unwrap().is_none());
+        assert!(expire_limit_orders(&pool, alice.id, at("2025-10-18 09:00")).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_pending_trade_is_replaced_and_taken_once() {
+        let Some(pool) = test_pool().await else { return };
+        let alice = create_user(&pool, "alice", None).await.unwrap();
+        assert!(take_pending_trade(&pool, alice.id).await.unwrap().is_none());
+
+        let now = chrono::Utc::now().naive_utc();
+        save_pending_trade(&pool, alice.id, &serde_json::json!({"kind": "swap", "amount": 0.1}), now).await.unwrap();
+        save_pending_trade(&pool, alice.id, &serde_json::json!({"kind": "swap", "amount": 0.2}), now).await.unwrap();
+        save_pending_trade(&pool, 1, &serde_json::json!({"kind": "swap", "amount": 5.0}), now).await.unwrap();
+
+        let pending = take_pending_trade(&pool, alice.id).await.unwrap().unwrap();
+        assert_eq!(pending.trade["amount"], 0.2);
+        assert!(take_pending_trade(&pool, alice.id).await.unwrap().is_none());
+        assert!(take_pending_trade(&pool, 1).await.unwrap().is_some());
+    }
+
+    #[tokio::test]
+    async fn test_imported_trades_are_not_duplicated() {
+        let Some(pool) = test_pool().await else { return };
+        let alice = create_user(&pool, "alice", None).await.unwrap();
+        let trades = [imported_trade(OrderType::Buy, 0.5, 60_000.0), imported_trade(OrderType::Sell, 0.2, 65_000.0)];
+        assert_eq!(import_trades(&pool, alice.id, &trades).await.unwrap(), 2);
+
+        // Importing the same export again stores nothing new
+        assert_eq!(import_trades(&pool, alice.id, &trades).await.unwrap(), 0);
+        assert_eq!(import_trades(&pool, 1, &trades[..1]).await.unwrap(), 1);
+
+        let stored = get_trades_by_user_id(&pool, alice.id, None, None).await.unwrap();
+        assert_eq!(stored.len(), 2);
+        assert!(stored.iter().all(|trade| trade.imported && trade.fee == 1.5));
+        assert_eq!(stored.iter().map(|trade| trade.side).collect::<Vec<_>>(), vec![OrderType::Buy, OrderType::Sell]);
+    }
+
+    #[tokio::test]
+    async fn test_executed_trades_are_recorded_and_filtered_by_date() {
+        let Some(pool) = test_pool().await else { return };
+        let alice = create_user(&pool, "alice", None).await.unwrap();
+        let day = |day| chrono::NaiveDate::from_ymd_opt(2025, 10, day).unwrap().and_hms_opt(12, 0, 0).unwrap();
+        let swap = NewTrade {
+            tx_hash: Some("0xabc".to_string()),
+            from_token: Some("0xusdc".to_string()),
+            to_token: Some("0xweth".to_string()),
+            from_amount: Some(250.0),
+            to_amount: Some(0.1),
+            price_usd_at_execution: Some(2500.0),
+            gas_cost: Some(0.0004),
+            aggregator: Some("0x".to_string()),
+            ..NewTrade::filled(day(3), OrderType::Buy, "ethereum", "USDC", 0.1, 2500.0, 0.0)
+        };
+        let stored = create_trade(&pool, alice.id, &swap).await.unwrap();
+        assert_eq!((stored.status, stored.imported), (TradeStatus::Filled, false));
+        assert_eq!((stored.tx_hash.as_deref(), stored.gas_cost), (Some("0xabc"), Some(0.0004)));
+        assert_eq!(stored.aggregator.as_deref(), Some("0x"));
+
+        let failed = NewTrade { executed_at: day(10), status: TradeStatus::Failed, tx_hash: None, ..swap.clone() };
+        create_trade(&pool, alice.id, &failed).await.unwrap();
+        create_trade(&pool, alice.id, &NewTrade { executed_at: day(20), ..swap.clone() }).await.unwrap();
+
+        let statuses = |trades: Vec<Trade>| trades.into_iter().map(|trade| (trade.executed_at, trade.status)).collect::<Vec<_>>();
+        assert_eq!(
+            statuses(get_trades_by_user_id(&pool, alice.id, Some(day(3)), Some(day(20))).await.unwrap()),
+            vec![(day(3), TradeStatus::Filled), (day(10), TradeStatus::Failed)]
+        );
+        assert_eq!(get_trades_by_user_id(&pool, alice.id, Some(day(4)), None).await.unwrap().len(), 2);
+        assert_eq!(get_trades_by_user_id(&pool, alice.id, None, Some(day(4))).await.unwrap().len(), 1);
+        assert!(get_trades_by_user_id(&pool, 1, None, None).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_sent_swaps_are_settled_by_transaction() {
+        let Some(pool) = test_pool().await else { return };
+        let alice = create_user(&pool, "alice", None).await.unwrap();
+        let day = |day| chrono::NaiveDate::from_ymd_opt(2025, 10, day).unwrap().and_hms_opt(12, 0, 0).unwrap();
+        let sent = |day, tx_hash: &str| NewTrade {
+            tx_hash: Some(tx_hash.to_string()),
+            status: TradeStatus::Pending,
+            ..NewTrade::filled(day, OrderType::Buy, "ethereum", "USDC", 0.1, 2500.0, 0.0)
+        };
+        assert!(get_last_swap(&pool, alice.id).await.unwrap().is_none());
+        create_trade(&pool, alice.id, &sent(day(3), "0xaaa")).await.unwrap();
+        create_trade(&pool, alice.id, &sent(day(5), "0xbbb")).await.unwrap();
+        create_trade(&pool, alice.id, &NewTrade::filled(day(9), OrderType::Sell, "ethereum", "USDC", 0.1, 2600.0, 0.0)).await.unwrap();
+        assert_eq!(get_last_swap(&pool, alice.id).await.unwrap().unwrap().tx_hash.as_deref(), Some("0xbbb"));
+
+        let confirmed = update_trade_status(&pool, alice.id, "0xBBB", TradeStatus::Confirmed, Some(4660), Some(173041), Some(0.00026))
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!((confirmed.status, confirmed.block_number, confirmed.gas_used), (TradeStatus::Confirmed, Some(4660), Some(173041)));
+        assert_eq!(confirmed.gas_cost, Some(0.00026));
+        // A settled swap doesn't get stuck, a pending one does; other users' swaps aren't touched
+        assert!(update_trade_status(&pool, alice.id, "0xbbb", TradeStatus::Stuck, None, None, None).await.unwrap().is_none());
+        assert!(update_trade_status(&pool, 1, "0xaaa", TradeStatus::Stuck, None, None, None).await.unwrap().is_none());
+        let stuck = update_trade_status(&pool, alice.id, "0xaaa", TradeStatus::Stuck, None, None, None).await.unwrap().unwrap();
+        assert_eq!((stuck.status, stuck.block_number), (TradeStatus::Stuck, None));
+        // A stuck swap that is mined after all is settled
+        let failed = update_trade_status(&pool, alice.id, "0xaaa", TradeStatus::Failed, Some(4700), Some(50000), None).await.unwrap().unwrap();
+        assert_eq!((failed.status, failed.block_number, failed.gas_cost), (TradeStatus::Failed, Some(4700), None));
+    }
+
+    #[tokio::test]
+    async fn test_strategy_progress_lifecycle() {
+        let Some(pool) = test_pool().await else { return };
+        let alice = create_user(&pool, "alice", None).await.unwrap();
+        seed_user_rows(&pool, alice.id).await;
+        let strategy = get_strategies_by_user_id(&pool, alice.id).await.unwrap().remove(0);
+
+        let progress = start_strategy_progress(&pool, alice.id, strategy.id, 3).await.unwrap();
+        assert_eq!(progress.iter().map(|step| step.step_index).collect::<Vec<_>>(), vec![0, 1, 2]);
+        assert!(progress.iter().all(|step| step.completed_at.is_none()));
+        assert_eq!(get_unfinished_strategy_ids(&pool, alice.id).await.unwrap(), vec![strategy.id]);
+
+        let done =
+            complete_strategy_step(&pool, alice.id, strategy.id, 1, Some("bought 0.1 ETH")).await.unwrap().unwrap();
+        assert!(done.completed_at.is_some());
+        assert_eq!(done.note.as_deref(), Some("bought 0.1 ETH"));
+        // Done steps, missing steps and other users' progress don't change
+        assert!(complete_strategy_step(&pool, alice.id, strategy.id, 1, None).await.unwrap().is_none());
+        assert!(complete_strategy_step(&pool, alice.id, strategy.id, 3, None).await.unwrap().is_none());
+        assert!(complete_strategy_step(&pool, 1, strategy.id, 0, None).await.unwrap().is_none());
+
+        complete_strategy_step(&pool, alice.id, strategy.id, 0, None).await.unwrap().unwrap();
+        complete_strategy_step(&pool, alice.id, strategy.id, 2, None).await.unwrap().unwrap();
+        assert!(get_unfinished_strategy_ids(&pool, alice.id).await.unwrap().is_empty());
+
+        // Starting again is a fresh run
+        start_strategy_progress(&pool, alice.id, strategy.id, 3).await.unwrap();
+        let progress = get_strategy_progress(&pool, alice.id, strategy.id).await.unwrap();
+        assert_eq!(progress.len(), 3);
+        assert!(progress.iter().all(|step| step.completed_at.is_none() && step.note.is_none()));
+
+        save_strategy_outcome(&pool, alice.id, strategy.id, "averaged in at $2,450").await.unwrap();
+        let notes: Vec<String> =
+            get_strategy_outcomes(&pool, alice.id).await.unwrap().into_iter().map(|outcome| outcome.note).collect();
+        assert_eq!(notes, vec!["went fine", "averaged in at $2,450"]);
+    }
+
+    #[tokio::test]
+    async fn test_weekly_report_queries() {
+        let Some(pool) = test_pool().await else { return };
+        let alice = create_user(&pool, "alice", None).await.unwrap();
+        // Everything seeded here is from today, outside the week
+        seed_user_rows(&pool, alice.id).await;
+        let at = |text: &str| NaiveDateTime::parse_from_str(text, "%Y-%m-%d %H:%M").unwrap();
+        let (start, end) = (at("2025-10-06 00:00"), at("2025-10-13 00:00"));
+
+        for (content, role, created_at) in [
+            ("first", MessageRole::User, "2025-10-07 12:00"),
+            ("an answer in the week", MessageRole::Assistant, "2025-10-07 12:20"),
+            ("follow up", MessageRole::User, "2025-10-07 12:30"),
+            ("second conversation", MessageRole::User, "2025-10-09 08:00"),
+            ("next week", MessageRole::User, "2025-10-13 00:00"),
+        ] {
+            save_message(&pool, alice.id, role, content).await.unwrap();
+            query("UPDATE messages SET created_at = $3 WHERE user_id = $1 AND content = $2")
+                .bind(alice.id).bind(content).bind(at(created_at)).execute(&pool).await.unwrap();
+        }
+        let conversations = get_conversations_between(&pool, alice.id, start, end, 3).await.unwrap();
+        assert_eq!(conversations, vec![
+            ConversationDigest { started_at: at("2025-10-07 12:00"), message_count: 3, opening: Some("first".to_string()) },
+            ConversationDigest { started_at: at("2025-10-09 08:00"), message_count: 1, opening: Some("second conversation".to_string()) },
+        ]);
+        assert_eq!(get_conversations_between(&pool, alice.id, start, end, 1).await.unwrap().len(), 1);
+
+        create_notification(&pool, alice.id, "peg_alert", "USDC depegged").await.unwrap();
+        create_notification(&pool, alice.id, "price_alert", "later alert").await.unwrap();
+        query("UPDATE notifications SET created_at = CASE WHEN message = 'USDC depegged' THEN timestamp '2025-10-10 09:00' ELSE timestamp '2025-10-14 09:00' END WHERE user_id = $1")
+            .bind(alice.id).execute(&pool).await.unwrap();
+        let alerts = get_notifications_between(&pool, alice.id, start, end).await.unwrap();
+        assert_eq!(alerts.iter().map(|alert| alert.message.as_str()).collect::<Vec<_>>(), vec!["USDC depegged"]);
+
+        // An order placed before the week counts when it filled during it
+        for (id, created_at, updated_at) in [("filled-in-week", "2025-09-30 10:00", "2025-10-08 10:00"), ("stale", "2025-09-01 10:00", "2025-09-02 10:00")] {
+            create_limit_order(&pool, alice.id, id, OrderType::Sell, "0xweth", 0.5, 2900.0, None).await.unwrap();
+            query("UPDATE limit_orders SET created_at = $2, updated_at = $3 WHERE id = $1")
+                .bind(id).bind(at(created_at)).bind(at(updated_at)).execute(&pool).await.unwrap();
+        }
+        let trades = get_limit_orders_between(&pool, alice.id, start, end).await.unwrap();
+        assert_eq!(trades.iter().map(|order| order.id.as_str()).collect::<Vec<_>>(), vec!["filled-in-week"]);
+
+        let strategy = get_strategies_by_user_id(&pool, alice.id).await.unwrap().remove(0);
+        query("UPDATE strategies SET created_at = '2025-09-01', updated_at = '2025-10-08 10:00' WHERE id = $1")
+            .bind(strategy.id).execute(&pool).await.unwrap();
+        start_strategy_progress(&pool, alice.id, strategy.id, 2).await.unwrap();
+        complete_strategy_step(&pool, alice.id, strategy.id, 0, None).await.unwrap();
+        query("UPDATE strategy_progress SET completed_at = '2025-10-07 09:00' WHERE strategy_id = $1 AND step_index = 0")
+            .bind(strategy.id).execute(&pool).await.unwrap();
+        assert_eq!(get_strategy_activity_between(&pool, alice.id, start, end).await.unwrap(), vec![StrategyActivity {
+            name: strategy.name.clone(),
+            created: false,
+            updated: true,
+            steps_completed: 1,
+            outcomes_noted: 0,
+        }]);
+        let earlier = get_strategy_activity_between(&pool, alice.id, at("2025-09-29 00:00"), start).await.unwrap();
+        assert!(earlier.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_get_price_points_since() {
+        let Some(pool) = test_pool().await else { return };
+        query("INSERT INTO price_history (coin_id, price_usd, fetched_at) VALUES
+            ('ethereum', 2000.0, '2025-09-01 00:00'), ('ethereum', 2100.0, '2025-09-02 00:00'), ('ethereum', 2200.0, '2025-09-03 00:00'),
+            ('bitcoin', 60000.0, '2025-09-02 12:00')")
+            .execute(&pool)
+            .await
+            .unwrap();
+
+        let since = NaiveDateTime::parse_from_str("2025-09-01 12:00:00", "%Y-%m-%d %H:%M:%S").unwrap();
+        let prices: Vec<f64> = get_price_points_since(&pool, "ethereum", since).await.unwrap().into_iter().map(|p| p.price_usd).collect();
+        assert_eq!(prices, vec![2100.0, 2200.0]);
+    }
+
+    #[tokio::test]
+    async fn test_api_usage_is_counted_per_upstream_and_day() {
+        let Some(pool) = test_pool().await else { return };
+        let day = NaiveDate::from_ymd_opt(2025, 10, 13).unwrap();
+        for upstream in ["exa", "coingecko", "exa"] {
+            record_api_call(&pool, upstream, day).await.unwrap();
+        }
+        record_api_call(&pool, "exa", day.succ_opt().unwrap()).await.unwrap();
+
+        assert_eq!(get_api_usage(&pool, day).await.unwrap(), [("coingecko".to_string(), 1), ("exa".to_string(), 2)]);
+        assert!(get_api_usage(&pool, day.pred_opt().unwrap()).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_gas_readings_window() {
+        let Some(pool) = test_pool().await else { return };
+        let at = |text: &str| NaiveDateTime::parse_from_str(text, "%Y-%m-%d %H:%M:%S").unwrap();
+        save_gas_reading(&pool, 30.0, 1.0, at("2025-09-01 00:00:00")).await.unwrap();
+        save_gas_reading(&pool, 12.0, 0.5, at("2025-09-02 06:00:00")).await.unwrap();
+        save_gas_reading(&pool, 18.0, 1.5, at("2025-09-02 12:00:00")).await.unwrap();
+
+        let readings = get_gas_readings_since(&pool, at("2025-09-01 12:00:00")).await.unwrap();
+        let base_fees: Vec<f64> = readings.iter().map(|r| r.base_fee_gwei).collect();
+        assert_eq!(base_fees, vec![12.0, 18.0]);
+
+        assert_eq!(delete_gas_readings_before(&pool, at("2025-09-02 00:00:00")).await.unwrap(), 1);
+        assert_eq!(get_gas_readings_since(&pool, at("2025-01-01 00:00:00")).await.unwrap().len(), 2);
+    }
+}
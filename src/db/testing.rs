@@ -0,0 +1,129 @@
+use sqlx::{Executor, Pool, Postgres, postgres::{PgConnectOptions, PgPoolOptions}};
+use std::env;
+use std::str::FromStr;
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+
+// Migration that creates the users table; sample data after it needs user 1
+const USERS_MIGRATION_VERSION: i64 = 20250913131800;
+
+/// Connect to the database named by TEST_DATABASE_URL with a fresh, fully migrated schema
+/// Returns None when TEST_DATABASE_URL is not set so database tests are skipped
+pub async fn test_pool() -> Option<Pool<Postgres>> {
+    let pool = empty_test_pool().await?;
+    run_migrations(&pool, |_| true).await;
+    Some(pool)
+}
+
+/// Like `test_pool`, but only applies migrations up to and including `version`
+/// Use `run_migrations_after` to apply the rest once test data is in place
+pub async fn test_pool_migrated_to(version: i64) -> Option<Pool<Postgres>> {
+    let pool = empty_test_pool().await?;
+    run_migrations(&pool, |v| v <= version).await;
+    Some(pool)
+}
+
+/// Apply the migrations newer than `version`
+pub async fn run_migrations_after(pool: &Pool<Postgres>, version: i64) {
+    run_migrations(pool, |v| v > version).await;
+}
+
+async fn empty_test_pool() -> Option<Pool<Postgres>> {
+    let database_url = env::var("TEST_DATABASE_URL").ok()?;
+    let schema = format!("test_{}", uuid::Uuid::new_v4().simple());
+
+    let admin = PgPoolOptions::new()
+        .max_connections(1)
+        .connect(&database_url)
+        .await
+        .expect("Failed to connect to TEST_DATABASE_URL");
+    admin
+        .execute(format!("CREATE SCHEMA {}", schema).as_str())
+        .await
+        .expect("Failed to create test schema");
+    admin.close().await;
+
+    let search_path = format!("SET search_path TO {}", schema);
+    let pool = PgPoolOptions::new()
+        .max_connections(2)
+        .after_connect(move |conn, _meta| {
+            let search_path = search_path.clone();
+            Box::pin(async move {
+                conn.execute(search_path.as_str()).await?;
+                Ok(())
+            })
+        })
+        .connect(&database_url)
+        .await
+        .expect("Failed to connect to TEST_DATABASE_URL");
+
+    Some(pool)
+}
+
+async fn run_migrations(pool: &Pool<Postgres>, include: impl Fn(i64) -> bool) {
+    for migration in sqlx::migrate!().iter().filter(|m| include(m.version)) {
+        pool.execute(&*migration.sql)
+            .await
+            .unwrap_or_else(|e| panic!("Migration {} failed: {}", migration.version, e));
+
+        if migration.version == USERS_MIGRATION_VERSION {
+            pool.execute("INSERT INTO users (id, username) VALUES (1, 'default_user'); SELECT setval('users_id_seq', 1)")
+                .await
+                .expect("Failed to create default user");
+        }
+    }
+}
+
+/// A second pool on `pool`'s schema whose every reply from the server arrives `delay` late
+///
+/// Connections go through a local proxy holding back what the server sends, as a database over a
+/// slow network would, so tests can tell sequential queries from concurrent ones.
+pub async fn slow_pool(pool: &Pool<Postgres>, delay: Duration, max_connections: u32) -> Pool<Postgres> {
+    let database_url = env::var("TEST_DATABASE_URL").expect("slow_pool needs TEST_DATABASE_URL");
+    let options = PgConnectOptions::from_str(&database_url).expect("Invalid TEST_DATABASE_URL");
+    let server = format!("{}:{}", options.get_host(), options.get_port());
+    let schema: String = sqlx::query_scalar("SELECT current_schema()").fetch_one(pool).await.expect("Failed to read the test schema");
+
+    let listener = TcpListener::bind("127.0.0.1:0").await.expect("Failed to start the latency proxy");
+    let proxy_port = listener.local_addr().unwrap().port();
+    tokio::spawn(async move {
+        while let Ok((client, _)) = listener.accept().await {
+            let Ok(upstream) = TcpStream::connect(&server).await else { continue };
+            tokio::spawn(relay(client, upstream, delay));
+        }
+    });
+
+    let search_path = format!("SET search_path TO {}", schema);
+    PgPoolOptions::new()
+        .max_connections(max_connections)
+        .after_connect(move |conn, _meta| {
+            let search_path = search_path.clone();
+            Box::pin(async move {
+                conn.execute(search_path.as_str()).await?;
+                Ok(())
+            })
+        })
+        .connect_with(options.host("127.0.0.1").port(proxy_port))
+        .await
+        .expect("Failed to connect through the latency proxy")
+}
+
+// Client bytes go straight through, each chunk from the server waits `delay` first
+async fn relay(client: TcpStream, upstream: TcpStream, delay: Duration) {
+    let (mut client_read, mut client_write) = client.into_split();
+    let (mut upstream_read, mut upstream_write) = upstream.into_split();
+    let requests = tokio::io::copy(&mut client_read, &mut upstream_write);
+    let replies = async {
+        let mut buffer = vec![0u8; 64 * 1024];
+        loop {
+            let read = upstream_read.read(&mut buffer).await?;
+            if read == 0 {
+                return Ok::<_, std::io::Error>(());
+            }
+            tokio::time::sleep(delay).await;
+            client_write.write_all(&buffer[..read]).await?;
+        }
+    };
+    let _ = tokio::try_join!(requests, replies);
+}
@@ -0,0 +1,257 @@
+use crate::fiat::{self, Currency, FiatAmount};
+use crate::price_format::format_price;
+use regex::Regex;
+use std::sync::OnceLock;
+
+/// Most buys a schedule is split into
+pub const MAX_BUYS: u32 = 520;
+
+/// How often a recurring buy happens
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Period {
+    Day,
+    Week,
+    Month,
+}
+
+impl Period {
+    fn parse(word: &str) -> Option<Self> {
+        let word = word.to_lowercase();
+        if word.starts_with("da") {
+            Some(Period::Day)
+        } else if word.starts_with("week") {
+            Some(Period::Week)
+        } else if word.starts_with("month") {
+            Some(Period::Month)
+        } else {
+            None
+        }
+    }
+
+    fn noun(&self, count: u32) -> String {
+        let noun = match self {
+            Period::Day => "day",
+            Period::Week => "week",
+            Period::Month => "month",
+        };
+        if count == 1 { format!("1 {}", noun) } else { format!("{} {}s", count, noun) }
+    }
+
+    fn adjective(&self) -> &'static str {
+        match self {
+            Period::Day => "daily",
+            Period::Week => "weekly",
+            Period::Month => "monthly",
+        }
+    }
+}
+
+/// When the amount is spent
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Schedule {
+    /// All of it today
+    Once,
+    /// The amount is the total, split evenly over `buys` periods: "over 4 weeks"
+    Split { buys: u32, period: Period },
+    /// The amount is spent every period, `buys` times when given: "weekly for 10 weeks"
+    Recurring { period: Period, buys: Option<u32> },
+}
+
+/// A dollar-cost averaging question, e.g. "DCA 2000 CAD into BTC over 4 weeks"
+#[derive(Debug, Clone, PartialEq)]
+pub struct DcaQuery {
+    pub coin: String,
+    /// The amount as written, in any currency
+    pub amount: FiatAmount,
+    pub schedule: Schedule,
+}
+
+fn dca_regex() -> &'static Regex {
+    static REGEX: OnceLock<Regex> = OnceLock::new();
+    REGEX.get_or_init(|| {
+        Regex::new(&format!(
+            r"(?i)\b(?P<verb>dca(?:ing)?|invest(?:ing)?|put(?:ting)?|spend(?:ing)?|buy(?:ing)?)\s+(?P<money>{})\s+(?:(?:worth\s+)?of|into|in|on)\s+(?P<coin>[a-z][a-z0-9-]*)\b",
+            fiat::money_pattern()
+        ))
+        .unwrap()
+    })
+}
+
+// "over 4 weeks", "across 6 months"
+fn split_regex() -> &'static Regex {
+    static REGEX: OnceLock<Regex> = OnceLock::new();
+    REGEX.get_or_init(|| Regex::new(r"(?i)\b(?:over|across)\s+(?:the\s+next\s+)?(\d{1,3})\s+(days?|weeks?|months?)\b").unwrap())
+}
+
+// "weekly", "every month", "a week", "per day"
+fn recurring_regex() -> &'static Regex {
+    static REGEX: OnceLock<Regex> = OnceLock::new();
+    REGEX.get_or_init(|| {
+        Regex::new(r"(?i)\b(?:(daily|weekly|monthly)|(?:every|each|a|per)\s+(day|week|month))\b(?:\s+for\s+(\d{1,3})\s+(?:days?|weeks?|months?)\b)?").unwrap()
+    })
+}
+
+/// Parse "DCA €500 into ETH weekly for 10 weeks" or "invest 2000 CAD into BTC over 4 weeks"
+///
+/// "DCA" needs no schedule and means buying once; other verbs only count with a schedule,
+/// "should I invest $500 in ETH" is a question for the model.
+pub fn parse_dca_query(message: &str) -> Option<DcaQuery> {
+    let captures = dca_regex().captures(message)?;
+    let amount = fiat::parse_money(&captures["money"])?;
+    let coin = captures["coin"].to_string();
+
+    let schedule = if let Some(split) = split_regex().captures(message) {
+        Schedule::Split { buys: split[1].parse().ok()?, period: Period::parse(&split[2])? }
+    } else if let Some(recurring) = recurring_regex().captures(message) {
+        let period = Period::parse(recurring.get(1).or(recurring.get(2))?.as_str())?;
+        Schedule::Recurring { period, buys: recurring.get(3).and_then(|buys| buys.as_str().parse().ok()) }
+    } else if captures["verb"].to_lowercase().starts_with("dca") {
+        Schedule::Once
+    } else {
+        return None;
+    };
+
+    let valid = match schedule {
+        Schedule::Split { buys, .. } | Schedule::Recurring { buys: Some(buys), .. } => (1..=MAX_BUYS).contains(&buys),
+        _ => true,
+    };
+    (valid && amount.amount > 0.0).then_some(DcaQuery { coin, amount, schedule })
+}
+
+fn coins(usd: f64, price: f64) -> f64 {
+    usd / price
+}
+
+/// The buys of the plan at today's price, showing amounts as written and in USD
+///
+/// `amount_usd` is the message's amount converted to USD, `price` the coin's USD price.
+pub fn render_plan(query: &DcaQuery, currency: &'static Currency, amount_usd: f64, price: f64) -> String {
+    let coin = query.coin.to_uppercase();
+    let usd = fiat::usd();
+    let money = |amount: f64, amount_usd: f64| {
+        fiat::format_converted(
+            fiat::round_to_unit(amount, currency),
+            currency,
+            fiat::round_to_unit(amount_usd, usd),
+            usd,
+        )
+    };
+    let amount = query.amount.amount;
+
+    let plan = match query.schedule {
+        Schedule::Once => format!(
+            "{} buys about {:.6} {} at today's price of {}.",
+            money(amount, amount_usd),
+            coins(amount_usd, price),
+            coin,
+            format_price(price)
+        ),
+        Schedule::Split { buys, period } => {
+            let each = f64::from(buys);
+            format!(
+                "{} over {} is {} {} buys of {}, about {:.6} {} each and {:.6} {} in total at today's price of {}.",
+                money(amount, amount_usd),
+                period.noun(buys),
+                buys,
+                period.adjective(),
+                money(amount / each, amount_usd / each),
+                coins(amount_usd / each, price),
+                coin,
+                coins(amount_usd, price),
+                coin,
+                format_price(price)
+            )
+        },
+        Schedule::Recurring { period, buys: Some(buys) } => {
+            let total = f64::from(buys);
+            format!(
+                "{} {} for {} is {} in total, about {:.6} {} per buy and {:.6} {} in total at today's price of {}.",
+                money(amount, amount_usd),
+                period.adjective(),
+                period.noun(buys),
+                money(amount * total, amount_usd * total),
+                coins(amount_usd, price),
+                coin,
+                coins(amount_usd * total, price),
+                coin,
+                format_price(price)
+            )
+        },
+        Schedule::Recurring { period, buys: None } => format!(
+            "{} {} buys about {:.6} {} each time at today's price of {}.",
+            money(amount, amount_usd),
+            period.adjective(),
+            coins(amount_usd, price),
+            coin,
+            format_price(price)
+        ),
+    };
+
+    if query.schedule == Schedule::Once {
+        plan
+    } else {
+        format!("{}\n\nLater buys get the price of their day, so the quantities will differ; averaging over them is the point of DCA.", plan)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fiat::{Denomination, find_currency};
+
+    fn amount(amount: f64, code: &str) -> FiatAmount {
+        FiatAmount { amount, denomination: Denomination::Known(find_currency(code).unwrap()) }
+    }
+
+    #[test]
+    fn test_parse_dca_query() {
+        let query = parse_dca_query("I want to invest 2000 CAD into BTC over 4 weeks").unwrap();
+        assert_eq!(query, DcaQuery {
+            coin: "BTC".to_string(),
+            amount: amount(2000.0, "cad"),
+            schedule: Schedule::Split { buys: 4, period: Period::Week },
+        });
+
+        let query = parse_dca_query("dca €500 into eth weekly for 10 weeks").unwrap();
+        assert_eq!(query.amount, amount(500.0, "eur"));
+        assert_eq!(query.schedule, Schedule::Recurring { period: Period::Week, buys: Some(10) });
+
+        let query = parse_dca_query("buying £50 of sol every month").unwrap();
+        assert_eq!(query.amount, amount(50.0, "gbp"));
+        assert_eq!(query.schedule, Schedule::Recurring { period: Period::Month, buys: None });
+
+        let query = parse_dca_query("DCA $1k into btc").unwrap();
+        assert_eq!(query.amount, amount(1000.0, "usd"));
+        assert_eq!(query.schedule, Schedule::Once);
+
+        let query = parse_dca_query("dca 500 kr into btc over 5 days").unwrap();
+        assert_eq!(query.amount.denomination, Denomination::Unknown("kr".to_string()));
+
+        // A schedule is needed unless the message says DCA
+        assert_eq!(parse_dca_query("should I invest $500 in ETH?"), None);
+        assert_eq!(parse_dca_query("invest $500 in eth over 0 weeks"), None);
+        assert_eq!(parse_dca_query("what is dca"), None);
+    }
+
+    #[test]
+    fn test_render_plan_shows_both_currencies() {
+        let cad = find_currency("cad").unwrap();
+        let query = parse_dca_query("invest 2000 CAD into btc over 4 weeks").unwrap();
+        let output = render_plan(&query, cad, 1459.85, 60_000.0);
+        assert!(output.starts_with(
+            "CA$2000.00 (≈ $1459.85) over 4 weeks is 4 weekly buys of CA$500.00 (≈ $364.96), \
+            about 0.006083 BTC each and 0.024331 BTC in total at today's price of $60000.00."
+        ));
+        assert!(output.contains("Later buys get the price of their day"));
+
+        let query = parse_dca_query("dca €100 into eth weekly for 3 weeks").unwrap();
+        let output = render_plan(&query, find_currency("eur").unwrap(), 108.7, 2000.0);
+        assert!(output.starts_with("€100.00 (≈ $108.70) weekly for 3 weeks is €300.00 (≈ $326.10) in total"));
+
+        let query = parse_dca_query("dca $1000 into sol").unwrap();
+        assert_eq!(
+            render_plan(&query, fiat::usd(), 1000.0, 125.0),
+            "$1000.00 buys about 8.000000 SOL at today's price of $125.00."
+        );
+    }
+}
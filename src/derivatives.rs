@@ -0,0 +1,475 @@
+use crate::offline;
+use crate::price_format::{format_compact, format_price};
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use once_cell::sync::Lazy;
+use regex::Regex;
+use reqwest::{Client, StatusCode};
+use serde::Deserialize;
+use serde::de::DeserializeOwned;
+use std::collections::HashMap;
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, Instant};
+use thiserror::Error;
+
+/// Binance USD-M futures API, public and keyless
+pub const BINANCE_FUTURES_BASE_URL: &str = "https://fapi.binance.com";
+
+/// How long a snapshot is reused, funding and open interest move slowly
+pub const SNAPSHOT_CACHE_TTL: Duration = Duration::from_secs(5 * 60);
+
+/// Coins of a leverage question whose snapshots go into the prompt
+pub const MAX_CONTEXT_COINS: usize = 3;
+
+/// Binance settles funding every 8 hours
+const FUNDING_INTERVALS_PER_DAY: f64 = 3.0;
+
+/// Hourly open interest points covering the past day, both ends included
+const OPEN_INTEREST_POINTS: &str = "25";
+
+/// Error code Binance returns for a symbol it doesn't list
+const INVALID_SYMBOL_CODE: i64 = -1121;
+
+/// Coins with a USDT perpetual, by CoinGecko id
+/// Memecoins with tiny unit prices trade in lots of 1000
+const EXCHANGE_SYMBOLS: &[(&str, &str)] = &[
+    ("bitcoin", "BTCUSDT"),
+    ("ethereum", "ETHUSDT"),
+    ("solana", "SOLUSDT"),
+    ("binancecoin", "BNBUSDT"),
+    ("ripple", "XRPUSDT"),
+    ("cardano", "ADAUSDT"),
+    ("dogecoin", "DOGEUSDT"),
+    ("avalanche-2", "AVAXUSDT"),
+    ("chainlink", "LINKUSDT"),
+    ("polkadot", "DOTUSDT"),
+    ("litecoin", "LTCUSDT"),
+    ("uniswap", "UNIUSDT"),
+    ("aave", "AAVEUSDT"),
+    ("arbitrum", "ARBUSDT"),
+    ("optimism", "OPUSDT"),
+    ("aptos", "APTUSDT"),
+    ("sui", "SUIUSDT"),
+    ("near", "NEARUSDT"),
+    ("aerodrome-finance", "AEROUSDT"),
+    ("pepe", "1000PEPEUSDT"),
+    ("shiba-inu", "1000SHIBUSDT"),
+];
+
+#[derive(Debug, Error)]
+pub enum DerivativesError {
+    #[error("No perpetual market known for {0}")]
+    UnsupportedCoin(String),
+
+    #[error("HTTP error: {0}")]
+    Http(#[from] reqwest::Error),
+
+    #[error("Invalid response: {0}")]
+    InvalidResponse(String),
+
+    #[error("Offline mode: live derivatives data is unavailable")]
+    Offline,
+}
+
+/// The exchange symbol of a coin's USDT perpetual, e.g. "ETHUSDT" for ethereum
+pub fn exchange_symbol(coin_id: &str) -> Option<&'static str> {
+    EXCHANGE_SYMBOLS
+        .iter()
+        .find(|(id, _)| *id == coin_id)
+        .map(|(_, symbol)| *symbol)
+}
+
+/// Funding and open interest of a coin's perpetual
+#[derive(Debug, Clone, PartialEq)]
+pub struct DerivativesSnapshot {
+    pub coin_id: String,
+    pub symbol: String,
+    /// Where the figures come from, e.g. "Binance"
+    pub venue: &'static str,
+    pub mark_price_usd: f64,
+    /// Rate of the current funding interval, in percent
+    pub funding_rate_pct: f64,
+    pub next_funding_at: Option<DateTime<Utc>>,
+    /// Open interest in coins and in USD
+    pub open_interest: f64,
+    pub open_interest_usd: f64,
+    /// None when the exchange has no open interest a day back
+    pub oi_change_24h_pct: Option<f64>,
+}
+
+impl DerivativesSnapshot {
+    /// Funding rate over a year at the current rate
+    pub fn annualized_funding_pct(&self) -> f64 {
+        self.funding_rate_pct * FUNDING_INTERVALS_PER_DAY * 365.0
+    }
+
+    /// Which side pays the other at the current funding rate
+    pub fn funding_side(&self) -> &'static str {
+        if self.funding_rate_pct > 0.0 {
+            "longs pay shorts"
+        } else if self.funding_rate_pct < 0.0 {
+            "shorts pay longs"
+        } else {
+            "neither side pays"
+        }
+    }
+
+    /// One line for the advisor prompt
+    pub fn context_line(&self) -> String {
+        let mut line = format!(
+            "DERIVATIVES: {} perpetual on {}: funding {:+.4}% per 8h ({:+.2}% annualized, {}), open interest ${}",
+            self.symbol,
+            self.venue,
+            self.funding_rate_pct,
+            self.annualized_funding_pct(),
+            self.funding_side(),
+            format_compact(self.open_interest_usd)
+        );
+        if let Some(change) = self.oi_change_24h_pct {
+            line.push_str(&format!(" ({:+.2}% over 24h)", change));
+        }
+        line.push('.');
+        line
+    }
+}
+
+/// Source of perpetual funding and open interest
+#[async_trait]
+pub trait DerivativesProvider: Send + Sync {
+    async fn snapshot(&self, coin_id: &str) -> Result<DerivativesSnapshot, DerivativesError>;
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct PremiumIndex {
+    mark_price: String,
+    last_funding_rate: String,
+    next_funding_time: i64,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct OpenInterestPoint {
+    sum_open_interest: String,
+    sum_open_interest_value: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct BinanceErrorBody {
+    code: i64,
+}
+
+/// Binance returns decimals as strings
+fn parse_decimal(field: &str, value: &str) -> Result<f64, DerivativesError> {
+    value
+        .parse()
+        .map_err(|_| DerivativesError::InvalidResponse(format!("{} is not a number: {}", field, value)))
+}
+
+/// Client for the Binance USD-M futures market data endpoints
+#[derive(Debug, Clone)]
+pub struct BinanceFuturesClient {
+    client: Client,
+    base_url: String,
+    timeout: Duration,
+}
+
+impl BinanceFuturesClient {
+    /// Create a client for the given base URL, e.g. `https://fapi.binance.com`
+    pub fn new(base_url: impl Into<String>) -> Self {
+        Self {
+            client: Client::new(),
+            base_url: base_url.into().trim_end_matches('/').to_string(),
+            timeout: Duration::from_secs(10),
+        }
+    }
+
+    /// Create a client using the base URL from the application config
+    pub fn from_config() -> Self {
+        match crate::config::Config::get_instance() {
+            Ok(config) => Self::new(config.derivatives_base_url.clone()),
+            Err(_) => Self::new(BINANCE_FUTURES_BASE_URL),
+        }
+    }
+
+    /// Set the per-request timeout
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    async fn fetch<T: DeserializeOwned>(&self, path: &str, query: &[(&str, &str)], coin_id: &str) -> Result<T, DerivativesError> {
+        let response = self.client
+            .get(format!("{}{}", self.base_url, path))
+            .query(query)
+            .timeout(self.timeout)
+            .send()
+            .await?;
+
+        let status = response.status();
+        let body = response.text().await?;
+        if status == StatusCode::BAD_REQUEST
+            && serde_json::from_str::<BinanceErrorBody>(&body).is_ok_and(|error| error.code == INVALID_SYMBOL_CODE)
+        {
+            return Err(DerivativesError::UnsupportedCoin(coin_id.to_string()));
+        }
+        if !status.is_success() {
+            return Err(DerivativesError::InvalidResponse(format!("Binance status code: {}", status)));
+        }
+        serde_json::from_str(&body).map_err(|e| DerivativesError::InvalidResponse(format!("Malformed JSON: {}", e)))
+    }
+}
+
+#[async_trait]
+impl DerivativesProvider for BinanceFuturesClient {
+    async fn snapshot(&self, coin_id: &str) -> Result<DerivativesSnapshot, DerivativesError> {
+        if offline::is_offline() {
+            return Err(DerivativesError::Offline);
+        }
+        let symbol = exchange_symbol(coin_id).ok_or_else(|| DerivativesError::UnsupportedCoin(coin_id.to_string()))?;
+
+        let index: PremiumIndex = self.fetch("/fapi/v1/premiumIndex", &[("symbol", symbol)], coin_id).await?;
+        let history: Vec<OpenInterestPoint> = self
+            .fetch(
+                "/futures/data/openInterestHist",
+                &[("symbol", symbol), ("period", "1h"), ("limit", OPEN_INTEREST_POINTS)],
+                coin_id,
+            )
+            .await?;
+
+        // Points are oldest first
+        let latest = history
+            .last()
+            .ok_or_else(|| DerivativesError::InvalidResponse(format!("no open interest for {}", symbol)))?;
+        let open_interest = parse_decimal("sumOpenInterest", &latest.sum_open_interest)?;
+        let open_interest_usd = parse_decimal("sumOpenInterestValue", &latest.sum_open_interest_value)?;
+        let day_ago = match history.first() {
+            Some(first) if history.len() > 1 => Some(parse_decimal("sumOpenInterest", &first.sum_open_interest)?),
+            _ => None,
+        };
+
+        Ok(DerivativesSnapshot {
+            coin_id: coin_id.to_string(),
+            symbol: symbol.to_string(),
+            venue: "Binance",
+            mark_price_usd: parse_decimal("markPrice", &index.mark_price)?,
+            funding_rate_pct: parse_decimal("lastFundingRate", &index.last_funding_rate)? * 100.0,
+            next_funding_at: DateTime::from_timestamp_millis(index.next_funding_time).filter(|_| index.next_funding_time > 0),
+            open_interest,
+            open_interest_usd,
+            oi_change_24h_pct: day_ago
+                .filter(|before| *before > 0.0)
+                .map(|before| (open_interest - before) / before * 100.0),
+        })
+    }
+}
+
+/// Reuses each coin's snapshot for `SNAPSHOT_CACHE_TTL`, so a conversation about one trade doesn't refetch it
+pub struct CachedDerivatives {
+    provider: Box<dyn DerivativesProvider>,
+    ttl: Duration,
+    snapshots: Mutex<HashMap<String, (Instant, DerivativesSnapshot)>>,
+}
+
+impl CachedDerivatives {
+    pub fn new(provider: Box<dyn DerivativesProvider>) -> Self {
+        Self { provider, ttl: SNAPSHOT_CACHE_TTL, snapshots: Mutex::new(HashMap::new()) }
+    }
+
+    /// Set how long snapshots are reused
+    pub fn with_ttl(mut self, ttl: Duration) -> Self {
+        self.ttl = ttl;
+        self
+    }
+
+    pub async fn snapshot(&self, coin_id: &str) -> Result<DerivativesSnapshot, DerivativesError> {
+        if let Some((fetched, snapshot)) = self.snapshots.lock().unwrap().get(coin_id)
+            && fetched.elapsed() < self.ttl
+        {
+            return Ok(snapshot.clone());
+        }
+
+        let snapshot = self.provider.snapshot(coin_id).await?;
+
+        let mut snapshots = self.snapshots.lock().unwrap();
+        snapshots.retain(|_, (fetched, _)| fetched.elapsed() < self.ttl);
+        snapshots.insert(coin_id.to_string(), (Instant::now(), snapshot.clone()));
+        Ok(snapshot)
+    }
+}
+
+static DEFAULT_DERIVATIVES: Lazy<CachedDerivatives> =
+    Lazy::new(|| CachedDerivatives::new(Box::new(BinanceFuturesClient::from_config())));
+
+/// Funding rate and open interest of a coin's perpetual, by CoinGecko id, cached for `SNAPSHOT_CACHE_TTL`
+pub async fn fetch_derivatives_snapshot(coin_id: &str) -> Result<DerivativesSnapshot, DerivativesError> {
+    DEFAULT_DERIVATIVES.snapshot(coin_id).await
+}
+
+/// The coin named in "what's the funding rate on btc" or "eth open interest"
+pub fn detect_funding_query(message: &str) -> Option<String> {
+    static AFTER: OnceLock<Regex> = OnceLock::new();
+    static BEFORE: OnceLock<Regex> = OnceLock::new();
+    const NOT_COINS: &[&str] = &["the", "current", "what's", "whats", "is", "its", "and", "perp", "perps"];
+
+    let after = AFTER.get_or_init(|| {
+        Regex::new(r"(?i)\b(?:funding(?:\s+rates?)?|open\s+interest)\s+(?:on|for|of|in)\s+(?:the\s+)?([a-z][a-z0-9-]*)").unwrap()
+    });
+    let before = BEFORE.get_or_init(|| Regex::new(r"(?i)\b([a-z][a-z0-9'-]*)\s+(?:perps?\s+)?(?:funding|open\s+interest)\b").unwrap());
+    after
+        .captures_iter(message)
+        .chain(before.captures_iter(message))
+        .map(|captures| captures[1].to_lowercase())
+        .find(|coin| !NOT_COINS.contains(&coin.as_str()))
+}
+
+/// Whether a message asks about a leveraged or directional trade, "should I long eth here"
+/// "Long term" and "short term" are about holding periods, not positions
+pub fn is_leverage_question(message: &str) -> bool {
+    static HOLDING_PERIOD: OnceLock<Regex> = OnceLock::new();
+    static POSITION: OnceLock<Regex> = OnceLock::new();
+    let holding_period = HOLDING_PERIOD
+        .get_or_init(|| Regex::new(r"(?i)\b(?:long|short)(?:er)?[\s-]+(?:term|run|haul)\b|\bhow\s+long\b|\bin\s+short\b").unwrap());
+    let position = POSITION.get_or_init(|| {
+        Regex::new(r"(?i)\b(?:long|longing|short|shorting|leverage[ds]?|perps?|perpetuals?|margin|futures|liquidat\w*|\d+x)\b").unwrap()
+    });
+    position.is_match(&holding_period.replace_all(message, " "))
+}
+
+/// Direct answer to a funding rate question
+pub fn render_snapshot(display_name: &str, snapshot: &DerivativesSnapshot) -> String {
+    let mut output = format!(
+        "{} perpetual on {} ({}), mark price {}:\n",
+        display_name,
+        snapshot.venue,
+        snapshot.symbol,
+        format_price(snapshot.mark_price_usd)
+    );
+    output.push_str(&format!(
+        "- Funding rate: {:+.4}% per 8h ({:+.2}% annualized), {}.",
+        snapshot.funding_rate_pct,
+        snapshot.annualized_funding_pct(),
+        snapshot.funding_side()
+    ));
+    if let Some(next) = snapshot.next_funding_at {
+        output.push_str(&format!(" Next funding at {} UTC.", next.format("%H:%M")));
+    }
+    output.push_str(&format!(
+        "\n- Open interest: {} coins (${})",
+        format_compact(snapshot.open_interest),
+        format_compact(snapshot.open_interest_usd)
+    ));
+    match snapshot.oi_change_24h_pct {
+        Some(change) => output.push_str(&format!(", {:+.2}% over 24h.", change)),
+        None => output.push('.'),
+    }
+
+    // Crowded positioning is the part worth spelling out
+    let annualized = snapshot.annualized_funding_pct();
+    if annualized >= 30.0 {
+        output.push_str("\n\nFunding is running hot: longs are crowded and pay a steep carry to hold, which tends to precede long squeezes.");
+    } else if annualized <= -10.0 {
+        output.push_str("\n\nFunding is negative: shorts are crowded and paying longs, which can fuel a short squeeze.");
+    }
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    fn snapshot(funding_rate_pct: f64) -> DerivativesSnapshot {
+        DerivativesSnapshot {
+            coin_id: "ethereum".to_string(),
+            symbol: "ETHUSDT".to_string(),
+            venue: "Binance",
+            mark_price_usd: 2500.0,
+            funding_rate_pct,
+            next_funding_at: DateTime::from_timestamp(1_760_630_400, 0),
+            open_interest: 2_000_000.0,
+            open_interest_usd: 5_000_000_000.0,
+            oi_change_24h_pct: Some(3.2),
+        }
+    }
+
+    struct CountingProvider {
+        calls: Arc<AtomicUsize>,
+    }
+
+    #[async_trait]
+    impl DerivativesProvider for CountingProvider {
+        async fn snapshot(&self, coin_id: &str) -> Result<DerivativesSnapshot, DerivativesError> {
+            self.calls.fetch_add(1, Ordering::SeqCst);
+            Ok(DerivativesSnapshot { coin_id: coin_id.to_string(), ..snapshot(0.01) })
+        }
+    }
+
+    #[test]
+    fn test_exchange_symbol() {
+        assert_eq!(exchange_symbol("bitcoin"), Some("BTCUSDT"));
+        assert_eq!(exchange_symbol("pepe"), Some("1000PEPEUSDT"));
+        assert_eq!(exchange_symbol("btc"), None);
+        assert_eq!(exchange_symbol("not-a-coin"), None);
+    }
+
+    #[test]
+    fn test_funding_figures() {
+        let snapshot = snapshot(0.01);
+        assert!((snapshot.annualized_funding_pct() - 10.95).abs() < 1e-9);
+        assert_eq!(snapshot.funding_side(), "longs pay shorts");
+        assert_eq!(
+            snapshot.context_line(),
+            "DERIVATIVES: ETHUSDT perpetual on Binance: funding +0.0100% per 8h (+10.95% annualized, longs pay shorts), \
+             open interest $5.00B (+3.20% over 24h)."
+        );
+        assert_eq!(self::snapshot(-0.02).funding_side(), "shorts pay longs");
+    }
+
+    #[test]
+    fn test_detect_funding_query() {
+        assert_eq!(detect_funding_query("what's the funding rate on btc"), Some("btc".to_string()));
+        assert_eq!(detect_funding_query("Open interest for ETH?"), Some("eth".to_string()));
+        assert_eq!(detect_funding_query("how is sol funding looking"), Some("sol".to_string()));
+        assert_eq!(detect_funding_query("what's the funding rate"), None);
+        assert_eq!(detect_funding_query("how do I fund my wallet"), None);
+    }
+
+    #[test]
+    fn test_is_leverage_question() {
+        assert!(is_leverage_question("should I long eth here"));
+        assert!(is_leverage_question("is it time to short BTC?"));
+        assert!(is_leverage_question("what about 5x leverage on sol"));
+        assert!(!is_leverage_question("is eth a good long-term hold"));
+        assert!(!is_leverage_question("how long should I hold btc in the short term"));
+        assert!(!is_leverage_question("what's the price of eth"));
+    }
+
+    #[test]
+    fn test_render_snapshot() {
+        let output = render_snapshot("Ethereum", &snapshot(0.01));
+        assert_eq!(
+            output,
+            "Ethereum perpetual on Binance (ETHUSDT), mark price $2500.00:\n\
+             - Funding rate: +0.0100% per 8h (+10.95% annualized), longs pay shorts. Next funding at 16:00 UTC.\n\
+             - Open interest: 2.00M coins ($5.00B), +3.20% over 24h."
+        );
+        assert!(render_snapshot("Ethereum", &snapshot(0.05)).contains("longs are crowded"));
+        assert!(render_snapshot("Ethereum", &snapshot(-0.02)).contains("shorts are crowded"));
+    }
+
+    #[tokio::test]
+    async fn test_snapshots_are_cached() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let cached = CachedDerivatives::new(Box::new(CountingProvider { calls: calls.clone() }));
+        cached.snapshot("ethereum").await.unwrap();
+        cached.snapshot("ethereum").await.unwrap();
+        cached.snapshot("bitcoin").await.unwrap();
+
+        let cached = cached.with_ttl(Duration::ZERO);
+        cached.snapshot("ethereum").await.unwrap();
+        // Two coins fetched once each, then one refetch once the TTL is zero
+        assert_eq!(calls.load(Ordering::SeqCst), 3);
+    }
+}
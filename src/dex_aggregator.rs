@@ -0,0 +1,340 @@
+use crate::trading::{
+    MAX_SLIPPAGE_PCT, MIN_SLIPPAGE_PCT, OneInchClient, ProtocolRoute, QuoteResponse, SwapResponse, Token, TradingError, TransactionData,
+    check_slippage,
+};
+use async_trait::async_trait;
+use reqwest::{Client, RequestBuilder};
+use serde::Deserialize;
+use serde::de::DeserializeOwned;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::warn;
+
+type Result<T> = std::result::Result<T, TradingError>;
+
+/// What an `async_trait` method returns
+type BoxFuture<'a, T> = Pin<Box<dyn Future<Output = T> + Send + 'a>>;
+
+/// Name 1inch's routes are logged and stored in the trade history under
+pub const ONE_INCH: &str = "1inch";
+
+/// Name 0x's routes are logged and stored in the trade history under
+pub const ZERO_EX: &str = "0x";
+
+const ZERO_EX_BASE_URL: &str = "https://api.0x.org";
+
+/// 0x's AllowanceHolder, the contract its swaps are sent to and ERC20 tokens are approved for
+pub const ZERO_EX_ALLOWANCE_HOLDER: &str = "0x0000000000001ff3684f28c67538d4d072c22734";
+
+/// A DEX aggregator that quotes swaps and prepares their transactions
+///
+/// Amounts are in the source token's smallest unit, `from` is the wallet that swaps. Quotes and
+/// swaps carry the name of the aggregator that produced them.
+#[async_trait]
+pub trait DexAggregator: Send + Sync {
+    /// Name the aggregator's routes are logged and stored under
+    fn name(&self) -> &str;
+
+    /// The aggregator called `name` among this one and those it falls back to
+    ///
+    /// A live swap is prepared by the aggregator that quoted it, whose router the token was approved for.
+    fn by_name(&self, name: &str) -> Option<&dyn DexAggregator>;
+
+    /// Quote swapping `amount` of `src` for `dst`, without preparing a transaction
+    async fn get_quote(&self, src: &Token, dst: &Token, amount: &str, from: &str) -> Result<QuoteResponse>;
+
+    /// Address of the contract a swap of an ERC20 token needs an allowance for
+    async fn get_spender(&self) -> Result<String>;
+
+    /// The transaction swapping `amount` of `src` for `dst`, accepting `slippage` percent
+    async fn get_swap_calldata(&self, src: &Token, dst: &Token, amount: &str, from: &str, slippage: f32) -> Result<SwapResponse>;
+}
+
+#[async_trait]
+impl<T: DexAggregator + ?Sized> DexAggregator for Arc<T> {
+    fn name(&self) -> &str {
+        (**self).name()
+    }
+
+    fn by_name(&self, name: &str) -> Option<&dyn DexAggregator> {
+        (**self).by_name(name)
+    }
+
+    async fn get_quote(&self, src: &Token, dst: &Token, amount: &str, from: &str) -> Result<QuoteResponse> {
+        (**self).get_quote(src, dst, amount, from).await
+    }
+
+    async fn get_spender(&self) -> Result<String> {
+        (**self).get_spender().await
+    }
+
+    async fn get_swap_calldata(&self, src: &Token, dst: &Token, amount: &str, from: &str, slippage: f32) -> Result<SwapResponse> {
+        (**self).get_swap_calldata(src, dst, amount, from, slippage).await
+    }
+}
+
+#[async_trait]
+impl DexAggregator for OneInchClient {
+    fn name(&self) -> &str {
+        ONE_INCH
+    }
+
+    fn by_name(&self, name: &str) -> Option<&dyn DexAggregator> {
+        (name == ONE_INCH).then_some(self as &dyn DexAggregator)
+    }
+
+    async fn get_quote(&self, src: &Token, dst: &Token, amount: &str, from: &str) -> Result<QuoteResponse> {
+        OneInchClient::get_quote(self, &src.address, &dst.address, amount, from).await
+    }
+
+    async fn get_spender(&self) -> Result<String> {
+        OneInchClient::get_spender(self).await
+    }
+
+    async fn get_swap_calldata(&self, src: &Token, dst: &Token, amount: &str, from: &str, slippage: f32) -> Result<SwapResponse> {
+        self.get_swap(&src.address, &dst.address, amount, from, slippage, false).await
+    }
+}
+
+// Price and quote responses from the 0x swap API, the price having no transaction
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ZeroExResponse {
+    liquidity_available: bool,
+    buy_amount: Option<String>,
+    sell_amount: Option<String>,
+    gas: Option<String>,
+    #[serde(default)]
+    route: ZeroExRoute,
+    transaction: Option<ZeroExTransaction>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct ZeroExRoute {
+    fills: Vec<ZeroExFill>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ZeroExFill {
+    from: String,
+    to: String,
+    source: String,
+    proportion_bps: String,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ZeroExTransaction {
+    to: String,
+    data: String,
+    gas: Option<String>,
+    gas_price: String,
+    value: String,
+}
+
+impl ZeroExResponse {
+    /// Sold and bought amounts, `InvalidZeroExResponse` when 0x found no route
+    fn amounts(&self) -> Result<(String, String)> {
+        match (self.liquidity_available, &self.sell_amount, &self.buy_amount) {
+            (true, Some(sell), Some(buy)) => Ok((sell.clone(), buy.clone())),
+            _ => Err(TradingError::InvalidZeroExResponse("no liquidity for this swap".to_string())),
+        }
+    }
+
+    /// The fills as one 1inch-style route, each fill's share in percent
+    fn protocols(&self) -> Vec<Vec<Vec<ProtocolRoute>>> {
+        let fills = self
+            .route
+            .fills
+            .iter()
+            .map(|fill| ProtocolRoute {
+                name: fill.source.clone(),
+                part: fill.proportion_bps.parse::<u32>().unwrap_or(0) / 100,
+                from_token_address: fill.from.clone(),
+                to_token_address: fill.to.clone(),
+            })
+            .collect();
+        vec![vec![fills]]
+    }
+}
+
+fn parse_gas(gas: Option<&str>) -> Result<u64> {
+    gas.and_then(|gas| gas.parse().ok())
+        .ok_or_else(|| TradingError::InvalidZeroExResponse(format!("gas estimate {:?}", gas)))
+}
+
+// 0x swap API client, using the AllowanceHolder flow
+pub struct ZeroExClient {
+    client: Client,
+    base_url: String,
+    api_key: String,
+    chain_id: u32,
+}
+
+impl ZeroExClient {
+    pub fn new(chain_id: u32, api_key: String) -> Self {
+        Self { client: Client::new(), base_url: ZERO_EX_BASE_URL.to_string(), api_key, chain_id }
+    }
+
+    /// Send requests to a different root URL, e.g. a proxy or a mock server
+    pub fn with_base_url(mut self, base_url: &str) -> Self {
+        self.base_url = base_url.trim_end_matches('/').to_string();
+        self
+    }
+
+    /// Fail requests that take longer than `timeout`
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.client = Client::builder().timeout(timeout).build().unwrap_or_default();
+        self
+    }
+
+    /// Send a request and decode the body, mapping error statuses
+    async fn send<T: DeserializeOwned>(&self, request: RequestBuilder) -> Result<T> {
+        let response = request.header("0x-api-key", &self.api_key).header("0x-version", "v2").send().await?;
+        let status = response.status();
+        let body = response.text().await?;
+        if !status.is_success() {
+            let message = serde_json::from_str::<serde_json::Value>(&body)
+                .ok()
+                .and_then(|json| json["message"].as_str().or(json["name"].as_str()).map(str::to_string))
+                .unwrap_or(body);
+            return Err(TradingError::ZeroExApi { status, message });
+        }
+
+        serde_json::from_str(&body).map_err(|e| TradingError::InvalidZeroExResponse(e.to_string()))
+    }
+
+    /// GET `endpoint` of the AllowanceHolder API for selling `amount` of `src` for `dst`
+    async fn fetch(&self, endpoint: &str, src: &Token, dst: &Token, amount: &str, from: &str, extra: &[(&str, &str)]) -> Result<ZeroExResponse> {
+        let url = format!("{}/swap/allowance-holder/{}", self.base_url, endpoint);
+        let chain_id = self.chain_id.to_string();
+        let request = self
+            .client
+            .get(&url)
+            .query(&[
+                ("chainId", chain_id.as_str()),
+                ("sellToken", src.address.as_str()),
+                ("buyToken", dst.address.as_str()),
+                ("sellAmount", amount),
+                ("taker", from),
+            ])
+            .query(extra);
+        self.send(request).await
+    }
+}
+
+#[async_trait]
+impl DexAggregator for ZeroExClient {
+    fn name(&self) -> &str {
+        ZERO_EX
+    }
+
+    fn by_name(&self, name: &str) -> Option<&dyn DexAggregator> {
+        (name == ZERO_EX).then_some(self as &dyn DexAggregator)
+    }
+
+    /// An indicative price, which 0x answers without committing liquidity
+    async fn get_quote(&self, src: &Token, dst: &Token, amount: &str, from: &str) -> Result<QuoteResponse> {
+        let price = self.fetch("price", src, dst, amount, from, &[]).await?;
+        let (from_amount, to_amount) = price.amounts()?;
+        Ok(QuoteResponse {
+            from_token: src.clone(),
+            to_token: dst.clone(),
+            from_amount,
+            to_amount,
+            protocols: price.protocols(),
+            estimated_gas: parse_gas(price.gas.as_deref())?,
+            aggregator: ZERO_EX.to_string(),
+        })
+    }
+
+    async fn get_spender(&self) -> Result<String> {
+        Ok(ZERO_EX_ALLOWANCE_HOLDER.to_string())
+    }
+
+    async fn get_swap_calldata(&self, src: &Token, dst: &Token, amount: &str, from: &str, slippage: f32) -> Result<SwapResponse> {
+        check_slippage(slippage, MIN_SLIPPAGE_PCT, MAX_SLIPPAGE_PCT)?;
+        let slippage_bps = ((slippage * 100.0).round() as u32).to_string();
+        let quote = self.fetch("quote", src, dst, amount, from, &[("slippageBps", slippage_bps.as_str())]).await?;
+        let (from_amount, to_amount) = quote.amounts()?;
+        let tx = quote
+            .transaction
+            .ok_or_else(|| TradingError::InvalidZeroExResponse("quote without a transaction".to_string()))?;
+        // The token was approved for the AllowanceHolder, a swap sent anywhere else would revert or worse
+        if !tx.to.eq_ignore_ascii_case(ZERO_EX_ALLOWANCE_HOLDER) {
+            return Err(TradingError::InvalidZeroExResponse(format!("swap sent to {} instead of the AllowanceHolder", tx.to)));
+        }
+        Ok(SwapResponse {
+            from_token: src.clone(),
+            to_token: dst.clone(),
+            from_amount,
+            to_amount,
+            tx: TransactionData {
+                from: from.to_string(),
+                gas: parse_gas(tx.gas.as_deref())?,
+                to: tx.to,
+                data: tx.data,
+                value: tx.value,
+                gas_price: tx.gas_price,
+            },
+            aggregator: ZERO_EX.to_string(),
+        })
+    }
+}
+
+/// Aggregators tried in order, the next one asked when one fails upstream
+///
+/// Only failures of the aggregator itself (HTTP errors, 5xx answers, rate limits and unreadable
+/// responses) move on to the next one; refusals like a too high slippage are returned at once.
+/// When every aggregator fails the first one's error is returned.
+pub struct FallbackAggregator {
+    aggregators: Vec<Box<dyn DexAggregator>>,
+}
+
+impl FallbackAggregator {
+    pub fn new(aggregators: Vec<Box<dyn DexAggregator>>) -> Self {
+        Self { aggregators }
+    }
+
+    /// The first answer of `call` asked of each aggregator in turn
+    async fn first<'a, T>(&'a self, action: &str, call: impl Fn(&'a dyn DexAggregator) -> BoxFuture<'a, Result<T>>) -> Result<T> {
+        let mut first_error = None;
+        for aggregator in &self.aggregators {
+            match call(aggregator.as_ref()).await {
+                Ok(answer) => return Ok(answer),
+                Err(e) if e.is_upstream_failure() => {
+                    warn!("{} failed {}: {}", aggregator.name(), action, e);
+                    first_error.get_or_insert(e);
+                },
+                Err(e) => return Err(e),
+            }
+        }
+        Err(first_error.unwrap_or_else(|| TradingError::Configuration("no DEX aggregator is configured".to_string())))
+    }
+}
+
+#[async_trait]
+impl DexAggregator for FallbackAggregator {
+    fn name(&self) -> &str {
+        "fallback"
+    }
+
+    fn by_name(&self, name: &str) -> Option<&dyn DexAggregator> {
+        self.aggregators.iter().find_map(|aggregator| aggregator.by_name(name))
+    }
+
+    async fn get_quote(&self, src: &Token, dst: &Token, amount: &str, from: &str) -> Result<QuoteResponse> {
+        self.first("quoting", |aggregator| aggregator.get_quote(src, dst, amount, from)).await
+    }
+
+    async fn get_spender(&self) -> Result<String> {
+        self.first("naming its spender", |aggregator| aggregator.get_spender()).await
+    }
+
+    async fn get_swap_calldata(&self, src: &Token, dst: &Token, amount: &str, from: &str, slippage: f32) -> Result<SwapResponse> {
+        self.first("preparing a swap", |aggregator| aggregator.get_swap_calldata(src, dst, amount, from, slippage)).await
+    }
+}
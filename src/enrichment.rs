@@ -0,0 +1,353 @@
+use crate::config::Config;
+use crate::db::{self, DbError};
+use crate::exa_api::{ExaApiClient, ExaApiError};
+use async_trait::async_trait;
+use chrono::{DateTime, Duration, Utc};
+use sqlx::{Pool, Postgres};
+use std::collections::{HashSet, VecDeque};
+use std::sync::{Arc, Mutex, OnceLock};
+use thiserror::Error;
+use tokio::sync::mpsc::{self, error::TrySendError};
+use tokio::task::JoinHandle;
+
+/// Research jobs started per hour when the budget isn't configured
+pub const DEFAULT_JOBS_PER_HOUR: u32 = 20;
+
+/// Jobs waiting for the worker when the capacity isn't configured
+pub const DEFAULT_QUEUE_CAPACITY: usize = 32;
+
+/// Exa results summarized per researched topic
+const RESULTS_PER_TOPIC: usize = 5;
+
+/// Errors raised by a background research job
+#[derive(Debug, Error)]
+pub enum EnrichmentError {
+    #[error("Exa API error: {0}")]
+    Exa(#[from] ExaApiError),
+
+    #[error("Database error: {0}")]
+    Database(#[from] DbError),
+}
+
+/// Limits on background research after chat turns
+#[derive(Debug, Clone, PartialEq)]
+pub struct EnrichmentSettings {
+    /// Jobs accepted in any rolling hour, later ones are dropped
+    pub jobs_per_hour: u32,
+    /// Jobs waiting for the worker, later ones are dropped
+    pub queue_capacity: usize,
+}
+
+impl Default for EnrichmentSettings {
+    fn default() -> Self {
+        Self {
+            jobs_per_hour: DEFAULT_JOBS_PER_HOUR,
+            queue_capacity: DEFAULT_QUEUE_CAPACITY,
+        }
+    }
+}
+
+/// A topic to research for a user, lowercased so "Pendle" and "pendle" are one job
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct ResearchJob {
+    pub user_id: i32,
+    pub topic: String,
+}
+
+impl ResearchJob {
+    pub fn new(user_id: i32, topic: &str) -> Self {
+        Self { user_id, topic: topic.trim().to_lowercase() }
+    }
+}
+
+/// What happened to a job handed to the queue
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Enqueued {
+    Queued,
+    /// The same job is already waiting or running
+    AlreadyPending,
+    /// The hourly budget is spent
+    OverBudget,
+    QueueFull,
+    /// The queue is shutting down
+    Stopped,
+}
+
+/// Researches a topic and stores what it finds
+#[async_trait]
+pub trait Researcher: Send + Sync {
+    async fn research(&self, job: &ResearchJob) -> Result<(), EnrichmentError>;
+}
+
+/// Searches Exa for the topic and saves the summary as knowledge tagged with it
+pub struct ExaResearcher {
+    pool: Pool<Postgres>,
+}
+
+impl ExaResearcher {
+    pub fn new(pool: Pool<Postgres>) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait]
+impl Researcher for ExaResearcher {
+    async fn research(&self, job: &ResearchJob) -> Result<(), EnrichmentError> {
+        let client = ExaApiClient::new()?;
+        let response = client.search_crypto_project(&job.topic, RESULTS_PER_TOPIC).await?;
+        let summary = client.summarize_project(&response.results);
+        if summary == "No information found." {
+            return Ok(());
+        }
+
+        let entry = db::KnowledgeInput {
+            source_id: format!("{}_research_{}", job.topic.replace(' ', "_"), Utc::now().timestamp()),
+            content: summary,
+            tags: vec![job.topic.clone(), "research".to_string(), "exa_api".to_string()],
+        };
+        db::create_knowledge_batch(&self.pool, job.user_id, &[entry], db::ConflictMode::Skip).await?;
+        Ok(())
+    }
+}
+
+#[derive(Default)]
+struct QueueState {
+    /// Jobs queued or running, for deduplication
+    pending: HashSet<ResearchJob>,
+    /// When each job of the last hour was accepted, oldest first
+    accepted: VecDeque<DateTime<Utc>>,
+}
+
+/// Bounded queue of research jobs run one at a time by a background worker
+///
+/// Jobs are deduplicated while pending and limited to a rolling hourly budget. Shutting down
+/// stops accepting jobs and waits for the queued ones to finish.
+pub struct EnrichmentQueue {
+    settings: EnrichmentSettings,
+    state: Arc<Mutex<QueueState>>,
+    sender: Mutex<Option<mpsc::Sender<ResearchJob>>>,
+    worker: tokio::sync::Mutex<Option<JoinHandle<()>>>,
+}
+
+impl EnrichmentQueue {
+    /// Start the worker on the current runtime
+    pub fn start(settings: EnrichmentSettings, researcher: Arc<dyn Researcher>) -> Self {
+        let (sender, mut receiver) = mpsc::channel::<ResearchJob>(settings.queue_capacity.max(1));
+        let state = Arc::new(Mutex::new(QueueState::default()));
+
+        let worker_state = state.clone();
+        let worker = tokio::spawn(async move {
+            while let Some(job) = receiver.recv().await {
+                if let Err(e) = researcher.research(&job).await {
+                    eprintln!("Error researching {}: {}", job.topic, e);
+                }
+                worker_state.lock().unwrap().pending.remove(&job);
+            }
+        });
+
+        Self {
+            settings,
+            state,
+            sender: Mutex::new(Some(sender)),
+            worker: tokio::sync::Mutex::new(Some(worker)),
+        }
+    }
+
+    pub fn settings(&self) -> &EnrichmentSettings {
+        &self.settings
+    }
+
+    /// Queue a job unless it's already pending, the budget is spent or the queue is full
+    pub fn enqueue(&self, job: ResearchJob, now: DateTime<Utc>) -> Enqueued {
+        let sender = self.sender.lock().unwrap();
+        let Some(sender) = sender.as_ref() else {
+            return Enqueued::Stopped;
+        };
+
+        let mut state = self.state.lock().unwrap();
+        if state.pending.contains(&job) {
+            return Enqueued::AlreadyPending;
+        }
+        while state.accepted.front().is_some_and(|&accepted| accepted <= now - Duration::hours(1)) {
+            state.accepted.pop_front();
+        }
+        if state.accepted.len() >= self.settings.jobs_per_hour as usize {
+            return Enqueued::OverBudget;
+        }
+
+        match sender.try_send(job.clone()) {
+            Ok(()) => {
+                state.pending.insert(job);
+                state.accepted.push_back(now);
+                Enqueued::Queued
+            },
+            Err(TrySendError::Full(_)) => Enqueued::QueueFull,
+            Err(TrySendError::Closed(_)) => Enqueued::Stopped,
+        }
+    }
+
+    /// Jobs queued or running
+    pub fn pending(&self) -> usize {
+        self.state.lock().unwrap().pending.len()
+    }
+
+    /// Stop accepting jobs and wait for the queued ones to finish
+    pub async fn shutdown(&self) {
+        self.sender.lock().unwrap().take();
+        if let Some(worker) = self.worker.lock().await.take()
+            && let Err(e) = worker.await
+        {
+            eprintln!("Enrichment worker failed: {}", e);
+        }
+    }
+}
+
+static QUEUE: OnceLock<Option<Arc<EnrichmentQueue>>> = OnceLock::new();
+
+/// The queue configured for this process, None when enrichment is off
+///
+/// Started once from the config on the current runtime, researching into the first pool passed in
+pub fn configured(pool: &Pool<Postgres>) -> Option<Arc<EnrichmentQueue>> {
+    QUEUE
+        .get_or_init(|| {
+            let settings = Config::get_instance().ok()?.enrichment.clone()?;
+            let researcher = Arc::new(ExaResearcher::new(pool.clone()));
+            Some(Arc::new(EnrichmentQueue::start(settings, researcher)))
+        })
+        .clone()
+}
+
+/// Jobs queued or running on the configured queue
+pub fn running() -> usize {
+    match QUEUE.get() {
+        Some(Some(queue)) => queue.pending(),
+        _ => 0,
+    }
+}
+
+/// Finish the configured queue's jobs before the process exits
+pub async fn shutdown() {
+    if let Some(Some(queue)) = QUEUE.get() {
+        queue.shutdown().await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+    use tokio::sync::Semaphore;
+
+    fn start() -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2025, 10, 1, 12, 0, 0).unwrap()
+    }
+
+    fn settings(jobs_per_hour: u32, queue_capacity: usize) -> EnrichmentSettings {
+        EnrichmentSettings { jobs_per_hour, queue_capacity }
+    }
+
+    /// Records researched topics, holding each job until released when gated
+    #[derive(Default)]
+    struct MockResearcher {
+        researched: Mutex<Vec<String>>,
+        gate: Option<Arc<Semaphore>>,
+    }
+
+    #[async_trait]
+    impl Researcher for MockResearcher {
+        async fn research(&self, job: &ResearchJob) -> Result<(), EnrichmentError> {
+            if let Some(gate) = &self.gate {
+                gate.acquire().await.unwrap().forget();
+            }
+            self.researched.lock().unwrap().push(job.topic.clone());
+            if job.topic == "broken" {
+                return Err(EnrichmentError::Database(DbError::Query("boom".to_string())));
+            }
+            Ok(())
+        }
+    }
+
+    fn gated() -> (Arc<MockResearcher>, Arc<Semaphore>) {
+        let gate = Arc::new(Semaphore::new(0));
+        (Arc::new(MockResearcher { gate: Some(gate.clone()), ..Default::default() }), gate)
+    }
+
+    #[tokio::test]
+    async fn test_pending_jobs_are_deduplicated() {
+        let (researcher, gate) = gated();
+        let queue = EnrichmentQueue::start(settings(10, 10), researcher.clone());
+
+        assert_eq!(queue.enqueue(ResearchJob::new(1, "Pendle"), start()), Enqueued::Queued);
+        assert_eq!(queue.enqueue(ResearchJob::new(1, "pendle "), start()), Enqueued::AlreadyPending);
+        // Same topic for another user is another job
+        assert_eq!(queue.enqueue(ResearchJob::new(2, "pendle"), start()), Enqueued::Queued);
+        assert_eq!(queue.pending(), 2);
+
+        gate.add_permits(2);
+        queue.shutdown().await;
+        assert_eq!(*researcher.researched.lock().unwrap(), vec!["pendle", "pendle"]);
+        assert_eq!(queue.pending(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_finished_jobs_can_be_queued_again() {
+        let researcher = Arc::new(MockResearcher::default());
+        let queue = EnrichmentQueue::start(settings(10, 10), researcher.clone());
+
+        assert_eq!(queue.enqueue(ResearchJob::new(1, "aave"), start()), Enqueued::Queued);
+        while queue.pending() > 0 {
+            tokio::task::yield_now().await;
+        }
+        assert_eq!(queue.enqueue(ResearchJob::new(1, "aave"), start()), Enqueued::Queued);
+        queue.shutdown().await;
+        assert_eq!(researcher.researched.lock().unwrap().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_hourly_budget_rolls_over() {
+        let queue = EnrichmentQueue::start(settings(2, 10), Arc::new(MockResearcher::default()));
+        let minutes = |m: i64| start() + Duration::minutes(m);
+
+        assert_eq!(queue.enqueue(ResearchJob::new(1, "aave"), minutes(0)), Enqueued::Queued);
+        assert_eq!(queue.enqueue(ResearchJob::new(1, "curve"), minutes(30)), Enqueued::Queued);
+        assert_eq!(queue.enqueue(ResearchJob::new(1, "pendle"), minutes(59)), Enqueued::OverBudget);
+        // The first job leaves the window an hour after it was accepted
+        assert_eq!(queue.enqueue(ResearchJob::new(1, "pendle"), minutes(60)), Enqueued::Queued);
+        assert_eq!(queue.enqueue(ResearchJob::new(1, "lido"), minutes(61)), Enqueued::OverBudget);
+        queue.shutdown().await;
+    }
+
+    #[tokio::test]
+    async fn test_full_queue_drops_jobs_without_spending_budget() {
+        let (researcher, gate) = gated();
+        let queue = EnrichmentQueue::start(settings(3, 1), researcher.clone());
+
+        assert_eq!(queue.enqueue(ResearchJob::new(1, "aave"), start()), Enqueued::Queued);
+        // Full until the worker takes the first job, the attempts spend no budget
+        while queue.enqueue(ResearchJob::new(1, "curve"), start()) != Enqueued::Queued {
+            tokio::task::yield_now().await;
+        }
+        assert_eq!(queue.enqueue(ResearchJob::new(1, "lido"), start()), Enqueued::QueueFull);
+
+        gate.add_permits(2);
+        queue.shutdown().await;
+        assert_eq!(*researcher.researched.lock().unwrap(), vec!["aave", "curve"]);
+    }
+
+    #[tokio::test]
+    async fn test_shutdown_drains_queued_jobs_and_stops_accepting() {
+        let researcher = Arc::new(MockResearcher::default());
+        let queue = EnrichmentQueue::start(settings(10, 10), researcher.clone());
+
+        for topic in ["aave", "broken", "curve"] {
+            assert_eq!(queue.enqueue(ResearchJob::new(1, topic), start()), Enqueued::Queued);
+        }
+        queue.shutdown().await;
+
+        // A failing job doesn't stop the ones after it
+        assert_eq!(*researcher.researched.lock().unwrap(), vec!["aave", "broken", "curve"]);
+        assert_eq!(queue.enqueue(ResearchJob::new(1, "lido"), start()), Enqueued::Stopped);
+        // Shutting down twice is harmless
+        queue.shutdown().await;
+    }
+}
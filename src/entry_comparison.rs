@@ -0,0 +1,456 @@
+use crate::fiat::{self, Currency, FiatAmount};
+use crate::price_fetcher::{DailyBar, DailyPrice};
+use crate::price_format::format_price;
+use crate::render::Table;
+use crate::technical_levels::{self, Level};
+use chrono::{Duration, NaiveDate};
+use regex::Regex;
+use std::sync::OnceLock;
+
+/// Days replayed when the message doesn't give a window
+pub const DEFAULT_WINDOW_DAYS: u32 = 90;
+
+/// Longest window replayed, the levels need `technical_levels::LOOKBACK_DAYS` more history before it
+pub const MAX_WINDOW_DAYS: u32 = 180;
+
+/// Days between the buys of the DCA strategy
+pub const DCA_INTERVAL_DAYS: i64 = 7;
+
+/// Ladder rungs below the start price used where the history before the window gives too few supports
+const LADDER_FALLBACK_PCT: [f64; 3] = [5.0, 10.0, 15.0];
+
+/// Words the coin capture picks up that are never coins
+const NOT_COINS: &[&str] = &["the", "a", "my", "it", "this"];
+
+/// A question comparing ways into a coin, e.g. "DCA or lump sum $5000 into ETH over the last 6 months?"
+#[derive(Debug, Clone, PartialEq)]
+pub struct ComparisonQuery {
+    pub coin: String,
+    /// The amount as written, in any currency
+    pub amount: FiatAmount,
+    pub window_days: u32,
+    /// Whether the window asked for was longer than `MAX_WINDOW_DAYS`
+    pub capped: bool,
+}
+
+fn comparison_regex() -> &'static Regex {
+    static REGEX: OnceLock<Regex> = OnceLock::new();
+    REGEX.get_or_init(|| {
+        Regex::new(r"(?i)\b(?:lump[\s-]?sum|buy(?:ing)?\s+(?:it\s+)?(?:all\s+)?(?:now|at\s+once)|all\s+at\s+once|entry\s+strateg(?:y|ies)|limit\s+ladder)\b").unwrap()
+    })
+}
+
+fn dca_mention_regex() -> &'static Regex {
+    static REGEX: OnceLock<Regex> = OnceLock::new();
+    REGEX.get_or_init(|| Regex::new(r"(?i)\b(?:dca|dollar[\s-]cost|entry\s+strateg(?:y|ies))\b").unwrap())
+}
+
+fn amount_regex() -> &'static Regex {
+    static REGEX: OnceLock<Regex> = OnceLock::new();
+    REGEX.get_or_init(|| {
+        Regex::new(&format!(
+            r"(?i)(?P<money>{})\s+(?:(?:worth\s+)?of|into|in|on)\s+(?P<coin>[a-z][a-z0-9-]*)\b",
+            fiat::money_pattern()
+        ))
+        .unwrap()
+    })
+}
+
+// "over the last 6 months", "past 90 days", "last year"
+fn window_regex() -> &'static Regex {
+    static REGEX: OnceLock<Regex> = OnceLock::new();
+    REGEX.get_or_init(|| Regex::new(r"(?i)\b(?:last|past)\s+(?:(\d{1,4})\s+)?(days?|weeks?|months?|years?)\b").unwrap())
+}
+
+/// Parse a question asking to compare lump sum and DCA entries with an amount and a coin
+///
+/// The message has to name both sides ("DCA or lump sum", "DCA vs buying now") or ask about
+/// entry strategies; the window defaults to `DEFAULT_WINDOW_DAYS` and is capped at `MAX_WINDOW_DAYS`.
+pub fn parse_comparison_query(message: &str) -> Option<ComparisonQuery> {
+    if !comparison_regex().is_match(message) || !dca_mention_regex().is_match(message) {
+        return None;
+    }
+    let captures = amount_regex().captures_iter(message).find(|captures| {
+        !NOT_COINS.contains(&captures["coin"].to_lowercase().as_str())
+    })?;
+    let amount = fiat::parse_money(&captures["money"]).filter(|amount| amount.amount > 0.0)?;
+
+    let days = match window_regex().captures(message) {
+        Some(window) => {
+            let count: u32 = match window.get(1) {
+                Some(count) => count.as_str().parse().ok()?,
+                None => 1,
+            };
+            let unit = window[2].to_lowercase();
+            let per_unit = if unit.starts_with("day") {
+                1
+            } else if unit.starts_with("week") {
+                7
+            } else if unit.starts_with("month") {
+                30
+            } else {
+                365
+            };
+            count.checked_mul(per_unit).filter(|days| *days > 0)?
+        },
+        None => DEFAULT_WINDOW_DAYS,
+    };
+
+    Some(ComparisonQuery {
+        coin: captures["coin"].to_string(),
+        amount,
+        window_days: days.min(MAX_WINDOW_DAYS),
+        capped: days > MAX_WINDOW_DAYS,
+    })
+}
+
+/// What one strategy bought over the window
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Fills {
+    pub units: f64,
+    /// USD spent, less than the amount when orders didn't fill
+    pub spent: f64,
+    pub buys: u32,
+}
+
+impl Fills {
+    fn buy(&mut self, usd: f64, price: f64) {
+        self.units += usd / price;
+        self.spent += usd;
+        self.buys += 1;
+    }
+
+    /// USD paid per unit, None without a fill
+    pub fn average_cost(&self) -> Option<f64> {
+        (self.units > 0.0).then(|| self.spent / self.units)
+    }
+
+    /// Units at `price` plus the cash left unspent
+    pub fn value(&self, amount: f64, price: f64) -> f64 {
+        self.units * price + (amount - self.spent)
+    }
+}
+
+/// Everything bought at the first close of the window
+pub fn lump_sum(prices: &[DailyPrice], amount: f64) -> Fills {
+    let mut fills = Fills::default();
+    if let Some(first) = prices.first() {
+        fills.buy(amount, first.price_usd);
+    }
+    fills
+}
+
+/// Equal buys every `DCA_INTERVAL_DAYS` from the first day of the window, at each buy day's close
+///
+/// A buy day without a price is bought on the next day that has one, unless the next buy day
+/// comes first; its share then stays unspent.
+pub fn weekly_dca(prices: &[DailyPrice], amount: f64) -> Fills {
+    let mut fills = Fills::default();
+    let (Some(first), Some(last)) = (prices.first(), prices.last()) else {
+        return fills;
+    };
+    let interval = Duration::days(DCA_INTERVAL_DAYS);
+    let buy_days: Vec<NaiveDate> = std::iter::successors(Some(first.date), |day| Some(*day + interval))
+        .take_while(|day| *day <= last.date)
+        .collect();
+    let each = amount / buy_days.len() as f64;
+
+    for day in buy_days {
+        let next = day + interval;
+        if let Some(point) = prices.iter().find(|point| point.date >= day && point.date < next) {
+            fills.buy(each, point.price_usd);
+        }
+    }
+    fills
+}
+
+/// An equal limit order at each level, filled at its price the first day the close reaches it
+///
+/// Orders that never fill keep their cash.
+pub fn limit_ladder(prices: &[DailyPrice], amount: f64, levels: &[f64]) -> Fills {
+    let mut fills = Fills::default();
+    if levels.is_empty() {
+        return fills;
+    }
+    let each = amount / levels.len() as f64;
+    for &level in levels {
+        if prices.iter().any(|point| point.price_usd <= level) {
+            fills.buy(each, level);
+        }
+    }
+    fills
+}
+
+/// The three strategies replayed over the same window
+#[derive(Debug, Clone, PartialEq)]
+pub struct Comparison {
+    pub start: NaiveDate,
+    pub end: NaiveDate,
+    pub start_price: f64,
+    pub final_price: f64,
+    /// USD invested by each strategy
+    pub amount: f64,
+    pub lump_sum: Fills,
+    pub dca: Fills,
+    pub ladder: Fills,
+    /// Supports the ladder's orders sat at, nearest the start price first
+    pub ladder_levels: Vec<Level>,
+}
+
+/// Replay the last `window_days` days of `history` for `amount` USD
+///
+/// The ladder's levels are the supports of the history before the window, as they stood at
+/// its start, topped up with `LADDER_FALLBACK_PCT` rungs, so nothing from inside the window
+/// decides where the orders go. None when the window has fewer than two prices.
+pub fn compare(history: &[DailyBar], window_days: u32, amount: f64) -> Option<Comparison> {
+    let end = history.last()?.date;
+    let start = end - Duration::days(i64::from(window_days));
+    let split = history.partition_point(|bar| bar.date < start);
+    let (before, window) = history.split_at(split);
+    if window.len() < 2 {
+        return None;
+    }
+    let prices: Vec<DailyPrice> = window.iter().map(|bar| DailyPrice { date: bar.date, price_usd: bar.price_usd }).collect();
+    let start_price = prices[0].price_usd;
+
+    let derived = technical_levels::key_levels(before, start_price);
+    let fallback = LADDER_FALLBACK_PCT.map(|pct| start_price * (1.0 - pct / 100.0));
+    let ladder_levels = technical_levels::fill_levels(start_price, &derived.supports, fallback);
+    let rungs: Vec<f64> = ladder_levels.iter().map(|level| level.price).collect();
+
+    Some(Comparison {
+        start: prices[0].date,
+        end,
+        start_price,
+        final_price: prices[prices.len() - 1].price_usd,
+        amount,
+        lump_sum: lump_sum(&prices, amount),
+        dca: weekly_dca(&prices, amount),
+        ladder: limit_ladder(&prices, amount, &rungs),
+        ladder_levels,
+    })
+}
+
+/// The comparison table with the window it covers, amounts in other currencies shown next to their USD value
+pub fn render_comparison(query: &ComparisonQuery, currency: &'static Currency, comparison: &Comparison) -> String {
+    let coin = query.coin.to_uppercase();
+    let days = (comparison.end - comparison.start).num_days();
+    let mut output = format!(
+        "Entering {} with {} from {} to {} ({} days, {} went from {} to {}):\n\n",
+        coin,
+        fiat::format_converted(query.amount.amount, currency, comparison.amount, fiat::usd()),
+        comparison.start,
+        comparison.end,
+        days,
+        coin,
+        format_price(comparison.start_price),
+        format_price(comparison.final_price),
+    );
+
+    let mut table = Table::new(["Strategy", "Buys", "Units", "Avg cost", "Spent", "Value now"]);
+    let strategies = [
+        ("Lump sum at start", &comparison.lump_sum),
+        ("Weekly DCA", &comparison.dca),
+        ("Limit ladder", &comparison.ladder),
+    ];
+    for (name, fills) in strategies {
+        table.push_row([
+            name.to_string(),
+            fills.buys.to_string(),
+            format!("{:.6}", fills.units),
+            fills.average_cost().map(format_price).unwrap_or_else(|| "-".to_string()),
+            format!("${:.2}", fills.spent),
+            format!("${:.2}", fills.value(comparison.amount, comparison.final_price)),
+        ]);
+    }
+    output.push_str(&table.render());
+
+    let rungs: Vec<String> = comparison
+        .ladder_levels
+        .iter()
+        .map(|level| format!("{} [{}]", format_price(level.price), level.source.label()))
+        .collect();
+    output.push_str(&format!(
+        "\n\nThe ladder put an equal order at {}, the supports of the {} days before the start. Orders that never \
+        filled kept their cash, which counts in the value at face value. DCA bought every {} days.",
+        rungs.join(", "),
+        technical_levels::LOOKBACK_DAYS,
+        DCA_INTERVAL_DAYS,
+    ));
+    if query.capped {
+        output.push_str(&format!(" Windows are capped at {} days.", MAX_WINDOW_DAYS));
+    }
+    output.push_str(
+        "\n\nThis is backward-looking: it replays past daily closes, without fees or slippage, and says nothing about \
+        which entry will do better from here.",
+    );
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fiat::{Denomination, find_currency};
+    use crate::technical_levels::LevelSource;
+
+    fn prices_start() -> NaiveDate {
+        NaiveDate::from_ymd_opt(2026, 1, 1).unwrap()
+    }
+
+    fn path(prices: &[f64]) -> Vec<DailyPrice> {
+        prices
+            .iter()
+            .enumerate()
+            .map(|(i, price_usd)| DailyPrice { date: prices_start() + Duration::days(i as i64), price_usd: *price_usd })
+            .collect()
+    }
+
+    // 29 daily closes from `from` in equal steps of `step`
+    fn line(from: f64, step: f64) -> Vec<f64> {
+        (0..29).map(|i| from + step * f64::from(i)).collect()
+    }
+
+    fn close(a: f64, b: f64) -> bool {
+        (a - b).abs() < 1e-9
+    }
+
+    #[test]
+    fn test_parse_comparison_query() {
+        let query = parse_comparison_query("Should I DCA or lump sum $5000 into ETH? Compare over the last 6 months").unwrap();
+        assert_eq!(query.coin, "ETH");
+        assert_eq!(query.amount.amount, 5000.0);
+        assert_eq!(query.window_days, 180);
+        assert!(!query.capped);
+
+        let query = parse_comparison_query("dca vs buying now with 2000 CAD of btc").unwrap();
+        assert_eq!(query.amount.denomination, Denomination::Known(find_currency("cad").unwrap()));
+        assert_eq!(query.window_days, DEFAULT_WINDOW_DAYS);
+
+        let query = parse_comparison_query("compare entry strategies for $1k in sol over the past year").unwrap();
+        assert_eq!((query.coin.as_str(), query.window_days, query.capped), ("sol", MAX_WINDOW_DAYS, true));
+
+        let query = parse_comparison_query("lump sum or dollar-cost average $300 on pendle, last 3 weeks").unwrap();
+        assert_eq!(query.window_days, 21);
+
+        // Both sides, an amount and a coin are needed
+        assert_eq!(parse_comparison_query("dca $1000 into btc over 4 weeks"), None);
+        assert_eq!(parse_comparison_query("is a lump sum better than DCA?"), None);
+        assert_eq!(parse_comparison_query("lump sum vs dca, $1000 in the last 90 days"), None);
+    }
+
+    #[test]
+    fn test_monotonic_up_favours_lump_sum() {
+        let prices = path(&line(100.0, 5.0));
+        let lump = lump_sum(&prices, 1000.0);
+        let dca = weekly_dca(&prices, 1000.0);
+        let ladder = limit_ladder(&prices, 1000.0, &[95.0, 90.0, 85.0]);
+
+        assert!(close(lump.units, 10.0));
+        assert_eq!(lump.average_cost(), Some(100.0));
+        // Buys on days 0, 7, 14, 21 and 28 at 100, 135, 170, 205 and 240
+        assert_eq!(dca.buys, 5);
+        assert!(close(dca.spent, 1000.0));
+        assert!(close(dca.units, 200.0 * (1.0 / 100.0 + 1.0 / 135.0 + 1.0 / 170.0 + 1.0 / 205.0 + 1.0 / 240.0)));
+        assert!(dca.units < lump.units);
+        // Nothing ever came down to the ladder
+        assert_eq!(ladder, Fills::default());
+        assert_eq!(ladder.average_cost(), None);
+        assert!(close(ladder.value(1000.0, 240.0), 1000.0));
+    }
+
+    #[test]
+    fn test_monotonic_down_favours_dca_and_fills_the_ladder() {
+        let prices = path(&line(100.0, -2.0));
+        let lump = lump_sum(&prices, 1000.0);
+        let dca = weekly_dca(&prices, 1000.0);
+        let ladder = limit_ladder(&prices, 1000.0, &[95.0, 90.0, 85.0]);
+
+        assert!(dca.units > lump.units);
+        assert!(dca.average_cost().unwrap() < 100.0);
+        // Every rung is crossed and fills at its own price
+        assert_eq!(ladder.buys, 3);
+        assert!(close(ladder.spent, 1000.0));
+        assert!(close(ladder.units, 1000.0 / 3.0 * (1.0 / 95.0 + 1.0 / 90.0 + 1.0 / 85.0)));
+        assert!(ladder.units > lump.units);
+    }
+
+    #[test]
+    fn test_v_shape_fills_part_of_the_ladder() {
+        let mut closes = line(100.0, -4.0)[..8].to_vec();
+        closes.extend((1..=21).map(|i| 72.0 + 2.0 * f64::from(i)));
+        let prices = path(&closes);
+        let lump = lump_sum(&prices, 900.0);
+        let dca = weekly_dca(&prices, 900.0);
+        let ladder = limit_ladder(&prices, 900.0, &[90.0, 80.0, 70.0]);
+
+        // The low is 72, so the 70 rung never fills
+        assert_eq!(ladder.buys, 2);
+        assert!(close(ladder.spent, 600.0));
+        assert!(close(ladder.average_cost().unwrap(), 600.0 / (300.0 / 90.0 + 300.0 / 80.0)));
+        assert!(close(ladder.value(900.0, 114.0), ladder.units * 114.0 + 300.0));
+        // Buying through the dip beats buying at the top
+        assert!(dca.average_cost().unwrap() < lump.average_cost().unwrap());
+
+        // Deterministic: the same path gives the same fills
+        assert_eq!(weekly_dca(&prices, 900.0), dca);
+    }
+
+    #[test]
+    fn test_dca_skips_a_week_without_prices() {
+        let mut prices = path(&line(100.0, 0.0));
+        prices.retain(|point| !(7..14).contains(&(point.date - prices_start()).num_days()));
+        let dca = weekly_dca(&prices, 500.0);
+        assert_eq!(dca.buys, 4);
+        assert!(close(dca.spent, 400.0));
+    }
+
+    #[test]
+    fn test_compare_sets_the_ladder_from_history_before_the_window() {
+        // 90 quiet days around 100 with a swing low at 80, then a 28 day window falling to 60
+        let mut history: Vec<DailyBar> = (0..90)
+            .map(|i| {
+                let price_usd = if i == 45 { 80.0 } else { 100.0 + f64::from(i % 3) };
+                DailyBar { date: prices_start() + Duration::days(i64::from(i)), price_usd, volume_usd: 1_000.0 }
+            })
+            .collect();
+        history.extend((0..29).map(|i| DailyBar {
+            date: prices_start() + Duration::days(90 + i64::from(i)),
+            price_usd: 100.0 - 40.0 * f64::from(i) / 28.0,
+            volume_usd: 1_000.0,
+        }));
+
+        let comparison = compare(&history, 28, 1000.0).unwrap();
+        assert_eq!(comparison.start, prices_start() + Duration::days(90));
+        assert_eq!((comparison.start_price, comparison.final_price), (100.0, 60.0));
+        assert_eq!(comparison.ladder_levels.len(), 3);
+        // The swing low, merged with the volume of its day
+        assert!(comparison.ladder_levels.iter().any(|level| (level.price - 80.0).abs() < 80.0 * technical_levels::MERGE_TOLERANCE
+            && level.source != LevelSource::Range));
+        assert!(comparison.ladder_levels.iter().all(|level| level.price < 100.0));
+        assert_eq!(comparison.ladder.buys, 3);
+        assert!(close(comparison.lump_sum.units, 10.0));
+
+        assert_eq!(compare(&history[..1], 28, 1000.0), None);
+    }
+
+    #[test]
+    fn test_render_comparison() {
+        let history: Vec<DailyBar> = line(100.0, 5.0)
+            .into_iter()
+            .enumerate()
+            .map(|(i, price_usd)| DailyBar { date: prices_start() + Duration::days(i as i64), price_usd, volume_usd: 1.0 })
+            .collect();
+        let query = parse_comparison_query("lump sum vs dca €1000 of btc over the last 4 weeks").unwrap();
+        let comparison = compare(&history, query.window_days, 1080.0).unwrap();
+        let output = render_comparison(&query, find_currency("eur").unwrap(), &comparison);
+
+        assert!(output.starts_with(
+            "Entering BTC with €1000.00 (≈ $1080.00) from 2026-01-01 to 2026-01-29 (28 days, BTC went from $100.00 to $240.00):"
+        ));
+        assert!(output.contains("Lump sum at start  "));
+        assert!(output.contains("$2592.00"));
+        assert!(output.contains("range estimate"));
+        assert!(output.ends_with("says nothing about which entry will do better from here."));
+        assert!(!output.contains("capped"));
+    }
+}
@@ -0,0 +1,128 @@
+use crate::agent_customizer::CustomizerError;
+use crate::llm::LlmError;
+use crate::briefing::BriefingError;
+use crate::config::ConfigError;
+use crate::daemon::DaemonError;
+use crate::data_source::DataSourceError;
+use crate::db::DbError;
+use crate::exa_api::ExaApiError;
+use crate::il_calculator::IlError;
+use crate::investment_chat::InvestmentChatError;
+use crate::personality::PersonalityError;
+use crate::position_sizing::SizingError;
+use crate::price_fetcher::PriceError;
+use crate::retention::RetentionError;
+use crate::setup::SetupError;
+use crate::strategy_manager::StrategyError;
+use crate::trading::TradingError;
+use thiserror::Error;
+
+/// Crate-level error wrapping the error of every domain module
+/// Callers that cross module boundaries (CLI, HTTP server) can match on the variant
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error(transparent)]
+    Config(#[from] ConfigError),
+
+    #[error(transparent)]
+    Database(#[from] DbError),
+
+    #[error(transparent)]
+    Llm(#[from] LlmError),
+
+    #[error(transparent)]
+    Exa(#[from] ExaApiError),
+
+    #[error(transparent)]
+    Price(#[from] PriceError),
+
+    #[error(transparent)]
+    Chat(#[from] InvestmentChatError),
+
+    #[error(transparent)]
+    Trading(#[from] TradingError),
+
+    #[error(transparent)]
+    Strategy(#[from] StrategyError),
+
+    #[error(transparent)]
+    Personality(#[from] PersonalityError),
+
+    #[error(transparent)]
+    Customizer(#[from] CustomizerError),
+
+    #[error(transparent)]
+    DataSource(#[from] DataSourceError),
+
+    #[error(transparent)]
+    Daemon(#[from] DaemonError),
+
+    #[error(transparent)]
+    Setup(#[from] SetupError),
+
+    #[error(transparent)]
+    Retention(#[from] RetentionError),
+
+    #[error(transparent)]
+    Briefing(#[from] BriefingError),
+
+    #[error(transparent)]
+    ImpermanentLoss(#[from] IlError),
+
+    #[error(transparent)]
+    Sizing(#[from] SizingError),
+}
+
+/// Result type using the crate-level error
+pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_domain_errors_convert_transparently() {
+        let cases: Vec<(Error, &str)> = vec![
+            (ConfigError::Initialization("missing".to_string()).into(), "Failed to initialize config: missing"),
+            (DbError::Query("boom".to_string()).into(), "Database query error: boom"),
+            (LlmError::Offline.into(), "LLM API is unavailable in offline mode"),
+            (ExaApiError::ApiKeyNotFound.into(), "API key not found"),
+            (PriceError::RateLimitExceeded(None).into(), "CoinGecko API rate limit exceeded"),
+            (InvestmentChatError::InvalidInput("bad".to_string()).into(), "Invalid input: bad"),
+            (TradingError::OrderNotFound("42".to_string()).into(), "Order not found: 42"),
+            (StrategyError::NotFound("dca".to_string()).into(), "Strategy not found: dca"),
+            (PersonalityError::Io(std::io::Error::other("disk")).into(), "IO error: disk"),
+            (CustomizerError::CreateUser(DbError::Constraint("unique".to_string())).into(), "Failed to create user: Database constraint violation: unique"),
+            (DataSourceError::Database("down".to_string()).into(), "Database error: down"),
+            (DaemonError::Engine("stuck".to_string()).into(), "Engine error: stuck"),
+            (SetupError::Aborted.into(), "Setup aborted"),
+            (RetentionError::Summary("timeout".to_string()).into(), "Summary error: timeout"),
+            (IlError::InvalidRatio(-1.0).into(), "Price ratio must be a positive number, got -1"),
+            (SizingError::InvalidRisk(0.0).into(), "Risk per trade must be between 0% and 100%, got 0%"),
+        ];
+
+        for (error, message) in cases {
+            assert_eq!(error.to_string(), message);
+        }
+    }
+
+    #[test]
+    fn test_variants_are_preserved() {
+        assert!(matches!(Error::from(DbError::NotFound("user".to_string())), Error::Database(DbError::NotFound(_))));
+        assert!(matches!(Error::from(PriceError::Offline), Error::Price(PriceError::Offline)));
+        assert!(matches!(
+            Error::from(InvestmentChatError::from(PriceError::Offline)),
+            Error::Chat(InvestmentChatError::PriceApi(PriceError::Offline))
+        ));
+    }
+
+    #[test]
+    fn test_question_mark_converts_into_crate_error() {
+        fn load() -> Result<()> {
+            Err(StrategyError::NotFound("grid".to_string()))?;
+            Ok(())
+        }
+
+        assert!(matches!(load(), Err(Error::Strategy(StrategyError::NotFound(_)))));
+    }
+}
@@ -1,3 +1,6 @@
+use crate::api_budget::BudgetExhausted;
+use crate::circuit_breaker::Refused;
+use std::time::Duration;
 use thiserror::Error;
 
 /// Custom error types for the Exa API client
@@ -9,6 +12,12 @@ pub enum ExaApiError {
     #[error("API request failed: {0}")]
     RequestFailed(String),
     
+    #[error("Exa API rejected the API key")]
+    Unauthorized,
+    
+    #[error("Exa API rate limit exceeded{}", .0.map(|d| format!(", retry after {}s", d.as_secs())).unwrap_or_default())]
+    RateLimited(Option<Duration>),
+    
     #[error("Failed to parse response: {0}")]
     ParseError(#[from] serde_json::Error),
     
@@ -17,6 +26,24 @@ pub enum ExaApiError {
     
     #[error("Invalid input: {0}")]
     InvalidInput(String),
+    
+    #[error("Exa API is unavailable in offline mode")]
+    Offline,
+    
+    #[error("Exa API calls are paused after repeated failures")]
+    CircuitOpen,
+    
+    #[error("Exa API calls are paused: {0}")]
+    BudgetExhausted(BudgetExhausted),
+}
+
+impl From<Refused> for ExaApiError {
+    fn from(refused: Refused) -> Self {
+        match refused {
+            Refused::CircuitOpen => ExaApiError::CircuitOpen,
+            Refused::OverBudget(exhausted) => ExaApiError::BudgetExhausted(exhausted),
+        }
+    }
 }
 
 // No need for a custom From implementation since thiserror derives std::error::Error,
@@ -4,57 +4,102 @@ mod models;
 
 pub use error::ExaApiError;
 pub use query_builder::QueryBuilder;
-pub use models::{ExaSearchResult, ExaSearchResponse};
+pub use models::{
+    ContentError, ContentStatus, ContentsOptions, ExaContent, ExaContentsResponse, ExaSearchResponse, ExaSearchResult,
+    HighlightsOptions, TextOptions,
+};
 
+use crate::circuit_breaker::{self, BreakerSettings, CircuitBreaker};
 use crate::config::Config;
-use reqwest::Client;
+use chrono::NaiveDate;
+use crate::http;
+use crate::offline;
+use reqwest::header::HeaderMap;
+use reqwest::{Client, StatusCode};
 use std::collections::HashSet;
-use std::sync::OnceLock;
+use std::sync::{Arc, OnceLock};
+use std::time::Duration;
 
-const EXA_API_BASE_URL: &str = "https://api.exa.ai/api/search";
+/// Default root URL of the Exa API
+pub const EXA_BASE_URL: &str = "https://api.exa.ai";
+
+/// Research results whose full page text is fetched, the rest keep their snippets
+pub const FULL_TEXT_RESULTS: usize = 3;
+
+/// Longest page text fetched for research, in characters
+pub const RESEARCH_TEXT_CHARS: usize = 5_000;
 
 /// Client for interacting with the Exa API
 pub struct ExaApiClient {
     client: Client,
+    base_url: String,
     api_key: String,
+    /// Skips calls while Exa keeps failing
+    breaker: Arc<CircuitBreaker>,
 }
 
 impl ExaApiClient {
     /// Create a new ExaApiClient using the application config
-    /// If the API key is not found, it will use a mock API key
+    /// If the API key is not found, it will use a mock API key. Clients created this way share one circuit breaker
     pub fn new() -> Result<Self, ExaApiError> {
-        let api_key = match Config::get_instance() {
+        let (api_key, base_url) = match Config::get_instance() {
             Ok(config) => {
-                if config.exa_api_key.is_empty() {
+                let api_key = if config.exa_api_key.is_empty() {
                     "mock_api_key_for_development".to_string()
                 } else {
                     config.exa_api_key.clone()
-                }
+                };
+                (api_key, config.exa_base_url.clone())
             },
-            Err(_) => "mock_api_key_for_development".to_string(),
+            Err(_) => ("mock_api_key_for_development".to_string(), EXA_BASE_URL.to_string()),
         };
         
-        Ok(Self {
-            client: Client::new(),
-            api_key,
-        })
+        Ok(Self::with_api_key(api_key)
+            .with_base_url(&base_url)
+            .with_breaker(circuit_breaker::shared(circuit_breaker::EXA)))
     }
     
     /// Create a new ExaApiClient with a specific API key
     pub fn with_api_key(api_key: String) -> Self {
         Self {
             client: Client::new(),
+            base_url: EXA_BASE_URL.to_string(),
             api_key,
+            breaker: Arc::new(CircuitBreaker::new(circuit_breaker::EXA, BreakerSettings::default())),
+        }
+    }
+    
+    /// Skip calls while this breaker is open, and report their outcomes to it
+    pub fn with_breaker(mut self, breaker: Arc<CircuitBreaker>) -> Self {
+        self.breaker = breaker;
+        self
+    }
+    
+    /// Send requests to a different root URL, e.g. a proxy or a mock server
+    pub fn with_base_url(mut self, base_url: &str) -> Self {
+        if !base_url.is_empty() {
+            self.base_url = base_url.trim_end_matches('/').to_string();
         }
+        self
+    }
+    
+    /// Fail requests that take longer than `timeout`
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.client = Client::builder()
+            .timeout(timeout)
+            .build()
+            .unwrap_or_default();
+        self
     }
     
     /// Search for crypto project information
+    /// The top results carry their full page text, the rest their snippets
     pub async fn search_crypto_project(&self, project_name: &str, num_results: usize) -> Result<ExaSearchResponse, ExaApiError> {
         let query = QueryBuilder::new(project_name)
             .add_aspects(&["details", "tokenomics", "technology"])
             .build();
             
-        match self.search(&query, num_results, None).await {
+        match self.search_with_full_text(&query, num_results, FULL_TEXT_RESULTS).await {
             Ok(response) => Ok(response),
             Err(e) => {
                 // Log the error
@@ -159,10 +204,105 @@ impl ExaApiClient {
         }
     }
     
+    /// Search news about a crypto project published on or after `since`
+    pub async fn search_news_since(&self, project_name: &str, num_results: usize, since: NaiveDate) -> Result<ExaSearchResponse, ExaApiError> {
+        let query = QueryBuilder::new(project_name)
+            .add_aspects(&["latest", "news"])
+            .build();
+        
+        self.search_request(&query, num_results, None, Some(since)).await
+    }
+    
     /// Perform a search using the Exa API
     pub async fn search(&self, query: &str, num_results: usize, next_page_id: Option<&str>) -> Result<ExaSearchResponse, ExaApiError> {
-        let mut url = format!("{}?query={}&num_results={}", 
-            EXA_API_BASE_URL, 
+        self.search_request(query, num_results, next_page_id, None).await
+    }
+    
+    /// Search, then fetch the full page text of every result
+    pub async fn search_and_contents(&self, query: &str, num_results: usize) -> Result<ExaSearchResponse, ExaApiError> {
+        self.search_with_full_text(query, num_results, num_results).await
+    }
+    
+    /// Search, then replace the snippets of the first `full_text` results with their page text
+    /// Results whose contents can't be fetched keep their snippets
+    pub async fn search_with_full_text(&self, query: &str, num_results: usize, full_text: usize) -> Result<ExaSearchResponse, ExaApiError> {
+        let mut response = self.search(query, num_results, None).await?;
+        let ids: Vec<String> = response.results.iter().take(full_text).map(|result| result.id.clone()).collect();
+        if ids.is_empty() {
+            return Ok(response);
+        }
+        
+        let contents = match self.get_contents(&ids, &ContentsOptions::text(RESEARCH_TEXT_CHARS)).await {
+            Ok(contents) => contents,
+            Err(e) => {
+                eprintln!("Exa API error fetching page contents, keeping snippets: {}", e);
+                return Ok(response);
+            }
+        };
+        for failed in contents.failed() {
+            eprintln!("Exa couldn't fetch {}, keeping its snippet", failed.id);
+        }
+        for result in response.results.iter_mut().take(full_text) {
+            if let Some(text) = contents.get(&result.id).and_then(|content| content.text.as_deref())
+                && !text.trim().is_empty()
+            {
+                result.content = text.to_string();
+            }
+        }
+        
+        Ok(response)
+    }
+    
+    /// Fetch the contents of pages by Exa result id or URL
+    pub async fn get_contents(&self, ids: &[String], options: &ContentsOptions) -> Result<ExaContentsResponse, ExaApiError> {
+        if ids.is_empty() {
+            return Err(ExaApiError::InvalidInput("no ids or URLs to fetch contents for".to_string()));
+        }
+        if offline::is_offline() {
+            return Err(ExaApiError::Offline);
+        }
+        let permit = self.breaker.try_call()?;
+        
+        let response = self.client
+            .post(format!("{}/contents", self.base_url))
+            .header("x-api-key", &self.api_key)
+            .header("Accept", "application/json")
+            .json(&models::ContentsRequest { ids, options })
+            .send()
+            .await
+            .inspect(|_| offline::note_network_success())
+            .map_err(|e| {
+                offline::note_network_error(&e);
+                ExaApiError::HttpError(e)
+            });
+        let response = match response {
+            Ok(response) => response,
+            Err(e) => {
+                permit.failed();
+                return Err(e);
+            },
+        };
+        permit.finish(!circuit_breaker::is_outage(response.status()));
+        
+        check_status(response.status(), response.headers())?;
+        let body = response.text().await?;
+        Ok(serde_json::from_str::<ExaContentsResponse>(&body)?)
+    }
+    
+    async fn search_request(
+        &self,
+        query: &str,
+        num_results: usize,
+        next_page_id: Option<&str>,
+        published_since: Option<NaiveDate>,
+    ) -> Result<ExaSearchResponse, ExaApiError> {
+        if offline::is_offline() {
+            return Err(ExaApiError::Offline);
+        }
+        let permit = self.breaker.try_call()?;
+        
+        let mut url = format!("{}/api/search?query={}&num_results={}", 
+            self.base_url, 
             urlencoding::encode(query), 
             num_results
         );
@@ -171,26 +311,34 @@ impl ExaApiClient {
             url.push_str(&format!("&next_page_id={}", page_id));
         }
         
+        if let Some(since) = published_since {
+            url.push_str(&format!("&start_published_date={}", since.format("%Y-%m-%d")));
+        }
+        
         let response = self.client
             .get(&url)
             .header("x-api-key", &self.api_key)
             .header("Accept", "application/json")
             .send()
             .await
-            .map_err(ExaApiError::HttpError)?;
-        
-        if !response.status().is_success() {
-            return Err(ExaApiError::RequestFailed(format!("API request failed with status: {}", response.status())));
-        }
-        
-        // Use a direct match to handle the error conversion properly
-        let search_response = match response.json::<ExaSearchResponse>().await {
+            .inspect(|_| offline::note_network_success())
+            .map_err(|e| {
+                offline::note_network_error(&e);
+                ExaApiError::HttpError(e)
+            });
+        let response = match response {
             Ok(response) => response,
             Err(e) => {
-                // Convert the reqwest::Error to a string first
-                return Err(ExaApiError::RequestFailed(format!("Failed to parse JSON: {}", e)));
-            }
+                permit.failed();
+                return Err(e);
+            },
         };
+        permit.finish(!circuit_breaker::is_outage(response.status()));
+        
+        check_status(response.status(), response.headers())?;
+        
+        let body = response.text().await?;
+        let search_response = serde_json::from_str::<ExaSearchResponse>(&body)?;
             
         Ok(search_response)
     }
@@ -227,7 +375,7 @@ impl ExaApiClient {
                 
                 // Look for sentences with key information using the keyword set
                 if keywords.iter().any(|&keyword| sentence.contains(keyword)) {
-                    insights.push(format!("{}", sentence));
+                    insights.push(sentence.to_string());
                 }
             }
         }
@@ -260,3 +408,13 @@ impl ExaApiClient {
         summary
     }
 }
+
+/// Map an error status of any Exa endpoint to its error
+fn check_status(status: StatusCode, headers: &HeaderMap) -> Result<(), ExaApiError> {
+    match status {
+        StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN => Err(ExaApiError::Unauthorized),
+        StatusCode::TOO_MANY_REQUESTS => Err(ExaApiError::RateLimited(http::retry_after(headers))),
+        status if !status.is_success() => Err(ExaApiError::RequestFailed(format!("API request failed with status: {}", status))),
+        _ => Ok(()),
+    }
+}
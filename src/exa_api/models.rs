@@ -18,3 +18,104 @@ pub struct ExaSearchResponse {
     pub results: Vec<ExaSearchResult>,
     pub next_page_id: Option<String>,
 }
+
+/// What `/contents` returns for each page
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct ContentsOptions {
+    /// Full page text, `None` leaves it out
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub text: Option<TextOptions>,
+    /// Sentences of the page most relevant to a query, `None` leaves them out
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub highlights: Option<HighlightsOptions>,
+}
+
+impl ContentsOptions {
+    /// Full text cut at `max_characters`
+    pub fn text(max_characters: usize) -> Self {
+        Self {
+            text: Some(TextOptions { max_characters: Some(max_characters), include_html_tags: false }),
+            highlights: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TextOptions {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_characters: Option<usize>,
+    pub include_html_tags: bool,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HighlightsOptions {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub num_sentences: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub highlights_per_url: Option<usize>,
+    /// Query the highlights are picked for, the page's own topic when absent
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub query: Option<String>,
+}
+
+/// Body of a `/contents` request
+#[derive(Debug, Serialize)]
+pub(crate) struct ContentsRequest<'a> {
+    pub ids: &'a [String],
+    #[serde(flatten)]
+    pub options: &'a ContentsOptions,
+}
+
+/// The contents of one page
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ExaContent {
+    pub id: String,
+    pub url: String,
+    pub title: Option<String>,
+    pub author: Option<String>,
+    pub published_date: Option<String>,
+    pub text: Option<String>,
+    #[serde(default)]
+    pub highlights: Vec<String>,
+    #[serde(default)]
+    pub highlight_scores: Vec<f64>,
+}
+
+/// Why a page's contents couldn't be fetched
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ContentError {
+    pub tag: Option<String>,
+    pub http_status_code: Option<u16>,
+}
+
+/// Outcome of fetching one page, failed pages are missing from `results`
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct ContentStatus {
+    pub id: String,
+    pub status: String,
+    pub error: Option<ContentError>,
+}
+
+/// Represents the response from the Exa API contents endpoint
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct ExaContentsResponse {
+    pub results: Vec<ExaContent>,
+    #[serde(default)]
+    pub statuses: Vec<ContentStatus>,
+}
+
+impl ExaContentsResponse {
+    /// Contents fetched for an id or URL
+    pub fn get(&self, id: &str) -> Option<&ExaContent> {
+        self.results.iter().find(|content| content.id == id || content.url == id)
+    }
+
+    /// Pages that couldn't be fetched
+    pub fn failed(&self) -> impl Iterator<Item = &ContentStatus> {
+        self.statuses.iter().filter(|status| status.status != "success")
+    }
+}
@@ -0,0 +1,382 @@
+use crate::db::{self, DbError, Feedback, Knowledge, SourceRating};
+use crate::render::Table;
+use chrono::{Datelike, NaiveDate, NaiveDateTime};
+use regex::Regex;
+use sqlx::{Pool, Postgres};
+use std::collections::HashMap;
+use std::sync::OnceLock;
+
+/// Days after which a rating counts half as much toward a demotion
+pub const DEMOTION_HALF_LIFE_DAYS: f64 = 30.0;
+
+/// Share of a good rating's weight that cancels out a bad one
+const GOOD_RATING_WEIGHT: f64 = 0.5;
+
+/// Positions a knowledge entry drops in the retrieval ranking per point of demotion
+pub const POSITIONS_PER_DEMOTION: f64 = 3.0;
+
+/// Weeks listed by `/stats feedback`, most recent last
+const STATS_WEEKS: usize = 8;
+
+/// Reasons of bad ratings listed by `/stats feedback`
+const STATS_REASONS: usize = 5;
+
+/// Reasons are cut to this many columns in the stats
+const REASON_WIDTH: usize = 80;
+
+/// A user's verdict on an answer
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Rating {
+    Good,
+    Bad,
+}
+
+impl Rating {
+    /// Stored score, 1 for good and -1 for bad
+    pub fn score(self) -> i16 {
+        match self {
+            Rating::Good => 1,
+            Rating::Bad => -1,
+        }
+    }
+}
+
+/// A rating typed in the chat, like "that was wrong, the unlock is in March"
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FeedbackMessage {
+    pub rating: Rating,
+    pub reason: Option<String>,
+}
+
+/// The rating a chat message gives the previous answer, None for other messages
+///
+/// Only messages opening with the verdict count, and never questions, so
+/// "that's wrong, what about solana?" is answered rather than recorded.
+pub fn parse_feedback_message(message: &str) -> Option<FeedbackMessage> {
+    static FEEDBACK: OnceLock<Regex> = OnceLock::new();
+    let feedback = FEEDBACK.get_or_init(|| {
+        Regex::new(
+            r"(?i)^\s*(?:(?P<bad>(?:that\s+was|that['’]s|that\s+is|this\s+is)\s+(?:wrong|incorrect|not\s+right|not\s+correct|unhelpful|not\s+helpful|useless)|(?:bad|wrong|unhelpful|useless)\s+(?:answer|response|reply))|(?P<good>(?:thanks[,!.]?\s+)?(?:that\s+was|that['’]s|that\s+is)\s+(?:a\s+)?(?:great|good|helpful|useful|perfect|excellent|spot\s+on|correct)(?:\s+(?:answer|response|reply))?|(?:great|good|helpful|perfect|excellent)\s+(?:answer|response|reply)))\b(?:\s*(?:[,.:;!]+|-+|\s+because|\s+since)\s*(?P<reason>[^?]*?))?\s*[.!]*\s*$",
+        )
+        .unwrap()
+    });
+
+    let captures = feedback.captures(message)?;
+    let rating = if captures.name("bad").is_some() { Rating::Bad } else { Rating::Good };
+    let reason = captures
+        .name("reason")
+        .map(|reason| reason.as_str().trim().to_string())
+        .filter(|reason| !reason.is_empty());
+    Some(FeedbackMessage { rating, reason })
+}
+
+/// Rate the user's latest answer, returning the reply to show
+pub async fn rate_last_answer(pool: &Pool<Postgres>, user_id: i32, rating: Rating, reason: Option<&str>) -> Result<String, DbError> {
+    let Some(message_id) = db::get_last_assistant_message_id(pool, user_id).await? else {
+        return Ok("There's no answer to rate yet.".to_string());
+    };
+    db::save_feedback(pool, user_id, message_id, rating.score(), reason).await?;
+
+    Ok(match rating {
+        Rating::Good => "Thanks, glad that helped.".to_string(),
+        Rating::Bad => "Thanks for flagging it. The notes behind that answer will rank lower from now on.".to_string(),
+    })
+}
+
+/// How much each knowledge entry should be demoted, by source id
+///
+/// Every bad rating of an answer an entry fed adds its weight, which halves every
+/// `DEMOTION_HALF_LIFE_DAYS`; good ratings take back half their weight. Entries whose
+/// answers were rated good at least as often end up at zero and aren't listed.
+pub fn demotion_scores(ratings: &[SourceRating], now: NaiveDateTime) -> HashMap<String, f64> {
+    let mut totals: HashMap<String, f64> = HashMap::new();
+    for rating in ratings {
+        let age_days = (now - rating.rated_at).num_seconds().max(0) as f64 / 86_400.0;
+        let weight = 0.5_f64.powf(age_days / DEMOTION_HALF_LIFE_DAYS);
+        let signed = if rating.score < 0 { weight } else { -weight * GOOD_RATING_WEIGHT };
+        *totals.entry(rating.source_id.clone()).or_default() += signed;
+    }
+    totals.retain(|_, score| *score > 0.0);
+    totals
+}
+
+/// Reorder retrieved knowledge so demoted entries fall behind better-rated ones
+///
+/// Each entry keeps its retrieval position plus `POSITIONS_PER_DEMOTION` per point of
+/// demotion, so a single old bad rating only nudges an entry while repeated recent ones
+/// push it out of the prompt. Entries without a demotion keep their order.
+pub fn rank_by_feedback(entries: Vec<Knowledge>, demotions: &HashMap<String, f64>) -> Vec<Knowledge> {
+    if demotions.is_empty() {
+        return entries;
+    }
+
+    let mut ranked: Vec<(f64, Knowledge)> = entries
+        .into_iter()
+        .enumerate()
+        .map(|(position, entry)| {
+            let demotion = demotions.get(&entry.source_id).copied().unwrap_or_default();
+            (position as f64 + demotion * POSITIONS_PER_DEMOTION, entry)
+        })
+        .collect();
+    ranked.sort_by(|(a, _), (b, _)| a.total_cmp(b));
+    ranked.into_iter().map(|(_, entry)| entry).collect()
+}
+
+/// Ratings given in one week, starting on Monday
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FeedbackWeek {
+    pub week_start: NaiveDate,
+    pub good: usize,
+    pub bad: usize,
+}
+
+/// Ratings grouped by the week they were given, oldest first
+pub fn summarize_by_week(feedback: &[Feedback]) -> Vec<FeedbackWeek> {
+    let mut weeks: Vec<FeedbackWeek> = Vec::new();
+    for rating in feedback {
+        let date = rating.created_at.date();
+        let week_start = date - chrono::Duration::days(date.weekday().num_days_from_monday() as i64);
+        let week = match weeks.iter_mut().find(|week| week.week_start == week_start) {
+            Some(week) => week,
+            None => {
+                weeks.push(FeedbackWeek { week_start, good: 0, bad: 0 });
+                weeks.last_mut().unwrap()
+            },
+        };
+        if rating.score < 0 {
+            week.bad += 1;
+        } else {
+            week.good += 1;
+        }
+    }
+    weeks.sort_by_key(|week| week.week_start);
+    weeks
+}
+
+/// Ratings over time and the latest reasons given for bad ones, as shown by `/stats feedback`
+pub fn render_feedback_stats(feedback: &[Feedback]) -> String {
+    if feedback.is_empty() {
+        return "No answers rated yet. Use /good or /bad [reason] after an answer.".to_string();
+    }
+
+    let good = feedback.iter().filter(|rating| rating.score > 0).count();
+    let bad = feedback.len() - good;
+    let mut output = format!(
+        "{} answers rated: {} good and {} bad ({}% good).\n\n",
+        feedback.len(),
+        good,
+        bad,
+        percent(good, feedback.len())
+    );
+
+    let weeks = summarize_by_week(feedback);
+    let mut table = Table::new(["Week of", "Good", "Bad", "Good %"]);
+    for week in &weeks[weeks.len().saturating_sub(STATS_WEEKS)..] {
+        table.push_row([
+            week.week_start.format("%Y-%m-%d").to_string(),
+            week.good.to_string(),
+            week.bad.to_string(),
+            format!("{}%", percent(week.good, week.good + week.bad)),
+        ]);
+    }
+    output.push_str(&table.render());
+
+    let reasons: Vec<&Feedback> = feedback
+        .iter()
+        .rev()
+        .filter(|rating| rating.score < 0 && rating.reason.is_some())
+        .take(STATS_REASONS)
+        .collect();
+    if !reasons.is_empty() {
+        output.push_str("\n\nLatest reasons for bad ratings:");
+        for rating in reasons {
+            let reason = rating.reason.as_deref().unwrap_or_default();
+            let reason = match reason.char_indices().nth(REASON_WIDTH - 1) {
+                Some((cut, _)) => format!("{}…", &reason[..cut]),
+                None => reason.to_string(),
+            };
+            output.push_str(&format!("\n- {}: {}", rating.created_at.format("%Y-%m-%d"), reason));
+        }
+    }
+    output
+}
+
+fn percent(part: usize, total: usize) -> usize {
+    (part * 100 + total / 2).checked_div(total).unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::testing::test_pool;
+
+    fn at(date: &str) -> NaiveDateTime {
+        NaiveDateTime::parse_from_str(&format!("{} 12:00:00", date), "%Y-%m-%d %H:%M:%S").unwrap()
+    }
+
+    fn source_rating(source_id: &str, score: i16, date: &str) -> SourceRating {
+        SourceRating { source_id: source_id.to_string(), score, rated_at: at(date) }
+    }
+
+    fn knowledge(source_id: &str) -> Knowledge {
+        Knowledge {
+            id: 0,
+            user_id: 1,
+            source_id: source_id.to_string(),
+            content: source_id.to_string(),
+            tags: vec![],
+            created_at: at("2025-09-01"),
+            updated_at: at("2025-09-01"),
+        }
+    }
+
+    fn rating(score: i16, reason: Option<&str>, date: &str) -> Feedback {
+        Feedback {
+            id: 0,
+            user_id: 1,
+            message_id: 0,
+            score,
+            reason: reason.map(str::to_string),
+            created_at: at(date),
+        }
+    }
+
+    fn feedback(rating: Rating, reason: Option<&str>) -> Option<FeedbackMessage> {
+        Some(FeedbackMessage { rating, reason: reason.map(str::to_string) })
+    }
+
+    #[test]
+    fn test_parse_feedback_message() {
+        assert_eq!(parse_feedback_message("that was wrong"), feedback(Rating::Bad, None));
+        assert_eq!(parse_feedback_message("That's not right."), feedback(Rating::Bad, None));
+        assert_eq!(
+            parse_feedback_message("that was wrong, the ARB unlock is in March"),
+            feedback(Rating::Bad, Some("the ARB unlock is in March"))
+        );
+        assert_eq!(
+            parse_feedback_message("bad answer because it ignored fees"),
+            feedback(Rating::Bad, Some("it ignored fees"))
+        );
+        assert_eq!(parse_feedback_message("great answer!"), feedback(Rating::Good, None));
+        assert_eq!(parse_feedback_message("thanks, that was helpful"), feedback(Rating::Good, None));
+        assert_eq!(parse_feedback_message("that's a good answer"), feedback(Rating::Good, None));
+
+        assert_eq!(parse_feedback_message("that's wrong, what about solana?"), None);
+        assert_eq!(parse_feedback_message("what's wrong with ETH today"), None);
+        assert_eq!(parse_feedback_message("is staking a good answer to inflation?"), None);
+        assert_eq!(parse_feedback_message("that was wrongheaded of me"), None);
+    }
+
+    #[test]
+    fn test_demotion_scores() {
+        let now = at("2025-10-01");
+        let ratings = vec![
+            source_rating("unlocks", -1, "2025-10-01"),
+            source_rating("unlocks", -1, "2025-09-01"),
+            // One good rating takes back half of a bad one
+            source_rating("staking", -1, "2025-10-01"),
+            source_rating("staking", 1, "2025-10-01"),
+            source_rating("fees", -1, "2025-10-01"),
+            source_rating("fees", 1, "2025-10-01"),
+            source_rating("fees", 1, "2025-10-01"),
+            source_rating("praised", 1, "2025-10-01"),
+        ];
+
+        let scores = demotion_scores(&ratings, now);
+        // Today's rating counts fully, the month old one half
+        assert!((scores["unlocks"] - 1.5).abs() < 1e-9);
+        assert!((scores["staking"] - 0.5).abs() < 1e-9);
+        assert!(!scores.contains_key("fees"));
+        assert!(!scores.contains_key("praised"));
+        assert_eq!(scores.len(), 2);
+    }
+
+    #[test]
+    fn test_rank_by_feedback() {
+        let entries = || vec![knowledge("a"), knowledge("b"), knowledge("c"), knowledge("d"), knowledge("e")];
+        let order = |entries: Vec<Knowledge>| entries.into_iter().map(|entry| entry.source_id).collect::<Vec<_>>();
+
+        assert_eq!(order(rank_by_feedback(entries(), &HashMap::new())), ["a", "b", "c", "d", "e"]);
+
+        // A half point of demotion moves "a" from 0 to 1.5, behind "b"
+        let demotions = HashMap::from([("a".to_string(), 0.5)]);
+        assert_eq!(order(rank_by_feedback(entries(), &demotions)), ["b", "a", "c", "d", "e"]);
+
+        // Two recent bad ratings push "b" from 1 to 7, to the end
+        let demotions = HashMap::from([("b".to_string(), 2.0), ("unknown".to_string(), 5.0)]);
+        assert_eq!(order(rank_by_feedback(entries(), &demotions)), ["a", "c", "d", "e", "b"]);
+    }
+
+    #[test]
+    fn test_summarize_by_week() {
+        let feedback = vec![
+            rating(1, None, "2025-09-29"),
+            rating(-1, None, "2025-10-05"),
+            rating(1, None, "2025-10-06"),
+            rating(1, None, "2025-09-24"),
+        ];
+        let weeks = summarize_by_week(&feedback);
+        let monday = |date: &str| NaiveDate::parse_from_str(date, "%Y-%m-%d").unwrap();
+        assert_eq!(
+            weeks,
+            vec![
+                FeedbackWeek { week_start: monday("2025-09-22"), good: 1, bad: 0 },
+                FeedbackWeek { week_start: monday("2025-09-29"), good: 1, bad: 1 },
+                FeedbackWeek { week_start: monday("2025-10-06"), good: 1, bad: 0 },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_render_feedback_stats() {
+        assert_eq!(render_feedback_stats(&[]), "No answers rated yet. Use /good or /bad [reason] after an answer.");
+
+        let feedback = vec![
+            rating(1, None, "2025-09-29"),
+            rating(-1, Some("wrong unlock date"), "2025-10-01"),
+            rating(1, None, "2025-10-06"),
+        ];
+        let output = render_feedback_stats(&feedback);
+        assert!(output.starts_with("3 answers rated: 2 good and 1 bad (67% good).\n\n"));
+        assert!(output.contains("2025-09-29     1    1     50%"));
+        assert!(output.contains("2025-10-06     1    0    100%"));
+        assert!(output.ends_with("Latest reasons for bad ratings:\n- 2025-10-01: wrong unlock date"));
+    }
+
+    #[tokio::test]
+    async fn test_rating_attributes_the_injected_sources() {
+        let Some(pool) = test_pool().await else { return };
+        let user = db::create_user(&pool, "alice", None).await.unwrap();
+
+        assert_eq!(rate_last_answer(&pool, user.id, Rating::Bad, None).await.unwrap(), "There's no answer to rate yet.");
+
+        let sources = vec!["unlock-notes".to_string(), "arb-research".to_string()];
+        db::save_assistant_message(&pool, user.id, "ARB unlocks in June", None, &sources, false).await.unwrap();
+        rate_last_answer(&pool, user.id, Rating::Bad, Some("it's in March")).await.unwrap();
+        db::save_assistant_message(&pool, user.id, "Staking yields 3%", None, &["arb-research".to_string()], false).await.unwrap();
+        rate_last_answer(&pool, user.id, Rating::Good, None).await.unwrap();
+        // Rating again replaces the earlier rating
+        rate_last_answer(&pool, user.id, Rating::Good, None).await.unwrap();
+
+        let stored = db::get_feedback(&pool, user.id).await.unwrap();
+        assert_eq!(stored.len(), 2);
+        assert_eq!(stored[0].reason.as_deref(), Some("it's in March"));
+
+        let mut ratings: Vec<(String, i16)> = db::get_source_ratings(&pool, user.id)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|rating| (rating.source_id, rating.score))
+            .collect();
+        ratings.sort();
+        assert_eq!(
+            ratings,
+            vec![("arb-research".to_string(), -1), ("arb-research".to_string(), 1), ("unlock-notes".to_string(), -1)]
+        );
+
+        // Ratings keep their sources once the rated messages are archived
+        let later = chrono::Utc::now().naive_utc() + chrono::Duration::days(1);
+        db::archive_messages_before(&pool, user.id, later).await.unwrap();
+        assert_eq!(db::get_source_ratings(&pool, user.id).await.unwrap().len(), 3);
+    }
+}
@@ -0,0 +1,332 @@
+use crate::price_fetcher::{self, PriceError};
+use regex::Regex;
+use std::collections::HashMap;
+use std::sync::OnceLock;
+use thiserror::Error;
+
+/// A fiat currency amounts in chat can be written in
+#[derive(Debug, PartialEq, Eq)]
+pub struct Currency {
+    /// ISO 4217 code, e.g. "CAD"
+    pub code: &'static str,
+    /// Written before the amount, empty for currencies shown with their code after it
+    pub symbol: &'static str,
+    /// Lowercase codes, symbols and words that name the currency in a message
+    pub aliases: &'static [&'static str],
+    /// Decimals of the smallest unit, amounts are rounded to it
+    pub decimals: usize,
+}
+
+/// Every currency amounts are recognised and converted in, USD first
+pub const CURRENCIES: &[Currency] = &[
+    Currency { code: "USD", symbol: "$", aliases: &["usd", "$", "us$", "dollar", "dollars", "bucks"], decimals: 2 },
+    Currency { code: "EUR", symbol: "€", aliases: &["eur", "€", "euro", "euros"], decimals: 2 },
+    Currency { code: "GBP", symbol: "£", aliases: &["gbp", "£", "pound", "pounds", "quid"], decimals: 2 },
+    Currency { code: "CAD", symbol: "CA$", aliases: &["cad", "c$", "ca$"], decimals: 2 },
+    Currency { code: "AUD", symbol: "A$", aliases: &["aud", "a$", "au$"], decimals: 2 },
+    Currency { code: "NZD", symbol: "NZ$", aliases: &["nzd", "nz$"], decimals: 2 },
+    Currency { code: "SGD", symbol: "S$", aliases: &["sgd", "s$"], decimals: 2 },
+    Currency { code: "HKD", symbol: "HK$", aliases: &["hkd", "hk$"], decimals: 2 },
+    Currency { code: "MXN", symbol: "MX$", aliases: &["mxn", "mx$"], decimals: 2 },
+    Currency { code: "BRL", symbol: "R$", aliases: &["brl", "r$", "reais"], decimals: 2 },
+    Currency { code: "JPY", symbol: "¥", aliases: &["jpy", "¥", "yen"], decimals: 0 },
+    Currency { code: "CNY", symbol: "CN¥", aliases: &["cny", "cn¥", "rmb", "yuan"], decimals: 2 },
+    Currency { code: "KRW", symbol: "₩", aliases: &["krw", "₩", "won"], decimals: 0 },
+    Currency { code: "INR", symbol: "₹", aliases: &["inr", "₹", "rupee", "rupees"], decimals: 2 },
+    Currency { code: "TRY", symbol: "₺", aliases: &["try", "₺", "lira"], decimals: 2 },
+    Currency { code: "CHF", symbol: "", aliases: &["chf", "franc", "francs"], decimals: 2 },
+    Currency { code: "SEK", symbol: "", aliases: &["sek"], decimals: 2 },
+    Currency { code: "NOK", symbol: "", aliases: &["nok"], decimals: 2 },
+    Currency { code: "DKK", symbol: "", aliases: &["dkk"], decimals: 2 },
+    Currency { code: "PLN", symbol: "", aliases: &["pln", "zł", "zloty"], decimals: 2 },
+    Currency { code: "ZAR", symbol: "", aliases: &["zar", "rand"], decimals: 2 },
+    Currency { code: "AED", symbol: "", aliases: &["aed", "dirham", "dirhams"], decimals: 2 },
+];
+
+/// The currency every price and portfolio value is shown in
+pub fn usd() -> &'static Currency {
+    &CURRENCIES[0]
+}
+
+/// The currency a code, symbol or word names, ignoring case
+pub fn find_currency(name: &str) -> Option<&'static Currency> {
+    let name = name.trim().to_lowercase();
+    CURRENCIES.iter().find(|currency| currency.aliases.contains(&name.as_str()))
+}
+
+/// Fiat error types
+#[derive(Debug, Error, PartialEq)]
+pub enum FiatError {
+    #[error("Unknown currency: {0}")]
+    UnknownCurrency(String),
+
+    #[error("No exchange rate for {0}")]
+    MissingRate(&'static str),
+}
+
+/// Which currency an amount was written in
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Denomination {
+    /// A bare number, taken to be USD like every amount before currencies were recognised
+    Unstated,
+    Known(&'static Currency),
+    /// A code or symbol that isn't in `CURRENCIES`, e.g. "kr" or "XYZ", never assumed to be USD
+    Unknown(String),
+}
+
+/// An amount of money as written in a message, e.g. "€1.5k" or "2,000 CAD"
+#[derive(Debug, Clone, PartialEq)]
+pub struct FiatAmount {
+    pub amount: f64,
+    pub denomination: Denomination,
+}
+
+impl FiatAmount {
+    /// The currency of the amount, USD for a bare number
+    pub fn currency(&self) -> Result<&'static Currency, FiatError> {
+        match &self.denomination {
+            Denomination::Unstated => Ok(usd()),
+            Denomination::Known(currency) => Ok(currency),
+            Denomination::Unknown(name) => Err(FiatError::UnknownCurrency(name.clone())),
+        }
+    }
+}
+
+/// Regex matching an amount of money, without capture groups, for use in other patterns
+///
+/// Matches "$25k", "€ 500", "CA$2,000", "eur 500", "2000 CAD" and "1.5k pounds", as well as
+/// unknown currencies written like known ones ("XX$100", "100 XYZ", "100 kr") so they can be
+/// asked about. Unknown codes after the number only match in capitals, "100 for" isn't one.
+pub fn money_pattern() -> &'static str {
+    static PATTERN: OnceLock<String> = OnceLock::new();
+    PATTERN.get_or_init(|| {
+        let codes: Vec<String> = CURRENCIES.iter().map(|currency| currency.code.to_lowercase()).collect();
+        let codes = codes.join("|");
+        let mut words: Vec<&str> = CURRENCIES
+            .iter()
+            .flat_map(|currency| currency.aliases.iter().copied())
+            .filter(|alias| alias.chars().all(|c| c.is_ascii_alphabetic()))
+            .chain(["kr"])
+            .collect();
+        // Longest first, so "euros" wins over "euro"
+        words.sort_by_key(|word| std::cmp::Reverse(word.len()));
+        let words = words.join("|");
+        let symbols = "[$€£¥₹₩₺]|zł";
+        format!(
+            r"(?:[a-z]{{1,3}}(?:\$|¥)\s?|(?:{symbols})\s?|(?:{codes})\s?)?\d[\d,]*(?:\.\d+)?(?:k\b)?(?:\s?(?:(?:{words}|zł)\b|(?-i:[A-Z]{{3}})\b|[$€£¥₹₩₺]))?"
+        )
+    })
+}
+
+/// Parse an amount matched by `money_pattern`
+pub fn parse_money(text: &str) -> Option<FiatAmount> {
+    static NUMBER: OnceLock<Regex> = OnceLock::new();
+    let number = NUMBER.get_or_init(|| Regex::new(r"(?i)\d[\d,]*(?:\.\d+)?(k\b)?").unwrap());
+
+    let text = text.trim();
+    let captures = number.captures(text)?;
+    let whole = captures.get(0).unwrap();
+    let digits = whole.as_str().trim_end_matches(['k', 'K']);
+    let mut amount: f64 = digits.replace(',', "").parse().ok()?;
+    if captures.get(1).is_some() {
+        amount *= 1000.0;
+    }
+
+    let before = text[..whole.start()].trim();
+    let after = text[whole.end()..].trim();
+    let name = if before.is_empty() { after } else { before };
+    let denomination = if name.is_empty() {
+        Denomination::Unstated
+    } else {
+        find_currency(name).map_or_else(|| Denomination::Unknown(name.to_string()), Denomination::Known)
+    };
+    Some(FiatAmount { amount, denomination })
+}
+
+/// Round an amount to the smallest unit of its currency
+pub fn round_to_unit(amount: f64, currency: &Currency) -> f64 {
+    let scale = 10f64.powi(currency.decimals as i32);
+    (amount * scale).round() / scale
+}
+
+/// An amount with its currency's symbol or code, e.g. "€1500.00", "¥20000" or "300.00 CHF"
+pub fn format_amount(amount: f64, currency: &Currency) -> String {
+    let sign = if amount < 0.0 { "-" } else { "" };
+    let digits = format!("{:.*}", currency.decimals, amount.abs());
+    if currency.symbol.is_empty() {
+        format!("{}{} {}", sign, digits, currency.code)
+    } else {
+        format!("{}{}{}", sign, currency.symbol, digits)
+    }
+}
+
+/// The amount as written with what it converts to, e.g. "CA$2000.00 (≈ $1460.00)"
+///
+/// Amounts already in the target currency are shown once.
+pub fn format_converted(amount: f64, from: &Currency, converted: f64, to: &Currency) -> String {
+    if from == to {
+        return format_amount(amount, to);
+    }
+    format!("{} (≈ {})", format_amount(amount, from), format_amount(converted, to))
+}
+
+/// What to ask when an amount is written in a currency that isn't recognised
+pub fn clarification(name: &str) -> String {
+    let codes: Vec<&str> = CURRENCIES.iter().map(|currency| currency.code).collect();
+    format!(
+        "I don't know which currency \"{}\" is, so I haven't assumed US dollars. Could you write the amount with \
+        a currency code or symbol, like 2,000 CAD or €500? I can convert {}.",
+        name,
+        codes.join(", ")
+    )
+}
+
+/// Fiat exchange rates, in units of each currency per US dollar
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExchangeRates {
+    /// Keyed by lowercase ISO code
+    pub per_usd: HashMap<String, f64>,
+}
+
+impl ExchangeRates {
+    pub fn new(per_usd: HashMap<String, f64>) -> Self {
+        Self { per_usd }
+    }
+
+    fn rate(&self, currency: &'static Currency) -> Result<f64, FiatError> {
+        if currency == usd() {
+            return Ok(1.0);
+        }
+        self.per_usd
+            .get(&currency.code.to_lowercase())
+            .copied()
+            .filter(|rate| rate.is_finite() && *rate > 0.0)
+            .ok_or(FiatError::MissingRate(currency.code))
+    }
+
+    /// `amount` of `from` in `to`, rounded to the smallest unit of `to`
+    pub fn convert(&self, amount: f64, from: &'static Currency, to: &'static Currency) -> Result<f64, FiatError> {
+        if from == to {
+            return Ok(amount);
+        }
+        let usd = amount / self.rate(from)?;
+        Ok(round_to_unit(usd * self.rate(to)?, to))
+    }
+}
+
+/// Current exchange rates from CoinGecko, or exchangerate.host when CoinGecko fails
+///
+/// Both providers' rates are cached for `price_fetcher::FIAT_RATE_CACHE_TTL`.
+pub async fn fetch_rates() -> Result<ExchangeRates, PriceError> {
+    match price_fetcher::fetch_fiat_rates().await {
+        Ok(per_usd) => Ok(ExchangeRates::new(per_usd)),
+        Err(e) => {
+            eprintln!("CoinGecko exchange rates failed, trying the secondary provider: {}", e);
+            price_fetcher::fetch_secondary_fiat_rates().await.map(ExchangeRates::new).map_err(|_| e)
+        },
+    }
+}
+
+/// `amount` in US dollars, fetching rates only when it was written in another currency
+///
+/// The error is the reply to send: a question for unknown currencies, or why the rate is missing.
+pub async fn to_usd(amount: &FiatAmount) -> Result<f64, String> {
+    let currency = match &amount.denomination {
+        Denomination::Unstated => return Ok(amount.amount),
+        Denomination::Known(currency) => *currency,
+        Denomination::Unknown(name) => return Err(clarification(name)),
+    };
+    if currency == usd() {
+        return Ok(amount.amount);
+    }
+    let rates = fetch_rates()
+        .await
+        .map_err(|e| format!("I couldn't get the {} exchange rate to convert {}: {}", currency.code, format_amount(amount.amount, currency), e))?;
+    rates.convert(amount.amount, currency, usd()).map_err(|e| format!("I couldn't convert {}: {}", format_amount(amount.amount, currency), e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn money(text: &str) -> Option<FiatAmount> {
+        let regex = Regex::new(&format!("(?i)^{}$", money_pattern())).unwrap();
+        assert!(regex.is_match(text), "{} isn't matched", text);
+        parse_money(text)
+    }
+
+    fn known(amount: f64, code: &str) -> Option<FiatAmount> {
+        Some(FiatAmount { amount, denomination: Denomination::Known(find_currency(code).unwrap()) })
+    }
+
+    fn rates() -> ExchangeRates {
+        ExchangeRates::new(HashMap::from([
+            ("eur".to_string(), 0.92),
+            ("cad".to_string(), 1.37),
+            ("jpy".to_string(), 151.37),
+        ]))
+    }
+
+    #[test]
+    fn test_parse_money() {
+        assert_eq!(money("$25k"), known(25_000.0, "usd"));
+        assert_eq!(money("€500"), known(500.0, "eur"));
+        assert_eq!(money("€ 1,250.50"), known(1250.5, "eur"));
+        assert_eq!(money("£1.5k"), known(1500.0, "gbp"));
+        assert_eq!(money("2,000 CAD"), known(2000.0, "cad"));
+        assert_eq!(money("2000 cad"), known(2000.0, "cad"));
+        assert_eq!(money("CA$2000"), known(2000.0, "cad"));
+        assert_eq!(money("C$2000"), known(2000.0, "cad"));
+        assert_eq!(money("eur 300"), known(300.0, "eur"));
+        assert_eq!(money("10k euros"), known(10_000.0, "eur"));
+        assert_eq!(money("¥150000"), known(150_000.0, "jpy"));
+        assert_eq!(money("300 CHF"), known(300.0, "chf"));
+        assert_eq!(money("2500"), Some(FiatAmount { amount: 2500.0, denomination: Denomination::Unstated }));
+
+        // Written like a currency but not one we know
+        assert_eq!(money("500 kr"), Some(FiatAmount { amount: 500.0, denomination: Denomination::Unknown("kr".to_string()) }));
+        assert_eq!(money("100 XYZ"), Some(FiatAmount { amount: 100.0, denomination: Denomination::Unknown("XYZ".to_string()) }));
+        assert_eq!(money("ZZ$100"), Some(FiatAmount { amount: 100.0, denomination: Denomination::Unknown("ZZ$".to_string()) }));
+        assert_eq!(parse_money("100 XYZ").unwrap().currency(), Err(FiatError::UnknownCurrency("XYZ".to_string())));
+    }
+
+    #[test]
+    fn test_unknown_lowercase_words_are_not_currencies() {
+        let regex = Regex::new(&format!("(?i){}", money_pattern())).unwrap();
+        assert_eq!(regex.find("invest 2000 for a year").unwrap().as_str(), "2000");
+        assert_eq!(regex.find("put 2000 CAD into btc").unwrap().as_str(), "2000 CAD");
+        assert_eq!(regex.find("a $25k account").unwrap().as_str(), "$25k");
+    }
+
+    #[test]
+    fn test_conversion_rounding() {
+        let rates = rates();
+        let (usd, eur, cad, jpy) =
+            (usd(), find_currency("eur").unwrap(), find_currency("cad").unwrap(), find_currency("jpy").unwrap());
+
+        assert_eq!(rates.convert(2000.0, cad, usd), Ok(1459.85));
+        assert_eq!(rates.convert(100.0, usd, eur), Ok(92.0));
+        // Through USD, rounded once at the end
+        assert_eq!(rates.convert(100.0, eur, cad), Ok(148.91));
+        // Yen has no minor unit
+        assert_eq!(rates.convert(10.0, usd, jpy), Ok(1514.0));
+        assert_eq!(rates.convert(12.345, usd, usd), Ok(12.345));
+        assert_eq!(rates.convert(1.0, find_currency("gbp").unwrap(), usd), Err(FiatError::MissingRate("GBP")));
+
+        assert_eq!(round_to_unit(0.125, usd), 0.13);
+        assert_eq!(round_to_unit(1514.49, jpy), 1514.0);
+    }
+
+    #[test]
+    fn test_format_both_figures() {
+        let cad = find_currency("cad").unwrap();
+        assert_eq!(format_converted(2000.0, cad, 1459.85, usd()), "CA$2000.00 (≈ $1459.85)");
+        assert_eq!(format_converted(2000.0, usd(), 2000.0, usd()), "$2000.00");
+        assert_eq!(format_amount(300.0, find_currency("chf").unwrap()), "300.00 CHF");
+        assert_eq!(format_amount(20000.0, find_currency("yen").unwrap()), "¥20000");
+
+        let reply = clarification("kr");
+        assert!(reply.starts_with("I don't know which currency \"kr\" is, so I haven't assumed US dollars."));
+        assert!(reply.contains("SEK, NOK, DKK"));
+    }
+}
@@ -0,0 +1,440 @@
+use crate::config::Config;
+use crate::db::GasReading;
+use crate::price_fetcher::CoinGeckoClient;
+use crate::price_format::format_price;
+use chrono::{DateTime, Duration, Utc};
+use ethers::providers::{Http, Middleware, Provider};
+use ethers::types::{BlockNumber, U256};
+use regex::Regex;
+use serde::Serialize;
+use std::fmt;
+use std::sync::{Arc, OnceLock};
+use thiserror::Error;
+
+/// Gas used by a plain ETH transfer
+pub const TRANSFER_GAS: u64 = 21_000;
+
+/// Typical gas used by a single-pool DEX swap
+pub const SWAP_GAS: u64 = 150_000;
+
+/// How far back readings are compared against
+pub const WINDOW_HOURS: i64 = 24;
+
+/// Fewest readings in the window before gas is called high or low
+pub const MIN_WINDOW_READINGS: usize = 12;
+
+/// Blocks of fee history the priority fees are suggested from
+const FEE_HISTORY_BLOCKS: u64 = 20;
+
+/// Reward percentiles asked for, giving the slow, standard and fast priority fees
+const REWARD_PERCENTILES: [f64; 3] = [10.0, 50.0, 90.0];
+
+/// Percentile ranks at or below which gas is low, and at or above which it is high
+const LOW_RANK: f64 = 25.0;
+const HIGH_RANK: f64 = 75.0;
+
+const WEI_PER_GWEI: f64 = 1e9;
+const GWEI_PER_ETH: f64 = 1e9;
+
+#[derive(Debug, Error)]
+pub enum GasError {
+    #[error("No RPC URL configured, set RPC_URL")]
+    NotConfigured,
+
+    #[error("RPC error: {0}")]
+    Rpc(String),
+}
+
+/// Tips to the block builder on top of the base fee, in gwei
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct PriorityFees {
+    pub slow_gwei: f64,
+    pub standard_gwei: f64,
+    pub fast_gwei: f64,
+}
+
+/// What one kind of transaction costs at the standard priority fee
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct GasCost {
+    pub gas_units: u64,
+    pub eth: f64,
+    /// None when the ETH price is unavailable
+    pub usd: Option<f64>,
+}
+
+impl GasCost {
+    pub fn new(gas_units: u64, gas_price_gwei: f64, eth_price_usd: Option<f64>) -> Self {
+        let eth = gas_units as f64 * gas_price_gwei / GWEI_PER_ETH;
+        Self { gas_units, eth, usd: eth_price_usd.map(|price| eth * price) }
+    }
+}
+
+/// Gas prices at one moment with what a swap and a transfer cost at them
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct GasSnapshot {
+    pub taken_at: DateTime<Utc>,
+    /// Base fee of the next block
+    pub base_fee_gwei: f64,
+    pub priority_fees: PriorityFees,
+    pub eth_price_usd: Option<f64>,
+    pub swap: GasCost,
+    pub transfer: GasCost,
+}
+
+impl GasSnapshot {
+    pub fn new(base_fee_gwei: f64, priority_fees: PriorityFees, eth_price_usd: Option<f64>, taken_at: DateTime<Utc>) -> Self {
+        let gas_price = base_fee_gwei + priority_fees.standard_gwei;
+        Self {
+            taken_at,
+            base_fee_gwei,
+            priority_fees,
+            eth_price_usd,
+            swap: GasCost::new(SWAP_GAS, gas_price, eth_price_usd),
+            transfer: GasCost::new(TRANSFER_GAS, gas_price, eth_price_usd),
+        }
+    }
+
+    /// Base fee plus the standard priority fee, what the window readings are compared on
+    pub fn gas_price_gwei(&self) -> f64 {
+        self.base_fee_gwei + self.priority_fees.standard_gwei
+    }
+}
+
+/// Where the current gas price falls in the recent window
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum GasLevel {
+    Low,
+    Typical,
+    High,
+}
+
+impl fmt::Display for GasLevel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            GasLevel::Low => "low",
+            GasLevel::Typical => "typical",
+            GasLevel::High => "high",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// The current gas price against the readings of the last `WINDOW_HOURS`
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct GasComparison {
+    /// Share of readings below the current price, in percent, ties counted half
+    pub percentile_rank: f64,
+    pub median_gwei: f64,
+    pub readings: usize,
+    pub level: GasLevel,
+}
+
+/// Compare `current_gwei` to the window, None with fewer than `MIN_WINDOW_READINGS` readings
+pub fn compare(current_gwei: f64, window: &[f64]) -> Option<GasComparison> {
+    if window.len() < MIN_WINDOW_READINGS {
+        return None;
+    }
+    let rank = percentile_rank(window, current_gwei);
+    let level = if rank <= LOW_RANK {
+        GasLevel::Low
+    } else if rank >= HIGH_RANK {
+        GasLevel::High
+    } else {
+        GasLevel::Typical
+    };
+    Some(GasComparison {
+        percentile_rank: rank,
+        median_gwei: percentile(window, 50.0)?,
+        readings: window.len(),
+        level,
+    })
+}
+
+/// Percent of `values` below `value`, values equal to it counted half
+pub fn percentile_rank(values: &[f64], value: f64) -> f64 {
+    if values.is_empty() {
+        return 50.0;
+    }
+    let below = values.iter().filter(|v| **v < value).count() as f64;
+    let equal = values.iter().filter(|v| **v == value).count() as f64;
+    (below + equal / 2.0) / values.len() as f64 * 100.0
+}
+
+/// The `p`th percentile of `values`, interpolating linearly between the closest ranks
+pub fn percentile(values: &[f64], p: f64) -> Option<f64> {
+    if values.is_empty() {
+        return None;
+    }
+    let mut sorted = values.to_vec();
+    sorted.sort_by(|a, b| a.total_cmp(b));
+    let position = p.clamp(0.0, 100.0) / 100.0 * (sorted.len() - 1) as f64;
+    let lower = position.floor() as usize;
+    let upper = position.ceil() as usize;
+    Some(sorted[lower] + (sorted[upper] - sorted[lower]) * (position - lower as f64))
+}
+
+/// Suggested priority fees from fee history rewards, one row per block at `REWARD_PERCENTILES`
+/// Each is the median over the blocks, so a single block of bidding wars doesn't set it
+pub fn priority_fees(rewards: &[Vec<f64>]) -> PriorityFees {
+    let column = |index: usize| {
+        let values: Vec<f64> = rewards.iter().filter_map(|row| row.get(index).copied()).collect();
+        percentile(&values, 50.0).unwrap_or(0.0)
+    };
+    PriorityFees { slow_gwei: column(0), standard_gwei: column(1), fast_gwei: column(2) }
+}
+
+/// Reads gas prices from an RPC node
+pub struct GasOracle {
+    provider: Arc<Provider<Http>>,
+    prices: CoinGeckoClient,
+}
+
+impl GasOracle {
+    pub fn new(provider: Arc<Provider<Http>>, prices: CoinGeckoClient) -> Self {
+        Self { provider, prices }
+    }
+
+    /// Oracle on the configured RPC node
+    pub fn from_config() -> Result<Self, GasError> {
+        let rpc_url = Config::get_instance()
+            .map(|config| config.rpc_url.clone())
+            .unwrap_or_default();
+        if rpc_url.is_empty() {
+            return Err(GasError::NotConfigured);
+        }
+        let provider = Provider::<Http>::try_from(rpc_url).map_err(|e| GasError::Rpc(e.to_string()))?;
+        Ok(Self::new(Arc::new(provider), CoinGeckoClient::from_config()))
+    }
+
+    /// The next block's base fee and suggested priority fees, in gwei
+    pub async fn fees(&self) -> Result<(f64, PriorityFees), GasError> {
+        let history = self.provider
+            .fee_history(FEE_HISTORY_BLOCKS, BlockNumber::Latest, &REWARD_PERCENTILES)
+            .await
+            .map_err(|e| GasError::Rpc(e.to_string()))?;
+
+        // The last base fee is the one the next block will charge
+        let base_fee = history
+            .base_fee_per_gas
+            .last()
+            .map(|fee| to_gwei(*fee))
+            .ok_or_else(|| GasError::Rpc("fee history has no base fee".to_string()))?;
+        let rewards: Vec<Vec<f64>> = history.reward.iter().map(|row| row.iter().map(|fee| to_gwei(*fee)).collect()).collect();
+        Ok((base_fee, priority_fees(&rewards)))
+    }
+
+    /// Current fees with swap and transfer costs, in USD when ETH has a price
+    pub async fn snapshot(&self, now: DateTime<Utc>) -> Result<GasSnapshot, GasError> {
+        let (base_fee, priority_fees) = self.fees().await?;
+        let eth_price = self.prices.fetch_coin_price("ethereum").await.ok();
+        Ok(GasSnapshot::new(base_fee, priority_fees, eth_price, now))
+    }
+}
+
+fn to_gwei(wei: U256) -> f64 {
+    // Fees fit in a u128 by many orders of magnitude
+    wei.low_u128() as f64 / WEI_PER_GWEI
+}
+
+/// Gas prices of the stored readings in the window ending at `now`
+pub fn window_prices(readings: &[GasReading], now: DateTime<Utc>) -> Vec<f64> {
+    let since = (now - Duration::hours(WINDOW_HOURS)).naive_utc();
+    readings
+        .iter()
+        .filter(|reading| reading.taken_at >= since)
+        .map(|reading| reading.base_fee_gwei + reading.priority_fee_gwei)
+        .collect()
+}
+
+/// "how's gas right now", "gas fees today?" or "should I wait to swap"
+pub fn is_gas_query(message: &str) -> bool {
+    static QUERY: OnceLock<Regex> = OnceLock::new();
+    QUERY
+        .get_or_init(|| {
+            Regex::new(r"(?i)\b(?:gas(?:\s+(?:price|prices|fees?|costs?))?\s+(?:right\s+now|now|today|at\s+the\s+moment)|how(?:'s|\s+is|\s+are|\s+high\s+is)\s+(?:the\s+)?gas|what(?:'s|\s+is|\s+are)\s+(?:the\s+)?(?:current\s+)?gas|should\s+i\s+wait\s+(?:to|before|for\s+gas)|(?:is\s+)?(?:now|it)\s+a\s+good\s+time\s+to\s+(?:transact|swap|send|bridge)|gas\s+(?:is\s+)?(?:high|low|cheap|expensive))").unwrap()
+        })
+        .is_match(message)
+}
+
+/// Whether the message asks about timing a transaction, not just the price of gas
+fn asks_about_timing(message: &str) -> bool {
+    let message = message.to_lowercase();
+    ["wait", "good time", "should i", "now or"].iter().any(|phrase| message.contains(phrase))
+}
+
+/// Gas prices small enough for cheap chains keep a few significant figures
+fn format_gwei(gwei: f64) -> String {
+    if gwei >= 1.0 {
+        format!("{:.1} gwei", gwei)
+    } else {
+        format!("{:.4} gwei", gwei)
+    }
+}
+
+fn format_cost(cost: &GasCost) -> String {
+    match cost.usd {
+        Some(usd) => format!("{:.6} ETH ({})", cost.eth, format_price(usd)),
+        None => format!("{:.6} ETH", cost.eth),
+    }
+}
+
+/// Answer to a gas question, with timing advice when the message asks whether to wait
+pub fn render_gas_answer(message: &str, snapshot: &GasSnapshot, comparison: Option<&GasComparison>) -> String {
+    let fees = snapshot.priority_fees;
+    let mut lines = vec![
+        format!(
+            "Gas right now: base fee {}, priority fee {} / {} / {} (slow / standard / fast).",
+            format_gwei(snapshot.base_fee_gwei),
+            format_gwei(fees.slow_gwei),
+            format_gwei(fees.standard_gwei),
+            format_gwei(fees.fast_gwei)
+        ),
+        format!(
+            "A swap costs about {} and a transfer about {}.",
+            format_cost(&snapshot.swap),
+            format_cost(&snapshot.transfer)
+        ),
+    ];
+
+    match comparison {
+        Some(comparison) => {
+            lines.push(format!(
+                "That's {} for the past {}h: higher than {:.0}% of {} readings, the median was {}.",
+                comparison.level,
+                WINDOW_HOURS,
+                comparison.percentile_rank,
+                comparison.readings,
+                format_gwei(comparison.median_gwei)
+            ));
+            if asks_about_timing(message) {
+                lines.push(
+                    match comparison.level {
+                        GasLevel::Low => "Now is a good time to transact.",
+                        GasLevel::Typical => "Gas is about usual, so waiting is unlikely to save much.",
+                        GasLevel::High => "If it isn't urgent, waiting for a quieter hour should make it cheaper.",
+                    }
+                    .to_string(),
+                );
+            }
+        },
+        None => lines.push(
+            "I don't have enough readings from the past 24h to say whether that's high or low; the daemon's gas watcher collects them."
+                .to_string(),
+        ),
+    }
+    lines.join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn now() -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339("2026-10-15T12:00:00Z").unwrap().with_timezone(&Utc)
+    }
+
+    #[test]
+    fn test_costs_in_eth_and_usd() {
+        let fees = PriorityFees { slow_gwei: 0.5, standard_gwei: 1.0, fast_gwei: 2.0 };
+        let snapshot = GasSnapshot::new(19.0, fees, Some(2_500.0), now());
+        assert_eq!(snapshot.gas_price_gwei(), 20.0);
+        // 21,000 gas at 20 gwei is 0.00042 ETH, $1.05 at $2,500
+        assert!((snapshot.transfer.eth - 0.00042).abs() < 1e-12);
+        assert!((snapshot.transfer.usd.unwrap() - 1.05).abs() < 1e-9);
+        assert!((snapshot.swap.eth - 0.003).abs() < 1e-12);
+        assert!((snapshot.swap.usd.unwrap() - 7.5).abs() < 1e-9);
+
+        let unpriced = GasSnapshot::new(19.0, fees, None, now());
+        assert_eq!(unpriced.swap.usd, None);
+        assert_eq!(format_cost(&unpriced.transfer), "0.000420 ETH");
+        assert_eq!(format_cost(&snapshot.transfer), "0.000420 ETH ($1.05)");
+    }
+
+    #[test]
+    fn test_percentiles() {
+        let values = [4.0, 1.0, 3.0, 2.0, 5.0];
+        assert_eq!(percentile(&values, 50.0), Some(3.0));
+        assert_eq!(percentile(&values, 0.0), Some(1.0));
+        assert_eq!(percentile(&values, 100.0), Some(5.0));
+        assert_eq!(percentile(&values, 25.0), Some(2.0));
+        assert_eq!(percentile(&[1.0, 2.0], 50.0), Some(1.5));
+        assert_eq!(percentile(&[], 50.0), None);
+
+        assert_eq!(percentile_rank(&values, 0.5), 0.0);
+        assert_eq!(percentile_rank(&values, 3.0), 50.0);
+        assert_eq!(percentile_rank(&values, 9.0), 100.0);
+        assert_eq!(percentile_rank(&[2.0, 2.0, 2.0, 2.0], 2.0), 50.0);
+    }
+
+    #[test]
+    fn test_compare_needs_a_full_window() {
+        let window: Vec<f64> = (1..=20).map(f64::from).collect();
+        assert_eq!(compare(10.0, &window[..MIN_WINDOW_READINGS - 1]), None);
+
+        let low = compare(3.0, &window).unwrap();
+        assert_eq!((low.level, low.readings, low.median_gwei), (GasLevel::Low, 20, 10.5));
+        assert_eq!(compare(11.0, &window).unwrap().level, GasLevel::Typical);
+        let high = compare(19.0, &window).unwrap();
+        assert_eq!(high.level, GasLevel::High);
+        assert_eq!(high.percentile_rank, 92.5);
+    }
+
+    #[test]
+    fn test_priority_fees_take_the_median_block() {
+        let rewards = vec![vec![0.1, 1.0, 3.0], vec![0.2, 1.5, 40.0], vec![0.1, 2.0, 4.0]];
+        assert_eq!(priority_fees(&rewards), PriorityFees { slow_gwei: 0.1, standard_gwei: 1.5, fast_gwei: 4.0 });
+        assert_eq!(priority_fees(&[]), PriorityFees { slow_gwei: 0.0, standard_gwei: 0.0, fast_gwei: 0.0 });
+    }
+
+    #[test]
+    fn test_window_drops_old_readings() {
+        let reading = |hours_ago: i64, gwei: f64| GasReading {
+            id: 0,
+            base_fee_gwei: gwei,
+            priority_fee_gwei: 1.0,
+            taken_at: (now() - Duration::hours(hours_ago)).naive_utc(),
+        };
+        let readings = [reading(30, 50.0), reading(23, 10.0), reading(1, 20.0)];
+        assert_eq!(window_prices(&readings, now()), vec![11.0, 21.0]);
+    }
+
+    #[test]
+    fn test_is_gas_query() {
+        for message in [
+            "how's gas right now?",
+            "What are gas fees today",
+            "should I wait to swap?",
+            "is now a good time to transact",
+            "gas is high?",
+        ] {
+            assert!(is_gas_query(message), "{}", message);
+        }
+        for message in ["what is a gas token", "price of ETH", "gasless approvals explained"] {
+            assert!(!is_gas_query(message), "{}", message);
+        }
+    }
+
+    #[test]
+    fn test_render_gas_answer() {
+        let fees = PriorityFees { slow_gwei: 0.5, standard_gwei: 1.0, fast_gwei: 2.0 };
+        let snapshot = GasSnapshot::new(19.0, fees, Some(2_500.0), now());
+        let window: Vec<f64> = (1..=40).map(f64::from).collect();
+        let comparison = compare(snapshot.gas_price_gwei(), &window);
+
+        let answer = render_gas_answer("should I wait to swap?", &snapshot, comparison.as_ref());
+        assert_eq!(
+            answer,
+            "Gas right now: base fee 19.0 gwei, priority fee 0.5000 gwei / 1.0 gwei / 2.0 gwei (slow / standard / fast).\n\
+            A swap costs about 0.003000 ETH ($7.50) and a transfer about 0.000420 ETH ($1.05).\n\
+            That's typical for the past 24h: higher than 49% of 40 readings, the median was 20.5 gwei.\n\
+            Gas is about usual, so waiting is unlikely to save much."
+        );
+
+        // Timing advice only when asked for
+        let plain = render_gas_answer("how's gas right now", &snapshot, comparison.as_ref());
+        assert!(!plain.contains("waiting"));
+        let unknown = render_gas_answer("how's gas right now", &snapshot, None);
+        assert!(unknown.ends_with("the daemon's gas watcher collects them."));
+    }
+}
@@ -0,0 +1,739 @@
+use crate::circuit_breaker::{self, BreakerSnapshot, BreakerState, CircuitBreaker};
+use crate::config::Config;
+use crate::llm::LlmProvider;
+use crate::offline;
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use reqwest::{Client, StatusCode};
+use serde::Serialize;
+use sqlx::{Pool, Postgres};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::task::JoinSet;
+
+/// Longest a single probe may take before it counts as down
+pub const PROBE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Probes answering slower than this are reported as degraded
+pub const SLOW_PROBE: Duration = Duration::from_secs(2);
+
+/// State of one subsystem, or of the whole agent
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ProbeStatus {
+    Up,
+    Degraded,
+    Down,
+}
+
+impl ProbeStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ProbeStatus::Up => "up",
+            ProbeStatus::Degraded => "degraded",
+            ProbeStatus::Down => "down",
+        }
+    }
+}
+
+/// Why a probe did not come back up
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ProbeFailure {
+    /// Reachable but not fully working, e.g. rate limited
+    Degraded(String),
+    /// Unreachable or rejecting our credentials
+    Down(String),
+}
+
+/// A check of one subsystem the agent depends on
+#[async_trait]
+pub trait Probe: Send + Sync {
+    /// Name used in the report
+    fn name(&self) -> &'static str;
+
+    /// Whether the agent can't work at all while this subsystem is down
+    fn critical(&self) -> bool {
+        false
+    }
+
+    /// Run the check, returning an optional detail on success
+    async fn check(&self) -> Result<Option<String>, ProbeFailure>;
+}
+
+/// Outcome of a single probe
+#[derive(Debug, Clone, Serialize)]
+pub struct ProbeResult {
+    pub name: String,
+    pub status: ProbeStatus,
+    pub critical: bool,
+    pub latency_ms: u64,
+    pub detail: Option<String>,
+    pub last_error: Option<String>,
+}
+
+/// Outcome of all probes, with the worst status that matters
+#[derive(Debug, Clone, Serialize)]
+pub struct SystemHealth {
+    pub status: ProbeStatus,
+    pub checked_at: DateTime<Utc>,
+    pub probes: Vec<ProbeResult>,
+    /// Circuit breakers around the upstreams chat turns call
+    pub breakers: Vec<BreakerSnapshot>,
+}
+
+/// Overall status: down when a critical probe is down, degraded when any probe isn't up
+pub fn aggregate_status(probes: &[ProbeResult]) -> ProbeStatus {
+    if probes.iter().any(|probe| probe.critical && probe.status == ProbeStatus::Down) {
+        ProbeStatus::Down
+    } else if probes.iter().any(|probe| probe.status != ProbeStatus::Up) {
+        ProbeStatus::Degraded
+    } else {
+        ProbeStatus::Up
+    }
+}
+
+/// Runs a set of probes concurrently, each under its own timeout
+pub struct HealthChecker {
+    probes: Vec<Arc<dyn Probe>>,
+    breakers: Vec<Arc<CircuitBreaker>>,
+    timeout: Duration,
+    slow_after: Duration,
+}
+
+impl HealthChecker {
+    pub fn new(probes: Vec<Arc<dyn Probe>>) -> Self {
+        Self {
+            probes,
+            breakers: Vec::new(),
+            timeout: PROBE_TIMEOUT,
+            slow_after: SLOW_PROBE,
+        }
+    }
+
+    /// Probes for the database, if there is a pool, and every configured integration
+    ///
+    /// Only the configured LLM provider is probed. Anthropic and Exa are skipped while their API key
+    /// is a development placeholder. The breakers shared by CoinGecko and Exa clients are reported too.
+    pub fn from_config(pool: Option<Pool<Postgres>>) -> Self {
+        let client = Client::new();
+        let mut probes: Vec<Arc<dyn Probe>> = Vec::new();
+        let mut breakers = vec![circuit_breaker::shared(circuit_breaker::COINGECKO)];
+        if let Some(pool) = pool {
+            probes.push(Arc::new(DatabaseProbe::new(pool)));
+        }
+        if let Ok(config) = Config::get_instance() {
+            match config.llm_provider {
+                LlmProvider::Anthropic if is_configured(&config.anthropic_api_key) => {
+                    probes.push(Arc::new(AnthropicProbe::new(client.clone(), &config.anthropic_base_url, &config.anthropic_api_key)));
+                },
+                LlmProvider::Anthropic => {},
+                provider => probes.push(Arc::new(
+                    OpenAiProbe::new(client.clone(), &config.openai_base_url, config.openai_api_key.as_deref())
+                        .named(if provider == LlmProvider::Ollama { "ollama" } else { "openai" }),
+                )),
+            }
+            probes.push(Arc::new(CoinGeckoProbe::new(client.clone(), &config.coingecko_base_url, config.coingecko_api_key.as_deref())
+                .with_pro(config.coingecko_pro)));
+            if is_configured(&config.exa_api_key) {
+                probes.push(Arc::new(ExaProbe::new(client.clone(), &config.exa_base_url, &config.exa_api_key)));
+                breakers.push(circuit_breaker::shared(circuit_breaker::EXA));
+            }
+            if !config.rpc_url.is_empty() {
+                probes.push(Arc::new(RpcProbe::new(client, &config.rpc_url)));
+            }
+        }
+        Self::new(probes).with_breakers(breakers)
+    }
+    
+    /// Report the state of these breakers with the probes
+    pub fn with_breakers(mut self, breakers: Vec<Arc<CircuitBreaker>>) -> Self {
+        self.breakers = breakers;
+        self
+    }
+
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    pub fn with_slow_after(mut self, slow_after: Duration) -> Self {
+        self.slow_after = slow_after;
+        self
+    }
+
+    /// Run every probe at once and collect the results in probe order
+    pub async fn check(&self) -> SystemHealth {
+        let mut set = JoinSet::new();
+        for (index, probe) in self.probes.iter().enumerate() {
+            let probe = probe.clone();
+            let timeout = self.timeout;
+            let slow_after = self.slow_after;
+            set.spawn(async move { (index, run_probe(probe.as_ref(), timeout, slow_after).await) });
+        }
+
+        let mut results: Vec<Option<ProbeResult>> = vec![None; self.probes.len()];
+        while let Some(joined) = set.join_next().await {
+            match joined {
+                Ok((index, result)) => results[index] = Some(result),
+                Err(e) => eprintln!("Health probe task failed: {}", e),
+            }
+        }
+
+        // A probe whose task panicked is reported as down
+        let probes: Vec<ProbeResult> = results
+            .into_iter()
+            .zip(&self.probes)
+            .map(|(result, probe)| {
+                result.unwrap_or_else(|| ProbeResult {
+                    name: probe.name().to_string(),
+                    status: ProbeStatus::Down,
+                    critical: probe.critical(),
+                    latency_ms: 0,
+                    detail: None,
+                    last_error: Some("probe failed to run".to_string()),
+                })
+            })
+            .collect();
+
+        SystemHealth {
+            status: aggregate_status(&probes),
+            checked_at: Utc::now(),
+            probes,
+            breakers: self.breakers.iter().map(|breaker| breaker.snapshot()).collect(),
+        }
+    }
+}
+
+async fn run_probe(probe: &dyn Probe, timeout: Duration, slow_after: Duration) -> ProbeResult {
+    let started = Instant::now();
+    let outcome = tokio::time::timeout(timeout, probe.check()).await;
+    let elapsed = started.elapsed();
+
+    let (status, detail, last_error) = match outcome {
+        Ok(Ok(detail)) if elapsed > slow_after => {
+            (ProbeStatus::Degraded, detail, Some(format!("slow response ({} ms)", elapsed.as_millis())))
+        },
+        Ok(Ok(detail)) => (ProbeStatus::Up, detail, None),
+        Ok(Err(ProbeFailure::Degraded(reason))) => (ProbeStatus::Degraded, None, Some(reason)),
+        Ok(Err(ProbeFailure::Down(reason))) => (ProbeStatus::Down, None, Some(reason)),
+        Err(_) => (ProbeStatus::Down, None, Some(format!("timed out after {} ms", timeout.as_millis()))),
+    };
+
+    ProbeResult {
+        name: probe.name().to_string(),
+        status,
+        critical: probe.critical(),
+        latency_ms: elapsed.as_millis() as u64,
+        detail,
+        last_error,
+    }
+}
+
+/// Multi-line report shown by `/health`
+pub fn render_health(health: &SystemHealth) -> String {
+    if health.probes.is_empty() {
+        return "System health: nothing to check, no integrations are configured.".to_string();
+    }
+    let mut lines = vec![format!("System health: {}", health.status.as_str())];
+    for probe in &health.probes {
+        let mut line = format!("- {}: {} ({} ms)", probe.name, probe.status.as_str(), probe.latency_ms);
+        if let Some(detail) = &probe.detail {
+            line.push_str(&format!(", {}", detail));
+        }
+        if let Some(error) = &probe.last_error {
+            line.push_str(&format!(" - {}", error));
+        }
+        lines.push(line);
+    }
+    for breaker in &health.breakers {
+        lines.push(render_breaker(breaker));
+    }
+    lines.join("\n")
+}
+
+/// e.g. "- exa calls: paused after 5 failures in a row, trying again in 20s (12 skipped)"
+fn render_breaker(breaker: &BreakerSnapshot) -> String {
+    let state = match breaker.state {
+        BreakerState::Closed => "going through".to_string(),
+        BreakerState::Open => format!(
+            "paused after {} failures in a row, trying again in {}s",
+            breaker.consecutive_failures,
+            breaker.retry_in_secs.unwrap_or_default()
+        ),
+        BreakerState::HalfOpen => "paused, one call is checking whether it's back".to_string(),
+    };
+    let mut line = format!("- {} calls: {}", breaker.name, state);
+    if breaker.short_circuited > 0 {
+        line.push_str(&format!(" ({} skipped)", breaker.short_circuited));
+    }
+    line
+}
+
+/// One-line summary for logs, e.g. "degraded: database up, anthropic up, exa degraded"
+pub fn summary_line(health: &SystemHealth) -> String {
+    let probes: Vec<String> = health
+        .probes
+        .iter()
+        .map(|probe| format!("{} {}", probe.name, probe.status.as_str()))
+        .collect();
+    format!("{}: {}", health.status.as_str(), probes.join(", "))
+}
+
+// API keys missing from the environment fall back to placeholders like "mock_exa_api_key_for_development"
+fn is_configured(api_key: &str) -> bool {
+    !api_key.is_empty() && !api_key.starts_with("mock_")
+}
+
+// Network probes report the agent's offline mode instead of calling out
+fn ensure_online() -> Result<(), ProbeFailure> {
+    if offline::is_offline() {
+        Err(ProbeFailure::Degraded("skipped, offline mode".to_string()))
+    } else {
+        Ok(())
+    }
+}
+
+// Rate limits and server errors leave the service usable later, auth failures don't
+fn check_status(service: &str, status: StatusCode) -> Result<(), ProbeFailure> {
+    if status.is_success() {
+        Ok(())
+    } else if status == StatusCode::UNAUTHORIZED || status == StatusCode::FORBIDDEN {
+        Err(ProbeFailure::Down(format!("{} rejected the API key", service)))
+    } else if status == StatusCode::TOO_MANY_REQUESTS || status.is_server_error() {
+        Err(ProbeFailure::Degraded(format!("{} returned status {}", service, status)))
+    } else {
+        Err(ProbeFailure::Down(format!("{} returned status {}", service, status)))
+    }
+}
+
+// Probes never switch the agent offline, a failed one only reports the service down
+fn unreachable(service: &str, error: reqwest::Error) -> ProbeFailure {
+    ProbeFailure::Down(format!("could not reach {}: {}", service, error))
+}
+
+/// `SELECT 1` on the database pool
+pub struct DatabaseProbe {
+    pool: Pool<Postgres>,
+}
+
+impl DatabaseProbe {
+    pub fn new(pool: Pool<Postgres>) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait]
+impl Probe for DatabaseProbe {
+    fn name(&self) -> &'static str {
+        "database"
+    }
+
+    fn critical(&self) -> bool {
+        true
+    }
+
+    async fn check(&self) -> Result<Option<String>, ProbeFailure> {
+        sqlx::query("SELECT 1")
+            .execute(&self.pool)
+            .await
+            .map_err(|e| ProbeFailure::Down(e.to_string()))?;
+        Ok(None)
+    }
+}
+
+/// Lists the Anthropic models, which checks the key without spending tokens
+pub struct AnthropicProbe {
+    client: Client,
+    base_url: String,
+    api_key: String,
+}
+
+impl AnthropicProbe {
+    pub fn new(client: Client, base_url: &str, api_key: &str) -> Self {
+        Self { client, base_url: base_url.trim_end_matches('/').to_string(), api_key: api_key.to_string() }
+    }
+}
+
+#[async_trait]
+impl Probe for AnthropicProbe {
+    fn name(&self) -> &'static str {
+        "anthropic"
+    }
+
+    fn critical(&self) -> bool {
+        true
+    }
+
+    async fn check(&self) -> Result<Option<String>, ProbeFailure> {
+        ensure_online()?;
+        let response = self.client
+            .get(format!("{}/v1/models", self.base_url))
+            .header("x-api-key", &self.api_key)
+            .header("anthropic-version", "2023-06-01")
+            .send()
+            .await
+            .map_err(|e| unreachable("Anthropic", e))?;
+        check_status("Anthropic", response.status())?;
+        Ok(None)
+    }
+}
+
+/// Lists the models of an OpenAI-compatible server, sending the key only when there is one
+pub struct OpenAiProbe {
+    client: Client,
+    base_url: String,
+    api_key: Option<String>,
+    name: &'static str,
+}
+
+impl OpenAiProbe {
+    pub fn new(client: Client, base_url: &str, api_key: Option<&str>) -> Self {
+        Self {
+            client,
+            base_url: base_url.trim_end_matches('/').to_string(),
+            api_key: api_key.map(str::to_string),
+            name: "openai",
+        }
+    }
+
+    /// Report under another name, e.g. "ollama" for a local server
+    pub fn named(mut self, name: &'static str) -> Self {
+        self.name = name;
+        self
+    }
+}
+
+#[async_trait]
+impl Probe for OpenAiProbe {
+    fn name(&self) -> &'static str {
+        self.name
+    }
+
+    fn critical(&self) -> bool {
+        true
+    }
+
+    async fn check(&self) -> Result<Option<String>, ProbeFailure> {
+        ensure_online()?;
+        let mut request = self.client.get(format!("{}/models", self.base_url));
+        if let Some(api_key) = &self.api_key {
+            request = request.bearer_auth(api_key);
+        }
+        let response = request.send().await.map_err(|e| unreachable("the LLM server", e))?;
+        check_status("The LLM server", response.status())?;
+        Ok(None)
+    }
+}
+
+/// CoinGecko's `/ping`, sent with the demo or pro key when there is one
+pub struct CoinGeckoProbe {
+    client: Client,
+    base_url: String,
+    api_key: Option<String>,
+    pro: bool,
+}
+
+impl CoinGeckoProbe {
+    pub fn new(client: Client, base_url: &str, api_key: Option<&str>) -> Self {
+        Self {
+            client,
+            base_url: base_url.trim_end_matches('/').to_string(),
+            api_key: api_key.map(str::to_string),
+            pro: false,
+        }
+    }
+
+    /// Send the key as a pro plan key
+    pub fn with_pro(mut self, pro: bool) -> Self {
+        self.pro = pro;
+        self
+    }
+}
+
+#[async_trait]
+impl Probe for CoinGeckoProbe {
+    fn name(&self) -> &'static str {
+        "coingecko"
+    }
+
+    async fn check(&self) -> Result<Option<String>, ProbeFailure> {
+        ensure_online()?;
+        let mut request = self.client.get(format!("{}/ping", self.base_url));
+        if let Some(key) = &self.api_key {
+            request = request.header(crate::price_fetcher::api_key_header(self.pro), key);
+        }
+        let response = request.send().await.map_err(|e| unreachable("CoinGecko", e))?;
+        check_status("CoinGecko", response.status())?;
+        Ok(None)
+    }
+}
+
+/// An Exa search for a single result
+pub struct ExaProbe {
+    client: Client,
+    base_url: String,
+    api_key: String,
+}
+
+impl ExaProbe {
+    pub fn new(client: Client, base_url: &str, api_key: &str) -> Self {
+        Self { client, base_url: base_url.trim_end_matches('/').to_string(), api_key: api_key.to_string() }
+    }
+}
+
+#[async_trait]
+impl Probe for ExaProbe {
+    fn name(&self) -> &'static str {
+        "exa"
+    }
+
+    async fn check(&self) -> Result<Option<String>, ProbeFailure> {
+        ensure_online()?;
+        let response = self.client
+            .post(format!("{}/search", self.base_url))
+            .header("x-api-key", &self.api_key)
+            .json(&serde_json::json!({ "query": "bitcoin", "numResults": 1 }))
+            .send()
+            .await
+            .map_err(|e| unreachable("Exa", e))?;
+        check_status("Exa", response.status())?;
+        Ok(None)
+    }
+}
+
+/// `eth_chainId` against the configured RPC endpoint
+pub struct RpcProbe {
+    client: Client,
+    url: String,
+}
+
+impl RpcProbe {
+    pub fn new(client: Client, url: &str) -> Self {
+        Self { client, url: url.to_string() }
+    }
+}
+
+#[async_trait]
+impl Probe for RpcProbe {
+    fn name(&self) -> &'static str {
+        "rpc"
+    }
+
+    async fn check(&self) -> Result<Option<String>, ProbeFailure> {
+        ensure_online()?;
+        let response = self.client
+            .post(&self.url)
+            .json(&serde_json::json!({ "jsonrpc": "2.0", "id": 1, "method": "eth_chainId", "params": [] }))
+            .send()
+            .await
+            .map_err(|e| unreachable("the RPC endpoint", e))?;
+        check_status("The RPC endpoint", response.status())?;
+
+        let body: serde_json::Value = response
+            .json()
+            .await
+            .map_err(|e| ProbeFailure::Degraded(format!("unreadable RPC response: {}", e)))?;
+        if let Some(error) = body.get("error") {
+            return Err(ProbeFailure::Degraded(format!("RPC error: {}", error)));
+        }
+        let chain_id = body["result"]
+            .as_str()
+            .and_then(|hex| u64::from_str_radix(hex.trim_start_matches("0x"), 16).ok())
+            .ok_or_else(|| ProbeFailure::Degraded("RPC response has no chain id".to_string()))?;
+        Ok(Some(format!("chain id {}", chain_id)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct MockProbe {
+        name: &'static str,
+        critical: bool,
+        delay: Duration,
+        outcome: Result<Option<String>, ProbeFailure>,
+    }
+
+    impl MockProbe {
+        fn up(name: &'static str) -> Arc<dyn Probe> {
+            Arc::new(Self { name, critical: false, delay: Duration::ZERO, outcome: Ok(None) })
+        }
+
+        fn failing(name: &'static str, critical: bool, failure: ProbeFailure) -> Arc<dyn Probe> {
+            Arc::new(Self { name, critical, delay: Duration::ZERO, outcome: Err(failure) })
+        }
+
+        fn slow(name: &'static str, delay: Duration) -> Arc<dyn Probe> {
+            Arc::new(Self { name, critical: false, delay, outcome: Ok(None) })
+        }
+    }
+
+    #[async_trait]
+    impl Probe for MockProbe {
+        fn name(&self) -> &'static str {
+            self.name
+        }
+
+        fn critical(&self) -> bool {
+            self.critical
+        }
+
+        async fn check(&self) -> Result<Option<String>, ProbeFailure> {
+            tokio::time::sleep(self.delay).await;
+            self.outcome.clone()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_all_probes_up() {
+        let health = HealthChecker::new(vec![MockProbe::up("database"), MockProbe::up("coingecko")]).check().await;
+        assert_eq!(health.status, ProbeStatus::Up);
+        assert_eq!(health.probes.len(), 2);
+        assert!(health.probes.iter().all(|probe| probe.last_error.is_none()));
+    }
+
+    #[tokio::test]
+    async fn test_non_critical_failure_degrades() {
+        let health = HealthChecker::new(vec![
+            MockProbe::up("database"),
+            MockProbe::failing("exa", false, ProbeFailure::Down("Exa rejected the API key".to_string())),
+        ])
+        .check()
+        .await;
+        assert_eq!(health.status, ProbeStatus::Degraded);
+        assert_eq!(health.probes[1].status, ProbeStatus::Down);
+        assert_eq!(health.probes[1].last_error.as_deref(), Some("Exa rejected the API key"));
+    }
+
+    #[tokio::test]
+    async fn test_critical_failure_is_down() {
+        let health = HealthChecker::new(vec![
+            MockProbe::failing("database", true, ProbeFailure::Down("connection refused".to_string())),
+            MockProbe::up("coingecko"),
+        ])
+        .check()
+        .await;
+        assert_eq!(health.status, ProbeStatus::Down);
+
+        // A degraded critical probe only degrades the whole
+        let health = HealthChecker::new(vec![
+            MockProbe::failing("anthropic", true, ProbeFailure::Degraded("rate limited".to_string())),
+        ])
+        .check()
+        .await;
+        assert_eq!(health.status, ProbeStatus::Degraded);
+    }
+
+    #[tokio::test]
+    async fn test_timeouts_and_slow_probes() {
+        let health = HealthChecker::new(vec![
+            MockProbe::slow("rpc", Duration::from_millis(500)),
+            MockProbe::slow("coingecko", Duration::from_millis(60)),
+            MockProbe::up("database"),
+        ])
+        .with_timeout(Duration::from_millis(200))
+        .with_slow_after(Duration::from_millis(40))
+        .check()
+        .await;
+
+        assert_eq!(health.probes[0].status, ProbeStatus::Down);
+        assert!(health.probes[0].last_error.as_deref().unwrap().contains("timed out"));
+        assert_eq!(health.probes[1].status, ProbeStatus::Degraded);
+        assert!(health.probes[1].last_error.as_deref().unwrap().starts_with("slow response"));
+        assert_eq!(health.probes[2].status, ProbeStatus::Up);
+    }
+
+    #[tokio::test]
+    async fn test_probes_run_concurrently() {
+        let probes = (0..4).map(|_| MockProbe::slow("slow", Duration::from_millis(100))).collect();
+        let started = Instant::now();
+        let health = HealthChecker::new(probes).with_slow_after(Duration::from_secs(1)).check().await;
+        assert_eq!(health.status, ProbeStatus::Up);
+        assert!(started.elapsed() < Duration::from_millis(300), "took {:?}", started.elapsed());
+    }
+
+    #[tokio::test]
+    async fn test_breakers_are_reported_with_the_probes() {
+        let breaker = Arc::new(CircuitBreaker::new("exa", circuit_breaker::BreakerSettings {
+            failure_threshold: 1,
+            cool_down: Duration::from_secs(60),
+        }));
+        breaker.try_call().unwrap().failed();
+
+        let health = HealthChecker::new(vec![MockProbe::up("database")]).with_breakers(vec![breaker]).check().await;
+        assert_eq!(health.breakers.len(), 1);
+        assert_eq!(health.breakers[0].state, BreakerState::Open);
+        assert_eq!(health.breakers[0].trips, 1);
+    }
+
+    #[test]
+    fn test_check_status() {
+        assert!(check_status("Exa", StatusCode::OK).is_ok());
+        assert!(matches!(check_status("Exa", StatusCode::UNAUTHORIZED), Err(ProbeFailure::Down(_))));
+        assert!(matches!(check_status("Exa", StatusCode::TOO_MANY_REQUESTS), Err(ProbeFailure::Degraded(_))));
+        assert!(matches!(check_status("Exa", StatusCode::BAD_GATEWAY), Err(ProbeFailure::Degraded(_))));
+        assert!(matches!(check_status("Exa", StatusCode::NOT_FOUND), Err(ProbeFailure::Down(_))));
+    }
+
+    #[test]
+    fn test_render_and_summary() {
+        let probes = vec![
+            ProbeResult {
+                name: "database".to_string(),
+                status: ProbeStatus::Up,
+                critical: true,
+                latency_ms: 3,
+                detail: None,
+                last_error: None,
+            },
+            ProbeResult {
+                name: "rpc".to_string(),
+                status: ProbeStatus::Degraded,
+                critical: false,
+                latency_ms: 2100,
+                detail: Some("chain id 84532".to_string()),
+                last_error: Some("slow response (2100 ms)".to_string()),
+            },
+        ];
+        let breakers = vec![
+            BreakerSnapshot {
+                name: "coingecko".to_string(),
+                state: BreakerState::Closed,
+                consecutive_failures: 0,
+                trips: 0,
+                short_circuited: 0,
+                retry_in_secs: None,
+            },
+            BreakerSnapshot {
+                name: "exa".to_string(),
+                state: BreakerState::Open,
+                consecutive_failures: 5,
+                trips: 1,
+                short_circuited: 12,
+                retry_in_secs: Some(20),
+            },
+        ];
+        let health = SystemHealth { status: aggregate_status(&probes), checked_at: Utc::now(), probes, breakers };
+
+        assert_eq!(summary_line(&health), "degraded: database up, rpc degraded");
+        let rendered = render_health(&health);
+        assert!(rendered.starts_with("System health: degraded"));
+        assert!(rendered.contains("- database: up (3 ms)"));
+        assert!(rendered.contains("- rpc: degraded (2100 ms), chain id 84532 - slow response (2100 ms)"));
+        assert!(rendered.contains("- coingecko calls: going through\n"));
+        assert!(rendered.ends_with("- exa calls: paused after 5 failures in a row, trying again in 20s (12 skipped)"));
+
+        let json = serde_json::to_value(&health).unwrap();
+        assert_eq!(json["status"], "degraded");
+        assert_eq!(json["probes"][1]["latency_ms"], 2100);
+        assert_eq!(json["breakers"][1]["state"], "open");
+    }
+
+    #[test]
+    fn test_is_configured() {
+        assert!(is_configured("sk-ant-123"));
+        assert!(!is_configured("mock_exa_api_key_for_development"));
+        assert!(!is_configured(""));
+    }
+}
@@ -0,0 +1,348 @@
+use chrono::NaiveDate;
+use regex::Regex;
+use std::sync::OnceLock;
+use thiserror::Error;
+
+/// Deposit the value comparison is shown for when the user doesn't name one
+pub const DEFAULT_DEPOSIT_USD: f64 = 1000.0;
+
+/// Quote assets whose price is taken as $1
+const STABLECOINS: &[&str] = &["usd", "usdc", "usdt", "dai", "busd", "tusd", "frax", "lusd", "gusd", "pyusd", "usdbc"];
+
+/// Impermanent loss calculator error types
+#[derive(Debug, Error)]
+pub enum IlError {
+    #[error("Price ratio must be a positive number, got {0}")]
+    InvalidRatio(f64),
+
+    #[error("Deposit must be a positive amount, got {0}")]
+    InvalidDeposit(f64),
+}
+
+/// How the price of the first asset of the pair moves against the second
+#[derive(Debug, Clone, PartialEq)]
+pub enum Scenario {
+    /// Multiplied by a factor ("doubles", "3x", "down 40%")
+    Change(f64),
+    /// Moves to a price quoted in the second asset ("goes to $4000")
+    TargetPrice(f64),
+    /// Today's price compared with the entry price
+    Live,
+}
+
+/// An impermanent loss question parsed from a chat message
+#[derive(Debug, Clone, PartialEq)]
+pub struct IlQuery {
+    /// First asset of the pair, as written by the user
+    pub base: String,
+    /// Second asset of the pair, the unit prices are quoted in
+    pub quote: String,
+    pub scenario: Scenario,
+    /// Entry price of the first asset in the second ("entered at $2000")
+    pub entry_price: Option<f64>,
+    pub deposit_usd: Option<f64>,
+}
+
+/// Value of a deposit held in the pool versus held outright
+#[derive(Debug, Clone, PartialEq)]
+pub struct IlOutcome {
+    /// Exit price divided by entry price
+    pub ratio: f64,
+    /// LP value relative to holding, minus one: -0.0572 is a 5.72% loss
+    pub impermanent_loss: f64,
+    pub deposit: f64,
+    pub hold_value: f64,
+    pub lp_value: f64,
+}
+
+/// Impermanent loss of a 50/50 constant-product pool after the price ratio moved by `ratio`
+pub fn impermanent_loss(ratio: f64) -> Result<f64, IlError> {
+    if !ratio.is_finite() || ratio <= 0.0 {
+        return Err(IlError::InvalidRatio(ratio));
+    }
+
+    Ok(2.0 * ratio.sqrt() / (1.0 + ratio) - 1.0)
+}
+
+/// Compare a deposit split 50/50 into the pool with the same two halves held outright,
+/// valued in the second asset at exit
+pub fn calculate(ratio: f64, deposit: f64) -> Result<IlOutcome, IlError> {
+    if !deposit.is_finite() || deposit <= 0.0 {
+        return Err(IlError::InvalidDeposit(deposit));
+    }
+    let impermanent_loss = impermanent_loss(ratio)?;
+
+    Ok(IlOutcome {
+        ratio,
+        impermanent_loss,
+        deposit,
+        hold_value: deposit / 2.0 * (1.0 + ratio),
+        lp_value: deposit * ratio.sqrt(),
+    })
+}
+
+/// Whether an asset is treated as worth $1
+pub fn is_stablecoin(symbol: &str) -> bool {
+    STABLECOINS.contains(&symbol.to_lowercase().as_str())
+}
+
+fn mentions_il_regex() -> &'static Regex {
+    static REGEX: OnceLock<Regex> = OnceLock::new();
+    REGEX.get_or_init(|| Regex::new(r"(?i)impermanent\s+loss|\bil\b").unwrap())
+}
+
+fn pair_regex() -> &'static Regex {
+    static REGEX: OnceLock<Regex> = OnceLock::new();
+    REGEX.get_or_init(|| Regex::new(r"(?i)\b([a-z][a-z0-9]{1,9})\s*[/-]\s*([a-z][a-z0-9]{1,9})\b").unwrap())
+}
+
+fn multiple_regex() -> &'static Regex {
+    static REGEX: OnceLock<Regex> = OnceLock::new();
+    REGEX.get_or_init(|| Regex::new(r"(?i)\b(\d+(?:\.\d+)?)\s*x\b").unwrap())
+}
+
+fn percent_regex() -> &'static Regex {
+    static REGEX: OnceLock<Regex> = OnceLock::new();
+    REGEX.get_or_init(|| {
+        Regex::new(r"(?i)\b(up|rises?|pumps?|gains?|increases?|down|drops?|falls?|dumps?|decreases?|loses)\s+(?:by\s+)?(\d+(?:\.\d+)?)\s*%").unwrap()
+    })
+}
+
+fn target_regex() -> &'static Regex {
+    static REGEX: OnceLock<Regex> = OnceLock::new();
+    REGEX.get_or_init(|| {
+        Regex::new(r"(?i)\b(?:goes|moves|rises|drops|falls|gets)\s+to\s+\$?(\d[\d,]*(?:\.\d+)?)(k?)\b|\b(?:hits|reaches)\s+\$?(\d[\d,]*(?:\.\d+)?)(k?)\b").unwrap()
+    })
+}
+
+fn entry_regex() -> &'static Regex {
+    static REGEX: OnceLock<Regex> = OnceLock::new();
+    REGEX.get_or_init(|| {
+        Regex::new(r"(?i)\b(?:entered|entry(?:\s+price)?|bought)(?:\s+(?:of|at|is|was))?\s+\$?(\d[\d,]*(?:\.\d+)?)(k?)\b").unwrap()
+    })
+}
+
+fn deposit_regex() -> &'static Regex {
+    static REGEX: OnceLock<Regex> = OnceLock::new();
+    REGEX.get_or_init(|| {
+        Regex::new(r"(?i)\b(?:with|deposit(?:ing|ed)?|put(?:ting)?(?:\s+in)?|provid(?:e|ing))\s+\$(\d[\d,]*(?:\.\d+)?)(k?)\b").unwrap()
+    })
+}
+
+/// Parse "1,500", "2.5" or "10" with an optional "k" suffix
+fn parse_amount(digits: &str, suffix: &str) -> Option<f64> {
+    let value: f64 = digits.replace(',', "").parse().ok()?;
+    Some(if suffix.eq_ignore_ascii_case("k") { value * 1000.0 } else { value })
+}
+
+fn parse_scenario(message: &str) -> Scenario {
+    let lower = message.to_lowercase();
+
+    if let Some(captures) = target_regex().captures(message) {
+        let (digits, suffix) = match captures.get(1) {
+            Some(digits) => (digits.as_str(), captures.get(2).map_or("", |m| m.as_str())),
+            None => (&captures[3], captures.get(4).map_or("", |m| m.as_str())),
+        };
+        if let Some(target) = parse_amount(digits, suffix) {
+            return Scenario::TargetPrice(target);
+        }
+    }
+
+    if let Some(captures) = percent_regex().captures(message) {
+        let percent: f64 = captures[2].parse().unwrap_or(0.0) / 100.0;
+        let direction = captures[1].to_lowercase();
+        let falls = ["down", "drop", "fall", "dump", "decrease", "lose"].iter().any(|word| direction.starts_with(word));
+        return Scenario::Change(if falls { 1.0 - percent } else { 1.0 + percent });
+    }
+
+    if let Some(captures) = multiple_regex().captures(message)
+        && let Ok(factor) = captures[1].parse()
+    {
+        return Scenario::Change(factor);
+    }
+
+    let words = [("doubles", 2.0), ("triples", 3.0), ("quadruples", 4.0), ("halves", 0.5)];
+    for (word, factor) in words {
+        if lower.contains(word) {
+            return Scenario::Change(factor);
+        }
+    }
+
+    Scenario::Live
+}
+
+/// Parse an impermanent loss question like "IL on ETH/USDC if ETH doubles with $10k"
+///
+/// Needs both a mention of impermanent loss and a pair; without a price scenario the
+/// question is about the live price since entry
+pub fn parse_il_query(message: &str) -> Option<IlQuery> {
+    if !mentions_il_regex().is_match(message) {
+        return None;
+    }
+    let pair = pair_regex().captures(message)?;
+
+    let entry_price = entry_regex()
+        .captures(message)
+        .and_then(|captures| parse_amount(&captures[1], &captures[2]));
+    let deposit_usd = deposit_regex()
+        .captures(message)
+        .and_then(|captures| parse_amount(&captures[1], &captures[2]));
+
+    Some(IlQuery {
+        base: pair[1].to_uppercase(),
+        quote: pair[2].to_uppercase(),
+        scenario: parse_scenario(message),
+        entry_price,
+        deposit_usd,
+    })
+}
+
+/// Render the calculation with the assumptions behind the formula
+///
+/// `prices` are the entry and exit price of the first asset in the second, when known;
+/// `entry_date` is set when the entry price came from the user's portfolio history
+pub fn render_calculation(query: &IlQuery, outcome: &IlOutcome, prices: Option<(f64, f64)>, entry_date: Option<NaiveDate>) -> String {
+    let stable_quote = is_stablecoin(&query.quote);
+    let unit = if stable_quote { "$" } else { "" };
+    let suffix = if stable_quote { String::new() } else { format!(" {}", query.quote) };
+
+    let mut output = format!("Impermanent loss for a {}/{} pool\n\n", query.base, query.quote);
+
+    output.push_str(&format!("Price change: {} moves {:.2}x against {}", query.base, outcome.ratio, query.quote));
+    if let Some((entry, exit)) = prices {
+        output.push_str(&format!(" ({}{:.2}{} -> {}{:.2}{})", unit, entry, suffix, unit, exit, suffix));
+    }
+    output.push('\n');
+    if let Some(date) = entry_date {
+        output.push_str(&format!("Entry price taken from when {} was added to your portfolio ({})\n", query.base, date));
+    }
+    output.push_str(&format!("Impermanent loss: {:.2}%\n\n", -outcome.impermanent_loss * 100.0));
+
+    let difference = outcome.lp_value - outcome.hold_value;
+    output.push_str(&format!(
+        "For ${:.2} deposited:\n\
+        - Holding both assets: ${:.2}\n\
+        - Providing liquidity: ${:.2}\n\
+        - Difference: -${:.2}\n\n",
+        outcome.deposit,
+        outcome.hold_value,
+        outcome.lp_value,
+        difference.abs()
+    ));
+
+    output.push_str(
+        "Formula: IL = 2 * sqrt(r) / (1 + r) - 1, where r is the exit price divided by the entry price.\n\
+        Assumptions: a 50/50 constant-product pool (x * y = k, like Uniswap v2) with full-range liquidity; \
+        trading fees and rewards are not counted, and they are what can offset the loss.",
+    );
+    if stable_quote {
+        output.push_str(&format!(" {} is taken as worth exactly $1.", query.quote));
+    } else {
+        output.push_str(&format!(" Values are in today's dollars assuming {}'s own price doesn't change.", query.quote));
+    }
+
+    output
+}
+
+/// Reply for a live question when neither the message nor the portfolio gives an entry price
+pub fn missing_entry_reply(query: &IlQuery) -> String {
+    format!(
+        "I need your entry price to compute impermanent loss on {}/{} so far. \
+        Tell me where you entered (e.g. \"IL on {}/{} entered at $2000\") or ask about a scenario like \"if {} doubles\".",
+        query.base, query.quote, query.base, query.quote, query.base
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn close(a: f64, b: f64) -> bool {
+        (a - b).abs() < 1e-6
+    }
+
+    #[test]
+    fn test_impermanent_loss_known_values() {
+        assert!(close(impermanent_loss(1.0).unwrap(), 0.0));
+        assert!(close(impermanent_loss(2.0).unwrap(), -0.057191));
+        assert!(close(impermanent_loss(4.0).unwrap(), -0.2));
+        assert!(close(impermanent_loss(5.0).unwrap(), -0.254644));
+        assert!(close(impermanent_loss(1.25).unwrap(), -0.006192));
+    }
+
+    #[test]
+    fn test_impermanent_loss_is_symmetric() {
+        assert!(close(impermanent_loss(0.5).unwrap(), impermanent_loss(2.0).unwrap()));
+        assert!(close(impermanent_loss(0.25).unwrap(), -0.2));
+    }
+
+    #[test]
+    fn test_rejects_invalid_ratios() {
+        assert!(matches!(impermanent_loss(0.0), Err(IlError::InvalidRatio(_))));
+        assert!(matches!(impermanent_loss(-2.0), Err(IlError::InvalidRatio(_))));
+        assert!(matches!(impermanent_loss(f64::INFINITY), Err(IlError::InvalidRatio(_))));
+        assert!(matches!(impermanent_loss(f64::NAN), Err(IlError::InvalidRatio(_))));
+        assert!(matches!(calculate(2.0, 0.0), Err(IlError::InvalidDeposit(_))));
+    }
+
+    #[test]
+    fn test_value_comparison() {
+        let outcome = calculate(4.0, 10_000.0).unwrap();
+
+        assert!(close(outcome.hold_value, 25_000.0));
+        assert!(close(outcome.lp_value, 20_000.0));
+        assert!(close(outcome.lp_value / outcome.hold_value - 1.0, outcome.impermanent_loss));
+    }
+
+    #[test]
+    fn test_parse_change_scenarios() {
+        let query = parse_il_query("What's my IL on ETH/USDC if ETH doubles?").unwrap();
+        assert_eq!(query.base, "ETH");
+        assert_eq!(query.quote, "USDC");
+        assert_eq!(query.scenario, Scenario::Change(2.0));
+
+        let query = parse_il_query("impermanent loss for sol-usdt at 3x").unwrap();
+        assert_eq!(query.scenario, Scenario::Change(3.0));
+
+        let query = parse_il_query("IL on ETH/BTC if ETH drops 40%").unwrap();
+        assert!(matches!(query.scenario, Scenario::Change(factor) if close(factor, 0.6)));
+
+        let query = parse_il_query("IL on ETH/BTC if ETH is up 50%").unwrap();
+        assert!(matches!(query.scenario, Scenario::Change(factor) if close(factor, 1.5)));
+    }
+
+    #[test]
+    fn test_parse_prices_and_deposit() {
+        let query = parse_il_query("IL for ETH/USDC entered at $2,000 if it goes to $3k with $10k").unwrap();
+
+        assert_eq!(query.entry_price, Some(2000.0));
+        assert_eq!(query.scenario, Scenario::TargetPrice(3000.0));
+        assert_eq!(query.deposit_usd, Some(10_000.0));
+    }
+
+    #[test]
+    fn test_parse_live_and_non_matches() {
+        let query = parse_il_query("what's my impermanent loss on ETH/USDC so far?").unwrap();
+        assert_eq!(query.scenario, Scenario::Live);
+        assert_eq!(query.entry_price, None);
+
+        assert_eq!(parse_il_query("What's the price of ETH/USDC?"), None);
+        assert_eq!(parse_il_query("Explain impermanent loss"), None);
+    }
+
+    #[test]
+    fn test_render_notes_assumptions() {
+        let query = parse_il_query("IL on ETH/USDC if ETH doubles with $10k").unwrap();
+        let outcome = calculate(2.0, 10_000.0).unwrap();
+        let output = render_calculation(&query, &outcome, Some((2000.0, 4000.0)), None);
+
+        assert!(output.contains("Impermanent loss: 5.72%"));
+        assert!(output.contains("($2000.00 -> $4000.00)"));
+        assert!(output.contains("Holding both assets: $15000.00"));
+        assert!(output.contains("Providing liquidity: $14142.14"));
+        assert!(output.contains("Difference: -$857.86"));
+        assert!(output.contains("constant-product"));
+        assert!(output.contains("fees"));
+        assert!(output.contains("USDC is taken as worth exactly $1"));
+    }
+}
@@ -0,0 +1,180 @@
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+/// Period of the short-term exponential moving average, in daily closes
+pub const SHORT_EMA_DAYS: usize = 20;
+
+/// Period of the long-term exponential moving average, in daily closes
+pub const LONG_EMA_DAYS: usize = 50;
+
+/// Days of daily closes fetched for the averages, enough for the long one to settle past its seed
+pub const HISTORY_DAYS: u32 = 120;
+
+/// How long computed averages are reused before the history is fetched again
+pub const TREND_CACHE_TTL: Duration = Duration::from_secs(4 * 60 * 60);
+
+/// Exponential moving average of `closes` (oldest first) over `period` closes
+///
+/// Seeded with the simple average of the first `period` closes, then smoothed by 2 / (period + 1)
+/// per close. None when there are fewer closes than the period.
+pub fn ema(closes: &[f64], period: usize) -> Option<f64> {
+    if period == 0 || closes.len() < period {
+        return None;
+    }
+    let alpha = 2.0 / (period as f64 + 1.0);
+    let seed = closes[..period].iter().sum::<f64>() / period as f64;
+    Some(closes[period..].iter().fold(seed, |average, close| average + alpha * (close - average)))
+}
+
+/// Short-term direction of the price relative to its averages
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TrendDirection {
+    /// Above the short average, which is above the long one
+    Up,
+    /// Below the short average, which is below the long one
+    Down,
+    /// The price and the averages disagree
+    Sideways,
+}
+
+impl TrendDirection {
+    pub fn label(&self) -> &'static str {
+        match self {
+            TrendDirection::Up => "up",
+            TrendDirection::Down => "down",
+            TrendDirection::Sideways => "sideways",
+        }
+    }
+}
+
+/// The 20-day and 50-day EMAs of a coin's daily closes
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Trend {
+    pub short_ema: f64,
+    pub long_ema: f64,
+}
+
+impl Trend {
+    /// Averages of `closes` (oldest first), None when the history is shorter than the long period
+    pub fn from_closes(closes: &[f64]) -> Option<Self> {
+        Some(Self { short_ema: ema(closes, SHORT_EMA_DAYS)?, long_ema: ema(closes, LONG_EMA_DAYS)? })
+    }
+
+    pub fn direction(&self, price: f64) -> TrendDirection {
+        if price > self.short_ema && self.short_ema > self.long_ema {
+            TrendDirection::Up
+        } else if price < self.short_ema && self.short_ema < self.long_ema {
+            TrendDirection::Down
+        } else {
+            TrendDirection::Sideways
+        }
+    }
+
+    /// One line placing `price` against the long average, e.g.
+    /// "Trend: trading 4.0% above the 50-day EMA, short-term trend up"
+    pub fn summary(&self, price: f64) -> String {
+        let distance = (price - self.long_ema) / self.long_ema * 100.0;
+        let position = if distance.abs() < 0.05 {
+            format!("trading at the {}-day EMA", LONG_EMA_DAYS)
+        } else {
+            format!(
+                "trading {:.1}% {} the {}-day EMA",
+                distance.abs(),
+                if distance > 0.0 { "above" } else { "below" },
+                LONG_EMA_DAYS
+            )
+        };
+        format!("Trend: {}, short-term trend {}", position, self.direction(price).label())
+    }
+}
+
+/// Averages per coin, reused for `TREND_CACHE_TTL` since daily closes change once a day
+#[derive(Debug, Default)]
+pub struct TrendCache {
+    trends: HashMap<String, (Trend, Instant)>,
+}
+
+impl TrendCache {
+    /// The cached averages of `coin_id`, if computed less than the TTL before `now`
+    pub fn get(&self, coin_id: &str, now: Instant) -> Option<Trend> {
+        self.trends
+            .get(coin_id)
+            .filter(|(_, computed_at)| now.saturating_duration_since(*computed_at) < TREND_CACHE_TTL)
+            .map(|(trend, _)| *trend)
+    }
+
+    pub fn insert(&mut self, coin_id: &str, trend: Trend, now: Instant) {
+        // Drop expired entries so the cache doesn't grow with every coin ever asked about
+        self.trends.retain(|_, (_, computed_at)| now.saturating_duration_since(*computed_at) < TREND_CACHE_TTL);
+        self.trends.insert(coin_id.to_string(), (trend, now));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(actual: f64, expected: f64) {
+        assert!((actual - expected).abs() < 1e-9, "{} != {}", actual, expected);
+    }
+
+    #[test]
+    fn test_ema_matches_hand_computed_values() {
+        // Seed (1 + 2 + 3) / 3 = 2, alpha 0.5: 3, 4, 5
+        assert_close(ema(&[1.0, 2.0, 3.0, 4.0, 5.0, 6.0], 3).unwrap(), 5.0);
+        // Seed (10 + 11) / 2 = 10.5, alpha 2/3: 11.5, 12.5, 17.5
+        assert_close(ema(&[10.0, 11.0, 12.0, 13.0, 20.0], 2).unwrap(), 17.5);
+        // Exactly one period is the simple average
+        assert_close(ema(&[4.0, 8.0], 2).unwrap(), 6.0);
+    }
+
+    #[test]
+    fn test_ema_needs_a_full_period() {
+        assert_eq!(ema(&[1.0, 2.0], 3), None);
+        assert_eq!(ema(&[1.0, 2.0], 0), None);
+        assert!(Trend::from_closes(&vec![100.0; LONG_EMA_DAYS - 1]).is_none());
+        assert!(Trend::from_closes(&vec![100.0; LONG_EMA_DAYS]).is_some());
+    }
+
+    #[test]
+    fn test_flat_history_averages_to_the_price() {
+        let trend = Trend::from_closes(&vec![100.0; 80]).unwrap();
+        assert_close(trend.short_ema, 100.0);
+        assert_close(trend.long_ema, 100.0);
+        assert_eq!(trend.summary(100.0), "Trend: trading at the 50-day EMA, short-term trend sideways");
+    }
+
+    #[test]
+    fn test_summary_places_the_price_against_the_averages() {
+        let trend = Trend { short_ema: 102.0, long_ema: 100.0 };
+        assert_eq!(trend.summary(104.0), "Trend: trading 4.0% above the 50-day EMA, short-term trend up");
+        assert_eq!(trend.summary(101.0), "Trend: trading 1.0% above the 50-day EMA, short-term trend sideways");
+
+        let trend = Trend { short_ema: 95.0, long_ema: 100.0 };
+        assert_eq!(trend.summary(90.0), "Trend: trading 10.0% below the 50-day EMA, short-term trend down");
+    }
+
+    #[test]
+    fn test_rising_history_trends_up() {
+        let closes: Vec<f64> = (1..=100).map(f64::from).collect();
+        let trend = Trend::from_closes(&closes).unwrap();
+        assert!(trend.short_ema > trend.long_ema);
+        assert_eq!(trend.direction(100.0), TrendDirection::Up);
+    }
+
+    #[test]
+    fn test_cache_expires_after_the_ttl() {
+        let mut cache = TrendCache::default();
+        let start = Instant::now();
+        let trend = Trend { short_ema: 2.0, long_ema: 1.0 };
+        cache.insert("bitcoin", trend, start);
+
+        assert_eq!(cache.get("bitcoin", start + Duration::from_secs(60)), Some(trend));
+        assert_eq!(cache.get("ethereum", start), None);
+        assert_eq!(cache.get("bitcoin", start + TREND_CACHE_TTL), None);
+
+        // A later insert drops the expired entries
+        cache.insert("ethereum", trend, start + TREND_CACHE_TTL);
+        assert_eq!(cache.trends.len(), 1);
+    }
+}
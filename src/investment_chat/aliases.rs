@@ -0,0 +1,264 @@
+use super::constants;
+use regex::Regex;
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::sync::OnceLock;
+
+/// Longest alias accepted, in characters
+pub const MAX_ALIAS_CHARS: usize = 40;
+
+/// An alias command typed in the chat
+#[derive(Debug, Clone, PartialEq)]
+pub enum AliasCommand {
+    /// "when I say X I mean Y"
+    Define { alias: String, target: String },
+    /// "forget the alias X"
+    Forget { alias: String },
+}
+
+/// An alias waiting for the user to confirm it
+#[derive(Debug, Clone, PartialEq)]
+pub struct PendingAlias {
+    pub alias: String,
+    pub target: String,
+    /// Message to answer again once the alias is saved
+    pub retry_message: Option<String>,
+}
+
+/// A user's own names for coins and projects, consulted before the built-in tables
+#[derive(Debug, Clone, Default)]
+pub struct AliasBook {
+    aliases: HashMap<String, String>,
+    // Matches any alias on word boundaries, longest first
+    pattern: Option<Regex>,
+}
+
+impl AliasBook {
+    pub fn new(pairs: impl IntoIterator<Item = (String, String)>) -> Self {
+        let mut book = Self {
+            aliases: pairs.into_iter().collect(),
+            pattern: None,
+        };
+        book.rebuild_pattern();
+        book
+    }
+
+    pub fn insert(&mut self, alias: &str, target: &str) {
+        self.aliases.insert(alias.to_lowercase(), target.to_lowercase());
+        self.rebuild_pattern();
+    }
+
+    /// Returns true if the alias existed
+    pub fn remove(&mut self, alias: &str) -> bool {
+        let removed = self.aliases.remove(&alias.to_lowercase()).is_some();
+        if removed {
+            self.rebuild_pattern();
+        }
+        removed
+    }
+
+    pub fn get(&self, alias: &str) -> Option<&str> {
+        self.aliases.get(&alias.to_lowercase()).map(String::as_str)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.aliases.is_empty()
+    }
+
+    /// Resolve a name to a CoinGecko id: the user's aliases first, then the built-in table
+    /// Unknown names are returned lowercased
+    pub fn resolve_coin_id(&self, name: &str) -> String {
+        let name = self.get(name).map(str::to_string).unwrap_or_else(|| name.to_lowercase());
+        match constants::known_coin_id(&name) {
+            Some(id) => id.to_string(),
+            None => name,
+        }
+    }
+
+    /// Target of the longest alias mentioned in a message
+    pub fn find_in(&self, message: &str) -> Option<&str> {
+        let pattern = self.pattern.as_ref()?;
+        let found = pattern.find(message)?;
+        self.get(found.as_str())
+    }
+
+    /// Targets of every alias mentioned in a message, with the byte offset of each mention
+    pub fn find_all_in(&self, message: &str) -> Vec<(usize, &str)> {
+        let Some(pattern) = &self.pattern else {
+            return Vec::new();
+        };
+        pattern
+            .find_iter(message)
+            .filter_map(|found| Some((found.start(), self.get(found.as_str())?)))
+            .collect()
+    }
+
+    /// Replace every alias in a message with what it stands for
+    pub fn expand<'a>(&self, message: &'a str) -> Cow<'a, str> {
+        match &self.pattern {
+            Some(pattern) => pattern.replace_all(message, |caps: &regex::Captures| {
+                self.get(&caps[0]).unwrap_or(&caps[0]).to_string()
+            }),
+            None => Cow::Borrowed(message),
+        }
+    }
+
+    fn rebuild_pattern(&mut self) {
+        if self.aliases.is_empty() {
+            self.pattern = None;
+            return;
+        }
+
+        let mut aliases: Vec<&String> = self.aliases.keys().collect();
+        aliases.sort_by(|a, b| b.len().cmp(&a.len()).then_with(|| a.cmp(b)));
+        let alternatives: Vec<String> = aliases.iter().map(|alias| regex::escape(alias)).collect();
+        self.pattern = Regex::new(&format!(r"(?i)\b(?:{})\b", alternatives.join("|"))).ok();
+    }
+}
+
+/// Parse "when I say X I mean Y" and "forget the alias X"
+pub fn parse_alias_command(message: &str) -> Option<AliasCommand> {
+    static DEFINE: OnceLock<Regex> = OnceLock::new();
+    static FORGET: OnceLock<Regex> = OnceLock::new();
+    let define = DEFINE.get_or_init(|| {
+        Regex::new(r#"(?i)^\s*when i say\s+["']?(.+?)["']?,?\s+i mean\s+["']?(.+?)["']?\s*[.!]?\s*$"#).unwrap()
+    });
+    let forget = FORGET.get_or_init(|| {
+        Regex::new(r#"(?i)^\s*forget (?:the |my )?alias\s+["']?(.+?)["']?\s*[.!]?\s*$"#).unwrap()
+    });
+
+    if let Some(caps) = define.captures(message) {
+        return Some(AliasCommand::Define {
+            alias: normalize(&caps[1]),
+            target: normalize(&caps[2]),
+        });
+    }
+
+    forget.captures(message).map(|caps| AliasCommand::Forget { alias: normalize(&caps[1]) })
+}
+
+/// Check that an alias can be matched on word boundaries
+pub fn validate_alias(alias: &str, target: &str) -> Result<(), String> {
+    let starts_and_ends_alphanumeric = alias.chars().next().is_some_and(char::is_alphanumeric)
+        && alias.chars().last().is_some_and(char::is_alphanumeric);
+    if !starts_and_ends_alphanumeric {
+        return Err("An alias has to start and end with a letter or digit.".to_string());
+    }
+    if alias.chars().count() > MAX_ALIAS_CHARS {
+        return Err(format!("An alias can be at most {} characters long.", MAX_ALIAS_CHARS));
+    }
+    if target.is_empty() {
+        return Err("Tell me what the alias stands for.".to_string());
+    }
+    if alias == target {
+        return Err(format!("\"{}\" already means itself.", alias));
+    }
+    Ok(())
+}
+
+/// The coin an alias would hide, when it is the ticker or name of a different coin
+pub fn shadowed_coin(alias: &str, target: &str) -> Option<&'static str> {
+    let shadowed = constants::known_coin_id(alias)?;
+    let target_id = constants::known_coin_id(target).unwrap_or(target);
+    (shadowed != target_id).then_some(shadowed)
+}
+
+/// Whether a reply confirms a pending question
+pub fn is_affirmative(message: &str) -> bool {
+    let reply = message.trim().trim_end_matches(['.', '!']).to_lowercase();
+    matches!(reply.as_str(), "yes" | "y" | "yep" | "yeah" | "sure" | "ok" | "okay" | "confirm" | "yes please")
+}
+
+fn normalize(value: &str) -> String {
+    value.trim().trim_matches(['"', '\'']).split_whitespace().collect::<Vec<_>>().join(" ").to_lowercase()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn book() -> AliasBook {
+        AliasBook::new([
+            ("big coin".to_string(), "btc".to_string()),
+            ("big".to_string(), "bigtime".to_string()),
+            ("aero".to_string(), "polygon".to_string()),
+            ("zk".to_string(), "zksync".to_string()),
+        ])
+    }
+
+    #[test]
+    fn test_parse_alias_commands() {
+        assert_eq!(
+            parse_alias_command("When I say \"Big Coin\" I mean BTC."),
+            Some(AliasCommand::Define { alias: "big coin".to_string(), target: "btc".to_string() })
+        );
+        assert_eq!(
+            parse_alias_command("when i say matic, i mean polygon"),
+            Some(AliasCommand::Define { alias: "matic".to_string(), target: "polygon".to_string() })
+        );
+        assert_eq!(
+            parse_alias_command("forget the alias big coin"),
+            Some(AliasCommand::Forget { alias: "big coin".to_string() })
+        );
+        assert_eq!(parse_alias_command("what does bitcoin mean when I say it?"), None);
+    }
+
+    #[test]
+    fn test_user_aliases_take_precedence_over_builtin_table() {
+        let book = book();
+        assert_eq!(book.resolve_coin_id("Big Coin"), "bitcoin");
+        // "aero" is Aerodrome in the built-in table, the user's alias wins
+        assert_eq!(book.resolve_coin_id("aero"), "matic-network");
+        // Unknown targets are returned as-is
+        assert_eq!(book.resolve_coin_id("zk"), "zksync");
+        // Names without an alias fall through to the built-in table
+        assert_eq!(book.resolve_coin_id("ETH"), "ethereum");
+        assert_eq!(book.resolve_coin_id("pepe"), "pepe");
+        assert_eq!(AliasBook::default().resolve_coin_id("aero"), "aerodrome-finance");
+    }
+
+    #[test]
+    fn test_find_and_expand_prefer_longest_alias_on_word_boundaries() {
+        let book = book();
+        assert_eq!(book.find_in("Should I buy more Big Coin?"), Some("btc"));
+        assert_eq!(book.find_in("is big a good project"), Some("bigtime"));
+        assert_eq!(book.find_in("tell me about zkevm"), None);
+        assert_eq!(book.find_all_in("big coin or zk?"), vec![(0, "btc"), (12, "zksync")]);
+        assert!(AliasBook::default().find_all_in("big coin").is_empty());
+        assert_eq!(book.expand("price of big coin and zk"), "price of btc and zksync");
+        assert!(matches!(AliasBook::default().expand("price of btc"), Cow::Borrowed(_)));
+    }
+
+    #[test]
+    fn test_remove_alias() {
+        let mut book = book();
+        assert!(book.remove("BIG COIN"));
+        assert!(!book.remove("big coin"));
+        assert_eq!(book.find_in("big coin"), Some("bigtime"));
+    }
+
+    #[test]
+    fn test_shadowing_real_tickers() {
+        assert_eq!(shadowed_coin("eth", "bitcoin"), Some("ethereum"));
+        assert_eq!(shadowed_coin("sol", "pepe"), Some("solana"));
+        // Same coin under another name is fine
+        assert_eq!(shadowed_coin("matic", "polygon"), None);
+        assert_eq!(shadowed_coin("big coin", "btc"), None);
+    }
+
+    #[test]
+    fn test_validate_alias() {
+        assert!(validate_alias("big coin", "btc").is_ok());
+        assert!(validate_alias("$btc", "bitcoin").is_err());
+        assert!(validate_alias("btc", "btc").is_err());
+        assert!(validate_alias(&"x".repeat(MAX_ALIAS_CHARS + 1), "btc").is_err());
+    }
+
+    #[test]
+    fn test_is_affirmative() {
+        assert!(is_affirmative(" Yes! "));
+        assert!(is_affirmative("confirm"));
+        assert!(!is_affirmative("no"));
+        assert!(!is_affirmative("yes but what about eth"));
+    }
+}
@@ -0,0 +1,601 @@
+use regex::Regex;
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::OnceLock;
+
+/// Decimal places shown for results that aren't dollar amounts
+const MAX_PLACES: u32 = 8;
+
+/// Most decimal digits kept from a typed number or a quote
+const MAX_INPUT_PLACES: usize = 12;
+
+/// An exact decimal value kept as a fraction, so 0.1 + 0.2 is 0.3 and 1/3 stays a third
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Exact {
+    num: i128,
+    den: i128,
+}
+
+fn gcd(mut a: i128, mut b: i128) -> i128 {
+    while b != 0 {
+        (a, b) = (b, a % b);
+    }
+    a.abs()
+}
+
+impl Exact {
+    pub fn integer(value: i128) -> Self {
+        Self { num: value, den: 1 }
+    }
+
+    fn new(num: i128, den: i128) -> Option<Self> {
+        if den == 0 {
+            return None;
+        }
+        let divisor = gcd(num, den).max(1);
+        let sign = if den < 0 { -1 } else { 1 };
+        Some(Self { num: sign * num / divisor, den: sign * den / divisor })
+    }
+
+    /// Parse "12,000", "0.035" or ".5"; commas must group thousands
+    pub fn parse(text: &str) -> Option<Self> {
+        let text = text.replace(',', "");
+        let (whole, fraction) = text.split_once('.').unwrap_or((&text, ""));
+        if (whole.is_empty() && fraction.is_empty()) || fraction.len() > MAX_INPUT_PLACES {
+            return None;
+        }
+        if !whole.chars().chain(fraction.chars()).all(|c| c.is_ascii_digit()) {
+            return None;
+        }
+        let num: i128 = format!("{}{}", whole, fraction).parse().ok()?;
+        Self::new(num, 10i128.checked_pow(fraction.len() as u32)?)
+    }
+
+    /// A quote from an API as it prints, so 67500.12 is exactly 67500.12
+    pub fn from_f64(value: f64) -> Option<Self> {
+        if !value.is_finite() {
+            return None;
+        }
+        let exact = Self::parse(&value.abs().to_string()).or_else(|| Self::parse(&format!("{:.*}", MAX_INPUT_PLACES, value.abs())))?;
+        Some(if value < 0.0 { exact.neg() } else { exact })
+    }
+
+    pub fn to_f64(self) -> f64 {
+        self.num as f64 / self.den as f64
+    }
+
+    pub fn neg(self) -> Self {
+        Self { num: -self.num, den: self.den }
+    }
+
+    pub fn add(self, other: Self) -> Option<Self> {
+        let num = self.num.checked_mul(other.den)?.checked_add(other.num.checked_mul(self.den)?)?;
+        Self::new(num, self.den.checked_mul(other.den)?)
+    }
+
+    pub fn sub(self, other: Self) -> Option<Self> {
+        self.add(other.neg())
+    }
+
+    pub fn mul(self, other: Self) -> Option<Self> {
+        // Cross-reducing first keeps chained products of decimals in range
+        let a = gcd(self.num, other.den).max(1);
+        let b = gcd(other.num, self.den).max(1);
+        let num = (self.num / a).checked_mul(other.num / b)?;
+        Self::new(num, (self.den / b).checked_mul(other.den / a)?)
+    }
+
+    /// None when dividing by zero
+    pub fn div(self, other: Self) -> Option<Self> {
+        if other.num == 0 {
+            return None;
+        }
+        self.mul(Self { num: other.den, den: other.num })
+    }
+
+    fn percent(self) -> Option<Self> {
+        self.div(Self::integer(100))
+    }
+
+    /// The value rounded half away from zero to `places` decimals, and whether rounding changed it
+    fn rounded(self, places: u32) -> Option<(i128, bool)> {
+        let scaled = self.num.checked_mul(10i128.checked_pow(places)?)?;
+        let quotient = scaled / self.den;
+        let remainder = scaled % self.den;
+        let round_up = remainder.abs().checked_mul(2)? >= self.den;
+        let value = if round_up { quotient + scaled.signum() } else { quotient };
+        Some((value, remainder != 0))
+    }
+
+    /// "8,100.00" for `places` 2 with `fixed`, "0.33333333" or "420" otherwise
+    fn format(self, places: u32, fixed: bool) -> Option<(String, bool)> {
+        let (scaled, inexact) = self.rounded(places)?;
+        let unit = 10i128.pow(places);
+        let whole = (scaled / unit).unsigned_abs();
+        let mut fraction = format!("{:0width$}", (scaled % unit).unsigned_abs(), width = places as usize);
+        if !fixed {
+            fraction.truncate(fraction.trim_end_matches('0').len());
+        }
+
+        let digits = whole.to_string();
+        let mut grouped = String::new();
+        for (i, digit) in digits.chars().enumerate() {
+            if i > 0 && (digits.len() - i).is_multiple_of(3) {
+                grouped.push(',');
+            }
+            grouped.push(digit);
+        }
+        let sign = if scaled < 0 { "-" } else { "" };
+        let text = if fraction.is_empty() { format!("{}{}", sign, grouped) } else { format!("{}{}.{}", sign, grouped, fraction) };
+        Some((text, inexact))
+    }
+}
+
+/// Errors evaluating a calculation
+#[derive(Debug, Clone, PartialEq)]
+pub enum CalcError {
+    DivisionByZero,
+    /// The numbers got too large to keep exact
+    Overflow,
+    /// No quote for a coin in the calculation
+    MissingPrice(String),
+}
+
+impl fmt::Display for CalcError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CalcError::DivisionByZero => write!(f, "that divides by zero"),
+            CalcError::Overflow => write!(f, "the numbers are too large to calculate exactly"),
+            CalcError::MissingPrice(coin) => write!(f, "I couldn't get a current price for {}", coin),
+        }
+    }
+}
+
+/// A parsed calculation
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expr {
+    Number(Exact),
+    /// A quantity of a coin, valued at its live price
+    Coin { quantity: Exact, coin_id: String, symbol: String },
+    /// `x%` on its own, as a fraction of 100
+    Percent(Box<Expr>),
+    Neg(Box<Expr>),
+    Add(Box<Expr>, Box<Expr>),
+    Sub(Box<Expr>, Box<Expr>),
+    Mul(Box<Expr>, Box<Expr>),
+    Div(Box<Expr>, Box<Expr>),
+}
+
+/// A calculation asked for in a message
+#[derive(Debug, Clone, PartialEq)]
+pub struct Calculation {
+    /// The expression as the user wrote it
+    pub text: String,
+    pub expr: Expr,
+    /// Dollar amounts or coin values are involved, so the result is shown in dollars
+    pub money: bool,
+    /// Asked as "buy X coin at $Y", answered as a cost
+    pub purchase: bool,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Number(Exact),
+    Dollar,
+    Percent,
+    Of,
+    Plus,
+    Minus,
+    Times,
+    Divide,
+    Open,
+    Close,
+    Word(String),
+}
+
+fn token_regex() -> &'static Regex {
+    static REGEX: OnceLock<Regex> = OnceLock::new();
+    REGEX.get_or_init(|| {
+        Regex::new(r"(?i)(?P<number>\d{1,3}(?:,\d{3})+(?:\.\d+)?|\d+(?:\.\d+)?|\.\d+)(?P<suffix>k\b|m\b)?|(?P<word>[a-z][a-z0-9-]*)|(?P<symbol>[$%+\-*/×÷()])|(?P<other>\S)")
+            .unwrap()
+    })
+}
+
+fn tokenize(text: &str) -> Option<Vec<Token>> {
+    let mut tokens = Vec::new();
+    for captures in token_regex().captures_iter(text) {
+        let token = if let Some(number) = captures.name("number") {
+            let mut value = Exact::parse(number.as_str())?;
+            match captures.name("suffix").map(|suffix| suffix.as_str().to_lowercase()) {
+                Some(suffix) if suffix == "k" => value = value.mul(Exact::integer(1_000))?,
+                Some(_) => value = value.mul(Exact::integer(1_000_000))?,
+                None => {},
+            }
+            Token::Number(value)
+        } else if let Some(word) = captures.name("word") {
+            match word.as_str().to_lowercase().as_str() {
+                "of" => Token::Of,
+                "plus" => Token::Plus,
+                "minus" => Token::Minus,
+                "times" | "x" => Token::Times,
+                "percent" => Token::Percent,
+                // "divided by" reads as one operator
+                "divided" => Token::Divide,
+                "by" if tokens.last() == Some(&Token::Divide) => continue,
+                "over" => Token::Divide,
+                "usd" | "dollars" | "dollar" => Token::Dollar,
+                other => Token::Word(other.to_string()),
+            }
+        } else if let Some(symbol) = captures.name("symbol") {
+            match symbol.as_str() {
+                "$" => Token::Dollar,
+                "%" => Token::Percent,
+                "+" => Token::Plus,
+                "-" => Token::Minus,
+                "*" | "×" => Token::Times,
+                "/" | "÷" => Token::Divide,
+                "(" => Token::Open,
+                _ => Token::Close,
+            }
+        } else {
+            return None;
+        };
+        tokens.push(token);
+    }
+    Some(tokens)
+}
+
+/// Recursive descent over the tokens of one expression
+struct Parser<'a, F: Fn(&str) -> Option<String>> {
+    tokens: &'a [Token],
+    position: usize,
+    coin_id: &'a F,
+    money: bool,
+}
+
+impl<F: Fn(&str) -> Option<String>> Parser<'_, F> {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.position)
+    }
+
+    fn next(&mut self) -> Option<&Token> {
+        let token = self.tokens.get(self.position);
+        self.position += 1;
+        token
+    }
+
+    fn eat(&mut self, token: &Token) -> bool {
+        if self.peek() == Some(token) {
+            self.position += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    // expression := term (("+" | "-") term)*
+    fn expression(&mut self) -> Option<Expr> {
+        let mut left = self.term()?;
+        loop {
+            if self.eat(&Token::Plus) {
+                let right = self.term()?;
+                left = match right {
+                    // "12,000 + 5%" adds 5% of 12,000
+                    Expr::Percent(_) => Expr::Mul(Box::new(left.clone()), Box::new(Expr::Add(Box::new(Expr::Number(Exact::integer(1))), Box::new(right)))),
+                    _ => Expr::Add(Box::new(left), Box::new(right)),
+                };
+            } else if self.eat(&Token::Minus) {
+                let right = self.term()?;
+                left = match right {
+                    Expr::Percent(_) => Expr::Mul(Box::new(left.clone()), Box::new(Expr::Sub(Box::new(Expr::Number(Exact::integer(1))), Box::new(right)))),
+                    _ => Expr::Sub(Box::new(left), Box::new(right)),
+                };
+            } else {
+                return Some(left);
+            }
+        }
+    }
+
+    // term := factor (("*" | "/" | "of") factor)*
+    fn term(&mut self) -> Option<Expr> {
+        let mut left = self.factor()?;
+        loop {
+            if self.eat(&Token::Times) || self.eat(&Token::Of) {
+                left = Expr::Mul(Box::new(left), Box::new(self.factor()?));
+            } else if self.eat(&Token::Divide) {
+                left = Expr::Div(Box::new(left), Box::new(self.factor()?));
+            } else {
+                return Some(left);
+            }
+        }
+    }
+
+    // factor := "-" factor | primary "%"?
+    fn factor(&mut self) -> Option<Expr> {
+        if self.eat(&Token::Minus) {
+            return Some(Expr::Neg(Box::new(self.factor()?)));
+        }
+        let primary = self.primary()?;
+        if self.eat(&Token::Percent) {
+            return Some(Expr::Percent(Box::new(primary)));
+        }
+        Some(primary)
+    }
+
+    // primary := "$"? number ("$" | coin)? | "(" expression ")"
+    fn primary(&mut self) -> Option<Expr> {
+        if self.eat(&Token::Open) {
+            let inner = self.expression()?;
+            return self.eat(&Token::Close).then_some(inner);
+        }
+        if self.eat(&Token::Dollar) {
+            self.money = true;
+        }
+        let Some(Token::Number(value)) = self.next().cloned() else {
+            return None;
+        };
+        if self.eat(&Token::Dollar) {
+            self.money = true;
+        }
+        if let Some(Token::Word(word)) = self.peek().cloned()
+            && let Some(coin_id) = (self.coin_id)(&word)
+        {
+            self.position += 1;
+            self.money = true;
+            return Some(Expr::Coin { quantity: value, coin_id, symbol: word.to_uppercase() });
+        }
+        Some(Expr::Number(value))
+    }
+}
+
+fn parse_expression<F: Fn(&str) -> Option<String>>(text: &str, coin_id: &F) -> Option<(Expr, bool)> {
+    let tokens = tokenize(text)?;
+    let mut parser = Parser { tokens: &tokens, position: 0, coin_id, money: false };
+    let expr = parser.expression()?;
+    // Every word has to be part of the calculation, otherwise it's a question with numbers in it
+    (parser.position == tokens.len()).then_some((expr, parser.money))
+}
+
+impl Expr {
+    fn is_operation(&self) -> bool {
+        matches!(self, Expr::Add(..) | Expr::Sub(..) | Expr::Mul(..) | Expr::Div(..))
+    }
+
+    /// CoinGecko ids and tickers of the coins whose prices the result needs, in order of appearance
+    pub fn coins(&self) -> Vec<(&str, &str)> {
+        let mut coins: Vec<(&str, &str)> = Vec::new();
+        let mut stack = vec![self];
+        while let Some(expr) = stack.pop() {
+            match expr {
+                Expr::Number(_) => {},
+                Expr::Coin { coin_id, symbol, .. } => {
+                    if !coins.iter().any(|(id, _)| id == coin_id) {
+                        coins.push((coin_id, symbol));
+                    }
+                },
+                Expr::Percent(inner) | Expr::Neg(inner) => stack.push(inner),
+                Expr::Add(left, right) | Expr::Sub(left, right) | Expr::Mul(left, right) | Expr::Div(left, right) => {
+                    stack.push(right);
+                    stack.push(left);
+                },
+            }
+        }
+        coins
+    }
+
+    /// Evaluate with coins valued at `prices`, keyed by CoinGecko id
+    pub fn evaluate(&self, prices: &HashMap<String, f64>) -> Result<Exact, CalcError> {
+        let both = |left: &Expr, right: &Expr| Ok::<_, CalcError>((left.evaluate(prices)?, right.evaluate(prices)?));
+        match self {
+            Expr::Number(value) => Ok(*value),
+            Expr::Coin { quantity, coin_id, symbol } => {
+                let price = prices
+                    .get(coin_id)
+                    .and_then(|&price| Exact::from_f64(price))
+                    .ok_or_else(|| CalcError::MissingPrice(symbol.clone()))?;
+                quantity.mul(price).ok_or(CalcError::Overflow)
+            },
+            Expr::Percent(inner) => inner.evaluate(prices)?.percent().ok_or(CalcError::Overflow),
+            Expr::Neg(inner) => Ok(inner.evaluate(prices)?.neg()),
+            Expr::Add(left, right) => {
+                let (left, right) = both(left, right)?;
+                left.add(right).ok_or(CalcError::Overflow)
+            },
+            Expr::Sub(left, right) => {
+                let (left, right) = both(left, right)?;
+                left.sub(right).ok_or(CalcError::Overflow)
+            },
+            Expr::Mul(left, right) => {
+                let (left, right) = both(left, right)?;
+                left.mul(right).ok_or(CalcError::Overflow)
+            },
+            Expr::Div(left, right) => {
+                let (left, right) = both(left, right)?;
+                if right == Exact::integer(0) {
+                    return Err(CalcError::DivisionByZero);
+                }
+                left.div(right).ok_or(CalcError::Overflow)
+            },
+        }
+    }
+}
+
+fn question_regex() -> &'static Regex {
+    static REGEX: OnceLock<Regex> = OnceLock::new();
+    REGEX.get_or_init(|| {
+        Regex::new(r"(?i)^\s*(?P<lead>(?:what(?:'s|’s| is)|whats|how much is|calculate|compute|calc)\s+)?(?P<expr>.+?)(?:\s+(?:worth|in usd|in dollars)(?:\s+(?:now|today))?)?\s*[?.!=]*\s*$").unwrap()
+    })
+}
+
+fn purchase_regex() -> &'static Regex {
+    static REGEX: OnceLock<Regex> = OnceLock::new();
+    REGEX.get_or_init(|| {
+        Regex::new(r"(?i)^\s*(?:if\s+)?(?:i\s+)?(?:buy|bought|purchase)\s+(?P<quantity>\d[\d,]*(?:\.\d+)?|\.\d+)\s+(?P<coin>[a-z][a-z0-9-]*)\s+(?:at|@|for)\s+\$?(?P<price>\d[\d,]*(?:\.\d+)?k?)(?:\s+(?:each|per\s+coin|apiece))?\s*[,;]?\s*(?:(?:what(?:'s|’s| is| would be| will be)|whats)\s+(?:my\s+|the\s+)?(?:total\s+)?cost|how\s+much\s+(?:is|would|will|does|did|do)\s+(?:it|that|i)\s+(?:cost|spend|pay))(?:\s+(?:be|me))?\s*[?.!]*\s*$")
+            .unwrap()
+    })
+}
+
+/// Recognise a message that only asks for arithmetic
+///
+/// "what's 3.5% of 12,000", "0.5 eth + 2 sol", "12000 * 1.05" and "if I buy 0.12 btc at 67,500 what's my cost"
+/// are calculations; "is 3% APY good?" or "what's the price of btc in 2021" are not, because every word
+/// after "what's" has to be part of the expression. `coin_id` maps a ticker or alias to a CoinGecko id.
+pub fn parse_calculation(message: &str, coin_id: impl Fn(&str) -> Option<String>) -> Option<Calculation> {
+    if let Some(captures) = purchase_regex().captures(message) {
+        let quantity = Exact::parse(&captures["quantity"])?;
+        let coin = &captures["coin"];
+        coin_id(coin)?;
+        let price_text = captures["price"].to_lowercase();
+        let price = match price_text.strip_suffix('k') {
+            Some(thousands) => Exact::parse(thousands)?.mul(Exact::integer(1_000))?,
+            None => Exact::parse(&price_text)?,
+        };
+        return Some(Calculation {
+            text: format!("{} {} at ${}", captures["quantity"].trim(), coin.to_uppercase(), captures["price"].trim()),
+            expr: Expr::Mul(Box::new(Expr::Number(quantity)), Box::new(Expr::Number(price))),
+            money: true,
+            purchase: true,
+        });
+    }
+
+    let captures = question_regex().captures(message)?;
+    let text = captures["expr"].trim();
+    let (expr, money) = parse_expression(text, &coin_id)?;
+    // Asked as a question anything but a lone number is a calculation, a bare "50%" or "2 eth" may answer something else
+    let asked = captures.name("lead").is_some();
+    let calculation = expr.is_operation() || (asked && !matches!(expr, Expr::Number(_)));
+    calculation.then(|| Calculation { text: text.to_string(), expr, money, purchase: false })
+}
+
+/// The answer to a calculation, exact to the cent for dollar amounts
+pub fn render_calculation(calculation: &Calculation, prices: &HashMap<String, f64>) -> Result<(String, f64), CalcError> {
+    let value = calculation.expr.evaluate(prices)?;
+    let (formatted, inexact) = if calculation.money {
+        value.format(2, true).map(|(text, inexact)| (format!("${}", text).replacen("$-", "-$", 1), inexact))
+    } else {
+        value.format(MAX_PLACES, false)
+    }
+    .ok_or(CalcError::Overflow)?;
+
+    let equals = if inexact { "≈" } else { "=" };
+    let mut text = if calculation.purchase {
+        format!("Buying {} costs {} before fees.", calculation.text, formatted)
+    } else {
+        format!("{} {} {}", calculation.text, equals, formatted)
+    };
+
+    // Say which quotes the answer used
+    let quotes: Vec<String> = calculation
+        .expr
+        .coins()
+        .into_iter()
+        .filter_map(|(coin_id, symbol)| prices.get(coin_id).map(|price| format!("{} at {}", symbol, crate::price_format::format_price(*price))))
+        .collect();
+    if !quotes.is_empty() {
+        text.push_str(&format!("\n(using live prices: {})", quotes.join(", ")));
+    }
+    Ok((text, value.to_f64()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn coin_id(word: &str) -> Option<String> {
+        super::super::constants::known_coin_id(word).map(str::to_string)
+    }
+
+    fn answer(message: &str, prices: &[(&str, f64)]) -> Option<String> {
+        let prices: HashMap<String, f64> = prices.iter().map(|(id, price)| (id.to_string(), *price)).collect();
+        let calculation = parse_calculation(message, coin_id)?;
+        Some(match render_calculation(&calculation, &prices) {
+            Ok((text, _)) => text,
+            Err(e) => format!("error: {}", e),
+        })
+    }
+
+    #[test]
+    fn test_exact_decimals() {
+        let tenth = Exact::parse("0.1").unwrap();
+        assert_eq!(tenth.add(Exact::parse("0.2").unwrap()), Exact::parse("0.3"));
+        assert_eq!(Exact::parse("12,000"), Some(Exact::integer(12_000)));
+        assert_eq!(Exact::parse("1,234,567.5").unwrap().to_f64(), 1_234_567.5);
+        assert_eq!(Exact::parse(".5"), Exact::integer(1).div(Exact::integer(2)));
+        assert_eq!(Exact::parse("1.2.3"), None);
+        assert_eq!(Exact::from_f64(67500.12), Exact::parse("67500.12"));
+        assert_eq!(Exact::integer(1).div(Exact::integer(0)), None);
+
+        let third = Exact::integer(1).div(Exact::integer(3)).unwrap();
+        assert_eq!(third.format(8, false), Some(("0.33333333".to_string(), true)));
+        assert_eq!(Exact::integer(-2).div(Exact::integer(3)).unwrap().format(2, true), Some(("-0.67".to_string(), true)));
+        assert_eq!(Exact::parse("1234567.005").unwrap().format(2, true), Some(("1,234,567.01".to_string(), true)));
+        assert_eq!(Exact::integer(420).format(8, false), Some(("420".to_string(), false)));
+    }
+
+    #[test]
+    fn test_percentages() {
+        assert_eq!(answer("what's 3.5% of 12,000", &[]).unwrap(), "3.5% of 12,000 = 420");
+        assert_eq!(answer("What is 15 percent of $2,400?", &[]).unwrap(), "15 percent of $2,400 = $360.00");
+        assert_eq!(answer("calculate 12000 + 5%", &[]).unwrap(), "12000 + 5% = 12,600");
+        assert_eq!(answer("$80,000 - 12.5%", &[]).unwrap(), "$80,000 - 12.5% = $70,000.00");
+        assert_eq!(answer("what's 50%?", &[]).unwrap(), "50% = 0.5");
+        assert_eq!(answer("how much is 2% of 10k", &[]).unwrap(), "2% of 10k = 200");
+    }
+
+    #[test]
+    fn test_arithmetic() {
+        assert_eq!(answer("what's 0.1 + 0.2", &[]).unwrap(), "0.1 + 0.2 = 0.3");
+        assert_eq!(answer("(1,500 + 2,500) * 1.05 =", &[]).unwrap(), "(1,500 + 2,500) * 1.05 = 4,200");
+        assert_eq!(answer("what is 10 divided by 3?", &[]).unwrap(), "10 divided by 3 ≈ 3.33333333");
+        assert_eq!(answer("what's -4 x 2.5", &[]).unwrap(), "-4 x 2.5 = -10");
+        assert_eq!(answer("2 / (3 - 3)", &[]).unwrap(), "error: that divides by zero");
+    }
+
+    #[test]
+    fn test_coin_quantities_use_live_prices() {
+        let prices = [("bitcoin", 67_500.0), ("ethereum", 3_120.5), ("solana", 142.37)];
+        assert_eq!(
+            answer("how much is 0.12 btc worth?", &prices).unwrap(),
+            "0.12 btc = $8,100.00\n(using live prices: BTC at $67500.00)"
+        );
+        assert_eq!(
+            answer("what's 0.5 eth + 10 sol", &prices).unwrap(),
+            "0.5 eth + 10 sol = $2,983.95\n(using live prices: ETH at $3120.50, SOL at $142.37)"
+        );
+        assert_eq!(answer("what is 0.5 eth in usd", &[]).unwrap(), "error: I couldn't get a current price for ETH");
+        assert_eq!(
+            answer("if I buy 0.12 btc at 67,500 what's my cost?", &[]).unwrap(),
+            "Buying 0.12 BTC at $67,500 costs $8,100.00 before fees."
+        );
+        assert_eq!(
+            answer("I bought 3 sol at $142.37, how much did I spend", &[]).unwrap(),
+            "Buying 3 SOL at $142.37 costs $427.11 before fees."
+        );
+        assert_eq!(
+            answer("buy 1.5 eth @ 3.1k, what would be the total cost?", &[]).unwrap(),
+            "Buying 1.5 ETH at $3.1k costs $4,650.00 before fees."
+        );
+    }
+
+    #[test]
+    fn test_questions_with_numbers_are_not_calculations() {
+        for message in [
+            "is 3% APY good for USDC?",
+            "what's the price of btc in 2021",
+            "what is 1inch",
+            "2024",
+            "what is 42?",
+            "50%",
+            "2 eth",
+            "should I put 10% of my portfolio in sol?",
+            "what happened to eth on 01-03-2024",
+            "what's 5 + 5 day moving average",
+            "what's aave",
+            "impermanent loss if eth goes up 50%",
+            "what's 2 + 2 in 2 weeks",
+            "1,2 + 3",
+        ] {
+            assert_eq!(parse_calculation(message, coin_id), None, "message: {:?}", message);
+        }
+    }
+}
@@ -0,0 +1,173 @@
+use crate::db::Knowledge;
+use regex::Regex;
+use std::collections::HashSet;
+use std::sync::OnceLock;
+
+/// Opens the list of the entries a general answer was given
+const FOOTER_HEADER: &str = "Sources:";
+
+/// Where an injected entry came from, which sets the letter of its marker
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CitationKind {
+    /// Stored research about a project the user named, marked R1, R2...
+    Research,
+    /// Knowledge matching the message keywords, marked K1, K2...
+    Knowledge,
+}
+
+impl CitationKind {
+    pub fn letter(&self) -> char {
+        match self {
+            CitationKind::Research => 'R',
+            CitationKind::Knowledge => 'K',
+        }
+    }
+}
+
+/// An entry injected into a prompt, with the marker the model was told to cite it by
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Citation {
+    pub kind: CitationKind,
+    /// Numbered from 1 across all entries of the kind
+    pub number: usize,
+    pub source_id: String,
+    /// Links the entry's content names, e.g. the search result it was summarized from
+    pub urls: Vec<String>,
+}
+
+impl Citation {
+    pub fn new(kind: CitationKind, number: usize, entry: &Knowledge) -> Self {
+        Self { kind, number, source_id: entry.source_id.clone(), urls: extract_urls(&entry.content) }
+    }
+
+    /// The marker without brackets, e.g. "R2"
+    pub fn marker(&self) -> String {
+        marker(self.kind, self.number)
+    }
+}
+
+/// The marker of the `number`th entry of `kind`, e.g. "K1"
+pub fn marker(kind: CitationKind, number: usize) -> String {
+    format!("{}{}", kind.letter(), number)
+}
+
+/// The links in `content`, each once, in the order they appear
+pub fn extract_urls(content: &str) -> Vec<String> {
+    static URL: OnceLock<Regex> = OnceLock::new();
+    let url = URL.get_or_init(|| Regex::new(r#"https?://[^\s)\]>"']+"#).unwrap());
+    let mut urls: Vec<String> = Vec::new();
+    for found in url.find_iter(content) {
+        let link = found.as_str().trim_end_matches(['.', ',', ';', ':']);
+        if !urls.iter().any(|seen| seen == link) {
+            urls.push(link.to_string());
+        }
+    }
+    urls
+}
+
+/// The markers an answer cites, e.g. "R1" for "[R1]"
+pub fn cited_markers(text: &str) -> HashSet<String> {
+    static MARKER: OnceLock<Regex> = OnceLock::new();
+    let marker = MARKER.get_or_init(|| Regex::new(r"\[([RK]\d+)\]").unwrap());
+    marker.captures_iter(text).map(|captures| captures[1].to_string()).collect()
+}
+
+/// The list of every entry the answer was given, those it didn't cite included
+///
+/// None when nothing was injected. Markers the answer uses that match no entry are left alone.
+pub fn render_footer(text: &str, citations: &[Citation]) -> Option<String> {
+    if citations.is_empty() {
+        return None;
+    }
+
+    let cited = cited_markers(text);
+    let mut footer = FOOTER_HEADER.to_string();
+    for citation in citations {
+        let marker = citation.marker();
+        footer.push_str(&format!("\n[{}] {}", marker, citation.source_id));
+        if !citation.urls.is_empty() {
+            footer.push_str(&format!(" ({})", citation.urls.join(", ")));
+        }
+        if !cited.contains(&marker) {
+            footer.push_str(", not cited");
+        }
+    }
+    Some(footer)
+}
+
+/// `text` followed by its sources footer, unchanged when nothing was injected
+pub fn append_footer(text: String, citations: &[Citation]) -> String {
+    match render_footer(&text, citations) {
+        Some(footer) => format!("{}\n\n{}", text, footer),
+        None => text,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::NaiveDateTime;
+
+    fn entry(source_id: &str, content: &str) -> Knowledge {
+        Knowledge {
+            id: 0,
+            user_id: 1,
+            source_id: source_id.to_string(),
+            content: content.to_string(),
+            tags: vec![],
+            created_at: NaiveDateTime::default(),
+            updated_at: NaiveDateTime::default(),
+        }
+    }
+
+    fn injected() -> Vec<Citation> {
+        vec![
+            Citation::new(
+                CitationKind::Research,
+                1,
+                &entry("aave_research_1760000000", "Summary from https://aave.com/docs/governance. The team doxxed in 2023"),
+            ),
+            Citation::new(CitationKind::Research, 2, &entry("aave-notes", "V4 ships hubs and spokes")),
+            Citation::new(CitationKind::Knowledge, 1, &entry("dca", "DCA weekly into majors")),
+        ]
+    }
+
+    #[test]
+    fn test_urls_are_taken_from_the_content() {
+        assert_eq!(
+            extract_urls("See https://a.io/x, and (http://b.io/y). Again https://a.io/x."),
+            ["https://a.io/x", "http://b.io/y"]
+        );
+        assert!(extract_urls("no links here").is_empty());
+    }
+
+    #[test]
+    fn test_footer_lists_every_injected_entry_with_its_links() {
+        let text = "The team doxxed in 2023 [R1], and weekly buys suit you [K1].";
+        assert_eq!(
+            render_footer(text, &injected()).unwrap(),
+            "Sources:\n\
+            [R1] aave_research_1760000000 (https://aave.com/docs/governance)\n\
+            [R2] aave-notes, not cited\n\
+            [K1] dca"
+        );
+    }
+
+    #[test]
+    fn test_footer_is_kept_when_the_model_omits_markers() {
+        let footer = render_footer("Aave looks solid.", &injected()).unwrap();
+        assert!(footer.starts_with("Sources:\n[R1] aave_research_1760000000"));
+        assert_eq!(footer.matches(", not cited").count(), 3);
+
+        // A marker matching no entry is not listed
+        let footer = render_footer("Audited twice [R7].", &injected()).unwrap();
+        assert!(!footer.contains("R7"));
+    }
+
+    #[test]
+    fn test_no_footer_without_injected_entries() {
+        assert_eq!(render_footer("Hello [K1]", &[]), None);
+        assert_eq!(append_footer("Hello".to_string(), &[]), "Hello");
+        assert!(append_footer("Aave [R2]".to_string(), &injected()).starts_with("Aave [R2]\n\nSources:\n"));
+    }
+}
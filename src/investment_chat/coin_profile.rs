@@ -0,0 +1,195 @@
+use crate::price_fetcher::CoinProfile;
+use crate::price_format::format_compact;
+use regex::Regex;
+use std::sync::OnceLock;
+
+/// Cards kept per session for follow-up questions, most recent first
+pub const MAX_SESSION_CARDS: usize = 3;
+
+/// Words that make "overview of ..." about something other than a coin
+const NOT_COINS: &[&str] = &["my", "our", "your", "portfolio", "holdings", "market", "markets", "watchlist", "strategy", "strategies"];
+
+/// Categories listed on a card, CoinGecko tags big coins with dozens of ecosystems
+const MAX_CATEGORIES: usize = 5;
+
+/// The coin a message asks the basics of, e.g. "tell me the basics of chainlink" or "overview of LINK"
+pub fn detect_profile_query(message: &str) -> Option<String> {
+    static QUERY: OnceLock<Regex> = OnceLock::new();
+    let query = QUERY.get_or_init(|| {
+        Regex::new(
+            r"(?i)\b(?:basics|overview|fundamentals|rundown|quick facts|fact sheet|profile)\s+(?:of|on|for|about)\s+(?:the\s+coin\s+|the\s+token\s+)?(?P<coin>[a-z0-9][a-z0-9.-]*(?:\s+[a-z0-9][a-z0-9.-]*){0,2}?)\s*[?.!]*\s*$",
+        )
+        .unwrap()
+    });
+
+    let coin = query.captures(message.trim())?.name("coin")?.as_str().trim_end_matches(['.', '-']).to_lowercase();
+    // "an overview of my portfolio" is about the user's holdings, not a coin
+    if coin.split_whitespace().any(|word| NOT_COINS.contains(&word)) {
+        return None;
+    }
+    Some(coin)
+}
+
+/// Whether a message names the coin of a card, by name, id or ticker
+pub fn mentions(profile: &CoinProfile, message: &str) -> bool {
+    let lower = message.to_lowercase();
+    let mut names = vec![profile.id.to_lowercase(), profile.name.to_lowercase()];
+    // Two-letter tickers ("op", "ar") are common words
+    if profile.symbol.len() > 2 {
+        names.push(profile.symbol.to_lowercase());
+    }
+    names.iter().any(|name| {
+        Regex::new(&format!(r"\b{}\b", regex::escape(name)))
+            .map(|pattern| pattern.is_match(&lower))
+            .unwrap_or(false)
+    })
+}
+
+/// Whether a message refers back to the coin just discussed, e.g. "is it a good buy?"
+pub fn is_follow_up(message: &str) -> bool {
+    static REFERENCE: OnceLock<Regex> = OnceLock::new();
+    REFERENCE
+        .get_or_init(|| Regex::new(r"(?i)\b(?:it|its|it's|this (?:coin|token|project)|that (?:coin|token|project))\b").unwrap())
+        .is_match(message)
+}
+
+/// A factual card about a coin: what it is, tags, supply, rank and links
+pub fn render_card(profile: &CoinProfile) -> String {
+    let mut title = format!("{} ({})", profile.name, profile.symbol.to_uppercase());
+    if let Some(rank) = profile.market_cap_rank {
+        title.push_str(&format!(", #{} by market cap", rank));
+    }
+
+    let mut lines = vec![title];
+    lines.push(if profile.description.is_empty() {
+        "CoinGecko has no description of this coin.".to_string()
+    } else {
+        profile.description.clone()
+    });
+    lines.push(String::new());
+
+    if !profile.categories.is_empty() {
+        let mut categories = profile.categories.iter().take(MAX_CATEGORIES).cloned().collect::<Vec<_>>().join(", ");
+        if profile.categories.len() > MAX_CATEGORIES {
+            categories.push_str(&format!(" and {} more", profile.categories.len() - MAX_CATEGORIES));
+        }
+        lines.push(format!("Categories: {}", categories));
+    }
+    lines.push(format!("Supply: {}", render_supply(profile)));
+    if let Some(date) = profile.genesis_date {
+        lines.push(format!("Launched: {}", date.format("%B %-d, %Y")));
+    }
+
+    let links: Vec<String> = [("Website", &profile.homepage), ("Whitepaper", &profile.whitepaper), ("Code", &profile.repository)]
+        .into_iter()
+        .filter_map(|(label, link)| link.as_ref().map(|link| format!("{}: {}", label, link)))
+        .collect();
+    lines.extend(links);
+
+    lines.join("\n")
+}
+
+/// Circulating supply against the cap, or against the total for uncapped coins
+fn render_supply(profile: &CoinProfile) -> String {
+    let circulating = profile.circulating_supply.filter(|supply| *supply > 0.0);
+    match (circulating, profile.max_supply) {
+        (Some(circulating), Some(max)) if max > 0.0 => format!(
+            "{} circulating of {} max ({:.1}%)",
+            format_compact(circulating),
+            format_compact(max),
+            circulating / max * 100.0
+        ),
+        (Some(circulating), _) => match profile.total_supply.filter(|total| *total > 0.0) {
+            Some(total) => format!("{} circulating of {} total, no max supply", format_compact(circulating), format_compact(total)),
+            None => format!("{} circulating, no max supply", format_compact(circulating)),
+        },
+        (None, Some(max)) if max > 0.0 => format!("unknown circulating of {} max", format_compact(max)),
+        _ => "unknown".to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::NaiveDate;
+
+    fn chainlink() -> CoinProfile {
+        CoinProfile {
+            id: "chainlink".to_string(),
+            symbol: "link".to_string(),
+            name: "Chainlink".to_string(),
+            description: "Chainlink is a decentralized oracle network.".to_string(),
+            categories: vec!["Oracle".to_string(), "Smart Contract Platform".to_string()],
+            homepage: Some("https://chain.link/".to_string()),
+            whitepaper: None,
+            repository: Some("https://github.com/smartcontractkit/chainlink".to_string()),
+            genesis_date: NaiveDate::from_ymd_opt(2017, 9, 19),
+            market_cap_rank: Some(12),
+            circulating_supply: Some(677_099_970.45),
+            total_supply: Some(1_000_000_000.0),
+            max_supply: Some(1_000_000_000.0),
+        }
+    }
+
+    #[test]
+    fn test_detect_profile_query() {
+        assert_eq!(detect_profile_query("tell me the basics of chainlink").as_deref(), Some("chainlink"));
+        assert_eq!(detect_profile_query("Give me an overview of Shiba Inu?").as_deref(), Some("shiba inu"));
+        assert_eq!(detect_profile_query("quick facts on the token AERO").as_deref(), Some("aero"));
+        assert_eq!(detect_profile_query("fundamentals of link.").as_deref(), Some("link"));
+
+        assert!(detect_profile_query("what's the price of chainlink").is_none());
+        assert!(detect_profile_query("give me an overview of my portfolio").is_none());
+        assert!(detect_profile_query("overview of the market").is_none());
+        assert!(detect_profile_query("the basics of yield farming on solana and how to start").is_none());
+        assert!(detect_profile_query("basics of").is_none());
+    }
+
+    #[test]
+    fn test_render_card() {
+        assert_eq!(
+            render_card(&chainlink()),
+            "Chainlink (LINK), #12 by market cap\n\
+             Chainlink is a decentralized oracle network.\n\
+             \n\
+             Categories: Oracle, Smart Contract Platform\n\
+             Supply: 677.10M circulating of 1.00B max (67.7%)\n\
+             Launched: September 19, 2017\n\
+             Website: https://chain.link/\n\
+             Code: https://github.com/smartcontractkit/chainlink"
+        );
+    }
+
+    #[test]
+    fn test_render_card_with_missing_fields() {
+        let mut profile = chainlink();
+        profile.description = String::new();
+        profile.categories = (1..=7).map(|i| format!("Tag {}", i)).collect();
+        profile.market_cap_rank = None;
+        profile.max_supply = None;
+        profile.genesis_date = None;
+        profile.homepage = None;
+        profile.repository = None;
+
+        let card = render_card(&profile);
+        assert!(card.starts_with("Chainlink (LINK)\nCoinGecko has no description of this coin.\n"));
+        assert!(card.contains("Categories: Tag 1, Tag 2, Tag 3, Tag 4, Tag 5 and 2 more"));
+        assert!(card.ends_with("Supply: 677.10M circulating of 1.00B total, no max supply"));
+
+        profile.circulating_supply = None;
+        profile.total_supply = None;
+        assert!(render_card(&profile).ends_with("Supply: unknown"));
+    }
+
+    #[test]
+    fn test_follow_ups_name_the_coin_or_refer_to_it() {
+        let profile = chainlink();
+        assert!(mentions(&profile, "Should I stake LINK?"));
+        assert!(mentions(&profile, "is chainlink overvalued"));
+        assert!(!mentions(&profile, "what about linkedin stock"));
+
+        assert!(is_follow_up("is it a good long-term hold?"));
+        assert!(is_follow_up("What are the risks of this token?"));
+        assert!(!is_follow_up("what is restaking"));
+    }
+}
@@ -48,6 +48,31 @@ pub fn crypto_projects() -> &'static HashSet<&'static str> {
     })
 }
 
+/// Map common cryptocurrency names and tickers to their CoinGecko IDs
+pub fn known_coin_id(name: &str) -> Option<&'static str> {
+    let id = match name.to_lowercase().as_str() {
+        "btc" | "bitcoin" => "bitcoin",
+        "eth" | "ethereum" => "ethereum",
+        "sol" | "solana" => "solana",
+        "ada" | "cardano" => "cardano",
+        "dot" | "polkadot" => "polkadot",
+        "doge" | "dogecoin" => "dogecoin",
+        "xrp" | "ripple" => "ripple",
+        "ltc" | "litecoin" => "litecoin",
+        "link" | "chainlink" => "chainlink",
+        "uni" | "uniswap" => "uniswap",
+        "aave" => "aave",
+        "matic" | "polygon" => "matic-network",
+        "avax" | "avalanche" => "avalanche-2",
+        "aero" | "aerodrome" => "aerodrome-finance",
+        "usdt" | "tether" => "tether",
+        "usdc" => "usd-coin",
+        "dai" => "dai",
+        _ => return None,
+    };
+    Some(id)
+}
+
 /// Get the set of investment-related keywords
 pub fn investment_keywords() -> &'static HashSet<&'static str> {
     static KEYWORDS: OnceLock<HashSet<&'static str>> = OnceLock::new();
@@ -0,0 +1,1104 @@
+use super::SystemPromptOverride;
+use super::citations::{self, Citation, CitationKind};
+use crate::compliance;
+use crate::db::{Knowledge, Message, Verbosity};
+use chrono::{Duration, NaiveDate, NaiveDateTime, Utc};
+use std::fmt::{self, Write};
+
+/// Default size of an assembled prompt, in estimated tokens
+pub const DEFAULT_PROMPT_TOKEN_BUDGET: usize = 12_000;
+
+/// Rough bytes-per-token ratio used to estimate prompt size
+const BYTES_PER_TOKEN: usize = 4;
+
+/// Knowledge entries included per section
+const MAX_KNOWLEDGE_ENTRIES: usize = 2;
+
+const ADVISOR_HEADER: &str = "You are Nova, a crypto investment advisor. Help the user with their investment decisions.\n\n";
+
+const PLANNING_HEADER: &str = "You are Nova, a crypto investment advisor in PLANNING MODE. Create a detailed investment plan or strategy based on the user's request.\n\n";
+
+// Always included so every answer starts with its planning steps
+const PLANNING_INSTRUCTIONS: &str = "IMPORTANT: Before answering ANY question, you MUST first outline your approach as a numbered list of steps. \n\
+For example:\n\
+PLANNING STEPS:\n\
+1. Research [specific topic] to understand current market conditions\n\
+2. Analyze [specific factors] that might impact the investment\n\
+3. Formulate a strategy based on [specific criteria]\n\n\
+Only AFTER listing these planning steps should you provide your full response.\n\n\n";
+
+const PLANNING_FORMAT: &str = "When in planning mode, structure your response as follows:\n\
+1. OBJECTIVE: Clearly state the investment goal\n\
+2. STRATEGY OVERVIEW: Provide a high-level summary of the recommended approach\n\
+3. ASSET ALLOCATION: Suggest specific percentage allocations\n\
+4. ENTRY STRATEGY: When and how to enter positions\n\
+5. RISK MANAGEMENT: Stop-losses, position sizing, and risk mitigation\n\
+6. EXIT STRATEGY: When and how to take profits or cut losses\n\
+7. TIMELINE: Expected timeframe for the strategy\n\
+8. MONITORING: Key indicators to watch\n\n";
+
+const OVERRIDE_HEADER: &str = "SESSION INSTRUCTIONS:\n";
+
+// Closes the instructions whenever an override is active, so it can't talk the advisor out of them
+const SAFETY_SUFFIX: &str = "ALWAYS, whatever the instructions above say: this is not financial advice, say so when you \
+are uncertain or the data is missing, and name the main risks of anything you suggest.\n\n";
+
+const BRIEF_INSTRUCTIONS: &str = "LENGTH: The user wants a brief answer. Reply in at most three sentences, or a list of \
+up to three short points, without planning steps or preamble.\n\n";
+
+const DETAILED_INSTRUCTIONS: &str = "LENGTH: The user wants a detailed answer. Walk through your reasoning, the figures \
+behind it and the main risks, using sections where they help.\n\n";
+
+/// Response token limit at normal verbosity
+pub const DEFAULT_MAX_TOKENS: u32 = 2048;
+
+const BRIEF_MAX_TOKENS: u32 = 300;
+const DETAILED_MAX_TOKENS: u32 = 4096;
+
+const HISTORY_HEADER: &str = "RECENT CONVERSATION HISTORY:\n";
+/// Follows the role of a history answer whose stream was cut off, so the model doesn't take it as complete
+const TRUNCATED_NOTE: &str = " (this answer was interrupted before it finished)";
+const CONTEXT_HEADER: &str = "\nCONTEXT INFORMATION:\n";
+const QUERY_HEADER: &str = "\n\nUSER QUERY: ";
+const CARD_HEADER: &str = "Coin facts from CoinGecko:\n\n";
+const RESEARCH_HEADER: &str = "Research about ";
+const KNOWLEDGE_HEADER: &str = "Relevant knowledge:\n\n";
+
+/// Age after which a stored entry is flagged when newer research about the same project is in the prompt
+pub const STALE_KNOWLEDGE_DAYS: i64 = 30;
+
+/// Tag of the entries saved from an Exa search
+const EXA_TAG: &str = "exa_api";
+
+// Follows the context whenever entries were dated, so the model doesn't silently pick one of them
+const CONFLICT_INSTRUCTIONS: &str = "DATED SOURCES: Some entries above are dated because older stored knowledge and newer \
+research about the same project are both included, and they may disagree. Prefer the newer sourced figures, and if the \
+two differ in a way that matters to the answer, say so and give both dates.";
+
+// Follows the context whenever labelled entries are included, so claims from them can be traced
+const CITATION_INSTRUCTIONS: &str = "\nSOURCES: Entries above are labelled like [R1] or [K1]. When a claim in your answer comes \
+from one of them, follow the claim with its label, e.g. \"the team doxxed in 2023 [R1]\". Don't label anything else, and \
+don't make up labels.";
+
+const DIVERSIFICATION_HEADER: &str = "You are Nova, a crypto investment advisor. The user asked about the diversification \
+of their portfolio. The figures below were computed from daily prices of their holdings over the last 90 days. \
+Base your recommendations on these figures only: do not invent other numbers, and mention when the history is too \
+short to draw conclusions. Keep it to a few concrete suggestions.\n\nPORTFOLIO ANALYSIS:\n";
+
+/// Prompt asking for recommendations on a computed portfolio analysis
+pub fn diversification_prompt(analysis: &str, user_message: &str) -> String {
+    format!("{}{}{}{}", DIVERSIFICATION_HEADER, analysis, QUERY_HEADER, user_message)
+}
+
+const SCENARIO_HEADER: &str = "You are Nova, a crypto investment advisor. The user asked what a hypothetical price move \
+would do to their portfolio. The before/after figures below were computed from their holdings at current prices. \
+Write one paragraph of commentary on what the scenario means for them, grounded in these figures only: do not invent \
+other numbers or predict whether the move will happen.\n\nSCENARIO RESULT:\n";
+
+const CATEGORY_HEADER: &str = "You are Nova, a crypto investment advisor. The user asked about the leading coins of a \
+sector. The ranking below was fetched from CoinGecko just now. Compare these coins in two or three sentences, grounded \
+in the figures shown only: do not add coins, prices or market caps that aren't listed, and don't recommend buying \
+any of them.\n\nCATEGORY RANKING:\n";
+
+/// Prompt asking for a brief comparison of a fetched category ranking
+pub fn category_prompt(ranking: &str, user_message: &str) -> String {
+    format!("{}{}{}{}", CATEGORY_HEADER, ranking, QUERY_HEADER, user_message)
+}
+
+/// Prompt asking for commentary on a computed scenario
+pub fn scenario_prompt(result: &str, user_message: &str) -> String {
+    format!("{}{}{}{}", SCENARIO_HEADER, result, QUERY_HEADER, user_message)
+}
+
+/// Everything retrieved for one turn
+#[derive(Debug, Default)]
+pub struct PromptInput<'a> {
+    pub user_message: &'a str,
+    pub planning: bool,
+    /// Most recent message first, as returned by `db::get_messages`
+    pub history: &'a [Message],
+    /// Coin cards shown earlier in the session that the message follows up on
+    pub cards: &'a [String],
+    /// Stored research about each project the user named, first mention first
+    pub research: &'a [(String, Vec<Knowledge>)],
+    /// Knowledge matching the message keywords
+    pub knowledge: &'a [Knowledge],
+}
+
+/// Assembles the prompt for a turn within a token budget
+///
+/// Knowledge sections are kept before conversation history, and whole entries or
+/// messages are dropped once the budget runs out. The output buffer is reused
+/// across calls.
+#[derive(Debug)]
+pub struct PromptBuilder {
+    token_budget: usize,
+    verbosity: Verbosity,
+    /// Current date and market lines, empty until `with_preamble`
+    preamble: String,
+    /// Session instructions with their header, empty without an override
+    system_override: String,
+    /// Whether the compliance instructions are included
+    compliance: bool,
+    /// Source ids of the knowledge the last built prompt includes
+    sources: Vec<String>,
+    /// Whether entries are labelled for the answer to cite
+    cite: bool,
+    /// Labelled entries of the last built prompt, empty unless citing
+    citations: Vec<Citation>,
+    /// When entries are dated against, see `find_conflicts`
+    now: NaiveDateTime,
+    buffer: String,
+}
+
+impl Default for PromptBuilder {
+    fn default() -> Self {
+        Self::new(DEFAULT_PROMPT_TOKEN_BUDGET)
+    }
+}
+
+impl PromptBuilder {
+    pub fn new(token_budget: usize) -> Self {
+        Self {
+            token_budget,
+            verbosity: Verbosity::Normal,
+            preamble: String::new(),
+            system_override: String::new(),
+            compliance: false,
+            sources: Vec::new(),
+            cite: false,
+            citations: Vec::new(),
+            now: Utc::now().naive_utc(),
+            buffer: String::new(),
+        }
+    }
+
+    /// Ask for answers of the given length
+    pub fn with_verbosity(mut self, verbosity: Verbosity) -> Self {
+        self.verbosity = verbosity;
+        self
+    }
+
+    /// Open the instructions with the current date and market, see `current_date::render_preamble`
+    pub fn with_preamble(mut self, preamble: &str) -> Self {
+        self.preamble = preamble.to_string();
+        self
+    }
+
+    /// Layer session instructions between the advisor's base prompt and the standing instructions
+    pub fn with_system_override(mut self, system_override: Option<&SystemPromptOverride>) -> Self {
+        self.system_override = match system_override {
+            Some(system_override) => format!("{}{}\n\n", OVERRIDE_HEADER, system_override.text()),
+            None => String::new(),
+        };
+        self
+    }
+
+    /// Add the compliance instructions after every other instruction but the safety suffix
+    pub fn with_compliance(mut self, compliance: bool) -> Self {
+        self.compliance = compliance;
+        self
+    }
+
+    /// Label research [R1], [R2]... and knowledge [K1], [K2]... and ask the answer to cite them
+    pub fn with_citations(mut self, cite: bool) -> Self {
+        self.cite = cite;
+        self
+    }
+
+    /// Date entries against `now` instead of the time the builder was made
+    pub fn with_now(mut self, now: NaiveDateTime) -> Self {
+        self.now = now;
+        self
+    }
+
+    /// Response token limit matching the requested length
+    pub fn max_tokens(&self) -> u32 {
+        match self.verbosity {
+            Verbosity::Brief => BRIEF_MAX_TOKENS,
+            Verbosity::Normal => DEFAULT_MAX_TOKENS,
+            Verbosity::Detailed => DETAILED_MAX_TOKENS,
+        }
+    }
+
+    /// Tokens left for history and knowledge once the fixed parts of the prompt are placed
+    /// Callers can skip retrieval entirely when this is zero
+    pub fn retrieval_budget(&self, planning: bool, user_message: &str) -> usize {
+        self.remaining_bytes(planning, user_message) / BYTES_PER_TOKEN
+    }
+
+    /// Build the prompt for a turn, returning a view into the reused buffer
+    pub fn build(&mut self, input: &PromptInput<'_>) -> &str {
+        let mut remaining = self.remaining_bytes(input.planning, input.user_message);
+
+        // Work out what fits before writing anything, so the buffer is sized once
+        let mut cards_len = 0;
+        let cards: Vec<&str> = input
+            .cards
+            .iter()
+            .filter(|card| {
+                let len = CARD_HEADER.len() + card.len() + "\n\n".len();
+                let fits = len <= remaining;
+                if fits {
+                    remaining -= len;
+                    cards_len += len;
+                }
+                fits
+            })
+            .map(String::as_str)
+            .collect();
+
+        // Entries are fitted with the dates they'd get if every candidate made it in,
+        // then dated again once it's known which did, which can only drop dates
+        let candidates: Vec<(&str, &[Knowledge])> = input
+            .research
+            .iter()
+            .map(|(project, entries)| (project.as_str(), &entries[..entries.len().min(MAX_KNOWLEDGE_ENTRIES)]))
+            .collect();
+        let knowledge_candidates = &input.knowledge[..input.knowledge.len().min(MAX_KNOWLEDGE_ENTRIES)];
+        let candidate_conflicts = find_conflicts(&candidates, knowledge_candidates, self.now, stale_after());
+        let conflicts_len = if candidate_conflicts.is_empty() { 0 } else { CONFLICT_INSTRUCTIONS.len() };
+        remaining = remaining.saturating_sub(conflicts_len);
+        let has_candidates = candidates.iter().any(|(_, entries)| !entries.is_empty()) || !knowledge_candidates.is_empty();
+        let citation_len = if self.cite && has_candidates { CITATION_INSTRUCTIONS.len() } else { 0 };
+        remaining = remaining.saturating_sub(citation_len);
+
+        let mut research_len = 0;
+        let mut next_research = 1;
+        let research: Vec<(&str, &[Knowledge])> = candidates
+            .iter()
+            .zip(&candidate_conflicts.research)
+            .map(|(&(project, entries), dates)| {
+                let overhead = RESEARCH_HEADER.len() + project.len() + ":\n\n".len() + "\n\n".len();
+                let labels = self.cite.then_some((CitationKind::Research, next_research));
+                let (count, len) = fit_knowledge(entries, dates, labels, overhead, remaining);
+                remaining -= len;
+                research_len += len;
+                next_research += count;
+                (project, &entries[..count])
+            })
+            .collect();
+        let knowledge_labels = self.cite.then_some((CitationKind::Knowledge, 1));
+        let (knowledge_count, knowledge_len) = fit_knowledge(
+            knowledge_candidates,
+            &candidate_conflicts.knowledge,
+            knowledge_labels,
+            KNOWLEDGE_HEADER.len() + "\n\n".len(),
+            remaining,
+        );
+        remaining -= knowledge_len;
+        let (history_count, history_len) = fit_history(input.history, remaining);
+        let conflicts = find_conflicts(&research, &input.knowledge[..knowledge_count], self.now, stale_after());
+
+        self.sources.clear();
+        let included = research.iter().flat_map(|(_, entries)| entries.iter()).chain(&input.knowledge[..knowledge_count]);
+        for entry in included {
+            if !self.sources.contains(&entry.source_id) {
+                self.sources.push(entry.source_id.clone());
+            }
+        }
+        self.citations.clear();
+        if self.cite {
+            let research_entries = research.iter().flat_map(|(_, entries)| entries.iter());
+            for (i, entry) in research_entries.enumerate() {
+                self.citations.push(Citation::new(CitationKind::Research, i + 1, entry));
+            }
+            for (i, entry) in input.knowledge[..knowledge_count].iter().enumerate() {
+                self.citations.push(Citation::new(CitationKind::Knowledge, i + 1, entry));
+            }
+        }
+        let citation_len = if self.citations.is_empty() { 0 } else { citation_len };
+
+        let total = self.fixed_bytes(input.planning, input.user_message)
+            + cards_len
+            + research_len
+            + knowledge_len
+            + conflicts_len
+            + citation_len
+            + history_len;
+
+        let buffer = &mut self.buffer;
+        buffer.clear();
+        buffer.reserve(total);
+
+        for part in header(input.planning, self.verbosity, &self.preamble, &self.system_override, self.compliance) {
+            buffer.push_str(part);
+        }
+
+        if history_count > 0 {
+            buffer.push_str(HISTORY_HEADER);
+            for message in &input.history[..history_count] {
+                buffer.push_str(message.role.label());
+                if message.truncated {
+                    buffer.push_str(TRUNCATED_NOTE);
+                }
+                buffer.push_str(":\n");
+                buffer.push_str(&message.content);
+                buffer.push_str("\n\n");
+            }
+        }
+
+        buffer.push_str(CONTEXT_HEADER);
+        for card in cards {
+            buffer.push_str(CARD_HEADER);
+            buffer.push_str(card);
+            buffer.push_str("\n\n");
+        }
+        let mut next_research = 1;
+        for ((project, entries), dates) in research.iter().zip(&conflicts.research) {
+            if entries.is_empty() {
+                continue;
+            }
+            buffer.push_str(RESEARCH_HEADER);
+            buffer.push_str(project);
+            buffer.push_str(":\n\n");
+            push_knowledge(buffer, entries, dates, self.cite.then_some((CitationKind::Research, next_research)));
+            buffer.push_str("\n\n");
+            next_research += entries.len();
+        }
+        if knowledge_count > 0 {
+            buffer.push_str(KNOWLEDGE_HEADER);
+            push_knowledge(buffer, &input.knowledge[..knowledge_count], &conflicts.knowledge, knowledge_labels);
+            buffer.push_str("\n\n");
+        }
+        if !conflicts.is_empty() {
+            buffer.push_str(CONFLICT_INSTRUCTIONS);
+        }
+        if !self.citations.is_empty() {
+            buffer.push_str(CITATION_INSTRUCTIONS);
+        }
+
+        buffer.push_str(QUERY_HEADER);
+        buffer.push_str(input.user_message);
+
+        &self.buffer
+    }
+
+    /// Source ids of the research and knowledge entries the last built prompt includes, first included first
+    pub fn sources(&self) -> &[String] {
+        &self.sources
+    }
+
+    /// Labelled entries of the last built prompt in label order, empty unless built `with_citations`
+    pub fn citations(&self) -> &[Citation] {
+        &self.citations
+    }
+
+    fn remaining_bytes(&self, planning: bool, user_message: &str) -> usize {
+        (self.token_budget * BYTES_PER_TOKEN).saturating_sub(self.fixed_bytes(planning, user_message))
+    }
+
+    /// Length of the parts of the prompt that are always included
+    fn fixed_bytes(&self, planning: bool, user_message: &str) -> usize {
+        let header: usize = header(planning, self.verbosity, &self.preamble, &self.system_override, self.compliance).iter().map(|part| part.len()).sum();
+
+        header + CONTEXT_HEADER.len() + QUERY_HEADER.len() + user_message.len()
+    }
+}
+
+/// Instructions opening the prompt, brief answers skip the planning steps
+///
+/// Order: base prompt, preamble, session override, standing instructions, compliance instructions when on,
+/// then the safety suffix when overridden
+fn header<'a>(planning: bool, verbosity: Verbosity, preamble: &'a str, system_override: &'a str, compliance: bool) -> [&'a str; 8] {
+    let steps = if verbosity == Verbosity::Brief { "" } else { PLANNING_INSTRUCTIONS };
+    let length = match verbosity {
+        Verbosity::Brief => BRIEF_INSTRUCTIONS,
+        Verbosity::Normal => "",
+        Verbosity::Detailed => DETAILED_INSTRUCTIONS,
+    };
+
+    let compliance = if compliance { compliance::PROMPT_INSTRUCTIONS } else { "" };
+    let suffix = if system_override.is_empty() { "" } else { SAFETY_SUFFIX };
+
+    if planning {
+        [PLANNING_HEADER, preamble, system_override, steps, PLANNING_FORMAT, length, compliance, suffix]
+    } else {
+        [ADVISOR_HEADER, preamble, system_override, steps, "", length, compliance, suffix]
+    }
+}
+
+/// Estimate the number of tokens in a piece of text
+pub fn estimate_tokens(text: &str) -> usize {
+    text.len().div_ceil(BYTES_PER_TOKEN)
+}
+
+fn stale_after() -> Duration {
+    Duration::days(STALE_KNOWLEDGE_DAYS)
+}
+
+/// When an entry in the prompt was written, shown only for entries that may conflict
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DateLabel {
+    /// Stored knowledge older than the staleness threshold
+    Stored(NaiveDate),
+    /// Exa research newer than the staleness threshold
+    Researched(NaiveDate),
+}
+
+impl fmt::Display for DateLabel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DateLabel::Stored(date) => write!(f, " (stored {})", date.format("%Y-%m-%d")),
+            DateLabel::Researched(date) => write!(f, " (researched {})", date.format("%Y-%m-%d")),
+        }
+    }
+}
+
+/// Date labels for the entries of each block, in the same shape as the blocks
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Conflicts {
+    pub research: Vec<Vec<Option<DateLabel>>>,
+    pub knowledge: Vec<Option<DateLabel>>,
+}
+
+impl Conflicts {
+    pub fn is_empty(&self) -> bool {
+        self.research.iter().flatten().chain(&self.knowledge).all(Option::is_none)
+    }
+}
+
+/// Find the projects whose stored knowledge may be contradicted by fresh research, and date both sides
+///
+/// A project's entries are those in its research block plus the keyword knowledge tagged with its name.
+/// When they include both an entry older than `stale_after` and Exa research newer than it, the old
+/// entries are labelled with the date they were stored and the research with the date it was made.
+/// Entries of projects without such a pair get no label.
+pub fn find_conflicts(
+    research: &[(&str, &[Knowledge])],
+    knowledge: &[Knowledge],
+    now: NaiveDateTime,
+    stale_after: Duration,
+) -> Conflicts {
+    let cutoff = now - stale_after;
+    let fresh = |entry: &Knowledge| entry.created_at >= cutoff && entry.tags.iter().any(|tag| tag == EXA_TAG);
+    let stale = |entry: &Knowledge| entry.created_at < cutoff;
+    let label = |entry: &Knowledge| {
+        let date = entry.created_at.date();
+        if fresh(entry) {
+            Some(DateLabel::Researched(date))
+        } else if stale(entry) {
+            Some(DateLabel::Stored(date))
+        } else {
+            None
+        }
+    };
+
+    let mut conflicts = Conflicts {
+        research: research.iter().map(|(_, entries)| vec![None; entries.len()]).collect(),
+        knowledge: vec![None; knowledge.len()],
+    };
+    for (block, (project, entries)) in research.iter().enumerate() {
+        let related: Vec<usize> = (0..knowledge.len())
+            .filter(|&i| knowledge[i].tags.iter().any(|tag| tag.eq_ignore_ascii_case(project)))
+            .collect();
+        let all = || entries.iter().chain(related.iter().map(|&i| &knowledge[i]));
+        if !(all().any(fresh) && all().any(stale)) {
+            continue;
+        }
+
+        for (i, entry) in entries.iter().enumerate() {
+            conflicts.research[block][i] = label(entry);
+        }
+        for i in related {
+            conflicts.knowledge[i] = label(&knowledge[i]);
+        }
+    }
+    conflicts
+}
+
+/// Length of "Knowledge N [label]<date>: <content>\n\n"
+fn knowledge_entry_len(index: usize, entry: &Knowledge, date: Option<&DateLabel>, label: Option<&str>) -> usize {
+    let date_len = date.map_or(0, |date| date.to_string().len());
+    let label_len = label.map_or(0, |label| " []".len() + label.len());
+    "Knowledge : ".len() + decimal_len(index) + label_len + date_len + entry.content.len() + "\n\n".len()
+}
+
+/// Count the knowledge entries that fit, returning the count and the section length
+/// `labels` is the kind and number of the first entry's citation label, None without labels
+fn fit_knowledge(
+    entries: &[Knowledge],
+    dates: &[Option<DateLabel>],
+    labels: Option<(CitationKind, usize)>,
+    overhead: usize,
+    available: usize,
+) -> (usize, usize) {
+    let mut len = overhead;
+    let mut count = 0;
+    for (i, entry) in entries.iter().enumerate().take(MAX_KNOWLEDGE_ENTRIES) {
+        let label = labels.map(|(kind, first)| citations::marker(kind, first + i));
+        let entry_len = knowledge_entry_len(i + 1, entry, dates.get(i).and_then(Option::as_ref), label.as_deref());
+        if len + entry_len > available {
+            break;
+        }
+        len += entry_len;
+        count += 1;
+    }
+
+    if count == 0 { (0, 0) } else { (count, len) }
+}
+
+/// Count the most recent messages that fit, returning the count and the section length
+fn fit_history(history: &[Message], available: usize) -> (usize, usize) {
+    let mut len = HISTORY_HEADER.len();
+    let mut count = 0;
+    for message in history {
+        let note_len = if message.truncated { TRUNCATED_NOTE.len() } else { 0 };
+        let message_len = message.role.label().len() + note_len + ":\n".len() + message.content.len() + "\n\n".len();
+        if len + message_len > available {
+            break;
+        }
+        len += message_len;
+        count += 1;
+    }
+
+    if count == 0 { (0, 0) } else { (count, len) }
+}
+
+fn push_knowledge(buffer: &mut String, entries: &[Knowledge], dates: &[Option<DateLabel>], labels: Option<(CitationKind, usize)>) {
+    for (i, entry) in entries.iter().enumerate() {
+        // Writing to a String cannot fail
+        let _ = write!(buffer, "Knowledge {}", i + 1);
+        if let Some((kind, first)) = labels {
+            let _ = write!(buffer, " [{}]", citations::marker(kind, first + i));
+        }
+        if let Some(date) = dates.get(i).and_then(Option::as_ref) {
+            let _ = write!(buffer, "{}", date);
+        }
+        buffer.push_str(": ");
+        buffer.push_str(&entry.content);
+        buffer.push_str("\n\n");
+    }
+}
+
+fn decimal_len(mut n: usize) -> usize {
+    let mut len = 1;
+    while n >= 10 {
+        n /= 10;
+        len += 1;
+    }
+    len
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::MessageRole;
+    use crate::investment_chat::current_date::render_preamble;
+    use chrono::{DateTime, FixedOffset, NaiveDateTime, Utc};
+
+    fn message(role: MessageRole, content: &str) -> Message {
+        Message {
+            id: 0,
+            user_id: Some(1),
+            role,
+            content: content.to_string(),
+            created_at: NaiveDateTime::default(),
+            truncated: false,
+        }
+    }
+
+    fn knowledge(content: &str) -> Knowledge {
+        Knowledge {
+            id: 0,
+            user_id: 1,
+            source_id: "source".to_string(),
+            content: content.to_string(),
+            tags: vec![],
+            created_at: NaiveDateTime::default(),
+            updated_at: NaiveDateTime::default(),
+        }
+    }
+
+    /// The prompt as `process_message` assembled it before the builder existed
+    fn legacy_prompt(input: &PromptInput<'_>) -> String {
+        let format_knowledge = |entries: &[Knowledge]| {
+            let mut combined = String::new();
+            for (i, entry) in entries.iter().enumerate().take(2) {
+                combined.push_str(&format!("Knowledge {}: {}\n\n", i + 1, entry.content));
+            }
+            combined
+        };
+
+        let mut context = String::new();
+        for (project, entries) in input.research {
+            let existing = format_knowledge(entries);
+            if !existing.is_empty() {
+                context.push_str(&format!("Research about {}:\n\n{}\n\n", project, existing));
+            }
+        }
+        let relevant = format_knowledge(input.knowledge);
+        if !relevant.is_empty() {
+            context.push_str(&format!("Relevant knowledge:\n\n{}\n\n", relevant));
+        }
+
+        let mut conversation_context = String::new();
+        if !input.history.is_empty() {
+            conversation_context.push_str("RECENT CONVERSATION HISTORY:\n");
+            for message in input.history {
+                conversation_context.push_str(&format!("{}:\n{}", message.role.as_str().to_uppercase(), message.content));
+                conversation_context.push_str("\n\n");
+            }
+        }
+
+        let planning_instructions = &PLANNING_INSTRUCTIONS[..PLANNING_INSTRUCTIONS.len() - 1];
+        if input.planning {
+            format!(
+                "{}{}\n{}{}\nCONTEXT INFORMATION:\n{}\n\nUSER QUERY: {}",
+                PLANNING_HEADER, planning_instructions, PLANNING_FORMAT, conversation_context, context, input.user_message
+            )
+        } else {
+            format!(
+                "{}{}\n{}\nCONTEXT INFORMATION:\n{}\n\nUSER QUERY: {}",
+                ADVISOR_HEADER, planning_instructions, conversation_context, context, input.user_message
+            )
+        }
+    }
+
+    #[test]
+    fn test_matches_legacy_prompt() {
+        let history = vec![message(MessageRole::Assistant, "AERO is trading at $1.20"), message(MessageRole::User, "What about AERO?")];
+        let research = vec![
+            ("aerodrome".to_string(), vec![knowledge("Aerodrome is a Base DEX"), knowledge("veAERO locks"), knowledge("dropped")]),
+            ("velodrome".to_string(), vec![knowledge("Velodrome is an Optimism DEX")]),
+            ("pendle".to_string(), Vec::new()),
+        ];
+        let relevant = vec![knowledge("DCA weekly")];
+
+        for planning in [false, true] {
+            let input = PromptInput {
+                user_message: "Plan an AERO strategy",
+                planning,
+                history: &history,
+                cards: &[],
+                research: &research,
+                knowledge: &relevant,
+            };
+            let mut builder = PromptBuilder::default();
+            assert_eq!(builder.build(&input), legacy_prompt(&input));
+        }
+
+        let empty = PromptInput {
+            user_message: "hello",
+            ..Default::default()
+        };
+        assert_eq!(PromptBuilder::default().build(&empty), legacy_prompt(&empty));
+    }
+
+    #[test]
+    fn test_research_for_each_project_until_the_budget_runs_out() {
+        let research = vec![
+            ("solana".to_string(), vec![knowledge(&format!("solana {}", "x".repeat(400)))]),
+            ("arbitrum".to_string(), vec![knowledge(&format!("arbitrum {}", "y".repeat(400)))]),
+            ("aave".to_string(), vec![knowledge(&format!("aave {}", "z".repeat(4_000)))]),
+        ];
+        let input = PromptInput {
+            user_message: "Solana or Arbitrum, and what about Aave?",
+            research: &research,
+            ..Default::default()
+        };
+
+        let mut builder = PromptBuilder::new(1_000);
+        let prompt = builder.build(&input).to_string();
+        assert!(estimate_tokens(&prompt) <= 1_000);
+        assert!(prompt.contains("Research about solana:\n\nKnowledge 1: solana "));
+        assert!(prompt.contains("Research about arbitrum:\n\nKnowledge 1: arbitrum "));
+        assert!(!prompt.contains("Research about aave"));
+    }
+
+    #[test]
+    fn test_sources_are_the_included_entries() {
+        let sourced = |source_id: &str, content: &str| Knowledge { source_id: source_id.to_string(), ..knowledge(content) };
+        let research = vec![
+            ("solana".to_string(), vec![sourced("solana-notes", "solana fees"), sourced("solana-notes", "solana validators")]),
+            ("aave".to_string(), vec![sourced("aave-notes", &"z".repeat(4_000))]),
+        ];
+        let relevant = vec![sourced("dca", "DCA weekly"), sourced("solana-notes", "solana staking")];
+        let input = PromptInput {
+            user_message: "Solana or Aave?",
+            research: &research,
+            knowledge: &relevant,
+            ..Default::default()
+        };
+
+        let mut builder = PromptBuilder::new(1_000);
+        builder.build(&input);
+        // Each source once, and none for entries left out of the prompt
+        assert_eq!(builder.sources(), ["solana-notes", "dca"]);
+
+        builder.build(&PromptInput { user_message: "hi", ..Default::default() });
+        assert!(builder.sources().is_empty());
+    }
+
+    #[test]
+    fn test_citations_label_the_included_entries() {
+        let sourced = |source_id: &str, content: &str| Knowledge { source_id: source_id.to_string(), ..knowledge(content) };
+        let research = vec![
+            ("aave".to_string(), vec![sourced("aave_research_1", "Summary from https://aave.com: audited"), sourced("aave-notes", "V4 hubs")]),
+            ("pendle".to_string(), vec![sourced("pendle-notes", "Pendle splits yield")]),
+        ];
+        let relevant = vec![sourced("dca", "DCA weekly")];
+        let input = PromptInput { user_message: "Aave or Pendle?", research: &research, knowledge: &relevant, ..Default::default() };
+
+        let mut builder = PromptBuilder::default().with_citations(true);
+        let prompt = builder.build(&input).to_string();
+        assert!(prompt.contains("Research about aave:\n\nKnowledge 1 [R1]: Summary from https://aave.com: audited\n\nKnowledge 2 [R2]: V4 hubs"));
+        assert!(prompt.contains("Research about pendle:\n\nKnowledge 1 [R3]: Pendle splits yield"));
+        assert!(prompt.contains("Relevant knowledge:\n\nKnowledge 1 [K1]: DCA weekly"));
+        assert!(prompt.ends_with(&format!("{}\n\nUSER QUERY: Aave or Pendle?", CITATION_INSTRUCTIONS)));
+
+        let injected: Vec<(String, &str)> = builder.citations().iter().map(|c| (c.marker(), c.source_id.as_str())).collect();
+        assert_eq!(injected, [
+            ("R1".to_string(), "aave_research_1"),
+            ("R2".to_string(), "aave-notes"),
+            ("R3".to_string(), "pendle-notes"),
+            ("K1".to_string(), "dca"),
+        ]);
+        assert_eq!(builder.citations()[0].urls, ["https://aave.com"]);
+
+        // Nothing to cite, no instruction and no record
+        let prompt = builder.build(&PromptInput { user_message: "hi", ..Default::default() }).to_string();
+        assert!(!prompt.contains(CITATION_INSTRUCTIONS));
+        assert!(builder.citations().is_empty());
+        // Without citations the entries stay unlabelled
+        assert!(PromptBuilder::default().build(&input).contains("Knowledge 1: DCA weekly"));
+        assert!(PromptBuilder::default().citations().is_empty());
+    }
+
+    #[test]
+    fn test_citation_labels_count_against_the_budget() {
+        let research = vec![("aave".to_string(), vec![knowledge(&"x".repeat(400)), knowledge(&"y".repeat(400))])];
+        let input = PromptInput { user_message: "aave?", research: &research, ..Default::default() };
+
+        for budget in 300..800 {
+            let mut builder = PromptBuilder::new(budget).with_citations(true);
+            let prompt = builder.build(&input).to_string();
+            assert!(estimate_tokens(&prompt) <= budget, "over budget {}", budget);
+            assert_eq!(prompt.contains(CITATION_INSTRUCTIONS), !builder.citations().is_empty());
+            assert_eq!(prompt.matches("]: ").count(), builder.citations().len());
+        }
+    }
+
+    #[test]
+    fn test_cards_come_first_in_the_context() {
+        let cards = vec!["Chainlink (LINK), #12 by market cap\nAn oracle network.".to_string(), "x".repeat(8_000)];
+        let research = vec![("chainlink".to_string(), vec![knowledge("LINK staking v0.2")])];
+        let input = PromptInput {
+            user_message: "is it worth staking?",
+            cards: &cards,
+            research: &research,
+            ..Default::default()
+        };
+
+        let mut builder = PromptBuilder::new(1_500);
+        let prompt = builder.build(&input).to_string();
+        assert!(estimate_tokens(&prompt) <= 1_500);
+        assert!(prompt.contains(
+            "CONTEXT INFORMATION:\nCoin facts from CoinGecko:\n\nChainlink (LINK), #12 by market cap\nAn oracle network.\n\nResearch about chainlink:"
+        ));
+        // A card that doesn't fit is left out whole
+        assert!(!prompt.contains("xxx"));
+    }
+
+    #[test]
+    fn test_budget_keeps_knowledge_and_newest_history() {
+        let history: Vec<Message> = (0..100)
+            .map(|i| message(MessageRole::User, &format!("message {} {}", i, "x".repeat(200))))
+            .collect();
+        let relevant = vec![knowledge("keep me")];
+        let input = PromptInput {
+            user_message: "hi",
+            planning: false,
+            history: &history,
+            cards: &[],
+            research: &[],
+            knowledge: &relevant,
+        };
+
+        let mut builder = PromptBuilder::new(1_000);
+        let prompt = builder.build(&input).to_string();
+        assert!(estimate_tokens(&prompt) <= 1_000);
+        assert!(prompt.contains("Knowledge 1: keep me"));
+        assert!(prompt.contains("message 0 "));
+        assert!(!prompt.contains("message 99 "));
+        assert!(prompt.ends_with("USER QUERY: hi"));
+    }
+
+    #[test]
+    fn test_interrupted_answers_are_marked_in_history() {
+        let mut cut_off = message(MessageRole::Assistant, "Aerodrome pays emissions to\n\n[response interrupted]");
+        cut_off.truncated = true;
+        let history = vec![message(MessageRole::User, "what is AERO?"), cut_off];
+        let input = PromptInput { user_message: "go on", history: &history, ..Default::default() };
+
+        let prompt = PromptBuilder::default().build(&input).to_string();
+        assert!(prompt.contains("USER:\nwhat is AERO?\n\n"));
+        assert!(prompt.contains(&format!("ASSISTANT{}:\nAerodrome pays emissions to", TRUNCATED_NOTE)));
+        assert_eq!(prompt.matches(TRUNCATED_NOTE).count(), 1);
+
+        // The note counts against the budget like the rest of the message
+        let (count, len) = fit_history(&history, usize::MAX);
+        assert_eq!(count, 2);
+        let plain: usize = history.iter().map(|m| m.role.label().len() + ":\n".len() + m.content.len() + "\n\n".len()).sum();
+        assert_eq!(len, HISTORY_HEADER.len() + plain + TRUNCATED_NOTE.len());
+    }
+
+    #[test]
+    fn test_no_room_for_retrieval() {
+        let builder = PromptBuilder::new(10);
+        assert_eq!(builder.retrieval_budget(true, "hello"), 0);
+
+        let history = vec![message(MessageRole::User, "dropped")];
+        let input = PromptInput {
+            user_message: "hello",
+            history: &history,
+            ..Default::default()
+        };
+        let mut builder = PromptBuilder::new(10);
+        assert!(!builder.build(&input).contains("RECENT CONVERSATION HISTORY"));
+
+        assert!(PromptBuilder::default().retrieval_budget(false, "hello") > 10_000);
+    }
+
+    #[test]
+    fn test_buffer_is_reused() {
+        let mut builder = PromptBuilder::default();
+        let long = "a".repeat(5_000);
+        builder.build(&PromptInput { user_message: &long, ..Default::default() });
+        let capacity = builder.buffer.capacity();
+
+        let prompt = builder.build(&PromptInput { user_message: "short", ..Default::default() });
+        assert!(prompt.ends_with("USER QUERY: short"));
+        assert_eq!(builder.buffer.capacity(), capacity);
+    }
+
+    #[test]
+    fn test_length_instruction_and_max_tokens_follow_verbosity() {
+        let input = PromptInput { user_message: "is ETH a good buy?", ..Default::default() };
+
+        let mut brief = PromptBuilder::default().with_verbosity(Verbosity::Brief);
+        let prompt = brief.build(&input);
+        assert!(prompt.contains(BRIEF_INSTRUCTIONS));
+        assert!(!prompt.contains("PLANNING STEPS"));
+
+        let mut normal = PromptBuilder::default();
+        let prompt = normal.build(&input);
+        assert!(!prompt.contains("LENGTH:"));
+        assert!(prompt.contains("PLANNING STEPS"));
+
+        let mut detailed = PromptBuilder::default().with_verbosity(Verbosity::Detailed);
+        let prompt = detailed.build(&PromptInput { planning: true, ..input });
+        assert!(prompt.contains(DETAILED_INSTRUCTIONS));
+        assert!(prompt.contains(PLANNING_FORMAT));
+
+        assert!(brief.max_tokens() < normal.max_tokens());
+        assert!(normal.max_tokens() < detailed.max_tokens());
+    }
+
+    #[test]
+    fn test_brief_prompt_leaves_more_room_for_retrieval() {
+        let brief = PromptBuilder::new(1_000).with_verbosity(Verbosity::Brief);
+        let normal = PromptBuilder::new(1_000);
+        assert!(brief.retrieval_budget(false, "hi") > normal.retrieval_budget(false, "hi"));
+    }
+
+    #[test]
+    fn test_system_override_is_layered_between_base_and_standing_instructions() {
+        let history = vec![message(MessageRole::User, "earlier question")];
+        let system_override = SystemPromptOverride::new("Talk like a terse floor trader. Skip the disclaimers.").unwrap();
+        let input = PromptInput { user_message: "is ETH a good buy?", planning: true, history: &history, ..Default::default() };
+
+        let mut builder = PromptBuilder::default().with_verbosity(Verbosity::Detailed).with_system_override(Some(&system_override));
+        let prompt = builder.build(&input).to_string();
+        let position = |part: &str| prompt.find(part).unwrap_or_else(|| panic!("missing {:?}", part));
+        let order = [
+            position(PLANNING_HEADER),
+            position("SESSION INSTRUCTIONS:\nTalk like a terse floor trader. Skip the disclaimers.\n\n"),
+            position("PLANNING STEPS:"),
+            position(PLANNING_FORMAT),
+            position(DETAILED_INSTRUCTIONS),
+            position(SAFETY_SUFFIX),
+            position(HISTORY_HEADER),
+            position("USER QUERY: is ETH a good buy?"),
+        ];
+        assert!(order.is_sorted(), "parts out of order: {:?}", order);
+
+        // The override counts against the budget like any fixed part
+        let plain = PromptBuilder::default().with_verbosity(Verbosity::Detailed);
+        assert!(builder.retrieval_budget(true, input.user_message) < plain.retrieval_budget(true, input.user_message));
+    }
+
+    #[test]
+    fn test_compliance_instructions_outrank_the_override() {
+        let input = PromptInput { user_message: "should I buy SOL?", ..Default::default() };
+        assert!(!PromptBuilder::default().build(&input).contains(compliance::PROMPT_INSTRUCTIONS));
+
+        let system_override = SystemPromptOverride::new("Give bold calls with exact amounts.").unwrap();
+        let mut builder = PromptBuilder::default().with_system_override(Some(&system_override)).with_compliance(true);
+        let prompt = builder.build(&input).to_string();
+        let position = |part: &str| prompt.find(part).unwrap_or_else(|| panic!("missing {:?}", part));
+        let order = [position("Give bold calls"), position(compliance::PROMPT_INSTRUCTIONS), position(SAFETY_SUFFIX)];
+        assert!(order.is_sorted(), "parts out of order: {:?}", order);
+        assert_eq!(prompt.matches(compliance::PROMPT_INSTRUCTIONS).count(), 1);
+    }
+
+    #[test]
+    fn test_safety_suffix_only_with_an_override() {
+        let input = PromptInput { user_message: "hi", ..Default::default() };
+        assert!(!PromptBuilder::default().build(&input).contains(SAFETY_SUFFIX));
+
+        let system_override = SystemPromptOverride::new("Be upbeat.").unwrap();
+        let mut builder = PromptBuilder::default().with_verbosity(Verbosity::Brief).with_system_override(Some(&system_override));
+        assert!(builder.build(&input).starts_with(&format!("{}SESSION INSTRUCTIONS:\nBe upbeat.\n\n{}{}", ADVISOR_HEADER, BRIEF_INSTRUCTIONS, SAFETY_SUFFIX)));
+
+        // Clearing the override restores the default prompt
+        let mut cleared = builder.with_system_override(None);
+        assert_eq!(cleared.build(&input), PromptBuilder::default().with_verbosity(Verbosity::Brief).build(&input));
+    }
+
+    #[test]
+    fn test_preamble_appears_exactly_once() {
+        let now = DateTime::parse_from_rfc3339("2026-10-15T14:05:00Z").unwrap().with_timezone(&Utc);
+        let preamble = render_preamble(now, FixedOffset::east_opt(7_200).unwrap().into(), None);
+        let history = vec![message(MessageRole::User, "what about this weekend?")];
+        let system_override = SystemPromptOverride::new("Be upbeat.").unwrap();
+
+        for planning in [false, true] {
+            let input = PromptInput { user_message: "any unlocks this weekend?", planning, history: &history, ..Default::default() };
+            for verbosity in [Verbosity::Brief, Verbosity::Normal, Verbosity::Detailed] {
+                let mut builder = PromptBuilder::default()
+                    .with_verbosity(verbosity)
+                    .with_system_override(Some(&system_override))
+                    .with_preamble(&preamble);
+                let prompt = builder.build(&input).to_string();
+                assert_eq!(prompt.matches("CURRENT DATE:").count(), 1);
+                // Right after the base prompt, before any instructions the model might weigh more
+                let base = if planning { PLANNING_HEADER } else { ADVISOR_HEADER };
+                assert!(prompt.starts_with(&format!("{}{}SESSION INSTRUCTIONS:", base, preamble)));
+            }
+        }
+
+        // The preamble counts against the budget like any fixed part
+        let with_preamble = PromptBuilder::default().with_preamble(&preamble);
+        assert!(with_preamble.retrieval_budget(false, "hi") < PromptBuilder::default().retrieval_budget(false, "hi"));
+    }
+
+    fn dated(content: &str, tags: &[&str], created_at: &str) -> Knowledge {
+        Knowledge {
+            tags: tags.iter().map(|tag| tag.to_string()).collect(),
+            created_at: NaiveDateTime::parse_from_str(&format!("{} 12:00:00", created_at), "%Y-%m-%d %H:%M:%S").unwrap(),
+            ..knowledge(content)
+        }
+    }
+
+    fn date(date: &str) -> NaiveDate {
+        NaiveDate::parse_from_str(date, "%Y-%m-%d").unwrap()
+    }
+
+    fn now() -> NaiveDateTime {
+        NaiveDateTime::parse_from_str("2026-10-15 12:00:00", "%Y-%m-%d %H:%M:%S").unwrap()
+    }
+
+    #[test]
+    fn test_find_conflicts_dates_old_knowledge_and_fresh_research_of_a_project() {
+        let aave = [
+            dated("Aave has 16M AAVE in circulation", &["aave"], "2025-01-10"),
+            dated("Aave now has 15M AAVE in circulation", &["aave", "research", "exa_api"], "2026-10-12"),
+        ];
+        let pendle = [dated("Pendle splits yield", &["pendle"], "2025-02-01")];
+        let relevant = [
+            dated("AAVE supply is capped", &["Aave"], "2024-12-01"),
+            dated("DCA weekly", &["strategy"], "2024-12-01"),
+        ];
+        let research: Vec<(&str, &[Knowledge])> = vec![("aave", &aave), ("pendle", &pendle)];
+
+        let conflicts = find_conflicts(&research, &relevant, now(), Duration::days(30));
+        assert_eq!(conflicts.research, vec![
+            vec![Some(DateLabel::Stored(date("2025-01-10"))), Some(DateLabel::Researched(date("2026-10-12")))],
+            vec![None],
+        ]);
+        // Keyword knowledge about the project is dated too, case aside
+        assert_eq!(conflicts.knowledge, vec![Some(DateLabel::Stored(date("2024-12-01"))), None]);
+        assert!(!conflicts.is_empty());
+    }
+
+    #[test]
+    fn test_find_conflicts_needs_both_sides() {
+        let fresh = dated("Aave now has 15M AAVE", &["aave", "exa_api"], "2026-10-12");
+        let recent = dated("Aave v4 is live", &["aave"], "2026-10-01");
+        let old = dated("Aave has 16M AAVE", &["aave"], "2025-01-10");
+        let old_research = dated("Aave had 14M AAVE", &["aave", "exa_api"], "2025-06-01");
+
+        for entries in [vec![fresh.clone(), recent], vec![old.clone(), old_research], vec![fresh.clone()]] {
+            let research: Vec<(&str, &[Knowledge])> = vec![("aave", &entries)];
+            assert!(find_conflicts(&research, &[], now(), Duration::days(30)).is_empty());
+        }
+
+        // The old entry can come from the keyword knowledge
+        let entries = [fresh];
+        let research: Vec<(&str, &[Knowledge])> = vec![("aave", &entries)];
+        let conflicts = find_conflicts(&research, &[old], now(), Duration::days(30));
+        assert_eq!(conflicts.research, vec![vec![Some(DateLabel::Researched(date("2026-10-12")))]]);
+        assert_eq!(conflicts.knowledge, vec![Some(DateLabel::Stored(date("2025-01-10")))]);
+
+        // Nothing to compare without research blocks
+        assert!(find_conflicts(&[], &entries, now(), Duration::days(30)).is_empty());
+    }
+
+    #[test]
+    fn test_conflicting_entries_are_dated_in_the_prompt() {
+        let research = vec![(
+            "aave".to_string(),
+            vec![
+                dated("Aave has 16M AAVE in circulation", &["aave"], "2025-01-10"),
+                dated("Aave now has 15M AAVE in circulation", &["aave", "exa_api"], "2026-10-12"),
+            ],
+        )];
+        let input = PromptInput { user_message: "what is AAVE's supply?", research: &research, ..Default::default() };
+
+        let mut builder = PromptBuilder::default().with_now(now());
+        let prompt = builder.build(&input).to_string();
+        assert!(prompt.contains(
+            "Research about aave:\n\nKnowledge 1 (stored 2025-01-10): Aave has 16M AAVE in circulation\n\n\
+            Knowledge 2 (researched 2026-10-12): Aave now has 15M AAVE in circulation\n\n"
+        ));
+        assert!(prompt.ends_with(&format!("{}\n\nUSER QUERY: what is AAVE's supply?", CONFLICT_INSTRUCTIONS)));
+
+        // Once the old entry is no longer stale there is nothing to flag
+        let mut builder = PromptBuilder::default().with_now(research[0].1[0].created_at);
+        let prompt = builder.build(&input).to_string();
+        assert!(prompt.contains("Knowledge 1: Aave has 16M"));
+        assert!(!prompt.contains(CONFLICT_INSTRUCTIONS));
+    }
+
+    #[test]
+    fn test_dates_count_against_the_budget() {
+        let research = vec![(
+            "aave".to_string(),
+            vec![
+                dated(&format!("old {}", "x".repeat(400)), &["aave"], "2025-01-10"),
+                dated(&format!("new {}", "y".repeat(400)), &["aave", "exa_api"], "2026-10-12"),
+            ],
+        )];
+        let input = PromptInput { user_message: "aave supply?", research: &research, ..Default::default() };
+
+        for budget in 400..1_000 {
+            let mut builder = PromptBuilder::new(budget).with_now(now());
+            let prompt = builder.build(&input).to_string();
+            assert!(estimate_tokens(&prompt) <= budget, "over budget {}", budget);
+            // The instruction only goes with a dated pair that made it in
+            assert_eq!(prompt.contains(CONFLICT_INSTRUCTIONS), prompt.contains("(researched 2026-10-12)"));
+            assert_eq!(prompt.contains("(stored 2025-01-10)"), prompt.contains("(researched 2026-10-12)"));
+        }
+        assert!(PromptBuilder::new(1_000).with_now(now()).build(&input).contains(CONFLICT_INSTRUCTIONS));
+        assert!(!PromptBuilder::new(400).with_now(now()).build(&input).contains(CONFLICT_INSTRUCTIONS));
+    }
+
+    #[test]
+    fn test_diversification_prompt_carries_the_computed_figures() {
+        let prompt = diversification_prompt("Concentration: largest position bitcoin at 80.0%", "is my portfolio diversified?");
+        assert!(prompt.starts_with(DIVERSIFICATION_HEADER));
+        assert!(prompt.contains("PORTFOLIO ANALYSIS:\nConcentration: largest position bitcoin at 80.0%\n\nUSER QUERY: "));
+        assert!(prompt.ends_with("is my portfolio diversified?"));
+    }
+}
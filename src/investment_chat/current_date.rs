@@ -0,0 +1,293 @@
+use crate::db::PricePoint;
+use crate::price_format::format_price;
+use crate::timezone::UserTimezone;
+use chrono::{DateTime, Duration, FixedOffset, Months, NaiveDate, NaiveDateTime, Utc};
+use regex::Regex;
+use std::sync::OnceLock;
+
+/// Cached prices older than this are left out of the market snapshot
+pub const MARKET_SNAPSHOT_MAX_AGE_HOURS: i64 = 6;
+
+/// Furthest a UTC offset can be from UTC, in hours (Kiribati is at +14)
+const MAX_OFFSET_HOURS: i32 = 14;
+
+/// Bitcoin's cached price for the prompt preamble
+#[derive(Debug, Clone, PartialEq)]
+pub struct MarketSnapshot {
+    pub price_usd: f64,
+    /// Change from the last price cached at least a day earlier, None when there is none
+    pub change_24h_pct: Option<f64>,
+    pub as_of: NaiveDateTime,
+}
+
+impl MarketSnapshot {
+    /// Snapshot from the latest cached price and the last one a day before it
+    /// None when there is no cached price or the latest is too old to call current
+    pub fn from_history(latest: Option<&PricePoint>, day_before: Option<&PricePoint>, now: DateTime<Utc>) -> Option<Self> {
+        let latest = latest?;
+        if now.naive_utc() - latest.fetched_at > Duration::hours(MARKET_SNAPSHOT_MAX_AGE_HOURS) {
+            return None;
+        }
+        let change_24h_pct = day_before
+            .filter(|before| before.price_usd > 0.0)
+            .map(|before| (latest.price_usd - before.price_usd) / before.price_usd * 100.0);
+        Some(Self { price_usd: latest.price_usd, change_24h_pct, as_of: latest.fetched_at })
+    }
+}
+
+/// Date, timezone and market lines opening every advisor prompt
+///
+/// Without them the model falls back to the year of its training data and can't place
+/// "this weekend" or "last week".
+pub fn render_preamble(now: DateTime<Utc>, timezone: UserTimezone, market: Option<&MarketSnapshot>) -> String {
+    let mut preamble = format!("CURRENT DATE: {} UTC.", now.format("%A %-d %B %Y, %H:%M"));
+    if timezone.is_utc() {
+        preamble.push_str(" The user's timezone is UTC.");
+    } else {
+        let local = now.with_timezone(&timezone);
+        preamble.push_str(&format!(
+            " The user's timezone is {}, where it is {}.",
+            timezone,
+            local.format("%A %-d %B, %H:%M")
+        ));
+    }
+    preamble.push_str(" Use this date for \"today\", \"this weekend\" and other relative dates, not your training data.\n");
+
+    if let Some(market) = market {
+        preamble.push_str(&format!("MARKET SNAPSHOT: BTC {}", format_price(market.price_usd)));
+        if let Some(change) = market.change_24h_pct {
+            preamble.push_str(&format!(", {:+.2}% over 24h", change));
+        }
+        preamble.push_str(&format!(" (cached {} UTC).\n", market.as_of.format("%H:%M")));
+    }
+    preamble.push('\n');
+    preamble
+}
+
+/// Parse a UTC offset like "UTC", "UTC+2", "GMT-05:30" or "+0530"
+pub fn parse_utc_offset(text: &str) -> Option<FixedOffset> {
+    static OFFSET: OnceLock<Regex> = OnceLock::new();
+    let offset = OFFSET.get_or_init(|| {
+        Regex::new(r"(?i)^\s*(?:utc|gmt|z)?\s*(?:(?P<sign>[+-])\s*(?P<hours>\d{1,2})(?::?(?P<minutes>\d{2}))?)?\s*$").unwrap()
+    });
+
+    let text = text.trim();
+    if text.is_empty() {
+        return None;
+    }
+    let captures = offset.captures(text)?;
+    let Some(sign) = captures.name("sign") else {
+        return FixedOffset::east_opt(0);
+    };
+    let hours: i32 = captures["hours"].parse().ok()?;
+    let minutes: i32 = captures.name("minutes").map_or(Some(0), |minutes| minutes.as_str().parse().ok())?;
+    if hours > MAX_OFFSET_HOURS || minutes >= 60 || (hours == MAX_OFFSET_HOURS && minutes > 0) {
+        return None;
+    }
+
+    let seconds = (hours * 60 + minutes) * 60;
+    FixedOffset::east_opt(if sign.as_str() == "-" { -seconds } else { seconds })
+}
+
+/// A UTC offset as "UTC" or "UTC+05:30", the form offsets are stored in the `timezone` column of `users`
+pub fn format_utc_offset(offset: FixedOffset) -> String {
+    let seconds = offset.local_minus_utc();
+    if seconds == 0 {
+        return "UTC".to_string();
+    }
+    let sign = if seconds < 0 { '-' } else { '+' };
+    let minutes = seconds.abs() / 60;
+    format!("UTC{}{:02}:{:02}", sign, minutes / 60, minutes % 60)
+}
+
+/// A "my timezone is ..." message
+#[derive(Debug, Clone, PartialEq)]
+pub enum TimezonePreference {
+    Zone(UserTimezone),
+    /// Neither a UTC offset nor a zone in the timezone database
+    Unknown(String),
+}
+
+/// Parse "my timezone is Europe/Berlin" or "set my timezone to GMT-5"
+pub fn parse_timezone_preference(message: &str) -> Option<TimezonePreference> {
+    static PREFERENCE: OnceLock<Regex> = OnceLock::new();
+    let preference = PREFERENCE.get_or_init(|| {
+        Regex::new(r"(?i)^\s*(?:please\s+)?(?:my\s+time\s*zone\s+is|set\s+my\s+time\s*zone\s+to|change\s+my\s+time\s*zone\s+to)\s+(?P<zone>[a-z0-9_/:+\- ]+?)[\s.!]*$").unwrap()
+    });
+
+    let zone = preference.captures(message)?.name("zone")?.as_str().trim();
+    Some(match UserTimezone::parse(zone) {
+        Some(timezone) => TimezonePreference::Zone(timezone),
+        None => TimezonePreference::Unknown(zone.to_string()),
+    })
+}
+
+/// Confirmation of a saved timezone, or why it couldn't be saved
+pub fn timezone_reply(preference: &TimezonePreference) -> String {
+    match preference {
+        TimezonePreference::Zone(timezone) => format!(
+            "Got it, your timezone is {}. Dates like \"yesterday\", the times I show you and your daily briefing now follow it.",
+            timezone
+        ),
+        TimezonePreference::Unknown(zone) => format!(
+            "I don't know the timezone \"{}\". Try a zone name like \"my timezone is Europe/Berlin\" or a UTC offset like \"my timezone is UTC-05:30\".",
+            zone
+        ),
+    }
+}
+
+/// The day a phrase like "yesterday", "3 days ago", "a week ago" or "last month" refers to
+pub fn resolve_relative_date(phrase: &str, today: NaiveDate) -> Option<NaiveDate> {
+    let phrase = phrase.trim().to_lowercase();
+    let words: Vec<&str> = phrase.split_whitespace().collect();
+    match words.as_slice() {
+        ["today"] => Some(today),
+        ["yesterday"] => today.pred_opt(),
+        ["last", "week"] => today.checked_sub_signed(Duration::weeks(1)),
+        ["last", "month"] => today.checked_sub_months(Months::new(1)),
+        ["last", "year"] => today.checked_sub_months(Months::new(12)),
+        [count, unit, "ago"] => {
+            let count: u32 = match *count {
+                "a" | "one" => 1,
+                count => count.parse().ok()?,
+            };
+            match unit.trim_end_matches('s') {
+                "day" => today.checked_sub_signed(Duration::days(count.into())),
+                "week" => today.checked_sub_signed(Duration::weeks(count.into())),
+                "month" => today.checked_sub_months(Months::new(count)),
+                "year" => today.checked_sub_months(Months::new(count.checked_mul(12)?)),
+                _ => None,
+            }
+        },
+        _ => None,
+    }
+}
+
+/// A historical price question with a relative date, e.g. "what was the price of BTC 3 days ago",
+/// as the coin and the day it refers to
+pub fn find_relative_price_date(message: &str, today: NaiveDate) -> Option<(String, NaiveDate)> {
+    static QUERY: OnceLock<Regex> = OnceLock::new();
+    let query = QUERY.get_or_init(|| {
+        Regex::new(r"(?i)\b(?:price|value)\s+(?:of\s+|for\s+)?([a-z][a-z0-9-]*)\s+(yesterday|last\s+(?:week|month|year)|(?:a|one|\d{1,3})\s+(?:days?|weeks?|months?|years?)\s+ago)\b").unwrap()
+    });
+
+    let captures = query.captures(message)?;
+    let date = resolve_relative_date(&captures[2], today)?;
+    Some((captures[1].to_lowercase(), date))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn now() -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339("2026-10-15T22:30:00Z").unwrap().with_timezone(&Utc)
+    }
+
+    fn point(price_usd: f64, fetched_at: &str) -> PricePoint {
+        PricePoint {
+            id: 0,
+            coin_id: "bitcoin".to_string(),
+            price_usd,
+            fetched_at: NaiveDateTime::parse_from_str(fetched_at, "%Y-%m-%d %H:%M").unwrap(),
+        }
+    }
+
+    #[test]
+    fn test_preamble_in_the_users_timezone() {
+        let market = MarketSnapshot::from_history(
+            Some(&point(67_250.0, "2026-10-15 22:00")),
+            Some(&point(66_000.0, "2026-10-14 21:55")),
+            now(),
+        );
+        let preamble = render_preamble(now(), parse_utc_offset("UTC+2").unwrap().into(), market.as_ref());
+        assert_eq!(
+            preamble,
+            "CURRENT DATE: Thursday 15 October 2026, 22:30 UTC. The user's timezone is UTC+02:00, where it is Friday 16 \
+            October, 00:30. Use this date for \"today\", \"this weekend\" and other relative dates, not your training data.\n\
+            MARKET SNAPSHOT: BTC $67250.00, +1.89% over 24h (cached 22:00 UTC).\n\n"
+        );
+
+        let berlin = render_preamble(now(), UserTimezone::Named(chrono_tz::Europe::Berlin), None);
+        assert!(berlin.contains(" The user's timezone is Europe/Berlin, where it is Friday 16 October, 00:30."));
+
+        let utc = render_preamble(now(), UserTimezone::default(), None);
+        assert!(utc.starts_with("CURRENT DATE: Thursday 15 October 2026, 22:30 UTC. The user's timezone is UTC. Use"));
+        assert!(!utc.contains("MARKET SNAPSHOT"));
+    }
+
+    #[test]
+    fn test_market_snapshot_skips_stale_or_missing_prices() {
+        assert_eq!(MarketSnapshot::from_history(None, None, now()), None);
+        assert_eq!(MarketSnapshot::from_history(Some(&point(67_000.0, "2026-10-15 12:00")), None, now()), None);
+
+        let without_change = MarketSnapshot::from_history(Some(&point(67_000.0, "2026-10-15 20:00")), None, now()).unwrap();
+        assert_eq!(without_change.change_24h_pct, None);
+        assert!(render_preamble(now(), UserTimezone::default(), Some(&without_change))
+            .contains("MARKET SNAPSHOT: BTC $67000.00 (cached 20:00 UTC).\n"));
+    }
+
+    #[test]
+    fn test_parse_utc_offset() {
+        let offset = |text: &str| parse_utc_offset(text).map(format_utc_offset);
+        assert_eq!(offset("UTC").as_deref(), Some("UTC"));
+        assert_eq!(offset("gmt+0").as_deref(), Some("UTC"));
+        assert_eq!(offset("UTC+2").as_deref(), Some("UTC+02:00"));
+        assert_eq!(offset("GMT-05:30").as_deref(), Some("UTC-05:30"));
+        assert_eq!(offset("+0545").as_deref(), Some("UTC+05:45"));
+        assert_eq!(offset("UTC+14").as_deref(), Some("UTC+14:00"));
+        assert_eq!(offset("UTC+15"), None);
+        assert_eq!(offset("UTC+3:75"), None);
+        assert_eq!(offset("Europe/Berlin"), None);
+        assert_eq!(offset(""), None);
+    }
+
+    #[test]
+    fn test_parse_timezone_preference() {
+        assert_eq!(
+            parse_timezone_preference("my timezone is UTC+2"),
+            Some(TimezonePreference::Zone(FixedOffset::east_opt(7_200).unwrap().into()))
+        );
+        assert_eq!(
+            parse_timezone_preference("Please set my time zone to GMT-5."),
+            Some(TimezonePreference::Zone(FixedOffset::west_opt(18_000).unwrap().into()))
+        );
+        let berlin = parse_timezone_preference("my timezone is europe/berlin").unwrap();
+        assert_eq!(berlin, TimezonePreference::Zone(UserTimezone::Named(chrono_tz::Europe::Berlin)));
+        assert!(timezone_reply(&berlin).starts_with("Got it, your timezone is Europe/Berlin."));
+        let unknown = parse_timezone_preference("my timezone is Middle Earth").unwrap();
+        assert_eq!(unknown, TimezonePreference::Unknown("Middle Earth".to_string()));
+        assert!(timezone_reply(&unknown).contains("I don't know the timezone \"Middle Earth\""));
+        assert_eq!(parse_timezone_preference("what timezone is the fed meeting in?"), None);
+    }
+
+    #[test]
+    fn test_resolve_relative_date() {
+        let today = NaiveDate::from_ymd_opt(2026, 3, 31).unwrap();
+        let resolve = |phrase: &str| resolve_relative_date(phrase, today).map(|date| date.to_string());
+        assert_eq!(resolve("today").as_deref(), Some("2026-03-31"));
+        assert_eq!(resolve("Yesterday").as_deref(), Some("2026-03-30"));
+        assert_eq!(resolve("3 days ago").as_deref(), Some("2026-03-28"));
+        assert_eq!(resolve("a week ago").as_deref(), Some("2026-03-24"));
+        assert_eq!(resolve("last week").as_deref(), Some("2026-03-24"));
+        // Month arithmetic clamps to the end of shorter months
+        assert_eq!(resolve("one month ago").as_deref(), Some("2026-02-28"));
+        assert_eq!(resolve("2 years ago").as_deref(), Some("2024-03-31"));
+        assert_eq!(resolve("next week"), None);
+        assert_eq!(resolve("3 fortnights ago"), None);
+    }
+
+    #[test]
+    fn test_find_relative_price_date() {
+        let today = NaiveDate::from_ymd_opt(2026, 10, 16).unwrap();
+        assert_eq!(
+            find_relative_price_date("what was the price of BTC yesterday?", today),
+            Some(("btc".to_string(), NaiveDate::from_ymd_opt(2026, 10, 15).unwrap()))
+        );
+        assert_eq!(
+            find_relative_price_date("value of aerodrome-finance 2 weeks ago", today),
+            Some(("aerodrome-finance".to_string(), NaiveDate::from_ymd_opt(2026, 10, 2).unwrap()))
+        );
+        assert_eq!(find_relative_price_date("what's the price of ETH today", today), None);
+    }
+}
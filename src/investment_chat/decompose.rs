@@ -0,0 +1,310 @@
+use super::error::InvestmentChatError;
+use super::service;
+use crate::llm::{ChatModel, Message, json_payload};
+use crate::db::MessageRole;
+use async_trait::async_trait;
+use regex::Regex;
+use std::collections::HashSet;
+use std::sync::OnceLock;
+
+/// Most parts a message is split into, later questions stay with the last part
+pub const MAX_PARTS: usize = 3;
+
+/// Shortest message worth an assisted split, in words
+const ASSISTED_SPLIT_MIN_WORDS: usize = 12;
+
+/// Share of a suggested part's words that must come from the original message
+const MIN_WORD_OVERLAP: f64 = 0.8;
+
+const SPLIT_PROMPT: &str = "You split a user's message to a crypto investment assistant into independent questions. \
+Only split when the message asks about unrelated things that can each be answered on their own; keep follow-ups that \
+depend on each other together. Reuse the user's wording and repeat the subject a part refers to, e.g. \
+\"what's the price of btc\" and \"should I stake my sol or LP it?\". Reply with a JSON array of at most 3 strings only, \
+with a single element when the message asks one thing.";
+
+/// Words that open a question or request after "and"
+const QUESTION_STARTERS: &str = r"what|what's|whats|how|should|is|are|can|could|would|will|do|does|which|why|where|tell\s+me|give\s+me|show\s+me|explain";
+
+/// Splits a message that the heuristics can't
+#[async_trait]
+pub trait MessageSplitter: Send + Sync {
+    async fn split(&self, message: &str) -> Result<Vec<String>, InvestmentChatError>;
+}
+
+/// Splits messages with a single model call
+pub struct LlmSplitter<'a> {
+    model: &'a dyn ChatModel,
+}
+
+impl<'a> LlmSplitter<'a> {
+    pub fn new(model: &'a dyn ChatModel) -> Self {
+        Self { model }
+    }
+}
+
+#[async_trait]
+impl MessageSplitter for LlmSplitter<'_> {
+    async fn split(&self, message: &str) -> Result<Vec<String>, InvestmentChatError> {
+        let messages = [Message {
+            role: MessageRole::User,
+            content: message.to_string(),
+        }];
+        let reply = self.model
+            .generate_json(SPLIT_PROMPT, &messages, 256)
+            .await
+            .map_err(service::describe_llm_error)?;
+
+        Ok(parse_split(&reply, message))
+    }
+}
+
+/// Separators between questions: "?", ";", "and also", ", also" and "and" followed by a new question
+fn separator_regex() -> &'static Regex {
+    static REGEX: OnceLock<Regex> = OnceLock::new();
+    REGEX.get_or_init(|| {
+        Regex::new(&format!(
+            r"(?i)(?P<question>\?)\s+|;\s*|,?\s+and\s+also\b,?\s*|[,.]\s+also\b,?\s*|,?\s+and\s+(?P<starter>(?:{})\b)",
+            QUESTION_STARTERS
+        ))
+        .unwrap()
+    })
+}
+
+fn request_regex() -> &'static Regex {
+    static REGEX: OnceLock<Regex> = OnceLock::new();
+    REGEX.get_or_init(|| Regex::new(&format!(r"(?i)^(?:{}|please|check|compare|list|find)\b", QUESTION_STARTERS)).unwrap())
+}
+
+/// Parts that lean on the previous one, like "what do you think?"
+fn dependent_regex() -> &'static Regex {
+    static REGEX: OnceLock<Regex> = OnceLock::new();
+    REGEX.get_or_init(|| {
+        Regex::new(r"(?i)^(?:what\s+do\s+you\s+think|thoughts|right|ok(?:ay)?|thanks|thank\s+you|why(?:\s+not)?|how\s+so|any\s+(?:advice|tips|ideas)|is\s+that\s+(?:right|true|correct|safe)|does\s+that\s+make\s+sense)\W*$").unwrap()
+    })
+}
+
+/// The part without a leading "and" or "also" and trailing commas or periods
+fn clean_part(part: &str) -> String {
+    static LEADING: OnceLock<Regex> = OnceLock::new();
+    let leading = LEADING.get_or_init(|| Regex::new(r"(?i)^(?:and\s+)?(?:also\b,?\s*)?").unwrap());
+    let part = part.trim().trim_end_matches([',', '.']).trim();
+    leading.replace(part, "").trim().to_string()
+}
+
+/// Whether a part stands on its own as a question or request
+fn is_question(part: &str) -> bool {
+    part.split_whitespace().count() >= 3 && (part.ends_with('?') || request_regex().is_match(part))
+}
+
+/// Split a message into independent questions on conjunctions and punctuation
+///
+/// Returns the whole message as the only part unless every part reads as a question or request
+/// on its own, so context like "I hold 10 ETH. Should I stake it?" stays together.
+pub fn split_heuristic(message: &str) -> Vec<String> {
+    let whole = vec![message.trim().to_string()];
+
+    let mut pieces = Vec::new();
+    let mut start = 0;
+    for captures in separator_regex().captures_iter(message) {
+        let separator = captures.get(0).unwrap();
+        let end = match captures.name("question") {
+            Some(question) => question.end(),
+            None => separator.start(),
+        };
+        pieces.push(&message[start..end]);
+        start = match captures.name("starter") {
+            Some(starter) => starter.start(),
+            None => separator.end(),
+        };
+    }
+    pieces.push(&message[start..]);
+
+    let mut parts: Vec<String> = Vec::new();
+    for piece in pieces {
+        let piece = clean_part(piece);
+        if piece.is_empty() {
+            continue;
+        }
+        match parts.last_mut() {
+            Some(previous) if dependent_regex().is_match(&piece) => {
+                previous.push(' ');
+                previous.push_str(&piece);
+            },
+            _ => parts.push(piece),
+        }
+    }
+
+    if parts.len() < 2 || !parts.iter().all(|part| is_question(part)) {
+        return whole;
+    }
+    cap_parts(parts)
+}
+
+/// Keep at most `MAX_PARTS`, joining the overflow into the last one
+fn cap_parts(mut parts: Vec<String>) -> Vec<String> {
+    if parts.len() > MAX_PARTS {
+        let overflow = parts.split_off(MAX_PARTS - 1).join(" ");
+        parts.push(overflow);
+    }
+    parts
+}
+
+/// Whether a message the heuristics left whole may still ask several things
+pub fn needs_assisted_split(message: &str) -> bool {
+    static STARTER: OnceLock<Regex> = OnceLock::new();
+    let starter = STARTER.get_or_init(|| {
+        Regex::new(r"(?i)\b(?:should\s+i|what's|what\s+is|how\s+(?:do|does|much|many)|can\s+i|is\s+it|tell\s+me)\b").unwrap()
+    });
+    message.split_whitespace().count() >= ASSISTED_SPLIT_MIN_WORDS && starter.find_iter(message).count() >= 2
+}
+
+fn words(text: &str) -> Vec<String> {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(str::to_lowercase)
+        .collect()
+}
+
+/// Read the splitter's JSON array, falling back to the whole message
+///
+/// Parts must mostly reuse the message's words so a rewrite can't change what was asked
+pub fn parse_split(reply: &str, message: &str) -> Vec<String> {
+    let whole = vec![message.trim().to_string()];
+    let reply = json_payload(reply);
+    let (Some(start), Some(end)) = (reply.find('['), reply.rfind(']')) else {
+        return whole;
+    };
+    if end <= start {
+        return whole;
+    }
+    let parts: Vec<String> = match serde_json::from_str::<Vec<String>>(&reply[start..=end]) {
+        Ok(parts) => parts.iter().map(|part| clean_part(part)).filter(|part| !part.is_empty()).collect(),
+        Err(e) => {
+            eprintln!("Ignoring malformed message split: {}", e);
+            return whole;
+        },
+    };
+    if parts.len() < 2 || parts.len() > MAX_PARTS {
+        return whole;
+    }
+
+    let known: HashSet<String> = words(message).into_iter().collect();
+    let faithful = parts.iter().all(|part| {
+        let part_words = words(part);
+        let reused = part_words.iter().filter(|word| known.contains(*word)).count();
+        !part_words.is_empty() && reused as f64 / part_words.len() as f64 >= MIN_WORD_OVERLAP
+    });
+    if faithful { parts } else { whole }
+}
+
+/// Join the answers to each part under the question they answer
+pub fn stitch_answers(parts: &[String], answers: &[String]) -> String {
+    parts
+        .iter()
+        .zip(answers)
+        .map(|(part, answer)| format!("> {}\n\n{}", part, answer.trim()))
+        .collect::<Vec<_>>()
+        .join("\n\n---\n\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parts(values: &[&str]) -> Vec<String> {
+        values.iter().map(|value| value.to_string()).collect()
+    }
+
+    #[test]
+    fn test_splits_compound_messages() {
+        assert_eq!(
+            split_heuristic("what's the price of btc, and also should I stake my sol or LP it?"),
+            parts(&["what's the price of btc", "should I stake my sol or LP it?"])
+        );
+        assert_eq!(
+            split_heuristic("What's the price of ETH? How does Aerodrome's ve(3,3) model work?"),
+            parts(&["What's the price of ETH?", "How does Aerodrome's ve(3,3) model work?"])
+        );
+        assert_eq!(
+            split_heuristic("tell me the price of sol and should I move my stables to Aave"),
+            parts(&["tell me the price of sol", "should I move my stables to Aave"])
+        );
+        assert_eq!(
+            split_heuristic("how is my portfolio diversified; what's the sentiment around eth"),
+            parts(&["how is my portfolio diversified", "what's the sentiment around eth"])
+        );
+    }
+
+    #[test]
+    fn test_keeps_single_questions_whole() {
+        for message in [
+            "should I buy eth and sol?",
+            "what's the difference between staking and providing liquidity?",
+            "I hold 10 ETH. Should I stake it?",
+            "What's the price of ETH? I want to buy some this week",
+            "when I say blue chips I mean btc and eth",
+            "hi",
+        ] {
+            assert_eq!(split_heuristic(message), parts(&[message]), "split: {}", message);
+        }
+    }
+
+    #[test]
+    fn test_dependent_follow_ups_stay_with_their_question() {
+        assert_eq!(
+            split_heuristic("Should I stake my ETH on Lido? What do you think?"),
+            parts(&["Should I stake my ETH on Lido? What do you think?"])
+        );
+        assert_eq!(
+            split_heuristic("what's the price of btc? is that right? and how does Pendle work?"),
+            parts(&["what's the price of btc? is that right?", "how does Pendle work?"])
+        );
+    }
+
+    #[test]
+    fn test_caps_parts() {
+        let split = split_heuristic("what's the price of btc? what's the price of eth? what's the price of sol? what's the price of aave?");
+
+        assert_eq!(split.len(), MAX_PARTS);
+        assert_eq!(split[2], "what's the price of sol? what's the price of aave?");
+    }
+
+    #[test]
+    fn test_needs_assisted_split() {
+        assert!(needs_assisted_split("I was reading about restaking, what's the catch with EigenLayer compared to Lido and should I move my ETH there now"));
+        assert!(!needs_assisted_split("what's the price of btc"));
+        assert!(!needs_assisted_split("I was reading about restaking and wondering what the catch with EigenLayer compared to Lido is"));
+    }
+
+    #[test]
+    fn test_parse_split() {
+        let message = "I was reading about restaking, what's the catch with EigenLayer and should I move my ETH there";
+
+        assert_eq!(
+            parse_split(r#"["what's the catch with EigenLayer restaking", "should I move my ETH there"]"#, message),
+            parts(&["what's the catch with EigenLayer restaking", "should I move my ETH there"])
+        );
+        // Rewritten parts, a single part, too many parts and malformed replies keep the message whole
+        assert_eq!(parse_split(r#"["explain slashing risk on EigenLayer validators", "should I move my ETH there"]"#, message), parts(&[message]));
+        assert_eq!(parse_split(r#"["should I move my ETH there"]"#, message), parts(&[message]));
+        assert_eq!(parse_split(r#"["restaking", "EigenLayer", "ETH", "move"]"#, message), parts(&[message]));
+        assert_eq!(parse_split("not json", message), parts(&[message]));
+        assert_eq!(
+            parse_split("```json\n[\"what's the catch with EigenLayer\", \"should I move my ETH there\"]\n```\n###END###\nHope that [helps]", message),
+            parts(&["what's the catch with EigenLayer", "should I move my ETH there"])
+        );
+    }
+
+    #[test]
+    fn test_stitch_answers() {
+        let output = stitch_answers(
+            &parts(&["what's the price of btc", "should I stake my sol?"]),
+            &parts(&["The current price of Bitcoin is $60000.00\n", "Staking keeps..."]),
+        );
+
+        assert_eq!(
+            output,
+            "> what's the price of btc\n\nThe current price of Bitcoin is $60000.00\n\n---\n\n> should I stake my sol?\n\nStaking keeps..."
+        );
+    }
+}
@@ -0,0 +1,142 @@
+use super::turn::TurnResult;
+use chrono::{DateTime, Duration, Utc};
+use std::sync::Mutex;
+use tokio::sync::watch;
+
+/// Seconds an identical message counts as a re-send of the previous one, unless DUPLICATE_WINDOW_SECS says otherwise
+pub const DEFAULT_DUPLICATE_WINDOW_SECS: u64 = 20;
+
+/// The answer to a message, None while it is being worked out
+pub type PendingAnswer = watch::Receiver<Option<TurnResult>>;
+
+/// Whether `message` arriving at `now` re-sends `previous`, which arrived at `previous_at`
+///
+/// Only a byte-identical message within `window` counts; a zero window turns detection off.
+pub fn is_resend(previous: &str, previous_at: DateTime<Utc>, message: &str, now: DateTime<Utc>, window: Duration) -> bool {
+    !window.is_zero() && message == previous && now >= previous_at && now - previous_at <= window
+}
+
+/// What to do with an incoming message
+pub enum Admission {
+    /// Answer it, then send the answer so re-sends of it get the same one
+    New(watch::Sender<Option<TurnResult>>),
+    /// A re-send of the message being answered or just answered
+    Resend(PendingAnswer),
+}
+
+struct LastMessage {
+    text: String,
+    received_at: DateTime<Utc>,
+    answer: PendingAnswer,
+}
+
+impl LastMessage {
+    /// Whether answering it failed: the turn ended without sending an answer
+    fn failed(&self) -> bool {
+        self.answer.has_changed().is_err() && self.answer.borrow().is_none()
+    }
+}
+
+/// The session's last message, so a double enter or a client retry gets its answer instead of a second one
+///
+/// Any other message replaces it, so a question asked again later in the conversation is answered again.
+pub struct DuplicateGuard {
+    window: Duration,
+    last: Mutex<Option<LastMessage>>,
+}
+
+impl DuplicateGuard {
+    pub fn new(window: std::time::Duration) -> Self {
+        Self { window: Duration::from_std(window).unwrap_or(Duration::MAX), last: Mutex::new(None) }
+    }
+
+    /// Admit `message` arriving at `now`, a re-send of a message whose answer failed is answered again
+    pub fn admit(&self, message: &str, now: DateTime<Utc>) -> Admission {
+        let mut last = self.last.lock().unwrap();
+        if let Some(previous) = last.as_ref()
+            && is_resend(&previous.text, previous.received_at, message, now, self.window)
+            && !previous.failed()
+        {
+            return Admission::Resend(previous.answer.clone());
+        }
+
+        let (sender, answer) = watch::channel(None);
+        *last = Some(LastMessage { text: message.to_string(), received_at: now, answer });
+        Admission::New(sender)
+    }
+}
+
+/// The answer once it's in, None when answering the message failed
+pub async fn wait_for(mut answer: PendingAnswer) -> Option<TurnResult> {
+    answer.wait_for(Option::is_some).await.ok().and_then(|answer| answer.clone())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::investment_chat::Intent;
+    use chrono::TimeZone;
+
+    fn at(seconds: u32) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2025, 10, 16, 10, 0, seconds).unwrap()
+    }
+
+    fn guard() -> DuplicateGuard {
+        DuplicateGuard::new(std::time::Duration::from_secs(DEFAULT_DUPLICATE_WINDOW_SECS))
+    }
+
+    fn admit_new(guard: &DuplicateGuard, message: &str, now: DateTime<Utc>) -> watch::Sender<Option<TurnResult>> {
+        match guard.admit(message, now) {
+            Admission::New(sender) => sender,
+            Admission::Resend(_) => panic!("{} at {} was taken as a re-send", message, now),
+        }
+    }
+
+    #[test]
+    fn test_only_identical_messages_inside_the_window_are_resends() {
+        let window = Duration::seconds(20);
+        assert!(is_resend("btc price?", at(0), "btc price?", at(0), window));
+        assert!(is_resend("btc price?", at(0), "btc price?", at(20), window));
+        assert!(!is_resend("btc price?", at(0), "btc price?", at(21), window));
+        // Byte-identical only
+        assert!(!is_resend("btc price?", at(0), "btc price? ", at(1), window));
+        assert!(!is_resend("btc price?", at(0), "BTC price?", at(1), window));
+        // A clock that went back, or detection turned off
+        assert!(!is_resend("btc price?", at(5), "btc price?", at(4), window));
+        assert!(!is_resend("btc price?", at(0), "btc price?", at(0), Duration::zero()));
+    }
+
+    #[tokio::test]
+    async fn test_resends_get_the_answer_of_the_first_copy() {
+        let guard = guard();
+        let sender = admit_new(&guard, "btc price?", at(0));
+        let Admission::Resend(pending) = guard.admit("btc price?", at(3)) else { panic!("not a re-send") };
+
+        // The re-send waits for the answer still being worked out
+        let waiting = tokio::spawn(wait_for(pending));
+        sender.send_replace(Some(TurnResult::new(Intent::Price, "$67,500")));
+        assert_eq!(waiting.await.unwrap().unwrap().text, "$67,500");
+        assert!(matches!(guard.admit("btc price?", at(19)), Admission::Resend(_)));
+    }
+
+    #[test]
+    fn test_later_or_interleaved_repeats_are_answered_again() {
+        let guard = guard();
+        let _first = admit_new(&guard, "btc price?", at(0));
+        // Another message in between makes the repeat a new question
+        let _eth = admit_new(&guard, "eth price?", at(1));
+        let _again = admit_new(&guard, "btc price?", at(2));
+        // As does a repeat after the window
+        admit_new(&guard, "btc price?", at(23));
+    }
+
+    #[tokio::test]
+    async fn test_resends_of_a_failed_message_are_answered_again() {
+        let guard = guard();
+        let sender = admit_new(&guard, "btc price?", at(0));
+        let Admission::Resend(pending) = guard.admit("btc price?", at(1)) else { panic!("not a re-send") };
+        drop(sender);
+        assert_eq!(wait_for(pending).await, None);
+        admit_new(&guard, "btc price?", at(2));
+    }
+}
@@ -1,6 +1,13 @@
 use thiserror::Error;
 use crate::db::DbError;
+use crate::gas::GasError;
+use crate::exa_api::ExaApiError;
 use crate::price_fetcher::PriceError;
+use crate::retention::RetentionError;
+use crate::briefing::BriefingError;
+use crate::report::ReportError;
+use crate::rate_limit::RateLimitError;
+use crate::trading::TradingError;
 
 /// Investment chat error types
 #[derive(Debug, Error)]
@@ -9,13 +16,28 @@ pub enum InvestmentChatError {
     Database(#[from] DbError),
     
     #[error("Exa API error: {0}")]
-    ExaApi(String),
+    ExaApi(#[from] ExaApiError),
     
-    #[error("Price fetcher error: {0}")]
-    PriceFetcher(#[from] PriceError),
+    #[error("Price API error: {0}")]
+    PriceApi(#[from] PriceError),
+    
+    #[error("Retention error: {0}")]
+    Retention(#[from] RetentionError),
+    
+    #[error("Briefing error: {0}")]
+    Briefing(#[from] BriefingError),
+    
+    #[error("Report error: {0}")]
+    Report(#[from] ReportError),
+    
+    #[error("Gas oracle error: {0}")]
+    Gas(#[from] GasError),
     
-    #[error("Anthropic API error: {0}")]
-    AnthropicApi(String),
+    #[error("Trading error: {0}")]
+    Trading(#[from] TradingError),
+    
+    #[error("LLM API error: {0}")]
+    LlmApi(String),
     
     #[error("Configuration error: {0}")]
     Configuration(String),
@@ -26,9 +48,49 @@ pub enum InvestmentChatError {
     #[error("Internal error: {0}")]
     Internal(String),
     
-    #[error("External API error: {0}")]
-    ExternalApi(String),
+    #[error("Offline: {0}")]
+    Offline(String),
     
-    #[error("Price API error: {0}")]
-    PriceApi(String),
+    /// The model answered with nothing twice, the CLI shows this as is
+    #[error("I didn't get an answer this time, please ask again")]
+    EmptyResponse { request_id: Option<String> },
+    
+    /// The user sent too many messages, the CLI shows this as is
+    #[error("Slow down: too many messages, try again in {}s", .retry_after.as_millis().div_ceil(1000))]
+    RateLimited { retry_after: std::time::Duration },
+}
+
+impl From<RateLimitError> for InvestmentChatError {
+    fn from(error: RateLimitError) -> Self {
+        match error {
+            RateLimitError::SlowDown { retry_after } => InvestmentChatError::RateLimited { retry_after },
+            RateLimitError::Database(e) => InvestmentChatError::Database(e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_domain_errors_convert() {
+        let error: InvestmentChatError = PriceError::RateLimitExceeded(None).into();
+        assert!(matches!(error, InvestmentChatError::PriceApi(PriceError::RateLimitExceeded(None))));
+        assert_eq!(error.to_string(), "Price API error: CoinGecko API rate limit exceeded");
+
+        let error: InvestmentChatError = ExaApiError::ApiKeyNotFound.into();
+        assert!(matches!(error, InvestmentChatError::ExaApi(ExaApiError::ApiKeyNotFound)));
+
+        let error: InvestmentChatError = DbError::NotFound("user".to_string()).into();
+        assert_eq!(error.to_string(), "Database error: Database record not found: user");
+
+        let error: InvestmentChatError = RateLimitError::SlowDown { retry_after: std::time::Duration::from_millis(1500) }.into();
+        assert!(matches!(error, InvestmentChatError::RateLimited { .. }));
+        assert_eq!(error.to_string(), "Slow down: too many messages, try again in 2s");
+
+        let error: InvestmentChatError = TradingError::SlippageTooHigh { slippage: 60.0, max: 50.0 }.into();
+        assert!(matches!(error, InvestmentChatError::Trading(TradingError::SlippageTooHigh { .. })));
+        assert_eq!(error.to_string(), "Trading error: Slippage of 60% is above the 50% maximum");
+    }
 }
@@ -0,0 +1,133 @@
+use chrono::{Duration, NaiveDateTime};
+use crate::price_format::format_price;
+
+/// How old a price may be before no levels or calculations are derived from it, in minutes
+pub const DEFAULT_MAX_QUOTE_AGE_MINUTES: i64 = 30;
+
+/// A price older than the staleness threshold
+#[derive(Debug, Clone, PartialEq)]
+pub struct StalePrice {
+    pub coin_id: String,
+    pub price_usd: f64,
+    pub as_of: NaiveDateTime,
+    pub age: Duration,
+}
+
+/// The staleness threshold from `MAX_QUOTE_AGE_MINUTES`
+pub fn max_quote_age() -> Duration {
+    let minutes = crate::config::Config::get_instance()
+        .map(|config| config.max_quote_age_minutes)
+        .unwrap_or(DEFAULT_MAX_QUOTE_AGE_MINUTES);
+    Duration::minutes(minutes)
+}
+
+/// The price if it was fetched more than `max_age` before `now`, live quotes (no `as_of`) are always fresh
+pub fn check(coin_id: &str, price_usd: f64, as_of: Option<NaiveDateTime>, now: NaiveDateTime, max_age: Duration) -> Option<StalePrice> {
+    let as_of = as_of?;
+    let age = now - as_of;
+    (age > max_age).then(|| StalePrice { coin_id: coin_id.to_string(), price_usd, as_of, age })
+}
+
+/// An age in its two largest units, e.g. "3 hours 10 minutes" or "2 days 4 hours"
+pub fn format_age(age: Duration) -> String {
+    let unit = |count: i64, name: &str| format!("{} {}{}", count, name, if count == 1 { "" } else { "s" });
+    let minutes = age.num_minutes().max(0);
+    let (days, hours, minutes) = (minutes / 1440, minutes / 60 % 24, minutes % 60);
+    match (days, hours, minutes) {
+        (0, 0, minutes) => unit(minutes, "minute"),
+        (0, hours, 0) => unit(hours, "hour"),
+        (0, hours, minutes) => format!("{} {}", unit(hours, "hour"), unit(minutes, "minute")),
+        (days, 0, _) => unit(days, "day"),
+        (days, hours, _) => format!("{} {}", unit(days, "day"), unit(hours, "hour")),
+    }
+}
+
+/// The shorter price answer given instead of entry and exit levels when the only price is stale
+pub fn render_stale_price(display_name: &str, stale: &StalePrice) -> String {
+    format!(
+        "I couldn't reach a live price source for {}. The last price I have is {} from {} UTC, {} old.\n\n\
+        That's too old to derive support, resistance or entry and exit levels from, so I won't give precise levels. \
+        Ask me again in a few minutes and I'll retry the live sources.",
+        display_name,
+        format_price(stale.price_usd),
+        stale.as_of.format("%Y-%m-%d %H:%M"),
+        format_age(stale.age)
+    )
+}
+
+/// The answer given instead of a calculation whose inputs include stale prices
+pub fn render_stale_inputs(calculation: &str, stale: &[StalePrice]) -> String {
+    let lines: Vec<String> = stale
+        .iter()
+        .map(|price| {
+            format!(
+                "- {}: {} from {} UTC, {} old",
+                price.coin_id,
+                format_price(price.price_usd),
+                price.as_of.format("%Y-%m-%d %H:%M"),
+                format_age(price.age)
+            )
+        })
+        .collect();
+    format!(
+        "I couldn't reach a live price source, and the last prices I have are too old to run {} on:\n{}\n\n\
+        Ask me again in a few minutes and I'll retry the live sources.",
+        calculation,
+        lines.join("\n")
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(value: &str) -> NaiveDateTime {
+        NaiveDateTime::parse_from_str(value, "%Y-%m-%d %H:%M").unwrap()
+    }
+
+    #[test]
+    fn test_check_flags_prices_older_than_the_threshold() {
+        let now = at("2025-09-24 12:00");
+        let max_age = Duration::minutes(30);
+        assert_eq!(check("bitcoin", 60000.0, None, now, max_age), None);
+        assert_eq!(check("bitcoin", 60000.0, Some(at("2025-09-24 11:30")), now, max_age), None);
+
+        let stale = check("bitcoin", 60000.0, Some(at("2025-09-24 08:50")), now, max_age).unwrap();
+        assert_eq!(stale.coin_id, "bitcoin");
+        assert_eq!(stale.age, Duration::minutes(190));
+        assert!(check("bitcoin", 60000.0, Some(at("2025-09-24 11:29")), now, max_age).is_some());
+    }
+
+    #[test]
+    fn test_format_age_uses_the_two_largest_units() {
+        assert_eq!(format_age(Duration::minutes(1)), "1 minute");
+        assert_eq!(format_age(Duration::minutes(45)), "45 minutes");
+        assert_eq!(format_age(Duration::minutes(120)), "2 hours");
+        assert_eq!(format_age(Duration::minutes(190)), "3 hours 10 minutes");
+        assert_eq!(format_age(Duration::minutes(1440 + 61)), "1 day 1 hour");
+        assert_eq!(format_age(Duration::days(3)), "3 days");
+    }
+
+    #[test]
+    fn test_stale_price_answer_states_the_age_and_gives_no_levels() {
+        let stale = check("bitcoin", 60000.0, Some(at("2025-09-24 08:50")), at("2025-09-24 12:00"), Duration::minutes(30)).unwrap();
+        let reply = render_stale_price("Bitcoin", &stale);
+        assert!(reply.contains("$60000.00 from 2025-09-24 08:50 UTC, 3 hours 10 minutes old"));
+        assert!(reply.contains("won't give precise levels"));
+        assert!(reply.contains("retry"));
+        assert!(!reply.to_lowercase().contains("support:"));
+    }
+
+    #[test]
+    fn test_stale_inputs_answer_lists_every_stale_price() {
+        let now = at("2025-09-24 12:00");
+        let stale = vec![
+            check("bitcoin", 60000.0, Some(at("2025-09-24 10:00")), now, Duration::minutes(30)).unwrap(),
+            check("ethereum", 2500.0, Some(at("2025-09-22 09:00")), now, Duration::minutes(30)).unwrap(),
+        ];
+        let reply = render_stale_inputs("this scenario", &stale);
+        assert!(reply.contains("too old to run this scenario on"));
+        assert!(reply.contains("- bitcoin: $60000.00 from 2025-09-24 10:00 UTC, 2 hours old"));
+        assert!(reply.contains("- ethereum: $2500.00 from 2025-09-22 09:00 UTC, 2 days 3 hours old"));
+    }
+}
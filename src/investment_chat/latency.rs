@@ -0,0 +1,198 @@
+use std::future::Future;
+use std::sync::Mutex;
+use std::time::Duration;
+use tokio::time::Instant;
+
+/// Total time a general answer may take unless TURN_BUDGET_SECS says otherwise
+pub const DEFAULT_TURN_BUDGET: Duration = Duration::from_secs(45);
+
+/// Least time the model gets, however long the context took
+pub const DEFAULT_MODEL_FLOOR: Duration = Duration::from_secs(15);
+
+/// How long one chat turn may spend gathering context and waiting for the model
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LatencySettings {
+    pub budget: Duration,
+    pub model_floor: Duration,
+}
+
+impl Default for LatencySettings {
+    fn default() -> Self {
+        Self { budget: DEFAULT_TURN_BUDGET, model_floor: DEFAULT_MODEL_FLOOR }
+    }
+}
+
+/// Context gathered before the model is asked, each under its own share of the budget
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Step {
+    /// Conversation history and stored knowledge matching the message
+    Knowledge,
+    /// Research stored about the projects the message names
+    Research,
+    /// Live figures from exchanges and price APIs
+    MarketData,
+}
+
+impl Step {
+    /// Share of the whole budget the step may take
+    fn share(self) -> f64 {
+        match self {
+            Step::Knowledge => 0.15,
+            Step::Research => 0.15,
+            Step::MarketData => 0.2,
+        }
+    }
+
+    fn label(self) -> &'static str {
+        match self {
+            Step::Knowledge => "your saved knowledge",
+            Step::Research => "project research",
+            Step::MarketData => "market data",
+        }
+    }
+}
+
+/// Deadline of one turn, recording the steps that ran out of time
+///
+/// A step gets its share of the budget, never more than what's left. The model gets the
+/// rest, at least the floor, so a slow provider shortens the context instead of the answer.
+#[derive(Debug)]
+pub struct LatencyBudget {
+    settings: LatencySettings,
+    deadline: Instant,
+    skipped: Mutex<Vec<Step>>,
+}
+
+impl LatencyBudget {
+    /// Start the clock of a turn
+    pub fn start(settings: LatencySettings) -> Self {
+        Self {
+            settings,
+            deadline: Instant::now() + settings.budget,
+            skipped: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Time left before the deadline
+    pub fn remaining(&self) -> Duration {
+        self.deadline.saturating_duration_since(Instant::now())
+    }
+
+    /// How long `step` may run from now
+    pub fn step_timeout(&self, step: Step) -> Duration {
+        self.settings.budget.mul_f64(step.share()).min(self.remaining())
+    }
+
+    /// How long the model may take from now
+    pub fn model_timeout(&self) -> Duration {
+        self.remaining().max(self.settings.model_floor)
+    }
+
+    /// Run `step` under its timeout, None when it didn't finish in time
+    pub async fn run<T>(&self, step: Step, future: impl Future<Output = T>) -> Option<T> {
+        match tokio::time::timeout(self.step_timeout(step), future).await {
+            Ok(value) => Some(value),
+            Err(_) => {
+                eprintln!("Skipping {} for this answer, it took longer than {:?}", step.label(), self.step_timeout(step));
+                let mut skipped = self.skipped.lock().unwrap();
+                if !skipped.contains(&step) {
+                    skipped.push(step);
+                }
+                None
+            },
+        }
+    }
+
+    /// Steps that ran out of time, in the order they did
+    pub fn skipped(&self) -> Vec<Step> {
+        self.skipped.lock().unwrap().clone()
+    }
+
+    /// Note ending an answer given without some of its context, None when nothing was skipped
+    pub fn footer(&self) -> Option<String> {
+        let skipped = self.skipped();
+        if skipped.is_empty() {
+            return None;
+        }
+        let labels: Vec<&str> = skipped.iter().map(|step| step.label()).collect();
+        let list = match labels.split_last() {
+            Some((last, rest)) if !rest.is_empty() => format!("{} and {}", rest.join(", "), last),
+            _ => labels.join(""),
+        };
+        Some(format!("_Answered without {}, it couldn't be fetched in time._", list))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::derivatives::{DerivativesError, DerivativesProvider, DerivativesSnapshot};
+    use async_trait::async_trait;
+
+    fn settings(budget_secs: u64, floor_secs: u64) -> LatencySettings {
+        LatencySettings { budget: Duration::from_secs(budget_secs), model_floor: Duration::from_secs(floor_secs) }
+    }
+
+    /// An exchange that answers only after `delay`
+    struct SlowExchange {
+        delay: Duration,
+    }
+
+    #[async_trait]
+    impl DerivativesProvider for SlowExchange {
+        async fn snapshot(&self, coin_id: &str) -> Result<DerivativesSnapshot, DerivativesError> {
+            tokio::time::sleep(self.delay).await;
+            Err(DerivativesError::UnsupportedCoin(coin_id.to_string()))
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_steps_get_their_share_and_the_model_the_rest() {
+        let budget = LatencyBudget::start(settings(40, 10));
+        assert_eq!(budget.step_timeout(Step::Knowledge), Duration::from_secs(6));
+        assert_eq!(budget.step_timeout(Step::MarketData), Duration::from_secs(8));
+        assert_eq!(budget.model_timeout(), Duration::from_secs(40));
+
+        tokio::time::advance(Duration::from_secs(25)).await;
+        assert_eq!(budget.model_timeout(), Duration::from_secs(15));
+        // A step never outlives the deadline, the model still gets its floor
+        tokio::time::advance(Duration::from_secs(12)).await;
+        assert_eq!(budget.step_timeout(Step::MarketData), Duration::from_secs(3));
+        tokio::time::advance(Duration::from_secs(10)).await;
+        assert_eq!(budget.step_timeout(Step::Knowledge), Duration::ZERO);
+        assert_eq!(budget.model_timeout(), Duration::from_secs(10));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_slow_provider_is_skipped_and_noted() {
+        let budget = LatencyBudget::start(settings(40, 10));
+        let fast = budget.run(Step::Knowledge, async { vec!["knowledge"] }).await;
+        assert_eq!(fast, Some(vec!["knowledge"]));
+        assert_eq!(budget.footer(), None);
+
+        let started = Instant::now();
+        let exchange = SlowExchange { delay: Duration::from_secs(60) };
+        assert!(budget.run(Step::MarketData, exchange.snapshot("bitcoin")).await.is_none());
+        // Given up after the step's share, not the provider's delay
+        assert_eq!(started.elapsed(), Duration::from_secs(8));
+        assert_eq!(budget.skipped(), vec![Step::MarketData]);
+        assert_eq!(budget.footer().unwrap(), "_Answered without market data, it couldn't be fetched in time._");
+        assert_eq!(budget.model_timeout(), Duration::from_secs(32));
+
+        let research = budget.run(Step::Research, tokio::time::sleep(Duration::from_secs(7))).await;
+        assert!(research.is_none());
+        assert_eq!(
+            budget.footer().unwrap(),
+            "_Answered without market data and project research, it couldn't be fetched in time._"
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_provider_inside_its_share_is_kept() {
+        let budget = LatencyBudget::start(settings(40, 10));
+        let exchange = SlowExchange { delay: Duration::from_secs(5) };
+        let result = budget.run(Step::MarketData, exchange.snapshot("bitcoin")).await;
+        assert!(matches!(result, Some(Err(DerivativesError::UnsupportedCoin(_)))));
+        assert!(budget.skipped().is_empty());
+    }
+}
@@ -0,0 +1,136 @@
+use crate::db::{Knowledge, PricePoint};
+use crate::price_format::format_price;
+
+/// Deterministic reply for general questions while offline
+pub const OFFLINE_GENERAL_RESPONSE: &str = "I'm currently in offline mode, so I can't reach my AI service, \
+live market data, or web research right now. I can still answer price questions from my last known prices, \
+share what I've stored about specific projects, and you can use /strategies, /history and /portfolio. \
+Ask me again once you're back online for a full answer.";
+
+/// Render a price answer from the local price history cache
+pub fn render_cached_price(display_name: &str, point: Option<&PricePoint>) -> String {
+    match point {
+        Some(point) => format!(
+            "I'm offline, so this is my last known price rather than a live quote.\n\n\
+            {}: {} (as of {} UTC)\n\n\
+            Prices may have moved since then. I'll fetch live data and key price levels once I'm back online.",
+            display_name,
+            format_price(point.price_usd),
+            point.fetched_at.format("%Y-%m-%d %H:%M")
+        ),
+        None => format!(
+            "I'm offline and have no stored price for {}. I'll be able to fetch it once I'm back online.",
+            display_name
+        ),
+    }
+}
+
+/// Render a research answer from stored knowledge only
+pub fn render_stored_knowledge(project_name: &str, entries: &[Knowledge]) -> String {
+    if entries.is_empty() {
+        return format!(
+            "I'm offline and don't have any stored knowledge about {}. \
+            I can research it once I'm back online.",
+            project_name
+        );
+    }
+
+    let mut response = format!(
+        "I'm offline, so this comes from my stored knowledge about {} only:\n\n",
+        project_name
+    );
+    for (i, entry) in entries.iter().enumerate().take(3) {
+        response.push_str(&format!(
+            "{}. [{}] {}\n\n",
+            i + 1,
+            entry.source_id,
+            entry.content
+        ));
+    }
+    response.push_str("This may be out of date. I'll check for newer information once I'm back online.");
+
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::offline;
+    use crate::price_fetcher::{self, PriceError};
+    use chrono::NaiveDate;
+
+    fn price_point(coin_id: &str, price_usd: f64) -> PricePoint {
+        PricePoint {
+            id: 1,
+            coin_id: coin_id.to_string(),
+            price_usd,
+            fetched_at: NaiveDate::from_ymd_opt(2025, 9, 20)
+                .unwrap()
+                .and_hms_opt(14, 3, 0)
+                .unwrap(),
+        }
+    }
+
+    #[test]
+    fn test_cached_price_includes_as_of_timestamp() {
+        let point = price_point("bitcoin", 64_250.5);
+        let response = render_cached_price("Bitcoin", Some(&point));
+
+        assert!(response.contains("Bitcoin: $64250.50"));
+        assert!(response.contains("as of 2025-09-20 14:03 UTC"));
+    }
+
+    #[test]
+    fn test_cached_price_missing() {
+        let response = render_cached_price("Solana", None);
+        assert!(response.contains("no stored price for Solana"));
+    }
+
+    #[test]
+    fn test_stored_knowledge_rendering() {
+        let now = chrono::Utc::now().naive_utc();
+        let entries = vec![Knowledge {
+            id: 1,
+            user_id: 1,
+            source_id: "pendle_overview".to_string(),
+            content: "Pendle splits yield-bearing tokens into PT and YT.".to_string(),
+            tags: vec!["pendle".to_string()],
+            created_at: now,
+            updated_at: now,
+        }];
+
+        let response = render_stored_knowledge("pendle", &entries);
+        assert!(response.contains("[pendle_overview]"));
+        assert!(response.contains("PT and YT"));
+
+        let empty = render_stored_knowledge("pendle", &[]);
+        assert!(empty.contains("don't have any stored knowledge about pendle"));
+    }
+
+    #[tokio::test]
+    async fn test_offline_session_never_touches_network() {
+        let _guard = offline::TEST_LOCK.lock().await;
+        offline::disable();
+        
+        // The first failed connection switches the session into offline mode
+        let error = offline::unroutable_request_error().await;
+        assert!(offline::note_network_error(&error));
+
+        // Every upstream short-circuits without attempting a request
+        let price = price_fetcher::fetch_coin_price("bitcoin").await;
+        assert!(matches!(price, Err(PriceError::Offline)));
+
+        let exa = crate::exa_api::ExaApiClient::with_api_key("test".to_string());
+        assert!(matches!(
+            exa.search("solana", 3, None).await,
+            Err(crate::exa_api::ExaApiError::Offline)
+        ));
+
+        let model = crate::llm::testing::MockChatModel::replying(["hi"]);
+        let ai = super::super::service::get_ai_response(&model, "hello", 16).await;
+        assert!(model.requests().is_empty());
+        assert!(matches!(ai, Err(super::super::InvestmentChatError::Offline(_))));
+
+        offline::disable();
+    }
+}
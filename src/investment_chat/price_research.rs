@@ -0,0 +1,190 @@
+use crate::exa_api::ExaSearchResult;
+use crate::price_format::format_price;
+use regex::Regex;
+use std::sync::OnceLock;
+
+/// A price quoted in a web article, kept with the article's publication date
+#[derive(Debug, Clone, PartialEq)]
+pub struct ResearchedPrice {
+    pub price_usd: f64,
+    pub published_date: String,
+    pub title: String,
+    pub url: String,
+}
+
+/// Find the most recently published USD price for a coin in search results
+/// Results without a published date are skipped, an undated figure can't be put in context
+pub fn extract_dated_price(results: &[ExaSearchResult], coin_terms: &[&str]) -> Option<ResearchedPrice> {
+    let coin_terms: Vec<String> = coin_terms.iter().map(|term| term.to_lowercase()).collect();
+
+    results
+        .iter()
+        .filter_map(|result| {
+            let published_date = result.published_date.as_deref()?.trim();
+            if published_date.is_empty() {
+                return None;
+            }
+
+            let price_usd = sentences(&result.content)
+                .filter(|sentence| {
+                    let sentence = sentence.to_lowercase();
+                    coin_terms.iter().any(|term| sentence.contains(term.as_str()))
+                })
+                .find_map(find_usd_price)?;
+
+            Some(ResearchedPrice {
+                price_usd,
+                published_date: published_date.to_string(),
+                title: result.title.clone(),
+                url: result.url.clone(),
+            })
+        })
+        .max_by(|a, b| a.published_date.cmp(&b.published_date))
+}
+
+/// Render a price answer built from web research, flagged as possibly stale
+/// `requested_date` is set when the user asked for a price on a specific day
+pub fn render_researched_price(display_name: &str, requested_date: Option<&str>, found: Option<&ResearchedPrice>) -> String {
+    let Some(found) = found else {
+        return match requested_date {
+            Some(date) => format!(
+                "I couldn't find a recorded price for {} on {}, and my research didn't turn up a dated price either.",
+                display_name, date
+            ),
+            None => format!(
+                "I couldn't find real-time price information for {}. Please check that the cryptocurrency name or ticker is correct and try again.",
+                display_name
+            ),
+        };
+    };
+
+    let intro = match requested_date {
+        Some(date) => format!(
+            "I couldn't find a recorded price for {} on {}, so this figure comes from a web article and may not match that date.",
+            display_name, date
+        ),
+        None => format!(
+            "I couldn't reach a live price source for {}, so this figure comes from a web article and may be stale.",
+            display_name
+        ),
+    };
+    let published = found.published_date.get(..10).unwrap_or(&found.published_date);
+
+    format!(
+        "{}\n\n\
+        {}: {} according to \"{}\" ({}), published {}.\n\n\
+        This is not a live quote and the price may have moved significantly since it was published, \
+        so I haven't calculated support or resistance levels from it.",
+        intro,
+        display_name,
+        format_price(found.price_usd),
+        found.title,
+        found.url,
+        published
+    )
+}
+
+/// Split text into sentences without breaking decimal numbers
+fn sentences(content: &str) -> impl Iterator<Item = &str> {
+    static SENTENCE_END: OnceLock<Regex> = OnceLock::new();
+    let sentence_end = SENTENCE_END.get_or_init(|| Regex::new(r"[.!?]\s+|\n").unwrap());
+
+    sentence_end.split(content).map(str::trim).filter(|sentence| !sentence.is_empty())
+}
+
+/// Find the first dollar amount in a sentence, ignoring market caps and volumes like "$1.2 billion"
+fn find_usd_price(sentence: &str) -> Option<f64> {
+    static USD_AMOUNT: OnceLock<Regex> = OnceLock::new();
+    let usd_amount = USD_AMOUNT.get_or_init(|| {
+        Regex::new(r"(?i)\$\s?([0-9]{1,3}(?:,[0-9]{3})+(?:\.[0-9]+)?|[0-9]+(?:\.[0-9]+)?)(\s*(?:k|m|b|bn|thousand|million|billion|trillion)\b)?").unwrap()
+    });
+
+    usd_amount
+        .captures_iter(sentence)
+        .filter(|caps| caps.get(2).is_none())
+        .filter_map(|caps| caps[1].replace(',', "").parse::<f64>().ok())
+        .find(|price| *price > 0.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn result(title: &str, content: &str, published_date: Option<&str>) -> ExaSearchResult {
+        ExaSearchResult {
+            id: title.to_string(),
+            url: format!("https://news.example/{}", title.to_lowercase().replace(' ', "-")),
+            title: title.to_string(),
+            content: content.to_string(),
+            score: 0.9,
+            published_date: published_date.map(str::to_string),
+            author: None,
+        }
+    }
+
+    #[test]
+    fn test_extracts_price_with_date() {
+        let results = vec![result(
+            "AERO rallies",
+            "Aerodrome has grown quickly. The AERO token traded at $1.23 on Monday. Its market cap is $800 million.",
+            Some("2024-05-01T12:00:00.000Z"),
+        )];
+
+        let found = extract_dated_price(&results, &["aero", "aerodrome"]).unwrap();
+        assert_eq!(found.price_usd, 1.23);
+        assert_eq!(found.published_date, "2024-05-01T12:00:00.000Z");
+        assert_eq!(found.title, "AERO rallies");
+    }
+
+    #[test]
+    fn test_prefers_most_recent_article() {
+        let results = vec![
+            result("Old", "Bitcoin hit $42,000.50 today", Some("2024-01-10")),
+            result("New", "Bitcoin is trading near $64,250", Some("2024-06-02")),
+        ];
+
+        let found = extract_dated_price(&results, &["bitcoin"]).unwrap();
+        assert_eq!(found.title, "New");
+        assert_eq!(found.price_usd, 64250.0);
+    }
+
+    #[test]
+    fn test_skips_undated_results_and_other_coins() {
+        let results = vec![
+            result("Undated", "Bitcoin is at $64,000", None),
+            result("Empty date", "Bitcoin is at $63,000", Some("  ")),
+            result("Other coin", "Ethereum is at $3,100", Some("2024-06-02")),
+        ];
+
+        assert_eq!(extract_dated_price(&results, &["bitcoin", "btc"]), None);
+    }
+
+    #[test]
+    fn test_ignores_market_caps_and_volumes() {
+        assert_eq!(find_usd_price("Solana volume reached $2.5 billion while SOL sits at $145.10"), Some(145.10));
+        assert_eq!(find_usd_price("Market cap of $1.2B"), None);
+        assert_eq!(find_usd_price("No dollar figure here"), None);
+    }
+
+    #[test]
+    fn test_render_flags_staleness_and_date() {
+        let found = ResearchedPrice {
+            price_usd: 0.5123,
+            published_date: "2024-05-01T12:00:00.000Z".to_string(),
+            title: "AERO rallies".to_string(),
+            url: "https://news.example/aero".to_string(),
+        };
+
+        let reply = render_researched_price("Aerodrome (AERO)", None, Some(&found));
+        assert!(reply.contains("may be stale"));
+        assert!(reply.contains("$0.5123"));
+        assert!(reply.contains("published 2024-05-01."));
+        assert!(reply.contains("haven't calculated support or resistance"));
+
+        let reply = render_researched_price("Aerodrome", Some("01-05-2024"), Some(&found));
+        assert!(reply.contains("on 01-05-2024"));
+
+        let reply = render_researched_price("Aerodrome", None, None);
+        assert!(reply.starts_with("I couldn't find real-time price information for Aerodrome"));
+    }
+}
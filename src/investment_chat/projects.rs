@@ -0,0 +1,174 @@
+use super::constants;
+use regex::Regex;
+use std::sync::OnceLock;
+
+/// Most projects whose stored research goes into one prompt
+pub const MAX_RESEARCHED_PROJECTS: usize = 4;
+
+/// Most projects queued for background research after one turn
+pub const MAX_ENRICHED_TOPICS: usize = 3;
+
+/// Project names that are also everyday words, matched only when capitalized ("Curve", "The Graph")
+const AMBIGUOUS_PROJECTS: &[&str] = &["the graph", "base", "near", "curve", "compound", "maker", "optimism", "gains", "avalanche"];
+
+/// A project named in a message
+#[derive(Debug, Clone, PartialEq)]
+pub struct ProjectMention {
+    pub name: String,
+    /// Byte offset of the mention in the message
+    pub start: usize,
+}
+
+struct Token<'a> {
+    text: &'a str,
+    lower: String,
+    start: usize,
+}
+
+fn token_regex() -> &'static Regex {
+    static REGEX: OnceLock<Regex> = OnceLock::new();
+    REGEX.get_or_init(|| Regex::new(r"[\p{L}\p{N}]+").unwrap())
+}
+
+/// Split a message into words, so "solana's", "Arbitrum," and "shiba-inu" end on word boundaries
+fn tokenize(message: &str) -> Vec<Token<'_>> {
+    token_regex()
+        .find_iter(message)
+        .map(|found| Token { text: found.as_str(), lower: found.as_str().to_lowercase(), start: found.start() })
+        .collect()
+}
+
+/// Words of a project name matched at `tokens`, returning how many tokens they span
+///
+/// "shiba inu" matches "Shiba Inu", "shiba-inu" and "ShibaInu"
+fn match_at(tokens: &[Token<'_>], words: &[&str]) -> Option<usize> {
+    let first = tokens.first()?;
+    if words.len() > 1 && first.lower == words.concat() {
+        return Some(1);
+    }
+    if tokens.len() < words.len() {
+        return None;
+    }
+    tokens.iter().zip(words).all(|(token, word)| token.lower == *word).then_some(words.len())
+}
+
+fn is_capitalized(token: &Token<'_>) -> bool {
+    token.text.chars().next().is_some_and(char::is_uppercase)
+}
+
+/// Every known project named in a message, in the order they first appear
+///
+/// Names match whole words only, the longest name wins where two overlap, and names that are also
+/// everyday words need a capital letter on their last word
+pub fn find_projects(message: &str) -> Vec<ProjectMention> {
+    let tokens = tokenize(message);
+    let mut projects: Vec<(&str, Vec<&str>)> = constants::crypto_projects()
+        .iter()
+        .map(|&project| (project, project.split(' ').collect()))
+        .collect();
+    // Longest names first, so "shiba inu" is tried before any one-word name; ties by name keep the order stable
+    projects.sort_by(|(a, a_words), (b, b_words)| b_words.len().cmp(&a_words.len()).then(a.cmp(b)));
+
+    let mut mentions: Vec<ProjectMention> = Vec::new();
+    let mut index = 0;
+    while index < tokens.len() {
+        let found = projects.iter().find_map(|(project, words)| {
+            let span = match_at(&tokens[index..], words)?;
+            let last = &tokens[index + span - 1];
+            let concatenated = span == 1 && words.len() > 1;
+            if AMBIGUOUS_PROJECTS.contains(project) && !concatenated && !is_capitalized(last) {
+                return None;
+            }
+            Some((*project, span))
+        });
+
+        match found {
+            Some((project, span)) => {
+                if !mentions.iter().any(|mention| mention.name == project) {
+                    mentions.push(ProjectMention { name: project.to_string(), start: tokens[index].start });
+                }
+                index += span;
+            },
+            None => index += 1,
+        }
+    }
+    mentions
+}
+
+/// Projects worth researching after a turn: those named in the question, then those only the answer named
+pub fn topics_from_exchange(question: &str, answer: &str) -> Vec<String> {
+    let mut topics: Vec<String> = Vec::new();
+    for mention in find_projects(question).into_iter().chain(find_projects(answer)) {
+        if !topics.contains(&mention.name) {
+            topics.push(mention.name);
+        }
+    }
+    topics.truncate(MAX_ENRICHED_TOPICS);
+    topics
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_find_projects_in_tricky_sentences() {
+        let cases: &[(&str, &[&str])] = &[
+            ("Tell me about Solana", &["solana"]),
+            ("what's solana's TVL?", &["solana"]),
+            ("What’s Solana’s roadmap?", &["solana"]),
+            ("bridging to Arbitrum, then Optimism", &["arbitrum", "optimism"]),
+            ("yield on Arbitrum vs on base", &["arbitrum"]),
+            ("Is Base cheaper than Ethereum?", &["base", "ethereum"]),
+            ("look at the graph below", &[]),
+            ("The graph shows a breakout", &[]),
+            ("How does The Graph index data?", &["the graph"]),
+            ("is thegraph undervalued", &["the graph"]),
+            ("shiba inu or dogecoin?", &["shiba inu", "dogecoin"]),
+            ("Shiba-Inu holders", &["shiba inu"]),
+            ("ShibaInu news", &["shiba inu"]),
+            ("my database is down", &[]),
+            ("compound interest on my gains", &[]),
+            ("Compound vs Aave lending rates", &["compound", "aave"]),
+            ("price near $2000, is uniswap a buy?", &["uniswap"]),
+            ("bitcoinmaxi thoughts", &[]),
+            ("swap on 1inch", &["1inch"]),
+            ("ethereum-based L2s like Arbitrum", &["ethereum", "arbitrum"]),
+            ("Aave, then aave again, then Pendle", &["aave", "pendle"]),
+            ("Compare Velodrome to Aerodrome", &["velodrome", "aerodrome"]),
+            ("", &[]),
+        ];
+
+        for (message, expected) in cases {
+            let found: Vec<String> = find_projects(message).into_iter().map(|mention| mention.name).collect();
+            assert_eq!(found, *expected, "message: {:?}", message);
+        }
+    }
+
+    #[test]
+    fn test_topics_from_exchange_adds_projects_only_the_answer_named() {
+        let topics = topics_from_exchange(
+            "where can I earn yield on Solana?",
+            "On Solana, Jito and Marinade are the main staking options; Kamino lends SOL. Solana fees are low.",
+        );
+        assert_eq!(topics[0], "solana");
+        assert!(topics.len() <= MAX_ENRICHED_TOPICS);
+        assert_eq!(topics.iter().filter(|topic| *topic == "solana").count(), 1);
+
+        let topics = topics_from_exchange("hi", "Aave, Compound, Pendle and Lido all offer yield.");
+        assert_eq!(topics, vec!["aave", "compound", "pendle"]);
+        assert!(topics_from_exchange("hi", "hello there").is_empty());
+    }
+
+    #[test]
+    fn test_mentions_carry_their_position() {
+        let mentions = find_projects("Aave or Solana's staking?");
+        assert_eq!(
+            mentions,
+            vec![
+                ProjectMention { name: "aave".to_string(), start: 0 },
+                ProjectMention { name: "solana".to_string(), start: 8 },
+            ]
+        );
+    }
+}
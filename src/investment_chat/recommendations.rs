@@ -0,0 +1,664 @@
+use super::aliases::AliasBook;
+use super::constants;
+use super::error::InvestmentChatError;
+use super::service;
+use crate::llm::{ChatModel, Message, json_payload};
+use crate::db::{self, DbError, MessageRole, PricePoint, Recommendation};
+use crate::price_format::format_price;
+use crate::render::Table;
+use async_trait::async_trait;
+use chrono::{NaiveDate, NaiveDateTime};
+use regex::Regex;
+use serde::Deserialize;
+use sqlx::{Pool, Postgres};
+use std::sync::OnceLock;
+
+/// Widest range, as high / low, still accepted as a concrete call from free-form text
+const MAX_RANGE_RATIO: f64 = 1.5;
+
+const EXTRACT_PROMPT: &str = "You extract explicit trading calls from a crypto advisor's answer. \
+Only include a call when the answer tells the reader to act on one asset at a stated USD price or price range, \
+e.g. \"accumulate ETH between $2,300 and $2,450\". Skip general advice, hypotheticals, past prices and levels \
+given without an action. Reply with a JSON array only, like \
+[{\"asset\": \"ETH\", \"action\": \"accumulate\", \"price_low\": 2300, \"price_high\": 2450, \"target_date\": null}]. \
+Use only \"accumulate\", \"buy\", \"take_profit\" or \"sell\" as the action, a single price for both bounds when \
+there is no range, and target_date as YYYY-MM-DD only when the answer gives one. Reply [] when there is no call.";
+
+/// What a call tells the user to do
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Action {
+    Accumulate,
+    Buy,
+    TakeProfit,
+    Sell,
+}
+
+impl Action {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Action::Accumulate => "accumulate",
+            Action::Buy => "buy",
+            Action::TakeProfit => "take_profit",
+            Action::Sell => "sell",
+        }
+    }
+
+    pub fn parse(action: &str) -> Option<Self> {
+        match action {
+            "accumulate" => Some(Action::Accumulate),
+            "buy" => Some(Action::Buy),
+            "take_profit" => Some(Action::TakeProfit),
+            "sell" => Some(Action::Sell),
+            _ => None,
+        }
+    }
+
+    /// Buying calls pay off when the price rises after the range is reached
+    pub fn is_buy(&self) -> bool {
+        matches!(self, Action::Accumulate | Action::Buy)
+    }
+
+    fn label(&self) -> &'static str {
+        match self {
+            Action::TakeProfit => "take profit",
+            other => other.as_str(),
+        }
+    }
+}
+
+/// A call found in an answer, before the asset is resolved to a coin id
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct Call {
+    /// The asset as written, e.g. "Ethereum (ETH)"
+    pub asset: String,
+    pub action: Action,
+    pub price_low: f64,
+    pub price_high: f64,
+    #[serde(default)]
+    pub target_date: Option<NaiveDate>,
+}
+
+/// Extracts calls from free-form answers
+#[async_trait]
+pub trait CallExtractor: Send + Sync {
+    async fn extract(&self, response: &str) -> Result<Vec<Call>, InvestmentChatError>;
+}
+
+/// Extracts calls with a single model call
+pub struct LlmExtractor<'a> {
+    model: &'a dyn ChatModel,
+}
+
+impl<'a> LlmExtractor<'a> {
+    pub fn new(model: &'a dyn ChatModel) -> Self {
+        Self { model }
+    }
+}
+
+#[async_trait]
+impl CallExtractor for LlmExtractor<'_> {
+    async fn extract(&self, response: &str) -> Result<Vec<Call>, InvestmentChatError> {
+        let messages = [Message {
+            role: MessageRole::User,
+            content: response.to_string(),
+        }];
+        let reply = self.model
+            .generate_json(EXTRACT_PROMPT, &messages, 512)
+            .await
+            .map_err(service::describe_llm_error)?;
+
+        Ok(parse_extracted_calls(&reply, response))
+    }
+}
+
+fn current_price_asset_regex() -> &'static Regex {
+    static REGEX: OnceLock<Regex> = OnceLock::new();
+    REGEX.get_or_init(|| Regex::new(r"The current price of (.+?) is \$").unwrap())
+}
+
+fn entry_points_asset_regex() -> &'static Regex {
+    static REGEX: OnceLock<Regex> = OnceLock::new();
+    REGEX.get_or_init(|| Regex::new(r"ENTRY POINTS ANALYSIS FOR (.+?):").unwrap())
+}
+
+fn support_zone_regex() -> &'static Regex {
+    static REGEX: OnceLock<Regex> = OnceLock::new();
+    REGEX.get_or_init(|| Regex::new(r"Accumulating at support levels \(\$([\d,]+(?:\.\d+)?) - \$([\d,]+(?:\.\d+)?)\)").unwrap())
+}
+
+fn resistance_zone_regex() -> &'static Regex {
+    static REGEX: OnceLock<Regex> = OnceLock::new();
+    REGEX.get_or_init(|| Regex::new(r"Taking partial profits at resistance \(\$([\d,]+(?:\.\d+)?) - \$([\d,]+(?:\.\d+)?)\)").unwrap())
+}
+
+fn medium_term_regex() -> &'static Regex {
+    static REGEX: OnceLock<Regex> = OnceLock::new();
+    REGEX.get_or_init(|| {
+        Regex::new(r"Medium-term investors: Accumulate between \$([\d,]+(?:\.\d+)?) and \$([\d,]+(?:\.\d+)?), sell between \$([\d,]+(?:\.\d+)?) and \$([\d,]+(?:\.\d+)?)").unwrap()
+    })
+}
+
+/// "accumulate ETH between $2,300 and $2,450": the ticker must be upper case to count
+fn explicit_call_regex() -> &'static Regex {
+    static REGEX: OnceLock<Regex> = OnceLock::new();
+    REGEX.get_or_init(|| {
+        Regex::new(r"\b(?i:(accumulate|buy|sell|take profits? on))\s+([A-Z][A-Z0-9]{1,9})\s+(?i:between|from)\s+\$([\d,]+(?:\.\d+)?)\s+(?i:and|to|-)\s+\$([\d,]+(?:\.\d+)?)").unwrap()
+    })
+}
+
+fn amount(digits: &str) -> Option<f64> {
+    digits.replace(',', "").parse().ok().filter(|value: &f64| *value > 0.0)
+}
+
+fn zone_call(asset: &str, action: Action, first: &str, second: &str) -> Option<Call> {
+    let (first, second) = (amount(first)?, amount(second)?);
+    Some(Call {
+        asset: asset.to_string(),
+        action,
+        price_low: first.min(second),
+        price_high: first.max(second),
+        target_date: None,
+    })
+}
+
+/// Calls in the templated price answers and explicit "accumulate ETH between $X and $Y" sentences
+///
+/// Only exact template lines count, so an answer that merely mentions support levels yields nothing
+pub fn extract_templated(response: &str) -> Vec<Call> {
+    let mut calls = Vec::new();
+
+    let asset = current_price_asset_regex()
+        .captures(response)
+        .or_else(|| entry_points_asset_regex().captures(response))
+        .map(|captures| captures[1].trim().to_string());
+    if let Some(asset) = &asset {
+        if let Some(captures) = medium_term_regex().captures(response) {
+            calls.extend(zone_call(asset, Action::Accumulate, &captures[1], &captures[2]));
+            calls.extend(zone_call(asset, Action::TakeProfit, &captures[3], &captures[4]));
+        }
+        if let Some(captures) = support_zone_regex().captures(response) {
+            calls.extend(zone_call(asset, Action::Accumulate, &captures[1], &captures[2]));
+        }
+        if let Some(captures) = resistance_zone_regex().captures(response) {
+            calls.extend(zone_call(asset, Action::TakeProfit, &captures[1], &captures[2]));
+        }
+    }
+
+    for captures in explicit_call_regex().captures_iter(response) {
+        let action = match captures[1].to_lowercase().as_str() {
+            "accumulate" => Action::Accumulate,
+            "buy" => Action::Buy,
+            "sell" => Action::Sell,
+            _ => Action::TakeProfit,
+        };
+        if let Some(call) = zone_call(&captures[2], action, &captures[3], &captures[4])
+            && !calls.contains(&call)
+        {
+            calls.push(call);
+        }
+    }
+
+    calls
+}
+
+/// Whether a free-form answer is worth an extraction call: it names a dollar price and an action
+pub fn might_contain_call(response: &str) -> bool {
+    static PRICE: OnceLock<Regex> = OnceLock::new();
+    static ACTION: OnceLock<Regex> = OnceLock::new();
+    let price = PRICE.get_or_init(|| Regex::new(r"\$\d").unwrap());
+    let action = ACTION.get_or_init(|| Regex::new(r"(?i)\b(?:accumulate|buy|sell|take profits?)\b").unwrap());
+    price.is_match(response) && action.is_match(response)
+}
+
+/// Whether `price` is written in the response, with or without thousands separators
+fn mentions_price(response: &str, price: f64) -> bool {
+    let plain = response.replace(',', "");
+    let whole = format!("${}", price.trunc() as i64);
+    plain.contains(&whole)
+}
+
+/// Read the extractor's JSON array, keeping only calls the response itself supports
+///
+/// Precision matters more than recall: a call is dropped when its asset or prices don't
+/// appear in the response, its range is inverted or implausibly wide, or the reply is malformed
+pub fn parse_extracted_calls(reply: &str, response: &str) -> Vec<Call> {
+    let reply = json_payload(reply);
+    let Some(start) = reply.find('[') else {
+        return Vec::new();
+    };
+    let Some(end) = reply.rfind(']').filter(|&end| end > start) else {
+        return Vec::new();
+    };
+    let calls: Vec<Call> = match serde_json::from_str(&reply[start..=end]) {
+        Ok(calls) => calls,
+        Err(e) => {
+            eprintln!("Ignoring malformed recommendation extraction: {}", e);
+            return Vec::new();
+        }
+    };
+
+    let response_lower = response.to_lowercase();
+    calls
+        .into_iter()
+        .filter(|call| {
+            !call.asset.trim().is_empty()
+                && response_lower.contains(&call.asset.trim().to_lowercase())
+                && call.price_low > 0.0
+                && call.price_low <= call.price_high
+                && call.price_high / call.price_low <= MAX_RANGE_RATIO
+                && mentions_price(response, call.price_low)
+                && mentions_price(response, call.price_high)
+        })
+        .collect()
+}
+
+/// Name to resolve to a coin id: the ticker in "Ethereum (ETH)", otherwise the name itself
+pub fn asset_key(asset: &str) -> String {
+    static TICKER: OnceLock<Regex> = OnceLock::new();
+    let ticker = TICKER.get_or_init(|| Regex::new(r"\(([A-Za-z0-9]{2,10})\)").unwrap());
+    match ticker.captures(asset) {
+        Some(captures) => captures[1].to_lowercase(),
+        None => asset.trim().to_lowercase(),
+    }
+}
+
+/// Coin id of a call's asset, only when it's a coin Nova knows or has priced before
+async fn resolve_asset(pool: &Pool<Postgres>, aliases: &AliasBook, asset: &str) -> Result<Option<String>, DbError> {
+    let name = asset.split('(').next().unwrap_or(asset).trim().to_lowercase();
+    for candidate in [asset_key(asset), name] {
+        if constants::known_coin_id(&candidate).is_some() || aliases.get(&candidate).is_some() {
+            return Ok(Some(aliases.resolve_coin_id(&candidate)));
+        }
+        if db::get_latest_price_point(pool, &candidate).await?.is_some() {
+            return Ok(Some(candidate));
+        }
+    }
+    Ok(None)
+}
+
+/// Store the calls whose asset resolves to a coin, returning how many were new
+pub async fn save_calls(pool: &Pool<Postgres>, user_id: i32, aliases: &AliasBook, calls: &[Call]) -> Result<usize, DbError> {
+    let mut saved = 0;
+    for call in calls {
+        let Some(coin_id) = resolve_asset(pool, aliases, &call.asset).await? else {
+            continue;
+        };
+        let recommendation = db::save_recommendation(
+            pool,
+            user_id,
+            &coin_id,
+            call.action.as_str(),
+            call.price_low,
+            call.price_high,
+            call.target_date,
+        )
+        .await?;
+        if recommendation.is_some() {
+            saved += 1;
+        }
+    }
+    Ok(saved)
+}
+
+/// Whether a message asks how Nova's calls have done
+pub fn is_track_record_query(message: &str) -> bool {
+    static QUERY: OnceLock<Regex> = OnceLock::new();
+    let query = QUERY.get_or_init(|| {
+        Regex::new(r"(?i)\bhow\s+(?:have|did|are|do)\s+your\s+(?:calls|recommendations|picks|predictions)\b|\byour\s+track\s+record\b").unwrap()
+    });
+    query.is_match(message)
+}
+
+/// How a call has played out so far
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Verdict {
+    /// Reached the range and moved the right way since; the change is relative to the range's midpoint
+    Hit(f64),
+    Miss(f64),
+    /// The price never reached the range
+    NotTriggered,
+    /// No price was recorded after the call
+    NoData,
+}
+
+/// Score a call against the prices recorded after it, oldest first
+///
+/// A buying call is triggered once the price trades at or below the top of the range and is a hit
+/// when the latest price is above the range's midpoint. A selling call is triggered at or above the
+/// bottom of the range and is a hit when the latest price is below the midpoint. Prices after the
+/// call's target date are ignored.
+pub fn score(recommendation: &Recommendation, prices: &[PricePoint]) -> Verdict {
+    let Some(action) = Action::parse(&recommendation.action) else {
+        return Verdict::NoData;
+    };
+    let cutoff = recommendation
+        .target_date
+        .and_then(|date| date.and_hms_opt(23, 59, 59))
+        .unwrap_or(NaiveDateTime::MAX);
+    let prices: Vec<f64> = prices
+        .iter()
+        .filter(|point| point.fetched_at > recommendation.made_at && point.fetched_at <= cutoff)
+        .map(|point| point.price_usd)
+        .collect();
+    let Some(&latest) = prices.last() else {
+        return Verdict::NoData;
+    };
+
+    let triggered = if action.is_buy() {
+        prices.iter().any(|&price| price <= recommendation.price_high)
+    } else {
+        prices.iter().any(|&price| price >= recommendation.price_low)
+    };
+    if !triggered {
+        return Verdict::NotTriggered;
+    }
+
+    let midpoint = (recommendation.price_low + recommendation.price_high) / 2.0;
+    let change_pct = if action.is_buy() {
+        (latest - midpoint) / midpoint * 100.0
+    } else {
+        (midpoint - latest) / midpoint * 100.0
+    };
+    if change_pct > 0.0 {
+        Verdict::Hit(change_pct)
+    } else {
+        Verdict::Miss(change_pct)
+    }
+}
+
+/// Render the track record, one line per call and a hit rate over the triggered ones
+pub fn render_track_record(scored: &[(Recommendation, Verdict)]) -> String {
+    if scored.is_empty() {
+        return "I haven't made any concrete calls yet. Ask for a coin's price or entry points and I'll start tracking them.".to_string();
+    }
+
+    let results: Vec<f64> = scored
+        .iter()
+        .filter_map(|(_, verdict)| match verdict {
+            Verdict::Hit(change) | Verdict::Miss(change) => Some(*change),
+            _ => None,
+        })
+        .collect();
+    let hits = scored.iter().filter(|(_, verdict)| matches!(verdict, Verdict::Hit(_))).count();
+    let not_triggered = scored.iter().filter(|(_, verdict)| *verdict == Verdict::NotTriggered).count();
+    let no_data = scored.iter().filter(|(_, verdict)| *verdict == Verdict::NoData).count();
+
+    let mut output = format!("Track record of my calls ({} recorded):\n", scored.len());
+    if results.is_empty() {
+        output.push_str("None of them can be scored yet.\n");
+    } else {
+        let misses = results.len() - hits;
+        let average = results.iter().sum::<f64>() / results.len() as f64;
+        output.push_str(&format!(
+            "- Hits: {} ({:.0}%), misses: {} ({:.0}%)\n- Average result: {:+.2}%\n",
+            hits,
+            hits as f64 / results.len() as f64 * 100.0,
+            misses,
+            misses as f64 / results.len() as f64 * 100.0,
+            average
+        ));
+    }
+    if not_triggered > 0 {
+        output.push_str(&format!("- {} call(s) haven't reached their price range yet\n", not_triggered));
+    }
+    if no_data > 0 {
+        output.push_str(&format!("- {} call(s) have no price recorded since\n", no_data));
+    }
+
+    let mut calls = Table::new(["Date", "Action", "Coin", "Range", "Result"]);
+    for (recommendation, verdict) in scored {
+        let action = Action::parse(&recommendation.action).map_or(recommendation.action.as_str(), |action| action.label());
+        let range = if recommendation.price_low == recommendation.price_high {
+            format_price(recommendation.price_low)
+        } else {
+            format!("{}-{}", format_price(recommendation.price_low), format_price(recommendation.price_high))
+        };
+        let outcome = match verdict {
+            Verdict::Hit(change) => format!("hit, {:+.2}%", change),
+            Verdict::Miss(change) => format!("miss, {:+.2}%", change),
+            Verdict::NotTriggered => "not triggered".to_string(),
+            Verdict::NoData => "no price data".to_string(),
+        };
+        calls.push_row([recommendation.made_at.format("%Y-%m-%d").to_string(), action.to_string(), recommendation.coin_id.clone(), range, outcome]);
+    }
+
+    output.push_str("\nCalls:\n");
+    output.push_str(&calls.render());
+    output.push_str("\n\nResults compare the latest recorded price with the middle of each range; fees and timing aren't counted.");
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The standard price answer, as rendered by `handle_price_query`
+    const PRICE_ANSWER: &str = "The current price of Ethereum (ETH) is $2500.00\n\n\
+        Key price levels for Ethereum (ETH):\n\
+        - Strong support: $2125.00\n\
+        - Support: $2300.00\n\
+        - Current price: $2500.00\n\
+        - Resistance: $2700.00\n\
+        - Strong resistance: $2875.00\n\n\
+        Based on these levels, consider:\n\
+        - Accumulating at support levels ($2300.00 - $2125.00)\n\
+        - Taking partial profits at resistance ($2700.00 - $2875.00)\n\
+        - Setting stop losses 5-8% below your entry price";
+
+    /// A coin priced below a cent, shown with four significant figures
+    const SMALL_COIN_ANSWER: &str = "The current price of Shiba Inu (SHIB) is $0.00001200\n\n\
+        Key price levels for Shiba Inu (SHIB):\n\
+        - Strong support: $0.000009360\n\
+        - Support: $0.00001020\n\
+        - Current price: $0.00001200\n\
+        - Resistance: $0.00001380\n\
+        - Strong resistance: $0.00001464\n\n\
+        Based on these levels, consider:\n\
+        - Accumulating at support levels ($0.00001020 - $0.000009360)\n\
+        - Taking partial profits at resistance ($0.00001380 - $0.00001464)\n\
+        - Setting stop losses 10-15% below your entry price for this more volatile asset\n\n\
+        CoinGecko was unavailable (Network error), so this price comes from DefiLlama.";
+
+    /// An excerpt of the entry points answer
+    const ENTRY_POINTS_ANSWER: &str = "ENTRY POINTS ANALYSIS FOR BITCOIN (BTC):\n\n\
+        Current Price: $60000.00\n\n\
+        SUPPORT LEVELS (Potential Entry Points):\n\
+        - Strong support: $51000.00 (Excellent entry, high probability of bounce)\n\
+        - Mid support: $53400.00 (Very good entry opportunity)\n\
+        - Support: $55200.00 (Good entry, moderate probability of bounce)\n\n\
+        ENTRY STRATEGY RECOMMENDATIONS:\n\
+        2. Scaled Entry: Allocate 20% at current price, 30% at $55200.00, and 50% at $51000.00\n\n\
+        TIME HORIZON CONSIDERATIONS:\n\
+        - Short-term traders: Focus on tighter ranges between $55200.00 and $64800.00\n\
+        - Medium-term investors: Accumulate between $53400.00 and $51000.00, sell between $64800.00 and $69000.00\n\
+        - Long-term investors: Focus on accumulation at or below $55200.00, consider holding through volatility";
+
+    /// Answers that mention prices and levels without making a call
+    const NON_CALLS: &[&str] = &[
+        "The price of Bitcoin on 01-12-2024 was $96400.00. Since then, the price has changed by -3.10% to the current price of $93411.00.\n\n\
+        Based on historical data, here are some insights:\n\
+        - Major support levels tend to form at previous cycle lows\n\
+        - Historical data suggests accumulating during 30%+ drawdowns from all-time highs",
+        "The price of Ethereum (ETH) one month ago (01-09-2025) was $2400.00. Historical price data can help identify trends and potential support/resistance levels.",
+        "I couldn't find a reliable price for Aerodrome. One article from 2025-09-01 mentioned $1.20, but it may be stale.",
+        "Many traders buy when ETH is between $2,000 and $2,500, but that's not advice I'd give without knowing your goals.",
+    ];
+
+    fn recommendation(action: &str, low: f64, high: f64) -> Recommendation {
+        Recommendation {
+            id: 1,
+            user_id: 1,
+            coin_id: "ethereum".to_string(),
+            action: action.to_string(),
+            price_low: low,
+            price_high: high,
+            target_date: None,
+            made_at: at(1),
+        }
+    }
+
+    fn at(day: u32) -> NaiveDateTime {
+        NaiveDate::from_ymd_opt(2025, 9, day).unwrap().and_hms_opt(12, 0, 0).unwrap()
+    }
+
+    fn prices(values: &[(u32, f64)]) -> Vec<PricePoint> {
+        values
+            .iter()
+            .map(|&(day, price_usd)| PricePoint { id: 0, coin_id: "ethereum".to_string(), price_usd, fetched_at: at(day) })
+            .collect()
+    }
+
+    #[test]
+    fn test_extracts_standard_price_answer() {
+        let calls = extract_templated(PRICE_ANSWER);
+
+        assert_eq!(calls, vec![
+            Call { asset: "Ethereum (ETH)".to_string(), action: Action::Accumulate, price_low: 2125.0, price_high: 2300.0, target_date: None },
+            Call { asset: "Ethereum (ETH)".to_string(), action: Action::TakeProfit, price_low: 2700.0, price_high: 2875.0, target_date: None },
+        ]);
+    }
+
+    #[test]
+    fn test_extracts_small_coin_answer() {
+        let calls = extract_templated(SMALL_COIN_ANSWER);
+
+        assert_eq!(calls.len(), 2);
+        assert_eq!(calls[0].asset, "Shiba Inu (SHIB)");
+        assert_eq!((calls[0].price_low, calls[0].price_high), (0.00000936, 0.0000102));
+        assert_eq!((calls[1].price_low, calls[1].price_high), (0.0000138, 0.00001464));
+    }
+
+    #[test]
+    fn test_extracts_medium_term_zone_of_entry_points_answer() {
+        let calls = extract_templated(ENTRY_POINTS_ANSWER);
+
+        assert_eq!(calls.len(), 2);
+        assert_eq!(asset_key(&calls[0].asset), "btc");
+        assert_eq!((calls[0].action, calls[0].price_low, calls[0].price_high), (Action::Accumulate, 51000.0, 53400.0));
+        assert_eq!((calls[1].action, calls[1].price_low, calls[1].price_high), (Action::TakeProfit, 64800.0, 69000.0));
+    }
+
+    #[test]
+    fn test_extracts_explicit_calls() {
+        let calls = extract_templated("I'd accumulate ETH between $2,300 and $2,450 and take profits on SOL from $180 to $195.");
+
+        assert_eq!(calls, vec![
+            Call { asset: "ETH".to_string(), action: Action::Accumulate, price_low: 2300.0, price_high: 2450.0, target_date: None },
+            Call { asset: "SOL".to_string(), action: Action::TakeProfit, price_low: 180.0, price_high: 195.0, target_date: None },
+        ]);
+    }
+
+    #[test]
+    fn test_ignores_answers_without_calls() {
+        for answer in NON_CALLS {
+            assert!(extract_templated(answer).is_empty(), "extracted a call from: {}", answer);
+        }
+    }
+
+    #[test]
+    fn test_parse_extracted_calls_keeps_supported_calls() {
+        let response = "Given the setup, accumulate Solana between $140 and $150 over the next two weeks.";
+        let reply = r#"Here you go:
+        [{"asset": "Solana", "action": "accumulate", "price_low": 140, "price_high": 150, "target_date": null},
+         {"asset": "Bitcoin", "action": "buy", "price_low": 60000, "price_high": 62000},
+         {"asset": "Solana", "action": "sell", "price_low": 200, "price_high": 210},
+         {"asset": "Solana", "action": "buy", "price_low": 150, "price_high": 140},
+         {"asset": "Solana", "action": "buy", "price_low": 10, "price_high": 150}]"#;
+
+        let calls = parse_extracted_calls(reply, response);
+
+        assert_eq!(calls, vec![Call {
+            asset: "Solana".to_string(),
+            action: Action::Accumulate,
+            price_low: 140.0,
+            price_high: 150.0,
+            target_date: None,
+        }]);
+    }
+
+    #[test]
+    fn test_parse_extracted_calls_rejects_malformed_replies() {
+        let response = "Buy ETH at $2,300.";
+        assert!(parse_extracted_calls("no calls here", response).is_empty());
+        assert!(parse_extracted_calls(r#"[{"asset": "ETH", "action": "hodl", "price_low": 2300, "price_high": 2300}]"#, response).is_empty());
+        assert_eq!(
+            parse_extracted_calls(r#"[{"asset": "ETH", "action": "buy", "price_low": 2300, "price_high": 2300, "target_date": "2025-10-01"}]"#, response)[0].target_date,
+            NaiveDate::from_ymd_opt(2025, 10, 1)
+        );
+    }
+
+    #[test]
+    fn test_might_contain_call() {
+        assert!(might_contain_call("You could buy some ETH around $2,300."));
+        assert!(!might_contain_call("Buying ETH depends on your goals."));
+        assert!(!might_contain_call("ETH is at $2,300 today."));
+    }
+
+    #[test]
+    fn test_is_track_record_query() {
+        assert!(is_track_record_query("How have your calls worked out?"));
+        assert!(is_track_record_query("how did your recommendations do last month"));
+        assert!(is_track_record_query("What's your track record?"));
+        assert!(!is_track_record_query("Should I call my broker?"));
+    }
+
+    #[test]
+    fn test_score_buy_calls() {
+        let call = recommendation("accumulate", 2300.0, 2450.0);
+
+        // Reached the range, now above its midpoint of 2375
+        assert!(matches!(score(&call, &prices(&[(2, 2500.0), (3, 2400.0), (4, 2612.5)])), Verdict::Hit(change) if (change - 10.0).abs() < 1e-9));
+        // Reached the range, now below its midpoint
+        assert!(matches!(score(&call, &prices(&[(2, 2440.0), (3, 2137.5)])), Verdict::Miss(change) if (change + 10.0).abs() < 1e-9));
+        assert_eq!(score(&call, &prices(&[(2, 2600.0), (3, 2700.0)])), Verdict::NotTriggered);
+        assert_eq!(score(&call, &[]), Verdict::NoData);
+    }
+
+    #[test]
+    fn test_score_sell_calls() {
+        let call = recommendation("take_profit", 2700.0, 2900.0);
+
+        assert!(matches!(score(&call, &prices(&[(2, 2750.0), (3, 2520.0)])), Verdict::Hit(_)));
+        assert!(matches!(score(&call, &prices(&[(2, 2750.0), (3, 3000.0)])), Verdict::Miss(_)));
+        assert_eq!(score(&call, &prices(&[(2, 2500.0)])), Verdict::NotTriggered);
+    }
+
+    #[test]
+    fn test_score_ignores_prices_outside_the_window() {
+        let mut call = recommendation("buy", 2300.0, 2300.0);
+        call.target_date = NaiveDate::from_ymd_opt(2025, 9, 3);
+
+        // Before the call and after its target date don't count
+        let points = prices(&[(1, 2000.0), (2, 2500.0), (3, 2450.0), (5, 2000.0)]);
+        assert_eq!(score(&call, &points), Verdict::NotTriggered);
+    }
+
+    #[test]
+    fn test_render_track_record() {
+        let scored = vec![
+            (recommendation("accumulate", 2300.0, 2450.0), Verdict::Hit(10.0)),
+            (recommendation("take_profit", 2700.0, 2900.0), Verdict::Miss(-4.0)),
+            (recommendation("buy", 2000.0, 2000.0), Verdict::NotTriggered),
+            (recommendation("sell", 3000.0, 3100.0), Verdict::NoData),
+        ];
+
+        let output = render_track_record(&scored);
+
+        assert!(output.starts_with("Track record of my calls (4 recorded):"));
+        assert!(output.contains("- Hits: 1 (50%), misses: 1 (50%)\n- Average result: +3.00%"));
+        assert!(output.contains("- 1 call(s) haven't reached their price range yet"));
+        assert!(output.contains("- 1 call(s) have no price recorded since"));
+        assert!(output.contains(
+            "Calls:\n\
+            Date        Action       Coin      Range              Result\n\
+            ----------  -----------  --------  -----------------  -------------\n\
+            2025-09-01  accumulate   ethereum  $2300.00-$2450.00  hit, +10.00%\n\
+            2025-09-01  take profit  ethereum  $2700.00-$2900.00  miss, -4.00%\n\
+            2025-09-01  buy          ethereum  $2000.00           not triggered\n\
+            2025-09-01  sell         ethereum  $3000.00-$3100.00  no price data\n\
+            \nResults compare"
+        ));
+        assert!(render_track_record(&[]).contains("haven't made any concrete calls"));
+    }
+}
@@ -0,0 +1,584 @@
+use super::error::InvestmentChatError;
+use super::service;
+use crate::llm::{ChatModel, Message, json_payload};
+use crate::db::MessageRole;
+use crate::exa_api::ExaSearchResult;
+use async_trait::async_trait;
+use chrono::{DateTime, Duration, NaiveDate, Utc};
+use regex::Regex;
+use serde::Deserialize;
+use std::collections::{HashMap, HashSet};
+use std::sync::OnceLock;
+
+/// Articles fetched for one snapshot
+pub const SENTIMENT_ARTICLE_COUNT: usize = 15;
+
+/// Days of news looked at when the question doesn't say
+pub const DEFAULT_SENTIMENT_DAYS: i64 = 7;
+
+/// How long a snapshot is reused before the news is fetched again
+pub const SENTIMENT_CACHE_HOURS: i64 = 3;
+
+/// Headlines listed per side in the summary
+const TOP_HEADLINES: usize = 3;
+
+/// Characters of article content sent along with each title
+const SNIPPET_CHARS: usize = 300;
+
+const CLASSIFY_PROMPT: &str = "You classify crypto news for market sentiment towards one coin. \
+For each numbered article, decide whether it is positive, negative or neutral for the coin and give a one-line reason. \
+Reply with a JSON array only, one object per article, like \
+[{\"index\": 1, \"sentiment\": \"positive\", \"reason\": \"ETF inflows hit a record\"}]. \
+Use only \"positive\", \"negative\" or \"neutral\" as the sentiment.";
+
+/// Sentiment of one article towards the coin
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Sentiment {
+    Positive,
+    Negative,
+    Neutral,
+}
+
+/// A news article considered for the snapshot
+#[derive(Debug, Clone, PartialEq)]
+pub struct Article {
+    pub title: String,
+    pub url: String,
+    pub snippet: String,
+    pub published_date: NaiveDate,
+}
+
+/// The classifier's verdict on the article at `index`, counted from 1
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct Classification {
+    pub index: usize,
+    pub sentiment: Sentiment,
+    #[serde(default)]
+    pub reason: String,
+}
+
+/// An article with the classifier's reason
+#[derive(Debug, Clone, PartialEq)]
+pub struct ClassifiedArticle {
+    pub article: Article,
+    pub reason: String,
+}
+
+/// Aggregated sentiment of the recent news about a coin
+#[derive(Debug, Clone, PartialEq)]
+pub struct SentimentSnapshot {
+    pub days: i64,
+    pub generated_at: DateTime<Utc>,
+    pub positive: usize,
+    pub negative: usize,
+    pub neutral: usize,
+    /// Articles the classifier skipped
+    pub unclassified: usize,
+    /// Most relevant first
+    pub top_positive: Vec<ClassifiedArticle>,
+    pub top_negative: Vec<ClassifiedArticle>,
+}
+
+impl SentimentSnapshot {
+    pub fn classified(&self) -> usize {
+        self.positive + self.negative + self.neutral
+    }
+
+    /// Overall mood, from the balance of positive and negative articles
+    pub fn label(&self) -> &'static str {
+        let classified = self.classified();
+        if classified == 0 {
+            return "unknown";
+        }
+
+        let balance = (self.positive as f64 - self.negative as f64) / classified as f64;
+        if balance >= 0.5 {
+            "strongly positive"
+        } else if balance >= 0.2 {
+            "mostly positive"
+        } else if balance <= -0.5 {
+            "strongly negative"
+        } else if balance <= -0.2 {
+            "mostly negative"
+        } else {
+            "mixed"
+        }
+    }
+}
+
+/// Labels each article as positive, negative or neutral
+#[async_trait]
+pub trait SentimentClassifier: Send + Sync {
+    async fn classify(&self, coin: &str, articles: &[Article]) -> Result<Vec<Classification>, InvestmentChatError>;
+}
+
+/// Classifies every article in a single model call
+pub struct LlmClassifier<'a> {
+    model: &'a dyn ChatModel,
+}
+
+impl<'a> LlmClassifier<'a> {
+    pub fn new(model: &'a dyn ChatModel) -> Self {
+        Self { model }
+    }
+}
+
+#[async_trait]
+impl SentimentClassifier for LlmClassifier<'_> {
+    async fn classify(&self, coin: &str, articles: &[Article]) -> Result<Vec<Classification>, InvestmentChatError> {
+        let messages = [Message {
+            role: MessageRole::User,
+            content: classification_request(coin, articles),
+        }];
+        let reply = self.model
+            .generate_json(CLASSIFY_PROMPT, &messages, 1024)
+            .await
+            .map_err(service::describe_llm_error)?;
+
+        parse_classifications(&reply)
+    }
+}
+
+/// Parse "what's the sentiment around <coin> this week", returning the coin and the number of days
+pub fn parse_sentiment_query(message: &str) -> Option<(String, i64)> {
+    static QUERY: OnceLock<Regex> = OnceLock::new();
+    static LAST_DAYS: OnceLock<Regex> = OnceLock::new();
+    let query = QUERY.get_or_init(|| {
+        Regex::new(r"(?i)\b(?:sentiment|mood)\s+(?:around|on|for|of|about|towards?)\s+([a-z][a-z0-9-]*)").unwrap()
+    });
+    let last_days = LAST_DAYS.get_or_init(|| Regex::new(r"(?i)\b(?:last|past)\s+(\d{1,2})\s+days?\b").unwrap());
+
+    let coin = query.captures(message)?[1].to_lowercase();
+
+    let lower = message.to_lowercase();
+    let days = if let Some(caps) = last_days.captures(message) {
+        caps[1].parse::<i64>().unwrap_or(DEFAULT_SENTIMENT_DAYS).clamp(1, 30)
+    } else if lower.contains("today") || lower.contains("24 hours") {
+        1
+    } else if lower.contains("month") {
+        30
+    } else {
+        DEFAULT_SENTIMENT_DAYS
+    };
+
+    Some((coin, days))
+}
+
+/// Turn search results into articles published on or after `since`, dropping repeats and undated ones
+pub fn articles_since(results: &[ExaSearchResult], since: NaiveDate) -> Vec<Article> {
+    let mut seen = HashSet::new();
+    results
+        .iter()
+        .filter_map(|result| {
+            let published = result.published_date.as_deref()?.get(..10)?;
+            let published_date = NaiveDate::parse_from_str(published, "%Y-%m-%d").ok()?;
+            if published_date < since || !seen.insert(result.url.as_str()) {
+                return None;
+            }
+
+            Some(Article {
+                title: result.title.trim().to_string(),
+                url: result.url.clone(),
+                snippet: result.content.split_whitespace().collect::<Vec<_>>().join(" ").chars().take(SNIPPET_CHARS).collect(),
+                published_date,
+            })
+        })
+        .collect()
+}
+
+/// The numbered article list sent to the classifier
+pub fn classification_request(coin: &str, articles: &[Article]) -> String {
+    let mut request = format!("Coin: {}\n\n", coin);
+    for (i, article) in articles.iter().enumerate() {
+        request.push_str(&format!("{}. {}\n{}\n\n", i + 1, article.title, article.snippet));
+    }
+    request
+}
+
+/// Read the JSON array out of the classifier's reply, tolerating text or code fences around it
+pub fn parse_classifications(reply: &str) -> Result<Vec<Classification>, InvestmentChatError> {
+    let invalid = |detail: String| {
+        InvestmentChatError::LlmApi(format!("Failed to parse sentiment classification: {}", detail))
+    };
+
+    let reply = json_payload(reply);
+    let start = reply.find('[').ok_or_else(|| invalid("no JSON array in reply".to_string()))?;
+    let end = reply.rfind(']').filter(|&end| end > start).ok_or_else(|| invalid("unterminated JSON array".to_string()))?;
+    serde_json::from_str(&reply[start..=end]).map_err(|e| invalid(e.to_string()))
+}
+
+/// Count the classifications and pick the top headlines on each side
+///
+/// Classifications for unknown indices are ignored and the first one wins for
+/// an article classified twice.
+pub fn aggregate(
+    articles: &[Article],
+    classifications: &[Classification],
+    days: i64,
+    generated_at: DateTime<Utc>,
+) -> SentimentSnapshot {
+    let mut verdicts: HashMap<usize, &Classification> = HashMap::new();
+    for classification in classifications {
+        if (1..=articles.len()).contains(&classification.index) {
+            verdicts.entry(classification.index).or_insert(classification);
+        }
+    }
+
+    let mut snapshot = SentimentSnapshot {
+        days,
+        generated_at,
+        positive: 0,
+        negative: 0,
+        neutral: 0,
+        unclassified: 0,
+        top_positive: Vec::new(),
+        top_negative: Vec::new(),
+    };
+
+    for (i, article) in articles.iter().enumerate() {
+        let Some(verdict) = verdicts.get(&(i + 1)) else {
+            snapshot.unclassified += 1;
+            continue;
+        };
+
+        let classified = || ClassifiedArticle {
+            article: article.clone(),
+            reason: verdict.reason.trim().to_string(),
+        };
+        match verdict.sentiment {
+            Sentiment::Positive => {
+                snapshot.positive += 1;
+                if snapshot.top_positive.len() < TOP_HEADLINES {
+                    snapshot.top_positive.push(classified());
+                }
+            },
+            Sentiment::Negative => {
+                snapshot.negative += 1;
+                if snapshot.top_negative.len() < TOP_HEADLINES {
+                    snapshot.top_negative.push(classified());
+                }
+            },
+            Sentiment::Neutral => snapshot.neutral += 1,
+        }
+    }
+
+    snapshot
+}
+
+/// Classify the articles and aggregate the result
+pub async fn take_snapshot(
+    classifier: &dyn SentimentClassifier,
+    coin: &str,
+    articles: &[Article],
+    days: i64,
+    generated_at: DateTime<Utc>,
+) -> Result<SentimentSnapshot, InvestmentChatError> {
+    // Nothing to classify, don't spend a model call on it
+    let classifications = if articles.is_empty() {
+        Vec::new()
+    } else {
+        classifier.classify(coin, articles).await?
+    };
+
+    Ok(aggregate(articles, &classifications, days, generated_at))
+}
+
+/// Render a snapshot as a chat answer
+pub fn render_snapshot(display_name: &str, snapshot: &SentimentSnapshot) -> String {
+    let period = if snapshot.days == 1 {
+        "the last 24 hours".to_string()
+    } else {
+        format!("the last {} days", snapshot.days)
+    };
+
+    if snapshot.classified() == 0 {
+        return format!(
+            "I couldn't find any news about {} from {} that I could classify, so I can't gauge the sentiment.",
+            display_name, period
+        );
+    }
+
+    let mut output = format!(
+        "News sentiment for {} over {}: {}\n\n\
+        Based on {} article(s): {} positive, {} negative, {} neutral.",
+        display_name,
+        period,
+        snapshot.label(),
+        snapshot.classified(),
+        snapshot.positive,
+        snapshot.negative,
+        snapshot.neutral
+    );
+    if snapshot.unclassified > 0 {
+        output.push_str(&format!(" {} more could not be classified.", snapshot.unclassified));
+    }
+
+    for (title, articles) in [("Top positive headlines", &snapshot.top_positive), ("Top negative headlines", &snapshot.top_negative)] {
+        if articles.is_empty() {
+            continue;
+        }
+        output.push_str(&format!("\n\n{}:", title));
+        for classified in articles {
+            output.push_str(&format!(
+                "\n- [{}]({}) ({})",
+                classified.article.title,
+                classified.article.url,
+                classified.article.published_date.format("%Y-%m-%d")
+            ));
+            if !classified.reason.is_empty() {
+                output.push_str(&format!(": {}", classified.reason));
+            }
+        }
+    }
+
+    output.push_str(&format!(
+        "\n\nClassified from headlines and snippets as of {} UTC. This reflects news coverage, not a price prediction.",
+        snapshot.generated_at.format("%Y-%m-%d %H:%M")
+    ));
+
+    output
+}
+
+/// Snapshots per coin and period, reused for `SENTIMENT_CACHE_HOURS`
+#[derive(Debug, Default)]
+pub struct SentimentCache {
+    snapshots: HashMap<(String, i64), SentimentSnapshot>,
+}
+
+impl SentimentCache {
+    /// The cached snapshot, if it is still fresh at `now`
+    pub fn get(&self, coin_id: &str, days: i64, now: DateTime<Utc>) -> Option<&SentimentSnapshot> {
+        self.snapshots
+            .get(&(coin_id.to_string(), days))
+            .filter(|snapshot| now - snapshot.generated_at < Duration::hours(SENTIMENT_CACHE_HOURS))
+    }
+
+    pub fn insert(&mut self, coin_id: &str, snapshot: SentimentSnapshot) {
+        // Drop stale entries so the cache doesn't grow with every coin ever asked about
+        let now = snapshot.generated_at;
+        self.snapshots.retain(|_, cached| now - cached.generated_at < Duration::hours(SENTIMENT_CACHE_HOURS));
+        self.snapshots.insert((coin_id.to_string(), snapshot.days), snapshot);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::llm::END_OF_JSON;
+    use crate::llm::testing::MockChatModel;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    fn article(title: &str, day: u32) -> Article {
+        Article {
+            title: title.to_string(),
+            url: format!("https://news.example/{}", title.to_lowercase().replace(' ', "-")),
+            snippet: format!("{} snippet", title),
+            published_date: NaiveDate::from_ymd_opt(2025, 9, day).unwrap(),
+        }
+    }
+
+    fn classification(index: usize, sentiment: Sentiment, reason: &str) -> Classification {
+        Classification { index, sentiment, reason: reason.to_string() }
+    }
+
+    fn generated_at() -> DateTime<Utc> {
+        NaiveDate::from_ymd_opt(2025, 9, 24).unwrap().and_hms_opt(8, 0, 0).unwrap().and_utc()
+    }
+
+    /// Classifies articles by the first word of their title
+    #[derive(Default)]
+    struct MockClassifier {
+        calls: AtomicUsize,
+    }
+
+    #[async_trait]
+    impl SentimentClassifier for MockClassifier {
+        async fn classify(&self, _coin: &str, articles: &[Article]) -> Result<Vec<Classification>, InvestmentChatError> {
+            self.calls.fetch_add(1, Ordering::SeqCst);
+            Ok(articles
+                .iter()
+                .enumerate()
+                .map(|(i, article)| {
+                    let sentiment = match article.title.split_whitespace().next() {
+                        Some("Surge") => Sentiment::Positive,
+                        Some("Outage") => Sentiment::Negative,
+                        _ => Sentiment::Neutral,
+                    };
+                    classification(i + 1, sentiment, &format!("{} reason", article.title))
+                })
+                .collect())
+        }
+    }
+
+    #[test]
+    fn test_parse_sentiment_query() {
+        assert_eq!(parse_sentiment_query("What's the sentiment around solana this week"), Some(("solana".to_string(), 7)));
+        assert_eq!(parse_sentiment_query("sentiment on BTC today?"), Some(("btc".to_string(), 1)));
+        assert_eq!(parse_sentiment_query("market mood for eth over the last 3 days"), Some(("eth".to_string(), 3)));
+        assert_eq!(parse_sentiment_query("sentiment about aave this month"), Some(("aave".to_string(), 30)));
+        assert_eq!(parse_sentiment_query("sentiment for sol in the past 90 days"), Some(("sol".to_string(), 30)));
+        assert_eq!(parse_sentiment_query("what is the price of solana"), None);
+    }
+
+    #[test]
+    fn test_articles_since_drops_old_undated_and_repeated_results() {
+        let result = |url: &str, date: Option<&str>| ExaSearchResult {
+            id: url.to_string(),
+            url: url.to_string(),
+            title: format!(" {} ", url),
+            content: "Solana   network\nupgrade ".repeat(40),
+            score: 1.0,
+            published_date: date.map(str::to_string),
+            author: None,
+        };
+        let results = vec![
+            result("a", Some("2025-09-20T10:00:00.000Z")),
+            result("b", Some("2025-09-01T10:00:00.000Z")),
+            result("c", None),
+            result("a", Some("2025-09-21T10:00:00.000Z")),
+            result("d", Some("2025-09-17")),
+        ];
+
+        let articles = articles_since(&results, NaiveDate::from_ymd_opt(2025, 9, 17).unwrap());
+        let urls: Vec<&str> = articles.iter().map(|a| a.url.as_str()).collect();
+        assert_eq!(urls, vec!["a", "d"]);
+        assert_eq!(articles[0].title, "a");
+        assert!(articles[0].snippet.starts_with("Solana network upgrade Solana"));
+        assert_eq!(articles[0].snippet.chars().count(), SNIPPET_CHARS);
+    }
+
+    #[test]
+    fn test_parse_classifications() {
+        let reply = "Here you go:\n```json\n[{\"index\": 1, \"sentiment\": \"positive\", \"reason\": \"ETF inflows\"},\n\
+            {\"index\": 2, \"sentiment\": \"neutral\"}]\n```";
+        assert_eq!(
+            parse_classifications(reply).unwrap(),
+            vec![
+                classification(1, Sentiment::Positive, "ETF inflows"),
+                classification(2, Sentiment::Neutral, ""),
+            ]
+        );
+
+        // Prose after the JSON would otherwise be read as part of the array
+        let chatty = "[{\"index\": 1, \"sentiment\": \"negative\"}]\n###END###\nSources: [1] CoinDesk";
+        assert_eq!(parse_classifications(chatty).unwrap(), vec![classification(1, Sentiment::Negative, "")]);
+        let unterminated = "```json\n[{\"index\": 1, \"sentiment\": \"neutral\"}]\n```\nNote [2] was an ad.";
+        assert_eq!(parse_classifications(unterminated).unwrap(), vec![classification(1, Sentiment::Neutral, "")]);
+
+        assert!(parse_classifications("I can't classify these.").is_err());
+        assert!(parse_classifications("[{\"index\": 1, \"sentiment\": \"bullish\"}]").is_err());
+    }
+
+    #[test]
+    fn test_aggregate_counts_and_top_headlines() {
+        let articles: Vec<Article> = (1..=7).map(|i| article(&format!("Story {}", i), 20)).collect();
+        let classifications = vec![
+            classification(1, Sentiment::Positive, "one"),
+            classification(2, Sentiment::Negative, "two"),
+            classification(3, Sentiment::Positive, "three"),
+            classification(4, Sentiment::Positive, "four"),
+            classification(5, Sentiment::Positive, "five"),
+            classification(6, Sentiment::Neutral, "six"),
+            // Repeated and out of range indices are ignored, article 7 stays unclassified
+            classification(2, Sentiment::Positive, "again"),
+            classification(9, Sentiment::Negative, "nine"),
+        ];
+
+        let snapshot = aggregate(&articles, &classifications, 7, generated_at());
+        assert_eq!((snapshot.positive, snapshot.negative, snapshot.neutral, snapshot.unclassified), (4, 1, 1, 1));
+        let top: Vec<&str> = snapshot.top_positive.iter().map(|c| c.reason.as_str()).collect();
+        assert_eq!(top, vec!["one", "three", "four"]);
+        assert_eq!(snapshot.top_negative[0].article.title, "Story 2");
+        // (4 - 1) / 6 classified
+        assert_eq!(snapshot.label(), "strongly positive");
+    }
+
+    #[test]
+    fn test_labels() {
+        let mut snapshot = aggregate(&[], &[], 7, generated_at());
+        assert_eq!(snapshot.label(), "unknown");
+
+        let mut label = |positive, negative, neutral| {
+            snapshot.positive = positive;
+            snapshot.negative = negative;
+            snapshot.neutral = neutral;
+            snapshot.label()
+        };
+        assert_eq!(label(3, 2, 5), "mixed");
+        assert_eq!(label(4, 1, 5), "mostly positive");
+        assert_eq!(label(1, 4, 5), "mostly negative");
+        assert_eq!(label(0, 6, 1), "strongly negative");
+    }
+
+    #[test]
+    fn test_render_snapshot() {
+        let articles = vec![article("Surge in TVL", 22), article("Outage halts blocks", 21), article("Conference recap", 20)];
+        let classifications = vec![
+            classification(1, Sentiment::Positive, "TVL at a record"),
+            classification(2, Sentiment::Negative, "Network halted for 5 hours"),
+            classification(3, Sentiment::Neutral, ""),
+        ];
+        let output = render_snapshot("Solana", &aggregate(&articles, &classifications, 7, generated_at()));
+
+        assert!(output.starts_with("News sentiment for Solana over the last 7 days: mixed\n\n"));
+        assert!(output.contains("Based on 3 article(s): 1 positive, 1 negative, 1 neutral."));
+        assert!(output.contains(
+            "Top positive headlines:\n- [Surge in TVL](https://news.example/surge-in-tvl) (2025-09-22): TVL at a record"
+        ));
+        assert!(output.contains("Top negative headlines:\n- [Outage halts blocks]"));
+        assert!(output.ends_with("as of 2025-09-24 08:00 UTC. This reflects news coverage, not a price prediction."));
+
+        let empty = render_snapshot("Solana", &aggregate(&[], &[], 1, generated_at()));
+        assert_eq!(
+            empty,
+            "I couldn't find any news about Solana from the last 24 hours that I could classify, so I can't gauge the sentiment."
+        );
+    }
+
+    #[tokio::test]
+    async fn test_take_snapshot_uses_one_classifier_call() {
+        let classifier = MockClassifier::default();
+        let articles = vec![article("Surge in TVL", 22), article("Outage halts blocks", 21), article("Surge in fees", 20)];
+
+        let snapshot = take_snapshot(&classifier, "solana", &articles, 7, generated_at()).await.unwrap();
+        assert_eq!(classifier.calls.load(Ordering::SeqCst), 1);
+        assert_eq!((snapshot.positive, snapshot.negative, snapshot.neutral), (2, 1, 0));
+        assert_eq!(snapshot.top_negative[0].reason, "Outage halts blocks reason");
+
+        let snapshot = take_snapshot(&classifier, "solana", &[], 7, generated_at()).await.unwrap();
+        assert_eq!(classifier.calls.load(Ordering::SeqCst), 1);
+        assert_eq!(snapshot.classified(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_llm_classifier_asks_for_json() {
+        let model = MockChatModel::replying([r#"[{"index": 1, "sentiment": "positive", "reason": "TVL up"}]"#]);
+        let classifications = LlmClassifier::new(&model).classify("solana", &[article("Surge in TVL", 22)]).await.unwrap();
+        assert_eq!(classifications, vec![classification(1, Sentiment::Positive, "TVL up")]);
+
+        let request = &model.requests()[0];
+        assert!(request.system.starts_with(CLASSIFY_PROMPT) && request.system.contains(END_OF_JSON));
+        assert_eq!(request.options.stop_sequences, [END_OF_JSON]);
+        assert!(request.messages[0].content.contains("Surge in TVL"));
+
+        let failing = MockChatModel::failing();
+        assert!(LlmClassifier::new(&failing).classify("solana", &[article("Surge in TVL", 22)]).await.is_err());
+    }
+
+    #[test]
+    fn test_cache_expires_after_a_few_hours() {
+        let mut cache = SentimentCache::default();
+        cache.insert("solana", aggregate(&[article("Surge", 22)], &[classification(1, Sentiment::Positive, "")], 7, generated_at()));
+
+        let later = |hours| generated_at() + Duration::hours(hours);
+        assert_eq!(cache.get("solana", 7, later(1)).unwrap().positive, 1);
+        assert!(cache.get("solana", 1, later(1)).is_none());
+        assert!(cache.get("bitcoin", 7, later(1)).is_none());
+        assert!(cache.get("solana", 7, later(SENTIMENT_CACHE_HOURS)).is_none());
+
+        // A newer snapshot evicts the stale ones
+        cache.insert("bitcoin", aggregate(&[], &[], 7, later(SENTIMENT_CACHE_HOURS)));
+        assert_eq!(cache.snapshots.len(), 1);
+    }
+}
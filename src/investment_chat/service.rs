@@ -1,131 +1,327 @@
+use crate::config::Config;
+use crate::db::MessageRole;
 use crate::investment_chat::InvestmentChatError;
-use reqwest::Client;
-use serde_json::json;
+use crate::llm::{CallOptions, ChatModel, Completion, LlmError, Message, TokenUsage};
+use crate::offline;
+use std::future::Future;
 use std::time::Duration;
-use tracing::{debug, error};
+use tracing::{debug, error, warn};
 
-/// Get AI response using Anthropic API
-pub async fn get_ai_response(prompt: &str, api_key: &str) -> Result<String, InvestmentChatError> {
-    debug!("Preparing AI request with prompt length: {}", prompt.len());
+/// How long an answer or extraction may take
+pub(crate) const MODEL_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Splitting runs before every multi-question answer, so it gives up sooner
+pub(crate) const SPLIT_TIMEOUT: Duration = Duration::from_secs(15);
+
+/// Replies shorter than this once trimmed count as empty, unless MIN_RESPONSE_CHARS says otherwise
+pub const MIN_RESPONSE_CHARS: usize = 2;
+
+/// Appended to the prompt when the first reply came back empty
+const EMPTY_RESPONSE_NUDGE: &str = "Your previous reply was empty. Answer the message above directly in plain text.";
+
+/// Ends an answer whose stream stopped early, shown and saved with what arrived
+pub const INTERRUPTED_MARKER: &str = "[response interrupted]";
+
+/// System prompt of every answer, whatever the user asked
+const SYSTEM_PROMPT: &str = "You are Nova, a crypto investment advisor with expertise in blockchain, DeFi, NFTs, and crypto markets. \
+    You can research projects, analyze market trends, provide investment advice, and explain complex crypto concepts. \
+    When asked about specific projects, provide detailed information about their technology, tokenomics, team, \
+    recent developments, and investment potential. Include both strengths and risks in your analysis. \
+    If the user asks about prices, trading, or portfolio management, provide thoughtful advice while being clear \
+    about market uncertainties. Always be helpful, concise, and focused on providing value to the user.";
+
+/// Openers a model sometimes sends with no answer after them
+const PLANNING_OPENERS: [&str; 5] = ["let me think", "let me analyze", "let me look", "let me check", "i'll analyze"];
+
+/// Get an answer from `model`, at most `max_tokens` long
+pub async fn get_ai_response(model: &dyn ChatModel, prompt: &str, max_tokens: u32) -> Result<String, InvestmentChatError> {
+    get_ai_completion(model, prompt, max_tokens).await.map(|completion| completion.text)
+}
+
+/// Like `get_ai_response`, also returning the tokens used
+///
+/// An empty reply is asked again once with a nudge; a second one fails with `EmptyResponse`
+/// so nothing blank is shown or saved. The tokens of both calls are counted.
+pub async fn get_ai_completion(model: &dyn ChatModel, prompt: &str, max_tokens: u32) -> Result<Completion, InvestmentChatError> {
+    let min_chars = Config::get_instance().map(|config| config.min_response_chars).unwrap_or(MIN_RESPONSE_CHARS);
     
-    let client = Client::builder()
-        .timeout(Duration::from_secs(30))
-        .connect_timeout(Duration::from_secs(10))
-        .build()
-        .map_err(|e| InvestmentChatError::Internal(format!("Failed to build HTTP client: {}", e)))?;
+    let first = request_completion(model, prompt, max_tokens).await?;
+    if has_answer(&first.text, min_chars) {
+        return Ok(first);
+    }
+    warn!("Empty model response (request id {}), asking again", describe_request_id(&first));
     
-    // Create system prompt that enables the AI to handle all functionality
-    let system_prompt = "You are Nova, a crypto investment advisor with expertise in blockchain, DeFi, NFTs, and crypto markets. \
-        You can research projects, analyze market trends, provide investment advice, and explain complex crypto concepts. \
-        When asked about specific projects, provide detailed information about their technology, tokenomics, team, \
-        recent developments, and investment potential. Include both strengths and risks in your analysis. \
-        If the user asks about prices, trading, or portfolio management, provide thoughtful advice while being clear \
-        about market uncertainties. Always be helpful, concise, and focused on providing value to the user.";
+    let retry = request_completion(model, &format!("{}\n\n{}", prompt, EMPTY_RESPONSE_NUDGE), max_tokens).await?;
+    if has_answer(&retry.text, min_chars) {
+        return Ok(Completion { usage: first.usage + retry.usage, ..retry });
+    }
+    error!(
+        "Model response was empty again (request ids {} and {})",
+        describe_request_id(&first),
+        describe_request_id(&retry)
+    );
+    Err(InvestmentChatError::EmptyResponse { request_id: retry.request_id })
+}
+
+/// Whether a reply says something: at least `min_chars` once trimmed, and more than a planning opener
+pub(crate) fn has_answer(text: &str, min_chars: usize) -> bool {
+    let text = text.trim();
+    if text.chars().count() < min_chars.max(1) {
+        return false;
+    }
+    !text.lines().filter(|line| !line.trim().is_empty()).all(|line| {
+        let line = line.trim().to_lowercase();
+        PLANNING_OPENERS.iter().any(|opener| line.starts_with(opener))
+    })
+}
+
+/// A streamed answer, which may have stopped before the model finished
+#[derive(Debug, Clone, PartialEq)]
+pub struct StreamedCompletion {
+    pub completion: Completion,
+    /// The stream failed or was interrupted; the text is what arrived followed by `INTERRUPTED_MARKER`
+    pub truncated: bool,
+}
+
+/// Like `get_ai_completion`, passing each piece of the answer to `on_text` as it arrives
+///
+/// When the stream fails after some of the answer arrived, or `interrupted` completes first,
+/// the pieces received so far are kept and returned as a truncated answer rather than lost.
+/// A stream that fails before any text arrived fails like `get_ai_completion`. An empty
+/// answer isn't asked again, since whatever arrived was already shown.
+pub async fn stream_ai_completion(
+    model: &dyn ChatModel,
+    prompt: &str,
+    max_tokens: u32,
+    on_text: &(dyn Fn(&str) + Send + Sync),
+    interrupted: impl Future<Output = ()>,
+) -> Result<StreamedCompletion, InvestmentChatError> {
+    if offline::is_offline() {
+        return Err(InvestmentChatError::Offline("LLM API is unavailable in offline mode".to_string()));
+    }
     
-    let request_body = json!({
-        "model": "claude-3-opus-20240229",
-        "max_tokens": 2048,
-        "system": system_prompt,
-        "messages": [
-            {
-                "role": "user",
-                "content": prompt
+    let messages = [Message {
+        role: MessageRole::User,
+        content: prompt.to_string(),
+    }];
+    let options = CallOptions::new(max_tokens);
+    let mut received = String::new();
+    let outcome = {
+        let mut on_piece = |piece: &str| {
+            received.push_str(piece);
+            on_text(piece);
+        };
+        let streaming = model.generate_streaming(SYSTEM_PROMPT, &messages, &options, &mut on_piece);
+        tokio::select! {
+            // Pieces that already arrived win over an interruption
+            biased;
+            result = streaming => Some(result),
+            _ = interrupted => None,
+        }
+    };
+    
+    let error = match outcome {
+        Some(Ok(completion)) => {
+            let min_chars = Config::get_instance().map(|config| config.min_response_chars).unwrap_or(MIN_RESPONSE_CHARS);
+            if !has_answer(&completion.text, min_chars) {
+                error!("Streamed model response was empty (request id {})", describe_request_id(&completion));
+                return Err(InvestmentChatError::EmptyResponse { request_id: completion.request_id });
             }
-        ]
-    });
+            return Ok(StreamedCompletion { completion, truncated: false });
+        },
+        Some(Err(e)) if received.trim().is_empty() => {
+            let error = describe_llm_error(e);
+            error!("LLM API error: {}", error);
+            return Err(error);
+        },
+        Some(Err(e)) => e.to_string(),
+        None => "interrupted".to_string(),
+    };
+    warn!("Answer stream stopped after {} bytes ({}), keeping the partial answer", received.len(), error);
     
-    debug!("Sending request to Anthropic API");
-    let response_result = client
-        .post("https://api.anthropic.com/v1/messages")
-        .header("x-api-key", api_key)
-        .header("anthropic-version", "2023-06-01")
-        .header("content-type", "application/json")
-        .json(&request_body)
-        .send()
-        .await;
-        
-    // Enhanced error handling for network/request errors
-    let response = match response_result {
-        Ok(resp) => resp,
-        Err(e) => {
-            let detailed_error = if e.is_timeout() {
-                format!("API request timed out: {}", e)
-            } else if e.is_connect() {
-                format!("Connection error: {}. Please check your internet connection and API endpoint.", e)
-            } else if e.is_status() {
-                format!("HTTP status error: {}", e)
-            } else {
-                format!("API request failed: {}", e)
-            };
-            
-            error!("Anthropic API error: {}", detailed_error);
-            return Err(InvestmentChatError::AnthropicApi(detailed_error));
-        }
+    let text = match received.trim_end() {
+        "" => INTERRUPTED_MARKER.to_string(),
+        partial => format!("{}\n\n{}", partial, INTERRUPTED_MARKER),
     };
-        
-    // Enhanced error handling for HTTP status errors
-    if !response.status().is_success() {
-        let status = response.status();
-        
-        // Try to get error details from response body
-        let error_body = match response.text().await {
-            Ok(body) => {
-                // Try to parse as JSON for better error details
-                match serde_json::from_str::<serde_json::Value>(&body) {
-                    Ok(json_body) => {
-                        if let Some(error_msg) = json_body.get("error").and_then(|e| e.get("message")).and_then(|m| m.as_str()) {
-                            format!("API error message: {}", error_msg)
-                        } else {
-                            format!("Response body: {}", body)
-                        }
-                    },
-                    Err(_) => format!("Response body: {}", body)
-                }
-            },
-            Err(_) => "Could not read response body".to_string()
-        };
-        
-        let error_msg = match status.as_u16() {
-            401 => format!("Authentication error (401): Invalid API key. Please check your ANTHROPIC_API_KEY. {}", error_body),
-            403 => format!("Authorization error (403): Your API key doesn't have permission. {}", error_body),
-            429 => format!("Rate limit exceeded (429): Too many requests. Please try again later. {}", error_body),
-            500..=599 => format!("Server error ({}): Anthropic API is experiencing issues. Please try again later. {}", status.as_u16(), error_body),
-            _ => format!("API returned error status: {} - {}", status, error_body)
-        };
-        
-        error!("Anthropic API error: {}", error_msg);
-        return Err(InvestmentChatError::AnthropicApi(error_msg));
+    // Providers report usage at the end of the stream, so what the partial answer cost isn't known
+    let completion = Completion { text, usage: TokenUsage::default(), stop_sequence: None, request_id: None };
+    Ok(StreamedCompletion { completion, truncated: true })
+}
+
+fn describe_request_id(completion: &Completion) -> &str {
+    completion.request_id.as_deref().unwrap_or("unknown")
+}
+
+async fn request_completion(model: &dyn ChatModel, prompt: &str, max_tokens: u32) -> Result<Completion, InvestmentChatError> {
+    debug!("Preparing AI request with prompt length: {}", prompt.len());
+    
+    if offline::is_offline() {
+        return Err(InvestmentChatError::Offline("LLM API is unavailable in offline mode".to_string()));
     }
     
-    debug!("Parsing Anthropic API response");
-    let response_json_result = response.json::<serde_json::Value>().await;
+    let messages = [Message {
+        role: MessageRole::User,
+        content: prompt.to_string(),
+    }];
+    
+    debug!("Sending request to the LLM API");
+    let completion = model
+        .generate(SYSTEM_PROMPT, &messages, &CallOptions::new(max_tokens))
+        .await
+        .map_err(|e| {
+            let error = describe_llm_error(e);
+            error!("LLM API error: {}", error);
+            error
+        })?;
     
-    // Enhanced error handling for JSON parsing
-    let response_json = match response_json_result {
-        Ok(json) => json,
-        Err(e) => {
-            let error_msg = format!("Failed to parse API response: {}. This may indicate an issue with the API or a change in response format.", e);
-            error!("Anthropic API error: {}", error_msg);
-            return Err(InvestmentChatError::AnthropicApi(error_msg));
+    debug!("Successfully received AI response with length: {}", completion.text.len());
+    Ok(completion)
+}
+
+/// Turn a model client error into a user-facing chat error
+pub(crate) fn describe_llm_error(error: LlmError) -> InvestmentChatError {
+    let message = match error {
+        LlmError::Offline => {
+            return InvestmentChatError::Offline("LLM API is unavailable in offline mode".to_string());
+        }
+        LlmError::Http(e) if offline::is_offline() => {
+            return InvestmentChatError::Offline(format!("Connection error: {}", e));
         }
+        LlmError::Http(e) if e.is_timeout() => format!("API request timed out: {}", e),
+        LlmError::Http(e) => format!("API request failed: {}", e),
+        LlmError::Unauthorized(body) => {
+            format!("Authentication error (401): Invalid API key. Please check the API key of your LLM_PROVIDER. {}", body)
+        }
+        LlmError::RateLimited(retry_after) => match retry_after {
+            Some(delay) => format!("Rate limit exceeded (429): Too many requests. Please try again in {}s.", delay.as_secs()),
+            None => "Rate limit exceeded (429): Too many requests. Please try again later.".to_string(),
+        },
+        LlmError::Api { status, message } => match status.as_u16() {
+            403 => format!("Authorization error (403): Your API key doesn't have permission. {}", message),
+            500..=599 => format!(
+                "Server error ({}): the LLM API is experiencing issues. Please try again later. {}",
+                status.as_u16(), message
+            ),
+            _ => format!("API returned error status: {} - {}", status, message),
+        },
+        LlmError::InvalidResponse(detail) => format!(
+            "Failed to parse API response: {}. This may indicate an issue with the API or a change in response format.",
+            detail
+        ),
+        LlmError::ApiKeyNotFound => "LLM API key not configured, set the key of your LLM_PROVIDER".to_string(),
     };
     
-    // Extract the response text with better error handling
-    let response_text = match response_json.get("content")
-        .and_then(|content| content.get(0))
-        .and_then(|first| first.get("text"))
-        .and_then(|text| text.as_str()) {
-            Some(text) => text,
-            None => {
-                let error_msg = format!(
-                    "Failed to extract response text. Unexpected response structure: {}",
-                    response_json.to_string()
-                );
-                error!("Anthropic API error: {}", error_msg);
-                return Err(InvestmentChatError::AnthropicApi(error_msg));
-            }
-        };
-    
-    debug!("Successfully received AI response with length: {}", response_text.len());
-    Ok(response_text.to_string())
+    InvestmentChatError::LlmApi(message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::llm::testing::MockChatModel;
+    use reqwest::StatusCode;
+
+    #[test]
+    fn test_has_answer() {
+        assert!(has_answer("Yes.", MIN_RESPONSE_CHARS));
+        assert!(has_answer("Let me think about this.\n\nAERO is Aerodrome's token.", MIN_RESPONSE_CHARS));
+        assert!(!has_answer("", MIN_RESPONSE_CHARS));
+        assert!(!has_answer(" \n\t ", MIN_RESPONSE_CHARS));
+        assert!(!has_answer(".", MIN_RESPONSE_CHARS));
+        assert!(!has_answer("Let me think about this...\n\n", MIN_RESPONSE_CHARS));
+        assert!(!has_answer("Short", 10));
+    }
+
+    #[tokio::test]
+    async fn test_empty_reply_is_asked_again_with_a_nudge() {
+        let model = MockChatModel::replying(["", "AERO is Aerodrome's token."]);
+
+        let completion = get_ai_completion(&model, "What is AERO?", 64).await.unwrap();
+        assert_eq!(completion.text, "AERO is Aerodrome's token.");
+        // The empty reply's tokens were billed too
+        assert_eq!(completion.usage.input_tokens, 20);
+
+        let requests = model.requests();
+        assert_eq!(requests.len(), 2);
+        assert_eq!(requests[0].messages[0].content, "What is AERO?");
+        assert_eq!(requests[1].messages[0].content, format!("What is AERO?\n\n{}", EMPTY_RESPONSE_NUDGE));
+    }
+
+    #[tokio::test]
+    async fn test_whitespace_twice_fails_with_the_request_id() {
+        let model = MockChatModel::replying(["  \n", "\n\n"]);
+
+        let error = get_ai_completion(&model, "What is AERO?", 64).await.unwrap_err();
+        assert!(matches!(&error, InvestmentChatError::EmptyResponse { request_id } if request_id.as_deref() == Some("mock-2")));
+        assert_eq!(error.to_string(), "I didn't get an answer this time, please ask again");
+        assert_eq!(model.requests().len(), 2);
+    }
+
+    fn collect(pieces: &std::sync::Mutex<Vec<String>>) -> impl Fn(&str) + Send + Sync + '_ {
+        |piece: &str| pieces.lock().unwrap().push(piece.to_string())
+    }
+
+    #[tokio::test]
+    async fn test_stream_failing_midway_keeps_the_partial_answer() {
+        let model = MockChatModel::cut_off("AERO is the governance token of");
+        let pieces = std::sync::Mutex::new(Vec::new());
+
+        let streamed = stream_ai_completion(&model, "What is AERO?", 64, &collect(&pieces), std::future::pending())
+            .await
+            .unwrap();
+        assert!(streamed.truncated);
+        assert_eq!(streamed.completion.text, format!("AERO is the governance token of\n\n{}", INTERRUPTED_MARKER));
+        assert_eq!(pieces.lock().unwrap().concat(), "AERO is the governance token of");
+        assert_eq!(streamed.completion.usage, TokenUsage::default());
+        assert_eq!(model.requests().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_interrupted_stream_keeps_the_partial_answer() {
+        let model = MockChatModel::stalled("Liquidity pools pair ");
+        let pieces = std::sync::Mutex::new(Vec::new());
+
+        let streamed = stream_ai_completion(&model, "pools?", 64, &collect(&pieces), async {}).await.unwrap();
+        assert!(streamed.truncated);
+        assert_eq!(streamed.completion.text, format!("Liquidity pools pair\n\n{}", INTERRUPTED_MARKER));
+
+        // Interrupted before anything arrived, the marker alone is the answer
+        let model = MockChatModel::stalled("");
+        let streamed = stream_ai_completion(&model, "pools?", 64, &collect(&pieces), async {}).await.unwrap();
+        assert_eq!((streamed.completion.text.as_str(), streamed.truncated), (INTERRUPTED_MARKER, true));
+    }
+
+    #[tokio::test]
+    async fn test_finished_and_failed_streams() {
+        let pieces = std::sync::Mutex::new(Vec::new());
+        let model = MockChatModel::replying(["AERO is Aerodrome's token."]);
+        let streamed = stream_ai_completion(&model, "What is AERO?", 64, &collect(&pieces), std::future::pending())
+            .await
+            .unwrap();
+        assert!(!streamed.truncated);
+        assert_eq!(streamed.completion.text, "AERO is Aerodrome's token.");
+        assert_eq!(pieces.lock().unwrap().concat(), "AERO is Aerodrome's token.");
+
+        // Nothing arrived, so there is nothing to keep
+        let error = stream_ai_completion(&MockChatModel::cut_off(""), "What is AERO?", 64, &collect(&pieces), std::future::pending())
+            .await
+            .unwrap_err();
+        assert!(matches!(error, InvestmentChatError::LlmApi(_)));
+    }
+
+    #[test]
+    fn test_llm_errors_keep_user_facing_messages() {
+        let error = describe_llm_error(LlmError::Unauthorized("invalid x-api-key".to_string()));
+        assert!(error.to_string().contains("Authentication error (401)"));
+
+        let error = describe_llm_error(LlmError::RateLimited(Some(Duration::from_secs(20))));
+        assert!(error.to_string().contains("try again in 20s"));
+
+        let error = describe_llm_error(LlmError::Api {
+            status: StatusCode::BAD_GATEWAY,
+            message: "upstream".to_string(),
+        });
+        assert!(error.to_string().contains("Server error (502)"));
+
+        assert!(matches!(describe_llm_error(LlmError::Offline), InvestmentChatError::Offline(_)));
+    }
 }
@@ -0,0 +1,503 @@
+use super::context::estimate_tokens;
+use crate::db::Knowledge;
+use regex::Regex;
+use std::sync::OnceLock;
+
+/// Largest chunk of an entry placed in a scoped prompt, in bytes
+pub const CHUNK_BYTES: usize = 1_500;
+
+/// Entries listed when a source can't be resolved
+const MAX_CANDIDATES: usize = 10;
+
+// Words that carry no meaning when naming a source
+const FILLER_WORDS: &[&str] = &["the", "a", "an", "my", "our", "of", "on", "about", "for"];
+
+// Words that say the user means a stored document rather than, say, "according to analysts"
+const DOCUMENT_WORDS: &[&str] = &[
+    "whitepaper", "paper", "doc", "docs", "document", "documentation", "article", "notes", "note",
+    "entry", "knowledge", "source", "report", "research", "litepaper",
+];
+
+const SCOPED_INSTRUCTIONS: &str = "Answer the user's question using ONLY the material below. Do not use outside \
+knowledge, even if you know more about the topic. If the material doesn't contain the answer, say plainly that it \
+isn't covered there instead of guessing, and mention what the material does cover if that helps.\n\n";
+
+const MATERIAL_HEADER: &str = "MATERIAL FROM ";
+const QUERY_HEADER: &str = "\nUSER QUERY: ";
+
+// Room for the "[Part i/n]" label around each chunk and the note saying the entry was cut
+const PART_LABEL_TOKENS: usize = 6;
+const EXCERPT_NOTE_TOKENS: usize = 32;
+
+/// A question the user wants answered from one knowledge entry
+#[derive(Debug, Clone, PartialEq)]
+pub struct ScopedQuestion {
+    /// How the user named the entry, e.g. "pendle whitepaper"
+    pub source: String,
+    pub question: String,
+}
+
+/// Outcome of looking up the entry a question names
+#[derive(Debug)]
+pub enum SourceResolution<'a> {
+    Found(&'a Knowledge),
+    /// Several entries match equally well
+    Ambiguous(Vec<&'a Knowledge>),
+    /// Nothing matches, holds the entries the user could pick from instead
+    NoMatch(Vec<&'a Knowledge>),
+}
+
+/// Parse "using the X, <question>", "according to X, <question>" and "<question> according to X"
+pub fn parse_scoped_question(message: &str) -> Option<ScopedQuestion> {
+    static LEADING: OnceLock<Regex> = OnceLock::new();
+    static TRAILING: OnceLock<Regex> = OnceLock::new();
+    let leading = LEADING.get_or_init(|| {
+        Regex::new(r"(?is)^\s*(?:using|according to|based on)\s+(.+?)\s*[,:]\s*(.+?)\s*$").unwrap()
+    });
+    let trailing = TRAILING.get_or_init(|| {
+        Regex::new(r"(?is)^\s*(.+?)\s*,?\s+(?:according to|based on)\s+(.+?)\s*[?.!]?\s*$").unwrap()
+    });
+
+    let (source, question) = if let Some(caps) = leading.captures(message) {
+        (caps[1].to_string(), caps[2].to_string())
+    } else {
+        let caps = trailing.captures(message)?;
+        (caps[2].to_string(), caps[1].to_string())
+    };
+
+    let source = normalize_source(&source);
+    if source.is_empty() || question.trim().is_empty() {
+        return None;
+    }
+
+    Some(ScopedQuestion { source, question: question.trim().to_string() })
+}
+
+/// Whether the words naming a source point at a stored document
+pub fn names_document(source: &str) -> bool {
+    tokens(source).iter().any(|token| DOCUMENT_WORDS.contains(&token.as_str()))
+}
+
+/// Find the entry a source name refers to, matching it against source ids and tags
+///
+/// An exact source id wins outright. Otherwise entries are scored by how many words
+/// of the name match their source id or tags, allowing prefixes and one typo, and
+/// the single best entry is picked.
+pub fn resolve_source<'a>(source: &str, entries: &'a [Knowledge]) -> SourceResolution<'a> {
+    let wanted = tokens(source);
+    let joined = wanted.join("_");
+    if let Some(entry) = entries.iter().find(|entry| tokens(&entry.source_id).join("_") == joined) {
+        return SourceResolution::Found(entry);
+    }
+
+    // Document words only count once a meaningful word matched, or every whitepaper would tie
+    let (document_words, core_words): (Vec<&String>, Vec<&String>) =
+        wanted.iter().partition(|token| DOCUMENT_WORDS.contains(&token.as_str()));
+    let required = if core_words.is_empty() { &document_words } else { &core_words };
+
+    let mut scored: Vec<(usize, &Knowledge)> = entries
+        .iter()
+        .filter_map(|entry| {
+            let entry_words = entry_words(entry);
+            if !required.iter().any(|word| matches_any(word, &entry_words)) {
+                return None;
+            }
+            let score = wanted.iter().map(|word| word_score(word, &entry_words)).sum();
+            Some((score, entry))
+        })
+        .collect();
+    scored.sort_by(|a, b| b.0.cmp(&a.0).then_with(|| a.1.source_id.cmp(&b.1.source_id)));
+
+    match scored.as_slice() {
+        [] => {
+            let mut candidates: Vec<&Knowledge> = entries.iter().collect();
+            candidates.sort_by(|a, b| a.source_id.cmp(&b.source_id));
+            candidates.truncate(MAX_CANDIDATES);
+            SourceResolution::NoMatch(candidates)
+        },
+        [(_, entry)] => SourceResolution::Found(entry),
+        [(best, entry), (second, _), ..] if best > second => SourceResolution::Found(entry),
+        [(best, _), ..] => {
+            let best = *best;
+            SourceResolution::Ambiguous(
+                scored
+                    .iter()
+                    .take_while(|(score, _)| *score == best)
+                    .map(|(_, entry)| *entry)
+                    .take(MAX_CANDIDATES)
+                    .collect(),
+            )
+        },
+    }
+}
+
+/// Build a prompt holding only the entry's content and the question
+///
+/// Content that doesn't fit the budget is cut into chunks and the chunks sharing the
+/// most words with the question are kept, in their original order.
+pub fn build_scoped_prompt(entry: &Knowledge, question: &str, token_budget: usize) -> String {
+    let header = format!("{}\"{}\":\n\n", MATERIAL_HEADER, entry.source_id);
+    let fixed = estimate_tokens(SCOPED_INSTRUCTIONS) + estimate_tokens(&header) + estimate_tokens(QUERY_HEADER)
+        + estimate_tokens(question);
+    let available = token_budget.saturating_sub(fixed);
+
+    let chunks = chunk_content(&entry.content, CHUNK_BYTES);
+    let total: usize = chunks.iter().map(|chunk| part_tokens(chunk)).sum();
+    let selected: Vec<usize> = if total <= available {
+        (0..chunks.len()).collect()
+    } else {
+        select_chunks(&chunks, question, available.saturating_sub(EXCERPT_NOTE_TOKENS))
+    };
+
+    let mut prompt = String::from(SCOPED_INSTRUCTIONS);
+    prompt.push_str(&header);
+    if selected.len() < chunks.len() {
+        prompt.push_str(&format!(
+            "(Excerpts: {} of {} parts of this entry fit, chosen for relevance to the question)\n\n",
+            selected.len(),
+            chunks.len()
+        ));
+    }
+    for index in selected {
+        prompt.push_str(&format!("[Part {}/{}]\n{}\n\n", index + 1, chunks.len(), chunks[index]));
+    }
+    prompt.push_str(QUERY_HEADER);
+    prompt.push_str(question);
+
+    prompt
+}
+
+/// Reply for a source that didn't resolve to a single entry, listing the candidates
+pub fn render_unresolved(scoped: &ScopedQuestion, candidates: &[&Knowledge], ambiguous: bool) -> String {
+    let mut reply = if ambiguous {
+        format!("\"{}\" matches more than one entry in your knowledge:\n", scoped.source)
+    } else if candidates.is_empty() {
+        return format!(
+            "I couldn't find \"{}\" because you don't have any stored knowledge yet. \
+            Import the document first, then ask again.",
+            scoped.source
+        );
+    } else {
+        format!("I couldn't find \"{}\" in your knowledge. These are the entries you have:\n", scoped.source)
+    };
+
+    for entry in candidates {
+        if entry.tags.is_empty() {
+            reply.push_str(&format!("- {}\n", entry.source_id));
+        } else {
+            reply.push_str(&format!("- {} (tags: {})\n", entry.source_id, entry.tags.join(", ")));
+        }
+    }
+    if let Some(first) = candidates.first() {
+        reply.push_str(&format!("\nName one of them, e.g. \"using {}, {}\".", first.source_id, scoped.question));
+    }
+
+    reply
+}
+
+/// Split content into chunks of at most `max_bytes`, on paragraph boundaries where possible
+pub fn chunk_content(content: &str, max_bytes: usize) -> Vec<String> {
+    let mut chunks = Vec::new();
+    let mut current = String::new();
+
+    let paragraphs = content.split("\n\n").map(str::trim).filter(|paragraph| !paragraph.is_empty());
+    for paragraph in paragraphs.flat_map(|paragraph| split_long(paragraph, max_bytes)) {
+        if !current.is_empty() && current.len() + "\n\n".len() + paragraph.len() > max_bytes {
+            chunks.push(std::mem::take(&mut current));
+        }
+        if !current.is_empty() {
+            current.push_str("\n\n");
+        }
+        current.push_str(paragraph);
+    }
+    if !current.is_empty() {
+        chunks.push(current);
+    }
+
+    chunks
+}
+
+/// Split a paragraph longer than `max_bytes` at whitespace, or at any character if it has none
+fn split_long(paragraph: &str, max_bytes: usize) -> Vec<&str> {
+    let mut pieces = Vec::new();
+    let mut rest = paragraph;
+    while rest.len() > max_bytes {
+        let mut end = max_bytes;
+        while !rest.is_char_boundary(end) {
+            end -= 1;
+        }
+        let cut = rest[..end].rfind(char::is_whitespace).filter(|&cut| cut > 0).unwrap_or(end);
+        pieces.push(rest[..cut].trim_end());
+        rest = rest[cut..].trim_start();
+    }
+    if !rest.is_empty() {
+        pieces.push(rest);
+    }
+    pieces
+}
+
+/// Indices of the chunks most relevant to the question that fit in `available` tokens, in order
+fn select_chunks(chunks: &[String], question: &str, available: usize) -> Vec<usize> {
+    let terms: Vec<String> = tokens(question).into_iter().filter(|term| term.len() >= 2).collect();
+    let mut ranked: Vec<(usize, usize)> = chunks
+        .iter()
+        .enumerate()
+        .map(|(index, chunk)| {
+            let words = tokens(chunk);
+            let hits = words.iter().filter(|word| terms.contains(word)).count();
+            (hits, index)
+        })
+        .collect();
+    // Most hits first, earlier chunks first among equals
+    ranked.sort_by(|a, b| b.0.cmp(&a.0).then_with(|| a.1.cmp(&b.1)));
+
+    let mut used = 0;
+    let mut selected = Vec::new();
+    for (_, index) in ranked {
+        let cost = part_tokens(&chunks[index]);
+        if used + cost <= available {
+            used += cost;
+            selected.push(index);
+        }
+    }
+    selected.sort_unstable();
+    selected
+}
+
+fn part_tokens(chunk: &str) -> usize {
+    estimate_tokens(chunk) + PART_LABEL_TOKENS
+}
+
+/// Lowercased words of a source id, tag or text, without filler words
+fn tokens(text: &str) -> Vec<String> {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(str::to_lowercase)
+        .filter(|word| !FILLER_WORDS.contains(&word.as_str()))
+        .collect()
+}
+
+fn entry_words(entry: &Knowledge) -> Vec<String> {
+    let mut words = tokens(&entry.source_id);
+    words.extend(entry.tags.iter().flat_map(|tag| tokens(tag)));
+    words
+}
+
+fn matches_any(word: &str, entry_words: &[String]) -> bool {
+    word_score(word, entry_words) > 0
+}
+
+/// 2 for an exact word, 1 for a prefix or a single typo
+fn word_score(word: &str, entry_words: &[String]) -> usize {
+    entry_words
+        .iter()
+        .map(|candidate| {
+            let prefix = word.len() >= 3
+                && (candidate.starts_with(word) || (candidate.len() >= 3 && word.starts_with(candidate.as_str())));
+            let typo = word.len() >= 5 && within_one_edit(word, candidate);
+            if candidate == word {
+                2
+            } else if prefix || typo {
+                1
+            } else {
+                0
+            }
+        })
+        .max()
+        .unwrap_or(0)
+}
+
+/// Whether two words differ by at most one inserted, removed, replaced or swapped character
+fn within_one_edit(a: &str, b: &str) -> bool {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+    let (shorter, longer) = if a.len() <= b.len() { (&a, &b) } else { (&b, &a) };
+    if longer.len() - shorter.len() > 1 {
+        return false;
+    }
+
+    let prefix = shorter.iter().zip(longer.iter()).take_while(|(x, y)| x == y).count();
+    if prefix == shorter.len() {
+        return true;
+    }
+    if shorter.len() == longer.len() {
+        let replaced = shorter[prefix + 1..] == longer[prefix + 1..];
+        let swapped = prefix + 1 < shorter.len()
+            && shorter[prefix] == longer[prefix + 1]
+            && shorter[prefix + 1] == longer[prefix]
+            && shorter[prefix + 2..] == longer[prefix + 2..];
+        replaced || swapped
+    } else {
+        shorter[prefix..] == longer[prefix + 1..]
+    }
+}
+
+fn normalize_source(source: &str) -> String {
+    let source = source.trim().trim_matches(['"', '\'']);
+    let source = source
+        .strip_prefix("the ")
+        .or_else(|| source.strip_prefix("The "))
+        .or_else(|| source.strip_prefix("my "))
+        .or_else(|| source.strip_prefix("My "))
+        .unwrap_or(source);
+    source.trim().trim_matches(['"', '\'']).split_whitespace().collect::<Vec<_>>().join(" ").to_lowercase()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::NaiveDateTime;
+
+    fn knowledge(source_id: &str, tags: &[&str], content: &str) -> Knowledge {
+        Knowledge {
+            id: 0,
+            user_id: 1,
+            source_id: source_id.to_string(),
+            content: content.to_string(),
+            tags: tags.iter().map(|tag| tag.to_string()).collect(),
+            created_at: NaiveDateTime::default(),
+            updated_at: NaiveDateTime::default(),
+        }
+    }
+
+    fn entries() -> Vec<Knowledge> {
+        vec![
+            knowledge("pendle_whitepaper", &["pendle", "whitepaper", "yield"], "Pendle splits yield."),
+            knowledge("aave_v3_docs", &["aave", "lending"], "Aave lends."),
+            knowledge("uniswap_v3_whitepaper", &["uniswap", "whitepaper"], "Concentrated liquidity."),
+            knowledge("uniswap_v4_whitepaper", &["uniswap", "whitepaper", "hooks"], "Hooks."),
+        ]
+    }
+
+    fn found(resolution: SourceResolution<'_>) -> Option<&str> {
+        match resolution {
+            SourceResolution::Found(entry) => Some(entry.source_id.as_str()),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn test_parse_scoped_questions() {
+        assert_eq!(
+            parse_scoped_question("using the pendle whitepaper, explain how PT and YT work"),
+            Some(ScopedQuestion {
+                source: "pendle whitepaper".to_string(),
+                question: "explain how PT and YT work".to_string(),
+            })
+        );
+        assert_eq!(
+            parse_scoped_question("According to aave_v3_docs: what is e-mode?"),
+            Some(ScopedQuestion { source: "aave_v3_docs".to_string(), question: "what is e-mode?".to_string() })
+        );
+        assert_eq!(
+            parse_scoped_question("What are hooks, according to the uniswap v4 whitepaper?"),
+            Some(ScopedQuestion { source: "uniswap v4 whitepaper".to_string(), question: "What are hooks".to_string() })
+        );
+        assert_eq!(parse_scoped_question("explain how PT and YT work"), None);
+        assert_eq!(parse_scoped_question("using the pendle whitepaper"), None);
+    }
+
+    #[test]
+    fn test_names_document() {
+        assert!(names_document("pendle whitepaper"));
+        assert!(names_document("my aave notes"));
+        assert!(!names_document("analysts"));
+        assert!(!names_document("leverage"));
+    }
+
+    #[test]
+    fn test_resolves_exact_source_id_and_fuzzy_names() {
+        let entries = entries();
+        assert_eq!(found(resolve_source("aave_v3_docs", &entries)), Some("aave_v3_docs"));
+        assert_eq!(found(resolve_source("pendle whitepaper", &entries)), Some("pendle_whitepaper"));
+        assert_eq!(found(resolve_source("pendle", &entries)), Some("pendle_whitepaper"));
+        // Typo and tag matches
+        assert_eq!(found(resolve_source("pendel whitepaper", &entries)), Some("pendle_whitepaper"));
+        assert_eq!(found(resolve_source("lending docs", &entries)), Some("aave_v3_docs"));
+        assert_eq!(found(resolve_source("uniswap v4 paper", &entries)), Some("uniswap_v4_whitepaper"));
+        assert_eq!(found(resolve_source("uniswap hooks", &entries)), Some("uniswap_v4_whitepaper"));
+    }
+
+    #[test]
+    fn test_unresolved_sources_list_candidates() {
+        let entries = entries();
+        match resolve_source("uniswap whitepaper", &entries) {
+            SourceResolution::Ambiguous(candidates) => {
+                let ids: Vec<&str> = candidates.iter().map(|entry| entry.source_id.as_str()).collect();
+                assert_eq!(ids, vec!["uniswap_v3_whitepaper", "uniswap_v4_whitepaper"]);
+
+                let scoped = ScopedQuestion { source: "uniswap whitepaper".to_string(), question: "what are ticks?".to_string() };
+                let reply = render_unresolved(&scoped, &candidates, true);
+                assert!(reply.starts_with("\"uniswap whitepaper\" matches more than one entry in your knowledge:\n"));
+                assert!(reply.contains("- uniswap_v4_whitepaper (tags: uniswap, whitepaper, hooks)\n"));
+            },
+            other => panic!("expected an ambiguous match, got {:?}", other),
+        }
+
+        // A document word alone doesn't pick one of several whitepapers
+        let resolution = resolve_source("curve whitepaper", &entries);
+        let SourceResolution::NoMatch(candidates) = &resolution else {
+            panic!("expected no match, got {:?}", resolution);
+        };
+        assert_eq!(candidates.len(), entries.len());
+        assert_eq!(candidates[0].source_id, "aave_v3_docs");
+
+        let scoped = ScopedQuestion { source: "curve whitepaper".to_string(), question: "what is veCRV?".to_string() };
+        let reply = render_unresolved(&scoped, candidates, false);
+        assert!(reply.starts_with("I couldn't find \"curve whitepaper\" in your knowledge."));
+        assert!(reply.contains("- pendle_whitepaper (tags: pendle, whitepaper, yield)\n"));
+        assert!(reply.ends_with("e.g. \"using aave_v3_docs, what is veCRV?\"."));
+
+        let reply = render_unresolved(&scoped, &[], false);
+        assert!(reply.contains("you don't have any stored knowledge yet"));
+    }
+
+    #[test]
+    fn test_scoped_prompt_holds_only_the_entry() {
+        let entry = knowledge("pendle_whitepaper", &["pendle"], "PT is the principal token.\n\nYT is the yield token.");
+        let prompt = build_scoped_prompt(&entry, "how do PT and YT work?", 2_000);
+
+        assert!(prompt.starts_with(SCOPED_INSTRUCTIONS));
+        assert!(prompt.contains("MATERIAL FROM \"pendle_whitepaper\":\n\n[Part 1/1]\nPT is the principal token.\n\nYT is the yield token.\n\n"));
+        assert!(prompt.ends_with("\nUSER QUERY: how do PT and YT work?"));
+        assert!(!prompt.contains("Excerpts"));
+    }
+
+    #[test]
+    fn test_scoped_prompt_keeps_relevant_chunks_within_budget() {
+        let filler = "Pendle governance uses vePENDLE voting. ".repeat(30);
+        let content = format!("{}\n\n{}\n\nYT holders receive the yield until maturity.\n\n{}", filler, filler, filler);
+        let entry = knowledge("pendle_whitepaper", &[], &content);
+
+        let chunks = chunk_content(&content, CHUNK_BYTES);
+        assert_eq!(chunks.len(), 3);
+        assert!(chunks.iter().all(|chunk| chunk.len() <= CHUNK_BYTES));
+
+        let question = "what do YT holders receive?";
+        let budget = estimate_tokens(SCOPED_INSTRUCTIONS) + estimate_tokens(&chunks[1]) + 150;
+        let prompt = build_scoped_prompt(&entry, question, budget);
+
+        assert!(estimate_tokens(&prompt) <= budget);
+        assert!(prompt.contains("(Excerpts: 1 of 3 parts"));
+        assert!(prompt.contains("[Part 2/3]\n"));
+        assert!(prompt.contains("YT holders receive the yield until maturity."));
+    }
+
+    #[test]
+    fn test_chunking_splits_long_paragraphs() {
+        let content = "word ".repeat(1_000);
+        let chunks = chunk_content(&content, 100);
+        assert!(chunks.iter().all(|chunk| chunk.len() <= 100 && !chunk.starts_with(' ')));
+        assert_eq!(chunks.concat().matches("word").count(), 1_000);
+        assert!(chunk_content("", 100).is_empty());
+    }
+
+    #[test]
+    fn test_within_one_edit() {
+        assert!(within_one_edit("pendle", "pendel"));
+        assert!(within_one_edit("pendle", "pandle"));
+        assert!(within_one_edit("uniswap", "uniswp"));
+        assert!(within_one_edit("aave", "aaves"));
+        assert!(!within_one_edit("pendle", "curve"));
+    }
+}
@@ -0,0 +1,380 @@
+use super::error::InvestmentChatError;
+use super::service;
+use crate::llm::{ChatModel, Message, json_payload};
+use crate::db::MessageRole;
+use async_trait::async_trait;
+use serde_json::Value;
+
+/// Longest strategy name accepted
+pub const MAX_NAME_CHARS: usize = 80;
+
+/// Longest category accepted
+pub const MAX_CATEGORY_CHARS: usize = 40;
+
+/// Most steps, requirements or tags kept from one extraction
+const MAX_LIST_ITEMS: usize = 20;
+
+const EXTRACT_PROMPT: &str = "You extract an investment strategy the user wants to save. \
+The user's message comes last; an earlier assistant message may describe the strategy they refer to. \
+Reply with one JSON object only, matching this schema: \
+{\"name\": string|null, \"category\": string|null, \"description\": string|null, \
+\"risk_level\": \"low\"|\"medium\"|\"high\"|\"experimental\"|null, \"tags\": [string], \"steps\": [string], \
+\"requirements\": [string]}. \
+Use a short title for the name and a one or two word category such as \"accumulation\", \"yield\" or \"trading\". \
+Only fill a field when the conversation states or clearly implies it; use null or [] otherwise. Never invent numbers.";
+
+/// Strategy fields as far as they are known, from the message or the extractor
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct StrategyDraft {
+    pub name: Option<String>,
+    pub category: Option<String>,
+    pub description: Option<String>,
+    pub risk_level: Option<String>,
+    pub tags: Vec<String>,
+    pub steps: Vec<String>,
+    pub requirements: Vec<String>,
+}
+
+/// A draft with every required field present and valid
+#[derive(Debug, Clone, PartialEq)]
+pub struct ValidStrategy {
+    pub name: String,
+    pub category: String,
+    pub description: String,
+    /// One of "low", "medium", "high" or "experimental"
+    pub risk_level: String,
+    pub tags: Vec<String>,
+    pub steps: Vec<String>,
+    pub requirements: Vec<String>,
+}
+
+/// Fields saved with a strategy that the user may type but is never asked for
+#[derive(Debug, Clone, PartialEq)]
+pub struct StrategyExtras {
+    pub author: String,
+    pub version: String,
+    pub expected_returns: Value,
+}
+
+/// A required field that is missing or unusable
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MissingField {
+    Name,
+    Category,
+    Description,
+    RiskLevel,
+}
+
+impl MissingField {
+    /// Label used in the strategy template
+    pub fn label(&self) -> &'static str {
+        match self {
+            MissingField::Name => "Name",
+            MissingField::Category => "Category",
+            MissingField::Description => "Description",
+            MissingField::RiskLevel => "Risk Level",
+        }
+    }
+}
+
+/// Extracts strategy fields from conversational messages
+#[async_trait]
+pub trait StrategyFieldExtractor: Send + Sync {
+    /// `previous` is the assistant turn before the message, which may describe the strategy
+    async fn extract(&self, message: &str, previous: Option<&str>) -> Result<StrategyDraft, InvestmentChatError>;
+}
+
+/// Extracts strategy fields with a single model call
+pub struct LlmStrategyExtractor<'a> {
+    model: &'a dyn ChatModel,
+}
+
+impl<'a> LlmStrategyExtractor<'a> {
+    pub fn new(model: &'a dyn ChatModel) -> Self {
+        Self { model }
+    }
+}
+
+#[async_trait]
+impl StrategyFieldExtractor for LlmStrategyExtractor<'_> {
+    async fn extract(&self, message: &str, previous: Option<&str>) -> Result<StrategyDraft, InvestmentChatError> {
+        let mut messages = Vec::with_capacity(3);
+        if let Some(previous) = previous {
+            // The API expects the conversation to open with a user turn
+            messages.push(Message { role: MessageRole::User, content: "(earlier conversation)".to_string() });
+            messages.push(Message { role: MessageRole::Assistant, content: previous.to_string() });
+        }
+        messages.push(Message { role: MessageRole::User, content: message.to_string() });
+
+        let reply = self.model
+            .generate_json(EXTRACT_PROMPT, &messages, 1024)
+            .await
+            .map_err(service::describe_llm_error)?;
+
+        Ok(parse_draft(&reply).unwrap_or_default())
+    }
+}
+
+fn string_field(object: &Value, key: &str) -> Option<String> {
+    match object.get(key)? {
+        Value::String(value) => Some(value.trim().to_string()).filter(|value| !value.is_empty()),
+        _ => None,
+    }
+}
+
+/// A list of strings, also accepting a single string of comma, semicolon or line separated items
+fn list_field(object: &Value, key: &str) -> Vec<String> {
+    let items: Vec<String> = match object.get(key) {
+        Some(Value::Array(values)) => values
+            .iter()
+            .filter_map(|value| value.as_str())
+            .map(|value| value.trim().to_string())
+            .collect(),
+        Some(Value::String(value)) => value.split([',', ';', '\n']).map(|item| item.trim().to_string()).collect(),
+        _ => Vec::new(),
+    };
+    items.into_iter().filter(|item| !item.is_empty()).take(MAX_LIST_ITEMS).collect()
+}
+
+/// Read the extractor's JSON object, tolerating code fences, surrounding prose and wrong field types
+///
+/// Returns None when there is no JSON object in the reply
+pub fn parse_draft(reply: &str) -> Option<StrategyDraft> {
+    let reply = json_payload(reply);
+    let start = reply.find('{')?;
+    let end = reply.rfind('}').filter(|&end| end > start)?;
+    let object: Value = match serde_json::from_str(&reply[start..=end]) {
+        Ok(object @ Value::Object(_)) => object,
+        Ok(_) => return None,
+        Err(e) => {
+            eprintln!("Ignoring malformed strategy extraction: {}", e);
+            return None;
+        },
+    };
+
+    Some(StrategyDraft {
+        name: string_field(&object, "name"),
+        category: string_field(&object, "category"),
+        description: string_field(&object, "description"),
+        risk_level: string_field(&object, "risk_level"),
+        tags: list_field(&object, "tags"),
+        steps: list_field(&object, "steps"),
+        requirements: list_field(&object, "requirements"),
+    })
+}
+
+impl StrategyDraft {
+    /// Fill the fields this draft lacks from `other`
+    pub fn merge(self, other: StrategyDraft) -> StrategyDraft {
+        let list = |mine: Vec<String>, theirs: Vec<String>| if mine.is_empty() { theirs } else { mine };
+        StrategyDraft {
+            name: self.name.or(other.name),
+            category: self.category.or(other.category),
+            description: self.description.or(other.description),
+            risk_level: self.risk_level.or(other.risk_level),
+            tags: list(self.tags, other.tags),
+            steps: list(self.steps, other.steps),
+            requirements: list(self.requirements, other.requirements),
+        }
+    }
+
+    /// Required fields that are absent
+    pub fn missing(&self) -> Vec<MissingField> {
+        let mut missing = Vec::new();
+        if self.name.is_none() {
+            missing.push(MissingField::Name);
+        }
+        if self.category.is_none() {
+            missing.push(MissingField::Category);
+        }
+        if self.description.is_none() {
+            missing.push(MissingField::Description);
+        }
+        if self.risk_level.is_none() {
+            missing.push(MissingField::RiskLevel);
+        }
+        missing
+    }
+}
+
+/// Map "Low", "low-risk" or "medium risk" to a stored risk level
+pub fn normalize_risk_level(value: &str) -> Option<&'static str> {
+    let value = value.trim().to_lowercase();
+    let value = value.trim_end_matches("risk").trim_end_matches(['-', ' ']);
+    match value {
+        "low" | "conservative" => Some("low"),
+        "medium" | "moderate" | "mid" => Some("medium"),
+        "high" | "aggressive" => Some("high"),
+        "experimental" => Some("experimental"),
+        _ => None,
+    }
+}
+
+/// Check a draft before it's saved, returning the required fields that are missing or invalid
+///
+/// Names and categories must be short single lines and the risk level one the strategy store knows.
+/// Tags are lowercased and deduplicated.
+pub fn validate_strategy(draft: &StrategyDraft) -> Result<ValidStrategy, Vec<MissingField>> {
+    let single_line = |value: &Option<String>, max: usize| {
+        value.as_ref().filter(|value| !value.contains('\n') && value.chars().count() <= max).cloned()
+    };
+    let name = single_line(&draft.name, MAX_NAME_CHARS);
+    let category = single_line(&draft.category, MAX_CATEGORY_CHARS);
+    let risk_level = draft.risk_level.as_deref().and_then(normalize_risk_level);
+
+    let mut invalid = Vec::new();
+    if name.is_none() {
+        invalid.push(MissingField::Name);
+    }
+    if category.is_none() {
+        invalid.push(MissingField::Category);
+    }
+    if draft.description.is_none() {
+        invalid.push(MissingField::Description);
+    }
+    if risk_level.is_none() {
+        invalid.push(MissingField::RiskLevel);
+    }
+    let (Some(name), Some(category), Some(description), Some(risk_level)) = (name, category, draft.description.clone(), risk_level) else {
+        return Err(invalid);
+    };
+
+    let mut tags: Vec<String> = Vec::new();
+    for tag in &draft.tags {
+        let tag = tag.to_lowercase();
+        if !tags.contains(&tag) {
+            tags.push(tag);
+        }
+    }
+
+    Ok(ValidStrategy {
+        name,
+        category: category.to_lowercase(),
+        description,
+        risk_level: risk_level.to_string(),
+        tags,
+        steps: draft.steps.clone(),
+        requirements: draft.requirements.clone(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FULL_REPLY: &str = r#"Here is the strategy:
+```json
+{
+  "name": "Weekly ETH accumulation",
+  "category": "Accumulation",
+  "description": "Buy ETH every week and stop if it drops 20%",
+  "risk_level": "Low",
+  "tags": ["ETH", "dca", "eth"],
+  "steps": ["Buy a fixed amount of ETH every Monday", "Stop buying after a 20% drawdown"],
+  "requirements": []
+}
+```"#;
+
+    fn draft(name: Option<&str>, category: Option<&str>, description: Option<&str>, risk_level: Option<&str>) -> StrategyDraft {
+        StrategyDraft {
+            name: name.map(str::to_string),
+            category: category.map(str::to_string),
+            description: description.map(str::to_string),
+            risk_level: risk_level.map(str::to_string),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_parse_fenced_reply() {
+        let draft = parse_draft(FULL_REPLY).unwrap();
+
+        assert_eq!(draft.name.as_deref(), Some("Weekly ETH accumulation"));
+        assert_eq!(draft.risk_level.as_deref(), Some("Low"));
+        assert_eq!(draft.steps.len(), 2);
+        assert!(draft.requirements.is_empty());
+    }
+
+    #[test]
+    fn test_parse_ignores_prose_after_the_json() {
+        let reply = "{\"name\": \"SOL DCA\", \"tags\": [\"solana\"]}\n###END###\nWant me to add {steps}?";
+        let draft = parse_draft(reply).unwrap();
+        assert_eq!(draft.name.as_deref(), Some("SOL DCA"));
+        assert_eq!(draft.tags, vec!["solana".to_string()]);
+
+        let fenced = "```json\n{\"name\": \"SOL DCA\"}\n```\nI left the {risk level} empty.";
+        assert_eq!(parse_draft(fenced).unwrap().name.as_deref(), Some("SOL DCA"));
+    }
+
+    #[test]
+    fn test_parse_tolerates_wrong_types() {
+        let draft = parse_draft(r#"{"name": "LP farming", "category": 3, "description": "", "risk_level": null, "steps": "Add liquidity; Stake LP tokens", "tags": [1, "yield"]}"#).unwrap();
+
+        assert_eq!(draft.name.as_deref(), Some("LP farming"));
+        assert_eq!(draft.category, None);
+        assert_eq!(draft.description, None);
+        assert_eq!(draft.risk_level, None);
+        assert_eq!(draft.steps, vec!["Add liquidity".to_string(), "Stake LP tokens".to_string()]);
+        assert_eq!(draft.tags, vec!["yield".to_string()]);
+    }
+
+    #[test]
+    fn test_parse_rejects_malformed_replies() {
+        assert_eq!(parse_draft("I can't help with that."), None);
+        assert_eq!(parse_draft(r#"{"name": "Unterminated"#), None);
+        assert_eq!(parse_draft(r#"{"name": "Trailing comma",}"#), None);
+        assert_eq!(parse_draft(r#"["not", "an", "object"]"#), None);
+        assert_eq!(parse_draft(r#"} stray brace {"#), None);
+    }
+
+    #[test]
+    fn test_validate_complete_draft() {
+        let valid = validate_strategy(&parse_draft(FULL_REPLY).unwrap()).unwrap();
+
+        assert_eq!(valid.category, "accumulation");
+        assert_eq!(valid.risk_level, "low");
+        assert_eq!(valid.tags, vec!["eth".to_string(), "dca".to_string()]);
+    }
+
+    #[test]
+    fn test_validate_reports_missing_and_invalid_fields() {
+        assert_eq!(
+            validate_strategy(&draft(Some("DCA"), None, Some("Buy weekly"), Some("yolo"))),
+            Err(vec![MissingField::Category, MissingField::RiskLevel])
+        );
+        let long_name = "x".repeat(MAX_NAME_CHARS + 1);
+        assert_eq!(
+            validate_strategy(&draft(Some(&long_name), Some("yield"), Some("Farm"), Some("high"))),
+            Err(vec![MissingField::Name])
+        );
+        assert_eq!(
+            validate_strategy(&draft(Some("DCA"), Some("two\nlines"), Some("Buy weekly"), Some("low"))),
+            Err(vec![MissingField::Category])
+        );
+    }
+
+    #[test]
+    fn test_normalize_risk_level() {
+        assert_eq!(normalize_risk_level("Low-risk"), Some("low"));
+        assert_eq!(normalize_risk_level("moderate risk"), Some("medium"));
+        assert_eq!(normalize_risk_level("HIGH"), Some("high"));
+        assert_eq!(normalize_risk_level("degen"), None);
+    }
+
+    #[test]
+    fn test_merge_keeps_message_fields_first() {
+        let from_message = draft(Some("My DCA"), None, None, Some("low"));
+        let extracted = StrategyDraft {
+            steps: vec!["Buy weekly".to_string()],
+            ..draft(Some("Weekly ETH"), Some("accumulation"), Some("Buy ETH weekly"), Some("medium"))
+        };
+
+        let merged = from_message.merge(extracted);
+
+        assert_eq!(merged.name.as_deref(), Some("My DCA"));
+        assert_eq!(merged.category.as_deref(), Some("accumulation"));
+        assert_eq!(merged.risk_level.as_deref(), Some("low"));
+        assert_eq!(merged.steps, vec!["Buy weekly".to_string()]);
+        assert!(merged.missing().is_empty());
+    }
+}
@@ -0,0 +1,442 @@
+use super::aliases;
+use super::turn::TurnResult;
+use super::strategy_extraction::{self, MAX_CATEGORY_CHARS, MAX_NAME_CHARS, MissingField, StrategyDraft, ValidStrategy};
+use chrono::{DateTime, Duration, Utc};
+use regex::Regex;
+use std::sync::OnceLock;
+
+/// Minutes without an answer after which an unfinished strategy is dropped
+pub const WIZARD_TIMEOUT_MINUTES: i64 = 30;
+
+/// Replies that drop the strategy
+const CANCEL_WORDS: &[&str] = &["cancel", "stop", "abort", "quit", "never mind", "nevermind", "cancel strategy"];
+
+/// Replies that leave an optional field empty
+const SKIP_WORDS: &[&str] = &["skip", "none", "no", "no steps", "-"];
+
+/// Replies that pick a paused strategy back up
+const RESUME_WORDS: &[&str] = &["resume", "continue", "resume strategy", "continue strategy", "back to the strategy"];
+
+/// Words opening a question rather than an answer
+const QUESTION_WORDS: &[&str] = &[
+    "what", "what's", "whats", "why", "how", "when", "where", "which", "who", "should", "can", "could", "would", "will",
+    "is", "are", "do", "does",
+];
+
+/// The field the wizard is asking for, in the order they are asked
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WizardStep {
+    Name,
+    Category,
+    RiskLevel,
+    Description,
+    /// Optional, skipped when the draft already has steps
+    Steps,
+    /// Showing the summary and waiting for a yes
+    Confirm,
+}
+
+impl WizardStep {
+    fn label(&self) -> &'static str {
+        match self {
+            WizardStep::Name => "name",
+            WizardStep::Category => "category",
+            WizardStep::RiskLevel => "risk level",
+            WizardStep::Description => "description",
+            WizardStep::Steps => "steps",
+            WizardStep::Confirm => "confirmation",
+        }
+    }
+
+    fn required_field(&self) -> Option<MissingField> {
+        match self {
+            WizardStep::Name => Some(MissingField::Name),
+            WizardStep::Category => Some(MissingField::Category),
+            WizardStep::RiskLevel => Some(MissingField::RiskLevel),
+            WizardStep::Description => Some(MissingField::Description),
+            WizardStep::Steps | WizardStep::Confirm => None,
+        }
+    }
+
+    fn question(&self) -> &'static str {
+        match self {
+            WizardStep::Name => "What should the strategy be called?",
+            WizardStep::Category => "Which category is it, e.g. accumulation, yield or trading?",
+            WizardStep::RiskLevel => "How risky is it: low, medium, high or experimental?",
+            WizardStep::Description => "Describe the strategy in a sentence or two.",
+            WizardStep::Steps => "List its steps, one per line (optional, say \"skip\" to leave them out).",
+            WizardStep::Confirm => "Reply \"yes\" to save it, \"edit <field>\" to change a field, or \"cancel\" to drop it.",
+        }
+    }
+}
+
+/// What the agent does with a message sent while a wizard is open
+#[derive(Debug, Clone, PartialEq)]
+pub enum WizardReply {
+    /// Send this to the user and keep the wizard open
+    Ask(String),
+    /// The user confirmed the summary, save this strategy and close the wizard
+    Confirmed(ValidStrategy),
+    /// The user cancelled, close the wizard
+    Cancelled(String),
+    /// The message is an unrelated question: answer it normally and add this note, the wizard stays paused
+    Paused(String),
+    /// The wizard is paused and the message isn't for it, answer it normally
+    Ignored,
+    /// Nothing was answered for too long: close the wizard, answer the message normally and add this note
+    Expired(String),
+}
+
+/// How the agent answers a message after offering it to the wizard
+#[derive(Debug)]
+pub enum WizardTurn {
+    /// The wizard answered the message
+    Answered(TurnResult),
+    /// Answer the message as usual, adding the note when there is one
+    PassThrough(Option<String>),
+}
+
+/// A strategy being built one field at a time over several turns
+///
+/// Fields the first message already had are kept and not asked again. Answers are checked with
+/// the same rules as a strategy sent in one message before the wizard moves on.
+#[derive(Debug, Clone, PartialEq)]
+pub struct StrategyWizard {
+    draft: StrategyDraft,
+    step: WizardStep,
+    /// Whether the optional steps were asked for, so a skip isn't asked again
+    steps_asked: bool,
+    paused: bool,
+    last_activity: DateTime<Utc>,
+}
+
+impl StrategyWizard {
+    /// Open a wizard on what's known so far, returning it with the first question
+    pub fn start(draft: StrategyDraft, now: DateTime<Utc>) -> (Self, String) {
+        let steps_asked = !draft.steps.is_empty();
+        let mut wizard = Self { draft, step: WizardStep::Name, steps_asked, paused: false, last_activity: now };
+        wizard.step = wizard.next_step();
+
+        let mut reply = String::from("Let's build this strategy step by step. Say \"skip\" to leave an optional field out or \"cancel\" to stop.\n\n");
+        let known = wizard.known_fields();
+        if !known.is_empty() {
+            reply.push_str(&format!("So far I have:\n{}\n\n", known.join("\n")));
+        }
+        reply.push_str(&wizard.prompt());
+        (wizard, reply)
+    }
+
+    pub fn is_expired(&self, now: DateTime<Utc>) -> bool {
+        now - self.last_activity > Duration::minutes(WIZARD_TIMEOUT_MINUTES)
+    }
+
+    /// Take a message as the answer to the current step
+    pub fn handle(&mut self, message: &str, now: DateTime<Utc>) -> WizardReply {
+        if self.is_expired(now) {
+            return WizardReply::Expired(format!(
+                "(The strategy you were building expired after {} minutes without an answer. Say \"save strategy\" to start again.)",
+                WIZARD_TIMEOUT_MINUTES
+            ));
+        }
+
+        let reply = normalize(message);
+        if CANCEL_WORDS.contains(&reply.as_str()) {
+            return WizardReply::Cancelled("Okay, I dropped the strategy, nothing was saved.".to_string());
+        }
+
+        if self.paused {
+            if !RESUME_WORDS.contains(&reply.as_str()) {
+                return WizardReply::Ignored;
+            }
+            self.paused = false;
+            self.last_activity = now;
+            return WizardReply::Ask(format!("Picking the strategy back up.\n\n{}", self.prompt()));
+        }
+
+        if is_question(message) {
+            self.paused = true;
+            return WizardReply::Paused(format!(
+                "(I paused the strategy you're building at the {} step. Say \"resume\" to continue or \"cancel\" to drop it.)",
+                self.step.label()
+            ));
+        }
+
+        self.last_activity = now;
+        match self.step {
+            WizardStep::Confirm => self.handle_confirmation(&reply),
+            WizardStep::Steps => {
+                self.steps_asked = true;
+                if !SKIP_WORDS.contains(&reply.as_str()) {
+                    self.draft.steps = parse_steps(message);
+                }
+                self.advance()
+            },
+            step => self.handle_required(step, message),
+        }
+    }
+
+    fn handle_required(&mut self, step: WizardStep, message: &str) -> WizardReply {
+        if SKIP_WORDS.contains(&normalize(message).as_str()) {
+            return WizardReply::Ask(format!("The {} is required. {}", step.label(), step.question()));
+        }
+
+        let answer = strip_label(message.trim(), step.label());
+        let mut draft = self.draft.clone();
+        match step {
+            WizardStep::Name => draft.name = Some(answer),
+            WizardStep::Category => draft.category = Some(answer),
+            WizardStep::RiskLevel => draft.risk_level = Some(answer),
+            _ => draft.description = Some(answer),
+        }
+
+        let invalid = match strategy_extraction::validate_strategy(&draft) {
+            Ok(_) => false,
+            Err(missing) => step.required_field().is_some_and(|field| missing.contains(&field)),
+        };
+        if invalid {
+            return WizardReply::Ask(format!("{} {}", rule(step), step.question()));
+        }
+
+        self.draft = draft;
+        self.advance()
+    }
+
+    fn handle_confirmation(&mut self, reply: &str) -> WizardReply {
+        if aliases::is_affirmative(reply) || reply == "save" || reply == "save it" {
+            return match strategy_extraction::validate_strategy(&self.draft) {
+                Ok(strategy) => WizardReply::Confirmed(strategy),
+                // Only reachable when a prefilled field went stale, ask for it again
+                Err(_) => self.advance(),
+            };
+        }
+
+        let edit = reply.strip_prefix("edit ").or_else(|| reply.strip_prefix("change ")).map(str::trim);
+        let step = match edit {
+            Some("name") => WizardStep::Name,
+            Some("category") => WizardStep::Category,
+            Some("risk" | "risk level") => WizardStep::RiskLevel,
+            Some("description") => WizardStep::Description,
+            Some("steps") => WizardStep::Steps,
+            _ => return WizardReply::Ask(self.step.question().to_string()),
+        };
+        self.step = step;
+        WizardReply::Ask(step.question().to_string())
+    }
+
+    /// Move to the first field still missing, or the summary
+    fn advance(&mut self) -> WizardReply {
+        self.step = self.next_step();
+        WizardReply::Ask(self.prompt())
+    }
+
+    fn next_step(&self) -> WizardStep {
+        let invalid = strategy_extraction::validate_strategy(&self.draft).err().unwrap_or_default();
+        [WizardStep::Name, WizardStep::Category, WizardStep::RiskLevel, WizardStep::Description]
+            .into_iter()
+            .find(|step| step.required_field().is_some_and(|field| invalid.contains(&field)))
+            .unwrap_or(if self.steps_asked { WizardStep::Confirm } else { WizardStep::Steps })
+    }
+
+    /// The question for the current step, with the summary before the confirmation
+    fn prompt(&self) -> String {
+        match (self.step, strategy_extraction::validate_strategy(&self.draft)) {
+            (WizardStep::Confirm, Ok(strategy)) => format!("{}\n\n{}", render_summary(&strategy), self.step.question()),
+            (step, _) => step.question().to_string(),
+        }
+    }
+
+    /// Valid fields of the draft, for the opening message
+    fn known_fields(&self) -> Vec<String> {
+        let invalid = strategy_extraction::validate_strategy(&self.draft).err().unwrap_or_default();
+        let fields = [
+            (MissingField::Name, self.draft.name.clone()),
+            (MissingField::Category, self.draft.category.clone()),
+            (MissingField::RiskLevel, self.draft.risk_level.as_deref().and_then(strategy_extraction::normalize_risk_level).map(str::to_string)),
+            (MissingField::Description, self.draft.description.clone()),
+        ];
+        fields
+            .into_iter()
+            .filter(|(field, _)| !invalid.contains(field))
+            .filter_map(|(field, value)| value.map(|value| format!("{}: {}", field.label(), value)))
+            .collect()
+    }
+}
+
+/// The strategy as it will be saved
+pub fn render_summary(strategy: &ValidStrategy) -> String {
+    let mut lines = vec![
+        "Here's the strategy:".to_string(),
+        String::new(),
+        format!("Name: {}", strategy.name),
+        format!("Category: {}", strategy.category),
+        format!("Risk Level: {}", strategy.risk_level),
+        format!("Description: {}", strategy.description),
+    ];
+    if !strategy.steps.is_empty() {
+        lines.push("Steps:".to_string());
+        lines.extend(strategy.steps.iter().enumerate().map(|(i, step)| format!("{}. {}", i + 1, step)));
+    }
+    lines.join("\n")
+}
+
+/// Why an answer for a required field was refused
+fn rule(step: WizardStep) -> String {
+    match step {
+        WizardStep::Name => format!("The name must be one line of at most {} characters.", MAX_NAME_CHARS),
+        WizardStep::Category => format!("The category must be one line of at most {} characters.", MAX_CATEGORY_CHARS),
+        WizardStep::RiskLevel => "That's not a risk level I know.".to_string(),
+        _ => "The description can't be empty.".to_string(),
+    }
+}
+
+/// Whether a message asks something instead of answering, e.g. "what's the price of ETH?"
+fn is_question(message: &str) -> bool {
+    let message = message.trim();
+    if message.ends_with('?') {
+        return true;
+    }
+    let first = message.split_whitespace().next().unwrap_or_default().to_lowercase();
+    QUESTION_WORDS.contains(&first.as_str())
+}
+
+/// One step per line or semicolon, without list numbers or bullets
+fn parse_steps(message: &str) -> Vec<String> {
+    static MARKER: OnceLock<Regex> = OnceLock::new();
+    let marker = MARKER.get_or_init(|| Regex::new(r"^(?:\d+[.)]|[-*•])\s*").unwrap());
+    message
+        .split(['\n', ';'])
+        .map(|step| marker.replace(step.trim(), "").trim().to_string())
+        .filter(|step| !step.is_empty())
+        .collect()
+}
+
+/// Drop a "Name:" the user typed in front of the answer
+fn strip_label(answer: &str, label: &str) -> String {
+    let lower = answer.to_lowercase();
+    match lower.strip_prefix(label).map(str::trim_start).and_then(|rest| rest.strip_prefix(':')) {
+        Some(rest) => answer[answer.len() - rest.len()..].trim().to_string(),
+        None => answer.to_string(),
+    }
+}
+
+fn normalize(message: &str) -> String {
+    message.trim().trim_end_matches(['.', '!']).split_whitespace().collect::<Vec<_>>().join(" ").to_lowercase()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn now() -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339("2025-06-01T12:00:00Z").unwrap().with_timezone(&Utc)
+    }
+
+    fn ask(reply: WizardReply) -> String {
+        match reply {
+            WizardReply::Ask(text) => text,
+            other => panic!("expected a question, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_happy_path_asks_each_field_in_order() {
+        let (mut wizard, opening) = StrategyWizard::start(StrategyDraft::default(), now());
+        assert!(opening.ends_with("What should the strategy be called?"));
+        assert!(!opening.contains("So far I have"));
+
+        assert!(ask(wizard.handle("Name: ETH DCA", now())).starts_with("Which category"));
+        assert!(ask(wizard.handle("Accumulation", now())).starts_with("How risky"));
+        assert_eq!(ask(wizard.handle("extreme", now())), "That's not a risk level I know. How risky is it: low, medium, high or experimental?");
+        assert_eq!(wizard.step, WizardStep::RiskLevel);
+        assert!(ask(wizard.handle("moderate", now())).starts_with("Describe"));
+        assert!(ask(wizard.handle("Buy a fixed amount of ETH every week.", now())).starts_with("List its steps"));
+
+        let summary = ask(wizard.handle("1. Set up a recurring buy\n2. Never sell before 2030", now()));
+        assert_eq!(
+            summary,
+            "Here's the strategy:\n\n\
+             Name: ETH DCA\n\
+             Category: accumulation\n\
+             Risk Level: medium\n\
+             Description: Buy a fixed amount of ETH every week.\n\
+             Steps:\n\
+             1. Set up a recurring buy\n\
+             2. Never sell before 2030\n\n\
+             Reply \"yes\" to save it, \"edit <field>\" to change a field, or \"cancel\" to drop it."
+        );
+
+        assert!(ask(wizard.handle("edit name", now())).starts_with("What should"));
+        assert!(ask(wizard.handle("Weekly ETH", now())).starts_with("Here's the strategy:\n\nName: Weekly ETH"));
+        let WizardReply::Confirmed(strategy) = wizard.handle("yes", now()) else { panic!("expected a confirmation") };
+        assert_eq!(strategy.name, "Weekly ETH");
+        assert_eq!(strategy.risk_level, "medium");
+        assert_eq!(strategy.steps, vec!["Set up a recurring buy", "Never sell before 2030"]);
+    }
+
+    #[test]
+    fn test_prefilled_fields_are_not_asked_again() {
+        let draft = StrategyDraft {
+            name: Some("SOL staking".to_string()),
+            risk_level: Some("Low risk".to_string()),
+            category: Some("a category name that goes on far longer than forty characters".to_string()),
+            ..StrategyDraft::default()
+        };
+        let (mut wizard, opening) = StrategyWizard::start(draft, now());
+        assert!(opening.contains("So far I have:\nName: SOL staking\nRisk Level: low\n\n"));
+        assert_eq!(wizard.step, WizardStep::Category);
+
+        assert!(ask(wizard.handle("yield", now())).starts_with("Describe"));
+        assert!(ask(wizard.handle("skip", now())).starts_with("The description is required."));
+        assert!(ask(wizard.handle("Stake SOL with a liquid staking token.", now())).starts_with("List its steps"));
+        assert!(ask(wizard.handle("skip", now())).contains("Risk Level: low\nDescription: Stake SOL with a liquid staking token.\n\nReply"));
+        assert!(matches!(wizard.handle("yes", now()), WizardReply::Confirmed(_)));
+    }
+
+    #[test]
+    fn test_cancel_drops_the_strategy() {
+        let (mut wizard, _) = StrategyWizard::start(StrategyDraft::default(), now());
+        wizard.handle("ETH DCA", now());
+        assert!(matches!(wizard.handle("Cancel.", now()), WizardReply::Cancelled(_)));
+
+        let (mut paused, _) = StrategyWizard::start(StrategyDraft::default(), now());
+        paused.handle("what's the price of ETH?", now());
+        assert!(matches!(paused.handle("never mind", now()), WizardReply::Cancelled(_)));
+    }
+
+    #[test]
+    fn test_unrelated_question_pauses_until_resumed() {
+        let (mut wizard, _) = StrategyWizard::start(StrategyDraft::default(), now());
+        wizard.handle("ETH DCA", now());
+
+        let WizardReply::Paused(note) = wizard.handle("what's the price of ETH", now()) else { panic!("expected a pause") };
+        assert!(note.contains("at the category step"));
+        assert!(wizard.paused);
+        // Later messages are answered normally and don't fill the category
+        assert_eq!(wizard.handle("accumulation", now()), WizardReply::Ignored);
+        assert_eq!(wizard.step, WizardStep::Category);
+
+        assert_eq!(ask(wizard.handle("resume", now())), "Picking the strategy back up.\n\nWhich category is it, e.g. accumulation, yield or trading?");
+        assert!(!wizard.paused);
+        assert!(ask(wizard.handle("accumulation", now())).starts_with("How risky"));
+    }
+
+    #[test]
+    fn test_inactive_wizard_expires() {
+        let (mut wizard, _) = StrategyWizard::start(StrategyDraft::default(), now());
+        let later = now() + Duration::minutes(WIZARD_TIMEOUT_MINUTES - 1);
+        assert!(ask(wizard.handle("ETH DCA", later)).starts_with("Which category"));
+
+        // The clock restarts with each answer
+        assert!(!wizard.is_expired(later + Duration::minutes(WIZARD_TIMEOUT_MINUTES)));
+        let expired = later + Duration::minutes(WIZARD_TIMEOUT_MINUTES + 1);
+        assert!(matches!(wizard.handle("accumulation", expired), WizardReply::Expired(_)));
+    }
+
+    #[test]
+    fn test_parse_steps() {
+        assert_eq!(parse_steps("1. Buy ETH\n2) Stake it\n- Hold"), vec!["Buy ETH", "Stake it", "Hold"]);
+        assert_eq!(parse_steps("buy weekly; rebalance monthly"), vec!["buy weekly", "rebalance monthly"]);
+        assert_eq!(strip_label("Category:  Yield", "category"), "Yield");
+        assert_eq!(strip_label("Namecheap", "name"), "Namecheap");
+    }
+}
@@ -0,0 +1,77 @@
+use super::InvestmentChatError;
+
+/// Longest system prompt override accepted, in characters
+pub const MAX_SYSTEM_PROMPT_OVERRIDE_CHARS: usize = 2_000;
+
+/// Custom advisor instructions applied for the rest of a session, e.g. to compare styles
+///
+/// The text is layered after the advisor's base prompt and before the standing instructions,
+/// and the prompt always ends with the safety notes it can't turn off
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SystemPromptOverride {
+    text: String,
+    variant: String,
+}
+
+impl SystemPromptOverride {
+    pub fn new(text: &str) -> Result<Self, InvestmentChatError> {
+        let text = text.trim();
+        if text.is_empty() {
+            return Err(InvestmentChatError::InvalidInput("The system prompt override is empty".to_string()));
+        }
+        let length = text.chars().count();
+        if length > MAX_SYSTEM_PROMPT_OVERRIDE_CHARS {
+            return Err(InvestmentChatError::InvalidInput(format!(
+                "The system prompt override is {} characters, the limit is {}",
+                length, MAX_SYSTEM_PROMPT_OVERRIDE_CHARS
+            )));
+        }
+
+        Ok(Self {
+            variant: variant_id(text),
+            text: text.to_string(),
+        })
+    }
+
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    /// Name recorded with the answers written under this override, the same for the same text
+    pub fn variant(&self) -> &str {
+        &self.variant
+    }
+}
+
+/// `custom-` and the 32-bit FNV-1a hash of the text, stable across builds unlike `DefaultHasher`
+fn variant_id(text: &str) -> String {
+    let hash = text.bytes().fold(0x811c_9dc5_u32, |hash, byte| (hash ^ byte as u32).wrapping_mul(0x0100_0193));
+    format!("custom-{:08x}", hash)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_override_is_trimmed_and_named_by_its_text() {
+        let terse = SystemPromptOverride::new("  Answer like a terse trader.\n").unwrap();
+        assert_eq!(terse.text(), "Answer like a terse trader.");
+        assert_eq!(terse.variant(), SystemPromptOverride::new("Answer like a terse trader.").unwrap().variant());
+        assert!(terse.variant().starts_with("custom-") && terse.variant().len() == "custom-".len() + 8);
+
+        let teacher = SystemPromptOverride::new("Explain like a patient teacher.").unwrap();
+        assert_ne!(teacher.variant(), terse.variant());
+        // FNV-1a of the empty string is the offset basis
+        assert_eq!(variant_id(""), "custom-811c9dc5");
+    }
+
+    #[test]
+    fn test_override_length_is_capped() {
+        assert!(SystemPromptOverride::new(&"é".repeat(MAX_SYSTEM_PROMPT_OVERRIDE_CHARS)).is_ok());
+
+        let error = SystemPromptOverride::new(&"a".repeat(MAX_SYSTEM_PROMPT_OVERRIDE_CHARS + 1)).unwrap_err();
+        assert_eq!(error.to_string(), "Invalid input: The system prompt override is 2001 characters, the limit is 2000");
+        assert!(matches!(SystemPromptOverride::new(" \n "), Err(InvestmentChatError::InvalidInput(_))));
+    }
+}
@@ -0,0 +1,569 @@
+use crate::db::TopicKind;
+use crate::unlocks::UnlockEvent;
+use crate::gas::GasLevel;
+use crate::llm::TokenUsage;
+use crate::price_fetcher::{CategoryCoin, Platform};
+use crate::rebalancing::Leg;
+use crate::scenario::{PositionOutcome, TriggerOutcome};
+use serde::Serialize;
+use std::time::Duration;
+
+/// Version of the `ask --json` envelope, bumped when a field changes meaning or goes away
+pub const ENVELOPE_VERSION: u32 = 1;
+
+/// Which handler answered a message
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Intent {
+    /// "be brief" and similar answer length preferences
+    Preference,
+    /// "that was wrong" and similar ratings of the previous answer
+    Feedback,
+    /// "undo that", reversing the last change made to the user's data
+    Undo,
+    Alias,
+    Watchlist,
+    /// Working through a saved strategy's steps as a checklist
+    StrategyProgress,
+    StoredData,
+    /// "what coins have we talked about most", counted from the topics of past messages
+    Topics,
+    /// "what trades have I made this month", read from the trade history
+    TradeHistory,
+    Profile,
+    /// Arithmetic computed locally, coin amounts at live prices
+    Calculation,
+    /// Answered from local data because the network is unavailable
+    Offline,
+    ScopedQuestion,
+    Sentiment,
+    /// "why did <coin> move today", the day's move explained from ranked news
+    PriceMove,
+    Diversification,
+    ImpermanentLoss,
+    PositionSizing,
+    /// Dollar-cost averaging buys of an amount, in any currency
+    Dca,
+    /// Lump sum, weekly DCA and a limit ladder replayed over past prices
+    EntryComparison,
+    /// Portfolio value after hypothetical price moves
+    Scenario,
+    /// Trades that move the portfolio to target weights, or staging them
+    Rebalance,
+    /// "swap 0.1 eth to usdc", staged with a quote and executed after a CONFIRM
+    Trade,
+    TrackRecord,
+    /// CoinGecko's facts about a coin: what it is, categories, supply and links
+    CoinCard,
+    /// The largest coins of a CoinGecko category, e.g. "top AI tokens"
+    Category,
+    /// Live gas prices, compared with the past day's readings
+    Gas,
+    /// "what's my portfolio worth", the wallet's balances at live prices
+    PortfolioValue,
+    /// Scheduled token unlocks of a coin, from the unlock data sources
+    Unlocks,
+    /// Funding rate and open interest of a coin's perpetual
+    Derivatives,
+    Price,
+    StrategyCreation,
+    /// Free-form answer written by the model
+    General,
+    /// A message asking several questions, answered part by part
+    MultiPart,
+    /// A message, or a part of a multi-part one, that failed
+    Failed,
+}
+
+/// Structured values behind an answer, for callers that shouldn't parse the prose
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum TurnData {
+    CurrentPrice {
+        coin_id: String,
+        price_usd: f64,
+        change_24h_pct: Option<f64>,
+        support_usd: f64,
+        resistance_usd: f64,
+    },
+    HistoricalPrice {
+        coin_id: String,
+        /// Date as written in the answer
+        date: String,
+        price_usd: f64,
+        current_price_usd: Option<f64>,
+    },
+    /// Price of a token looked up by contract address
+    TokenPrice {
+        platform: Platform,
+        address: String,
+        symbol: Option<String>,
+        name: Option<String>,
+        price_usd: f64,
+        change_24h_pct: Option<f64>,
+    },
+    /// Figures of a coin card, supplies in coins
+    CoinCard {
+        coin_id: String,
+        market_cap_rank: Option<u32>,
+        categories: Vec<String>,
+        circulating_supply: Option<f64>,
+        max_supply: Option<f64>,
+    },
+    /// Gas price in gwei, costs at the standard priority fee
+    Gas {
+        base_fee_gwei: f64,
+        priority_fee_gwei: f64,
+        swap_cost_usd: Option<f64>,
+        transfer_cost_usd: Option<f64>,
+        /// Absent until enough readings of the past day are recorded
+        level: Option<GasLevel>,
+    },
+    /// Coins of a category as ranked in the answer, largest market cap first
+    Category {
+        category_id: String,
+        name: String,
+        coins: Vec<CategoryCoin>,
+    },
+    /// Unlocks listed in the answer, those inside the user's timeframe flagged
+    Unlocks {
+        coin_id: String,
+        events: Vec<UnlockEvent>,
+        within_timeframe: usize,
+    },
+    /// Funding rate in percent per 8h interval, open interest in USD
+    Derivatives {
+        coin_id: String,
+        symbol: String,
+        funding_rate_pct: f64,
+        annualized_funding_pct: f64,
+        open_interest_usd: f64,
+        oi_change_24h_pct: Option<f64>,
+    },
+    StrategyCreated {
+        strategy_id: String,
+        name: String,
+    },
+    /// Result of a calculation, in USD when it involves dollars or coins
+    Calculation {
+        expression: String,
+        result: f64,
+    },
+    /// Per-asset trades of a rebalancing plan, assets without a trade included
+    Rebalance {
+        total_usd: f64,
+        legs: Vec<Leg>,
+        estimated_fees_usd: Option<f64>,
+    },
+    /// A trade staged or executed: a swap's transaction and approval, or a placed limit order's id
+    /// Everything but the quoting aggregator is absent while a swap waits for its CONFIRM
+    Trade {
+        tx_hash: Option<String>,
+        approval_tx: Option<String>,
+        aggregator: Option<String>,
+        order_id: Option<String>,
+    },
+    /// Holdings and triggers before and after a hypothetical price move
+    Scenario {
+        before_usd: f64,
+        after_usd: f64,
+        positions: Vec<PositionOutcome>,
+        triggers: Vec<TriggerOutcome>,
+    },
+    Parts {
+        parts: Vec<TurnPart>,
+    },
+}
+
+/// One answered question of a multi-part message
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct TurnPart {
+    pub question: String,
+    pub intent: Intent,
+    pub data: Option<TurnData>,
+}
+
+/// Outcome of one chat turn, the plain-text answer is `text`
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct TurnResult {
+    pub text: String,
+    pub intent: Intent,
+    pub data: Option<TurnData>,
+    /// Tokens used by the model call that wrote the answer, absent for answers computed locally
+    pub usage: Option<TokenUsage>,
+    /// Source ids of the knowledge injected into the prompt, saved with the answer so ratings reach them
+    #[serde(skip)]
+    pub sources: Vec<String>,
+    /// The model's stream stopped before the answer finished, `text` is what arrived
+    #[serde(skip)]
+    pub truncated: bool,
+    /// Coins and keywords the routing already extracted from the question, recorded with it
+    #[serde(skip)]
+    pub topics: Vec<(String, TopicKind)>,
+}
+
+impl TurnResult {
+    pub fn new(intent: Intent, text: impl Into<String>) -> Self {
+        Self {
+            text: text.into(),
+            intent,
+            data: None,
+            usage: None,
+            sources: Vec::new(),
+            truncated: false,
+            topics: Vec::new(),
+        }
+    }
+
+    pub fn with_data(mut self, data: TurnData) -> Self {
+        self.data = Some(data);
+        self
+    }
+
+    pub fn with_usage(mut self, usage: TokenUsage) -> Self {
+        self.usage = Some(usage);
+        self
+    }
+
+    pub fn with_sources(mut self, sources: &[String]) -> Self {
+        self.sources = sources.to_vec();
+        self
+    }
+
+    pub fn with_truncated(mut self, truncated: bool) -> Self {
+        self.truncated = truncated;
+        self
+    }
+
+    pub fn with_topics(mut self, topics: Vec<(String, TopicKind)>) -> Self {
+        self.topics = topics;
+        self
+    }
+
+    /// Combine the answers to the parts of a message, `text` is the stitched answer
+    pub fn multi_part(text: String, parts: &[String], results: Vec<TurnResult>) -> Self {
+        let usage = results.iter().filter_map(|result| result.usage).reduce(|total, usage| total + usage);
+        let truncated = results.iter().any(|result| result.truncated);
+        let mut topics: Vec<(String, TopicKind)> = Vec::new();
+        for topic in results.iter().flat_map(|result| &result.topics) {
+            if !topics.contains(topic) {
+                topics.push(topic.clone());
+            }
+        }
+        let mut sources: Vec<String> = Vec::new();
+        for source in results.iter().flat_map(|result| &result.sources) {
+            if !sources.contains(source) {
+                sources.push(source.clone());
+            }
+        }
+        let parts = parts
+            .iter()
+            .zip(results)
+            .map(|(question, result)| TurnPart {
+                question: question.clone(),
+                intent: result.intent,
+                data: result.data,
+            })
+            .collect();
+        Self {
+            text,
+            intent: Intent::MultiPart,
+            data: Some(TurnData::Parts { parts }),
+            usage,
+            sources,
+            truncated,
+            topics,
+        }
+    }
+
+    /// The stable JSON shape printed by `ask --json`
+    pub fn envelope(&self, latency: Duration) -> TurnEnvelope {
+        TurnEnvelope {
+            version: ENVELOPE_VERSION,
+            answer: self.text.clone(),
+            intent: self.intent,
+            data: self.data.clone(),
+            usage: self.usage,
+            latency_ms: latency.as_millis() as u64,
+            error: None,
+        }
+    }
+}
+
+/// Machine-readable result of a one-shot question
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct TurnEnvelope {
+    pub version: u32,
+    pub answer: String,
+    pub intent: Intent,
+    pub data: Option<TurnData>,
+    pub usage: Option<TokenUsage>,
+    pub latency_ms: u64,
+    /// Why the question couldn't be answered, only present when it wasn't
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl TurnEnvelope {
+    /// The envelope of a question that failed with `error`, with an empty answer
+    pub fn failed(error: impl Into<String>, latency: Duration) -> Self {
+        Self {
+            version: ENVELOPE_VERSION,
+            answer: String::new(),
+            intent: Intent::Failed,
+            data: None,
+            usage: None,
+            latency_ms: latency.as_millis() as u64,
+            error: Some(error.into()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    // These tests pin the envelope schema: a change here breaks scripts reading `ask --json`
+
+    #[test]
+    fn test_envelope_schema_general_answer() {
+        let result = TurnResult::new(Intent::General, "AERO is the governance token of Aerodrome.")
+            .with_usage(TokenUsage { input_tokens: 812, output_tokens: 96 });
+        let envelope = serde_json::to_value(result.envelope(Duration::from_millis(1450))).unwrap();
+        assert_eq!(
+            envelope,
+            json!({
+                "version": 1,
+                "answer": "AERO is the governance token of Aerodrome.",
+                "intent": "general",
+                "data": null,
+                "usage": { "input_tokens": 812, "output_tokens": 96 },
+                "latency_ms": 1450
+            })
+        );
+    }
+
+    #[test]
+    fn test_envelope_schema_failed_question() {
+        let envelope = serde_json::to_value(TurnEnvelope::failed("Database error: connection refused", Duration::from_millis(30))).unwrap();
+        assert_eq!(
+            envelope,
+            json!({
+                "version": 1,
+                "answer": "",
+                "intent": "failed",
+                "data": null,
+                "usage": null,
+                "latency_ms": 30,
+                "error": "Database error: connection refused"
+            })
+        );
+    }
+
+    #[test]
+    fn test_envelope_schema_price_data() {
+        let result = TurnResult::new(Intent::Price, "The current price of Bitcoin is $60000.00").with_data(TurnData::CurrentPrice {
+            coin_id: "bitcoin".to_string(),
+            price_usd: 60000.0,
+            change_24h_pct: Some(-1.5),
+            support_usd: 55200.0,
+            resistance_usd: 64800.0,
+        });
+        let envelope = serde_json::to_value(result.envelope(Duration::from_millis(12))).unwrap();
+        assert_eq!(envelope["intent"], "price");
+        assert_eq!(envelope["usage"], json!(null));
+        assert_eq!(
+            envelope["data"],
+            json!({
+                "kind": "current_price",
+                "coin_id": "bitcoin",
+                "price_usd": 60000.0,
+                "change_24h_pct": -1.5,
+                "support_usd": 55200.0,
+                "resistance_usd": 64800.0
+            })
+        );
+
+        let historical = TurnData::HistoricalPrice {
+            coin_id: "ethereum".to_string(),
+            date: "01-03-2024".to_string(),
+            price_usd: 3400.0,
+            current_price_usd: None,
+        };
+        assert_eq!(
+            serde_json::to_value(historical).unwrap(),
+            json!({ "kind": "historical_price", "coin_id": "ethereum", "date": "01-03-2024", "price_usd": 3400.0, "current_price_usd": null })
+        );
+
+        let token = TurnData::TokenPrice {
+            platform: Platform::Base,
+            address: "0x4200000000000000000000000000000000000006".to_string(),
+            symbol: Some("WETH".to_string()),
+            name: None,
+            price_usd: 3120.5,
+            change_24h_pct: None,
+        };
+        assert_eq!(
+            serde_json::to_value(token).unwrap(),
+            json!({
+                "kind": "token_price",
+                "platform": "base",
+                "address": "0x4200000000000000000000000000000000000006",
+                "symbol": "WETH",
+                "name": null,
+                "price_usd": 3120.5,
+                "change_24h_pct": null
+            })
+        );
+
+        let created = TurnData::StrategyCreated { strategy_id: "dca_default_user_1".to_string(), name: "DCA".to_string() };
+        assert_eq!(
+            serde_json::to_value(created).unwrap(),
+            json!({ "kind": "strategy_created", "strategy_id": "dca_default_user_1", "name": "DCA" })
+        );
+
+        let swap = TurnData::Trade {
+            tx_hash: Some("0xabab".to_string()),
+            approval_tx: None,
+            aggregator: Some("1inch".to_string()),
+            order_id: None,
+        };
+        assert_eq!(
+            serde_json::to_value(swap).unwrap(),
+            json!({ "kind": "trade", "tx_hash": "0xabab", "approval_tx": null, "aggregator": "1inch", "order_id": null })
+        );
+        let order = TurnData::Trade { tx_hash: None, approval_tx: None, aggregator: None, order_id: Some("4f1c".to_string()) };
+        assert_eq!(
+            serde_json::to_value(order).unwrap(),
+            json!({ "kind": "trade", "tx_hash": null, "approval_tx": null, "aggregator": null, "order_id": "4f1c" })
+        );
+
+        let calculation = TurnData::Calculation { expression: "3.5% of 12,000".to_string(), result: 420.0 };
+        assert_eq!(
+            serde_json::to_value(calculation).unwrap(),
+            json!({ "kind": "calculation", "expression": "3.5% of 12,000", "result": 420.0 })
+        );
+
+        let rebalance = TurnData::Rebalance {
+            total_usd: 1000.0,
+            legs: vec![Leg {
+                asset: "ethereum".to_string(),
+                label: "ETH".to_string(),
+                price_usd: 2000.0,
+                current_usd: 400.0,
+                current_pct: 40.0,
+                target_pct: 50.0,
+                targeted: true,
+                trade: Some(crate::rebalancing::Trade {
+                    side: crate::rebalancing::Side::Buy,
+                    units: 0.05,
+                    value_usd: 100.0,
+                    fee_usd: Some(0.3),
+                }),
+            }],
+            estimated_fees_usd: Some(0.3),
+        };
+        assert_eq!(
+            serde_json::to_value(rebalance).unwrap(),
+            json!({
+                "kind": "rebalance",
+                "total_usd": 1000.0,
+                "legs": [{
+                    "asset": "ethereum",
+                    "label": "ETH",
+                    "price_usd": 2000.0,
+                    "current_usd": 400.0,
+                    "current_pct": 40.0,
+                    "target_pct": 50.0,
+                    "targeted": true,
+                    "trade": { "side": "buy", "units": 0.05, "value_usd": 100.0, "fee_usd": 0.3 }
+                }],
+                "estimated_fees_usd": 0.3
+            })
+        );
+    }
+
+    #[test]
+    fn test_intent_names() {
+        let names: Vec<serde_json::Value> = [
+            Intent::Preference,
+            Intent::Feedback,
+            Intent::Undo,
+            Intent::Alias,
+            Intent::Watchlist,
+            Intent::StrategyProgress,
+            Intent::StoredData,
+            Intent::Topics,
+            Intent::Profile,
+            Intent::Calculation,
+            Intent::Offline,
+            Intent::ScopedQuestion,
+            Intent::Sentiment,
+            Intent::Diversification,
+            Intent::ImpermanentLoss,
+            Intent::PositionSizing,
+            Intent::Dca,
+            Intent::EntryComparison,
+            Intent::Rebalance,
+            Intent::TrackRecord,
+            Intent::CoinCard,
+            Intent::Price,
+            Intent::StrategyCreation,
+            Intent::General,
+            Intent::MultiPart,
+            Intent::Failed,
+        ]
+        .iter()
+        .map(|intent| serde_json::to_value(intent).unwrap())
+        .collect();
+        assert_eq!(
+            names,
+            vec![
+                "preference", "feedback", "undo", "alias", "watchlist", "strategy_progress", "stored_data", "topics", "profile", "calculation", "offline", "scoped_question", "sentiment",
+                "diversification", "impermanent_loss", "position_sizing", "dca", "entry_comparison", "rebalance", "track_record", "coin_card", "price", "strategy_creation",
+                "general", "multi_part", "failed",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_multi_part_sums_usage_and_keeps_part_data() {
+        let parts = vec!["price of btc?".to_string(), "what is AERO?".to_string()];
+        let results = vec![
+            TurnResult::new(Intent::Price, "BTC is $60000.00").with_data(TurnData::CurrentPrice {
+                coin_id: "bitcoin".to_string(),
+                price_usd: 60000.0,
+                change_24h_pct: None,
+                support_usd: 55200.0,
+                resistance_usd: 64800.0,
+            }),
+            TurnResult::new(Intent::General, "A DEX token")
+                .with_usage(TokenUsage { input_tokens: 10, output_tokens: 5 })
+                .with_topics(vec![("aerodrome".to_string(), TopicKind::Coin), ("dex".to_string(), TopicKind::Keyword)]),
+        ];
+        let result = TurnResult::multi_part("combined".to_string(), &parts, results);
+
+        assert_eq!(result.intent, Intent::MultiPart);
+        assert_eq!(result.usage, Some(TokenUsage { input_tokens: 10, output_tokens: 5 }));
+        assert_eq!(result.topics, vec![("aerodrome".to_string(), TopicKind::Coin), ("dex".to_string(), TopicKind::Keyword)]);
+        let envelope = serde_json::to_value(result.envelope(Duration::ZERO)).unwrap();
+        assert_eq!(envelope["data"]["kind"], "parts");
+        assert_eq!(envelope["data"]["parts"][0], json!({
+            "question": "price of btc?",
+            "intent": "price",
+            "data": {
+                "kind": "current_price",
+                "coin_id": "bitcoin",
+                "price_usd": 60000.0,
+                "change_24h_pct": null,
+                "support_usd": 55200.0,
+                "resistance_usd": 64800.0
+            }
+        }));
+        assert_eq!(envelope["data"]["parts"][1], json!({ "question": "what is AERO?", "intent": "general", "data": null }));
+
+        let without_model = TurnResult::multi_part("x".to_string(), &parts[..1], vec![TurnResult::new(Intent::Watchlist, "ok")]);
+        assert_eq!(without_model.usage, None);
+    }
+}
@@ -0,0 +1,230 @@
+use crate::db::{self, DbError};
+use regex::Regex;
+use sqlx::{Pool, Postgres};
+use std::sync::{Mutex, OnceLock};
+
+/// A change the chat made to the user's data, with what it takes to reverse it
+#[derive(Debug, Clone, PartialEq)]
+pub enum Action {
+    /// A strategy saved from the chat, by its row id
+    StrategySaved { id: i32, name: String },
+    /// An alias set, `previous` being what it meant before, None for a new alias
+    AliasSaved { alias: String, previous: Option<String> },
+    AliasForgotten { alias: String, target: String },
+    WatchlistAdded { coin_id: String },
+    WatchlistRemoved { coin_id: String, note: Option<String> },
+    WatchlistNoteChanged { coin_id: String, previous: Option<String> },
+    /// A holding set with /portfolio, `previous` being the amount before, None for a new holding
+    HoldingSet { coin_id: String, previous: Option<f64> },
+    HoldingRemoved { coin_id: String, amount: f64 },
+    /// A swap sent on-chain, which can't be taken back
+    TradeExecuted { tx_hash: String },
+}
+
+/// The session's last data-changing action, the only one "undo" reverses
+#[derive(Default)]
+pub struct Journal {
+    last: Mutex<Option<Action>>,
+}
+
+impl Journal {
+    /// Remember `action` in place of the one before it
+    pub fn record(&self, action: Action) {
+        *self.last.lock().unwrap() = Some(action);
+    }
+
+    /// The last action, forgetting it so it isn't undone twice
+    pub fn take(&self) -> Option<Action> {
+        self.last.lock().unwrap().take()
+    }
+}
+
+/// Whether a message only asks to undo the last change, e.g. "undo that" or "revert it"
+pub fn is_undo_request(message: &str) -> bool {
+    static UNDO: OnceLock<Regex> = OnceLock::new();
+    let undo = UNDO.get_or_init(|| {
+        Regex::new(
+            r"^(?:please\s+)?(?:undo|revert)(?:\s+(?:that|it|this|the last (?:change|action)|what you just did))?(?:,?\s+please)?[.!]*$",
+        )
+        .unwrap()
+    });
+    undo.is_match(message.trim().to_lowercase().as_str())
+}
+
+/// Reverse `action` for the user, returning what was undone
+///
+/// Trades are on-chain for good, for them the reply says so and nothing changes.
+pub async fn revert(pool: &Pool<Postgres>, user_id: i32, action: &Action) -> Result<String, DbError> {
+    let reply = match action {
+        Action::StrategySaved { id, name } => match db::delete_strategy(pool, user_id, *id).await? {
+            true => format!("Undone: the strategy '{}' is deleted.", name),
+            false => format!("The strategy '{}' is already gone, there was nothing to undo.", name),
+        },
+        Action::AliasSaved { alias, previous: Some(target) } => {
+            db::upsert_user_alias(pool, user_id, alias, target).await?;
+            format!("Undone: \"{}\" means {} again.", alias, target)
+        },
+        Action::AliasSaved { alias, previous: None } => {
+            db::delete_user_alias(pool, user_id, alias).await?;
+            format!("Undone: the alias \"{}\" is removed.", alias)
+        },
+        Action::AliasForgotten { alias, target } => {
+            db::upsert_user_alias(pool, user_id, alias, target).await?;
+            format!("Undone: \"{}\" means {} again.", alias, target)
+        },
+        Action::WatchlistAdded { coin_id } => {
+            db::remove_watchlist_entry(pool, user_id, coin_id).await?;
+            format!("Undone: {} is off your watchlist.", coin_id)
+        },
+        Action::WatchlistRemoved { coin_id, note } => {
+            db::add_watchlist_entry(pool, user_id, coin_id, note.as_deref()).await?;
+            format!("Undone: {} is back on your watchlist.", coin_id)
+        },
+        Action::WatchlistNoteChanged { coin_id, previous } => {
+            match db::set_watchlist_note(pool, user_id, coin_id, previous.as_deref()).await? {
+                true => format!("Undone: the note for {} is back to what it was.", coin_id),
+                false => format!("{} isn't on your watchlist anymore, there was nothing to undo.", coin_id),
+            }
+        },
+        Action::HoldingSet { coin_id, previous: Some(amount) } | Action::HoldingRemoved { coin_id, amount } => {
+            db::upsert_holding(pool, user_id, coin_id, *amount).await?;
+            format!("Undone: your {} holding is {} again.", coin_id, amount)
+        },
+        Action::HoldingSet { coin_id, previous: None } => {
+            db::delete_holding(pool, user_id, coin_id).await?;
+            format!("Undone: {} is out of your portfolio.", coin_id)
+        },
+        Action::TradeExecuted { tx_hash } => format!(
+            "That trade was executed on-chain (transaction {}) and can't be undone. To reverse it, place the opposite trade.",
+            tx_hash
+        ),
+    };
+    Ok(reply)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::testing::test_pool;
+
+    #[test]
+    fn test_is_undo_request() {
+        for message in ["undo", "Undo that", "undo it.", "revert that", "please undo the last change", "undo that please", "revert what you just did!"] {
+            assert!(is_undo_request(message), "{}", message);
+        }
+        for message in ["how do I undo a swap?", "undo my last trade and buy eth", "revert to the old strategy", "undone"] {
+            assert!(!is_undo_request(message), "{}", message);
+        }
+    }
+
+    #[test]
+    fn test_journal_keeps_only_the_last_action() {
+        let journal = Journal::default();
+        assert_eq!(journal.take(), None);
+
+        journal.record(Action::WatchlistAdded { coin_id: "solana".to_string() });
+        journal.record(Action::AliasSaved { alias: "doge".to_string(), previous: None });
+        assert_eq!(journal.take(), Some(Action::AliasSaved { alias: "doge".to_string(), previous: None }));
+        // Undoing forgets it, and the action before it is not undoable
+        assert_eq!(journal.take(), None);
+    }
+
+    #[tokio::test]
+    async fn test_undo_a_saved_strategy_deletes_it() {
+        let Some(pool) = test_pool().await else { return };
+        let user = db::create_user(&pool, "alice", None).await.unwrap();
+        let strategy = db::create_strategy(
+            &pool, user.id, "hodl_alice_1", "HODL", "passive", "Buy and hold", "low",
+            &[], &["Buy".to_string()], &[], serde_json::json!({}), "User", "1.0",
+        )
+        .await
+        .unwrap();
+        db::start_strategy_progress(&pool, user.id, strategy.id, 1).await.unwrap();
+        let action = Action::StrategySaved { id: strategy.id, name: "HODL".to_string() };
+
+        assert_eq!(revert(&pool, user.id, &action).await.unwrap(), "Undone: the strategy 'HODL' is deleted.");
+        assert!(db::get_strategies_by_user_id(&pool, user.id).await.unwrap().is_empty());
+        assert_eq!(
+            revert(&pool, user.id, &action).await.unwrap(),
+            "The strategy 'HODL' is already gone, there was nothing to undo."
+        );
+    }
+
+    #[tokio::test]
+    async fn test_undo_alias_changes_restores_the_previous_target() {
+        let Some(pool) = test_pool().await else { return };
+        let user = db::create_user(&pool, "alice", None).await.unwrap();
+        let targets = |pool: Pool<Postgres>| async move {
+            db::get_user_aliases(&pool, user.id).await.unwrap().into_iter().map(|alias| (alias.alias, alias.target)).collect::<Vec<_>>()
+        };
+
+        // A new alias goes away
+        db::upsert_user_alias(&pool, user.id, "sats", "bitcoin").await.unwrap();
+        revert(&pool, user.id, &Action::AliasSaved { alias: "sats".to_string(), previous: None }).await.unwrap();
+        assert!(targets(pool.clone()).await.is_empty());
+
+        // A changed one means what it did before
+        db::upsert_user_alias(&pool, user.id, "sol", "solend").await.unwrap();
+        let action = Action::AliasSaved { alias: "sol".to_string(), previous: Some("solana".to_string()) };
+        assert_eq!(revert(&pool, user.id, &action).await.unwrap(), "Undone: \"sol\" means solana again.");
+        assert_eq!(targets(pool.clone()).await, [("sol".to_string(), "solana".to_string())]);
+
+        // And a forgotten one comes back
+        db::delete_user_alias(&pool, user.id, "sol").await.unwrap();
+        revert(&pool, user.id, &Action::AliasForgotten { alias: "sol".to_string(), target: "solana".to_string() }).await.unwrap();
+        assert_eq!(targets(pool.clone()).await, [("sol".to_string(), "solana".to_string())]);
+    }
+
+    #[tokio::test]
+    async fn test_undo_watchlist_changes() {
+        let Some(pool) = test_pool().await else { return };
+        let user = db::create_user(&pool, "alice", None).await.unwrap();
+        let notes = |pool: Pool<Postgres>| async move {
+            db::get_watchlist(&pool, user.id).await.unwrap().into_iter().map(|entry| (entry.coin_id, entry.note)).collect::<Vec<_>>()
+        };
+
+        db::add_watchlist_entry(&pool, user.id, "solana", None).await.unwrap();
+        revert(&pool, user.id, &Action::WatchlistAdded { coin_id: "solana".to_string() }).await.unwrap();
+        assert!(notes(pool.clone()).await.is_empty());
+
+        // A removed entry comes back with its note
+        let action = Action::WatchlistRemoved { coin_id: "ethereum".to_string(), note: Some("merge".to_string()) };
+        assert_eq!(revert(&pool, user.id, &action).await.unwrap(), "Undone: ethereum is back on your watchlist.");
+        assert_eq!(notes(pool.clone()).await, [("ethereum".to_string(), Some("merge".to_string()))]);
+
+        db::set_watchlist_note(&pool, user.id, "ethereum", Some("sell at 5k")).await.unwrap();
+        let action = Action::WatchlistNoteChanged { coin_id: "ethereum".to_string(), previous: Some("merge".to_string()) };
+        revert(&pool, user.id, &action).await.unwrap();
+        assert_eq!(notes(pool.clone()).await, [("ethereum".to_string(), Some("merge".to_string()))]);
+    }
+
+    #[tokio::test]
+    async fn test_undo_holding_changes() {
+        let Some(pool) = test_pool().await else { return };
+        let user = db::create_user(&pool, "alice", None).await.unwrap();
+        let amounts = |pool: Pool<Postgres>| async move {
+            db::get_holdings_by_user_id(&pool, user.id).await.unwrap().into_iter().map(|holding| (holding.coin_id, holding.amount)).collect::<Vec<_>>()
+        };
+
+        db::upsert_holding(&pool, user.id, "bitcoin", 2.0).await.unwrap();
+        let action = Action::HoldingSet { coin_id: "bitcoin".to_string(), previous: Some(0.5) };
+        assert_eq!(revert(&pool, user.id, &action).await.unwrap(), "Undone: your bitcoin holding is 0.5 again.");
+        assert_eq!(amounts(pool.clone()).await, [("bitcoin".to_string(), 0.5)]);
+
+        db::upsert_holding(&pool, user.id, "solana", 10.0).await.unwrap();
+        revert(&pool, user.id, &Action::HoldingSet { coin_id: "solana".to_string(), previous: None }).await.unwrap();
+        assert_eq!(amounts(pool.clone()).await, [("bitcoin".to_string(), 0.5)]);
+
+        db::delete_holding(&pool, user.id, "bitcoin").await.unwrap();
+        revert(&pool, user.id, &Action::HoldingRemoved { coin_id: "bitcoin".to_string(), amount: 0.5 }).await.unwrap();
+        assert_eq!(amounts(pool.clone()).await, [("bitcoin".to_string(), 0.5)]);
+    }
+
+    #[tokio::test]
+    async fn test_trades_cant_be_undone() {
+        let Some(pool) = test_pool().await else { return };
+        let user = db::create_user(&pool, "alice", None).await.unwrap();
+        let reply = revert(&pool, user.id, &Action::TradeExecuted { tx_hash: "0xabc".to_string() }).await.unwrap();
+        assert!(reply.contains("0xabc") && reply.contains("can't be undone"), "{}", reply);
+    }
+}
@@ -0,0 +1,103 @@
+use crate::db::Verbosity;
+use regex::Regex;
+use std::sync::OnceLock;
+
+/// A length asked for in a single message
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LengthOverride {
+    pub verbosity: Verbosity,
+    /// Set when the message only asks for another length, e.g. "give me the detailed version",
+    /// so the previous question should be answered again
+    pub refers_back: bool,
+}
+
+fn preference_regex() -> &'static Regex {
+    static REGEX: OnceLock<Regex> = OnceLock::new();
+    REGEX.get_or_init(|| {
+        Regex::new(r"(?i)^\s*(?:please\s+)?(?:(?P<brief>be\s+(?:more\s+)?(?:brief|concise|short)|keep\s+(?:it|things|(?:your\s+)?answers)\s+(?:short|brief)|(?:use\s+)?(?:short|shorter|brief)\s+(?:answers|mode))|(?P<detailed>be\s+(?:more\s+)?(?:detailed|thorough)|(?:give\s+me\s+|use\s+)?(?:detailed|longer)\s+(?:answers|mode))|(?P<normal>(?:go\s+)?back\s+to\s+normal(?:\s+length)?|(?:use\s+)?normal\s+(?:length|answers|mode)))(?:\s+from\s+now\s+on)?(?:\s+please)?[\s.!]*$").unwrap()
+    })
+}
+
+fn override_regex() -> &'static Regex {
+    static REGEX: OnceLock<Regex> = OnceLock::new();
+    REGEX.get_or_init(|| {
+        Regex::new(r"(?i)(?P<detailed>\b(?:give\s+me\s+)?the\s+(?:detailed|long|full)\s+version\b|\bin\s+(?:more\s+)?(?:detail|depth)\b|\bdetailed\s+(?:answer|explanation|breakdown)\b)|(?P<brief>\b(?:give\s+me\s+)?the\s+(?:short|brief)\s+version\b|\bbriefly\b|\bin\s+short(?:\s*[,.?!]|\s*$)|\btl;?dr\b|\b(?:short|quick)\s+answer\b|\bin\s+(?:one|a)\s+sentence\b)").unwrap()
+    })
+}
+
+/// Parse a lasting preference like "be brief", "be more detailed" or "back to normal length"
+pub fn parse_preference(message: &str) -> Option<Verbosity> {
+    let captures = preference_regex().captures(message)?;
+    if captures.name("brief").is_some() {
+        Some(Verbosity::Brief)
+    } else if captures.name("detailed").is_some() {
+        Some(Verbosity::Detailed)
+    } else {
+        Some(Verbosity::Normal)
+    }
+}
+
+/// Parse a length asked for in this message only, like "briefly, what is restaking?"
+pub fn parse_override(message: &str) -> Option<LengthOverride> {
+    let found = override_regex().captures(message)?;
+    let verbosity = if found.name("detailed").is_some() { Verbosity::Detailed } else { Verbosity::Brief };
+
+    // What's left once the length request and filler are removed
+    let rest = override_regex().replace_all(message, "");
+    let words = rest
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty() && !matches!(word.to_lowercase().as_str(), "please" | "can" | "could" | "you" | "i" | "get" | "want" | "now" | "ok" | "okay" | "and" | "that" | "it" | "this"))
+        .count();
+
+    Some(LengthOverride { verbosity, refers_back: words < 2 })
+}
+
+/// Confirmation of a saved preference
+pub fn preference_reply(verbosity: Verbosity) -> String {
+    match verbosity {
+        Verbosity::Brief => "Got it, I'll keep my answers brief. Say \"give me the detailed version\" when you want more.",
+        Verbosity::Normal => "Got it, back to answers of normal length.",
+        Verbosity::Detailed => "Got it, I'll give detailed answers. Say \"briefly\" in a message when you want the short version.",
+    }
+    .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_preference() {
+        assert_eq!(parse_preference("be brief"), Some(Verbosity::Brief));
+        assert_eq!(parse_preference("Please keep your answers short from now on."), Some(Verbosity::Brief));
+        assert_eq!(parse_preference("be more detailed"), Some(Verbosity::Detailed));
+        assert_eq!(parse_preference("detailed answers please"), Some(Verbosity::Detailed));
+        assert_eq!(parse_preference("back to normal length"), Some(Verbosity::Normal));
+    }
+
+    #[test]
+    fn test_preference_needs_the_whole_message() {
+        assert_eq!(parse_preference("be brief, what's the price of eth?"), None);
+        assert_eq!(parse_preference("should I be brief with my broker?"), None);
+        assert_eq!(parse_preference("what's the price of eth?"), None);
+    }
+
+    #[test]
+    fn test_parse_override() {
+        assert_eq!(
+            parse_override("give me the detailed version"),
+            Some(LengthOverride { verbosity: Verbosity::Detailed, refers_back: true })
+        );
+        assert_eq!(
+            parse_override("Can you explain restaking in detail?"),
+            Some(LengthOverride { verbosity: Verbosity::Detailed, refers_back: false })
+        );
+        assert_eq!(
+            parse_override("briefly, should I stake my ETH?"),
+            Some(LengthOverride { verbosity: Verbosity::Brief, refers_back: false })
+        );
+        assert_eq!(parse_override("tl;dr please"), Some(LengthOverride { verbosity: Verbosity::Brief, refers_back: true }));
+        assert_eq!(parse_override("what's the price of eth?"), None);
+        assert_eq!(parse_override("should I hold stables in short-term treasuries?"), None);
+    }
+}
@@ -1,4 +1,5 @@
 use crate::personality::{KnowledgeSource, Personality};
+use crate::vault;
 use std::collections::HashMap;
 use std::fs::{self, File};
 use std::io::{self, Read, Write};
@@ -44,7 +45,8 @@ impl KnowledgeManager {
                 let mut contents = String::new();
                 file.read_to_string(&mut contents)?;
                 
-                let knowledge_entry: KnowledgeEntry = serde_json::from_str(&contents)?;
+                let mut knowledge_entry: KnowledgeEntry = serde_json::from_str(&contents)?;
+                knowledge_entry.content = vault::open_from_storage(knowledge_entry.content);
                 entries.insert(knowledge_entry.source_id.clone(), knowledge_entry);
             }
         }
@@ -121,7 +123,12 @@ impl KnowledgeManager {
     /// Save an entry to disk
     fn save_entry(&self, entry: &KnowledgeEntry) -> Result<()> {
         let file_path = self.get_file_path(&entry.source_id);
-        let json = serde_json::to_string_pretty(entry)?;
+        // Private entries only reach the disk encrypted
+        let stored = KnowledgeEntry {
+            content: vault::seal_for_storage(&entry.content, &entry.tags)?,
+            ..entry.clone()
+        };
+        let json = serde_json::to_string_pretty(&stored)?;
         
         let mut file = File::create(file_path)?;
         file.write_all(json.as_bytes())?;
@@ -155,10 +162,11 @@ impl KnowledgeManager {
     }
     
     /// Get all entries as a formatted string for context injection
+    /// Entries still encrypted because no key is loaded are left out
     pub fn get_all_entries_as_context(&self) -> String {
         let mut context = String::new();
         
-        for entry in self.entries.values() {
+        for entry in self.entries.values().filter(|entry| !vault::is_sealed(&entry.content)) {
             context.push_str(&format!("--- BEGIN KNOWLEDGE: {} ---\n", entry.source_id));
             context.push_str(&entry.content);
             context.push_str(&format!("\n--- END KNOWLEDGE: {} ---\n\n", entry.source_id));
@@ -172,7 +180,7 @@ impl KnowledgeManager {
         let mut context = String::new();
         
         for entry in self.entries.values() {
-            if entry.tags.contains(&source_type.to_string()) {
+            if entry.tags.contains(&source_type.to_string()) && !vault::is_sealed(&entry.content) {
                 context.push_str(&format!("--- BEGIN {}: {} ---\n", source_type.to_uppercase(), entry.source_id));
                 context.push_str(&entry.content);
                 context.push_str(&format!("\n--- END {}: {} ---\n\n", source_type.to_uppercase(), entry.source_id));
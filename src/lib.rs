@@ -1,16 +1,70 @@
+pub mod error;
 pub mod db;
 pub mod anthropic;
+pub mod llm;
+pub mod openai;
 pub mod data_source;
 pub mod agent_customizer;
 pub mod exa_api;
 pub mod investment_chat;
 pub mod config;
 pub mod logging;
+pub mod http;
 pub mod price_fetcher;
+pub mod offline;
+pub mod commands;
+pub mod notifications;
+pub mod daemon;
+pub mod setup;
+pub mod personality;
+pub mod strategy_manager;
+pub mod trading;
+pub mod dex_aggregator;
+pub mod transaction_monitor;
+pub mod nonce_manager;
+pub mod retention;
+pub mod maintenance;
+pub mod write_queue;
+pub mod briefing;
+pub mod portfolio;
+pub mod portfolio_analysis;
+pub mod price_format;
+pub mod fiat;
+pub mod il_calculator;
+pub mod position_sizing;
+pub mod dca;
+pub mod entry_comparison;
+pub mod watchlist;
+pub mod health;
+pub mod circuit_breaker;
+pub mod api_budget;
+pub mod technical_levels;
+pub mod indicators;
+pub mod cost_basis;
+pub mod trade_import;
+pub mod trade_history;
+pub mod price_move;
+pub mod price_performance;
+pub mod trade_command;
+pub mod rebalancing;
+pub mod rate_limit;
+pub mod enrichment;
+pub mod render;
+pub mod stablecoins;
+pub mod clock;
+pub mod timezone;
+pub mod gas;
+pub mod scenario;
+pub mod unlocks;
+pub mod derivatives;
+pub mod vault;
+pub mod categories;
 
 // Re-export commonly used types
+pub use error::{Error, Result};
+
 pub use db::{
-    User, Strategy, Knowledge, DataSource,
+    User, Strategy, Knowledge, DataSource, Message, MessageRole,
     get_db_pool, save_message, get_messages, init_db_pool,
 };
 
@@ -23,3 +77,10 @@ pub use exa_api::{
 };
 
 pub use config::Config;
+pub mod feedback;
+pub mod strategy_progress;
+pub mod report;
+pub mod topics;
+pub mod compliance;
+pub mod turn_debug;
+pub mod agent_registry;
@@ -0,0 +1,329 @@
+use async_trait::async_trait;
+use reqwest::{Response, StatusCode};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+use std::time::Duration;
+use thiserror::Error;
+use crate::anthropic::AnthropicClient;
+use crate::config::Config;
+use crate::db::MessageRole;
+use crate::http;
+use crate::openai::OpenAiClient;
+
+#[cfg(test)]
+pub(crate) mod testing;
+
+/// Errors returned by a chat model, whichever provider serves it
+#[derive(Debug, Error)]
+pub enum LlmError {
+    #[error("LLM API key not configured")]
+    ApiKeyNotFound,
+
+    #[error("HTTP error: {0}")]
+    Http(#[from] reqwest::Error),
+
+    #[error("LLM API rejected the API key: {0}")]
+    Unauthorized(String),
+
+    #[error("LLM API rate limit exceeded{}", .0.map(|d| format!(", retry after {}s", d.as_secs())).unwrap_or_default())]
+    RateLimited(Option<Duration>),
+
+    #[error("API request failed with status {status}: {message}")]
+    Api { status: StatusCode, message: String },
+
+    #[error("Invalid response: {0}")]
+    InvalidResponse(String),
+
+    #[error("LLM API is unavailable in offline mode")]
+    Offline,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct Message {
+    pub role: MessageRole,
+    pub content: String,
+}
+
+/// Tokens billed for one completion
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TokenUsage {
+    pub input_tokens: u32,
+    pub output_tokens: u32,
+}
+
+impl std::ops::Add for TokenUsage {
+    type Output = TokenUsage;
+
+    fn add(self, other: TokenUsage) -> TokenUsage {
+        TokenUsage {
+            input_tokens: self.input_tokens + other.input_tokens,
+            output_tokens: self.output_tokens + other.output_tokens,
+        }
+    }
+}
+
+/// Text of a completion with the tokens it used
+#[derive(Debug, Clone, PartialEq)]
+pub struct Completion {
+    pub text: String,
+    pub usage: TokenUsage,
+    /// The stop sequence that ended the completion, not included in `text`
+    /// Providers that don't say which sequence matched leave it empty
+    pub stop_sequence: Option<String>,
+    /// Id the provider gave the response, logged when a reply has to be thrown away
+    pub request_id: Option<String>,
+}
+
+/// Per-call settings of a completion
+#[derive(Debug, Clone, PartialEq)]
+pub struct CallOptions {
+    pub max_tokens: u32,
+    /// Text that ends the completion as soon as the model writes it
+    pub stop_sequences: Vec<String>,
+}
+
+impl CallOptions {
+    pub fn new(max_tokens: u32) -> Self {
+        Self { max_tokens, stop_sequences: Vec::new() }
+    }
+
+    pub fn with_stop_sequence(mut self, stop_sequence: &str) -> Self {
+        self.stop_sequences.push(stop_sequence.to_string());
+        self
+    }
+}
+
+/// Sentinel structured prompts ask the model to write after its JSON, sent as a stop sequence
+pub const END_OF_JSON: &str = "###END###";
+
+/// Appended to the system prompt of structured calls so the reply ends at the sentinel
+const END_OF_JSON_INSTRUCTION: &str = "Write ###END### on its own line right after the JSON and nothing after it.";
+
+/// Which API serves completions, set with LLM_PROVIDER
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum LlmProvider {
+    #[default]
+    Anthropic,
+    /// OpenAI or any server speaking its chat completions API, e.g. vLLM
+    OpenAi,
+    /// A local Ollama server through its OpenAI-compatible endpoint, no API key needed
+    Ollama,
+}
+
+impl FromStr for LlmProvider {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.trim().to_lowercase().as_str() {
+            "anthropic" | "claude" => Ok(LlmProvider::Anthropic),
+            "openai" | "openai-compatible" | "vllm" => Ok(LlmProvider::OpenAi),
+            "ollama" => Ok(LlmProvider::Ollama),
+            other => Err(format!("unknown LLM provider '{}', expected anthropic, openai or ollama", other)),
+        }
+    }
+}
+
+impl fmt::Display for LlmProvider {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            LlmProvider::Anthropic => "anthropic",
+            LlmProvider::OpenAi => "openai",
+            LlmProvider::Ollama => "ollama",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// A chat completion API
+///
+/// Each implementation maps the shared message roles and options onto its own request format, so
+/// callers never see which provider answers.
+#[async_trait]
+pub trait ChatModel: Send + Sync {
+    /// Send a conversation and return the whole reply
+    async fn generate(&self, system: &str, messages: &[Message], options: &CallOptions) -> Result<Completion, LlmError>;
+
+    /// Like `generate`, calling `on_text` with each piece of the reply as it arrives
+    async fn generate_streaming(
+        &self,
+        system: &str,
+        messages: &[Message],
+        options: &CallOptions,
+        on_text: &mut (dyn for<'t> FnMut(&'t str) + Send),
+    ) -> Result<Completion, LlmError>;
+
+    /// Whether the provider accepts native tool definitions
+    /// Tool output in the conversation works either way, it's sent as a user turn where needed
+    fn supports_tools(&self) -> bool;
+
+    /// Name of the model answering, when the provider was given one
+    fn model_name(&self) -> Option<&str> {
+        None
+    }
+
+    /// Ask for a JSON reply and return just the JSON
+    ///
+    /// The reply stops at `END_OF_JSON`, so prose the model adds after the JSON never arrives;
+    /// a sentinel the API didn't stop at and code fences are removed by `json_payload`
+    async fn generate_json(&self, system: &str, messages: &[Message], max_tokens: u32) -> Result<String, LlmError> {
+        let system = format!("{}\n\n{}", system, END_OF_JSON_INSTRUCTION);
+        let options = CallOptions::new(max_tokens).with_stop_sequence(END_OF_JSON);
+        let completion = self.generate(&system, messages, &options).await?;
+        Ok(json_payload(&completion.text).to_string())
+    }
+}
+
+/// The model of the configured provider, failing requests that take longer than `timeout`
+///
+/// Without a config the Anthropic API is used with ANTHROPIC_API_KEY.
+pub fn configured(timeout: Option<Duration>) -> Result<Box<dyn ChatModel>, LlmError> {
+    let provider = Config::get_instance().map(|config| config.llm_provider).unwrap_or_default();
+    let model: Box<dyn ChatModel> = match (provider, timeout) {
+        (LlmProvider::Anthropic, Some(timeout)) => Box::new(AnthropicClient::from_config()?.with_timeout(timeout)),
+        (LlmProvider::Anthropic, None) => Box::new(AnthropicClient::from_config()?),
+        (LlmProvider::OpenAi | LlmProvider::Ollama, Some(timeout)) => Box::new(OpenAiClient::from_config()?.with_timeout(timeout)),
+        (LlmProvider::OpenAi | LlmProvider::Ollama, None) => Box::new(OpenAiClient::from_config()?),
+    };
+    Ok(model)
+}
+
+/// The JSON in a structured reply: the text before `END_OF_JSON`, without markdown code fences
+///
+/// "```json\n[1, 2]\n```\n###END###\nHope this helps!" gives "[1, 2]"
+pub fn json_payload(reply: &str) -> &str {
+    let reply = match reply.find(END_OF_JSON) {
+        Some(end) => &reply[..end],
+        None => reply,
+    };
+    let Some(open) = reply.find("```") else {
+        return reply.trim();
+    };
+    let fenced = &reply[open + 3..];
+    // Skip a language tag like "json" on the opening fence
+    let fenced = match fenced.find('\n') {
+        Some(newline) if fenced[..newline].trim().chars().all(|c| c.is_ascii_alphanumeric()) => &fenced[newline + 1..],
+        _ => fenced,
+    };
+    match fenced.find("```") {
+        Some(close) => fenced[..close].trim(),
+        None => fenced.trim(),
+    }
+}
+
+/// Turn an unsuccessful response into the matching error, passing successful ones through
+pub(crate) async fn check_response(response: Response) -> Result<Response, LlmError> {
+    match response.status() {
+        StatusCode::UNAUTHORIZED => Err(LlmError::Unauthorized(error_message(response).await)),
+        StatusCode::TOO_MANY_REQUESTS => Err(LlmError::RateLimited(http::retry_after(response.headers()))),
+        status if !status.is_success() => Err(LlmError::Api { status, message: error_message(response).await }),
+        _ => Ok(response),
+    }
+}
+
+/// Pull the error message out of an `{"error": {"message": ...}}` body, or return the raw body
+async fn error_message(response: Response) -> String {
+    let body = response.text().await.unwrap_or_default();
+    serde_json::from_str::<serde_json::Value>(&body)
+        .ok()
+        .and_then(|json| json["error"]["message"].as_str().map(str::to_string))
+        .unwrap_or(body)
+}
+
+/// The `data:` payloads of a server-sent event stream read in chunks
+///
+/// A chunk may end in the middle of a line, or of a character, so the unfinished tail is kept
+/// for the next one.
+#[derive(Debug, Default)]
+pub(crate) struct SseBuffer {
+    pending: Vec<u8>,
+}
+
+impl SseBuffer {
+    /// Add a chunk, returning the payloads of the lines it completed
+    pub fn push(&mut self, chunk: &[u8]) -> Vec<String> {
+        self.pending.extend_from_slice(chunk);
+        let mut payloads = Vec::new();
+        while let Some(newline) = self.pending.iter().position(|&byte| byte == b'\n') {
+            let line: Vec<u8> = self.pending.drain(..=newline).collect();
+            payloads.extend(data_payload(&line));
+        }
+        payloads
+    }
+
+    /// The payload of a last line the stream didn't end with a newline
+    pub fn finish(&mut self) -> Option<String> {
+        let line = std::mem::take(&mut self.pending);
+        data_payload(&line)
+    }
+}
+
+fn data_payload(line: &[u8]) -> Option<String> {
+    let line = String::from_utf8_lossy(line);
+    line.trim_end_matches(['\r', '\n'])
+        .strip_prefix("data:")
+        .map(|payload| payload.trim_start().to_string())
+}
+
+/// Read a streamed response, passing each payload to `on_event` until it returns false
+pub(crate) async fn read_events(
+    mut response: Response,
+    mut on_event: impl FnMut(&str) -> Result<bool, LlmError>,
+) -> Result<(), LlmError> {
+    let mut buffer = SseBuffer::default();
+    while let Some(chunk) = response.chunk().await? {
+        for payload in buffer.push(&chunk) {
+            if !on_event(&payload)? {
+                return Ok(());
+            }
+        }
+    }
+    if let Some(payload) = buffer.finish() {
+        on_event(&payload)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_json_payload() {
+        let cases = [
+            ("[1, 2]", "[1, 2]"),
+            ("  {\"a\": 1}\n", "{\"a\": 1}"),
+            ("[1, 2]\n###END###\nLet me know if you need anything else!", "[1, 2]"),
+            ("```json\n[1, 2]\n```", "[1, 2]"),
+            ("```\n{\"a\": [1]}\n```\nThese are [my] picks.", "{\"a\": [1]}"),
+            ("Here you go:\n```json\n[1]\n```\n###END###", "[1]"),
+            ("```json\n[1, 2]", "[1, 2]"),
+            ("```[1]```", "[1]"),
+            ("", ""),
+        ];
+        for (reply, expected) in cases {
+            assert_eq!(json_payload(reply), expected, "reply: {:?}", reply);
+        }
+    }
+
+    #[test]
+    fn test_sse_buffer_joins_lines_split_across_chunks() {
+        let mut buffer = SseBuffer::default();
+        assert!(buffer.push(b"event: content_block_delta\ndata: {\"text\":\"caf").is_empty());
+        // "é" split between two chunks
+        assert_eq!(buffer.push(b"\xc3"), Vec::<String>::new());
+        assert_eq!(buffer.push(b"\xa9\"}\r\n\ndata: [DONE]"), vec!["{\"text\":\"café\"}".to_string()]);
+        assert_eq!(buffer.finish().as_deref(), Some("[DONE]"));
+        assert_eq!(buffer.finish(), None);
+    }
+
+    #[test]
+    fn test_provider_names() {
+        assert_eq!("Anthropic".parse::<LlmProvider>(), Ok(LlmProvider::Anthropic));
+        assert_eq!(" openai ".parse::<LlmProvider>(), Ok(LlmProvider::OpenAi));
+        assert_eq!("vllm".parse::<LlmProvider>(), Ok(LlmProvider::OpenAi));
+        assert_eq!("ollama".parse::<LlmProvider>(), Ok(LlmProvider::Ollama));
+        assert!("gemini".parse::<LlmProvider>().is_err());
+        assert_eq!(LlmProvider::Ollama.to_string(), "ollama");
+    }
+}
@@ -0,0 +1,118 @@
+use super::{CallOptions, ChatModel, Completion, LlmError, Message, TokenUsage};
+use async_trait::async_trait;
+use std::collections::VecDeque;
+use std::sync::Mutex;
+
+/// One call a `MockChatModel` received
+#[derive(Debug, Clone)]
+pub struct Request {
+    pub system: String,
+    pub messages: Vec<Message>,
+    pub options: CallOptions,
+}
+
+/// One canned reply of a `MockChatModel`
+#[derive(Debug, Clone)]
+enum Reply {
+    Text(String),
+    /// Streams the text, then fails as if the connection dropped
+    CutOff(String),
+    /// Streams the text, then never finishes
+    Stalled(String),
+}
+
+/// A model answering with canned replies in order and recording every request
+///
+/// Once the replies run out every call fails.
+#[derive(Debug, Default)]
+pub struct MockChatModel {
+    replies: Mutex<VecDeque<Reply>>,
+    requests: Mutex<Vec<Request>>,
+}
+
+impl MockChatModel {
+    pub fn replying<I, S>(replies: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        Self {
+            replies: Mutex::new(replies.into_iter().map(|reply| Reply::Text(reply.into())).collect()),
+            requests: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// A model whose streamed reply fails after `partial`, a plain call fails outright
+    pub fn cut_off(partial: &str) -> Self {
+        Self { replies: Mutex::new(VecDeque::from([Reply::CutOff(partial.to_string())])), ..Self::default() }
+    }
+
+    /// A model whose streamed reply stops after `partial` and waits forever
+    pub fn stalled(partial: &str) -> Self {
+        Self { replies: Mutex::new(VecDeque::from([Reply::Stalled(partial.to_string())])), ..Self::default() }
+    }
+
+    /// A model whose every call fails
+    pub fn failing() -> Self {
+        Self::default()
+    }
+
+    pub fn requests(&self) -> Vec<Request> {
+        self.requests.lock().unwrap().clone()
+    }
+
+    fn next_reply(&self, system: &str, messages: &[Message], options: &CallOptions) -> Option<Reply> {
+        self.requests.lock().unwrap().push(Request {
+            system: system.to_string(),
+            messages: messages.to_vec(),
+            options: options.clone(),
+        });
+        self.replies.lock().unwrap().pop_front()
+    }
+
+    fn completion(&self, text: String) -> Completion {
+        Completion {
+            usage: TokenUsage { input_tokens: 10, output_tokens: text.split_whitespace().count() as u32 },
+            text,
+            stop_sequence: None,
+            request_id: Some(format!("mock-{}", self.requests.lock().unwrap().len())),
+        }
+    }
+}
+
+fn no_reply() -> LlmError {
+    LlmError::InvalidResponse("mock model has no reply".to_string())
+}
+
+#[async_trait]
+impl ChatModel for MockChatModel {
+    async fn generate(&self, system: &str, messages: &[Message], options: &CallOptions) -> Result<Completion, LlmError> {
+        match self.next_reply(system, messages, options) {
+            Some(Reply::Text(text)) => Ok(self.completion(text)),
+            _ => Err(no_reply()),
+        }
+    }
+
+    async fn generate_streaming(
+        &self,
+        system: &str,
+        messages: &[Message],
+        options: &CallOptions,
+        on_text: &mut (dyn for<'t> FnMut(&'t str) + Send),
+    ) -> Result<Completion, LlmError> {
+        let reply = self.next_reply(system, messages, options).ok_or_else(no_reply)?;
+        let (Reply::Text(text) | Reply::CutOff(text) | Reply::Stalled(text)) = &reply;
+        for piece in text.split_inclusive(' ') {
+            on_text(piece);
+        }
+        match reply {
+            Reply::Text(text) => Ok(self.completion(text)),
+            Reply::CutOff(_) => Err(LlmError::InvalidResponse("connection reset mid-stream".to_string())),
+            Reply::Stalled(_) => std::future::pending().await,
+        }
+    }
+
+    fn supports_tools(&self) -> bool {
+        false
+    }
+}
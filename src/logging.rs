@@ -29,10 +29,11 @@ pub fn init_logging(log_dir: impl AsRef<Path>) -> Result<(), Box<dyn std::error:
     }
     
     // Set up the subscriber with both terminal and file output
+    // Terminal logs go to stderr so stdout only carries answers, e.g. for `ask --json`
     tracing_subscriber::registry()
         .with(
             fmt::Layer::new()
-                .with_writer(std::io::stdout)
+                .with_writer(std::io::stderr)
                 .with_ansi(true)
                 .with_filter(EnvFilter::from_default_env().add_directive(Level::INFO.into()))
         )
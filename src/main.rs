@@ -1,14 +1,210 @@
 use agent_friend::{
+    agent_registry::{self, PoolAgentFactory},
+    api_budget,
+    commands,
+    config::AGENT_CONFIG_PATH,
+    daemon::{self, Daemon, DaemonConfig},
     db, 
-    investment_chat::InvestmentChatAgent, 
-    logging
+    enrichment,
+    exa_api::ExaApiError,
+    health::{self, HealthChecker},
+    investment_chat::{TurnEnvelope, TurnResult},
+    logging,
+    notifications,
+    offline,
+    price_fetcher::PriceError,
+    setup::{LiveValidator, SetupOptions, SetupWizard, StdioPrompter},
+    trading::TradingError,
+    vault,
+    write_queue,
 };
+use clap::{Args, Parser, Subcommand};
 use std::io::{self, Write};
-use std::path::Path;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 use tracing::{info, error};
 
+/// Nova - your crypto investment advisor
+#[derive(Debug, Parser)]
+#[command(version, about)]
+struct Cli {
+    /// Run without any network access (the LLM provider, Exa and CoinGecko are disabled)
+    #[arg(long)]
+    offline: bool,
+
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// Run the background engines (price watcher, data-source refresher) without the chat
+    Daemon(DaemonArgs),
+    /// Interactively create or update agent.toml and .env
+    Setup(SetupArgs),
+    /// Ask one question, print the answer and exit
+    Ask(AskArgs),
+}
+
+#[derive(Debug, Args)]
+struct AskArgs {
+    /// The question, quoted or as separate words
+    #[arg(required = true)]
+    question: Vec<String>,
+
+    /// Print a JSON envelope with the intent, structured data, token usage and latency
+    #[arg(long)]
+    json: bool,
+}
+
+#[derive(Debug, Args)]
+struct SetupArgs {
+    /// Configuration file to create or update
+    #[arg(long, default_value = AGENT_CONFIG_PATH)]
+    config: PathBuf,
+
+    /// Environment file to optionally write
+    #[arg(long, default_value = ".env")]
+    env_file: PathBuf,
+}
+
+#[derive(Debug, Args)]
+struct DaemonArgs {
+    /// Configuration file with a [daemon] section
+    #[arg(long, default_value = AGENT_CONFIG_PATH)]
+    config: PathBuf,
+
+    /// Comma-separated list of engines to run, overriding the config file
+    #[arg(long, value_delimiter = ',')]
+    engines: Option<Vec<String>>,
+
+    /// Address for the /healthz endpoint
+    #[arg(long)]
+    healthz_addr: Option<String>,
+
+    /// Seconds between heartbeat log lines
+    #[arg(long)]
+    heartbeat_secs: Option<u64>,
+
+    /// Seconds between price watcher runs
+    #[arg(long)]
+    price_interval_secs: Option<u64>,
+
+    /// Seconds between data source refreshes
+    #[arg(long)]
+    data_sources_interval_secs: Option<u64>,
+}
+
+impl DaemonArgs {
+    /// Load the config file and apply command line overrides
+    fn into_config(self) -> Result<DaemonConfig, daemon::DaemonError> {
+        let mut config = DaemonConfig::load(&self.config)?;
+        if let Some(engines) = &self.engines {
+            config.only_engines(engines)?;
+        }
+        if let Some(addr) = self.healthz_addr {
+            config.healthz_addr = addr;
+        }
+        if let Some(secs) = self.heartbeat_secs {
+            config.heartbeat_interval_secs = secs;
+        }
+        if let Some(secs) = self.price_interval_secs {
+            config.price_watcher.interval_secs = secs;
+        }
+        if let Some(secs) = self.data_sources_interval_secs {
+            config.data_sources.interval_secs = secs;
+        }
+        Ok(config)
+    }
+}
+
+/// Run the background engines until Ctrl-C or SIGTERM
+async fn run_daemon(args: DaemonArgs) -> anyhow::Result<()> {
+    let config = args.into_config()?;
+    info!("Starting daemon with engines: {:?}", config.enabled_engines());
+
+    let pool = db::init_db_pool().await.map_err(|e| {
+        error!("Database connection failed: {}", e);
+        anyhow::anyhow!("The daemon requires a database: {}", e)
+    })?;
+    api_budget::start(pool).await;
+
+    let checker = HealthChecker::from_config(Some(pool.clone()));
+    info!("Health: {}", health::summary_line(&checker.check().await));
+
+    let engines = daemon::build_engines(&config, pool).await?;
+    Daemon::new(config, engines)
+        .with_health_checker(checker)
+        .run(daemon::shutdown_signal())
+        .await?;
+    db::close_db_pool().await;
+    Ok(())
+}
+
+/// Answer a single question, as prose or as a JSON envelope
+async fn run_ask(args: AskArgs) -> anyhow::Result<()> {
+    let started = Instant::now();
+    let answered = ask(&args.question.join(" ")).await;
+    match &answered {
+        Ok((turn, latency)) if args.json => println!("{}", serde_json::to_string(&turn.envelope(*latency))?),
+        Ok((turn, _)) => println!("{}", turn.text),
+        // Scripts still get an envelope on stdout, the error itself goes to stderr
+        Err(e) if args.json => {
+            println!("{}", serde_json::to_string(&TurnEnvelope::failed(e.to_string(), started.elapsed()))?)
+        },
+        Err(_) => {},
+    }
+    answered?;
+    Ok(())
+}
+
+/// Answer `question` as the default user, returning the answer and how long the turn took
+async fn ask(question: &str) -> anyhow::Result<(TurnResult, Duration)> {
+    let pool = db::init_db_pool().await.map_err(|e| {
+        error!("Database connection failed: {}", e);
+        anyhow::anyhow!("Answering a question requires a database: {}", e)
+    })?;
+    write_queue::start(pool).await;
+    api_budget::start(pool).await;
+    let agent = agent_registry::start(pool)
+        .get("default_user")
+        .await
+        .map_err(|e| anyhow::anyhow!("Failed to initialize agent: {}", e))?;
+
+    let started = Instant::now();
+    let turn = agent.process_turn(question).await;
+    let latency = started.elapsed();
+
+    // Let research queued by the answer finish before exiting
+    enrichment::shutdown().await;
+    write_queue::shutdown().await;
+    db::close_db_pool().await;
+    Ok((turn?, latency))
+}
+
+/// Run the interactive setup wizard
+async fn run_setup(args: SetupArgs) -> anyhow::Result<()> {
+    println!("\n=== Nova setup ===");
+    println!("Values are checked live; press Enter to skip optional ones.\n");
+    
+    let options = SetupOptions {
+        config_path: args.config,
+        env_path: args.env_file,
+    };
+    let validator = LiveValidator::new();
+    let mut prompter = StdioPrompter;
+    SetupWizard::new(&mut prompter, &validator).run(&options).await?;
+    
+    println!("\nSetup complete. Run `agent-friend` to start chatting.");
+    Ok(())
+}
+
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
+    let cli = Cli::parse();
+    
     // Initialize logging
     let log_dir = Path::new("./logs");
     if let Err(e) = logging::init_logging(log_dir) {
@@ -19,20 +215,60 @@ async fn main() -> anyhow::Result<()> {
     dotenv::dotenv().ok();
     info!("Starting Crypto Investment Agent");
     
+    if cli.offline {
+        offline::enable();
+    }
+    
+    // Knowledge tagged private stays locked without a passphrase
+    match vault::from_config() {
+        Ok(Some(key)) => vault::unlock(key),
+        Ok(None) => {},
+        Err(e) => error!("Private knowledge stays locked: {}", e),
+    }
+    
+    match cli.command {
+        Some(Command::Daemon(args)) => return run_daemon(args).await,
+        Some(Command::Setup(args)) => return run_setup(args).await,
+        Some(Command::Ask(args)) => return run_ask(args).await,
+        None => {}
+    }
+    
+    // General answers are printed as they arrive, the rest of the turn once it's done
+    let streamed = Arc::new(Mutex::new(String::new()));
+    let shown = streamed.clone();
+    let stream = Arc::new(move |piece: &str| {
+        let mut shown = shown.lock().unwrap();
+        if shown.is_empty() {
+            print!("\r\nNova: ");
+        }
+        shown.push_str(piece);
+        print!("{}", piece);
+        let _ = io::stdout().flush();
+    });
+    
     // Initialize database
     info!("Initializing database connection");
     match db::init_db_pool().await {
-        Ok(_) => info!("Database connection established"),
+        Ok(pool) => {
+            info!("Database connection established");
+            // Messages left unsaved by the last session are written first
+            write_queue::start(pool).await;
+            api_budget::start(pool).await;
+            agent_registry::start_with(PoolAgentFactory::new(pool.clone()).with_stream(stream));
+        },
         Err(e) => {
             error!("Database connection failed: {}", e);
             println!("Warning: Database connection failed. The agent will work without database features.");
         }
     }
     
-    // Create agent
+    // Create agent, kept by the registry until the user's profile changes
     let username = "default_user";
     info!("Creating investment chat agent for user: {}", username);
-    let agent = match InvestmentChatAgent::new(username).await {
+    let Some(registry) = agent_registry::configured() else {
+        return Err(anyhow::anyhow!("Failed to initialize agent: the chat needs a database"));
+    };
+    let agent = match registry.get(username).await {
         Ok(agent) => {
             info!("Agent created successfully");
             agent
@@ -43,11 +279,46 @@ async fn main() -> anyhow::Result<()> {
         }
     };
     
+    // Ctrl-C stops an answer being written, keeping what arrived; at the prompt it still quits
+    let answering = Arc::new(AtomicBool::new(false));
+    let current = Arc::new(Mutex::new(agent.clone()));
+    {
+        let current = current.clone();
+        let answering = answering.clone();
+        tokio::spawn(async move {
+            while tokio::signal::ctrl_c().await.is_ok() {
+                if !answering.load(Ordering::SeqCst) {
+                    std::process::exit(130);
+                }
+                current.lock().unwrap().interrupt();
+            }
+        });
+    }
+    
+    // Log the state of the integrations without holding up the chat
+    let checker = HealthChecker::from_config(Some(agent.pool().clone()));
+    tokio::spawn(async move {
+        info!("Health: {}", health::summary_line(&checker.check().await));
+    });
+    
     // Welcome message
     println!("\n=== Nova - Your Crypto Investment Advisor ===");
     println!("Chat with Nova about crypto investments, market trends, and trading strategies.");
     println!("Nova can research projects in real-time and provide personalized investment advice.");
-    println!("Type 'exit' or 'quit' to end the conversation.\n");
+    println!("Type /help for local commands, or 'exit' or 'quit' to end the conversation.\n");
+    if offline::is_offline() {
+        println!("Running in offline mode: answers come from your stored prices, knowledge and history only.\n");
+    }
+    
+    // Surface events recorded by the daemon since the last session
+    match notifications::take_unread(agent.pool(), agent.user_id()).await {
+        Ok(unread) => {
+            if let Some(summary) = notifications::render_while_away(&unread, agent.timezone()) {
+                println!("{}", summary);
+            }
+        },
+        Err(e) => error!("Failed to load notifications: {}", e),
+    }
     
     // Initial greeting
     let greeting = "Hi! I'm Nova, your crypto investment advisor. I can help you research projects, analyze market trends, and make informed investment decisions. What would you like to discuss today?";
@@ -72,11 +343,47 @@ async fn main() -> anyhow::Result<()> {
             continue;
         }
         
+        // A changed profile drops the user's agent, this message then gets one built from the new profile
+        let agent = match registry.get(username).await {
+            Ok(agent) => agent,
+            Err(e) => {
+                error!("Failed to create investment chat agent: {}", e);
+                println!("\nError: {}", e);
+                continue;
+            }
+        };
+        *current.lock().unwrap() = agent.clone();
+        
+        // Handle local slash commands without involving the AI
+        if let Some(result) = commands::handle_command(&agent, input).await {
+            match result {
+                Ok(output) => println!("\n{}", output),
+                Err(e) => {
+                    error!("Error running command: {}", e);
+                    println!("\nError: {}", e);
+                }
+            }
+            // The session can't continue once the account is deleted
+            if input.starts_with("/account") && matches!(db::get_user_by_id(agent.pool(), agent.user_id()).await, Ok(None)) {
+                break;
+            }
+            continue;
+        }
+        
         // Process the message
         print!("\nNova is thinking...");
         io::stdout().flush()?;
         
-        match agent.process_message(input).await {
+        answering.store(true, Ordering::SeqCst);
+        let result = agent.process_message(input).await;
+        answering.store(false, Ordering::SeqCst);
+        let shown = std::mem::take(&mut *streamed.lock().unwrap());
+        
+        match result {
+            // A streamed answer only needs what came after the stream, like a footer or the interruption marker
+            Ok(response) if !shown.is_empty() && response.starts_with(shown.trim_end()) => {
+                println!("{}", &response[shown.trim_end().len()..]);
+            },
             Ok(response) => {
                 print!("\r"); // Clear the "thinking" message
                 println!("\nNova: {}", response);
@@ -85,11 +392,46 @@ async fn main() -> anyhow::Result<()> {
                 print!("\r"); // Clear the "thinking" message
                 error!("Error processing message: {}", e);
                 
+                // Waiting is all the user can do about a rate limit, asking again about an empty answer
+                if let agent_friend::investment_chat::InvestmentChatError::RateLimited { .. }
+                    | agent_friend::investment_chat::InvestmentChatError::EmptyResponse { .. } = e
+                {
+                    println!("\nNova: {}", e);
+                    continue;
+                }
+                
+                // A refused trade says what to change, e.g. the amount or the slippage
+                if let agent_friend::investment_chat::InvestmentChatError::Trading(
+                    ref refusal @ (TradingError::InsufficientBalance { .. }
+                    | TradingError::SlippageTooHigh { .. }
+                    | TradingError::SlippageTooLow { .. }
+                    | TradingError::SuspiciousQuote { .. }
+                    | TradingError::GasTooExpensive { .. }
+                    | TradingError::GasPriceTooHigh { .. }
+                    | TradingError::TokenNotFound(_)
+                    | TradingError::OrderNotFound(_)
+                    | TradingError::OrderNotOpen { .. }
+                    | TradingError::OrderExpired(_)
+                    | TradingError::SignerRequired(_)),
+                ) = e
+                {
+                    println!("\nNova: Sorry, {}", refusal);
+                    continue;
+                }
+                
+                // A feature skipped because its API's daily budget ran out says until when
+                if let agent_friend::investment_chat::InvestmentChatError::PriceApi(PriceError::BudgetExhausted(ref exhausted))
+                    | agent_friend::investment_chat::InvestmentChatError::ExaApi(ExaApiError::BudgetExhausted(ref exhausted)) = e
+                {
+                    println!("\nNova: Sorry, I had to skip that: {}.", exhausted);
+                    continue;
+                }
+                
                 // Provide more specific error messages based on error type
                 let user_message = match e {
-                    agent_friend::investment_chat::InvestmentChatError::AnthropicApi(ref msg) => {
+                    agent_friend::investment_chat::InvestmentChatError::LlmApi(ref msg) => {
                         if msg.contains("Authentication error") || msg.contains("Invalid API key") {
-                            "Sorry, I'm having trouble with my API authentication. Please check that your Anthropic API key is valid in the .env file."
+                            "Sorry, I'm having trouble with my API authentication. Please check that the API key of your LLM provider is valid in the .env file."
                         } else if msg.contains("Connection error") || msg.contains("timed out") {
                             "Sorry, I'm having trouble connecting to my AI service. Please check your internet connection and try again."
                         } else if msg.contains("Rate limit") {
@@ -97,21 +439,38 @@ async fn main() -> anyhow::Result<()> {
                         } else if msg.contains("Server error") {
                             "Sorry, the AI service is currently experiencing issues. Please try again later."
                         } else {
-                            "Sorry, I encountered an error while processing your request. There might be an issue with the Anthropic API service."
+                            "Sorry, I encountered an error while processing your request. There might be an issue with the LLM API service."
                         }
                     },
                     agent_friend::investment_chat::InvestmentChatError::Configuration(ref _msg) => {
                         "Sorry, there's a configuration issue. Please check your .env file and ensure all required API keys are set correctly."
                     },
-                    agent_friend::investment_chat::InvestmentChatError::PriceApi(ref _msg) => {
-                        "Sorry, I couldn't fetch the cryptocurrency price data. The price API might be experiencing issues or the cryptocurrency symbol might not be supported."
+                    agent_friend::investment_chat::InvestmentChatError::PriceApi(ref _err) => {
+                        "Sorry, I couldn't fetch the cryptocurrency price data. The price API might be experiencing issues, the cryptocurrency symbol might not be supported, or the date might not be in DD-MM-YYYY format."
                     },
-                    agent_friend::investment_chat::InvestmentChatError::ExternalApi(ref _msg) => {
-                        if _msg.contains("price") {
-                            "Sorry, I couldn't fetch the latest cryptocurrency price data. The price API might be experiencing issues."
-                        } else {
-                            "Sorry, I encountered an issue with an external API. Please try again later."
-                        }
+                    agent_friend::investment_chat::InvestmentChatError::Offline(ref _msg) => {
+                        "Sorry, I can't reach the network right now. I've switched to offline mode for a minute, so ask again and I'll answer from your stored data."
+                    },
+                    agent_friend::investment_chat::InvestmentChatError::ExaApi(ref _err) => {
+                        "Sorry, I encountered an issue with my research API. Please try again later."
+                    },
+                    agent_friend::investment_chat::InvestmentChatError::Trading(ref err) => match err {
+                        TradingError::Configuration(_) | TradingError::Wallet(_) | TradingError::InvalidAddress(_) => {
+                            "Sorry, the trading wallet isn't set up correctly. Please check PRIVATE_KEY, CHAIN_ID and RPC_URL in the .env file."
+                        },
+                        TradingError::Provider(_) | TradingError::Contract(_) | TradingError::Broadcast(_) | TradingError::Approval(_) => {
+                            "Sorry, I couldn't get through to the blockchain node. Please check that RPC_URL is reachable and try again."
+                        },
+                        TradingError::RateLimited(_) => {
+                            "Sorry, I've reached my usage limit with 1inch. Please try again in a few minutes."
+                        },
+                        TradingError::Http(_) | TradingError::Api { .. } | TradingError::InvalidResponse(_) => {
+                            "Sorry, 1inch couldn't prepare the trade. Please try again later."
+                        },
+                        TradingError::ZeroExApi { .. } | TradingError::InvalidZeroExResponse(_) => {
+                            "Sorry, 0x couldn't prepare the trade. Please try again later."
+                        },
+                        _ => "Sorry, I encountered an error with the trade. Please try again.",
                     },
                     _ => "Sorry, I encountered an error while processing your request. Please try again."
                 };
@@ -121,5 +480,13 @@ async fn main() -> anyhow::Result<()> {
         }
     }
     
+    // Research queued during the session is saved for the next one
+    if enrichment::running() > 0 {
+        println!("Finishing background research...");
+    }
+    enrichment::shutdown().await;
+    // Messages still unsaved stay in the spill file for the next session
+    write_queue::shutdown().await;
+    
     Ok(())
 }
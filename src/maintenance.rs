@@ -0,0 +1,234 @@
+use crate::db::{self, DbError};
+use crate::retention::{self, RetentionError, Summarizer};
+use sqlx::{Pool, Postgres};
+use std::fmt;
+use thiserror::Error;
+
+/// Default age after which unchanged knowledge is pruned
+pub const DEFAULT_KNOWLEDGE_MAX_AGE_DAYS: i64 = 90;
+
+/// Source ids listed per line of a dry run before the rest are counted
+const MAX_LISTED: usize = 20;
+
+/// Errors raised by a maintenance task
+#[derive(Debug, Error)]
+pub enum MaintenanceError {
+    #[error("Database error: {0}")]
+    Database(#[from] DbError),
+
+    #[error("Archive error: {0}")]
+    Retention(#[from] RetentionError),
+
+    #[error("Invalid age {0:?}: use a number of days or weeks, e.g. 90d or 12w")]
+    InvalidAge(String),
+}
+
+/// Parse an age such as "90d", "12w" or "90" (days) into days
+pub fn parse_age_days(text: &str) -> Result<i64, MaintenanceError> {
+    let text = text.trim().to_lowercase();
+    let (number, per_unit) = if let Some(days) = text.strip_suffix('d') {
+        (days, 1)
+    } else if let Some(weeks) = text.strip_suffix('w') {
+        (weeks, 7)
+    } else {
+        (text.as_str(), 1)
+    };
+    number
+        .parse::<i64>()
+        .ok()
+        .and_then(|number| number.checked_mul(per_unit))
+        .filter(|days| *days > 0)
+        .ok_or_else(|| MaintenanceError::InvalidAge(text.clone()))
+}
+
+/// A housekeeping job run outside the chat
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Task {
+    /// Delete knowledge not updated for this many days
+    PruneKnowledge { older_than_days: i64 },
+    /// Summarize and archive messages older than this many days, see `retention`
+    ArchiveMessages { older_than_days: i64 },
+    /// Merge knowledge entries of a user with the same content
+    DedupeKnowledge,
+    /// Recompute knowledge embeddings, none are stored yet
+    ReindexEmbeddings,
+    /// Row counts and sizes of the tables that grow with use
+    Stats,
+}
+
+impl Task {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Task::PruneKnowledge { .. } => "prune-knowledge",
+            Task::ArchiveMessages { .. } => "archive-messages",
+            Task::DedupeKnowledge => "dedupe-knowledge",
+            Task::ReindexEmbeddings => "reindex-embeddings",
+            Task::Stats => "stats",
+        }
+    }
+}
+
+/// What a task changed, or would change on a dry run
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Report {
+    pub task: &'static str,
+    pub dry_run: bool,
+    /// Rows changed, or that would be
+    pub changed: u64,
+    pub lines: Vec<String>,
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let prefix = if self.dry_run { "[dry run] " } else { "" };
+        for line in &self.lines {
+            writeln!(f, "{}{}", prefix, line)?;
+        }
+        Ok(())
+    }
+}
+
+/// Run a task, or on a dry run report what it would change without writing anything
+pub async fn run(
+    pool: &Pool<Postgres>,
+    task: Task,
+    dry_run: bool,
+    summarizer: &dyn Summarizer,
+) -> Result<Report, MaintenanceError> {
+    let (changed, lines) = match task {
+        Task::PruneKnowledge { older_than_days } => prune_knowledge(pool, older_than_days, dry_run).await?,
+        Task::ArchiveMessages { older_than_days } => archive_messages(pool, older_than_days, dry_run, summarizer).await?,
+        Task::DedupeKnowledge => dedupe_knowledge(pool, dry_run).await?,
+        Task::ReindexEmbeddings => {
+            (0, vec!["Knowledge has no embeddings stored, it is matched by tags and text. Nothing to reindex.".to_string()])
+        },
+        Task::Stats => stats(pool).await?,
+    };
+    Ok(Report { task: task.name(), dry_run, changed, lines })
+}
+
+async fn prune_knowledge(pool: &Pool<Postgres>, older_than_days: i64, dry_run: bool) -> Result<(u64, Vec<String>), MaintenanceError> {
+    let cutoff = retention::retention_cutoff(older_than_days);
+    if !dry_run {
+        let deleted = db::delete_knowledge_updated_before(pool, cutoff).await?;
+        return Ok((deleted, vec![format!("Deleted {} knowledge entries not updated in {} days.", deleted, older_than_days)]));
+    }
+
+    let stale = db::get_knowledge_updated_before(pool, cutoff).await?;
+    let mut lines = vec![format!("Would delete {} knowledge entries not updated in {} days.", stale.len(), older_than_days)];
+    let sources: Vec<String> = stale
+        .iter()
+        .map(|entry| format!("user {} {} ({})", entry.user_id, entry.source_id, entry.updated_at.format("%Y-%m-%d")))
+        .collect();
+    lines.extend(listed(sources));
+    Ok((stale.len() as u64, lines))
+}
+
+async fn archive_messages(
+    pool: &Pool<Postgres>,
+    older_than_days: i64,
+    dry_run: bool,
+    summarizer: &dyn Summarizer,
+) -> Result<(u64, Vec<String>), MaintenanceError> {
+    if !dry_run {
+        let archived = retention::run_retention(pool, older_than_days, summarizer).await?;
+        return Ok((archived, vec![format!("Archived {} messages older than {} days.", archived, older_than_days)]));
+    }
+
+    let counts = db::count_messages_before(pool, retention::retention_cutoff(older_than_days)).await?;
+    let total: i64 = counts.iter().map(|(_, count)| count).sum();
+    let mut lines = vec![format!(
+        "Would summarize and archive {} messages older than {} days for {} users.",
+        total,
+        older_than_days,
+        counts.len()
+    )];
+    lines.extend(listed(counts.iter().map(|(user_id, count)| format!("user {}: {} messages", user_id, count)).collect()));
+    Ok((total as u64, lines))
+}
+
+async fn dedupe_knowledge(pool: &Pool<Postgres>, dry_run: bool) -> Result<(u64, Vec<String>), MaintenanceError> {
+    let groups = db::get_duplicate_knowledge(pool).await?;
+    let duplicates: usize = groups.iter().map(|group| group.duplicates.len()).sum();
+    let described: Vec<String> = groups
+        .iter()
+        .map(|group| {
+            let removed: Vec<&str> = group.duplicates.iter().map(|entry| entry.source_id.as_str()).collect();
+            format!("user {} keeps {}, drops {}", group.kept.user_id, group.kept.source_id, removed.join(", "))
+        })
+        .collect();
+
+    if dry_run {
+        let mut lines = vec![format!("Would delete {} duplicate knowledge entries in {} groups.", duplicates, groups.len())];
+        lines.extend(listed(described));
+        return Ok((duplicates as u64, lines));
+    }
+
+    let deleted = db::merge_duplicate_knowledge(pool, &groups).await?;
+    let mut lines = vec![format!("Deleted {} duplicate knowledge entries, their tags kept on the remaining entry.", deleted)];
+    lines.extend(listed(described));
+    Ok((deleted, lines))
+}
+
+async fn stats(pool: &Pool<Postgres>) -> Result<(u64, Vec<String>), MaintenanceError> {
+    let tables = db::get_table_stats(pool).await?;
+    let stale = db::get_knowledge_updated_before(pool, retention::retention_cutoff(DEFAULT_KNOWLEDGE_MAX_AGE_DAYS))
+        .await?
+        .len();
+    let duplicates: usize = db::get_duplicate_knowledge(pool).await?.iter().map(|group| group.duplicates.len()).sum();
+    let old_messages: i64 = db::count_messages_before(pool, retention::retention_cutoff(retention::DEFAULT_RETENTION_DAYS))
+        .await?
+        .iter()
+        .map(|(_, count)| count)
+        .sum();
+
+    let mut lines: Vec<String> = tables
+        .iter()
+        .map(|table| format!("{}: {} rows, {} KiB", table.name, table.rows, table.bytes / 1024))
+        .collect();
+    lines.push(format!("Knowledge not updated in {} days: {}", DEFAULT_KNOWLEDGE_MAX_AGE_DAYS, stale));
+    lines.push(format!("Duplicate knowledge entries: {}", duplicates));
+    lines.push(format!("Messages older than {} days: {}", retention::DEFAULT_RETENTION_DAYS, old_messages));
+    Ok((0, lines))
+}
+
+/// The first `MAX_LISTED` items indented, with a count of the rest
+fn listed(items: Vec<String>) -> Vec<String> {
+    let more = items.len().saturating_sub(MAX_LISTED);
+    let mut lines: Vec<String> = items.into_iter().take(MAX_LISTED).map(|item| format!("  {}", item)).collect();
+    if more > 0 {
+        lines.push(format!("  and {} more", more));
+    }
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_age_days() {
+        assert_eq!(parse_age_days("90d").unwrap(), 90);
+        assert_eq!(parse_age_days("12W").unwrap(), 84);
+        assert_eq!(parse_age_days(" 30 ").unwrap(), 30);
+        for invalid in ["0d", "-5d", "90m", "d", "ninety"] {
+            assert!(matches!(parse_age_days(invalid), Err(MaintenanceError::InvalidAge(_))), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn test_listed_counts_the_rest() {
+        let items: Vec<String> = (0..MAX_LISTED + 3).map(|i| i.to_string()).collect();
+        let lines = listed(items);
+        assert_eq!(lines.len(), MAX_LISTED + 1);
+        assert_eq!(lines[0], "  0");
+        assert_eq!(lines[MAX_LISTED], "  and 3 more");
+        assert!(listed(Vec::new()).is_empty());
+    }
+
+    #[test]
+    fn test_dry_run_lines_are_marked() {
+        let report = Report { task: "stats", dry_run: true, changed: 0, lines: vec!["a".to_string(), "b".to_string()] };
+        assert_eq!(report.to_string(), "[dry run] a\n[dry run] b\n");
+    }
+}
@@ -0,0 +1,107 @@
+use crate::trading::BroadcastError;
+use ethers::providers::{Middleware, PendingTransaction};
+use ethers::types::transaction::eip2718::TypedTransaction;
+use ethers::types::{Address, BlockNumber, U256};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, OnceLock};
+use tracing::warn;
+
+/// Hands out the wallet's nonces one send at a time
+///
+/// Two transactions sent from the same wallet at once would otherwise both be signed with the
+/// node's pending count, and the second rejected with "nonce too low". The next nonce is read from
+/// the node on first use, and again after a send fails, since a failed send may or may not have
+/// used it. A "nonce too low" rejection means something else sent from the wallet: the nonce is
+/// read again and the transaction sent once more.
+pub struct NonceManager {
+    address: Address,
+    /// None until read from the node, and after a failed send
+    next: tokio::sync::Mutex<Option<U256>>,
+}
+
+impl NonceManager {
+    /// A manager for `address`, reading its next nonce from the node on the first send
+    pub fn new(address: Address) -> Self {
+        Self { address, next: tokio::sync::Mutex::new(None) }
+    }
+
+    /// The manager every client of `address` in this process shares, created on first use
+    pub fn shared(address: Address) -> Arc<Self> {
+        registry()
+            .lock()
+            .unwrap()
+            .entry(address)
+            .or_insert_with(|| Arc::new(Self::new(address)))
+            .clone()
+    }
+
+    /// Send `tx` from the wallet through `client` with the next nonce
+    /// Sends from the wallet wait for the one before them to be accepted or rejected by the node
+    pub async fn send<'a, M: Middleware>(
+        &self,
+        client: &'a M,
+        tx: impl Into<TypedTransaction>,
+    ) -> Result<PendingTransaction<'a, M::Provider>, BroadcastError> {
+        let mut tx = tx.into();
+        let mut next = self.next.lock().await;
+        let mut resynced = false;
+        loop {
+            let nonce = match *next {
+                Some(nonce) => nonce,
+                None => self.pending_count(client).await?,
+            };
+            tx.set_nonce(nonce);
+            match client.send_transaction(tx.clone(), None).await {
+                Ok(pending) => {
+                    *next = Some(nonce + 1);
+                    return Ok(pending);
+                },
+                Err(e) => {
+                    *next = None;
+                    let reason = e.to_string();
+                    if resynced || !is_nonce_too_low(&reason) {
+                        return Err(BroadcastError::Send(reason));
+                    }
+                    warn!("Nonce {} of {:?} was already used, reading it again from the node", nonce, self.address);
+                    resynced = true;
+                },
+            }
+        }
+    }
+
+    /// The nonce of the wallet's next transaction, counting the ones waiting in the mempool
+    async fn pending_count<M: Middleware>(&self, client: &M) -> Result<U256, BroadcastError> {
+        client
+            .get_transaction_count(self.address, Some(BlockNumber::Pending.into()))
+            .await
+            .map_err(|e| BroadcastError::Send(format!("could not read the nonce: {}", e)))
+    }
+}
+
+fn is_nonce_too_low(reason: &str) -> bool {
+    reason.to_lowercase().contains("nonce too low")
+}
+
+fn registry() -> &'static Mutex<HashMap<Address, Arc<NonceManager>>> {
+    static REGISTRY: OnceLock<Mutex<HashMap<Address, Arc<NonceManager>>>> = OnceLock::new();
+    REGISTRY.get_or_init(|| Mutex::new(HashMap::new()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_nonce_too_low_is_recognised() {
+        assert!(is_nonce_too_low("(code: -32000, message: nonce too low, data: None)"));
+        assert!(is_nonce_too_low("Nonce too low: next nonce 9, tx nonce 7"));
+        assert!(!is_nonce_too_low("insufficient funds for gas * price + value"));
+    }
+
+    #[test]
+    fn test_clients_of_a_wallet_share_its_manager() {
+        let (alice, bob) = (Address::repeat_byte(0xa1), Address::repeat_byte(0xb0));
+        assert!(Arc::ptr_eq(&NonceManager::shared(alice), &NonceManager::shared(alice)));
+        assert!(!Arc::ptr_eq(&NonceManager::shared(alice), &NonceManager::shared(bob)));
+    }
+}
@@ -0,0 +1,87 @@
+use crate::db::{self, DbError, Notification};
+use crate::timezone::UserTimezone;
+use sqlx::{Pool, Postgres};
+
+/// Notification kind for price alerts fired by the daemon
+pub const KIND_PRICE_ALERT: &str = "price_alert";
+
+/// Notification kind for stablecoins drifting from or returning to their peg
+pub const KIND_DEPEG: &str = "depeg";
+
+/// Record an event for a user so the chat can surface it on next start
+pub async fn notify(pool: &Pool<Postgres>, user_id: i32, kind: &str, message: &str) -> Result<Notification, DbError> {
+    db::create_notification(pool, user_id, kind, message).await
+}
+
+/// Fetch the unread notifications for a user and mark them as read
+pub async fn take_unread(pool: &Pool<Postgres>, user_id: i32) -> Result<Vec<Notification>, DbError> {
+    let notifications = db::get_unread_notifications(pool, user_id).await?;
+    if !notifications.is_empty() {
+        let ids: Vec<i32> = notifications.iter().map(|n| n.id).collect();
+        db::mark_notifications_read(pool, user_id, &ids).await?;
+    }
+
+    Ok(notifications)
+}
+
+/// Render the "while you were away" summary shown when the chat starts, stamped in the user's timezone
+/// Returns None when there is nothing to report
+pub fn render_while_away(notifications: &[Notification], timezone: UserTimezone) -> Option<String> {
+    if notifications.is_empty() {
+        return None;
+    }
+
+    let mut output = String::from("While you were away:\n");
+    for notification in notifications {
+        output.push_str(&format!(
+            "- [{}] {}\n",
+            timezone.local(notification.created_at).format("%Y-%m-%d %H:%M"),
+            notification.message
+        ));
+    }
+
+    Some(output)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::NaiveDate;
+
+    #[test]
+    fn test_render_while_away() {
+        assert!(render_while_away(&[], UserTimezone::default()).is_none());
+
+        let notifications = vec![Notification {
+            id: 1,
+            user_id: 1,
+            kind: KIND_PRICE_ALERT.to_string(),
+            message: "bitcoin moved +6.0% to $64000.00".to_string(),
+            created_at: NaiveDate::from_ymd_opt(2025, 9, 21).unwrap().and_hms_opt(8, 30, 0).unwrap(),
+            read_at: None,
+        }];
+
+        let output = render_while_away(&notifications, UserTimezone::default()).unwrap();
+        assert!(output.starts_with("While you were away:\n"));
+        assert!(output.contains("- [2025-09-21 08:30] bitcoin moved +6.0% to $64000.00"));
+
+        let sydney = render_while_away(&notifications, UserTimezone::Named(chrono_tz::Australia::Sydney)).unwrap();
+        assert!(sydney.contains("- [2025-09-21 18:30] bitcoin moved"));
+    }
+
+    #[tokio::test]
+    async fn test_notifications_are_read_once() {
+        let Some(pool) = db::testing::test_pool().await else {
+            return;
+        };
+
+        notify(&pool, 1, KIND_PRICE_ALERT, "first").await.unwrap();
+        notify(&pool, 1, KIND_PRICE_ALERT, "second").await.unwrap();
+
+        let unread = take_unread(&pool, 1).await.unwrap();
+        let messages: Vec<&str> = unread.iter().map(|n| n.message.as_str()).collect();
+        assert_eq!(messages, vec!["first", "second"]);
+
+        assert!(take_unread(&pool, 1).await.unwrap().is_empty());
+    }
+}
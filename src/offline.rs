@@ -0,0 +1,136 @@
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+use tracing::{info, warn};
+
+/// How long a connect error keeps the application offline before requests are tried again
+pub const AUTO_OFFLINE_DURATION: Duration = Duration::from_secs(60);
+
+// Offline mode asked for with --offline or /offline on, kept until it's turned off
+static OFFLINE: AtomicBool = AtomicBool::new(false);
+
+// End of the offline mode a connect error started, None while online
+static AUTO_OFFLINE_UNTIL: Mutex<Option<Instant>> = Mutex::new(None);
+
+/// Put the whole application into offline mode
+/// Anthropic, Exa and CoinGecko calls are skipped until `disable` is called
+pub fn enable() {
+    if !OFFLINE.swap(true, Ordering::SeqCst) {
+        warn!("Offline mode enabled: network calls to Anthropic, Exa and CoinGecko are disabled");
+    }
+}
+
+/// Leave offline mode, whether it was asked for or started by a connect error
+pub fn disable() {
+    OFFLINE.store(false, Ordering::SeqCst);
+    set_auto_offline_until(None);
+}
+
+/// Check whether the application is running in offline mode
+pub fn is_offline() -> bool {
+    is_forced() || auto_offline_until().is_some_and(|until| Instant::now() < until)
+}
+
+/// Check whether offline mode was asked for, rather than started by a connect error
+pub fn is_forced() -> bool {
+    OFFLINE.load(Ordering::SeqCst)
+}
+
+/// Inspect a failed HTTP request and go offline for `AUTO_OFFLINE_DURATION` on connect errors
+/// Returns true if the error caused (or confirmed) offline mode
+pub fn note_network_error(error: &reqwest::Error) -> bool {
+    if error.is_connect() {
+        go_offline_for(AUTO_OFFLINE_DURATION);
+        return true;
+    }
+
+    is_offline()
+}
+
+/// Record a request that got a response, ending offline mode started by a connect error
+pub fn note_network_success() {
+    if auto_offline_until().is_some() {
+        set_auto_offline_until(None);
+        if !is_forced() {
+            info!("Network reachable again, leaving offline mode");
+        }
+    }
+}
+
+fn go_offline_for(duration: Duration) {
+    if !is_offline() {
+        warn!("Network unreachable: offline mode for the next {} seconds", duration.as_secs());
+    }
+    set_auto_offline_until(Some(Instant::now() + duration));
+}
+
+fn auto_offline_until() -> Option<Instant> {
+    *AUTO_OFFLINE_UNTIL.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+fn set_auto_offline_until(until: Option<Instant>) {
+    *AUTO_OFFLINE_UNTIL.lock().unwrap_or_else(|poisoned| poisoned.into_inner()) = until;
+}
+
+/// Serializes tests that flip the global offline switch
+#[cfg(test)]
+pub(crate) static TEST_LOCK: tokio::sync::Mutex<()> = tokio::sync::Mutex::const_new(());
+
+/// Issue a request to an unroutable address and return the resulting error
+#[cfg(test)]
+pub(crate) async fn unroutable_request_error() -> reqwest::Error {
+    // TEST-NET-1 is reserved for documentation and never routed
+    let client = reqwest::Client::builder()
+        .connect_timeout(std::time::Duration::from_millis(200))
+        .build()
+        .unwrap();
+    client.get("http://192.0.2.1:9/ping").send().await.unwrap_err()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_connect_error_enables_offline_mode() {
+        let _guard = TEST_LOCK.lock().await;
+        disable();
+
+        let error = unroutable_request_error().await;
+        assert!(note_network_error(&error));
+        assert!(is_offline());
+        assert!(!is_forced());
+
+        disable();
+        assert!(!is_offline());
+    }
+
+    #[tokio::test]
+    async fn test_comes_back_online_after_a_connect_error() {
+        let _guard = TEST_LOCK.lock().await;
+        disable();
+
+        let error = unroutable_request_error().await;
+        assert!(note_network_error(&error));
+        note_network_success();
+        assert!(!is_offline());
+
+        go_offline_for(Duration::ZERO);
+        assert!(!is_offline());
+
+        disable();
+    }
+
+    #[tokio::test]
+    async fn test_asked_for_offline_mode_stays_on() {
+        let _guard = TEST_LOCK.lock().await;
+        disable();
+
+        enable();
+        note_network_success();
+        assert!(is_offline());
+
+        disable();
+        assert!(!is_offline());
+    }
+}
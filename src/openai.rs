@@ -0,0 +1,274 @@
+use async_trait::async_trait;
+use reqwest::Client;
+use std::time::Duration;
+use crate::config::Config;
+use crate::db::MessageRole;
+use crate::llm::{self, CallOptions, ChatModel, Completion, LlmError, LlmProvider, Message, TokenUsage};
+use crate::offline;
+
+/// Default root URL of the OpenAI API
+pub const OPENAI_BASE_URL: &str = "https://api.openai.com/v1";
+
+/// Default root URL of a local Ollama server's OpenAI-compatible API
+pub const OLLAMA_BASE_URL: &str = "http://localhost:11434/v1";
+
+/// Model used on OpenAI when LLM_MODEL doesn't name another
+pub const OPENAI_MODEL: &str = "gpt-4o-mini";
+
+/// Model used on Ollama when LLM_MODEL doesn't name another
+pub const OLLAMA_MODEL: &str = "llama3.1";
+
+/// Most stop sequences the chat completions API accepts
+const MAX_STOP_SEQUENCES: usize = 4;
+
+/// Client for OpenAI's chat completions endpoint and servers compatible with it (Ollama, vLLM)
+pub struct OpenAiClient {
+    client: Client,
+    base_url: String,
+    /// Local servers usually run without one
+    api_key: Option<String>,
+    model: String,
+    supports_tools: bool,
+}
+
+impl OpenAiClient {
+    /// Create a client for the server at `base_url`, e.g. `OPENAI_BASE_URL`
+    ///
+    /// Native tool calling is assumed on OpenAI itself only, local models vary.
+    pub fn new(base_url: &str, api_key: Option<&str>) -> Self {
+        let base_url = base_url.trim_end_matches('/').to_string();
+        Self {
+            client: Client::new(),
+            supports_tools: base_url == OPENAI_BASE_URL,
+            base_url,
+            api_key: api_key.filter(|key| !key.is_empty()).map(str::to_string),
+            model: OPENAI_MODEL.to_string(),
+        }
+    }
+
+    /// Create a client from the application config
+    ///
+    /// OpenAI itself needs OPENAI_API_KEY, other servers get the key only when one is set.
+    pub fn from_config() -> Result<Self, LlmError> {
+        let config = Config::get_instance().map_err(|_| LlmError::ApiKeyNotFound)?;
+        if config.openai_api_key.is_none() && config.openai_base_url.trim_end_matches('/') == OPENAI_BASE_URL {
+            return Err(LlmError::ApiKeyNotFound);
+        }
+
+        let default_model = match config.llm_provider {
+            LlmProvider::Ollama => OLLAMA_MODEL,
+            _ => OPENAI_MODEL,
+        };
+        Ok(Self::new(&config.openai_base_url, config.openai_api_key.as_deref())
+            .with_model(config.llm_model.as_deref().unwrap_or(default_model)))
+    }
+
+    pub fn with_model(mut self, model: &str) -> Self {
+        if !model.is_empty() {
+            self.model = model.to_string();
+        }
+        self
+    }
+
+    /// Override whether the model takes native tool definitions
+    pub fn with_tools(mut self, supports_tools: bool) -> Self {
+        self.supports_tools = supports_tools;
+        self
+    }
+
+    /// Fail requests that take longer than `timeout`
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.client = Client::builder()
+            .timeout(timeout)
+            .connect_timeout(timeout.min(Duration::from_secs(10)))
+            .build()
+            .unwrap_or_default();
+        self
+    }
+
+    async fn send(&self, request_body: &serde_json::Value) -> Result<reqwest::Response, LlmError> {
+        if offline::is_offline() {
+            return Err(LlmError::Offline);
+        }
+
+        let mut request = self.client
+            .post(format!("{}/chat/completions", self.base_url))
+            .header("content-type", "application/json")
+            .json(request_body);
+        if let Some(api_key) = &self.api_key {
+            request = request.bearer_auth(api_key);
+        }
+        let response = request
+            .send()
+            .await
+            .inspect(|_| offline::note_network_success())
+            .inspect_err(|e| {
+                offline::note_network_error(e);
+            })?;
+
+        llm::check_response(response).await
+    }
+}
+
+#[async_trait]
+impl ChatModel for OpenAiClient {
+    async fn generate(&self, system: &str, messages: &[Message], options: &CallOptions) -> Result<Completion, LlmError> {
+        let response = self.send(&request_body(&self.model, system, messages, options)).await?;
+
+        let body = response.text().await?;
+        let response_json: serde_json::Value = serde_json::from_str(&body)
+            .map_err(|e| LlmError::InvalidResponse(format!("Malformed JSON: {}", e)))?;
+
+        // A null content is an empty reply, callers decide whether to ask again
+        let message = &response_json["choices"][0]["message"];
+        let text = match &message["content"] {
+            serde_json::Value::String(content) => content.clone(),
+            serde_json::Value::Null if message.is_object() => String::new(),
+            _ => return Err(LlmError::InvalidResponse("Failed to extract response text".to_string())),
+        };
+        let request_id = response_json["id"].as_str().map(str::to_string);
+
+        // The API doesn't say which stop sequence matched, only that one did
+        Ok(Completion { text, usage: usage(&response_json["usage"]), stop_sequence: None, request_id })
+    }
+
+    /// Streams the content deltas of the chat completions endpoint, asking for usage in the last chunk
+    async fn generate_streaming(
+        &self,
+        system: &str,
+        messages: &[Message],
+        options: &CallOptions,
+        on_text: &mut (dyn for<'t> FnMut(&'t str) + Send),
+    ) -> Result<Completion, LlmError> {
+        let mut body = request_body(&self.model, system, messages, options);
+        body["stream"] = serde_json::json!(true);
+        body["stream_options"] = serde_json::json!({ "include_usage": true });
+        let response = self.send(&body).await?;
+
+        let mut completion = Completion { text: String::new(), usage: TokenUsage::default(), stop_sequence: None, request_id: None };
+        llm::read_events(response, |payload| {
+            if payload == "[DONE]" {
+                return Ok(false);
+            }
+            let chunk: serde_json::Value = serde_json::from_str(payload)
+                .map_err(|e| LlmError::InvalidResponse(format!("Malformed stream chunk: {}", e)))?;
+            if completion.request_id.is_none() {
+                completion.request_id = chunk["id"].as_str().map(str::to_string);
+            }
+            // The first chunk carries the role with empty content
+            if let Some(text) = chunk["choices"][0]["delta"]["content"].as_str()
+                && !text.is_empty()
+            {
+                completion.text.push_str(text);
+                on_text(text);
+            }
+            if chunk["usage"].is_object() {
+                completion.usage = usage(&chunk["usage"]);
+            }
+            Ok(true)
+        })
+        .await?;
+
+        Ok(completion)
+    }
+
+    fn supports_tools(&self) -> bool {
+        self.supports_tools
+    }
+    
+    fn model_name(&self) -> Option<&str> {
+        Some(&self.model)
+    }
+}
+
+fn usage(usage: &serde_json::Value) -> TokenUsage {
+    let count = |key: &str| usage[key].as_u64().unwrap_or_default() as u32;
+    TokenUsage { input_tokens: count("prompt_tokens"), output_tokens: count("completion_tokens") }
+}
+
+/// Build a chat completions request body
+/// The system prompt and system messages become one leading system message, and tool output is
+/// sent as a user turn since the `tool` role needs the id of a native tool call
+fn request_body(model: &str, system: &str, messages: &[Message], options: &CallOptions) -> serde_json::Value {
+    let mut system_prompt = system.to_string();
+    let mut turns = Vec::with_capacity(messages.len() + 1);
+
+    for message in messages {
+        let role = match message.role {
+            MessageRole::User | MessageRole::Tool => "user",
+            MessageRole::Assistant => "assistant",
+            MessageRole::System => {
+                if !system_prompt.is_empty() {
+                    system_prompt.push_str("\n\n");
+                }
+                system_prompt.push_str(&message.content);
+                continue;
+            }
+        };
+        turns.push(serde_json::json!({
+            "role": role,
+            "content": message.content
+        }));
+    }
+    if !system_prompt.is_empty() {
+        turns.insert(0, serde_json::json!({ "role": "system", "content": system_prompt }));
+    }
+
+    let mut body = serde_json::json!({
+        "model": model,
+        "max_tokens": options.max_tokens,
+        "messages": turns
+    });
+    if !options.stop_sequences.is_empty() {
+        let stop: Vec<&String> = options.stop_sequences.iter().take(MAX_STOP_SEQUENCES).collect();
+        body["stop"] = serde_json::json!(stop);
+    }
+    body
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::llm::END_OF_JSON;
+
+    #[test]
+    fn test_request_body_maps_roles() {
+        let message = |role, content: &str| Message { role, content: content.to_string() };
+        let messages = [
+            message(MessageRole::System, "Answer in one line."),
+            message(MessageRole::User, "Price of AERO?"),
+            message(MessageRole::Tool, "aerodrome-finance: 1.21"),
+            message(MessageRole::Assistant, "About $1.21."),
+        ];
+
+        let body = request_body("llama3.1", "Be brief.", &messages, &CallOptions::new(256));
+        assert_eq!(body["model"], "llama3.1");
+        assert_eq!(body["max_tokens"], 256);
+        assert!(body.get("stop").is_none());
+        assert_eq!(
+            body["messages"],
+            serde_json::json!([
+                { "role": "system", "content": "Be brief.\n\nAnswer in one line." },
+                { "role": "user", "content": "Price of AERO?" },
+                { "role": "user", "content": "aerodrome-finance: 1.21" },
+                { "role": "assistant", "content": "About $1.21." }
+            ])
+        );
+    }
+
+    #[test]
+    fn test_request_body_without_system_prompt() {
+        let messages = [Message { role: MessageRole::User, content: "Classify this".to_string() }];
+        let options = CallOptions::new(512).with_stop_sequence(END_OF_JSON);
+        let body = request_body(OPENAI_MODEL, "", &messages, &options);
+        assert_eq!(body["messages"], serde_json::json!([{ "role": "user", "content": "Classify this" }]));
+        assert_eq!(body["stop"], serde_json::json!(["###END###"]));
+    }
+
+    #[test]
+    fn test_tools_are_assumed_on_openai_only() {
+        assert!(OpenAiClient::new("https://api.openai.com/v1/", Some("sk-test")).supports_tools());
+        assert!(!OpenAiClient::new(OLLAMA_BASE_URL, None).supports_tools());
+        assert!(OpenAiClient::new(OLLAMA_BASE_URL, None).with_tools(true).supports_tools());
+    }
+}
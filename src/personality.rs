@@ -1,6 +1,17 @@
 use serde::{Deserialize, Serialize};
 use std::fs;
 use std::collections::HashMap;
+use thiserror::Error;
+
+/// Errors raised while loading or saving a personality file
+#[derive(Debug, Error)]
+pub enum PersonalityError {
+    #[error("IO error: {0}")]
+    Io(#[from] std::io::Error),
+    
+    #[error("Invalid personality file: {0}")]
+    Parse(#[from] serde_json::Error),
+}
 
 #[derive(Deserialize, Serialize, Debug, Clone)]
 pub struct Personality {
@@ -98,18 +109,18 @@ impl Personality {
     /// Add a new strategy to a category
     pub fn add_strategy(&mut self, category: &str, strategy: String) {
         self.strategies.entry(category.to_string())
-            .or_insert_with(Vec::new)
+            .or_default()
             .push(strategy);
     }
 }
 
-pub fn load_personality(path: &str) -> anyhow::Result<Personality> {
+pub fn load_personality(path: &str) -> Result<Personality, PersonalityError> {
     let data = fs::read_to_string(path)?;
     let persona: Personality = serde_json::from_str(&data)?;
     Ok(persona)
 }
 
-pub fn save_personality(path: &str, personality: &Personality) -> anyhow::Result<()> {
+pub fn save_personality(path: &str, personality: &Personality) -> Result<(), PersonalityError> {
     let data = serde_json::to_string_pretty(personality)?;
     fs::write(path, data)?;
     Ok(())
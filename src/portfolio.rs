@@ -0,0 +1,186 @@
+use crate::investment_chat::known_coin_id;
+use crate::render::Table;
+use std::collections::HashMap;
+
+/// An amount of one token held by the wallet, in whole tokens
+#[derive(Debug, Clone, PartialEq)]
+pub struct TokenBalance {
+    pub symbol: String,
+    pub amount: f64,
+}
+
+/// One token of the valued wallet
+#[derive(Debug, Clone, PartialEq)]
+pub struct PortfolioLine {
+    pub symbol: String,
+    pub amount: f64,
+    /// None when the token has no price
+    pub usd_value: Option<f64>,
+    /// Share of the priced total, None when the token has no price
+    pub pct_of_portfolio: Option<f64>,
+}
+
+/// The wallet's tokens valued at live prices
+#[derive(Debug, Clone, PartialEq)]
+pub struct PortfolioValue {
+    /// Largest value first, unpriced tokens last
+    pub lines: Vec<PortfolioLine>,
+    /// Sum of the priced tokens
+    pub total_usd: f64,
+}
+
+impl PortfolioValue {
+    /// Tokens listed without a value
+    pub fn unpriced(&self) -> impl Iterator<Item = &PortfolioLine> {
+        self.lines.iter().filter(|line| line.usd_value.is_none())
+    }
+}
+
+/// CoinGecko id of a token symbol, wrapped ETH counting as ETH, None for tokens it doesn't know
+pub fn coin_id_for_symbol(symbol: &str) -> Option<&'static str> {
+    match symbol.to_lowercase().as_str() {
+        "weth" => Some("ethereum"),
+        symbol => known_coin_id(symbol),
+    }
+}
+
+/// Value `balances` at `prices` (by CoinGecko id), keeping tokens without a price
+pub fn value_portfolio(balances: &[TokenBalance], prices: &HashMap<String, f64>) -> PortfolioValue {
+    let values: Vec<Option<f64>> = balances
+        .iter()
+        .map(|balance| {
+            let price = coin_id_for_symbol(&balance.symbol).and_then(|coin_id| prices.get(coin_id))?;
+            Some(balance.amount * price)
+        })
+        .collect();
+    let total_usd: f64 = values.iter().flatten().sum();
+
+    let mut lines: Vec<PortfolioLine> = balances
+        .iter()
+        .zip(values)
+        .map(|(balance, usd_value)| PortfolioLine {
+            symbol: balance.symbol.clone(),
+            amount: balance.amount,
+            usd_value,
+            pct_of_portfolio: usd_value.map(|value| if total_usd > 0.0 { value / total_usd * 100.0 } else { 0.0 }),
+        })
+        .collect();
+    lines.sort_by(|a, b| match (a.usd_value, b.usd_value) {
+        (Some(a), Some(b)) => b.total_cmp(&a),
+        (Some(_), None) => std::cmp::Ordering::Less,
+        (None, Some(_)) => std::cmp::Ordering::Greater,
+        (None, None) => a.symbol.cmp(&b.symbol),
+    });
+    PortfolioValue { lines, total_usd }
+}
+
+/// Whether a message asks what the user's wallet or portfolio is worth
+pub fn is_portfolio_value_query(message: &str) -> bool {
+    let message = message.to_lowercase();
+    let about_own_holdings = ["my portfolio", "my wallet", "my holdings", "my bag"].iter().any(|words| message.contains(words));
+    let about_value = ["worth", "value", "valued", "how much"].iter().any(|word| message.contains(word));
+    // "What would my portfolio be worth if..." is a scenario, not a valuation
+    let hypothetical = [" if ", "would", "rebalanc", "diversif"].iter().any(|word| message.contains(word));
+    about_own_holdings && about_value && !hypothetical
+}
+
+/// The valuation as a markdown table followed by the total
+pub fn render_portfolio_value(value: &PortfolioValue) -> String {
+    if value.lines.is_empty() {
+        return "Your wallet doesn't hold any of the tokens I track.".to_string();
+    }
+
+    let mut table = Table::new(["Token", "Amount", "Value", "Share"]);
+    for line in &value.lines {
+        table.push_row([
+            line.symbol.clone(),
+            line.amount.to_string(),
+            line.usd_value.map(|value| format!("${:.2}", value)).unwrap_or_else(|| "n/a".to_string()),
+            line.pct_of_portfolio.map(|pct| format!("{:.1}%", pct)).unwrap_or_else(|| "n/a".to_string()),
+        ]);
+    }
+
+    let mut output = format!("Your wallet:\n{}\n\nTotal: ${:.2}", table.render_markdown(), value.total_usd);
+    let unpriced: Vec<&str> = value.unpriced().map(|line| line.symbol.as_str()).collect();
+    if !unpriced.is_empty() {
+        output.push_str(&format!(" (not counting {}, which have no price)", unpriced.join(", ")));
+    }
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn balance(symbol: &str, amount: f64) -> TokenBalance {
+        TokenBalance { symbol: symbol.to_string(), amount }
+    }
+
+    fn prices() -> HashMap<String, f64> {
+        [("ethereum".to_string(), 3000.0), ("usd-coin".to_string(), 1.0)].into_iter().collect()
+    }
+
+    #[test]
+    fn test_symbols_map_to_coingecko_ids() {
+        assert_eq!(coin_id_for_symbol("WETH"), Some("ethereum"));
+        assert_eq!(coin_id_for_symbol("ETH"), Some("ethereum"));
+        assert_eq!(coin_id_for_symbol("USDC"), Some("usd-coin"));
+        assert_eq!(coin_id_for_symbol("FROG"), None);
+    }
+
+    #[test]
+    fn test_balances_are_valued_and_weighted() {
+        let value = value_portfolio(&[balance("USDC", 1000.0), balance("ETH", 0.5), balance("WETH", 0.5)], &prices());
+        assert_eq!(value.total_usd, 4000.0);
+        let symbols: Vec<&str> = value.lines.iter().map(|line| line.symbol.as_str()).collect();
+        assert_eq!(symbols, ["ETH", "WETH", "USDC"]);
+        assert_eq!(value.lines[0].usd_value, Some(1500.0));
+        let shares: f64 = value.lines.iter().filter_map(|line| line.pct_of_portfolio).sum();
+        assert!((shares - 100.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_unpriced_tokens_are_listed_last_without_a_value() {
+        let value = value_portfolio(&[balance("FROG", 1_000_000.0), balance("USDC", 10.0)], &prices());
+        assert_eq!(value.total_usd, 10.0);
+        assert_eq!(value.lines[0].pct_of_portfolio, Some(100.0));
+        assert_eq!(
+            value.lines[1],
+            PortfolioLine { symbol: "FROG".to_string(), amount: 1_000_000.0, usd_value: None, pct_of_portfolio: None }
+        );
+
+        // A known token CoinGecko didn't price is unpriced too
+        let value = value_portfolio(&[balance("DAI", 5.0)], &prices());
+        assert_eq!(value.lines[0].usd_value, None);
+        assert_eq!(value.total_usd, 0.0);
+    }
+
+    #[test]
+    fn test_render_portfolio_value() {
+        let value = value_portfolio(&[balance("ETH", 0.5), balance("USDC", 1500.0), balance("FROG", 42.0)], &prices());
+        assert_eq!(
+            render_portfolio_value(&value),
+            "Your wallet:\n\
+            | Token | Amount |    Value | Share |\n\
+            |-------|-------:|---------:|------:|\n\
+            | ETH   |    0.5 | $1500.00 | 50.0% |\n\
+            | USDC  |   1500 | $1500.00 | 50.0% |\n\
+            | FROG  |     42 |      n/a |   n/a |\n\
+            \n\
+            Total: $3000.00 (not counting FROG, which have no price)"
+        );
+
+        let empty = PortfolioValue { lines: vec![], total_usd: 0.0 };
+        assert_eq!(render_portfolio_value(&empty), "Your wallet doesn't hold any of the tokens I track.");
+    }
+
+    #[test]
+    fn test_is_portfolio_value_query() {
+        assert!(is_portfolio_value_query("what's my portfolio worth?"));
+        assert!(is_portfolio_value_query("How much is my wallet worth"));
+        assert!(is_portfolio_value_query("value of my holdings"));
+        assert!(!is_portfolio_value_query("what would my portfolio be worth if btc drops 20%"));
+        assert!(!is_portfolio_value_query("is my portfolio diversified"));
+        assert!(!is_portfolio_value_query("what's bitcoin worth"));
+    }
+}
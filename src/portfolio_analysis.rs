@@ -0,0 +1,450 @@
+use crate::price_fetcher::DailyPrice;
+use crate::render::Table;
+use chrono::NaiveDate;
+use std::collections::{BTreeMap, HashMap};
+
+/// Days of daily prices fetched for the analysis
+pub const HISTORY_DAYS: u32 = 90;
+
+/// Fewest daily returns a correlation or volatility is computed from
+pub const MIN_OBSERVATIONS: usize = 10;
+
+/// Crypto trades every day, so daily volatility is annualized over 365 days
+const DAYS_PER_YEAR: f64 = 365.0;
+
+/// A holding to analyze
+#[derive(Debug, Clone, PartialEq)]
+pub struct Position {
+    pub coin_id: String,
+    pub amount: f64,
+}
+
+/// One asset of the analyzed portfolio
+#[derive(Debug, Clone, PartialEq)]
+pub struct AssetStats {
+    pub coin_id: String,
+    /// Valued at the last price of its history
+    pub value_usd: f64,
+    pub weight: f64,
+    /// Annualized volatility of daily returns
+    pub volatility: Option<f64>,
+}
+
+/// Correlation, volatility and concentration of a portfolio
+#[derive(Debug, Clone, PartialEq)]
+pub struct PortfolioAnalysis {
+    /// Largest position first
+    pub assets: Vec<AssetStats>,
+    /// Holdings left out because they have no price history
+    pub missing: Vec<String>,
+    /// Pairwise correlations of daily returns, indexed like `assets`
+    pub correlations: Vec<Vec<Option<f64>>>,
+    /// Daily returns every asset has in common
+    pub observations: usize,
+    /// Annualized volatility of the weighted portfolio
+    pub portfolio_volatility: Option<f64>,
+    /// Weighted average of the assets' own volatilities
+    pub average_volatility: Option<f64>,
+    pub total_value: f64,
+    pub largest_weight: f64,
+    /// 1 / sum of squared weights: how many equally sized positions the portfolio behaves like
+    pub effective_assets: f64,
+}
+
+/// Whether a message asks about the diversification of the user's own holdings
+pub fn is_diversification_query(message: &str) -> bool {
+    let message = message.to_lowercase();
+    let about_diversification = ["diversif", "correlat", "concentrat"].iter().any(|word| message.contains(word));
+    let about_own_holdings = ["portfolio", "holdings", "my ", " i "].iter().any(|word| message.contains(word));
+    about_diversification && about_own_holdings
+}
+
+/// Simple returns between consecutive prices
+pub fn daily_returns(prices: &[f64]) -> Vec<f64> {
+    prices
+        .windows(2)
+        .filter(|pair| pair[0] > 0.0)
+        .map(|pair| pair[1] / pair[0] - 1.0)
+        .collect()
+}
+
+/// Pearson correlation, None for too few observations or a series that doesn't move
+pub fn correlation(a: &[f64], b: &[f64]) -> Option<f64> {
+    if a.len() != b.len() || a.len() < MIN_OBSERVATIONS {
+        return None;
+    }
+
+    let mean_a = mean(a);
+    let mean_b = mean(b);
+    let (mut covariance, mut variance_a, mut variance_b) = (0.0, 0.0, 0.0);
+    for (x, y) in a.iter().zip(b) {
+        covariance += (x - mean_a) * (y - mean_b);
+        variance_a += (x - mean_a).powi(2);
+        variance_b += (y - mean_b).powi(2);
+    }
+
+    if variance_a <= f64::EPSILON || variance_b <= f64::EPSILON {
+        return None;
+    }
+    Some((covariance / (variance_a * variance_b).sqrt()).clamp(-1.0, 1.0))
+}
+
+/// Annualized sample standard deviation of daily returns
+pub fn annualized_volatility(returns: &[f64]) -> Option<f64> {
+    if returns.len() < MIN_OBSERVATIONS {
+        return None;
+    }
+
+    let mean = mean(returns);
+    let variance = returns.iter().map(|r| (r - mean).powi(2)).sum::<f64>() / (returns.len() - 1) as f64;
+    Some(variance.sqrt() * DAYS_PER_YEAR.sqrt())
+}
+
+/// Largest weight and effective number of assets
+pub fn concentration(weights: &[f64]) -> (f64, f64) {
+    let largest = weights.iter().copied().fold(0.0, f64::max);
+    let sum_of_squares: f64 = weights.iter().map(|w| w * w).sum();
+    let effective = if sum_of_squares > 0.0 { 1.0 / sum_of_squares } else { 0.0 };
+    (largest, effective)
+}
+
+/// Prices of several series on the dates they all share, one column per series
+fn aligned_prices(series: &[&[DailyPrice]]) -> Vec<Vec<f64>> {
+    let mut by_date: BTreeMap<NaiveDate, Vec<Option<f64>>> = BTreeMap::new();
+    for (i, prices) in series.iter().enumerate() {
+        for day in prices.iter() {
+            by_date.entry(day.date).or_insert_with(|| vec![None; series.len()])[i] = Some(day.price_usd);
+        }
+    }
+
+    let rows: Vec<Vec<f64>> = by_date.into_values().filter_map(|row| row.into_iter().collect()).collect();
+    (0..series.len()).map(|i| rows.iter().map(|row| row[i]).collect()).collect()
+}
+
+/// Analyze the positions from their daily price history
+///
+/// Correlations use the dates each pair shares, the portfolio volatility uses the
+/// dates every asset shares. Positions without history are listed in `missing`.
+pub fn analyze(positions: &[Position], history: &HashMap<String, Vec<DailyPrice>>) -> PortfolioAnalysis {
+    let mut missing = Vec::new();
+    let mut priced: Vec<(&Position, &[DailyPrice], f64)> = Vec::new();
+    for position in positions {
+        match history.get(&position.coin_id).and_then(|series| Some((series, series.last()?))) {
+            Some((series, last)) => priced.push((position, series, position.amount * last.price_usd)),
+            None => missing.push(position.coin_id.clone()),
+        }
+    }
+    priced.sort_by(|a, b| b.2.total_cmp(&a.2).then_with(|| a.0.coin_id.cmp(&b.0.coin_id)));
+
+    let total_value: f64 = priced.iter().map(|(_, _, value)| value).sum();
+    let weights: Vec<f64> = priced
+        .iter()
+        .map(|(_, _, value)| if total_value > 0.0 { value / total_value } else { 0.0 })
+        .collect();
+
+    let assets: Vec<AssetStats> = priced
+        .iter()
+        .zip(&weights)
+        .map(|((position, series, value), weight)| {
+            let prices: Vec<f64> = series.iter().map(|day| day.price_usd).collect();
+            AssetStats {
+                coin_id: position.coin_id.clone(),
+                value_usd: *value,
+                weight: *weight,
+                volatility: annualized_volatility(&daily_returns(&prices)),
+            }
+        })
+        .collect();
+
+    let correlations = (0..priced.len())
+        .map(|i| {
+            (0..priced.len())
+                .map(|j| {
+                    let pair = aligned_prices(&[priced[i].1, priced[j].1]);
+                    correlation(&daily_returns(&pair[0]), &daily_returns(&pair[1]))
+                })
+                .collect()
+        })
+        .collect();
+
+    // The portfolio's own daily returns over the dates every asset shares
+    let all: Vec<&[DailyPrice]> = priced.iter().map(|(_, series, _)| *series).collect();
+    let returns: Vec<Vec<f64>> = aligned_prices(&all).iter().map(|prices| daily_returns(prices)).collect();
+    let observations = returns.iter().map(Vec::len).min().unwrap_or(0);
+    let portfolio_returns: Vec<f64> = (0..observations)
+        .map(|t| returns.iter().zip(&weights).map(|(asset, weight)| asset[t] * weight).sum())
+        .collect();
+    let portfolio_volatility = if assets.is_empty() { None } else { annualized_volatility(&portfolio_returns) };
+
+    let average_volatility = assets
+        .iter()
+        .map(|asset| asset.volatility.map(|volatility| volatility * asset.weight))
+        .sum::<Option<f64>>()
+        .filter(|_| !assets.is_empty());
+
+    let (largest_weight, effective_assets) = concentration(&weights);
+
+    PortfolioAnalysis {
+        assets,
+        missing,
+        correlations,
+        observations,
+        portfolio_volatility,
+        average_volatility,
+        total_value,
+        largest_weight,
+        effective_assets,
+    }
+}
+
+/// Render the analysis as a summary with a correlation table
+pub fn render_analysis(analysis: &PortfolioAnalysis) -> String {
+    if analysis.assets.is_empty() {
+        let mut output = "I couldn't get price history for any of your holdings, so I can't analyze the portfolio.".to_string();
+        if !analysis.missing.is_empty() {
+            output.push_str(&format!(" Missing: {}.", analysis.missing.join(", ")));
+        }
+        return output;
+    }
+
+    let largest = &analysis.assets[0];
+    let mut output = format!(
+        "Portfolio value: ${:.2} across {} asset(s)\n\
+        Concentration: largest position {} at {:.1}%, effective number of assets {:.1}\n",
+        analysis.total_value,
+        analysis.assets.len(),
+        largest.coin_id,
+        analysis.largest_weight * 100.0,
+        analysis.effective_assets
+    );
+
+    match (analysis.portfolio_volatility, analysis.average_volatility) {
+        (Some(portfolio), Some(average)) => output.push_str(&format!(
+            "Volatility (annualized, {} shared daily returns): portfolio {:.1}%, weighted average of the assets {:.1}%\n",
+            analysis.observations,
+            portfolio * 100.0,
+            average * 100.0
+        )),
+        (Some(portfolio), None) => output.push_str(&format!(
+            "Volatility (annualized, {} shared daily returns): portfolio {:.1}%\n",
+            analysis.observations,
+            portfolio * 100.0
+        )),
+        (None, _) => output.push_str(&format!(
+            "Volatility: not enough shared price history ({} daily returns, need {})\n",
+            analysis.observations, MIN_OBSERVATIONS
+        )),
+    }
+
+    let mut positions = Table::new(["Coin", "Value", "Weight", "Volatility"]);
+    for asset in &analysis.assets {
+        let volatility = asset
+            .volatility
+            .map(|volatility| format!("{:.1}%", volatility * 100.0))
+            .unwrap_or_else(|| "n/a".to_string());
+        positions.push_row([
+            asset.coin_id.clone(),
+            format!("${:.2}", asset.value_usd),
+            format!("{:.1}%", asset.weight * 100.0),
+            volatility,
+        ]);
+    }
+    output.push_str("\nPositions:\n");
+    output.push_str(&positions.render_markdown());
+    output.push('\n');
+
+    if analysis.assets.len() > 1 {
+        output.push_str("\nCorrelation of daily returns:\n");
+        output.push_str(&correlation_table(analysis));
+    }
+
+    if !analysis.missing.is_empty() {
+        output.push_str(&format!("\nLeft out, no price history: {}\n", analysis.missing.join(", ")));
+    }
+
+    output.trim_end().to_string()
+}
+
+fn correlation_table(analysis: &PortfolioAnalysis) -> String {
+    let names: Vec<&str> = analysis.assets.iter().map(|asset| asset.coin_id.as_str()).collect();
+    let mut table = Table::new(std::iter::once("").chain(names.iter().copied()));
+
+    for (name, row) in names.iter().zip(&analysis.correlations) {
+        // Keep rounding noise from showing up as -0.00
+        let cells = row.iter().map(|value| {
+            value
+                .map(|c| format!("{:.2}", if c.abs() < 0.005 { 0.0 } else { c }))
+                .unwrap_or_else(|| "n/a".to_string())
+        });
+        table.push_row(std::iter::once(name.to_string()).chain(cells));
+    }
+
+    table.render_markdown() + "\n"
+}
+
+fn mean(values: &[f64]) -> f64 {
+    values.iter().sum::<f64>() / values.len() as f64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn series(prices: &[f64]) -> Vec<DailyPrice> {
+        let start = NaiveDate::from_ymd_opt(2025, 6, 1).unwrap();
+        prices
+            .iter()
+            .enumerate()
+            .map(|(i, price)| DailyPrice { date: start + chrono::Duration::days(i as i64), price_usd: *price })
+            .collect()
+    }
+
+    /// Prices following the given daily returns from 100
+    fn from_returns(returns: &[f64]) -> Vec<f64> {
+        let mut prices = vec![100.0];
+        for r in returns {
+            prices.push(prices.last().unwrap() * (1.0 + r));
+        }
+        prices
+    }
+
+    fn position(coin_id: &str, amount: f64) -> Position {
+        Position { coin_id: coin_id.to_string(), amount }
+    }
+
+    // Alternating up/down days and a pattern that is uncorrelated with it
+    const ZIGZAG: [f64; 12] = [0.02, -0.02, 0.02, -0.02, 0.02, -0.02, 0.02, -0.02, 0.02, -0.02, 0.02, -0.02];
+    const INDEPENDENT: [f64; 12] = [0.01, 0.01, -0.01, -0.01, 0.01, 0.01, -0.01, -0.01, 0.01, 0.01, -0.01, -0.01];
+
+    fn close(a: f64, b: f64) -> bool {
+        (a - b).abs() < 1e-9
+    }
+
+    #[test]
+    fn test_correlation_of_known_series() {
+        let doubled: Vec<f64> = ZIGZAG.iter().map(|r| r * 2.0).collect();
+        let opposite: Vec<f64> = ZIGZAG.iter().map(|r| -r).collect();
+
+        assert!(close(correlation(&ZIGZAG, &doubled).unwrap(), 1.0));
+        assert!(close(correlation(&ZIGZAG, &opposite).unwrap(), -1.0));
+        assert!(close(correlation(&ZIGZAG, &INDEPENDENT).unwrap(), 0.0));
+    }
+
+    #[test]
+    fn test_short_or_flat_series_have_no_statistics() {
+        assert_eq!(correlation(&ZIGZAG[..5], &INDEPENDENT[..5]), None);
+        assert_eq!(correlation(&ZIGZAG, &[0.0; 12]), None);
+        assert_eq!(correlation(&ZIGZAG, &INDEPENDENT[..11]), None);
+        assert_eq!(annualized_volatility(&ZIGZAG[..MIN_OBSERVATIONS - 1]), None);
+        assert!(close(annualized_volatility(&[0.0; 12]).unwrap(), 0.0));
+    }
+
+    #[test]
+    fn test_volatility_and_returns() {
+        let returns = daily_returns(&[100.0, 110.0, 99.0]);
+        assert!(returns.len() == 2 && close(returns[0], 0.1) && close(returns[1], -0.1));
+        assert!(daily_returns(&[100.0]).is_empty());
+
+        // Sample standard deviation of +-2% alternating over 12 days
+        let expected = (0.0004_f64 * 12.0 / 11.0).sqrt() * 365.0_f64.sqrt();
+        assert!(close(annualized_volatility(&ZIGZAG).unwrap(), expected));
+    }
+
+    #[test]
+    fn test_concentration() {
+        let (largest, effective) = concentration(&[0.25, 0.25, 0.25, 0.25]);
+        assert!(close(largest, 0.25) && close(effective, 4.0));
+
+        let (largest, effective) = concentration(&[1.0]);
+        assert!(close(largest, 1.0) && close(effective, 1.0));
+
+        assert_eq!(concentration(&[]), (0.0, 0.0));
+    }
+
+    #[test]
+    fn test_analyze_correlated_and_independent_holdings() {
+        let history = HashMap::from([
+            ("bitcoin".to_string(), series(&from_returns(&ZIGZAG))),
+            ("wrapped-bitcoin".to_string(), series(&from_returns(&ZIGZAG))),
+            ("gold-token".to_string(), series(&from_returns(&INDEPENDENT))),
+        ]);
+        let positions = vec![position("gold-token", 1.0), position("bitcoin", 2.0), position("wrapped-bitcoin", 1.0)];
+
+        let analysis = analyze(&positions, &history);
+        let names: Vec<&str> = analysis.assets.iter().map(|a| a.coin_id.as_str()).collect();
+        assert_eq!(names, vec!["bitcoin", "gold-token", "wrapped-bitcoin"]);
+        assert_eq!(analysis.observations, 12);
+        assert!(analysis.missing.is_empty());
+
+        assert!(close(analysis.correlations[0][2].unwrap(), 1.0));
+        assert!(close(analysis.correlations[0][1].unwrap(), 0.0));
+        assert!(close(analysis.correlations[1][1].unwrap(), 1.0));
+
+        // Mixing in an uncorrelated asset brings the portfolio below the average volatility
+        assert!(analysis.portfolio_volatility.unwrap() < analysis.average_volatility.unwrap());
+        assert!(close(analysis.assets.iter().map(|a| a.weight).sum::<f64>(), 1.0));
+        assert!(analysis.effective_assets > 1.0 && analysis.effective_assets < 3.0);
+    }
+
+    #[test]
+    fn test_analyze_short_and_missing_data() {
+        let mut short = series(&from_returns(&ZIGZAG));
+        short.truncate(4);
+        let history = HashMap::from([
+            ("bitcoin".to_string(), series(&from_returns(&ZIGZAG))),
+            ("new-coin".to_string(), short),
+            ("empty".to_string(), Vec::new()),
+        ]);
+        let positions = vec![position("bitcoin", 2.0), position("new-coin", 1.0), position("obscure", 5.0), position("empty", 1.0)];
+
+        let analysis = analyze(&positions, &history);
+        assert_eq!(analysis.missing, vec!["obscure".to_string(), "empty".to_string()]);
+        assert_eq!(analysis.assets.len(), 2);
+        assert_eq!(analysis.correlations[0][1], None);
+        assert_eq!(analysis.observations, 3);
+        assert_eq!(analysis.portfolio_volatility, None);
+        assert_eq!(analysis.average_volatility, None);
+        assert!(analysis.assets[0].volatility.is_some());
+
+        let output = render_analysis(&analysis);
+        assert!(output.contains("Volatility: not enough shared price history (3 daily returns, need 10)"));
+        assert!(output.contains("% |        n/a |"));
+        assert!(output.ends_with("Left out, no price history: obscure, empty"));
+
+        let nothing = analyze(&[position("obscure", 1.0)], &HashMap::new());
+        assert_eq!(
+            render_analysis(&nothing),
+            "I couldn't get price history for any of your holdings, so I can't analyze the portfolio. Missing: obscure."
+        );
+    }
+
+    #[test]
+    fn test_render_analysis() {
+        let history = HashMap::from([
+            ("bitcoin".to_string(), series(&from_returns(&ZIGZAG))),
+            ("eth".to_string(), series(&from_returns(&INDEPENDENT))),
+        ]);
+        let analysis = analyze(&[position("bitcoin", 3.0), position("eth", 1.0)], &history);
+        let output = render_analysis(&analysis);
+
+        assert!(output.starts_with("Portfolio value: $"));
+        assert!(output.contains("Concentration: largest position bitcoin at 75."));
+        assert!(output.contains("Volatility (annualized, 12 shared daily returns): portfolio "));
+        assert!(output.contains(
+            "Correlation of daily returns:\n\
+            |         | bitcoin |  eth |\n\
+            |---------|--------:|-----:|\n\
+            | bitcoin |    1.00 | 0.00 |\n\
+            | eth     |    0.00 | 1.00 |"
+        ));
+        assert!(output.contains("Positions:\n| Coin    |"));
+    }
+
+    #[test]
+    fn test_is_diversification_query() {
+        assert!(is_diversification_query("is my portfolio diversified?"));
+        assert!(is_diversification_query("How correlated are my holdings"));
+        assert!(!is_diversification_query("what is diversification"));
+        assert!(!is_diversification_query("show my portfolio"));
+    }
+}
@@ -0,0 +1,344 @@
+use crate::fiat::{self, FiatAmount};
+use regex::Regex;
+use std::sync::OnceLock;
+use thiserror::Error;
+
+/// CoinGecko IDs of holdings that count as balance available to buy with
+pub const STABLECOIN_IDS: &[&str] = &["usd-coin", "tether", "dai", "binance-usd", "true-usd", "frax", "paypal-usd"];
+
+/// Position sizing error types
+#[derive(Debug, Error, PartialEq)]
+pub enum SizingError {
+    #[error("Stop ({stop}) must be below the entry price ({entry}) for a long position")]
+    InvalidStopDistance { entry: f64, stop: f64 },
+
+    #[error("Risk per trade must be between 0% and 100%, got {0}%")]
+    InvalidRisk(f64),
+
+    #[error("Account size must be a positive amount, got {0}")]
+    InvalidAccountSize(f64),
+
+    #[error("Entry price must be a positive amount, got {0}")]
+    InvalidEntry(f64),
+}
+
+/// Size of a long position that loses the risked amount if the stop is hit
+#[derive(Debug, Clone, PartialEq)]
+pub struct PositionSize {
+    pub account_size: f64,
+    pub risk_percent: f64,
+    /// Account size times risk per trade: what hitting the stop costs
+    pub risk_amount: f64,
+    pub entry: f64,
+    pub stop: f64,
+    pub stop_distance: f64,
+    /// Units of the coin to buy
+    pub quantity: f64,
+    /// Quantity times entry price, in USD
+    pub notional: f64,
+}
+
+/// What the position is checked against before it's recommended
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SizingLimits {
+    /// USD available to buy with
+    pub available_balance: Option<f64>,
+    /// Largest single trade allowed by the risk settings
+    pub max_trade_usd: Option<f64>,
+}
+
+/// A position sizing question parsed from a chat message
+#[derive(Debug, Clone, PartialEq)]
+pub struct SizingQuery {
+    pub coin: String,
+    pub risk_percent: f64,
+    pub stop: f64,
+    /// Entry given in the message; the live price is used otherwise
+    pub entry: Option<f64>,
+    /// Account size given in the message, in any currency; the portfolio value is used otherwise
+    pub account_size: Option<FiatAmount>,
+}
+
+/// Size a long position: quantity = account size * risk % / (entry - stop)
+pub fn size_position(account_size: f64, risk_percent: f64, entry: f64, stop: f64) -> Result<PositionSize, SizingError> {
+    if !account_size.is_finite() || account_size <= 0.0 {
+        return Err(SizingError::InvalidAccountSize(account_size));
+    }
+    if !risk_percent.is_finite() || risk_percent <= 0.0 || risk_percent > 100.0 {
+        return Err(SizingError::InvalidRisk(risk_percent));
+    }
+    if !entry.is_finite() || entry <= 0.0 {
+        return Err(SizingError::InvalidEntry(entry));
+    }
+    let stop_distance = entry - stop;
+    if !stop_distance.is_finite() || stop_distance <= 0.0 {
+        return Err(SizingError::InvalidStopDistance { entry, stop });
+    }
+
+    let risk_amount = account_size * risk_percent / 100.0;
+    let quantity = risk_amount / stop_distance;
+
+    Ok(PositionSize {
+        account_size,
+        risk_percent,
+        risk_amount,
+        entry,
+        stop,
+        stop_distance,
+        quantity,
+        notional: quantity * entry,
+    })
+}
+
+/// Warnings for a position the user can't or shouldn't take in full
+pub fn limit_warnings(size: &PositionSize, limits: &SizingLimits) -> Vec<String> {
+    let mut warnings = Vec::new();
+
+    if let Some(available) = limits.available_balance
+        && size.notional > available
+    {
+        warnings.push(format!(
+            "The position (${:.2}) exceeds your available balance of ${:.2}; buying only ${:.2} keeps the risk below {}%.",
+            size.notional, available, available, size.risk_percent
+        ));
+    }
+    if let Some(max_trade) = limits.max_trade_usd
+        && size.notional > max_trade
+    {
+        warnings.push(format!(
+            "The position (${:.2}) exceeds your configured max trade size of ${:.2}.",
+            size.notional, max_trade
+        ));
+    }
+    if size.notional > size.account_size {
+        warnings.push(format!(
+            "The position is {:.1}x your account size, which needs leverage: the stop is only {:.2}% below entry.",
+            size.notional / size.account_size,
+            size.stop_distance / size.entry * 100.0
+        ));
+    }
+
+    warnings
+}
+
+fn coin_regex() -> &'static Regex {
+    static REGEX: OnceLock<Regex> = OnceLock::new();
+    REGEX.get_or_init(|| Regex::new(r"(?i)\bhow\s+(?:much|many)\s+([a-z][a-z0-9-]*)\s+(?:should|can|could|do|would)\s+i\s+buy\b").unwrap())
+}
+
+fn risk_regex() -> &'static Regex {
+    static REGEX: OnceLock<Regex> = OnceLock::new();
+    REGEX.get_or_init(|| Regex::new(r"(?i)\brisk(?:ing)?\s+(\d+(?:\.\d+)?)\s*%").unwrap())
+}
+
+fn stop_regex() -> &'static Regex {
+    static REGEX: OnceLock<Regex> = OnceLock::new();
+    REGEX.get_or_init(|| Regex::new(r"(?i)\bstop(?:[\s-]+loss)?\s+(?:at|of|is)?\s*(-?)\$?(\d[\d,]*(?:\.\d+)?)(k?)\b").unwrap())
+}
+
+fn entry_regex() -> &'static Regex {
+    static REGEX: OnceLock<Regex> = OnceLock::new();
+    REGEX.get_or_init(|| Regex::new(r"(?i)\b(?:entry|enter(?:ing)?|buy(?:ing)?)\s+(?:at|of|is)\s+\$?(\d[\d,]*(?:\.\d+)?)(k?)\b").unwrap())
+}
+
+fn account_regex() -> &'static Regex {
+    static REGEX: OnceLock<Regex> = OnceLock::new();
+    REGEX.get_or_init(|| {
+        let money = fiat::money_pattern();
+        Regex::new(&format!(r"(?i)({money})\s+(?:account|portfolio)\b|\b(?:account|portfolio)(?:\s+size)?\s+(?:of|is)\s+({money})")).unwrap()
+    })
+}
+
+/// Parse "1,500", "2.5" or "10" with an optional "k" suffix
+fn parse_amount(digits: &str, suffix: &str) -> Option<f64> {
+    let value: f64 = digits.replace(',', "").parse().ok()?;
+    Some(if suffix.eq_ignore_ascii_case("k") { value * 1000.0 } else { value })
+}
+
+/// Parse "how much ETH should I buy if I risk 1% with a stop at $1900"
+///
+/// Needs the coin, the risk and the stop; entry and account size are optional
+pub fn parse_sizing_query(message: &str) -> Option<SizingQuery> {
+    let coin = coin_regex().captures(message)?[1].to_string();
+    let risk_percent: f64 = risk_regex().captures(message)?[1].parse().ok()?;
+
+    let stop_captures = stop_regex().captures(message)?;
+    let mut stop = parse_amount(&stop_captures[2], &stop_captures[3])?;
+    if &stop_captures[1] == "-" {
+        stop = -stop;
+    }
+
+    let entry = entry_regex()
+        .captures(message)
+        .and_then(|captures| parse_amount(&captures[1], &captures[2]));
+    let account_size = account_regex()
+        .captures(message)
+        .and_then(|captures| fiat::parse_money(captures.get(1).or(captures.get(2))?.as_str()));
+
+    Some(SizingQuery { coin, risk_percent, stop, entry, account_size })
+}
+
+/// Render the position with the inputs of the formula and any limit warnings
+pub fn render_position(coin: &str, size: &PositionSize, account_source: &str, warnings: &[String]) -> String {
+    let mut output = format!(
+        "Buy about {:.6} {} (${:.2} at ${:.2}).\n\n\
+        Inputs:\n\
+        - Account size: ${:.2} ({})\n\
+        - Risk per trade: {}% = ${:.2}\n\
+        - Entry: ${:.2}\n\
+        - Stop: ${:.2} ({:.2}% below entry, ${:.2} per {})\n\n\
+        Formula: quantity = account size * risk % / (entry - stop) = ${:.2} / ${:.2}\n",
+        size.quantity,
+        coin,
+        size.notional,
+        size.entry,
+        size.account_size,
+        account_source,
+        size.risk_percent,
+        size.risk_amount,
+        size.entry,
+        size.stop,
+        size.stop_distance / size.entry * 100.0,
+        size.stop_distance,
+        coin,
+        size.risk_amount,
+        size.stop_distance
+    );
+
+    if !warnings.is_empty() {
+        output.push('\n');
+        for warning in warnings {
+            output.push_str(&format!("Warning: {}\n", warning));
+        }
+    }
+
+    output.push_str("\nIf the stop fills with slippage the loss will be larger than the risked amount.");
+    output
+}
+
+/// Reply asking for the account size when the portfolio can't provide it
+pub fn account_size_request(query: &SizingQuery) -> String {
+    format!(
+        "To size the position I need your account size, and your portfolio is empty or has no prices. \
+        Ask again with it, e.g. \"how much {} should I buy if I risk {}% with a stop at ${} on a $10k account\".",
+        query.coin, query.risk_percent, query.stop
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn close(a: f64, b: f64) -> bool {
+        (a - b).abs() < 1e-9
+    }
+
+    #[test]
+    fn test_size_position() {
+        let size = size_position(10_000.0, 1.0, 2000.0, 1900.0).unwrap();
+
+        assert!(close(size.risk_amount, 100.0));
+        assert!(close(size.stop_distance, 100.0));
+        assert!(close(size.quantity, 1.0));
+        assert!(close(size.notional, 2000.0));
+    }
+
+    #[test]
+    fn test_tighter_stop_means_bigger_position() {
+        let wide = size_position(10_000.0, 1.0, 100.0, 90.0).unwrap();
+        let tight = size_position(10_000.0, 1.0, 100.0, 95.0).unwrap();
+
+        assert!(close(wide.notional, 1000.0));
+        assert!(close(tight.notional, 2000.0));
+    }
+
+    #[test]
+    fn test_rejects_zero_and_negative_stop_distance() {
+        assert_eq!(
+            size_position(10_000.0, 1.0, 2000.0, 2000.0),
+            Err(SizingError::InvalidStopDistance { entry: 2000.0, stop: 2000.0 })
+        );
+        assert_eq!(
+            size_position(10_000.0, 1.0, 2000.0, 2100.0),
+            Err(SizingError::InvalidStopDistance { entry: 2000.0, stop: 2100.0 })
+        );
+    }
+
+    #[test]
+    fn test_rejects_invalid_inputs() {
+        assert_eq!(size_position(0.0, 1.0, 100.0, 90.0), Err(SizingError::InvalidAccountSize(0.0)));
+        assert_eq!(size_position(1000.0, 0.0, 100.0, 90.0), Err(SizingError::InvalidRisk(0.0)));
+        assert_eq!(size_position(1000.0, 150.0, 100.0, 90.0), Err(SizingError::InvalidRisk(150.0)));
+        assert_eq!(size_position(1000.0, 1.0, -5.0, -10.0), Err(SizingError::InvalidEntry(-5.0)));
+        assert!(size_position(1000.0, 1.0, f64::NAN, 90.0).is_err());
+    }
+
+    #[test]
+    fn test_limit_warnings() {
+        let size = size_position(10_000.0, 2.0, 100.0, 95.0).unwrap();
+        let limits = SizingLimits { available_balance: Some(1500.0), max_trade_usd: Some(3000.0) };
+
+        let warnings = limit_warnings(&size, &limits);
+
+        assert_eq!(warnings.len(), 2);
+        assert!(warnings[0].contains("available balance of $1500.00"));
+        assert!(warnings[1].contains("max trade size of $3000.00"));
+        assert!(limit_warnings(&size, &SizingLimits::default()).is_empty());
+    }
+
+    #[test]
+    fn test_warns_when_position_needs_leverage() {
+        let size = size_position(1000.0, 2.0, 100.0, 99.0).unwrap();
+
+        let warnings = limit_warnings(&size, &SizingLimits::default());
+
+        assert_eq!(warnings.len(), 1);
+        assert!(warnings[0].contains("2.0x your account size"));
+    }
+
+    #[test]
+    fn test_parse_sizing_query() {
+        let query = parse_sizing_query("How much ETH should I buy if I risk 1% with a stop at $1,900?").unwrap();
+        assert_eq!(query, SizingQuery {
+            coin: "ETH".to_string(),
+            risk_percent: 1.0,
+            stop: 1900.0,
+            entry: None,
+            account_size: None,
+        });
+
+        let query = parse_sizing_query("how much sol can I buy risking 0.5% with a stop-loss at 120, entry at 130 on a $25k account").unwrap();
+        assert_eq!(query.risk_percent, 0.5);
+        assert_eq!(query.stop, 120.0);
+        assert_eq!(query.entry, Some(130.0));
+        assert_eq!(query.account_size, Some(FiatAmount { amount: 25_000.0, denomination: fiat::Denomination::Known(fiat::usd()) }));
+
+        let query = parse_sizing_query("how much btc should I buy risking 1% with a stop at 58k on a 10,000 EUR account").unwrap();
+        assert_eq!(query.account_size.unwrap().currency(), Ok(fiat::find_currency("eur").unwrap()));
+        let query = parse_sizing_query("how much btc should I buy risking 1% with a stop at 58k, portfolio of £8k").unwrap();
+        assert_eq!(query.account_size.map(|size| size.amount), Some(8000.0));
+        let query = parse_sizing_query("how much btc should I buy risking 1% with a stop at 58k, account of 5000 XYZ").unwrap();
+        assert!(query.account_size.unwrap().currency().is_err());
+    }
+
+    #[test]
+    fn test_parse_requires_risk_and_stop() {
+        assert_eq!(parse_sizing_query("How much ETH should I buy?"), None);
+        assert_eq!(parse_sizing_query("How much ETH should I buy if I risk 1%?"), None);
+        assert_eq!(parse_sizing_query("What's the price of ETH?"), None);
+    }
+
+    #[test]
+    fn test_render_shows_inputs_and_warnings() {
+        let size = size_position(10_000.0, 1.0, 2000.0, 1900.0).unwrap();
+        let warnings = vec!["too big".to_string()];
+
+        let output = render_position("ETH", &size, "portfolio value", &warnings);
+
+        assert!(output.contains("Buy about 1.000000 ETH ($2000.00 at $2000.00)"));
+        assert!(output.contains("Account size: $10000.00 (portfolio value)"));
+        assert!(output.contains("Risk per trade: 1% = $100.00"));
+        assert!(output.contains("Stop: $1900.00 (5.00% below entry"));
+        assert!(output.contains("Warning: too big"));
+    }
+}
@@ -1,35 +1,93 @@
-use reqwest::{self, Client, StatusCode};
+use reqwest::{self, Client, RequestBuilder, Response, StatusCode};
+use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::fmt;
 use std::time::{Duration, Instant};
-use std::sync::Mutex;
+use std::sync::{Arc, Mutex};
 use once_cell::sync::Lazy;
+use crate::api_budget::BudgetExhausted;
+use crate::circuit_breaker::{self, BreakerSettings, CircuitBreaker, Refused};
+use crate::config::Config;
+use crate::http;
+use crate::offline;
+
+/// Default CoinGecko API base URL
+pub const COINGECKO_BASE_URL: &str = "https://api.coingecko.com/api/v3";
+
+/// CoinGecko API base URL for pro plan keys
+pub const COINGECKO_PRO_BASE_URL: &str = "https://pro-api.coingecko.com/api/v3";
+
+/// Default DefiLlama coins API base URL, used when CoinGecko is unavailable
+pub const DEFILLAMA_BASE_URL: &str = "https://coins.llama.fi";
+
+/// Default exchangerate.host API base URL, used for fiat rates when CoinGecko is unavailable
+pub const EXCHANGE_RATE_BASE_URL: &str = "https://api.exchangerate.host";
 
 // Custom error type for price fetcher
 #[derive(Debug)]
 pub enum PriceError {
     NetworkError(reqwest::Error),
-    RateLimitExceeded,
+    /// Carries the Retry-After delay when CoinGecko sends one
+    RateLimitExceeded(Option<Duration>),
     InvalidResponse(String),
     PriceNotFound(String),
+    Offline,
+    /// Text that looks like a contract address but isn't a valid one
+    InvalidAddress(String),
+    /// A chain CoinGecko token prices aren't looked up on
+    UnknownPlatform(String),
+    /// CoinGecko kept failing, so calls are skipped for a while
+    CircuitOpen,
+    /// Today's CoinGecko budget is used up, calls are skipped until midnight UTC
+    BudgetExhausted(BudgetExhausted),
+    /// The date is older than the history the CoinGecko plan serves
+    OutOfRange { coin_id: String, earliest: chrono::NaiveDate },
+    /// CoinGecko refused the configured API key
+    InvalidApiKey,
 }
 
 impl fmt::Display for PriceError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
             PriceError::NetworkError(e) => write!(f, "Network error: {}", e),
-            PriceError::RateLimitExceeded => write!(f, "CoinGecko API rate limit exceeded"),
+            PriceError::RateLimitExceeded(Some(wait)) => {
+                write!(f, "CoinGecko API rate limit exceeded, retry after {}s", wait.as_secs())
+            },
+            PriceError::RateLimitExceeded(None) => write!(f, "CoinGecko API rate limit exceeded"),
             PriceError::InvalidResponse(msg) => write!(f, "Invalid API response: {}", msg),
             PriceError::PriceNotFound(coin) => write!(f, "Price not found for {}", coin),
+            PriceError::Offline => write!(f, "Price lookups are unavailable in offline mode"),
+            PriceError::InvalidAddress(reason) => write!(f, "Invalid contract address: {}", reason),
+            PriceError::UnknownPlatform(name) => {
+                write!(f, "Unknown platform {}, token prices can be looked up on {}", name, Platform::supported_names())
+            },
+            PriceError::CircuitOpen => write!(f, "CoinGecko calls are paused after repeated failures"),
+            PriceError::BudgetExhausted(exhausted) => write!(f, "CoinGecko calls are paused: {}", exhausted),
+            PriceError::OutOfRange { coin_id, earliest } => {
+                write!(f, "No price data for {} before {} on the current CoinGecko plan", coin_id, earliest)
+            },
+            PriceError::InvalidApiKey => {
+                write!(f, "CoinGecko rejected the API key, check COINGECKO_API_KEY and whether COINGECKO_PRO matches its plan")
+            },
         }
     }
 }
 
 impl std::error::Error for PriceError {}
 
+impl From<Refused> for PriceError {
+    fn from(refused: Refused) -> Self {
+        match refused {
+            Refused::CircuitOpen => PriceError::CircuitOpen,
+            Refused::OverBudget(exhausted) => PriceError::BudgetExhausted(exhausted),
+        }
+    }
+}
+
 impl From<reqwest::Error> for PriceError {
     fn from(err: reqwest::Error) -> Self {
+        crate::offline::note_network_error(&err);
         PriceError::NetworkError(err)
     }
 }
@@ -40,196 +98,1503 @@ pub struct PriceResponse {
     pub coins: HashMap<String, HashMap<String, f64>>,
 }
 
+/// Current USD price of a coin with its change, market cap and volume over the last 24 hours
+///
+/// CoinGecko sends the 24h figures as null for some coins, usually ones listed in the last day.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CoinQuote {
+    pub price_usd: f64,
+    pub change_24h_pct: Option<f64>,
+    pub market_cap: Option<f64>,
+    pub volume_24h: Option<f64>,
+}
+
+/// A current price with how old it is
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CachedPrice {
+    pub price: f64,
+    /// Time since the price was fetched, zero when this call fetched it
+    pub age: Duration,
+    /// Whether the price was reused from the cache instead of fetched
+    pub cached: bool,
+    /// Reused past the cache's TTL because CoinGecko was rate limiting
+    pub stale: bool,
+}
+
+impl CachedPrice {
+    fn fetched(price: f64) -> Self {
+        Self { price, age: Duration::ZERO, cached: false, stale: false }
+    }
+    
+    /// "as of ~1 minute ago" for a reused price, None for one just fetched
+    pub fn as_of(&self) -> Option<String> {
+        if !self.cached {
+            return None;
+        }
+        let minutes = ((self.age.as_secs_f64() / 60.0).round() as u64).max(1);
+        Some(match minutes {
+            1 => "as of ~1 minute ago".to_string(),
+            2..120 => format!("as of ~{} minutes ago", minutes),
+            _ => format!("as of ~{} hours ago", (minutes as f64 / 60.0).round()),
+        })
+    }
+}
+
+/// A coin id with the quote currency, or the date, of its price
+type PriceKey = (String, String);
+
+/// Prices fetched by a client and its clones
+///
+/// Current prices are keyed by coin and quote currency and reused for the TTL, and the last one
+/// is kept past it to answer with while CoinGecko is rate limiting. Historical prices don't change,
+/// so they are kept for as long as the process runs.
+#[derive(Debug, Clone)]
+pub struct PriceCache {
+    ttl: Duration,
+    current: Arc<tokio::sync::RwLock<HashMap<PriceKey, (Instant, f64)>>>,
+    /// The quote each USD price came with, dropped when a bare price replaces it
+    quotes: Arc<tokio::sync::RwLock<HashMap<String, CoinQuote>>>,
+    /// Keyed by coin and dd-mm-yyyy date
+    historical: Arc<tokio::sync::RwLock<HashMap<PriceKey, f64>>>,
+}
+
+impl PriceCache {
+    /// An empty cache reusing current prices for `ttl`, zero to always fetch them
+    pub fn new(ttl: Duration) -> Self {
+        Self {
+            ttl,
+            current: Arc::new(tokio::sync::RwLock::new(HashMap::new())),
+            quotes: Arc::new(tokio::sync::RwLock::new(HashMap::new())),
+            historical: Arc::new(tokio::sync::RwLock::new(HashMap::new())),
+        }
+    }
+    
+    pub fn ttl(&self) -> Duration {
+        self.ttl
+    }
+    
+    /// The price of `coin_id` in `vs_currency` when it was fetched within the TTL
+    pub async fn get(&self, coin_id: &str, vs_currency: &str) -> Option<CachedPrice> {
+        self.get_stale(coin_id, vs_currency).await.filter(|cached| cached.age < self.ttl).map(|cached| CachedPrice {
+            stale: false,
+            ..cached
+        })
+    }
+    
+    /// The last price of `coin_id` in `vs_currency` however old it is
+    pub async fn get_stale(&self, coin_id: &str, vs_currency: &str) -> Option<CachedPrice> {
+        let current = self.current.read().await;
+        let (fetched, price) = current.get(&(coin_id.to_string(), vs_currency.to_string()))?;
+        let age = fetched.elapsed();
+        Some(CachedPrice { price: *price, age, cached: true, stale: age >= self.ttl })
+    }
+    
+    pub async fn insert(&self, coin_id: &str, vs_currency: &str, price: f64) {
+        let key = (coin_id.to_string(), vs_currency.to_string());
+        self.current.write().await.insert(key, (Instant::now(), price));
+        if vs_currency == "usd" {
+            self.quotes.write().await.remove(coin_id);
+        }
+    }
+    
+    /// The last quote of `coin_id` with how old its price is, however old that is
+    pub async fn quote(&self, coin_id: &str) -> Option<(CoinQuote, CachedPrice)> {
+        let cached = self.get_stale(coin_id, "usd").await?;
+        let quote = self.quotes.read().await.get(coin_id).copied()?;
+        Some((quote, cached))
+    }
+    
+    pub async fn insert_quote(&self, coin_id: &str, quote: CoinQuote) {
+        self.insert(coin_id, "usd", quote.price_usd).await;
+        self.quotes.write().await.insert(coin_id.to_string(), quote);
+    }
+    
+    /// The price of `coin_id` on `date` (dd-mm-yyyy) when it was fetched before
+    pub async fn historical(&self, coin_id: &str, date: &str) -> Option<f64> {
+        self.historical.read().await.get(&(coin_id.to_string(), date.to_string())).copied()
+    }
+    
+    pub async fn insert_historical(&self, coin_id: &str, date: &str, price: f64) {
+        self.historical.write().await.insert((coin_id.to_string(), date.to_string()), price);
+    }
+    
+    /// Forget every price, current and historical
+    pub async fn clear(&self) {
+        self.current.write().await.clear();
+        self.quotes.write().await.clear();
+        self.historical.write().await.clear();
+    }
+}
+
+/// `/simple/price` body with 24h changes, which CoinGecko sends as null for some coins
+#[derive(Debug, Deserialize)]
+struct QuoteResponse {
+    #[serde(flatten)]
+    coins: HashMap<String, HashMap<String, Option<f64>>>,
+}
+
+#[derive(Debug, Deserialize)]
+struct HistoricalResponse {
+    /// Missing for dates before the coin was listed
+    market_data: Option<MarketData>,
+}
+
+#[derive(Debug, Deserialize)]
+struct MarketData {
+    current_price: HashMap<String, f64>,
+}
+
+/// A coin returned by the CoinGecko search endpoint
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct CoinMatch {
+    pub id: String,
+    pub name: String,
+    pub symbol: String,
+    pub market_cap_rank: Option<u32>,
+}
+
+#[derive(Debug, Deserialize)]
+struct SearchResponse {
+    coins: Vec<CoinMatch>,
+}
+
+/// A CoinGecko category, from `/coins/categories/list`
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct CoinCategory {
+    /// Slug the markets endpoint filters by, e.g. "real-world-assets-rwa"
+    pub category_id: String,
+    /// Display name, often with an acronym, e.g. "Real World Assets (RWA)"
+    pub name: String,
+}
+
+/// A coin of a category with its market figures, from `/coins/markets`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CategoryCoin {
+    pub id: String,
+    pub symbol: String,
+    pub name: String,
+    pub market_cap_rank: Option<u32>,
+    #[serde(rename = "current_price")]
+    pub price_usd: Option<f64>,
+    #[serde(rename = "market_cap")]
+    pub market_cap_usd: Option<f64>,
+    #[serde(rename = "price_change_percentage_7d_in_currency", default)]
+    pub change_7d_pct: Option<f64>,
+}
+
+/// What a coin is, from CoinGecko's `/coins/{id}`
+#[derive(Debug, Clone, PartialEq)]
+pub struct CoinProfile {
+    pub id: String,
+    pub symbol: String,
+    pub name: String,
+    /// First paragraph of the English description as plain text, empty when there is none
+    pub description: String,
+    pub categories: Vec<String>,
+    pub homepage: Option<String>,
+    pub whitepaper: Option<String>,
+    /// First source repository listed
+    pub repository: Option<String>,
+    pub genesis_date: Option<chrono::NaiveDate>,
+    pub market_cap_rank: Option<u32>,
+    pub circulating_supply: Option<f64>,
+    pub total_supply: Option<f64>,
+    /// None for coins without a supply cap
+    pub max_supply: Option<f64>,
+}
+
+/// `/coins/{id}` body, most fields are null or empty for small coins
+#[derive(Debug, Deserialize)]
+struct CoinResponse {
+    id: String,
+    symbol: String,
+    name: String,
+    #[serde(default)]
+    description: HashMap<String, Option<String>>,
+    #[serde(default)]
+    categories: Vec<Option<String>>,
+    #[serde(default)]
+    links: CoinLinks,
+    genesis_date: Option<String>,
+    market_cap_rank: Option<u32>,
+    market_data: Option<ProfileMarketData>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct CoinLinks {
+    #[serde(default)]
+    homepage: Vec<String>,
+    whitepaper: Option<String>,
+    #[serde(default)]
+    repos_url: HashMap<String, Vec<String>>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ProfileMarketData {
+    market_cap_rank: Option<u32>,
+    circulating_supply: Option<f64>,
+    total_supply: Option<f64>,
+    max_supply: Option<f64>,
+}
+
+impl From<CoinResponse> for CoinProfile {
+    fn from(coin: CoinResponse) -> Self {
+        let non_empty = |link: &str| Some(link.trim().to_string()).filter(|link| !link.is_empty());
+        let market_data = coin.market_data;
+        let mut repositories: Vec<&String> = coin.links.repos_url.values().flatten().collect();
+        // GitHub first, it's where most projects keep their code
+        repositories.sort_by_key(|url| !url.contains("github.com"));
+
+        CoinProfile {
+            description: coin.description.get("en").cloned().flatten().map(|html| clean_description(&html)).unwrap_or_default(),
+            categories: coin.categories.into_iter().flatten().filter(|category| !category.trim().is_empty()).collect(),
+            homepage: coin.links.homepage.iter().find_map(|link| non_empty(link)),
+            whitepaper: coin.links.whitepaper.as_deref().and_then(non_empty),
+            repository: repositories.into_iter().find_map(|link| non_empty(link)),
+            genesis_date: coin.genesis_date.and_then(|date| chrono::NaiveDate::parse_from_str(&date, "%Y-%m-%d").ok()),
+            market_cap_rank: coin.market_cap_rank.or(market_data.as_ref().and_then(|data| data.market_cap_rank)),
+            circulating_supply: market_data.as_ref().and_then(|data| data.circulating_supply),
+            total_supply: market_data.as_ref().and_then(|data| data.total_supply),
+            max_supply: market_data.as_ref().and_then(|data| data.max_supply),
+            id: coin.id,
+            symbol: coin.symbol,
+            name: coin.name,
+        }
+    }
+}
+
+/// First paragraph of a CoinGecko description as plain text
+///
+/// Descriptions are HTML with links and entities, paragraphs separated by blank lines or `<p>` tags.
+pub fn clean_description(html: &str) -> String {
+    static BREAKS: std::sync::OnceLock<regex::Regex> = std::sync::OnceLock::new();
+    static TAGS: std::sync::OnceLock<regex::Regex> = std::sync::OnceLock::new();
+    static PARAGRAPHS: std::sync::OnceLock<regex::Regex> = std::sync::OnceLock::new();
+    let breaks = BREAKS.get_or_init(|| regex::Regex::new(r"(?i)</p\s*>|<p(?:\s[^>]*)?>|(?:<br\s*/?>\s*){2,}").unwrap());
+    let tags = TAGS.get_or_init(|| regex::Regex::new(r"<[^>]*>").unwrap());
+    let paragraphs = PARAGRAPHS.get_or_init(|| regex::Regex::new(r"\n\s*\n").unwrap());
+
+    let text = breaks.replace_all(&html.replace("\r\n", "\n"), "\n\n").into_owned();
+    let text = tags.replace_all(&text, " ");
+    paragraphs
+        .split(&text)
+        .map(|paragraph| decode_entities(&paragraph.split_whitespace().collect::<Vec<_>>().join(" ")))
+        .find(|paragraph| !paragraph.is_empty())
+        .unwrap_or_default()
+        .replace(" ,", ",")
+        .replace(" .", ".")
+}
+
+/// Decode the HTML entities found in descriptions, unknown ones are kept as written
+fn decode_entities(text: &str) -> String {
+    static ENTITY: std::sync::OnceLock<regex::Regex> = std::sync::OnceLock::new();
+    let entity = ENTITY.get_or_init(|| regex::Regex::new(r"&(#[xX][0-9a-fA-F]+|#[0-9]+|[a-zA-Z]+);").unwrap());
+    entity
+        .replace_all(text, |captures: &regex::Captures| {
+            let name = &captures[1];
+            let decoded = match name {
+                "amp" => Some('&'),
+                "lt" => Some('<'),
+                "gt" => Some('>'),
+                "quot" => Some('"'),
+                "apos" => Some('\''),
+                "nbsp" => Some(' '),
+                _ => match name.strip_prefix("#x").or_else(|| name.strip_prefix("#X")) {
+                    Some(hex) => u32::from_str_radix(hex, 16).ok().and_then(char::from_u32),
+                    None => name.strip_prefix('#').and_then(|digits| digits.parse().ok()).and_then(char::from_u32),
+                },
+            };
+            decoded.map(String::from).unwrap_or_else(|| captures[0].to_string())
+        })
+        .into_owned()
+}
+
+/// Closing price of a coin on one day
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DailyPrice {
+    pub date: chrono::NaiveDate,
+    pub price_usd: f64,
+}
+
+/// Closing price of a coin on one day with the USD volume traded that day
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DailyBar {
+    pub date: chrono::NaiveDate,
+    pub price_usd: f64,
+    pub volume_usd: f64,
+}
+
+/// Price of a coin at one moment of an intraday chart
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PricePoint {
+    pub at: chrono::DateTime<chrono::Utc>,
+    pub price_usd: f64,
+}
+
+#[derive(Debug, Deserialize)]
+struct MarketChartResponse {
+    /// [unix milliseconds, price] pairs, oldest first
+    prices: Vec<(f64, f64)>,
+    /// [unix milliseconds, 24h volume] pairs at the same timestamps
+    #[serde(default)]
+    total_volumes: Vec<(f64, f64)>,
+    /// [unix milliseconds, market cap] pairs at the same timestamps
+    #[serde(default)]
+    market_caps: Vec<(f64, f64)>,
+}
+
+/// Spacing of the points `/market_chart` returns
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChartInterval {
+    /// CoinGecko's choice: 5-minutely for one day, hourly up to 90 days, daily beyond
+    Auto,
+    /// One point per UTC day
+    Daily,
+}
+
+/// A coin's USD prices over a window, oldest first, with the volumes and market caps sent along
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct MarketChart {
+    pub prices: Vec<(chrono::DateTime<chrono::Utc>, f64)>,
+    /// 24h volumes at the prices' timestamps, None when CoinGecko sent none
+    pub volumes: Option<Vec<(chrono::DateTime<chrono::Utc>, f64)>>,
+    pub market_caps: Option<Vec<(chrono::DateTime<chrono::Utc>, f64)>>,
+}
+
+/// Days either side of a date searched when `/history` can't serve it
+const RANGE_WINDOW_DAYS: i64 = 3;
+
+/// Lookback of the public CoinGecko plan, used when the error doesn't state it
+const DEFAULT_PLAN_LOOKBACK_DAYS: u32 = 365;
+
+// CoinGecko answers dates past the plan's history with 401 or 403
+fn is_plan_limited(status: StatusCode) -> bool {
+    status == StatusCode::UNAUTHORIZED || status == StatusCode::FORBIDDEN
+}
+
+// Error code 10012, "Your request exceeds the allowed time range"
+fn is_time_range_limit(body: &str) -> bool {
+    body.contains("10012") || body.contains("allowed time range")
+}
+
+/// Header a CoinGecko API key is sent in, pro keys having their own
+pub fn api_key_header(pro: bool) -> &'static str {
+    if pro { "x-cg-pro-api-key" } else { "x-cg-demo-api-key" }
+}
+
+// "limited to querying historical data within the past 365 days"
+fn plan_lookback_days(body: &str) -> u32 {
+    static PAST_DAYS: std::sync::OnceLock<regex::Regex> = std::sync::OnceLock::new();
+    let past_days = PAST_DAYS.get_or_init(|| regex::Regex::new(r"past (\d+) days").unwrap());
+    past_days
+        .captures(body)
+        .and_then(|caps| caps[1].parse().ok())
+        .unwrap_or(DEFAULT_PLAN_LOOKBACK_DAYS)
+}
+
+// The value of the point nearest `target_ms`
+fn closest_point(points: &[(f64, f64)], target_ms: f64) -> Option<f64> {
+    points
+        .iter()
+        .min_by(|a, b| (a.0 - target_ms).abs().total_cmp(&(b.0 - target_ms).abs()))
+        .map(|&(_, value)| value)
+}
+
+// One (date, value) per day, a later point replaces the day's earlier one
+fn timed_points(points: &[(f64, f64)]) -> Result<Vec<(chrono::DateTime<chrono::Utc>, f64)>, PriceError> {
+    points
+        .iter()
+        .map(|&(timestamp_ms, value)| {
+            chrono::DateTime::from_timestamp_millis(timestamp_ms as i64)
+                .map(|at| (at, value))
+                .ok_or_else(|| PriceError::InvalidResponse(format!("Invalid timestamp {}", timestamp_ms)))
+        })
+        .collect()
+}
+
+// A daily chart's last point is the current price, after that day's midnight one: the last point of a day wins
+fn daily_buckets(points: Vec<(chrono::DateTime<chrono::Utc>, f64)>) -> Vec<(chrono::DateTime<chrono::Utc>, f64)> {
+    let mut series: Vec<(chrono::DateTime<chrono::Utc>, f64)> = Vec::with_capacity(points.len());
+    for point in points {
+        match series.last_mut() {
+            Some(last) if last.0.date_naive() == point.0.date_naive() => *last = point,
+            _ => series.push(point),
+        }
+    }
+    series
+}
+
+fn daily_points(points: &[(f64, f64)]) -> Result<Vec<(chrono::NaiveDate, f64)>, PriceError> {
+    let mut series: Vec<(chrono::NaiveDate, f64)> = Vec::with_capacity(points.len());
+    for &(timestamp_ms, value) in points {
+        let date = chrono::DateTime::from_timestamp_millis(timestamp_ms as i64)
+            .ok_or_else(|| PriceError::InvalidResponse(format!("Invalid timestamp {}", timestamp_ms)))?
+            .date_naive();
+        match series.last_mut() {
+            Some(last) if last.0 == date => last.1 = value,
+            _ => series.push((date, value)),
+        }
+    }
+    Ok(series)
+}
+
+#[derive(Debug, Deserialize)]
+struct LlamaPricesResponse {
+    coins: HashMap<String, LlamaPrice>,
+}
+
+#[derive(Debug, Deserialize)]
+struct LlamaPrice {
+    price: f64,
+}
+
+#[derive(Debug, Deserialize)]
+struct ExchangeRatesResponse {
+    rates: HashMap<String, ExchangeRate>,
+}
+
+/// Value of one bitcoin in a currency
+#[derive(Debug, Deserialize)]
+struct ExchangeRate {
+    value: f64,
+    /// "fiat", "crypto" or "commodity"
+    #[serde(rename = "type")]
+    kind: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct LatestRatesResponse {
+    #[serde(default)]
+    rates: HashMap<String, f64>,
+}
+
+/// Chain a token contract lives on, for `/simple/token_price` lookups
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Platform {
+    Base,
+    Ethereum,
+    Arbitrum,
+}
+
+impl Platform {
+    pub const ALL: [Platform; 3] = [Platform::Base, Platform::Ethereum, Platform::Arbitrum];
+
+    /// Asset platform id used in CoinGecko paths
+    pub fn coingecko_id(&self) -> &'static str {
+        match self {
+            Platform::Base => "base",
+            Platform::Ethereum => "ethereum",
+            Platform::Arbitrum => "arbitrum-one",
+        }
+    }
+
+    /// Name shown to the user
+    pub fn display_name(&self) -> &'static str {
+        match self {
+            Platform::Base => "Base",
+            Platform::Ethereum => "Ethereum",
+            Platform::Arbitrum => "Arbitrum",
+        }
+    }
+
+    /// Parse a chain name as users write it, e.g. "base", "eth", "mainnet" or "arbitrum-one"
+    pub fn parse(name: &str) -> Result<Platform, PriceError> {
+        match name.trim().to_lowercase().as_str() {
+            "base" => Ok(Platform::Base),
+            "ethereum" | "eth" | "mainnet" => Ok(Platform::Ethereum),
+            "arbitrum" | "arb" | "arbitrum-one" | "arbitrum one" => Ok(Platform::Arbitrum),
+            other => Err(PriceError::UnknownPlatform(other.to_string())),
+        }
+    }
+
+    fn supported_names() -> String {
+        Platform::ALL.iter().map(|platform| platform.display_name()).collect::<Vec<_>>().join(", ")
+    }
+}
+
+/// Chains people name that token prices aren't looked up on, so "on polygon" is an error rather than ignored
+const UNSUPPORTED_CHAINS: &[&str] = &[
+    "polygon", "matic", "solana", "bsc", "bnb", "optimism", "avalanche", "avax", "fantom", "gnosis", "linea",
+    "zksync", "scroll", "blast", "mantle", "celo", "tron",
+];
+
+/// A 0x-prefixed hex string in a message, as long as a contract address or not
+fn hex_candidate_regex() -> &'static regex::Regex {
+    static REGEX: std::sync::OnceLock<regex::Regex> = std::sync::OnceLock::new();
+    REGEX.get_or_init(|| regex::Regex::new(r"\b0x[0-9a-fA-F]+(?:(?:\.{2,3}|…)[0-9a-fA-F]+)?").unwrap())
+}
+
+/// Find the contract address in a message, lowercased
+///
+/// Returns None when there is nothing address-like, and an error for hex that is shortened
+/// ("0x4200...0006") or has the wrong length.
+pub fn find_contract_address(message: &str) -> Option<Result<String, PriceError>> {
+    let candidate = hex_candidate_regex().find(message)?.as_str();
+    Some(parse_contract_address(candidate))
+}
+
+/// The contract address of a message asking for a token's price
+///
+/// The message has to ask about price or value, or be little more than the address, so
+/// transaction hashes pasted into other questions aren't mistaken for tokens.
+pub fn detect_token_price_query(message: &str) -> Option<Result<String, PriceError>> {
+    static PRICE_WORDS: std::sync::OnceLock<regex::Regex> = std::sync::OnceLock::new();
+    let price_words = PRICE_WORDS.get_or_init(|| {
+        regex::Regex::new(r"(?i)\b(?:price|priced|worth|cost|value|quote|trading at)\b").unwrap()
+    });
+    let found = find_contract_address(message)?;
+    let other_words = hex_candidate_regex().replace(message, "").split_whitespace().count();
+    if price_words.is_match(message) || other_words <= 2 {
+        Some(found)
+    } else {
+        None
+    }
+}
+
+/// Validate a 0x-prefixed, 40 hex digit contract address and lowercase it
+pub fn parse_contract_address(text: &str) -> Result<String, PriceError> {
+    let text = text.trim();
+    let Some(hex) = text.strip_prefix("0x").or_else(|| text.strip_prefix("0X")) else {
+        return Err(PriceError::InvalidAddress(format!("{} doesn't start with 0x", text)));
+    };
+    if hex.contains("..") || hex.contains('…') {
+        return Err(PriceError::InvalidAddress(format!("{} is shortened, paste the full address", text)));
+    }
+    if !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Err(PriceError::InvalidAddress(format!("{} contains characters that aren't hex digits", text)));
+    }
+    if hex.len() != 40 {
+        return Err(PriceError::InvalidAddress(format!("{} has {} hex digits instead of 40", text, hex.len())));
+    }
+    Ok(format!("0x{}", hex.to_lowercase()))
+}
+
+/// Chain named in a message ("on arbitrum", "ethereum mainnet"), or `default` when none is
+///
+/// A chain that isn't supported, e.g. "on polygon", is an error instead of a silent fallback.
+pub fn infer_platform(message: &str, default: Platform) -> Result<Platform, PriceError> {
+    static WORD: std::sync::OnceLock<regex::Regex> = std::sync::OnceLock::new();
+    let word = WORD.get_or_init(|| regex::Regex::new(r"[a-z][a-z0-9-]*").unwrap());
+
+    let lower = message.to_lowercase();
+    for candidate in word.find_iter(&lower).map(|m| m.as_str()) {
+        if let Ok(platform) = Platform::parse(candidate) {
+            return Ok(platform);
+        }
+        if UNSUPPORTED_CHAINS.contains(&candidate) {
+            return Err(PriceError::UnknownPlatform(candidate.to_string()));
+        }
+    }
+    Ok(default)
+}
+
+/// Current price of a token looked up by contract address
+#[derive(Debug, Clone, PartialEq)]
+pub struct TokenPrice {
+    pub platform: Platform,
+    pub address: String,
+    pub price_usd: f64,
+    pub change_24h_pct: Option<f64>,
+}
+
+/// Coin a contract address belongs to, from CoinGecko's token list
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct TokenInfo {
+    pub id: String,
+    pub symbol: String,
+    pub name: String,
+}
+
 // Track API request times to respect rate limits
 static LAST_REQUEST: Lazy<Mutex<Option<Instant>>> = Lazy::new(|| Mutex::new(None));
 
+/// Most coin ids sent in one `/simple/price` request, longer lists are split
+pub const MAX_IDS_PER_REQUEST: usize = 100;
+
 // Minimum time between API requests (milliseconds)
 const MIN_REQUEST_INTERVAL_MS: u64 = 1500; // 1.5 seconds between requests
 
+/// How long a coin profile is reused, descriptions and supply caps rarely change
+pub const PROFILE_CACHE_TTL: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// Most coins `/coins/markets` returns per page
+pub const MAX_COINS_PER_PAGE: usize = 250;
+
+/// How long a current price is reused, unless PRICE_CACHE_TTL_SECS says otherwise
+pub const PRICE_CACHE_TTL: Duration = Duration::from_secs(60);
+
+/// How long fiat exchange rates are reused
+pub const FIAT_RATE_CACHE_TTL: Duration = Duration::from_secs(60 * 60);
+
+/// A fetched list with when it was fetched
+type Cached<T> = Arc<Mutex<Option<(Instant, T)>>>;
+
+// Client used by the module-level fetch functions
+static DEFAULT_CLIENT: Lazy<CoinGeckoClient> = Lazy::new(CoinGeckoClient::from_config);
+
+// Secondary provider used by the module-level fallback functions
+static SECONDARY_CLIENT: Lazy<DefiLlamaClient> = Lazy::new(DefiLlamaClient::from_config);
+
+// Secondary provider of fiat exchange rates
+static EXCHANGE_RATE_CLIENT: Lazy<ExchangeRateClient> = Lazy::new(ExchangeRateClient::from_config);
+
 /// Respects rate limits by waiting if needed
-async fn respect_rate_limit() {
-    if let Ok(last_request) = LAST_REQUEST.lock() {
-        if let Some(time) = *last_request {
-            let elapsed = time.elapsed();
-            let min_interval = Duration::from_millis(MIN_REQUEST_INTERVAL_MS);
-            
-            if elapsed < min_interval {
-                let wait_time = min_interval - elapsed;
-                tokio::time::sleep(wait_time).await;
-            }
+async fn respect_rate_limit(min_interval: Duration) {
+    // Read the last request time and release the lock before sleeping
+    let last_request = LAST_REQUEST.lock().ok().and_then(|last| *last);
+    
+    if let Some(time) = last_request {
+        let elapsed = time.elapsed();
+        
+        if elapsed < min_interval {
+            let wait_time = min_interval - elapsed;
+            tokio::time::sleep(wait_time).await;
         }
     }
 }
 
-/// Fetches the current price of any cryptocurrency in USD
-pub async fn fetch_coin_price(coin_id: &str) -> Result<f64, PriceError> {
-    // Respect rate limits
-    respect_rate_limit().await;
+/// Client for the CoinGecko price endpoints
+#[derive(Debug, Clone)]
+pub struct CoinGeckoClient {
+    client: Client,
+    base_url: String,
+    api_key: Option<String>,
+    /// Whether the key is a pro plan one, sent in `x-cg-pro-api-key`
+    pro: bool,
+    timeout: Duration,
+    min_request_interval: Duration,
+    profile_ttl: Duration,
+    /// Profiles fetched by this client and its clones, with when they were fetched
+    profiles: Arc<Mutex<HashMap<String, (Instant, CoinProfile)>>>,
+    /// The category list, reused for as long as profiles
+    categories: Cached<Vec<CoinCategory>>,
+    /// Units of each fiat currency per US dollar, reused for `FIAT_RATE_CACHE_TTL`
+    fiat_rates: Cached<HashMap<String, f64>>,
+    /// Current and historical prices fetched by this client and its clones
+    prices: PriceCache,
+    /// Skips calls while CoinGecko keeps failing, shared by clones
+    breaker: Arc<CircuitBreaker>,
+}
+
+impl CoinGeckoClient {
+    /// Create a client for the given base URL, e.g. `https://api.coingecko.com/api/v3`
+    pub fn new(base_url: impl Into<String>) -> Self {
+        Self {
+            client: Client::new(),
+            base_url: base_url.into().trim_end_matches('/').to_string(),
+            api_key: None,
+            pro: false,
+            timeout: Duration::from_secs(10),
+            min_request_interval: Duration::from_millis(MIN_REQUEST_INTERVAL_MS),
+            profile_ttl: PROFILE_CACHE_TTL,
+            profiles: Arc::new(Mutex::new(HashMap::new())),
+            categories: Arc::new(Mutex::new(None)),
+            fiat_rates: Arc::new(Mutex::new(None)),
+            prices: PriceCache::new(PRICE_CACHE_TTL),
+            breaker: Arc::new(CircuitBreaker::new(circuit_breaker::COINGECKO, BreakerSettings::default())),
+        }
+    }
     
-    let url = format!("https://api.coingecko.com/api/v3/simple/price?ids={}&vs_currencies=usd", coin_id);
+    /// Create a client using the base URL and API key from the application config
+    /// Clients created this way share one circuit breaker
+    pub fn from_config() -> Self {
+        let client = match Config::get_instance() {
+            Ok(config) => Self::new(config.coingecko_base_url.clone())
+                .with_api_key(config.coingecko_api_key.clone())
+                .with_pro(config.coingecko_pro)
+                .with_price_ttl(config.price_cache_ttl),
+            Err(_) => Self::new(COINGECKO_BASE_URL),
+        };
+        client.with_breaker(circuit_breaker::shared(circuit_breaker::COINGECKO))
+    }
     
-    let client = Client::new();
-    let request = client.get(&url);
+    /// Skip calls while this breaker is open, and report their outcomes to it
+    pub fn with_breaker(mut self, breaker: Arc<CircuitBreaker>) -> Self {
+        self.breaker = breaker;
+        self
+    }
     
-    // Send request with timeout
-    let response = request
-        .timeout(Duration::from_secs(10))
-        .send()
-        .await?;
+    /// Send the API key with every request
+    pub fn with_api_key(mut self, api_key: Option<String>) -> Self {
+        self.api_key = api_key;
+        self
+    }
     
-    // Check for rate limiting
-    if response.status() == StatusCode::TOO_MANY_REQUESTS {
-        eprintln!("CoinGecko API rate limit reached. Waiting before retrying.");
-        return Err(PriceError::RateLimitExceeded);
+    /// Send the API key as a pro plan key, for `COINGECKO_PRO_BASE_URL`
+    pub fn with_pro(mut self, pro: bool) -> Self {
+        self.pro = pro;
+        self
     }
     
-    // Check for other error status codes
-    if !response.status().is_success() {
-        eprintln!("CoinGecko API returned error status: {}", response.status());
-        return Err(PriceError::InvalidResponse(format!("Status code: {}", response.status())));
+    /// Set the per-request timeout
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
     }
     
-    // Parse response
-    let price_data = response.json::<PriceResponse>().await?;
+    /// Set the minimum delay between two requests
+    pub fn with_min_request_interval(mut self, interval: Duration) -> Self {
+        self.min_request_interval = interval;
+        self
+    }
     
-    // Update last request time
-    if let Ok(mut last_request) = LAST_REQUEST.lock() {
-        *last_request = Some(Instant::now());
+    /// Set how long fetched coin profiles are reused
+    pub fn with_profile_ttl(mut self, ttl: Duration) -> Self {
+        self.profile_ttl = ttl;
+        self
     }
     
-    // Extract price
-    match price_data.coins.get(coin_id) {
-        Some(prices) => {
-            match prices.get("usd") {
-                Some(price) => Ok(*price),
-                None => Err(PriceError::PriceNotFound(format!("USD price for {}", coin_id)))
-            }
-        },
-        None => Err(PriceError::PriceNotFound(coin_id.to_string()))
+    /// Set how long fetched current prices are reused, starting from an empty cache
+    pub fn with_price_ttl(mut self, ttl: Duration) -> Self {
+        self.prices = PriceCache::new(ttl);
+        self
     }
-}
-
-/// Fetches the current prices of multiple cryptocurrencies in USD
-/// Returns a HashMap with coin_id as key and price as value
-pub async fn fetch_multiple_coin_prices(coin_ids: &[&str]) -> Result<HashMap<String, f64>, PriceError> {
-    if coin_ids.is_empty() {
-        return Ok(HashMap::new());
+    
+    /// The prices this client and its clones reuse
+    pub fn price_cache(&self) -> &PriceCache {
+        &self.prices
+    }
+    
+    /// Build a GET request, sending the API key when one is configured
+    fn get(&self, path: &str) -> RequestBuilder {
+        let request = self.client
+            .get(format!("{}{}", self.base_url, path))
+            .timeout(self.timeout);
+        match &self.api_key {
+            Some(key) => request.header(api_key_header(self.pro), key),
+            None => request,
+        }
+    }
+    
+    /// Send a request and decode the JSON body, mapping rate limits and error statuses
+    async fn fetch<T: DeserializeOwned>(&self, request: RequestBuilder) -> Result<T, PriceError> {
+        let response = self.send(request).await?;
+        self.decode(response).await
+    }
+    
+    /// Send a request through the breaker and rate limit, leaving the status to the caller
+    async fn send(&self, request: RequestBuilder) -> Result<Response, PriceError> {
+        if offline::is_offline() {
+            return Err(PriceError::Offline);
+        }
+        
+        let permit = self.breaker.try_call()?;
+        
+        // Respect rate limits
+        respect_rate_limit(self.min_request_interval).await;
+        
+        let response = match request.send().await {
+            Ok(response) => {
+                offline::note_network_success();
+                response
+            },
+            Err(e) => {
+                permit.failed();
+                return Err(e.into());
+            },
+        };
+        permit.finish(!circuit_breaker::is_outage(response.status()));
+        
+        // Update last request time
+        if let Ok(mut last_request) = LAST_REQUEST.lock() {
+            *last_request = Some(Instant::now());
+        }
+        
+        Ok(response)
     }
     
-    // Respect rate limits
-    respect_rate_limit().await;
+    async fn decode<T: DeserializeOwned>(&self, response: Response) -> Result<T, PriceError> {
+        if response.status() == StatusCode::UNAUTHORIZED && self.api_key.is_some() {
+            return Err(PriceError::InvalidApiKey);
+        }
+        
+        // Check for rate limiting
+        if response.status() == StatusCode::TOO_MANY_REQUESTS {
+            eprintln!("CoinGecko API rate limit reached. Waiting before retrying.");
+            return Err(PriceError::RateLimitExceeded(http::retry_after(response.headers())));
+        }
+        
+        // Check for other error status codes
+        if !response.status().is_success() {
+            eprintln!("CoinGecko API returned error status: {}", response.status());
+            return Err(PriceError::InvalidResponse(format!("Status code: {}", response.status())));
+        }
+        
+        let body = response.text().await?;
+        serde_json::from_str(&body)
+            .map_err(|e| PriceError::InvalidResponse(format!("Malformed JSON: {}", e)))
+    }
     
-    let ids = coin_ids.join(",");
-    let url = format!("https://api.coingecko.com/api/v3/simple/price?ids={}&vs_currencies=usd", ids);
+    /// Fetches the current price of any cryptocurrency in USD, reusing one fetched within the cache's TTL
+    pub async fn fetch_coin_price(&self, coin_id: &str) -> Result<f64, PriceError> {
+        Ok(self.fetch_cached_coin_price(coin_id).await?.price)
+    }
     
-    let client = Client::new();
-    let request = client.get(&url);
+    /// Fetches the current USD price of a coin with whether, and how long ago, it was fetched before
+    ///
+    /// A price within the cache's TTL is reused. When CoinGecko is rate limiting, the last price
+    /// fetched is returned however old it is, marked stale; without one the rate limit is the error.
+    pub async fn fetch_cached_coin_price(&self, coin_id: &str) -> Result<CachedPrice, PriceError> {
+        if let Some(cached) = self.prices.get(coin_id, "usd").await {
+            return Ok(cached);
+        }
+        match self.request_coin_price(coin_id).await {
+            Ok(price) => {
+                self.prices.insert(coin_id, "usd", price).await;
+                Ok(CachedPrice::fetched(price))
+            },
+            Err(PriceError::RateLimitExceeded(wait)) => {
+                self.prices.get_stale(coin_id, "usd").await.ok_or(PriceError::RateLimitExceeded(wait))
+            },
+            Err(e) => Err(e),
+        }
+    }
     
-    // Send request with timeout
-    let response = request
-        .timeout(Duration::from_secs(10))
-        .send()
-        .await?;
+    async fn request_coin_price(&self, coin_id: &str) -> Result<f64, PriceError> {
+        let request = self.get("/simple/price")
+            .query(&[("ids", coin_id), ("vs_currencies", "usd")]);
+        let price_data: PriceResponse = self.fetch(request).await?;
+        
+        // Extract price
+        match price_data.coins.get(coin_id) {
+            Some(prices) => {
+                match prices.get("usd") {
+                    Some(price) => Ok(*price),
+                    None => Err(PriceError::PriceNotFound(format!("USD price for {}", coin_id)))
+                }
+            },
+            None => Err(PriceError::PriceNotFound(coin_id.to_string()))
+        }
+    }
+    
+    /// Fetches the current prices of multiple cryptocurrencies in USD
+    /// Returns a HashMap with coin_id as key and price as value
+    ///
+    /// Only coins without a price in the cache are requested. When CoinGecko is rate limiting,
+    /// their last prices are used if every one of them has one.
+    pub async fn fetch_multiple_coin_prices(&self, coin_ids: &[&str]) -> Result<HashMap<String, f64>, PriceError> {
+        let mut prices = HashMap::new();
+        let mut missing = Vec::new();
+        for coin_id in coin_ids {
+            match self.prices.get(coin_id, "usd").await {
+                Some(cached) => {
+                    prices.insert(coin_id.to_string(), cached.price);
+                },
+                None => missing.push(*coin_id),
+            }
+        }
+        if missing.is_empty() {
+            return Ok(prices);
+        }
+        
+        match self.fetch_multiple_coin_quotes(&missing).await {
+            Ok(quotes) => prices.extend(quotes.into_iter().map(|(coin_id, quote)| (coin_id, quote.price_usd))),
+            Err(PriceError::RateLimitExceeded(wait)) => {
+                for coin_id in missing {
+                    let stale = self.prices.get_stale(coin_id, "usd").await.ok_or(PriceError::RateLimitExceeded(wait))?;
+                    prices.insert(coin_id.to_string(), stale.price);
+                }
+            },
+            Err(e) => return Err(e),
+        }
+        Ok(prices)
+    }
     
-    // Check for rate limiting
-    if response.status() == StatusCode::TOO_MANY_REQUESTS {
-        eprintln!("CoinGecko API rate limit reached. Waiting before retrying.");
-        return Err(PriceError::RateLimitExceeded);
+    /// Fetches the current USD price of a coin with its 24h change, market cap and 24h volume
+    pub async fn fetch_coin_quote(&self, coin_id: &str) -> Result<CoinQuote, PriceError> {
+        Ok(self.fetch_cached_coin_quote(coin_id).await?.0)
     }
     
-    // Check for other error status codes
-    if !response.status().is_success() {
-        eprintln!("CoinGecko API returned error status: {}", response.status());
-        return Err(PriceError::InvalidResponse(format!("Status code: {}", response.status())));
+    /// Fetches a coin's quote with whether, and how long ago, its price was fetched before
+    ///
+    /// Reused and served stale while rate limited like `fetch_cached_coin_price`, as long as the
+    /// cached price came with a quote.
+    pub async fn fetch_cached_coin_quote(&self, coin_id: &str) -> Result<(CoinQuote, CachedPrice), PriceError> {
+        let last = self.prices.quote(coin_id).await;
+        if let Some((quote, cached)) = last
+            && !cached.stale
+        {
+            return Ok((quote, cached));
+        }
+        match self.fetch_multiple_coin_quotes(&[coin_id]).await {
+            Ok(mut quotes) => quotes
+                .remove(coin_id)
+                .map(|quote| (quote, CachedPrice::fetched(quote.price_usd)))
+                .ok_or_else(|| PriceError::PriceNotFound(format!("USD price for {}", coin_id))),
+            Err(PriceError::RateLimitExceeded(wait)) => last.ok_or(PriceError::RateLimitExceeded(wait)),
+            Err(e) => Err(e),
+        }
     }
     
-    // Parse response
-    let price_data = response.json::<PriceResponse>().await?;
+    /// Fetches the current USD prices, 24h changes, market caps and 24h volumes of multiple cryptocurrencies
+    /// Sends one request per `MAX_IDS_PER_REQUEST` coins; coins CoinGecko doesn't know are left out
+    pub async fn fetch_multiple_coin_quotes(&self, coin_ids: &[&str]) -> Result<HashMap<String, CoinQuote>, PriceError> {
+        let mut result = HashMap::new();
+        for chunk in coin_ids.chunks(MAX_IDS_PER_REQUEST) {
+            let ids = chunk.join(",");
+            let request = self.get("/simple/price").query(&[
+                ("ids", ids.as_str()),
+                ("vs_currencies", "usd"),
+                ("include_24hr_change", "true"),
+                ("include_market_cap", "true"),
+                ("include_24hr_vol", "true"),
+            ]);
+            let quote_data: QuoteResponse = self.fetch(request).await?;
+            
+            for coin_id in chunk {
+                if let Some(fields) = quote_data.coins.get(*coin_id)
+                    && let Some(Some(price)) = fields.get("usd")
+                {
+                    let field = |name: &str| fields.get(name).copied().flatten();
+                    let quote = CoinQuote {
+                        price_usd: *price,
+                        change_24h_pct: field("usd_24h_change"),
+                        market_cap: field("usd_market_cap"),
+                        volume_24h: field("usd_24h_vol"),
+                    };
+                    self.prices.insert_quote(coin_id, quote).await;
+                    result.insert(coin_id.to_string(), quote);
+                }
+            }
+        }
+        
+        Ok(result)
+    }
     
-    // Update last request time
-    if let Ok(mut last_request) = LAST_REQUEST.lock() {
-        *last_request = Some(Instant::now());
+    /// Fetches historical price of any cryptocurrency for a specific date
+    /// Date format should be dd-mm-yyyy (e.g., "01-12-2024")
+    ///
+    /// Dates `/history` can't serve on the current plan are looked up in `/market_chart/range`,
+    /// and `PriceError::OutOfRange` says how far back the plan goes when that can't either.
+    /// A price found once is reused without asking again.
+    pub async fn fetch_coin_historical_price(&self, coin_id: &str, date: &str) -> Result<f64, PriceError> {
+        if let Some(price) = self.prices.historical(coin_id, date).await {
+            return Ok(price);
+        }
+        let price = self.request_historical_price(coin_id, date).await?;
+        self.prices.insert_historical(coin_id, date, price).await;
+        Ok(price)
     }
     
-    let mut result = HashMap::new();
-    for coin_id in coin_ids {
-        if let Some(prices) = price_data.coins.get(*coin_id) {
-            if let Some(price) = prices.get("usd") {
-                result.insert(coin_id.to_string(), *price);
+    async fn request_historical_price(&self, coin_id: &str, date: &str) -> Result<f64, PriceError> {
+        let request = self.get(&format!("/coins/{}/history", coin_id))
+            .query(&[("date", date)]);
+        let response = self.send(request).await?;
+        let lookback_days = if is_plan_limited(response.status()) {
+            plan_lookback_days(&self.plan_limit_body(response).await?)
+        } else {
+            let historical_data: HistoricalResponse = self.decode(response).await?;
+            if let Some(price) = historical_data.market_data.and_then(|data| data.current_price.get("usd").copied()) {
+                return Ok(price);
             }
+            DEFAULT_PLAN_LOOKBACK_DAYS
+        };
+        
+        let day = chrono::NaiveDate::parse_from_str(date, "%d-%m-%Y")
+            .map_err(|_| PriceError::InvalidResponse(format!("Invalid date {}", date)))?;
+        self.fetch_price_near(coin_id, day, lookback_days).await
+    }
+    
+    /// The body of an answer refusing a date past the plan's history
+    /// With a key configured, a 401 for anything else is the key being rejected
+    async fn plan_limit_body(&self, response: Response) -> Result<String, PriceError> {
+        let status = response.status();
+        let body = response.text().await?;
+        if status == StatusCode::UNAUTHORIZED && self.api_key.is_some() && !is_time_range_limit(&body) {
+            return Err(PriceError::InvalidApiKey);
         }
+        Ok(body)
     }
     
-    Ok(result)
-}
-
-/// Fetches the current price of Aerodrome token in USD (legacy function)
-pub async fn fetch_current_price() -> Result<f64, PriceError> {
-    fetch_coin_price("aerodrome-finance").await
+    /// Price at the daily point closest to midnight UTC of `day`, from `/market_chart/range`
+    async fn fetch_price_near(&self, coin_id: &str, day: chrono::NaiveDate, lookback_days: u32) -> Result<f64, PriceError> {
+        let midnight = day.and_time(chrono::NaiveTime::MIN).and_utc().timestamp();
+        let window = RANGE_WINDOW_DAYS * 24 * 60 * 60;
+        let (from, to) = ((midnight - window).to_string(), (midnight + window).to_string());
+        let request = self.get(&format!("/coins/{}/market_chart/range", coin_id))
+            .query(&[("vs_currency", "usd"), ("from", from.as_str()), ("to", to.as_str())]);
+        let response = self.send(request).await?;
+        
+        if is_plan_limited(response.status()) {
+            self.plan_limit_body(response).await?;
+            let earliest = self.earliest_available(coin_id, lookback_days).await;
+            return Err(PriceError::OutOfRange { coin_id: coin_id.to_string(), earliest });
+        }
+        let chart: MarketChartResponse = self.decode(response).await?;
+        closest_point(&chart.prices, (midnight * 1000) as f64)
+            .ok_or_else(|| PriceError::PriceNotFound(format!("Historical USD price for {}", coin_id)))
+    }
+    
+    /// First day the plan has prices for, counted back from today when the chart can't say
+    async fn earliest_available(&self, coin_id: &str, lookback_days: u32) -> chrono::NaiveDate {
+        let fallback = chrono::Utc::now().date_naive() - chrono::Duration::days(i64::from(lookback_days));
+        match self.fetch_chart(coin_id, lookback_days, ChartInterval::Daily).await {
+            Ok(chart) => daily_points(&chart.prices)
+                .ok()
+                .and_then(|series| series.first().map(|(date, _)| *date))
+                .unwrap_or(fallback),
+            Err(_) => fallback,
+        }
+    }
+    
+    /// Search coins by name or ticker, best matches first
+    pub async fn search_coins(&self, query: &str) -> Result<Vec<CoinMatch>, PriceError> {
+        let request = self.get("/search").query(&[("query", query)]);
+        let response: SearchResponse = self.fetch(request).await?;
+        Ok(response.coins)
+    }
+    
+    /// Fetches what a coin is: description, categories, links and supply
+    /// Profiles are cached for `PROFILE_CACHE_TTL`, so follow-up questions don't hit the API again
+    pub async fn fetch_coin_profile(&self, coin_id: &str) -> Result<CoinProfile, PriceError> {
+        if let Some((fetched, profile)) = self.profiles.lock().unwrap().get(coin_id)
+            && fetched.elapsed() < self.profile_ttl
+        {
+            return Ok(profile.clone());
+        }
+        
+        let request = self.get(&format!("/coins/{}", coin_id)).query(&[
+            ("localization", "false"),
+            ("tickers", "false"),
+            ("market_data", "true"),
+            ("community_data", "false"),
+            ("developer_data", "false"),
+            ("sparkline", "false"),
+        ]);
+        let profile = CoinProfile::from(self.fetch::<CoinResponse>(request).await?);
+        
+        let mut profiles = self.profiles.lock().unwrap();
+        profiles.retain(|_, (fetched, _)| fetched.elapsed() < self.profile_ttl);
+        profiles.insert(coin_id.to_string(), (Instant::now(), profile.clone()));
+        Ok(profile)
+    }
+    
+    /// Fetches every category CoinGecko groups coins by, cached like profiles
+    pub async fn fetch_category_list(&self) -> Result<Vec<CoinCategory>, PriceError> {
+        if let Some((fetched, categories)) = self.categories.lock().unwrap().as_ref()
+            && fetched.elapsed() < self.profile_ttl
+        {
+            return Ok(categories.clone());
+        }
+        
+        let categories: Vec<CoinCategory> = self.fetch(self.get("/coins/categories/list")).await?;
+        *self.categories.lock().unwrap() = Some((Instant::now(), categories.clone()));
+        Ok(categories)
+    }
+    
+    /// Fetches units of each fiat currency CoinGecko quotes in per US dollar, keyed by lowercase code
+    ///
+    /// CoinGecko's exchange rates are per bitcoin, so each is divided by the USD one.
+    pub async fn fetch_fiat_rates(&self) -> Result<HashMap<String, f64>, PriceError> {
+        if let Some((fetched, rates)) = self.fiat_rates.lock().unwrap().as_ref()
+            && fetched.elapsed() < FIAT_RATE_CACHE_TTL
+        {
+            return Ok(rates.clone());
+        }
+        
+        let response: ExchangeRatesResponse = self.fetch(self.get("/exchange_rates")).await?;
+        let usd = response.rates.get("usd")
+            .map(|rate| rate.value)
+            .filter(|value| *value > 0.0)
+            .ok_or_else(|| PriceError::PriceNotFound("USD exchange rate".to_string()))?;
+        let rates: HashMap<String, f64> = response.rates
+            .into_iter()
+            .filter(|(_, rate)| rate.kind == "fiat")
+            .map(|(code, rate)| (code, rate.value / usd))
+            .collect();
+        *self.fiat_rates.lock().unwrap() = Some((Instant::now(), rates.clone()));
+        Ok(rates)
+    }
+    
+    /// Fetches the `per_page` largest coins of a category by market cap, with prices and 7d changes
+    pub async fn fetch_coins_by_category(&self, category_id: &str, per_page: usize) -> Result<Vec<CategoryCoin>, PriceError> {
+        let per_page = per_page.clamp(1, MAX_COINS_PER_PAGE).to_string();
+        let request = self.get("/coins/markets").query(&[
+            ("vs_currency", "usd"),
+            ("category", category_id),
+            ("order", "market_cap_desc"),
+            ("per_page", per_page.as_str()),
+            ("page", "1"),
+            ("sparkline", "false"),
+            ("price_change_percentage", "7d"),
+        ]);
+        self.fetch(request).await
+    }
+    
+    /// Fetches the current USD price and 24h change of a token by contract address
+    pub async fn fetch_token_price(&self, platform: Platform, address: &str) -> Result<TokenPrice, PriceError> {
+        let address = parse_contract_address(address)?;
+        let request = self.get(&format!("/simple/token_price/{}", platform.coingecko_id()))
+            .query(&[("contract_addresses", address.as_str()), ("vs_currencies", "usd"), ("include_24hr_change", "true")]);
+        let quote_data: QuoteResponse = self.fetch(request).await?;
+        
+        // Keys are lowercase addresses
+        let fields = quote_data.coins.get(&address)
+            .ok_or_else(|| PriceError::PriceNotFound(format!("{} on {}", address, platform.display_name())))?;
+        let price_usd = fields.get("usd").copied().flatten()
+            .ok_or_else(|| PriceError::PriceNotFound(format!("USD price for {} on {}", address, platform.display_name())))?;
+        Ok(TokenPrice {
+            platform,
+            address,
+            price_usd,
+            change_24h_pct: fields.get("usd_24h_change").copied().flatten(),
+        })
+    }
+    
+    /// Looks up which coin a contract address belongs to
+    pub async fn fetch_token_info(&self, platform: Platform, address: &str) -> Result<TokenInfo, PriceError> {
+        let address = parse_contract_address(address)?;
+        let request = self.get(&format!("/coins/{}/contract/{}", platform.coingecko_id(), address));
+        self.fetch(request).await
+    }
+    
+    /// Fetches daily USD prices for the last `days` days, oldest first, one price per day
+    /// CoinGecko appends the current price as a last point, it replaces that day's close
+    pub async fn fetch_market_chart(&self, coin_id: &str, days: u32) -> Result<Vec<DailyPrice>, PriceError> {
+        let chart = self.fetch_chart(coin_id, days, ChartInterval::Daily).await?;
+        let series: Vec<DailyPrice> = daily_points(&chart.prices)?
+            .into_iter()
+            .map(|(date, price_usd)| DailyPrice { date, price_usd })
+            .collect();
+        
+        if series.is_empty() {
+            return Err(PriceError::PriceNotFound(format!("Price history for {}", coin_id)));
+        }
+        Ok(series)
+    }
+    
+    /// Like `fetch_market_chart`, with the USD volume of each day
+    /// Days CoinGecko sends no volume for are left out
+    pub async fn fetch_market_chart_with_volume(&self, coin_id: &str, days: u32) -> Result<Vec<DailyBar>, PriceError> {
+        let chart = self.fetch_chart(coin_id, days, ChartInterval::Daily).await?;
+        let volumes: HashMap<chrono::NaiveDate, f64> = daily_points(&chart.total_volumes)?.into_iter().collect();
+        let series: Vec<DailyBar> = daily_points(&chart.prices)?
+            .into_iter()
+            .filter_map(|(date, price_usd)| {
+                volumes.get(&date).map(|volume_usd| DailyBar { date, price_usd, volume_usd: *volume_usd })
+            })
+            .collect();
+        
+        if series.is_empty() {
+            return Err(PriceError::PriceNotFound(format!("Price and volume history for {}", coin_id)));
+        }
+        Ok(series)
+    }
+    
+    /// Fetches hourly USD prices for the last `days` days, oldest first
+    /// CoinGecko sends hourly points for 2 to 90 days when no interval is asked for, so `days` is
+    /// kept in that range; the last point is the current price.
+    pub async fn fetch_hourly_chart(&self, coin_id: &str, days: u32) -> Result<Vec<PricePoint>, PriceError> {
+        let chart = self.fetch_chart(coin_id, days.clamp(2, 90), ChartInterval::Auto).await?;
+        let series: Vec<PricePoint> = timed_points(&chart.prices)?
+            .into_iter()
+            .map(|(at, price_usd)| PricePoint { at, price_usd })
+            .collect();
+        if series.is_empty() {
+            return Err(PriceError::PriceNotFound(format!("Hourly prices for {}", coin_id)));
+        }
+        Ok(series)
+    }
+    
+    /// Fetches a coin's USD prices, volumes and market caps over the last `days` days, oldest first
+    ///
+    /// With `ChartInterval::Daily` each UTC day keeps its last point, so today's is the current price.
+    /// A coin listed too recently to have any points gives an empty chart rather than an error.
+    pub async fn fetch_coin_market_chart(&self, coin_id: &str, days: u32, interval: ChartInterval) -> Result<MarketChart, PriceError> {
+        let chart = self.fetch_chart(coin_id, days, interval).await?;
+        let series = |points: &[(f64, f64)]| -> Result<Vec<_>, PriceError> {
+            let points = timed_points(points)?;
+            Ok(match interval {
+                ChartInterval::Auto => points,
+                ChartInterval::Daily => daily_buckets(points),
+            })
+        };
+        let optional = |points: &[(f64, f64)]| (!points.is_empty()).then(|| series(points)).transpose();
+        
+        Ok(MarketChart {
+            prices: series(&chart.prices)?,
+            volumes: optional(&chart.total_volumes)?,
+            market_caps: optional(&chart.market_caps)?,
+        })
+    }
+    
+    async fn fetch_chart(&self, coin_id: &str, days: u32, interval: ChartInterval) -> Result<MarketChartResponse, PriceError> {
+        let days = days.to_string();
+        let mut query = vec![("vs_currency", "usd"), ("days", days.as_str())];
+        if interval == ChartInterval::Daily {
+            query.push(("interval", "daily"));
+        }
+        let request = self.get(&format!("/coins/{}/market_chart", coin_id)).query(&query);
+        self.fetch(request).await
+    }
 }
 
-/// Fetches the current price of Ethereum in USD (legacy function)
-pub async fn fetch_ethereum_price() -> Result<f64, PriceError> {
-    fetch_coin_price("ethereum").await
+/// Client for the DefiLlama coins API, keyed by CoinGecko ids
+#[derive(Debug, Clone)]
+pub struct DefiLlamaClient {
+    client: Client,
+    base_url: String,
+    timeout: Duration,
 }
 
-/// Fetches historical price of any cryptocurrency for a specific date
-/// Date format should be dd-mm-yyyy (e.g., "01-12-2024")
-pub async fn fetch_coin_historical_price(coin_id: &str, date: &str) -> Result<f64, PriceError> {
-    // Respect rate limits
-    respect_rate_limit().await;
-    
-    let url = format!(
-        "https://api.coingecko.com/api/v3/coins/{}/history?date={}",
-        coin_id, date
-    );
-    
-    #[derive(Debug, Deserialize)]
-    struct HistoricalResponse {
-        market_data: MarketData,
+impl DefiLlamaClient {
+    /// Create a client for the given base URL, e.g. `https://coins.llama.fi`
+    pub fn new(base_url: impl Into<String>) -> Self {
+        Self {
+            client: Client::new(),
+            base_url: base_url.into().trim_end_matches('/').to_string(),
+            timeout: Duration::from_secs(10),
+        }
     }
     
-    #[derive(Debug, Deserialize)]
-    struct MarketData {
-        current_price: HashMap<String, f64>,
+    /// Create a client using the base URL from the application config
+    pub fn from_config() -> Self {
+        match Config::get_instance() {
+            Ok(config) => Self::new(config.defillama_base_url.clone()),
+            Err(_) => Self::new(DEFILLAMA_BASE_URL),
+        }
     }
     
-    let client = Client::new();
-    let request = client.get(&url);
+    /// Set the per-request timeout
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
     
-    // Send request with timeout
-    let response = request
-        .timeout(Duration::from_secs(10))
-        .send()
-        .await?;
+    async fn fetch_price(&self, path: &str, coin_id: &str) -> Result<f64, PriceError> {
+        if offline::is_offline() {
+            return Err(PriceError::Offline);
+        }
+        
+        let response = self.client
+            .get(format!("{}{}", self.base_url, path))
+            .timeout(self.timeout)
+            .send()
+            .await
+            .inspect(|_| offline::note_network_success())?;
+        
+        if !response.status().is_success() {
+            return Err(PriceError::InvalidResponse(format!("DefiLlama status code: {}", response.status())));
+        }
+        
+        let body = response.text().await?;
+        let prices: LlamaPricesResponse = serde_json::from_str(&body)
+            .map_err(|e| PriceError::InvalidResponse(format!("Malformed JSON: {}", e)))?;
+        
+        prices.coins
+            .get(&format!("coingecko:{}", coin_id))
+            .map(|coin| coin.price)
+            .ok_or_else(|| PriceError::PriceNotFound(coin_id.to_string()))
+    }
     
-    // Check for rate limiting
-    if response.status() == StatusCode::TOO_MANY_REQUESTS {
-        eprintln!("CoinGecko API rate limit reached. Waiting before retrying.");
-        return Err(PriceError::RateLimitExceeded);
+    /// Fetches the current price of a coin in USD
+    pub async fn fetch_coin_price(&self, coin_id: &str) -> Result<f64, PriceError> {
+        self.fetch_price(&format!("/prices/current/coingecko:{}", coin_id), coin_id).await
     }
     
-    // Check for other error status codes
-    if !response.status().is_success() {
-        eprintln!("CoinGecko API returned error status: {}", response.status());
-        return Err(PriceError::InvalidResponse(format!("Status code: {}", response.status())));
+    /// Fetches the price of a coin at midnight UTC on a date in dd-mm-yyyy format
+    pub async fn fetch_coin_historical_price(&self, coin_id: &str, date: &str) -> Result<f64, PriceError> {
+        let timestamp = chrono::NaiveDate::parse_from_str(date, "%d-%m-%Y")
+            .map_err(|e| PriceError::InvalidResponse(format!("Invalid date {}: {}", date, e)))?
+            .and_hms_opt(0, 0, 0)
+            .map(|midnight| midnight.and_utc().timestamp())
+            .unwrap_or_default();
+        self.fetch_price(&format!("/prices/historical/{}/coingecko:{}", timestamp, coin_id), coin_id).await
+    }
+}
+
+/// Client for the exchangerate.host fiat rates API
+#[derive(Debug, Clone)]
+pub struct ExchangeRateClient {
+    client: Client,
+    base_url: String,
+    timeout: Duration,
+    rates: Cached<HashMap<String, f64>>,
+}
+
+impl ExchangeRateClient {
+    /// Create a client for the given base URL, e.g. `https://api.exchangerate.host`
+    pub fn new(base_url: impl Into<String>) -> Self {
+        Self {
+            client: Client::new(),
+            base_url: base_url.into().trim_end_matches('/').to_string(),
+            timeout: Duration::from_secs(10),
+            rates: Arc::new(Mutex::new(None)),
+        }
     }
     
-    // Parse response
-    let historical_data = response.json::<HistoricalResponse>().await?;
+    /// Create a client using the base URL from the application config
+    pub fn from_config() -> Self {
+        match Config::get_instance() {
+            Ok(config) => Self::new(config.exchange_rate_base_url.clone()),
+            Err(_) => Self::new(EXCHANGE_RATE_BASE_URL),
+        }
+    }
     
-    // Update last request time
-    if let Ok(mut last_request) = LAST_REQUEST.lock() {
-        *last_request = Some(Instant::now());
+    /// Set the per-request timeout
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
     }
     
-    // Extract price
-    match historical_data.market_data.current_price.get("usd") {
-        Some(price) => Ok(*price),
-        None => Err(PriceError::PriceNotFound(format!("Historical USD price for {}", coin_id)))
+    /// Fetches units of each currency per US dollar, keyed by lowercase code
+    pub async fn fetch_fiat_rates(&self) -> Result<HashMap<String, f64>, PriceError> {
+        if let Some((fetched, rates)) = self.rates.lock().unwrap().as_ref()
+            && fetched.elapsed() < FIAT_RATE_CACHE_TTL
+        {
+            return Ok(rates.clone());
+        }
+        if offline::is_offline() {
+            return Err(PriceError::Offline);
+        }
+        
+        let response = self.client
+            .get(format!("{}/latest", self.base_url))
+            .query(&[("base", "USD")])
+            .timeout(self.timeout)
+            .send()
+            .await
+            .inspect(|_| offline::note_network_success())?;
+        
+        if !response.status().is_success() {
+            return Err(PriceError::InvalidResponse(format!("exchangerate.host status code: {}", response.status())));
+        }
+        
+        let body = response.text().await?;
+        let latest: LatestRatesResponse = serde_json::from_str(&body)
+            .map_err(|e| PriceError::InvalidResponse(format!("Malformed JSON: {}", e)))?;
+        if latest.rates.is_empty() {
+            return Err(PriceError::PriceNotFound("exchange rates".to_string()));
+        }
+        
+        let rates: HashMap<String, f64> = latest.rates
+            .into_iter()
+            .map(|(code, rate)| (code.to_lowercase(), rate))
+            .collect();
+        *self.rates.lock().unwrap() = Some((Instant::now(), rates.clone()));
+        Ok(rates)
     }
 }
 
+/// Fetches the current price of any cryptocurrency in USD
+pub async fn fetch_coin_price(coin_id: &str) -> Result<f64, PriceError> {
+    DEFAULT_CLIENT.fetch_coin_price(coin_id).await
+}
+
+/// Fetches the current USD price of a coin with whether it came from the cache, and how old it is
+pub async fn fetch_cached_coin_price(coin_id: &str) -> Result<CachedPrice, PriceError> {
+    DEFAULT_CLIENT.fetch_cached_coin_price(coin_id).await
+}
+
+/// Forget the prices the module-level fetch functions reuse
+pub async fn clear_price_cache() {
+    DEFAULT_CLIENT.price_cache().clear().await
+}
+
+/// Fetches the current prices of multiple cryptocurrencies in USD
+/// Returns a HashMap with coin_id as key and price as value
+pub async fn fetch_multiple_coin_prices(coin_ids: &[&str]) -> Result<HashMap<String, f64>, PriceError> {
+    DEFAULT_CLIENT.fetch_multiple_coin_prices(coin_ids).await
+}
+
+/// Fetches the current USD price of a coin with its 24h change, market cap and 24h volume
+pub async fn fetch_coin_quote(coin_id: &str) -> Result<CoinQuote, PriceError> {
+    DEFAULT_CLIENT.fetch_coin_quote(coin_id).await
+}
+
+/// Fetches a coin's quote with whether its price came from the cache, and how old it is
+pub async fn fetch_cached_coin_quote(coin_id: &str) -> Result<(CoinQuote, CachedPrice), PriceError> {
+    DEFAULT_CLIENT.fetch_cached_coin_quote(coin_id).await
+}
+
+/// Fetches the current USD prices, 24h changes, market caps and 24h volumes of multiple cryptocurrencies
+pub async fn fetch_multiple_coin_quotes(coin_ids: &[&str]) -> Result<HashMap<String, CoinQuote>, PriceError> {
+    DEFAULT_CLIENT.fetch_multiple_coin_quotes(coin_ids).await
+}
+
+/// Fetches the current price of Aerodrome token in USD (legacy function)
+pub async fn fetch_current_price() -> Result<f64, PriceError> {
+    fetch_coin_price("aerodrome-finance").await
+}
+
+/// Fetches the current price of Ethereum in USD (legacy function)
+pub async fn fetch_ethereum_price() -> Result<f64, PriceError> {
+    fetch_coin_price("ethereum").await
+}
+
+/// Fetches historical price of any cryptocurrency for a specific date
+/// Date format should be dd-mm-yyyy (e.g., "01-12-2024")
+pub async fn fetch_coin_historical_price(coin_id: &str, date: &str) -> Result<f64, PriceError> {
+    DEFAULT_CLIENT.fetch_coin_historical_price(coin_id, date).await
+}
+
+/// Search coins by name or ticker, best matches first
+pub async fn search_coins(query: &str) -> Result<Vec<CoinMatch>, PriceError> {
+    DEFAULT_CLIENT.search_coins(query).await
+}
+
+/// Fetches what a coin is, cached for `PROFILE_CACHE_TTL`
+pub async fn fetch_coin_profile(coin_id: &str) -> Result<CoinProfile, PriceError> {
+    DEFAULT_CLIENT.fetch_coin_profile(coin_id).await
+}
+
+/// Fetches every CoinGecko category, cached for `PROFILE_CACHE_TTL`
+pub async fn fetch_category_list() -> Result<Vec<CoinCategory>, PriceError> {
+    DEFAULT_CLIENT.fetch_category_list().await
+}
+
+/// Fetches the largest coins of a category by market cap
+pub async fn fetch_coins_by_category(category_id: &str, per_page: usize) -> Result<Vec<CategoryCoin>, PriceError> {
+    DEFAULT_CLIENT.fetch_coins_by_category(category_id, per_page).await
+}
+
+/// Fetches the current USD price of a token by contract address
+pub async fn fetch_token_price(platform: Platform, address: &str) -> Result<TokenPrice, PriceError> {
+    DEFAULT_CLIENT.fetch_token_price(platform, address).await
+}
+
+/// Looks up which coin a contract address belongs to
+pub async fn fetch_token_info(platform: Platform, address: &str) -> Result<TokenInfo, PriceError> {
+    DEFAULT_CLIENT.fetch_token_info(platform, address).await
+}
+
+/// Fetches daily USD prices for the last `days` days, oldest first
+pub async fn fetch_market_chart(coin_id: &str, days: u32) -> Result<Vec<DailyPrice>, PriceError> {
+    DEFAULT_CLIENT.fetch_market_chart(coin_id, days).await
+}
+
+/// Fetches daily USD prices and volumes for the last `days` days, oldest first
+pub async fn fetch_market_chart_with_volume(coin_id: &str, days: u32) -> Result<Vec<DailyBar>, PriceError> {
+    DEFAULT_CLIENT.fetch_market_chart_with_volume(coin_id, days).await
+}
+
+/// Fetches USD prices, volumes and market caps for the last `days` days at `interval`, oldest first
+pub async fn fetch_coin_market_chart(coin_id: &str, days: u32, interval: ChartInterval) -> Result<MarketChart, PriceError> {
+    DEFAULT_CLIENT.fetch_coin_market_chart(coin_id, days, interval).await
+}
+
+/// Fetches the current price from the secondary provider
+pub async fn fetch_secondary_coin_price(coin_id: &str) -> Result<f64, PriceError> {
+    SECONDARY_CLIENT.fetch_coin_price(coin_id).await
+}
+
+/// Fetches a historical price from the secondary provider
+/// Date format should be dd-mm-yyyy (e.g., "01-12-2024")
+pub async fn fetch_secondary_historical_price(coin_id: &str, date: &str) -> Result<f64, PriceError> {
+    SECONDARY_CLIENT.fetch_coin_historical_price(coin_id, date).await
+}
+
+/// Fetches units of each fiat currency per US dollar from CoinGecko
+pub async fn fetch_fiat_rates() -> Result<HashMap<String, f64>, PriceError> {
+    DEFAULT_CLIENT.fetch_fiat_rates().await
+}
+
+/// Fetches units of each fiat currency per US dollar from exchangerate.host
+pub async fn fetch_secondary_fiat_rates() -> Result<HashMap<String, f64>, PriceError> {
+    EXCHANGE_RATE_CLIENT.fetch_fiat_rates().await
+}
+
 /// Fetches historical price of Aerodrome token for a specific date (legacy function)
 /// Date format should be dd-mm-yyyy (e.g., "01-12-2024")
 pub async fn fetch_historical_price(date: &str) -> Result<f64, PriceError> {
@@ -244,9 +1609,138 @@ pub async fn fetch_ethereum_historical_price(date: &str) -> Result<f64, PriceErr
 
 #[cfg(test)]
 mod tests {
+    // Live API checks, run with `cargo test -- --ignored`
+    // The hermetic coverage lives in tests/coingecko.rs
     use super::*;
     
+    const WETH_BASE: &str = "0x4200000000000000000000000000000000000006";
+    
+    #[test]
+    fn test_find_contract_address() {
+        let found = find_contract_address("what's the price of 0x4200000000000000000000000000000000000006?");
+        assert_eq!(found.unwrap().unwrap(), WETH_BASE);
+        
+        // Mixed-case checksummed addresses are lowercased
+        let found = find_contract_address("price of 0x940181a94A35A4569E4529A3CDfB74e38FD98631 on base");
+        assert_eq!(found.unwrap().unwrap(), "0x940181a94a35a4569e4529a3cdfb74e38fd98631");
+        
+        assert!(find_contract_address("what's the price of bitcoin").is_none());
+        assert!(find_contract_address("price of 0xabc").unwrap().is_err());
+        assert!(matches!(
+            find_contract_address("price of 0x4200...0006"),
+            Some(Err(PriceError::InvalidAddress(reason))) if reason.contains("shortened")
+        ));
+        assert!(matches!(
+            find_contract_address("price of 0x42000000000000000000000000000000000000061"),
+            Some(Err(PriceError::InvalidAddress(reason))) if reason.contains("41 hex digits")
+        ));
+    }
+    
+    #[test]
+    fn test_detect_token_price_query() {
+        assert!(detect_token_price_query(&format!("how much is {} worth on arbitrum?", WETH_BASE)).is_some());
+        assert!(detect_token_price_query(WETH_BASE).is_some());
+        assert!(detect_token_price_query(&format!("{} on base", WETH_BASE)).is_some());
+        assert!(detect_token_price_query("price of 0x4200...0006").unwrap().is_err());
+        
+        // An address inside another kind of question is left to the other intents
+        let tx = "why did my transaction 0x5c504ed432cb51138bcf09aa5e8a410dd4a1e204ef84bfed1be16dfba1b22060 fail";
+        assert!(detect_token_price_query(tx).is_none());
+    }
+    
+    #[test]
+    fn test_parse_contract_address() {
+        assert_eq!(parse_contract_address(&WETH_BASE.to_uppercase().replacen("0X", "0x", 1)).unwrap(), WETH_BASE);
+        assert!(matches!(parse_contract_address("4200000000000000000000000000000000000006"), Err(PriceError::InvalidAddress(_))));
+        assert!(matches!(parse_contract_address("0x42000000000000000000000000000000000000zz"), Err(PriceError::InvalidAddress(_))));
+    }
+    
+    #[test]
+    fn test_infer_platform() {
+        let message = |suffix: &str| format!("price of {} {}", WETH_BASE, suffix);
+        assert_eq!(infer_platform(&message(""), Platform::Base).unwrap(), Platform::Base);
+        assert_eq!(infer_platform(&message(""), Platform::Ethereum).unwrap(), Platform::Ethereum);
+        assert_eq!(infer_platform(&message("on arbitrum"), Platform::Base).unwrap(), Platform::Arbitrum);
+        assert_eq!(infer_platform(&message("on Ethereum mainnet"), Platform::Base).unwrap(), Platform::Ethereum);
+        assert_eq!(infer_platform(&message("(arbitrum-one)"), Platform::Base).unwrap(), Platform::Arbitrum);
+        assert_eq!(infer_platform(&message("on eth"), Platform::Base).unwrap(), Platform::Ethereum);
+        assert!(matches!(
+            infer_platform(&message("on polygon"), Platform::Base),
+            Err(PriceError::UnknownPlatform(name)) if name == "polygon"
+        ));
+    }
+    
+    #[test]
+    fn test_clean_description() {
+        let html = "<a href=\"https://www.coingecko.com/en/coins/chainlink\">Chainlink</a> is a decentralized \
+            oracle network that connects smart contracts to real-world data.\r\n\r\nLINK pays node operators.";
+        assert_eq!(
+            clean_description(html),
+            "Chainlink is a decentralized oracle network that connects smart contracts to real-world data."
+        );
+        
+        assert_eq!(
+            clean_description("<p>Fees &amp; rewards go to <b>veAERO</b> lockers &#8212; not LPs&#x21;</p><p>Second.</p>"),
+            "Fees & rewards go to veAERO lockers \u{2014} not LPs!"
+        );
+        assert_eq!(clean_description("\r\n<br><br>Starts late,<br/> on two lines"), "Starts late, on two lines");
+        assert_eq!(clean_description("Keeps &unknown; entities and a < b"), "Keeps &unknown; entities and a < b");
+        assert_eq!(clean_description("  <p></p>  "), "");
+    }
+    
+    #[test]
+    fn test_platform_parse() {
+        assert_eq!(Platform::parse("Base").unwrap(), Platform::Base);
+        assert_eq!(Platform::parse("arbitrum").unwrap().coingecko_id(), "arbitrum-one");
+        let error = Platform::parse("solana").unwrap_err();
+        assert_eq!(error.to_string(), "Unknown platform solana, token prices can be looked up on Base, Ethereum, Arbitrum");
+    }
+    
+    #[test]
+    fn test_closest_point() {
+        let chart: MarketChartResponse =
+            serde_json::from_str(include_str!("../tests/fixtures/coingecko/market_chart_range.json")).unwrap();
+        let midnight = 1513468800000.0;
+        assert_eq!(closest_point(&chart.prices, midnight), Some(19065.71));
+        
+        // Between two daily points the nearer one wins
+        assert_eq!(closest_point(&chart.prices, midnight + 13.0 * 3600.0 * 1000.0), Some(18972.32));
+        assert_eq!(closest_point(&chart.prices, midnight - 11.0 * 3600.0 * 1000.0), Some(19065.71));
+        
+        // Outside the window the nearest edge is taken
+        assert_eq!(closest_point(&chart.prices, 0.0), Some(16467.91));
+        assert_eq!(closest_point(&[], midnight), None);
+    }
+    
+    #[test]
+    fn test_plan_limit_mapping() {
+        assert!(is_plan_limited(StatusCode::UNAUTHORIZED));
+        assert!(is_plan_limited(StatusCode::FORBIDDEN));
+        assert!(!is_plan_limited(StatusCode::NOT_FOUND));
+        assert!(!is_plan_limited(StatusCode::TOO_MANY_REQUESTS));
+        
+        assert_eq!(plan_lookback_days(include_str!("../tests/fixtures/coingecko/history_plan_limit.json")), 365);
+        assert_eq!(plan_lookback_days("limited to querying historical data within the past 90 days"), 90);
+        assert_eq!(plan_lookback_days("Unauthorized"), DEFAULT_PLAN_LOOKBACK_DAYS);
+        
+        let error = PriceError::OutOfRange {
+            coin_id: "bitcoin".to_string(),
+            earliest: chrono::NaiveDate::from_ymd_opt(2024, 10, 7).unwrap(),
+        };
+        assert_eq!(error.to_string(), "No price data for bitcoin before 2024-10-07 on the current CoinGecko plan");
+    }
+    
+    #[test]
+    fn test_cached_price_age() {
+        let cached = |secs| CachedPrice { price: 1.0, age: Duration::from_secs(secs), cached: true, stale: false };
+        assert_eq!(cached(5).as_of().unwrap(), "as of ~1 minute ago");
+        assert_eq!(cached(100).as_of().unwrap(), "as of ~2 minutes ago");
+        assert_eq!(cached(3 * 60 * 60).as_of().unwrap(), "as of ~3 hours ago");
+        assert_eq!(CachedPrice::fetched(1.0).as_of(), None);
+    }
+    
     #[tokio::test]
+    #[ignore = "hits the live CoinGecko API"]
     async fn test_fetch_current_price() {
         let price = fetch_current_price().await;
         assert!(price.is_ok());
@@ -256,6 +1750,7 @@ mod tests {
     }
     
     #[tokio::test]
+    #[ignore = "hits the live CoinGecko API"]
     async fn test_fetch_ethereum_price() {
         let price = fetch_ethereum_price().await;
         assert!(price.is_ok());
@@ -265,6 +1760,7 @@ mod tests {
     }
     
     #[tokio::test]
+    #[ignore = "hits the live CoinGecko API"]
     async fn test_fetch_historical_price() {
         let date = "01-12-2024"; // December 1, 2024
         let price = fetch_historical_price(date).await;
@@ -274,6 +1770,7 @@ mod tests {
     }
     
     #[tokio::test]
+    #[ignore = "hits the live CoinGecko API"]
     async fn test_fetch_ethereum_historical_price() {
         let date = "01-12-2024"; // December 1, 2024
         let price = fetch_ethereum_historical_price(date).await;
@@ -283,6 +1780,7 @@ mod tests {
     }
     
     #[tokio::test]
+    #[ignore = "hits the live CoinGecko API"]
     async fn test_fetch_generic_coin_price() {
         // Test with Bitcoin
         let price = fetch_coin_price("bitcoin").await;
@@ -304,6 +1802,7 @@ mod tests {
     }
     
     #[tokio::test]
+    #[ignore = "hits the live CoinGecko API"]
     async fn test_fetch_generic_historical_price() {
         let date = "01-01-2024"; // January 1, 2024
         
@@ -329,6 +1828,7 @@ mod tests {
     }
     
     #[tokio::test]
+    #[ignore = "hits the live CoinGecko API"]
     async fn test_fetch_multiple_coin_prices() {
         let coin_ids = ["bitcoin", "ethereum", "solana", "cardano"];
         let prices = fetch_multiple_coin_prices(&coin_ids).await;
@@ -0,0 +1,172 @@
+/// Significant figures shown for prices below one cent
+const SMALL_PRICE_SIGNIFICANT_FIGURES: i32 = 4;
+
+/// Most decimals shown, anything smaller reads as zero
+const MAX_DECIMALS: i32 = 12;
+
+/// Coins up to this market cap rank count as lower volatility when their price history is unknown
+pub const LOW_VOLATILITY_MAX_RANK: u32 = 10;
+
+/// Highest annualized 30-day volatility that counts as lower volatility
+pub const LOW_VOLATILITY_MAX_ANNUALIZED: f64 = 0.9;
+
+/// Decimal places for a USD price of this magnitude
+///
+/// Prices from $1 up show cents, prices from one cent show 4 decimals and smaller prices
+/// show 4 significant figures, so $0.00001234 isn't rounded away.
+pub fn price_decimals(price: f64) -> usize {
+    let magnitude = price.abs();
+    if !magnitude.is_finite() || magnitude == 0.0 || magnitude >= 1.0 {
+        return 2;
+    }
+    if magnitude >= 0.01 {
+        return 4;
+    }
+    let leading_zeros = -magnitude.log10().floor() as i32 - 1;
+    (leading_zeros + SMALL_PRICE_SIGNIFICANT_FIGURES).clamp(4, MAX_DECIMALS) as usize
+}
+
+/// Format a USD price with decimals chosen from its magnitude, e.g. "$60000.00", "$0.0812" or "$0.00001234"
+pub fn format_price(price: f64) -> String {
+    if !price.is_finite() {
+        return "n/a".to_string();
+    }
+    let sign = if price < 0.0 { "-" } else { "" };
+    format!("{}${:.*}", sign, price_decimals(price), price.abs())
+}
+
+/// Large amounts in thousands, millions, billions or trillions, e.g. "677.10M"
+pub fn format_compact(amount: f64) -> String {
+    const UNITS: [(f64, &str); 4] = [(1e12, "T"), (1e9, "B"), (1e6, "M"), (1e3, "K")];
+    for (size, suffix) in UNITS {
+        if amount >= size {
+            return format!("{:.2}{}", amount / size, suffix);
+        }
+    }
+    format!("{:.0}", amount)
+}
+
+/// A percentage change with an arrow for its direction, e.g. "▲ 2.45%", "▼ 1.08%" or "0.00%" when flat
+pub fn format_change(change_pct: f64) -> String {
+    let arrow = if change_pct >= 0.005 {
+        "▲ "
+    } else if change_pct <= -0.005 {
+        "▼ "
+    } else {
+        ""
+    };
+    format!("{}{:.2}%", arrow, change_pct.abs())
+}
+
+/// How much a coin's price tends to swing, which sets the width of its price levels
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VolatilityClass {
+    /// Large caps and coins with calm recent price history
+    Lower,
+    /// Smaller caps, coins with large recent swings and coins we know nothing about
+    Higher,
+}
+
+impl VolatilityClass {
+    /// Support and resistance as multiples of the current price
+    pub fn level_factors(&self) -> (f64, f64) {
+        match self {
+            VolatilityClass::Lower => (0.92, 1.08),
+            VolatilityClass::Higher => (0.85, 1.15),
+        }
+    }
+
+    /// Stop loss advice matching the swings to expect
+    pub fn stop_loss_advice(&self) -> &'static str {
+        match self {
+            VolatilityClass::Lower => "Setting stop losses 5-8% below your entry price",
+            VolatilityClass::Higher => "Setting stop losses 10-15% below your entry price for this more volatile asset",
+        }
+    }
+}
+
+/// Classify a coin from its annualized 30-day volatility, or its market cap rank when the history is unavailable
+///
+/// Coins with neither are treated as more volatile.
+pub fn classify_volatility(market_cap_rank: Option<u32>, volatility_30d: Option<f64>) -> VolatilityClass {
+    let lower = match (volatility_30d, market_cap_rank) {
+        (Some(volatility), _) => volatility <= LOW_VOLATILITY_MAX_ANNUALIZED,
+        (None, Some(rank)) => rank > 0 && rank <= LOW_VOLATILITY_MAX_RANK,
+        (None, None) => false,
+    };
+    if lower { VolatilityClass::Lower } else { VolatilityClass::Higher }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_price_across_magnitudes() {
+        let cases = [
+            (1_234_567.891, "$1234567.89"),
+            (60_000.0, "$60000.00"),
+            (2_451.5, "$2451.50"),
+            (142.456, "$142.46"),
+            (1.0, "$1.00"),
+            (0.999_99, "$1.0000"),
+            (0.5, "$0.5000"),
+            (0.081_234, "$0.0812"),
+            (0.01, "$0.0100"),
+            (0.009_876_6, "$0.009877"),
+            (0.001_234_4, "$0.001234"),
+            (0.000_012_346, "$0.00001235"),
+            (0.000_000_123_4, "$0.0000001234"),
+            (0.000_000_000_000_1, "$0.000000000000"),
+            (0.0, "$0.00"),
+            (-0.5, "-$0.5000"),
+            (-2_451.5, "-$2451.50"),
+        ];
+        for (price, expected) in cases {
+            assert_eq!(format_price(price), expected, "price: {}", price);
+        }
+    }
+
+    #[test]
+    fn test_format_change_points_the_way_it_moved() {
+        assert_eq!(format_change(2.4512), "▲ 2.45%");
+        assert_eq!(format_change(-1.08), "▼ 1.08%");
+        assert_eq!(format_change(0.001), "0.00%");
+        assert_eq!(format_change(-0.004), "0.00%");
+    }
+
+    #[test]
+    fn test_format_price_non_finite() {
+        assert_eq!(format_price(f64::NAN), "n/a");
+        assert_eq!(format_price(f64::INFINITY), "n/a");
+    }
+
+    #[test]
+    fn test_small_prices_keep_significant_figures() {
+        for price in [0.009_999, 0.000_5, 0.000_012_34, 0.000_000_98] {
+            let formatted = format_price(price);
+            let digits = formatted.trim_start_matches("$0.").trim_start_matches('0');
+            assert_eq!(digits.len(), 4, "{} formatted as {}", price, formatted);
+        }
+    }
+
+    #[test]
+    fn test_classify_volatility() {
+        // Recent volatility wins over rank
+        assert_eq!(classify_volatility(Some(1), Some(1.2)), VolatilityClass::Higher);
+        assert_eq!(classify_volatility(Some(250), Some(0.45)), VolatilityClass::Lower);
+        assert_eq!(classify_volatility(None, Some(LOW_VOLATILITY_MAX_ANNUALIZED)), VolatilityClass::Lower);
+
+        assert_eq!(classify_volatility(Some(LOW_VOLATILITY_MAX_RANK), None), VolatilityClass::Lower);
+        assert_eq!(classify_volatility(Some(LOW_VOLATILITY_MAX_RANK + 1), None), VolatilityClass::Higher);
+        assert_eq!(classify_volatility(Some(0), None), VolatilityClass::Higher);
+        assert_eq!(classify_volatility(None, None), VolatilityClass::Higher);
+    }
+
+    #[test]
+    fn test_volatility_class_levels() {
+        assert_eq!(VolatilityClass::Lower.level_factors(), (0.92, 1.08));
+        assert_eq!(VolatilityClass::Higher.level_factors(), (0.85, 1.15));
+        assert!(VolatilityClass::Higher.stop_loss_advice().contains("10-15%"));
+    }
+}
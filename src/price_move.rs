@@ -0,0 +1,397 @@
+use crate::db::MessageRole;
+use crate::exa_api::{ExaApiClient, ExaApiError, ExaSearchResult};
+use crate::llm::{self, CallOptions, ChatModel, LlmError};
+use crate::price_fetcher::{CoinGeckoClient, PriceError, PricePoint};
+use crate::price_format::format_price;
+use crate::timezone::UserTimezone;
+use chrono::{DateTime, Duration, NaiveDate, NaiveDateTime, Utc};
+use regex::Regex;
+use std::collections::HashSet;
+use std::sync::OnceLock;
+use thiserror::Error;
+
+/// Stretch of prices a move is measured over
+pub const MOVE_WINDOW_HOURS: i64 = 24;
+
+/// Articles asked for from Exa, before undated and older ones are dropped
+const NEWS_SEARCH_COUNT: usize = 10;
+
+/// Articles the model is given and the reply lists
+pub const MOVE_ARTICLE_COUNT: usize = 5;
+
+/// Characters of an article's text shown to the model
+const SNIPPET_CHARS: usize = 400;
+
+/// Outlets with their own newsrooms and editors
+const TOP_SOURCES: &[&str] = &[
+    "reuters.com", "bloomberg.com", "wsj.com", "ft.com", "cnbc.com", "coindesk.com", "theblock.co", "blockworks.co",
+    "decrypt.co",
+];
+
+/// Crypto news sites that mostly report and aggregate
+const TRADE_SOURCES: &[&str] = &[
+    "cointelegraph.com", "dlnews.com", "thedefiant.io", "cryptoslate.com", "bitcoinmagazine.com", "cryptonews.com",
+    "beincrypto.com", "coinmarketcap.com", "coingecko.com",
+];
+
+/// Posts anyone can publish
+const USER_SOURCES: &[&str] = &["medium.com", "reddit.com", "x.com", "twitter.com", "youtube.com", "substack.com", "mirror.xyz"];
+
+const MOVE_PROMPT: &str = "You are Nova, a crypto investment advisor. The user asked why a coin's price moved. \
+Explain the move in one short paragraph using only the price figures and the numbered news articles given. \
+Connect the move to events the articles report and cite them by number, like [1]. If no article clearly explains \
+the move, say so and start any possible cause you mention with \"Speculation:\". Don't invent events, figures or links.";
+
+/// Errors raised while explaining a price move
+#[derive(Debug, Error)]
+pub enum MoveError {
+    #[error("Price API error: {0}")]
+    Price(#[from] PriceError),
+
+    #[error("Exa API error: {0}")]
+    Exa(#[from] ExaApiError),
+
+    #[error("LLM API error: {0}")]
+    Llm(#[from] LlmError),
+}
+
+/// Parse "why did solana move today?", "why is BTC down" and similar questions into the coin they name
+pub fn parse_move_query(message: &str) -> Option<String> {
+    static QUERY: OnceLock<Regex> = OnceLock::new();
+    let query = QUERY.get_or_init(|| {
+        Regex::new(
+            r"(?i)\bwhy\s+(?:is|did|has|was|are)\s+(?:the\s+price\s+of\s+)?([a-z][a-z0-9-]*)(?:'s)?(?:\s+price)?\s+(?:moving|move|moved|up|down|pumping|pumped|pump|dumping|dumped|dump|dropping|dropped|drop|falling|fell|fall|rising|rose|rise|rallying|rallied|rally|crashing|crashed|crash|surging|surged|surge|spiking|spiked|spike|tanking|tanked|tank)\b",
+        )
+        .unwrap()
+    });
+    let coin = query.captures(message)?[1].to_lowercase();
+    (!matches!(coin.as_str(), "it" | "the" | "crypto" | "market")).then_some(coin)
+}
+
+/// How a coin's price went from the start of a window to its last point
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct IntradayMove {
+    pub start: PricePoint,
+    pub end: PricePoint,
+    pub high_usd: f64,
+    pub low_usd: f64,
+}
+
+impl IntradayMove {
+    pub fn change_pct(&self) -> f64 {
+        (self.end.price_usd - self.start.price_usd) / self.start.price_usd * 100.0
+    }
+
+    /// Hours between the first and last point, rounded
+    pub fn hours(&self) -> i64 {
+        ((self.end.at - self.start.at).num_minutes() as f64 / 60.0).round() as i64
+    }
+}
+
+/// The move over the last `window` of `points` (oldest first), ending at the last point
+///
+/// Starts at the first point inside the window, so a chart shorter than the window gives a
+/// shorter move. None without two points in the window or with a non-positive start price.
+pub fn intraday_move(points: &[PricePoint], window: Duration) -> Option<IntradayMove> {
+    let end = *points.last()?;
+    let inside: Vec<&PricePoint> = points.iter().filter(|point| point.at >= end.at - window && point.at <= end.at).collect();
+    let start = **inside.first()?;
+    if start.at >= end.at || start.price_usd <= 0.0 {
+        return None;
+    }
+    let high_usd = inside.iter().map(|point| point.price_usd).fold(f64::MIN, f64::max);
+    let low_usd = inside.iter().map(|point| point.price_usd).fold(f64::MAX, f64::min);
+    Some(IntradayMove { start, end, high_usd, low_usd })
+}
+
+/// A news article picked to explain a move
+#[derive(Debug, Clone, PartialEq)]
+pub struct RankedArticle {
+    pub title: String,
+    pub url: String,
+    /// Host without "www.", e.g. "coindesk.com"
+    pub source: String,
+    pub published_at: DateTime<Utc>,
+    pub snippet: String,
+    pub score: f64,
+}
+
+/// Host of a URL without the scheme, port, path or "www."
+fn host(url: &str) -> String {
+    let rest = url.split_once("://").map_or(url, |(_, rest)| rest);
+    let host = rest.split(['/', '?', '#', ':']).next().unwrap_or_default().to_lowercase();
+    host.strip_prefix("www.").map(str::to_string).unwrap_or(host)
+}
+
+fn is_on(host: &str, domains: &[&str]) -> bool {
+    domains.iter().any(|domain| host == *domain || host.ends_with(&format!(".{}", domain)))
+}
+
+/// How much an outlet's reporting is trusted, from 0.2 for user posts to 1 for major newsrooms
+pub fn source_quality(host: &str) -> f64 {
+    if is_on(host, TOP_SOURCES) {
+        1.0
+    } else if is_on(host, TRADE_SOURCES) {
+        0.6
+    } else if is_on(host, USER_SOURCES) {
+        0.2
+    } else {
+        0.4
+    }
+}
+
+/// Exa's published date, which is either a timestamp or a bare date taken as midnight UTC
+fn parse_published(published: &str) -> Option<DateTime<Utc>> {
+    let published = published.trim();
+    if let Ok(at) = DateTime::parse_from_rfc3339(published) {
+        return Some(at.with_timezone(&Utc));
+    }
+    if let Ok(at) = NaiveDateTime::parse_from_str(published, "%Y-%m-%dT%H:%M:%S%.f") {
+        return Some(at.and_utc());
+    }
+    let date = NaiveDate::parse_from_str(published.get(..10)?, "%Y-%m-%d").ok()?;
+    Some(date.and_hms_opt(0, 0, 0)?.and_utc())
+}
+
+/// The articles published from the day `since` falls on, best first
+///
+/// Articles score their source's quality plus up to 1 for recency, which fades over two days.
+/// Undated articles, ones dated after `now` and repeated links are dropped.
+pub fn rank_news(results: &[ExaSearchResult], since: DateTime<Utc>, now: DateTime<Utc>) -> Vec<RankedArticle> {
+    let mut seen = HashSet::new();
+    let mut ranked: Vec<RankedArticle> = results
+        .iter()
+        .filter_map(|result| {
+            let published_at = parse_published(result.published_date.as_deref()?)?;
+            if published_at.date_naive() < since.date_naive() || published_at > now || !seen.insert(result.url.as_str()) {
+                return None;
+            }
+            let source = host(&result.url);
+            let age_hours = (now - published_at).num_minutes() as f64 / 60.0;
+            let recency = (1.0 - age_hours / 48.0).clamp(0.0, 1.0);
+            Some(RankedArticle {
+                title: result.title.trim().to_string(),
+                url: result.url.clone(),
+                score: source_quality(&source) + recency,
+                source,
+                published_at,
+                snippet: result.content.split_whitespace().collect::<Vec<_>>().join(" ").chars().take(SNIPPET_CHARS).collect(),
+            })
+        })
+        .collect();
+    ranked.sort_by(|a, b| b.score.total_cmp(&a.score).then(b.published_at.cmp(&a.published_at)));
+    ranked
+}
+
+/// "12:00 Oct 15 to 11:27 Oct 16, UTC+01:00"
+fn render_window(window: &IntradayMove, timezone: UserTimezone) -> String {
+    let local = |at: DateTime<Utc>| at.with_timezone(&timezone).format("%H:%M %b %-d").to_string();
+    format!("{} to {}, {}", local(window.start.at), local(window.end.at), timezone)
+}
+
+/// The move in figures, shown before any explanation
+pub fn render_move(display_name: &str, window: &IntradayMove, timezone: UserTimezone) -> String {
+    let change = window.change_pct();
+    let direction = if change.abs() < 0.005 {
+        "flat".to_string()
+    } else {
+        format!("{} {:.2}%", if change > 0.0 { "up" } else { "down" }, change.abs())
+    };
+    format!(
+        "{} is {} over the last {} hours ({}), from {} to {}. Range: {} to {}.",
+        display_name,
+        direction,
+        window.hours(),
+        render_window(window, timezone),
+        format_price(window.start.price_usd),
+        format_price(window.end.price_usd),
+        format_price(window.low_usd),
+        format_price(window.high_usd)
+    )
+}
+
+/// The price figures and numbered articles the model explains the move from
+pub fn move_request(display_name: &str, window: &IntradayMove, articles: &[RankedArticle]) -> String {
+    let mut request = format!(
+        "Coin: {}\nMove: {:+.2}% over {} hours, from {} to {}, ranging {} to {}\n\nNews articles:\n",
+        display_name,
+        window.change_pct(),
+        window.hours(),
+        format_price(window.start.price_usd),
+        format_price(window.end.price_usd),
+        format_price(window.low_usd),
+        format_price(window.high_usd)
+    );
+    for (i, article) in articles.iter().enumerate() {
+        request.push_str(&format!(
+            "[{}] {} ({}, {})\n{}\n\n",
+            i + 1,
+            article.title,
+            article.source,
+            article.published_at.format("%Y-%m-%d %H:%M UTC"),
+            article.snippet
+        ));
+    }
+    request
+}
+
+/// The numbered links the explanation cites
+pub fn render_sources(articles: &[RankedArticle]) -> String {
+    let mut sources = "Sources:".to_string();
+    for (i, article) in articles.iter().enumerate() {
+        sources.push_str(&format!("\n[{}] {} ({})", i + 1, article.title, article.url));
+    }
+    sources
+}
+
+/// Answer why `coin_id` moved: its move over the last day from hourly prices, then the model's
+/// explanation from the day's best-ranked news, then the links it cites
+///
+/// Without any news the model isn't asked, the reply says no cause was reported.
+pub async fn explain_move(
+    prices: &CoinGeckoClient,
+    news: &ExaApiClient,
+    model: &dyn ChatModel,
+    coin_id: &str,
+    display_name: &str,
+    now: DateTime<Utc>,
+    timezone: UserTimezone,
+) -> Result<String, MoveError> {
+    let points = prices.fetch_hourly_chart(coin_id, 2).await?;
+    let Some(window) = intraday_move(&points, Duration::hours(MOVE_WINDOW_HOURS)) else {
+        return Err(PriceError::PriceNotFound(format!("Hourly prices for {} over the last day", coin_id)).into());
+    };
+    let figures = render_move(display_name, &window, timezone);
+
+    let since = window.start.at;
+    let response = news.search_news_since(coin_id, NEWS_SEARCH_COUNT, since.date_naive()).await?;
+    let mut articles = rank_news(&response.results, since, now);
+    articles.truncate(MOVE_ARTICLE_COUNT);
+    if articles.is_empty() {
+        return Ok(format!(
+            "{}\n\nI found no news about {} from this window, so there's no reported cause for the move.",
+            figures, display_name
+        ));
+    }
+
+    let messages = [llm::Message {
+        role: MessageRole::User,
+        content: move_request(display_name, &window, &articles),
+    }];
+    let explanation = model.generate(MOVE_PROMPT, &messages, &CallOptions::new(500)).await?.text;
+    Ok(format!("{}\n\n{}\n\n{}", figures, explanation.trim(), render_sources(&articles)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{FixedOffset, TimeZone};
+
+    fn at(day: u32, hour: u32, minute: u32) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2025, 10, day, hour, minute, 0).unwrap()
+    }
+
+    fn point(day: u32, hour: u32, price_usd: f64) -> PricePoint {
+        PricePoint { at: at(day, hour, 0), price_usd }
+    }
+
+    fn result(url: &str, published_date: Option<&str>) -> ExaSearchResult {
+        ExaSearchResult {
+            id: url.to_string(),
+            url: url.to_string(),
+            title: format!("Story on {}", host(url)),
+            content: "Bitcoin   slid after\nthe ETF outflows.".to_string(),
+            score: 0.5,
+            published_date: published_date.map(str::to_string),
+            author: None,
+        }
+    }
+
+    #[test]
+    fn test_parse_move_query() {
+        assert_eq!(parse_move_query("Why did SOL move today?"), Some("sol".to_string()));
+        assert_eq!(parse_move_query("why is bitcoin down so much"), Some("bitcoin".to_string()));
+        assert_eq!(parse_move_query("why has the price of aave pumped"), Some("aave".to_string()));
+        assert_eq!(parse_move_query("why is eth's price dropping"), Some("eth".to_string()));
+        assert_eq!(parse_move_query("why is the market down"), None);
+        assert_eq!(parse_move_query("is bitcoin down today?"), None);
+        assert_eq!(parse_move_query("why is staking popular"), None);
+    }
+
+    #[test]
+    fn test_intraday_move_starts_inside_the_window() {
+        let points = [point(14, 12, 90.0), point(15, 10, 100.0), point(15, 11, 104.0), point(15, 20, 95.0), point(16, 10, 98.0)];
+        let window = intraday_move(&points, Duration::hours(24)).unwrap();
+        assert_eq!(window.start, point(15, 10, 100.0));
+        assert_eq!(window.end, point(16, 10, 98.0));
+        assert_eq!((window.high_usd, window.low_usd), (104.0, 95.0));
+        assert!((window.change_pct() - -2.0).abs() < 1e-9);
+        assert_eq!(window.hours(), 24);
+
+        // A chart shorter than the window measures what it has
+        let window = intraday_move(&points[3..], Duration::hours(24)).unwrap();
+        assert_eq!((window.hours(), window.start.price_usd), (14, 95.0));
+    }
+
+    #[test]
+    fn test_intraday_move_needs_two_points() {
+        assert_eq!(intraday_move(&[], Duration::hours(24)), None);
+        assert_eq!(intraday_move(&[point(16, 10, 98.0)], Duration::hours(24)), None);
+        // The only other point is outside the window
+        assert_eq!(intraday_move(&[point(14, 10, 90.0), point(16, 10, 98.0)], Duration::hours(24)), None);
+        assert_eq!(intraday_move(&[point(16, 9, 0.0), point(16, 10, 98.0)], Duration::hours(24)), None);
+    }
+
+    #[test]
+    fn test_news_is_ranked_by_source_and_recency() {
+        let now = at(16, 12, 0);
+        let results = [
+            result("https://medium.com/@trader/btc-crash", Some("2025-10-16T11:00:00.000Z")),
+            result("https://www.coindesk.com/markets/btc-slides", Some("2025-10-16T02:00:00.000Z")),
+            result("https://cointelegraph.com/news/btc", Some("2025-10-16T10:00:00Z")),
+            result("https://blog.example.org/btc", Some("2025-10-15")),
+            // Undated, from the day before the window, repeated and from the future
+            result("https://www.reuters.com/undated", None),
+            result("https://www.reuters.com/old", Some("2025-10-14T23:00:00Z")),
+            result("https://cointelegraph.com/news/btc", Some("2025-10-16T10:00:00Z")),
+            result("https://www.bloomberg.com/later", Some("2025-10-16T13:00:00Z")),
+        ];
+        let ranked = rank_news(&results, at(15, 12, 0), now);
+        let sources: Vec<&str> = ranked.iter().map(|article| article.source.as_str()).collect();
+        assert_eq!(sources, ["coindesk.com", "cointelegraph.com", "medium.com", "blog.example.org"]);
+        assert!((ranked[0].score - (1.0 + (1.0 - 10.0 / 48.0))).abs() < 1e-9);
+        assert_eq!(ranked[0].snippet, "Bitcoin slid after the ETF outflows.");
+        assert_eq!(ranked[3].published_at, at(15, 0, 0));
+    }
+
+    #[test]
+    fn test_source_quality() {
+        assert_eq!(source_quality(&host("https://www.reuters.com/markets")), 1.0);
+        assert_eq!(source_quality(&host("https://markets.bloomberg.com/news")), 1.0);
+        assert_eq!(source_quality(&host("https://thedefiant.io/news")), 0.6);
+        assert_eq!(source_quality(&host("http://old.reddit.com/r/bitcoin")), 0.2);
+        assert_eq!(source_quality(&host("https://notreuters.com/x")), 0.4);
+    }
+
+    #[test]
+    fn test_move_is_shown_with_its_window() {
+        let window = IntradayMove { start: point(15, 11, 112950.5), end: point(16, 10, 107780.4), high_usd: 113210.0, low_usd: 107560.0 };
+        assert_eq!(
+            render_move("Bitcoin", &window, FixedOffset::east_opt(3600).unwrap().into()),
+            "Bitcoin is down 4.58% over the last 23 hours (12:00 Oct 15 to 11:00 Oct 16, UTC+01:00), \
+            from $112950.50 to $107780.40. Range: $107560.00 to $113210.00."
+        );
+        let flat = IntradayMove { end: point(16, 11, 112950.5), ..window };
+        assert!(render_move("Bitcoin", &flat, UserTimezone::default()).starts_with("Bitcoin is flat over the last 24 hours"));
+    }
+
+    #[test]
+    fn test_request_numbers_the_articles() {
+        let window = IntradayMove { start: point(15, 12, 100.0), end: point(16, 12, 110.0), high_usd: 111.0, low_usd: 99.0 };
+        let articles = rank_news(&[result("https://www.coindesk.com/a", Some("2025-10-16T02:00:00Z"))], at(15, 12, 0), at(16, 12, 0));
+        let request = move_request("Solana", &window, &articles);
+        assert!(request.starts_with("Coin: Solana\nMove: +10.00% over 24 hours, from $100.00 to $110.00, ranging $99.00 to $111.00"));
+        assert!(request.contains("[1] Story on coindesk.com (coindesk.com, 2025-10-16 02:00 UTC)\nBitcoin slid after the ETF outflows."));
+        assert_eq!(render_sources(&articles), "Sources:\n[1] Story on coindesk.com (https://www.coindesk.com/a)");
+    }
+}
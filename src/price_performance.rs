@@ -0,0 +1,233 @@
+use crate::price_format::format_price;
+use chrono::{DateTime, Utc};
+use regex::Regex;
+use std::sync::OnceLock;
+
+/// Window a performance question that doesn't name one is answered over
+pub const DEFAULT_PERFORMANCE_DAYS: u32 = 30;
+
+/// Longest window a performance question is answered over
+pub const MAX_PERFORMANCE_DAYS: u32 = 365;
+
+/// Rise or fall of the fitted trend over the window, in percent, below which it counts as sideways
+const SIDEWAYS_PCT: f64 = 2.0;
+
+/// "how has solana performed over the last 30 days"
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PerformanceQuery {
+    pub coin: String,
+    pub days: u32,
+}
+
+/// Parse "how has solana performed over the last 30 days", "how did BTC do in the past week" and
+/// "ETH performance over the last 3 months" into the coin and window they name
+pub fn parse_performance_query(message: &str) -> Option<PerformanceQuery> {
+    static QUERY: OnceLock<Regex> = OnceLock::new();
+    static WINDOW: OnceLock<Regex> = OnceLock::new();
+    let query = QUERY.get_or_init(|| {
+        Regex::new(
+            r"(?i)\bhow\s+(?:has|have|did|is)\s+([a-z][a-z0-9-]*)\s+(?:performed|perform|done|do|fared|been\s+doing|been\s+performing)\b|\b([a-z][a-z0-9-]*)(?:'s)?\s+(?:price\s+)?performance\b",
+        )
+        .unwrap()
+    });
+    let window = WINDOW.get_or_init(|| Regex::new(r"(?i)\b(?:last|past)\s+(?:(\d+)\s+)?(day|week|month|year)s?\b").unwrap());
+
+    let caps = query.captures(message)?;
+    let coin = caps.get(1).or_else(|| caps.get(2))?.as_str().to_lowercase();
+    if matches!(coin.as_str(), "it" | "my" | "the" | "your" | "portfolio" | "crypto" | "market") {
+        return None;
+    }
+    let days = match window.captures(message) {
+        Some(window) => {
+            let count: u32 = window.get(1).and_then(|count| count.as_str().parse().ok()).unwrap_or(1);
+            let unit = match window[2].to_lowercase().as_str() {
+                "week" => 7,
+                "month" => 30,
+                "year" => 365,
+                _ => 1,
+            };
+            count.saturating_mul(unit).clamp(1, MAX_PERFORMANCE_DAYS)
+        },
+        None => DEFAULT_PERFORMANCE_DAYS,
+    };
+    Some(PerformanceQuery { coin, days })
+}
+
+/// Which way a price series leans, from a straight line fitted through it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    Up,
+    Down,
+    Sideways,
+}
+
+impl Direction {
+    pub fn label(&self) -> &'static str {
+        match self {
+            Direction::Up => "up",
+            Direction::Down => "down",
+            Direction::Sideways => "sideways",
+        }
+    }
+}
+
+/// How a coin's price went over a window
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Performance {
+    pub start: (DateTime<Utc>, f64),
+    pub end: (DateTime<Utc>, f64),
+    pub high: (DateTime<Utc>, f64),
+    pub low: (DateTime<Utc>, f64),
+    pub direction: Direction,
+}
+
+impl Performance {
+    /// The performance of `prices` (oldest first), None with fewer than two points to compare
+    pub fn from_series(prices: &[(DateTime<Utc>, f64)]) -> Option<Self> {
+        let (&start, &end) = (prices.first()?, prices.last()?);
+        if prices.len() < 2 || start.1 <= 0.0 {
+            return None;
+        }
+        let high = *prices.iter().max_by(|a, b| a.1.total_cmp(&b.1))?;
+        let low = *prices.iter().min_by(|a, b| a.1.total_cmp(&b.1))?;
+        Some(Self { start, end, high, low, direction: direction(prices) })
+    }
+
+    pub fn change_pct(&self) -> f64 {
+        (self.end.1 - self.start.1) / self.start.1 * 100.0
+    }
+}
+
+/// Rise of the least-squares line through `prices` over the window, against their mean
+fn direction(prices: &[(DateTime<Utc>, f64)]) -> Direction {
+    let n = prices.len() as f64;
+    let mean_x = (n - 1.0) / 2.0;
+    let mean_y = prices.iter().map(|(_, price)| price).sum::<f64>() / n;
+    let (covariance, variance) = prices.iter().enumerate().fold((0.0, 0.0), |(covariance, variance), (i, (_, price))| {
+        let dx = i as f64 - mean_x;
+        (covariance + dx * (price - mean_y), variance + dx * dx)
+    });
+    if variance == 0.0 || mean_y <= 0.0 {
+        return Direction::Sideways;
+    }
+    let rise_pct = covariance / variance * (n - 1.0) / mean_y * 100.0;
+    if rise_pct >= SIDEWAYS_PCT {
+        Direction::Up
+    } else if rise_pct <= -SIDEWAYS_PCT {
+        Direction::Down
+    } else {
+        Direction::Sideways
+    }
+}
+
+/// "the last 30 days", "the last 24 hours" for one day
+fn window_label(days: u32) -> String {
+    match days {
+        1 => "the last 24 hours".to_string(),
+        365 => "the last year".to_string(),
+        days => format!("the last {} days", days),
+    }
+}
+
+/// The change, range and trend of `name` over the last `days` days, noting when its history is shorter
+pub fn render_performance(name: &str, days: u32, performance: &Performance) -> String {
+    let change = performance.change_pct();
+    let moved = if change.abs() < 0.005 {
+        "flat".to_string()
+    } else {
+        format!("{} {:.2}%", if change > 0.0 { "up" } else { "down" }, change.abs())
+    };
+    let day = |at: &DateTime<Utc>| at.format("%b %-d").to_string();
+    let mut lines = vec![
+        format!(
+            "{} over {}: {}, from {} on {} to {} on {}.",
+            name,
+            window_label(days),
+            moved,
+            format_price(performance.start.1),
+            day(&performance.start.0),
+            format_price(performance.end.1),
+            day(&performance.end.0)
+        ),
+        format!(
+            "High {} on {}, low {} on {}.",
+            format_price(performance.high.1),
+            day(&performance.high.0),
+            format_price(performance.low.1),
+            day(&performance.low.0)
+        ),
+        format!("Trend: {} across the window.", performance.direction.label()),
+    ];
+    let covered = (performance.end.0 - performance.start.0).num_days();
+    if days > 2 && covered + 2 < i64::from(days) {
+        lines.push(format!(
+            "{} only has prices since {}, so this covers {} days.",
+            name,
+            performance.start.0.format("%B %-d, %Y"),
+            covered.max(1)
+        ));
+    }
+    lines.join("\n")
+}
+
+/// Reply for a coin without two prices in the window, e.g. one listed today
+pub fn render_no_history(name: &str, days: u32) -> String {
+    format!("I don't have enough price history for {} over {} to say how it performed, it may have only just been listed.", name, window_label(days))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn series(prices: &[f64]) -> Vec<(DateTime<Utc>, f64)> {
+        let start = Utc.with_ymd_and_hms(2025, 9, 16, 0, 0, 0).unwrap();
+        prices.iter().enumerate().map(|(i, price)| (start + chrono::Duration::days(i as i64), *price)).collect()
+    }
+
+    #[test]
+    fn test_parse_performance_query() {
+        let parsed = |message: &str| parse_performance_query(message).map(|query| (query.coin, query.days));
+        assert_eq!(parsed("how has solana performed over the last 30 days"), Some(("solana".to_string(), 30)));
+        assert_eq!(parsed("How did BTC do in the past week?"), Some(("btc".to_string(), 7)));
+        assert_eq!(parsed("ETH performance over the last 3 months"), Some(("eth".to_string(), 90)));
+        assert_eq!(parsed("how has aave been doing"), Some(("aave".to_string(), DEFAULT_PERFORMANCE_DAYS)));
+        assert_eq!(parsed("how has ethereum performed in the past 5 years"), Some(("ethereum".to_string(), MAX_PERFORMANCE_DAYS)));
+        assert_eq!(parsed("how has my portfolio performed"), None);
+        assert_eq!(parsed("what's the price of solana"), None);
+    }
+
+    #[test]
+    fn test_performance_of_a_series() {
+        let performance = Performance::from_series(&series(&[100.0, 95.0, 120.0, 110.0, 125.0])).unwrap();
+        assert!((performance.change_pct() - 25.0).abs() < 1e-9);
+        assert_eq!((performance.high.1, performance.low.1), (125.0, 95.0));
+        assert_eq!(performance.low.0, Utc.with_ymd_and_hms(2025, 9, 17, 0, 0, 0).unwrap());
+        assert_eq!(performance.direction, Direction::Up);
+
+        assert_eq!(Performance::from_series(&series(&[100.0, 90.0, 85.0])).unwrap().direction, Direction::Down);
+        // Ending where it started after a round trip is no trend
+        assert_eq!(Performance::from_series(&series(&[100.0, 110.0, 100.0])).unwrap().direction, Direction::Sideways);
+    }
+
+    #[test]
+    fn test_too_little_history_has_no_performance() {
+        assert!(Performance::from_series(&[]).is_none());
+        assert!(Performance::from_series(&series(&[100.0])).is_none());
+    }
+
+    #[test]
+    fn test_render_performance() {
+        let performance = Performance::from_series(&series(&[100.0, 95.0, 120.0, 110.0, 125.0])).unwrap();
+        assert_eq!(
+            render_performance("Solana", 4, &performance),
+            "Solana over the last 4 days: up 25.00%, from $100.00 on Sep 16 to $125.00 on Sep 20.\n\
+            High $125.00 on Sep 20, low $95.00 on Sep 17.\n\
+            Trend: up across the window."
+        );
+
+        // A coin listed a few days into the window says how much it covers
+        let rendered = render_performance("Solana", 30, &performance);
+        assert!(rendered.ends_with("Solana only has prices since September 16, 2025, so this covers 4 days."), "{}", rendered);
+    }
+}
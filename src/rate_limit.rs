@@ -0,0 +1,290 @@
+use crate::config::Config;
+use crate::db::{self, DbError};
+use chrono::{DateTime, Utc};
+use sqlx::{Pool, Postgres};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::Duration;
+use thiserror::Error;
+
+/// Messages a user can send at once when the burst isn't configured
+pub const DEFAULT_BURST: u32 = 5;
+
+// Float slack so a bucket refilled to exactly one token lets the message through
+const TOKEN_EPSILON: f64 = 1e-9;
+
+/// Errors raised while checking a user's rate limit
+#[derive(Debug, Error)]
+pub enum RateLimitError {
+    #[error("Slow down: too many messages, try again in {}s", .retry_after.as_millis().div_ceil(1000))]
+    SlowDown { retry_after: Duration },
+
+    #[error("Database error: {0}")]
+    Database(#[from] DbError),
+}
+
+/// Limits on how often one user can ask the agent something
+#[derive(Debug, Clone, PartialEq)]
+pub struct RateLimitSettings {
+    /// Messages per minute a user can keep sending
+    pub requests_per_minute: u32,
+    /// Messages a user can send back to back after being idle
+    pub burst: u32,
+    /// Usernames that are never limited, e.g. admins
+    pub bypass: Vec<String>,
+    /// Keep the buckets in the database, so processes sharing it enforce one limit
+    pub shared: bool,
+}
+
+impl RateLimitSettings {
+    pub fn new(requests_per_minute: u32, burst: u32) -> Self {
+        Self {
+            requests_per_minute,
+            burst,
+            bypass: Vec::new(),
+            shared: false,
+        }
+    }
+
+    fn capacity(&self) -> f64 {
+        self.burst.max(1) as f64
+    }
+
+    fn tokens_per_second(&self) -> f64 {
+        self.requests_per_minute as f64 / 60.0
+    }
+}
+
+/// Tokens a user has left, refilled at `requests_per_minute` up to `burst`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TokenBucket {
+    pub tokens: f64,
+    pub updated_at: DateTime<Utc>,
+}
+
+impl TokenBucket {
+    /// A bucket for a user who hasn't sent anything yet
+    pub fn full(settings: &RateLimitSettings, now: DateTime<Utc>) -> Self {
+        Self { tokens: settings.capacity(), updated_at: now }
+    }
+
+    /// Add the tokens earned since the last update
+    ///
+    /// A clock that went backwards adds nothing
+    pub fn refill(&mut self, settings: &RateLimitSettings, now: DateTime<Utc>) {
+        if now <= self.updated_at {
+            return;
+        }
+        let elapsed = (now - self.updated_at).num_milliseconds() as f64 / 1000.0;
+        self.tokens = (self.tokens + elapsed * settings.tokens_per_second()).min(settings.capacity());
+        self.updated_at = now;
+    }
+
+    /// Spend one token for a message, or return how long until one is available
+    pub fn try_take(&mut self, settings: &RateLimitSettings, now: DateTime<Utc>) -> Result<(), Duration> {
+        self.refill(settings, now);
+        if self.tokens + TOKEN_EPSILON >= 1.0 {
+            self.tokens = (self.tokens - 1.0).max(0.0);
+            return Ok(());
+        }
+
+        let rate = settings.tokens_per_second();
+        if rate <= 0.0 {
+            return Err(Duration::MAX);
+        }
+        // Whole milliseconds, rounded up so retrying after the wait always succeeds
+        let wait_ms = ((1.0 - self.tokens) / rate * 1000.0 - TOKEN_EPSILON).ceil();
+        Err(Duration::from_millis(wait_ms as u64))
+    }
+}
+
+/// Per-user token buckets checked before each message
+///
+/// Buckets live in memory unless the limiter has a pool, in which case they are stored in
+/// `rate_limit_buckets` and every process using that database shares them
+pub struct RateLimiter {
+    settings: RateLimitSettings,
+    buckets: Mutex<HashMap<i32, TokenBucket>>,
+    pool: Option<Pool<Postgres>>,
+}
+
+impl RateLimiter {
+    pub fn new(settings: RateLimitSettings) -> Self {
+        Self {
+            settings,
+            buckets: Mutex::new(HashMap::new()),
+            pool: None,
+        }
+    }
+
+    /// Store the buckets in the database instead of in memory
+    pub fn with_pool(mut self, pool: Pool<Postgres>) -> Self {
+        self.pool = Some(pool);
+        self
+    }
+
+    pub fn settings(&self) -> &RateLimitSettings {
+        &self.settings
+    }
+
+    /// Whether `username` is on the bypass list
+    pub fn is_exempt(&self, username: &str) -> bool {
+        self.settings.bypass.iter().any(|name| name.eq_ignore_ascii_case(username))
+    }
+
+    /// Let a message from the user through, or say how long they have to wait
+    pub async fn check(&self, user_id: i32, username: &str, now: DateTime<Utc>) -> Result<(), RateLimitError> {
+        if self.is_exempt(username) {
+            return Ok(());
+        }
+
+        let taken = match &self.pool {
+            Some(pool) => {
+                let settings = &self.settings;
+                db::update_rate_limit_bucket(pool, user_id, |stored| {
+                    let mut bucket = match stored {
+                        Some((tokens, updated_at)) => TokenBucket { tokens, updated_at: updated_at.and_utc() },
+                        None => TokenBucket::full(settings, now),
+                    };
+                    let taken = bucket.try_take(settings, now);
+                    ((bucket.tokens, bucket.updated_at.naive_utc()), taken)
+                })
+                .await?
+            },
+            None => {
+                let mut buckets = self.buckets.lock().unwrap();
+                buckets
+                    .entry(user_id)
+                    .or_insert_with(|| TokenBucket::full(&self.settings, now))
+                    .try_take(&self.settings, now)
+            },
+        };
+        taken.map_err(|retry_after| RateLimitError::SlowDown { retry_after })
+    }
+}
+
+/// The limiter configured for this process, None when rate limiting is off
+///
+/// Built once from the config; shared buckets use the first pool passed in
+pub fn configured(pool: &Pool<Postgres>) -> Option<Arc<RateLimiter>> {
+    static LIMITER: OnceLock<Option<Arc<RateLimiter>>> = OnceLock::new();
+    LIMITER
+        .get_or_init(|| {
+            let settings = Config::get_instance().ok()?.rate_limit.clone()?;
+            let shared = settings.shared;
+            let limiter = RateLimiter::new(settings);
+            Some(Arc::new(if shared { limiter.with_pool(pool.clone()) } else { limiter }))
+        })
+        .clone()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::testing::test_pool;
+    use chrono::TimeZone;
+
+    fn start() -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2025, 10, 1, 12, 0, 0).unwrap()
+    }
+
+    fn at(millis: i64) -> DateTime<Utc> {
+        start() + chrono::Duration::milliseconds(millis)
+    }
+
+    #[test]
+    fn test_burst_then_limit() {
+        // One token a second, three at once
+        let settings = RateLimitSettings::new(60, 3);
+        let mut bucket = TokenBucket::full(&settings, start());
+
+        for _ in 0..3 {
+            assert_eq!(bucket.try_take(&settings, start()), Ok(()));
+        }
+        assert_eq!(bucket.try_take(&settings, start()), Err(Duration::from_secs(1)));
+        assert_eq!(bucket.tokens, 0.0);
+    }
+
+    #[test]
+    fn test_refill_boundaries() {
+        let settings = RateLimitSettings::new(30, 1);
+        let mut bucket = TokenBucket::full(&settings, start());
+        assert_eq!(bucket.try_take(&settings, start()), Ok(()));
+
+        // Half a token after a second, the wait is for the other half
+        assert_eq!(bucket.try_take(&settings, at(1000)), Err(Duration::from_secs(1)));
+        assert_eq!(bucket.try_take(&settings, at(1999)), Err(Duration::from_millis(1)));
+        // Exactly one token at two seconds
+        assert_eq!(bucket.try_take(&settings, at(2000)), Ok(()));
+        assert_eq!(bucket.try_take(&settings, at(2000)), Err(Duration::from_secs(2)));
+    }
+
+    #[test]
+    fn test_refill_caps_at_burst() {
+        let settings = RateLimitSettings::new(60, 2);
+        let mut bucket = TokenBucket { tokens: 0.0, updated_at: start() };
+
+        bucket.refill(&settings, at(60_000));
+        assert_eq!(bucket.tokens, 2.0);
+        assert_eq!(bucket.updated_at, at(60_000));
+
+        // A clock going backwards neither adds tokens nor moves the bucket back
+        bucket.tokens = 0.5;
+        bucket.refill(&settings, at(30_000));
+        assert_eq!(bucket.tokens, 0.5);
+        assert_eq!(bucket.updated_at, at(60_000));
+    }
+
+    #[test]
+    fn test_uneven_rate_reaches_one_token() {
+        // 7 a minute: one token every 60/7 seconds
+        let settings = RateLimitSettings::new(7, 1);
+        let mut bucket = TokenBucket { tokens: 0.0, updated_at: start() };
+        assert!(bucket.try_take(&settings, at(8571)).is_err());
+        assert_eq!(bucket.try_take(&settings, at(8572)), Ok(()));
+    }
+
+    #[test]
+    fn test_zero_burst_still_allows_one_message() {
+        let settings = RateLimitSettings::new(60, 0);
+        let mut bucket = TokenBucket::full(&settings, start());
+        assert_eq!(bucket.try_take(&settings, start()), Ok(()));
+        assert!(bucket.try_take(&settings, start()).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_limiter_tracks_users_separately_and_honours_bypass() {
+        let mut settings = RateLimitSettings::new(60, 1);
+        settings.bypass = vec!["Admin".to_string()];
+        let limiter = RateLimiter::new(settings);
+
+        assert!(limiter.check(1, "alice", start()).await.is_ok());
+        let error = limiter.check(1, "alice", at(250)).await.unwrap_err();
+        assert!(matches!(error, RateLimitError::SlowDown { retry_after } if retry_after == Duration::from_millis(750)));
+        assert_eq!(error.to_string(), "Slow down: too many messages, try again in 1s");
+
+        assert!(limiter.check(2, "bob", at(250)).await.is_ok());
+        for _ in 0..10 {
+            assert!(limiter.check(3, "admin", start()).await.is_ok());
+        }
+        assert!(limiter.check(1, "alice", at(1000)).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_shared_buckets_survive_the_limiter() {
+        let Some(pool) = test_pool().await else { return };
+        let settings = RateLimitSettings::new(60, 2);
+
+        let first = RateLimiter::new(settings.clone()).with_pool(pool.clone());
+        assert!(first.check(1, "default_user", start()).await.is_ok());
+        assert!(first.check(1, "default_user", start()).await.is_ok());
+
+        // Another process sees the same empty bucket
+        let second = RateLimiter::new(settings).with_pool(pool.clone());
+        assert!(matches!(
+            second.check(1, "default_user", at(500)).await,
+            Err(RateLimitError::SlowDown { retry_after }) if retry_after == Duration::from_millis(500)
+        ));
+        assert!(second.check(1, "default_user", at(1000)).await.is_ok());
+    }
+}
@@ -0,0 +1,598 @@
+use crate::price_format::format_price;
+use crate::render::Table;
+use regex::Regex;
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+use std::sync::OnceLock;
+use thiserror::Error;
+
+/// Asset id of the stablecoin bucket, priced at $1 with amounts in USD
+pub const STABLES: &str = "stables";
+
+/// Default tolerance band: weights this many percentage points from target are left alone
+pub const DEFAULT_BAND_PCT: f64 = 1.0;
+
+/// Default smallest trade worth making, in USD
+pub const DEFAULT_MIN_TRADE_USD: f64 = 10.0;
+
+/// Swap fee assumed for fee estimates (a 0.3% DEX pool)
+pub const SWAP_FEE_RATE: f64 = 0.003;
+
+/// Rebalancing error types
+#[derive(Debug, Error, PartialEq)]
+pub enum RebalanceError {
+    #[error("I couldn't find target weights in your message. Write them like \"50% BTC, 30% ETH, 20% stables\".")]
+    NoWeights,
+
+    #[error("The weight for {asset} must be between 0% and 100%, got {percent}%.")]
+    InvalidWeight { asset: String, percent: f64 },
+
+    #[error("{0} is listed more than once in your targets.")]
+    DuplicateAsset(String),
+
+    #[error("Your portfolio is empty or has no prices. Add holdings with /portfolio set <coin> <amount> and ask again.")]
+    EmptyPortfolio,
+
+    #[error("I couldn't get a price for {0}, so I can't size its trade.")]
+    MissingPrice(String),
+}
+
+/// A target weight as written in the message
+#[derive(Debug, Clone, PartialEq)]
+pub struct TargetWeight {
+    /// Lowercase name as written, e.g. "btc" or "stables"
+    pub name: String,
+    pub percent: f64,
+}
+
+/// A target weight resolved to an asset id
+#[derive(Debug, Clone, PartialEq)]
+pub struct Target {
+    /// CoinGecko ID, or `STABLES`
+    pub asset: String,
+    pub label: String,
+    pub percent: f64,
+}
+
+/// A current position, with stablecoins merged into one `STABLES` holding
+#[derive(Debug, Clone, PartialEq)]
+pub struct Holding {
+    pub asset: String,
+    pub label: String,
+    /// Units of the coin, or USD for `STABLES`
+    pub amount: f64,
+}
+
+/// What counts as a trade worth making
+#[derive(Debug, Clone, PartialEq)]
+pub struct RebalanceSettings {
+    /// Percentage points an asset may drift from its target before it's traded
+    pub band_pct: f64,
+    /// Trades smaller than this are dust and skipped
+    pub min_trade_usd: f64,
+    /// Fee per trade as a fraction of its value, set when trading is enabled
+    pub fee_rate: Option<f64>,
+}
+
+impl Default for RebalanceSettings {
+    fn default() -> Self {
+        Self {
+            band_pct: DEFAULT_BAND_PCT,
+            min_trade_usd: DEFAULT_MIN_TRADE_USD,
+            fee_rate: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Side {
+    Buy,
+    Sell,
+}
+
+/// A buy or sell that moves an asset to its target
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Trade {
+    pub side: Side,
+    /// Units of the coin, or USD for `STABLES`
+    pub units: f64,
+    pub value_usd: f64,
+    pub fee_usd: Option<f64>,
+}
+
+/// One asset of the plan, traded or not
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Leg {
+    pub asset: String,
+    pub label: String,
+    pub price_usd: f64,
+    pub current_usd: f64,
+    pub current_pct: f64,
+    pub target_pct: f64,
+    /// False for holdings the targets don't mention, which are sold
+    pub targeted: bool,
+    /// None when the drift is within the band or the trade would be dust
+    pub trade: Option<Trade>,
+}
+
+/// Trades that bring the portfolio to the target weights
+#[derive(Debug, Clone, PartialEq)]
+pub struct RebalancePlan {
+    pub total_usd: f64,
+    pub legs: Vec<Leg>,
+    /// Sum of the weights as written, set when they didn't add up to 100% and were scaled
+    pub scaled_from: Option<f64>,
+    pub settings: RebalanceSettings,
+}
+
+impl RebalancePlan {
+    /// Legs that need a trade
+    pub fn trades(&self) -> impl Iterator<Item = (&Leg, &Trade)> {
+        self.legs.iter().filter_map(|leg| leg.trade.as_ref().map(|trade| (leg, trade)))
+    }
+
+    /// Total USD raised by sells and spent on buys
+    pub fn flows(&self) -> (f64, f64) {
+        self.trades().fold((0.0, 0.0), |(sells, buys), (_, trade)| match trade.side {
+            Side::Sell => (sells + trade.value_usd, buys),
+            Side::Buy => (sells, buys + trade.value_usd),
+        })
+    }
+
+    /// Estimated fees of every trade, when a fee rate is set
+    pub fn estimated_fees(&self) -> Option<f64> {
+        self.settings.fee_rate?;
+        Some(self.trades().filter_map(|(_, trade)| trade.fee_usd).sum())
+    }
+}
+
+fn rebalance_regex() -> &'static Regex {
+    static REGEX: OnceLock<Regex> = OnceLock::new();
+    REGEX.get_or_init(|| Regex::new(r"(?i)\b(?:rebalanc\w*|reallocat\w*)\b").unwrap())
+}
+
+// "50% BTC", "20% in stables"
+fn percent_first_regex() -> &'static Regex {
+    static REGEX: OnceLock<Regex> = OnceLock::new();
+    REGEX.get_or_init(|| Regex::new(r"(?i)(\d+(?:\.\d+)?)\s*%\s*(?:(?:in|of|into)\s+)?([a-z][a-z0-9-]*)").unwrap())
+}
+
+// "BTC 50%", "ETH: 30%", "stables at 20%"
+fn name_first_regex() -> &'static Regex {
+    static REGEX: OnceLock<Regex> = OnceLock::new();
+    REGEX.get_or_init(|| Regex::new(r"(?i)\b([a-z][a-z0-9-]*)\s*(?:(?:at|=)\s*|:\s*)?(\d+(?:\.\d+)?)\s*%").unwrap())
+}
+
+/// Words that sit next to a percentage without being an asset
+const NOT_ASSETS: &[&str] = &[
+    "to", "at", "in", "of", "into", "and", "with", "my", "is", "be", "for", "about", "rebalance", "portfolio", "keep", "put",
+    "hold", "each", "the", "a", "an",
+];
+
+/// Names that mean the stablecoin bucket
+const STABLE_NAMES: &[&str] = &["stables", "stable", "stablecoin", "stablecoins", "cash", "usd", "usdc", "usdt", "dai"];
+
+/// Whether a name written in a target means the stablecoin bucket
+pub fn is_stables(name: &str) -> bool {
+    STABLE_NAMES.contains(&name.to_lowercase().as_str())
+}
+
+/// Whether a message asks to rebalance the portfolio
+pub fn is_rebalance_query(message: &str) -> bool {
+    rebalance_regex().is_match(message)
+}
+
+fn collect_weights<'a>(captures: impl Iterator<Item = (&'a str, &'a str)>) -> Vec<(String, &'a str)> {
+    captures
+        .map(|(name, percent)| (name.to_lowercase(), percent))
+        .filter(|(name, _)| !NOT_ASSETS.contains(&name.as_str()))
+        .collect()
+}
+
+/// Parse "50% BTC, 30% ETH, 20% stables" or "BTC 50%, ETH 30%" into weights in the order written
+///
+/// Weights don't have to add up to 100, `plan_rebalance` scales them
+pub fn parse_target_weights(message: &str) -> Result<Vec<TargetWeight>, RebalanceError> {
+    let percent_first = collect_weights(percent_first_regex().captures_iter(message).map(|captures| {
+        let (_, [percent, name]) = captures.extract();
+        (name, percent)
+    }));
+    let name_first = collect_weights(name_first_regex().captures_iter(message).map(|captures| {
+        let (_, [name, percent]) = captures.extract();
+        (name, percent)
+    }));
+    // "BTC 50% ETH 30%" also reads as "50% ETH", whichever style finds more weights was meant
+    let found = if name_first.len() > percent_first.len() { name_first } else { percent_first };
+    if found.is_empty() {
+        return Err(RebalanceError::NoWeights);
+    }
+
+    let mut seen = HashSet::new();
+    let mut weights = Vec::with_capacity(found.len());
+    for (name, percent) in found {
+        let percent: f64 = percent.parse().map_err(|_| RebalanceError::NoWeights)?;
+        if percent > 100.0 {
+            return Err(RebalanceError::InvalidWeight { asset: name.to_uppercase(), percent });
+        }
+        if !seen.insert(name.clone()) {
+            return Err(RebalanceError::DuplicateAsset(name.to_uppercase()));
+        }
+        weights.push(TargetWeight { name, percent });
+    }
+    Ok(weights)
+}
+
+/// Work out the trades that move `holdings` to `targets`
+///
+/// `prices` are USD prices by asset id; `STABLES` is always $1. Weights are scaled to add up to 100%,
+/// and holdings the targets don't mention get a 0% target. A trade is skipped when the asset is within
+/// the band of its target or the trade is smaller than the minimum.
+pub fn plan_rebalance(
+    holdings: &[Holding],
+    targets: &[Target],
+    prices: &HashMap<String, f64>,
+    settings: &RebalanceSettings,
+) -> Result<RebalancePlan, RebalanceError> {
+    let mut seen = HashSet::new();
+    for target in targets {
+        if !target.percent.is_finite() || target.percent < 0.0 || target.percent > 100.0 {
+            return Err(RebalanceError::InvalidWeight { asset: target.label.clone(), percent: target.percent });
+        }
+        if !seen.insert(target.asset.as_str()) {
+            return Err(RebalanceError::DuplicateAsset(target.label.clone()));
+        }
+    }
+    let weight_sum: f64 = targets.iter().map(|target| target.percent).sum();
+    if weight_sum <= 0.0 {
+        return Err(RebalanceError::NoWeights);
+    }
+    let scaled_from = ((weight_sum - 100.0).abs() > 0.01).then_some(weight_sum);
+
+    let price_of = |asset: &str, label: &str| -> Result<f64, RebalanceError> {
+        if asset == STABLES {
+            return Ok(1.0);
+        }
+        match prices.get(asset) {
+            Some(price) if price.is_finite() && *price > 0.0 => Ok(*price),
+            _ => Err(RebalanceError::MissingPrice(label.to_string())),
+        }
+    };
+
+    // Amounts per asset, a coin held twice counts once
+    let mut held: HashMap<&str, f64> = HashMap::new();
+    for holding in holdings.iter().filter(|holding| holding.amount > 0.0) {
+        *held.entry(holding.asset.as_str()).or_default() += holding.amount;
+    }
+    let mut total_usd = 0.0;
+    for holding in holdings.iter().filter(|holding| holding.amount > 0.0) {
+        total_usd += holding.amount * price_of(&holding.asset, &holding.label)?;
+    }
+    if total_usd <= 0.0 {
+        return Err(RebalanceError::EmptyPortfolio);
+    }
+
+    let mut assets: Vec<(&str, &str, f64, bool)> = targets
+        .iter()
+        .map(|target| (target.asset.as_str(), target.label.as_str(), target.percent / weight_sum * 100.0, true))
+        .collect();
+    for holding in holdings {
+        if holding.amount > 0.0 && !assets.iter().any(|(asset, ..)| *asset == holding.asset) {
+            assets.push((&holding.asset, &holding.label, 0.0, false));
+        }
+    }
+
+    let mut legs = Vec::with_capacity(assets.len());
+    for (asset, label, target_pct, targeted) in assets {
+        let price_usd = price_of(asset, label)?;
+        let current_usd = held.get(asset).copied().unwrap_or(0.0) * price_usd;
+        let current_pct = current_usd / total_usd * 100.0;
+        let difference = target_pct / 100.0 * total_usd - current_usd;
+
+        let within_band = (target_pct - current_pct).abs() < settings.band_pct;
+        let dust = difference.abs() < settings.min_trade_usd;
+        let trade = (!within_band && !dust).then(|| Trade {
+            side: if difference > 0.0 { Side::Buy } else { Side::Sell },
+            units: difference.abs() / price_usd,
+            value_usd: difference.abs(),
+            fee_usd: settings.fee_rate.map(|rate| difference.abs() * rate),
+        });
+
+        legs.push(Leg {
+            asset: asset.to_string(),
+            label: label.to_string(),
+            price_usd,
+            current_usd,
+            current_pct,
+            target_pct,
+            targeted,
+            trade,
+        });
+    }
+
+    Ok(RebalancePlan { total_usd, legs, scaled_from, settings: settings.clone() })
+}
+
+fn format_units(leg: &Leg, units: f64) -> String {
+    if leg.asset == STABLES {
+        format!("${:.2} of stablecoins", units)
+    } else {
+        format!("{:.6} {}", units, leg.label)
+    }
+}
+
+/// Render the plan with one line per asset and the fee estimate
+pub fn render_plan(plan: &RebalancePlan) -> String {
+    let mut output = format!("Rebalancing ${:.2}:\n\n", plan.total_usd);
+    if let Some(sum) = plan.scaled_from {
+        output.push_str(&format!("Your weights add up to {}%, so I scaled them to 100%.\n\n", sum));
+    }
+
+    let mut table = Table::new(["Asset", "Now", "Target", "Trade", "Value", "Price", "Note"]);
+    for leg in &plan.legs {
+        let price = if leg.asset == STABLES { "-".to_string() } else { format_price(leg.price_usd) };
+        let (trade, value, note) = match &leg.trade {
+            Some(trade) => {
+                let verb = match trade.side {
+                    Side::Buy => "buy",
+                    Side::Sell if !leg.targeted => "sell all",
+                    Side::Sell => "sell",
+                };
+                let note = if leg.targeted { "" } else { "not in your targets" };
+                (format!("{} {}", verb, format_units(leg, trade.units)), format!("${:.2}", trade.value_usd), note.to_string())
+            },
+            None if (leg.target_pct - leg.current_pct).abs() < plan.settings.band_pct => {
+                ("no trade".to_string(), "-".to_string(), format!("within the {}% band", plan.settings.band_pct))
+            },
+            None => ("no trade".to_string(), "-".to_string(), format!("under ${:.2}", plan.settings.min_trade_usd)),
+        };
+        table.push_row([
+            leg.label.clone(),
+            format!("{:.1}%", leg.current_pct),
+            format!("{:.1}%", leg.target_pct),
+            trade,
+            value,
+            price,
+            note,
+        ]);
+    }
+    output.push_str(&table.render());
+    output.push('\n');
+
+    let (sells, buys) = plan.flows();
+    if sells == 0.0 && buys == 0.0 {
+        output.push_str("\nEverything is within the band already, nothing to trade.");
+        return output;
+    }
+    output.push_str(&format!("\nSells raise ${:.2} and buys spend ${:.2}.", sells, buys));
+    if let (Some(fees), Some(rate)) = (plan.estimated_fees(), plan.settings.fee_rate) {
+        output.push_str(&format!(
+            " Estimated fees: ${:.2} ({:.2}% per trade), which leaves the result slightly under target.",
+            fees,
+            rate * 100.0
+        ));
+    }
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn close(a: f64, b: f64) -> bool {
+        (a - b).abs() < 1e-6
+    }
+
+    fn weights(message: &str) -> Vec<(String, f64)> {
+        parse_target_weights(message)
+            .unwrap()
+            .into_iter()
+            .map(|weight| (weight.name, weight.percent))
+            .collect()
+    }
+
+    fn target(asset: &str, percent: f64) -> Target {
+        Target { asset: asset.to_string(), label: asset.to_uppercase(), percent }
+    }
+
+    fn holding(asset: &str, amount: f64) -> Holding {
+        Holding { asset: asset.to_string(), label: asset.to_uppercase(), amount }
+    }
+
+    fn prices() -> HashMap<String, f64> {
+        HashMap::from([("btc".to_string(), 50_000.0), ("eth".to_string(), 2_000.0), ("doge".to_string(), 0.1)])
+    }
+
+    fn leg<'a>(plan: &'a RebalancePlan, asset: &str) -> &'a Leg {
+        plan.legs.iter().find(|leg| leg.asset == asset).unwrap()
+    }
+
+    #[test]
+    fn test_detects_rebalance_queries() {
+        assert!(is_rebalance_query("Rebalance my portfolio to 50% BTC, 30% ETH, 20% stables"));
+        assert!(is_rebalance_query("how should I reallocate to 60% eth"));
+        assert!(!is_rebalance_query("is my portfolio diversified?"));
+    }
+
+    #[test]
+    fn test_parse_percent_first() {
+        assert_eq!(
+            weights("Rebalance my portfolio to 50% BTC, 30% ETH, 20% stables"),
+            vec![("btc".to_string(), 50.0), ("eth".to_string(), 30.0), ("stables".to_string(), 20.0)]
+        );
+        assert_eq!(
+            weights("rebalance: 62.5 % eth and 37.5% in usdc"),
+            vec![("eth".to_string(), 62.5), ("usdc".to_string(), 37.5)]
+        );
+    }
+
+    #[test]
+    fn test_parse_name_first() {
+        assert_eq!(
+            weights("rebalance to BTC 50%, ETH: 30%, stables at 20%"),
+            vec![("btc".to_string(), 50.0), ("eth".to_string(), 30.0), ("stables".to_string(), 20.0)]
+        );
+        // Without commas "50% ETH" also matches, the style with more weights wins
+        assert_eq!(
+            weights("rebalance BTC 50% ETH 30% SOL 20%"),
+            vec![("btc".to_string(), 50.0), ("eth".to_string(), 30.0), ("sol".to_string(), 20.0)]
+        );
+    }
+
+    #[test]
+    fn test_parse_rejects_bad_weights() {
+        assert_eq!(parse_target_weights("rebalance my portfolio"), Err(RebalanceError::NoWeights));
+        assert_eq!(parse_target_weights("rebalance to 20%"), Err(RebalanceError::NoWeights));
+        assert_eq!(
+            parse_target_weights("rebalance to 150% BTC"),
+            Err(RebalanceError::InvalidWeight { asset: "BTC".to_string(), percent: 150.0 })
+        );
+        assert_eq!(
+            parse_target_weights("rebalance to 50% BTC, 30% btc"),
+            Err(RebalanceError::DuplicateAsset("BTC".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_stable_names() {
+        assert!(is_stables("Stables"));
+        assert!(is_stables("usdc"));
+        assert!(is_stables("cash"));
+        assert!(!is_stables("eth"));
+    }
+
+    #[test]
+    fn test_plan_trade_sizes() {
+        // $50k BTC + $40k ETH + $10k stables = $100k
+        let holdings = vec![holding("btc", 1.0), holding("eth", 20.0), holding(STABLES, 10_000.0)];
+        let targets = vec![target("btc", 50.0), target("eth", 30.0), target(STABLES, 20.0)];
+
+        let plan = plan_rebalance(&holdings, &targets, &prices(), &RebalanceSettings::default()).unwrap();
+
+        assert!(close(plan.total_usd, 100_000.0));
+        assert_eq!(plan.scaled_from, None);
+        assert_eq!(leg(&plan, "btc").trade, None);
+        let eth = leg(&plan, "eth").trade.as_ref().unwrap();
+        assert_eq!(eth.side, Side::Sell);
+        assert!(close(eth.value_usd, 10_000.0));
+        assert!(close(eth.units, 5.0));
+        let stables = leg(&plan, STABLES).trade.as_ref().unwrap();
+        assert_eq!(stables.side, Side::Buy);
+        assert!(close(stables.units, 10_000.0));
+        assert_eq!(plan.flows(), (10_000.0, 10_000.0));
+        assert_eq!(plan.estimated_fees(), None);
+    }
+
+    #[test]
+    fn test_plan_scales_weights_that_dont_add_up() {
+        let holdings = vec![holding("btc", 1.0), holding("eth", 25.0)];
+
+        // 60 + 20 = 80 scales to 75% / 25%
+        let plan = plan_rebalance(&holdings, &[target("btc", 60.0), target("eth", 20.0)], &prices(), &RebalanceSettings::default()).unwrap();
+        assert_eq!(plan.scaled_from, Some(80.0));
+        assert!(close(leg(&plan, "btc").target_pct, 75.0));
+        assert!(close(leg(&plan, "eth").target_pct, 25.0));
+        let btc = leg(&plan, "btc").trade.as_ref().unwrap();
+        assert_eq!(btc.side, Side::Buy);
+        assert!(close(btc.value_usd, 25_000.0));
+        assert!(close(btc.units, 0.5));
+
+        // 80 + 40 = 120 scales down the same way
+        let plan = plan_rebalance(&holdings, &[target("btc", 80.0), target("eth", 40.0)], &prices(), &RebalanceSettings::default()).unwrap();
+        assert_eq!(plan.scaled_from, Some(120.0));
+        assert!(close(leg(&plan, "btc").target_pct, 200.0 / 3.0));
+    }
+
+    #[test]
+    fn test_plan_sells_untargeted_and_buys_unheld_assets() {
+        let holdings = vec![holding("btc", 1.0), holding("doge", 100_000.0)];
+        let targets = vec![target("btc", 50.0), target("eth", 50.0)];
+
+        let plan = plan_rebalance(&holdings, &targets, &prices(), &RebalanceSettings::default()).unwrap();
+
+        assert!(close(plan.total_usd, 60_000.0));
+        let doge = leg(&plan, "doge");
+        assert!(!doge.targeted);
+        assert_eq!(doge.target_pct, 0.0);
+        let sell = doge.trade.as_ref().unwrap();
+        assert_eq!(sell.side, Side::Sell);
+        assert!(close(sell.units, 100_000.0));
+        let eth = leg(&plan, "eth");
+        assert_eq!(eth.current_usd, 0.0);
+        assert!(close(eth.trade.as_ref().unwrap().units, 15.0));
+        assert_eq!(plan.legs.iter().map(|leg| leg.asset.as_str()).collect::<Vec<_>>(), vec!["btc", "eth", "doge"]);
+    }
+
+    #[test]
+    fn test_band_and_dust_skip_small_trades() {
+        let holdings = vec![holding("btc", 1.0), holding("eth", 24.75)];
+
+        // BTC is 50.25% of $99.5k, 0.25 points from its target
+        let plan = plan_rebalance(&holdings, &[target("btc", 50.0), target("eth", 50.0)], &prices(), &RebalanceSettings::default()).unwrap();
+        assert_eq!(plan.trades().count(), 0);
+        assert!(render_plan(&plan).contains("BTC    50.3%   50.0%  no trade  -      $50000.00  within the 1% band"));
+        assert!(render_plan(&plan).contains("nothing to trade"));
+
+        // Without a band the same drift is still dust on a small portfolio
+        let small = vec![holding("eth", 0.01), holding(STABLES, 21.0)];
+        let settings = RebalanceSettings { band_pct: 0.0, ..RebalanceSettings::default() };
+        let plan = plan_rebalance(&small, &[target("eth", 50.0), target(STABLES, 50.0)], &prices(), &settings).unwrap();
+        assert_eq!(plan.trades().count(), 0);
+        assert!(render_plan(&plan).contains("ETH      48.8%   50.0%  no trade  -      $2000.00  under $10.00"));
+    }
+
+    #[test]
+    fn test_fees_when_trading_is_enabled() {
+        let holdings = vec![holding("btc", 1.0), holding(STABLES, 50_000.0)];
+        let settings = RebalanceSettings { fee_rate: Some(SWAP_FEE_RATE), ..RebalanceSettings::default() };
+
+        let plan = plan_rebalance(&holdings, &[target("btc", 80.0), target(STABLES, 20.0)], &prices(), &settings).unwrap();
+
+        let btc = leg(&plan, "btc").trade.as_ref().unwrap();
+        assert!(close(btc.value_usd, 30_000.0));
+        assert!(close(btc.fee_usd.unwrap(), 90.0));
+        assert!(close(plan.estimated_fees().unwrap(), 180.0));
+        assert!(render_plan(&plan).contains("Estimated fees: $180.00 (0.30% per trade)"));
+    }
+
+    #[test]
+    fn test_plan_errors() {
+        let holdings = vec![holding("btc", 1.0)];
+        let settings = RebalanceSettings::default();
+
+        assert_eq!(
+            plan_rebalance(&holdings, &[target("pepe", 100.0)], &prices(), &settings),
+            Err(RebalanceError::MissingPrice("PEPE".to_string()))
+        );
+        assert_eq!(
+            plan_rebalance(&[holding("pepe", 5.0)], &[target("btc", 100.0)], &prices(), &settings),
+            Err(RebalanceError::MissingPrice("PEPE".to_string()))
+        );
+        assert_eq!(plan_rebalance(&[], &[target("btc", 100.0)], &prices(), &settings), Err(RebalanceError::EmptyPortfolio));
+        assert_eq!(plan_rebalance(&holdings, &[target("btc", 0.0)], &prices(), &settings), Err(RebalanceError::NoWeights));
+        assert_eq!(
+            plan_rebalance(&holdings, &[target("btc", 50.0), target("btc", 50.0)], &prices(), &settings),
+            Err(RebalanceError::DuplicateAsset("BTC".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_render_plan() {
+        let holdings = vec![holding("btc", 1.0), holding("eth", 20.0), holding("doge", 100_000.0)];
+        let plan = plan_rebalance(&holdings, &[target("btc", 70.0), target(STABLES, 35.0)], &prices(), &RebalanceSettings::default()).unwrap();
+
+        let output = render_plan(&plan);
+
+        assert!(output.starts_with("Rebalancing $100000.00:"));
+        assert!(output.contains("Your weights add up to 105%, so I scaled them to 100%."));
+        assert!(output.contains(
+            "Asset      Now  Target  Trade                             Value      Price  Note\n\
+            -------  -----  ------  ----------------------------  ---------  ---------  -------------------\n\
+            BTC      50.0%   66.7%  buy 0.333333 BTC              $16666.67  $50000.00\n\
+            STABLES   0.0%   33.3%  buy $33333.33 of stablecoins  $33333.33          -\n\
+            ETH      40.0%    0.0%  sell all 20.000000 ETH        $40000.00   $2000.00  not in your targets\n\
+            DOGE     10.0%    0.0%  sell all 100000.000000 DOGE   $10000.00    $0.1000  not in your targets\n"
+        ));
+        assert!(output.contains("Sells raise $50000.00 and buys spend $50000.00."));
+    }
+}
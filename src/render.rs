@@ -0,0 +1,309 @@
+use regex::Regex;
+use std::sync::OnceLock;
+
+/// Cells shown in numeric columns when there's no value, they don't stop a column being numeric
+const BLANK_CELLS: &[&str] = &["", "-", "n/a"];
+
+const ELLIPSIS: char = '…';
+
+/// Column alignment, numeric columns are right-aligned
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Align {
+    Left,
+    Right,
+}
+
+/// Rows of text rendered as aligned columns for the terminal or as a markdown table,
+/// e.g. `Table::new(["Coin", "Price"]).row(["bitcoin", "$67500.00"]).render()`
+///
+/// Columns whose cells all look like numbers ("$1,200.50", "-3.2%", "0.5") are right-aligned.
+/// Widths count terminal columns, so wide characters such as emoji take two, and cells longer
+/// than the maximum width are cut with an ellipsis.
+#[derive(Debug, Clone, Default)]
+pub struct Table {
+    headers: Vec<String>,
+    rows: Vec<Vec<String>>,
+    max_width: Option<usize>,
+}
+
+impl Table {
+    pub fn new<I, S>(headers: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        Self {
+            headers: headers.into_iter().map(Into::into).collect(),
+            rows: Vec::new(),
+            max_width: None,
+        }
+    }
+
+    /// Add a row, missing cells are blank and extra ones are dropped
+    pub fn row<I, S>(mut self, cells: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.push_row(cells);
+        self
+    }
+
+    /// Add a row to a table built in a loop
+    pub fn push_row<I, S>(&mut self, cells: I)
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        let mut row: Vec<String> = cells.into_iter().map(Into::into).take(self.headers.len()).collect();
+        row.resize(self.headers.len(), String::new());
+        self.rows.push(row);
+    }
+
+    /// Cut cells wider than `width` terminal columns
+    pub fn max_width(mut self, width: usize) -> Self {
+        self.max_width = Some(width.max(1));
+        self
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rows.is_empty()
+    }
+
+    /// Aligned columns separated by two spaces, with the headers underlined
+    pub fn render(&self) -> String {
+        let (headers, rows) = self.cells(|cell| cell.to_string());
+        let aligns = self.aligns(&rows);
+        let widths = widths(&headers, &rows);
+
+        let mut lines = Vec::with_capacity(rows.len() + 2);
+        lines.push(join_padded(&headers, &widths, &aligns, "  "));
+        lines.push(widths.iter().map(|&width| "-".repeat(width)).collect::<Vec<_>>().join("  "));
+        for row in &rows {
+            lines.push(join_padded(row, &widths, &aligns, "  "));
+        }
+        lines.iter().map(|line| line.trim_end()).collect::<Vec<_>>().join("\n")
+    }
+
+    /// A markdown table with padded columns, numeric ones marked right-aligned
+    pub fn render_markdown(&self) -> String {
+        let (headers, rows) = self.cells(|cell| cell.replace('|', "\\|"));
+        let aligns = self.aligns(&rows);
+        let widths = widths(&headers, &rows);
+
+        let mut lines = Vec::with_capacity(rows.len() + 2);
+        lines.push(format!("| {} |", join_padded(&headers, &widths, &aligns, " | ")));
+        let rule: Vec<String> = widths
+            .iter()
+            .zip(&aligns)
+            .map(|(&width, align)| match align {
+                Align::Left => "-".repeat(width + 2),
+                Align::Right => format!("{}:", "-".repeat(width + 1)),
+            })
+            .collect();
+        lines.push(format!("|{}|", rule.join("|")));
+        for row in &rows {
+            lines.push(format!("| {} |", join_padded(row, &widths, &aligns, " | ")));
+        }
+        lines.join("\n")
+    }
+
+    /// Headers and rows on one line each, escaped and cut to the maximum width
+    fn cells(&self, escape: impl Fn(&str) -> String) -> (Vec<String>, Vec<Vec<String>>) {
+        let prepare = |cell: &String| {
+            let flat = escape(&cell.split_whitespace().collect::<Vec<_>>().join(" "));
+            match self.max_width {
+                Some(width) => truncate(&flat, width),
+                None => flat,
+            }
+        };
+        let headers = self.headers.iter().map(prepare).collect();
+        let rows = self.rows.iter().map(|row| row.iter().map(prepare).collect()).collect();
+        (headers, rows)
+    }
+
+    fn aligns(&self, rows: &[Vec<String>]) -> Vec<Align> {
+        (0..self.headers.len())
+            .map(|column| {
+                let mut values = rows.iter().map(|row| row[column].as_str()).filter(|cell| !BLANK_CELLS.contains(cell)).peekable();
+                if values.peek().is_some() && values.all(is_numeric) { Align::Right } else { Align::Left }
+            })
+            .collect()
+    }
+}
+
+fn widths(headers: &[String], rows: &[Vec<String>]) -> Vec<usize> {
+    headers
+        .iter()
+        .enumerate()
+        .map(|(column, header)| rows.iter().map(|row| display_width(&row[column])).fold(display_width(header), usize::max))
+        .collect()
+}
+
+fn join_padded(cells: &[String], widths: &[usize], aligns: &[Align], separator: &str) -> String {
+    cells
+        .iter()
+        .zip(widths)
+        .zip(aligns)
+        .map(|((cell, &width), align)| {
+            let padding = " ".repeat(width - display_width(cell));
+            match align {
+                Align::Left => format!("{}{}", cell, padding),
+                Align::Right => format!("{}{}", padding, cell),
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(separator)
+}
+
+/// Whether a cell reads as a number, with an optional sign, dollar sign, separators and percent
+pub fn is_numeric(cell: &str) -> bool {
+    static NUMBER: OnceLock<Regex> = OnceLock::new();
+    NUMBER
+        .get_or_init(|| Regex::new(r"^[+-]?\$?[+-]?\d[\d,]*(?:\.\d+)?%?$").unwrap())
+        .is_match(cell)
+}
+
+/// Terminal columns taken by the text
+pub fn display_width(text: &str) -> usize {
+    text.chars().map(char_width).sum()
+}
+
+/// Terminal columns of one character: 0 for combining marks and joiners, 2 for wide characters
+fn char_width(c: char) -> usize {
+    match c as u32 {
+        0x0300..=0x036F | 0x200B..=0x200F | 0x20D0..=0x20FF | 0xFE00..=0xFE0F => 0,
+        0x1100..=0x115F
+        | 0x2E80..=0x303E
+        | 0x3041..=0x33FF
+        | 0x3400..=0x4DBF
+        | 0x4E00..=0x9FFF
+        | 0xA000..=0xA4CF
+        | 0xAC00..=0xD7A3
+        | 0xF900..=0xFAFF
+        | 0xFE30..=0xFE4F
+        | 0xFF00..=0xFF60
+        | 0xFFE0..=0xFFE6
+        | 0x1F300..=0x1F64F
+        | 0x1F680..=0x1F6FF
+        | 0x1F900..=0x1F9FF
+        | 0x20000..=0x3FFFD => 2,
+        _ => 1,
+    }
+}
+
+/// Cut text to `width` terminal columns, ending with an ellipsis when anything was cut
+pub fn truncate(text: &str, width: usize) -> String {
+    if display_width(text) <= width {
+        return text.to_string();
+    }
+
+    let mut cut = String::new();
+    let mut used = 0;
+    for c in text.chars() {
+        let char_width = char_width(c);
+        if used + char_width > width - 1 {
+            break;
+        }
+        cut.push(c);
+        used += char_width;
+    }
+    cut.truncate(cut.trim_end().len());
+    cut.push(ELLIPSIS);
+    cut
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn holdings() -> Table {
+        Table::new(["Coin", "Amount", "Price", "24h"])
+            .row(["bitcoin", "0.5", "$67,500.00", "+2.45%"])
+            .row(["shiba-inu", "12,000,000", "$0.00001234", "-10.20%"])
+            .row(["obscure", "10", "n/a", "n/a"])
+    }
+
+    #[test]
+    fn test_render_aligns_numeric_columns_right() {
+        assert_eq!(
+            holdings().render(),
+            "Coin           Amount        Price      24h\n\
+             ---------  ----------  -----------  -------\n\
+             bitcoin           0.5   $67,500.00   +2.45%\n\
+             shiba-inu  12,000,000  $0.00001234  -10.20%\n\
+             obscure            10          n/a      n/a"
+        );
+    }
+
+    #[test]
+    fn test_render_markdown() {
+        assert_eq!(
+            holdings().render_markdown(),
+            "| Coin      |     Amount |       Price |     24h |\n\
+             |-----------|-----------:|------------:|--------:|\n\
+             | bitcoin   |        0.5 |  $67,500.00 |  +2.45% |\n\
+             | shiba-inu | 12,000,000 | $0.00001234 | -10.20% |\n\
+             | obscure   |         10 |         n/a |     n/a |"
+        );
+    }
+
+    #[test]
+    fn test_unicode_cells_are_measured_in_terminal_columns() {
+        let table = Table::new(["Coin", "Note", "Change"])
+            .row(["🚀 pepe", "to the moon", "+120%"])
+            .row(["ÉTH", "café ✓", "-1.5%"])
+            .row(["比特币", "", "0%"]);
+
+        assert_eq!(
+            table.render(),
+            "Coin     Note         Change\n\
+             -------  -----------  ------\n\
+             🚀 pepe  to the moon   +120%\n\
+             ÉTH      café ✓        -1.5%\n\
+             比特币                    0%"
+        );
+        assert_eq!(display_width("🚀 pepe"), 7);
+        // A combining accent takes no column of its own
+        assert_eq!(display_width("e\u{301}th"), 3);
+    }
+
+    #[test]
+    fn test_long_cells_are_truncated() {
+        let table = Table::new(["Name", "Description"])
+            .row(["SOL DCA", "Buy a fixed amount of SOL every week, whatever the price does, and never sell before 2030"])
+            .row(["Grid | range", "Trade\nbetween two levels"])
+            .max_width(24);
+
+        assert_eq!(
+            table.render(),
+            "Name          Description\n\
+             ------------  ------------------------\n\
+             SOL DCA       Buy a fixed amount of S…\n\
+             Grid | range  Trade between two levels"
+        );
+        assert!(table.render_markdown().contains("| Grid \\| range | Trade between two levels |"));
+        assert_eq!(truncate("比特币比特币", 6), "比特…");
+        assert_eq!(truncate("short", 5), "short");
+    }
+
+    #[test]
+    fn test_rows_are_padded_to_the_headers() {
+        let mut table = Table::new(["Coin", "Amount", "Note"]);
+        assert!(table.is_empty());
+        table.push_row(["eth"]);
+        table.push_row(["sol", "3", "staked", "ignored"]);
+
+        assert_eq!(table.render(), "Coin  Amount  Note\n----  ------  ------\neth\nsol        3  staked");
+    }
+
+    #[test]
+    fn test_is_numeric() {
+        for cell in ["0.5", "$1,200.50", "-3.2%", "+10.00%", "$-4.00", "42"] {
+            assert!(is_numeric(cell), "{}", cell);
+        }
+        for cell in ["n/a", "bitcoin", "1.5 ETH", "$", "2025-09-20", ""] {
+            assert!(!is_numeric(cell), "{}", cell);
+        }
+    }
+}
@@ -0,0 +1,469 @@
+use crate::briefing;
+use crate::config::Config;
+use crate::db::{self, ConversationDigest, DbError, LimitOrder, Notification, StrategyActivity};
+use crate::price_format::format_price;
+use crate::render::{self, Table};
+use crate::timezone::UserTimezone;
+use chrono::{Datelike, Duration, NaiveDate, NaiveDateTime, NaiveTime};
+use sqlx::{Pool, Postgres};
+use std::fs;
+use std::path::{Path, PathBuf};
+use thiserror::Error;
+
+/// Where weekly reports are written when `REPORT_DIR` isn't set
+pub const REPORT_DIR: &str = "reports";
+
+/// Conversations listed in the report, the longest first
+pub const TOP_CONVERSATIONS: i64 = 3;
+
+/// Characters of a conversation's opening question shown in the report
+const OPENING_WIDTH: usize = 100;
+
+/// Errors raised while building or writing a weekly report
+#[derive(Debug, Error)]
+pub enum ReportError {
+    #[error("Database error: {0}")]
+    Database(#[from] DbError),
+
+    #[error("Failed to write {path}: {source}")]
+    Write { path: PathBuf, source: std::io::Error },
+}
+
+/// A Monday-to-Sunday week in the user's timezone
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Week {
+    pub monday: NaiveDate,
+    pub timezone: UserTimezone,
+}
+
+impl Week {
+    /// The week `date` falls in
+    pub fn containing(date: NaiveDate, timezone: UserTimezone) -> Self {
+        let monday = date - Duration::days(i64::from(date.weekday().num_days_from_monday()));
+        Self { monday, timezone }
+    }
+
+    /// The week before this one
+    pub fn previous(&self) -> Self {
+        Self { monday: self.monday - Duration::days(7), timezone: self.timezone }
+    }
+
+    pub fn sunday(&self) -> NaiveDate {
+        self.monday + Duration::days(6)
+    }
+
+    /// Local midnight on Monday and on the following Monday, as UTC timestamps
+    ///
+    /// A week with a daylight saving change is an hour shorter or longer.
+    pub fn bounds(&self) -> (NaiveDateTime, NaiveDateTime) {
+        let midnight = |date: NaiveDate| self.timezone.resolve_local(date.and_time(NaiveTime::MIN)).naive_utc();
+        (midnight(self.monday), midnight(self.monday + Duration::days(7)))
+    }
+
+    /// A UTC timestamp as the user's local time
+    fn local(&self, at: NaiveDateTime) -> NaiveDateTime {
+        self.timezone.local(at)
+    }
+}
+
+/// A holding with its price when the week started and when it ended
+#[derive(Debug, Clone, PartialEq)]
+pub struct HoldingChange {
+    pub coin_id: String,
+    pub amount: f64,
+    pub start_price: Option<f64>,
+    pub end_price: Option<f64>,
+}
+
+impl HoldingChange {
+    fn change_pct(&self) -> Option<f64> {
+        match (self.start_price, self.end_price) {
+            (Some(start), Some(end)) if start > 0.0 => Some((end - start) / start * 100.0),
+            _ => None,
+        }
+    }
+}
+
+/// Everything that happened in a user's week
+#[derive(Debug, Clone)]
+pub struct WeeklyReport {
+    pub week: Week,
+    pub holdings: Vec<HoldingChange>,
+    pub trades: Vec<LimitOrder>,
+    pub alerts: Vec<Notification>,
+    pub strategies: Vec<StrategyActivity>,
+    pub conversations: Vec<ConversationDigest>,
+}
+
+/// The user's timezone preference, UTC when it can't be read
+pub fn user_timezone(user: &db::User) -> UserTimezone {
+    UserTimezone::from_stored(&user.timezone)
+}
+
+/// Collect the week's holdings, trades, alerts, strategies and conversations
+///
+/// Holdings are the current amounts, priced from the stored price history at both ends of the week.
+pub async fn aggregate(pool: &Pool<Postgres>, user_id: i32, week: Week) -> Result<WeeklyReport, DbError> {
+    let (start, end) = week.bounds();
+
+    let mut holdings = Vec::new();
+    for holding in db::get_holdings_by_user_id(pool, user_id).await? {
+        let start_price = db::get_price_point_before(pool, &holding.coin_id, start).await?.map(|p| p.price_usd);
+        let end_price = db::get_price_point_before(pool, &holding.coin_id, end).await?.map(|p| p.price_usd);
+        holdings.push(HoldingChange { coin_id: holding.coin_id, amount: holding.amount, start_price, end_price });
+    }
+
+    Ok(WeeklyReport {
+        week,
+        holdings,
+        trades: db::get_limit_orders_between(pool, user_id, start, end).await?,
+        alerts: db::get_notifications_between(pool, user_id, start, end).await?,
+        strategies: db::get_strategy_activity_between(pool, user_id, start, end).await?,
+        conversations: db::get_conversations_between(pool, user_id, start, end, TOP_CONVERSATIONS).await?,
+    })
+}
+
+/// Render the report as markdown that Notion and other editors import as is
+///
+/// Every section is always there, saying so when the week had nothing for it.
+pub fn render(report: &WeeklyReport) -> String {
+    let week = &report.week;
+    let mut output = format!(
+        "# Weekly report: {} to {}\n\nTimes are in {}.\n",
+        week.monday.format("%a %-d %b"),
+        week.sunday().format("%a %-d %b %Y"),
+        week.timezone
+    );
+
+    output.push_str("\n## Portfolio\n");
+    output.push_str(&render_portfolio(&report.holdings));
+
+    output.push_str("\n\n## Trades\n");
+    if report.trades.is_empty() {
+        output.push_str("No trades this week.");
+    } else {
+        let mut table = Table::new(["Created", "Side", "Amount", "Price", "Status"]);
+        for order in &report.trades {
+            table.push_row([
+                week.local(order.created_at).format("%a %H:%M").to_string(),
+                order.order_type.as_str().to_string(),
+                order.amount.to_string(),
+                format_price(order.price),
+                order.status.as_str().to_string(),
+            ]);
+        }
+        output.push_str(&table.render_markdown());
+    }
+
+    output.push_str("\n\n## Alerts\n");
+    if report.alerts.is_empty() {
+        output.push_str("No alerts this week.");
+    } else {
+        let lines: Vec<String> = report
+            .alerts
+            .iter()
+            .map(|alert| briefing::render_alert(alert, week.local(alert.created_at), None))
+            .collect();
+        output.push_str(&lines.join("\n"));
+    }
+
+    output.push_str("\n\n## Strategies\n");
+    if report.strategies.is_empty() {
+        output.push_str("No strategies touched this week.");
+    } else {
+        let mut table = Table::new(["Strategy", "Activity"]);
+        for strategy in &report.strategies {
+            table.push_row([strategy.name.clone(), describe_activity(strategy)]);
+        }
+        output.push_str(&table.render_markdown());
+    }
+
+    output.push_str("\n\n## Conversations\n");
+    if report.conversations.is_empty() {
+        output.push_str("No conversations this week.");
+    } else {
+        let lines: Vec<String> = report
+            .conversations
+            .iter()
+            .map(|conversation| {
+                let opening = match &conversation.opening {
+                    Some(opening) => format!("\"{}\"", render::truncate(opening.trim(), OPENING_WIDTH)),
+                    None => "no question asked".to_string(),
+                };
+                format!(
+                    "- {}, {} message{}: {}",
+                    week.local(conversation.started_at).format("%a %-d %b %H:%M"),
+                    conversation.message_count,
+                    if conversation.message_count == 1 { "" } else { "s" },
+                    opening
+                )
+            })
+            .collect();
+        output.push_str(&lines.join("\n"));
+    }
+
+    output.push('\n');
+    output
+}
+
+fn render_portfolio(holdings: &[HoldingChange]) -> String {
+    if holdings.is_empty() {
+        return "No holdings yet. Add one with /portfolio set <coin> <amount>.".to_string();
+    }
+
+    let mut table = Table::new(["Coin", "Amount", "Week start", "Week end", "Change"]);
+    for holding in holdings {
+        table.push_row([
+            holding.coin_id.clone(),
+            holding.amount.to_string(),
+            holding.start_price.map_or("n/a".to_string(), format_price),
+            holding.end_price.map_or("n/a".to_string(), format_price),
+            holding.change_pct().map_or("n/a".to_string(), |change| format!("{:+.2}%", change)),
+        ]);
+    }
+    let mut output = table.render_markdown();
+
+    // Compare only the holdings priced at both ends of the week
+    let (start, end) = holdings
+        .iter()
+        .filter_map(|h| Some((h.start_price? * h.amount, h.end_price? * h.amount)))
+        .fold((0.0, 0.0), |(start, end), (s, e)| (start + s, end + e));
+    if start > 0.0 {
+        let change = end - start;
+        output.push_str(&format!(
+            "\n\nValue: ${:.2} to ${:.2} ({:+.2}%, {}${:.2}), at current amounts.",
+            start,
+            end,
+            change / start * 100.0,
+            if change < 0.0 { "-" } else { "+" },
+            change.abs()
+        ));
+    } else {
+        output.push_str("\n\nNo price history to compare the week's start and end.");
+    }
+    output
+}
+
+fn describe_activity(strategy: &StrategyActivity) -> String {
+    let mut activity = Vec::new();
+    if strategy.created {
+        activity.push("created".to_string());
+    }
+    if strategy.updated {
+        activity.push("edited".to_string());
+    }
+    match strategy.steps_completed {
+        0 => {},
+        1 => activity.push("1 step done".to_string()),
+        steps => activity.push(format!("{} steps done", steps)),
+    }
+    if strategy.outcomes_noted > 0 {
+        activity.push("outcome noted".to_string());
+    }
+    activity.join(", ")
+}
+
+/// The configured report folder, `REPORT_DIR` or ./reports
+pub fn report_dir() -> PathBuf {
+    Config::get_instance()
+        .map(|config| config.report_dir.clone())
+        .unwrap_or_else(|_| PathBuf::from(REPORT_DIR))
+}
+
+/// Where a user's report for a week is kept: one folder per user, one file per week
+pub fn report_path(dir: &Path, username: &str, week: &Week) -> PathBuf {
+    dir.join(username).join(format!("week-{}.md", week.monday.format("%Y-%m-%d")))
+}
+
+/// Build, render and write a user's report for a week, replacing an earlier one
+pub async fn generate(
+    pool: &Pool<Postgres>,
+    dir: &Path,
+    user_id: i32,
+    username: &str,
+    week: Week,
+) -> Result<PathBuf, ReportError> {
+    let report = aggregate(pool, user_id, week).await?;
+    let path = report_path(dir, username, &week);
+    let write = |path: &Path| -> std::io::Result<()> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(path, render(&report))
+    };
+    write(&path).map_err(|source| ReportError::Write { path: path.clone(), source })?;
+    Ok(path)
+}
+
+/// Remove every report written for a user, returning how many there were
+pub fn delete_reports(dir: &Path, username: &str) -> std::io::Result<usize> {
+    let folder = dir.join(username);
+    if !folder.exists() {
+        return Ok(0);
+    }
+    let count = fs::read_dir(&folder)?.count();
+    fs::remove_dir_all(&folder)?;
+    Ok(count)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::testing::test_pool;
+    use crate::db::{OrderStatus, OrderType};
+    use chrono::FixedOffset;
+
+    fn date(text: &str) -> NaiveDate {
+        NaiveDate::parse_from_str(text, "%Y-%m-%d").unwrap()
+    }
+
+    fn at(text: &str) -> NaiveDateTime {
+        NaiveDateTime::parse_from_str(text, "%Y-%m-%d %H:%M").unwrap()
+    }
+
+    fn utc() -> UserTimezone {
+        UserTimezone::default()
+    }
+
+    #[test]
+    fn test_week_bounds_follow_the_timezone() {
+        let week = Week::containing(date("2025-10-09"), utc());
+        assert_eq!((week.monday, week.sunday()), (date("2025-10-06"), date("2025-10-12")));
+        assert_eq!(week.bounds(), (at("2025-10-06 00:00"), at("2025-10-13 00:00")));
+        assert_eq!(Week::containing(date("2025-10-06"), utc()), week);
+        assert_eq!(Week::containing(date("2025-10-12"), utc()), week);
+        assert_eq!(week.previous().monday, date("2025-09-29"));
+
+        // Monday midnight at UTC+2 is Sunday 22:00 UTC, at UTC-5 it's Monday 05:00 UTC
+        let berlin = Week::containing(date("2025-10-09"), FixedOffset::east_opt(2 * 3600).unwrap().into());
+        assert_eq!(berlin.bounds(), (at("2025-10-05 22:00"), at("2025-10-12 22:00")));
+        let new_york = Week::containing(date("2025-10-09"), FixedOffset::west_opt(5 * 3600).unwrap().into());
+        assert_eq!(new_york.bounds(), (at("2025-10-06 05:00"), at("2025-10-13 05:00")));
+
+        // Berlin leaves summer time on Sunday 26 October, making that week an hour longer
+        let dst = Week::containing(date("2025-10-22"), UserTimezone::Named(chrono_tz::Europe::Berlin));
+        assert_eq!(dst.bounds(), (at("2025-10-19 22:00"), at("2025-10-26 23:00")));
+    }
+
+    fn empty_report(timezone: UserTimezone) -> WeeklyReport {
+        WeeklyReport {
+            week: Week::containing(date("2025-10-06"), timezone),
+            holdings: Vec::new(),
+            trades: Vec::new(),
+            alerts: Vec::new(),
+            strategies: Vec::new(),
+            conversations: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_empty_sections_say_so() {
+        let markdown = render(&empty_report(utc()));
+        assert_eq!(
+            markdown,
+            "# Weekly report: Mon 6 Oct to Sun 12 Oct 2025\n\nTimes are in UTC.\n\
+            \n## Portfolio\nNo holdings yet. Add one with /portfolio set <coin> <amount>.\
+            \n\n## Trades\nNo trades this week.\
+            \n\n## Alerts\nNo alerts this week.\
+            \n\n## Strategies\nNo strategies touched this week.\
+            \n\n## Conversations\nNo conversations this week.\n"
+        );
+    }
+
+    #[test]
+    fn test_render_full_week() {
+        let mut report = empty_report(FixedOffset::east_opt(2 * 3600).unwrap().into());
+        report.holdings = vec![
+            HoldingChange { coin_id: "bitcoin".to_string(), amount: 0.5, start_price: Some(60000.0), end_price: Some(66000.0) },
+            HoldingChange { coin_id: "obscure".to_string(), amount: 10.0, start_price: None, end_price: Some(1.0) },
+        ];
+        report.trades = vec![LimitOrder {
+            id: "order-1".to_string(),
+            user_id: 1,
+            order_type: OrderType::Buy,
+            token_address: "0xusdc".to_string(),
+            amount: 100.0,
+            price: 2300.0,
+            status: OrderStatus::Filled,
+            created_at: at("2025-10-07 07:30"),
+            updated_at: at("2025-10-08 10:00"),
+            expires_at: None,
+        }];
+        report.alerts = vec![Notification {
+            id: 1,
+            user_id: 1,
+            kind: "price_alert".to_string(),
+            message: "bitcoin moved +6.0% from $62,000.00 to $65,720.00".to_string(),
+            created_at: at("2025-10-09 22:15"),
+            read_at: None,
+        }];
+        report.strategies = vec![
+            StrategyActivity { name: "Weekly DCA".to_string(), created: false, updated: true, steps_completed: 2, outcomes_noted: 1 },
+            StrategyActivity { name: "Yield".to_string(), created: true, updated: false, steps_completed: 0, outcomes_noted: 0 },
+        ];
+        report.conversations = vec![
+            ConversationDigest { started_at: at("2025-10-07 12:05"), message_count: 12, opening: Some("Should I rebalance into ETH?".to_string()) },
+            ConversationDigest { started_at: at("2025-10-11 08:00"), message_count: 2, opening: None },
+        ];
+
+        let markdown = render(&report);
+        assert!(markdown.starts_with("# Weekly report: Mon 6 Oct to Sun 12 Oct 2025\n\nTimes are in UTC+02:00.\n"));
+        assert!(markdown.contains(
+            "| Coin    | Amount | Week start |  Week end |  Change |\n\
+            |---------|-------:|-----------:|----------:|--------:|\n\
+            | bitcoin |    0.5 |  $60000.00 | $66000.00 | +10.00% |\n\
+            | obscure |     10 |        n/a |     $1.00 |     n/a |\n\
+            \nValue: $30000.00 to $33000.00 (+10.00%, +$3000.00), at current amounts."
+        ), "{}", markdown);
+        // Times are shown where the user is
+        assert!(markdown.contains("| Tue 09:30 | buy  |    100 | $2300.00 | filled |"), "{}", markdown);
+        assert!(markdown.contains("## Alerts\n- [2025-10-10 00:15] bitcoin moved +6.0% from $62,000.00 to $65,720.00\n"));
+        assert!(markdown.contains("| Weekly DCA | edited, 2 steps done, outcome noted |\n| Yield      | created                             |"));
+        assert!(markdown.ends_with(
+            "## Conversations\n- Tue 7 Oct 14:05, 12 messages: \"Should I rebalance into ETH?\"\n\
+            - Sat 11 Oct 10:00, 2 messages: no question asked\n"
+        ));
+    }
+
+    #[test]
+    fn test_unpriced_portfolio_has_no_comparison() {
+        let mut report = empty_report(utc());
+        report.holdings = vec![HoldingChange { coin_id: "obscure".to_string(), amount: 1.0, start_price: None, end_price: None }];
+        assert!(render(&report).contains("\n\nNo price history to compare the week's start and end.\n\n## Trades"));
+    }
+
+    #[test]
+    fn test_report_files_are_kept_per_user() {
+        let dir = std::env::temp_dir().join(format!("reports-{}", uuid::Uuid::new_v4().simple()));
+        let week = Week::containing(date("2025-10-09"), utc());
+        assert_eq!(report_path(&dir, "alice", &week), dir.join("alice").join("week-2025-10-06.md"));
+
+        fs::create_dir_all(dir.join("alice")).unwrap();
+        fs::write(report_path(&dir, "alice", &week), "report").unwrap();
+        fs::write(report_path(&dir, "alice", &week.previous()), "report").unwrap();
+        assert_eq!(delete_reports(&dir, "alice").unwrap(), 2);
+        assert_eq!(delete_reports(&dir, "bob").unwrap(), 0);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_generate_writes_the_week() {
+        let Some(pool) = test_pool().await else { return };
+        let alice = db::create_user(&pool, "alice", None).await.unwrap();
+        db::upsert_holding(&pool, alice.id, "bitcoin", 0.5).await.unwrap();
+        db::save_message(&pool, alice.id, db::MessageRole::User, "How is bitcoin doing?").await.unwrap();
+
+        let dir = std::env::temp_dir().join(format!("reports-{}", uuid::Uuid::new_v4().simple()));
+        let today = chrono::Utc::now().date_naive();
+        let path = generate(&pool, &dir, alice.id, "alice", Week::containing(today, utc())).await.unwrap();
+        let markdown = fs::read_to_string(&path).unwrap();
+        assert!(markdown.contains("| bitcoin |    0.5 | n/a        | n/a      | n/a    |"), "{}", markdown);
+        assert!(markdown.contains(", 1 message: \"How is bitcoin doing?\""));
+        assert!(markdown.contains("No trades this week."));
+
+        // Last week had none of it
+        let path = generate(&pool, &dir, alice.id, "alice", Week::containing(today, utc()).previous()).await.unwrap();
+        assert!(fs::read_to_string(&path).unwrap().contains("No conversations this week."));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
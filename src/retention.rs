@@ -0,0 +1,342 @@
+use crate::llm::{self, CallOptions, ChatModel, LlmError};
+use crate::db::{self, ConversationSummary, DbError, Message, MessageRole};
+use async_trait::async_trait;
+use chrono::{Duration, NaiveDateTime, Utc};
+use sqlx::{Pool, Postgres};
+use thiserror::Error;
+use tracing::warn;
+
+/// Default number of days messages stay in the live history
+pub const DEFAULT_RETENTION_DAYS: i64 = 90;
+
+/// Longest transcript sent to the model for a summary, in bytes
+const MAX_TRANSCRIPT_BYTES: usize = 48_000;
+
+/// User messages quoted in an extractive summary
+const EXTRACTIVE_TOPICS: usize = 10;
+
+const SUMMARY_PROMPT: &str = "You summarize past conversations between a user and a DeFi investment assistant. \
+Write a short summary of the conversation below: the user's goals, the coins and strategies discussed, \
+decisions taken and any open questions. Answer with the summary only.";
+
+/// Errors raised while archiving messages
+#[derive(Debug, Error)]
+pub enum RetentionError {
+    #[error("Database error: {0}")]
+    Database(#[from] DbError),
+
+    #[error("Summary error: {0}")]
+    Summary(String),
+}
+
+/// Outcome of archiving a user's old messages
+#[derive(Debug, Clone)]
+pub struct ArchiveReport {
+    pub archived: u64,
+    /// None when there was nothing to archive
+    pub summary: Option<ConversationSummary>,
+}
+
+/// Produces the summary stored before messages leave the live history
+#[async_trait]
+pub trait Summarizer: Send + Sync {
+    async fn summarize(&self, messages: &[Message]) -> Result<String, RetentionError>;
+}
+
+/// Builds a summary from the messages themselves, works offline
+pub struct ExtractiveSummarizer;
+
+#[async_trait]
+impl Summarizer for ExtractiveSummarizer {
+    async fn summarize(&self, messages: &[Message]) -> Result<String, RetentionError> {
+        Ok(extractive_summary(messages))
+    }
+}
+
+/// Asks the model for a summary, falling back to an extractive one if the API is unavailable
+pub struct LlmSummarizer {
+    model: Option<Box<dyn ChatModel>>,
+}
+
+impl LlmSummarizer {
+    pub fn new(model: Box<dyn ChatModel>) -> Self {
+        Self { model: Some(model) }
+    }
+
+    /// Use the configured model, or only extractive summaries if there is none
+    pub fn from_config() -> Self {
+        match llm::configured(None) {
+            Ok(model) => Self::new(model),
+            Err(e) => {
+                warn!("Summaries will be extractive: {}", e);
+                Self { model: None }
+            },
+        }
+    }
+}
+
+#[async_trait]
+impl Summarizer for LlmSummarizer {
+    async fn summarize(&self, messages: &[Message]) -> Result<String, RetentionError> {
+        let Some(model) = &self.model else {
+            return Ok(extractive_summary(messages));
+        };
+
+        let request = [llm::Message {
+            role: MessageRole::User,
+            content: transcript(messages, MAX_TRANSCRIPT_BYTES),
+        }];
+        match model.generate(SUMMARY_PROMPT, &request, &CallOptions::new(1024)).await {
+            Ok(summary) if !summary.text.trim().is_empty() => Ok(summary.text.trim().to_string()),
+            Ok(_) => Ok(extractive_summary(messages)),
+            Err(LlmError::Offline) => Ok(extractive_summary(messages)),
+            Err(e) => {
+                warn!("Falling back to an extractive summary: {}", e);
+                Ok(extractive_summary(messages))
+            },
+        }
+    }
+}
+
+/// Cutoff for a retention period counted back from now
+pub fn retention_cutoff(retention_days: i64) -> NaiveDateTime {
+    (Utc::now() - Duration::days(retention_days.max(0))).naive_utc()
+}
+
+/// Summarize a user's messages older than `cutoff`, then move them to the archive
+/// Nothing is archived if the summary can't be generated or stored
+pub async fn archive_with_summary(
+    pool: &Pool<Postgres>,
+    user_id: i32,
+    cutoff: NaiveDateTime,
+    summarizer: &dyn Summarizer,
+) -> Result<ArchiveReport, RetentionError> {
+    let messages = db::get_messages_before(pool, user_id, cutoff).await?;
+    let (Some(first), Some(last)) = (messages.first(), messages.last()) else {
+        return Ok(ArchiveReport { archived: 0, summary: None });
+    };
+
+    let summary_text = summarizer.summarize(&messages).await?;
+    let summary = db::save_conversation_summary(
+        pool,
+        user_id,
+        first.created_at,
+        last.created_at,
+        messages.len() as i32,
+        &summary_text,
+    )
+    .await?;
+
+    // Archive up to the last summarized message, so anything saved meanwhile stays live
+    let archived = db::archive_messages_before(pool, user_id, last.created_at + Duration::microseconds(1)).await?;
+
+    Ok(ArchiveReport { archived, summary: Some(summary) })
+}
+
+/// Apply the retention period to every user, returning the number of archived messages
+pub async fn run_retention(
+    pool: &Pool<Postgres>,
+    retention_days: i64,
+    summarizer: &dyn Summarizer,
+) -> Result<u64, RetentionError> {
+    let cutoff = retention_cutoff(retention_days);
+    let mut archived = 0;
+
+    for user_id in db::get_user_ids_with_messages_before(pool, cutoff).await? {
+        archived += archive_with_summary(pool, user_id, cutoff, summarizer).await?.archived;
+    }
+
+    Ok(archived)
+}
+
+/// Summary listing the period covered and what the user asked about
+pub fn extractive_summary(messages: &[Message]) -> String {
+    let (Some(first), Some(last)) = (messages.first(), messages.last()) else {
+        return "No messages.".to_string();
+    };
+
+    let mut summary = format!(
+        "{} messages between {} and {} UTC.",
+        messages.len(),
+        first.created_at.format("%Y-%m-%d %H:%M"),
+        last.created_at.format("%Y-%m-%d %H:%M")
+    );
+
+    let topics: Vec<String> = messages
+        .iter()
+        .filter(|message| message.role == MessageRole::User)
+        .map(|message| first_line(&message.content, 120))
+        .filter(|line| !line.is_empty())
+        .collect();
+    if !topics.is_empty() {
+        summary.push_str("\nThe user asked about:");
+        for topic in topics.iter().take(EXTRACTIVE_TOPICS) {
+            summary.push_str("\n- ");
+            summary.push_str(topic);
+        }
+        if topics.len() > EXTRACTIVE_TOPICS {
+            summary.push_str(&format!("\n- and {} more", topics.len() - EXTRACTIVE_TOPICS));
+        }
+    }
+
+    summary
+}
+
+/// Render messages as a transcript, keeping the most recent ones within `max_bytes`
+fn transcript(messages: &[Message], max_bytes: usize) -> String {
+    let mut lines: Vec<String> = Vec::new();
+    let mut size = 0;
+
+    for message in messages.iter().rev() {
+        let line = format!("{}: {}\n", message.role.label(), message.content);
+        if size + line.len() > max_bytes && !lines.is_empty() {
+            break;
+        }
+        size += line.len();
+        lines.push(line);
+    }
+
+    lines.reverse();
+    lines.concat()
+}
+
+fn first_line(content: &str, max_chars: usize) -> String {
+    let line = content.lines().map(str::trim).find(|line| !line.is_empty()).unwrap_or_default();
+    if line.chars().count() > max_chars {
+        format!("{}...", line.chars().take(max_chars).collect::<String>())
+    } else {
+        line.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::testing::test_pool;
+    use chrono::NaiveDate;
+
+    fn message(role: MessageRole, content: &str, hour: u32) -> Message {
+        Message {
+            id: hour as i32,
+            user_id: Some(1),
+            role,
+            content: content.to_string(),
+            created_at: NaiveDate::from_ymd_opt(2025, 1, 2).unwrap().and_hms_opt(hour, 0, 0).unwrap(),
+            truncated: false,
+        }
+    }
+
+    async fn insert_message(pool: &Pool<Postgres>, role: MessageRole, content: &str, created_at: &str) {
+        sqlx::query("INSERT INTO messages (user_id, role, content, created_at) VALUES (1, $1, $2, $3::timestamp)")
+            .bind(role.as_str())
+            .bind(content)
+            .bind(created_at)
+            .execute(pool)
+            .await
+            .unwrap();
+    }
+
+    struct FailingSummarizer;
+
+    #[async_trait]
+    impl Summarizer for FailingSummarizer {
+        async fn summarize(&self, _messages: &[Message]) -> Result<String, RetentionError> {
+            Err(RetentionError::Summary("model unavailable".to_string()))
+        }
+    }
+
+    #[test]
+    fn test_extractive_summary_lists_user_questions() {
+        let messages = vec![
+            message(MessageRole::User, "What is the price of AERO?\nThanks", 9),
+            message(MessageRole::Assistant, "AERO is at $1.20", 10),
+            message(MessageRole::User, "Should I stake it?", 11),
+        ];
+
+        let summary = extractive_summary(&messages);
+        assert!(summary.starts_with("3 messages between 2025-01-02 09:00 and 2025-01-02 11:00 UTC."));
+        assert!(summary.contains("- What is the price of AERO?\n- Should I stake it?"));
+        assert!(!summary.contains("$1.20"));
+    }
+
+    #[test]
+    fn test_transcript_keeps_most_recent_messages() {
+        let messages = vec![
+            message(MessageRole::User, "old question", 9),
+            message(MessageRole::Assistant, "recent answer", 10),
+        ];
+
+        assert_eq!(transcript(&messages, 1_000), "USER: old question\nASSISTANT: recent answer\n");
+        assert_eq!(transcript(&messages, 30), "ASSISTANT: recent answer\n");
+    }
+
+    #[tokio::test]
+    async fn test_summarizer_falls_back_when_the_model_fails() {
+        use crate::llm::testing::MockChatModel;
+
+        let messages = vec![message(MessageRole::User, "Should I stake AERO?", 9)];
+        let summarizer = LlmSummarizer::new(Box::new(MockChatModel::replying(["Asked about staking AERO."])));
+        assert_eq!(summarizer.summarize(&messages).await.unwrap(), "Asked about staking AERO.");
+
+        let summarizer = LlmSummarizer::new(Box::new(MockChatModel::failing()));
+        assert_eq!(summarizer.summarize(&messages).await.unwrap(), extractive_summary(&messages));
+    }
+
+    #[tokio::test]
+    async fn test_archived_messages_leave_history_but_remain_exportable() {
+        let Some(pool) = test_pool().await else { return };
+        insert_message(&pool, MessageRole::User, "old question", "2025-01-01 09:00:00").await;
+        insert_message(&pool, MessageRole::Assistant, "old answer", "2025-01-01 09:01:00").await;
+        insert_message(&pool, MessageRole::User, "new question", "2025-03-01 09:00:00").await;
+
+        let cutoff = NaiveDate::from_ymd_opt(2025, 2, 1).unwrap().and_hms_opt(0, 0, 0).unwrap();
+        let report = archive_with_summary(&pool, 1, cutoff, &ExtractiveSummarizer).await.unwrap();
+        assert_eq!(report.archived, 2);
+
+        let history = db::get_messages(&pool, 1, 10).await.unwrap();
+        let contents: Vec<&str> = history.iter().map(|m| m.content.as_str()).collect();
+        assert_eq!(contents, vec!["new question"]);
+
+        let exported = db::export_messages(&pool, 1).await.unwrap();
+        let contents: Vec<&str> = exported.iter().map(|m| m.content.as_str()).collect();
+        assert_eq!(contents, vec!["old question", "old answer", "new question"]);
+        assert_eq!(exported[1].role, MessageRole::Assistant);
+
+        let summaries = db::get_conversation_summaries(&pool, 1).await.unwrap();
+        assert_eq!(summaries.len(), 1);
+        assert_eq!(summaries[0].message_count, 2);
+        assert!(summaries[0].summary.contains("- old question"));
+
+        // Nothing left to archive
+        let report = archive_with_summary(&pool, 1, cutoff, &ExtractiveSummarizer).await.unwrap();
+        assert_eq!(report.archived, 0);
+        assert!(report.summary.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_failed_summary_archives_nothing() {
+        let Some(pool) = test_pool().await else { return };
+        insert_message(&pool, MessageRole::User, "old question", "2025-01-01 09:00:00").await;
+
+        let cutoff = NaiveDate::from_ymd_opt(2025, 2, 1).unwrap().and_hms_opt(0, 0, 0).unwrap();
+        assert!(archive_with_summary(&pool, 1, cutoff, &FailingSummarizer).await.is_err());
+
+        assert_eq!(db::get_messages(&pool, 1, 10).await.unwrap().len(), 1);
+        assert!(db::get_conversation_summaries(&pool, 1).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_run_retention_uses_retention_period() {
+        let Some(pool) = test_pool().await else { return };
+        insert_message(&pool, MessageRole::User, "ancient", "2020-01-01 09:00:00").await;
+        sqlx::query("INSERT INTO messages (user_id, role, content) VALUES (1, 'user', 'today')")
+            .execute(&pool)
+            .await
+            .unwrap();
+
+        assert_eq!(run_retention(&pool, 30, &ExtractiveSummarizer).await.unwrap(), 1);
+        let history = db::get_messages(&pool, 1, 10).await.unwrap();
+        assert_eq!(history.len(), 1);
+        assert_eq!(history[0].content, "today");
+    }
+}
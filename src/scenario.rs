@@ -0,0 +1,551 @@
+use crate::portfolio_analysis::Position;
+use crate::price_format::format_price;
+use crate::render::Table;
+use regex::Regex;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::ops::Range;
+use std::sync::OnceLock;
+
+/// Words standing for every holding not named in the message, "everything else -40%"
+const OTHERS_WORDS: &[&str] = &["else", "rest", "others", "everything", "market", "all", "alts", "portfolio", "crypto"];
+
+/// Words the coin captures pick up that are never coins
+const NOT_COINS: &[&str] = &["if", "and", "or", "my", "the", "when", "then", "price", "stop", "loss", "order", "limit", "buy", "sell", "what", "happens", "to"];
+
+/// How one asset's price moves in a scenario
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(tag = "kind", content = "value", rename_all = "snake_case")]
+pub enum Shock {
+    /// Moves by a percentage, -30 for a 30% drop
+    Percent(f64),
+    /// Moves to an absolute USD price
+    Price(f64),
+}
+
+impl Shock {
+    /// Price after the shock, never below zero
+    pub fn apply(&self, price: f64) -> f64 {
+        match self {
+            Shock::Percent(pct) => (price * (1.0 + pct / 100.0)).max(0.0),
+            Shock::Price(target) => *target,
+        }
+    }
+
+    fn describe(&self) -> String {
+        match self {
+            Shock::Percent(pct) => format!("{:+}%", pct),
+            Shock::Price(target) => format!("to {}", format_price(*target)),
+        }
+    }
+}
+
+/// What the message says about holdings it doesn't name
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Others {
+    /// Nothing, the configured default shock applies
+    Unstated,
+    /// "everything else flat"
+    Flat,
+    /// "everything else -40%"
+    Percent(f64),
+}
+
+/// A scenario question parsed from a chat message, coins as written
+#[derive(Debug, Clone, PartialEq)]
+pub struct ScenarioQuery {
+    pub shocks: Vec<(String, Shock)>,
+    pub others: Others,
+    /// Stop losses given in the message, "stop on eth at 1500"
+    pub stops: Vec<(String, f64)>,
+}
+
+/// Price shocks by coin id, with the move applied to unnamed holdings
+#[derive(Debug, Clone, PartialEq)]
+pub struct PriceShocks {
+    pub shocks: Vec<(String, Shock)>,
+    /// Percent move of holdings without their own shock, 0 keeps them flat
+    pub others_pct: f64,
+    /// Coins the others move never applies to, the stablecoins
+    pub pegged: Vec<String>,
+}
+
+/// Where the move applied to a position came from
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ShockSource {
+    Named,
+    Others,
+    Flat,
+}
+
+impl PriceShocks {
+    /// The price of `coin_id` after the scenario
+    pub fn price_after(&self, coin_id: &str, price: f64) -> (f64, ShockSource) {
+        if let Some((_, shock)) = self.shocks.iter().find(|(id, _)| id == coin_id) {
+            return (shock.apply(price), ShockSource::Named);
+        }
+        if self.others_pct == 0.0 || self.pegged.iter().any(|id| id == coin_id) {
+            return (price, ShockSource::Flat);
+        }
+        (Shock::Percent(self.others_pct).apply(price), ShockSource::Others)
+    }
+
+    /// "ethereum -30%, bitcoin to $40000.00, everything else -40%"
+    pub fn describe(&self) -> String {
+        let mut parts: Vec<String> = self.shocks.iter().map(|(coin_id, shock)| format!("{} {}", coin_id, shock.describe())).collect();
+        if self.others_pct == 0.0 {
+            parts.push("everything else flat".to_string());
+        } else {
+            parts.push(format!("everything else {:+}% (stablecoins flat)", self.others_pct));
+        }
+        parts.join(", ")
+    }
+}
+
+/// Whether a trigger fires when the price falls to it or rises to it
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TriggerKind {
+    StopLoss,
+    LimitBuy,
+    LimitSell,
+}
+
+impl TriggerKind {
+    fn label(&self) -> &'static str {
+        match self {
+            TriggerKind::StopLoss => "Stop loss",
+            TriggerKind::LimitBuy => "Buy limit order",
+            TriggerKind::LimitSell => "Sell limit order",
+        }
+    }
+}
+
+/// A stop loss or open limit order on a coin
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Trigger {
+    pub kind: TriggerKind,
+    pub coin_id: String,
+    pub price: f64,
+}
+
+/// A trigger checked against the price after the scenario
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct TriggerOutcome {
+    pub trigger: Trigger,
+    pub price_after: f64,
+    /// How far the price would still have to move to reach the trigger, in percent of the price
+    pub distance_pct: f64,
+    pub hit: bool,
+}
+
+/// One holding before and after the scenario
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct PositionOutcome {
+    pub coin_id: String,
+    pub amount: f64,
+    pub price_before: f64,
+    pub price_after: f64,
+    pub value_before: f64,
+    pub value_after: f64,
+    pub pnl_usd: f64,
+    pub pnl_pct: f64,
+    pub source: ShockSource,
+}
+
+/// The portfolio before and after the scenario
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ScenarioResult {
+    pub before_usd: f64,
+    pub after_usd: f64,
+    pub positions: Vec<PositionOutcome>,
+    pub triggers: Vec<TriggerOutcome>,
+    /// Holdings left out because they have no price
+    pub unpriced: Vec<String>,
+}
+
+impl ScenarioResult {
+    pub fn pnl_usd(&self) -> f64 {
+        self.after_usd - self.before_usd
+    }
+
+    pub fn pnl_pct(&self) -> f64 {
+        if self.before_usd > 0.0 { self.pnl_usd() / self.before_usd * 100.0 } else { 0.0 }
+    }
+}
+
+/// Apply the shocks to each position and check every trigger against the shocked price
+///
+/// `prices` holds the current USD price of the held coins and of the coins triggers are on.
+pub fn run_scenario(shocks: &PriceShocks, positions: &[Position], triggers: &[Trigger], prices: &HashMap<String, f64>) -> ScenarioResult {
+    let mut outcomes = Vec::with_capacity(positions.len());
+    let mut unpriced = Vec::new();
+    for position in positions {
+        let Some(price) = prices.get(&position.coin_id).copied() else {
+            unpriced.push(position.coin_id.clone());
+            continue;
+        };
+        let (price_after, source) = shocks.price_after(&position.coin_id, price);
+        let value_before = position.amount * price;
+        let value_after = position.amount * price_after;
+        outcomes.push(PositionOutcome {
+            coin_id: position.coin_id.clone(),
+            amount: position.amount,
+            price_before: price,
+            price_after,
+            value_before,
+            value_after,
+            pnl_usd: value_after - value_before,
+            pnl_pct: if price > 0.0 { (price_after - price) / price * 100.0 } else { 0.0 },
+            source,
+        });
+    }
+
+    let triggers = triggers
+        .iter()
+        .filter_map(|trigger| {
+            let price = prices.get(&trigger.coin_id).copied()?;
+            let (price_after, _) = shocks.price_after(&trigger.coin_id, price);
+            let hit = match trigger.kind {
+                TriggerKind::StopLoss | TriggerKind::LimitBuy => price_after <= trigger.price,
+                TriggerKind::LimitSell => price_after >= trigger.price,
+            };
+            let distance_pct = if price_after > 0.0 { (trigger.price - price_after).abs() / price_after * 100.0 } else { 0.0 };
+            Some(TriggerOutcome { trigger: trigger.clone(), price_after, distance_pct, hit })
+        })
+        .collect();
+
+    ScenarioResult {
+        before_usd: outcomes.iter().map(|outcome| outcome.value_before).sum(),
+        after_usd: outcomes.iter().map(|outcome| outcome.value_after).sum(),
+        positions: outcomes,
+        triggers,
+        unpriced,
+    }
+}
+
+fn signed_usd(value: f64) -> String {
+    if value < 0.0 { format!("-${:.2}", -value) } else { format!("+${:.2}", value) }
+}
+
+/// Render the before/after comparison, the facts the commentary is written from
+pub fn render_scenario(shocks: &PriceShocks, result: &ScenarioResult) -> String {
+    let mut output = format!(
+        "Scenario: {}.\n\nPortfolio: ${:.2} -> ${:.2} ({}, {:+.1}%)\n\n",
+        shocks.describe(),
+        result.before_usd,
+        result.after_usd,
+        signed_usd(result.pnl_usd()),
+        result.pnl_pct()
+    );
+
+    let mut table = Table::new(["Asset", "Amount", "Price", "Value", "P&L"]);
+    for position in &result.positions {
+        table.push_row([
+            position.coin_id.clone(),
+            format!("{}", position.amount),
+            format!("{} -> {}", format_price(position.price_before), format_price(position.price_after)),
+            format!("${:.2} -> ${:.2}", position.value_before, position.value_after),
+            format!("{} ({:+.1}%)", signed_usd(position.pnl_usd), position.pnl_pct),
+        ]);
+    }
+    output.push_str(&table.render());
+
+    if !result.triggers.is_empty() {
+        output.push_str("\n\nStops and orders:\n");
+        for outcome in &result.triggers {
+            let trigger = &outcome.trigger;
+            let status = if outcome.hit {
+                format!("triggered, {} would be at {}", trigger.coin_id, format_price(outcome.price_after))
+            } else {
+                format!("not triggered, {:.1}% away from {}", outcome.distance_pct, format_price(outcome.price_after))
+            };
+            output.push_str(&format!("- {} on {} at {}: {}\n", trigger.kind.label(), trigger.coin_id, format_price(trigger.price), status));
+        }
+        output.truncate(output.trim_end().len());
+    }
+
+    if !result.unpriced.is_empty() {
+        output.push_str(&format!("\n\nLeft out for lack of a price: {}.", result.unpriced.join(", ")));
+    }
+    output
+}
+
+const DOWN_VERBS: &str = r"drops?|dropped|falls?|crash(?:es)?|dumps?|down|loses?|declines?|sinks?|tanks?";
+const UP_VERBS: &str = r"rises?|pumps?|jumps?|up|gains?|rall(?:y|ies)|climbs?|moons?";
+
+// "eth drops 30%", "btc -15%", "everything else down 40%"
+fn percent_regex() -> &'static Regex {
+    static REGEX: OnceLock<Regex> = OnceLock::new();
+    REGEX.get_or_init(|| {
+        Regex::new(&format!(
+            r"(?i)\b([a-z][a-z0-9-]*)\s+(?:(?:(?P<down>{})|(?P<up>{}))\s+(?:by\s+)?)?(?P<pct>[+-]?\d+(?:\.\d+)?)\s*%",
+            DOWN_VERBS, UP_VERBS
+        ))
+        .unwrap()
+    })
+}
+
+// "eth doubles", "sol halves"
+fn multiple_regex() -> &'static Regex {
+    static REGEX: OnceLock<Regex> = OnceLock::new();
+    REGEX.get_or_init(|| Regex::new(r"(?i)\b([a-z][a-z0-9-]*)\s+(doubles|triples|halves)\b").unwrap())
+}
+
+// "btc goes to 40k", "eth at $1500"
+fn price_regex() -> &'static Regex {
+    static REGEX: OnceLock<Regex> = OnceLock::new();
+    REGEX.get_or_init(|| {
+        Regex::new(r"(?i)\b([a-z][a-z0-9-]*)\s+(?:(?:goes|gets|drops|falls|crashes|rises|pumps|jumps|climbs)\s+)?(?:to|at|hits)\s+\$?(\d[\d,]*(?:\.\d+)?)(k?)\b").unwrap()
+    })
+}
+
+// "everything else flat", "the rest unchanged"
+fn flat_regex() -> &'static Regex {
+    static REGEX: OnceLock<Regex> = OnceLock::new();
+    REGEX.get_or_init(|| {
+        Regex::new(r"(?i)\b(?:everything\s+else|the\s+rest|rest|others|other\s+coins)\s+(?:(?:stays?|stay|remains?|is|are|held|kept)\s+)?(?:flat|unchanged)\b").unwrap()
+    })
+}
+
+// "stop on eth at 1500", "eth stop loss at $1500"
+fn stop_regex() -> &'static Regex {
+    static REGEX: OnceLock<Regex> = OnceLock::new();
+    REGEX.get_or_init(|| {
+        Regex::new(r"(?i)\bstop(?:[\s-]*loss)?\s+(?:on|for)\s+([a-z][a-z0-9-]*)\s+(?:at\s+)?\$?(\d[\d,]*(?:\.\d+)?)(k?)\b|\b([a-z][a-z0-9-]*)\s+stop(?:[\s-]*loss)?\s+(?:at\s+)?\$?(\d[\d,]*(?:\.\d+)?)(k?)\b").unwrap()
+    })
+}
+
+// The message must be about the user's own holdings
+fn holdings_regex() -> &'static Regex {
+    static REGEX: OnceLock<Regex> = OnceLock::new();
+    REGEX.get_or_init(|| Regex::new(r"(?i)\b(?:portfolio|holdings|positions|bags?|stress[\s-]*test|scenario)\b").unwrap())
+}
+
+/// Parse "1,500", "2.5" or "40" with an optional "k" suffix
+fn parse_amount(digits: &str, suffix: &str) -> Option<f64> {
+    let value: f64 = digits.replace(',', "").parse().ok()?;
+    Some(if suffix.eq_ignore_ascii_case("k") { value * 1000.0 } else { value })
+}
+
+fn overlaps(range: &Range<usize>, taken: &[Range<usize>]) -> bool {
+    taken.iter().any(|other| range.start < other.end && other.start < range.end)
+}
+
+/// Parse "what happens to my portfolio if eth -30%, btc goes to 40k, everything else -40%"
+///
+/// Needs a mention of the user's holdings and at least one price move. A coin named twice
+/// keeps its first move.
+pub fn parse_scenario_query(message: &str) -> Option<ScenarioQuery> {
+    if !holdings_regex().is_match(message) {
+        return None;
+    }
+
+    let mut taken = Vec::new();
+    let mut stops = Vec::new();
+    for captures in stop_regex().captures_iter(message) {
+        let (coin, digits, suffix) = match captures.get(1) {
+            Some(coin) => (coin.as_str(), &captures[2], &captures[3]),
+            None => (&captures[4], &captures[5], &captures[6]),
+        };
+        if let Some(price) = parse_amount(digits, suffix) {
+            stops.push((coin.to_lowercase(), price));
+        }
+        taken.push(captures.get(0).unwrap().range());
+    }
+
+    let mut others = if flat_regex().is_match(message) { Others::Flat } else { Others::Unstated };
+    let mut moves: Vec<(usize, String, Shock)> = Vec::new();
+    let mut push = |start: usize, coin: &str, shock: Shock, others: &mut Others| {
+        let coin = coin.to_lowercase();
+        if OTHERS_WORDS.contains(&coin.as_str()) {
+            if let (Others::Unstated, Shock::Percent(pct)) = (*others, shock) {
+                *others = Others::Percent(pct);
+            }
+        } else if !NOT_COINS.contains(&coin.as_str()) && !moves.iter().any(|(_, named, _)| *named == coin) {
+            moves.push((start, coin, shock));
+        }
+    };
+
+    for captures in percent_regex().captures_iter(message) {
+        let whole = captures.get(0).unwrap();
+        if overlaps(&whole.range(), &taken) {
+            continue;
+        }
+        let Ok(mut pct) = captures["pct"].parse::<f64>() else { continue };
+        let signed = captures["pct"].starts_with(['+', '-']);
+        if captures.name("down").is_some() {
+            pct = -pct.abs();
+        } else if captures.name("up").is_none() && !signed {
+            // "btc 50%" is a weight, not a move
+            continue;
+        }
+        push(whole.start(), &captures[1], Shock::Percent(pct), &mut others);
+    }
+    for captures in multiple_regex().captures_iter(message) {
+        let pct = match captures[2].to_lowercase().as_str() {
+            "doubles" => 100.0,
+            "triples" => 200.0,
+            _ => -50.0,
+        };
+        push(captures.get(0).unwrap().start(), &captures[1], Shock::Percent(pct), &mut others);
+    }
+    for captures in price_regex().captures_iter(message) {
+        let whole = captures.get(0).unwrap();
+        // "at 30%" is a percentage, and stops aren't moves
+        if overlaps(&whole.range(), &taken) || message[whole.end()..].trim_start().starts_with('%') {
+            continue;
+        }
+        if let Some(price) = parse_amount(&captures[2], &captures[3]).filter(|price| *price > 0.0) {
+            push(whole.start(), &captures[1], Shock::Price(price), &mut others);
+        }
+    }
+
+    if moves.is_empty() && others == Others::Unstated {
+        return None;
+    }
+    moves.sort_by_key(|(start, _, _)| *start);
+    Some(ScenarioQuery {
+        shocks: moves.into_iter().map(|(_, coin, shock)| (coin, shock)).collect(),
+        others,
+        stops,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn close(a: f64, b: f64) -> bool {
+        (a - b).abs() < 1e-9
+    }
+
+    fn position(coin_id: &str, amount: f64) -> Position {
+        Position { coin_id: coin_id.to_string(), amount }
+    }
+
+    #[test]
+    fn test_parse_percent_shocks() {
+        let query = parse_scenario_query("what happens to my portfolio if eth -30%, btc -15%, everything else -40%").unwrap();
+        assert_eq!(query.shocks, vec![("eth".to_string(), Shock::Percent(-30.0)), ("btc".to_string(), Shock::Percent(-15.0))]);
+        assert_eq!(query.others, Others::Percent(-40.0));
+        assert!(query.stops.is_empty());
+
+        let query = parse_scenario_query("What happens to my portfolio if ETH drops 30%?").unwrap();
+        assert_eq!(query.shocks, vec![("eth".to_string(), Shock::Percent(-30.0))]);
+        assert_eq!(query.others, Others::Unstated);
+
+        let query = parse_scenario_query("stress test my portfolio: sol doubles, the rest unchanged").unwrap();
+        assert_eq!(query.shocks, vec![("sol".to_string(), Shock::Percent(100.0))]);
+        assert_eq!(query.others, Others::Flat);
+
+        let query = parse_scenario_query("my portfolio if the market falls by 20%").unwrap();
+        assert!(query.shocks.is_empty());
+        assert_eq!(query.others, Others::Percent(-20.0));
+    }
+
+    #[test]
+    fn test_parse_price_shocks_and_stops() {
+        let query = parse_scenario_query("portfolio if btc goes to 40k and eth up 10%, stop on eth at $1,500").unwrap();
+        assert_eq!(
+            query.shocks,
+            vec![("btc".to_string(), Shock::Price(40_000.0)), ("eth".to_string(), Shock::Percent(10.0))]
+        );
+        assert_eq!(query.stops, vec![("eth".to_string(), 1500.0)]);
+
+        let query = parse_scenario_query("my holdings if eth at 1800, sol stop loss at 90").unwrap();
+        assert_eq!(query.shocks, vec![("eth".to_string(), Shock::Price(1800.0))]);
+        assert_eq!(query.stops, vec![("sol".to_string(), 90.0)]);
+    }
+
+    #[test]
+    fn test_parse_needs_holdings_and_a_move() {
+        assert_eq!(parse_scenario_query("what if eth drops 30%"), None);
+        assert_eq!(parse_scenario_query("how is my portfolio doing"), None);
+        assert_eq!(parse_scenario_query("rebalance my portfolio to btc 50%, eth 50%"), None);
+    }
+
+    #[test]
+    fn test_shock_apply() {
+        assert!(close(Shock::Percent(-30.0).apply(2000.0), 1400.0));
+        assert!(close(Shock::Percent(25.0).apply(100.0), 125.0));
+        assert_eq!(Shock::Percent(-150.0).apply(100.0), 0.0);
+        assert_eq!(Shock::Price(40_000.0).apply(60_000.0), 40_000.0);
+    }
+
+    #[test]
+    fn test_run_scenario_with_percent_and_price_shocks() {
+        let shocks = PriceShocks {
+            shocks: vec![("ethereum".to_string(), Shock::Percent(-30.0)), ("bitcoin".to_string(), Shock::Price(40_000.0))],
+            others_pct: -40.0,
+            pegged: vec!["usd-coin".to_string()],
+        };
+        let positions = [position("ethereum", 2.0), position("bitcoin", 0.1), position("solana", 10.0), position("usd-coin", 500.0), position("obscure", 1.0)];
+        let prices = HashMap::from([
+            ("ethereum".to_string(), 2000.0),
+            ("bitcoin".to_string(), 50_000.0),
+            ("solana".to_string(), 100.0),
+            ("usd-coin".to_string(), 1.0),
+        ]);
+
+        let result = run_scenario(&shocks, &positions, &[], &prices);
+        // 4000 + 5000 + 1000 + 500 before, 2800 + 4000 + 600 + 500 after
+        assert!(close(result.before_usd, 10_500.0));
+        assert!(close(result.after_usd, 7_900.0));
+        assert!(close(result.pnl_usd(), -2_600.0));
+        assert_eq!(result.unpriced, vec!["obscure".to_string()]);
+
+        let bitcoin = &result.positions[1];
+        assert!(close(bitcoin.pnl_usd, -1000.0));
+        assert!(close(bitcoin.pnl_pct, -20.0));
+        assert_eq!(bitcoin.source, ShockSource::Named);
+        assert_eq!(result.positions[2].source, ShockSource::Others);
+        assert_eq!(result.positions[3].source, ShockSource::Flat);
+        assert!(close(result.positions[3].value_after, 500.0));
+    }
+
+    #[test]
+    fn test_unnamed_holdings_stay_flat_without_a_default() {
+        let shocks = PriceShocks { shocks: vec![("ethereum".to_string(), Shock::Percent(-50.0))], others_pct: 0.0, pegged: Vec::new() };
+        let prices = HashMap::from([("ethereum".to_string(), 2000.0), ("solana".to_string(), 100.0)]);
+        let result = run_scenario(&shocks, &[position("ethereum", 1.0), position("solana", 10.0)], &[], &prices);
+        assert!(close(result.after_usd, 2000.0));
+        assert!(close(result.pnl_pct(), -1000.0 / 3000.0 * 100.0));
+        assert_eq!(result.positions[1].source, ShockSource::Flat);
+        assert_eq!(shocks.describe(), "ethereum -50%, everything else flat");
+    }
+
+    #[test]
+    fn test_triggers() {
+        let shocks = PriceShocks { shocks: vec![("ethereum".to_string(), Shock::Percent(-30.0))], others_pct: 0.0, pegged: Vec::new() };
+        let prices = HashMap::from([("ethereum".to_string(), 2000.0)]);
+        let trigger = |kind, price| Trigger { kind, coin_id: "ethereum".to_string(), price };
+        let triggers = [
+            trigger(TriggerKind::StopLoss, 1500.0),
+            trigger(TriggerKind::StopLoss, 1200.0),
+            trigger(TriggerKind::LimitBuy, 1400.0),
+            trigger(TriggerKind::LimitSell, 2500.0),
+        ];
+
+        let result = run_scenario(&shocks, &[], &triggers, &prices);
+        let hits: Vec<bool> = result.triggers.iter().map(|outcome| outcome.hit).collect();
+        assert_eq!(hits, vec![true, false, true, false]);
+        // $1400 is $200 above the $1200 stop
+        assert!(close(result.triggers[1].distance_pct, 200.0 / 1400.0 * 100.0));
+        assert!(close(result.triggers[3].distance_pct, 1100.0 / 1400.0 * 100.0));
+    }
+
+    #[test]
+    fn test_render_scenario() {
+        let shocks = PriceShocks { shocks: vec![("ethereum".to_string(), Shock::Percent(-30.0))], others_pct: -40.0, pegged: Vec::new() };
+        let prices = HashMap::from([("ethereum".to_string(), 2000.0)]);
+        let triggers = [Trigger { kind: TriggerKind::StopLoss, coin_id: "ethereum".to_string(), price: 1500.0 }];
+        let result = run_scenario(&shocks, &[position("ethereum", 2.0)], &triggers, &prices);
+
+        let output = render_scenario(&shocks, &result);
+        assert!(output.starts_with("Scenario: ethereum -30%, everything else -40% (stablecoins flat).\n\nPortfolio: $4000.00 -> $2800.00 (-$1200.00, -30.0%)"));
+        assert!(output.contains("$2000.00 -> $1400.00"));
+        assert!(output.ends_with("- Stop loss on ethereum at $1500.00: triggered, ethereum would be at $1400.00"));
+    }
+}
@@ -0,0 +1,299 @@
+use super::SetupError;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io::Write;
+use std::path::Path;
+
+/// Values collected by the setup wizard and stored in agent.toml
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AgentSettings {
+    pub agent: AgentSection,
+    pub api_keys: ApiKeys,
+    pub database: DatabaseSection,
+    pub risk: RiskPreferences,
+    pub rate_limit: RateLimitSection,
+    pub enrichment: EnrichmentSection,
+    pub stablecoins: StablecoinSection,
+    pub llm: LlmSection,
+    pub unlocks: UnlockSection,
+    pub knowledge: KnowledgeSection,
+    pub compliance: ComplianceSection,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AgentSection {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub username: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ApiKeys {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub anthropic: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub exa: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub coingecko: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub openai: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DatabaseSection {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RiskPreferences {
+    /// One of low, medium or high
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tolerance: Option<String>,
+    /// One of short, medium or long
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub horizon: Option<String>,
+    /// Largest single trade in USD, checked by the position sizing answer
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_trade_usd: Option<f64>,
+    /// Percent move of holdings a scenario question doesn't name, e.g. -20
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub scenario_default_shock_pct: Option<f64>,
+}
+
+/// Per-user message limits for shared deployments, off unless requests_per_minute is set
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RateLimitSection {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub requests_per_minute: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub burst: Option<u32>,
+    /// Usernames that are never limited
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub bypass: Vec<String>,
+    /// Keep the limiter state in the database for several processes
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub shared: bool,
+}
+
+/// Background research into projects named in chat turns, on unless enabled is false
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct EnrichmentSection {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub enabled: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub jobs_per_hour: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub queue_capacity: Option<usize>,
+}
+
+/// Stablecoins watched for depegs, USDT, USDC and DAI unless coins is set
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct StablecoinSection {
+    /// CoinGecko ids
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub coins: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub warning_pct: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub alert_pct: Option<f64>,
+}
+
+/// Encryption of knowledge tagged private
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct KnowledgeSection {
+    /// File holding the passphrase, `KNOWLEDGE_PASSPHRASE` takes precedence
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub key_file: Option<String>,
+}
+
+/// Guardrails for shared deployments, off unless enabled is true or the user is listed
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ComplianceSection {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub enabled: Option<bool>,
+    /// Usernames it is on for when it isn't enabled for everyone
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub users: Vec<String>,
+}
+
+/// Token unlock schedules stored as knowledge by the data_sources engine
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct UnlockSection {
+    /// API answering with a JSON array of unlock events
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub api_url: Option<String>,
+    /// CSV or JSON schedules, one per project or several
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub files: Vec<String>,
+}
+
+/// Which API serves completions, Anthropic unless provider is set
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LlmSection {
+    /// One of anthropic, openai or ollama
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub provider: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub model: Option<String>,
+    /// Root URL of an OpenAI-compatible server, e.g. vLLM's
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub base_url: Option<String>,
+    /// Replies shorter than this once trimmed are asked again
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub min_response_chars: Option<usize>,
+    /// Seconds an answer may take in total, context included
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub turn_budget_secs: Option<u64>,
+    /// Seconds the model gets however long the context took
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub model_floor_secs: Option<u64>,
+}
+
+impl AgentSettings {
+    /// Load settings from agent.toml, returning defaults if the file does not exist
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, SetupError> {
+        let path = path.as_ref();
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+
+        let contents = fs::read_to_string(path)?;
+        toml::from_str(&contents).map_err(|e| SetupError::InvalidConfig(e.to_string()))
+    }
+
+    /// Write the settings to agent.toml, keeping any other sections already in the file
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), SetupError> {
+        let path = path.as_ref();
+        let mut document = if path.exists() {
+            fs::read_to_string(path)?
+                .parse::<toml::Table>()
+                .map_err(|e| SetupError::InvalidConfig(e.to_string()))?
+        } else {
+            toml::Table::new()
+        };
+
+        let sections = toml::Table::try_from(self).map_err(|e| SetupError::InvalidConfig(e.to_string()))?;
+        for (name, value) in sections {
+            document.insert(name, value);
+        }
+
+        write_private(path, &document.to_string())
+    }
+
+    /// Environment variables equivalent to these settings
+    pub fn env_vars(&self) -> Vec<(&'static str, String)> {
+        let mut vars = Vec::new();
+        if let Some(url) = &self.database.url {
+            vars.push(("DATABASE_URL", url.clone()));
+        }
+        if let Some(key) = &self.api_keys.anthropic {
+            vars.push(("ANTHROPIC_API_KEY", key.clone()));
+        }
+        if let Some(key) = &self.api_keys.exa {
+            vars.push(("EXA_API_KEY", key.clone()));
+        }
+        if let Some(key) = &self.api_keys.coingecko {
+            vars.push(("COINGECKO_API_KEY", key.clone()));
+        }
+        if let Some(key) = &self.api_keys.openai {
+            vars.push(("OPENAI_API_KEY", key.clone()));
+        }
+        vars
+    }
+}
+
+/// Update the given variables in a .env file, leaving unrelated lines untouched
+pub fn write_env_file(path: impl AsRef<Path>, vars: &[(&str, String)]) -> Result<(), SetupError> {
+    let path = path.as_ref();
+    let existing = if path.exists() { fs::read_to_string(path)? } else { String::new() };
+
+    let mut remaining: Vec<&(&str, String)> = vars.iter().collect();
+    let mut lines = Vec::new();
+    for line in existing.lines() {
+        let name = line.split_once('=').map(|(name, _)| name.trim());
+        match remaining.iter().position(|(var, _)| Some(*var) == name) {
+            Some(index) => {
+                let (var, value) = remaining.remove(index);
+                lines.push(format!("{}={}", var, value));
+            },
+            None => lines.push(line.to_string()),
+        }
+    }
+    for (var, value) in remaining {
+        lines.push(format!("{}={}", var, value));
+    }
+
+    let mut contents = lines.join("\n");
+    contents.push('\n');
+    write_private(path, &contents)
+}
+
+/// Write a file that may contain secrets, readable only by the owner on unix
+fn write_private(path: &Path, contents: &str) -> Result<(), SetupError> {
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+        options.mode(0o600);
+        // The mode only applies to new files, so tighten existing ones too
+        if path.exists() {
+            fs::set_permissions(path, fs::Permissions::from_mode(0o600))?;
+        }
+    }
+
+    let mut file = options.open(path)?;
+    file.write_all(contents.as_bytes())?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_save_keeps_other_sections() {
+        let dir = super::super::tests::temp_dir();
+        let path = dir.join("agent.toml");
+        fs::write(&path, "[daemon]\nheartbeat_interval_secs = 30\n").unwrap();
+
+        let mut settings = AgentSettings::default();
+        settings.agent.username = Some("alice".to_string());
+        settings.api_keys.anthropic = Some("sk-ant-test".to_string());
+        settings.save(&path).unwrap();
+
+        let contents = fs::read_to_string(&path).unwrap();
+        assert!(contents.contains("heartbeat_interval_secs = 30"));
+        assert_eq!(AgentSettings::load(&path).unwrap(), settings);
+    }
+
+    #[test]
+    fn test_write_env_file_updates_in_place() {
+        let dir = super::super::tests::temp_dir();
+        let path = dir.join(".env");
+        fs::write(&path, "# comment\nANTHROPIC_API_KEY=old\nPRIVATE_KEY=abc\n").unwrap();
+
+        write_env_file(&path, &[
+            ("ANTHROPIC_API_KEY", "new".to_string()),
+            ("DATABASE_URL", "postgres://localhost/agent".to_string()),
+        ]).unwrap();
+
+        assert_eq!(
+            fs::read_to_string(&path).unwrap(),
+            "# comment\nANTHROPIC_API_KEY=new\nPRIVATE_KEY=abc\nDATABASE_URL=postgres://localhost/agent\n"
+        );
+    }
+}
@@ -0,0 +1,108 @@
+use async_trait::async_trait;
+use reqwest::{Client, StatusCode};
+use sqlx::postgres::PgPoolOptions;
+use std::time::Duration;
+
+/// Checks a value entered in the setup wizard against the real service
+/// Errors are human readable reasons shown to the user
+#[async_trait]
+pub trait Validator: Send + Sync {
+    async fn check_anthropic_key(&self, key: &str) -> Result<(), String>;
+    async fn check_exa_key(&self, key: &str) -> Result<(), String>;
+    async fn check_coingecko_key(&self, key: &str) -> Result<(), String>;
+    async fn check_database_url(&self, url: &str) -> Result<(), String>;
+}
+
+/// Validator that pings the APIs and connects to the database
+pub struct LiveValidator {
+    client: Client,
+}
+
+impl LiveValidator {
+    pub fn new() -> Self {
+        let client = Client::builder()
+            .timeout(Duration::from_secs(10))
+            .build()
+            .unwrap_or_default();
+        Self { client }
+    }
+}
+
+impl Default for LiveValidator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// Turn the status of a ping request into a validation result
+fn check_status(service: &str, status: StatusCode) -> Result<(), String> {
+    if status.is_success() {
+        Ok(())
+    } else if status == StatusCode::UNAUTHORIZED || status == StatusCode::FORBIDDEN {
+        Err(format!("{} rejected the API key", service))
+    } else {
+        Err(format!("{} returned status {}", service, status))
+    }
+}
+
+#[async_trait]
+impl Validator for LiveValidator {
+    async fn check_anthropic_key(&self, key: &str) -> Result<(), String> {
+        let response = self.client
+            .get("https://api.anthropic.com/v1/models")
+            .header("x-api-key", key)
+            .header("anthropic-version", "2023-06-01")
+            .send()
+            .await
+            .map_err(|e| format!("Could not reach Anthropic: {}", e))?;
+
+        check_status("Anthropic", response.status())
+    }
+
+    async fn check_exa_key(&self, key: &str) -> Result<(), String> {
+        let response = self.client
+            .post("https://api.exa.ai/search")
+            .header("x-api-key", key)
+            .json(&serde_json::json!({ "query": "bitcoin", "numResults": 1 }))
+            .send()
+            .await
+            .map_err(|e| format!("Could not reach Exa: {}", e))?;
+
+        check_status("Exa", response.status())
+    }
+
+    async fn check_coingecko_key(&self, key: &str) -> Result<(), String> {
+        let response = self.client
+            .get("https://api.coingecko.com/api/v3/ping")
+            .header("x-cg-demo-api-key", key)
+            .send()
+            .await
+            .map_err(|e| format!("Could not reach CoinGecko: {}", e))?;
+
+        check_status("CoinGecko", response.status())
+    }
+
+    async fn check_database_url(&self, url: &str) -> Result<(), String> {
+        if url.starts_with("sqlite:") {
+            return Err("SQLite is not supported by this build yet, please use a PostgreSQL URL".to_string());
+        }
+        if !url.starts_with("postgres://") && !url.starts_with("postgresql://") {
+            return Err("Expected a URL starting with postgres://".to_string());
+        }
+
+        let pool = PgPoolOptions::new()
+            .max_connections(1)
+            .acquire_timeout(Duration::from_secs(5))
+            .connect(url)
+            .await
+            .map_err(|e| format!("Could not connect to the database: {}", e))?;
+
+        let result = sqlx::query("SELECT 1")
+            .execute(&pool)
+            .await
+            .map(|_| ())
+            .map_err(|e| format!("Database query failed: {}", e));
+        pool.close().await;
+        result
+    }
+}
@@ -0,0 +1,227 @@
+use crate::config::Config;
+use crate::price_fetcher::{self, PriceError};
+use std::fmt;
+
+/// CoinGecko ids of the stablecoins watched when none are configured: USDT, USDC and DAI
+pub const DEFAULT_STABLECOINS: &[&str] = &["tether", "usd-coin", "dai"];
+
+/// Distance from $1, in percent, that raises a warning
+pub const DEFAULT_WARNING_PCT: f64 = 0.5;
+
+/// Distance from $1, in percent, that raises an alert
+pub const DEFAULT_ALERT_PCT: f64 = 2.0;
+
+/// Which stablecoins are watched and how far from $1 they may drift
+#[derive(Debug, Clone, PartialEq)]
+pub struct PegSettings {
+    /// CoinGecko ids
+    pub stablecoins: Vec<String>,
+    pub warning_pct: f64,
+    pub alert_pct: f64,
+}
+
+impl Default for PegSettings {
+    fn default() -> Self {
+        Self {
+            stablecoins: DEFAULT_STABLECOINS.iter().map(|id| id.to_string()).collect(),
+            warning_pct: DEFAULT_WARNING_PCT,
+            alert_pct: DEFAULT_ALERT_PCT,
+        }
+    }
+}
+
+impl PegSettings {
+    pub fn is_stablecoin(&self, coin_id: &str) -> bool {
+        self.stablecoins.iter().any(|id| id.eq_ignore_ascii_case(coin_id))
+    }
+}
+
+/// How far a stablecoin has drifted, ordered from least to most severe
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum PegLevel {
+    Stable,
+    Warning,
+    Alert,
+}
+
+impl fmt::Display for PegLevel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            PegLevel::Stable => "stable",
+            PegLevel::Warning => "warning",
+            PegLevel::Alert => "alert",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// A stablecoin's price against its $1 peg
+#[derive(Debug, Clone, PartialEq)]
+pub struct PegStatus {
+    pub coin_id: String,
+    pub price_usd: f64,
+    /// Signed distance from $1 in percent, rounded to a hundredth of a basis point
+    pub deviation_pct: f64,
+    pub level: PegLevel,
+}
+
+impl PegStatus {
+    /// Compare a price to $1 using the thresholds in `settings`, both bounds inclusive
+    pub fn evaluate(coin_id: &str, price_usd: f64, settings: &PegSettings) -> Self {
+        // Rounded so $1.005 is exactly 0.5% away despite float error
+        let deviation_pct = ((price_usd - 1.0) * 1_000_000.0).round() / 10_000.0;
+        let level = if deviation_pct.abs() >= settings.alert_pct {
+            PegLevel::Alert
+        } else if deviation_pct.abs() >= settings.warning_pct {
+            PegLevel::Warning
+        } else {
+            PegLevel::Stable
+        };
+        Self { coin_id: coin_id.to_string(), price_usd, deviation_pct, level }
+    }
+
+    /// One line describing the peg, e.g. "Depeg warning: USDC is at $0.9940, 0.60% below its $1 peg."
+    pub fn message(&self) -> String {
+        let symbol = symbol(&self.coin_id);
+        let side = if self.deviation_pct < 0.0 { "below" } else { "above" };
+        match self.level {
+            PegLevel::Stable if self.deviation_pct == 0.0 => format!("Peg: {} is holding its $1 peg at ${:.4}.", symbol, self.price_usd),
+            PegLevel::Stable => format!(
+                "Peg: {} is holding its $1 peg at ${:.4}, {:.2}% {}.",
+                symbol,
+                self.price_usd,
+                self.deviation_pct.abs(),
+                side
+            ),
+            PegLevel::Warning | PegLevel::Alert => format!(
+                "Depeg {}: {} is at ${:.4}, {:.2}% {} its $1 peg.",
+                self.level,
+                symbol,
+                self.price_usd,
+                self.deviation_pct.abs(),
+                side
+            ),
+        }
+    }
+}
+
+/// Ticker of a stablecoin for messages, the uppercased id for ones not listed here
+pub fn symbol(coin_id: &str) -> String {
+    match coin_id {
+        "tether" => "USDT".to_string(),
+        "usd-coin" => "USDC".to_string(),
+        "dai" => "DAI".to_string(),
+        "ethena-usde" => "USDe".to_string(),
+        "first-digital-usd" => "FDUSD".to_string(),
+        "paypal-usd" => "PYUSD".to_string(),
+        other => other.to_uppercase(),
+    }
+}
+
+/// The message to send when a stablecoin's level changed since the last check
+///
+/// Nothing is sent while the level stays the same, so a lasting depeg isn't repeated every tick.
+/// A first check only reports a depeg, and a recovery is reported once the price is back within the warning band.
+pub fn peg_change_message(previous: Option<PegLevel>, status: &PegStatus) -> Option<String> {
+    match (previous, status.level) {
+        (Some(previous), level) if previous == level => None,
+        (None, PegLevel::Stable) => None,
+        (Some(_), PegLevel::Stable) => Some(format!(
+            "{} is back at its $1 peg at ${:.4}.",
+            symbol(&status.coin_id),
+            status.price_usd
+        )),
+        _ => Some(status.message()),
+    }
+}
+
+/// Peg settings from the config, the defaults when it can't be loaded
+pub fn settings() -> PegSettings {
+    Config::get_instance().map(|config| config.stablecoins.clone()).unwrap_or_default()
+}
+
+/// Price every configured stablecoin and compare it to $1
+/// Stablecoins without a price are left out
+pub async fn check_stablecoin_pegs(settings: &PegSettings) -> Result<Vec<PegStatus>, PriceError> {
+    if settings.stablecoins.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let coin_ids: Vec<&str> = settings.stablecoins.iter().map(String::as_str).collect();
+    let prices = price_fetcher::fetch_multiple_coin_prices(&coin_ids).await?;
+    Ok(coin_ids
+        .iter()
+        .filter_map(|coin_id| prices.get(*coin_id).map(|price| PegStatus::evaluate(coin_id, *price, settings)))
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn level(price: f64) -> PegLevel {
+        PegStatus::evaluate("usd-coin", price, &PegSettings::default()).level
+    }
+
+    #[test]
+    fn test_thresholds_are_inclusive_on_both_sides() {
+        assert_eq!(level(1.0), PegLevel::Stable);
+        assert_eq!(level(0.9951), PegLevel::Stable);
+        assert_eq!(level(1.0049), PegLevel::Stable);
+        assert_eq!(level(0.995), PegLevel::Warning);
+        assert_eq!(level(1.005), PegLevel::Warning);
+        assert_eq!(level(0.9801), PegLevel::Warning);
+        assert_eq!(level(0.98), PegLevel::Alert);
+        assert_eq!(level(1.02), PegLevel::Alert);
+        assert_eq!(level(0.87), PegLevel::Alert);
+
+        let tight = PegSettings { warning_pct: 0.1, alert_pct: 0.3, ..PegSettings::default() };
+        assert_eq!(PegStatus::evaluate("dai", 0.998, &tight).level, PegLevel::Warning);
+        assert_eq!(PegStatus::evaluate("dai", 1.003, &tight).level, PegLevel::Alert);
+    }
+
+    #[test]
+    fn test_messages_above_and_below_peg() {
+        let settings = PegSettings::default();
+        let message = |coin_id: &str, price: f64| PegStatus::evaluate(coin_id, price, &settings).message();
+
+        assert_eq!(message("usd-coin", 1.0), "Peg: USDC is holding its $1 peg at $1.0000.");
+        assert_eq!(message("tether", 0.9998), "Peg: USDT is holding its $1 peg at $0.9998, 0.02% below.");
+        assert_eq!(message("dai", 1.0012), "Peg: DAI is holding its $1 peg at $1.0012, 0.12% above.");
+        assert_eq!(message("usd-coin", 0.994), "Depeg warning: USDC is at $0.9940, 0.60% below its $1 peg.");
+        assert_eq!(message("tether", 1.025), "Depeg alert: USDT is at $1.0250, 2.50% above its $1 peg.");
+        assert_eq!(message("usd-coin", 0.877), "Depeg alert: USDC is at $0.8770, 12.30% below its $1 peg.");
+        assert_eq!(message("gho", 0.97), "Depeg alert: GHO is at $0.9700, 3.00% below its $1 peg.");
+    }
+
+    #[test]
+    fn test_only_level_changes_are_notified() {
+        let settings = PegSettings::default();
+        let status = |price: f64| PegStatus::evaluate("usd-coin", price, &settings);
+
+        assert_eq!(peg_change_message(None, &status(1.0)), None);
+        assert_eq!(
+            peg_change_message(None, &status(0.99)).as_deref(),
+            Some("Depeg warning: USDC is at $0.9900, 1.00% below its $1 peg.")
+        );
+        assert_eq!(peg_change_message(Some(PegLevel::Warning), &status(0.992)), None);
+        assert_eq!(
+            peg_change_message(Some(PegLevel::Warning), &status(0.95)).as_deref(),
+            Some("Depeg alert: USDC is at $0.9500, 5.00% below its $1 peg.")
+        );
+        assert!(peg_change_message(Some(PegLevel::Alert), &status(0.99)).unwrap().starts_with("Depeg warning"));
+        assert_eq!(
+            peg_change_message(Some(PegLevel::Warning), &status(0.9995)).as_deref(),
+            Some("USDC is back at its $1 peg at $0.9995.")
+        );
+        assert_eq!(peg_change_message(Some(PegLevel::Stable), &status(1.0001)), None);
+    }
+
+    #[test]
+    fn test_is_stablecoin() {
+        let settings = PegSettings::default();
+        assert!(settings.is_stablecoin("usd-coin"));
+        assert!(settings.is_stablecoin("Tether"));
+        assert!(!settings.is_stablecoin("bitcoin"));
+    }
+}
@@ -4,9 +4,27 @@ use std::fs::{self, File};
 use std::io::{self, Read, Write};
 use std::path::{Path, PathBuf};
 use chrono::{DateTime, Utc};
-use anyhow::Result;
+use thiserror::Error;
 use crate::personality::Personality;
 
+/// Errors raised while managing strategies on disk
+#[derive(Debug, Error)]
+pub enum StrategyError {
+    #[error("IO error: {0}")]
+    Io(#[from] io::Error),
+    
+    #[error("Serialization error: {0}")]
+    Serialization(#[from] serde_json::Error),
+    
+    #[error("Strategy not found: {0}")]
+    NotFound(String),
+}
+
+pub type Result<T> = std::result::Result<T, StrategyError>;
+
+/// Default directory holding strategy files
+pub const STRATEGIES_DIR: &str = "./strategies";
+
 /// Structure to represent a trading or yield strategy
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Strategy {
@@ -64,7 +82,7 @@ impl StrategyManager {
             let entry = entry?;
             let path = entry.path();
             
-            if path.is_file() && path.extension().map_or(false, |ext| ext == "json") {
+            if path.is_file() && path.extension().is_some_and(|ext| ext == "json") {
                 let mut file = File::open(&path)?;
                 let mut contents = String::new();
                 file.read_to_string(&mut contents)?;
@@ -101,7 +119,7 @@ impl StrategyManager {
             self.strategies.insert(strategy.id.clone(), strategy);
             Ok(())
         } else {
-            Err(anyhow::anyhow!("Strategy not found: {}", strategy.id))
+            Err(StrategyError::NotFound(strategy.id))
         }
     }
     
@@ -145,10 +163,24 @@ impl StrategyManager {
             }
             Ok(())
         } else {
-            Err(anyhow::anyhow!("Strategy not found: {}", id))
+            Err(StrategyError::NotFound(id.to_string()))
         }
     }
     
+    /// Delete every strategy written by `author`, returning how many were removed
+    pub fn delete_strategies_by_author(&mut self, author: &str) -> Result<usize> {
+        let ids: Vec<String> = self.strategies.values()
+            .filter(|s| s.author.as_deref() == Some(author))
+            .map(|s| s.id.clone())
+            .collect();
+        
+        for id in &ids {
+            self.delete_strategy(id)?;
+        }
+        
+        Ok(ids.len())
+    }
+    
     /// Save a strategy to disk
     fn save_strategy(&self, strategy: &Strategy) -> Result<()> {
         let file_path = self.get_file_path(&strategy.id);
@@ -179,6 +211,7 @@ impl StrategyManager {
     }
     
     /// Create a strategy from template
+    #[allow(clippy::too_many_arguments)]
     pub fn create_strategy_from_template(
         id: &str,
         name: &str,
@@ -356,3 +389,54 @@ pub async fn interactive_add_strategy(strategy_manager: &mut StrategyManager) ->
     
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_dir() -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("agent-friend-strategies-{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn test_invalid_strategy_file_is_a_serialization_error() {
+        let dir = temp_dir();
+        fs::write(dir.join("broken.json"), "{ not json").unwrap();
+
+        assert!(matches!(StrategyManager::new(&dir), Err(StrategyError::Serialization(_))));
+    }
+
+    #[test]
+    fn test_missing_strategy_is_not_found() {
+        let mut manager = StrategyManager::new(&temp_dir()).unwrap();
+        let strategy = StrategyManager::create_strategy_from_template(
+            "dca", "DCA", "accumulation", "Buy weekly", RiskLevel::Low,
+            vec![], vec![], vec![], None, None,
+        );
+
+        assert!(matches!(manager.update_strategy(strategy), Err(StrategyError::NotFound(id)) if id == "dca"));
+        assert!(matches!(manager.delete_strategy("dca"), Err(StrategyError::NotFound(_))));
+    }
+
+    #[test]
+    fn test_delete_strategies_by_author() {
+        let dir = temp_dir();
+        let mut manager = StrategyManager::new(&dir).unwrap();
+        for (id, author) in [("dca", Some("alice")), ("grid", Some("bob")), ("hodl", None), ("lp", Some("alice"))] {
+            manager.add_strategy(StrategyManager::create_strategy_from_template(
+                id, id, "accumulation", "", RiskLevel::Low,
+                vec![], vec![], vec![], None, author.map(str::to_string),
+            )).unwrap();
+        }
+
+        assert_eq!(manager.delete_strategies_by_author("alice").unwrap(), 2);
+        let mut remaining: Vec<String> = fs::read_dir(&dir).unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+            .collect();
+        remaining.sort();
+        assert_eq!(remaining, vec!["grid.json", "hodl.json"]);
+        assert!(manager.get_strategy("dca").is_none());
+    }
+}
@@ -0,0 +1,388 @@
+use crate::db::{Strategy, StrategyOutcome, StrategyProgress};
+use chrono::NaiveDateTime;
+use regex::Regex;
+use std::sync::OnceLock;
+use thiserror::Error;
+
+/// Working through a saved strategy's steps, or a request to see how far along it is
+#[derive(Debug, Clone, PartialEq)]
+pub enum ProgressCommand {
+    Start {
+        strategy: String,
+    },
+    /// `step` counts from 1, as the checklist shows it; no strategy means the one being worked through
+    Complete {
+        step: usize,
+        strategy: Option<String>,
+        note: Option<String>,
+    },
+    Show {
+        strategy: Option<String>,
+    },
+}
+
+/// Why a step can't be marked done
+#[derive(Debug, Clone, PartialEq, Error)]
+pub enum StepError {
+    #[error("There's no step {step}, the strategy has {steps} step(s).")]
+    OutOfRange { step: usize, steps: usize },
+
+    #[error("Step {step} is already done, since {}.", .completed_at.format("%Y-%m-%d"))]
+    AlreadyComplete { step: usize, completed_at: NaiveDateTime },
+}
+
+/// Parse the arguments of `/strategy`
+pub fn parse_command_args(args: &[&str]) -> Option<ProgressCommand> {
+    let words = |words: &[&str]| Some(words.join(" ")).filter(|text| !text.is_empty());
+
+    match args {
+        ["start", name @ ..] => words(name).map(|strategy| ProgressCommand::Start { strategy }),
+        ["done", step, note @ ..] => {
+            Some(ProgressCommand::Complete { step: step.parse().ok()?, strategy: None, note: words(note) })
+        }
+        ["progress", name @ ..] => Some(ProgressCommand::Show { strategy: words(name) }),
+        _ => None,
+    }
+}
+
+fn start_regex() -> &'static Regex {
+    static REGEX: OnceLock<Regex> = OnceLock::new();
+    REGEX.get_or_init(|| {
+        Regex::new(r"(?i)^\s*(?:please\s+)?(?:start|begin)\s+(?:executing|working\s+(?:through|on)|following|running)\s+(?:my\s+|the\s+)?(.+?)\s+strategy\b").unwrap()
+    })
+}
+
+fn complete_regex() -> &'static Regex {
+    static REGEX: OnceLock<Regex> = OnceLock::new();
+    REGEX.get_or_init(|| {
+        Regex::new(r"(?i)^\s*(?:please\s+)?(?:mark\s+)?step\s+(\d+)(?:\s+(?:of|on|in|for)\s+(?:my\s+|the\s+)?(.+?)\s+strategy)?\s+(?:as\s+)?(?:done|complete|completed|finished)\b[.!]?(?:\s*[:,-]\s*(.+))?").unwrap()
+    })
+}
+
+fn did_step_regex() -> &'static Regex {
+    static REGEX: OnceLock<Regex> = OnceLock::new();
+    REGEX.get_or_init(|| {
+        Regex::new(r"(?i)^\s*i(?:\s+have|'ve)?\s+(?:did|done|finished|completed)\s+step\s+(\d+)(?:\s+(?:of|on|in|for)\s+(?:my\s+|the\s+)?(.+?)\s+strategy)?\b[.!]?(?:\s*[:,-]\s*(.+))?").unwrap()
+    })
+}
+
+fn show_regex() -> &'static Regex {
+    static REGEX: OnceLock<Regex> = OnceLock::new();
+    REGEX.get_or_init(|| {
+        Regex::new(r"(?i)(?:where\s+am\s+i|how\s+far\s+(?:along\s+)?am\s+i|(?:show|check)\s+(?:me\s+)?(?:my\s+)?progress|what'?s\s+my\s+progress)\s+(?:on|with|in|of|for)\s+(?:my\s+|the\s+)?(?:(.+?)\s+)?strategy\b").unwrap()
+    })
+}
+
+fn text(capture: Option<regex::Match>) -> Option<String> {
+    capture.map(|found| found.as_str().trim().to_string()).filter(|found| !found.is_empty())
+}
+
+/// Parse "start executing my DCA strategy", "mark step 2 done: bought 0.1 ETH",
+/// "mark step 2 of my DCA strategy done" and "where am I on my ETH accumulation strategy"
+pub fn parse_chat_message(message: &str) -> Option<ProgressCommand> {
+    if let Some(captures) = start_regex().captures(message) {
+        return Some(ProgressCommand::Start { strategy: captures[1].trim().to_string() });
+    }
+    if let Some(captures) = complete_regex().captures(message).or_else(|| did_step_regex().captures(message)) {
+        return Some(ProgressCommand::Complete {
+            step: captures[1].parse().ok()?,
+            strategy: text(captures.get(2)),
+            note: text(captures.get(3)),
+        });
+    }
+    if let Some(captures) = show_regex().captures(message) {
+        return Some(ProgressCommand::Show { strategy: text(captures.get(1)) });
+    }
+    None
+}
+
+/// Whether a reply turns down recording an outcome note
+pub fn is_skip(message: &str) -> bool {
+    let reply = message.trim().trim_end_matches(['.', '!']).to_lowercase();
+    matches!(reply.as_str(), "skip" | "no" | "n" | "nope" | "no thanks" | "not now")
+}
+
+/// A reply to the outcome question taken as the note: not a question or another progress command
+pub fn outcome_note(message: &str) -> Option<&str> {
+    let note = message.trim();
+    if note.is_empty()
+        || note.starts_with('/')
+        || note.ends_with('?')
+        || is_skip(note)
+        || parse_chat_message(note).is_some()
+    {
+        return None;
+    }
+    Some(note)
+}
+
+/// The strategy a name like "DCA" or "ETH accumulation" refers to
+///
+/// Names containing every word win over matches that need the description or tags.
+pub fn find_strategy<'a>(strategies: &'a [Strategy], name: &str) -> Option<&'a Strategy> {
+    let name = name.to_lowercase();
+    let words: Vec<&str> = name.split_whitespace().filter(|word| !matches!(*word, "my" | "the" | "strategy")).collect();
+    if words.is_empty() {
+        return None;
+    }
+
+    let matches = |haystack: &str| words.iter().all(|word| haystack.contains(word));
+    strategies
+        .iter()
+        .find(|strategy| strategy.name.to_lowercase() == name)
+        .or_else(|| strategies.iter().find(|strategy| matches(&strategy.name.to_lowercase())))
+        .or_else(|| {
+            strategies.iter().find(|strategy| {
+                let haystack = format!(
+                    "{} {} {} {}",
+                    strategy.name,
+                    strategy.category,
+                    strategy.description,
+                    strategy.tags.join(" ")
+                );
+                matches(&haystack.to_lowercase())
+            })
+        })
+}
+
+/// Reply when no saved strategy matches a name
+pub fn unknown_strategy(name: &str) -> String {
+    format!("I couldn't find a saved strategy matching \"{}\". /strategies lists yours.", name)
+}
+
+/// The `step_index` of step `step` (from 1) if it can be marked done
+pub fn validate_step(progress: &[StrategyProgress], step: usize) -> Result<i32, StepError> {
+    let out_of_range = StepError::OutOfRange { step, steps: progress.len() };
+    let index = step.checked_sub(1).ok_or(out_of_range.clone())?;
+    let current = progress.get(index).ok_or(out_of_range)?;
+    match current.completed_at {
+        Some(completed_at) => Err(StepError::AlreadyComplete { step, completed_at }),
+        None => Ok(current.step_index),
+    }
+}
+
+/// Whether every step of a started strategy is done
+pub fn is_finished(progress: &[StrategyProgress]) -> bool {
+    !progress.is_empty() && progress.iter().all(|step| step.completed_at.is_some())
+}
+
+/// The strategy's steps as a checklist with completion dates and notes, then its past outcomes
+pub fn render_checklist(strategy: &Strategy, progress: &[StrategyProgress], outcomes: &[StrategyOutcome]) -> String {
+    if progress.is_empty() {
+        return format!(
+            "You haven't started {}. Say \"start executing my {} strategy\" to work through its steps.",
+            strategy.name, strategy.name
+        );
+    }
+
+    let done = progress.iter().filter(|step| step.completed_at.is_some()).count();
+    let mut output = format!("{}: {} of {} steps done\n", strategy.name, done, progress.len());
+    for step in progress {
+        let description = strategy
+            .steps
+            .get(step.step_index as usize)
+            .map(String::as_str)
+            .unwrap_or("(step removed from the strategy)");
+        let line = match step.completed_at {
+            Some(completed_at) => {
+                format!("[x] {}. {} (done {})", step.step_index + 1, description, completed_at.format("%Y-%m-%d"))
+            }
+            None => format!("[ ] {}. {}", step.step_index + 1, description),
+        };
+        output.push_str(&line);
+        if let Some(note) = &step.note {
+            output.push_str(&format!(" - {}", note));
+        }
+        output.push('\n');
+    }
+
+    if !outcomes.is_empty() {
+        output.push_str("\nPast outcomes:\n");
+        for outcome in outcomes {
+            output.push_str(&format!("- {}: {}\n", outcome.created_at.format("%Y-%m-%d"), outcome.note));
+        }
+    }
+    output.trim_end().to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(text: &str) -> NaiveDateTime {
+        NaiveDateTime::parse_from_str(text, "%Y-%m-%d %H:%M:%S").unwrap()
+    }
+
+    fn strategy(name: &str, description: &str, steps: &[&str]) -> Strategy {
+        Strategy {
+            id: 7,
+            user_id: 1,
+            strategy_id: name.to_lowercase().replace(' ', "-"),
+            name: name.to_string(),
+            category: "accumulation".to_string(),
+            description: description.to_string(),
+            risk_level: "low".to_string(),
+            tags: vec!["eth".to_string()],
+            steps: steps.iter().map(|step| step.to_string()).collect(),
+            requirements: Vec::new(),
+            expected_returns: serde_json::json!({}),
+            created_at: at("2025-10-01 00:00:00"),
+            updated_at: at("2025-10-01 00:00:00"),
+            author: "alice".to_string(),
+            version: "1.0".to_string(),
+        }
+    }
+
+    fn step(step_index: i32, completed_at: Option<&str>, note: Option<&str>) -> StrategyProgress {
+        StrategyProgress {
+            strategy_id: 7,
+            user_id: 1,
+            step_index,
+            completed_at: completed_at.map(at),
+            note: note.map(str::to_string),
+        }
+    }
+
+    #[test]
+    fn test_parse_command_args() {
+        assert_eq!(
+            parse_command_args(&["start", "ETH", "accumulation"]),
+            Some(ProgressCommand::Start { strategy: "ETH accumulation".to_string() })
+        );
+        assert_eq!(
+            parse_command_args(&["done", "2", "bought", "0.1", "ETH"]),
+            Some(ProgressCommand::Complete { step: 2, strategy: None, note: Some("bought 0.1 ETH".to_string()) })
+        );
+        assert_eq!(parse_command_args(&["progress"]), Some(ProgressCommand::Show { strategy: None }));
+        assert_eq!(
+            parse_command_args(&["progress", "dca"]),
+            Some(ProgressCommand::Show { strategy: Some("dca".to_string()) })
+        );
+        assert_eq!(parse_command_args(&["start"]), None);
+        assert_eq!(parse_command_args(&["done", "two"]), None);
+    }
+
+    #[test]
+    fn test_parse_chat_message() {
+        assert_eq!(
+            parse_chat_message("Start executing my DCA strategy"),
+            Some(ProgressCommand::Start { strategy: "DCA".to_string() })
+        );
+        assert_eq!(
+            parse_chat_message("please begin working through the ETH accumulation strategy"),
+            Some(ProgressCommand::Start { strategy: "ETH accumulation".to_string() })
+        );
+        assert_eq!(
+            parse_chat_message("mark step 2 done"),
+            Some(ProgressCommand::Complete { step: 2, strategy: None, note: None })
+        );
+        assert_eq!(
+            parse_chat_message("Mark step 3 of my DCA strategy as complete: bought 0.1 ETH"),
+            Some(ProgressCommand::Complete {
+                step: 3,
+                strategy: Some("DCA".to_string()),
+                note: Some("bought 0.1 ETH".to_string())
+            })
+        );
+        assert_eq!(
+            parse_chat_message("I finished step 1"),
+            Some(ProgressCommand::Complete { step: 1, strategy: None, note: None })
+        );
+        assert_eq!(
+            parse_chat_message("where am I on my ETH accumulation strategy?"),
+            Some(ProgressCommand::Show { strategy: Some("ETH accumulation".to_string()) })
+        );
+        assert_eq!(
+            parse_chat_message("how far along am I with my strategy"),
+            Some(ProgressCommand::Show { strategy: None })
+        );
+        assert_eq!(parse_chat_message("what's a good DCA strategy?"), None);
+        assert_eq!(parse_chat_message("what is step 2 of a DCA strategy"), None);
+    }
+
+    #[test]
+    fn test_outcome_note() {
+        assert_eq!(
+            outcome_note("  Averaged in at $2,450, would buy dips sooner next time "),
+            Some("Averaged in at $2,450, would buy dips sooner next time")
+        );
+        assert!(is_skip("No thanks."));
+        assert_eq!(outcome_note("skip"), None);
+        assert_eq!(outcome_note("what's the price of eth?"), None);
+        assert_eq!(outcome_note("start executing my DCA strategy"), None);
+        assert_eq!(outcome_note("/strategies"), None);
+    }
+
+    #[test]
+    fn test_find_strategy() {
+        let strategies = [
+            strategy("Weekly DCA", "Buy a fixed amount of ETH every week", &["Buy"]),
+            strategy("ETH Accumulation", "Add to ETH on dips", &["Buy"]),
+        ];
+        assert_eq!(find_strategy(&strategies, "DCA").unwrap().name, "Weekly DCA");
+        assert_eq!(find_strategy(&strategies, "eth accumulation").unwrap().name, "ETH Accumulation");
+        // Words missing from every name are looked for in descriptions
+        assert_eq!(find_strategy(&strategies, "dips").unwrap().name, "ETH Accumulation");
+        assert!(find_strategy(&strategies, "yield farming").is_none());
+        assert!(find_strategy(&strategies, "my").is_none());
+    }
+
+    #[test]
+    fn test_validate_step() {
+        let progress = [step(0, Some("2025-10-07 09:00:00"), None), step(1, None, None), step(2, None, None)];
+        assert_eq!(validate_step(&progress, 2), Ok(1));
+        assert_eq!(validate_step(&progress, 3), Ok(2));
+        assert_eq!(validate_step(&progress, 4), Err(StepError::OutOfRange { step: 4, steps: 3 }));
+        assert_eq!(validate_step(&progress, 0), Err(StepError::OutOfRange { step: 0, steps: 3 }));
+        assert_eq!(
+            validate_step(&progress, 1),
+            Err(StepError::AlreadyComplete { step: 1, completed_at: at("2025-10-07 09:00:00") })
+        );
+
+        assert_eq!(
+            validate_step(&progress, 4).unwrap_err().to_string(),
+            "There's no step 4, the strategy has 3 step(s)."
+        );
+        assert_eq!(validate_step(&progress, 1).unwrap_err().to_string(), "Step 1 is already done, since 2025-10-07.");
+    }
+
+    #[test]
+    fn test_is_finished() {
+        assert!(!is_finished(&[]));
+        assert!(!is_finished(&[step(0, Some("2025-10-07 09:00:00"), None), step(1, None, None)]));
+        assert!(is_finished(&[step(0, Some("2025-10-07 09:00:00"), None), step(1, Some("2025-10-08 09:00:00"), None)]));
+    }
+
+    #[test]
+    fn test_render_checklist() {
+        let dca = strategy("Weekly DCA", "", &["Pick a weekly budget", "Buy ETH every Monday", "Review after 8 weeks"]);
+        let progress = [
+            step(0, Some("2025-10-06 09:00:00"), Some("$100 a week")),
+            step(1, Some("2025-10-07 18:30:00"), None),
+            step(2, None, None),
+        ];
+        assert_eq!(
+            render_checklist(&dca, &progress, &[]),
+            "Weekly DCA: 2 of 3 steps done\n\
+            [x] 1. Pick a weekly budget (done 2025-10-06) - $100 a week\n\
+            [x] 2. Buy ETH every Monday (done 2025-10-07)\n\
+            [ ] 3. Review after 8 weeks"
+        );
+
+        let outcome = StrategyOutcome {
+            id: 1,
+            strategy_id: 7,
+            user_id: 1,
+            note: "averaged in at $2,450".to_string(),
+            created_at: at("2025-09-01 12:00:00"),
+        };
+        let rendered = render_checklist(&dca, &progress[..1], &[outcome]);
+        assert!(rendered.starts_with("Weekly DCA: 1 of 1 steps done\n"));
+        assert!(rendered.ends_with("\n\nPast outcomes:\n- 2025-09-01: averaged in at $2,450"));
+
+        assert_eq!(
+            render_checklist(&dca, &[], &[]),
+            "You haven't started Weekly DCA. Say \"start executing my Weekly DCA strategy\" to work through its steps."
+        );
+    }
+}
@@ -0,0 +1,373 @@
+use crate::price_fetcher::DailyBar;
+
+/// Days of daily prices and volumes the levels are derived from
+pub const LOOKBACK_DAYS: u32 = 90;
+
+/// Price buckets of the volume profile
+pub const VOLUME_BINS: usize = 24;
+
+/// Days on each side a swing high or low has to stand out from
+pub const SWING_WINDOW: usize = 3;
+
+/// Highest-volume nodes reported as levels
+pub const VOLUME_NODES: usize = 3;
+
+/// Levels closer than this fraction of their price are merged into one
+pub const MERGE_TOLERANCE: f64 = 0.015;
+
+/// Where a price level comes from
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LevelSource {
+    /// A local high or low of the daily closes
+    Swing,
+    /// A price zone where much of the volume traded
+    VolumeNode,
+    /// A swing level and a volume node at the same price
+    SwingAndVolume,
+    /// A fixed percentage away from the current price, used when the history has too few levels
+    Range,
+}
+
+impl LevelSource {
+    pub fn label(&self) -> &'static str {
+        match self {
+            LevelSource::Swing => "swing",
+            LevelSource::VolumeNode => "volume node",
+            LevelSource::SwingAndVolume => "swing + volume node",
+            LevelSource::Range => "range estimate",
+        }
+    }
+
+    // Derived sources win over range estimates, swing and volume together make both
+    fn combine(self, other: LevelSource) -> LevelSource {
+        match (self, other) {
+            (a, b) if a == b => a,
+            (LevelSource::Range, other) | (other, LevelSource::Range) => other,
+            _ => LevelSource::SwingAndVolume,
+        }
+    }
+}
+
+/// A support or resistance candidate
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Level {
+    pub price: f64,
+    pub source: LevelSource,
+}
+
+/// Volume traded while the close was in one price bucket
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct VolumeBin {
+    pub low: f64,
+    pub high: f64,
+    pub volume: f64,
+}
+
+impl VolumeBin {
+    pub fn mid(&self) -> f64 {
+        (self.low + self.high) / 2.0
+    }
+}
+
+/// Supports below and resistances above the current price, nearest first
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct KeyLevels {
+    pub supports: Vec<Level>,
+    pub resistances: Vec<Level>,
+}
+
+/// Volume-by-price histogram: each day's volume goes to the bucket of its close
+///
+/// Buckets split the range between the lowest and highest close evenly. Days with an unusable
+/// price or volume are skipped.
+pub fn volume_profile(bars: &[DailyBar], bins: usize) -> Vec<VolumeBin> {
+    let usable: Vec<&DailyBar> = bars
+        .iter()
+        .filter(|bar| bar.price_usd.is_finite() && bar.price_usd > 0.0 && bar.volume_usd.is_finite() && bar.volume_usd >= 0.0)
+        .collect();
+    if usable.is_empty() || bins == 0 {
+        return Vec::new();
+    }
+
+    let low = usable.iter().map(|bar| bar.price_usd).fold(f64::INFINITY, f64::min);
+    let high = usable.iter().map(|bar| bar.price_usd).fold(f64::NEG_INFINITY, f64::max);
+    if high == low {
+        let volume = usable.iter().map(|bar| bar.volume_usd).sum();
+        return vec![VolumeBin { low, high, volume }];
+    }
+
+    let width = (high - low) / bins as f64;
+    let mut profile: Vec<VolumeBin> = (0..bins)
+        .map(|i| VolumeBin {
+            low: low + width * i as f64,
+            high: low + width * (i + 1) as f64,
+            volume: 0.0,
+        })
+        .collect();
+    for bar in usable {
+        // The highest close belongs to the last bucket
+        let index = (((bar.price_usd - low) / width) as usize).min(bins - 1);
+        profile[index].volume += bar.volume_usd;
+    }
+    profile
+}
+
+/// Mid prices of the `count` highest-volume peaks of a profile, highest volume first
+///
+/// A peak is a bucket with more volume than its neighbours, so one wide zone of heavy trading
+/// counts once rather than filling every slot.
+pub fn volume_nodes(profile: &[VolumeBin], count: usize) -> Vec<f64> {
+    let mut peaks: Vec<&VolumeBin> = profile
+        .iter()
+        .enumerate()
+        .filter(|(i, bin)| {
+            let left = if *i > 0 { profile[i - 1].volume } else { 0.0 };
+            let right = profile.get(i + 1).map(|next| next.volume).unwrap_or(0.0);
+            bin.volume > 0.0 && bin.volume >= left && bin.volume > right
+        })
+        .map(|(_, bin)| bin)
+        .collect();
+    peaks.sort_by(|a, b| b.volume.total_cmp(&a.volume));
+    peaks.into_iter().take(count).map(VolumeBin::mid).collect()
+}
+
+/// Closes that are strictly the lowest or highest within `window` days on each side, oldest first
+pub fn swing_levels(bars: &[DailyBar], window: usize) -> Vec<f64> {
+    if window == 0 || bars.len() < 2 * window + 1 {
+        return Vec::new();
+    }
+    (window..bars.len() - window)
+        .filter_map(|i| {
+            let price = bars[i].price_usd;
+            let neighbours = bars[i - window..=i + window]
+                .iter()
+                .enumerate()
+                .filter(|(j, _)| *j != window)
+                .map(|(_, bar)| bar.price_usd);
+            let (mut lowest, mut highest) = (true, true);
+            for other in neighbours {
+                lowest &= price < other;
+                highest &= price > other;
+            }
+            (lowest || highest).then_some(price)
+        })
+        .collect()
+}
+
+/// Sort levels by price and merge those within `tolerance` (a fraction of the price) of each other
+///
+/// A merged level sits at the average price of its members.
+pub fn merge_levels(mut levels: Vec<Level>, tolerance: f64) -> Vec<Level> {
+    levels.retain(|level| level.price.is_finite() && level.price > 0.0);
+    levels.sort_by(|a, b| a.price.total_cmp(&b.price));
+
+    let mut merged: Vec<(Level, usize)> = Vec::new();
+    for level in levels {
+        match merged.last_mut() {
+            Some((group, members)) if (level.price - group.price).abs() <= group.price * tolerance => {
+                group.price = (group.price * *members as f64 + level.price) / (*members + 1) as f64;
+                group.source = group.source.combine(level.source);
+                *members += 1;
+            },
+            _ => merged.push((level, 1)),
+        }
+    }
+    merged.into_iter().map(|(level, _)| level).collect()
+}
+
+/// Swing and volume node levels split around the current price
+///
+/// Levels within the merge tolerance of the current price are neither support nor resistance.
+pub fn key_levels(bars: &[DailyBar], current_price: f64) -> KeyLevels {
+    let swings = swing_levels(bars, SWING_WINDOW)
+        .into_iter()
+        .map(|price| Level { price, source: LevelSource::Swing });
+    let nodes = volume_nodes(&volume_profile(bars, VOLUME_BINS), VOLUME_NODES)
+        .into_iter()
+        .map(|price| Level { price, source: LevelSource::VolumeNode });
+    let merged = merge_levels(swings.chain(nodes).collect(), MERGE_TOLERANCE);
+
+    let mut supports: Vec<Level> = merged
+        .iter()
+        .copied()
+        .filter(|level| level.price < current_price * (1.0 - MERGE_TOLERANCE))
+        .collect();
+    supports.reverse();
+    let resistances = merged
+        .into_iter()
+        .filter(|level| level.price > current_price * (1.0 + MERGE_TOLERANCE))
+        .collect();
+    KeyLevels { supports, resistances }
+}
+
+/// Exactly three levels on one side of the price, nearest first
+///
+/// The nearest derived levels come first; range estimates from `fallback` fill the missing
+/// slots, skipping any that would duplicate a derived level.
+pub fn fill_levels(current_price: f64, derived: &[Level], fallback: [f64; 3]) -> Vec<Level> {
+    let mut levels: Vec<Level> = derived.iter().take(3).copied().collect();
+    for price in fallback {
+        if levels.len() == 3 {
+            break;
+        }
+        let duplicate = levels
+            .iter()
+            .any(|level| (level.price - price).abs() <= level.price * MERGE_TOLERANCE);
+        if !duplicate {
+            levels.push(Level { price, source: LevelSource::Range });
+        }
+    }
+    levels.sort_by(|a, b| (a.price - current_price).abs().total_cmp(&(b.price - current_price).abs()));
+    levels
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::NaiveDate;
+
+    fn bars(points: &[(f64, f64)]) -> Vec<DailyBar> {
+        let start = NaiveDate::from_ymd_opt(2024, 1, 1).unwrap();
+        points
+            .iter()
+            .enumerate()
+            .map(|(i, (price_usd, volume_usd))| DailyBar {
+                date: start + chrono::Duration::days(i as i64),
+                price_usd: *price_usd,
+                volume_usd: *volume_usd,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_volume_profile_buckets() {
+        let series = bars(&[(100.0, 10.0), (110.0, 5.0), (120.0, 7.0), (200.0, 1.0), (105.0, 3.0)]);
+        let profile = volume_profile(&series, 4);
+
+        assert_eq!(profile.len(), 4);
+        assert_eq!((profile[0].low, profile[0].high), (100.0, 125.0));
+        assert_eq!(profile[0].volume, 25.0);
+        assert_eq!(profile[1].volume, 0.0);
+        // The highest close lands in the last bucket
+        assert_eq!(profile[3].volume, 1.0);
+        assert_eq!(profile[3].high, 200.0);
+        let total: f64 = profile.iter().map(|bin| bin.volume).sum();
+        assert_eq!(total, 26.0);
+    }
+
+    #[test]
+    fn test_volume_profile_edge_cases() {
+        assert!(volume_profile(&[], 10).is_empty());
+        assert!(volume_profile(&bars(&[(1.0, 1.0)]), 0).is_empty());
+
+        let flat = volume_profile(&bars(&[(5.0, 2.0), (5.0, 3.0)]), 10);
+        assert_eq!(flat, vec![VolumeBin { low: 5.0, high: 5.0, volume: 5.0 }]);
+
+        let skipped = volume_profile(&bars(&[(f64::NAN, 1.0), (10.0, -1.0), (10.0, 4.0), (20.0, 1.0)]), 2);
+        assert_eq!(skipped[0].volume, 4.0);
+    }
+
+    #[test]
+    fn test_volume_nodes_are_peaks() {
+        let bin = |low: f64, volume: f64| VolumeBin { low, high: low + 10.0, volume };
+        let profile = vec![
+            bin(0.0, 1.0),
+            bin(10.0, 8.0),
+            bin(20.0, 9.0),
+            bin(30.0, 2.0),
+            bin(40.0, 6.0),
+            bin(50.0, 0.0),
+        ];
+        // 10-20 is the shoulder of the 20-30 peak, not a node of its own
+        assert_eq!(volume_nodes(&profile, 3), vec![25.0, 45.0]);
+        assert_eq!(volume_nodes(&profile, 1), vec![25.0]);
+        assert!(volume_nodes(&[], 3).is_empty());
+    }
+
+    #[test]
+    fn test_swing_levels() {
+        let series = bars(&[
+            (10.0, 1.0), (11.0, 1.0), (12.0, 1.0), (15.0, 1.0), (12.0, 1.0), (11.0, 1.0), (8.0, 1.0),
+            (9.0, 1.0), (10.0, 1.0), (11.0, 1.0), (11.0, 1.0),
+        ]);
+        assert_eq!(swing_levels(&series, 2), vec![15.0, 8.0]);
+        // Too short for the window
+        assert!(swing_levels(&series[..4], 2).is_empty());
+        // Ties are not swings
+        assert!(swing_levels(&bars(&[(1.0, 1.0); 7]), 2).is_empty());
+    }
+
+    #[test]
+    fn test_merge_levels_within_tolerance() {
+        let level = |price: f64, source| Level { price, source };
+        let merged = merge_levels(
+            vec![
+                level(101.0, LevelSource::VolumeNode),
+                level(100.0, LevelSource::Swing),
+                level(120.0, LevelSource::Swing),
+                level(80.0, LevelSource::Range),
+                level(80.5, LevelSource::VolumeNode),
+                level(f64::NAN, LevelSource::Swing),
+            ],
+            0.015,
+        );
+        assert_eq!(
+            merged,
+            vec![
+                level(80.25, LevelSource::VolumeNode),
+                level(100.5, LevelSource::SwingAndVolume),
+                level(120.0, LevelSource::Swing),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_key_levels_split_around_price() {
+        // Trades mostly around 90 and 110, with a swing low at 80 and a swing high at 130
+        let mut points = Vec::new();
+        for _ in 0..5 {
+            points.extend([(90.0, 50.0), (91.0, 40.0), (90.5, 45.0)]);
+        }
+        points.extend([(85.0, 5.0), (80.0, 5.0), (86.0, 5.0), (95.0, 5.0), (100.0, 5.0)]);
+        for _ in 0..5 {
+            points.extend([(110.0, 30.0), (111.0, 20.0), (110.5, 25.0)]);
+        }
+        points.extend([(120.0, 5.0), (130.0, 5.0), (121.0, 5.0), (112.0, 5.0), (105.0, 5.0), (104.0, 5.0), (103.0, 5.0)]);
+        let levels = key_levels(&bars(&points), 100.0);
+
+        let support_sources: Vec<(f64, LevelSource)> =
+            levels.supports.iter().map(|level| (level.price.round(), level.source)).collect();
+        assert_eq!(support_sources.first().map(|(price, _)| *price), Some(91.0));
+        assert!(support_sources.contains(&(80.0, LevelSource::Swing)));
+        assert!(levels.supports.iter().any(|level| level.source != LevelSource::Swing));
+        assert!(levels.supports.windows(2).all(|pair| pair[0].price > pair[1].price));
+
+        assert!(levels.resistances.iter().any(|level| level.price.round() == 130.0));
+        assert!(levels.resistances.windows(2).all(|pair| pair[0].price < pair[1].price));
+        assert!(levels.resistances.iter().all(|level| level.price > 100.0));
+    }
+
+    #[test]
+    fn test_fill_levels_prefers_derived() {
+        let derived = [Level { price: 95.0, source: LevelSource::VolumeNode }];
+        let filled = fill_levels(100.0, &derived, [92.0, 88.0, 85.0]);
+        assert_eq!(
+            filled,
+            vec![
+                Level { price: 95.0, source: LevelSource::VolumeNode },
+                Level { price: 92.0, source: LevelSource::Range },
+                Level { price: 88.0, source: LevelSource::Range },
+            ]
+        );
+
+        // A range estimate on top of a derived level is skipped
+        let derived = [Level { price: 92.5, source: LevelSource::Swing }];
+        let filled = fill_levels(100.0, &derived, [92.0, 88.0, 85.0]);
+        let prices: Vec<f64> = filled.iter().map(|level| level.price).collect();
+        assert_eq!(prices, vec![92.5, 88.0, 85.0]);
+
+        let none = fill_levels(100.0, &[], [108.0, 111.5, 115.0]);
+        assert!(none.iter().all(|level| level.source == LevelSource::Range));
+        assert_eq!(none.len(), 3);
+    }
+}
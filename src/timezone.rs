@@ -0,0 +1,266 @@
+use crate::investment_chat::{format_utc_offset, parse_utc_offset};
+use chrono::{DateTime, Duration, FixedOffset, MappedLocalTime, NaiveDate, NaiveDateTime, NaiveTime, Offset, TimeZone, Utc};
+use chrono_tz::Tz;
+use regex::Regex;
+use std::fmt;
+use std::sync::OnceLock;
+
+/// The timezone a user's times are shown and scheduled in
+///
+/// Stored as UTC everywhere, times are only converted when they are rendered or scheduled.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UserTimezone {
+    /// A fixed offset like UTC+05:30, the same all year
+    Offset(FixedOffset),
+    /// An IANA zone like Europe/Berlin, following its daylight saving rules
+    Named(Tz),
+}
+
+impl Default for UserTimezone {
+    fn default() -> Self {
+        Self::Offset(FixedOffset::east_opt(0).unwrap())
+    }
+}
+
+impl From<FixedOffset> for UserTimezone {
+    fn from(offset: FixedOffset) -> Self {
+        Self::Offset(offset)
+    }
+}
+
+impl UserTimezone {
+    /// Parse a UTC offset like "UTC+2" or an IANA name like "Europe/Berlin", in any case
+    pub fn parse(text: &str) -> Option<Self> {
+        if let Some(offset) = parse_utc_offset(text) {
+            return Some(Self::Offset(offset));
+        }
+        Tz::from_str_insensitive(text.trim()).ok().map(Self::Named)
+    }
+
+    /// The `timezone` column of `users`, UTC when it can't be read
+    pub fn from_stored(text: &str) -> Self {
+        Self::parse(text).unwrap_or_default()
+    }
+
+    /// The form stored in `users.timezone` and shown to the user: "UTC", "UTC+05:30" or "Europe/Berlin"
+    pub fn name(&self) -> String {
+        match self {
+            Self::Offset(offset) => format_utc_offset(*offset),
+            Self::Named(tz) => tz.name().to_string(),
+        }
+    }
+
+    /// Whether times are shown as they are stored
+    pub fn is_utc(&self) -> bool {
+        matches!(self, Self::Offset(offset) if offset.local_minus_utc() == 0)
+    }
+
+    /// A stored UTC timestamp as the user's local time
+    pub fn local(&self, at: NaiveDateTime) -> NaiveDateTime {
+        self.from_utc_datetime(&at).naive_local()
+    }
+
+    /// The instant a local time refers to
+    ///
+    /// A time skipped when clocks go forward lands as far past the change as it would have been
+    /// before it, 02:30 on a night clocks jump from 02:00 to 03:00 being 03:30. A time repeated
+    /// when clocks go back is its first occurrence.
+    pub fn resolve_local(&self, local: NaiveDateTime) -> DateTime<Utc> {
+        match self.from_local_datetime(&local) {
+            MappedLocalTime::Single(at) | MappedLocalTime::Ambiguous(at, _) => at.with_timezone(&Utc),
+            MappedLocalTime::None => {
+                // No clock change follows another within a day, so the offset a day earlier is the one before the gap
+                let before = self.offset_from_utc_datetime(&(local - Duration::days(1))).fix();
+                (local - Duration::seconds(i64::from(before.local_minus_utc()))).and_utc()
+            },
+        }
+    }
+}
+
+impl fmt::Display for UserTimezone {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.name())
+    }
+}
+
+/// A `UserTimezone` at one instant, remembering the zone so `DateTime::timezone` gives it back
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct UserOffset {
+    zone: UserTimezone,
+    fixed: FixedOffset,
+}
+
+impl Offset for UserOffset {
+    fn fix(&self) -> FixedOffset {
+        self.fixed
+    }
+}
+
+impl fmt::Display for UserOffset {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.fixed.fmt(f)
+    }
+}
+
+impl TimeZone for UserTimezone {
+    type Offset = UserOffset;
+
+    fn from_offset(offset: &UserOffset) -> Self {
+        offset.zone
+    }
+
+    fn offset_from_local_date(&self, local: &NaiveDate) -> MappedLocalTime<UserOffset> {
+        self.offset_from_local_datetime(&local.and_time(NaiveTime::MIN))
+    }
+
+    fn offset_from_local_datetime(&self, local: &NaiveDateTime) -> MappedLocalTime<UserOffset> {
+        let fixed = match self {
+            Self::Offset(offset) => MappedLocalTime::Single(*offset),
+            Self::Named(tz) => tz.offset_from_local_datetime(local).map(|offset| offset.fix()),
+        };
+        fixed.map(|fixed| UserOffset { zone: *self, fixed })
+    }
+
+    fn offset_from_utc_date(&self, utc: &NaiveDate) -> UserOffset {
+        self.offset_from_utc_datetime(&utc.and_time(NaiveTime::MIN))
+    }
+
+    fn offset_from_utc_datetime(&self, utc: &NaiveDateTime) -> UserOffset {
+        let fixed = match self {
+            Self::Offset(offset) => *offset,
+            Self::Named(tz) => tz.offset_from_utc_datetime(utc).fix(),
+        };
+        UserOffset { zone: *self, fixed }
+    }
+}
+
+/// The time of day of a daily schedule: "every day at 8am", "daily at 6:30 pm" or plain "07:00"
+pub fn parse_daily_time(text: &str) -> Option<NaiveTime> {
+    static DAILY: OnceLock<Regex> = OnceLock::new();
+    let daily = DAILY.get_or_init(|| {
+        Regex::new(r"(?i)^\s*(?:(?:every\s+day|daily|each\s+day)\s+at\s+)?(?P<hour>\d{1,2})(?::(?P<minute>\d{2}))?\s*(?P<meridiem>am|pm)?\s*$").unwrap()
+    });
+
+    let captures = daily.captures(text)?;
+    let mut hour: u32 = captures["hour"].parse().ok()?;
+    let minute: u32 = captures.name("minute").map_or(Some(0), |minute| minute.as_str().parse().ok())?;
+    match captures.name("meridiem").map(|meridiem| meridiem.as_str().to_lowercase()) {
+        Some(meridiem) => {
+            if !(1..=12).contains(&hour) {
+                return None;
+            }
+            hour = hour % 12 + if meridiem == "pm" { 12 } else { 0 };
+        },
+        // A bare hour like "8" is too vague to schedule on
+        None if captures.name("minute").is_none() => return None,
+        None => {},
+    }
+    NaiveTime::from_hms_opt(hour, minute, 0)
+}
+
+/// When a daily schedule at local `time` comes due on `date` in `zone`
+pub fn daily_occurrence(time: NaiveTime, date: NaiveDate, zone: UserTimezone) -> DateTime<Utc> {
+    zone.resolve_local(date.and_time(time))
+}
+
+/// The first time a daily schedule at local `time` comes due after `after`
+pub fn next_daily_occurrence(time: NaiveTime, zone: UserTimezone, after: DateTime<Utc>) -> DateTime<Utc> {
+    let today = after.with_timezone(&zone).date_naive();
+    let due = daily_occurrence(time, today, zone);
+    if due > after {
+        return due;
+    }
+    daily_occurrence(time, today + Duration::days(1), zone)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn berlin() -> UserTimezone {
+        UserTimezone::Named(chrono_tz::Europe::Berlin)
+    }
+
+    fn utc(text: &str) -> DateTime<Utc> {
+        NaiveDateTime::parse_from_str(text, "%Y-%m-%d %H:%M").unwrap().and_utc()
+    }
+
+    fn date(text: &str) -> NaiveDate {
+        NaiveDate::parse_from_str(text, "%Y-%m-%d").unwrap()
+    }
+
+    fn eight() -> NaiveTime {
+        NaiveTime::from_hms_opt(8, 0, 0).unwrap()
+    }
+
+    #[test]
+    fn test_parse_offsets_and_iana_names() {
+        let name = |text: &str| UserTimezone::parse(text).map(|zone| zone.name());
+        assert_eq!(name("UTC+2").as_deref(), Some("UTC+02:00"));
+        assert_eq!(name("utc").as_deref(), Some("UTC"));
+        assert_eq!(name("Europe/Berlin").as_deref(), Some("Europe/Berlin"));
+        assert_eq!(name("america/new_york").as_deref(), Some("America/New_York"));
+        assert_eq!(name("Mars/Olympus_Mons"), None);
+        assert_eq!(name("Berlin"), None);
+
+        // What's stored reads back the same, anything unreadable is UTC
+        assert_eq!(UserTimezone::from_stored("Asia/Kolkata").name(), "Asia/Kolkata");
+        assert_eq!(UserTimezone::from_stored("UTC-05:30").name(), "UTC-05:30");
+        assert!(UserTimezone::from_stored("garbage").is_utc());
+        assert!(!UserTimezone::from_stored("Europe/London").is_utc());
+    }
+
+    #[test]
+    fn test_local_times_follow_daylight_saving() {
+        let render = |zone: UserTimezone, at: &str| utc(at).with_timezone(&zone).format("%Y-%m-%d %H:%M %:z").to_string();
+        assert_eq!(render(berlin(), "2026-01-15 12:00"), "2026-01-15 13:00 +01:00");
+        assert_eq!(render(berlin(), "2026-07-15 12:00"), "2026-07-15 14:00 +02:00");
+        let new_york = UserTimezone::Named(chrono_tz::America::New_York);
+        assert_eq!(render(new_york, "2026-07-15 12:00"), "2026-07-15 08:00 -04:00");
+        assert_eq!(render(FixedOffset::east_opt(19_800).unwrap().into(), "2026-07-15 12:00"), "2026-07-15 17:30 +05:30");
+
+        // The zone survives the round trip through a DateTime
+        assert_eq!(utc("2026-07-15 12:00").with_timezone(&berlin()).timezone(), berlin());
+        assert_eq!(berlin().local(utc("2026-03-29 01:30").naive_utc()).to_string(), "2026-03-29 03:30:00");
+    }
+
+    #[test]
+    fn test_parse_daily_time() {
+        let time = |text: &str| parse_daily_time(text).map(|time| time.format("%H:%M").to_string());
+        assert_eq!(time("every day at 8am").as_deref(), Some("08:00"));
+        assert_eq!(time("Daily at 6:30 PM").as_deref(), Some("18:30"));
+        assert_eq!(time("each day at 12am").as_deref(), Some("00:00"));
+        assert_eq!(time("12pm").as_deref(), Some("12:00"));
+        assert_eq!(time("07:00").as_deref(), Some("07:00"));
+        assert_eq!(time("8"), None);
+        assert_eq!(time("25:00"), None);
+        assert_eq!(time("13pm"), None);
+        assert_eq!(time("every week at 8am"), None);
+    }
+
+    #[test]
+    fn test_daily_schedule_across_daylight_saving_changes() {
+        // 8am in Berlin is 07:00 UTC in winter and 06:00 UTC in summer
+        assert_eq!(daily_occurrence(eight(), date("2026-03-28"), berlin()), utc("2026-03-28 07:00"));
+        assert_eq!(daily_occurrence(eight(), date("2026-03-29"), berlin()), utc("2026-03-29 06:00"));
+        assert_eq!(daily_occurrence(eight(), date("2026-10-25"), berlin()), utc("2026-10-25 07:00"));
+
+        // Clocks skip 02:00-03:00 on 29 March: 02:30 happens at 03:30 local
+        let half_two = NaiveTime::from_hms_opt(2, 30, 0).unwrap();
+        assert_eq!(daily_occurrence(half_two, date("2026-03-29"), berlin()), utc("2026-03-29 01:30"));
+        // And 02:00-03:00 happens twice on 25 October, the first one counts
+        assert_eq!(daily_occurrence(half_two, date("2026-10-25"), berlin()), utc("2026-10-25 00:30"));
+
+        // The next one after 8am today is tomorrow's, an hour earlier in UTC across the change
+        assert_eq!(next_daily_occurrence(eight(), berlin(), utc("2026-03-28 07:00")), utc("2026-03-29 06:00"));
+        assert_eq!(next_daily_occurrence(eight(), berlin(), utc("2026-03-28 06:59")), utc("2026-03-28 07:00"));
+        // Late in the UTC day it's already tomorrow in Tokyo
+        let tokyo = UserTimezone::Named(chrono_tz::Asia::Tokyo);
+        assert_eq!(next_daily_occurrence(eight(), tokyo, utc("2026-03-28 23:30")), utc("2026-03-29 23:00"));
+        // A fixed offset never shifts, even on the day New York moves its clocks
+        let fixed: UserTimezone = FixedOffset::west_opt(5 * 3600).unwrap().into();
+        assert_eq!(next_daily_occurrence(eight(), fixed, utc("2026-03-08 14:00")), utc("2026-03-09 13:00"));
+        let new_york = UserTimezone::Named(chrono_tz::America::New_York);
+        assert_eq!(next_daily_occurrence(eight(), new_york, utc("2026-03-08 14:00")), utc("2026-03-09 12:00"));
+    }
+}
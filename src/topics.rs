@@ -0,0 +1,259 @@
+use crate::db::{TopicCount, TopicKind};
+use crate::timezone::UserTimezone;
+use chrono::{DateTime, Datelike, Duration, NaiveDateTime, NaiveTime};
+use regex::Regex;
+use std::sync::OnceLock;
+
+/// Topics listed by `/topics` and frequency questions
+pub const TOP_TOPICS: i64 = 10;
+
+/// Most days a "last N days" period reaches back
+const MAX_DAYS: u32 = 3650;
+
+/// Stretch of time topics are counted over, in the user's timezone
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Period {
+    Today,
+    /// Since Monday
+    Week,
+    Month,
+    Year,
+    LastDays(u32),
+    #[default]
+    All,
+}
+
+impl Period {
+    /// Parse a `/topics` argument: today, week, month, year, all, or a number of days like 30d
+    pub fn parse(text: &str) -> Option<Self> {
+        let text = text.trim().to_lowercase();
+        let period = match text.as_str() {
+            "today" => Period::Today,
+            "week" => Period::Week,
+            "month" => Period::Month,
+            "year" => Period::Year,
+            "all" => Period::All,
+            _ => {
+                let days: u32 = text.strip_suffix('d').unwrap_or(&text).parse().ok()?;
+                Period::LastDays(days)
+            },
+        };
+        match period {
+            Period::LastDays(days) if !(1..=MAX_DAYS).contains(&days) => None,
+            period => Some(period),
+        }
+    }
+
+    /// When the period started as a UTC timestamp, None for all time
+    ///
+    /// Local midnight takes the offset it had then, which differs from today's across a daylight saving change.
+    pub fn start(&self, now: DateTime<UserTimezone>) -> Option<NaiveDateTime> {
+        let today = now.date_naive();
+        let start = match self {
+            Period::Today => today,
+            Period::Week => today - Duration::days(i64::from(today.weekday().num_days_from_monday())),
+            Period::Month => today.with_day(1)?,
+            Period::Year => today.with_ordinal(1)?,
+            Period::LastDays(days) => return Some(now.naive_utc() - Duration::days(i64::from(*days))),
+            Period::All => return None,
+        };
+        Some(now.timezone().resolve_local(start.and_time(NaiveTime::MIN)).naive_utc())
+    }
+
+    /// "this month", "in the last 7 days"...
+    pub fn describe(&self) -> String {
+        match self {
+            Period::Today => "today".to_string(),
+            Period::Week => "this week".to_string(),
+            Period::Month => "this month".to_string(),
+            Period::Year => "this year".to_string(),
+            Period::LastDays(1) => "in the last day".to_string(),
+            Period::LastDays(days) => format!("in the last {} days", days),
+            Period::All => "so far".to_string(),
+        }
+    }
+}
+
+/// Which topics to count, and over what stretch of time
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct TopicQuery {
+    /// None counts coins and keywords alike
+    pub kind: Option<TopicKind>,
+    pub period: Period,
+}
+
+/// The topics of a turn from what the project and keyword extractors found, each once
+///
+/// Keywords that name a coin found by the project extractor, like "solana", count as the coin.
+pub fn tag(projects: &[String], keywords: &[String]) -> Vec<(String, TopicKind)> {
+    let mut topics: Vec<(String, TopicKind)> = Vec::with_capacity(projects.len() + keywords.len());
+    let found = projects
+        .iter()
+        .map(|project| (project, TopicKind::Coin))
+        .chain(keywords.iter().map(|keyword| (keyword, TopicKind::Keyword)));
+    for (name, kind) in found {
+        let topic = (name.trim().to_lowercase(), kind);
+        if !topic.0.is_empty() && !topics.iter().any(|(found, _)| *found == topic.0) {
+            topics.push(topic);
+        }
+    }
+    topics
+}
+
+fn topic_query_regex() -> &'static Regex {
+    static REGEX: OnceLock<Regex> = OnceLock::new();
+    REGEX.get_or_init(|| {
+        Regex::new(
+            r"(?i)\b(?:what|which)\s+(?P<noun>coins?|tokens?|projects?|topics?|things|keywords?)\s+(?:have\s+|did\s+|do\s+)?(?:we|i)\s+(?:talked|talk|discussed|discuss|asked|ask|chatted|chat)\s+(?:about\s+)?(?:the\s+)?most\b|\bmost\s+(?:talked[\s-]about|discussed|mentioned)\s+(?P<noun2>coins?|tokens?|projects?|topics?|keywords?)\b",
+        )
+        .unwrap()
+    })
+}
+
+// "this month", "in the last 30 days", "all time"
+fn period_regex() -> &'static Regex {
+    static REGEX: OnceLock<Regex> = OnceLock::new();
+    REGEX.get_or_init(|| {
+        Regex::new(r"(?i)\b(?:(?P<today>today)|this\s+(?P<unit>week|month|year)|(?:the\s+)?(?:last|past)\s+(?P<days>\d{1,4})\s+days?|(?P<all>all\s+time|ever))\b")
+            .unwrap()
+    })
+}
+
+/// Parse "what coins have we talked about most this month?" and similar questions
+pub fn parse_topic_query(message: &str) -> Option<TopicQuery> {
+    let captures = topic_query_regex().captures(message)?;
+    let noun = captures.name("noun").or(captures.name("noun2"))?.as_str().to_lowercase();
+    let kind = if noun.starts_with("coin") || noun.starts_with("token") || noun.starts_with("project") {
+        Some(TopicKind::Coin)
+    } else if noun.starts_with("keyword") {
+        Some(TopicKind::Keyword)
+    } else {
+        None
+    };
+
+    Some(TopicQuery { kind, period: parse_period(message)? })
+}
+
+/// The period a question names, like "this month" or "in the last 30 days", all time when it names none
+///
+/// None when the number of days is out of range.
+pub fn parse_period(message: &str) -> Option<Period> {
+    let period = match period_regex().captures(message) {
+        Some(period) if period.name("today").is_some() => Period::Today,
+        Some(period) if period.name("all").is_some() => Period::All,
+        Some(period) => match (period.name("unit"), period.name("days")) {
+            (Some(unit), _) => Period::parse(unit.as_str())?,
+            (None, Some(days)) => Period::parse(days.as_str())?,
+            (None, None) => Period::All,
+        },
+        None => Period::All,
+    };
+    Some(period)
+}
+
+/// The most mentioned topics, counted by the messages mentioning them
+pub fn render_topics(query: &TopicQuery, counts: &[TopicCount]) -> String {
+    let noun = match query.kind {
+        Some(TopicKind::Coin) => "coins",
+        Some(TopicKind::Keyword) => "keywords",
+        None => "topics",
+    };
+    if counts.is_empty() {
+        return format!("We haven't talked about any {} {}.", noun, query.period.describe());
+    }
+
+    let mut lines = vec![format!("The {} we talked about most {}:", noun, query.period.describe())];
+    for (rank, count) in counts.iter().enumerate() {
+        let messages = if count.messages == 1 { "1 message".to_string() } else { format!("{} messages", count.messages) };
+        match query.kind {
+            Some(_) => lines.push(format!("{}. {}: {}", rank + 1, count.topic, messages)),
+            None => lines.push(format!("{}. {} ({}): {}", rank + 1, count.topic, count.kind.as_str(), messages)),
+        }
+    }
+    lines.join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{FixedOffset, TimeZone};
+
+    #[test]
+    fn test_tag_dedupes_and_keeps_order() {
+        let projects = vec!["Solana".to_string(), "aerodrome".to_string(), "solana".to_string()];
+        let keywords = vec!["staking".to_string(), "solana".to_string(), "staking".to_string()];
+        assert_eq!(tag(&projects, &keywords), vec![
+            ("solana".to_string(), TopicKind::Coin),
+            ("aerodrome".to_string(), TopicKind::Coin),
+            ("staking".to_string(), TopicKind::Keyword),
+        ]);
+        assert!(tag(&[], &[]).is_empty());
+    }
+
+    #[test]
+    fn test_parse_period() {
+        assert_eq!(Period::parse("Month"), Some(Period::Month));
+        assert_eq!(Period::parse("30d"), Some(Period::LastDays(30)));
+        assert_eq!(Period::parse("7"), Some(Period::LastDays(7)));
+        assert_eq!(Period::parse("all"), Some(Period::All));
+        for invalid in ["0d", "fortnight", "-3", "99999d"] {
+            assert_eq!(Period::parse(invalid), None, "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn test_period_start_is_local_midnight() {
+        // Wednesday 2025-10-15 01:30 at UTC+2 is still Tuesday in UTC
+        let now = UserTimezone::from(FixedOffset::east_opt(2 * 3600).unwrap()).with_ymd_and_hms(2025, 10, 15, 1, 30, 0).unwrap();
+        let at = |text: &str| NaiveDateTime::parse_from_str(text, "%Y-%m-%d %H:%M").unwrap();
+        assert_eq!(Period::Today.start(now), Some(at("2025-10-14 22:00")));
+        assert_eq!(Period::Week.start(now), Some(at("2025-10-12 22:00")));
+        assert_eq!(Period::Month.start(now), Some(at("2025-09-30 22:00")));
+        assert_eq!(Period::Year.start(now), Some(at("2024-12-31 22:00")));
+        assert_eq!(Period::LastDays(2).start(now), Some(at("2025-10-12 23:30")));
+        assert_eq!(Period::All.start(now), None);
+    }
+
+    #[test]
+    fn test_period_start_across_daylight_saving() {
+        // Berlin left summer time on Sunday 26 October 2025, the week started in winter time and the month in summer time
+        let now = UserTimezone::Named(chrono_tz::Europe::Berlin).with_ymd_and_hms(2025, 10, 28, 9, 0, 0).unwrap();
+        let at = |text: &str| NaiveDateTime::parse_from_str(text, "%Y-%m-%d %H:%M").unwrap();
+        assert_eq!(Period::Today.start(now), Some(at("2025-10-27 23:00")));
+        assert_eq!(Period::Week.start(now), Some(at("2025-10-26 23:00")));
+        assert_eq!(Period::Month.start(now), Some(at("2025-09-30 22:00")));
+    }
+
+    #[test]
+    fn test_parse_topic_query() {
+        let query = parse_topic_query("what coins have we talked about most this month?").unwrap();
+        assert_eq!(query, TopicQuery { kind: Some(TopicKind::Coin), period: Period::Month });
+
+        let query = parse_topic_query("Which topics did I ask about the most in the last 14 days").unwrap();
+        assert_eq!(query, TopicQuery { kind: None, period: Period::LastDays(14) });
+
+        let query = parse_topic_query("show me our most discussed tokens of all time").unwrap();
+        assert_eq!(query, TopicQuery { kind: Some(TopicKind::Coin), period: Period::All });
+
+        assert_eq!(parse_topic_query("what keywords do we discuss most today").unwrap().period, Period::Today);
+        assert_eq!(parse_topic_query("what coins should I buy this month?"), None);
+        assert_eq!(parse_topic_query("which coin moved most this week"), None);
+    }
+
+    #[test]
+    fn test_render_topics() {
+        let counts = vec![
+            TopicCount { topic: "bitcoin".to_string(), kind: TopicKind::Coin, messages: 5 },
+            TopicCount { topic: "staking".to_string(), kind: TopicKind::Keyword, messages: 1 },
+        ];
+        let query = TopicQuery { kind: None, period: Period::Month };
+        assert_eq!(
+            render_topics(&query, &counts),
+            "The topics we talked about most this month:\n1. bitcoin (coin): 5 messages\n2. staking (keyword): 1 message"
+        );
+
+        let query = TopicQuery { kind: Some(TopicKind::Coin), period: Period::LastDays(7) };
+        assert_eq!(render_topics(&query, &counts[..1]), "The coins we talked about most in the last 7 days:\n1. bitcoin: 5 messages");
+        assert_eq!(render_topics(&query, &[]), "We haven't talked about any coins in the last 7 days.");
+    }
+}
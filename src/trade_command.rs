@@ -0,0 +1,628 @@
+use crate::db::{OrderType, Trade, TradeStatus};
+use crate::price_format::format_price;
+use crate::timezone::UserTimezone;
+use crate::trading::{QuoteResponse, Token, from_units, native_token};
+use chrono::{DateTime, Datelike, Duration, NaiveDate, NaiveDateTime, Utc, Weekday};
+use ethers::types::U256;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::OnceLock;
+
+/// Slippage a swap is prepared with when the message doesn't give one, in percent
+pub const DEFAULT_SLIPPAGE_PCT: f32 = 1.0;
+
+/// Minutes a staged trade can be confirmed for before its quote counts as stale
+pub const PENDING_TRADE_MINUTES: i64 = 10;
+
+/// Tokens offered when a name matches several
+const MAX_CANDIDATES: usize = 5;
+
+/// Reply to trade commands when no wallet is configured
+pub const TRADING_DISABLED: &str = "Trading from the chat is turned off: there is no wallet to trade from. \
+Set PRIVATE_KEY and RPC_URL in the .env file to turn it on.";
+
+/// A trade asked for in the chat, with the tokens as the user named them
+#[derive(Debug, Clone, PartialEq)]
+pub enum TradeCommand {
+    /// "swap 0.1 eth to usdc with 1% slippage"
+    Swap { amount: f64, from: String, to: String, slippage: Option<f32> },
+    /// "place a limit buy for 100 usdc of weth at 2500 valid until friday", `amount` being in `amount_token`
+    Limit { side: OrderType, amount: f64, amount_token: String, asset: Option<String>, price: f64, validity: Option<Validity> },
+}
+
+/// How long a limit order asked for in the chat stays good, None in `TradeCommand::Limit` being until cancelled
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Validity {
+    /// "valid for 7 days"
+    For(Duration),
+    /// "valid until friday", to the end of the next such day in the user's timezone, today included
+    UntilWeekday(Weekday),
+    /// "good till 2025-10-24", to the end of that day in the user's timezone
+    UntilDate(NaiveDate),
+}
+
+/// A trade ready to execute once confirmed, kept in `pending_trades` between messages
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum StagedTrade {
+    /// A 1inch swap of `amount` whole tokens of `from_token`
+    Swap {
+        from_token: String,
+        from_symbol: String,
+        to_token: String,
+        to_symbol: String,
+        amount: f64,
+        decimals: u32,
+        slippage: f32,
+    },
+    /// A WETH limit order of `amount` WETH at `price` USDC, good until `expires_at` when there is one
+    LimitOrder {
+        order_type: OrderType,
+        amount: f64,
+        price: f64,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        expires_at: Option<DateTime<Utc>>,
+    },
+}
+
+impl StagedTrade {
+    /// The trade in a few words, e.g. "swap of 0.1 ETH to USDC"
+    pub fn describe(&self) -> String {
+        match self {
+            StagedTrade::Swap { from_symbol, to_symbol, amount, .. } => {
+                format!("swap of {} {} to {}", format_amount(*amount), from_symbol, to_symbol)
+            },
+            StagedTrade::LimitOrder { order_type, amount, price, .. } => {
+                format!("limit {} of {} WETH at {}", order_type.as_str(), format_amount(*amount), format_price(*price))
+            },
+        }
+    }
+}
+
+/// What a reply to a staged trade asks for
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Confirmation {
+    Confirm,
+    Cancel,
+}
+
+/// How a token name the user gave matched 1inch's token list
+#[derive(Debug, Clone)]
+pub enum TokenLookup {
+    Found(Token),
+    /// Several tokens could be meant, the user has to pick one
+    Ambiguous(Vec<Token>),
+    Unknown,
+}
+
+const AMOUNT: &str = r"(\d+(?:\.\d+)?)";
+const TOKEN: &str = r"([a-z0-9]+(?:[.-][a-z0-9]+)*)";
+
+/// Parse "swap 0.1 weth to usdc with 1% slippage" and "place a limit buy for 100 USDC of WETH at 2500",
+/// the limit order optionally followed by "valid for 7 days" or "valid until friday"
+pub fn parse_trade_command(message: &str) -> Option<TradeCommand> {
+    static SWAP: OnceLock<Regex> = OnceLock::new();
+    static LIMIT: OnceLock<Regex> = OnceLock::new();
+    let swap = SWAP.get_or_init(|| {
+        Regex::new(&format!(
+            r"^(?:please\s+)?(?:swap|convert|trade)\s+{AMOUNT}\s+{TOKEN}\s+(?:to|for|into)\s+{TOKEN}(?:,?\s+(?:with|at)\s+(?:a\s+)?(?:max(?:imum)?\s+)?(?:slippage\s+(?:of\s+)?{AMOUNT}\s*%|{AMOUNT}\s*%\s+(?:max\s+)?slippage))?\s*[.!]?$"
+        ))
+        .unwrap()
+    });
+    let limit = LIMIT.get_or_init(|| {
+        Regex::new(&format!(
+            r"^(?:please\s+)?(?:place|create|set|put in)\s+(?:a\s+)?limit\s+(buy|sell)(?:\s+order)?\s+(?:for\s+|of\s+)?{AMOUNT}\s+{TOKEN}(?:\s+(?:worth\s+)?of\s+{TOKEN})?\s+at\s+\$?(\d+(?:,\d{{3}})*(?:\.\d+)?)(?:,?\s+(?:valid|good)\s+(?:for\s+(\d+)\s+(hour|day|week)s?|(?:until|till|til)\s+([a-z0-9-]+)))?\s*[.!]?$"
+        ))
+        .unwrap()
+    });
+
+    let message = message.trim().to_lowercase();
+    if let Some(caps) = swap.captures(&message) {
+        let slippage = caps.get(4).or(caps.get(5)).and_then(|pct| pct.as_str().parse().ok());
+        return Some(TradeCommand::Swap {
+            amount: caps[1].parse().ok()?,
+            from: caps[2].to_string(),
+            to: caps[3].to_string(),
+            slippage,
+        });
+    }
+
+    let caps = limit.captures(&message)?;
+    let validity = match (caps.get(6), caps.get(7), caps.get(8)) {
+        (Some(count), Some(unit), _) => {
+            let count: i64 = count.as_str().parse().ok()?;
+            Some(Validity::For(match unit.as_str() {
+                "hour" => Duration::hours(count),
+                "day" => Duration::days(count),
+                _ => Duration::weeks(count),
+            }))
+        },
+        // A day that can't be read makes the whole message something else than an order
+        (_, _, Some(day)) => Some(match NaiveDate::parse_from_str(day.as_str(), "%Y-%m-%d") {
+            Ok(date) => Validity::UntilDate(date),
+            Err(_) => Validity::UntilWeekday(day.as_str().parse().ok()?),
+        }),
+        _ => None,
+    };
+    Some(TradeCommand::Limit {
+        side: caps[1].parse().ok()?,
+        amount: caps[2].parse().ok()?,
+        amount_token: caps[3].to_string(),
+        asset: caps.get(4).map(|asset| asset.as_str().to_string()),
+        price: caps[5].replace(',', "").parse().ok()?,
+        validity,
+    })
+}
+
+/// When an order asked to be good for `validity` at `now` expires, a day ending at 23:59:59 in `zone`
+pub fn order_expiry(validity: Validity, now: DateTime<Utc>, zone: UserTimezone) -> Result<DateTime<Utc>, String> {
+    let today = now.with_timezone(&zone).date_naive();
+    let end_of = |date: NaiveDate| zone.resolve_local(date.and_hms_opt(23, 59, 59).unwrap());
+    match validity {
+        Validity::For(duration) if duration <= Duration::zero() => {
+            Err("A limit order has to be good for at least an hour.".to_string())
+        },
+        Validity::For(duration) => Ok(now + duration),
+        Validity::UntilWeekday(day) => {
+            let ahead = (7 + day.num_days_from_monday() - today.weekday().num_days_from_monday()) % 7;
+            Ok(end_of(today + Duration::days(i64::from(ahead))))
+        },
+        Validity::UntilDate(date) if date < today => {
+            Err(format!("{} is already past, a limit order can't be good until then.", date.format("%a %-d %b %Y")))
+        },
+        Validity::UntilDate(date) => Ok(end_of(date)),
+    }
+}
+
+/// Parse a reply to a staged trade: CONFIRM executes it, cancel drops it
+pub fn parse_confirmation(message: &str) -> Option<Confirmation> {
+    let reply = message.trim().trim_end_matches(['.', '!']).to_lowercase();
+    match reply.as_str() {
+        "confirm" => Some(Confirmation::Confirm),
+        "cancel" | "cancel it" | "cancel that" | "cancel the trade" | "cancel the swap" | "cancel the order" => {
+            Some(Confirmation::Cancel)
+        },
+        _ => None,
+    }
+}
+
+/// Whether the message asks how the last swap went, "did my last swap go through?"
+pub fn is_swap_status_query(message: &str) -> bool {
+    static REGEX: OnceLock<Regex> = OnceLock::new();
+    REGEX
+        .get_or_init(|| {
+            Regex::new(
+                r"(?i)\b(?:did|has|have|is)\s+(?:my|the)\s+(?:last|latest|previous)\s+(?:swap|trade|transaction)\s+(?:go\s+through|gone\s+through|confirm(?:ed)?|land(?:ed)?|succeed(?:ed)?|work(?:ed)?|fail(?:ed)?|(?:been\s+)?mined)\b|\bstatus\s+of\s+(?:my|the)\s+(?:last|latest|previous)\s+(?:swap|trade|transaction)\b",
+            )
+            .unwrap()
+        })
+        .is_match(message)
+}
+
+/// How the swap in a trade history row went, as far as the row knows
+pub fn render_swap_status(trade: &Trade) -> String {
+    let swap = format!(
+        "Your last swap, a {} of {} {} in transaction {},",
+        trade.side.as_str(),
+        format_amount(trade.amount),
+        trade.coin_id,
+        trade.tx_hash.as_deref().unwrap_or("-")
+    );
+    let block = trade.block_number.map(|block| format!(" in block {}", block)).unwrap_or_default();
+    let gas = trade.gas_cost.map(|gas| format!(", paying {} ETH for gas", format_amount(gas))).unwrap_or_default();
+    match trade.status {
+        TradeStatus::Confirmed | TradeStatus::Filled => format!("{} went through{}{}.", swap, block, gas),
+        TradeStatus::Failed if trade.block_number.is_some() => {
+            format!("{} was mined{} but reverted, so nothing was swapped{}.", swap, block, gas)
+        },
+        TradeStatus::Failed => format!("{} failed before it was mined, nothing was swapped.", swap),
+        TradeStatus::Pending => format!("{} hasn't been confirmed yet. Blocks come every few seconds, so ask again in a minute.", swap),
+        TradeStatus::Stuck => format!(
+            "{} still isn't mined and was given up on: it may have been dropped, or its gas price is too low for the network. \
+            Look it up on a block explorer before trying the swap again.",
+            swap
+        ),
+    }
+}
+
+/// Whether a trade staged at `staged_at` is too old to confirm at `now`
+pub fn is_expired(staged_at: NaiveDateTime, now: DateTime<Utc>) -> bool {
+    now.naive_utc() - staged_at > Duration::minutes(PENDING_TRADE_MINUTES)
+}
+
+/// Find the token `name` means among 1inch's tokens by lowercase address, never guessing
+///
+/// An address or a symbol only one token has is found. A symbol several tokens share, or a name
+/// that is no token's symbol but part of some tokens' names, is ambiguous.
+pub fn lookup_token(name: &str, tokens: &HashMap<String, Token>) -> TokenLookup {
+    let name = name.to_lowercase();
+    if name.starts_with("0x") {
+        return match tokens.get(&name) {
+            Some(token) => TokenLookup::Found(token.clone()),
+            None => TokenLookup::Unknown,
+        };
+    }
+
+    let mut symbol_matches: Vec<&Token> = tokens.values().filter(|token| token.symbol.to_lowercase() == name).collect();
+    symbol_matches.sort_by(|a, b| a.address.cmp(&b.address));
+    match symbol_matches.as_slice() {
+        [token] => return TokenLookup::Found((*token).clone()),
+        [] => {},
+        several => return TokenLookup::Ambiguous(several.iter().map(|token| (*token).clone()).collect()),
+    }
+    if name == "eth" {
+        return TokenLookup::Found(native_token());
+    }
+
+    let mut candidates: Vec<&Token> = tokens
+        .values()
+        .filter(|token| token.name.to_lowercase().split_whitespace().any(|word| word == name))
+        .collect();
+    candidates.sort_by(|a, b| a.symbol.cmp(&b.symbol).then(a.address.cmp(&b.address)));
+    if candidates.is_empty() {
+        return TokenLookup::Unknown;
+    }
+    TokenLookup::Ambiguous(candidates.into_iter().take(MAX_CANDIDATES).cloned().collect())
+}
+
+/// The chain's native ETH as 1inch addresses it
+/// The question to ask when `name` didn't match exactly one token
+pub fn render_lookup(name: &str, lookup: &TokenLookup) -> String {
+    let candidates = match lookup {
+        TokenLookup::Found(token) => return format!("\"{}\" is {} ({}).", name, token.symbol, token.address),
+        TokenLookup::Unknown => return format!("I couldn't find a token called \"{}\" that 1inch can trade on this chain.", name),
+        TokenLookup::Ambiguous(candidates) => candidates,
+    };
+
+    let listed: Vec<String> = candidates
+        .iter()
+        .map(|token| format!("- {} ({}) at {}", token.symbol, token.name, token.address))
+        .collect();
+    if candidates.iter().all(|token| token.symbol.eq_ignore_ascii_case(name)) {
+        format!(
+            "Several tokens are called {}:\n{}\nWhich one do you mean? Ask again with its address in place of \"{}\".",
+            name.to_uppercase(),
+            listed.join("\n"),
+            name
+        )
+    } else {
+        format!(
+            "No token has the symbol \"{}\". Did you mean one of these?\n{}\nAsk again with the symbol you mean.",
+            name,
+            listed.join("\n")
+        )
+    }
+}
+
+/// Whether a token named in a command is the WETH that limit orders trade
+pub fn is_weth(token: &str) -> bool {
+    matches!(token, "weth" | "eth")
+}
+
+/// The limit order a command asks for, or why it can't be placed
+///
+/// Limit orders trade WETH against USDC: "100 usdc of weth" is worth 100 USDC of WETH at the order's
+/// price, "0.5 weth" is the WETH amount itself.
+pub fn limit_order(
+    side: OrderType,
+    amount: f64,
+    amount_token: &str,
+    asset: Option<&str>,
+    price: f64,
+    expires_at: Option<DateTime<Utc>>,
+) -> Result<StagedTrade, String> {
+    let is_usdc = |token: &str| matches!(token, "usdc" | "usd");
+    if amount <= 0.0 || price <= 0.0 {
+        return Err("The amount and the price of a limit order have to be above zero.".to_string());
+    }
+
+    let amount = match asset {
+        Some(asset) if is_weth(asset) && is_usdc(amount_token) => amount / price,
+        None if is_weth(amount_token) => amount,
+        _ => {
+            return Err("Limit orders can only trade WETH against USDC, e.g. \"place a limit buy for 100 USDC of WETH at 2500\"."
+                .to_string());
+        },
+    };
+    Ok(StagedTrade::LimitOrder { order_type: side, amount, price, expires_at })
+}
+
+/// The staged swap with what 1inch quoted for it, asking for a CONFIRM
+pub fn render_swap_quote(trade: &StagedTrade, quote: &QuoteResponse) -> String {
+    let StagedTrade::Swap { from_symbol, to_symbol, amount, slippage, .. } = trade else {
+        return render_staged(trade, UserTimezone::default());
+    };
+    let received = U256::from_dec_str(&quote.to_amount)
+        .ok()
+        .and_then(|amount| from_units(amount, quote.to_token.decimals).ok())
+        .map(format_amount)
+        .unwrap_or_else(|| "an unknown amount of".to_string());
+    format!(
+        "Swap {} {} for about {} {}, accepting up to {}% slippage (estimated gas: {} units).\n{}",
+        format_amount(*amount),
+        from_symbol,
+        received,
+        to_symbol,
+        slippage,
+        quote.estimated_gas,
+        confirmation_prompt()
+    )
+}
+
+/// The staged trade with its expiry in `zone`, asking for a CONFIRM
+pub fn render_staged(trade: &StagedTrade, zone: UserTimezone) -> String {
+    match trade {
+        StagedTrade::LimitOrder { order_type, amount, price, expires_at } => format!(
+            "Place a limit {} of {} WETH at {} (about {} USDC){}.\n{}",
+            order_type.as_str(),
+            format_amount(*amount),
+            format_price(*price),
+            format_amount(amount * price),
+            expires_at
+                .map(|at| format!(", good until {} {}", zone.local(at.naive_utc()).format("%a %-d %b %Y, %H:%M"), zone))
+                .unwrap_or_default(),
+            confirmation_prompt()
+        ),
+        StagedTrade::Swap { .. } => format!("Execute the {}?\n{}", trade.describe(), confirmation_prompt()),
+    }
+}
+
+fn confirmation_prompt() -> String {
+    format!(
+        "Reply CONFIRM to execute it within {} minutes, or cancel to drop it.",
+        PENDING_TRADE_MINUTES
+    )
+}
+
+/// A token amount with up to 6 decimals and no trailing zeros, e.g. "0.1" or "250.123457"
+pub fn format_amount(amount: f64) -> String {
+    let formatted = format!("{:.6}", amount);
+    formatted.trim_end_matches('0').trim_end_matches('.').to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::trading::NATIVE_TOKEN;
+    use chrono::TimeZone;
+
+    fn token(symbol: &str, name: &str, address: &str) -> Token {
+        Token { address: address.to_string(), decimals: 18, symbol: symbol.to_string(), name: name.to_string(), logo_uri: None }
+    }
+
+    fn tokens() -> HashMap<String, Token> {
+        [
+            token("WETH", "Wrapped Ether", "0x4200000000000000000000000000000000000006"),
+            token("USDC", "USD Coin", "0xf175520c52418dfe19c8098071a252da48cd1c19"),
+            token("USDC", "Bridged USD Coin", "0xd9aaec86b65d86f6a7b5b1b0c42ffa531710b6ca"),
+            token("AERO", "Aerodrome", "0x940181a94a35a4569e4529a3cdfb74e38fd98631"),
+        ]
+        .into_iter()
+        .map(|token| (token.address.clone(), token))
+        .collect()
+    }
+
+    #[test]
+    fn test_parse_swaps() {
+        assert_eq!(
+            parse_trade_command("swap 0.1 weth to usdc with 1% slippage"),
+            Some(TradeCommand::Swap { amount: 0.1, from: "weth".to_string(), to: "usdc".to_string(), slippage: Some(1.0) })
+        );
+        assert_eq!(
+            parse_trade_command("Swap 0.1 ETH to USDC"),
+            Some(TradeCommand::Swap { amount: 0.1, from: "eth".to_string(), to: "usdc".to_string(), slippage: None })
+        );
+        assert_eq!(
+            parse_trade_command("convert 250 usdc into aero, with slippage of 0.5%."),
+            Some(TradeCommand::Swap { amount: 250.0, from: "usdc".to_string(), to: "aero".to_string(), slippage: Some(0.5) })
+        );
+        assert!(parse_trade_command("should I swap eth to usdc?").is_none());
+        assert!(parse_trade_command("how do I swap 0.1 eth to usdc").is_none());
+    }
+
+    #[test]
+    fn test_parse_limit_orders() {
+        assert_eq!(
+            parse_trade_command("place a limit buy for 100 USDC of WETH at 2500"),
+            Some(TradeCommand::Limit {
+                side: OrderType::Buy,
+                amount: 100.0,
+                amount_token: "usdc".to_string(),
+                asset: Some("weth".to_string()),
+                price: 2500.0,
+                validity: None,
+            })
+        );
+        assert_eq!(
+            parse_trade_command("create a limit sell order of 0.5 weth at $3,100"),
+            Some(TradeCommand::Limit { side: OrderType::Sell, amount: 0.5, amount_token: "weth".to_string(), asset: None, price: 3100.0, validity: None })
+        );
+        assert!(parse_trade_command("what's a limit buy at 2500?").is_none());
+    }
+
+    #[test]
+    fn test_parse_limit_order_validity() {
+        let validity = |message: &str| match parse_trade_command(message) {
+            Some(TradeCommand::Limit { validity, .. }) => validity,
+            other => panic!("{:?}", other),
+        };
+        assert_eq!(validity("place a limit buy for 100 usdc of weth at 2500 valid until Friday"), Some(Validity::UntilWeekday(Weekday::Fri)));
+        assert_eq!(validity("place a limit sell of 0.5 weth at 3000, valid for 7 days."), Some(Validity::For(Duration::days(7))));
+        assert_eq!(validity("place a limit sell of 0.5 weth at 3000 good for 1 week"), Some(Validity::For(Duration::weeks(1))));
+        assert_eq!(validity("place a limit sell of 0.5 weth at 3000 good till 2025-10-24"), Some(Validity::UntilDate(NaiveDate::from_ymd_opt(2025, 10, 24).unwrap())));
+        assert_eq!(validity("place a limit sell of 0.5 weth at 3000 good till tue"), Some(Validity::UntilWeekday(Weekday::Tue)));
+        assert!(parse_trade_command("place a limit buy for 100 usdc of weth at 2500 valid until the merge").is_none());
+    }
+
+    #[test]
+    fn test_order_expiry_ends_the_day_in_the_users_timezone() {
+        let berlin = UserTimezone::Named(chrono_tz::Europe::Berlin);
+        // Thursday 16 October 2025, 10:00 UTC
+        let now = Utc.with_ymd_and_hms(2025, 10, 16, 10, 0, 0).unwrap();
+        let friday = Utc.with_ymd_and_hms(2025, 10, 17, 21, 59, 59).unwrap();
+        assert_eq!(order_expiry(Validity::UntilWeekday(Weekday::Fri), now, berlin), Ok(friday));
+        // Today counts, and last week's day is next week's
+        assert_eq!(order_expiry(Validity::UntilWeekday(Weekday::Thu), now, berlin), Ok(Utc.with_ymd_and_hms(2025, 10, 16, 21, 59, 59).unwrap()));
+        assert_eq!(order_expiry(Validity::UntilWeekday(Weekday::Wed), now, UserTimezone::default()), Ok(Utc.with_ymd_and_hms(2025, 10, 22, 23, 59, 59).unwrap()));
+        assert_eq!(order_expiry(Validity::For(Duration::days(7)), now, berlin), Ok(now + Duration::days(7)));
+        assert_eq!(order_expiry(Validity::UntilDate(NaiveDate::from_ymd_opt(2025, 10, 17).unwrap()), now, berlin), Ok(friday));
+        assert!(order_expiry(Validity::UntilDate(NaiveDate::from_ymd_opt(2025, 10, 15).unwrap()), now, berlin).is_err());
+        assert!(order_expiry(Validity::For(Duration::days(0)), now, berlin).is_err());
+    }
+
+    #[test]
+    fn test_limit_orders_trade_weth_against_usdc() {
+        assert_eq!(
+            limit_order(OrderType::Buy, 100.0, "usdc", Some("weth"), 2500.0, None),
+            Ok(StagedTrade::LimitOrder { order_type: OrderType::Buy, amount: 0.04, price: 2500.0, expires_at: None })
+        );
+        assert_eq!(
+            limit_order(OrderType::Sell, 0.5, "weth", None, 3000.0, None),
+            Ok(StagedTrade::LimitOrder { order_type: OrderType::Sell, amount: 0.5, price: 3000.0, expires_at: None })
+        );
+        assert!(limit_order(OrderType::Buy, 100.0, "usdc", Some("aero"), 1.2, None).is_err());
+        assert!(limit_order(OrderType::Sell, 100.0, "usdc", None, 3000.0, None).is_err());
+        assert!(limit_order(OrderType::Buy, 0.0, "weth", None, 3000.0, None).is_err());
+    }
+
+    #[test]
+    fn test_parse_confirmation() {
+        assert_eq!(parse_confirmation("CONFIRM"), Some(Confirmation::Confirm));
+        assert_eq!(parse_confirmation(" confirm. "), Some(Confirmation::Confirm));
+        assert_eq!(parse_confirmation("cancel the swap"), Some(Confirmation::Cancel));
+        assert_eq!(parse_confirmation("confirm the price of btc"), None);
+        assert_eq!(parse_confirmation("yes"), None);
+    }
+
+    #[test]
+    fn test_is_swap_status_query() {
+        assert!(is_swap_status_query("Did my last swap go through?"));
+        assert!(is_swap_status_query("has the latest trade confirmed"));
+        assert!(is_swap_status_query("what's the status of my last transaction"));
+        assert!(!is_swap_status_query("swap 0.1 eth to usdc"));
+        assert!(!is_swap_status_query("did my portfolio go up?"));
+    }
+
+    fn sent_swap(status: TradeStatus, block_number: Option<i64>, gas_cost: Option<f64>) -> Trade {
+        Trade {
+            id: 1,
+            user_id: 1,
+            executed_at: NaiveDateTime::parse_from_str("2025-10-16 10:00:00", "%Y-%m-%d %H:%M:%S").unwrap(),
+            side: OrderType::Buy,
+            coin_id: "ethereum".to_string(),
+            quote: "USDC".to_string(),
+            amount: 0.1,
+            price: 2500.0,
+            fee: 0.0,
+            imported: false,
+            created_at: NaiveDateTime::parse_from_str("2025-10-16 10:00:00", "%Y-%m-%d %H:%M:%S").unwrap(),
+            tx_hash: Some("0x5e8f".to_string()),
+            from_token: None,
+            to_token: None,
+            from_amount: None,
+            to_amount: None,
+            price_usd_at_execution: Some(2500.0),
+            gas_cost,
+            status,
+            aggregator: Some("1inch".to_string()),
+            block_number,
+            gas_used: None,
+        }
+    }
+
+    #[test]
+    fn test_render_swap_status() {
+        assert_eq!(
+            render_swap_status(&sent_swap(TradeStatus::Confirmed, Some(4660), Some(0.00026))),
+            "Your last swap, a buy of 0.1 ethereum in transaction 0x5e8f, went through in block 4660, paying 0.00026 ETH for gas."
+        );
+        assert_eq!(
+            render_swap_status(&sent_swap(TradeStatus::Failed, Some(4660), None)),
+            "Your last swap, a buy of 0.1 ethereum in transaction 0x5e8f, was mined in block 4660 but reverted, so nothing was swapped."
+        );
+        assert!(render_swap_status(&sent_swap(TradeStatus::Pending, None, None)).ends_with("hasn't been confirmed yet. Blocks come every few seconds, so ask again in a minute."));
+        assert!(render_swap_status(&sent_swap(TradeStatus::Stuck, None, None)).contains("still isn't mined and was given up on"));
+    }
+
+    #[test]
+    fn test_staged_trades_expire() {
+        let staged = NaiveDateTime::parse_from_str("2025-10-16 10:00:00", "%Y-%m-%d %H:%M:%S").unwrap();
+        assert!(!is_expired(staged, Utc.with_ymd_and_hms(2025, 10, 16, 10, 10, 0).unwrap()));
+        assert!(is_expired(staged, Utc.with_ymd_and_hms(2025, 10, 16, 10, 10, 1).unwrap()));
+    }
+
+    #[test]
+    fn test_tokens_are_found_by_unique_symbol_or_address() {
+        let tokens = tokens();
+        assert!(matches!(lookup_token("weth", &tokens), TokenLookup::Found(token) if token.symbol == "WETH"));
+        assert!(matches!(lookup_token("AERO", &tokens), TokenLookup::Found(token) if token.name == "Aerodrome"));
+        assert!(matches!(lookup_token("eth", &tokens), TokenLookup::Found(token) if token.address == NATIVE_TOKEN));
+        let bridged = lookup_token("0xD9AAEC86B65D86F6A7B5B1B0C42FFA531710B6CA", &tokens);
+        assert!(matches!(bridged, TokenLookup::Found(token) if token.name == "Bridged USD Coin"));
+        assert!(matches!(lookup_token("0x0000000000000000000000000000000000000001", &tokens), TokenLookup::Unknown));
+        assert!(matches!(lookup_token("pepe", &tokens), TokenLookup::Unknown));
+    }
+
+    #[test]
+    fn test_ambiguous_tokens_ask_instead_of_guessing() {
+        let tokens = tokens();
+        let shared = lookup_token("usdc", &tokens);
+        assert!(matches!(&shared, TokenLookup::Ambiguous(candidates) if candidates.len() == 2));
+        assert_eq!(
+            render_lookup("usdc", &shared),
+            "Several tokens are called USDC:\n\
+            - USDC (Bridged USD Coin) at 0xd9aaec86b65d86f6a7b5b1b0c42ffa531710b6ca\n\
+            - USDC (USD Coin) at 0xf175520c52418dfe19c8098071a252da48cd1c19\n\
+            Which one do you mean? Ask again with its address in place of \"usdc\"."
+        );
+
+        // A word of a token's name is only a suggestion, even when one token has it
+        let named = lookup_token("aerodrome", &tokens);
+        assert_eq!(
+            render_lookup("aerodrome", &named),
+            "No token has the symbol \"aerodrome\". Did you mean one of these?\n\
+            - AERO (Aerodrome) at 0x940181a94a35a4569e4529a3cdfb74e38fd98631\n\
+            Ask again with the symbol you mean."
+        );
+    }
+
+    #[test]
+    fn test_staged_trades_round_trip_through_json() {
+        let swap = StagedTrade::Swap {
+            from_token: NATIVE_TOKEN.to_string(),
+            from_symbol: "ETH".to_string(),
+            to_token: "0xf175520c52418dfe19c8098071a252da48cd1c19".to_string(),
+            to_symbol: "USDC".to_string(),
+            amount: 0.1,
+            decimals: 18,
+            slippage: 1.0,
+        };
+        let json = serde_json::to_value(&swap).unwrap();
+        assert_eq!(json["kind"], "swap");
+        assert_eq!(serde_json::from_value::<StagedTrade>(json).unwrap(), swap);
+        assert_eq!(swap.describe(), "swap of 0.1 ETH to USDC");
+
+        let order = StagedTrade::LimitOrder { order_type: OrderType::Buy, amount: 0.04, price: 2500.0, expires_at: None };
+        assert_eq!(serde_json::to_value(&order).unwrap()["order_type"], "buy");
+        assert_eq!(
+            render_staged(&order, UserTimezone::default()),
+            "Place a limit buy of 0.04 WETH at $2500.00 (about 100 USDC).\n\
+            Reply CONFIRM to execute it within 10 minutes, or cancel to drop it."
+        );
+
+        // Orders staged before expiries existed still read back
+        let json = serde_json::json!({"kind": "limit_order", "order_type": "buy", "amount": 0.04, "price": 2500.0});
+        assert_eq!(serde_json::from_value::<StagedTrade>(json).unwrap(), order);
+
+        let expires_at = Some(Utc.with_ymd_and_hms(2025, 10, 17, 21, 59, 59).unwrap());
+        let good_until_friday = StagedTrade::LimitOrder { order_type: OrderType::Buy, amount: 0.04, price: 2500.0, expires_at };
+        let json = serde_json::to_value(&good_until_friday).unwrap();
+        assert_eq!(serde_json::from_value::<StagedTrade>(json).unwrap(), good_until_friday);
+        assert_eq!(
+            render_staged(&good_until_friday, UserTimezone::Named(chrono_tz::Europe::Berlin)),
+            "Place a limit buy of 0.04 WETH at $2500.00 (about 100 USDC), good until Fri 17 Oct 2025, 23:59 Europe/Berlin.\n\
+            Reply CONFIRM to execute it within 10 minutes, or cancel to drop it."
+        );
+    }
+}
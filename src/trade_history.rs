@@ -0,0 +1,188 @@
+use crate::db::{OrderType, Trade, TradeStatus};
+use crate::price_format::format_price;
+use crate::render::Table;
+use crate::timezone::UserTimezone;
+use crate::topics::{self, Period};
+use crate::trade_import::USD_QUOTES;
+use regex::Regex;
+use std::sync::OnceLock;
+
+fn trade_history_regex() -> &'static Regex {
+    static REGEX: OnceLock<Regex> = OnceLock::new();
+    REGEX.get_or_init(|| {
+        Regex::new(
+            r"(?i)\b(?:what|which)\s+trades\s+(?:have|did)\s+(?:i|we)\s+(?:made|make|done|do|executed|execute|placed|place)\b|\b(?:show|list)\s+(?:me\s+)?my\s+(?:recent\s+)?trades\b|\bmy\s+trade\s+history\b",
+        )
+        .unwrap()
+    })
+}
+
+/// Parse "what trades have I made this month?" and similar questions into the period they ask about
+pub fn parse_trade_history_query(message: &str) -> Option<Period> {
+    trade_history_regex().is_match(message).then(|| topics::parse_period(message)).flatten()
+}
+
+/// "0x1234…abcd" for a transaction, "import" for a trade from an exchange export
+fn source(trade: &Trade) -> String {
+    match &trade.tx_hash {
+        Some(hash) if hash.len() > 12 => format!("{}…{}", &hash[..6], &hash[hash.len() - 4..]),
+        Some(hash) => hash.clone(),
+        None if trade.imported => "import".to_string(),
+        None => "-".to_string(),
+    }
+}
+
+/// The USD price of a trade, None when it was paid in another coin and the USD price wasn't recorded
+fn usd_price(trade: &Trade) -> Option<f64> {
+    trade
+        .price_usd_at_execution
+        .or_else(|| USD_QUOTES.contains(&trade.quote.as_str()).then_some(trade.price))
+}
+
+/// The price in USD when known, otherwise in the coin it was paid with
+fn render_price(trade: &Trade) -> String {
+    match usd_price(trade) {
+        Some(price) => format_price(price),
+        None => format!("{} {}", (trade.price * 1e8).round() / 1e8, trade.quote),
+    }
+}
+
+/// The trades executed `period`, one row each in the user's timezone, with what they add up to
+pub fn render_trade_history(period: Period, trades: &[Trade], timezone: UserTimezone) -> String {
+    if trades.is_empty() {
+        return format!("You haven't made any trades {}.", period.describe());
+    }
+
+    let mut table = Table::new(["Date", "Side", "Coin", "Amount", "Price", "Status", "Tx"]);
+    for trade in trades {
+        table.push_row([
+            trade.executed_at.and_utc().with_timezone(&timezone).format("%Y-%m-%d %H:%M").to_string(),
+            trade.side.as_str().to_string(),
+            trade.coin_id.clone(),
+            format!("{}", (trade.amount * 1e8).round() / 1e8),
+            render_price(trade),
+            trade.status.as_str().to_string(),
+            source(trade),
+        ]);
+    }
+
+    let filled: Vec<&Trade> = trades.iter().filter(|trade| trade.status.is_filled()).collect();
+    let usd_total = |side: OrderType| -> f64 {
+        filled
+            .iter()
+            .filter(|trade| trade.side == side)
+            .filter_map(|trade| usd_price(trade).map(|price| price * trade.amount))
+            .sum()
+    };
+    let count = |n: usize, noun: &str| if n == 1 { format!("1 {}", noun) } else { format!("{} {}s", n, noun) };
+
+    let mut summary = format!("{} {}", count(trades.len(), "trade"), period.describe());
+    let failed = trades.iter().filter(|trade| trade.status == TradeStatus::Failed).count();
+    if failed > 0 {
+        summary.push_str(&format!(", {} failed", failed));
+    }
+    // Swaps still waiting to be mined, or given up on, moved nothing yet
+    let unconfirmed = trades.len() - filled.len() - failed;
+    if unconfirmed > 0 {
+        summary.push_str(&format!(", {} not confirmed", unconfirmed));
+    }
+    summary.push_str(&format!(
+        ". Bought {} and sold {} in USD terms",
+        format_price(usd_total(OrderType::Buy)),
+        format_price(usd_total(OrderType::Sell))
+    ));
+    let gas: f64 = trades.iter().filter_map(|trade| trade.gas_cost).sum();
+    if gas > 0.0 {
+        summary.push_str(&format!(", {} ETH spent on gas", (gas * 1e8).round() / 1e8));
+    }
+    summary.push('.');
+
+    format!("Your trades {}:\n{}\n{}", period.describe(), table.render(), summary)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{FixedOffset, NaiveDate, NaiveDateTime};
+
+    fn at(day: u32, hour: u32) -> NaiveDateTime {
+        NaiveDate::from_ymd_opt(2025, 10, day).unwrap().and_hms_opt(hour, 0, 0).unwrap()
+    }
+
+    fn trade(side: OrderType, coin_id: &str, amount: f64, price: f64, executed_at: NaiveDateTime) -> Trade {
+        Trade {
+            id: 0,
+            user_id: 1,
+            executed_at,
+            side,
+            coin_id: coin_id.to_string(),
+            quote: "USDC".to_string(),
+            amount,
+            price,
+            fee: 0.0,
+            imported: false,
+            created_at: executed_at,
+            tx_hash: None,
+            from_token: None,
+            to_token: None,
+            from_amount: None,
+            to_amount: None,
+            price_usd_at_execution: Some(price),
+            gas_cost: None,
+            status: TradeStatus::Filled,
+            aggregator: None,
+            block_number: None,
+            gas_used: None,
+        }
+    }
+
+    #[test]
+    fn test_parse_trade_history_query() {
+        assert_eq!(parse_trade_history_query("What trades have I made this month?"), Some(Period::Month));
+        assert_eq!(parse_trade_history_query("which trades did we execute in the last 7 days"), Some(Period::LastDays(7)));
+        assert_eq!(parse_trade_history_query("show me my trades"), Some(Period::All));
+        assert_eq!(parse_trade_history_query("my trade history today"), Some(Period::Today));
+        assert_eq!(parse_trade_history_query("should I trade eth this month?"), None);
+        assert_eq!(parse_trade_history_query("what trades have I made in the last 0 days"), None);
+    }
+
+    #[test]
+    fn test_render_trade_history() {
+        let swap = Trade {
+            tx_hash: Some(format!("0x{}", "ab".repeat(32))),
+            gas_cost: Some(0.0003),
+            status: TradeStatus::Confirmed,
+            ..trade(OrderType::Buy, "ethereum", 0.1, 2500.0, at(3, 22))
+        };
+        let pending = Trade {
+            tx_hash: Some(format!("0x{}", "cd".repeat(32))),
+            status: TradeStatus::Pending,
+            ..trade(OrderType::Buy, "ethereum", 0.2, 2550.0, at(16, 9))
+        };
+        let failed = Trade { status: TradeStatus::Failed, ..trade(OrderType::Sell, "ethereum", 0.05, 2600.0, at(10, 9)) };
+        let in_eth = Trade { quote: "WETH".to_string(), price_usd_at_execution: None, ..trade(OrderType::Buy, "aave", 10.0, 0.1, at(12, 9)) };
+        let limit_sell = trade(OrderType::Sell, "ethereum", 0.04, 2700.0, at(15, 9));
+
+        let rendered = render_trade_history(Period::Month, &[swap, failed, in_eth, limit_sell, pending], FixedOffset::east_opt(3600).unwrap().into());
+        assert_eq!(
+            rendered,
+            "Your trades this month:\n\
+            Date              Side  Coin      Amount  Price     Status     Tx\n\
+            ----------------  ----  --------  ------  --------  ---------  -----------\n\
+            2025-10-03 23:00  buy   ethereum     0.1  $2500.00  confirmed  0xabab…abab\n\
+            2025-10-10 10:00  sell  ethereum    0.05  $2600.00  failed     -\n\
+            2025-10-12 10:00  buy   aave          10  0.1 WETH  filled     -\n\
+            2025-10-15 10:00  sell  ethereum    0.04  $2700.00  filled     -\n\
+            2025-10-16 10:00  buy   ethereum     0.2  $2550.00  pending    0xcdcd…cdcd\n\
+            5 trades this month, 1 failed, 1 not confirmed. Bought $250.00 and sold $108.00 in USD terms, 0.0003 ETH spent on gas."
+        );
+    }
+
+    #[test]
+    fn test_render_without_trades() {
+        assert_eq!(
+            render_trade_history(Period::LastDays(7), &[], UserTimezone::default()),
+            "You haven't made any trades in the last 7 days."
+        );
+    }
+}
@@ -0,0 +1,582 @@
+use crate::db::{NewTrade, OrderType};
+use chrono::{DateTime, NaiveDate, NaiveDateTime};
+use regex::Regex;
+use std::sync::OnceLock;
+use thiserror::Error;
+
+/// Lines searched for the header, exchange exports start with a few lines of account details
+const HEADER_SEARCH_LINES: usize = 10;
+
+/// Quotes taken as US dollars, the only ones trades are imported in
+pub const USD_QUOTES: &[&str] = &["USD", "USDT", "USDC", "BUSD", "FDUSD", "TUSD", "DAI", "USDP"];
+
+/// Quotes stripped from the end of pairs written without a separator, like BTCUSDT
+const PAIR_QUOTES: &[&str] = &["FDUSD", "USDT", "USDC", "BUSD", "TUSD", "USDP", "DAI", "USD", "BTC", "ETH", "BNB", "EUR", "GBP"];
+
+/// Header names per field, lower case, in order of preference
+const TIMESTAMP_HEADERS: &[&str] = &["timestamp", "date(utc)", "date (utc)", "date", "time", "datetime", "created at", "trade time"];
+const SIDE_HEADERS: &[&str] = &["side", "transaction type", "trade type", "type"];
+const BASE_HEADERS: &[&str] = &["base", "base asset", "base currency", "asset", "coin"];
+const PAIR_HEADERS: &[&str] = &["pair", "market", "product", "symbol"];
+const QUOTE_HEADERS: &[&str] = &["quote", "quote asset", "quote currency", "price currency", "spot price currency"];
+// Binance's newer export has the base amount in "Executed" and the quote total in "Amount"
+const AMOUNT_HEADERS: &[&str] = &["executed", "amount", "quantity transacted", "quantity", "size", "filled"];
+const PRICE_HEADERS: &[&str] = &["price", "price at transaction", "spot price at transaction", "usd spot price at transaction", "average price"];
+const FEE_HEADERS: &[&str] = &["fee", "fees", "fees and/or spread", "usd fees", "commission"];
+const FEE_ASSET_HEADERS: &[&str] = &["fee coin", "fee asset", "fee currency", "commission asset"];
+
+#[derive(Debug, Error)]
+pub enum ImportError {
+    #[error("Couldn't read {0}: {1}")]
+    Read(String, std::io::Error),
+
+    #[error("No header row found, expected columns for {0}")]
+    NoHeader(String),
+}
+
+/// Where a row's coin comes from
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum BaseColumn {
+    /// A column naming the coin, like "BTC"
+    Asset(usize),
+    /// A column naming the pair, like "BTC-USD" or "BTCUSDT"
+    Pair(usize),
+}
+
+/// Which column holds each field of a trade
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HeaderMap {
+    timestamp: usize,
+    side: usize,
+    base: BaseColumn,
+    quote: Option<usize>,
+    /// Set by headers naming the currency, like Coinbase's "USD Spot Price at Transaction"
+    implied_quote: Option<&'static str>,
+    amount: usize,
+    price: usize,
+    fee: Option<usize>,
+    fee_asset: Option<usize>,
+}
+
+impl HeaderMap {
+    /// Map the columns of a header row, naming the fields no column was found for
+    pub fn detect(header: &[String]) -> Result<Self, Vec<&'static str>> {
+        let names: Vec<String> = header.iter().map(|name| normalize_header(name)).collect();
+        let find = |aliases: &[&str]| aliases.iter().find_map(|alias| names.iter().position(|name| name == alias));
+
+        let timestamp = find(TIMESTAMP_HEADERS);
+        let side = find(SIDE_HEADERS);
+        let base = find(BASE_HEADERS).map(BaseColumn::Asset).or_else(|| find(PAIR_HEADERS).map(BaseColumn::Pair));
+        let amount = find(AMOUNT_HEADERS);
+        let price = find(PRICE_HEADERS);
+        let implied_quote = price.filter(|&column| names[column].starts_with("usd ")).map(|_| "USD");
+        let quote = find(QUOTE_HEADERS);
+
+        let mut missing = Vec::new();
+        for (field, column) in [("timestamp", timestamp), ("side", side), ("amount", amount), ("price", price)] {
+            if column.is_none() {
+                missing.push(field);
+            }
+        }
+        if base.is_none() {
+            missing.push("base");
+        }
+        if quote.is_none() && implied_quote.is_none() && !matches!(base, Some(BaseColumn::Pair(_))) {
+            missing.push("quote");
+        }
+        if !missing.is_empty() {
+            return Err(missing);
+        }
+
+        Ok(Self {
+            timestamp: timestamp.unwrap(),
+            side: side.unwrap(),
+            base: base.unwrap(),
+            quote,
+            implied_quote,
+            amount: amount.unwrap(),
+            price: price.unwrap(),
+            fee: find(FEE_HEADERS),
+            fee_asset: find(FEE_ASSET_HEADERS),
+        })
+    }
+}
+
+/// A valid row of an export, the coin still named as the exchange writes it
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParsedTrade {
+    /// Line of the file, counted from 1
+    pub line: usize,
+    pub executed_at: NaiveDateTime,
+    pub side: OrderType,
+    /// Upper case symbol, like "BTC"
+    pub base: String,
+    pub quote: String,
+    pub amount: f64,
+    pub price: f64,
+    /// In quote units, zero when the exchange charged it in another coin
+    pub fee: f64,
+}
+
+impl ParsedTrade {
+    /// The trade to store, with the coin resolved to `coin_id`
+    pub fn to_new_trade(&self, coin_id: &str) -> NewTrade {
+        // Only USD-like quotes are imported, so the price is already in USD
+        NewTrade {
+            price_usd_at_execution: Some(self.price),
+            ..NewTrade::filled(self.executed_at, self.side, coin_id, &self.quote, self.amount, self.price, self.fee)
+        }
+    }
+}
+
+/// A row that was skipped, with why
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RowError {
+    pub line: usize,
+    pub reason: String,
+}
+
+/// The trades of an export and the rows that couldn't be read
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ParsedImport {
+    pub trades: Vec<ParsedTrade>,
+    pub errors: Vec<RowError>,
+    /// Trades whose fee was charged in a coin other than the base or quote, imported without it
+    pub fees_left_out: usize,
+}
+
+/// Read the export at `path`, see `parse`
+pub fn parse_file(path: &str, now: NaiveDateTime) -> Result<ParsedImport, ImportError> {
+    let content = std::fs::read_to_string(path).map_err(|e| ImportError::Read(path.to_string(), e))?;
+    parse(&content, now)
+}
+
+/// Parse a CSV of trades, finding its header among the first lines
+///
+/// Rows that can't be read are listed with their line number instead of failing the import.
+/// Trades after `now` are rejected.
+pub fn parse(content: &str, now: NaiveDateTime) -> Result<ParsedImport, ImportError> {
+    let lines: Vec<&str> = content.lines().collect();
+    let mut header = None;
+    let mut missing = Vec::new();
+    for (index, line) in lines.iter().enumerate().take(HEADER_SEARCH_LINES) {
+        if line.trim().is_empty() {
+            continue;
+        }
+        match HeaderMap::detect(&split_csv_line(line)) {
+            Ok(map) => {
+                header = Some((index, map));
+                break;
+            },
+            // Report what the most promising line lacked
+            Err(fields) if missing.is_empty() || fields.len() < missing.len() => missing = fields,
+            Err(_) => {},
+        }
+    }
+    let Some((header_index, map)) = header else {
+        let expected = if missing.is_empty() { vec!["timestamp", "side", "base", "quote", "amount", "price"] } else { missing };
+        return Err(ImportError::NoHeader(expected.join(", ")));
+    };
+
+    let mut parsed = ParsedImport::default();
+    for (index, line) in lines.iter().enumerate().skip(header_index + 1) {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let line_number = index + 1;
+        match parse_row(&map, &split_csv_line(line), line_number, now) {
+            Ok((trade, fee_left_out)) => {
+                parsed.fees_left_out += usize::from(fee_left_out);
+                parsed.trades.push(trade);
+            },
+            Err(reason) => parsed.errors.push(RowError { line: line_number, reason }),
+        }
+    }
+    Ok(parsed)
+}
+
+// The trade of a row, and whether its fee was left out for being in another coin
+fn parse_row(map: &HeaderMap, fields: &[String], line: usize, now: NaiveDateTime) -> Result<(ParsedTrade, bool), String> {
+    let field = |column: usize| fields.get(column).map(|value| value.trim()).unwrap_or("");
+
+    let executed_at = parse_timestamp(field(map.timestamp)).ok_or_else(|| format!("invalid timestamp \"{}\"", field(map.timestamp)))?;
+    if executed_at > now {
+        return Err(format!("timestamp {} is in the future", executed_at.format("%Y-%m-%d %H:%M")));
+    }
+    let side = parse_side(field(map.side))?;
+
+    let (base, pair_quote) = match map.base {
+        BaseColumn::Asset(column) => (field(column).to_uppercase(), None),
+        BaseColumn::Pair(column) => split_pair(field(column)).ok_or_else(|| format!("can't split pair \"{}\"", field(column)))?,
+    };
+    if base.is_empty() {
+        return Err("missing coin".to_string());
+    }
+    let quote = map
+        .quote
+        .map(|column| field(column).to_uppercase())
+        .filter(|quote| !quote.is_empty())
+        .or(pair_quote)
+        .or_else(|| map.implied_quote.map(str::to_string))
+        .ok_or("missing quote currency")?;
+    if !USD_QUOTES.contains(&quote.as_str()) {
+        return Err(format!("quote {} isn't USD or a dollar stablecoin", quote));
+    }
+
+    let (amount, _) = parse_number(field(map.amount)).ok_or_else(|| format!("invalid amount \"{}\"", field(map.amount)))?;
+    // Some exports write sells as negative quantities
+    let amount = amount.abs();
+    if amount == 0.0 {
+        return Err("amount is zero".to_string());
+    }
+    let (price, _) = parse_number(field(map.price)).ok_or_else(|| format!("invalid price \"{}\"", field(map.price)))?;
+    if price <= 0.0 {
+        return Err(format!("price must be positive, got {}", field(map.price)));
+    }
+
+    let (fee, fee_unit) = match map.fee.map(field).filter(|fee| !fee.is_empty()) {
+        Some(fee) => parse_number(fee).ok_or_else(|| format!("invalid fee \"{}\"", fee))?,
+        None => (0.0, None),
+    };
+    if fee < 0.0 {
+        return Err(format!("fee can't be negative, got {}", fee));
+    }
+    let fee_asset = map
+        .fee_asset
+        .map(|column| field(column).to_uppercase())
+        .filter(|asset| !asset.is_empty())
+        .or(fee_unit);
+    let (fee, fee_left_out) = match fee_asset {
+        Some(asset) if asset == base => (fee * price, false),
+        Some(asset) if asset != quote && !USD_QUOTES.contains(&asset.as_str()) => (0.0, fee > 0.0),
+        _ => (fee, false),
+    };
+
+    Ok((ParsedTrade { line, executed_at, side, base, quote, amount, price, fee }, fee_left_out))
+}
+
+/// Normalize a header name for matching: lower case, trimmed, single spaces
+pub fn normalize_header(name: &str) -> String {
+    name.trim_start_matches('\u{feff}').split_whitespace().collect::<Vec<_>>().join(" ").to_lowercase()
+}
+
+/// Split one CSV line, honoring quoted fields with commas and doubled quotes inside
+pub fn split_csv_line(line: &str) -> Vec<String> {
+    let mut fields = Vec::new();
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = line.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                field.push('"');
+                chars.next();
+            },
+            '"' => quoted = !quoted,
+            ',' if !quoted => fields.push(std::mem::take(&mut field)),
+            _ => field.push(c),
+        }
+    }
+    fields.push(field);
+    fields
+}
+
+/// A number as exchanges write it, with the unit some of them append: "$1,234.5" or "0.5BTC"
+pub fn parse_number(value: &str) -> Option<(f64, Option<String>)> {
+    static NUMBER: OnceLock<Regex> = OnceLock::new();
+    let number = NUMBER.get_or_init(|| Regex::new(r"^([+-]?(?:\d+\.?\d*|\.\d+)(?:[eE][+-]?\d+)?)\s*([A-Za-z]*)$").unwrap());
+    let cleaned: String = value.trim().chars().filter(|c| !matches!(c, '$' | ',' | ' ')).collect();
+    let captures = number.captures(&cleaned)?;
+    let amount = captures[1].parse::<f64>().ok().filter(|amount| amount.is_finite())?;
+    let unit = Some(captures[2].to_uppercase()).filter(|unit| !unit.is_empty());
+    Some((amount, unit))
+}
+
+/// A timestamp in one of the layouts exchanges export, taken as UTC
+pub fn parse_timestamp(value: &str) -> Option<NaiveDateTime> {
+    let value = value.trim();
+    if let Ok(parsed) = DateTime::parse_from_rfc3339(value) {
+        return Some(parsed.naive_utc());
+    }
+    let value = value.trim_end_matches(" UTC").trim_end_matches('Z');
+    for format in ["%Y-%m-%d %H:%M:%S%.f", "%Y-%m-%dT%H:%M:%S%.f", "%Y-%m-%d %H:%M", "%m/%d/%Y %H:%M:%S", "%m/%d/%Y %H:%M"] {
+        if let Ok(parsed) = NaiveDateTime::parse_from_str(value, format) {
+            return Some(parsed);
+        }
+    }
+    for format in ["%Y-%m-%d", "%m/%d/%Y"] {
+        if let Ok(date) = NaiveDate::parse_from_str(value, format) {
+            return date.and_hms_opt(0, 0, 0);
+        }
+    }
+    // Unix seconds, or milliseconds when too large to be seconds
+    let seconds: i64 = value.parse().ok()?;
+    if seconds > 100_000_000_000 {
+        DateTime::from_timestamp_millis(seconds).map(|parsed| parsed.naive_utc())
+    } else {
+        DateTime::from_timestamp(seconds, 0).map(|parsed| parsed.naive_utc())
+    }
+}
+
+/// Buy or sell, also out of descriptions like Coinbase's "Advanced Trade Buy"
+fn parse_side(value: &str) -> Result<OrderType, String> {
+    let lower = value.to_lowercase();
+    match (lower.contains("buy"), lower.contains("sell")) {
+        (true, false) => Ok(OrderType::Buy),
+        (false, true) => Ok(OrderType::Sell),
+        _ if value.is_empty() => Err("missing side".to_string()),
+        _ => Err(format!("\"{}\" is not a buy or sell", value)),
+    }
+}
+
+/// Base and quote of a pair written "BTC-USD", "BTC/USDT" or "BTCUSDT"
+pub fn split_pair(pair: &str) -> Option<(String, Option<String>)> {
+    let pair = pair.trim().to_uppercase();
+    if let Some((base, quote)) = pair.split_once(['-', '/', '_']) {
+        return (!base.is_empty() && !quote.is_empty()).then(|| (base.to_string(), Some(quote.to_string())));
+    }
+    PAIR_QUOTES.iter().find_map(|quote| {
+        let base = pair.strip_suffix(quote)?;
+        (!base.is_empty()).then(|| (base.to_string(), Some(quote.to_string())))
+    })
+}
+
+/// The trades stored and the rows skipped, for the import command's reply
+pub fn render_report(path: &str, parsed: &ParsedImport, stored: u64) -> String {
+    let duplicates = parsed.trades.len() as u64 - stored.min(parsed.trades.len() as u64);
+    let mut output = format!("Imported {} trade(s) from {}", stored, path);
+    if duplicates > 0 {
+        output.push_str(&format!(", {} already imported", duplicates));
+    }
+    output.push('.');
+    if parsed.fees_left_out > 0 {
+        output.push_str(&format!(
+            "\n{} trade(s) paid their fee in another coin, their cost doesn't include it.",
+            parsed.fees_left_out
+        ));
+    }
+    if !parsed.errors.is_empty() {
+        output.push_str(&format!("\nSkipped {} row(s):", parsed.errors.len()));
+        for error in &parsed.errors {
+            output.push_str(&format!("\n- line {}: {}", error.line, error.reason));
+        }
+    }
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn now() -> NaiveDateTime {
+        NaiveDate::from_ymd_opt(2025, 10, 1).unwrap().and_hms_opt(0, 0, 0).unwrap()
+    }
+
+    fn at(y: i32, m: u32, d: u32, h: u32, min: u32, s: u32) -> NaiveDateTime {
+        NaiveDate::from_ymd_opt(y, m, d).unwrap().and_hms_opt(h, min, s).unwrap()
+    }
+
+    fn header(line: &str) -> Result<HeaderMap, Vec<&'static str>> {
+        HeaderMap::detect(&split_csv_line(line))
+    }
+
+    #[test]
+    fn test_split_csv_line_handles_quotes() {
+        assert_eq!(split_csv_line("a,b,,c"), ["a", "b", "", "c"]);
+        assert_eq!(split_csv_line(r#""1,234.50","say ""hi""",x"#), ["1,234.50", r#"say "hi""#, "x"]);
+        assert_eq!(split_csv_line(""), [""]);
+    }
+
+    #[test]
+    fn test_parse_number_strips_formatting_and_units() {
+        assert_eq!(parse_number("$1,234.50"), Some((1234.5, None)));
+        assert_eq!(parse_number("0.5BTC"), Some((0.5, Some("BTC".to_string()))));
+        assert_eq!(parse_number("0.00075 bnb"), Some((0.00075, Some("BNB".to_string()))));
+        assert_eq!(parse_number("0.5ETH"), Some((0.5, Some("ETH".to_string()))));
+        assert_eq!(parse_number("-2"), Some((-2.0, None)));
+        assert_eq!(parse_number("1e-3"), Some((0.001, None)));
+        assert_eq!(parse_number("abc"), None);
+        assert_eq!(parse_number(""), None);
+    }
+
+    #[test]
+    fn test_parse_timestamp_layouts() {
+        let expected = at(2024, 3, 5, 14, 30, 0);
+        assert_eq!(parse_timestamp("2024-03-05T14:30:00Z"), Some(expected));
+        assert_eq!(parse_timestamp("2024-03-05T16:30:00+02:00"), Some(expected));
+        assert_eq!(parse_timestamp("2024-03-05 14:30:00 UTC"), Some(expected));
+        assert_eq!(parse_timestamp("2024-03-05 14:30:00"), Some(expected));
+        assert_eq!(parse_timestamp("2024-03-05 14:30"), Some(expected));
+        assert_eq!(parse_timestamp("03/05/2024 14:30:00"), Some(expected));
+        assert_eq!(parse_timestamp("1709649000"), Some(expected));
+        assert_eq!(parse_timestamp("1709649000000"), Some(expected));
+        assert_eq!(parse_timestamp("2024-03-05"), Some(at(2024, 3, 5, 0, 0, 0)));
+        assert_eq!(parse_timestamp("yesterday"), None);
+    }
+
+    #[test]
+    fn test_split_pair() {
+        assert_eq!(split_pair("BTC-USD"), Some(("BTC".to_string(), Some("USD".to_string()))));
+        assert_eq!(split_pair("eth/usdt"), Some(("ETH".to_string(), Some("USDT".to_string()))));
+        assert_eq!(split_pair("SOLUSDT"), Some(("SOL".to_string(), Some("USDT".to_string()))));
+        assert_eq!(split_pair("ETHFDUSD"), Some(("ETH".to_string(), Some("FDUSD".to_string()))));
+        assert_eq!(split_pair("ETHBTC"), Some(("ETH".to_string(), Some("BTC".to_string()))));
+        assert_eq!(split_pair("USDT"), None);
+        assert_eq!(split_pair("-USD"), None);
+    }
+
+    #[test]
+    fn test_header_mapping_of_documented_format() {
+        let map = header("timestamp,side,base,quote,amount,price,fee").unwrap();
+        assert_eq!(map.base, BaseColumn::Asset(2));
+        assert_eq!((map.quote, map.amount, map.price, map.fee), (Some(3), 4, 5, Some(6)));
+        // Case, spacing and a byte order mark don't matter
+        assert!(header("\u{feff}Timestamp , SIDE,Base,Quote,Amount,Price").is_ok());
+    }
+
+    #[test]
+    fn test_header_mapping_of_exchange_layouts() {
+        let coinbase = header("Timestamp,Transaction Type,Asset,Quantity Transacted,Spot Price Currency,Spot Price at Transaction,Subtotal,Total (inclusive of fees and/or spread),Fees and/or Spread,Notes").unwrap();
+        assert_eq!((coinbase.side, coinbase.base, coinbase.amount, coinbase.price), (1, BaseColumn::Asset(2), 3, 5));
+        assert_eq!((coinbase.quote, coinbase.fee), (Some(4), Some(8)));
+
+        let old_coinbase = header("Timestamp,Transaction Type,Asset,Quantity Transacted,USD Spot Price at Transaction,USD Subtotal,USD Fees").unwrap();
+        assert_eq!((old_coinbase.quote, old_coinbase.implied_quote), (None, Some("USD")));
+
+        let binance = header("Date(UTC),Pair,Side,Price,Executed,Amount,Fee").unwrap();
+        assert_eq!((binance.base, binance.side, binance.amount), (BaseColumn::Pair(1), 2, 4));
+
+        let old_binance = header("Date(UTC),Market,Type,Price,Amount,Total,Fee,Fee Coin").unwrap();
+        assert_eq!((old_binance.base, old_binance.amount, old_binance.fee_asset), (BaseColumn::Pair(1), 4, Some(7)));
+    }
+
+    #[test]
+    fn test_header_mapping_names_missing_fields() {
+        assert_eq!(header("date,coin,amount").unwrap_err(), vec!["side", "price", "quote"]);
+        assert_eq!(header("User,alice@example.com").unwrap_err(), vec!["timestamp", "side", "amount", "price", "base", "quote"]);
+    }
+
+    #[test]
+    fn test_parse_documented_format() {
+        let csv = "timestamp,side,base,quote,amount,price,fee\n\
+            2024-01-05T12:00:00Z,buy,BTC,USD,0.5,42000,10\n\
+            \n\
+            2024-02-01 09:30:00,SELL,eth,USDC,2,\"2,300.00\",\n";
+        let parsed = parse(csv, now()).unwrap();
+        assert!(parsed.errors.is_empty());
+        assert_eq!(
+            parsed.trades,
+            vec![
+                ParsedTrade {
+                    line: 2,
+                    executed_at: at(2024, 1, 5, 12, 0, 0),
+                    side: OrderType::Buy,
+                    base: "BTC".to_string(),
+                    quote: "USD".to_string(),
+                    amount: 0.5,
+                    price: 42_000.0,
+                    fee: 10.0,
+                },
+                ParsedTrade {
+                    line: 4,
+                    executed_at: at(2024, 2, 1, 9, 30, 0),
+                    side: OrderType::Sell,
+                    base: "ETH".to_string(),
+                    quote: "USDC".to_string(),
+                    amount: 2.0,
+                    price: 2_300.0,
+                    fee: 0.0,
+                },
+            ]
+        );
+        let stored = parsed.trades[0].to_new_trade("bitcoin");
+        assert_eq!((stored.coin_id.as_str(), stored.amount, stored.fee), ("bitcoin", 0.5, 10.0));
+    }
+
+    #[test]
+    fn test_parse_coinbase_export_skips_preamble_and_transfers() {
+        let csv = "\"You can use this transaction report to inform your likely tax obligations.\"\n\
+            User,alice@example.com,abc123\n\
+            Timestamp,Transaction Type,Asset,Quantity Transacted,Spot Price Currency,Spot Price at Transaction,Subtotal,Total (inclusive of fees and/or spread),Fees and/or Spread,Notes\n\
+            2024-01-05 12:00:00 UTC,Buy,BTC,0.01,USD,$42000.00,$420.00,$424.99,$4.99,Bought 0.01 BTC\n\
+            2024-01-06 12:00:00 UTC,Send,BTC,0.005,USD,$43000.00,,,,Sent to wallet\n\
+            2024-01-07 12:00:00 UTC,Advanced Trade Sell,BTC,-0.002,USD,$44000.00,$88.00,$87.50,$0.50,\n";
+        let parsed = parse(csv, now()).unwrap();
+        assert_eq!(parsed.trades.len(), 2);
+        assert_eq!((parsed.trades[0].line, parsed.trades[0].fee), (4, 4.99));
+        assert_eq!((parsed.trades[1].side, parsed.trades[1].amount), (OrderType::Sell, 0.002));
+        assert_eq!(parsed.errors, vec![RowError { line: 5, reason: "\"Send\" is not a buy or sell".to_string() }]);
+    }
+
+    #[test]
+    fn test_parse_binance_exports_convert_fees() {
+        let csv = "Date(UTC),Pair,Side,Price,Executed,Amount,Fee\n\
+            2024-03-01 10:00:00,BTCUSDT,BUY,60000,0.1BTC,6000USDT,0.0001BTC\n\
+            2024-03-02 10:00:00,ETHUSDT,SELL,3000,1ETH,3000USDT,3USDT\n\
+            2024-03-03 10:00:00,SOLUSDT,BUY,100,10SOL,1000USDT,0.002BNB\n";
+        let parsed = parse(csv, now()).unwrap();
+        let fees: Vec<f64> = parsed.trades.iter().map(|trade| (trade.fee * 1e6).round() / 1e6).collect();
+        // Fees in the base are worth the price, fees in BNB are left out
+        assert_eq!(fees, vec![6.0, 3.0, 0.0]);
+        assert_eq!(parsed.trades[0].amount, 0.1);
+        assert_eq!(parsed.fees_left_out, 1);
+
+        let csv = "Date(UTC),Market,Type,Price,Amount,Total,Fee,Fee Coin\n\
+            2021-05-01 10:00:00,ETHBUSD,BUY,2900,1.5,4350,0.0015,ETH\n";
+        let parsed = parse(csv, now()).unwrap();
+        assert_eq!(parsed.trades[0].base, "ETH");
+        assert_eq!(parsed.trades[0].quote, "BUSD");
+        assert!((parsed.trades[0].fee - 4.35).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_bad_rows_are_reported_with_line_numbers() {
+        let csv = "timestamp,side,base,quote,amount,price,fee\n\
+            not a date,buy,BTC,USD,1,100,0\n\
+            2024-01-01,hold,BTC,USD,1,100,0\n\
+            2024-01-01,buy,BTC,EUR,1,100,0\n\
+            2024-01-01,buy,BTC,USD,lots,100,0\n\
+            2024-01-01,buy,BTC,USD,1,0,0\n\
+            2024-01-01,buy,BTC,USD,1,100,-1\n\
+            2030-01-01,buy,BTC,USD,1,100,0\n\
+            2024-01-01,buy,,USD,1,100,0\n\
+            2024-01-01,buy,BTC,USD,1,100,0\n";
+        let parsed = parse(csv, now()).unwrap();
+        assert_eq!(parsed.trades.len(), 1);
+        assert_eq!(parsed.trades[0].line, 10);
+        let errors: Vec<(usize, &str)> = parsed.errors.iter().map(|error| (error.line, error.reason.as_str())).collect();
+        assert_eq!(
+            errors,
+            vec![
+                (2, "invalid timestamp \"not a date\""),
+                (3, "\"hold\" is not a buy or sell"),
+                (4, "quote EUR isn't USD or a dollar stablecoin"),
+                (5, "invalid amount \"lots\""),
+                (6, "price must be positive, got 0"),
+                (7, "fee can't be negative, got -1"),
+                (8, "timestamp 2030-01-01 00:00 is in the future"),
+                (9, "missing coin"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_missing_header_fails_the_import() {
+        let error = parse("date,coin,amount\n2024-01-01,BTC,1\n", now()).unwrap_err();
+        assert_eq!(error.to_string(), "No header row found, expected columns for side, price, quote");
+        assert!(matches!(parse("", now()), Err(ImportError::NoHeader(_))));
+    }
+
+    #[test]
+    fn test_render_report() {
+        let parsed = parse(
+            "timestamp,side,base,quote,amount,price\n2024-01-01,buy,BTC,USD,1,100\n2024-01-02,buy,BTC,USD,1,110\nbad,buy,BTC,USD,1,1\n",
+            now(),
+        )
+        .unwrap();
+        assert_eq!(
+            render_report("trades.csv", &parsed, 1),
+            "Imported 1 trade(s) from trades.csv, 1 already imported.\nSkipped 1 row(s):\n- line 4: invalid timestamp \"bad\""
+        );
+    }
+}
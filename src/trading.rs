@@ -1,20 +1,168 @@
-use reqwest::Client;
-use serde::{Deserialize, Serialize};
+use reqwest::{Client, RequestBuilder, StatusCode};
+use serde::Deserialize;
+use serde::de::DeserializeOwned;
 use std::collections::HashMap;
 use std::env;
 use std::str::FromStr;
+use std::time::{Duration, Instant};
 use dotenv::dotenv;
 use ethers::{
     prelude::*,
     providers::{Http, Provider},
     signers::{LocalWallet, Signer},
-    types::{Address, U256, Chain},
+    types::{Address, Chain},
     middleware::SignerMiddleware,
 };
 use std::sync::Arc;
 use uuid::Uuid;
-use chrono::{DateTime, Utc};
-use tokio::sync::Mutex;
+use async_trait::async_trait;
+use chrono::{DateTime, NaiveDateTime, Utc};
+use sqlx::{Pool, Postgres};
+use thiserror::Error;
+use crate::config::Config;
+use crate::db::{self, DbError, NewTrade, TradeStatus};
+use crate::dex_aggregator::{DexAggregator, FallbackAggregator, ONE_INCH, ZeroExClient};
+use crate::transaction_monitor::{DEFAULT_POLL_INTERVAL, DEFAULT_TIMEOUT_BLOCKS, TransactionMonitor, TransactionStatus};
+use crate::nonce_manager::NonceManager;
+use crate::gas::{GasError, GasOracle, GasSnapshot};
+use crate::price_fetcher::{CoinGeckoClient, PriceError};
+use crate::portfolio::{PortfolioValue, TokenBalance, coin_id_for_symbol, value_portfolio};
+use crate::price_format::format_price;
+use crate::trade_import::USD_QUOTES;
+use tracing::{info, warn};
+
+pub use crate::db::{LimitOrder, OrderStatus, OrderType};
+
+/// Errors raised by the DEX aggregator clients and the trading client
+#[derive(Debug, Error)]
+pub enum TradingError {
+    #[error("Configuration error: {0}")]
+    Configuration(String),
+    
+    #[error("HTTP error: {0}")]
+    Http(#[from] reqwest::Error),
+    
+    #[error("1inch API returned {status}: {message}")]
+    Api { status: StatusCode, message: String },
+    
+    #[error("1inch API rate limit exceeded{}", .0.map(|d| format!(", retry after {}s", d.as_secs())).unwrap_or_default())]
+    RateLimited(Option<Duration>),
+    
+    #[error("Invalid 1inch response: {0}")]
+    InvalidResponse(String),
+    
+    #[error("0x API returned {status}: {message}")]
+    ZeroExApi { status: StatusCode, message: String },
+    
+    #[error("Invalid 0x response: {0}")]
+    InvalidZeroExResponse(String),
+    
+    #[error("Provider error: {0}")]
+    Provider(String),
+    
+    #[error("Wallet error: {0}")]
+    Wallet(#[from] WalletError),
+    
+    #[error("Contract call failed: {0}")]
+    Contract(String),
+    
+    #[error("Invalid address: {0}")]
+    InvalidAddress(String),
+    
+    #[error("Token not found: {0}")]
+    TokenNotFound(String),
+    
+    #[error("Invalid amount: {0}")]
+    InvalidAmount(String),
+    
+    #[error("Order not found: {0}")]
+    OrderNotFound(String),
+    
+    #[error("Order {id} is {status}, only open orders can be cancelled")]
+    OrderNotOpen { id: String, status: OrderStatus },
+    
+    #[error("Order {0} already expired, there is nothing to cancel")]
+    OrderExpired(String),
+    
+    #[error("Database error: {0}")]
+    Database(#[from] DbError),
+    
+    #[error("Gas oracle error: {0}")]
+    Gas(#[from] GasError),
+    
+    #[error("Price error: {0}")]
+    Price(#[from] PriceError),
+    
+    #[error("Swap transaction failed: {0}")]
+    Broadcast(#[from] BroadcastError),
+    
+    #[error("Token approval failed: {0}")]
+    Approval(BroadcastError),
+    
+    #[error("Gas is currently {}, above your {} limit", format_price(*.estimated_usd), format_price(*.max_usd))]
+    GasTooExpensive { estimated_usd: f64, max_usd: f64 },
+    
+    #[error("Gas is currently {gas_gwei:.2} gwei, above your {max_gwei:.2} gwei limit")]
+    GasPriceTooHigh { gas_gwei: f64, max_gwei: f64 },
+    
+    #[error("Not enough {token}: the trade needs {needed} and the wallet holds {available}")]
+    InsufficientBalance { token: String, needed: f64, available: f64 },
+    
+    #[error("Slippage of {slippage}% is above the {max}% maximum")]
+    SlippageTooHigh { slippage: f32, max: f32 },
+    
+    #[error("Slippage of {slippage}% is below the {min}% minimum, the swap would most likely revert")]
+    SlippageTooLow { slippage: f32, min: f32 },
+    
+    #[error("Suspicious quote: the swap values the token sold at {}, the market at {}; nothing was sent", format_price(*.implied), format_price(*.reference))]
+    SuspiciousQuote { implied: f64, reference: f64 },
+    
+    #[error("The wallet is watch-only: {0} needs PRIVATE_KEY to sign")]
+    SignerRequired(String),
+}
+
+impl TradingError {
+    /// Whether a DEX aggregator failed to answer, rather than refused the request, so another may be asked
+    pub fn is_upstream_failure(&self) -> bool {
+        match self {
+            TradingError::Http(_)
+            | TradingError::RateLimited(_)
+            | TradingError::InvalidResponse(_)
+            | TradingError::InvalidZeroExResponse(_) => true,
+            TradingError::Api { status, .. } | TradingError::ZeroExApi { status, .. } => status.is_server_error(),
+            _ => false,
+        }
+    }
+}
+
+/// Failures after 1inch prepared a swap or a token approval: sending it, waiting for it or its execution on chain
+#[derive(Debug, Error)]
+pub enum BroadcastError {
+    #[error("could not send: {0}")]
+    Send(String),
+    
+    #[error("{tx_hash:?} could not be tracked: {reason}")]
+    Receipt { tx_hash: H256, reason: String },
+    
+    #[error("{0:?} was dropped before it was mined")]
+    Dropped(H256),
+    
+    #[error("{0:?} reverted")]
+    Reverted(H256),
+}
+
+impl BroadcastError {
+    /// The transaction that failed, None when it was never sent
+    pub fn tx_hash(&self) -> Option<H256> {
+        match self {
+            BroadcastError::Send(_) => None,
+            BroadcastError::Receipt { tx_hash, .. } => Some(*tx_hash),
+            BroadcastError::Dropped(tx_hash) | BroadcastError::Reverted(tx_hash) => Some(*tx_hash),
+        }
+    }
+}
+
+pub type Result<T> = std::result::Result<T, TradingError>;
 
 // ABI for a simple ERC20 token interface
 abigen!(
@@ -27,38 +175,6 @@ abigen!(
     ]"#
 );
 
-// Enum to represent the type of limit order
-#[derive(Debug, Clone, PartialEq)]
-pub enum OrderType {
-    Buy,
-    Sell,
-}
-
-// Enum to represent the status of a limit order
-#[derive(Debug, Clone, PartialEq)]
-pub enum OrderStatus {
-    Open,
-    Filled,
-    Cancelled,
-}
-
-// Struct to represent a limit order
-#[derive(Debug, Clone)]
-pub struct LimitOrder {
-    pub id: String,
-    pub order_type: OrderType,
-    pub token_address: String,
-    pub amount: f64,
-    pub price: f64,
-    pub created_at: DateTime<Utc>,
-    pub status: OrderStatus,
-}
-
-// In-memory storage for limit orders
-lazy_static::lazy_static! {
-    static ref LIMIT_ORDERS: Mutex<Vec<LimitOrder>> = Mutex::new(Vec::new());
-}
-
 // Token structure for 1inch API responses
 #[derive(Debug, Deserialize, Clone)]
 pub struct Token {
@@ -89,6 +205,9 @@ pub struct QuoteResponse {
     pub protocols: Vec<Vec<Vec<ProtocolRoute>>>,
     #[serde(rename = "estimatedGas")]
     pub estimated_gas: u64,
+    /// DEX aggregator that quoted the swap
+    #[serde(skip_deserializing, default = "one_inch")]
+    pub aggregator: String,
 }
 
 // Protocol route information
@@ -114,6 +233,19 @@ pub struct SwapResponse {
     #[serde(rename = "toTokenAmount")]
     pub to_amount: String,
     pub tx: TransactionData,
+    /// DEX aggregator that prepared the swap
+    #[serde(skip_deserializing, default = "one_inch")]
+    pub aggregator: String,
+}
+
+fn one_inch() -> String {
+    ONE_INCH.to_string()
+}
+
+// Spender response from 1inch API, the router swaps need an allowance for
+#[derive(Debug, Deserialize)]
+pub struct SpenderResponse {
+    pub address: String,
 }
 
 // Transaction data for swap
@@ -128,38 +260,679 @@ pub struct TransactionData {
     pub gas: u64,
 }
 
+/// Blocks a broadcast swap waits for when `TRADE_CONFIRMATIONS` isn't set
+pub const DEFAULT_CONFIRMATIONS: usize = 1;
+
+/// Chain traded on when `CHAIN_ID` isn't set, Base Sepolia
+pub const DEFAULT_CHAIN_ID: u64 = 84532;
+
+/// A chain the client can trade on, with a public RPC node and its USDC and WETH contracts
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SupportedChain {
+    pub chain: Chain,
+    pub name: &'static str,
+    pub rpc_url: &'static str,
+    pub usdc: &'static str,
+    pub weth: &'static str,
+}
+
+impl SupportedChain {
+    pub fn chain_id(&self) -> u64 {
+        self.chain.into()
+    }
+}
+
+/// Chains `CHAIN_ID` may name
+pub const SUPPORTED_CHAINS: [SupportedChain; 4] = [
+    SupportedChain {
+        chain: Chain::BaseSepolia,
+        name: "Base Sepolia",
+        rpc_url: "https://sepolia.base.org",
+        usdc: "0xf175520c52418dfe19c8098071a252da48cd1c19",
+        weth: "0x4200000000000000000000000000000000000006",
+    },
+    SupportedChain {
+        chain: Chain::Base,
+        name: "Base",
+        rpc_url: "https://mainnet.base.org",
+        usdc: "0x833589fcd6edb6e08f4c7c32d4f71b54bda02913",
+        weth: "0x4200000000000000000000000000000000000006",
+    },
+    SupportedChain {
+        chain: Chain::Arbitrum,
+        name: "Arbitrum One",
+        rpc_url: "https://arb1.arbitrum.io/rpc",
+        usdc: "0xaf88d065e77c8cc2239327c5edb3a432268e5831",
+        weth: "0x82af49447d8a07e3bd95bd0d56f35241523fbab1",
+    },
+    SupportedChain {
+        chain: Chain::Mainnet,
+        name: "Ethereum",
+        rpc_url: "https://ethereum-rpc.publicnode.com",
+        usdc: "0xa0b86991c6218b36c1d19d4a2e9eb0ce3606eb48",
+        weth: "0xc02aaa39b223fe8d0a0e5c4f27ead9083c756cc2",
+    },
+];
+
+/// The supported chain with id `chain_id`, a configuration error naming the supported ones otherwise
+pub fn supported_chain(chain_id: u64) -> Result<&'static SupportedChain> {
+    SUPPORTED_CHAINS.iter().find(|supported| supported.chain_id() == chain_id).ok_or_else(|| {
+        let supported: Vec<String> = SUPPORTED_CHAINS
+            .iter()
+            .map(|supported| format!("{} ({})", supported.chain_id(), supported.name))
+            .collect();
+        TradingError::Configuration(format!("CHAIN_ID {} is not supported, use one of {}", chain_id, supported.join(", ")))
+    })
+}
+
+/// Check that the node behind `client` serves `supported`, so nothing is signed for the wrong chain
+pub async fn verify_node_chain<M: Middleware>(client: &M, supported: &SupportedChain) -> Result<()> {
+    let node_chain_id = client.get_chainid().await.map_err(|e| TradingError::Provider(e.to_string()))?;
+    if node_chain_id != U256::from(supported.chain_id()) {
+        return Err(TradingError::Configuration(format!(
+            "RPC_URL serves chain {} but CHAIN_ID is {} ({})",
+            node_chain_id, supported.chain_id(), supported.name
+        )));
+    }
+    Ok(())
+}
+
+/// USDC and WETH contracts the client swaps between on a chain
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChainTokens {
+    pub usdc: String,
+    pub weth: String,
+}
+
+/// The USDC and WETH contracts of every supported chain, by chain id
+pub fn default_chain_tokens() -> HashMap<u64, ChainTokens> {
+    SUPPORTED_CHAINS
+        .iter()
+        .map(|supported| {
+            let tokens = ChainTokens { usdc: supported.usdc.to_string(), weth: supported.weth.to_string() };
+            (supported.chain_id(), tokens)
+        })
+        .collect()
+}
+
+/// Address 1inch uses for the chain's native token, which needs no approval
+pub const NATIVE_TOKEN: &str = "0xEeeeeEeeeEeEeeEeEeEeeEEEeeeeEeeeeeeeEEeE";
+
+/// Whether `token` is the native token rather than an ERC20 contract
+pub fn is_native_token(token: &str) -> bool {
+    token.eq_ignore_ascii_case(NATIVE_TOKEN)
+}
+
+/// The chain's native token, ETH on Base
+pub fn native_token() -> Token {
+    Token {
+        address: NATIVE_TOKEN.to_string(),
+        decimals: 18,
+        symbol: "ETH".to_string(),
+        name: "Ether".to_string(),
+        logo_uri: None,
+    }
+}
+
+/// How much of a token the 1inch router is approved for when its allowance is short
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ApprovalMode {
+    /// Just the amount being swapped, so every swap of the token sends its own approval
+    #[default]
+    Exact,
+    /// The largest possible amount, so later swaps of the token skip the approval
+    Infinite,
+}
+
+impl ApprovalMode {
+    /// The allowance to approve for a swap of `amount`
+    pub fn allowance_for(self, amount: U256) -> U256 {
+        match self {
+            ApprovalMode::Exact => amount,
+            ApprovalMode::Infinite => U256::MAX,
+        }
+    }
+}
+
+impl FromStr for ApprovalMode {
+    type Err = TradingError;
+    
+    fn from_str(s: &str) -> Result<Self> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "exact" => Ok(ApprovalMode::Exact),
+            "infinite" => Ok(ApprovalMode::Infinite),
+            _ => Err(TradingError::Configuration(format!("TRADE_APPROVAL must be exact or infinite, not {}", s))),
+        }
+    }
+}
+
+/// Limits on what a swap may pay for gas, unset limits aren't checked
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct GasPolicy {
+    /// Highest gas price a swap is sent at, in gwei
+    pub max_gas_gwei: Option<f64>,
+    /// Highest gas cost of a swap, in USD
+    pub max_cost_usd: Option<f64>,
+}
+
+impl GasPolicy {
+    /// Limits from `MAX_GAS_GWEI` and `MAX_GAS_COST_USD`, unset or not positive ones off
+    pub fn from_env() -> Self {
+        let limit = |name: &str| env::var(name).ok().and_then(|value| value.parse().ok()).filter(|limit: &f64| *limit > 0.0);
+        Self { max_gas_gwei: limit("MAX_GAS_GWEI"), max_cost_usd: limit("MAX_GAS_COST_USD") }
+    }
+    
+    /// Whether any limit is set, so the gas has to be estimated before swapping
+    pub fn is_limited(&self) -> bool {
+        self.max_gas_gwei.is_some() || self.max_cost_usd.is_some()
+    }
+    
+    /// Fail with `GasPriceTooHigh` or `GasTooExpensive` when `estimate` breaks a limit
+    /// `eth_price_usd` is only asked for when there is a USD limit
+    pub async fn check<F>(&self, estimate: &GasEstimate, eth_price_usd: F) -> Result<()>
+    where
+        F: std::future::Future<Output = Result<f64>>,
+    {
+        if let Some(max_gwei) = self.max_gas_gwei
+            && estimate.gas_price_gwei > max_gwei
+        {
+            return Err(TradingError::GasPriceTooHigh { gas_gwei: estimate.gas_price_gwei, max_gwei });
+        }
+        if let Some(max_usd) = self.max_cost_usd {
+            let estimated_usd = estimate.cost_eth * eth_price_usd.await?;
+            if estimated_usd > max_usd {
+                return Err(TradingError::GasTooExpensive { estimated_usd, max_usd });
+            }
+        }
+        Ok(())
+    }
+}
+
+/// What a swap is expected to pay for gas at a gas price
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct GasEstimate {
+    pub gas_limit: u64,
+    /// Price per unit of gas: the next block's base fee, or the price a prepared swap is sent at
+    pub gas_price_gwei: f64,
+    /// `gas_limit` at the gas price
+    pub cost_eth: f64,
+}
+
+impl GasEstimate {
+    pub fn new(gas_limit: u64, gas_price_wei: U256) -> Result<Self> {
+        Ok(Self {
+            gas_limit,
+            gas_price_gwei: from_units(gas_price_wei, 9)?,
+            cost_eth: from_units(U256::from(gas_limit) * gas_price_wei, 18)?,
+        })
+    }
+}
+
+/// Estimate the gas of a swap using `gas_limit` at the base fee the next block charges
+/// The base fee is the least a swap pays per unit of gas, whatever price it is sent at
+pub async fn estimate_gas<M: Middleware>(client: &M, gas_limit: u64) -> Result<GasEstimate> {
+    let history = client
+        .fee_history(1u64, BlockNumber::Latest, &[])
+        .await
+        .map_err(|e| TradingError::Provider(e.to_string()))?;
+    let base_fee = history
+        .base_fee_per_gas
+        .last()
+        .copied()
+        .ok_or_else(|| TradingError::Provider("fee history has no base fee".to_string()))?;
+    GasEstimate::new(gas_limit, base_fee)
+}
+
+/// What `execute_trade_strategy` did with the swap an aggregator prepared
+#[derive(Debug)]
+pub enum TradeExecution {
+    /// A dry run: the swap was quoted, nothing was sent
+    Quoted(Box<SwapResponse>),
+    /// The swap was signed, sent and mined with the client's confirmations
+    Confirmed {
+        /// Approval of the router sent and mined before the swap, when the allowance was short
+        approval: Option<H256>,
+        receipt: SwapReceipt,
+        /// DEX aggregator that routed the swap
+        aggregator: String,
+    },
+}
+
+/// A mined swap transaction
+#[derive(Debug, Clone, PartialEq)]
+pub struct SwapReceipt {
+    pub tx_hash: H256,
+    pub block_number: Option<u64>,
+    pub gas_used: Option<U256>,
+    /// Wei paid per unit of gas, None from nodes that don't report it
+    pub effective_gas_price: Option<U256>,
+}
+
+impl SwapReceipt {
+    /// What the transaction paid for gas, in ETH
+    pub fn gas_cost(&self) -> Option<f64> {
+        let (used, price) = self.gas_used.zip(self.effective_gas_price)?;
+        from_units(used * price, 18).ok()
+    }
+}
+
+impl TransactionData {
+    /// The legacy transaction 1inch describes, `from` is left to the signer
+    ///
+    /// 1inch sends `value` and `gasPrice` as decimal wei and `data` as hex.
+    pub fn to_request(&self) -> Result<TransactionRequest> {
+        let to = parse_address(&self.to)?;
+        let data = Bytes::from_str(&self.data)
+            .map_err(|e| TradingError::InvalidResponse(format!("swap data is not hex: {}", e)))?;
+        let value = U256::from_dec_str(&self.value)
+            .map_err(|e| TradingError::InvalidResponse(format!("swap value {}: {}", self.value, e)))?;
+        
+        Ok(TransactionRequest::new()
+            .to(to)
+            .data(data)
+            .value(value)
+            .gas(self.gas)
+            .gas_price(self.gas_price_wei()?))
+    }
+    
+    /// The gas the transaction is sent with, at the gas price it is sent at
+    pub fn gas_estimate(&self) -> Result<GasEstimate> {
+        GasEstimate::new(self.gas, self.gas_price_wei()?)
+    }
+    
+    fn gas_price_wei(&self) -> Result<U256> {
+        U256::from_dec_str(&self.gas_price)
+            .map_err(|e| TradingError::InvalidResponse(format!("swap gas price {}: {}", self.gas_price, e)))
+    }
+}
+
+/// CoinGecko id of a token by its symbol, wrapped ETH counting as ETH
+fn token_coin_id(symbol: &str) -> String {
+    coin_id_for_symbol(symbol).map(str::to_string).unwrap_or_else(|| symbol.to_lowercase())
+}
+
+/// The trade history row of a live swap quoted as `quote`, pending in the transaction `outcome`
+/// sent or failed as it says
+///
+/// A swap into a USD stablecoin sells the source token, any other swap buys the destination token
+/// priced in the source one. Its block and gas are filled in once it is mined, see
+/// `TransactionMonitor`. None when the quoted amounts can't be read.
+pub fn swap_trade(
+    quote: &QuoteResponse,
+    outcome: std::result::Result<H256, &TradingError>,
+    executed_at: NaiveDateTime,
+) -> Option<NewTrade> {
+    let from_amount = from_units(U256::from_dec_str(&quote.from_amount).ok()?, quote.from_token.decimals).ok()?;
+    let to_amount = from_units(U256::from_dec_str(&quote.to_amount).ok()?, quote.to_token.decimals).ok()?;
+    if from_amount <= 0.0 || to_amount <= 0.0 {
+        return None;
+    }
+    
+    let is_usd = |token: &Token| USD_QUOTES.contains(&token.symbol.to_uppercase().as_str());
+    let (side, coin, quote_token, amount, price) = if is_usd(&quote.to_token) {
+        (OrderType::Sell, &quote.from_token, &quote.to_token, from_amount, to_amount / from_amount)
+    } else {
+        (OrderType::Buy, &quote.to_token, &quote.from_token, to_amount, from_amount / to_amount)
+    };
+    let (status, tx_hash) = match outcome {
+        Ok(tx_hash) => (TradeStatus::Pending, Some(tx_hash)),
+        // Sent, but the node lost track of it before it was settled
+        Err(TradingError::Broadcast(BroadcastError::Receipt { tx_hash, .. })) => (TradeStatus::Pending, Some(*tx_hash)),
+        Err(TradingError::Broadcast(error)) => (TradeStatus::Failed, error.tx_hash()),
+        Err(_) => (TradeStatus::Failed, None),
+    };
+    
+    Some(NewTrade {
+        tx_hash: tx_hash.map(|hash| format!("{:?}", hash)),
+        from_token: Some(quote.from_token.address.clone()),
+        to_token: Some(quote.to_token.address.clone()),
+        from_amount: Some(from_amount),
+        to_amount: Some(to_amount),
+        price_usd_at_execution: is_usd(quote_token).then_some(price),
+        status,
+        aggregator: Some(quote.aggregator.clone()),
+        ..NewTrade::filled(executed_at, side, &token_coin_id(&coin.symbol), &quote_token.symbol.to_uppercase(), amount, price, 0.0)
+    })
+}
+
+fn parse_address(address: &str) -> Result<Address> {
+    Address::from_str(address).map_err(|e| TradingError::InvalidAddress(format!("{}: {}", address, e)))
+}
+
+/// Wait until a sent transaction has `confirmations` blocks, failing if it was dropped or reverted
+async fn confirm<P: JsonRpcClient>(
+    pending: PendingTransaction<'_, P>,
+    confirmations: usize,
+) -> std::result::Result<SwapReceipt, BroadcastError> {
+    let tx_hash = pending.tx_hash();
+    let receipt = pending
+        .confirmations(confirmations)
+        .await
+        .map_err(|e| BroadcastError::Receipt { tx_hash, reason: e.to_string() })?
+        .ok_or(BroadcastError::Dropped(tx_hash))?;
+    if receipt.status != Some(1.into()) {
+        return Err(BroadcastError::Reverted(tx_hash));
+    }
+    
+    Ok(SwapReceipt {
+        tx_hash,
+        block_number: receipt.block_number.map(|block| block.as_u64()),
+        gas_used: receipt.gas_used,
+        effective_gas_price: receipt.effective_gas_price,
+    })
+}
+
+/// Sign and send a swap through `client` with the next of `nonces`, then wait until it has `confirmations` blocks
+/// A mined swap that reverted is `BroadcastError::Reverted`
+pub async fn broadcast_swap<M: Middleware>(
+    client: &M,
+    nonces: &NonceManager,
+    tx: &TransactionData,
+    confirmations: usize,
+) -> Result<SwapReceipt> {
+    let pending = nonces.send(client, tx.to_request()?).await?;
+    Ok(confirm(pending, confirmations).await?)
+}
+
+/// Sign and send a swap through `client` with the next of `nonces`, without waiting for it to be mined
+pub async fn send_swap<M: Middleware>(client: &M, nonces: &NonceManager, tx: &TransactionData) -> Result<H256> {
+    let pending = nonces.send(client, tx.to_request()?).await?;
+    Ok(pending.tx_hash())
+}
+
+/// Make sure `spender` may move at least `amount` of `token` from the client's account
+///
+/// When the allowance is short, an approval for `mode`'s amount is sent as a legacy transaction with
+/// the next of `nonces` and waited for, and its hash returned. Failures sending or mining it are
+/// `TradingError::Approval`.
+pub async fn ensure_allowance<M: Middleware + 'static>(
+    client: Arc<M>,
+    nonces: &NonceManager,
+    token: Address,
+    spender: Address,
+    amount: U256,
+    mode: ApprovalMode,
+    confirmations: usize,
+) -> Result<Option<H256>> {
+    let owner = client
+        .default_sender()
+        .ok_or_else(|| TradingError::Configuration("no account to approve the swap from".to_string()))?;
+    let contract = IERC20::new(token, client.clone());
+    let allowance = contract.allowance(owner, spender).call().await
+        .map_err(|e| TradingError::Contract(e.to_string()))?;
+    if allowance >= amount {
+        return Ok(None);
+    }
+    
+    let approve = contract.approve(spender, mode.allowance_for(amount)).legacy();
+    let pending = nonces.send(client.as_ref(), approve.tx).await.map_err(TradingError::Approval)?;
+    let receipt = confirm(pending, confirmations).await.map_err(TradingError::Approval)?;
+    Ok(Some(receipt.tx_hash))
+}
+
+/// A raw token amount in whole tokens, e.g. 1500000 with 6 decimals is 1.5
+pub fn from_units(amount: U256, decimals: u32) -> Result<f64> {
+    ethers::utils::format_units(amount, decimals)
+        .map_err(|e| TradingError::InvalidResponse(format!("amount {}: {}", amount, e)))?
+        .parse()
+        .map_err(|e| TradingError::InvalidResponse(format!("amount {}: {}", amount, e)))
+}
+
+/// How much of the ERC20 `token` `owner` holds, in whole tokens of `decimals`
+pub async fn token_balance<M: Middleware + 'static>(client: Arc<M>, token: Address, owner: Address, decimals: u32) -> Result<f64> {
+    let contract = IERC20::new(token, client);
+    let balance = contract.balance_of(owner).call().await
+        .map_err(|e| TradingError::Contract(e.to_string()))?;
+    from_units(balance, decimals)
+}
+
+/// How much of the chain's native coin `owner` holds, in ETH
+pub async fn native_balance<M: Middleware>(client: &M, owner: Address) -> Result<f64> {
+    let balance = client
+        .get_balance(owner, None)
+        .await
+        .map_err(|e| TradingError::Provider(e.to_string()))?;
+    from_units(balance, 18)
+}
+
+/// Highest slippage 1inch prepares a swap with, in percent
+pub const MAX_SLIPPAGE_PCT: f32 = 50.0;
+
+/// Lowest slippage a swap is prepared with, in percent; with less almost any price move reverts it
+pub const MIN_SLIPPAGE_PCT: f32 = 0.05;
+
+/// How far a swap's price may be from CoinGecko's, in percent, unless MAX_PRICE_DEVIATION_PCT says otherwise
+pub const DEFAULT_MAX_PRICE_DEVIATION_PCT: f64 = 3.0;
+
+/// `SlippageTooHigh` or `SlippageTooLow` when `slippage` is outside [min, max], or isn't a number
+pub fn check_slippage(slippage: f32, min: f32, max: f32) -> Result<()> {
+    if !slippage.is_finite() || slippage > max {
+        return Err(TradingError::SlippageTooHigh { slippage, max });
+    }
+    if slippage < min {
+        return Err(TradingError::SlippageTooLow { slippage, min });
+    }
+    Ok(())
+}
+
+/// What a swap is checked against before anything is sent, kept in `Config`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SwapLimits {
+    /// Slippage a swap may accept, in percent, within `MIN_SLIPPAGE_PCT` and `MAX_SLIPPAGE_PCT`
+    pub min_slippage_pct: f32,
+    pub max_slippage_pct: f32,
+    /// How far the price a swap gets may be from CoinGecko's, in percent; zero turns the check off
+    pub max_price_deviation_pct: f64,
+}
+
+impl Default for SwapLimits {
+    fn default() -> Self {
+        Self {
+            min_slippage_pct: MIN_SLIPPAGE_PCT,
+            max_slippage_pct: MAX_SLIPPAGE_PCT,
+            max_price_deviation_pct: DEFAULT_MAX_PRICE_DEVIATION_PCT,
+        }
+    }
+}
+
+impl SwapLimits {
+    /// Refuse a slippage outside the configured range
+    pub fn check_slippage(&self, slippage: f32) -> Result<()> {
+        check_slippage(slippage, self.min_slippage_pct, self.max_slippage_pct)
+    }
+    
+    /// Refuse swapping `from_amount` of `from` for `to_amount` of `to` (in their smallest units) when the
+    /// price it implies for `from`, with `to` at `to_usd`, is too far from `from_usd`
+    pub fn check_price(&self, from: &Token, to: &Token, from_amount: &str, to_amount: &str, from_usd: f64, to_usd: f64) -> Result<()> {
+        if self.max_price_deviation_pct <= 0.0 {
+            return Ok(());
+        }
+        let amount = |amount: &str, token: &Token| {
+            U256::from_dec_str(amount)
+                .ok()
+                .and_then(|amount| from_units(amount, token.decimals).ok())
+                .ok_or_else(|| TradingError::InvalidResponse(format!("unreadable {} amount {}", token.symbol, amount)))
+        };
+        let (sold, bought) = (amount(from_amount, from)?, amount(to_amount, to)?);
+        if sold <= 0.0 || from_usd <= 0.0 {
+            return Ok(());
+        }
+        
+        let implied = bought * to_usd / sold;
+        if ((implied - from_usd) / from_usd).abs() * 100.0 > self.max_price_deviation_pct {
+            return Err(TradingError::SuspiciousQuote { implied, reference: from_usd });
+        }
+        Ok(())
+    }
+}
+
+/// `InsufficientBalance` when `available` of `token` doesn't cover `needed`
+pub fn ensure_balance(token: &str, needed: f64, available: f64) -> Result<()> {
+    if available < needed {
+        return Err(TradingError::InsufficientBalance { token: token.to_string(), needed, available });
+    }
+    Ok(())
+}
+
+/// Default root URL of the 1inch swap API, the chain id is appended per client
+pub const ONE_INCH_BASE_URL: &str = "https://api.1inch.dev/swap/v5.2";
+
+/// How long the 1inch token list is kept before it is fetched again, unless 1INCH_TOKEN_TTL_SECS says otherwise
+pub const DEFAULT_TOKEN_TTL: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// Supported tokens by lowercase address
+pub type TokenMap = HashMap<String, Token>;
+
+/// The 1inch token list, kept for `ttl` so balance lookups don't refetch it for each token's decimals
+pub struct TokenRegistry {
+    ttl: Duration,
+    /// The list and when it was fetched, None until the first lookup
+    tokens: tokio::sync::Mutex<Option<(Instant, Arc<TokenMap>)>>,
+}
+
+impl TokenRegistry {
+    pub fn new(ttl: Duration) -> Self {
+        Self { ttl, tokens: tokio::sync::Mutex::new(None) }
+    }
+    
+    /// The kept list, or the one `fetch` returns when there is none, it expired or `force` is set
+    ///
+    /// The lock is held while fetching, so concurrent callers wait for one request instead of each sending their own.
+    async fn get_or_fetch<F>(&self, force: bool, fetch: F) -> Result<Arc<TokenMap>>
+    where
+        F: Future<Output = Result<TokensResponse>>,
+    {
+        let mut cached = self.tokens.lock().await;
+        if let Some((fetched, tokens)) = cached.as_ref()
+            && !force
+            && fetched.elapsed() < self.ttl
+        {
+            return Ok(tokens.clone());
+        }
+        
+        let tokens: TokenMap = fetch
+            .await?
+            .tokens
+            .into_values()
+            .map(|token| (token.address.to_lowercase(), token))
+            .collect();
+        let tokens = Arc::new(tokens);
+        *cached = Some((Instant::now(), tokens.clone()));
+        Ok(tokens)
+    }
+}
+
 // 1inch API client
 pub struct OneInchClient {
     client: Client,
     base_url: String,
     api_key: Option<String>,
     chain_id: u32,
+    tokens: TokenRegistry,
 }
 
 impl OneInchClient {
     pub fn new(chain_id: u32, api_key: Option<String>) -> Self {
-        // Chain ID 84532 is Base Sepolia
-        let base_url = format!("https://api.1inch.dev/swap/v5.2/{}", chain_id);
+        let base_url = format!("{}/{}", ONE_INCH_BASE_URL, chain_id);
         
         Self {
             client: Client::new(),
             base_url,
             api_key,
             chain_id,
+            tokens: TokenRegistry::new(DEFAULT_TOKEN_TTL),
         }
     }
     
-    /// Fetches all supported tokens on the specified chain
-    pub async fn get_tokens(&self) -> Result<TokensResponse, Box<dyn std::error::Error>> {
-        let url = format!("{}/tokens", self.base_url);
-        
-        let mut request = self.client.get(&url);
+    /// Keep the token list for `ttl` instead of a day
+    pub fn with_token_ttl(mut self, ttl: Duration) -> Self {
+        self.tokens = TokenRegistry::new(ttl);
+        self
+    }
+    
+    /// Send requests to a different root URL, e.g. a proxy or a mock server
+    pub fn with_base_url(mut self, base_url: &str) -> Self {
+        self.base_url = format!("{}/{}", base_url.trim_end_matches('/'), self.chain_id);
+        self
+    }
+    
+    /// Fail requests that take longer than `timeout`
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.client = Client::builder()
+            .timeout(timeout)
+            .build()
+            .unwrap_or_default();
+        self
+    }
+    
+    /// Send a request and decode the body, mapping error statuses
+    async fn send<T: DeserializeOwned>(&self, mut request: RequestBuilder) -> Result<T> {
         if let Some(ref key) = self.api_key {
             request = request.header("Authorization", format!("Bearer {}", key));
         }
         
-        let response = request.send().await?.json::<TokensResponse>().await?;
-        Ok(response)
+        let response = request.send().await?;
+        let status = response.status();
+        if status == StatusCode::TOO_MANY_REQUESTS {
+            return Err(TradingError::RateLimited(crate::http::retry_after(response.headers())));
+        }
+        
+        let body = response.text().await?;
+        if !status.is_success() {
+            let message = serde_json::from_str::<serde_json::Value>(&body)
+                .ok()
+                .and_then(|json| json["description"].as_str().or(json["error"].as_str()).map(str::to_string))
+                .unwrap_or(body);
+            return Err(TradingError::Api { status, message });
+        }
+        
+        serde_json::from_str(&body).map_err(|e| TradingError::InvalidResponse(e.to_string()))
+    }
+    
+    /// Fetches all supported tokens on the specified chain
+    pub async fn get_tokens(&self) -> Result<TokensResponse> {
+        let url = format!("{}/tokens", self.base_url);
+        
+        self.send(self.client.get(&url)).await
+    }
+    
+    /// Supported tokens by lowercase address, fetched once per token TTL
+    pub async fn get_token_map(&self) -> Result<Arc<TokenMap>> {
+        self.tokens.get_or_fetch(false, self.get_tokens()).await
+    }
+    
+    /// Fetch the token list again now, e.g. for a token listed since it was kept
+    pub async fn refresh_tokens(&self) -> Result<Arc<TokenMap>> {
+        self.tokens.get_or_fetch(true, self.get_tokens()).await
+    }
+    
+    /// The supported token at `address`, `TokenNotFound` when 1inch doesn't list it
+    pub async fn get_token(&self, address: &str) -> Result<Token> {
+        self.get_token_map()
+            .await?
+            .get(&address.to_lowercase())
+            .cloned()
+            .ok_or_else(|| TradingError::TokenNotFound(address.to_string()))
+    }
+    
+    /// The supported token with `symbol`, any case, `TokenNotFound` when 1inch doesn't list one
+    /// When several share the symbol the lowest address wins, so the answer doesn't change between lookups.
+    pub async fn find_by_symbol(&self, symbol: &str) -> Result<Token> {
+        self.get_token_map()
+            .await?
+            .values()
+            .filter(|token| token.symbol.eq_ignore_ascii_case(symbol))
+            .min_by(|a, b| a.address.to_lowercase().cmp(&b.address.to_lowercase()))
+            .cloned()
+            .ok_or_else(|| TradingError::TokenNotFound(symbol.to_string()))
+    }
+    
+    /// Address of the 1inch router that swaps need an allowance for
+    pub async fn get_spender(&self) -> Result<String> {
+        let url = format!("{}/approve/spender", self.base_url);
+        
+        let spender: SpenderResponse = self.send(self.client.get(&url)).await?;
+        Ok(spender.address)
     }
     
     /// Gets a price quote without executing a trade
@@ -169,23 +942,18 @@ impl OneInchClient {
         dst: &str,
         amount: &str,
         from: &str
-    ) -> Result<QuoteResponse, Box<dyn std::error::Error>> {
+    ) -> Result<QuoteResponse> {
         let url = format!("{}/quote", self.base_url);
         
-        let mut request = self.client.get(&url)
+        let request = self.client.get(&url)
             .query(&[
                 ("src", src),
                 ("dst", dst),
                 ("amount", amount),
                 ("from", from),
             ]);
-            
-        if let Some(ref key) = self.api_key {
-            request = request.header("Authorization", format!("Bearer {}", key));
-        }
         
-        let response = request.send().await?.json::<QuoteResponse>().await?;
-        Ok(response)
+        self.send(request).await
     }
     
     /// Gets transaction data ready for blockchain submission
@@ -197,201 +965,584 @@ impl OneInchClient {
         from: &str,
         slippage: f32,
         disable_estimate: bool
-    ) -> Result<SwapResponse, Box<dyn std::error::Error>> {
+    ) -> Result<SwapResponse> {
+        check_slippage(slippage, MIN_SLIPPAGE_PCT, MAX_SLIPPAGE_PCT)?;
         let url = format!("{}/swap", self.base_url);
+        let slippage = slippage.to_string();
+        let disable_estimate = disable_estimate.to_string();
         
-        let mut request = self.client.get(&url)
+        let request = self.client.get(&url)
             .query(&[
                 ("src", src),
                 ("dst", dst),
                 ("amount", amount),
                 ("from", from),
-                ("slippage", slippage.to_string()),
-                ("disableEstimate", disable_estimate.to_string()),
+                ("slippage", slippage.as_str()),
+                ("disableEstimate", disable_estimate.as_str()),
             ]);
-            
-        if let Some(ref key) = self.api_key {
-            request = request.header("Authorization", format!("Bearer {}", key));
-        }
         
-        let response = request.send().await?.json::<SwapResponse>().await?;
-        Ok(response)
+        self.send(request).await
+    }
+    
+    /// Chain the client is quoting on
+    pub fn chain_id(&self) -> u32 {
+        self.chain_id
     }
     
     /// Helper function to convert human-readable amounts to blockchain format (wei)
-    pub fn to_wei(amount: f64, decimals: u32) -> String {
-        let multiplier = 10_u64.pow(decimals) as f64;
-        let wei_amount = (amount * multiplier) as u64;
-        wei_amount.to_string()
+    /// Digits past the token's decimals are dropped; negative, non-finite and amounts too large for
+    /// a U256 are `TradingError::InvalidAmount`
+    pub fn to_wei(amount: f64, decimals: u32) -> Result<U256> {
+        let invalid = || TradingError::InvalidAmount(format!("{} with {} decimals", amount, decimals));
+        // U256 tops out near 1.16e77, past that multiplying by the decimals would overflow
+        if !amount.is_finite() || amount < 0.0 || amount * 10_f64.powi(decimals as i32) >= 1e77 {
+            return Err(invalid());
+        }
+        match ethers::utils::parse_units(amount.to_string(), decimals).map_err(|_| invalid())? {
+            ethers::utils::ParseUnits::U256(wei) => Ok(wei),
+            ethers::utils::ParseUnits::I256(_) => Err(invalid()),
+        }
     }
 }
 
-// Main trading client that uses 1inch API
-pub struct TradingClient {
-    wallet: LocalWallet,
-    provider: Arc<Provider<Http>>,
-    client: Arc<SignerMiddleware<Arc<Provider<Http>>, LocalWallet>>,
-    pub one_inch: OneInchClient,
-    usdc_address: String,
-    weth_address: String,
+/// Source of the WETH price in USDC that limit orders are checked against
+#[async_trait]
+pub trait MarketPrice: Send + Sync {
+    async fn weth_price(&self) -> Result<f64>;
+    
+    /// Price of ETH, what gas costs are converted to USD at
+    async fn eth_price(&self) -> Result<f64>;
+    
+    /// USD prices by CoinGecko id, what a swap's price is checked against
+    /// A coin without a price is `PriceError::PriceNotFound`
+    async fn coin_prices(&self, coin_ids: &[&str]) -> Result<HashMap<String, f64>>;
 }
 
-impl TradingClient {
-    pub async fn new() -> Result<Self, Box<dyn std::error::Error>> {
-        dotenv().ok();
-        
-        let rpc_url = env::var("BASE_SEPOLIA_RPC_URL")?;
-        let private_key = env::var("PRIVATE_KEY")?;
-        let api_key = env::var("1INCH_API_KEY").ok();
-        
-        let provider = Provider::<Http>::try_from(rpc_url)?;
-        let provider = Arc::new(provider);
-        
-        let wallet = private_key.parse::<LocalWallet>()?;
-        let wallet = wallet.with_chain_id(Chain::BaseSepolia);
+#[async_trait]
+impl MarketPrice for CoinGeckoClient {
+    async fn weth_price(&self) -> Result<f64> {
+        Ok(self.fetch_coin_price("weth").await?)
+    }
+    
+    async fn eth_price(&self) -> Result<f64> {
+        Ok(self.fetch_coin_price("ethereum").await?)
+    }
+    
+    async fn coin_prices(&self, coin_ids: &[&str]) -> Result<HashMap<String, f64>> {
+        let prices = self.fetch_multiple_coin_prices(coin_ids).await?;
+        if let Some(missing) = coin_ids.iter().find(|coin_id| !prices.contains_key(**coin_id)) {
+            return Err(PriceError::PriceNotFound(missing.to_string()).into());
+        }
+        Ok(prices)
+    }
+}
+
+/// A limit order filled by `check_and_execute_limit_orders`
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExecutedOrder {
+    pub order_id: String,
+    pub order_type: OrderType,
+    pub amount: f64,
+    /// Market price the order was filled at
+    pub fill_price: f64,
+    pub filled_at: NaiveDateTime,
+}
+
+/// What `create_limit_order` answers for a newly stored order
+pub fn describe_created_order(order: &LimitOrder) -> String {
+    let mut created = format!("Created {} limit order for {} tokens at ${}", order.order_type.as_str(), order.amount, order.price);
+    if let Some(expires_at) = order.expires_at {
+        created.push_str(&format!(", good until {} UTC", expires_at.format("%Y-%m-%d %H:%M")));
+    }
+    format!("{} (ID: {})", created, order.id)
+}
+
+/// Whether an open order fills at `market`: buys at or above it, sells at or below it
+fn is_marketable(order: &LimitOrder, market: f64) -> bool {
+    match order.order_type {
+        OrderType::Buy => order.price >= market,
+        OrderType::Sell => order.price <= market,
+    }
+}
+
+/// The provider with the PRIVATE_KEY wallet signing for it
+type SigningClient = SignerMiddleware<Arc<Provider<Http>>, LocalWallet>;
+
+// Main trading client, swapping through 1inch or the aggregators it falls back to
+pub struct TradingClient {
+    /// Wallet the balances are read for and swaps are quoted from
+    address: Address,
+    provider: Arc<Provider<Http>>,
+    /// None for a watch-only client, which reads balances and quotes but can't trade
+    signer: Option<Arc<SigningClient>>,
+    /// 1inch, whose token list gives the tokens' symbols and decimals
+    pub one_inch: Arc<OneInchClient>,
+    /// Quotes and prepares swaps: 1inch, falling back to 0x when ZEROEX_API_KEY is set
+    aggregator: Box<dyn DexAggregator>,
+    /// Chain the wallet signs for and the tokens live on, from CHAIN_ID
+    chain: Chain,
+    usdc_address: String,
+    weth_address: String,
+    /// Other ERC20 tokens the wallet's value counts, from WALLET_TOKENS
+    wallet_tokens: Vec<String>,
+    /// Where limit orders are kept, so they outlive the process
+    pool: Pool<Postgres>,
+    /// Owner of the limit orders this client creates and lists
+    user_id: i32,
+    /// Price limit orders are filled against
+    market: Arc<dyn MarketPrice>,
+    /// Blocks a broadcast swap waits for before it counts as done
+    confirmations: usize,
+    /// How much of a token the router is approved for when a swap needs an allowance
+    approval: ApprovalMode,
+    /// Blocks a sent swap may go unmined before it is marked stuck
+    monitor_blocks: u64,
+    /// First wait between polls for a sent swap's receipt
+    poll_interval: Duration,
+    /// Slippage range and price deviation a swap is checked against
+    limits: SwapLimits,
+    /// Nonces of the wallet, shared with every other client sending from it
+    nonces: Arc<NonceManager>,
+}
+
+impl TradingClient {
+    /// A client trading for `user_id` with the PRIVATE_KEY wallet, keeping its limit orders in the shared database pool
+    pub async fn with_signer(user_id: i32) -> Result<Self> {
+        dotenv().ok();
         
-        // Create a client with the wallet and provider
-        let client = Arc::new(SignerMiddleware::new(provider.clone(), wallet.clone()));
+        let private_key = env::var("PRIVATE_KEY")
+            .map_err(|_| TradingError::Configuration("PRIVATE_KEY not set".to_string()))?;
+        let wallet = private_key.parse::<LocalWallet>()?;
         
-        // Create 1inch client for Base Sepolia (chain ID 84532)
-        let one_inch = OneInchClient::new(84532, api_key);
+        let mut client = Self::connect(user_id, wallet.address()).await?;
+        let wallet = wallet.with_chain_id(client.chain);
+        client.signer = Some(Arc::new(SignerMiddleware::new(client.provider.clone(), wallet)));
+        Ok(client)
+    }
+    
+    /// A watch-only client for the wallet at `address`
+    ///
+    /// Balances, valuation, quotes and the limit order list work without a private key; swaps,
+    /// approvals and limit orders fail with `TradingError::SignerRequired`.
+    pub async fn read_only(user_id: i32, address: &str) -> Result<Self> {
+        dotenv().ok();
+        Self::connect(user_id, parse_address(address)?).await
+    }
+    
+    /// The signing client when PRIVATE_KEY is set, otherwise a watch-only one for `wallet_address`
+    pub async fn for_user(user_id: i32, wallet_address: Option<&str>) -> Result<Self> {
+        dotenv().ok();
+        match wallet_address {
+            Some(address) if env::var("PRIVATE_KEY").is_err() => Self::read_only(user_id, address).await,
+            _ => Self::with_signer(user_id).await,
+        }
+    }
+    
+    /// A client without a signer for `address`, configured from the environment
+    ///
+    /// Fails when `CHAIN_ID` isn't a supported chain, or isn't the chain the RPC node serves,
+    /// rather than when the first transaction is signed for the wrong one.
+    async fn connect(user_id: i32, address: Address) -> Result<Self> {
+        let config = Config::get_instance().map_err(|e| TradingError::Configuration(e.to_string()))?;
+        let supported = supported_chain(config.chain_id)?;
+        let tokens = config.chain_tokens.get(&config.chain_id).ok_or_else(|| {
+            TradingError::Configuration(format!("No USDC and WETH addresses for chain {}", config.chain_id))
+        })?;
+        for (name, token) in [("USDC_ADDRESS", &tokens.usdc), ("WETH_ADDRESS", &tokens.weth)] {
+            parse_address(token).map_err(|_| TradingError::Configuration(format!("{} {} is not an address", name, token)))?;
+        }
+        if config.rpc_url.is_empty() {
+            return Err(TradingError::Configuration("RPC_URL not set".to_string()));
+        }
+        let api_key = env::var("1INCH_API_KEY").ok();
+        let confirmations = env::var("TRADE_CONFIRMATIONS")
+            .ok()
+            .and_then(|value| value.parse().ok())
+            .filter(|confirmations| *confirmations > 0)
+            .unwrap_or(DEFAULT_CONFIRMATIONS);
+        let approval = match env::var("TRADE_APPROVAL") {
+            Ok(value) => value.parse()?,
+            Err(_) => ApprovalMode::default(),
+        };
+        let monitor_blocks = env::var("TRADE_MONITOR_BLOCKS")
+            .ok()
+            .and_then(|value| value.parse().ok())
+            .filter(|blocks| *blocks > 0)
+            .unwrap_or(DEFAULT_TIMEOUT_BLOCKS);
+        let token_ttl = env::var("1INCH_TOKEN_TTL_SECS")
+            .ok()
+            .and_then(|value| value.parse().ok())
+            .map(Duration::from_secs)
+            .unwrap_or(DEFAULT_TOKEN_TTL);
+        let wallet_tokens = env::var("WALLET_TOKENS")
+            .map(|value| value.split(',').map(str::trim).filter(|token| !token.is_empty()).map(str::to_string).collect())
+            .unwrap_or_default();
+        
+        let provider = Provider::<Http>::try_from(config.rpc_url.as_str())
+            .map_err(|e| TradingError::Provider(e.to_string()))?;
+        let provider = Arc::new(provider);
+        verify_node_chain(&provider, supported).await?;
+        
+        // The 1inch and 0x APIs are both addressed per chain
+        let chain_id = supported.chain_id() as u32;
+        let one_inch = Arc::new(OneInchClient::new(chain_id, api_key).with_token_ttl(token_ttl));
+        let aggregator: Box<dyn DexAggregator> = match env::var("ZEROEX_API_KEY") {
+            Ok(key) => Box::new(FallbackAggregator::new(vec![
+                Box::new(one_inch.clone()),
+                Box::new(ZeroExClient::new(chain_id, key)),
+            ])),
+            Err(_) => Box::new(one_inch.clone()),
+        };
         
-        // Token addresses on Base Sepolia
-        // USDC on Base Sepolia
-        let usdc_address = "0xf175520c52418dfe19c8098071a252da48cd1c19".to_string();
-        // WETH on Base Sepolia
-        let weth_address = "0x4200000000000000000000000000000000000006".to_string();
+        let pool = db::get_db_pool().await?.clone();
         
         Ok(Self {
-            wallet,
+            address,
             provider,
-            client,
+            signer: None,
             one_inch,
-            usdc_address,
-            weth_address,
+            aggregator,
+            chain: supported.chain,
+            usdc_address: tokens.usdc.clone(),
+            weth_address: tokens.weth.clone(),
+            wallet_tokens,
+            pool,
+            user_id,
+            market: Arc::new(CoinGeckoClient::from_config()),
+            confirmations,
+            approval,
+            monitor_blocks,
+            poll_interval: DEFAULT_POLL_INTERVAL,
+            limits: config.swap_limits,
+            nonces: NonceManager::shared(address),
         })
     }
     
+    /// Quote and prepare swaps with `aggregator` instead
+    pub fn with_aggregator(mut self, aggregator: Box<dyn DexAggregator>) -> Self {
+        self.aggregator = aggregator;
+        self
+    }
+    
+    /// Check limit orders against another price source
+    pub fn with_market_price(mut self, market: Arc<dyn MarketPrice>) -> Self {
+        self.market = market;
+        self
+    }
+    
+    /// RPC provider used for read-only chain queries
+    pub fn provider(&self) -> &Arc<Provider<Http>> {
+        &self.provider
+    }
+    
+    /// Whether the client only watches its wallet, without a key to trade with
+    pub fn is_read_only(&self) -> bool {
+        self.signer.is_none()
+    }
+    
+    /// The signing middleware, `TradingError::SignerRequired` naming `action` for a watch-only client
+    fn signer(&self, action: &str) -> Result<&Arc<SigningClient>> {
+        self.signer.as_ref().ok_or_else(|| TradingError::SignerRequired(action.to_string()))
+    }
+    
+    /// Current base fee, suggested priority fees and what a swap and a transfer cost
+    pub async fn get_gas_snapshot(&self) -> Result<GasSnapshot> {
+        let oracle = GasOracle::new(self.provider.clone(), CoinGeckoClient::from_config());
+        Ok(oracle.snapshot(Utc::now()).await?)
+    }
+    
+    /// The wallet as full 0x-prefixed hex, `Address`'s Display shortens it
     pub async fn get_wallet_address(&self) -> String {
-        self.wallet.address().to_string()
+        format!("{:?}", self.address)
+    }
+    
+    /// The token at `address` from 1inch's token list, the native token when 1inch doesn't list it
+    async fn token(&self, address: &str) -> Result<Token> {
+        match self.one_inch.get_token(address).await {
+            Err(TradingError::TokenNotFound(_)) if is_native_token(address) => Ok(native_token()),
+            found => found,
+        }
+    }
+    
+    /// Balance of the ERC20 token at `token_address` in whole tokens, with the token's details from 1inch
+    pub async fn get_token_balance(&self, token_address: &str) -> Result<(f64, Token)> {
+        let token = self.one_inch.get_token(token_address).await?;
+        let balance = token_balance(
+            self.provider.clone(),
+            parse_address(token_address)?,
+            self.address,
+            token.decimals,
+        )
+        .await?;
+        Ok((balance, token))
+    }
+    
+    /// ETH balance of the wallet
+    pub async fn get_native_balance(&self) -> Result<f64> {
+        native_balance(self.provider.as_ref(), self.address).await
     }
     
     /// Get USDC balance
-    pub async fn get_usdc_balance(&self) -> Result<f64, Box<dyn std::error::Error>> {
-        // Get tokens to find USDC decimals
-        let tokens = self.one_inch.get_tokens().await?;
-        let usdc_token = tokens.tokens.values().find(|t| t.address.to_lowercase() == self.usdc_address.to_lowercase())
-            .ok_or("USDC token not found")?;
-        
-        // Create ERC20 contract instance
-        let address = Address::from_str(&self.usdc_address)?;
-        let contract = IERC20::new(address, Arc::clone(&self.client));
-        let wallet_address = self.wallet.address();
-        
-        // Get balance
-        let balance = contract.balance_of(wallet_address).call().await?;
-        
-        // Convert to human-readable format
-        let balance_float = balance.as_u128() as f64 / 10_f64.powi(usdc_token.decimals as i32);
-        
-        Ok(balance_float)
+    pub async fn get_usdc_balance(&self) -> Result<f64> {
+        Ok(self.get_token_balance(&self.usdc_address).await?.0)
     }
     
     /// Get WETH balance
-    pub async fn get_weth_balance(&self) -> Result<f64, Box<dyn std::error::Error>> {
-        // Get tokens to find WETH decimals
-        let tokens = self.one_inch.get_tokens().await?;
-        let weth_token = tokens.tokens.values().find(|t| t.address.to_lowercase() == self.weth_address.to_lowercase())
-            .ok_or("WETH token not found")?;
-        
-        // Create ERC20 contract instance
-        let address = Address::from_str(&self.weth_address)?;
-        let contract = IERC20::new(address, Arc::clone(&self.client));
-        let wallet_address = self.wallet.address();
-        
-        // Get balance
-        let balance = contract.balance_of(wallet_address).call().await?;
+    pub async fn get_weth_balance(&self) -> Result<f64> {
+        Ok(self.get_token_balance(&self.weth_address).await?.0)
+    }
+    
+    /// Non-zero balances of ETH, USDC, WETH and the WALLET_TOKENS, each token once
+    /// A WALLET_TOKENS address 1inch doesn't list is skipped with a warning, its decimals are unknown
+    pub async fn get_token_balances(&self) -> Result<Vec<TokenBalance>> {
+        let mut balances = Vec::new();
+        let eth = self.get_native_balance().await?;
+        if eth > 0.0 {
+            balances.push(TokenBalance { symbol: "ETH".to_string(), amount: eth });
+        }
         
-        // Convert to human-readable format
-        let balance_float = balance.as_u128() as f64 / 10_f64.powi(weth_token.decimals as i32);
+        let mut addresses = vec![self.usdc_address.to_lowercase(), self.weth_address.to_lowercase()];
+        for token in &self.wallet_tokens {
+            if !addresses.contains(&token.to_lowercase()) {
+                addresses.push(token.to_lowercase());
+            }
+        }
+        for (index, address) in addresses.iter().enumerate() {
+            let (amount, token) = match self.get_token_balance(address).await {
+                Ok(balance) => balance,
+                // USDC and WETH come first and are always needed
+                Err(TradingError::TokenNotFound(_)) if index >= 2 => {
+                    warn!("WALLET_TOKENS address {} isn't in 1inch's token list, leaving it out", address);
+                    continue;
+                },
+                Err(e) => return Err(e),
+            };
+            if amount > 0.0 {
+                balances.push(TokenBalance { symbol: token.symbol, amount });
+            }
+        }
+        Ok(balances)
+    }
+    
+    /// The wallet's balances valued at CoinGecko prices, tokens without a price listed without a value
+    pub async fn get_portfolio_value(&self) -> Result<PortfolioValue> {
+        let balances = self.get_token_balances().await?;
+        let mut coin_ids: Vec<&str> = balances.iter().filter_map(|balance| coin_id_for_symbol(&balance.symbol)).collect();
+        coin_ids.sort_unstable();
+        coin_ids.dedup();
         
-        Ok(balance_float)
+        let prices = if coin_ids.is_empty() {
+            HashMap::new()
+        } else {
+            crate::price_fetcher::fetch_multiple_coin_prices(&coin_ids).await?
+        };
+        Ok(value_portfolio(&balances, &prices))
     }
     
-    /// Create a limit order
+    /// Create a limit order, which a watch-only client can't fill, and describe it
+    /// An order given `expires_at` is good until then and expires at the first check past it
     pub async fn create_limit_order(
         &self,
         order_type: OrderType,
         amount: f64,
-        price: f64
-    ) -> Result<String, Box<dyn std::error::Error>> {
+        price: f64,
+        expires_at: Option<DateTime<Utc>>
+    ) -> Result<String> {
+        let order = self.place_limit_order(order_type, amount, price, expires_at).await?;
+        Ok(describe_created_order(&order))
+    }
+    
+    /// Create a limit order like `create_limit_order`, returning the stored order
+    pub async fn place_limit_order(
+        &self,
+        order_type: OrderType,
+        amount: f64,
+        price: f64,
+        expires_at: Option<DateTime<Utc>>
+    ) -> Result<LimitOrder> {
+        self.signer("placing a limit order")?;
         let id = Uuid::new_v4().to_string();
         let token_address = match order_type {
-            OrderType::Buy => self.usdc_address.clone(),
-            OrderType::Sell => self.weth_address.clone(),
-        };
-        
-        let order = LimitOrder {
-            id: id.clone(),
-            order_type,
-            token_address,
-            amount,
-            price,
-            created_at: Utc::now(),
-            status: OrderStatus::Open,
+            OrderType::Buy => &self.usdc_address,
+            OrderType::Sell => &self.weth_address,
         };
         
         // Store the order
-        let mut orders = LIMIT_ORDERS.lock().await;
-        orders.push(order);
-        
-        let order_type_str = match order_type {
-            OrderType::Buy => "buy",
-            OrderType::Sell => "sell",
-        };
-        
-        Ok(format!("Created {} limit order for {} tokens at ${} (ID: {})", 
-                  order_type_str, amount, price, id))
+        let expires_at = expires_at.map(|at| at.naive_utc());
+        Ok(db::create_limit_order(&self.pool, self.user_id, &id, order_type, token_address, amount, price, expires_at).await?)
     }
     
-    /// Get all open limit orders
-    pub async fn get_open_limit_orders(&self) -> Result<Vec<LimitOrder>, Box<dyn std::error::Error>> {
-        let orders = LIMIT_ORDERS.lock().await;
-        let open_orders = orders.iter()
-            .filter(|order| order.status == OrderStatus::Open)
-            .cloned()
-            .collect();
-        
-        Ok(open_orders)
+    /// Get all open limit orders, including those created before a restart
+    /// Orders past their expiry are left out, whether or not a check has expired them yet
+    pub async fn get_open_limit_orders(&self) -> Result<Vec<LimitOrder>> {
+        Ok(db::get_open_limit_orders(&self.pool, self.user_id, Utc::now().naive_utc()).await?)
     }
     
-    /// Cancel a limit order
-    pub async fn cancel_limit_order(&self, order_id: &str) -> Result<String, Box<dyn std::error::Error>> {
-        let mut orders = LIMIT_ORDERS.lock().await;
+    /// Cancel an open limit order
+    /// Expired orders fail with `OrderExpired`, filled and cancelled ones with `OrderNotOpen` and
+    /// unknown ones with `OrderNotFound`
+    pub async fn cancel_limit_order(&self, order_id: &str) -> Result<String> {
+        // An order past its expiry is expired even when no check moved it there yet
+        db::expire_limit_orders(&self.pool, self.user_id, Utc::now().naive_utc()).await?;
+        if db::cancel_limit_order(&self.pool, self.user_id, order_id).await?.is_some() {
+            return Ok(format!("Cancelled order {}", order_id));
+        }
+        match db::get_limit_order(&self.pool, self.user_id, order_id).await? {
+            Some(order) if order.status == OrderStatus::Expired => Err(TradingError::OrderExpired(order.id)),
+            Some(order) => Err(TradingError::OrderNotOpen { id: order.id, status: order.status }),
+            None => Err(TradingError::OrderNotFound(order_id.to_string())),
+        }
+    }
+    
+    /// Fill the open limit orders the current WETH price reaches
+    ///
+    /// Orders past their expiry are moved to expired first and never fill. Each order moves
+    /// from open to filled in one conditional update, so concurrent checks never execute the
+    /// same order twice. The price isn't fetched when no order is open.
+    pub async fn check_and_execute_limit_orders(&self) -> Result<Vec<ExecutedOrder>> {
+        self.signer("filling limit orders")?;
+        let now = Utc::now().naive_utc();
+        db::expire_limit_orders(&self.pool, self.user_id, now).await?;
+        let open = db::get_open_limit_orders(&self.pool, self.user_id, now).await?;
+        if open.is_empty() {
+            return Ok(Vec::new());
+        }
         
-        if let Some(order) = orders.iter_mut().find(|o| o.id == order_id && o.status == OrderStatus::Open) {
-            order.status = OrderStatus::Cancelled;
-            Ok(format!("Cancelled order {}", order_id))
-        } else {
-            Err("Order not found or not open".into())
+        let market = self.market.weth_price().await?;
+        let mut executed = Vec::new();
+        for order in open.iter().filter(|order| is_marketable(order, market)) {
+            if let Some(filled) = db::update_limit_order_status(&self.pool, self.user_id, &order.id, OrderStatus::Filled).await? {
+                let filled = ExecutedOrder {
+                    order_id: filled.id,
+                    order_type: filled.order_type,
+                    amount: filled.amount,
+                    fill_price: market,
+                    filled_at: filled.updated_at,
+                };
+                // The order is already filled, a history row that can't be written must not undo that
+                if let Err(e) = db::create_trade(&self.pool, self.user_id, &self.fill_trade(&filled)).await {
+                    warn!("Could not record the fill of limit order {}: {}", filled.order_id, e);
+                }
+                executed.push(filled);
+            }
         }
+        Ok(executed)
     }
     
-    /// Check and execute limit orders based on current price
-    pub async fn check_and_execute_limit_orders(&self) -> Result<String, Box<dyn std::error::Error>> {
-        // In a real implementation, this would check current market prices and execute orders
-        // For this example, we'll just return a placeholder message
-        Ok("Checked limit orders. None ready to execute.".to_string())
+    /// The trade history row of a filled limit order, which moves `amount` WETH against USDC
+    fn fill_trade(&self, order: &ExecutedOrder) -> NewTrade {
+        let (from_token, to_token, from_amount, to_amount) = match order.order_type {
+            OrderType::Buy => (&self.usdc_address, &self.weth_address, order.amount * order.fill_price, order.amount),
+            OrderType::Sell => (&self.weth_address, &self.usdc_address, order.amount, order.amount * order.fill_price),
+        };
+        NewTrade {
+            from_token: Some(from_token.clone()),
+            to_token: Some(to_token.clone()),
+            from_amount: Some(from_amount),
+            to_amount: Some(to_amount),
+            price_usd_at_execution: Some(order.fill_price),
+            ..NewTrade::filled(order.filled_at, order.order_type, "ethereum", "USDC", order.amount, order.fill_price, 0.0)
+        }
     }
     
     /// Analyze trading data and suggest strategies
-    pub async fn analyze_and_suggest_strategy(&self) -> Result<String, Box<dyn std::error::Error>> {
+    pub async fn analyze_and_suggest_strategy(&self) -> Result<String> {
         // In a real implementation, this would analyze market data and suggest strategies
         // For this example, we'll just return a placeholder message
         Ok("Market analysis: Consider setting limit orders at key support/resistance levels.".to_string())
     }
     
-    /// Execute a trade using 1inch API
+    /// Wait for `confirmations` blocks after a swap is mined
+    pub fn with_confirmations(mut self, confirmations: usize) -> Self {
+        self.confirmations = confirmations.max(1);
+        self
+    }
+    
+    /// Approve the router exactly or without limit when a swap needs an allowance
+    pub fn with_approval_mode(mut self, approval: ApprovalMode) -> Self {
+        self.approval = approval;
+        self
+    }
+    
+    /// Mark a sent swap stuck after `timeout_blocks` unmined blocks, polling for it every
+    /// `poll_interval` at first
+    pub fn with_monitor(mut self, timeout_blocks: u64, poll_interval: Duration) -> Self {
+        self.monitor_blocks = timeout_blocks;
+        self.poll_interval = poll_interval;
+        self
+    }
+    
+    /// Check swaps against `limits` instead of the configured ones
+    pub fn with_swap_limits(mut self, limits: SwapLimits) -> Self {
+        self.limits = limits;
+        self
+    }
+    
+    /// Follows the client's sent swaps until they are mined, updating their trade history rows
+    pub fn transaction_monitor(&self) -> TransactionMonitor {
+        TransactionMonitor::new(self.provider.clone(), self.pool.clone(), self.user_id)
+            .with_confirmations(self.confirmations)
+            .with_timeout_blocks(self.monitor_blocks)
+            .with_poll_interval(self.poll_interval)
+    }
+    
+    /// Where the swap sent in `tx_hash` stands right now, its trade row updated when it is settled
+    pub async fn check_transaction(&self, tx_hash: H256) -> Result<TransactionStatus> {
+        self.transaction_monitor().check(tx_hash).await
+    }
+    
+    /// Follow the swap sent in `tx_hash` until it is confirmed, fails or is stuck, updating its trade row
+    /// Live swaps are waited for already; this resumes one left pending, e.g. after a restart.
+    pub async fn monitor_transaction(&self, tx_hash: H256) -> Result<TransactionStatus> {
+        self.transaction_monitor().watch(tx_hash).await
+    }
+    
+    /// Make sure `spender` may move `amount` (in the token's smallest unit) of `token` from the wallet
+    /// Returns the hash of the approval when one had to be sent
+    pub async fn ensure_allowance(&self, token: &str, spender: &str, amount: U256) -> Result<Option<H256>> {
+        ensure_allowance(
+            self.signer("approving a token")?.clone(),
+            &self.nonces,
+            parse_address(token)?,
+            parse_address(spender)?,
+            amount,
+            self.approval,
+            self.confirmations,
+        )
+        .await
+    }
+    
+    /// Quote swapping `amount_in_tokens` of `from_token` for `to_token` from the client's wallet, without trading
+    pub async fn get_quote(
+        &self,
+        from_token: &str,
+        to_token: &str,
+        amount_in_tokens: f64,
+        decimals: u32,
+    ) -> Result<QuoteResponse> {
+        let amount = OneInchClient::to_wei(amount_in_tokens, decimals)?.to_string();
+        let (src, dst) = (self.token(from_token).await?, self.token(to_token).await?);
+        self.aggregator.get_quote(&src, &dst, &amount, &self.get_wallet_address().await).await
+    }
+    
+    /// Execute a trade through the client's DEX aggregator
+    /// A dry run only asks for the swap; otherwise the swap is quoted and the gas the aggregator
+    /// estimates is checked against `gas_policy` at the next base fee, an ERC20 source token is
+    /// approved for the router if needed, then the swap's own gas and gas price are checked again
+    /// and it is signed and sent. The swap is prepared by the aggregator that
+    /// quoted it, whose router the approval is for.
+    /// A slippage outside the configured range is `TradingError::SlippageTooHigh` or
+    /// `TradingError::SlippageTooLow`, dry run or not.
+    /// Gas over a limit is `TradingError::GasTooExpensive` or `TradingError::GasPriceTooHigh`, before
+    /// the swap is sent. The price the quote and then the prepared swap give the token sold is checked
+    /// against CoinGecko's, too far from it is `TradingError::SuspiciousQuote`. Failures sending the
+    /// swap are `TradingError::Broadcast`, failed approvals `TradingError::Approval`.
+    /// A live swap is waited for until it has the client's confirmations; one that reverted is
+    /// `BroadcastError::Reverted`. Every live swap past the gas check is recorded in the trade history,
+    /// confirmed or failed; one the node loses track of is left pending and followed in the background
+    /// by `monitor_transaction`.
+    /// A watch-only client can dry run, a live swap is `TradingError::SignerRequired`.
+    #[allow(clippy::too_many_arguments)]
     pub async fn execute_trade_strategy(
         &self,
         from_token: &str,
@@ -399,26 +1550,674 @@ impl TradingClient {
         amount_in_tokens: f64,
         decimals: u32,
         max_slippage: f32,
-    ) -> Result<SwapResponse, Box<dyn std::error::Error>> {
+        gas_policy: &GasPolicy,
+        dry_run: bool,
+    ) -> Result<TradeExecution> {
+        self.limits.check_slippage(max_slippage)?;
+        
         // Convert amount to wei format
-        let amount = OneInchClient::to_wei(amount_in_tokens, decimals);
+        let amount = OneInchClient::to_wei(amount_in_tokens, decimals)?.to_string();
         
         // Get wallet address
-        let wallet_address = self.wallet.address().to_string();
-        
-        // Get swap data
-        let swap = self.one_inch.get_swap(
-            from_token,
-            to_token,
-            &amount,
-            &wallet_address,
-            max_slippage,
-            false
-        ).await?;
-        
-        // In a real implementation, we would now sign and submit this transaction
-        // For this example, we'll just return the swap response
-        
-        Ok(swap)
+        let wallet_address = self.get_wallet_address().await;
+        
+        if dry_run {
+            let (src, dst) = (self.token(from_token).await?, self.token(to_token).await?);
+            let swap = self.aggregator.get_swap_calldata(&src, &dst, &amount, &wallet_address, max_slippage).await?;
+            return Ok(TradeExecution::Quoted(Box::new(swap)));
+        }
+        self.signer("swapping")?;
+        
+        // A swap the wallet can't cover would only fail on chain after paying for gas
+        let (available, symbol) = if is_native_token(from_token) {
+            (self.get_native_balance().await?, "ETH".to_string())
+        } else {
+            let (balance, token) = self.get_token_balance(from_token).await?;
+            (balance, token.symbol)
+        };
+        ensure_balance(&symbol, amount_in_tokens, available)?;
+        
+        let (src, dst) = (self.token(from_token).await?, self.token(to_token).await?);
+        let quote = self.aggregator.get_quote(&src, &dst, &amount, &wallet_address).await?;
+        // The base fee is a floor for the price the swap is sent at, so a swap over a limit already
+        // is refused before the router is approved
+        if gas_policy.is_limited() {
+            let estimate = estimate_gas(self.provider.as_ref(), quote.estimated_gas).await?;
+            gas_policy.check(&estimate, self.market.eth_price()).await?;
+        }
+        let reference = self.reference_prices(&quote).await?;
+        if let Some((from_usd, to_usd)) = reference {
+            self.limits.check_price(&quote.from_token, &quote.to_token, &quote.from_amount, &quote.to_amount, from_usd, to_usd)?;
+        }
+        
+        let swapped = self.approve_and_swap(&quote, &amount, &wallet_address, max_slippage, gas_policy, reference).await;
+        self.record_swap(&quote, swapped.as_ref().map(|(_, receipt)| receipt.tx_hash)).await;
+        
+        // The row is written pending, then settled with the gas the swap paid
+        let monitor = self.transaction_monitor();
+        match &swapped {
+            Ok((_, receipt)) => monitor.record(receipt.tx_hash, &TransactionStatus::Confirmed(receipt.clone())).await,
+            Err(TradingError::Broadcast(BroadcastError::Reverted(tx_hash))) => {
+                if let Err(e) = monitor.check(*tx_hash).await {
+                    warn!("Could not record the gas of reverted swap {:?}: {}", tx_hash, e);
+                }
+            },
+            Err(TradingError::Broadcast(BroadcastError::Receipt { tx_hash, .. })) => {
+                monitor.spawn(*tx_hash);
+            },
+            Err(_) => {},
+        }
+        
+        let (approval, receipt) = swapped?;
+        info!("Swap {:?} of {} {} was routed by {}", receipt.tx_hash, amount_in_tokens, quote.from_token.symbol, quote.aggregator);
+        Ok(TradeExecution::Confirmed { approval, receipt, aggregator: quote.aggregator })
+    }
+    
+    /// USD prices of the tokens `quote` swaps, None when the price check is off
+    async fn reference_prices(&self, quote: &QuoteResponse) -> Result<Option<(f64, f64)>> {
+        if self.limits.max_price_deviation_pct <= 0.0 {
+            return Ok(None);
+        }
+        let (from, to) = (token_coin_id(&quote.from_token.symbol), token_coin_id(&quote.to_token.symbol));
+        let prices = self.market.coin_prices(&[from.as_str(), to.as_str()]).await?;
+        let price = |coin_id: &String| prices.get(coin_id).copied().ok_or_else(|| PriceError::PriceNotFound(coin_id.clone()));
+        Ok(Some((price(&from)?, price(&to)?)))
+    }
+    
+    /// Approve the router of the aggregator that gave `quote` if needed, then have it prepare the swap
+    /// and send it, once its price is checked against the `reference` USD prices of both tokens and its
+    /// gas and gas price against `gas_policy`, and wait for its confirmations
+    /// Returns the approval's hash and the swap's receipt
+    async fn approve_and_swap(
+        &self,
+        quote: &QuoteResponse,
+        amount: &str,
+        wallet_address: &str,
+        max_slippage: f32,
+        gas_policy: &GasPolicy,
+        reference: Option<(f64, f64)>,
+    ) -> Result<(Option<H256>, SwapReceipt)> {
+        let aggregator = self.aggregator.by_name(&quote.aggregator).ok_or_else(|| {
+            TradingError::Configuration(format!("the swap was quoted by {}, which isn't configured", quote.aggregator))
+        })?;
+        let (src, dst) = (&quote.from_token, &quote.to_token);
+        
+        // 1inch checks the router's allowance when it prepares the swap, so the approval goes first
+        let approval = if is_native_token(&src.address) {
+            None
+        } else {
+            let spender = aggregator.get_spender().await?;
+            let needed = U256::from_dec_str(amount)
+                .map_err(|e| TradingError::Configuration(format!("swap amount {}: {}", amount, e)))?;
+            self.ensure_allowance(&src.address, &spender, needed).await?
+        };
+        
+        let swap = aggregator.get_swap_calldata(src, dst, amount, wallet_address, max_slippage).await?;
+        if let Some((from_usd, to_usd)) = reference {
+            self.limits.check_price(src, dst, &swap.from_amount, &swap.to_amount, from_usd, to_usd)?;
+        }
+        if gas_policy.is_limited() {
+            gas_policy.check(&swap.tx.gas_estimate()?, self.market.eth_price()).await?;
+        }
+        let receipt = broadcast_swap(self.signer("swapping")?.as_ref(), &self.nonces, &swap.tx, self.confirmations).await?;
+        Ok((approval, receipt))
+    }
+    
+    /// Write a live swap to the trade history; a row that can't be written doesn't change the swap's result
+    async fn record_swap(&self, quote: &QuoteResponse, outcome: std::result::Result<H256, &TradingError>) {
+        let Some(trade) = swap_trade(quote, outcome, Utc::now().naive_utc()) else {
+            warn!("Could not record a swap of {} {}: unreadable quote amounts", quote.from_amount, quote.from_token.symbol);
+            return;
+        };
+        if let Err(e) = db::create_trade(&self.pool, self.user_id, &trade).await {
+            warn!("Could not record a swap of {} {}: {}", quote.from_amount, quote.from_token.symbol, e);
+        }
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod testing {
+    use super::*;
+
+    /// Market whose every price lookup fails, as when offline
+    pub struct NoPrice;
+
+    #[async_trait]
+    impl MarketPrice for NoPrice {
+        async fn weth_price(&self) -> Result<f64> {
+            Err(TradingError::Price(PriceError::Offline))
+        }
+
+        async fn eth_price(&self) -> Result<f64> {
+            Err(TradingError::Price(PriceError::Offline))
+        }
+
+        async fn coin_prices(&self, _coin_ids: &[&str]) -> Result<HashMap<String, f64>> {
+            Err(TradingError::Price(PriceError::Offline))
+        }
+    }
+
+    /// Client with a known test key, for orders that need a signer
+    pub fn client(pool: &Pool<Postgres>, user_id: i32) -> TradingClient {
+        let wallet: LocalWallet = "0x4c0883a69102937d6231471b5dbb6204fe5129617082792ae468d01a3f362318".parse().unwrap();
+        let provider = Arc::new(Provider::<Http>::try_from("http://127.0.0.1:8545").unwrap());
+        TradingClient {
+            signer: Some(Arc::new(SignerMiddleware::new(provider.clone(), wallet.clone()))),
+            ..watch_only(pool, user_id, wallet.address())
+        }
+    }
+
+    /// Client that only watches `address`, with no signer
+    pub fn watch_only(pool: &Pool<Postgres>, user_id: i32, address: Address) -> TradingClient {
+        TradingClient {
+            address,
+            provider: Arc::new(Provider::<Http>::try_from("http://127.0.0.1:8545").unwrap()),
+            signer: None,
+            one_inch: Arc::new(OneInchClient::new(84532, None)),
+            aggregator: Box::new(OneInchClient::new(84532, None)),
+            chain: Chain::BaseSepolia,
+            usdc_address: "0xusdc".to_string(),
+            weth_address: "0xweth".to_string(),
+            wallet_tokens: Vec::new(),
+            pool: pool.clone(),
+            user_id,
+            market: Arc::new(NoPrice),
+            confirmations: DEFAULT_CONFIRMATIONS,
+            approval: ApprovalMode::Exact,
+            monitor_blocks: DEFAULT_TIMEOUT_BLOCKS,
+            poll_interval: DEFAULT_POLL_INTERVAL,
+            limits: SwapLimits::default(),
+            nonces: Arc::new(NonceManager::new(address)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::testing::{NoPrice, client, watch_only};
+    use crate::db::testing::test_pool;
+    use chrono::TimeZone;
+
+    #[test]
+    fn test_invalid_private_key_is_a_wallet_error() {
+        fn parse(key: &str) -> Result<LocalWallet> {
+            Ok(key.parse::<LocalWallet>()?)
+        }
+
+        assert!(matches!(parse("not-a-key"), Err(TradingError::Wallet(_))));
+    }
+
+    struct FixedPrice(f64);
+
+    #[async_trait]
+    impl MarketPrice for FixedPrice {
+        async fn weth_price(&self) -> Result<f64> {
+            Ok(self.0)
+        }
+
+        async fn eth_price(&self) -> Result<f64> {
+            Ok(self.0)
+        }
+
+        async fn coin_prices(&self, coin_ids: &[&str]) -> Result<HashMap<String, f64>> {
+            Ok(coin_ids.iter().map(|coin_id| (coin_id.to_string(), self.0)).collect())
+        }
+    }
+
+    fn swap_tx(to: &str, data: &str, value: &str) -> TransactionData {
+        TransactionData {
+            from: "0x1111111111111111111111111111111111111111".to_string(),
+            to: to.to_string(),
+            data: data.to_string(),
+            value: value.to_string(),
+            gas_price: "1500000000".to_string(),
+            gas: 210000,
+        }
+    }
+
+    #[test]
+    fn test_swap_transaction_request() {
+        let request = swap_tx("0x1111111254eeb25477b68fb85ed929f73a960582", "0x12aa3caf", "1000000000000000000")
+            .to_request()
+            .unwrap();
+        assert_eq!(request.to, Some(NameOrAddress::Address("0x1111111254eeb25477b68fb85ed929f73a960582".parse().unwrap())));
+        assert_eq!(request.data, Some(Bytes::from(vec![0x12, 0xaa, 0x3c, 0xaf])));
+        assert_eq!(request.value, Some(U256::exp10(18)));
+        assert_eq!((request.gas, request.gas_price), (Some(210000.into()), Some(1_500_000_000u64.into())));
+        // The signer fills in its own address
+        assert_eq!(request.from, None);
+
+        let to = "0x1111111254eeb25477b68fb85ed929f73a960582";
+        assert!(matches!(swap_tx("0xnope", "0x", "0").to_request(), Err(TradingError::InvalidAddress(_))));
+        assert!(matches!(swap_tx(to, "0xzz", "0").to_request(), Err(TradingError::InvalidResponse(_))));
+        assert!(matches!(swap_tx(to, "0x", "0x10").to_request(), Err(TradingError::InvalidResponse(_))));
+    }
+
+    #[test]
+    fn test_approval_mode() {
+        assert_eq!("Infinite".parse::<ApprovalMode>().unwrap(), ApprovalMode::Infinite);
+        assert_eq!(" exact ".parse::<ApprovalMode>().unwrap(), ApprovalMode::Exact);
+        assert!(matches!("max".parse::<ApprovalMode>(), Err(TradingError::Configuration(_))));
+
+        let amount = U256::from(100_000_000u64);
+        assert_eq!(ApprovalMode::Exact.allowance_for(amount), amount);
+        assert_eq!(ApprovalMode::Infinite.allowance_for(amount), U256::MAX);
+    }
+
+    #[test]
+    fn test_native_token_needs_no_approval() {
+        assert!(is_native_token("0xeeeeeeeeeeeeeeeeeeeeeeeeeeeeeeeeeeeeeeee"));
+        assert!(is_native_token(NATIVE_TOKEN));
+        assert!(!is_native_token("0x4200000000000000000000000000000000000006"));
+    }
+
+    #[test]
+    fn test_gas_estimate_in_gwei_and_eth() {
+        // 185,000 gas at a 12.5 gwei base fee
+        let estimate = GasEstimate::new(185_000, U256::from(12_500_000_000u64)).unwrap();
+        assert_eq!(estimate.gas_price_gwei, 12.5);
+        assert!((estimate.cost_eth - 0.0023125).abs() < 1e-12);
+
+        // Large enough that the product doesn't fit in a u64 of wei
+        let estimate = GasEstimate::new(30_000_000, U256::from(900_000_000_000u64)).unwrap();
+        assert!((estimate.cost_eth - 27.0).abs() < 1e-9);
+    }
+
+    #[tokio::test]
+    async fn test_gas_policy_limits() {
+        let estimate = GasEstimate::new(185_000, U256::from(12_500_000_000u64)).unwrap();
+        let price = |usd: f64| async move { Ok(usd) };
+
+        assert!(GasPolicy::default().check(&estimate, NoPrice.eth_price()).await.is_ok());
+        let loose = GasPolicy { max_gas_gwei: Some(20.0), max_cost_usd: Some(10.0) };
+        assert!(loose.check(&estimate, price(4000.0)).await.is_ok());
+
+        // $9.25 at $4000, over a $5 limit
+        let tight = GasPolicy { max_gas_gwei: None, max_cost_usd: Some(5.0) };
+        let error = tight.check(&estimate, price(4000.0)).await.unwrap_err();
+        assert!(matches!(error, TradingError::GasTooExpensive { estimated_usd, max_usd } if (estimated_usd - 9.25).abs() < 1e-9 && max_usd == 5.0));
+        assert_eq!(error.to_string(), "Gas is currently $9.25, above your $5.00 limit");
+
+        // The gas price is checked without a price
+        let capped = GasPolicy { max_gas_gwei: Some(10.0), max_cost_usd: Some(100.0) };
+        let error = capped.check(&estimate, NoPrice.eth_price()).await.unwrap_err();
+        assert_eq!(error.to_string(), "Gas is currently 12.50 gwei, above your 10.00 gwei limit");
+        // Without a price a USD limit can't be checked, so the swap isn't sent
+        assert!(matches!(tight.check(&estimate, NoPrice.eth_price()).await, Err(TradingError::Price(_))));
+
+        // A prepared swap is checked at the gas and gas price it is sent with: 210,000 gas at 1.5 gwei
+        let prepared = swap_tx("0x1111111254eeb25477b68fb85ed929f73a960582", "0x12aa3caf", "0").gas_estimate().unwrap();
+        assert_eq!((prepared.gas_limit, prepared.gas_price_gwei), (210_000, 1.5));
+        let error = GasPolicy { max_gas_gwei: Some(1.0), max_cost_usd: None }.check(&prepared, NoPrice.eth_price()).await.unwrap_err();
+        assert_eq!(error.to_string(), "Gas is currently 1.50 gwei, above your 1.00 gwei limit");
+        // 0.000315 ETH, $1.26 at $4000
+        let error = GasPolicy { max_gas_gwei: None, max_cost_usd: Some(1.0) }.check(&prepared, price(4000.0)).await.unwrap_err();
+        assert_eq!(error.to_string(), "Gas is currently $1.26, above your $1.00 limit");
+    }
+
+    fn token(symbol: &str, address: &str, decimals: u32) -> Token {
+        Token { address: address.to_string(), decimals, symbol: symbol.to_string(), name: symbol.to_string(), logo_uri: None }
+    }
+
+    fn quote(from: Token, to: Token, from_amount: &str, to_amount: &str) -> QuoteResponse {
+        QuoteResponse { from_token: from, to_token: to, from_amount: from_amount.to_string(), to_amount: to_amount.to_string(), protocols: vec![], estimated_gas: 180_000, aggregator: ONE_INCH.to_string() }
+    }
+
+    fn executed_at() -> NaiveDateTime {
+        chrono::NaiveDate::from_ymd_opt(2025, 10, 3).unwrap().and_hms_opt(12, 0, 0).unwrap()
+    }
+
+    #[test]
+    fn test_unsupported_chain_names_the_supported_ones() {
+        assert_eq!(supported_chain(42161).unwrap().name, "Arbitrum One");
+        let error = supported_chain(10).unwrap_err().to_string();
+        assert_eq!(
+            error,
+            "Configuration error: CHAIN_ID 10 is not supported, use one of 84532 (Base Sepolia), 8453 (Base), 42161 (Arbitrum One), 1 (Ethereum)"
+        );
+    }
+
+    #[test]
+    fn test_every_supported_chain_has_token_addresses() {
+        let tokens = default_chain_tokens();
+        for supported in SUPPORTED_CHAINS {
+            let chain_tokens = &tokens[&supported.chain_id()];
+            assert!(parse_address(&chain_tokens.usdc).is_ok() && parse_address(&chain_tokens.weth).is_ok(), "{}", supported.name);
+        }
+    }
+
+    #[test]
+    fn test_swaps_need_the_balance_to_cover_them() {
+        assert!(ensure_balance("USDC", 100.0, 100.0).is_ok());
+        let error = ensure_balance("USDC", 100.0, 42.5).unwrap_err();
+        assert!(matches!(error, TradingError::InsufficientBalance { ref token, needed, available } if token == "USDC" && needed == 100.0 && available == 42.5));
+        assert_eq!(error.to_string(), "Not enough USDC: the trade needs 100 and the wallet holds 42.5");
+    }
+
+    #[test]
+    fn test_swaps_into_usd_are_sells() {
+        // 0.1 WETH for 250 USDC
+        let quote = quote(token("WETH", "0xweth", 18), token("USDC", "0xusdc", 6), "100000000000000000", "250000000");
+        let trade = swap_trade(&quote, Ok(H256::repeat_byte(0xab)), executed_at()).unwrap();
+
+        assert_eq!((trade.side, trade.coin_id.as_str(), trade.quote.as_str()), (OrderType::Sell, "ethereum", "USDC"));
+        assert_eq!((trade.amount, trade.price, trade.price_usd_at_execution), (0.1, 2500.0, Some(2500.0)));
+        assert_eq!((trade.from_amount, trade.to_amount), (Some(0.1), Some(250.0)));
+        assert_eq!((trade.from_token.as_deref(), trade.to_token.as_deref()), (Some("0xweth"), Some("0xusdc")));
+        assert_eq!(trade.tx_hash, Some(format!("0x{}", "ab".repeat(32))));
+        // Sent, its gas is known once it is mined
+        assert_eq!((trade.status, trade.gas_cost), (TradeStatus::Pending, None));
+        assert_eq!(trade.aggregator.as_deref(), Some("1inch"));
+    }
+
+    #[test]
+    fn test_slippage_range() {
+        assert!(check_slippage(0.05, MIN_SLIPPAGE_PCT, MAX_SLIPPAGE_PCT).is_ok());
+        assert!(check_slippage(50.0, MIN_SLIPPAGE_PCT, MAX_SLIPPAGE_PCT).is_ok());
+        assert!(matches!(check_slippage(0.04, MIN_SLIPPAGE_PCT, MAX_SLIPPAGE_PCT), Err(TradingError::SlippageTooLow { .. })));
+        assert!(matches!(check_slippage(f32::NAN, MIN_SLIPPAGE_PCT, MAX_SLIPPAGE_PCT), Err(TradingError::SlippageTooHigh { .. })));
+
+        let limits = SwapLimits { max_slippage_pct: 3.0, ..SwapLimits::default() };
+        let error = limits.check_slippage(5.0).unwrap_err();
+        assert_eq!(error.to_string(), "Slippage of 5% is above the 3% maximum");
+        assert_eq!(
+            SwapLimits::default().check_slippage(0.01).unwrap_err().to_string(),
+            "Slippage of 0.01% is below the 0.05% minimum, the swap would most likely revert"
+        );
+    }
+
+    #[test]
+    fn test_quotes_far_from_the_market_are_suspicious() {
+        let (weth, usdc) = (token("WETH", "0xweth", 18), token("USDC", "0xusdc", 6));
+        let limits = SwapLimits::default();
+        // 0.1 WETH for 245 USDC with ETH at $2500 is 2% off
+        assert!(limits.check_price(&weth, &usdc, "100000000000000000", "245000000", 2500.0, 1.0).is_ok());
+        // For 240 USDC, 4% off
+        let error = limits.check_price(&weth, &usdc, "100000000000000000", "240000000", 2500.0, 1.0).unwrap_err();
+        assert!(matches!(error, TradingError::SuspiciousQuote { implied, reference } if (implied - 2400.0).abs() < 1e-9 && reference == 2500.0));
+        assert_eq!(error.to_string(), "Suspicious quote: the swap values the token sold at $2400.00, the market at $2500.00; nothing was sent");
+        // Getting far more than the market is as suspicious, and so is nothing at all
+        assert!(limits.check_price(&usdc, &weth, "250000000", "200000000000000000", 1.0, 2500.0).is_err());
+        assert!(limits.check_price(&weth, &usdc, "100000000000000000", "0", 2500.0, 1.0).is_err());
+
+        let off = SwapLimits { max_price_deviation_pct: 0.0, ..limits };
+        assert!(off.check_price(&weth, &usdc, "100000000000000000", "0", 2500.0, 1.0).is_ok());
+        assert!(matches!(limits.check_price(&weth, &usdc, "lots", "0", 2500.0, 1.0), Err(TradingError::InvalidResponse(_))));
+    }
+
+    #[test]
+    fn test_gas_cost_of_a_mined_swap() {
+        let receipt = SwapReceipt {
+            tx_hash: H256::repeat_byte(0xab),
+            block_number: Some(7),
+            gas_used: Some(U256::from(150_000)),
+            effective_gas_price: Some(U256::from(2_000_000_000u64)),
+        };
+        // 150k gas at 2 gwei
+        assert!((receipt.gas_cost().unwrap() - 0.0003).abs() < 1e-12);
+        assert_eq!(SwapReceipt { effective_gas_price: None, ..receipt }.gas_cost(), None);
+    }
+
+    #[test]
+    fn test_other_swaps_buy_the_destination_token() {
+        // 500 USDC for 2 AAVE, then 1 WETH for 10 AAVE
+        let bought = quote(token("USDC", "0xusdc", 6), token("AAVE", "0xaave", 18), "500000000", "2000000000000000000");
+        let trade = swap_trade(&bought, Err(&TradingError::Broadcast(BroadcastError::Reverted(H256::zero()))), executed_at()).unwrap();
+        assert_eq!((trade.side, trade.coin_id.as_str(), trade.amount, trade.price), (OrderType::Buy, "aave", 2.0, 250.0));
+        assert_eq!((trade.status, trade.tx_hash.is_some(), trade.gas_cost), (TradeStatus::Failed, true, None));
+
+        let in_eth = quote(token("WETH", "0xweth", 18), token("AAVE", "0xaave", 18), "1000000000000000000", "10000000000000000000");
+        let error = TradingError::Approval(BroadcastError::Reverted(H256::zero()));
+        let trade = swap_trade(&in_eth, Err(&error), executed_at()).unwrap();
+        assert_eq!((trade.quote.as_str(), trade.price, trade.price_usd_at_execution), ("WETH", 0.1, None));
+        // The approval's transaction isn't the swap's
+        assert_eq!((trade.status, trade.tx_hash), (TradeStatus::Failed, None));
+
+        let unreadable = quote(token("WETH", "0xweth", 18), token("USDC", "0xusdc", 6), "lots", "250000000");
+        assert!(swap_trade(&unreadable, Err(&error), executed_at()).is_none());
+    }
+
+    #[test]
+    fn test_swap_the_node_lost_track_of_stays_pending() {
+        let sold = quote(token("WETH", "0xweth", 18), token("USDC", "0xusdc", 6), "100000000000000000", "250000000");
+        let lost = TradingError::Broadcast(BroadcastError::Receipt { tx_hash: H256::repeat_byte(0xab), reason: "timeout".to_string() });
+        let trade = swap_trade(&sold, Err(&lost), executed_at()).unwrap();
+        // Followed by the monitor until it is settled
+        assert_eq!((trade.status, trade.tx_hash), (TradeStatus::Pending, Some(format!("{:?}", H256::repeat_byte(0xab)))));
+
+        let dropped = TradingError::Broadcast(BroadcastError::Dropped(H256::repeat_byte(0xab)));
+        assert_eq!(swap_trade(&sold, Err(&dropped), executed_at()).unwrap().status, TradeStatus::Failed);
+    }
+
+    #[tokio::test]
+    async fn test_cancel_unknown_order_is_order_not_found() {
+        let Some(pool) = test_pool().await else { return };
+        let result = client(&pool, 1).cancel_limit_order("missing").await;
+        assert!(matches!(result, Err(TradingError::OrderNotFound(id)) if id == "missing"));
+    }
+
+    #[tokio::test]
+    async fn test_open_orders_survive_a_restart() {
+        let Some(pool) = test_pool().await else { return };
+        let before = client(&pool, 1);
+        let created = before.create_limit_order(OrderType::Sell, 0.5, 2900.0, None).await.unwrap();
+        assert!(created.starts_with("Created sell limit order for 0.5 tokens at $2900 (ID: "));
+        drop(before);
+
+        // A new client, as after a crash, finds the order in the database
+        let after = client(&pool, 1);
+        let open = after.get_open_limit_orders().await.unwrap();
+        assert_eq!(open.len(), 1);
+        assert_eq!((open[0].order_type, open[0].token_address.as_str(), open[0].amount, open[0].price), (OrderType::Sell, "0xweth", 0.5, 2900.0));
+        assert!(created.contains(&open[0].id));
+        assert!(client(&pool, 2).get_open_limit_orders().await.unwrap().is_empty());
+
+        assert_eq!(after.cancel_limit_order(&open[0].id).await.unwrap(), format!("Cancelled order {}", open[0].id));
+        assert!(after.get_open_limit_orders().await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_watch_only_client_refuses_to_sign() {
+        let Some(pool) = test_pool().await else { return };
+        let address: Address = "0x1111111111111111111111111111111111111111".parse().unwrap();
+        let watching = watch_only(&pool, 1, address);
+        assert!(watching.is_read_only() && !client(&pool, 1).is_read_only());
+        assert_eq!(watching.get_wallet_address().await, format!("{:?}", address));
+
+        let placed = watching.create_limit_order(OrderType::Buy, 100.0, 2300.0, None).await;
+        assert!(matches!(placed, Err(TradingError::SignerRequired(ref action)) if action == "placing a limit order"));
+        assert!(watching.get_open_limit_orders().await.unwrap().is_empty());
+        assert!(matches!(watching.check_and_execute_limit_orders().await, Err(TradingError::SignerRequired(_))));
+        assert!(matches!(watching.ensure_allowance("0xweth", "0xrouter", U256::one()).await, Err(TradingError::SignerRequired(_))));
+
+        // Refused before any balance or quote is fetched from the unreachable node and 1inch
+        let swap = watching.execute_trade_strategy("0xusdc", "0xweth", 10.0, 6, 1.0, &GasPolicy::default(), false).await;
+        assert_eq!(swap.unwrap_err().to_string(), "The wallet is watch-only: swapping needs PRIVATE_KEY to sign");
+    }
+
+    #[tokio::test]
+    async fn test_wallet_token_missing_from_the_token_list_is_skipped() {
+        use wiremock::matchers::{body_partial_json, method, path};
+        use wiremock::{Mock, MockServer, ResponseTemplate};
+
+        let Some(pool) = test_pool().await else { return };
+        let (usdc, weth) = ("0x036cbd53842c5426634e7929541ec2318f3dcf7e", "0x4200000000000000000000000000000000000006");
+        let one_inch = MockServer::start().await;
+        let listed = |symbol: &str, address: &str, decimals: u32| {
+            serde_json::json!({ "symbol": symbol, "name": symbol, "address": address, "decimals": decimals, "logoURI": null })
+        };
+        Mock::given(method("GET"))
+            .and(path("/84532/tokens"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "tokens": { usdc: listed("USDC", usdc, 6), weth: listed("WETH", weth, 18) }
+            })))
+            .mount(&one_inch)
+            .await;
+        // Every token balance is 1,500,000 units, 1.5 USDC, and the wallet holds no ETH
+        let node = MockServer::start().await;
+        for (rpc_method, result) in [("eth_getBalance", "0x0".to_string()), ("eth_call", format!("0x{:064x}", 1_500_000u64))] {
+            Mock::given(method("POST"))
+                .and(body_partial_json(serde_json::json!({ "method": rpc_method })))
+                .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({ "jsonrpc": "2.0", "id": 1, "result": result })))
+                .mount(&node)
+                .await;
+        }
+
+        let trading = TradingClient {
+            provider: Arc::new(Provider::<Http>::try_from(node.uri()).unwrap()),
+            one_inch: Arc::new(OneInchClient::new(84532, None).with_base_url(&one_inch.uri())),
+            usdc_address: usdc.to_string(),
+            weth_address: weth.to_string(),
+            wallet_tokens: vec!["0x00000000000000000000000000000000000000aa".to_string()],
+            ..client(&pool, 1)
+        };
+        let balances = trading.get_token_balances().await.unwrap();
+        let symbols: Vec<&str> = balances.iter().map(|balance| balance.symbol.as_str()).collect();
+        assert_eq!(symbols, vec!["USDC", "WETH"]);
+        assert_eq!(balances[0].amount, 1.5);
+
+        // USDC itself missing from the list is still an error
+        let unlisted_usdc = TradingClient { usdc_address: "0x00000000000000000000000000000000000000bb".to_string(), ..trading };
+        assert!(matches!(unlisted_usdc.get_token_balances().await, Err(TradingError::TokenNotFound(_))));
+    }
+
+    #[test]
+    fn test_swap_amounts_past_u64_convert_exactly() {
+        let wei = |amount: &str| U256::from_dec_str(amount).unwrap();
+        // u64 wei tops out at 18.44 ETH
+        assert_eq!(OneInchClient::to_wei(25.0, 18).unwrap(), wei("25000000000000000000"));
+        assert_eq!(OneInchClient::to_wei(1234.5, 18).unwrap(), wei("1234500000000000000000"));
+        assert_eq!(OneInchClient::to_wei(0.1, 18).unwrap(), wei("100000000000000000"));
+        assert_eq!(OneInchClient::to_wei(100.0, 6).unwrap(), wei("100000000"));
+        // 10^20 doesn't fit a u64 either
+        assert_eq!(OneInchClient::to_wei(2.0, 20).unwrap(), wei("200000000000000000000"));
+        // Digits past the decimals are dropped
+        assert_eq!(OneInchClient::to_wei(1.2345678, 6).unwrap(), wei("1234567"));
+
+        for amount in [-1.0, f64::NAN, f64::INFINITY, 1e60] {
+            assert!(matches!(OneInchClient::to_wei(amount, 18), Err(TradingError::InvalidAmount(_))), "{}", amount);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_slippage_outside_the_limits_is_refused_even_for_a_dry_run() {
+        let Some(pool) = test_pool().await else { return };
+        let trading = client(&pool, 1).with_swap_limits(SwapLimits { max_slippage_pct: 2.0, ..SwapLimits::default() });
+        // Refused before 1inch or the unreachable node are asked
+        let swap = trading.execute_trade_strategy("0xusdc", "0xweth", 10.0, 6, 5.0, &GasPolicy::default(), true).await;
+        assert!(matches!(swap, Err(TradingError::SlippageTooHigh { max, .. }) if max == 2.0));
+        let swap = trading.execute_trade_strategy("0xusdc", "0xweth", 10.0, 6, 0.01, &GasPolicy::default(), false).await;
+        assert!(matches!(swap, Err(TradingError::SlippageTooLow { .. })));
+    }
+
+    #[tokio::test]
+    async fn test_cancelling_a_filled_order_is_order_not_open() {
+        let Some(pool) = test_pool().await else { return };
+        let trading = client(&pool, 1);
+        trading.create_limit_order(OrderType::Buy, 100.0, 2300.0, None).await.unwrap();
+        let order = trading.get_open_limit_orders().await.unwrap().remove(0);
+        db::update_limit_order_status(&pool, 1, &order.id, OrderStatus::Filled).await.unwrap();
+
+        let result = trading.cancel_limit_order(&order.id).await;
+        assert!(matches!(result, Err(TradingError::OrderNotOpen { ref id, status: OrderStatus::Filled }) if *id == order.id));
+        assert_eq!(result.unwrap_err().to_string(), format!("Order {} is filled, only open orders can be cancelled", order.id));
+    }
+
+    #[tokio::test]
+    async fn test_expired_orders_never_fill() {
+        let Some(pool) = test_pool().await else { return };
+        // NoPrice fails, so the check only succeeds when no order is left to price
+        let trading = client(&pool, 1);
+        let expired_at = Utc::now() - chrono::Duration::minutes(1);
+        trading.create_limit_order(OrderType::Buy, 100.0, 1_000_000.0, Some(expired_at)).await.unwrap();
+        let order = db::get_limit_orders(&pool, 1).await.unwrap().remove(0);
+        assert!(trading.get_open_limit_orders().await.unwrap().is_empty());
+
+        assert!(trading.check_and_execute_limit_orders().await.unwrap().is_empty());
+        let stored = db::get_limit_order(&pool, 1, &order.id).await.unwrap().unwrap();
+        assert_eq!(stored.status, OrderStatus::Expired);
+
+        let result = trading.cancel_limit_order(&order.id).await;
+        assert!(matches!(result, Err(TradingError::OrderExpired(ref id)) if *id == order.id));
+        assert_eq!(result.unwrap_err().to_string(), format!("Order {} already expired, there is nothing to cancel", order.id));
+    }
+
+    #[tokio::test]
+    async fn test_orders_stay_open_until_they_expire() {
+        let Some(pool) = test_pool().await else { return };
+        let trading = client(&pool, 1);
+        let friday = Utc.with_ymd_and_hms(2099, 10, 16, 21, 59, 59).unwrap();
+        let created = trading.create_limit_order(OrderType::Buy, 100.0, 2300.0, Some(friday)).await.unwrap();
+        assert!(created.starts_with("Created buy limit order for 100 tokens at $2300, good until 2099-10-16 21:59 UTC (ID: "), "{}", created);
+        let open = trading.get_open_limit_orders().await.unwrap();
+        assert_eq!(open[0].expires_at, Some(friday.naive_utc()));
+
+        // Cancelling before the expiry cancels it
+        assert_eq!(trading.cancel_limit_order(&open[0].id).await.unwrap(), format!("Cancelled order {}", open[0].id));
+    }
+
+    #[tokio::test]
+    async fn test_orders_fill_when_the_market_reaches_their_price() {
+        let Some(pool) = test_pool().await else { return };
+        let trading = client(&pool, 1).with_market_price(Arc::new(FixedPrice(2400.0)));
+        for (order_type, price) in [(OrderType::Buy, 2450.0), (OrderType::Buy, 2400.0), (OrderType::Buy, 2350.0),
+            (OrderType::Sell, 2350.0), (OrderType::Sell, 2400.0), (OrderType::Sell, 2450.0)]
+        {
+            trading.create_limit_order(order_type, 1.0, price, None).await.unwrap();
+        }
+
+        let executed = trading.check_and_execute_limit_orders().await.unwrap();
+        let filled: Vec<(OrderType, f64)> = db::get_limit_orders(&pool, 1)
+            .await
+            .unwrap()
+            .into_iter()
+            .filter(|order| order.status == OrderStatus::Filled)
+            .map(|order| (order.order_type, order.price))
+            .collect();
+        assert_eq!(filled, [(OrderType::Buy, 2450.0), (OrderType::Buy, 2400.0), (OrderType::Sell, 2350.0), (OrderType::Sell, 2400.0)]);
+        assert_eq!(executed.len(), 4);
+        assert!(executed.iter().all(|order| order.fill_price == 2400.0 && order.amount == 1.0));
+
+        // Each fill is in the trade history, at the market price
+        let trades = db::get_trades_by_user_id(&pool, 1, None, None).await.unwrap();
+        assert_eq!(trades.len(), 4);
+        assert!(trades.iter().all(|trade| trade.price == 2400.0 && trade.coin_id == "ethereum" && trade.status == TradeStatus::Filled));
+        let sell = trades.iter().find(|trade| trade.side == OrderType::Sell).unwrap();
+        assert_eq!((sell.from_token.as_deref(), sell.from_amount, sell.to_amount), (Some("0xweth"), Some(1.0), Some(2400.0)));
+
+        let open = trading.get_open_limit_orders().await.unwrap();
+        assert_eq!(open.iter().map(|order| (order.order_type, order.price)).collect::<Vec<_>>(), [(OrderType::Buy, 2350.0), (OrderType::Sell, 2450.0)]);
+        assert!(trading.check_and_execute_limit_orders().await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_concurrent_checks_fill_each_order_once() {
+        let Some(pool) = test_pool().await else { return };
+        let first = client(&pool, 1).with_market_price(Arc::new(FixedPrice(2000.0)));
+        let second = client(&pool, 1).with_market_price(Arc::new(FixedPrice(2000.0)));
+        for _ in 0..5 {
+            first.create_limit_order(OrderType::Buy, 50.0, 2100.0, None).await.unwrap();
+        }
+
+        let (a, b) = tokio::join!(first.check_and_execute_limit_orders(), second.check_and_execute_limit_orders());
+        let mut ids: Vec<String> = a.unwrap().into_iter().chain(b.unwrap()).map(|order| order.order_id).collect();
+        ids.sort();
+        ids.dedup();
+        assert_eq!(ids.len(), 5);
+        assert!(first.get_open_limit_orders().await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_no_open_orders_skips_the_price_lookup() {
+        let Some(pool) = test_pool().await else { return };
+        // NoPrice fails, so the check only succeeds when it isn't asked
+        assert!(client(&pool, 1).check_and_execute_limit_orders().await.unwrap().is_empty());
+
+        let trading = client(&pool, 1);
+        trading.create_limit_order(OrderType::Sell, 1.0, 3000.0, None).await.unwrap();
+        let result = trading.check_and_execute_limit_orders().await;
+        assert!(matches!(result, Err(TradingError::Price(PriceError::Offline))));
+        assert_eq!(trading.get_open_limit_orders().await.unwrap().len(), 1);
     }
 }
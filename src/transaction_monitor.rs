@@ -0,0 +1,200 @@
+use crate::db::{self, TradeStatus};
+use crate::trading::{SwapReceipt, TradingError};
+use ethers::providers::{Http, Middleware, Provider};
+use ethers::types::H256;
+use sqlx::{Pool, Postgres};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::task::JoinHandle;
+use tracing::{info, warn};
+
+type Result<T> = std::result::Result<T, TradingError>;
+
+/// Blocks a sent swap may go unmined before it counts as stuck, unless TRADE_MONITOR_BLOCKS says otherwise
+/// About five minutes of Base blocks
+pub const DEFAULT_TIMEOUT_BLOCKS: u64 = 150;
+
+/// Wait before the second receipt poll, doubled after every poll
+pub const DEFAULT_POLL_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Longest wait between two receipt polls
+const MAX_POLL_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Where a sent transaction stands on chain
+#[derive(Debug, Clone, PartialEq)]
+pub enum TransactionStatus {
+    /// Not mined yet, or mined in `block_number` without enough confirmations on top
+    Pending { block_number: Option<u64> },
+    Confirmed(SwapReceipt),
+    /// Mined but reverted: nothing was swapped, the gas was still paid
+    Failed(SwapReceipt),
+    /// Not mined within the monitor's timeout, it may have been dropped or priced out
+    Stuck,
+}
+
+impl TransactionStatus {
+    /// What the trade history records a swap in this state as
+    pub fn trade_status(&self) -> TradeStatus {
+        match self {
+            TransactionStatus::Pending { .. } => TradeStatus::Pending,
+            TransactionStatus::Confirmed(_) => TradeStatus::Confirmed,
+            TransactionStatus::Failed(_) => TradeStatus::Failed,
+            TransactionStatus::Stuck => TradeStatus::Stuck,
+        }
+    }
+}
+
+/// Where `tx_hash` stands, settled once it has `confirmations` blocks counting the one that mined it
+pub async fn transaction_status<M: Middleware>(client: &M, tx_hash: H256, confirmations: usize) -> Result<TransactionStatus> {
+    let receipt = client
+        .get_transaction_receipt(tx_hash)
+        .await
+        .map_err(|e| TradingError::Provider(e.to_string()))?;
+    let Some((receipt, mined)) = receipt.and_then(|receipt| receipt.block_number.map(|block| (receipt, block.as_u64()))) else {
+        return Ok(TransactionStatus::Pending { block_number: None });
+    };
+
+    let latest = client
+        .get_block_number()
+        .await
+        .map_err(|e| TradingError::Provider(e.to_string()))?
+        .as_u64();
+    if latest + 1 < mined + confirmations.max(1) as u64 {
+        return Ok(TransactionStatus::Pending { block_number: Some(mined) });
+    }
+
+    let swap = SwapReceipt {
+        tx_hash,
+        block_number: Some(mined),
+        gas_used: receipt.gas_used,
+        effective_gas_price: receipt.effective_gas_price,
+    };
+    Ok(if receipt.status == Some(1.into()) {
+        TransactionStatus::Confirmed(swap)
+    } else {
+        TransactionStatus::Failed(swap)
+    })
+}
+
+/// Follows swaps sent from the wallet until they are mined, keeping their rows in `trades` up to date
+///
+/// The receipt is polled with a wait that doubles after every poll. A swap is confirmed or failed
+/// once it has the monitor's confirmations, and stuck when `timeout_blocks` pass without it being
+/// mined. Rows that can't be written are logged and don't change what the monitor returns.
+#[derive(Clone)]
+pub struct TransactionMonitor {
+    provider: Arc<Provider<Http>>,
+    pool: Pool<Postgres>,
+    /// Owner of the trades the monitor updates
+    user_id: i32,
+    confirmations: usize,
+    timeout_blocks: u64,
+    poll_interval: Duration,
+}
+
+impl TransactionMonitor {
+    /// A monitor of `user_id`'s swaps settling them on their first block, with the default timeout and backoff
+    pub fn new(provider: Arc<Provider<Http>>, pool: Pool<Postgres>, user_id: i32) -> Self {
+        Self {
+            provider,
+            pool,
+            user_id,
+            confirmations: 1,
+            timeout_blocks: DEFAULT_TIMEOUT_BLOCKS,
+            poll_interval: DEFAULT_POLL_INTERVAL,
+        }
+    }
+
+    /// Settle swaps once they have `confirmations` blocks
+    pub fn with_confirmations(mut self, confirmations: usize) -> Self {
+        self.confirmations = confirmations.max(1);
+        self
+    }
+
+    /// Mark a swap stuck when `timeout_blocks` pass without it being mined
+    pub fn with_timeout_blocks(mut self, timeout_blocks: u64) -> Self {
+        self.timeout_blocks = timeout_blocks;
+        self
+    }
+
+    /// Wait `poll_interval` after the first poll
+    pub fn with_poll_interval(mut self, poll_interval: Duration) -> Self {
+        self.poll_interval = poll_interval;
+        self
+    }
+
+    /// Check `tx_hash` once, recording it as confirmed or failed when it is settled
+    pub async fn check(&self, tx_hash: H256) -> Result<TransactionStatus> {
+        let status = transaction_status(self.provider.as_ref(), tx_hash, self.confirmations).await?;
+        self.record(tx_hash, &status).await;
+        Ok(status)
+    }
+
+    /// Poll `tx_hash` until it is confirmed or failed, or stuck after `timeout_blocks` unmined blocks
+    pub async fn watch(&self, tx_hash: H256) -> Result<TransactionStatus> {
+        let started = self.block_number().await?;
+        let mut interval = self.poll_interval;
+        loop {
+            match self.check(tx_hash).await? {
+                TransactionStatus::Pending { block_number: None } => {
+                    if self.block_number().await? >= started + self.timeout_blocks {
+                        self.record(tx_hash, &TransactionStatus::Stuck).await;
+                        return Ok(TransactionStatus::Stuck);
+                    }
+                },
+                // Mined, so it only needs its confirmations
+                TransactionStatus::Pending { .. } => {},
+                settled => return Ok(settled),
+            }
+            tokio::time::sleep(interval).await;
+            interval = (interval * 2).min(MAX_POLL_INTERVAL);
+        }
+    }
+
+    /// `watch` `tx_hash` in the background, logging how it ended
+    pub fn spawn(&self, tx_hash: H256) -> JoinHandle<()> {
+        let monitor = self.clone();
+        tokio::spawn(async move {
+            match monitor.watch(tx_hash).await {
+                Ok(status) => info!("Swap {:?} is {}", tx_hash, status.trade_status()),
+                Err(e) => warn!("Stopped following swap {:?}: {}", tx_hash, e),
+            }
+        })
+    }
+
+    async fn block_number(&self) -> Result<u64> {
+        let block = self
+            .provider
+            .get_block_number()
+            .await
+            .map_err(|e| TradingError::Provider(e.to_string()))?;
+        Ok(block.as_u64())
+    }
+
+    /// Write a settled or stuck `status` to the swap's trade row
+    pub(crate) async fn record(&self, tx_hash: H256, status: &TransactionStatus) {
+        let (block_number, gas_used, gas_cost) = match status {
+            TransactionStatus::Pending { .. } => return,
+            TransactionStatus::Confirmed(receipt) | TransactionStatus::Failed(receipt) => (
+                receipt.block_number.map(|block| block as i64),
+                receipt.gas_used.map(|gas| gas.low_u64() as i64),
+                receipt.gas_cost(),
+            ),
+            TransactionStatus::Stuck => (None, None, None),
+        };
+        let tx_hash = format!("{:?}", tx_hash);
+        let updated = db::update_trade_status(
+            &self.pool,
+            self.user_id,
+            &tx_hash,
+            status.trade_status(),
+            block_number,
+            gas_used,
+            gas_cost,
+        )
+        .await;
+        if let Err(e) = updated {
+            warn!("Could not record swap {} as {}: {}", tx_hash, status.trade_status(), e);
+        }
+    }
+}
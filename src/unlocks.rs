@@ -0,0 +1,523 @@
+use crate::data_source::{self, DataSourceError, DataSourcePlugin};
+use crate::db::{Knowledge, KnowledgeInput};
+use async_trait::async_trait;
+use chrono::{Duration, NaiveDate};
+use regex::Regex;
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+use sqlx::types::JsonValue;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::OnceLock;
+use thiserror::Error;
+
+/// Knowledge tag shared by every stored unlock event
+pub const UNLOCK_TAG: &str = "unlock";
+
+/// Data source type of unlock schedules
+pub const SOURCE_TYPE: &str = "token_unlocks";
+
+/// Days ahead the briefing lists unlocks for
+pub const BRIEFING_WINDOW_DAYS: i64 = 7;
+
+/// Days ahead unlocks are listed when the user states no timeframe
+pub const DEFAULT_WINDOW_DAYS: i64 = 90;
+
+/// Errors raised while loading unlock schedules
+#[derive(Debug, Error)]
+pub enum UnlockError {
+    #[error("IO error: {0}")]
+    Io(#[from] std::io::Error),
+
+    #[error("HTTP error: {0}")]
+    Http(#[from] reqwest::Error),
+
+    #[error("Invalid unlock schedule on line {line}: {message}")]
+    InvalidCsv { line: usize, message: String },
+
+    #[error("Invalid unlock schedule: {0}")]
+    InvalidJson(#[from] serde_json::Error),
+
+    #[error("Unsupported unlock schedule file {0}, expected .csv or .json")]
+    UnsupportedFile(String),
+}
+
+/// A scheduled release of locked tokens
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct UnlockEvent {
+    /// CoinGecko id
+    pub coin_id: String,
+    pub date: NaiveDate,
+    /// Share of the total supply released, 2.5 for 2.5%
+    pub percent_of_supply: f64,
+    /// Tokens released, when the schedule gives them
+    #[serde(default)]
+    pub tokens: Option<f64>,
+    /// Who receives them, e.g. "team and investors"
+    #[serde(default)]
+    pub note: Option<String>,
+}
+
+impl UnlockEvent {
+    /// One line used as the knowledge content, and parsed back by `from_knowledge`
+    pub fn describe(&self) -> String {
+        let mut line = format!("{} unlock on {}: {}% of supply", self.coin_id, self.date.format("%Y-%m-%d"), self.percent_of_supply);
+        if let Some(tokens) = self.tokens {
+            line.push_str(&format!(", {} tokens", tokens));
+        }
+        if let Some(note) = &self.note {
+            line.push_str(&format!(" ({})", note));
+        }
+        line
+    }
+
+    /// Knowledge source id, one entry per coin and date
+    pub fn source_id(&self) -> String {
+        format!("{}-{}-{}", UNLOCK_TAG, self.coin_id, self.date.format("%Y-%m-%d"))
+    }
+
+    /// The entry stored for this event, tagged with the coin and the date
+    pub fn to_knowledge(&self) -> KnowledgeInput {
+        KnowledgeInput {
+            source_id: self.source_id(),
+            content: self.describe(),
+            tags: vec![UNLOCK_TAG.to_string(), self.coin_id.clone(), self.date.format("%Y-%m-%d").to_string()],
+        }
+    }
+
+    /// Read back an event stored with `to_knowledge`
+    pub fn from_knowledge(entry: &Knowledge) -> Option<Self> {
+        static LINE: OnceLock<Regex> = OnceLock::new();
+        let captures = LINE
+            .get_or_init(|| {
+                Regex::new(r"^(\S+) unlock on (\d{4}-\d{2}-\d{2}): ([\d.]+)% of supply(?:, ([\d.e+]+) tokens)?(?: \((.*)\))?$").unwrap()
+            })
+            .captures(entry.content.trim())?;
+        Some(Self {
+            coin_id: captures[1].to_string(),
+            date: NaiveDate::parse_from_str(&captures[2], "%Y-%m-%d").ok()?,
+            percent_of_supply: captures[3].parse().ok()?,
+            tokens: captures.get(4).and_then(|tokens| tokens.as_str().parse().ok()),
+            note: captures.get(5).map(|note| note.as_str().to_string()),
+        })
+    }
+}
+
+/// Split one CSV line, honouring double quotes around fields
+fn split_csv_line(line: &str) -> Vec<String> {
+    let mut fields = Vec::new();
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = line.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                field.push('"');
+                chars.next();
+            },
+            '"' => quoted = !quoted,
+            ',' if !quoted => fields.push(std::mem::take(&mut field).trim().to_string()),
+            _ => field.push(c),
+        }
+    }
+    fields.push(field.trim().to_string());
+    fields
+}
+
+/// Parse a CSV schedule with a `coin_id,date,percent_of_supply` header, `tokens` and `note` optional
+///
+/// Dates are YYYY-MM-DD, a trailing % on the percentage is allowed and blank lines are skipped.
+pub fn parse_csv(text: &str) -> Result<Vec<UnlockEvent>, UnlockError> {
+    let mut lines = text.lines().enumerate().filter(|(_, line)| !line.trim().is_empty());
+    let Some((_, header)) = lines.next() else {
+        return Ok(Vec::new());
+    };
+    let header: Vec<String> = split_csv_line(header).into_iter().map(|name| name.to_lowercase()).collect();
+    let column = |name: &str| header.iter().position(|column| column == name);
+    let missing = |name: &str| UnlockError::InvalidCsv { line: 1, message: format!("missing the {} column", name) };
+    let coin_column = column("coin_id").ok_or_else(|| missing("coin_id"))?;
+    let date_column = column("date").ok_or_else(|| missing("date"))?;
+    let percent_column = column("percent_of_supply").ok_or_else(|| missing("percent_of_supply"))?;
+    let tokens_column = column("tokens");
+    let note_column = column("note");
+
+    let mut events = Vec::new();
+    for (index, line) in lines {
+        let fields = split_csv_line(line);
+        let invalid = |message: String| UnlockError::InvalidCsv { line: index + 1, message };
+        let field = |column: usize| fields.get(column).map(String::as_str).unwrap_or("");
+        let optional = |column: Option<usize>| column.map(field).filter(|value| !value.is_empty());
+
+        let coin_id = field(coin_column).to_lowercase();
+        if coin_id.is_empty() {
+            return Err(invalid("empty coin_id".to_string()));
+        }
+        let date = NaiveDate::parse_from_str(field(date_column), "%Y-%m-%d")
+            .map_err(|_| invalid(format!("invalid date '{}', expected YYYY-MM-DD", field(date_column))))?;
+        let percent = field(percent_column);
+        let percent_of_supply: f64 = percent
+            .trim_end_matches('%')
+            .trim()
+            .parse()
+            .map_err(|_| invalid(format!("invalid percent_of_supply '{}'", percent)))?;
+        let tokens = match optional(tokens_column) {
+            Some(tokens) => Some(tokens.replace(',', "").parse().map_err(|_| invalid(format!("invalid tokens '{}'", tokens)))?),
+            None => None,
+        };
+
+        events.push(UnlockEvent { coin_id, date, percent_of_supply, tokens, note: optional(note_column).map(str::to_string) });
+    }
+    Ok(events)
+}
+
+/// Parse a JSON array of events with the same fields as the CSV columns
+pub fn parse_json(text: &str) -> Result<Vec<UnlockEvent>, UnlockError> {
+    let mut events: Vec<UnlockEvent> = serde_json::from_str(text)?;
+    for event in &mut events {
+        event.coin_id = event.coin_id.to_lowercase();
+    }
+    Ok(events)
+}
+
+/// Where an unlock schedule is read from
+#[derive(Debug, Clone, PartialEq)]
+pub enum UnlockSource {
+    /// An API answering GET with a JSON array of events
+    Api(String),
+    /// A CSV or JSON file maintained by the user
+    File(PathBuf),
+}
+
+impl UnlockSource {
+    /// Read the source from a data source config, `{"url": ...}` or `{"file": ...}`
+    pub fn from_config(config: &JsonValue) -> Option<Self> {
+        if let Some(url) = config["url"].as_str() {
+            return Some(UnlockSource::Api(url.to_string()));
+        }
+        config["file"].as_str().map(|path| UnlockSource::File(PathBuf::from(path)))
+    }
+
+    pub fn to_config(&self) -> JsonValue {
+        match self {
+            UnlockSource::Api(url) => serde_json::json!({ "url": url }),
+            UnlockSource::File(path) => serde_json::json!({ "file": path.to_string_lossy() }),
+        }
+    }
+
+    /// Data source id, stable for the same url or file
+    pub fn source_id(&self) -> String {
+        match self {
+            UnlockSource::Api(url) => format!("{}:{}", SOURCE_TYPE, url),
+            UnlockSource::File(path) => format!("{}:{}", SOURCE_TYPE, path.display()),
+        }
+    }
+
+    pub async fn load(&self, client: &Client) -> Result<Vec<UnlockEvent>, UnlockError> {
+        match self {
+            UnlockSource::Api(url) => {
+                let body = client.get(url).send().await?.error_for_status()?.text().await?;
+                parse_json(&body)
+            },
+            UnlockSource::File(path) => {
+                let text = fs::read_to_string(path)?;
+                match path.extension().and_then(|extension| extension.to_str()).map(str::to_lowercase).as_deref() {
+                    Some("csv") => parse_csv(&text),
+                    Some("json") => parse_json(&text),
+                    _ => Err(UnlockError::UnsupportedFile(path.display().to_string())),
+                }
+            },
+        }
+    }
+}
+
+/// Events from `today` through `days` days later, soonest first
+pub fn upcoming(events: &[UnlockEvent], today: NaiveDate, days: i64) -> Vec<UnlockEvent> {
+    let last = today + Duration::days(days);
+    let mut upcoming: Vec<UnlockEvent> = events.iter().filter(|event| event.date >= today && event.date <= last).cloned().collect();
+    upcoming.sort_by(|a, b| a.date.cmp(&b.date).then_with(|| a.coin_id.cmp(&b.coin_id)));
+    upcoming
+}
+
+/// Stores the upcoming events of an unlock schedule as knowledge
+pub struct UnlockPlugin {
+    client: Client,
+    today: fn() -> NaiveDate,
+}
+
+impl UnlockPlugin {
+    pub fn new() -> Self {
+        Self { client: Client::new(), today: || chrono::Local::now().date_naive() }
+    }
+
+    /// Plugin with a fixed date, for tests
+    pub fn on(today: fn() -> NaiveDate) -> Self {
+        Self { client: Client::new(), today }
+    }
+}
+
+impl Default for UnlockPlugin {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[async_trait]
+impl DataSourcePlugin for UnlockPlugin {
+    fn source_type(&self) -> &'static str {
+        SOURCE_TYPE
+    }
+
+    async fn fetch(&self, config: &JsonValue) -> data_source::Result<Vec<KnowledgeInput>> {
+        let source = UnlockSource::from_config(config)
+            .ok_or_else(|| DataSourceError::Plugin("unlock source needs a url or a file".to_string()))?;
+        let events = source.load(&self.client).await.map_err(|e| DataSourceError::Plugin(e.to_string()))?;
+        // Past unlocks are already priced in
+        let upcoming = events.into_iter().filter(|event| event.date >= (self.today)());
+        Ok(upcoming.map(|event| event.to_knowledge()).collect())
+    }
+}
+
+/// How far ahead the user is looking, from the message or their configured horizon
+#[derive(Debug, Clone, PartialEq)]
+pub struct Timeframe {
+    pub days: i64,
+    /// As the user put it, e.g. "3 months"
+    pub label: String,
+}
+
+/// Parse "holding for 3 months" or "over the next 2 weeks"
+pub fn parse_timeframe(message: &str) -> Option<Timeframe> {
+    static TIMEFRAME: OnceLock<Regex> = OnceLock::new();
+    let captures = TIMEFRAME
+        .get_or_init(|| {
+            Regex::new(r"(?i)\b(?:for|over|in|within|during)\s+(?:the\s+)?(?:next\s+)?(\d+)\s+(day|week|month|year)s?\b").unwrap()
+        })
+        .captures(message)?;
+    let count: i64 = captures[1].parse().ok()?;
+    let unit = captures[2].to_lowercase();
+    let days_per_unit = match unit.as_str() {
+        "day" => 1,
+        "week" => 7,
+        "month" => 30,
+        _ => 365,
+    };
+    Some(Timeframe { days: count * days_per_unit, label: format!("{} {}{}", count, unit, if count == 1 { "" } else { "s" }) })
+}
+
+/// The configured investment horizon, short, medium or long
+pub fn horizon_timeframe(horizon: &str) -> Option<Timeframe> {
+    let (days, label) = match horizon.trim().to_lowercase().as_str() {
+        "short" => (30, "short-term horizon (30 days)"),
+        "medium" => (180, "medium-term horizon (6 months)"),
+        "long" => (365, "long-term horizon (1 year)"),
+        _ => return None,
+    };
+    Some(Timeframe { days, label: label.to_string() })
+}
+
+/// The coin named in "are there any unlocks coming for ARB" or "vesting schedule of OP"
+pub fn detect_unlock_query(message: &str) -> Option<String> {
+    static AFTER: OnceLock<Regex> = OnceLock::new();
+    static BEFORE: OnceLock<Regex> = OnceLock::new();
+    const NOT_COINS: &[&str] = &["any", "the", "token", "upcoming", "next", "big", "large", "coming", "are", "there", "of"];
+
+    let after = AFTER.get_or_init(|| {
+        Regex::new(r"(?i)\b(?:(?:token\s+)?unlocks?|vesting(?:\s+(?:schedule|events?|cliffs?))?|cliffs?)(?:\s+(?:coming\s+up|coming|scheduled|upcoming|ahead))?\s+(?:for|of|on)\s+(?:the\s+)?([a-z][a-z0-9-]*)").unwrap()
+    });
+    let before = BEFORE.get_or_init(|| Regex::new(r"(?i)\b([a-z][a-z0-9-]*)\s+(?:token\s+)?(?:unlocks?|vesting)\b").unwrap());
+    after
+        .captures_iter(message)
+        .chain(before.captures_iter(message))
+        .map(|captures| captures[1].to_lowercase())
+        .find(|coin| !NOT_COINS.contains(&coin.as_str()))
+}
+
+/// List the upcoming unlocks of a coin, warning about those inside the user's timeframe
+pub fn render_unlocks(coin_id: &str, events: &[UnlockEvent], today: NaiveDate, timeframe: Option<&Timeframe>) -> String {
+    let window = timeframe.map(|timeframe| timeframe.days).unwrap_or(DEFAULT_WINDOW_DAYS).max(DEFAULT_WINDOW_DAYS);
+    let listed = upcoming(events, today, window);
+    if listed.is_empty() {
+        return format!("I don't know of any {} unlocks in the next {} days.", coin_id, window);
+    }
+
+    let mut output = format!("Upcoming {} unlocks:\n", coin_id);
+    for event in &listed {
+        let days = (event.date - today).num_days();
+        output.push_str(&format!("- {} (in {} day{})\n", event.describe(), days, if days == 1 { "" } else { "s" }));
+    }
+
+    if let Some(timeframe) = timeframe {
+        let inside: Vec<&UnlockEvent> = listed.iter().filter(|event| (event.date - today).num_days() <= timeframe.days).collect();
+        if inside.is_empty() {
+            output.push_str(&format!("\nNone of these fall within your {}.", timeframe.label));
+        } else {
+            let share: f64 = inside.iter().map(|event| event.percent_of_supply).sum();
+            output.push_str(&format!(
+                "\nWarning: {} unlock{} releasing {}% of supply fall{} within your {}. New supply often weighs on the price \
+                around the unlock date.",
+                inside.len(),
+                if inside.len() == 1 { "" } else { "s" },
+                (share * 100.0).round() / 100.0,
+                if inside.len() == 1 { "s" } else { "" },
+                timeframe.label
+            ));
+        }
+    }
+    output.truncate(output.trim_end().len());
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data_source::DataSourceManager;
+    use crate::db;
+    use chrono::NaiveDateTime;
+
+    fn date(text: &str) -> NaiveDate {
+        NaiveDate::parse_from_str(text, "%Y-%m-%d").unwrap()
+    }
+
+    fn event(coin_id: &str, day: &str, percent: f64) -> UnlockEvent {
+        UnlockEvent { coin_id: coin_id.to_string(), date: date(day), percent_of_supply: percent, tokens: None, note: None }
+    }
+
+    #[test]
+    fn test_parse_csv() {
+        let csv = "coin_id,date,percent_of_supply,tokens,note\n\
+            arbitrum,2026-11-16,2.1%,\"92,650,000\",\"team, advisors\"\n\
+            \n\
+            Optimism,2026-10-31,1.5,,\n";
+        let events = parse_csv(csv).unwrap();
+        assert_eq!(events.len(), 2);
+        assert_eq!(
+            events[0],
+            UnlockEvent {
+                coin_id: "arbitrum".to_string(),
+                date: date("2026-11-16"),
+                percent_of_supply: 2.1,
+                tokens: Some(92_650_000.0),
+                note: Some("team, advisors".to_string()),
+            }
+        );
+        assert_eq!(events[1], event("optimism", "2026-10-31", 1.5));
+
+        // Columns can come in any order, only the three required ones
+        let events = parse_csv("date,percent_of_supply,coin_id\n2026-12-01,0.8,sui\n").unwrap();
+        assert_eq!(events, vec![event("sui", "2026-12-01", 0.8)]);
+        assert_eq!(parse_csv("").unwrap(), Vec::new());
+    }
+
+    #[test]
+    fn test_parse_csv_errors() {
+        let error = parse_csv("coin,date,percent_of_supply\narbitrum,2026-11-16,2.1\n").unwrap_err();
+        assert_eq!(error.to_string(), "Invalid unlock schedule on line 1: missing the coin_id column");
+
+        let error = parse_csv("coin_id,date,percent_of_supply\narbitrum,2026-11-16,2.1\narbitrum,16/11/2026,2.1\n").unwrap_err();
+        assert_eq!(error.to_string(), "Invalid unlock schedule on line 3: invalid date '16/11/2026', expected YYYY-MM-DD");
+
+        let error = parse_csv("coin_id,date,percent_of_supply\narbitrum,2026-11-16,lots\n").unwrap_err();
+        assert!(matches!(error, UnlockError::InvalidCsv { line: 2, .. }));
+    }
+
+    #[test]
+    fn test_parse_json() {
+        let json = r#"[{"coin_id": "ARBITRUM", "date": "2026-11-16", "percent_of_supply": 2.1, "note": "team"}]"#;
+        let events = parse_json(json).unwrap();
+        assert_eq!(events[0].coin_id, "arbitrum");
+        assert_eq!(events[0].note.as_deref(), Some("team"));
+        assert!(parse_json(r#"[{"coin_id": "arbitrum"}]"#).is_err());
+    }
+
+    #[test]
+    fn test_upcoming_keeps_the_window() {
+        let events = [
+            event("arbitrum", "2026-10-14", 1.0),
+            event("arbitrum", "2026-10-22", 2.0),
+            event("optimism", "2026-10-15", 3.0),
+            event("sui", "2026-10-23", 4.0),
+        ];
+        let listed = upcoming(&events, date("2026-10-15"), 7);
+        let dates: Vec<(&str, NaiveDate)> = listed.iter().map(|event| (event.coin_id.as_str(), event.date)).collect();
+        // Today and the seventh day are included, yesterday and the eighth day aren't
+        assert_eq!(dates, vec![("optimism", date("2026-10-15")), ("arbitrum", date("2026-10-22"))]);
+    }
+
+    #[test]
+    fn test_knowledge_round_trip() {
+        let event = UnlockEvent { tokens: Some(92_650_000.0), note: Some("team, advisors".to_string()), ..event("arbitrum", "2026-11-16", 2.1) };
+        let input = event.to_knowledge();
+        assert_eq!(input.source_id, "unlock-arbitrum-2026-11-16");
+        assert_eq!(input.tags, vec!["unlock", "arbitrum", "2026-11-16"]);
+        assert_eq!(input.content, "arbitrum unlock on 2026-11-16: 2.1% of supply, 92650000 tokens (team, advisors)");
+
+        let stamp = NaiveDateTime::default();
+        let stored = Knowledge { id: 1, user_id: 1, source_id: input.source_id, content: input.content, tags: input.tags, created_at: stamp, updated_at: stamp };
+        assert_eq!(UnlockEvent::from_knowledge(&stored), Some(event));
+    }
+
+    #[test]
+    fn test_parse_timeframe() {
+        assert_eq!(parse_timeframe("I'm holding ARB for 3 months").unwrap(), Timeframe { days: 90, label: "3 months".to_string() });
+        assert_eq!(parse_timeframe("any unlocks over the next 2 weeks?").unwrap().days, 14);
+        assert_eq!(parse_timeframe("within 1 year").unwrap().label, "1 year");
+        assert_eq!(parse_timeframe("are there unlocks for arb"), None);
+        assert_eq!(horizon_timeframe("Short").unwrap().days, 30);
+        assert_eq!(horizon_timeframe("someday"), None);
+    }
+
+    #[test]
+    fn test_detect_unlock_query() {
+        assert_eq!(detect_unlock_query("Are there any unlocks coming for ARB?"), Some("arb".to_string()));
+        assert_eq!(detect_unlock_query("vesting schedule of optimism"), Some("optimism".to_string()));
+        assert_eq!(detect_unlock_query("any big SUI unlocks soon"), Some("sui".to_string()));
+        assert_eq!(detect_unlock_query("any token unlocks coming up?"), None);
+        assert_eq!(detect_unlock_query("price of arb"), None);
+    }
+
+    #[test]
+    fn test_render_unlocks_warns_within_timeframe() {
+        let events = [event("arbitrum", "2026-10-20", 2.1), event("arbitrum", "2026-12-14", 1.0)];
+        let timeframe = Timeframe { days: 30, label: "1 month".to_string() };
+        let output = render_unlocks("arbitrum", &events, date("2026-10-15"), Some(&timeframe));
+        assert_eq!(
+            output,
+            "Upcoming arbitrum unlocks:\n\
+            - arbitrum unlock on 2026-10-20: 2.1% of supply (in 5 days)\n\
+            - arbitrum unlock on 2026-12-14: 1% of supply (in 60 days)\n\n\
+            Warning: 1 unlock releasing 2.1% of supply falls within your 1 month. New supply often weighs on the price \
+            around the unlock date."
+        );
+
+        let short = Timeframe { days: 3, label: "3 days".to_string() };
+        assert!(render_unlocks("arbitrum", &events, date("2026-10-15"), Some(&short)).ends_with("None of these fall within your 3 days."));
+        assert_eq!(
+            render_unlocks("arbitrum", &events, date("2027-01-01"), None),
+            "I don't know of any arbitrum unlocks in the next 90 days."
+        );
+    }
+
+    #[tokio::test]
+    async fn test_refresh_stores_upcoming_unlocks_as_knowledge() {
+        let Some(pool) = crate::db::testing::test_pool().await else { return };
+        let user = db::create_user(&pool, "alice", None).await.unwrap();
+        let dir = std::env::temp_dir().join(format!("agent-friend-unlocks-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let file = dir.join("unlocks.csv");
+        std::fs::write(&file, "coin_id,date,percent_of_supply\narbitrum,2026-11-16,2.1\narbitrum,2026-01-16,2.1\n").unwrap();
+
+        let mut manager = DataSourceManager::new(dir.join("sources")).unwrap();
+        manager.set_pool(pool.clone());
+        manager.set_user_id(user.id);
+        manager.register_plugin(Box::new(UnlockPlugin::on(|| NaiveDate::from_ymd_opt(2026, 10, 1).unwrap())));
+        let source = UnlockSource::File(file);
+        manager.add_source(&source.source_id(), "Unlocks", "", SOURCE_TYPE, 60, source.to_config()).await.unwrap();
+        assert_eq!(manager.refresh_all_sources().await.unwrap(), vec![source.source_id()]);
+
+        // The past unlock is dropped
+        let stored = db::get_knowledge_by_tag(&pool, user.id, UNLOCK_TAG).await.unwrap();
+        let events: Vec<UnlockEvent> = stored.iter().filter_map(UnlockEvent::from_knowledge).collect();
+        assert_eq!(events, vec![event("arbitrum", "2026-11-16", 2.1)]);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
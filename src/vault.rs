@@ -0,0 +1,430 @@
+use crate::config::Config;
+use crate::db::{self, DbError, Knowledge};
+use aes_gcm::aead::{Aead, KeyInit, Payload};
+use aes_gcm::{Aes256Gcm, Nonce};
+use argon2::{Algorithm, Argon2, Params, Version};
+use rand::RngCore;
+use sqlx::{Pool, Postgres};
+use std::collections::HashMap;
+use std::fs;
+use std::sync::{Arc, Mutex, RwLock};
+use thiserror::Error;
+
+/// Knowledge tagged this way is encrypted before it is stored
+pub const PRIVATE_TAG: &str = "private";
+
+/// Marks stored content as encrypted, followed by the argon2 cost, salt, nonce and AES-GCM ciphertext
+const SEALED_PREFIX: &str = "aes-gcm:";
+
+/// argon2id memory cost (log2 of KiB) for new encryptions, 32 MB per derivation
+pub const DEFAULT_COST: u8 = 15;
+
+/// Lowest memory cost argon2 accepts, 8 KiB
+pub const MIN_COST: u8 = 3;
+
+/// Highest memory cost a stored entry may ask for, so a corrupted row can't exhaust memory
+pub const MAX_COST: u8 = DEFAULT_COST + 2;
+
+const ARGON2_ITERATIONS: u32 = 2;
+const ARGON2_LANES: u32 = 1;
+const SALT_LEN: usize = 16;
+const NONCE_LEN: usize = 12;
+const KEY_LEN: usize = 32;
+
+/// Ciphers keyed by (cost, salt), argon2 is slow on purpose
+type KeyCache = Mutex<HashMap<(u8, [u8; SALT_LEN]), Arc<Aes256Gcm>>>;
+
+// Key for the private knowledge of this process, None until a passphrase is supplied
+static VAULT: RwLock<Option<Arc<Vault>>> = RwLock::new(None);
+
+#[derive(Debug, Error)]
+pub enum VaultError {
+    #[error("private knowledge needs a passphrase: set KNOWLEDGE_PASSPHRASE or a key file")]
+    NoKey,
+
+    #[error("the passphrase doesn't open this entry")]
+    WrongKey,
+
+    #[error("malformed encrypted content: {0}")]
+    Malformed(String),
+
+    #[error("can't read the key file {path}: {message}")]
+    KeyFile { path: String, message: String },
+
+    #[error("invalid key derivation cost: {0}")]
+    InvalidCost(u8),
+}
+
+/// A passphrase and the keys derived from it
+///
+/// New entries are sealed with this vault's salt; entries sealed under another salt or cost
+/// derive their own keys once, which are then reused.
+pub struct Vault {
+    passphrase: String,
+    cost: u8,
+    salt: [u8; SALT_LEN],
+    keys: KeyCache,
+}
+
+impl std::fmt::Debug for Vault {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Vault").field("cost", &self.cost).field("salt", &hex::encode(self.salt)).finish_non_exhaustive()
+    }
+}
+
+impl Vault {
+    /// A vault sealing with a fresh random salt
+    pub fn new(passphrase: &str) -> Self {
+        let mut salt = [0u8; SALT_LEN];
+        rand::thread_rng().fill_bytes(&mut salt);
+        Self {
+            passphrase: passphrase.to_string(),
+            cost: DEFAULT_COST,
+            salt,
+            keys: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Set the argon2 memory cost of new encryptions, lower values are only meant for tests
+    pub fn with_cost(mut self, cost: u8) -> Self {
+        self.cost = cost;
+        self
+    }
+
+    /// The same passphrase under a fresh salt, so re-sealed entries get new keys
+    pub fn rotated(&self) -> Self {
+        Self::new(&self.passphrase).with_cost(self.cost)
+    }
+
+    /// argon2 memory cost of new encryptions
+    pub fn cost(&self) -> u8 {
+        self.cost
+    }
+
+    fn cipher(&self, cost: u8, salt: [u8; SALT_LEN]) -> Result<Arc<Aes256Gcm>, VaultError> {
+        if let Some(cipher) = self.keys.lock().unwrap().get(&(cost, salt)) {
+            return Ok(cipher.clone());
+        }
+        if !(MIN_COST..=MAX_COST).contains(&cost) {
+            return Err(VaultError::InvalidCost(cost));
+        }
+
+        let params = Params::new(1 << cost, ARGON2_ITERATIONS, ARGON2_LANES, Some(KEY_LEN))
+            .map_err(|_| VaultError::InvalidCost(cost))?;
+        let mut key = [0u8; KEY_LEN];
+        Argon2::new(Algorithm::Argon2id, Version::V0x13, params)
+            .hash_password_into(self.passphrase.as_bytes(), &salt, &mut key)
+            .map_err(|_| VaultError::InvalidCost(cost))?;
+
+        let cipher = Arc::new(Aes256Gcm::new(&key.into()));
+        self.keys.lock().unwrap().insert((cost, salt), cipher.clone());
+        Ok(cipher)
+    }
+
+    /// Encrypt content for storage, a random nonce is stored alongside the ciphertext
+    ///
+    /// The cost and salt are authenticated with the ciphertext, so changing any stored field fails to open.
+    pub fn seal(&self, plaintext: &str) -> Result<String, VaultError> {
+        let cipher = self.cipher(self.cost, self.salt)?;
+        let mut nonce = [0u8; NONCE_LEN];
+        rand::thread_rng().fill_bytes(&mut nonce);
+
+        let header = format!("{}{}:{}:{}:", SEALED_PREFIX, self.cost, hex::encode(self.salt), hex::encode(nonce));
+        let ciphertext = cipher
+            .encrypt(&Nonce::from(nonce), Payload { msg: plaintext.as_bytes(), aad: header.as_bytes() })
+            .map_err(|_| VaultError::Malformed("content too long to encrypt".to_string()))?;
+        Ok(format!("{}{}", header, hex::encode(ciphertext)))
+    }
+
+    /// Decrypt stored content, failing with `WrongKey` when AES-GCM doesn't authenticate it
+    ///
+    /// A cost outside `MIN_COST..=MAX_COST` is refused before any key is derived.
+    pub fn open(&self, sealed: &str) -> Result<String, VaultError> {
+        let fields = sealed
+            .strip_prefix(SEALED_PREFIX)
+            .ok_or_else(|| VaultError::Malformed("missing prefix".to_string()))?;
+        let parts: Vec<&str> = fields.split(':').collect();
+        let [cost, salt, nonce, body] = parts.as_slice() else {
+            return Err(VaultError::Malformed(format!("expected 4 fields, found {}", parts.len())));
+        };
+        let cost = cost
+            .parse::<u8>()
+            .ok()
+            .filter(|cost| (MIN_COST..=MAX_COST).contains(cost))
+            .ok_or_else(|| VaultError::Malformed(format!("cost {} is outside {}..={}", cost, MIN_COST, MAX_COST)))?;
+        let salt: [u8; SALT_LEN] = decode_array(salt, "salt")?;
+        let nonce: [u8; NONCE_LEN] = decode_array(nonce, "nonce")?;
+        let ciphertext = hex::decode(body).map_err(|e| VaultError::Malformed(format!("ciphertext: {}", e)))?;
+
+        let cipher = self.cipher(cost, salt)?;
+        let header = &sealed[..sealed.len() - body.len()];
+        let plaintext = cipher
+            .decrypt(&Nonce::from(nonce), Payload { msg: &ciphertext, aad: header.as_bytes() })
+            .map_err(|_| VaultError::WrongKey)?;
+        String::from_utf8(plaintext).map_err(|_| VaultError::Malformed("plaintext is not UTF-8".to_string()))
+    }
+}
+
+fn decode_array<const N: usize>(text: &str, field: &str) -> Result<[u8; N], VaultError> {
+    hex::decode(text)
+        .ok()
+        .and_then(|bytes| bytes.try_into().ok())
+        .ok_or_else(|| VaultError::Malformed(format!("{} is not {} hex bytes", field, N)))
+}
+
+/// Whether stored content is encrypted
+pub fn is_sealed(content: &str) -> bool {
+    content.starts_with(SEALED_PREFIX)
+}
+
+/// Whether entries with these tags are encrypted at rest
+pub fn is_private(tags: &[String]) -> bool {
+    tags.iter().any(|tag| tag == PRIVATE_TAG)
+}
+
+/// Whether an entry is still encrypted after retrieval, because no key or the wrong key is loaded
+/// Locked entries are listed but never placed in a prompt
+pub fn is_locked(entry: &Knowledge) -> bool {
+    is_sealed(&entry.content)
+}
+
+/// Drop locked entries, for everything that feeds the model
+pub fn unlocked(entries: Vec<Knowledge>) -> Vec<Knowledge> {
+    entries.into_iter().filter(|entry| !is_locked(entry)).collect()
+}
+
+/// Use this vault for every later knowledge read and write
+pub fn unlock(vault: Vault) {
+    *VAULT.write().unwrap() = Some(Arc::new(vault));
+}
+
+/// Forget the key, private entries read from now on stay locked
+pub fn lock() {
+    *VAULT.write().unwrap() = None;
+}
+
+/// The vault in use, if a passphrase was supplied
+pub fn current() -> Option<Arc<Vault>> {
+    VAULT.read().unwrap().clone()
+}
+
+/// Content as it should be stored: encrypted for private entries, unchanged otherwise
+/// Private entries are refused rather than stored in plaintext when no key is loaded
+pub fn seal_for_storage(content: &str, tags: &[String]) -> Result<String, VaultError> {
+    if !is_private(tags) || is_sealed(content) {
+        return Ok(content.to_string());
+    }
+    current().ok_or(VaultError::NoKey)?.seal(content)
+}
+
+/// Content as read back: decrypted when the loaded key opens it, left sealed otherwise
+pub fn open_from_storage(content: String) -> String {
+    if !is_sealed(&content) {
+        return content;
+    }
+    match current().map(|vault| vault.open(&content)) {
+        Some(Ok(plaintext)) => plaintext,
+        _ => content,
+    }
+}
+
+/// Decrypt retrieved entries in place, see `open_from_storage`
+pub fn open_entries(entries: &mut [Knowledge]) {
+    for entry in entries {
+        entry.content = open_from_storage(std::mem::take(&mut entry.content));
+    }
+}
+
+/// Private entries re-encrypted by `rekey`, and those left as they were because no key opened them
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Rekeyed {
+    pub reencrypted: usize,
+    pub locked: usize,
+}
+
+/// Re-encrypt every private entry of a user under `new`, then use it for the rest of the process
+///
+/// Private entries stored in plaintext before a key was configured are encrypted too. All
+/// entries are rewritten in one transaction, so a failure leaves the old key working.
+pub async fn rekey(pool: &Pool<Postgres>, user_id: i32, new: Vault) -> Result<Rekeyed, DbError> {
+    let mut rekeyed = Rekeyed::default();
+    let mut contents = Vec::new();
+    for entry in db::get_knowledge_by_tag(pool, user_id, PRIVATE_TAG).await? {
+        if is_locked(&entry) {
+            rekeyed.locked += 1;
+        } else {
+            contents.push((entry.id, new.seal(&entry.content)?));
+        }
+    }
+    db::replace_knowledge_content(pool, user_id, &contents).await?;
+    rekeyed.reencrypted = contents.len();
+
+    unlock(new);
+    Ok(rekeyed)
+}
+
+/// The vault configured by `KNOWLEDGE_PASSPHRASE`, or by the contents of the key file
+pub fn from_config() -> Result<Option<Vault>, VaultError> {
+    let Ok(config) = Config::get_instance() else {
+        return Ok(None);
+    };
+    if let Some(passphrase) = &config.knowledge_passphrase {
+        return Ok(Some(Vault::new(passphrase)));
+    }
+    let Some(path) = &config.knowledge_key_file else {
+        return Ok(None);
+    };
+    let passphrase = fs::read_to_string(path).map_err(|e| VaultError::KeyFile {
+        path: path.display().to_string(),
+        message: e.to_string(),
+    })?;
+    let passphrase = passphrase.trim();
+    if passphrase.is_empty() {
+        return Err(VaultError::KeyFile { path: path.display().to_string(), message: "the file is empty".to_string() });
+    }
+    Ok(Some(Vault::new(passphrase)))
+}
+
+/// Serializes tests that install or clear the process-wide vault
+#[cfg(test)]
+pub(crate) static TEST_LOCK: tokio::sync::Mutex<()> = tokio::sync::Mutex::const_new(());
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Cheap derivations keep the tests fast
+    fn test_vault(passphrase: &str) -> Vault {
+        Vault::new(passphrase).with_cost(4)
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let vault = test_vault("correct horse");
+        let sealed = vault.seal("cold wallet holds 12 BTC").unwrap();
+        assert!(is_sealed(&sealed));
+        assert!(!sealed.contains("12 BTC"));
+        assert_eq!(vault.open(&sealed).unwrap(), "cold wallet holds 12 BTC");
+
+        // Every seal draws a new nonce
+        assert_ne!(vault.seal("cold wallet holds 12 BTC").unwrap(), sealed);
+        assert_eq!(vault.open(&vault.seal("").unwrap()).unwrap(), "");
+    }
+
+    #[test]
+    fn test_wrong_key_and_tampering() {
+        let sealed = test_vault("correct horse").seal("taxed at 45%").unwrap();
+        assert!(matches!(test_vault("battery staple").open(&sealed), Err(VaultError::WrongKey)));
+
+        // A flipped ciphertext byte fails authentication instead of decrypting to garbage
+        let vault = test_vault("correct horse");
+        let sealed = vault.seal("taxed at 45%").unwrap();
+        let mut parts: Vec<String> = sealed.split(':').map(str::to_string).collect();
+        let body = &mut parts[4];
+        let flipped = if body.starts_with('0') { "1" } else { "0" };
+        body.replace_range(..1, flipped);
+        assert!(matches!(vault.open(&parts.join(":")), Err(VaultError::WrongKey)));
+
+        assert!(matches!(vault.open("aes-gcm:4:zz"), Err(VaultError::Malformed(_))));
+    }
+
+    #[test]
+    fn test_stored_cost_is_bounded() {
+        let vault = test_vault("correct horse");
+        let sealed = vault.seal("taxed at 45%").unwrap();
+        let with_cost = |cost: &str| {
+            let mut parts: Vec<&str> = sealed.split(':').collect();
+            parts[1] = cost;
+            parts.join(":")
+        };
+
+        // Refused before a key is derived, a corrupted cost would otherwise allocate gigabytes
+        for cost in ["0", "2", "18", "40", "255", "-1", "x"] {
+            assert!(matches!(vault.open(&with_cost(cost)), Err(VaultError::Malformed(_))), "{}", cost);
+        }
+        assert!(vault.keys.lock().unwrap().keys().all(|(cost, _)| *cost == 4));
+
+        // An in-range cost that isn't the one sealed with derives another key and fails to authenticate
+        assert!(matches!(vault.open(&with_cost("5")), Err(VaultError::WrongKey)));
+        assert_eq!(vault.open(&sealed).unwrap(), "taxed at 45%");
+    }
+
+    #[test]
+    fn test_rotated_vault_opens_old_entries() {
+        let vault = test_vault("correct horse");
+        let old = vault.seal("note").unwrap();
+        let rotated = vault.rotated();
+        let new = rotated.seal("note").unwrap();
+        assert_ne!(old.split(':').nth(2), new.split(':').nth(2));
+        assert_eq!(rotated.open(&old).unwrap(), "note");
+        assert_eq!(vault.open(&new).unwrap(), "note");
+    }
+
+    #[tokio::test]
+    async fn test_storage_needs_a_key_for_private_entries() {
+        let _guard = TEST_LOCK.lock().await;
+        lock();
+        let private = vec!["taxes".to_string(), PRIVATE_TAG.to_string()];
+        assert!(matches!(seal_for_storage("taxed at 45%", &private), Err(VaultError::NoKey)));
+        assert_eq!(seal_for_storage("public note", &["defi".to_string()]).unwrap(), "public note");
+
+        unlock(test_vault("correct horse"));
+        let sealed = seal_for_storage("taxed at 45%", &private).unwrap();
+        assert!(is_sealed(&sealed));
+        assert_eq!(open_from_storage(sealed.clone()), "taxed at 45%");
+
+        // Without the key the entry reads back sealed, which marks it locked
+        lock();
+        assert_eq!(open_from_storage(sealed.clone()), sealed);
+    }
+
+    #[tokio::test]
+    async fn test_private_knowledge_in_the_database() {
+        let Some(pool) = crate::db::testing::test_pool().await else { return };
+        let _guard = TEST_LOCK.lock().await;
+        let user = db::create_user(&pool, "alice", None).await.unwrap();
+        let private = vec![PRIVATE_TAG.to_string(), "taxes".to_string()];
+
+        unlock(test_vault("correct horse"));
+        db::create_knowledge(&pool, user.id, "tax-notes", "taxed at 45% on gains", &private).await.unwrap();
+        db::create_knowledge(&pool, user.id, "eth-notes", "staking on Lido", &["taxes".to_string()]).await.unwrap();
+
+        // Only the private entry is encrypted at rest, reads decrypt it transparently
+        let raw: String = sqlx::query_scalar("SELECT content FROM knowledge WHERE source_id = 'tax-notes'")
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+        assert!(is_sealed(&raw) && !raw.contains("45%"));
+        let entry = db::get_knowledge_by_source_id(&pool, user.id, "tax-notes").await.unwrap().unwrap();
+        assert_eq!(entry.content, "taxed at 45% on gains");
+
+        // Under the wrong key the entry is listed as locked and kept out of prompts
+        unlock(test_vault("battery staple"));
+        let entries = db::get_knowledge_by_tag(&pool, user.id, "taxes").await.unwrap();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries.iter().filter(|entry| is_locked(entry)).count(), 1);
+        let visible = unlocked(entries);
+        assert_eq!(visible.len(), 1);
+        assert_eq!(visible[0].content, "staking on Lido");
+
+        // Without a key private entries can't be saved
+        lock();
+        let error = db::upsert_knowledge(&pool, user.id, "wallets", "12 BTC in cold storage", &private).await.unwrap_err();
+        assert!(matches!(error, DbError::Encryption(VaultError::NoKey)));
+
+        // Rekeying re-encrypts under the new key, the old one no longer opens the entry
+        let old = test_vault("correct horse");
+        unlock(old.rotated());
+        let rekeyed = rekey(&pool, user.id, test_vault("new passphrase")).await.unwrap();
+        assert_eq!(rekeyed, Rekeyed { reencrypted: 1, locked: 0 });
+        let raw_after: String = sqlx::query_scalar("SELECT content FROM knowledge WHERE source_id = 'tax-notes'")
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+        assert_ne!(raw_after, raw);
+        assert!(matches!(old.open(&raw_after), Err(VaultError::WrongKey)));
+        let entry = db::get_knowledge_by_source_id(&pool, user.id, "tax-notes").await.unwrap().unwrap();
+        assert_eq!(entry.content, "taxed at 45% on gains");
+        lock();
+    }
+}
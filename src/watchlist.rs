@@ -0,0 +1,275 @@
+use crate::db::{self, DbError, WatchlistEntry};
+use crate::offline;
+use crate::price_fetcher;
+use crate::price_format::format_price;
+use crate::render::Table;
+use chrono::NaiveDateTime;
+use regex::Regex;
+use sqlx::{Pool, Postgres};
+use std::sync::OnceLock;
+
+/// Widest cell of the watchlist table, long notes are cut
+const NOTE_COLUMN_WIDTH: usize = 60;
+
+/// A change to the watchlist, or a request to show it
+#[derive(Debug, Clone, PartialEq)]
+pub enum WatchlistCommand {
+    List,
+    Add { coin: String, note: Option<String> },
+    Remove { coin: String },
+    /// Replace the note of an entry, None clears it
+    Annotate { coin: String, note: Option<String> },
+}
+
+/// A watched coin with the price used to show it
+#[derive(Debug, Clone, PartialEq)]
+pub struct WatchlistRow {
+    pub coin_id: String,
+    pub note: Option<String>,
+    pub price_usd: Option<f64>,
+    pub change_24h_pct: Option<f64>,
+    /// Set when the price comes from the local price history instead of a live quote
+    pub as_of: Option<NaiveDateTime>,
+}
+
+/// Parse the arguments of `/watchlist`
+pub fn parse_command_args(args: &[&str]) -> Option<WatchlistCommand> {
+    let note = |words: &[&str]| Some(words.join(" ")).filter(|note| !note.is_empty());
+
+    match args {
+        [] | ["list"] => Some(WatchlistCommand::List),
+        ["add", coin, rest @ ..] => Some(WatchlistCommand::Add { coin: coin.to_string(), note: note(rest) }),
+        ["remove", coin] => Some(WatchlistCommand::Remove { coin: coin.to_string() }),
+        ["note", coin, rest @ ..] => Some(WatchlistCommand::Annotate { coin: coin.to_string(), note: note(rest) }),
+        _ => None,
+    }
+}
+
+fn add_regex() -> &'static Regex {
+    static REGEX: OnceLock<Regex> = OnceLock::new();
+    REGEX.get_or_init(|| {
+        Regex::new(r"(?i)^\s*(?:please\s+)?(?:add|put)\s+(.+?)\s+(?:to|on)\s+(?:my\s+)?watch\s?list\b(?:\s*[:,-]\s*(.+))?").unwrap()
+    })
+}
+
+fn remove_regex() -> &'static Regex {
+    static REGEX: OnceLock<Regex> = OnceLock::new();
+    REGEX.get_or_init(|| {
+        Regex::new(r"(?i)^\s*(?:please\s+)?(?:remove|delete|drop|take)\s+(.+?)\s+(?:from|off)\s+(?:of\s+)?(?:my\s+)?watch\s?list\b").unwrap()
+    })
+}
+
+fn annotate_regex() -> &'static Regex {
+    static REGEX: OnceLock<Regex> = OnceLock::new();
+    REGEX.get_or_init(|| {
+        Regex::new(r"(?i)^\s*(?:annotate|note\s+(?:for|on))\s+(.+?)\s+(?:on|in)\s+(?:my\s+)?watch\s?list\s*[:,-]\s*(.+)").unwrap()
+    })
+}
+
+fn list_regex() -> &'static Regex {
+    static REGEX: OnceLock<Regex> = OnceLock::new();
+    REGEX.get_or_init(|| {
+        Regex::new(r"(?i)^\s*(?:(?:show|list|view|see|display|check)\b.*|what'?s\s+on\s+|what\s+is\s+on\s+)?(?:my\s+)?watch\s?list\b").unwrap()
+    })
+}
+
+/// The coin as written, without a leading "the" or trailing punctuation
+fn clean_coin(coin: &str) -> String {
+    let coin = coin.trim().trim_end_matches(['?', '!', '.', ',']);
+    let coin = coin.strip_prefix("the ").unwrap_or(coin);
+    coin.trim().to_string()
+}
+
+fn clean_note(note: Option<regex::Match>) -> Option<String> {
+    note.map(|note| note.as_str().trim().to_string()).filter(|note| !note.is_empty())
+}
+
+/// Parse "add SOL to my watchlist: waiting for $120", "remove SOL from my watchlist",
+/// "annotate SOL on my watchlist: ..." and "show my watchlist"
+pub fn parse_chat_message(message: &str) -> Option<WatchlistCommand> {
+    if let Some(captures) = annotate_regex().captures(message) {
+        return Some(WatchlistCommand::Annotate { coin: clean_coin(&captures[1]), note: clean_note(captures.get(2)) });
+    }
+    if let Some(captures) = add_regex().captures(message) {
+        return Some(WatchlistCommand::Add { coin: clean_coin(&captures[1]), note: clean_note(captures.get(2)) });
+    }
+    if let Some(captures) = remove_regex().captures(message) {
+        return Some(WatchlistCommand::Remove { coin: clean_coin(&captures[1]) });
+    }
+    if list_regex().is_match(message) {
+        return Some(WatchlistCommand::List);
+    }
+    None
+}
+
+/// The chat message that adds a coin, replayed once an unknown name is resolved
+pub fn add_message(coin: &str, note: Option<&str>) -> String {
+    match note {
+        Some(note) => format!("add {} to my watchlist: {}", coin, note),
+        None => format!("add {} to my watchlist", coin),
+    }
+}
+
+/// Price entries with one batch of live quotes, falling back to the last known prices
+pub async fn price_entries(pool: &Pool<Postgres>, entries: Vec<WatchlistEntry>) -> Result<Vec<WatchlistRow>, DbError> {
+    let coin_ids: Vec<&str> = entries.iter().map(|entry| entry.coin_id.as_str()).collect();
+
+    let quotes = if offline::is_offline() || coin_ids.is_empty() {
+        Default::default()
+    } else {
+        match price_fetcher::fetch_multiple_coin_quotes(&coin_ids).await {
+            Ok(quotes) => quotes,
+            Err(e) => {
+                eprintln!("Error fetching live prices for watchlist: {}", e);
+                Default::default()
+            }
+        }
+    };
+
+    let mut rows = Vec::with_capacity(entries.len());
+    for entry in entries {
+        let row = match quotes.get(&entry.coin_id) {
+            Some(quote) => {
+                if let Err(e) = db::save_price_point(pool, &entry.coin_id, quote.price_usd).await {
+                    eprintln!("Error saving price history for {}: {}", entry.coin_id, e);
+                }
+                WatchlistRow {
+                    coin_id: entry.coin_id,
+                    note: entry.note,
+                    price_usd: Some(quote.price_usd),
+                    change_24h_pct: quote.change_24h_pct,
+                    as_of: None,
+                }
+            },
+            None => {
+                let point = db::get_latest_price_point(pool, &entry.coin_id).await?;
+                WatchlistRow {
+                    coin_id: entry.coin_id,
+                    note: entry.note,
+                    price_usd: point.as_ref().map(|p| p.price_usd),
+                    change_24h_pct: None,
+                    as_of: point.map(|p| p.fetched_at),
+                }
+            },
+        };
+        rows.push(row);
+    }
+
+    Ok(rows)
+}
+
+/// Render the watchlist with prices, 24h changes and notes
+pub fn render_watchlist(rows: &[WatchlistRow]) -> String {
+    if rows.is_empty() {
+        return "Your watchlist is empty. Add a coin with /watchlist add <coin> [note] or say \"add SOL to my watchlist\".".to_string();
+    }
+
+    let mut table = Table::new(["Coin", "Price", "24h", "Note"]).max_width(NOTE_COLUMN_WIDTH);
+    for row in rows {
+        let price = row.price_usd.map(format_price).unwrap_or_else(|| "n/a".to_string());
+        let change = match (row.change_24h_pct, row.as_of) {
+            (Some(change), None) => format!("{:+.2}%", change),
+            _ => "n/a".to_string(),
+        };
+        // Where the price came from matters more than the user's note
+        let note = match (row.price_usd, row.as_of) {
+            (Some(_), Some(as_of)) => format!("last known price, as of {} UTC", as_of.format("%Y-%m-%d %H:%M")),
+            (None, _) => "no price available".to_string(),
+            (Some(_), None) => String::new(),
+        };
+        let note = match &row.note {
+            Some(user_note) if note.is_empty() => user_note.clone(),
+            Some(user_note) => format!("{}; {}", user_note, note),
+            None => note,
+        };
+        table.push_row([row.coin_id.clone(), price, change, note]);
+    }
+
+    format!("Your watchlist ({}):\n{}", rows.len(), table.render())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn row(coin_id: &str, price_usd: Option<f64>, change_24h_pct: Option<f64>) -> WatchlistRow {
+        WatchlistRow { coin_id: coin_id.to_string(), note: None, price_usd, change_24h_pct, as_of: None }
+    }
+
+    #[test]
+    fn test_parse_command_args() {
+        assert_eq!(parse_command_args(&[]), Some(WatchlistCommand::List));
+        assert_eq!(
+            parse_command_args(&["add", "sol", "waiting", "for", "$120"]),
+            Some(WatchlistCommand::Add { coin: "sol".to_string(), note: Some("waiting for $120".to_string()) })
+        );
+        assert_eq!(parse_command_args(&["add", "sol"]), Some(WatchlistCommand::Add { coin: "sol".to_string(), note: None }));
+        assert_eq!(parse_command_args(&["remove", "sol"]), Some(WatchlistCommand::Remove { coin: "sol".to_string() }));
+        assert_eq!(parse_command_args(&["note", "sol"]), Some(WatchlistCommand::Annotate { coin: "sol".to_string(), note: None }));
+        assert_eq!(parse_command_args(&["remove"]), None);
+    }
+
+    #[test]
+    fn test_parse_chat_message() {
+        assert_eq!(
+            parse_chat_message("Add Solana to my watchlist: waiting for $120"),
+            Some(WatchlistCommand::Add { coin: "Solana".to_string(), note: Some("waiting for $120".to_string()) })
+        );
+        assert_eq!(
+            parse_chat_message("please put the pepe on my watch list"),
+            Some(WatchlistCommand::Add { coin: "pepe".to_string(), note: None })
+        );
+        assert_eq!(
+            parse_chat_message("take LINK off my watchlist"),
+            Some(WatchlistCommand::Remove { coin: "LINK".to_string() })
+        );
+        assert_eq!(
+            parse_chat_message("annotate sol on my watchlist: breakout above $150"),
+            Some(WatchlistCommand::Annotate { coin: "sol".to_string(), note: Some("breakout above $150".to_string()) })
+        );
+        assert_eq!(parse_chat_message("Show my watchlist"), Some(WatchlistCommand::List));
+        assert_eq!(parse_chat_message("what's on my watchlist?"), Some(WatchlistCommand::List));
+        assert_eq!(parse_chat_message("watchlist"), Some(WatchlistCommand::List));
+    }
+
+    #[test]
+    fn test_parse_chat_ignores_other_messages() {
+        assert_eq!(parse_chat_message("Should I add more ETH?"), None);
+        assert_eq!(parse_chat_message("Is a watchlist a good idea for beginners?"), None);
+    }
+
+    #[test]
+    fn test_add_message_round_trips() {
+        assert_eq!(
+            parse_chat_message(&add_message("pepe", Some("meme bet"))),
+            Some(WatchlistCommand::Add { coin: "pepe".to_string(), note: Some("meme bet".to_string()) })
+        );
+    }
+
+    #[test]
+    fn test_render_watchlist() {
+        let mut solana = row("solana", Some(142.1), Some(2.4512));
+        solana.note = Some("waiting for $120".to_string());
+        let mut cached = row("chainlink", Some(14.2), None);
+        cached.as_of = NaiveDateTime::parse_from_str("2025-09-24 10:00:00", "%Y-%m-%d %H:%M:%S").ok();
+        let rows = vec![solana, row("ethereum", Some(3120.5), None), cached, row("obscure", None, None)];
+
+        let output = render_watchlist(&rows);
+
+        assert_eq!(
+            output,
+            "Your watchlist (4):\n\
+            Coin          Price     24h  Note\n\
+            ---------  --------  ------  --------------------------------------------\n\
+            solana      $142.10  +2.45%  waiting for $120\n\
+            ethereum   $3120.50     n/a\n\
+            chainlink    $14.20     n/a  last known price, as of 2025-09-24 10:00 UTC\n\
+            obscure         n/a     n/a  no price available"
+        );
+    }
+
+    #[test]
+    fn test_render_empty_watchlist() {
+        assert!(render_watchlist(&[]).contains("watchlist is empty"));
+    }
+}
@@ -0,0 +1,525 @@
+use crate::config::Config;
+use crate::db::{self, DbError, MessageRole};
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use sqlx::{Pool, Postgres};
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::Duration;
+use tokio::sync::{Notify, watch};
+use tokio::task::JoinHandle;
+
+/// Where queued messages are mirrored when `MESSAGE_SPILL_FILE` isn't set
+pub const SPILL_FILE: &str = "pending_messages.jsonl";
+
+/// Wait before the first retry after the database went away
+pub const INITIAL_BACKOFF: Duration = Duration::from_secs(1);
+
+/// Longest wait between two retries
+pub const MAX_BACKOFF: Duration = Duration::from_secs(60);
+
+/// A message waiting to be saved, one line of the spill file
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct QueuedMessage {
+    /// Taken when the message was saved, messages are written in this order
+    pub seq: u64,
+    pub user_id: i32,
+    pub role: MessageRole,
+    pub content: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub prompt_variant: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub knowledge_sources: Vec<String>,
+    /// An answer cut off mid-stream, see `db::save_assistant_message`
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub truncated: bool,
+}
+
+/// What happened to a saved message
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Saved {
+    Written,
+    /// The database was unreachable, or earlier messages of the conversation are still
+    /// queued; the message is written when the database is back
+    Queued,
+}
+
+/// Where messages are written
+#[async_trait]
+pub trait MessageStore: Send + Sync {
+    async fn insert(&self, message: &QueuedMessage) -> Result<(), DbError>;
+
+    /// Whether writes are worth retrying
+    async fn healthy(&self) -> bool;
+}
+
+/// Writes messages to the `messages` table
+pub struct PgMessageStore {
+    pool: Pool<Postgres>,
+}
+
+impl PgMessageStore {
+    pub fn new(pool: Pool<Postgres>) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait]
+impl MessageStore for PgMessageStore {
+    async fn insert(&self, message: &QueuedMessage) -> Result<(), DbError> {
+        if message.prompt_variant.is_none() && message.knowledge_sources.is_empty() && !message.truncated {
+            return db::save_message(&self.pool, message.user_id, message.role, &message.content).await;
+        }
+        db::save_assistant_message(
+            &self.pool,
+            message.user_id,
+            &message.content,
+            message.prompt_variant.as_deref(),
+            &message.knowledge_sources,
+            message.truncated,
+        )
+        .await
+    }
+
+    async fn healthy(&self) -> bool {
+        sqlx::query("SELECT 1").execute(&self.pool).await.is_ok()
+    }
+}
+
+/// Outcome of writing out the queue
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Flushed {
+    /// Everything queued was written, or dropped for a permanent error
+    Complete,
+    /// The database went away again, the rest stays queued
+    Interrupted,
+}
+
+/// Messages whose save failed because the database was unreachable, retried in order
+///
+/// A failed save is queued and mirrored to the spill file, and a background worker writes the
+/// queue out once the store's health check passes again, backing off between checks. Later
+/// messages of a conversation with queued ones queue behind them so the conversation keeps its
+/// order. Starting the queue drains a spill file left by a process that stopped with messages
+/// queued.
+pub struct WriteQueue {
+    store: Arc<dyn MessageStore>,
+    spill_file: Option<PathBuf>,
+    /// Sorted by `seq`
+    pending: Mutex<Vec<QueuedMessage>>,
+    next_seq: AtomicU64,
+    /// Held while writing so queued messages and new ones don't overtake each other
+    writing: tokio::sync::Mutex<()>,
+    wake: Notify,
+    stop: watch::Sender<bool>,
+    worker: tokio::sync::Mutex<Option<JoinHandle<()>>>,
+}
+
+impl WriteQueue {
+    /// Create a queue writing to `store` and mirrored to `spill_file`, loading the messages
+    /// already in the file; `start_worker` retries them in the background
+    pub fn new(store: Arc<dyn MessageStore>, spill_file: Option<PathBuf>) -> Self {
+        let pending = spill_file.as_deref().map(read_spill).unwrap_or_default();
+        let next_seq = pending.last().map_or(1, |message| message.seq + 1);
+        let (stop, _) = watch::channel(false);
+        Self {
+            store,
+            spill_file,
+            pending: Mutex::new(pending),
+            next_seq: AtomicU64::new(next_seq),
+            writing: tokio::sync::Mutex::new(()),
+            wake: Notify::new(),
+            stop,
+            worker: tokio::sync::Mutex::new(None),
+        }
+    }
+
+    /// Start the retry worker on the current runtime, backing off from `initial` up to `max`
+    /// between failed attempts
+    pub async fn start_worker(self: &Arc<Self>, initial: Duration, max: Duration) {
+        let queue = self.clone();
+        let mut stop = self.stop.subscribe();
+        let worker = tokio::spawn(async move {
+            let mut backoff = initial;
+            loop {
+                if queue.pending() == 0 {
+                    tokio::select! {
+                        _ = queue.wake.notified() => {},
+                        _ = stop.changed() => return,
+                    }
+                    continue;
+                }
+                let flushed = if queue.store.healthy().await { queue.flush().await } else { Flushed::Interrupted };
+                if flushed == Flushed::Complete {
+                    backoff = initial;
+                    continue;
+                }
+                tokio::select! {
+                    _ = tokio::time::sleep(backoff) => {},
+                    _ = stop.changed() => return,
+                }
+                backoff = (backoff * 2).min(max);
+            }
+        });
+        *self.worker.lock().await = Some(worker);
+    }
+
+    /// Save a message, queueing it when the database is unreachable
+    ///
+    /// Errors that retrying can't fix, like a constraint violation, are returned instead.
+    pub async fn save(
+        &self,
+        user_id: i32,
+        role: MessageRole,
+        content: &str,
+        prompt_variant: Option<&str>,
+        knowledge_sources: &[String],
+        truncated: bool,
+    ) -> Result<Saved, DbError> {
+        let _writing = self.writing.lock().await;
+        let message = QueuedMessage {
+            seq: self.next_seq.fetch_add(1, Ordering::SeqCst),
+            user_id,
+            role,
+            content: content.to_string(),
+            prompt_variant: prompt_variant.map(str::to_string),
+            knowledge_sources: knowledge_sources.to_vec(),
+            truncated,
+        };
+
+        // Earlier messages of the conversation go first, even with no worker running
+        if self.has_pending(user_id) {
+            self.flush_locked().await;
+        }
+        if !self.has_pending(user_id) {
+            match self.store.insert(&message).await {
+                Ok(()) => return Ok(Saved::Written),
+                Err(e) if e.is_transient() => eprintln!("Queueing message after a failed save: {}", e),
+                Err(e) => return Err(e),
+            }
+        }
+
+        self.pending.lock().unwrap().push(message);
+        self.spill();
+        self.wake.notify_one();
+        Ok(Saved::Queued)
+    }
+
+    /// Write the queued messages in order, stopping at the first one the database is unreachable for
+    pub async fn flush(&self) -> Flushed {
+        let _writing = self.writing.lock().await;
+        self.flush_locked().await
+    }
+
+    async fn flush_locked(&self) -> Flushed {
+        let mut outcome = Flushed::Complete;
+        loop {
+            let Some(message) = self.pending.lock().unwrap().first().cloned() else { break };
+            match self.store.insert(&message).await {
+                Ok(()) => {},
+                Err(e) if e.is_transient() => {
+                    outcome = Flushed::Interrupted;
+                    break;
+                },
+                // Retrying won't help, and keeping it would hold up the conversation forever
+                Err(e) => eprintln!("Dropping queued message {} of user {}: {}", message.seq, message.user_id, e),
+            }
+            self.pending.lock().unwrap().remove(0);
+        }
+        self.spill();
+        outcome
+    }
+
+    /// Messages waiting to be written
+    pub fn pending(&self) -> usize {
+        self.pending.lock().unwrap().len()
+    }
+
+    fn has_pending(&self, user_id: i32) -> bool {
+        self.pending.lock().unwrap().iter().any(|message| message.user_id == user_id)
+    }
+
+    /// Rewrite the spill file with the queue, removing it once the queue is empty
+    fn spill(&self) {
+        let Some(path) = &self.spill_file else { return };
+        let pending = self.pending.lock().unwrap().clone();
+        if let Err(e) = write_spill(path, &pending) {
+            eprintln!("Error writing {}: {}", path.display(), e);
+        }
+    }
+
+    /// Stop the worker and try once more to write what's queued, the rest stays in the spill file
+    pub async fn shutdown(&self) {
+        self.stop.send_replace(true);
+        if let Some(worker) = self.worker.lock().await.take()
+            && let Err(e) = worker.await
+        {
+            eprintln!("Write queue worker failed: {}", e);
+        }
+        if self.pending() > 0 && self.store.healthy().await {
+            self.flush().await;
+        }
+    }
+}
+
+/// Queued messages in a spill file, oldest first; unreadable lines are skipped
+pub fn read_spill(path: &Path) -> Vec<QueuedMessage> {
+    let Ok(contents) = fs::read_to_string(path) else {
+        return Vec::new();
+    };
+    let mut messages: Vec<QueuedMessage> = contents
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .filter_map(|line| match serde_json::from_str(line) {
+            Ok(message) => Some(message),
+            Err(e) => {
+                eprintln!("Skipping unreadable line of {}: {}", path.display(), e);
+                None
+            },
+        })
+        .collect();
+    messages.sort_by_key(|message| message.seq);
+    messages.dedup_by_key(|message| message.seq);
+    messages
+}
+
+/// Replace the spill file with `messages`, one JSON object per line
+///
+/// Written to a temporary file first, so a crash mid-write leaves the previous file.
+fn write_spill(path: &Path, messages: &[QueuedMessage]) -> std::io::Result<()> {
+    if messages.is_empty() {
+        return match fs::remove_file(path) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e),
+            _ => Ok(()),
+        };
+    }
+    let temporary = path.with_extension("jsonl.tmp");
+    let mut file = fs::File::create(&temporary)?;
+    for message in messages {
+        let line = serde_json::to_string(message).map_err(std::io::Error::other)?;
+        writeln!(file, "{}", line)?;
+    }
+    file.sync_all()?;
+    fs::rename(&temporary, path)
+}
+
+static QUEUE: OnceLock<Arc<WriteQueue>> = OnceLock::new();
+
+/// Start the process's queue on `pool`, draining the configured spill file in the background
+pub async fn start(pool: &Pool<Postgres>) -> Arc<WriteQueue> {
+    if let Some(queue) = QUEUE.get() {
+        return queue.clone();
+    }
+    let spill_file = Config::get_instance()
+        .map(|config| config.message_spill_file.clone())
+        .unwrap_or_else(|_| PathBuf::from(SPILL_FILE));
+    let queue = Arc::new(WriteQueue::new(Arc::new(PgMessageStore::new(pool.clone())), Some(spill_file)));
+    if queue.pending() > 0 {
+        println!("Saving {} messages left from the last session...", queue.pending());
+    }
+    queue.start_worker(INITIAL_BACKOFF, MAX_BACKOFF).await;
+    queue.wake.notify_one();
+    QUEUE.get_or_init(|| queue).clone()
+}
+
+/// The queue started for this process, None when messages are written directly
+pub fn configured() -> Option<Arc<WriteQueue>> {
+    QUEUE.get().cloned()
+}
+
+/// Stop the process's queue before it exits
+pub async fn shutdown() {
+    if let Some(queue) = QUEUE.get() {
+        queue.shutdown().await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::AtomicBool;
+
+    /// A database that goes up and down, recording what was written
+    #[derive(Default)]
+    struct FlakyStore {
+        down: AtomicBool,
+        /// Fail every other insert while true, even when up
+        flapping: AtomicBool,
+        attempts: AtomicU64,
+        written: Mutex<Vec<QueuedMessage>>,
+    }
+
+    impl FlakyStore {
+        fn set_down(&self, down: bool) {
+            self.down.store(down, Ordering::SeqCst);
+        }
+
+        fn contents(&self) -> Vec<String> {
+            self.written.lock().unwrap().iter().map(|message| message.content.clone()).collect()
+        }
+    }
+
+    #[async_trait]
+    impl MessageStore for FlakyStore {
+        async fn insert(&self, message: &QueuedMessage) -> Result<(), DbError> {
+            let attempt = self.attempts.fetch_add(1, Ordering::SeqCst);
+            if self.down.load(Ordering::SeqCst) || (self.flapping.load(Ordering::SeqCst) && attempt.is_multiple_of(2)) {
+                return Err(DbError::Connection("connection reset by peer".to_string()));
+            }
+            if message.content == "bad" {
+                return Err(DbError::Constraint("messages_role_check".to_string()));
+            }
+            self.written.lock().unwrap().push(message.clone());
+            Ok(())
+        }
+
+        async fn healthy(&self) -> bool {
+            !self.down.load(Ordering::SeqCst)
+        }
+    }
+
+    fn spill_path() -> PathBuf {
+        std::env::temp_dir().join(format!("agent-friend-spill-{}.jsonl", uuid::Uuid::new_v4()))
+    }
+
+    async fn save(queue: &WriteQueue, user_id: i32, content: &str) -> Saved {
+        queue.save(user_id, MessageRole::User, content, None, &[], false).await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_saves_directly_while_the_database_is_up() {
+        let store = Arc::new(FlakyStore::default());
+        let path = spill_path();
+        let queue = WriteQueue::new(store.clone(), Some(path.clone()));
+
+        assert_eq!(save(&queue, 1, "hello").await, Saved::Written);
+        assert_eq!(store.contents(), ["hello"]);
+        assert_eq!(queue.pending(), 0);
+        assert!(!path.exists());
+    }
+
+    #[tokio::test]
+    async fn test_failed_saves_are_spilled_and_keep_their_order() {
+        let store = Arc::new(FlakyStore::default());
+        let path = spill_path();
+        let queue = WriteQueue::new(store.clone(), Some(path.clone()));
+
+        store.set_down(true);
+        assert_eq!(save(&queue, 1, "first").await, Saved::Queued);
+        assert_eq!(
+            queue.save(1, MessageRole::Assistant, "second", Some("terse"), &["arb-research".to_string()], true).await.unwrap(),
+            Saved::Queued
+        );
+        assert_eq!(save(&queue, 2, "other user").await, Saved::Queued);
+
+        let spilled = read_spill(&path);
+        assert_eq!(spilled.iter().map(|message| message.seq).collect::<Vec<_>>(), [1, 2, 3]);
+        assert_eq!(spilled[1].prompt_variant.as_deref(), Some("terse"));
+        assert_eq!(spilled[1].knowledge_sources, ["arb-research"]);
+        assert!(spilled[1].truncated && !spilled[0].truncated);
+
+        // Back up: the next save writes the conversation's queued messages before itself
+        store.set_down(false);
+        assert_eq!(save(&queue, 1, "third").await, Saved::Written);
+        assert_eq!(store.contents(), ["first", "second", "other user", "third"]);
+        assert_eq!(queue.pending(), 0);
+        assert!(!path.exists());
+    }
+
+    #[tokio::test]
+    async fn test_flapping_database_is_retried_in_order() {
+        let store = Arc::new(FlakyStore::default());
+        let queue = Arc::new(WriteQueue::new(store.clone(), Some(spill_path())));
+        queue.start_worker(Duration::from_millis(5), Duration::from_millis(20)).await;
+
+        store.set_down(true);
+        for content in ["a", "b", "c", "d"] {
+            assert_eq!(save(&queue, 1, content).await, Saved::Queued);
+        }
+        // Up, but every other write fails
+        store.flapping.store(true, Ordering::SeqCst);
+        store.set_down(false);
+
+        tokio::time::timeout(Duration::from_secs(5), async {
+            while queue.pending() > 0 {
+                tokio::time::sleep(Duration::from_millis(5)).await;
+            }
+        })
+        .await
+        .expect("the worker should write the queue out");
+        assert_eq!(store.contents(), ["a", "b", "c", "d"]);
+        queue.shutdown().await;
+    }
+
+    #[tokio::test]
+    async fn test_spill_file_is_drained_on_start() {
+        let path = spill_path();
+        let store = Arc::new(FlakyStore::default());
+        store.set_down(true);
+        let crashed = WriteQueue::new(store.clone(), Some(path.clone()));
+        save(&crashed, 1, "before the crash").await;
+        save(&crashed, 1, "also before").await;
+        drop(crashed);
+        // A corrupt line from a half-written file doesn't lose the rest
+        fs::write(&path, format!("{}{{\"seq\": 9, \"user_id\"\n", fs::read_to_string(&path).unwrap())).unwrap();
+
+        store.set_down(false);
+        let restarted = Arc::new(WriteQueue::new(store.clone(), Some(path.clone())));
+        assert_eq!(restarted.pending(), 2);
+        restarted.start_worker(Duration::from_millis(5), Duration::from_millis(20)).await;
+        restarted.wake.notify_one();
+        tokio::time::timeout(Duration::from_secs(5), async {
+            while restarted.pending() > 0 {
+                tokio::time::sleep(Duration::from_millis(5)).await;
+            }
+        })
+        .await
+        .expect("the spilled messages should be written");
+
+        // New messages get later sequence numbers than the drained ones
+        save(&restarted, 1, "after").await;
+        let seqs: Vec<u64> = store.written.lock().unwrap().iter().map(|message| message.seq).collect();
+        assert_eq!(seqs, [1, 2, 3]);
+        assert_eq!(store.contents(), ["before the crash", "also before", "after"]);
+        assert!(!path.exists());
+        restarted.shutdown().await;
+    }
+
+    #[tokio::test]
+    async fn test_connection_errors_are_transient() {
+        let Some(pool) = crate::db::testing::test_pool().await else { return };
+        let user = db::create_user(&pool, "alice", None).await.unwrap();
+        let queue = WriteQueue::new(Arc::new(PgMessageStore::new(pool.clone())), None);
+        assert_eq!(queue.save(user.id, MessageRole::User, "hello", None, &[], false).await.unwrap(), Saved::Written);
+
+        // Constraint violations aren't retried
+        let error = db::save_message(&pool, -1, MessageRole::User, "orphan").await.unwrap_err();
+        assert!(!error.is_transient(), "{}", error);
+
+        // A closed pool fails like a dropped connection
+        pool.close().await;
+        let error = db::save_message(&pool, user.id, MessageRole::User, "lost").await.unwrap_err();
+        assert!(error.is_transient(), "{}", error);
+        assert_eq!(queue.save(user.id, MessageRole::User, "queued", None, &[], false).await.unwrap(), Saved::Queued);
+    }
+
+    #[tokio::test]
+    async fn test_permanent_errors_are_returned_or_dropped() {
+        let store = Arc::new(FlakyStore::default());
+        let queue = WriteQueue::new(store.clone(), None);
+
+        let error = queue.save(1, MessageRole::User, "bad", None, &[], false).await.unwrap_err();
+        assert!(!error.is_transient());
+
+        // Queued while down, then refused: dropped so it doesn't hold up the conversation
+        store.set_down(true);
+        save(&queue, 1, "bad").await;
+        save(&queue, 1, "fine").await;
+        store.set_down(false);
+        assert_eq!(queue.flush().await, Flushed::Complete);
+        assert_eq!(store.contents(), ["fine"]);
+    }
+}
@@ -0,0 +1,128 @@
+mod common;
+
+use agent_friend::agent_customizer::{
+    AgentCustomizationRequest, CustomizerError, KnowledgeInput, StrategyInput, customize_agent, get_agent_profile, search_agent_data,
+};
+use agent_friend::agent_registry;
+use common::db::{a_strategy_for, a_user, knowledge_tagged, test_db};
+use serde_json::json;
+
+fn strategy_input(strategy_id: &str, name: &str) -> StrategyInput {
+    StrategyInput {
+        strategy_id: strategy_id.to_string(),
+        name: name.to_string(),
+        category: "trading".to_string(),
+        description: "Buy a fixed amount of SOL every week".to_string(),
+        risk_level: "medium".to_string(),
+        tags: vec!["solana".to_string(), "dca".to_string()],
+        steps: vec!["Pick a weekday".to_string(), "Buy $50 of SOL".to_string()],
+        requirements: vec!["A funded wallet".to_string()],
+        expected_returns: json!({ "horizon": "1y" }),
+        author: "alice".to_string(),
+        version: "1.0".to_string(),
+    }
+}
+
+#[tokio::test]
+async fn test_customize_agent_round_trip() {
+    let Some(pool) = test_db().await else { return };
+    let request = AgentCustomizationRequest {
+        username: "alice".to_string(),
+        wallet_address: Some("0xabc".to_string()),
+        strategies: Some(vec![strategy_input("sol_dca", "SOL DCA")]),
+        knowledge: Some(vec![KnowledgeInput {
+            source_id: "sol-notes".to_string(),
+            content: "Solana validators earn about 7% a year".to_string(),
+            tags: vec!["solana".to_string(), "staking".to_string()],
+        }]),
+    };
+
+    let profile = customize_agent(&pool, request).await.unwrap();
+    assert_eq!(profile.user.username, "alice");
+    assert_eq!(profile.user.wallet_address.as_deref(), Some("0xabc"));
+    assert_eq!(profile.strategies.len(), 1);
+    let strategy = &profile.strategies[0];
+    assert_eq!(strategy.strategy_id, "sol_dca");
+    assert_eq!(strategy.steps, vec!["Pick a weekday", "Buy $50 of SOL"]);
+    assert_eq!(strategy.expected_returns, json!({ "horizon": "1y" }));
+    assert_eq!(profile.knowledge.len(), 1);
+    assert_eq!(profile.knowledge[0].tags, vec!["solana", "staking"]);
+
+    let stored = get_agent_profile(&pool, "alice").await.unwrap().unwrap();
+    assert_eq!(stored.user.id, profile.user.id);
+    assert_eq!(stored.strategies[0].id, strategy.id);
+    assert_eq!(stored.knowledge[0].id, profile.knowledge[0].id);
+
+    // A second request adds to the same user
+    let more = AgentCustomizationRequest {
+        username: "alice".to_string(),
+        wallet_address: None,
+        strategies: Some(vec![strategy_input("eth_dca", "ETH DCA")]),
+        knowledge: None,
+    };
+    let profile = customize_agent(&pool, more).await.unwrap();
+    assert_eq!(profile.user.id, stored.user.id);
+    assert_eq!(profile.strategies.len(), 2);
+
+    assert!(get_agent_profile(&pool, "nobody").await.unwrap().is_none());
+}
+
+#[tokio::test]
+async fn test_duplicate_strategy_names_the_failing_strategy() {
+    let Some(pool) = test_db().await else { return };
+    let user = a_user().named("alice").create(&pool).await;
+    a_strategy_for(&user).named("SOL DCA").create(&pool).await;
+
+    let request = AgentCustomizationRequest {
+        username: "alice".to_string(),
+        wallet_address: None,
+        strategies: Some(vec![strategy_input("sol_dca", "SOL DCA")]),
+        knowledge: None,
+    };
+    match customize_agent(&pool, request).await {
+        Err(CustomizerError::CreateStrategy { name, .. }) => assert_eq!(name, "SOL DCA"),
+        other => panic!("expected a strategy error, got {:?}", other.map(|profile| profile.strategies.len())),
+    }
+}
+
+#[tokio::test]
+async fn test_search_agent_data_finds_strategies_and_knowledge() {
+    let Some(pool) = test_db().await else { return };
+    let user = a_user().create(&pool).await;
+    let grid = a_strategy_for(&user).named("Solana Grid").description("Grid trade SOL between two levels").create(&pool).await;
+    a_strategy_for(&user).create(&pool).await;
+    let notes = knowledge_tagged(&user, ["solana"]).content("Solana fees are paid in SOL").create(&pool).await;
+    knowledge_tagged(&user, ["ethereum"]).create(&pool).await;
+
+    let (strategies, knowledge) = search_agent_data(&pool, user.id, "solana").await.unwrap();
+    assert_eq!(strategies.iter().map(|strategy| strategy.id).collect::<Vec<_>>(), vec![grid.id]);
+    assert_eq!(knowledge.iter().map(|entry| entry.id).collect::<Vec<_>>(), vec![notes.id]);
+
+    // Only the searching user's rows
+    let other = a_user().create(&pool).await;
+    let (strategies, knowledge) = search_agent_data(&pool, other.id, "solana").await.unwrap();
+    assert!(strategies.is_empty() && knowledge.is_empty());
+}
+
+#[tokio::test]
+async fn test_customizing_rebuilds_the_shared_agent() {
+    let Some(pool) = test_db().await else { return };
+    let registry = agent_registry::start(&pool);
+    assert!(std::sync::Arc::ptr_eq(&registry, &agent_registry::configured().unwrap()));
+
+    let before = registry.get("alice").await.unwrap();
+    assert!(std::sync::Arc::ptr_eq(&before, &registry.get("alice").await.unwrap()));
+
+    let request = AgentCustomizationRequest {
+        username: "alice".to_string(),
+        wallet_address: Some("0xabc".to_string()),
+        strategies: None,
+        knowledge: None,
+    };
+    customize_agent(&pool, request).await.unwrap();
+    assert!(!registry.contains("alice"));
+
+    let after = registry.get("alice").await.unwrap();
+    assert!(!std::sync::Arc::ptr_eq(&before, &after));
+    assert_eq!(after.user_id(), before.user_id());
+}
@@ -0,0 +1,247 @@
+mod common;
+
+use agent_friend::anthropic::{AnthropicClient, CallOptions, END_OF_JSON, LlmError, Message, TokenUsage};
+use agent_friend::llm::ChatModel;
+use agent_friend::db::MessageRole;
+use common::{event_stream_fixture, json_fixture, malformed_json, rate_limited};
+use reqwest::StatusCode;
+use std::time::Duration;
+use wiremock::matchers::{body_partial_json, header, method, path};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+
+fn client(server: &MockServer) -> AnthropicClient {
+    AnthropicClient::new("sk-ant-test").with_base_url(&server.uri())
+}
+
+fn messages() -> Vec<Message> {
+    vec![Message {
+        role: MessageRole::User,
+        content: "What is AERO?".to_string(),
+    }]
+}
+
+#[tokio::test]
+async fn test_complete() {
+    let server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path("/v1/messages"))
+        .and(header("x-api-key", "sk-ant-test"))
+        .and(header("anthropic-version", "2023-06-01"))
+        .and(body_partial_json(serde_json::json!({
+            "system": "Be brief.",
+            "max_tokens": 256,
+            "messages": [{ "role": "user", "content": "What is AERO?" }]
+        })))
+        .respond_with(json_fixture("anthropic/messages.json"))
+        .mount(&server)
+        .await;
+
+    let text = client(&server).complete("Be brief.", &messages(), 256).await.unwrap();
+    assert_eq!(text, "AERO is the governance token of Aerodrome.");
+}
+
+#[tokio::test]
+async fn test_complete_with_usage() {
+    let server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path("/v1/messages"))
+        .respond_with(json_fixture("anthropic/messages.json"))
+        .mount(&server)
+        .await;
+
+    let completion = client(&server).complete_with_usage("", &messages(), 256).await.unwrap();
+    assert_eq!(completion.text, "AERO is the governance token of Aerodrome.");
+    assert_eq!(completion.usage, TokenUsage { input_tokens: 12, output_tokens: 9 });
+    assert_eq!(completion.request_id.as_deref(), Some("msg_01"));
+}
+
+#[tokio::test]
+async fn test_generate_streaming() {
+    let server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path("/v1/messages"))
+        .and(body_partial_json(serde_json::json!({ "stream": true, "system": "Be brief." })))
+        .respond_with(event_stream_fixture("anthropic/stream.txt"))
+        .mount(&server)
+        .await;
+
+    let mut pieces = Vec::new();
+    let completion = client(&server)
+        .generate_streaming("Be brief.", &messages(), &CallOptions::new(256), &mut |text| pieces.push(text.to_string()))
+        .await
+        .unwrap();
+    assert_eq!(pieces, ["AERO is the governance ", "token of Aerodrome."]);
+    assert_eq!(completion.text, "AERO is the governance token of Aerodrome.");
+    assert_eq!(completion.usage, TokenUsage { input_tokens: 12, output_tokens: 9 });
+    assert_eq!(completion.stop_sequence, None);
+    assert_eq!(completion.request_id.as_deref(), Some("msg_02"));
+}
+
+const CLASSIFICATION: &str = r#"[{"index": 1, "sentiment": "positive", "reason": "ETF inflows hit a record"}]"#;
+
+#[tokio::test]
+async fn test_complete_with_options_reports_stop_sequence() {
+    let server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path("/v1/messages"))
+        .and(body_partial_json(serde_json::json!({ "max_tokens": 512, "stop_sequences": ["###END###"] })))
+        .respond_with(json_fixture("anthropic/structured_stopped.json"))
+        .mount(&server)
+        .await;
+
+    let options = CallOptions::new(512).with_stop_sequence(END_OF_JSON);
+    let completion = client(&server).complete_with_options("", &messages(), &options).await.unwrap();
+    assert_eq!(completion.stop_sequence.as_deref(), Some("###END###"));
+    assert!(completion.text.starts_with("```json"));
+}
+
+#[tokio::test]
+async fn test_complete_json_stops_at_sentinel() {
+    let server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path("/v1/messages"))
+        .and(body_partial_json(serde_json::json!({ "stop_sequences": ["###END###"] })))
+        .respond_with(json_fixture("anthropic/structured_stopped.json"))
+        .mount(&server)
+        .await;
+
+    let json = client(&server).complete_json("Classify the articles.", &messages(), 512).await.unwrap();
+    assert_eq!(json, CLASSIFICATION);
+
+    let requests = server.received_requests().await.unwrap();
+    let body: serde_json::Value = serde_json::from_slice(&requests[0].body).unwrap();
+    let system = body["system"].as_str().unwrap();
+    assert!(system.starts_with("Classify the articles.") && system.contains("###END###"));
+}
+
+#[tokio::test]
+async fn test_complete_json_cuts_prose_after_unstopped_sentinel() {
+    // Proxies that drop stop_sequences return the sentinel and whatever follows it
+    let server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .respond_with(json_fixture("anthropic/structured_unstopped.json"))
+        .mount(&server)
+        .await;
+
+    let json = client(&server).complete_json("", &messages(), 512).await.unwrap();
+    assert_eq!(json, CLASSIFICATION);
+}
+
+#[tokio::test]
+async fn test_complete_json_without_sentinel_keeps_fenced_json() {
+    let server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .respond_with(json_fixture("anthropic/structured_no_sentinel.json"))
+        .mount(&server)
+        .await;
+
+    let json = client(&server).complete_json("", &messages(), 512).await.unwrap();
+    assert_eq!(json, CLASSIFICATION);
+}
+
+#[tokio::test]
+async fn test_unauthorized_keeps_api_message() {
+    let server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .respond_with(ResponseTemplate::new(401).set_body_json(serde_json::json!({
+            "type": "error",
+            "error": { "type": "authentication_error", "message": "invalid x-api-key" }
+        })))
+        .mount(&server)
+        .await;
+
+    let error = client(&server).complete("", &messages(), 256).await.unwrap_err();
+    assert!(matches!(error, LlmError::Unauthorized(msg) if msg == "invalid x-api-key"));
+}
+
+#[tokio::test]
+async fn test_rate_limit_carries_retry_after() {
+    let server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .respond_with(rate_limited(15))
+        .mount(&server)
+        .await;
+
+    let error = client(&server).complete("", &messages(), 256).await.unwrap_err();
+    assert!(matches!(error, LlmError::RateLimited(Some(wait)) if wait == Duration::from_secs(15)));
+}
+
+#[tokio::test]
+async fn test_server_error() {
+    let server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .respond_with(ResponseTemplate::new(529).set_body_string("overloaded"))
+        .mount(&server)
+        .await;
+
+    let error = client(&server).complete("", &messages(), 256).await.unwrap_err();
+    assert!(matches!(
+        error,
+        LlmError::Api { status, message } if status.as_u16() == 529 && message == "overloaded"
+    ));
+}
+
+#[tokio::test]
+async fn test_bad_request() {
+    let server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .respond_with(ResponseTemplate::new(400))
+        .mount(&server)
+        .await;
+
+    let error = client(&server).complete("", &messages(), 256).await.unwrap_err();
+    assert!(matches!(error, LlmError::Api { status: StatusCode::BAD_REQUEST, .. }));
+}
+
+#[tokio::test]
+async fn test_malformed_json() {
+    let server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .respond_with(malformed_json())
+        .mount(&server)
+        .await;
+
+    let error = client(&server).complete("", &messages(), 256).await.unwrap_err();
+    assert!(matches!(error, LlmError::InvalidResponse(msg) if msg.starts_with("Malformed JSON")));
+}
+
+#[tokio::test]
+async fn test_empty_content_is_an_empty_reply() {
+    let server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({ "id": "msg_empty", "content": [] })))
+        .mount(&server)
+        .await;
+
+    let completion = client(&server).complete_with_usage("", &messages(), 256).await.unwrap();
+    assert_eq!(completion.text, "");
+    assert_eq!(completion.request_id.as_deref(), Some("msg_empty"));
+}
+
+#[tokio::test]
+async fn test_missing_content() {
+    let server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({ "id": "msg_01" })))
+        .mount(&server)
+        .await;
+
+    let error = client(&server).complete("", &messages(), 256).await.unwrap_err();
+    assert!(matches!(error, LlmError::InvalidResponse(_)));
+}
+
+#[tokio::test]
+async fn test_timeout() {
+    let server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .respond_with(json_fixture("anthropic/messages.json").set_delay(Duration::from_millis(500)))
+        .mount(&server)
+        .await;
+
+    let error = client(&server)
+        .with_timeout(Duration::from_millis(50))
+        .complete("", &messages(), 256)
+        .await
+        .unwrap_err();
+    assert!(matches!(error, LlmError::Http(e) if e.is_timeout()));
+}
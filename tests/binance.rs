@@ -0,0 +1,118 @@
+mod common;
+
+use agent_friend::derivatives::{BinanceFuturesClient, DerivativesError, DerivativesProvider};
+use common::{fixture, json_fixture, malformed_json};
+use std::time::Duration;
+use wiremock::matchers::{method, path, query_param};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+
+async fn mount_eth(server: &MockServer) {
+    Mock::given(method("GET"))
+        .and(path("/fapi/v1/premiumIndex"))
+        .and(query_param("symbol", "ETHUSDT"))
+        .respond_with(json_fixture("binance/premium_index.json"))
+        .mount(server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/futures/data/openInterestHist"))
+        .and(query_param("symbol", "ETHUSDT"))
+        .and(query_param("period", "1h"))
+        .and(query_param("limit", "25"))
+        .respond_with(json_fixture("binance/open_interest_hist.json"))
+        .mount(server)
+        .await;
+}
+
+#[tokio::test]
+async fn test_snapshot_maps_coin_id_and_parses_figures() {
+    let server = MockServer::start().await;
+    mount_eth(&server).await;
+
+    let snapshot = BinanceFuturesClient::new(server.uri()).snapshot("ethereum").await.unwrap();
+    assert_eq!(snapshot.symbol, "ETHUSDT");
+    assert_eq!(snapshot.venue, "Binance");
+    assert_eq!(snapshot.mark_price_usd, 2512.34);
+    assert!((snapshot.funding_rate_pct - 0.01).abs() < 1e-12);
+    assert_eq!(snapshot.next_funding_at.unwrap().timestamp(), 1_760_630_400);
+    assert_eq!(snapshot.open_interest, 2_060_000.0);
+    assert_eq!(snapshot.open_interest_usd, 5_175_420_400.0);
+    // The oldest of the 25 hourly points is a day before the latest
+    assert!((snapshot.oi_change_24h_pct.unwrap() - 3.0).abs() < 1e-9);
+}
+
+#[tokio::test]
+async fn test_unmapped_coin_is_unsupported_without_a_request() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET")).respond_with(ResponseTemplate::new(500)).expect(0).mount(&server).await;
+
+    let error = BinanceFuturesClient::new(server.uri()).snapshot("not-a-coin").await.unwrap_err();
+    assert!(matches!(error, DerivativesError::UnsupportedCoin(coin) if coin == "not-a-coin"));
+}
+
+#[tokio::test]
+async fn test_delisted_symbol_is_unsupported() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/fapi/v1/premiumIndex"))
+        .respond_with(ResponseTemplate::new(400).set_body_raw(fixture("binance/invalid_symbol.json"), "application/json"))
+        .mount(&server)
+        .await;
+
+    let error = BinanceFuturesClient::new(server.uri()).snapshot("aerodrome-finance").await.unwrap_err();
+    assert!(matches!(error, DerivativesError::UnsupportedCoin(coin) if coin == "aerodrome-finance"));
+}
+
+#[tokio::test]
+async fn test_error_status_and_malformed_json() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(query_param("symbol", "BTCUSDT"))
+        .respond_with(ResponseTemplate::new(502))
+        .mount(&server)
+        .await;
+    Mock::given(method("GET"))
+        .and(query_param("symbol", "SOLUSDT"))
+        .respond_with(malformed_json())
+        .mount(&server)
+        .await;
+
+    let client = BinanceFuturesClient::new(server.uri());
+    let error = client.snapshot("bitcoin").await.unwrap_err();
+    assert!(matches!(error, DerivativesError::InvalidResponse(msg) if msg.contains("502")));
+    let error = client.snapshot("solana").await.unwrap_err();
+    assert!(matches!(error, DerivativesError::InvalidResponse(msg) if msg.starts_with("Malformed JSON")));
+}
+
+#[tokio::test]
+async fn test_empty_open_interest_history() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/fapi/v1/premiumIndex"))
+        .respond_with(json_fixture("binance/premium_index.json"))
+        .mount(&server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/futures/data/openInterestHist"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!([])))
+        .mount(&server)
+        .await;
+
+    let error = BinanceFuturesClient::new(server.uri()).snapshot("ethereum").await.unwrap_err();
+    assert!(matches!(error, DerivativesError::InvalidResponse(msg) if msg.contains("no open interest")));
+}
+
+#[tokio::test]
+async fn test_timeout() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .respond_with(json_fixture("binance/premium_index.json").set_delay(Duration::from_millis(500)))
+        .mount(&server)
+        .await;
+
+    let error = BinanceFuturesClient::new(server.uri())
+        .with_timeout(Duration::from_millis(50))
+        .snapshot("ethereum")
+        .await
+        .unwrap_err();
+    assert!(matches!(error, DerivativesError::Http(e) if e.is_timeout()));
+}
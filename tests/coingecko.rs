@@ -0,0 +1,944 @@
+mod common;
+
+use agent_friend::circuit_breaker::{BreakerSettings, BreakerState, CircuitBreaker};
+use agent_friend::categories::{detect_category_query, match_category, render_ranking};
+use agent_friend::fiat::{ExchangeRates, find_currency, usd};
+use agent_friend::price_fetcher::{CategoryCoin, ChartInterval, CoinCategory, CoinGeckoClient, MAX_IDS_PER_REQUEST, Platform, PriceError};
+use common::{fixture, json_fixture, malformed_json, rate_limited};
+use std::sync::Arc;
+use std::time::Duration;
+use wiremock::matchers::{header, method, path, query_param, query_param_is_missing};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+
+fn client(server: &MockServer) -> CoinGeckoClient {
+    CoinGeckoClient::new(server.uri()).with_min_request_interval(Duration::ZERO)
+}
+
+#[tokio::test]
+async fn test_fetch_coin_price() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/simple/price"))
+        .and(query_param("ids", "bitcoin"))
+        .and(query_param("vs_currencies", "usd"))
+        .respond_with(json_fixture("coingecko/simple_price.json"))
+        .mount(&server)
+        .await;
+
+    let price = client(&server).fetch_coin_price("bitcoin").await.unwrap();
+    assert_eq!(price, 64250.12);
+}
+
+#[tokio::test]
+async fn test_fetch_multiple_coin_prices_skips_unknown_coins() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/simple/price"))
+        .and(query_param("ids", "bitcoin,ethereum,dogecoin"))
+        .respond_with(json_fixture("coingecko/simple_price.json"))
+        .mount(&server)
+        .await;
+
+    let prices = client(&server)
+        .fetch_multiple_coin_prices(&["bitcoin", "ethereum", "dogecoin"])
+        .await
+        .unwrap();
+    assert_eq!(prices.len(), 2);
+    assert_eq!(prices["ethereum"], 3120.5);
+}
+
+#[tokio::test]
+async fn test_fetch_multiple_coin_quotes_include_24h_change() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/simple/price"))
+        .and(query_param("ids", "bitcoin,ethereum,fresh-listing"))
+        .and(query_param("include_24hr_change", "true"))
+        .respond_with(json_fixture("coingecko/simple_price_24h.json"))
+        .mount(&server)
+        .await;
+
+    let quotes = client(&server)
+        .fetch_multiple_coin_quotes(&["bitcoin", "ethereum", "fresh-listing"])
+        .await
+        .unwrap();
+    assert_eq!(quotes["bitcoin"].price_usd, 64250.12);
+    assert_eq!(quotes["ethereum"].change_24h_pct, Some(-1.08));
+    assert_eq!(quotes["fresh-listing"].change_24h_pct, None);
+}
+
+#[tokio::test]
+async fn test_fetch_coin_quote_includes_market_cap_and_volume() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/simple/price"))
+        .and(query_param("ids", "bitcoin"))
+        .and(query_param("include_24hr_change", "true"))
+        .and(query_param("include_market_cap", "true"))
+        .and(query_param("include_24hr_vol", "true"))
+        .respond_with(json_fixture("coingecko/simple_price_24h.json"))
+        .expect(1)
+        .mount(&server)
+        .await;
+    let client = client(&server);
+
+    let quote = client.fetch_coin_quote("bitcoin").await.unwrap();
+    assert_eq!(quote.price_usd, 64250.12);
+    assert_eq!(quote.change_24h_pct, Some(2.4512));
+    assert_eq!(quote.market_cap, Some(1268543210987.4));
+    assert_eq!(quote.volume_24h, Some(35212345678.9));
+    // The quote is reused within the TTL, with the price it came with
+    let (again, cached) = client.fetch_cached_coin_quote("bitcoin").await.unwrap();
+    assert_eq!((again, cached.cached), (quote, true));
+    assert_eq!(client.fetch_coin_price("bitcoin").await.unwrap(), 64250.12);
+}
+
+#[tokio::test]
+async fn test_fetch_coin_quote_of_a_new_listing_has_no_24h_figures() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/simple/price"))
+        .respond_with(json_fixture("coingecko/simple_price_24h.json"))
+        .mount(&server)
+        .await;
+
+    let quote = client(&server).fetch_coin_quote("fresh-listing").await.unwrap();
+    assert_eq!((quote.price_usd, quote.change_24h_pct, quote.market_cap, quote.volume_24h), (0.042, None, None, None));
+    let error = client(&server).fetch_coin_quote("dogecoin").await.unwrap_err();
+    assert!(matches!(error, PriceError::PriceNotFound(_)));
+}
+
+#[tokio::test]
+async fn test_rate_limited_quote_serves_the_last_one() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/simple/price"))
+        .and(query_param("include_market_cap", "true"))
+        .respond_with(json_fixture("coingecko/simple_price_24h.json"))
+        .up_to_n_times(1)
+        .mount(&server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/simple/price"))
+        .and(query_param_is_missing("include_market_cap"))
+        .respond_with(json_fixture("coingecko/simple_price.json"))
+        .up_to_n_times(1)
+        .mount(&server)
+        .await;
+    Mock::given(method("GET")).respond_with(rate_limited(30)).mount(&server).await;
+    let client = client(&server).with_price_ttl(Duration::ZERO);
+
+    client.fetch_coin_quote("ethereum").await.unwrap();
+    let (quote, cached) = client.fetch_cached_coin_quote("ethereum").await.unwrap();
+    assert_eq!((quote.change_24h_pct, cached.cached, cached.stale), (Some(-1.08), true, true));
+
+    // A bare price replaces the quote it no longer matches
+    client.fetch_coin_price("ethereum").await.unwrap();
+    let error = client.fetch_cached_coin_quote("ethereum").await.unwrap_err();
+    assert!(matches!(error, PriceError::RateLimitExceeded(Some(_))));
+}
+
+#[tokio::test]
+async fn test_fetch_multiple_coin_prices_splits_long_lists() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/simple/price"))
+        .respond_with(json_fixture("coingecko/simple_price.json"))
+        .expect(2)
+        .mount(&server)
+        .await;
+
+    let mut coin_ids: Vec<String> = (0..MAX_IDS_PER_REQUEST + 10).map(|i| format!("coin-{}", i)).collect();
+    coin_ids.push("bitcoin".to_string());
+    let coin_ids: Vec<&str> = coin_ids.iter().map(String::as_str).collect();
+
+    let prices = client(&server).fetch_multiple_coin_prices(&coin_ids).await.unwrap();
+    assert_eq!(prices.len(), 1);
+    assert_eq!(prices["bitcoin"], 64250.12);
+
+    let requests = server.received_requests().await.unwrap();
+    let sizes: Vec<usize> = requests
+        .iter()
+        .map(|request| {
+            let (_, ids) = request.url.query_pairs().find(|(name, _)| name == "ids").unwrap();
+            ids.split(',').count()
+        })
+        .collect();
+    assert_eq!(sizes, vec![MAX_IDS_PER_REQUEST, 11]);
+}
+
+#[tokio::test]
+async fn test_fetch_historical_price() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/coins/aerodrome-finance/history"))
+        .and(query_param("date", "01-12-2024"))
+        .respond_with(json_fixture("coingecko/history.json"))
+        .mount(&server)
+        .await;
+
+    let price = client(&server)
+        .fetch_coin_historical_price("aerodrome-finance", "01-12-2024")
+        .await
+        .unwrap();
+    assert_eq!(price, 1.23);
+}
+
+#[tokio::test]
+async fn test_current_price_is_reused_within_ttl() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/simple/price"))
+        .respond_with(json_fixture("coingecko/simple_price.json"))
+        .expect(2)
+        .mount(&server)
+        .await;
+    let client = client(&server);
+
+    let first = client.fetch_cached_coin_price("bitcoin").await.unwrap();
+    assert_eq!((first.price, first.cached, first.as_of()), (64250.12, false, None));
+    let second = client.fetch_cached_coin_price("bitcoin").await.unwrap();
+    assert_eq!((second.price, second.cached, second.stale), (64250.12, true, false));
+    assert_eq!(second.as_of().unwrap(), "as of ~1 minute ago");
+    // Clones share the cache, and a cleared one asks again
+    assert_eq!(client.clone().fetch_coin_price("bitcoin").await.unwrap(), 64250.12);
+    client.price_cache().clear().await;
+    assert!(!client.fetch_cached_coin_price("bitcoin").await.unwrap().cached);
+}
+
+#[tokio::test]
+async fn test_zero_ttl_fetches_every_time() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/simple/price"))
+        .respond_with(json_fixture("coingecko/simple_price.json"))
+        .expect(2)
+        .mount(&server)
+        .await;
+    let client = client(&server).with_price_ttl(Duration::ZERO);
+
+    for _ in 0..2 {
+        assert!(!client.fetch_cached_coin_price("bitcoin").await.unwrap().cached);
+    }
+}
+
+#[tokio::test]
+async fn test_rate_limited_lookup_serves_the_last_price() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/simple/price"))
+        .respond_with(json_fixture("coingecko/simple_price.json"))
+        .up_to_n_times(1)
+        .mount(&server)
+        .await;
+    Mock::given(method("GET")).respond_with(rate_limited(30)).mount(&server).await;
+    let client = client(&server).with_price_ttl(Duration::ZERO);
+
+    client.fetch_multiple_coin_prices(&["bitcoin", "ethereum"]).await.unwrap();
+    let served = client.fetch_cached_coin_price("bitcoin").await.unwrap();
+    assert_eq!((served.price, served.cached, served.stale), (64250.12, true, true));
+    let prices = client.fetch_multiple_coin_prices(&["bitcoin", "ethereum"]).await.unwrap();
+    assert_eq!((prices["bitcoin"], prices["ethereum"]), (64250.12, 3120.5));
+
+    // A coin never fetched has nothing to fall back on
+    let error = client.fetch_multiple_coin_prices(&["bitcoin", "solana"]).await.unwrap_err();
+    assert!(matches!(error, PriceError::RateLimitExceeded(Some(_))));
+}
+
+#[tokio::test]
+async fn test_multiple_prices_only_request_coins_not_cached() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/simple/price"))
+        .and(query_param("ids", "bitcoin"))
+        .respond_with(json_fixture("coingecko/simple_price.json"))
+        .expect(1)
+        .mount(&server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/simple/price"))
+        .and(query_param("ids", "ethereum"))
+        .respond_with(json_fixture("coingecko/simple_price.json"))
+        .expect(1)
+        .mount(&server)
+        .await;
+    let client = client(&server);
+
+    client.fetch_coin_price("bitcoin").await.unwrap();
+    let prices = client.fetch_multiple_coin_prices(&["bitcoin", "ethereum"]).await.unwrap();
+    assert_eq!(prices.len(), 2);
+    let cached = client.price_cache().get("ethereum", "usd").await.unwrap();
+    assert_eq!(cached.price, 3120.5);
+}
+
+#[tokio::test]
+async fn test_historical_price_is_fetched_once() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/coins/aerodrome-finance/history"))
+        .respond_with(json_fixture("coingecko/history.json"))
+        .expect(1)
+        .mount(&server)
+        .await;
+    let client = client(&server).with_price_ttl(Duration::ZERO);
+
+    for _ in 0..2 {
+        assert_eq!(client.fetch_coin_historical_price("aerodrome-finance", "01-12-2024").await.unwrap(), 1.23);
+    }
+}
+
+fn plan_limited() -> ResponseTemplate {
+    ResponseTemplate::new(401).set_body_raw(fixture("coingecko/history_plan_limit.json"), "application/json")
+}
+
+#[tokio::test]
+async fn test_historical_price_past_plan_limit_uses_market_chart_range() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/coins/bitcoin/history"))
+        .respond_with(plan_limited())
+        .mount(&server)
+        .await;
+    // Three days either side of 2017-12-17 00:00 UTC
+    Mock::given(method("GET"))
+        .and(path("/coins/bitcoin/market_chart/range"))
+        .and(query_param("vs_currency", "usd"))
+        .and(query_param("from", "1513209600"))
+        .and(query_param("to", "1513728000"))
+        .respond_with(json_fixture("coingecko/market_chart_range.json"))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let price = client(&server).fetch_coin_historical_price("bitcoin", "17-12-2017").await.unwrap();
+    assert_eq!(price, 19065.71);
+}
+
+#[tokio::test]
+async fn test_historical_price_before_listing_uses_market_chart_range() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/coins/bitcoin/history"))
+        .respond_with(json_fixture("coingecko/history_unlisted.json"))
+        .mount(&server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/coins/bitcoin/market_chart/range"))
+        .respond_with(json_fixture("coingecko/market_chart_range.json"))
+        .mount(&server)
+        .await;
+
+    let price = client(&server).fetch_coin_historical_price("bitcoin", "18-12-2017").await.unwrap();
+    assert_eq!(price, 18972.32);
+}
+
+#[tokio::test]
+async fn test_historical_price_outside_plan_is_out_of_range() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/coins/bitcoin/history"))
+        .respond_with(plan_limited())
+        .mount(&server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/coins/bitcoin/market_chart/range"))
+        .respond_with(plan_limited())
+        .mount(&server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/coins/bitcoin/market_chart"))
+        .and(query_param("days", "365"))
+        .respond_with(json_fixture("coingecko/market_chart.json"))
+        .mount(&server)
+        .await;
+
+    let error = client(&server).fetch_coin_historical_price("bitcoin", "17-12-2017").await.unwrap_err();
+    assert!(matches!(
+        error,
+        PriceError::OutOfRange { ref coin_id, earliest }
+            if coin_id == "bitcoin" && earliest == chrono::NaiveDate::from_ymd_opt(2024, 9, 15).unwrap()
+    ));
+}
+
+#[tokio::test]
+async fn test_out_of_range_counts_back_the_stated_lookback_without_a_chart() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/coins/bitcoin/history"))
+        .respond_with(ResponseTemplate::new(403).set_body_string("within the past 90 days"))
+        .mount(&server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/coins/bitcoin/market_chart/range"))
+        .respond_with(ResponseTemplate::new(403))
+        .mount(&server)
+        .await;
+
+    let error = client(&server).fetch_coin_historical_price("bitcoin", "17-12-2017").await.unwrap_err();
+    let expected = chrono::Utc::now().date_naive() - chrono::Duration::days(90);
+    assert!(matches!(error, PriceError::OutOfRange { earliest, .. } if earliest == expected));
+}
+
+#[tokio::test]
+async fn test_api_key_is_sent() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(header("x-cg-demo-api-key", "cg-test"))
+        .respond_with(json_fixture("coingecko/simple_price.json"))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    client(&server)
+        .with_api_key(Some("cg-test".to_string()))
+        .fetch_coin_price("bitcoin")
+        .await
+        .unwrap();
+}
+
+#[tokio::test]
+async fn test_pro_key_is_sent_in_the_pro_header() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(header("x-cg-pro-api-key", "cg-pro"))
+        .respond_with(json_fixture("coingecko/simple_price.json"))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    client(&server)
+        .with_api_key(Some("cg-pro".to_string()))
+        .with_pro(true)
+        .fetch_coin_price("bitcoin")
+        .await
+        .unwrap();
+}
+
+#[tokio::test]
+async fn test_rejected_key_is_invalid_api_key() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .respond_with(ResponseTemplate::new(401).set_body_json(serde_json::json!({
+            "status": { "error_code": 10002, "error_message": "API Key Missing or invalid" }
+        })))
+        .mount(&server)
+        .await;
+    let client = client(&server).with_api_key(Some("cg-wrong".to_string()));
+
+    let error = client.fetch_coin_price("bitcoin").await.unwrap_err();
+    assert!(matches!(error, PriceError::InvalidApiKey));
+    assert!(error.to_string().contains("COINGECKO_API_KEY"));
+    let error = client.fetch_coin_historical_price("bitcoin", "17-12-2017").await.unwrap_err();
+    assert!(matches!(error, PriceError::InvalidApiKey), "{}", error);
+}
+
+#[tokio::test]
+async fn test_plan_limit_with_a_key_is_not_a_rejected_key() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/coins/bitcoin/history"))
+        .respond_with(plan_limited())
+        .mount(&server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/coins/bitcoin/market_chart/range"))
+        .respond_with(json_fixture("coingecko/market_chart_range.json"))
+        .mount(&server)
+        .await;
+
+    let price = client(&server)
+        .with_api_key(Some("cg-test".to_string()))
+        .fetch_coin_historical_price("bitcoin", "17-12-2017")
+        .await
+        .unwrap();
+    assert_eq!(price, 19065.71);
+}
+
+#[tokio::test]
+async fn test_unauthorized_is_an_invalid_response() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .respond_with(ResponseTemplate::new(401))
+        .mount(&server)
+        .await;
+
+    let error = client(&server).fetch_coin_price("bitcoin").await.unwrap_err();
+    assert!(matches!(error, PriceError::InvalidResponse(msg) if msg.contains("401")));
+}
+
+#[tokio::test]
+async fn test_rate_limit_carries_retry_after() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .respond_with(rate_limited(42))
+        .mount(&server)
+        .await;
+
+    let error = client(&server).fetch_coin_price("bitcoin").await.unwrap_err();
+    assert!(matches!(error, PriceError::RateLimitExceeded(Some(wait)) if wait == Duration::from_secs(42)));
+    assert_eq!(error.to_string(), "CoinGecko API rate limit exceeded, retry after 42s");
+}
+
+#[tokio::test]
+async fn test_server_error() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .respond_with(ResponseTemplate::new(503))
+        .mount(&server)
+        .await;
+
+    let error = client(&server).fetch_coin_price("bitcoin").await.unwrap_err();
+    assert!(matches!(error, PriceError::InvalidResponse(msg) if msg.contains("503")));
+}
+
+#[tokio::test]
+async fn test_circuit_breaker_skips_calls_until_cool_down() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .respond_with(ResponseTemplate::new(503))
+        .up_to_n_times(2)
+        .mount(&server)
+        .await;
+    Mock::given(method("GET"))
+        .respond_with(json_fixture("coingecko/simple_price.json"))
+        .mount(&server)
+        .await;
+    let breaker = Arc::new(CircuitBreaker::new("coingecko", BreakerSettings {
+        failure_threshold: 2,
+        cool_down: Duration::from_millis(200),
+    }));
+    let client = client(&server).with_breaker(breaker.clone());
+
+    for _ in 0..2 {
+        assert!(matches!(client.fetch_coin_price("bitcoin").await, Err(PriceError::InvalidResponse(_))));
+    }
+    assert_eq!(breaker.state(), BreakerState::Open);
+    let error = client.fetch_coin_price("bitcoin").await.unwrap_err();
+    assert!(matches!(error, PriceError::CircuitOpen));
+    assert_eq!(error.to_string(), "CoinGecko calls are paused after repeated failures");
+    assert_eq!(server.received_requests().await.unwrap().len(), 2);
+
+    // The first call after the cool-down goes through and closes the breaker
+    tokio::time::sleep(Duration::from_millis(250)).await;
+    assert_eq!(client.fetch_coin_price("bitcoin").await.unwrap(), 64250.12);
+    assert_eq!(breaker.state(), BreakerState::Closed);
+    assert_eq!(breaker.snapshot().short_circuited, 1);
+}
+
+#[tokio::test]
+async fn test_malformed_json() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .respond_with(malformed_json())
+        .mount(&server)
+        .await;
+
+    let error = client(&server).fetch_coin_price("bitcoin").await.unwrap_err();
+    assert!(matches!(error, PriceError::InvalidResponse(msg) if msg.starts_with("Malformed JSON")));
+}
+
+#[tokio::test]
+async fn test_missing_coin_is_price_not_found() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .respond_with(json_fixture("coingecko/simple_price.json"))
+        .mount(&server)
+        .await;
+
+    let error = client(&server).fetch_coin_price("dogecoin").await.unwrap_err();
+    assert!(matches!(error, PriceError::PriceNotFound(coin) if coin == "dogecoin"));
+}
+
+#[tokio::test]
+async fn test_timeout() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .respond_with(json_fixture("coingecko/simple_price.json").set_delay(Duration::from_millis(500)))
+        .mount(&server)
+        .await;
+
+    let error = client(&server)
+        .with_timeout(Duration::from_millis(50))
+        .fetch_coin_price("bitcoin")
+        .await
+        .unwrap_err();
+    assert!(matches!(error, PriceError::NetworkError(e) if e.is_timeout()));
+}
+
+#[tokio::test]
+async fn test_search_coins() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/search"))
+        .and(query_param("query", "pepe"))
+        .respond_with(json_fixture("coingecko/search.json"))
+        .mount(&server)
+        .await;
+
+    let coins = client(&server).search_coins("pepe").await.unwrap();
+    assert_eq!(coins.len(), 2);
+    assert_eq!(coins[0].id, "pepe");
+    assert_eq!(coins[0].symbol, "PEPE");
+    assert_eq!(coins[0].market_cap_rank, Some(28));
+    assert_eq!(coins[1].market_cap_rank, None);
+}
+
+#[tokio::test]
+async fn test_fetch_market_chart_keeps_one_price_per_day() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/coins/ethereum/market_chart"))
+        .and(query_param("vs_currency", "usd"))
+        .and(query_param("days", "90"))
+        .and(query_param("interval", "daily"))
+        .respond_with(json_fixture("coingecko/market_chart.json"))
+        .mount(&server)
+        .await;
+
+    let series = client(&server).fetch_market_chart("ethereum", 90).await.unwrap();
+    let dates: Vec<String> = series.iter().map(|day| day.date.to_string()).collect();
+    assert_eq!(dates, vec!["2024-09-15", "2024-09-16", "2024-09-17"]);
+    // The intraday point replaces the day's first price
+    assert_eq!(series[2].price_usd, 2352.44);
+}
+
+#[tokio::test]
+async fn test_fetch_market_chart_with_volume() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/coins/ethereum/market_chart"))
+        .and(query_param("days", "90"))
+        .respond_with(json_fixture("coingecko/market_chart.json"))
+        .mount(&server)
+        .await;
+
+    let bars = client(&server).fetch_market_chart_with_volume("ethereum", 90).await.unwrap();
+    assert_eq!(bars.len(), 3);
+    assert_eq!(bars[0].price_usd, 2310.52);
+    assert_eq!(bars[0].volume_usd, 9876543210.5);
+    // The intraday point replaces both the day's price and its volume
+    assert_eq!(bars[2].price_usd, 2352.44);
+    assert_eq!(bars[2].volume_usd, 12001234567.3);
+}
+
+#[tokio::test]
+async fn test_fetch_hourly_chart_keeps_every_point() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/coins/bitcoin/market_chart"))
+        .and(query_param("days", "2"))
+        .and(query_param_is_missing("interval"))
+        .respond_with(json_fixture("coingecko/market_chart_hourly.json"))
+        .mount(&server)
+        .await;
+
+    // A single day is widened to two so a full 24 hours back is covered
+    let points = client(&server).fetch_hourly_chart("bitcoin", 1).await.unwrap();
+    assert_eq!(points.len(), 9);
+    assert_eq!(points[0].at.to_rfc3339(), "2025-10-15T10:00:00+00:00");
+    assert_eq!(points[8].at.to_rfc3339(), "2025-10-16T10:27:12+00:00");
+    assert_eq!(points[8].price_usd, 107780.4);
+}
+
+#[tokio::test]
+async fn test_fetch_coin_market_chart_keeps_the_last_point_of_each_day() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/coins/ethereum/market_chart"))
+        .and(query_param("days", "3"))
+        .and(query_param("interval", "daily"))
+        .respond_with(json_fixture("coingecko/market_chart.json"))
+        .mount(&server)
+        .await;
+
+    // The fixture's last two points are both on Sep 17, the later one is today's price
+    let chart = client(&server).fetch_coin_market_chart("ethereum", 3, ChartInterval::Daily).await.unwrap();
+    let days: Vec<String> = chart.prices.iter().map(|(at, _)| at.format("%Y-%m-%d").to_string()).collect();
+    assert_eq!(days, ["2024-09-15", "2024-09-16", "2024-09-17"]);
+    assert_eq!(chart.prices[2].1, 2352.44);
+    assert_eq!(chart.volumes.unwrap()[2].1, 12001234567.3);
+    assert_eq!(chart.market_caps.unwrap()[0].1, 277912345678.1);
+}
+
+#[tokio::test]
+async fn test_fetch_coin_market_chart_auto_keeps_every_point() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/coins/ethereum/market_chart"))
+        .and(query_param_is_missing("interval"))
+        .respond_with(json_fixture("coingecko/market_chart.json"))
+        .mount(&server)
+        .await;
+
+    let chart = client(&server).fetch_coin_market_chart("ethereum", 3, ChartInterval::Auto).await.unwrap();
+    assert_eq!(chart.prices.len(), 4);
+    assert_eq!(chart.prices[3].0.to_rfc3339(), "2024-09-17T10:15:12+00:00");
+}
+
+#[tokio::test]
+async fn test_fetch_coin_market_chart_of_a_new_coin_is_empty() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .respond_with(ResponseTemplate::new(200).set_body_raw(
+            "{\"prices\": [], \"market_caps\": [], \"total_volumes\": []}",
+            "application/json",
+        ))
+        .mount(&server)
+        .await;
+
+    let chart = client(&server).fetch_coin_market_chart("brand-new-coin", 30, ChartInterval::Daily).await.unwrap();
+    assert!(chart.prices.is_empty());
+    assert!(chart.volumes.is_none());
+    assert!(chart.market_caps.is_none());
+}
+
+#[tokio::test]
+async fn test_empty_market_chart_is_price_not_found() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .respond_with(ResponseTemplate::new(200).set_body_raw("{\"prices\": []}", "application/json"))
+        .mount(&server)
+        .await;
+
+    let error = client(&server).fetch_market_chart("obscure", 90).await.unwrap_err();
+    assert!(matches!(error, PriceError::PriceNotFound(_)));
+}
+
+#[tokio::test]
+async fn test_fetch_token_price_by_contract_address() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/simple/token_price/base"))
+        .and(query_param("contract_addresses", "0x4200000000000000000000000000000000000006"))
+        .and(query_param("vs_currencies", "usd"))
+        .and(query_param("include_24hr_change", "true"))
+        .respond_with(json_fixture("coingecko/token_price.json"))
+        .mount(&server)
+        .await;
+
+    let price = client(&server)
+        .fetch_token_price(Platform::Base, "0x4200000000000000000000000000000000000006")
+        .await
+        .unwrap();
+    assert_eq!(price.price_usd, 3120.5);
+    assert_eq!(price.change_24h_pct, Some(1.2034));
+    assert_eq!(price.address, "0x4200000000000000000000000000000000000006");
+}
+
+#[tokio::test]
+async fn test_fetch_token_price_uses_platform_id() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/simple/token_price/arbitrum-one"))
+        .respond_with(ResponseTemplate::new(200).set_body_raw("{}", "application/json"))
+        .mount(&server)
+        .await;
+
+    // An empty body means CoinGecko doesn't know the token on that chain
+    let error = client(&server)
+        .fetch_token_price(Platform::Arbitrum, "0x82aF49447D8a07e3bd95BD0d56f35241523fBab1")
+        .await
+        .unwrap_err();
+    assert!(matches!(error, PriceError::PriceNotFound(_)));
+}
+
+#[tokio::test]
+async fn test_fetch_token_price_rejects_invalid_address() {
+    let server = MockServer::start().await;
+    let error = client(&server).fetch_token_price(Platform::Base, "0x4200").await.unwrap_err();
+    assert!(matches!(error, PriceError::InvalidAddress(_)));
+    assert!(server.received_requests().await.unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn test_fetch_token_info() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/coins/base/contract/0x4200000000000000000000000000000000000006"))
+        .respond_with(json_fixture("coingecko/contract.json"))
+        .mount(&server)
+        .await;
+
+    let info = client(&server)
+        .fetch_token_info(Platform::Base, "0x4200000000000000000000000000000000000006")
+        .await
+        .unwrap();
+    assert_eq!(info.id, "weth");
+    assert_eq!(info.symbol, "weth");
+    assert_eq!(info.name, "WETH");
+}
+
+#[tokio::test]
+async fn test_fetch_coin_profile() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/coins/chainlink"))
+        .and(query_param("tickers", "false"))
+        .and(query_param("market_data", "true"))
+        .respond_with(json_fixture("coingecko/coin.json"))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let client = client(&server);
+    let profile = client.fetch_coin_profile("chainlink").await.unwrap();
+    assert_eq!(profile.name, "Chainlink");
+    assert_eq!(profile.symbol, "link");
+    assert_eq!(
+        profile.description,
+        "Chainlink is a decentralized oracle network that brings off-chain data such as prices & weather to smart contracts."
+    );
+    assert_eq!(profile.categories, vec!["Oracle", "Smart Contract Platform", "Ethereum Ecosystem"]);
+    assert_eq!(profile.homepage.as_deref(), Some("https://chain.link/"));
+    assert_eq!(profile.whitepaper.as_deref(), Some("https://research.chain.link/whitepaper-v2.pdf"));
+    assert_eq!(profile.repository.as_deref(), Some("https://github.com/smartcontractkit/chainlink"));
+    assert_eq!(profile.genesis_date, chrono::NaiveDate::from_ymd_opt(2017, 9, 19));
+    assert_eq!(profile.market_cap_rank, Some(12));
+    assert_eq!(profile.circulating_supply, Some(677099970.45));
+    assert_eq!(profile.max_supply, Some(1_000_000_000.0));
+
+    // The second lookup is served from the cache, the mock expects one request
+    assert_eq!(client.clone().fetch_coin_profile("chainlink").await.unwrap(), profile);
+}
+
+#[tokio::test]
+async fn test_fetch_coin_profile_with_missing_fields() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/coins/fresh-listing"))
+        .respond_with(json_fixture("coingecko/coin_sparse.json"))
+        .expect(2)
+        .mount(&server)
+        .await;
+
+    // Without a cache lifetime every lookup goes to the API
+    let client = client(&server).with_profile_ttl(Duration::ZERO);
+    let profile = client.fetch_coin_profile("fresh-listing").await.unwrap();
+    assert_eq!(profile.description, "");
+    assert!(profile.categories.is_empty());
+    assert_eq!(profile.homepage, None);
+    assert_eq!(profile.whitepaper, None);
+    assert_eq!(profile.repository, None);
+    assert_eq!(profile.genesis_date, None);
+    assert_eq!(profile.market_cap_rank, None);
+    assert_eq!(profile.max_supply, None);
+    client.fetch_coin_profile("fresh-listing").await.unwrap();
+}
+
+#[tokio::test]
+async fn test_unknown_coin_profile_is_an_invalid_response() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/coins/not-a-coin"))
+        .respond_with(ResponseTemplate::new(404).set_body_string(r#"{"error":"coin not found"}"#))
+        .mount(&server)
+        .await;
+
+    let error = client(&server).fetch_coin_profile("not-a-coin").await.unwrap_err();
+    assert!(matches!(error, PriceError::InvalidResponse(message) if message.contains("404")));
+}
+
+#[tokio::test]
+async fn test_fetch_category_list_is_cached() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/coins/categories/list"))
+        .respond_with(json_fixture("coingecko/categories_list.json"))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let client = client(&server);
+    let categories = client.fetch_category_list().await.unwrap();
+    assert_eq!(categories.len(), 18);
+    assert_eq!(categories[3].category_id, "artificial-intelligence");
+    assert_eq!(categories[3].name, "Artificial Intelligence (AI)");
+    assert_eq!(client.fetch_category_list().await.unwrap(), categories);
+}
+
+#[tokio::test]
+async fn test_fetch_coins_by_category() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/coins/markets"))
+        .and(query_param("vs_currency", "usd"))
+        .and(query_param("category", "layer-2"))
+        .and(query_param("order", "market_cap_desc"))
+        .and(query_param("per_page", "3"))
+        .and(query_param("price_change_percentage", "7d"))
+        .respond_with(json_fixture("coingecko/category_markets.json"))
+        .mount(&server)
+        .await;
+
+    let coins = client(&server).fetch_coins_by_category("layer-2", 3).await.unwrap();
+    assert_eq!(coins.len(), 3);
+    assert_eq!(coins[0].id, "mantle");
+    assert_eq!(coins[0].price_usd, Some(1.21));
+    assert_eq!(coins[0].market_cap_usd, Some(4_012_345_678.0));
+    assert_eq!(coins[1].change_7d_pct, Some(-3.25));
+    assert_eq!(coins[2].change_7d_pct, None);
+}
+
+#[test]
+fn test_user_phrasing_matches_category_ids() {
+    let categories: Vec<CoinCategory> = serde_json::from_str(&fixture("coingecko/categories_list.json")).unwrap();
+    let matched = |message: &str| {
+        let query = detect_category_query(message).unwrap();
+        match_category(&query.phrase, &categories).map(|category| category.category_id.as_str())
+    };
+
+    assert_eq!(matched("show me the top AI tokens"), Some("artificial-intelligence"));
+    assert_eq!(matched("top AI agent tokens"), Some("ai-agents"));
+    assert_eq!(matched("best RWA tokens"), Some("real-world-assets-rwa"));
+    assert_eq!(matched("top real world asset coins"), Some("real-world-assets-rwa"));
+    assert_eq!(matched("top 5 layer 2s by market cap"), Some("layer-2"));
+    assert_eq!(matched("biggest L1 coins"), Some("layer-1"));
+    assert_eq!(matched("top memecoins"), Some("meme-token"));
+    assert_eq!(matched("best meme coins"), Some("meme-token"));
+    assert_eq!(matched("top DeFi tokens"), Some("decentralized-finance-defi"));
+    assert_eq!(matched("best liquid staking tokens"), Some("liquid-staking-tokens"));
+    assert_eq!(matched("top gamefi tokens"), Some("gaming"));
+    assert_eq!(matched("top base ecosystem tokens"), Some("base-ecosystem"));
+    assert_eq!(matched("top stablecoins by market cap"), Some("stablecoins"));
+    assert_eq!(matched("best quantum tokens"), None);
+}
+
+#[test]
+fn test_render_layer_2_ranking() {
+    let coins: Vec<CategoryCoin> = serde_json::from_str(&fixture("coingecko/category_markets.json")).unwrap();
+    let layer_2 = CoinCategory { category_id: "layer-2".to_string(), name: "Layer 2 (L2)".to_string() };
+
+    let ranking = render_ranking(&layer_2, &coins);
+    assert_eq!(
+        ranking,
+        "Top 3 Layer 2 (L2) coins by market cap (CoinGecko):\n\n\
+         #  Coin              Price      7d  Market cap\n\
+         -  --------------  -------  ------  ----------\n\
+         1  Mantle (MNT)      $1.21  +12.4%  $4.01B\n\
+         2  Arbitrum (ARB)  $0.4312   -3.2%  $2.29B\n\
+         3  Optimism (OP)   $0.7123     n/a  $1.25B"
+    );
+}
+
+#[tokio::test]
+async fn test_fetch_fiat_rates_per_usd_and_cached() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/exchange_rates"))
+        .respond_with(json_fixture("coingecko/exchange_rates.json"))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let client = client(&server);
+    let rates = client.fetch_fiat_rates().await.unwrap();
+    assert_eq!(rates.get("usd"), Some(&1.0));
+    assert_eq!(rates.get("eur"), Some(&0.92));
+    assert_eq!(rates.get("cad"), Some(&1.37));
+    // Only fiat currencies, no coins or gold
+    assert_eq!(rates.len(), 3);
+
+    let rates = ExchangeRates::new(client.fetch_fiat_rates().await.unwrap());
+    let cad = find_currency("CAD").unwrap();
+    assert_eq!(rates.convert(2000.0, cad, usd()), Ok(1459.85));
+}
@@ -0,0 +1,240 @@
+//! Scratch databases and fixtures for tests that need Postgres
+//!
+//! Every `test_db()` call gets its own schema in the database named by TEST_DATABASE_URL, with all
+//! migrations applied and the seeded default user (id 1). Tests skip when the variable is not set:
+//!
+//! ```ignore
+//! let Some(pool) = test_db().await else { return };
+//! let alice = a_user().named("alice").create(&pool).await;
+//! a_strategy_for(&alice).named("Grid").create(&pool).await;
+//! knowledge_tagged(&alice, ["solana"]).content("Solana has fast blocks").create(&pool).await;
+//! ```
+//!
+//! Fixture defaults are fixed, and generated names count the rows already there, so the same
+//! test builds the same rows on every run.
+
+use agent_friend::db::{self, Knowledge, KnowledgeInput, Strategy, User};
+use serde_json::json;
+use sqlx::{Executor, Pool, Postgres, postgres::PgPoolOptions};
+
+// Migration that creates the users table; sample data after it needs user 1
+const USERS_MIGRATION_VERSION: i64 = 20250913131800;
+
+/// A fresh, fully migrated schema, or None when TEST_DATABASE_URL is not set
+pub async fn test_db() -> Option<Pool<Postgres>> {
+    let database_url = std::env::var("TEST_DATABASE_URL").ok()?;
+    let schema = format!("it_{}", uuid::Uuid::new_v4().simple());
+
+    let admin = PgPoolOptions::new()
+        .max_connections(1)
+        .connect(&database_url)
+        .await
+        .expect("Failed to connect to TEST_DATABASE_URL");
+    admin
+        .execute(format!("CREATE SCHEMA {}", schema).as_str())
+        .await
+        .expect("Failed to create test schema");
+    admin.close().await;
+
+    let search_path = format!("SET search_path TO {}", schema);
+    let pool = PgPoolOptions::new()
+        .max_connections(2)
+        .after_connect(move |conn, _meta| {
+            let search_path = search_path.clone();
+            Box::pin(async move {
+                conn.execute(search_path.as_str()).await?;
+                Ok(())
+            })
+        })
+        .connect(&database_url)
+        .await
+        .expect("Failed to connect to TEST_DATABASE_URL");
+
+    for migration in sqlx::migrate!().iter() {
+        pool.execute(&*migration.sql)
+            .await
+            .unwrap_or_else(|e| panic!("Migration {} failed: {}", migration.version, e));
+        if migration.version == USERS_MIGRATION_VERSION {
+            pool.execute("INSERT INTO users (id, username) VALUES (1, 'default_user'); SELECT setval('users_id_seq', 1)")
+                .await
+                .expect("Failed to create default user");
+        }
+    }
+
+    Some(pool)
+}
+
+async fn count(pool: &Pool<Postgres>, sql: &str, user_id: Option<i32>) -> i64 {
+    let query = sqlx::query_scalar::<_, i64>(sql);
+    let query = match user_id {
+        Some(user_id) => query.bind(user_id),
+        None => query,
+    };
+    query.fetch_one(pool).await.expect("Failed to count fixture rows")
+}
+
+/// A user, named `trader_<n>` unless named explicitly
+pub fn a_user() -> UserBuilder {
+    UserBuilder::default()
+}
+
+#[derive(Default)]
+pub struct UserBuilder {
+    username: Option<String>,
+    wallet_address: Option<String>,
+}
+
+impl UserBuilder {
+    pub fn named(mut self, username: &str) -> Self {
+        self.username = Some(username.to_string());
+        self
+    }
+
+    pub fn with_wallet(mut self, wallet_address: &str) -> Self {
+        self.wallet_address = Some(wallet_address.to_string());
+        self
+    }
+
+    pub async fn create(self, pool: &Pool<Postgres>) -> User {
+        let username = match self.username {
+            Some(username) => username,
+            None => format!("trader_{}", count(pool, "SELECT count(*) FROM users", None).await),
+        };
+        db::create_user(pool, &username, self.wallet_address.as_deref())
+            .await
+            .expect("Failed to create fixture user")
+    }
+}
+
+/// A low-risk yield strategy owned by `user`
+pub fn a_strategy_for(user: &User) -> StrategyBuilder {
+    StrategyBuilder {
+        user_id: user.id,
+        name: None,
+        category: "yield".to_string(),
+        description: "Provide stablecoin liquidity and compound the rewards weekly.".to_string(),
+        risk_level: "low".to_string(),
+        tags: vec!["stablecoins".to_string()],
+        steps: vec!["Deposit USDC/USDT".to_string(), "Compound weekly".to_string()],
+    }
+}
+
+pub struct StrategyBuilder {
+    user_id: i32,
+    name: Option<String>,
+    category: String,
+    description: String,
+    risk_level: String,
+    tags: Vec<String>,
+    steps: Vec<String>,
+}
+
+impl StrategyBuilder {
+    pub fn named(mut self, name: &str) -> Self {
+        self.name = Some(name.to_string());
+        self
+    }
+
+    pub fn category(mut self, category: &str) -> Self {
+        self.category = category.to_string();
+        self
+    }
+
+    pub fn description(mut self, description: &str) -> Self {
+        self.description = description.to_string();
+        self
+    }
+
+    pub fn risk_level(mut self, risk_level: &str) -> Self {
+        self.risk_level = risk_level.to_string();
+        self
+    }
+
+    pub fn tags<'a>(mut self, tags: impl IntoIterator<Item = &'a str>) -> Self {
+        self.tags = tags.into_iter().map(str::to_string).collect();
+        self
+    }
+
+    pub fn steps<'a>(mut self, steps: impl IntoIterator<Item = &'a str>) -> Self {
+        self.steps = steps.into_iter().map(str::to_string).collect();
+        self
+    }
+
+    pub async fn create(self, pool: &Pool<Postgres>) -> Strategy {
+        let name = match self.name {
+            Some(name) => name,
+            None => {
+                let existing = count(pool, "SELECT count(*) FROM strategies WHERE user_id = $1", Some(self.user_id)).await;
+                format!("Strategy {}", existing + 1)
+            },
+        };
+        let strategy_id = name.to_lowercase().replace(' ', "_");
+        db::create_strategy(
+            pool,
+            self.user_id,
+            &strategy_id,
+            &name,
+            &self.category,
+            &self.description,
+            &self.risk_level,
+            &self.tags,
+            &self.steps,
+            &[],
+            json!({ "apy": "4-8%" }),
+            "fixture",
+            "1.0",
+        )
+        .await
+        .expect("Failed to create fixture strategy")
+    }
+}
+
+/// A knowledge entry owned by `user` carrying `tags`
+pub fn knowledge_tagged<'a>(user: &User, tags: impl IntoIterator<Item = &'a str>) -> KnowledgeBuilder {
+    let tags: Vec<String> = tags.into_iter().map(str::to_string).collect();
+    KnowledgeBuilder {
+        user_id: user.id,
+        source_id: None,
+        content: format!("Notes about {}", tags.join(", ")),
+        tags,
+    }
+}
+
+pub struct KnowledgeBuilder {
+    user_id: i32,
+    source_id: Option<String>,
+    content: String,
+    tags: Vec<String>,
+}
+
+impl KnowledgeBuilder {
+    pub fn source_id(mut self, source_id: &str) -> Self {
+        self.source_id = Some(source_id.to_string());
+        self
+    }
+
+    pub fn content(mut self, content: &str) -> Self {
+        self.content = content.to_string();
+        self
+    }
+
+    /// The input `create` would insert, for APIs that take `KnowledgeInput`
+    pub async fn input(self, pool: &Pool<Postgres>) -> KnowledgeInput {
+        let source_id = match self.source_id {
+            Some(source_id) => source_id,
+            None => {
+                let existing = count(pool, "SELECT count(*) FROM knowledge WHERE user_id = $1", Some(self.user_id)).await;
+                format!("note-{}", existing + 1)
+            },
+        };
+        KnowledgeInput { source_id, content: self.content, tags: self.tags }
+    }
+
+    pub async fn create(self, pool: &Pool<Postgres>) -> Knowledge {
+        let user_id = self.user_id;
+        let input = self.input(pool).await;
+        db::create_knowledge(pool, user_id, &input.source_id, &input.content, &input.tags)
+            .await
+            .expect("Failed to create fixture knowledge")
+    }
+}
@@ -0,0 +1,33 @@
+// Each test binary uses only some of these helpers
+#![allow(dead_code)]
+
+pub mod db;
+
+use std::path::PathBuf;
+use wiremock::ResponseTemplate;
+
+/// Read a recorded response body from `tests/fixtures`
+pub fn fixture(name: &str) -> String {
+    let path = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures").join(name);
+    std::fs::read_to_string(&path).unwrap_or_else(|e| panic!("missing fixture {}: {}", path.display(), e))
+}
+
+/// A 200 response carrying a JSON fixture
+pub fn json_fixture(name: &str) -> ResponseTemplate {
+    ResponseTemplate::new(200).set_body_raw(fixture(name), "application/json")
+}
+
+/// A 200 server-sent event stream recorded in a fixture
+pub fn event_stream_fixture(name: &str) -> ResponseTemplate {
+    ResponseTemplate::new(200).set_body_raw(fixture(name), "text/event-stream")
+}
+
+/// A 429 response asking the client to retry after `secs`
+pub fn rate_limited(secs: u64) -> ResponseTemplate {
+    ResponseTemplate::new(429).insert_header("Retry-After", secs.to_string().as_str())
+}
+
+/// A 200 response whose body is not valid JSON
+pub fn malformed_json() -> ResponseTemplate {
+    ResponseTemplate::new(200).set_body_raw("{\"truncated\": ", "application/json")
+}
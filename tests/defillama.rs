@@ -0,0 +1,85 @@
+mod common;
+
+use agent_friend::price_fetcher::{DefiLlamaClient, PriceError};
+use common::{json_fixture, malformed_json};
+use std::time::Duration;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+
+#[tokio::test]
+async fn test_fetch_coin_price() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/prices/current/coingecko:aerodrome-finance"))
+        .respond_with(json_fixture("defillama/prices.json"))
+        .mount(&server)
+        .await;
+
+    let price = DefiLlamaClient::new(server.uri()).fetch_coin_price("aerodrome-finance").await.unwrap();
+    assert_eq!(price, 1.21);
+}
+
+#[tokio::test]
+async fn test_fetch_historical_price_uses_midnight_utc() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/prices/historical/1717286400/coingecko:aerodrome-finance"))
+        .respond_with(json_fixture("defillama/prices.json"))
+        .mount(&server)
+        .await;
+
+    let price = DefiLlamaClient::new(server.uri())
+        .fetch_coin_historical_price("aerodrome-finance", "02-06-2024")
+        .await
+        .unwrap();
+    assert_eq!(price, 1.21);
+}
+
+#[tokio::test]
+async fn test_unknown_coin_is_price_not_found() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({ "coins": {} })))
+        .mount(&server)
+        .await;
+
+    let error = DefiLlamaClient::new(server.uri()).fetch_coin_price("not-a-coin").await.unwrap_err();
+    assert!(matches!(error, PriceError::PriceNotFound(coin) if coin == "not-a-coin"));
+}
+
+#[tokio::test]
+async fn test_error_status_and_malformed_json() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/prices/current/coingecko:bitcoin"))
+        .respond_with(ResponseTemplate::new(502))
+        .mount(&server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/prices/current/coingecko:ethereum"))
+        .respond_with(malformed_json())
+        .mount(&server)
+        .await;
+
+    let client = DefiLlamaClient::new(server.uri());
+    let error = client.fetch_coin_price("bitcoin").await.unwrap_err();
+    assert!(matches!(error, PriceError::InvalidResponse(msg) if msg.contains("502")));
+    let error = client.fetch_coin_price("ethereum").await.unwrap_err();
+    assert!(matches!(error, PriceError::InvalidResponse(msg) if msg.starts_with("Malformed JSON")));
+}
+
+#[tokio::test]
+async fn test_timeout() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .respond_with(json_fixture("defillama/prices.json").set_delay(Duration::from_millis(500)))
+        .mount(&server)
+        .await;
+
+    let error = DefiLlamaClient::new(server.uri())
+        .with_timeout(Duration::from_millis(50))
+        .fetch_coin_price("aerodrome-finance")
+        .await
+        .unwrap_err();
+    assert!(matches!(error, PriceError::NetworkError(e) if e.is_timeout()));
+}
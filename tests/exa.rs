@@ -0,0 +1,301 @@
+mod common;
+
+use agent_friend::api_budget::BudgetLimits;
+use agent_friend::circuit_breaker::{BreakerSettings, BreakerState, CircuitBreaker};
+use agent_friend::exa_api::{ContentsOptions, ExaApiClient, ExaApiError, ExaContentsResponse, HighlightsOptions};
+use common::{json_fixture, malformed_json, rate_limited};
+use chrono::NaiveDate;
+use std::sync::Arc;
+use std::time::Duration;
+use serde_json::json;
+use wiremock::matchers::{body_json, body_partial_json, header, method, path, query_param};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+
+fn client(server: &MockServer) -> ExaApiClient {
+    ExaApiClient::with_api_key("exa-test".to_string()).with_base_url(&server.uri())
+}
+
+#[tokio::test]
+async fn test_search() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/api/search"))
+        .and(query_param("query", "aerodrome"))
+        .and(query_param("num_results", "3"))
+        .and(header("x-api-key", "exa-test"))
+        .respond_with(json_fixture("exa/search.json"))
+        .mount(&server)
+        .await;
+
+    let response = client(&server).search("aerodrome", 3, None).await.unwrap();
+    assert_eq!(response.results.len(), 1);
+    assert_eq!(response.results[0].title, "Aerodrome Finance Docs");
+    assert!(response.next_page_id.is_none());
+}
+
+#[tokio::test]
+async fn test_news_search_filters_by_published_date() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/api/search"))
+        .and(query_param("query", "cryptocurrency solana latest news"))
+        .and(query_param("num_results", "10"))
+        .and(query_param("start_published_date", "2025-09-17"))
+        .respond_with(json_fixture("exa/search.json"))
+        .mount(&server)
+        .await;
+
+    let since = NaiveDate::from_ymd_opt(2025, 9, 17).unwrap();
+    let response = client(&server).search_news_since("solana", 10, since).await.unwrap();
+    assert_eq!(response.results.len(), 1);
+}
+
+#[tokio::test]
+async fn test_unauthorized() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .respond_with(ResponseTemplate::new(401))
+        .mount(&server)
+        .await;
+
+    let error = client(&server).search("aerodrome", 3, None).await.unwrap_err();
+    assert!(matches!(error, ExaApiError::Unauthorized));
+}
+
+#[tokio::test]
+async fn test_rate_limit_carries_retry_after() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .respond_with(rate_limited(7))
+        .mount(&server)
+        .await;
+
+    let error = client(&server).search("aerodrome", 3, None).await.unwrap_err();
+    assert!(matches!(error, ExaApiError::RateLimited(Some(wait)) if wait == Duration::from_secs(7)));
+}
+
+#[tokio::test]
+async fn test_server_error() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .respond_with(ResponseTemplate::new(500))
+        .mount(&server)
+        .await;
+
+    let error = client(&server).search("aerodrome", 3, None).await.unwrap_err();
+    assert!(matches!(error, ExaApiError::RequestFailed(msg) if msg.contains("500")));
+}
+
+#[tokio::test]
+async fn test_malformed_json() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .respond_with(malformed_json())
+        .mount(&server)
+        .await;
+
+    let error = client(&server).search("aerodrome", 3, None).await.unwrap_err();
+    assert!(matches!(error, ExaApiError::ParseError(_)));
+}
+
+#[tokio::test]
+async fn test_failed_research_falls_back_to_empty_results() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .respond_with(ResponseTemplate::new(502))
+        .mount(&server)
+        .await;
+
+    let response = client(&server).search_crypto_project("aerodrome", 3).await.unwrap();
+    assert!(response.results.is_empty());
+}
+
+#[tokio::test]
+async fn test_calls_past_the_daily_budget_are_skipped() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/api/search"))
+        .respond_with(json_fixture("exa/search.json"))
+        .expect(2)
+        .mount(&server)
+        .await;
+    let breaker = Arc::new(
+        CircuitBreaker::new("exa", BreakerSettings::default()).with_budget(BudgetLimits { soft: Some(1), hard: Some(2) }),
+    );
+    let client = client(&server).with_breaker(breaker.clone());
+
+    // Past the soft limit calls still go through
+    client.search("aerodrome", 3, None).await.unwrap();
+    client.search("aerodrome", 3, None).await.unwrap();
+    let error = client.search("aerodrome", 3, None).await.unwrap_err();
+    assert_eq!(error.to_string(), "Exa API calls are paused: today's budget of 2 exa calls is used up, they resume at midnight UTC");
+    // Research degrades to empty results like it does for an outage
+    assert!(client.search_crypto_project("aerodrome", 3).await.unwrap().results.is_empty());
+    let usage = breaker.usage();
+    assert_eq!((usage.calls, usage.skipped), (2, 2));
+    assert_eq!(breaker.state(), BreakerState::Closed);
+}
+
+#[tokio::test]
+async fn test_circuit_breaker_reopens_when_the_probe_fails() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .respond_with(ResponseTemplate::new(502))
+        .mount(&server)
+        .await;
+    let breaker = Arc::new(CircuitBreaker::new("exa", BreakerSettings {
+        failure_threshold: 1,
+        cool_down: Duration::from_millis(200),
+    }));
+    let client = client(&server).with_breaker(breaker.clone());
+
+    assert!(matches!(client.search("aerodrome", 3, None).await, Err(ExaApiError::RequestFailed(_))));
+    assert!(matches!(client.search("aerodrome", 3, None).await, Err(ExaApiError::CircuitOpen)));
+    // Research still degrades to empty results, without waiting on Exa
+    assert!(client.search_crypto_project("aerodrome", 3).await.unwrap().results.is_empty());
+    assert_eq!(server.received_requests().await.unwrap().len(), 1);
+
+    tokio::time::sleep(Duration::from_millis(250)).await;
+    assert!(matches!(client.search("aerodrome", 3, None).await, Err(ExaApiError::RequestFailed(_))));
+    let snapshot = breaker.snapshot();
+    assert_eq!(snapshot.state, BreakerState::Open);
+    assert_eq!(snapshot.trips, 2);
+    assert_eq!(server.received_requests().await.unwrap().len(), 2);
+}
+
+#[tokio::test]
+async fn test_timeout() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .respond_with(json_fixture("exa/search.json").set_delay(Duration::from_millis(500)))
+        .mount(&server)
+        .await;
+
+    let error = client(&server)
+        .with_timeout(Duration::from_millis(50))
+        .search("aerodrome", 3, None)
+        .await
+        .unwrap_err();
+    assert!(matches!(error, ExaApiError::HttpError(e) if e.is_timeout()));
+}
+
+fn ids(ids: &[&str]) -> Vec<String> {
+    ids.iter().map(|id| id.to_string()).collect()
+}
+
+#[test]
+fn test_contents_fixture_deserializes() {
+    let response: ExaContentsResponse = serde_json::from_str(&common::fixture("exa/contents.json")).unwrap();
+
+    assert_eq!(response.results.len(), 2);
+    let docs = response.get("https://aerodrome.finance/docs").unwrap();
+    assert_eq!(docs.title.as_deref(), Some("Aerodrome Finance Docs"));
+    assert_eq!(docs.published_date.as_deref(), Some("2024-05-01T00:00:00.000Z"));
+    assert!(docs.text.as_deref().unwrap().contains("veAERO"));
+    assert_eq!(docs.highlight_scores, vec![0.62]);
+    // Highlights weren't requested for this page
+    assert!(response.get("https://news.example.com/aerodrome-slipstream").unwrap().highlights.is_empty());
+
+    let failed: Vec<_> = response.failed().collect();
+    assert_eq!(failed.len(), 1);
+    assert_eq!(failed[0].id, "https://blog.example.com/aero-tokenomics");
+    let error = failed[0].error.as_ref().unwrap();
+    assert_eq!(error.tag.as_deref(), Some("CRAWL_NOT_FOUND"));
+    assert_eq!(error.http_status_code, Some(404));
+}
+
+#[tokio::test]
+async fn test_get_contents_sends_text_and_highlight_options() {
+    let server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path("/contents"))
+        .and(header("x-api-key", "exa-test"))
+        .and(body_json(json!({
+            "ids": ["https://aerodrome.finance/docs"],
+            "text": { "maxCharacters": 2000, "includeHtmlTags": false },
+            "highlights": { "numSentences": 2, "query": "emissions" }
+        })))
+        .respond_with(json_fixture("exa/contents.json"))
+        .mount(&server)
+        .await;
+
+    let mut options = ContentsOptions::text(2000);
+    options.highlights = Some(HighlightsOptions {
+        num_sentences: Some(2),
+        highlights_per_url: None,
+        query: Some("emissions".to_string()),
+    });
+    let response = client(&server)
+        .get_contents(&ids(&["https://aerodrome.finance/docs"]), &options)
+        .await
+        .unwrap();
+    assert_eq!(response.get("https://aerodrome.finance/docs").unwrap().highlights.len(), 1);
+}
+
+#[tokio::test]
+async fn test_get_contents_errors() {
+    let server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path("/contents"))
+        .respond_with(ResponseTemplate::new(401))
+        .mount(&server)
+        .await;
+
+    let error = client(&server).get_contents(&ids(&["a"]), &ContentsOptions::default()).await.unwrap_err();
+    assert!(matches!(error, ExaApiError::Unauthorized));
+
+    let error = client(&server).get_contents(&[], &ContentsOptions::default()).await.unwrap_err();
+    assert!(matches!(error, ExaApiError::InvalidInput(_)));
+}
+
+#[tokio::test]
+async fn test_research_uses_full_text_for_top_results_only() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/api/search"))
+        .respond_with(json_fixture("exa/search_research.json"))
+        .mount(&server)
+        .await;
+    Mock::given(method("POST"))
+        .and(path("/contents"))
+        .and(body_partial_json(json!({
+            "ids": [
+                "https://aerodrome.finance/docs",
+                "https://blog.example.com/aero-tokenomics",
+                "https://news.example.com/aerodrome-slipstream"
+            ]
+        })))
+        .respond_with(json_fixture("exa/contents.json"))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let response = client(&server).search_crypto_project("aerodrome", 4).await.unwrap();
+    let contents: Vec<&str> = response.results.iter().map(|result| result.content.as_str()).collect();
+
+    assert!(contents[0].contains("voters earn the trading fees"));
+    // The page Exa couldn't crawl keeps its snippet
+    assert_eq!(contents[1], "A look at AERO emissions.");
+    assert!(contents[2].starts_with("Aerodrome launched Slipstream"));
+    // Past the top three only the snippet is used
+    assert_eq!(contents[3], "veAERO voters directed emissions to stable pools this epoch.");
+}
+
+#[tokio::test]
+async fn test_search_and_contents_keeps_snippets_when_contents_fail() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/api/search"))
+        .respond_with(json_fixture("exa/search.json"))
+        .mount(&server)
+        .await;
+    Mock::given(method("POST"))
+        .and(path("/contents"))
+        .respond_with(ResponseTemplate::new(503))
+        .mount(&server)
+        .await;
+
+    let response = client(&server).search_and_contents("aerodrome", 3).await.unwrap();
+    assert_eq!(response.results.len(), 1);
+    assert!(response.results[0].content.starts_with("Aerodrome is the central liquidity hub on Base."));
+}
@@ -0,0 +1,48 @@
+mod common;
+
+use agent_friend::price_fetcher::{ExchangeRateClient, PriceError};
+use common::{json_fixture, malformed_json};
+use wiremock::matchers::{method, path, query_param};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+
+#[tokio::test]
+async fn test_fetch_fiat_rates_lowercases_codes_and_caches() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/latest"))
+        .and(query_param("base", "USD"))
+        .respond_with(json_fixture("exchangerate_host/latest.json"))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let client = ExchangeRateClient::new(server.uri());
+    let rates = client.fetch_fiat_rates().await.unwrap();
+    assert_eq!(rates.get("cad"), Some(&1.37));
+    assert_eq!(rates.get("jpy"), Some(&151.37));
+    assert_eq!(client.fetch_fiat_rates().await.unwrap(), rates);
+}
+
+#[tokio::test]
+async fn test_error_status_empty_rates_and_malformed_json() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .respond_with(ResponseTemplate::new(502))
+        .mount(&server)
+        .await;
+    let error = ExchangeRateClient::new(server.uri()).fetch_fiat_rates().await.unwrap_err();
+    assert!(matches!(error, PriceError::InvalidResponse(message) if message.contains("502")));
+
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({ "success": false })))
+        .mount(&server)
+        .await;
+    let error = ExchangeRateClient::new(server.uri()).fetch_fiat_rates().await.unwrap_err();
+    assert!(matches!(error, PriceError::PriceNotFound(_)));
+
+    let server = MockServer::start().await;
+    Mock::given(method("GET")).respond_with(malformed_json()).mount(&server).await;
+    let error = ExchangeRateClient::new(server.uri()).fetch_fiat_rates().await.unwrap_err();
+    assert!(matches!(error, PriceError::InvalidResponse(_)));
+}
@@ -0,0 +1,112 @@
+use agent_friend::health::{
+    AnthropicProbe, CoinGeckoProbe, ExaProbe, HealthChecker, OpenAiProbe, Probe, ProbeStatus, RpcProbe,
+};
+use reqwest::Client;
+use std::sync::Arc;
+use wiremock::matchers::{body_partial_json, header, method, path};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+
+#[tokio::test]
+async fn test_probes_report_each_integration() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/v1/models"))
+        .and(header("x-api-key", "bad-key"))
+        .respond_with(ResponseTemplate::new(401))
+        .mount(&server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/ping"))
+        .and(header("x-cg-demo-api-key", "cg-key"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({ "gecko_says": "(V3) To the Moon!" })))
+        .mount(&server)
+        .await;
+    Mock::given(method("POST"))
+        .and(path("/search"))
+        .respond_with(ResponseTemplate::new(429))
+        .mount(&server)
+        .await;
+    Mock::given(method("POST"))
+        .and(path("/rpc"))
+        .and(body_partial_json(serde_json::json!({ "method": "eth_chainId" })))
+        .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({ "jsonrpc": "2.0", "id": 1, "result": "0x14a34" })))
+        .mount(&server)
+        .await;
+
+    let client = Client::new();
+    let probes: Vec<Arc<dyn Probe>> = vec![
+        Arc::new(AnthropicProbe::new(client.clone(), &server.uri(), "bad-key")),
+        Arc::new(CoinGeckoProbe::new(client.clone(), &server.uri(), Some("cg-key"))),
+        Arc::new(ExaProbe::new(client.clone(), &server.uri(), "exa-key")),
+        Arc::new(RpcProbe::new(client, &format!("{}/rpc", server.uri()))),
+    ];
+    let health = HealthChecker::new(probes).check().await;
+
+    let anthropic = &health.probes[0];
+    assert_eq!(anthropic.status, ProbeStatus::Down);
+    assert_eq!(anthropic.last_error.as_deref(), Some("Anthropic rejected the API key"));
+
+    assert_eq!(health.probes[1].status, ProbeStatus::Up);
+
+    let exa = &health.probes[2];
+    assert_eq!(exa.status, ProbeStatus::Degraded);
+    assert!(exa.last_error.as_deref().unwrap().contains("429"));
+
+    let rpc = &health.probes[3];
+    assert_eq!(rpc.status, ProbeStatus::Up);
+    assert_eq!(rpc.detail.as_deref(), Some("chain id 84532"));
+
+    // Anthropic is critical, so the agent as a whole is down
+    assert_eq!(health.status, ProbeStatus::Down);
+}
+
+#[tokio::test]
+async fn test_rpc_error_is_degraded() {
+    let server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+            "jsonrpc": "2.0",
+            "id": 1,
+            "error": { "code": -32601, "message": "method not found" }
+        })))
+        .mount(&server)
+        .await;
+
+    let health = HealthChecker::new(vec![Arc::new(RpcProbe::new(Client::new(), &server.uri()))]).check().await;
+    assert_eq!(health.probes[0].status, ProbeStatus::Degraded);
+    assert!(health.probes[0].last_error.as_deref().unwrap().contains("method not found"));
+    assert_eq!(health.status, ProbeStatus::Degraded);
+}
+
+#[tokio::test]
+async fn test_openai_compatible_probe_lists_models() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/v1/models"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({ "object": "list", "data": [] })))
+        .mount(&server)
+        .await;
+
+    let probe = OpenAiProbe::new(Client::new(), &format!("{}/v1", server.uri()), None).named("ollama");
+    let health = HealthChecker::new(vec![Arc::new(probe)]).check().await;
+    assert_eq!(health.probes[0].name, "ollama");
+    assert_eq!(health.probes[0].status, ProbeStatus::Up);
+
+    let requests = server.received_requests().await.unwrap();
+    assert!(!requests[0].headers.contains_key("authorization"));
+}
+
+#[tokio::test]
+async fn test_coingecko_pro_key_is_sent_in_the_pro_header() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/ping"))
+        .and(header("x-cg-pro-api-key", "cg-pro"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({ "gecko_says": "(V3) To the Moon!" })))
+        .mount(&server)
+        .await;
+
+    let probe = CoinGeckoProbe::new(Client::new(), &server.uri(), Some("cg-pro")).with_pro(true);
+    let health = HealthChecker::new(vec![Arc::new(probe)]).check().await;
+    assert_eq!(health.probes[0].status, ProbeStatus::Up);
+}
@@ -0,0 +1,597 @@
+mod common;
+
+use agent_friend::anthropic::AnthropicClient;
+use agent_friend::clock::Clock;
+use agent_friend::commands::handle_command;
+use agent_friend::compliance;
+use agent_friend::db::{self, MessageRole, NewTrade, OrderType, TopicKind, Verbosity};
+use agent_friend::investment_chat::Intent;
+use agent_friend::investment_chat::{InvestmentChatAgent, InvestmentChatError};
+use agent_friend::rate_limit::{RateLimitSettings, RateLimiter};
+use agent_friend::trade_command::{self, StagedTrade};
+use agent_friend::timezone::UserTimezone;
+use agent_friend::investment_chat::INTERRUPTED_MARKER;
+use common::db::{a_strategy_for, a_user, knowledge_tagged, test_db};
+use chrono::{DateTime, TimeZone, Utc};
+use common::{event_stream_fixture, json_fixture};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+
+#[tokio::test]
+async fn test_messages_come_back_most_recent_first() {
+    let Some(pool) = test_db().await else { return };
+    let user = a_user().create(&pool).await;
+
+    db::save_message(&pool, user.id, MessageRole::User, "what is restaking?").await.unwrap();
+    db::save_message(&pool, user.id, MessageRole::Assistant, "Restaking reuses staked ETH.").await.unwrap();
+    db::save_message(&pool, user.id, MessageRole::User, "and the risks?").await.unwrap();
+
+    let messages = db::get_messages(&pool, user.id, 10).await.unwrap();
+    let contents: Vec<&str> = messages.iter().map(|message| message.content.as_str()).collect();
+    assert_eq!(contents, vec!["and the risks?", "Restaking reuses staked ETH.", "what is restaking?"]);
+    assert_eq!(messages[1].role, MessageRole::Assistant);
+
+    let latest = db::get_messages(&pool, user.id, 1).await.unwrap();
+    assert_eq!(latest.len(), 1);
+    assert_eq!(latest[0].content, "and the risks?");
+
+    // Another user's history stays separate
+    let other = a_user().create(&pool).await;
+    assert!(db::get_messages(&pool, other.id, 10).await.unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn test_knowledge_matches_any_overlapping_tag() {
+    let Some(pool) = test_db().await else { return };
+    let user = a_user().create(&pool).await;
+    let solana = knowledge_tagged(&user, ["solana"]).content("Solana has sub-second blocks").create(&pool).await;
+    let staking = knowledge_tagged(&user, ["solana", "staking"]).create(&pool).await;
+    knowledge_tagged(&user, ["ethereum"]).create(&pool).await;
+    let other = a_user().create(&pool).await;
+    knowledge_tagged(&other, ["solana"]).create(&pool).await;
+
+    let found = db::get_knowledge_by_tags(&pool, user.id, &["solana".to_string(), "defi".to_string()]).await.unwrap();
+    let mut ids: Vec<i32> = found.iter().map(|entry| entry.id).collect();
+    ids.sort();
+    assert_eq!(ids, vec![solana.id, staking.id]);
+    assert_eq!(staking.source_id, "note-2");
+
+    let staking_only = db::get_knowledge_by_tags(&pool, user.id, &["staking".to_string()]).await.unwrap();
+    assert_eq!(staking_only.len(), 1);
+    assert_eq!(staking_only[0].content, "Notes about solana, staking");
+
+    assert!(db::get_knowledge_by_tags(&pool, user.id, &[]).await.unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn test_agent_reuses_the_existing_user() {
+    let Some(pool) = test_db().await else { return };
+    let user = a_user().named("alice").create(&pool).await;
+
+    let agent = InvestmentChatAgent::with_pool(&pool, "alice").await.unwrap();
+    assert_eq!(agent.user_id(), user.id);
+
+    let newcomer = InvestmentChatAgent::with_pool(&pool, "bob").await.unwrap();
+    assert_ne!(newcomer.user_id(), user.id);
+    assert!(db::get_user_by_username(&pool, "bob").await.unwrap().is_some());
+}
+
+#[tokio::test]
+async fn test_preference_turn_saves_both_messages() {
+    let Some(pool) = test_db().await else { return };
+    let agent = InvestmentChatAgent::with_pool(&pool, "alice").await.unwrap();
+
+    let reply = agent.process_message("please be brief from now on").await.unwrap();
+    assert_eq!(agent.verbosity(), Verbosity::Brief);
+
+    let messages = db::get_messages(&pool, agent.user_id(), 10).await.unwrap();
+    assert_eq!(messages.len(), 2);
+    assert_eq!(messages[0].role, MessageRole::Assistant);
+    assert_eq!(messages[0].content, reply);
+    assert_eq!(messages[1].role, MessageRole::User);
+    assert_eq!(messages[1].content, "please be brief from now on");
+
+    // The preference outlives the agent
+    let user = db::get_user_by_id(&pool, agent.user_id()).await.unwrap().unwrap();
+    assert_eq!(user.verbosity, Verbosity::Brief);
+    let again = InvestmentChatAgent::with_pool(&pool, "alice").await.unwrap();
+    assert_eq!(again.verbosity(), Verbosity::Brief);
+}
+
+#[tokio::test]
+async fn test_timezone_preference_is_saved() {
+    let Some(pool) = test_db().await else { return };
+    let agent = InvestmentChatAgent::with_pool(&pool, "alice").await.unwrap();
+
+    let reply = agent.process_message("my timezone is GMT+5:30").await.unwrap();
+    assert_eq!(
+        reply,
+        "Got it, your timezone is UTC+05:30. Dates like \"yesterday\", the times I show you and your daily briefing now follow it."
+    );
+    let user = db::get_user_by_id(&pool, agent.user_id()).await.unwrap().unwrap();
+    assert_eq!(user.timezone, "UTC+05:30");
+
+    // Named zones are saved by their canonical name
+    let reply = agent.process_message("set my timezone to europe/berlin").await.unwrap();
+    assert!(reply.starts_with("Got it, your timezone is Europe/Berlin."));
+    let user = db::get_user_by_id(&pool, agent.user_id()).await.unwrap().unwrap();
+    assert_eq!(user.timezone, "Europe/Berlin");
+    let again = InvestmentChatAgent::with_pool(&pool, "alice").await.unwrap();
+    assert_eq!(again.timezone(), UserTimezone::Named(chrono_tz::Europe::Berlin));
+
+    // Unknown ones are explained, not saved
+    let reply = agent.process_message("set my timezone to Atlantis/Capital").await.unwrap();
+    assert!(reply.starts_with("I don't know the timezone \"Atlantis/Capital\""));
+    let user = db::get_user_by_id(&pool, agent.user_id()).await.unwrap().unwrap();
+    assert_eq!(user.timezone, "Europe/Berlin");
+    assert_eq!(db::get_messages(&pool, agent.user_id(), 10).await.unwrap().len(), 6);
+}
+
+#[tokio::test]
+async fn test_rate_limited_turn_is_not_saved() {
+    let Some(pool) = test_db().await else { return };
+    let mut settings = RateLimitSettings::new(1, 1);
+    settings.bypass = vec!["admin".to_string()];
+    let limiter = Arc::new(RateLimiter::new(settings));
+    let agent = InvestmentChatAgent::with_pool(&pool, "alice").await.unwrap().with_rate_limiter(limiter.clone());
+
+    agent.process_message("be brief").await.unwrap();
+    let error = agent.process_message("be more detailed").await.unwrap_err();
+    assert!(matches!(error, InvestmentChatError::RateLimited { retry_after } if retry_after.as_secs() > 50));
+    assert_eq!(db::get_messages(&pool, agent.user_id(), 10).await.unwrap().len(), 2);
+    assert_eq!(agent.verbosity(), Verbosity::Brief);
+
+    let admin = InvestmentChatAgent::with_pool(&pool, "admin").await.unwrap().with_rate_limiter(limiter);
+    admin.process_message("be brief").await.unwrap();
+    admin.process_message("be more detailed").await.unwrap();
+    assert_eq!(admin.verbosity(), Verbosity::Detailed);
+}
+
+/// A clock the test moves forward by hand
+struct SteppedClock(Mutex<DateTime<Utc>>);
+
+impl SteppedClock {
+    fn advance(&self, seconds: i64) {
+        *self.0.lock().unwrap() += chrono::Duration::seconds(seconds);
+    }
+}
+
+impl Clock for SteppedClock {
+    fn now(&self) -> DateTime<Utc> {
+        *self.0.lock().unwrap()
+    }
+}
+
+#[tokio::test]
+async fn test_resent_messages_get_the_first_answer_without_a_second_entry() {
+    let Some(pool) = test_db().await else { return };
+    let clock = Arc::new(SteppedClock(Mutex::new(Utc.with_ymd_and_hms(2025, 10, 16, 10, 0, 0).unwrap())));
+    let agent = InvestmentChatAgent::with_pool(&pool, "alice").await.unwrap().with_clock(clock.clone());
+    let user_id = agent.user_id();
+    let history = || async { db::get_messages(&pool, user_id, 20).await.unwrap().len() };
+
+    let first = agent.process_turn("what's 2 + 2").await.unwrap();
+    clock.advance(5);
+    let resent = agent.process_turn("what's 2 + 2").await.unwrap();
+    assert_eq!(resent, first);
+    assert_eq!(history().await, 2);
+
+    // After the window, or with another message in between, the question is answered again
+    clock.advance(16);
+    agent.process_turn("what's 2 + 2").await.unwrap();
+    assert_eq!(history().await, 4);
+    agent.process_turn("what's 3 + 3").await.unwrap();
+    agent.process_turn("what's 2 + 2").await.unwrap();
+    assert_eq!(history().await, 8);
+
+    // A zero window answers every copy
+    let agent = agent.with_duplicate_window(Duration::ZERO);
+    agent.process_turn("what's 3 + 3").await.unwrap();
+    agent.process_turn("what's 3 + 3").await.unwrap();
+    assert_eq!(history().await, 12);
+}
+
+#[tokio::test]
+async fn test_resend_while_answering_waits_for_the_same_answer() {
+    let Some(pool) = test_db().await else { return };
+    let server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path("/v1/messages"))
+        .respond_with(json_fixture("anthropic/messages.json").set_delay(Duration::from_millis(300)))
+        .expect(1)
+        .mount(&server)
+        .await;
+    let model = AnthropicClient::new("test-key").with_base_url(&server.uri());
+    let agent = InvestmentChatAgent::with_pool(&pool, "alice").await.unwrap().with_model(Arc::new(model));
+
+    let (first, resent) = tokio::join!(
+        agent.process_message("Explain how liquidity pools work"),
+        agent.process_message("Explain how liquidity pools work"),
+    );
+    assert_eq!(first.unwrap(), resent.unwrap());
+
+    let messages = db::get_messages(&pool, agent.user_id(), 10).await.unwrap();
+    let roles: Vec<MessageRole> = messages.iter().map(|message| message.role).collect();
+    assert_eq!(roles, vec![MessageRole::Assistant, MessageRole::User]);
+}
+
+#[tokio::test]
+async fn test_answers_record_the_active_prompt_variant() {
+    let Some(pool) = test_db().await else { return };
+    let agent = InvestmentChatAgent::with_pool(&pool, "alice").await.unwrap();
+
+    let reply = handle_command(&agent, "/system set Answer like a terse floor trader.\nNo small talk.").await.unwrap().unwrap();
+    let variant = agent.system_prompt().unwrap().variant().to_string();
+    assert!(reply.contains(&variant));
+    assert_eq!(agent.system_prompt().unwrap().text(), "Answer like a terse floor trader.\nNo small talk.");
+    let conversation = db::get_conversation(&pool, agent.conversation_id()).await.unwrap().unwrap();
+    assert_eq!(conversation.user_id, agent.user_id());
+    assert_eq!(conversation.system_prompt_override.as_deref(), Some("Answer like a terse floor trader.\nNo small talk."));
+    agent.process_message("what's 2 + 2").await.unwrap();
+
+    handle_command(&agent, "/system clear").await.unwrap().unwrap();
+    assert!(agent.system_prompt().is_none());
+    let conversation = db::get_conversation(&pool, agent.conversation_id()).await.unwrap().unwrap();
+    assert!(conversation.system_prompt_override.is_none());
+    agent.process_message("what's 3 + 3").await.unwrap();
+
+    let variants: Vec<(String, String, Option<String>)> =
+        sqlx::query_as("SELECT role, content, prompt_variant FROM messages WHERE user_id = $1 ORDER BY id")
+            .bind(agent.user_id())
+            .fetch_all(&pool)
+            .await
+            .unwrap();
+    let assistant: Vec<Option<&str>> = variants
+        .iter()
+        .filter(|(role, _, _)| role == "assistant")
+        .map(|(_, _, variant)| variant.as_deref())
+        .collect();
+    assert_eq!(assistant, vec![Some(variant.as_str()), None]);
+
+    // A new session starts on the default prompt
+    let again = InvestmentChatAgent::with_pool(&pool, "alice").await.unwrap();
+    assert!(again.system_prompt().is_none());
+    assert_ne!(again.conversation_id(), agent.conversation_id());
+    let too_long = format!("/system set {}", "a".repeat(2_001));
+    assert!(matches!(handle_command(&again, &too_long).await.unwrap(), Err(InvestmentChatError::InvalidInput(_))));
+}
+
+#[tokio::test]
+async fn test_blank_answers_are_not_saved() {
+    let Some(pool) = test_db().await else { return };
+    let server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path("/v1/messages"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({ "id": "msg_blank", "content": [] })))
+        .up_to_n_times(1)
+        .mount(&server)
+        .await;
+    Mock::given(method("POST"))
+        .and(path("/v1/messages"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+            "id": "msg_whitespace",
+            "content": [{ "type": "text", "text": "\n\n  " }]
+        })))
+        .mount(&server)
+        .await;
+    let model = AnthropicClient::new("test-key").with_base_url(&server.uri());
+    let agent = InvestmentChatAgent::with_pool(&pool, "alice").await.unwrap().with_model(Arc::new(model));
+
+    let error = agent.process_message("Explain how liquidity pools work").await.unwrap_err();
+    assert!(matches!(error, InvestmentChatError::EmptyResponse { request_id } if request_id.as_deref() == Some("msg_whitespace")));
+    assert_eq!(server.received_requests().await.unwrap().len(), 2);
+
+    let messages = db::get_messages(&pool, agent.user_id(), 10).await.unwrap();
+    assert_eq!(messages.len(), 1);
+    assert_eq!(messages[0].role, MessageRole::User);
+}
+
+#[tokio::test]
+async fn test_compliance_mode_adds_one_disclaimer_and_refuses_sizing() {
+    let Some(pool) = test_db().await else { return };
+    let server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path("/v1/messages"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+            "id": "msg_pools",
+            "content": [{ "type": "text", "text": "Liquidity pools pair two tokens.\n\n*Disclaimer: this is not financial advice.*" }]
+        })))
+        .mount(&server)
+        .await;
+    let model = AnthropicClient::new("test-key").with_base_url(&server.uri());
+    let agent = InvestmentChatAgent::with_pool(&pool, "guest")
+        .await
+        .unwrap()
+        .with_model(Arc::new(model))
+        .with_compliance(true);
+
+    let answer = agent.process_message("Explain how liquidity pools work").await.unwrap();
+    assert_eq!(answer, format!("Liquidity pools pair two tokens.\n\n{}", compliance::DISCLAIMER));
+    let request = String::from_utf8(server.received_requests().await.unwrap()[0].body.clone()).unwrap();
+    assert!(request.contains("COMPLIANCE: You are talking to members of the public"));
+
+    let sizing = agent
+        .process_turn("how much sol can I buy risking 0.5% with a stop-loss at 120, entry at 130 on a $25k account")
+        .await
+        .unwrap();
+    assert_eq!(sizing.text, compliance::blocked_reply(sizing.intent).unwrap());
+    assert!(!sizing.text.contains(&compliance::DISCLAIMER[..20]));
+
+    // The saved answer is the one shown
+    let saved = db::get_messages(&pool, agent.user_id(), 3).await.unwrap();
+    assert_eq!(saved[2].content, answer);
+}
+
+#[tokio::test]
+async fn test_interrupted_stream_saves_the_partial_answer() {
+    let Some(pool) = test_db().await else { return };
+    let server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path("/v1/messages"))
+        .respond_with(event_stream_fixture("anthropic/stream_overloaded.txt"))
+        .up_to_n_times(1)
+        .mount(&server)
+        .await;
+    Mock::given(method("POST"))
+        .and(path("/v1/messages"))
+        .respond_with(event_stream_fixture("anthropic/stream.txt"))
+        .mount(&server)
+        .await;
+    let shown = Arc::new(Mutex::new(String::new()));
+    let sink = shown.clone();
+    let model = AnthropicClient::new("test-key").with_base_url(&server.uri());
+    let agent = InvestmentChatAgent::with_pool(&pool, "alice")
+        .await
+        .unwrap()
+        .with_model(Arc::new(model))
+        .with_stream(Arc::new(move |piece: &str| sink.lock().unwrap().push_str(piece)));
+
+    let turn = agent.process_turn("Explain how liquidity pools work").await.unwrap();
+    let partial = "Liquidity pools pair two tokens so traders can swap against";
+    assert_eq!(turn.text, format!("{}\n\n{}", partial, INTERRUPTED_MARKER));
+    assert!(turn.truncated);
+    assert_eq!(shown.lock().unwrap().trim_end(), partial);
+
+    let saved: (String, serde_json::Value) =
+        sqlx::query_as("SELECT content, metadata FROM messages WHERE user_id = $1 AND role = 'assistant'")
+            .bind(agent.user_id())
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+    assert_eq!(saved.0, turn.text);
+    assert_eq!(saved.1["truncated"], serde_json::json!(true));
+    assert!(db::get_messages(&pool, agent.user_id(), 1).await.unwrap()[0].truncated);
+
+    // The next prompt tells the model its last answer was cut off
+    let turn = agent.process_turn("How are the fees shared?").await.unwrap();
+    assert!(!turn.truncated);
+    let requests = server.received_requests().await.unwrap();
+    let prompt = String::from_utf8(requests[1].body.clone()).unwrap();
+    assert!(prompt.contains("ASSISTANT (this answer was interrupted before it finished):"), "{}", prompt);
+    let latest = db::get_messages(&pool, agent.user_id(), 1).await.unwrap();
+    assert!(!latest[0].truncated);
+}
+
+#[tokio::test]
+async fn test_turns_record_their_topics() {
+    let Some(pool) = test_db().await else { return };
+    let server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path("/v1/messages"))
+        .respond_with(json_fixture("anthropic/messages.json"))
+        .mount(&server)
+        .await;
+    let model = AnthropicClient::new("test-key").with_base_url(&server.uri());
+    let agent = InvestmentChatAgent::with_pool(&pool, "alice").await.unwrap().with_model(Arc::new(model));
+    let topics = |message_id: i32| {
+        let pool = pool.clone();
+        async move {
+            sqlx::query_as::<_, (String, String)>("SELECT topic, kind FROM message_topics WHERE message_id = $1 ORDER BY id")
+                .bind(message_id)
+                .fetch_all(&pool)
+                .await
+                .unwrap()
+        }
+    };
+
+    // The model's answer carries what routing extracted
+    let turn = agent.process_turn("Is staking on Solana worth it?").await.unwrap();
+    assert_eq!(turn.intent, Intent::General);
+    assert_eq!(turn.topics, vec![("solana".to_string(), TopicKind::Coin), ("staking".to_string(), TopicKind::Keyword)]);
+    let question = db::get_last_user_message_id(&pool, agent.user_id()).await.unwrap().unwrap();
+    assert_eq!(topics(question).await, vec![
+        ("solana".to_string(), "coin".to_string()),
+        ("staking".to_string(), "keyword".to_string()),
+    ]);
+
+    // Answers computed locally are tagged from the question
+    let turn = agent.process_turn("add solana to my watchlist").await.unwrap();
+    assert_eq!(turn.intent, Intent::Watchlist);
+    assert!(turn.topics.is_empty());
+    let question = db::get_last_user_message_id(&pool, agent.user_id()).await.unwrap().unwrap();
+    assert_eq!(topics(question).await, vec![("solana".to_string(), "coin".to_string())]);
+
+    let turn = agent.process_turn("what coins have we talked about most this month?").await.unwrap();
+    assert_eq!(turn.intent, Intent::Topics);
+    assert_eq!(turn.text, "The coins we talked about most this month:\n1. solana: 2 messages");
+    let question = db::get_last_user_message_id(&pool, agent.user_id()).await.unwrap().unwrap();
+    assert!(topics(question).await.is_empty());
+
+    let listed = handle_command(&agent, "/topics 7d").await.unwrap().unwrap();
+    assert_eq!(listed, "The topics we talked about most in the last 7 days:\n1. solana (coin): 2 messages\n2. staking (keyword): 1 message");
+    assert!(handle_command(&agent, "/topics fortnight").await.unwrap().unwrap().starts_with("Available commands"));
+    assert_eq!(server.received_requests().await.unwrap().len(), 1);
+}
+
+#[tokio::test]
+async fn test_trade_history_questions_read_the_recorded_trades() {
+    let Some(pool) = test_db().await else { return };
+    let agent = InvestmentChatAgent::with_pool(&pool, "alice").await.unwrap();
+
+    let turn = agent.process_turn("what trades have I made this month?").await.unwrap();
+    assert_eq!(turn.intent, Intent::TradeHistory);
+    assert_eq!(turn.text, "You haven't made any trades this month.");
+
+    let now = chrono::Utc::now().naive_utc();
+    let recent = NewTrade { tx_hash: Some("0xabc".to_string()), ..NewTrade::filled(now, OrderType::Buy, "ethereum", "USDC", 0.1, 2500.0, 0.0) };
+    db::create_trade(&pool, agent.user_id(), &recent).await.unwrap();
+    let old = NewTrade::filled(now - chrono::Duration::days(40), OrderType::Sell, "bitcoin", "USDC", 0.01, 60000.0, 0.0);
+    db::create_trade(&pool, agent.user_id(), &old).await.unwrap();
+
+    // Asked differently, the same question right away would get the first answer again
+    let turn = agent.process_turn("which trades have I made this month?").await.unwrap();
+    assert!(turn.text.starts_with("Your trades this month:"), "{}", turn.text);
+    assert!(turn.text.contains("ethereum") && !turn.text.contains("bitcoin"), "{}", turn.text);
+    assert!(turn.text.ends_with("1 trade this month. Bought $250.00 and sold $0.00 in USD terms."), "{}", turn.text);
+
+    let turn = agent.process_turn("show me my trades").await.unwrap();
+    assert!(turn.text.contains("bitcoin") && turn.text.contains("2 trades so far"), "{}", turn.text);
+}
+
+#[tokio::test]
+async fn test_debug_shows_what_turns_sent_to_the_model() {
+    let Some(pool) = test_db().await else { return };
+    let server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path("/v1/messages"))
+        .respond_with(json_fixture("anthropic/messages.json"))
+        .mount(&server)
+        .await;
+    let model = AnthropicClient::new("test-key").with_base_url(&server.uri());
+    let agent = InvestmentChatAgent::with_pool(&pool, "alice").await.unwrap().with_model(Arc::new(model));
+
+    // Nothing is recorded unless capture is on
+    agent.process_turn("add solana to my watchlist").await.unwrap();
+    let off = handle_command(&agent, "/debug last").await.unwrap().unwrap();
+    assert!(off.starts_with("Debug capture is off"), "{}", off);
+
+    let agent = agent.with_debug_capture(true);
+    agent.process_turn("Is staking on Solana worth it? My key is sk-ant-REDACTED").await.unwrap();
+    let shown = handle_command(&agent, "/debug last").await.unwrap().unwrap();
+    assert!(shown.contains("Question: Is staking on Solana worth it? My key is [REDACTED]"), "{}", shown);
+    assert!(shown.contains("Intent: general"), "{}", shown);
+    assert!(shown.contains(&format!("=== Call 1 of 1: {}", agent_friend::anthropic::ANTHROPIC_MODEL)), "{}", shown);
+    assert!(shown.contains("System prompt:\n"));
+    assert!(shown.contains("Reply:\n    AERO is the governance token of Aerodrome."));
+    assert!(!shown.contains("sk-ant-api03"));
+
+    let turn = db::get_turn_debug(&pool, agent.user_id(), None).await.unwrap().unwrap();
+    assert!(!turn.payload.contains("sk-ant-api03"));
+    assert_eq!(turn.message_id, db::get_last_user_message_id(&pool, agent.user_id()).await.unwrap());
+    let by_id = handle_command(&agent, &format!("/debug --turn {}", turn.id)).await.unwrap().unwrap();
+    assert_eq!(by_id, shown);
+    let missing = handle_command(&agent, &format!("/debug --turn {}", turn.id + 1)).await.unwrap().unwrap();
+    assert_eq!(missing, format!("No recorded turn {}. Only the latest 50 turns are kept.", turn.id + 1));
+    assert!(handle_command(&agent, "/debug --turn x").await.unwrap().is_err());
+}
+
+#[tokio::test]
+async fn test_working_through_a_strategy_checklist() {
+    let Some(pool) = test_db().await else { return };
+    let agent = InvestmentChatAgent::with_pool(&pool, "alice").await.unwrap();
+    let alice = db::get_user_by_username(&pool, "alice").await.unwrap().unwrap();
+    a_strategy_for(&alice)
+        .named("Weekly DCA")
+        .steps(["Pick a weekly budget", "Buy ETH every Monday"])
+        .create(&pool)
+        .await;
+
+    let turn = agent.process_turn("start executing my DCA strategy").await.unwrap();
+    assert_eq!(turn.intent, Intent::StrategyProgress);
+    assert_eq!(
+        turn.text,
+        "Started Weekly DCA. Say \"mark step 1 done\" as you go.\n\n\
+        Weekly DCA: 0 of 2 steps done\n[ ] 1. Pick a weekly budget\n[ ] 2. Buy ETH every Monday"
+    );
+
+    let turn = agent.process_turn("mark step 3 done").await.unwrap();
+    assert_eq!(turn.text, "There's no step 3, the strategy has 2 step(s).");
+    let turn = agent.process_turn("mark step 1 done: $100 a week").await.unwrap();
+    assert!(
+        turn.text.starts_with(
+            "Marked step 1 of Weekly DCA done.\n\nWeekly DCA: 1 of 2 steps done\n[x] 1. Pick a weekly budget (done "
+        ),
+        "{}",
+        turn.text
+    );
+    assert!(turn.text.contains(") - $100 a week\n[ ] 2. Buy ETH every Monday"));
+    let turn = agent.process_turn("I finished step 1").await.unwrap();
+    assert!(turn.text.starts_with("Step 1 is already done, since "), "{}", turn.text);
+
+    // The last step asks how it went, and the reply is kept with the strategy
+    let finished = handle_command(&agent, "/strategy done 2").await.unwrap().unwrap();
+    assert!(finished.contains("Weekly DCA: 2 of 2 steps done"));
+    assert!(finished.ends_with("That was the last step. How did it go? Reply with a short note and I'll keep it with the strategy for reviewing its performance later, or say \"skip\"."));
+    let turn = agent.process_turn("Averaged in at $2,450, would buy dips sooner").await.unwrap();
+    assert_eq!(turn.intent, Intent::StrategyProgress);
+    assert!(turn.text.starts_with("Saved your outcome note for Weekly DCA."));
+
+    let turn = agent.process_turn("where am I on my weekly dca strategy?").await.unwrap();
+    assert!(turn.text.contains("Past outcomes:\n- "), "{}", turn.text);
+    assert!(turn.text.ends_with(": Averaged in at $2,450, would buy dips sooner"));
+    assert_eq!(db::get_strategy_outcomes(&pool, alice.id).await.unwrap().len(), 1);
+
+    // A finished strategy starts over, unknown ones are named back
+    let restarted = handle_command(&agent, "/strategy start weekly dca").await.unwrap().unwrap();
+    assert!(restarted.contains("0 of 2 steps done"));
+    let unknown = handle_command(&agent, "/strategy start grid").await.unwrap().unwrap();
+    assert_eq!(unknown, "I couldn't find a saved strategy matching \"grid\". /strategies lists yours.");
+}
+
+#[tokio::test]
+async fn test_undo_reverses_the_last_change_only() {
+    let Some(pool) = test_db().await else { return };
+    let agent = InvestmentChatAgent::with_pool(&pool, "alice").await.unwrap();
+    let user_id = agent.user_id();
+
+    let turn = agent.process_turn("undo that").await.unwrap();
+    assert_eq!(turn.intent, Intent::Undo);
+    assert_eq!(turn.text, "There's nothing to undo, I haven't changed any of your data this session.");
+
+    // A new alias goes away again
+    agent.process_turn("when I say moon I mean solana").await.unwrap();
+    let turn = agent.process_turn("undo that").await.unwrap();
+    assert_eq!(turn.text, "Undone: the alias \"moon\" is removed.");
+    assert!(db::get_user_aliases(&pool, user_id).await.unwrap().is_empty());
+
+    // Only the last of two changes is undone, once
+    agent.process_turn("add solana to my watchlist").await.unwrap();
+    handle_command(&agent, "/portfolio set bitcoin 0.5").await.unwrap().unwrap();
+    let turn = agent.process_turn("revert that").await.unwrap();
+    assert_eq!(turn.text, "Undone: bitcoin is out of your portfolio.");
+    assert!(db::get_holdings_by_user_id(&pool, user_id).await.unwrap().is_empty());
+    let watchlist = db::get_watchlist(&pool, user_id).await.unwrap();
+    assert_eq!(watchlist.iter().map(|entry| entry.coin_id.as_str()).collect::<Vec<_>>(), ["solana"]);
+    let turn = agent.process_turn("undo it").await.unwrap();
+    assert!(turn.text.starts_with("There's nothing to undo"));
+}
+
+#[tokio::test]
+async fn test_staged_trades_wait_for_a_confirmation() {
+    let Some(pool) = test_db().await else { return };
+    let agent = InvestmentChatAgent::with_pool(&pool, "alice").await.unwrap();
+    let user_id = agent.user_id();
+
+    // Without a wallet nothing is quoted or staged
+    let turn = agent.process_turn("swap 0.1 eth to usdc with 1% slippage").await.unwrap();
+    assert_eq!(turn.intent, Intent::Trade);
+    assert_eq!(turn.text, trade_command::TRADING_DISABLED);
+    assert!(db::take_pending_trade(&pool, user_id).await.unwrap().is_none());
+    let turn = agent.process_turn("CONFIRM").await.unwrap();
+    assert_eq!(turn.text, "There's no trade waiting for confirmation.");
+
+    let staged = serde_json::to_value(StagedTrade::LimitOrder { order_type: OrderType::Buy, amount: 0.04, price: 2500.0, expires_at: None }).unwrap();
+    db::save_pending_trade(&pool, user_id, &staged, Utc::now().naive_utc()).await.unwrap();
+    let turn = agent.process_turn("cancel").await.unwrap();
+    assert_eq!(turn.text, "Dropped the limit buy of 0.04 WETH at $2500.00.");
+
+    // A quote confirmed too late isn't executed, and is gone afterwards
+    let staged_at = Utc::now().naive_utc() - chrono::Duration::minutes(trade_command::PENDING_TRADE_MINUTES + 1);
+    db::save_pending_trade(&pool, user_id, &staged, staged_at).await.unwrap();
+    let turn = agent.process_turn("confirm").await.unwrap();
+    assert!(turn.text.contains("its quote is stale"), "{}", turn.text);
+    assert!(db::take_pending_trade(&pool, user_id).await.unwrap().is_none());
+    assert!(db::get_limit_orders(&pool, user_id).await.unwrap().is_empty());
+}
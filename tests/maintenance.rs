@@ -0,0 +1,177 @@
+mod common;
+
+use agent_friend::db::{self, MessageRole};
+use agent_friend::maintenance::{self, Task};
+use agent_friend::retention::ExtractiveSummarizer;
+use common::db::{a_user, knowledge_tagged, test_db};
+use sqlx::{Pool, Postgres};
+use std::process::Command;
+
+async fn count(pool: &Pool<Postgres>, sql: &str) -> i64 {
+    sqlx::query_scalar::<_, i64>(sql).fetch_one(pool).await.unwrap()
+}
+
+async fn backdate(pool: &Pool<Postgres>, table: &str, id: i32, days: i32) {
+    let column = if table == "knowledge" { "created_at = now() - make_interval(days => $1), updated_at" } else { "created_at" };
+    sqlx::query(&format!("UPDATE {} SET {} = now() - make_interval(days => $1) WHERE id = $2", table, column))
+        .bind(days)
+        .bind(id)
+        .execute(pool)
+        .await
+        .unwrap();
+}
+
+async fn run(pool: &Pool<Postgres>, task: Task, dry_run: bool) -> maintenance::Report {
+    maintenance::run(pool, task, dry_run, &ExtractiveSummarizer).await.unwrap()
+}
+
+#[tokio::test]
+async fn test_prune_knowledge_removes_only_stale_entries() {
+    let Some(pool) = test_db().await else { return };
+    let user = a_user().create(&pool).await;
+    let stale = knowledge_tagged(&user, ["luna"]).source_id("luna-2021").create(&pool).await;
+    knowledge_tagged(&user, ["eth"]).source_id("eth-2026").create(&pool).await;
+    backdate(&pool, "knowledge", stale.id, 120).await;
+    let before = count(&pool, "SELECT count(*) FROM knowledge").await;
+
+    let task = Task::PruneKnowledge { older_than_days: 90 };
+    let report = run(&pool, task, true).await;
+    assert_eq!(report.changed, 1);
+    assert!(report.lines[1].contains("luna-2021"));
+    assert_eq!(count(&pool, "SELECT count(*) FROM knowledge").await, before);
+
+    let report = run(&pool, task, false).await;
+    assert_eq!(report.changed, 1);
+    assert_eq!(count(&pool, "SELECT count(*) FROM knowledge").await, before - 1);
+    assert!(db::get_knowledge_by_source_id(&pool, user.id, "luna-2021").await.unwrap().is_none());
+    assert!(db::get_knowledge_by_source_id(&pool, user.id, "eth-2026").await.unwrap().is_some());
+}
+
+#[tokio::test]
+async fn test_archive_messages_moves_old_messages_with_a_summary() {
+    let Some(pool) = test_db().await else { return };
+    let user = a_user().create(&pool).await;
+    db::save_message(&pool, user.id, MessageRole::User, "what was LUNA's supply?").await.unwrap();
+    db::save_message(&pool, user.id, MessageRole::User, "and ETH today?").await.unwrap();
+    let old: i32 = sqlx::query_scalar("SELECT id FROM messages WHERE content LIKE 'what was LUNA%'").fetch_one(&pool).await.unwrap();
+    backdate(&pool, "messages", old, 200).await;
+
+    let task = Task::ArchiveMessages { older_than_days: 90 };
+    let report = run(&pool, task, true).await;
+    assert_eq!(report.changed, 1);
+    assert_eq!(count(&pool, "SELECT count(*) FROM messages_archive").await, 0);
+    assert_eq!(count(&pool, "SELECT count(*) FROM conversation_summaries").await, 0);
+
+    let report = run(&pool, task, false).await;
+    assert_eq!(report.changed, 1);
+    assert_eq!(count(&pool, "SELECT count(*) FROM messages_archive").await, 1);
+    assert_eq!(count(&pool, "SELECT count(*) FROM conversation_summaries").await, 1);
+    let live = db::get_messages(&pool, user.id, 10).await.unwrap();
+    assert_eq!(live.len(), 1);
+    assert_eq!(live[0].content, "and ETH today?");
+}
+
+#[tokio::test]
+async fn test_dedupe_knowledge_keeps_one_entry_with_every_tag() {
+    let Some(pool) = test_db().await else { return };
+    let user = a_user().create(&pool).await;
+    let other = a_user().create(&pool).await;
+    let content = "Solana has 400ms slots";
+    knowledge_tagged(&user, ["sol"]).source_id("sol-a").content(content).create(&pool).await;
+    knowledge_tagged(&user, ["solana", "staking"]).source_id("sol-b").content(&format!(" {}\n", content)).create(&pool).await;
+    knowledge_tagged(&other, ["sol"]).source_id("sol-a").content(content).create(&pool).await;
+    let before = count(&pool, "SELECT count(*) FROM knowledge").await;
+
+    let report = run(&pool, Task::DedupeKnowledge, true).await;
+    assert_eq!(report.changed, 1);
+    assert_eq!(count(&pool, "SELECT count(*) FROM knowledge").await, before);
+
+    let report = run(&pool, Task::DedupeKnowledge, false).await;
+    assert_eq!(report.changed, 1);
+    assert_eq!(count(&pool, "SELECT count(*) FROM knowledge").await, before - 1);
+    let kept = db::get_knowledge_by_tag(&pool, user.id, "sol").await.unwrap();
+    assert_eq!(kept.len(), 1);
+    for tag in ["sol", "solana", "staking"] {
+        assert!(kept[0].tags.contains(&tag.to_string()), "missing {}", tag);
+    }
+    // The other user's copy isn't a duplicate of anything
+    assert_eq!(db::get_knowledge_by_tag(&pool, other.id, "sol").await.unwrap().len(), 1);
+
+    assert_eq!(run(&pool, Task::DedupeKnowledge, false).await.changed, 0);
+}
+
+#[tokio::test]
+async fn test_reindex_embeddings_changes_nothing() {
+    let Some(pool) = test_db().await else { return };
+    let user = a_user().create(&pool).await;
+    knowledge_tagged(&user, ["aave"]).create(&pool).await;
+    let stamp = "SELECT count(*) FROM knowledge WHERE updated_at = created_at";
+    let before = (count(&pool, "SELECT count(*) FROM knowledge").await, count(&pool, stamp).await);
+
+    for dry_run in [true, false] {
+        let report = run(&pool, Task::ReindexEmbeddings, dry_run).await;
+        assert_eq!(report.changed, 0);
+        assert!(report.lines[0].contains("no embeddings"));
+    }
+    assert_eq!((count(&pool, "SELECT count(*) FROM knowledge").await, count(&pool, stamp).await), before);
+}
+
+#[tokio::test]
+async fn test_stats_only_reads() {
+    let Some(pool) = test_db().await else { return };
+    let user = a_user().create(&pool).await;
+    let content = "Aave v3 is on Base";
+    knowledge_tagged(&user, ["aave"]).source_id("a").content(content).create(&pool).await;
+    knowledge_tagged(&user, ["aave"]).source_id("b").content(content).create(&pool).await;
+    let before = count(&pool, "SELECT count(*) FROM knowledge").await;
+
+    let dry = run(&pool, Task::Stats, true).await;
+    let real = run(&pool, Task::Stats, false).await;
+    assert_eq!((dry.changed, real.changed), (0, 0));
+    assert_eq!(dry.lines, real.lines);
+    assert!(real.lines.iter().any(|line| line.starts_with(&format!("knowledge: {} rows", before))));
+    assert!(real.lines.contains(&"Duplicate knowledge entries: 1".to_string()));
+    assert_eq!(count(&pool, "SELECT count(*) FROM knowledge").await, before);
+}
+
+fn maintenance_binary(database_url: &str) -> Command {
+    let mut command = Command::new(env!("CARGO_BIN_EXE_maintenance"));
+    command.env("DATABASE_URL", database_url);
+    command
+}
+
+#[test]
+fn test_exit_codes_without_a_database() {
+    let unreachable = "postgres://nobody@127.0.0.1:1/none";
+    let output = maintenance_binary(unreachable).arg("stats").output().unwrap();
+    assert_eq!(output.status.code(), Some(3));
+
+    let output = maintenance_binary(unreachable).args(["prune-knowledge", "--older-than", "soon"]).output().unwrap();
+    assert_eq!(output.status.code(), Some(2));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("Invalid age"));
+
+    let output = maintenance_binary(unreachable).arg("vacuum-everything").output().unwrap();
+    assert_eq!(output.status.code(), Some(2));
+}
+
+#[tokio::test]
+async fn test_binary_dry_run_then_run() {
+    let Some(pool) = test_db().await else { return };
+    let user = a_user().create(&pool).await;
+    let stale = knowledge_tagged(&user, ["luna"]).source_id("luna-2021").create(&pool).await;
+    backdate(&pool, "knowledge", stale.id, 400).await;
+
+    // The binary connects on its own, so point it at the test schema
+    let schema: String = sqlx::query_scalar("SELECT current_schema()").fetch_one(&pool).await.unwrap();
+    let database_url = format!("{}?options=-c%20search_path%3D{}", std::env::var("TEST_DATABASE_URL").unwrap(), schema);
+
+    let output = maintenance_binary(&database_url).args(["--dry-run", "prune-knowledge", "--older-than", "52w"]).output().unwrap();
+    assert_eq!(output.status.code(), Some(0), "{}", String::from_utf8_lossy(&output.stderr));
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.starts_with("[dry run] Would delete 1 knowledge entries not updated in 364 days."), "{}", stdout);
+    assert!(db::get_knowledge_by_source_id(&pool, user.id, "luna-2021").await.unwrap().is_some());
+
+    let output = maintenance_binary(&database_url).args(["prune-knowledge", "--older-than", "52w"]).output().unwrap();
+    assert_eq!(output.status.code(), Some(0));
+    assert!(db::get_knowledge_by_source_id(&pool, user.id, "luna-2021").await.unwrap().is_none());
+}
@@ -0,0 +1,598 @@
+mod common;
+
+use agent_friend::nonce_manager::NonceManager;
+use agent_friend::trading::{
+    ApprovalMode, BroadcastError, GasPolicy, OneInchClient, TradingError, broadcast_swap, ensure_allowance, estimate_gas,
+    native_balance, send_swap, supported_chain, token_balance, verify_node_chain,
+};
+use common::{json_fixture, malformed_json, rate_limited};
+use ethers::middleware::SignerMiddleware;
+use ethers::providers::{Http, Provider};
+use ethers::signers::{LocalWallet, Signer};
+use ethers::types::transaction::eip2718::TypedTransaction;
+use ethers::types::{H256, U256};
+use ethers::utils::{hex, rlp};
+use serde_json::json;
+use std::sync::Arc;
+use std::time::Duration;
+use wiremock::matchers::{body_partial_json, header, method, path, query_param};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+
+const USDC: &str = "0x036cbd53842c5426634e7929541ec2318f3dcf7e";
+const WETH: &str = "0x4200000000000000000000000000000000000006";
+const WALLET: &str = "0x1111111111111111111111111111111111111111";
+const ROUTER: &str = "0x1111111254eeb25477b68fb85ed929f73a960582";
+
+fn client(server: &MockServer) -> OneInchClient {
+    OneInchClient::new(84532, Some("1inch-test".to_string())).with_base_url(&server.uri())
+}
+
+#[tokio::test]
+async fn test_get_tokens() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/84532/tokens"))
+        .and(header("Authorization", "Bearer 1inch-test"))
+        .respond_with(json_fixture("oneinch/tokens.json"))
+        .mount(&server)
+        .await;
+
+    let tokens = client(&server).get_tokens().await.unwrap();
+    assert_eq!(tokens.tokens[USDC].symbol, "USDC");
+    assert_eq!(tokens.tokens[USDC].decimals, 6);
+}
+
+#[tokio::test]
+async fn test_token_list_is_fetched_once() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/84532/tokens"))
+        .respond_with(json_fixture("oneinch/tokens.json"))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let client = client(&server);
+    let usdc = client.get_token("0x036CbD53842c5426634e7929541eC2318f3dCF7e").await.unwrap();
+    assert_eq!((usdc.symbol.as_str(), usdc.decimals), ("USDC", 6));
+    assert_eq!(client.get_token(USDC).await.unwrap().symbol, "USDC");
+    assert!(matches!(client.get_token(WETH).await, Err(TradingError::TokenNotFound(address)) if address == WETH));
+}
+
+#[tokio::test]
+async fn test_tokens_are_found_by_symbol() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/84532/tokens"))
+        .respond_with(json_fixture("oneinch/tokens.json"))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let client = client(&server);
+    assert_eq!(client.find_by_symbol("usdc").await.unwrap().address, USDC);
+    assert!(matches!(client.find_by_symbol("WETH").await, Err(TradingError::TokenNotFound(symbol)) if symbol == "WETH"));
+}
+
+#[tokio::test]
+async fn test_token_list_is_refetched_when_expired_or_refreshed() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/84532/tokens"))
+        .respond_with(json_fixture("oneinch/tokens.json"))
+        .expect(3)
+        .mount(&server)
+        .await;
+
+    // A list kept for no time at all is fetched on every lookup
+    let expiring = client(&server).with_token_ttl(Duration::ZERO);
+    expiring.get_token(USDC).await.unwrap();
+    expiring.get_token(USDC).await.unwrap();
+
+    // A kept one only when forced
+    let kept = client(&server);
+    kept.refresh_tokens().await.unwrap();
+    kept.get_token(USDC).await.unwrap();
+}
+
+#[tokio::test]
+async fn test_concurrent_balance_lookups_fetch_the_token_list_once() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/84532/tokens"))
+        .respond_with(json_fixture("oneinch/tokens.json").set_delay(Duration::from_millis(200)))
+        .expect(1)
+        .mount(&server)
+        .await;
+    let node = MockServer::start().await;
+    mount_rpc(&node, "eth_call", rpc_result(json!(format!("0x{:064x}", 1_500_000u64)))).await;
+
+    // Each lookup is what a balance call does: the token's decimals from 1inch, then its balance from the node
+    let client = Arc::new(client(&server));
+    let provider = Arc::new(Provider::<Http>::try_from(node.uri()).unwrap());
+    let lookups: Vec<_> = (0..8)
+        .map(|_| {
+            let (client, provider) = (client.clone(), provider.clone());
+            tokio::spawn(async move {
+                let token = client.get_token(USDC).await?;
+                token_balance(provider, USDC.parse().unwrap(), WALLET.parse().unwrap(), token.decimals).await
+            })
+        })
+        .collect();
+    for lookup in lookups {
+        assert_eq!(lookup.await.unwrap().unwrap(), 1.5);
+    }
+}
+
+#[tokio::test]
+async fn test_get_quote() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/84532/quote"))
+        .and(query_param("src", USDC))
+        .and(query_param("dst", WETH))
+        .and(query_param("amount", "100000000"))
+        .respond_with(json_fixture("oneinch/quote.json"))
+        .mount(&server)
+        .await;
+
+    let quote = client(&server).get_quote(USDC, WETH, "100000000", WALLET).await.unwrap();
+    assert_eq!(quote.to_amount, "32051282051282051");
+    assert_eq!(quote.estimated_gas, 185000);
+    assert_eq!(quote.protocols[0][0][0].name, "UNISWAP_V3");
+    assert_eq!(quote.aggregator, "1inch");
+}
+
+#[tokio::test]
+async fn test_get_swap() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/84532/swap"))
+        .and(query_param("slippage", "1"))
+        .and(query_param("disableEstimate", "true"))
+        .respond_with(json_fixture("oneinch/swap.json"))
+        .mount(&server)
+        .await;
+
+    let swap = client(&server)
+        .get_swap(USDC, WETH, "100000000", WALLET, 1.0, true)
+        .await
+        .unwrap();
+    assert_eq!(swap.tx.data, "0x12aa3caf");
+    assert_eq!(swap.tx.gas, 210000);
+}
+
+#[tokio::test]
+async fn test_swap_above_max_slippage_is_refused_before_asking_1inch() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/84532/swap"))
+        .respond_with(json_fixture("oneinch/swap.json"))
+        .expect(0)
+        .mount(&server)
+        .await;
+
+    let result = client(&server).get_swap(USDC, WETH, "100000000", WALLET, 60.0, true).await;
+    assert!(matches!(result, Err(TradingError::SlippageTooHigh { slippage, .. }) if slippage == 60.0));
+    // Nor is one too low to ever go through
+    let result = client(&server).get_swap(USDC, WETH, "100000000", WALLET, 0.01, true).await;
+    assert!(matches!(result, Err(TradingError::SlippageTooLow { min, .. }) if min == 0.05));
+}
+
+#[tokio::test]
+async fn test_get_spender() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/84532/approve/spender"))
+        .respond_with(json_fixture("oneinch/approve_spender.json"))
+        .mount(&server)
+        .await;
+
+    let spender = client(&server).get_spender().await.unwrap();
+    assert_eq!(spender, ROUTER);
+}
+
+#[tokio::test]
+async fn test_unauthorized() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .respond_with(ResponseTemplate::new(401).set_body_json(serde_json::json!({
+            "statusCode": 401,
+            "description": "Invalid API key"
+        })))
+        .mount(&server)
+        .await;
+
+    let error = client(&server).get_tokens().await.unwrap_err();
+    assert!(matches!(
+        error,
+        TradingError::Api { status, message } if status.as_u16() == 401 && message == "Invalid API key"
+    ));
+}
+
+#[tokio::test]
+async fn test_rate_limit_carries_retry_after() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .respond_with(rate_limited(3))
+        .mount(&server)
+        .await;
+
+    let error = client(&server).get_tokens().await.unwrap_err();
+    assert!(matches!(error, TradingError::RateLimited(Some(wait)) if wait == Duration::from_secs(3)));
+}
+
+#[tokio::test]
+async fn test_server_error() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .respond_with(ResponseTemplate::new(500).set_body_string("internal"))
+        .mount(&server)
+        .await;
+
+    let error = client(&server).get_quote(USDC, WETH, "1", WALLET).await.unwrap_err();
+    assert!(matches!(error, TradingError::Api { status, .. } if status.as_u16() == 500));
+}
+
+#[tokio::test]
+async fn test_malformed_json() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .respond_with(malformed_json())
+        .mount(&server)
+        .await;
+
+    let error = client(&server).get_tokens().await.unwrap_err();
+    assert!(matches!(error, TradingError::InvalidResponse(_)));
+}
+
+#[tokio::test]
+async fn test_timeout() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .respond_with(json_fixture("oneinch/tokens.json").set_delay(Duration::from_millis(500)))
+        .mount(&server)
+        .await;
+
+    let error = client(&server)
+        .with_timeout(Duration::from_millis(50))
+        .get_tokens()
+        .await
+        .unwrap_err();
+    assert!(matches!(error, TradingError::Http(e) if e.is_timeout()));
+}
+
+const TX_HASH: &str = "0x5e8fd3b0a1f1c1e2b6c2d6a6f9d0c8b7a6e5f4d3c2b1a09f8e7d6c5b4a392817";
+
+/// A JSON-RPC success answering `rpc_method`
+async fn mount_rpc(server: &MockServer, rpc_method: &str, response: ResponseTemplate) {
+    Mock::given(method("POST"))
+        .and(body_partial_json(json!({ "method": rpc_method })))
+        .respond_with(response)
+        .mount(server)
+        .await;
+}
+
+fn rpc_result(result: serde_json::Value) -> ResponseTemplate {
+    ResponseTemplate::new(200).set_body_json(json!({ "jsonrpc": "2.0", "id": 1, "result": result }))
+}
+
+/// A node that takes the raw transaction and has mined it by the time it is polled
+async fn rpc_node(receipt: &str) -> MockServer {
+    let node = MockServer::start().await;
+    mount_rpc(&node, "eth_getTransactionCount", rpc_result(json!("0x7"))).await;
+    mount_rpc(&node, "eth_sendRawTransaction", rpc_result(json!(TX_HASH))).await;
+    mount_rpc(&node, "eth_getTransactionByHash", json_fixture("rpc/transaction.json")).await;
+    mount_rpc(&node, "eth_getTransactionReceipt", json_fixture(receipt)).await;
+    mount_rpc(&node, "eth_blockNumber", rpc_result(json!("0x1236"))).await;
+    node
+}
+
+fn signer(node: &MockServer) -> SignerMiddleware<Provider<Http>, LocalWallet> {
+    let provider = Provider::<Http>::try_from(node.uri()).unwrap().interval(Duration::from_millis(10));
+    let wallet: LocalWallet = "0x4c0883a69102937d6231471b5dbb6204fe5129617082792ae468d01a3f362318".parse().unwrap();
+    SignerMiddleware::new(provider, wallet.with_chain_id(84532u64))
+}
+
+fn nonces(node: &MockServer) -> NonceManager {
+    NonceManager::new(signer(node).address())
+}
+
+async fn prepared_swap() -> agent_friend::trading::SwapResponse {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/84532/swap"))
+        .respond_with(json_fixture("oneinch/swap.json"))
+        .mount(&server)
+        .await;
+    client(&server).get_swap(USDC, WETH, "100000000", WALLET, 1.0, false).await.unwrap()
+}
+
+#[tokio::test]
+async fn test_swap_is_signed_broadcast_and_confirmed() {
+    let swap = prepared_swap().await;
+    let node = rpc_node("rpc/receipt.json").await;
+
+    let receipt = broadcast_swap(&signer(&node), &nonces(&node), &swap.tx, 2).await.unwrap();
+    assert_eq!(receipt.tx_hash, TX_HASH.parse::<H256>().unwrap());
+    assert_eq!(receipt.block_number, Some(0x1234));
+    assert_eq!(receipt.gas_used, Some(U256::from(0x2a3f1)));
+
+    // One signed legacy transaction went out, built from the 1inch calldata
+    let requests = node.received_requests().await.unwrap();
+    let sent: Vec<serde_json::Value> = requests
+        .iter()
+        .map(|request| request.body_json::<serde_json::Value>().unwrap())
+        .filter(|body| body["method"] == "eth_sendRawTransaction")
+        .collect();
+    assert_eq!(sent.len(), 1);
+    assert!(sent[0]["params"][0].as_str().unwrap().contains("12aa3caf"));
+}
+
+#[tokio::test]
+async fn test_sent_swap_is_not_waited_for() {
+    let swap = prepared_swap().await;
+    let node = rpc_node("rpc/receipt.json").await;
+
+    assert_eq!(send_swap(&signer(&node), &nonces(&node), &swap.tx).await.unwrap(), TX_HASH.parse::<H256>().unwrap());
+    assert_eq!(sent_transactions(&node).await.len(), 1);
+    let polled = node
+        .received_requests()
+        .await
+        .unwrap()
+        .iter()
+        .any(|request| request.body_json::<serde_json::Value>().unwrap()["method"] == "eth_getTransactionReceipt");
+    assert!(!polled);
+}
+
+#[tokio::test]
+async fn test_reverted_swap_is_a_broadcast_error() {
+    let swap = prepared_swap().await;
+    let node = rpc_node("rpc/receipt_reverted.json").await;
+
+    let error = broadcast_swap(&signer(&node), &nonces(&node), &swap.tx, 1).await.unwrap_err();
+    assert!(matches!(error, TradingError::Broadcast(BroadcastError::Reverted(hash)) if hash == TX_HASH.parse::<H256>().unwrap()));
+    assert!(error.to_string().starts_with("Swap transaction failed: 0x5e8f"));
+}
+
+#[tokio::test]
+async fn test_rejected_swap_is_a_broadcast_error() {
+    let swap = prepared_swap().await;
+    let node = MockServer::start().await;
+    mount_rpc(&node, "eth_getTransactionCount", rpc_result(json!("0x7"))).await;
+    mount_rpc(
+        &node,
+        "eth_sendRawTransaction",
+        ResponseTemplate::new(200).set_body_json(json!({
+            "jsonrpc": "2.0",
+            "id": 1,
+            "error": { "code": -32000, "message": "insufficient funds for gas * price + value" }
+        })),
+    )
+    .await;
+
+    let error = broadcast_swap(&signer(&node), &nonces(&node), &swap.tx, 1).await.unwrap_err();
+    assert!(matches!(error, TradingError::Broadcast(BroadcastError::Send(ref reason)) if reason.contains("insufficient funds")));
+}
+
+/// Nonces of the raw transactions the node was asked to broadcast, in the order it got them
+async fn sent_nonces(node: &MockServer) -> Vec<u64> {
+    sent_transactions(node)
+        .await
+        .iter()
+        .map(|raw| {
+            let raw = hex::decode(raw.trim_start_matches("0x")).unwrap();
+            let (tx, _) = TypedTransaction::decode_signed(&rlp::Rlp::new(&raw)).unwrap();
+            tx.nonce().unwrap().as_u64()
+        })
+        .collect()
+}
+
+async fn nonce_reads(node: &MockServer) -> usize {
+    node.received_requests()
+        .await
+        .unwrap()
+        .iter()
+        .filter(|request| request.body_json::<serde_json::Value>().unwrap()["method"] == "eth_getTransactionCount")
+        .count()
+}
+
+#[tokio::test]
+async fn test_concurrent_swaps_use_consecutive_nonces() {
+    let swap = prepared_swap().await;
+    let node = rpc_node("rpc/receipt.json").await;
+    let (signer, nonces) = (signer(&node), nonces(&node));
+
+    let (first, second) = tokio::join!(send_swap(&signer, &nonces, &swap.tx), send_swap(&signer, &nonces, &swap.tx));
+    first.unwrap();
+    second.unwrap();
+    let mut sent = sent_nonces(&node).await;
+    sent.sort();
+    assert_eq!(sent, [7, 8]);
+    // The pending count is read once, the second swap takes the nonce after the first's
+    assert_eq!(nonce_reads(&node).await, 1);
+}
+
+#[tokio::test]
+async fn test_nonce_too_low_resyncs_and_sends_again() {
+    let swap = prepared_swap().await;
+    let node = MockServer::start().await;
+    // Two transactions went out from the wallet elsewhere after the first read
+    for count in ["0x7", "0x9"] {
+        Mock::given(method("POST"))
+            .and(body_partial_json(json!({ "method": "eth_getTransactionCount" })))
+            .respond_with(rpc_result(json!(count)))
+            .up_to_n_times(1)
+            .mount(&node)
+            .await;
+    }
+    Mock::given(method("POST"))
+        .and(body_partial_json(json!({ "method": "eth_sendRawTransaction" })))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "jsonrpc": "2.0",
+            "id": 1,
+            "error": { "code": -32000, "message": "nonce too low" }
+        })))
+        .up_to_n_times(1)
+        .mount(&node)
+        .await;
+    mount_rpc(&node, "eth_sendRawTransaction", rpc_result(json!(TX_HASH))).await;
+    let (signer, nonces) = (signer(&node), nonces(&node));
+
+    send_swap(&signer, &nonces, &swap.tx).await.unwrap();
+    send_swap(&signer, &nonces, &swap.tx).await.unwrap();
+    assert_eq!(sent_nonces(&node).await, [7, 9, 10]);
+    assert_eq!(nonce_reads(&node).await, 2);
+}
+
+#[tokio::test]
+async fn test_nonce_is_read_again_after_a_rejected_send() {
+    let swap = prepared_swap().await;
+    let node = MockServer::start().await;
+    mount_rpc(&node, "eth_getTransactionCount", rpc_result(json!("0x7"))).await;
+    Mock::given(method("POST"))
+        .and(body_partial_json(json!({ "method": "eth_sendRawTransaction" })))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "jsonrpc": "2.0",
+            "id": 1,
+            "error": { "code": -32000, "message": "insufficient funds for gas * price + value" }
+        })))
+        .up_to_n_times(1)
+        .mount(&node)
+        .await;
+    mount_rpc(&node, "eth_sendRawTransaction", rpc_result(json!(TX_HASH))).await;
+    let (signer, nonces) = (signer(&node), nonces(&node));
+
+    // Not retried, and the nonce it would have used is not skipped
+    assert!(send_swap(&signer, &nonces, &swap.tx).await.is_err());
+    send_swap(&signer, &nonces, &swap.tx).await.unwrap();
+    assert_eq!(sent_nonces(&node).await, [7, 7]);
+    assert_eq!(nonce_reads(&node).await, 2);
+}
+
+/// A node whose `allowance` call answers `allowance` and that mines any approval sent to it
+async fn token_node(allowance: u64, receipt: &str) -> MockServer {
+    let node = rpc_node(receipt).await;
+    mount_rpc(&node, "eth_call", rpc_result(json!(format!("0x{:064x}", allowance)))).await;
+    mount_rpc(&node, "eth_gasPrice", rpc_result(json!("0x59682f00"))).await;
+    mount_rpc(&node, "eth_estimateGas", rpc_result(json!("0xb5a0"))).await;
+    node
+}
+
+async fn approve(node: &MockServer, mode: ApprovalMode) -> Result<Option<H256>, TradingError> {
+    let amount = U256::from(100_000_000u64);
+    ensure_allowance(Arc::new(signer(node)), &nonces(node), USDC.parse().unwrap(), ROUTER.parse().unwrap(), amount, mode, 1).await
+}
+
+/// Raw transactions the node was asked to broadcast
+async fn sent_transactions(node: &MockServer) -> Vec<String> {
+    node.received_requests()
+        .await
+        .unwrap()
+        .iter()
+        .map(|request| request.body_json::<serde_json::Value>().unwrap())
+        .filter(|body| body["method"] == "eth_sendRawTransaction")
+        .map(|body| body["params"][0].as_str().unwrap().to_string())
+        .collect()
+}
+
+#[tokio::test]
+async fn test_sufficient_allowance_sends_no_approval() {
+    let node = token_node(200_000_000, "rpc/receipt.json").await;
+
+    assert_eq!(approve(&node, ApprovalMode::Exact).await.unwrap(), None);
+    assert!(sent_transactions(&node).await.is_empty());
+}
+
+#[tokio::test]
+async fn test_short_allowance_is_approved_for_the_swap_amount() {
+    let node = token_node(0, "rpc/receipt.json").await;
+
+    let approval = approve(&node, ApprovalMode::Exact).await.unwrap();
+    assert_eq!(approval, Some(TX_HASH.parse::<H256>().unwrap()));
+
+    // approve(router, 100 USDC) to the token contract
+    let sent = sent_transactions(&node).await;
+    assert_eq!(sent.len(), 1);
+    let calldata = format!("095ea7b3{:0>64}{:064x}", &ROUTER[2..], 100_000_000u64);
+    assert!(sent[0].contains(&calldata), "{}", sent[0]);
+    assert!(sent[0].contains(&USDC[2..]));
+}
+
+#[tokio::test]
+async fn test_infinite_approval_approves_the_maximum() {
+    let node = token_node(0, "rpc/receipt.json").await;
+
+    approve(&node, ApprovalMode::Infinite).await.unwrap();
+    let sent = sent_transactions(&node).await;
+    assert!(sent[0].contains(&format!("095ea7b3{:0>64}{}", &ROUTER[2..], "f".repeat(64))), "{}", sent[0]);
+}
+
+#[tokio::test]
+async fn test_reverted_approval_is_an_approval_error() {
+    let node = token_node(0, "rpc/receipt_reverted.json").await;
+
+    let error = approve(&node, ApprovalMode::Exact).await.unwrap_err();
+    assert!(matches!(error, TradingError::Approval(BroadcastError::Reverted(hash)) if hash == TX_HASH.parse::<H256>().unwrap()));
+    assert!(error.to_string().starts_with("Token approval failed: 0x5e8f"));
+}
+
+#[tokio::test]
+async fn test_token_and_native_balances_are_in_whole_tokens() {
+    let node = MockServer::start().await;
+    mount_rpc(&node, "eth_call", rpc_result(json!(format!("0x{:064x}", 1_500_000u64)))).await;
+    mount_rpc(&node, "eth_getBalance", rpc_result(json!("0x1bc16d674ec80000"))).await;
+    let provider = Provider::<Http>::try_from(node.uri()).unwrap();
+    let owner = WALLET.parse().unwrap();
+
+    let usdc = token_balance(Arc::new(provider.clone()), USDC.parse().unwrap(), owner, 6).await.unwrap();
+    assert_eq!(usdc, 1.5);
+    assert_eq!(native_balance(&provider, owner).await.unwrap(), 2.0);
+}
+
+#[tokio::test]
+async fn test_quoted_gas_is_priced_at_the_next_base_fee() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/84532/quote"))
+        .respond_with(json_fixture("oneinch/quote.json"))
+        .mount(&server)
+        .await;
+    let quote = client(&server).get_quote(USDC, WETH, "100000000", WALLET).await.unwrap();
+
+    // The last base fee is the next block's: 40 gwei
+    let node = MockServer::start().await;
+    mount_rpc(
+        &node,
+        "eth_feeHistory",
+        rpc_result(json!({
+            "oldestBlock": "0x1234",
+            "baseFeePerGas": ["0x4a817c800", "0x9502f9000"],
+            "gasUsedRatio": [0.5],
+            "reward": []
+        })),
+    )
+    .await;
+    let provider = Provider::<Http>::try_from(node.uri()).unwrap();
+
+    let estimate = estimate_gas(&provider, quote.estimated_gas).await.unwrap();
+    assert_eq!((estimate.gas_limit, estimate.gas_price_gwei), (185_000, 40.0));
+    // 185,000 * 40 gwei = 0.0074 ETH, $22.20 at $3000
+    assert!((estimate.cost_eth - 0.0074).abs() < 1e-12);
+
+    let policy = GasPolicy { max_gas_gwei: None, max_cost_usd: Some(10.0) };
+    let error = policy.check(&estimate, async { Ok(3000.0) }).await.unwrap_err();
+    assert_eq!(error.to_string(), "Gas is currently $22.20, above your $10.00 limit");
+}
+
+#[tokio::test]
+async fn test_node_of_another_chain_is_a_configuration_error() {
+    let node = MockServer::start().await;
+    mount_rpc(&node, "eth_chainId", rpc_result(json!("0x14a34"))).await;
+    let provider = Provider::<Http>::try_from(node.uri()).unwrap();
+
+    // 0x14a34 is Base Sepolia
+    verify_node_chain(&provider, supported_chain(84532).unwrap()).await.unwrap();
+    let error = verify_node_chain(&provider, supported_chain(8453).unwrap()).await.unwrap_err();
+    assert!(matches!(error, TradingError::Configuration(ref message) if message == "RPC_URL serves chain 84532 but CHAIN_ID is 8453 (Base)"));
+}
@@ -0,0 +1,150 @@
+mod common;
+
+use agent_friend::db::MessageRole;
+use agent_friend::llm::{CallOptions, ChatModel, LlmError, Message, TokenUsage};
+use agent_friend::openai::OpenAiClient;
+use common::{event_stream_fixture, json_fixture, rate_limited};
+use std::time::Duration;
+use wiremock::matchers::{body_partial_json, header, method, path};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+
+fn client(server: &MockServer) -> OpenAiClient {
+    OpenAiClient::new(&format!("{}/v1", server.uri()), Some("sk-test")).with_model("gpt-4o-mini")
+}
+
+fn messages() -> Vec<Message> {
+    vec![Message {
+        role: MessageRole::User,
+        content: "What is AERO?".to_string(),
+    }]
+}
+
+#[tokio::test]
+async fn test_generate() {
+    let server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path("/v1/chat/completions"))
+        .and(header("authorization", "Bearer sk-test"))
+        .and(body_partial_json(serde_json::json!({
+            "model": "gpt-4o-mini",
+            "max_tokens": 256,
+            "messages": [
+                { "role": "system", "content": "Be brief." },
+                { "role": "user", "content": "What is AERO?" }
+            ]
+        })))
+        .respond_with(json_fixture("openai/chat_completion.json"))
+        .mount(&server)
+        .await;
+
+    let completion = client(&server).generate("Be brief.", &messages(), &CallOptions::new(256)).await.unwrap();
+    assert_eq!(completion.text, "AERO is the governance token of Aerodrome.");
+    assert_eq!(completion.usage, TokenUsage { input_tokens: 14, output_tokens: 9 });
+    assert_eq!(completion.request_id.as_deref(), Some("chatcmpl-01"));
+}
+
+#[tokio::test]
+async fn test_local_server_without_api_key() {
+    let server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path("/v1/chat/completions"))
+        .and(body_partial_json(serde_json::json!({ "model": "llama3.1" })))
+        .respond_with(json_fixture("openai/chat_completion.json"))
+        .mount(&server)
+        .await;
+
+    let client = OpenAiClient::new(&format!("{}/v1/", server.uri()), None).with_model("llama3.1");
+    assert!(!client.supports_tools());
+    client.generate("", &messages(), &CallOptions::new(256)).await.unwrap();
+
+    let requests = server.received_requests().await.unwrap();
+    assert!(!requests[0].headers.contains_key("authorization"));
+}
+
+#[tokio::test]
+async fn test_generate_json_cuts_prose_after_sentinel() {
+    let server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(body_partial_json(serde_json::json!({ "stop": ["###END###"] })))
+        .respond_with(json_fixture("openai/structured_unstopped.json"))
+        .mount(&server)
+        .await;
+
+    let json = client(&server).generate_json("Classify the articles.", &messages(), 512).await.unwrap();
+    assert_eq!(json, r#"[{"index": 1, "sentiment": "positive", "reason": "ETF inflows hit a record"}]"#);
+}
+
+#[tokio::test]
+async fn test_generate_streaming() {
+    let server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path("/v1/chat/completions"))
+        .and(body_partial_json(serde_json::json!({ "stream": true, "stream_options": { "include_usage": true } })))
+        .respond_with(event_stream_fixture("openai/stream.txt"))
+        .mount(&server)
+        .await;
+
+    let mut pieces = Vec::new();
+    let completion = client(&server)
+        .generate_streaming("", &messages(), &CallOptions::new(256), &mut |text| pieces.push(text.to_string()))
+        .await
+        .unwrap();
+    assert_eq!(pieces, ["AERO is the governance ", "token of Aerodrome."]);
+    assert_eq!(completion.text, "AERO is the governance token of Aerodrome.");
+    assert_eq!(completion.usage, TokenUsage { input_tokens: 14, output_tokens: 9 });
+    assert_eq!(completion.request_id.as_deref(), Some("chatcmpl-03"));
+}
+
+#[tokio::test]
+async fn test_unauthorized_keeps_api_message() {
+    let server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .respond_with(ResponseTemplate::new(401).set_body_json(serde_json::json!({
+            "error": { "message": "Incorrect API key provided", "type": "invalid_request_error" }
+        })))
+        .mount(&server)
+        .await;
+
+    let error = client(&server).generate("", &messages(), &CallOptions::new(256)).await.unwrap_err();
+    assert!(matches!(error, LlmError::Unauthorized(msg) if msg == "Incorrect API key provided"));
+}
+
+#[tokio::test]
+async fn test_rate_limit_carries_retry_after() {
+    let server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .respond_with(rate_limited(20))
+        .mount(&server)
+        .await;
+
+    let error = client(&server).generate("", &messages(), &CallOptions::new(256)).await.unwrap_err();
+    assert!(matches!(error, LlmError::RateLimited(Some(wait)) if wait == Duration::from_secs(20)));
+}
+
+#[tokio::test]
+async fn test_missing_choice() {
+    let server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({ "choices": [] })))
+        .mount(&server)
+        .await;
+
+    let error = client(&server).generate("", &messages(), &CallOptions::new(256)).await.unwrap_err();
+    assert!(matches!(error, LlmError::InvalidResponse(_)));
+}
+
+#[tokio::test]
+async fn test_null_content_is_an_empty_reply() {
+    let server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+            "id": "chatcmpl-04",
+            "choices": [{ "index": 0, "message": { "role": "assistant", "content": null }, "finish_reason": "stop" }]
+        })))
+        .mount(&server)
+        .await;
+
+    let completion = client(&server).generate("", &messages(), &CallOptions::new(256)).await.unwrap();
+    assert_eq!(completion.text, "");
+    assert_eq!(completion.request_id.as_deref(), Some("chatcmpl-04"));
+}
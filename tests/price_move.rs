@@ -0,0 +1,112 @@
+mod common;
+
+use agent_friend::anthropic::AnthropicClient;
+use agent_friend::exa_api::ExaApiClient;
+use agent_friend::price_fetcher::CoinGeckoClient;
+use agent_friend::price_move::{MoveError, explain_move};
+use agent_friend::timezone::UserTimezone;
+use chrono::{DateTime, FixedOffset, TimeZone, Utc};
+use common::json_fixture;
+use serde_json::json;
+use std::time::Duration;
+use wiremock::matchers::{body_string_contains, method, path, query_param};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+
+fn now() -> DateTime<Utc> {
+    Utc.with_ymd_and_hms(2025, 10, 16, 10, 30, 0).unwrap()
+}
+
+fn utc_plus_one() -> UserTimezone {
+    FixedOffset::east_opt(3600).unwrap().into()
+}
+
+async fn mount_prices(server: &MockServer) {
+    Mock::given(method("GET"))
+        .and(path("/coins/bitcoin/market_chart"))
+        .and(query_param("days", "2"))
+        .respond_with(json_fixture("coingecko/market_chart_hourly.json"))
+        .mount(server)
+        .await;
+}
+
+async fn mount_news(server: &MockServer, fixture: ResponseTemplate) {
+    Mock::given(method("GET"))
+        .and(path("/api/search"))
+        .and(query_param("query", "cryptocurrency bitcoin latest news"))
+        .and(query_param("start_published_date", "2025-10-15"))
+        .respond_with(fixture)
+        .mount(server)
+        .await;
+}
+
+fn clients(server: &MockServer) -> (CoinGeckoClient, ExaApiClient, AnthropicClient) {
+    (
+        CoinGeckoClient::new(server.uri()).with_min_request_interval(Duration::ZERO),
+        ExaApiClient::with_api_key("exa-test".to_string()).with_base_url(&server.uri()),
+        AnthropicClient::new("sk-ant-test").with_base_url(&server.uri()),
+    )
+}
+
+#[tokio::test]
+async fn test_move_is_explained_from_the_days_ranked_news() {
+    let server = MockServer::start().await;
+    mount_prices(&server).await;
+    mount_news(&server, json_fixture("exa/search_news.json")).await;
+    // The prompt carries the move and the articles in ranked order
+    Mock::given(method("POST"))
+        .and(path("/v1/messages"))
+        .and(body_string_contains("Move: -4.58% over 23 hours"))
+        .and(body_string_contains("[1] Bitcoin Slides as ETF Outflows Mount (coindesk.com, 2025-10-16 06:10 UTC)"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "id": "msg_01",
+            "type": "message",
+            "role": "assistant",
+            "model": "claude-3-opus-20240229",
+            "content": [{ "type": "text", "text": "The drop lines up with $530 million of ETF outflows [1]." }],
+            "stop_reason": "end_turn",
+            "usage": { "input_tokens": 40, "output_tokens": 14 }
+        })))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let (prices, news, model) = clients(&server);
+    let reply = explain_move(&prices, &news, &model, "bitcoin", "Bitcoin", now(), utc_plus_one()).await.unwrap();
+    assert_eq!(
+        reply,
+        "Bitcoin is down 4.58% over the last 23 hours (12:00 Oct 15 to 11:27 Oct 16, UTC+01:00), \
+        from $112950.50 to $107780.40. Range: $107560.00 to $113210.00.\n\n\
+        The drop lines up with $530 million of ETF outflows [1].\n\n\
+        Sources:\n\
+        [1] Bitcoin Slides as ETF Outflows Mount (https://www.coindesk.com/markets/2025/10/16/bitcoin-slides-as-etf-outflows-mount)\n\
+        [2] Why I think BTC is crashing (https://medium.com/@trader/btc-crash-explained)"
+    );
+}
+
+#[tokio::test]
+async fn test_move_without_news_says_so_without_asking_the_model() {
+    let server = MockServer::start().await;
+    mount_prices(&server).await;
+    mount_news(&server, ResponseTemplate::new(200).set_body_json(json!({ "results": [], "next_page_id": null }))).await;
+    Mock::given(method("POST")).respond_with(ResponseTemplate::new(500)).expect(0).mount(&server).await;
+
+    let (prices, news, model) = clients(&server);
+    let reply = explain_move(&prices, &news, &model, "bitcoin", "Bitcoin", now(), utc_plus_one()).await.unwrap();
+    assert!(reply.starts_with("Bitcoin is down 4.58% over the last 23 hours"));
+    assert!(reply.ends_with("I found no news about Bitcoin from this window, so there's no reported cause for the move."));
+}
+
+#[tokio::test]
+async fn test_missing_prices_fail_before_the_news_search() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/coins/bitcoin/market_chart"))
+        .respond_with(ResponseTemplate::new(200).set_body_raw("{\"prices\": []}", "application/json"))
+        .mount(&server)
+        .await;
+    Mock::given(method("GET")).and(path("/api/search")).respond_with(ResponseTemplate::new(500)).expect(0).mount(&server).await;
+
+    let (prices, news, model) = clients(&server);
+    let error = explain_move(&prices, &news, &model, "bitcoin", "Bitcoin", now(), utc_plus_one()).await.unwrap_err();
+    assert!(matches!(error, MoveError::Price(_)));
+}
@@ -0,0 +1,159 @@
+mod common;
+
+use agent_friend::db::{self, NewTrade, OrderType, Trade, TradeStatus};
+use agent_friend::transaction_monitor::{TransactionMonitor, TransactionStatus, transaction_status};
+use common::db::{a_user, test_db};
+use common::json_fixture;
+use ethers::providers::{Http, Provider};
+use ethers::types::{H256, U256};
+use serde_json::json;
+use sqlx::{Pool, Postgres};
+use std::sync::Arc;
+use std::time::Duration;
+use wiremock::matchers::{body_partial_json, method};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+
+const TX_HASH: &str = "0x5e8fd3b0a1f1c1e2b6c2d6a6f9d0c8b7a6e5f4d3c2b1a09f8e7d6c5b4a392817";
+
+fn rpc_result(result: serde_json::Value) -> ResponseTemplate {
+    ResponseTemplate::new(200).set_body_json(json!({ "jsonrpc": "2.0", "id": 1, "result": result }))
+}
+
+/// Answer `rpc_method` with `response`, only the first `times` times when given
+async fn mount_rpc(node: &MockServer, rpc_method: &str, response: ResponseTemplate, times: Option<u64>) {
+    let mock = Mock::given(method("POST")).and(body_partial_json(json!({ "method": rpc_method }))).respond_with(response);
+    match times {
+        Some(times) => mock.up_to_n_times(times).mount(node).await,
+        None => mock.mount(node).await,
+    }
+}
+
+/// A node at `blocks`, the last one repeated, answering receipt polls with `receipt`
+async fn rpc_node(receipt: ResponseTemplate, blocks: &[u64]) -> MockServer {
+    let node = MockServer::start().await;
+    mount_rpc(&node, "eth_getTransactionReceipt", receipt, None).await;
+    for (i, block) in blocks.iter().enumerate() {
+        let times = (i + 1 < blocks.len()).then_some(1);
+        mount_rpc(&node, "eth_blockNumber", rpc_result(json!(format!("0x{:x}", block))), times).await;
+    }
+    node
+}
+
+fn provider(node: &MockServer) -> Arc<Provider<Http>> {
+    Arc::new(Provider::<Http>::try_from(node.uri()).unwrap())
+}
+
+fn monitor(node: &MockServer, pool: &Pool<Postgres>, user_id: i32) -> TransactionMonitor {
+    TransactionMonitor::new(provider(node), pool.clone(), user_id).with_poll_interval(Duration::from_millis(10))
+}
+
+fn tx_hash() -> H256 {
+    TX_HASH.parse().unwrap()
+}
+
+/// A swap of `user_id` sent in TX_HASH, pending in the trade history
+async fn sent_swap(pool: &Pool<Postgres>, user_id: i32) -> Trade {
+    let executed_at = chrono::NaiveDate::from_ymd_opt(2025, 10, 16).unwrap().and_hms_opt(10, 0, 0).unwrap();
+    let trade = NewTrade {
+        tx_hash: Some(TX_HASH.to_string()),
+        status: TradeStatus::Pending,
+        ..NewTrade::filled(executed_at, OrderType::Buy, "ethereum", "USDC", 0.032, 3125.0, 0.0)
+    };
+    db::create_trade(pool, user_id, &trade).await.unwrap()
+}
+
+async fn last_swap(pool: &Pool<Postgres>, user_id: i32) -> Trade {
+    db::get_last_swap(pool, user_id).await.unwrap().unwrap()
+}
+
+#[tokio::test]
+async fn test_swap_is_settled_once_it_has_its_confirmations() {
+    // Mined in 0x1234, the head moving from 0x1234 to 0x1236
+    let node = rpc_node(json_fixture("rpc/receipt.json"), &[0x1234, 0x1236]).await;
+    let client = provider(&node);
+
+    assert_eq!(
+        transaction_status(client.as_ref(), tx_hash(), 3).await.unwrap(),
+        TransactionStatus::Pending { block_number: Some(0x1234) }
+    );
+    let TransactionStatus::Confirmed(receipt) = transaction_status(client.as_ref(), tx_hash(), 3).await.unwrap() else {
+        panic!("a swap with three blocks is confirmed");
+    };
+    assert_eq!((receipt.tx_hash, receipt.block_number), (tx_hash(), Some(0x1234)));
+    assert_eq!((receipt.gas_used, receipt.effective_gas_price), (Some(U256::from(0x2a3f1)), Some(U256::from(0x59682f00))));
+}
+
+#[tokio::test]
+async fn test_unmined_swap_is_pending() {
+    let node = rpc_node(rpc_result(json!(null)), &[0x1234]).await;
+
+    let status = transaction_status(provider(&node).as_ref(), tx_hash(), 1).await.unwrap();
+    assert_eq!(status, TransactionStatus::Pending { block_number: None });
+}
+
+#[tokio::test]
+async fn test_confirmed_swap_gets_its_block_and_gas() {
+    let Some(pool) = test_db().await else { return };
+    let alice = a_user().create(&pool).await;
+    sent_swap(&pool, alice.id).await;
+    let node = rpc_node(json_fixture("rpc/receipt.json"), &[0x1234, 0x1235, 0x1236]).await;
+
+    let status = monitor(&node, &pool, alice.id).with_confirmations(2).watch(tx_hash()).await.unwrap();
+    assert!(matches!(status, TransactionStatus::Confirmed(_)), "{:?}", status);
+
+    let trade = last_swap(&pool, alice.id).await;
+    assert_eq!((trade.status, trade.block_number, trade.gas_used), (TradeStatus::Confirmed, Some(0x1234), Some(0x2a3f1)));
+    // 173041 gas at 1.5 gwei
+    assert!((trade.gas_cost.unwrap() - 0.0002595615).abs() < 1e-12);
+}
+
+#[tokio::test]
+async fn test_reverted_swap_is_failed() {
+    let Some(pool) = test_db().await else { return };
+    let alice = a_user().create(&pool).await;
+    sent_swap(&pool, alice.id).await;
+    let node = rpc_node(json_fixture("rpc/receipt_reverted.json"), &[0x1236]).await;
+
+    let status = monitor(&node, &pool, alice.id).watch(tx_hash()).await.unwrap();
+    assert!(matches!(status, TransactionStatus::Failed(_)), "{:?}", status);
+    let trade = last_swap(&pool, alice.id).await;
+    assert_eq!((trade.status, trade.block_number.is_some()), (TradeStatus::Failed, true));
+}
+
+#[tokio::test]
+async fn test_swap_not_mined_in_time_is_stuck() {
+    let Some(pool) = test_db().await else { return };
+    let alice = a_user().create(&pool).await;
+    sent_swap(&pool, alice.id).await;
+    // Started at 0x1234, still there after the first poll, 12 blocks later after the second
+    let node = rpc_node(rpc_result(json!(null)), &[0x1234, 0x1234, 0x1240]).await;
+    let monitor = monitor(&node, &pool, alice.id).with_timeout_blocks(10);
+
+    // A single check leaves an unmined swap pending
+    assert_eq!(monitor.check(tx_hash()).await.unwrap(), TransactionStatus::Pending { block_number: None });
+    assert_eq!(last_swap(&pool, alice.id).await.status, TradeStatus::Pending);
+
+    assert_eq!(monitor.watch(tx_hash()).await.unwrap(), TransactionStatus::Stuck);
+    let trade = last_swap(&pool, alice.id).await;
+    assert_eq!((trade.status, trade.block_number), (TradeStatus::Stuck, None));
+    let receipt_polls = node
+        .received_requests()
+        .await
+        .unwrap()
+        .iter()
+        .filter(|request| request.body_json::<serde_json::Value>().unwrap()["method"] == "eth_getTransactionReceipt")
+        .count();
+    assert_eq!(receipt_polls, 3);
+}
+
+#[tokio::test]
+async fn test_other_users_swaps_are_left_alone() {
+    let Some(pool) = test_db().await else { return };
+    let alice = a_user().create(&pool).await;
+    sent_swap(&pool, alice.id).await;
+    let node = rpc_node(json_fixture("rpc/receipt.json"), &[0x1236]).await;
+
+    let status = monitor(&node, &pool, 1).check(tx_hash()).await.unwrap();
+    assert!(matches!(status, TransactionStatus::Confirmed(_)));
+    assert_eq!(last_swap(&pool, alice.id).await.status, TradeStatus::Pending);
+}
@@ -0,0 +1,202 @@
+mod common;
+
+use agent_friend::dex_aggregator::{DexAggregator, FallbackAggregator, ZERO_EX_ALLOWANCE_HOLDER, ZeroExClient};
+use agent_friend::trading::{OneInchClient, Token, TradingError};
+use common::{json_fixture, malformed_json};
+use serde_json::json;
+use wiremock::matchers::{header, method, path, query_param};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+
+const USDC: &str = "0x036cbd53842c5426634e7929541ec2318f3dcf7e";
+const WETH: &str = "0x4200000000000000000000000000000000000006";
+const WALLET: &str = "0x1111111111111111111111111111111111111111";
+
+fn token(symbol: &str, address: &str, decimals: u32) -> Token {
+    Token { address: address.to_string(), decimals, symbol: symbol.to_string(), name: symbol.to_string(), logo_uri: None }
+}
+
+fn usdc() -> Token {
+    token("USDC", USDC, 6)
+}
+
+fn weth() -> Token {
+    token("WETH", WETH, 18)
+}
+
+fn zero_ex(server: &MockServer) -> ZeroExClient {
+    ZeroExClient::new(84532, "0x-test".to_string()).with_base_url(&server.uri())
+}
+
+fn one_inch(server: &MockServer) -> OneInchClient {
+    OneInchClient::new(84532, None).with_base_url(&server.uri())
+}
+
+/// 1inch falling back to 0x, each answered by its own mock server
+fn fallback(one_inch_server: &MockServer, zero_ex_server: &MockServer) -> FallbackAggregator {
+    FallbackAggregator::new(vec![Box::new(one_inch(one_inch_server)), Box::new(zero_ex(zero_ex_server))])
+}
+
+async fn mount_price(server: &MockServer, expected: u64) {
+    Mock::given(method("GET"))
+        .and(path("/swap/allowance-holder/price"))
+        .respond_with(json_fixture("zero_ex/price.json"))
+        .expect(expected)
+        .mount(server)
+        .await;
+}
+
+#[tokio::test]
+async fn test_price_is_a_quote() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/swap/allowance-holder/price"))
+        .and(header("0x-api-key", "0x-test"))
+        .and(header("0x-version", "v2"))
+        .and(query_param("chainId", "84532"))
+        .and(query_param("sellToken", USDC))
+        .and(query_param("buyToken", WETH))
+        .and(query_param("sellAmount", "100000000"))
+        .and(query_param("taker", WALLET))
+        .respond_with(json_fixture("zero_ex/price.json"))
+        .mount(&server)
+        .await;
+
+    let quote = zero_ex(&server).get_quote(&usdc(), &weth(), "100000000", WALLET).await.unwrap();
+    assert_eq!((quote.from_amount.as_str(), quote.to_amount.as_str()), ("100000000", "32000000000000000"));
+    assert_eq!((quote.from_token.decimals, quote.to_token.symbol.as_str()), (6, "WETH"));
+    assert_eq!(quote.estimated_gas, 192000);
+    let route: Vec<(&str, u32)> = quote.protocols[0][0].iter().map(|fill| (fill.name.as_str(), fill.part)).collect();
+    assert_eq!(route, [("Uniswap_V3", 70), ("Aerodrome_V2", 30)]);
+    assert_eq!(quote.aggregator, "0x");
+}
+
+#[tokio::test]
+async fn test_swap_calldata_goes_through_the_allowance_holder() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/swap/allowance-holder/quote"))
+        .and(query_param("slippageBps", "50"))
+        .respond_with(json_fixture("zero_ex/quote.json"))
+        .mount(&server)
+        .await;
+
+    let client = zero_ex(&server);
+    let swap = client.get_swap_calldata(&usdc(), &weth(), "100000000", WALLET, 0.5).await.unwrap();
+    assert_eq!((swap.tx.from.as_str(), swap.tx.to.as_str()), (WALLET, ZERO_EX_ALLOWANCE_HOLDER));
+    assert_eq!((swap.tx.data.as_str(), swap.tx.gas, swap.tx.gas_price.as_str()), ("0x2213bc0b", 192000, "1500000000"));
+    assert_eq!((swap.to_amount.as_str(), swap.aggregator.as_str()), ("32000000000000000", "0x"));
+    assert_eq!(client.get_spender().await.unwrap(), ZERO_EX_ALLOWANCE_HOLDER);
+}
+
+#[tokio::test]
+async fn test_swap_sent_elsewhere_than_the_allowance_holder_is_refused() {
+    let server = MockServer::start().await;
+    let mut quote: serde_json::Value = serde_json::from_str(&common::fixture("zero_ex/quote.json")).unwrap();
+    quote["transaction"]["to"] = json!("0x2222222222222222222222222222222222222222");
+    Mock::given(method("GET"))
+        .and(path("/swap/allowance-holder/quote"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(quote))
+        .mount(&server)
+        .await;
+
+    let error = zero_ex(&server).get_swap_calldata(&usdc(), &weth(), "100000000", WALLET, 1.0).await.unwrap_err();
+    assert!(matches!(error, TradingError::InvalidZeroExResponse(ref message) if message.contains("0x2222")), "{}", error);
+}
+
+#[tokio::test]
+async fn test_no_liquidity_and_errors() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/swap/allowance-holder/price"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({"liquidityAvailable": false, "zid": "0x1"})))
+        .mount(&server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/swap/allowance-holder/quote"))
+        .respond_with(ResponseTemplate::new(400).set_body_json(json!({"name": "INPUT_INVALID", "message": "Invalid sellToken"})))
+        .mount(&server)
+        .await;
+
+    let client = zero_ex(&server);
+    let error = client.get_quote(&usdc(), &weth(), "1", WALLET).await.unwrap_err();
+    assert_eq!(error.to_string(), "Invalid 0x response: no liquidity for this swap");
+    let error = client.get_swap_calldata(&usdc(), &weth(), "1", WALLET, 1.0).await.unwrap_err();
+    assert!(matches!(error, TradingError::ZeroExApi { status, ref message } if status.as_u16() == 400 && message == "Invalid sellToken"));
+    assert!(!error.is_upstream_failure());
+}
+
+#[tokio::test]
+async fn test_1inch_server_errors_fall_back_to_0x() {
+    let (one_inch_server, zero_ex_server) = (MockServer::start().await, MockServer::start().await);
+    Mock::given(method("GET"))
+        .and(path("/84532/quote"))
+        .respond_with(ResponseTemplate::new(502).set_body_string("bad gateway"))
+        .expect(1)
+        .mount(&one_inch_server)
+        .await;
+    mount_price(&zero_ex_server, 1).await;
+
+    let aggregator = fallback(&one_inch_server, &zero_ex_server);
+    let quote = aggregator.get_quote(&usdc(), &weth(), "100000000", WALLET).await.unwrap();
+    assert_eq!((quote.aggregator.as_str(), quote.to_amount.as_str()), ("0x", "32000000000000000"));
+
+    // The swap is prepared, and its token approved, by the aggregator that quoted it
+    let pinned = aggregator.by_name(&quote.aggregator).unwrap();
+    assert_eq!(pinned.get_spender().await.unwrap(), ZERO_EX_ALLOWANCE_HOLDER);
+    assert_eq!(aggregator.by_name("1inch").unwrap().name(), "1inch");
+    assert!(aggregator.by_name("paraswap").is_none());
+}
+
+#[tokio::test]
+async fn test_working_1inch_is_not_second_guessed() {
+    let (one_inch_server, zero_ex_server) = (MockServer::start().await, MockServer::start().await);
+    Mock::given(method("GET"))
+        .and(path("/84532/quote"))
+        .respond_with(json_fixture("oneinch/quote.json"))
+        .mount(&one_inch_server)
+        .await;
+    mount_price(&zero_ex_server, 0).await;
+
+    let quote = fallback(&one_inch_server, &zero_ex_server).get_quote(&usdc(), &weth(), "100000000", WALLET).await.unwrap();
+    assert_eq!((quote.aggregator.as_str(), quote.estimated_gas), ("1inch", 185000));
+}
+
+#[tokio::test]
+async fn test_refusals_are_not_retried_elsewhere() {
+    let (one_inch_server, zero_ex_server) = (MockServer::start().await, MockServer::start().await);
+    Mock::given(method("GET"))
+        .and(path("/84532/quote"))
+        .respond_with(ResponseTemplate::new(400).set_body_json(json!({"description": "insufficient liquidity"})))
+        .mount(&one_inch_server)
+        .await;
+    mount_price(&zero_ex_server, 0).await;
+
+    let aggregator = fallback(&one_inch_server, &zero_ex_server);
+    let error = aggregator.get_quote(&usdc(), &weth(), "100000000", WALLET).await.unwrap_err();
+    assert!(matches!(error, TradingError::Api { status, .. } if status.as_u16() == 400));
+    // Nor is a slippage no aggregator accepts
+    let error = aggregator.get_swap_calldata(&usdc(), &weth(), "100000000", WALLET, 60.0).await.unwrap_err();
+    assert!(matches!(error, TradingError::SlippageTooHigh { .. }));
+}
+
+#[tokio::test]
+async fn test_when_every_aggregator_fails_the_first_error_is_returned() {
+    let (one_inch_server, zero_ex_server) = (MockServer::start().await, MockServer::start().await);
+    Mock::given(method("GET"))
+        .and(path("/84532/swap"))
+        .respond_with(ResponseTemplate::new(500).set_body_string("internal"))
+        .mount(&one_inch_server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/swap/allowance-holder/quote"))
+        .respond_with(malformed_json())
+        .expect(1)
+        .mount(&zero_ex_server)
+        .await;
+
+    let error = fallback(&one_inch_server, &zero_ex_server)
+        .get_swap_calldata(&usdc(), &weth(), "100000000", WALLET, 1.0)
+        .await
+        .unwrap_err();
+    assert!(matches!(error, TradingError::Api { status, .. } if status.as_u16() == 500));
+}